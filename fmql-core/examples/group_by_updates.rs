@@ -1,5 +1,5 @@
 use chrono::{DateTime, TimeZone, Utc};
-use fmql::sql::{execute_query, parse_sql};
+use fmql_core::sql::{execute_query, parse_sql};
 use std::fs::{self, File};
 use std::io::Write;
 use std::path::Path;
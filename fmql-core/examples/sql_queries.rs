@@ -1,4 +1,4 @@
-use fmql::sql::{execute_query, parse_sql};
+use fmql_core::sql::{execute_query, parse_sql};
 use std::fs::{self, File};
 use std::io::Write;
 use std::path::Path;
@@ -0,0 +1,277 @@
+//! Error handling module for the file manager.
+//!
+//! This module defines the error types and result type used throughout the application.
+//! It provides a consistent error handling approach using the `thiserror` crate to
+//! implement error types that are both user-friendly and programmatically useful.
+//!
+//! Parsing and execution keep their own detailed error enums
+//! ([`ParserError`] and [`ExecutorError`]); [`FMQLError`] wraps both so that
+//! library users can handle a single type, and maps every error to a stable
+//! code and a process exit code, from the table at [`exit_code`].
+//! [`Diagnostic`] is the same information as data, which `fmql` writes to
+//! stderr as JSON under `--format json`.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use fmql_core::error::FMQLError;
+//! use fmql_core::sql::{run, ExecutionOptions};
+//!
+//! match run("SELECT * FROM ~/Documents WHERE size >", &ExecutionOptions::default()) {
+//!     Ok(results) => println!("{} files", results.len()),
+//!     Err(err) => {
+//!         eprintln!("[{}] {}", err.code(), err);
+//!         std::process::exit(err.exit_code());
+//!     }
+//! }
+//! ```
+
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+use thiserror::Error;
+
+use crate::sql::executor::ExecutorError;
+use crate::sql::lexer::Span;
+use crate::sql::lint::LintWarning;
+use crate::sql::parser::ParserError;
+use crate::sql::paths::PathProblem;
+use crate::sql::script::Problem;
+
+/// Comprehensive error type for file manager operations.
+///
+/// This enum represents all possible errors that can occur during file operations,
+/// from parsing a query to running it against the file system.
+///
+/// # Examples
+///
+/// Creating and handling different error types:
+///
+/// ```
+/// use fmql_core::error::FMQLError;
+///
+/// // Creating an IoError
+/// let io_error = FMQLError::IoError(std::io::Error::new(std::io::ErrorKind::NotFound, "File not found"));
+/// assert!(format!("{}", io_error).contains("IO error"));
+/// assert_eq!(io_error.code(), "io.not_found");
+/// assert_eq!(io_error.exit_code(), 66);
+/// ```
+#[derive(Error, Debug)]
+pub enum FMQLError {
+    /// Error from underlying IO operations.
+    ///
+    /// This wraps standard IO errors from the Rust standard library.
+    #[error("IO error: {0}")]
+    IoError(#[from] std::io::Error),
+
+    /// Error when the query text could not be parsed.
+    #[error("Invalid query: {0}")]
+    Parse(#[from] ParserError),
+
+    /// Error while running a parsed query.
+    #[error("Query failed: {0}")]
+    Execution(#[from] ExecutorError),
+}
+
+/// Result type for operations that can fail with an [`FMQLError`].
+pub type Result<T> = std::result::Result<T, FMQLError>;
+
+impl FMQLError {
+    /// Returns a stable, machine-readable code for the error, such as
+    /// `parse.syntax` or `exec.denied`.
+    pub fn code(&self) -> &'static str {
+        match self {
+            FMQLError::IoError(err) => io_code(err),
+            FMQLError::Parse(err) => match err {
+                ParserError::Sql(_) => "parse.sql",
+                ParserError::UnsupportedStatement(_) => "parse.unsupported_statement",
+                ParserError::InvalidPath(_) => "parse.invalid_path",
+                ParserError::MissingClause(_) => "parse.missing_clause",
+                ParserError::Syntax { .. } => "parse.syntax",
+                ParserError::UnknownAttribute { .. } => "parse.unknown_attribute",
+                ParserError::InvalidPattern { .. } => "parse.invalid_pattern",
+                ParserError::Find(_) => "parse.find",
+                ParserError::Snippet { .. } => "parse.snippet",
+            },
+            FMQLError::Execution(err) => match err {
+                ExecutorError::IoError(err) => io_code(err),
+                ExecutorError::UnsupportedAttribute(_) => "exec.unsupported_attribute",
+                ExecutorError::UnsupportedOperation(_) => "exec.unsupported_operation",
+                ExecutorError::InvalidRegex(_) => "exec.invalid_regex",
+                ExecutorError::TypeError(_) => "exec.type_error",
+                ExecutorError::InvalidPath(err) => match err.problem {
+                    PathProblem::NotFound => "exec.path_not_found",
+                    PathProblem::NotADirectory => "exec.not_a_directory",
+                    PathProblem::NotReadable => "exec.path_not_readable",
+                    PathProblem::NoMatches => "exec.no_matches",
+                    PathProblem::InvalidPattern => "exec.invalid_pattern",
+                },
+                ExecutorError::Denied(_) => "exec.denied",
+                ExecutorError::LimitExceeded(_) => "exec.limit_exceeded",
+                ExecutorError::InvalidJoin(_) => "exec.invalid_join",
+                ExecutorError::InvalidPolicy(_) => "exec.invalid_policy",
+                ExecutorError::RenameConflict(_) => "exec.rename_conflict",
+                ExecutorError::InvalidCheckpoint(_) => "exec.invalid_checkpoint",
+                ExecutorError::InvalidShards(_) => "exec.invalid_shards",
+                ExecutorError::InvalidTags(_) => "exec.invalid_tags",
+                ExecutorError::InvalidResults(_) => "exec.invalid_results",
+                ExecutorError::InsufficientSpace(_) => "exec.insufficient_space",
+                ExecutorError::EvaluationFailed(..) => "exec.evaluation_failed",
+                ExecutorError::OverBudget(_) => "exec.over_budget",
+                ExecutorError::InvalidImage(_) => "exec.invalid_image",
+                ExecutorError::InvalidBackup(_) => "exec.invalid_backup",
+                ExecutorError::InvalidDevice(_) => "exec.invalid_device",
+                ExecutorError::InvalidTrace(_) => "exec.invalid_trace",
+                ExecutorError::Locked(_) => "exec.locked",
+            },
+        }
+    }
+
+    /// Returns the process exit code the CLI uses for the error; see
+    /// [`exit_code`].
+    pub fn exit_code(&self) -> i32 {
+        exit_code(self.code())
+    }
+
+    /// Returns where in the query the error is, if it is about one part of it.
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            FMQLError::Parse(err) => err.span(),
+            _ => None,
+        }
+    }
+
+    /// Returns the path the error is about, if it is about one.
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            FMQLError::Parse(ParserError::InvalidPath(path)) => Some(Path::new(path)),
+            FMQLError::Execution(ExecutorError::InvalidPath(err)) => Some(&err.path),
+            _ => None,
+        }
+    }
+}
+
+/// An error or a warning as data, for tools that run `fmql` and need to
+/// tell failures apart without parsing messages meant for people.
+///
+/// Every field is always there, `null` when it doesn't apply, so a reader
+/// can rely on the shape.
+///
+/// # Examples
+///
+/// ```
+/// use fmql_core::error::{Diagnostic, FMQLError};
+/// use fmql_core::sql::parse_sql;
+///
+/// let err = FMQLError::from(parse_sql("SELECT * FROM . WHERE colour = 'red'").unwrap_err());
+/// let diagnostic = Diagnostic::from(&err);
+/// assert_eq!(diagnostic.level, "error");
+/// assert_eq!(diagnostic.code, "parse.unknown_attribute");
+/// assert_eq!(diagnostic.span.unwrap().start.column, 23);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Diagnostic {
+    /// `error` or `warning`.
+    pub level: &'static str,
+    /// The error's code (see [`FMQLError::code`]) or the warning's.
+    pub code: &'static str,
+    /// A description for people, as fmql prints it otherwise.
+    pub message: String,
+    /// Where in the query the problem is.
+    pub span: Option<Span>,
+    /// The path the problem is about.
+    pub path: Option<PathBuf>,
+}
+
+impl From<&FMQLError> for Diagnostic {
+    fn from(err: &FMQLError) -> Self {
+        Diagnostic {
+            level: "error",
+            code: err.code(),
+            message: err.to_string(),
+            span: err.span(),
+            path: err.path().map(Path::to_path_buf),
+        }
+    }
+}
+
+impl From<&LintWarning> for Diagnostic {
+    fn from(warning: &LintWarning) -> Self {
+        Diagnostic {
+            level: "warning",
+            code: warning.code,
+            message: warning.message.clone(),
+            span: None,
+            path: None,
+        }
+    }
+}
+
+impl From<&Problem> for Diagnostic {
+    fn from(problem: &Problem) -> Self {
+        Diagnostic {
+            level: "error",
+            code: "script.would_fail",
+            message: problem.to_string(),
+            span: None,
+            path: problem.path.clone(),
+        }
+    }
+}
+
+/// Returns the process exit code for an error code, such as 2 for
+/// `parse.syntax`.
+///
+/// Codes follow the BSD `sysexits.h` conventions where one fits. This is
+/// the whole table `fmql` exits by:
+///
+/// | Exit | Meaning | Error codes |
+/// |------|---------|-------------|
+/// | 0 | Success | |
+/// | 1 | A reported outcome: differences, a fired alert, failed changes, `check` problems | |
+/// | 2 | An invalid query or command line | `parse.*` |
+/// | 3 | A resource limit was exceeded | `exec.limit_exceeded` |
+/// | 65 | A valid query failed on the data it read | other `exec.*`, `sqlite.*` |
+/// | 66 | A missing input | `io.not_found`, `exec.path_not_found`, `exec.no_matches`, ... |
+/// | 70 | fmql hit a bug | `internal.panic` |
+/// | 74 | Reading or writing failed | other `io.*`, `exec.insufficient_space` |
+/// | 75 | Another run holds a lock on the files; try again later | `exec.locked` |
+/// | 77 | Permission was denied | `io.permission_denied`, `exec.denied`, `exec.path_not_readable` |
+/// | 130 | The user cancelled | |
+/// | 141 | The output was closed before everything was written, as by `head` | `io.broken_pipe` |
+///
+/// # Examples
+///
+/// ```
+/// use fmql_core::error::exit_code;
+///
+/// assert_eq!(exit_code("parse.syntax"), 2);
+/// assert_eq!(exit_code("io.broken_pipe"), 141);
+/// assert_eq!(exit_code("internal.panic"), 70);
+/// assert_eq!(exit_code("exec.type_error"), 65);
+/// ```
+pub fn exit_code(code: &str) -> i32 {
+    match code {
+        code if code.starts_with("parse.") => 2,
+        "exec.limit_exceeded" => 3,
+        "io.not_found" | "exec.path_not_found" | "exec.not_a_directory" | "exec.no_matches" => 66,
+        "io.permission_denied" | "exec.denied" | "exec.path_not_readable" => 77,
+        "internal.panic" => 70,
+        "io.broken_pipe" => 141,
+        code if code.starts_with("io.") => 74,
+        "exec.insufficient_space" => 74,
+        "exec.locked" => 75,
+        _ => 65,
+    }
+}
+
+/// Returns the error code for an I/O error.
+fn io_code(err: &std::io::Error) -> &'static str {
+    match err.kind() {
+        std::io::ErrorKind::NotFound => "io.not_found",
+        std::io::ErrorKind::PermissionDenied => "io.permission_denied",
+        std::io::ErrorKind::AlreadyExists => "io.already_exists",
+        std::io::ErrorKind::BrokenPipe => "io.broken_pipe",
+        _ => "io.other",
+    }
+}
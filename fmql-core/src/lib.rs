@@ -0,0 +1,25 @@
+//! fmql-core - The query engine behind fmql, a fast and feature-rich file manager written in Rust.
+//! 
+//! This crate parses and runs fmql's SQL-like queries over the file system: the parser, the AST,
+//! the executor and its backends. The `fmql` command-line tool is built on it, and any other
+//! frontend can be too, without the tool's own dependencies.
+//! 
+//! # Examples
+//! 
+//! Using SQL-like queries:
+//! ```no_run
+//! use fmql_core::sql::{parse_sql, execute_query};
+//! 
+//! // Find all text files in the Documents folder
+//! let query = parse_sql("SELECT * FROM ~/Documents WHERE extension = '.txt'").unwrap();
+//! let results = execute_query(&query).unwrap();
+//! 
+//! for file in results {
+//!     println!("{}", file.name);
+//! }
+//! ```
+
+pub mod error;
+pub mod sql;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
\ No newline at end of file
@@ -0,0 +1,206 @@
+//! Aggregate functions for grouped SELECT queries.
+//!
+//! An [`Accumulator`] folds the values of one aggregate column over the
+//! files of one group, one file at a time, so a group never has to hold its
+//! files in memory.
+//!
+//! # Examples
+//!
+//! ```
+//! use fmql_core::sql::aggregate::Accumulator;
+//! use fmql_core::sql::ast::{AggregateFunction, FileValue};
+//!
+//! let mut total = Accumulator::new(AggregateFunction::Sum);
+//! for size in [100, 250] {
+//!     total.add(Some(&FileValue::Integer(size))).unwrap();
+//! }
+//! total.add(Some(&FileValue::Null)).unwrap();
+//! assert_eq!(total.finish(), FileValue::Integer(350));
+//!
+//! // A fraction makes the sum a float
+//! total.add(Some(&FileValue::Number(0.5))).unwrap();
+//! assert_eq!(total.finish(), FileValue::Number(350.5));
+//! ```
+
+use std::cmp::Ordering;
+
+use serde::{Deserialize, Serialize};
+
+use crate::sql::ast::{AggregateFunction, FileValue};
+use crate::sql::lexer::parse_numeric;
+use crate::sql::sample::{Estimate, Z_95};
+use crate::sql::sort::compare_values;
+
+/// The running state of one aggregate over one group. It can be saved and
+/// merged with the state of the same aggregate over other files, which is
+/// how the shards of a query are combined; see [`shard`](crate::sql::shard).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Accumulator {
+    function: AggregateFunction,
+    /// The number of values added (rows, for `COUNT(*)`).
+    count: u64,
+    sum: f64,
+    /// The sum exactly, while every value has been a whole number and the
+    /// sum fits; SUM gives it rather than `sum` when it can.
+    #[serde(default)]
+    exact_sum: Option<i64>,
+    /// The sum of the squares of the values, for the error of an estimate.
+    sum_of_squares: f64,
+    /// The smallest or largest value so far, for MIN and MAX.
+    best: Option<FileValue>,
+}
+
+impl Accumulator {
+    /// Starts an empty aggregate.
+    pub fn new(function: AggregateFunction) -> Self {
+        Accumulator {
+            function,
+            count: 0,
+            sum: 0.0,
+            exact_sum: Some(0),
+            sum_of_squares: 0.0,
+            best: None,
+        }
+    }
+
+    /// Adds one file's value. `None` stands for the row itself, as in
+    /// `COUNT(*)`; NULL values are skipped.
+    ///
+    /// SUM and AVG accept numbers and numeric text, and fail with a message
+    /// on anything else.
+    pub fn add(&mut self, value: Option<&FileValue>) -> Result<(), String> {
+        let value = match value {
+            None => {
+                self.count += 1;
+                return Ok(());
+            }
+            Some(FileValue::Null) => return Ok(()),
+            Some(value) => value,
+        };
+
+        match self.function {
+            AggregateFunction::Count => {}
+            AggregateFunction::Sum | AggregateFunction::Avg => {
+                let number = match value {
+                    FileValue::Integer(_) | FileValue::Number(_) => value.clone(),
+                    FileValue::String(s) => parse_numeric(s.trim())
+                        .ok_or_else(|| format!("Cannot add up '{}' as a number", s))?,
+                    other => return Err(format!("Cannot add up {:?}", other)),
+                };
+                self.exact_sum = match number {
+                    FileValue::Integer(n) => self.exact_sum.and_then(|sum| sum.checked_add(n)),
+                    _ => None,
+                };
+                let number = number.as_f64().unwrap_or_default();
+                self.sum += number;
+                self.sum_of_squares += number * number;
+            }
+            AggregateFunction::Min | AggregateFunction::Max => self.offer(value),
+        }
+        self.count += 1;
+        Ok(())
+    }
+
+    /// Adds the values another accumulator of the same aggregate has seen,
+    /// as if they had been added to this one.
+    pub fn merge(&mut self, other: &Accumulator) {
+        self.count += other.count;
+        self.sum += other.sum;
+        self.exact_sum = self.exact_sum.zip(other.exact_sum).and_then(|(a, b)| a.checked_add(b));
+        self.sum_of_squares += other.sum_of_squares;
+        if let Some(best) = &other.best {
+            self.offer(best);
+        }
+    }
+
+    /// Keeps `value` as the MIN or MAX if it beats the one so far.
+    fn offer(&mut self, value: &FileValue) {
+        let wanted = if self.function == AggregateFunction::Min {
+            Ordering::Less
+        } else {
+            Ordering::Greater
+        };
+        let better = self
+            .best
+            .as_ref()
+            .is_none_or(|best| compare_values(value, best, false) == wanted);
+        if better {
+            self.best = Some(value.clone());
+        }
+    }
+
+    /// Returns the aggregate's value. Every function but COUNT gives NULL
+    /// for a group with no values.
+    pub fn finish(&self) -> FileValue {
+        match self.function {
+            AggregateFunction::Count => FileValue::from_u64(self.count),
+            _ if self.count == 0 => FileValue::Null,
+            AggregateFunction::Sum => {
+                self.exact_sum.map_or(FileValue::Number(self.sum), FileValue::Integer)
+            }
+            AggregateFunction::Avg => FileValue::Number(self.sum / self.count as f64),
+            AggregateFunction::Min | AggregateFunction::Max => {
+                self.best.clone().unwrap_or(FileValue::Null)
+            }
+        }
+    }
+
+    /// Estimates the aggregate over everything from its value over a
+    /// sample of `fraction` of it; see [`sample`](crate::sql::sample).
+    pub fn estimate(&self, fraction: f64) -> Estimate {
+        let value = self.finish();
+        let exact = |value: FileValue| Estimate {
+            value: value.clone(),
+            low: value.clone(),
+            high: value,
+        };
+        if fraction >= 1.0 {
+            return exact(value);
+        }
+        let count = self.count as f64;
+        let interval = |value: f64, error: f64| Estimate {
+            value: FileValue::Number(value),
+            low: FileValue::Number(value - Z_95 * error),
+            high: FileValue::Number(value + Z_95 * error),
+        };
+        match self.function {
+            AggregateFunction::Count => {
+                let error = ((1.0 - fraction) * count).sqrt() / fraction;
+                let scaled = count / fraction;
+                // With nothing found, the rule of three still bounds the count
+                let high = if self.count == 0 { 3.0 / fraction } else { scaled + Z_95 * error };
+                Estimate {
+                    value: FileValue::Number(scaled.round()),
+                    low: FileValue::Number((scaled - Z_95 * error).max(count).round()),
+                    high: FileValue::Number(high.round()),
+                }
+            }
+            _ if self.count == 0 => exact(FileValue::Null),
+            AggregateFunction::Sum => {
+                let error = ((1.0 - fraction) * self.sum_of_squares).sqrt() / fraction;
+                interval(self.sum / fraction, error)
+            }
+            AggregateFunction::Avg if self.count < 2 => Estimate {
+                value,
+                low: FileValue::Null,
+                high: FileValue::Null,
+            },
+            AggregateFunction::Avg => {
+                let mean = self.sum / count;
+                let variance =
+                    ((self.sum_of_squares - count * mean * mean) / (count - 1.0)).max(0.0);
+                interval(mean, ((1.0 - fraction) * variance / count).sqrt())
+            }
+            AggregateFunction::Min => Estimate {
+                value: value.clone(),
+                low: FileValue::Null,
+                high: value,
+            },
+            AggregateFunction::Max => Estimate {
+                value: value.clone(),
+                low: value,
+                high: FileValue::Null,
+            },
+        }
+    }
+}
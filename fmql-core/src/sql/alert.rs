@@ -0,0 +1,145 @@
+//! Thresholds on query results, for monitoring with `fmql alert`.
+//!
+//! A [`Threshold`] such as `count > 0` or `bytes >= 10GB` compares a column
+//! of a query's result with a number. For a query that lists files, the
+//! result is summarised by [`summarise`] as a single row with `count`, the
+//! number of files, and `bytes`, their total size. A grouped query's rows
+//! are checked as they are, and the threshold holds if any row crosses it.
+//! `rows`, the number of result rows, can always be used.
+//!
+//! # Examples
+//!
+//! ```
+//! use fmql_core::sql::alert::Threshold;
+//! use fmql_core::sql::ast::FileValue;
+//! use fmql_core::sql::executor::GroupRow;
+//!
+//! let threshold: Threshold = "bytes > 1GB".parse().unwrap();
+//! let row = GroupRow {
+//!     columns: vec![("owner".to_string(), FileValue::String("alice".to_string())),
+//!                   ("bytes".to_string(), FileValue::Number(2e9))],
+//! };
+//! assert!(threshold.holds(&[row]).unwrap());
+//! assert!(!threshold.holds(&[]).unwrap());
+//! ```
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::sql::ast::{ComparisonOperator, FileValue};
+use crate::sql::executor::{ExecutorError, FileResult, GroupRow, Result};
+use crate::sql::throttle::parse_size;
+
+/// A comparison between a result column and a number, such as `count > 0`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Threshold {
+    /// The column compared.
+    pub column: String,
+    /// How the column is compared.
+    pub operator: ComparisonOperator,
+    /// The number it is compared with.
+    pub value: f64,
+}
+
+impl FromStr for Threshold {
+    type Err = String;
+
+    /// Parses `column operator number`. The number may have a size unit, as
+    /// in `bytes > 500MB`.
+    fn from_str(text: &str) -> std::result::Result<Self, String> {
+        let invalid = || {
+            format!("Invalid threshold '{}', expected something like 'count > 0'", text)
+        };
+        let start = text.find(['<', '>', '=', '!']).ok_or_else(invalid)?;
+        let end = text[start..]
+            .find(|c: char| !matches!(c, '<' | '>' | '=' | '!'))
+            .map_or(text.len(), |end| start + end);
+        let operator = match &text[start..end] {
+            "=" | "==" => ComparisonOperator::Eq,
+            "!=" | "<>" => ComparisonOperator::NotEq,
+            "<" => ComparisonOperator::Lt,
+            "<=" => ComparisonOperator::LtEq,
+            ">" => ComparisonOperator::Gt,
+            ">=" => ComparisonOperator::GtEq,
+            _ => return Err(invalid()),
+        };
+        let column = text[..start].trim();
+        let number = text[end..].trim();
+        if column.is_empty() {
+            return Err(invalid());
+        }
+        let value = match number.parse::<f64>() {
+            Ok(value) => value,
+            Err(_) => parse_size(number).map_err(|_| invalid())? as f64,
+        };
+        Ok(Threshold {
+            column: column.to_string(),
+            operator,
+            value,
+        })
+    }
+}
+
+impl fmt::Display for Threshold {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} {}", self.column, self.operator, self.value)
+    }
+}
+
+impl Threshold {
+    /// Returns true if any of the rows crosses the threshold. A row whose
+    /// value isn't a number, such as NULL, doesn't.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ExecutorError::UnsupportedOperation` if the rows have no
+    /// such column.
+    pub fn holds(&self, rows: &[GroupRow]) -> Result<bool> {
+        if self.column.eq_ignore_ascii_case("rows") {
+            return Ok(self.compare(rows.len() as f64));
+        }
+        let mut crossed = false;
+        for row in rows {
+            match row.get(&self.column) {
+                Some(value) => crossed |= self.crossed_by(value),
+                None => {
+                    let columns: Vec<&str> =
+                        row.columns.iter().map(|(name, _)| name.as_str()).collect();
+                    return Err(ExecutorError::UnsupportedOperation(format!(
+                        "the threshold needs a column '{}', but the result has {} and rows",
+                        self.column,
+                        columns.join(", ")
+                    )));
+                }
+            }
+        }
+        Ok(crossed)
+    }
+
+    /// Returns true if a value crosses the threshold. Only numbers can.
+    pub fn crossed_by(&self, value: &FileValue) -> bool {
+        value.as_f64().is_some_and(|n| self.compare(n))
+    }
+
+    fn compare(&self, n: f64) -> bool {
+        match self.operator {
+            ComparisonOperator::Eq => n == self.value,
+            ComparisonOperator::NotEq => n != self.value,
+            ComparisonOperator::Lt => n < self.value,
+            ComparisonOperator::LtEq => n <= self.value,
+            ComparisonOperator::Gt => n > self.value,
+            ComparisonOperator::GtEq => n >= self.value,
+        }
+    }
+}
+
+/// Summarises a list of files as one row with `count` and `bytes`.
+pub fn summarise(results: &[FileResult]) -> GroupRow {
+    let bytes: u64 = results.iter().map(|file| file.size).sum();
+    GroupRow {
+        columns: vec![
+            ("count".to_string(), FileValue::from_u64(results.len() as u64)),
+            ("bytes".to_string(), FileValue::from_u64(bytes)),
+        ],
+    }
+}
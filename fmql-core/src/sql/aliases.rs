@@ -0,0 +1,150 @@
+//! Short names for attributes and whole conditions, expanded before a query
+//! is parsed.
+//!
+//! Queries typed by hand repeat the same attributes and the same conditions.
+//! [`Aliases`] holds two kinds of shorthand for them: attribute aliases,
+//! such as `mtime` for `modified`, and snippets, such as `@big` for
+//! `size > 100 * 1024 * 1024`. [`Aliases::expand`] rewrites a query's text
+//! with them, so the grammar itself doesn't change: a query that uses none
+//! comes back as it was.
+//!
+//! Expansion works on the query's tokens, so names inside string literals
+//! and paths are left alone. An alias only stands in for a name that isn't
+//! already an attribute, and not for the name a column is given with `AS`.
+//! A snippet is put in parentheses, so `NOT @big` negates all of it, and may
+//! use aliases and other snippets, but not itself.
+//!
+//! # Examples
+//!
+//! ```
+//! use fmql_core::sql::aliases::Aliases;
+//!
+//! let mut aliases = Aliases::default();
+//! aliases.attributes.insert("mtime".to_string(), "modified".to_string());
+//! aliases.snippets.insert("big".to_string(), "size > 100 * 1024 * 1024".to_string());
+//! aliases.snippets.insert("old".to_string(), "mtime < '2024-01-01'".to_string());
+//! assert!(aliases.check().is_ok());
+//!
+//! let sql = aliases.expand("SELECT name, mtime FROM . WHERE @big AND NOT @old").unwrap();
+//! assert_eq!(
+//!     sql,
+//!     "SELECT name, modified FROM . WHERE (size > 100 * 1024 * 1024) \
+//!      AND NOT (modified < '2024-01-01')"
+//! );
+//!
+//! // Names in strings are text, not aliases
+//! let sql = aliases.expand("SELECT * FROM . WHERE name = 'mtime'").unwrap();
+//! assert_eq!(sql, "SELECT * FROM . WHERE name = 'mtime'");
+//!
+//! assert!(aliases.expand("SELECT * FROM . WHERE @huge").is_err());
+//! ```
+
+use std::collections::BTreeMap;
+
+use crate::sql::ast::FileAttribute;
+use crate::sql::lexer::{tokenize, Span, TokenKind};
+use crate::sql::parser::{ParserError, Result};
+
+/// The attribute aliases and snippets queries are expanded with.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Aliases {
+    /// Other names for attributes, such as `mtime` for `modified`.
+    pub attributes: BTreeMap<String, String>,
+    /// Conditions by name, used in a query as `@name`.
+    pub snippets: BTreeMap<String, String>,
+}
+
+impl Aliases {
+    /// Returns true if there is nothing to expand.
+    pub fn is_empty(&self) -> bool {
+        self.attributes.is_empty() && self.snippets.is_empty()
+    }
+
+    /// Checks that the names are plain words, and that every alias names
+    /// an attribute.
+    pub fn check(&self) -> std::result::Result<(), String> {
+        let word = |name: &str| {
+            !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        };
+        for (alias, attribute) in &self.attributes {
+            if !word(alias) {
+                return Err(format!("the alias '{}' isn't a plain word", alias));
+            }
+            if FileAttribute::from_name(attribute).is_none() {
+                return Err(format!(
+                    "the alias '{}' is for '{}', which isn't an attribute",
+                    alias, attribute
+                ));
+            }
+        }
+        match self.snippets.keys().find(|name| !word(name)) {
+            Some(name) => Err(format!("the snippet '@{}' isn't named with a plain word", name)),
+            None => Ok(()),
+        }
+    }
+
+    /// Returns the query with its aliases and snippets expanded.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ParserError::Snippet` for a snippet that isn't defined or
+    /// uses itself, and `ParserError::Sql` for text that can't be
+    /// tokenized.
+    pub fn expand(&self, sql: &str) -> Result<String> {
+        if self.is_empty() {
+            return Ok(sql.to_string());
+        }
+        self.expand_within(sql, &mut Vec::new(), None)
+    }
+
+    /// Expands `sql`, which is the text of the snippets in `using` when
+    /// there are any; errors are reported at `at`, where the outermost of
+    /// them is used.
+    fn expand_within(
+        &self,
+        sql: &str,
+        using: &mut Vec<String>,
+        at: Option<Span>,
+    ) -> Result<String> {
+        let tokens = tokenize(sql)?;
+        let mut expanded = String::with_capacity(sql.len());
+        let mut copied = 0;
+        for (index, token) in tokens.iter().enumerate() {
+            if token.kind != TokenKind::Identifier {
+                continue;
+            }
+            let replacement = if let Some(name) = token.text.strip_prefix('@') {
+                let span = at.unwrap_or(token.span);
+                let error = |message: String| ParserError::Snippet { message, span };
+                let (name, snippet) = lookup(&self.snippets, name)
+                    .ok_or_else(|| error(format!("there is no snippet named @{}", name)))?;
+                if using.iter().any(|used| used == name) {
+                    return Err(error(format!("@{} is used inside itself", name)));
+                }
+                using.push(name.to_string());
+                let snippet = self.expand_within(snippet, using, Some(span))?;
+                using.pop();
+                format!("({})", snippet)
+            } else {
+                let named = index > 0 && tokens[index - 1].is_keyword("AS");
+                match lookup(&self.attributes, &token.text) {
+                    Some((_, attribute)) if !named => attribute.clone(),
+                    _ => continue,
+                }
+            };
+            expanded.push_str(&sql[copied..token.span.start.offset_in(sql)]);
+            expanded.push_str(&replacement);
+            copied = token.span.end.offset_in(sql);
+        }
+        expanded.push_str(&sql[copied..]);
+        Ok(expanded)
+    }
+}
+
+/// Finds a name in `names`, ignoring case as attribute names do.
+fn lookup<'a>(names: &'a BTreeMap<String, String>, name: &str) -> Option<(&'a str, &'a String)> {
+    names
+        .iter()
+        .find(|(key, _)| key.eq_ignore_ascii_case(name))
+        .map(|(key, value)| (key.as_str(), value))
+}
@@ -0,0 +1,198 @@
+//! Android devices as directories to query, for
+//! `FROM 'adb:///sdcard/DCIM'`.
+//!
+//! A phone plugged in over MTP and mounted by gvfs or `jmtpfs` is an
+//! ordinary directory, which any statement can read and organize. A device
+//! reached over `adb` isn't mounted anywhere, so [`list`] asks it what is
+//! under a directory, through `find` and `stat` in `adb shell`, and sets
+//! the listing out in a private temporary directory as the
+//! [`backups`](crate::sql::backups) module does a snapshot's: each file a
+//! sparse stand-in with the size, mode and modification time the device
+//! gave it, and nothing copied off the device. `adb://SERIAL/path` names
+//! one of several devices by its serial number, as `adb devices` lists
+//! them, and `adb:///path` the only one connected.
+//!
+//! The query runs on that directory, and its results are given their paths
+//! on the device, such as `adb:///sdcard/DCIM/Camera/VID_0001.mp4`. Symlinks
+//! are listed as what they lead to. Directories the device won't list, as
+//! much of `/data` is without root, are left out.
+//!
+//! # Examples
+//!
+//! ```
+//! use std::path::Path;
+//!
+//! use fmql_core::sql::android::{stat_entries, Device, DeviceSource};
+//!
+//! let source = DeviceSource::parse(Path::new("adb:///sdcard/DCIM")).unwrap();
+//! assert_eq!(source.serial, None);
+//!
+//! let stat = b"41f9 3452 1709285400 /sdcard/DCIM\n81b0 734003200 1709285400 /sdcard/DCIM/VID 1.mp4\n";
+//! let device = Device::new(&source, stat_entries(&stat[..])).unwrap();
+//! let video = device.root().join("VID 1.mp4");
+//! assert_eq!(std::fs::metadata(&video).unwrap().len(), 734003200);
+//! assert_eq!(device.path_on_device(&video), Path::new("adb:///sdcard/DCIM/VID 1.mp4"));
+//! ```
+
+use std::io::{self, BufRead};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::{Duration, SystemTime};
+
+use crate::sql::backups::{Entry, EntryKind, Listing};
+
+/// The kinds of file in the `st_mode` that `stat -c %f` writes.
+const S_IFMT: u32 = 0o170000;
+const S_IFDIR: u32 = 0o040000;
+const S_IFREG: u32 = 0o100000;
+
+/// A directory on an Android device.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceSource {
+    /// The device's serial number, or None for the only device connected.
+    pub serial: Option<String>,
+    /// The directory, an absolute path on the device.
+    pub path: String,
+}
+
+impl DeviceSource {
+    /// Reads a FROM path naming a directory on a device,
+    /// `adb://SERIAL/path` or `adb:///path`, or returns None for any other
+    /// path.
+    pub fn parse(from: &Path) -> Option<DeviceSource> {
+        let rest = from.to_str()?.strip_prefix("adb://")?;
+        let (serial, path) = match rest.find('/') {
+            Some(slash) => rest.split_at(slash),
+            None => (rest, "/"),
+        };
+        let path = match path.trim_end_matches('/') {
+            "" => "/",
+            path => path,
+        };
+        let serial = (!serial.is_empty()).then(|| serial.to_string());
+        Some(DeviceSource { serial, path: path.to_string() })
+    }
+
+    /// How the device's files are named in results: `adb://` and the
+    /// serial number, which their paths on the device follow.
+    pub fn prefix(&self) -> String {
+        format!("adb://{}", self.serial.as_deref().unwrap_or(""))
+    }
+}
+
+/// A directory of a device listed into a temporary directory, which is
+/// deleted when this is dropped.
+#[derive(Debug)]
+pub struct Device {
+    listing: Listing,
+    root: PathBuf,
+}
+
+impl Device {
+    /// Sets out the entries a device listed under the directory `source`
+    /// names.
+    pub fn new(
+        source: &DeviceSource,
+        entries: impl IntoIterator<Item = io::Result<Entry>>,
+    ) -> io::Result<Device> {
+        let mut listing = Listing::new(source.prefix())?;
+        listing.add_root(entries)?;
+        let root = listing.root().join(source.path.trim_start_matches('/'));
+        std::fs::create_dir_all(&root)?;
+        Ok(Device { listing, root })
+    }
+
+    /// The directory the device's directory is listed in.
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Returns what a path in the temporary directory is called in
+    /// results: its path on the device after the device's name.
+    pub fn path_on_device(&self, path: &Path) -> PathBuf {
+        self.listing.path_in_backup(path)
+    }
+
+    /// Returns `text` with the temporary directory's path at its start
+    /// replaced as [`path_on_device`](Device::path_on_device) does, or
+    /// None if it doesn't start with it.
+    pub fn rewrite(&self, text: &str) -> Option<String> {
+        self.listing.rewrite(text)
+    }
+}
+
+/// Lists a directory of a device, and everything under it, into a new
+/// temporary directory.
+///
+/// # Errors
+///
+/// Fails if `adb` isn't installed, the device isn't connected, or it
+/// lists nothing under the directory.
+pub fn list(source: &DeviceSource) -> io::Result<Device> {
+    let mut adb = Command::new("adb");
+    if let Some(serial) = &source.serial {
+        adb.args(["-s", serial]);
+    }
+    // The device's shell runs this, so the directory is quoted for it
+    let script = format!(
+        "find -H {} -exec stat -L -c '%f %s %Y %n' {{}} +",
+        quote(&source.path)
+    );
+    let output = adb
+        .args(["shell", &script])
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|err| match err.kind() {
+            io::ErrorKind::NotFound => {
+                io::Error::new(io::ErrorKind::NotFound, "adb isn't installed")
+            }
+            _ => err,
+        })?;
+    // `find` fails for every directory it can't read, which on a phone is
+    // usual, so only a listing with nothing in it is taken as failing
+    if output.stdout.is_empty() {
+        let message = String::from_utf8_lossy(&output.stderr);
+        return Err(io::Error::other(format!(
+            "adb found nothing at {}: {}",
+            source.path,
+            message.trim()
+        )));
+    }
+    Device::new(source, stat_entries(&output.stdout[..]))
+}
+
+/// Reads the entries of what `stat -c '%f %s %Y %n'` writes on a device:
+/// the mode in hex, the size, the modification time in seconds since the
+/// epoch and the path, on a line for each file.
+pub fn stat_entries(listing: impl BufRead) -> impl Iterator<Item = io::Result<Entry>> {
+    listing.split(b'\n').filter_map(|line| {
+        let line = match line {
+            Ok(line) => line,
+            Err(err) => return Some(Err(err)),
+        };
+        let line = String::from_utf8_lossy(&line);
+        let mut fields = line.trim_end_matches('\r').splitn(4, ' ');
+        let mode = u32::from_str_radix(fields.next()?, 16).ok()?;
+        let size = fields.next()?.parse().ok()?;
+        let modified = fields.next()?.parse().ok()?;
+        let path = fields.next().filter(|path| path.starts_with('/'))?;
+        let kind = match mode & S_IFMT {
+            S_IFDIR => EntryKind::Dir,
+            S_IFREG => EntryKind::File,
+            _ => EntryKind::Other,
+        };
+        Some(Ok(Entry {
+            path: PathBuf::from(path),
+            kind,
+            size,
+            mode: mode & 0o7777,
+            modified: Some(SystemTime::UNIX_EPOCH + Duration::from_secs(modified)),
+            link: None,
+        }))
+    })
+}
+
+/// Quotes a path for the device's shell.
+fn quote(path: &str) -> String {
+    format!("'{}'", path.replace('\'', r"'\''"))
+}
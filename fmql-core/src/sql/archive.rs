@@ -0,0 +1,256 @@
+//! Packing files into archives for `ARCHIVE`.
+//!
+//! An `ARCHIVE` statement sends each file it matches to the archive its
+//! `INTO` template names for that file, in which `{YYYY}`, `{MM}` and
+//! `{DD}` stand for the year, month and day the file was last modified:
+//! `reports-{YYYY}.tar.zst` gathers each year's reports into one archive.
+//! [`archive_path`] fills in the template, and [`write_archive`] packs one
+//! archive's files, as a tarball (plain, gzipped or zstd-compressed) or a
+//! zip file, by the archive's extension.
+//!
+//! The originals may be deleted once they're archived, so an archive is
+//! only trusted once it has been read back. It is written under a
+//! temporary name beside its final one and flushed to disk, and every
+//! file in it is checked against its original's BLAKE3 checksum before it
+//! takes its name. An archive that is already there is never added to or
+//! replaced, and a file that changes while it is packed fails its archive.
+//!
+//! # Examples
+//!
+//! ```
+//! use chrono::{TimeZone, Utc};
+//! use fmql_core::sql::archive::{archive_path, write_archive, Member};
+//! use fmql_core::sql::timezone::Tz;
+//!
+//! let dir = tempfile::tempdir().unwrap();
+//! std::fs::write(dir.path().join("q1.csv"), "revenue,10").unwrap();
+//! let template = dir.path().join("reports-{YYYY}-{MM}.tar.gz");
+//! let modified = Utc.with_ymd_and_hms(2024, 3, 31, 12, 0, 0).unwrap();
+//! let archive = archive_path(&template, modified, Tz::UTC);
+//! assert!(archive.ends_with("reports-2024-03.tar.gz"));
+//!
+//! let members = [Member {
+//!     path: dir.path().join("q1.csv"),
+//!     name: "q1.csv".into(),
+//! }];
+//! write_archive(&archive, &members, None).unwrap();
+//! assert!(archive.exists());
+//!
+//! // An archive is never added to
+//! assert!(write_archive(&archive, &members, None).is_err());
+//! ```
+
+use std::collections::BTreeSet;
+use std::fs::{self, File, Metadata};
+use std::io::{self, Read, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Datelike, Local, Timelike, Utc};
+use flate2::write::GzEncoder;
+
+use crate::sql::compressed::Hashed;
+use crate::sql::extract::{entry_checksums, ArchiveFormat};
+use crate::sql::throttle::{throttled, Throttle};
+use crate::sql::timezone::Tz;
+
+/// A file to pack, and its path in the archive.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Member {
+    /// The file to pack.
+    pub path: PathBuf,
+    /// Its path in the archive, which must be relative.
+    pub name: PathBuf,
+}
+
+/// Fills in the `{YYYY}`, `{MM}` and `{DD}` of an ARCHIVE template with
+/// the date a file was `modified`, as it was in `zone`.
+pub fn archive_path(template: &Path, modified: DateTime<Utc>, zone: Tz) -> PathBuf {
+    let local = modified.with_timezone(&zone);
+    let path = template
+        .to_string_lossy()
+        .replace("{YYYY}", &format!("{:04}", local.year()))
+        .replace("{MM}", &format!("{:02}", local.month()))
+        .replace("{DD}", &format!("{:02}", local.day()));
+    PathBuf::from(path)
+}
+
+/// Fails if `archive` couldn't be written with `members`: if its name
+/// isn't that of an archive format, it exists already, or two members
+/// would have the same path in it. A dry run makes only these checks.
+pub fn check_archive(archive: &Path, members: &[Member]) -> io::Result<()> {
+    if ArchiveFormat::of(archive).is_none() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} is not a .tar, .tar.gz, .tar.zst or .zip file", archive.display()),
+        ));
+    }
+    if archive.symlink_metadata().is_ok() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("{} already exists", archive.display()),
+        ));
+    }
+    let mut names = BTreeSet::new();
+    for member in members {
+        if member.name.is_absolute() || !names.insert(&member.name) {
+            let message = format!(
+                "{} can't be {} in {}",
+                member.path.display(),
+                member.name.display(),
+                archive.display()
+            );
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, message));
+        }
+    }
+    Ok(())
+}
+
+/// Packs `members` into a new archive at `archive`, making its directory
+/// as needed, and returns the archive's size. The archive is read back and
+/// checked against the originals before it takes its name; see the
+/// [module documentation](self). The originals are read as fast as
+/// `throttle` allows.
+pub fn write_archive(
+    archive: &Path,
+    members: &[Member],
+    throttle: Option<&Throttle>,
+) -> io::Result<u64> {
+    check_archive(archive, members)?;
+    let format = ArchiveFormat::of(archive).expect("checked by check_archive");
+    let dir = match archive.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    fs::create_dir_all(dir)?;
+    let mut temp = tempfile::NamedTempFile::new_in(dir)?;
+    let packed = match format {
+        ArchiveFormat::Zip => write_zip(temp.as_file_mut(), members, throttle)?,
+        ArchiveFormat::Tar => write_tar(temp.as_file_mut(), members, throttle)?,
+        ArchiveFormat::TarGz => {
+            let mut encoder = GzEncoder::new(temp.as_file_mut(), flate2::Compression::default());
+            let packed = write_tar(&mut encoder, members, throttle)?;
+            encoder.finish()?;
+            packed
+        }
+        ArchiveFormat::TarZst => {
+            let mut encoder = zstd::stream::Encoder::new(temp.as_file_mut(), 0)?;
+            let packed = write_tar(&mut encoder, members, throttle)?;
+            encoder.finish()?;
+            packed
+        }
+    };
+    temp.as_file().sync_all()?;
+
+    let written = entry_checksums(temp.path(), format)?;
+    for (member, packed) in members.iter().zip(&packed) {
+        let now = fs::metadata(&member.path)?;
+        if (now.len(), now.modified()?) != (packed.metadata.len(), packed.metadata.modified()?) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{} changed while it was being archived", member.path.display()),
+            ));
+        }
+        if written.get(&member.name) != Some(&packed.checksum) {
+            let message = format!(
+                "the copy of {} in {} doesn't match it",
+                member.path.display(),
+                archive.display()
+            );
+            return Err(io::Error::new(io::ErrorKind::InvalidData, message));
+        }
+    }
+
+    // No more readable than the most private of its files
+    let mode = packed
+        .iter()
+        .fold(0o666, |mode, packed| mode & packed.metadata.permissions().mode());
+    temp.as_file().set_permissions(fs::Permissions::from_mode(mode | 0o600))?;
+    let size = temp.as_file().metadata()?.len();
+    temp.persist_noclobber(archive).map_err(|err| err.error)?;
+    Ok(size)
+}
+
+/// What was packed of a member: its metadata when it was opened, and the
+/// checksum of what was read of it.
+struct Packed {
+    metadata: Metadata,
+    checksum: blake3::Hash,
+}
+
+/// Writes a tarball of `members` to `out`.
+fn write_tar(
+    out: impl Write,
+    members: &[Member],
+    throttle: Option<&Throttle>,
+) -> io::Result<Vec<Packed>> {
+    let mut tarball = tar::Builder::new(out);
+    let mut packed = Vec::with_capacity(members.len());
+    for member in members {
+        let file = File::open(&member.path)?;
+        let metadata = file.metadata()?;
+        let mut header = tar::Header::new_gnu();
+        header.set_metadata(&metadata);
+        // As much as the header says, even if the file grows meanwhile
+        let mut contents = Hashed::new(throttled(file.take(metadata.len()), throttle));
+        tarball.append_data(&mut header, &member.name, &mut contents)?;
+        packed.push(Packed {
+            metadata,
+            checksum: contents.hasher.finalize(),
+        });
+    }
+    tarball.finish()?;
+    Ok(packed)
+}
+
+/// Writes a zip file of `members` to `out`, deflating each one.
+fn write_zip(
+    out: &mut File,
+    members: &[Member],
+    throttle: Option<&Throttle>,
+) -> io::Result<Vec<Packed>> {
+    let mut zip = zip::ZipWriter::new(out);
+    let mut packed = Vec::with_capacity(members.len());
+    for member in members {
+        let name = member.name.to_str().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} can't be named in a zip file", member.name.display()),
+            )
+        })?;
+        let file = File::open(&member.path)?;
+        let metadata = file.metadata()?;
+        let options = zip::write::SimpleFileOptions::default()
+            .compression_method(zip::CompressionMethod::Deflated)
+            .unix_permissions(metadata.permissions().mode() & 0o777)
+            .last_modified_time(zip_time(&metadata))
+            .large_file(metadata.len() >= u64::from(u32::MAX));
+        zip.start_file(name, options).map_err(io::Error::other)?;
+        let mut contents = Hashed::new(throttled(file.take(metadata.len()), throttle));
+        io::copy(&mut contents, &mut zip)?;
+        packed.push(Packed {
+            metadata,
+            checksum: contents.hasher.finalize(),
+        });
+    }
+    zip.finish().map_err(io::Error::other)?;
+    Ok(packed)
+}
+
+/// Returns a file's modification time as a zip file records it: in local
+/// time, to the even second, and no earlier than 1980.
+fn zip_time(metadata: &Metadata) -> zip::DateTime {
+    let Ok(modified) = metadata.modified() else {
+        return zip::DateTime::default();
+    };
+    let local = DateTime::<Local>::from(modified);
+    zip::DateTime::from_date_and_time(
+        u16::try_from(local.year()).unwrap_or_default(),
+        local.month() as u8,
+        local.day() as u8,
+        local.hour() as u8,
+        local.minute() as u8,
+        local.second() as u8,
+    )
+    .unwrap_or_default()
+}
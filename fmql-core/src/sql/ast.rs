@@ -0,0 +1,1725 @@
+//! Abstract Syntax Tree (AST) structures for file management operations.
+//!
+//! This module defines the custom AST structures that represent parsed SQL-like
+//! file management operations, such as querying files or updating file attributes.
+//! These structures form the intermediary representation between the SQL parser
+//! and the executor that performs the actual file operations.
+//!
+//! # Structure
+//!
+//! The AST is organized hierarchically:
+//! - `FileQuery` is the top-level structure representing complete queries
+//! - `FileCondition` represents filtering conditions (WHERE clauses)
+//! - `FileAttribute` represents file properties that can be queried or updated
+//! - `FileValue` represents literal values used in conditions
+//!
+//! # Examples
+//!
+//! Building a query programmatically:
+//!
+//! ```no_run
+//! use fmql_core::sql::ast::{FileQuery, FileAttribute, FileCondition, ComparisonOperator, FileValue};
+//! use std::path::PathBuf;
+//!
+//! // Equivalent to: SELECT * FROM ~/Documents WHERE size > 1000000
+//! let query = FileQuery::Select {
+//!     path: PathBuf::from("~/Documents"),
+//!     recursive: false,
+//!     include_self: false,
+//!     entry_type: None,
+//!     join: None,
+//!     attributes: vec![FileAttribute::All],
+//!     computed: vec![],
+//!     aggregates: vec![],
+//!     group_by: vec![],
+//!     rollup: false,
+//!     order_by: vec![],
+//!     limit: None,
+//!     limit_size: None,
+//!     within: None,
+//!     sample: None,
+//!     sample_rows: None,
+//!     condition: Some(FileCondition::Compare {
+//!         attribute: FileAttribute::Size,
+//!         operator: ComparisonOperator::Gt,
+//!         value: FileValue::Number(1000000.0),
+//!     }),
+//! };
+//!
+//! // Now use the query with an executor...
+//! ```
+
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use chrono::{DateTime, TimeDelta, Utc};
+use serde::{Serialize, Deserialize};
+
+use crate::sql::lexer::format_interval;
+
+/// The path that stands for `FROM STDIN`: the query reads its candidate
+/// files from a list piped in rather than from a directory.
+pub const STDIN_PATH: &str = "-";
+
+/// The main query structure representing a complete file management operation.
+///
+/// This enum represents the main types of queries supported:
+/// - `Select`: For retrieving files matching certain criteria
+/// - `Update`: For modifying files matching certain criteria
+/// - `Copy`: For copying files matching certain criteria elsewhere
+/// - `Compress`: For compressing files matching certain criteria in place
+/// - `Extract`: For unpacking archives matching certain criteria
+/// - `Archive`: For packing files matching certain criteria into archives
+/// - `Restore`: For putting trashed files matching certain criteria back
+/// - `ShowFilesystem`: For finding out what a file system supports
+/// - `Explain`: For seeing how another query would run
+///
+/// Each query type contains information about the target path, conditions,
+/// and either attributes to retrieve or updates to apply.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum FileQuery {
+    /// A query to select files matching specific criteria.
+    ///
+    /// # Examples
+    ///
+    /// This represents a query like:
+    /// ```sql
+    /// SELECT name, size FROM ~/Documents WHERE extension = 'pdf'
+    /// ```
+    ///
+    /// Or with recursion:
+    /// ```sql
+    /// WITH RECURSIVE SELECT * FROM . WHERE size > 1000000
+    /// ```
+    ///
+    /// The FROM directory itself is only listed with `INCLUDE SELF`:
+    /// ```sql
+    /// SELECT * FROM ~/Documents INCLUDE SELF
+    /// ```
+    ///
+    /// Grouped, one row per owner:
+    /// ```sql
+    /// WITH RECURSIVE SELECT owner, SUM(size), COUNT(*) FROM /srv GROUP BY owner
+    /// ```
+    ///
+    /// Sorted, with numbers in names compared by value:
+    /// ```sql
+    /// SELECT * FROM ~/Music ORDER BY depth, path NATURAL
+    /// ```
+    ///
+    /// Stopping at the first match, and within a time budget:
+    /// ```sql
+    /// SELECT * FROM /var/log WHERE name = 'panic.log' LIMIT 1 WITHIN '10s'
+    /// ```
+    ///
+    /// Or only one kind of entry:
+    /// ```sql
+    /// SELECT DIRS FROM ~/projects
+    /// SELECT * FROM ~/projects TYPE symlink
+    /// ```
+    ///
+    /// With a column from an external table:
+    /// ```sql
+    /// SELECT name, inventory.team FROM /srv JOIN 'inventory.csv' ON name = inventory.filename
+    /// ```
+    Select {
+        /// The directory path to search in.
+        path: PathBuf,
+        /// Whether to search recursively through subdirectories.
+        recursive: bool,
+        /// Whether the FROM directory itself is listed (`INCLUDE SELF`).
+        include_self: bool,
+        /// The only kind of entry to list (None means every kind).
+        entry_type: Option<EntryType>,
+        /// An external table to look up a row in for each file.
+        join: Option<Box<Join>>,
+        /// The file attributes to return (empty means all).
+        attributes: Vec<FileAttribute>,
+        /// Expressions to compute for each file, such as `size / 1024 AS kib`.
+        computed: Vec<ComputedColumn>,
+        /// Aggregates to compute for each group, such as `SUM(size)`.
+        aggregates: Vec<AggregateColumn>,
+        /// The conditions to filter files by (None means all files).
+        condition: Option<FileCondition>,
+        /// The expressions files are grouped by (`GROUP BY owner`), each
+        /// named by its source text.
+        group_by: Vec<ComputedColumn>,
+        /// Roll the aggregates up the directory tree (`ROLLUP BY path`):
+        /// one set for each directory, covering everything that matched
+        /// below it.
+        rollup: bool,
+        /// How to sort the results (empty means the order they were found).
+        order_by: Vec<OrderBy>,
+        /// Stop after this many matches (`LIMIT n`).
+        limit: Option<usize>,
+        /// Stop once the sizes of the matches, in order, add up to this
+        /// many bytes (`LIMIT 10GB BY size`). The match that gets there is
+        /// kept, so that "the oldest files until 50GB is freed" frees it.
+        limit_size: Option<u64>,
+        /// Give up if the query runs for longer than this (`WITHIN '10s'`).
+        within: Option<Duration>,
+        /// Look at only this fraction of the entries (`SAMPLE 1%`), and
+        /// estimate the aggregates of the rest; see
+        /// [`sample`](crate::sql::sample).
+        sample: Option<f64>,
+        /// Return a random few of the matches (`SAMPLE 100 ROWS`), before
+        /// they are sorted and limited.
+        sample_rows: Option<RowSample>,
+    },
+    
+    /// A query to update file attributes for files matching specific criteria.
+    ///
+    /// # Examples
+    ///
+    /// This represents a query like:
+    /// ```sql
+    /// UPDATE ~/scripts SET permissions = '755' WHERE extension = 'sh'
+    /// ```
+    Update {
+        /// The directory path containing files to update.
+        path: PathBuf,
+        /// Whether the directory itself is updated too (`INCLUDE SELF`).
+        include_self: bool,
+        /// The only kind of entry to update (None means every kind).
+        entry_type: Option<EntryType>,
+        /// The attributes to update and their new values.
+        updates: Vec<FileAttributeUpdate>,
+        /// The conditions to filter files by (None means all files).
+        condition: Option<FileCondition>,
+        /// What to do when a rename target already exists.
+        on_conflict: ConflictPolicy,
+    },
+
+    /// A query to copy the files matching specific criteria into another
+    /// directory, keeping their paths relative to the FROM directory.
+    ///
+    /// # Examples
+    ///
+    /// This represents a query like:
+    /// ```sql
+    /// WITH RECURSIVE COPY ~/Videos TO /mnt/archive WHERE size > 1000000000 USING REFLINK
+    /// ```
+    ///
+    /// Or, for a backup that keeps everything and is checked once it is on
+    /// disk:
+    /// ```sql
+    /// WITH RECURSIVE COPY ~/Documents TO /mnt/backup PRESERVE ALL SYNC VERIFY CHECKSUM
+    /// ```
+    Copy {
+        /// The directory path containing files to copy.
+        path: PathBuf,
+        /// Whether to copy files in subdirectories too.
+        recursive: bool,
+        /// The directory the copies are made in.
+        destination: PathBuf,
+        /// The conditions to filter files by (None means all files).
+        condition: Option<FileCondition>,
+        /// How the copies are made (`USING HARDLINK`).
+        method: CopyMethod,
+        /// What the copies keep of the originals (`PRESERVE (times, owner)`).
+        preserve: Preserve,
+        /// Whether each copy is flushed to disk before it counts as made
+        /// (`SYNC`).
+        sync: bool,
+        /// Whether each copy is read back and checked against its original
+        /// (`VERIFY CHECKSUM`).
+        verify: bool,
+        /// What to do when a copy's target already exists.
+        on_conflict: ConflictPolicy,
+    },
+
+    /// A query to compress the files matching specific criteria, each into
+    /// a file beside it with the format's extension added.
+    ///
+    /// # Examples
+    ///
+    /// This represents a query like:
+    /// ```sql
+    /// COMPRESS FROM ~/logs WHERE extension = 'log' USING ZSTD LEVEL 9 DELETE ORIGINAL
+    /// ```
+    Compress {
+        /// The directory path containing files to compress.
+        path: PathBuf,
+        /// Whether to compress files in subdirectories too.
+        recursive: bool,
+        /// The conditions to filter files by (None means all files).
+        condition: Option<FileCondition>,
+        /// The format to compress to (`USING ZSTD`).
+        format: CompressFormat,
+        /// The compression level (`LEVEL 9`; None means the format's
+        /// default).
+        level: Option<u32>,
+        /// Whether each original is removed once its compressed copy has
+        /// been checked (`DELETE ORIGINAL`).
+        delete_original: bool,
+    },
+
+    /// A query to unpack the archives matching specific criteria, each into
+    /// a directory named by a template.
+    ///
+    /// # Examples
+    ///
+    /// This represents a query like:
+    /// ```sql
+    /// EXTRACT FROM ~/downloads WHERE name LIKE '%.zip' TO ~/unpacked/{stem}/ ON CONFLICT SKIP
+    /// ```
+    Extract {
+        /// The directory path containing archives to unpack.
+        path: PathBuf,
+        /// Whether to look for archives in subdirectories too.
+        recursive: bool,
+        /// The conditions to filter files by (None means all files).
+        condition: Option<FileCondition>,
+        /// The directory each archive is unpacked into, in which `{stem}`
+        /// stands for the archive's name without its extensions and
+        /// `{name}` for its whole name.
+        destination: PathBuf,
+        /// What to do when an unpacked file's path is already taken.
+        on_conflict: ConflictPolicy,
+    },
+
+    /// A query to pack the files matching specific criteria into archives
+    /// named by a template, so that files of one month or year share one.
+    ///
+    /// # Examples
+    ///
+    /// This represents a query like:
+    /// ```sql
+    /// ARCHIVE FROM ~/reports WHERE age > '1 year' INTO ~/old/{YYYY}.tar.zst DELETE ORIGINAL
+    /// ```
+    Archive {
+        /// The directory path containing files to archive.
+        path: PathBuf,
+        /// Whether to archive files in subdirectories too.
+        recursive: bool,
+        /// The conditions to filter files by (None means all files).
+        condition: Option<FileCondition>,
+        /// The archive each file goes into, in which `{YYYY}`, `{MM}` and
+        /// `{DD}` stand for the year, month and day the file was modified.
+        /// Its extension says the format: `.tar`, `.tar.gz`, `.tar.zst` or
+        /// `.zip`.
+        destination: PathBuf,
+        /// Whether each original is removed once its archive has been
+        /// checked (`DELETE ORIGINAL`).
+        delete_original: bool,
+    },
+
+    /// A query to move trashed files matching specific criteria back to
+    /// where they were before they were trashed.
+    ///
+    /// # Examples
+    ///
+    /// This represents a query like:
+    /// ```sql
+    /// RESTORE FROM TRASH WHERE original_path LIKE '/home/me/thesis/%' ON CONFLICT RENAME
+    /// ```
+    Restore {
+        /// The trash's `files` directory, or a directory in it.
+        path: PathBuf,
+        /// Whether files in trashed directories are restored one by one
+        /// too, rather than only whole items.
+        recursive: bool,
+        /// The conditions to filter files by (None means all files).
+        condition: Option<FileCondition>,
+        /// What to do when a file's original path is taken again.
+        on_conflict: ConflictPolicy,
+    },
+
+    /// A query for what the file system holding a path supports, with one
+    /// row per path it names.
+    ///
+    /// # Examples
+    ///
+    /// This represents a query like:
+    /// ```sql
+    /// SHOW FILESYSTEM FROM /mnt/usb
+    /// ```
+    ShowFilesystem {
+        /// The path whose file system is probed (`.` when there's no FROM).
+        path: PathBuf,
+    },
+
+    /// A query for how another query would run, with one row per step,
+    /// which doesn't run it.
+    ///
+    /// # Examples
+    ///
+    /// This represents a query like:
+    /// ```sql
+    /// EXPLAIN SELECT * FROM . WHERE size > 10 AND size > 100
+    /// ```
+    Explain {
+        /// The query explained.
+        query: Box<FileQuery>,
+    },
+}
+
+impl FileQuery {
+    /// Returns true if the query says `FROM STDIN`, so its candidates are a
+    /// list of paths rather than the contents of a directory.
+    pub fn reads_stdin(&self) -> bool {
+        match self {
+            FileQuery::Select { path, .. }
+            | FileQuery::Update { path, .. }
+            | FileQuery::Copy { path, .. }
+            | FileQuery::Compress { path, .. }
+            | FileQuery::Extract { path, .. }
+            | FileQuery::Archive { path, .. }
+            | FileQuery::Restore { path, .. } => path.as_os_str() == STDIN_PATH,
+            FileQuery::ShowFilesystem { .. } | FileQuery::Explain { .. } => false,
+        }
+    }
+
+    /// Returns true if the query says `FROM 'paths.csv' AS TABLE`, so its
+    /// candidates are the paths in a column of a table.
+    pub fn reads_table(&self) -> bool {
+        matches!(self, FileQuery::Select { join: Some(join), .. } if join.lists_files)
+    }
+
+    /// Returns true if the query's candidates are a list of paths, from
+    /// STDIN or a table, rather than the contents of a directory.
+    pub fn lists_paths(&self) -> bool {
+        self.reads_stdin() || self.reads_table()
+    }
+
+    /// Returns true for a query that returns rows rather than files: a
+    /// SELECT with a `GROUP BY` clause or aggregates, `SHOW FILESYSTEM` or
+    /// `EXPLAIN`.
+    pub fn is_grouped(&self) -> bool {
+        match self {
+            FileQuery::Select {
+                aggregates,
+                group_by,
+                ..
+            } => !aggregates.is_empty() || !group_by.is_empty(),
+            FileQuery::Update { .. }
+            | FileQuery::Copy { .. }
+            | FileQuery::Compress { .. }
+            | FileQuery::Extract { .. }
+            | FileQuery::Archive { .. }
+            | FileQuery::Restore { .. } => false,
+            FileQuery::ShowFilesystem { .. } | FileQuery::Explain { .. } => true,
+        }
+    }
+
+    /// Returns true for a SELECT that rolls its aggregates up the directory
+    /// tree with `ROLLUP BY path`.
+    pub fn is_rollup(&self) -> bool {
+        matches!(self, FileQuery::Select { rollup: true, .. })
+    }
+
+    /// Returns the path the query reads: the one after FROM, or after
+    /// UPDATE, COPY, COMPRESS, EXTRACT, ARCHIVE or RESTORE, or `None` for
+    /// a query that reads a list of paths `FROM STDIN` or a table.
+    pub fn root(&self) -> Option<&Path> {
+        match self {
+            _ if self.lists_paths() => None,
+            FileQuery::Select { path, .. }
+            | FileQuery::Update { path, .. }
+            | FileQuery::Copy { path, .. }
+            | FileQuery::Compress { path, .. }
+            | FileQuery::Extract { path, .. }
+            | FileQuery::Archive { path, .. }
+            | FileQuery::Restore { path, .. }
+            | FileQuery::ShowFilesystem { path } => Some(path),
+            FileQuery::Explain { query } => query.root(),
+        }
+    }
+}
+
+/// An external table joined to the files, as in
+/// `JOIN 'inventory.csv' ON name = inventory.filename`.
+///
+/// The table is a CSV file with a header row, or a JSON array of objects,
+/// and is read into memory before the scan starts. Each file is matched to
+/// at most one row, so the key column must not repeat.
+///
+/// `FROM 'inventory.csv' AS TABLE` is a join too: the files are the ones
+/// the table's `path` column lists, each joined to its row.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Join {
+    /// The file holding the table.
+    pub source: PathBuf,
+    /// The name columns are qualified with: the `AS` alias, or the file's
+    /// stem.
+    pub alias: String,
+    /// The file's side of the ON condition, such as `name`.
+    pub key: FileExpr,
+    /// The table column the key must equal.
+    pub column: String,
+    /// Keep files that have no row (`LEFT JOIN`), with NULL for the
+    /// table's columns. A plain `JOIN` leaves them out.
+    pub left: bool,
+    /// The table is the query's source (`FROM 'paths.csv' AS TABLE`): the
+    /// files are the paths in its key column, rather than ones found by
+    /// scanning a directory.
+    pub lists_files: bool,
+}
+
+/// Represents a file attribute that can be queried or displayed.
+///
+/// These attributes correspond to file metadata and properties that can be
+/// included in query results or used in filtering conditions.
+///
+/// # Examples
+///
+/// ```no_run
+/// use fmql_core::sql::ast::FileAttribute;
+///
+/// // In a SELECT query:
+/// // SELECT name, size, modified FROM ...
+/// let attributes = vec![
+///     FileAttribute::Name,
+///     FileAttribute::Size,
+///     FileAttribute::Modified
+/// ];
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum FileAttribute {
+    /// All attributes (*).
+    All,
+    /// The file name without path.
+    Name,
+    /// The full file path.
+    Path,
+    /// The file size in bytes.
+    Size,
+    /// The file extension (part after the last dot).
+    Extension,
+    /// The file modification time.
+    Modified,
+    /// The file creation time.
+    Created,
+    /// The file access time.
+    Accessed,
+    /// How long ago the file was modified, as of when the query started.
+    Age,
+    /// The path the query that found the file reads, as its FROM gives it.
+    SourceRoot,
+    /// The id of the query run that found the file.
+    ScanId,
+    /// The file permissions (mode bits without the file type).
+    Permissions,
+    /// The file owner username.
+    Owner,
+    /// The name of the file's group.
+    Group,
+    /// Whether the file is a directory.
+    IsDirectory,
+    /// Whether the file is a symbolic link.
+    IsSymlink,
+    /// Whether the file is executable.
+    IsExecutable,
+    /// Whether anyone may read the file: the other-read bit (`o+r`).
+    IsWorldReadable,
+    /// Whether the file's group may write to it (`g+w`).
+    IsGroupWritable,
+    /// Whether anyone may execute the file, or search the directory
+    /// (`o+x`).
+    IsOtherExecutable,
+    /// Whether the file belongs to the user fmql runs as.
+    IsMine,
+    /// The first few lines of the file, cleaned up for display.
+    Preview,
+    /// The whole file as text, for LIKE, REGEXP and CONTAINS_STR.
+    Content,
+    /// The lines that matched the query's content predicates.
+    Matches,
+    /// The extension implied by the file's magic bytes, if recognised.
+    SignatureExtension,
+    /// Whether the file's magic bytes say it is something its extension
+    /// doesn't.
+    ExtensionMismatch,
+    /// The size of a gzip, zstd or xz file's contents, if recorded.
+    UncompressedSize,
+    /// The space the file takes on disk, its allocated blocks, as `du`
+    /// counts it; `size` is the length `ls -l` shows.
+    DiskUsage,
+    /// Whether the file has holes: fewer bytes allocated on disk than its
+    /// length.
+    IsSparse,
+    /// How many of the file's bytes are in extents shared with other files,
+    /// such as reflinked copies (Linux only).
+    SharedExtents,
+    /// The BSD file flags, such as `uchg,hidden` (macOS and the BSDs only).
+    Flags,
+    /// The Linux file attributes `chattr` sets, such as `immutable,nodump`
+    /// (Linux only).
+    FsFlags,
+    /// The SELinux security context, such as
+    /// `system_u:object_r:httpd_sys_content_t:s0` (Linux only).
+    SecurityContext,
+    /// The capabilities `setcap` gave the file, such as
+    /// `cap_net_bind_service+ep` (Linux only).
+    FileCapabilities,
+    /// Whether the file has capabilities (Linux only).
+    HasFileCapabilities,
+    /// The app that downloaded and quarantined the file, such as `Safari`
+    /// (macOS only).
+    Quarantine,
+    /// The URLs the file was downloaded from, separated by spaces (macOS
+    /// only).
+    WhereFrom,
+    /// How many pages a PDF has.
+    PdfPageCount,
+    /// The title a PDF or Office document records.
+    DocTitle,
+    /// The author a PDF or Office document records.
+    DocAuthor,
+    /// The line endings of a text file: `lf`, `crlf`, `cr` or `mixed`.
+    LineEndings,
+    /// Whether a file starts with a UTF-8 or UTF-16 byte order mark.
+    HasBom,
+    /// The executable format of a binary: `elf`, `pe` or `mach-o`.
+    BinaryFormat,
+    /// The architecture a binary was built for, such as `x86_64`.
+    TargetArch,
+    /// Whether a binary's symbols were stripped.
+    IsStripped,
+    /// Where a trashed file was before it was trashed.
+    OriginalPath,
+    /// When a trashed file was trashed.
+    DeletedAt,
+    /// Whether a path a query lists (`FROM STDIN` or a table) is on disk.
+    Exists,
+    /// Where a listed path that has gone may have moved to: a file of the
+    /// same name elsewhere under the query's root.
+    WasMovedHint,
+    /// Why a listed path's metadata couldn't be read.
+    MetadataError,
+}
+
+impl FileAttribute {
+    /// Looks up an attribute by its query-language name (case-insensitive).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use fmql_core::sql::ast::FileAttribute;
+    ///
+    /// assert_eq!(FileAttribute::from_name("SIZE"), Some(FileAttribute::Size));
+    /// assert_eq!(FileAttribute::from_name("colour"), None);
+    /// ```
+    pub fn from_name(name: &str) -> Option<FileAttribute> {
+        let attribute = match name.to_lowercase().as_str() {
+            "*" => FileAttribute::All,
+            "name" => FileAttribute::Name,
+            "path" => FileAttribute::Path,
+            "size" => FileAttribute::Size,
+            "extension" | "ext" => FileAttribute::Extension,
+            "modified" => FileAttribute::Modified,
+            "created" => FileAttribute::Created,
+            "accessed" => FileAttribute::Accessed,
+            "age" => FileAttribute::Age,
+            "source_root" => FileAttribute::SourceRoot,
+            "scan_id" => FileAttribute::ScanId,
+            "permissions" => FileAttribute::Permissions,
+            "owner" => FileAttribute::Owner,
+            "group" => FileAttribute::Group,
+            "is_directory" => FileAttribute::IsDirectory,
+            "is_symlink" => FileAttribute::IsSymlink,
+            "is_executable" => FileAttribute::IsExecutable,
+            "is_world_readable" => FileAttribute::IsWorldReadable,
+            "is_group_writable" => FileAttribute::IsGroupWritable,
+            "is_other_executable" => FileAttribute::IsOtherExecutable,
+            "is_mine" => FileAttribute::IsMine,
+            "preview" => FileAttribute::Preview,
+            "content" => FileAttribute::Content,
+            "matches" => FileAttribute::Matches,
+            "signature_extension" => FileAttribute::SignatureExtension,
+            "extension_mismatch" => FileAttribute::ExtensionMismatch,
+            "uncompressed_size" => FileAttribute::UncompressedSize,
+            "disk_usage" => FileAttribute::DiskUsage,
+            "is_sparse" => FileAttribute::IsSparse,
+            "shared_extents" => FileAttribute::SharedExtents,
+            "flags" => FileAttribute::Flags,
+            "fs_flags" => FileAttribute::FsFlags,
+            "security_context" => FileAttribute::SecurityContext,
+            "file_capabilities" => FileAttribute::FileCapabilities,
+            "has_file_capabilities" => FileAttribute::HasFileCapabilities,
+            "quarantine" => FileAttribute::Quarantine,
+            "where_from" => FileAttribute::WhereFrom,
+            "pdf_page_count" => FileAttribute::PdfPageCount,
+            "doc_title" => FileAttribute::DocTitle,
+            "doc_author" => FileAttribute::DocAuthor,
+            "line_endings" => FileAttribute::LineEndings,
+            "has_bom" => FileAttribute::HasBom,
+            "binary_format" => FileAttribute::BinaryFormat,
+            "target_arch" => FileAttribute::TargetArch,
+            "is_stripped" => FileAttribute::IsStripped,
+            "original_path" => FileAttribute::OriginalPath,
+            "deleted_at" => FileAttribute::DeletedAt,
+            "exists" => FileAttribute::Exists,
+            "was_moved_hint" => FileAttribute::WasMovedHint,
+            "metadata_error" => FileAttribute::MetadataError,
+            _ => return None,
+        };
+        Some(attribute)
+    }
+
+    /// Returns the query-language name of the attribute.
+    pub fn name(&self) -> &'static str {
+        match self {
+            FileAttribute::All => "*",
+            FileAttribute::Name => "name",
+            FileAttribute::Path => "path",
+            FileAttribute::Size => "size",
+            FileAttribute::Extension => "extension",
+            FileAttribute::Modified => "modified",
+            FileAttribute::Created => "created",
+            FileAttribute::Accessed => "accessed",
+            FileAttribute::Age => "age",
+            FileAttribute::SourceRoot => "source_root",
+            FileAttribute::ScanId => "scan_id",
+            FileAttribute::Permissions => "permissions",
+            FileAttribute::Owner => "owner",
+            FileAttribute::Group => "group",
+            FileAttribute::IsDirectory => "is_directory",
+            FileAttribute::IsSymlink => "is_symlink",
+            FileAttribute::IsExecutable => "is_executable",
+            FileAttribute::IsWorldReadable => "is_world_readable",
+            FileAttribute::IsGroupWritable => "is_group_writable",
+            FileAttribute::IsOtherExecutable => "is_other_executable",
+            FileAttribute::IsMine => "is_mine",
+            FileAttribute::Preview => "preview",
+            FileAttribute::Content => "content",
+            FileAttribute::Matches => "matches",
+            FileAttribute::SignatureExtension => "signature_extension",
+            FileAttribute::ExtensionMismatch => "extension_mismatch",
+            FileAttribute::UncompressedSize => "uncompressed_size",
+            FileAttribute::DiskUsage => "disk_usage",
+            FileAttribute::IsSparse => "is_sparse",
+            FileAttribute::SharedExtents => "shared_extents",
+            FileAttribute::Flags => "flags",
+            FileAttribute::FsFlags => "fs_flags",
+            FileAttribute::SecurityContext => "security_context",
+            FileAttribute::FileCapabilities => "file_capabilities",
+            FileAttribute::HasFileCapabilities => "has_file_capabilities",
+            FileAttribute::Quarantine => "quarantine",
+            FileAttribute::WhereFrom => "where_from",
+            FileAttribute::PdfPageCount => "pdf_page_count",
+            FileAttribute::DocTitle => "doc_title",
+            FileAttribute::DocAuthor => "doc_author",
+            FileAttribute::LineEndings => "line_endings",
+            FileAttribute::HasBom => "has_bom",
+            FileAttribute::BinaryFormat => "binary_format",
+            FileAttribute::TargetArch => "target_arch",
+            FileAttribute::IsStripped => "is_stripped",
+            FileAttribute::OriginalPath => "original_path",
+            FileAttribute::DeletedAt => "deleted_at",
+            FileAttribute::Exists => "exists",
+            FileAttribute::WasMovedHint => "was_moved_hint",
+            FileAttribute::MetadataError => "metadata_error",
+        }
+    }
+}
+
+/// Represents an update operation for a file attribute.
+///
+/// This structure defines which attribute to update and what value
+/// to set it to.
+///
+/// # Examples
+///
+/// ```no_run
+/// use fmql_core::sql::ast::{FileAttributeUpdate, FileAttribute, FileExpr, FileValue};
+///
+/// // In an UPDATE query:
+/// // UPDATE ... SET permissions = '755'
+/// let update = FileAttributeUpdate {
+///     attribute: FileAttribute::Permissions, 
+///     value: FileExpr::Literal(FileValue::String("755".to_string())),
+/// };
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileAttributeUpdate {
+    /// The attribute to update.
+    pub attribute: FileAttribute,
+    /// The new value for the attribute, evaluated for each file.
+    pub value: FileExpr,
+}
+
+/// Represents a value expression that is evaluated for each file.
+///
+/// Expressions appear on the right-hand side of SET clauses, so that the new
+/// value can depend on the file being updated.
+///
+/// # Examples
+///
+/// ```no_run
+/// use fmql_core::sql::ast::{ComparisonOperator, FileAttribute, FileCondition, FileExpr, FileValue};
+///
+/// // CASE WHEN is_directory = TRUE THEN '755' ELSE '644' END
+/// let expr = FileExpr::Case {
+///     branches: vec![(
+///         FileCondition::Compare {
+///             attribute: FileAttribute::IsDirectory,
+///             operator: ComparisonOperator::Eq,
+///             value: FileValue::Boolean(true),
+///         },
+///         FileExpr::Literal(FileValue::String("755".to_string())),
+///     )],
+///     otherwise: Some(Box::new(FileExpr::Literal(FileValue::String("644".to_string())))),
+/// };
+///
+/// // CONCAT(STEM(name), '.bak')
+/// let expr = FileExpr::Function {
+///     name: "CONCAT".to_string(),
+///     args: vec![
+///         FileExpr::Function {
+///             name: "STEM".to_string(),
+///             args: vec![FileExpr::Attribute(FileAttribute::Name)],
+///         },
+///         FileExpr::Literal(FileValue::String(".bak".to_string())),
+///     ],
+/// };
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum FileExpr {
+    /// A literal value.
+    Literal(FileValue),
+    /// The value of one of the file's own attributes.
+    Attribute(FileAttribute),
+    /// A column of the joined table's row, such as `inventory.team`.
+    Joined {
+        /// The table's name in the query.
+        table: String,
+        /// The column name.
+        column: String,
+    },
+    /// A scalar function call such as `CONCAT(...)` or `YEAR(modified)`.
+    Function {
+        /// The function name, in upper case.
+        name: String,
+        /// The argument expressions.
+        args: Vec<FileExpr>,
+    },
+    /// A `CASE WHEN ... THEN ... [ELSE ...] END` expression.
+    Case {
+        /// Conditions paired with the value to use when they hold, tried in order.
+        branches: Vec<(FileCondition, FileExpr)>,
+        /// The value to use when no branch matches (None means NULL).
+        otherwise: Option<Box<FileExpr>>,
+    },
+    /// An arithmetic expression such as `size / 1048576`.
+    Binary {
+        /// The left operand.
+        left: Box<FileExpr>,
+        /// The arithmetic operator.
+        operator: ArithmeticOperator,
+        /// The right operand.
+        right: Box<FileExpr>,
+    },
+    /// A `CAST(value AS type)` expression, such as `CAST(size AS TEXT)`.
+    Cast {
+        /// The value to convert.
+        expr: Box<FileExpr>,
+        /// What to convert it to.
+        to: CastType,
+    },
+}
+
+/// The types a [`FileExpr::Cast`] converts to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CastType {
+    /// Text, as the value is shown in results (`TEXT`).
+    Text,
+    /// A number (`NUMBER`); text is read as a number, a date is its Unix
+    /// seconds and a boolean is 1 or 0.
+    Number,
+    /// A whole number, rounded toward zero (`INTEGER`).
+    Integer,
+    /// A date (`DATE`); text is read as date literals are, and a number is
+    /// Unix seconds.
+    Date,
+    /// A boolean (`BOOLEAN`); a number is true unless it is 0, and text is
+    /// `true`, `false`, `yes`, `no`, `1` or `0`.
+    Boolean,
+    /// Octal (`OCTAL`): a number becomes its octal digits, so
+    /// `CAST(permissions AS OCTAL)` is `'755'`, and text is read as octal
+    /// digits, so `CAST('755' AS OCTAL)` is 493.
+    Octal,
+}
+
+impl CastType {
+    /// Looks up a type by name, ignoring case. Common SQL spellings such as
+    /// `VARCHAR`, `INT` and `TIMESTAMP` are accepted too.
+    pub fn from_name(name: &str) -> Option<Self> {
+        let to = match name.to_uppercase().as_str() {
+            "TEXT" | "STRING" | "VARCHAR" | "CHAR" => CastType::Text,
+            "NUMBER" | "NUMERIC" | "REAL" | "FLOAT" | "DOUBLE" | "DECIMAL" => CastType::Number,
+            "INTEGER" | "INT" | "BIGINT" => CastType::Integer,
+            "DATE" | "DATETIME" | "TIMESTAMP" => CastType::Date,
+            "BOOLEAN" | "BOOL" => CastType::Boolean,
+            "OCTAL" => CastType::Octal,
+            _ => return None,
+        };
+        Some(to)
+    }
+
+    /// Returns the type's name in query text.
+    pub fn name(self) -> &'static str {
+        match self {
+            CastType::Text => "TEXT",
+            CastType::Number => "NUMBER",
+            CastType::Integer => "INTEGER",
+            CastType::Date => "DATE",
+            CastType::Boolean => "BOOLEAN",
+            CastType::Octal => "OCTAL",
+        }
+    }
+}
+
+/// Arithmetic operators for [`FileExpr::Binary`].
+///
+/// `*`, `/` and `%` bind more tightly than `+` and `-`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ArithmeticOperator {
+    /// Addition (`+`).
+    Add,
+    /// Subtraction (`-`).
+    Sub,
+    /// Multiplication (`*`).
+    Mul,
+    /// Division (`/`).
+    Div,
+    /// Remainder (`%`).
+    Mod,
+}
+
+impl ArithmeticOperator {
+    /// Returns the operator for a symbol such as `+`, if it is one.
+    pub fn from_symbol(symbol: &str) -> Option<ArithmeticOperator> {
+        let operator = match symbol {
+            "+" => ArithmeticOperator::Add,
+            "-" => ArithmeticOperator::Sub,
+            "*" => ArithmeticOperator::Mul,
+            "/" => ArithmeticOperator::Div,
+            "%" => ArithmeticOperator::Mod,
+            _ => return None,
+        };
+        Some(operator)
+    }
+
+    /// Returns true for `*`, `/` and `%`.
+    pub fn is_multiplicative(&self) -> bool {
+        matches!(
+            self,
+            ArithmeticOperator::Mul | ArithmeticOperator::Div | ArithmeticOperator::Mod
+        )
+    }
+}
+
+impl std::fmt::Display for ArithmeticOperator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let symbol = match self {
+            ArithmeticOperator::Add => "+",
+            ArithmeticOperator::Sub => "-",
+            ArithmeticOperator::Mul => "*",
+            ArithmeticOperator::Div => "/",
+            ArithmeticOperator::Mod => "%",
+        };
+        write!(f, "{}", symbol)
+    }
+}
+
+/// Writes the expression as query text, such as `size / 1024`, with
+/// parentheses only where they are needed.
+impl std::fmt::Display for FileExpr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            // Numbers in expressions are parsed as text, and written back bare
+            FileExpr::Literal(FileValue::String(text))
+                if text.bytes().all(|b| b.is_ascii_digit() || b == b'.')
+                    && text.parse::<f64>().is_ok() =>
+            {
+                write!(f, "{}", text)
+            }
+            FileExpr::Literal(value) => write!(f, "{}", sql_literal(value)),
+            FileExpr::Attribute(attribute) => write!(f, "{}", attribute.name()),
+            FileExpr::Joined { table, column } => write!(f, "{}.{}", table, column),
+            FileExpr::Function { name, args } => {
+                write!(f, "{}(", name)?;
+                for (i, arg) in args.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", arg)?;
+                }
+                write!(f, ")")
+            }
+            FileExpr::Case {
+                branches,
+                otherwise,
+            } => {
+                write!(f, "CASE")?;
+                for (condition, value) in branches {
+                    write!(f, " WHEN {} THEN {}", condition, value)?;
+                }
+                if let Some(otherwise) = otherwise {
+                    write!(f, " ELSE {}", otherwise)?;
+                }
+                write!(f, " END")
+            }
+            FileExpr::Binary {
+                left,
+                operator,
+                right,
+            } => {
+                // Operators of one precedence group to the left
+                let binds_looser = |operand: &FileExpr, right_side: bool| match operand {
+                    FileExpr::Binary { operator: inner, .. } => {
+                        (operator.is_multiplicative() && !inner.is_multiplicative())
+                            || (right_side
+                                && operator.is_multiplicative() == inner.is_multiplicative())
+                    }
+                    _ => false,
+                };
+                match binds_looser(left, false) {
+                    true => write!(f, "({})", left)?,
+                    false => write!(f, "{}", left)?,
+                }
+                write!(f, " {} ", operator)?;
+                match binds_looser(right, true) {
+                    true => write!(f, "({})", right),
+                    false => write!(f, "{}", right),
+                }
+            }
+            FileExpr::Cast { expr, to } => write!(f, "CAST({} AS {})", expr, to.name()),
+        }
+    }
+}
+
+/// A computed column in a SELECT list, such as `size / 1024 AS kib`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ComputedColumn {
+    /// The column name: the `AS` alias, or the expression's source text.
+    pub name: String,
+    /// The expression to evaluate for each file.
+    pub expr: FileExpr,
+}
+
+/// An aggregate in a SELECT list, such as `SUM(size) AS total`.
+///
+/// A SELECT with aggregates returns one row per group of files rather than
+/// one row per file; without `GROUP BY` every match is in a single group.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AggregateColumn {
+    /// The column name: the `AS` alias, or the aggregate's source text.
+    pub name: String,
+    /// The aggregate function.
+    pub function: AggregateFunction,
+    /// The expression to aggregate (None for `COUNT(*)`).
+    pub argument: Option<FileExpr>,
+}
+
+/// The aggregate functions. All of them skip NULL values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AggregateFunction {
+    /// The number of files (`COUNT(*)`) or of non-NULL values (`COUNT(x)`).
+    Count,
+    /// The total of the values.
+    Sum,
+    /// The smallest value.
+    Min,
+    /// The largest value.
+    Max,
+    /// The mean of the values.
+    Avg,
+}
+
+impl AggregateFunction {
+    /// Looks up an aggregate function by name, ignoring case.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_uppercase().as_str() {
+            "COUNT" => Some(AggregateFunction::Count),
+            "SUM" => Some(AggregateFunction::Sum),
+            "MIN" => Some(AggregateFunction::Min),
+            "MAX" => Some(AggregateFunction::Max),
+            "AVG" => Some(AggregateFunction::Avg),
+            _ => None,
+        }
+    }
+}
+
+/// One key of an `ORDER BY` clause, such as `path NATURAL DESC` or
+/// `extension NULLS LAST`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OrderBy {
+    /// What to sort on.
+    pub key: SortKey,
+    /// Sort largest first (`DESC`).
+    pub descending: bool,
+    /// Compare runs of digits by their value, so `file2` sorts before
+    /// `file10` (`NATURAL`).
+    pub natural: bool,
+    /// Compare text by the rules of this locale, such as `de_DE`
+    /// (`COLLATE 'de_DE'`).
+    pub collation: Option<String>,
+    /// Put NULLs before (`NULLS FIRST`) or after (`NULLS LAST`) every
+    /// value, whichever the direction. Unset, NULLs sort after every value,
+    /// so last ascending and first descending.
+    pub nulls_first: Option<bool>,
+}
+
+/// What an `ORDER BY` key sorts on.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum SortKey {
+    /// A file attribute, such as `size`.
+    Attribute(FileAttribute),
+    /// How many directories deep the file is, shallowest first (`depth`).
+    Depth,
+    /// A column of the joined table, such as `inventory.team`.
+    Joined {
+        /// The table's name in the query.
+        table: String,
+        /// The column name.
+        column: String,
+    },
+    /// A random order (`RANDOM()`), the same every time for the same seed
+    /// (`RANDOM(42)`).
+    Random {
+        /// The seed, or None for a different order each run.
+        seed: Option<u64>,
+    },
+}
+
+/// A random sample of a fixed number of matches, as in
+/// `SAMPLE 100 ROWS WEIGHTED BY size SEED 42`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RowSample {
+    /// How many matches to keep.
+    pub rows: usize,
+    /// The attribute that makes a match more likely to be picked, in
+    /// proportion to its value (None means every match is as likely).
+    pub weight: Option<FileAttribute>,
+    /// The seed, or None for a different sample each run.
+    pub seed: Option<u64>,
+}
+
+/// Represents a condition for filtering files.
+///
+/// This enum represents the various types of conditions that can appear
+/// in the WHERE clause of a query, including simple comparisons, logical
+/// operations, and pattern matching.
+///
+/// # Examples
+///
+/// ```no_run
+/// use fmql_core::sql::ast::{FileCondition, FileAttribute, ComparisonOperator, FileValue};
+///
+/// // WHERE size > 1000000
+/// let condition = FileCondition::Compare {
+///     attribute: FileAttribute::Size,
+///     operator: ComparisonOperator::Gt,
+///     value: FileValue::Number(1000000.0),
+/// };
+///
+/// // WHERE name LIKE '%.txt'
+/// let text_files = FileCondition::Like {
+///     attribute: FileAttribute::Name,
+///     pattern: "%.txt".to_string(),
+///     case_sensitive: false,
+/// };
+///
+/// // Complex condition: WHERE (size > 1000000 AND extension = 'pdf')
+/// let complex = FileCondition::And(
+///     Box::new(condition),
+///     Box::new(FileCondition::Compare {
+///         attribute: FileAttribute::Extension,
+///         operator: ComparisonOperator::Eq,
+///         value: FileValue::String("pdf".to_string()),
+///     })
+/// );
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum FileCondition {
+    /// Compares a file attribute to a value.
+    Compare {
+        /// The attribute to compare.
+        attribute: FileAttribute,
+        /// The comparison operator.
+        operator: ComparisonOperator,
+        /// The value to compare against.
+        value: FileValue,
+    },
+    /// A logical AND of multiple conditions.
+    And(Box<FileCondition>, Box<FileCondition>),
+    /// A logical OR of multiple conditions.
+    Or(Box<FileCondition>, Box<FileCondition>),
+    /// A logical NOT of a condition.
+    Not(Box<FileCondition>),
+    /// A condition that always (`TRUE`) or never (`FALSE`) holds.
+    Constant(bool),
+    /// A LIKE pattern matching condition.
+    Like {
+        /// The attribute to match.
+        attribute: FileAttribute,
+        /// The pattern to match against.
+        pattern: String,
+        /// Whether the match is case-sensitive.
+        case_sensitive: bool,
+    },
+    /// A BETWEEN condition (value is between two bounds).
+    Between {
+        /// The attribute to check.
+        attribute: FileAttribute,
+        /// The lower bound.
+        lower: FileValue,
+        /// The upper bound.
+        upper: FileValue,
+    },
+    /// A regular expression matching condition.
+    Regexp {
+        /// The attribute to match.
+        attribute: FileAttribute,
+        /// The regular expression to match against.
+        pattern: String,
+    },
+    /// A symbolic permission check such as `HAS_PERM('g+w')`.
+    HasPerm {
+        /// The chmod-style spec, e.g. `u+x` or `go-w`.
+        spec: String,
+    },
+    /// Whether the file's owner is in a group, such as
+    /// `OWNER_IN_GROUP('developers')`; see [`crate::sql::users::in_group`].
+    OwnerInGroup {
+        /// The group's name.
+        group: String,
+    },
+    /// Membership of a tag, such as `TAGGED('project-x')`.
+    Tagged {
+        /// The tag's name.
+        tag: String,
+        /// The tag store to look in; see [`crate::sql::tags`].
+        store: PathBuf,
+    },
+    /// An octal permission pattern such as `PERM_MATCHES('??5')`.
+    PermMatches {
+        /// Octal digits, with `?` matching any digit.
+        pattern: String,
+    },
+    /// A comparison between two expressions, such as
+    /// `size / 1048576 > 100` or `YEAR(modified) = 2024`.
+    Expression {
+        /// The left-hand expression.
+        left: FileExpr,
+        /// The comparison operator.
+        operator: ComparisonOperator,
+        /// The right-hand expression.
+        right: FileExpr,
+    },
+    /// A literal substring check such as `STARTS_WITH(name, 'IMG_')`.
+    ///
+    /// Unlike LIKE, `%` and `_` in the text have no special meaning.
+    Substring {
+        /// The attribute to check.
+        attribute: FileAttribute,
+        /// Where in the value the text must appear.
+        position: SubstringPosition,
+        /// The text to look for (case-sensitive).
+        text: String,
+    },
+    /// A search of the file's contents for a byte sequence, such as
+    /// `CONTAINS_BYTES(x'7f454c46')`.
+    ContainsBytes {
+        /// The bytes to look for.
+        bytes: Vec<u8>,
+        /// Search only this many bytes from the start of the file, instead
+        /// of the default cap.
+        max_bytes: Option<u64>,
+    },
+    /// A comparison on masked permission bits, such as
+    /// `PERM_AND(permissions, 0o022) != 0` or `permissions & 0o022 != 0`.
+    PermMask {
+        /// The bits to keep from the file's permissions.
+        mask: u32,
+        /// The comparison operator.
+        operator: ComparisonOperator,
+        /// The value to compare the masked bits against.
+        value: FileValue,
+    },
+    /// A condition that may take only so long for each file, such as
+    /// `CONTAINS_STR(content, 'secret') BUDGET '2s' PER FILE`; see
+    /// [`crate::sql::budget`].
+    Budget {
+        /// The condition.
+        condition: Box<FileCondition>,
+        /// How long it may take for one file.
+        limit: Duration,
+    },
+}
+
+/// Writes the condition as the query text of a WHERE clause, such as
+/// `size > 100 AND NOT name LIKE '%.tmp'`, with parentheses only where
+/// they are needed. A `TAGGED` condition's tag store is left out.
+impl std::fmt::Display for FileCondition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FileCondition::Compare {
+                attribute,
+                operator,
+                value,
+            } => write!(f, "{} {} {}", attribute.name(), operator, sql_literal(value)),
+            FileCondition::And(left, right) => {
+                for (i, side) in [left, right].into_iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " AND ")?;
+                    }
+                    match side.as_ref() {
+                        FileCondition::Or(..) => write!(f, "({})", side)?,
+                        _ => write!(f, "{}", side)?,
+                    }
+                }
+                Ok(())
+            }
+            FileCondition::Or(left, right) => write!(f, "{} OR {}", left, right),
+            FileCondition::Not(inner) => match inner.as_ref() {
+                FileCondition::And(..) | FileCondition::Or(..) => write!(f, "NOT ({})", inner),
+                _ => write!(f, "NOT {}", inner),
+            },
+            FileCondition::Constant(true) => write!(f, "TRUE"),
+            FileCondition::Constant(false) => write!(f, "FALSE"),
+            FileCondition::Like {
+                attribute, pattern, ..
+            } => write!(f, "{} LIKE {}", attribute.name(), sql_string(pattern)),
+            FileCondition::Between {
+                attribute,
+                lower,
+                upper,
+            } => write!(
+                f,
+                "{} BETWEEN {} AND {}",
+                attribute.name(),
+                sql_literal(lower),
+                sql_literal(upper)
+            ),
+            FileCondition::Regexp { attribute, pattern } => {
+                write!(f, "{} REGEXP {}", attribute.name(), sql_string(pattern))
+            }
+            FileCondition::HasPerm { spec } => write!(f, "HAS_PERM({})", sql_string(spec)),
+            FileCondition::OwnerInGroup { group } => {
+                write!(f, "OWNER_IN_GROUP({})", sql_string(group))
+            }
+            FileCondition::Tagged { tag, .. } => write!(f, "TAGGED({})", sql_string(tag)),
+            FileCondition::PermMatches { pattern } => {
+                write!(f, "PERM_MATCHES({})", sql_string(pattern))
+            }
+            FileCondition::Expression {
+                left,
+                operator,
+                right,
+            } => write!(f, "{} {} {}", left, operator, right),
+            FileCondition::Substring {
+                attribute,
+                position,
+                text,
+            } => {
+                let function = match position {
+                    SubstringPosition::Start => "STARTS_WITH",
+                    SubstringPosition::End => "ENDS_WITH",
+                    SubstringPosition::Anywhere => "CONTAINS_STR",
+                };
+                write!(f, "{}({}, {})", function, attribute.name(), sql_string(text))
+            }
+            FileCondition::ContainsBytes { bytes, max_bytes } => {
+                let hex: String = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+                match max_bytes {
+                    Some(max_bytes) => write!(f, "CONTAINS_BYTES(x'{}', {})", hex, max_bytes),
+                    None => write!(f, "CONTAINS_BYTES(x'{}')", hex),
+                }
+            }
+            FileCondition::PermMask {
+                mask,
+                operator,
+                value,
+            } => write!(f, "permissions & 0o{:03o} {} {}", mask, operator, sql_literal(value)),
+            FileCondition::Budget { condition, limit } => {
+                let limit = sql_string(&format!("{}s", limit.as_secs_f64()));
+                match condition.as_ref() {
+                    FileCondition::And(..) | FileCondition::Or(..) | FileCondition::Not(..) => {
+                        write!(f, "({}) BUDGET {} PER FILE", condition, limit)
+                    }
+                    _ => write!(f, "{} BUDGET {} PER FILE", condition, limit),
+                }
+            }
+        }
+    }
+}
+
+/// Where a [`FileCondition::Substring`] looks for its text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SubstringPosition {
+    /// At the start of the value (`STARTS_WITH`).
+    Start,
+    /// At the end of the value (`ENDS_WITH`).
+    End,
+    /// Anywhere in the value (`CONTAINS_STR`).
+    Anywhere,
+}
+
+/// A kind of directory entry, used to restrict a query to files,
+/// directories or symlinks.
+///
+/// Written as `SELECT FILES` / `SELECT DIRS`, or as a `TYPE file | dir |
+/// symlink` modifier after the path. Like `find -type`, symlinks are never
+/// files or directories, whatever they point to.
+///
+/// # Examples
+///
+/// ```
+/// use fmql_core::sql::ast::EntryType;
+///
+/// assert_eq!("d".parse::<EntryType>(), Ok(EntryType::Directory));
+/// assert_eq!("symlink".parse::<EntryType>(), Ok(EntryType::Symlink));
+/// assert!("socket".parse::<EntryType>().is_err());
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum EntryType {
+    /// A regular file.
+    File,
+    /// A directory.
+    Directory,
+    /// A symbolic link.
+    Symlink,
+}
+
+impl std::str::FromStr for EntryType {
+    type Err = String;
+
+    /// Accepts `file`, `dir`, `directory`, `symlink` and `link`, or the
+    /// `find`-style letters `f`, `d` and `l`, in any case.
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "f" | "file" => Ok(EntryType::File),
+            "d" | "dir" | "directory" => Ok(EntryType::Directory),
+            "l" | "link" | "symlink" => Ok(EntryType::Symlink),
+            _ => Err(format!("Unknown entry type '{}' (expected file, dir or symlink)", s)),
+        }
+    }
+}
+
+/// What to do when a rename would replace an existing file.
+///
+/// Written as `ON CONFLICT SKIP | OVERWRITE | RENAME | FAIL` at the end of an
+/// UPDATE query. The default is `FAIL`.
+///
+/// # Examples
+///
+/// ```sql
+/// UPDATE ~/Photos SET name = LOWER(name) ON CONFLICT RENAME
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum ConflictPolicy {
+    /// Leave the file where it is and report it as failed.
+    #[default]
+    Fail,
+    /// Leave the file where it is.
+    Skip,
+    /// Replace the existing file.
+    Overwrite,
+    /// Append a numeric suffix (`photo_1.jpg`) until the name is free.
+    Rename,
+}
+
+/// How COPY makes each copy, from `USING <method>`.
+///
+/// Hardlinks and reflinks are instant and take no space, but only work
+/// within one file system; across devices every method falls back to
+/// copying the bytes. A hardlink is the same file under a second name, so
+/// changing one changes the other, while a reflink shares the data only
+/// until either is written.
+///
+/// # Examples
+///
+/// ```sql
+/// COPY ~/builds TO ~/builds-snapshot USING AUTO
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CopyMethod {
+    /// Copy the contents (the default).
+    #[default]
+    Bytes,
+    /// Link the copy to the same inode as the original.
+    Hardlink,
+    /// Clone the file on a copy-on-write file system (btrfs, XFS, APFS).
+    Reflink,
+    /// A reflink if the file system can make one, else a hardlink, else a
+    /// byte copy.
+    Auto,
+}
+
+impl CopyMethod {
+    /// Returns the past tense of the method, such as `hardlinked`.
+    pub fn verb(&self) -> &'static str {
+        match self {
+            CopyMethod::Bytes | CopyMethod::Auto => "copied",
+            CopyMethod::Hardlink => "hardlinked",
+            CopyMethod::Reflink => "reflinked",
+        }
+    }
+}
+
+impl std::fmt::Display for CopyMethod {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let text = match self {
+            CopyMethod::Bytes => "BYTES",
+            CopyMethod::Hardlink => "HARDLINK",
+            CopyMethod::Reflink => "REFLINK",
+            CopyMethod::Auto => "AUTO",
+        };
+        f.write_str(text)
+    }
+}
+
+/// What COPY keeps of each original besides its contents, from
+/// `PRESERVE (times, permissions, owner, xattrs)`, `PRESERVE ALL` or
+/// `PRESERVE NONE`.
+///
+/// Without a PRESERVE clause a copy keeps the original's permissions and
+/// its access and modification times, like `cp -p` does for a user who
+/// can't give files away. Keeping the owner of another user's file takes
+/// root. A hardlink is the original under another name, so it keeps
+/// everything whatever this says, and so does a clone on APFS.
+///
+/// # Examples
+///
+/// ```sql
+/// COPY /srv/www TO /mnt/backup/www PRESERVE (times, permissions, owner, xattrs)
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Preserve {
+    /// The access and modification times.
+    pub times: bool,
+    /// The permission bits, setuid, setgid and sticky included.
+    pub permissions: bool,
+    /// The owning user and group.
+    pub owner: bool,
+    /// The extended attributes.
+    pub xattrs: bool,
+}
+
+impl Preserve {
+    /// Keep everything.
+    pub const ALL: Preserve = Preserve {
+        times: true,
+        permissions: true,
+        owner: true,
+        xattrs: true,
+    };
+
+    /// Keep only the contents.
+    pub const NONE: Preserve = Preserve {
+        times: false,
+        permissions: false,
+        owner: false,
+        xattrs: false,
+    };
+
+    /// The names that can be listed in `PRESERVE (...)`.
+    pub const NAMES: &'static [&'static str] = &["times", "permissions", "owner", "xattrs"];
+
+    /// Keeps the piece of metadata called `name` as well, returning false
+    /// if there is no such piece.
+    pub fn add(&mut self, name: &str) -> bool {
+        match name.to_lowercase().as_str() {
+            "times" => self.times = true,
+            "permissions" => self.permissions = true,
+            "owner" => self.owner = true,
+            "xattrs" => self.xattrs = true,
+            _ => return false,
+        }
+        true
+    }
+}
+
+impl Default for Preserve {
+    fn default() -> Self {
+        Preserve {
+            times: true,
+            permissions: true,
+            ..Preserve::NONE
+        }
+    }
+}
+
+/// The format COMPRESS writes, from `USING GZIP` or `USING ZSTD`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CompressFormat {
+    /// gzip, which everything can read (the default).
+    #[default]
+    Gzip,
+    /// Zstandard, which is faster and smaller at its higher levels.
+    Zstd,
+}
+
+impl CompressFormat {
+    /// Returns the extension compressed files get, without the dot.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            CompressFormat::Gzip => "gz",
+            CompressFormat::Zstd => "zst",
+        }
+    }
+
+    /// Returns the levels the format has, from fastest to smallest.
+    pub fn levels(&self) -> std::ops::RangeInclusive<u32> {
+        match self {
+            CompressFormat::Gzip => 1..=9,
+            CompressFormat::Zstd => 1..=19,
+        }
+    }
+
+    /// Returns the level used when a query doesn't give one.
+    pub fn default_level(&self) -> u32 {
+        match self {
+            CompressFormat::Gzip => 6,
+            CompressFormat::Zstd => 3,
+        }
+    }
+}
+
+impl std::fmt::Display for CompressFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let text = match self {
+            CompressFormat::Gzip => "GZIP",
+            CompressFormat::Zstd => "ZSTD",
+        };
+        f.write_str(text)
+    }
+}
+
+/// Comparison operators for file conditions.
+///
+/// These operators define how attributes are compared to values
+/// in query conditions.
+///
+/// # Examples
+///
+/// ```no_run
+/// use fmql_core::sql::ast::ComparisonOperator;
+///
+/// // Using operators in conditions:
+/// assert_eq!(ComparisonOperator::Eq.to_string(), "=");
+/// assert_eq!(ComparisonOperator::Gt.to_string(), ">");
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ComparisonOperator {
+    /// Equal to (=).
+    Eq,
+    /// Not equal to (!=).
+    NotEq,
+    /// Less than (<).
+    Lt,
+    /// Less than or equal to (<=).
+    LtEq,
+    /// Greater than (>).
+    Gt,
+    /// Greater than or equal to (>=).
+    GtEq,
+}
+
+impl std::fmt::Display for ComparisonOperator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ComparisonOperator::Eq => write!(f, "="),
+            ComparisonOperator::NotEq => write!(f, "!="),
+            ComparisonOperator::Lt => write!(f, "<"),
+            ComparisonOperator::LtEq => write!(f, "<="),
+            ComparisonOperator::Gt => write!(f, ">"),
+            ComparisonOperator::GtEq => write!(f, ">="),
+        }
+    }
+}
+
+/// Represents a value in a file condition.
+///
+/// This enum represents the different types of literal values that
+/// can be used in file conditions, such as strings, numbers, and dates.
+///
+/// # Examples
+///
+/// ```no_run
+/// use fmql_core::sql::ast::FileValue;
+/// use chrono::{TimeDelta, Utc};
+///
+/// // Different value types:
+/// let string_value = FileValue::String("example.txt".to_string());
+/// let integer_value = FileValue::Integer(1024);
+/// let number_value = FileValue::Number(0.5);
+/// let date_value = FileValue::DateTime(Utc::now());
+/// let duration_value = FileValue::Duration(TimeDelta::days(90));
+/// let bool_value = FileValue::Boolean(true);
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum FileValue {
+    /// A string value.
+    String(String),
+    /// A whole number, such as a size, kept exact however large it is.
+    Integer(i64),
+    /// A number that may have a fraction, such as an average.
+    Number(f64),
+    /// A date/time value.
+    DateTime(DateTime<Utc>),
+    /// A length of time, such as a file's `age` or the difference between
+    /// two dates; negative if the second date is later.
+    Duration(#[serde(with = "millis")] TimeDelta),
+    /// A boolean value.
+    Boolean(bool),
+    /// A null value.
+    Null,
+}
+
+impl FileValue {
+    /// Returns the value as a float if it is a number of either kind.
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            FileValue::Integer(n) => Some(*n as f64),
+            FileValue::Number(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    /// Makes a value of a count or size. Sizes never come near the limit;
+    /// one that did would become a float rather than wrap.
+    pub fn from_u64(n: u64) -> FileValue {
+        i64::try_from(n).map_or(FileValue::Number(n as f64), FileValue::Integer)
+    }
+}
+
+/// Writes a value as a literal of the query language: text in single
+/// quotes, dates as UTC text, and `TRUE`, `FALSE` and `NULL` as keywords.
+fn sql_literal(value: &FileValue) -> String {
+    match value {
+        FileValue::String(text) => sql_string(text),
+        FileValue::Integer(n) => n.to_string(),
+        FileValue::Number(n) => n.to_string(),
+        FileValue::DateTime(time) => sql_string(&time.format("%Y-%m-%d %H:%M:%S").to_string()),
+        FileValue::Duration(delta) => format!("INTERVAL {}", sql_string(&format_interval(*delta))),
+        FileValue::Boolean(true) => "TRUE".to_string(),
+        FileValue::Boolean(false) => "FALSE".to_string(),
+        FileValue::Null => "NULL".to_string(),
+    }
+}
+
+/// Saves a [`FileValue::Duration`] as whole milliseconds.
+mod millis {
+    use chrono::TimeDelta;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(delta: &TimeDelta, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_i64(delta.num_milliseconds())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<TimeDelta, D::Error> {
+        let millis = i64::deserialize(deserializer)?;
+        TimeDelta::try_milliseconds(millis)
+            .ok_or_else(|| serde::de::Error::custom("duration out of range"))
+    }
+}
+
+/// Quotes text as a string literal, doubling any single quotes in it.
+fn sql_string(text: &str) -> String {
+    format!("'{}'", text.replace('\'', "''"))
+}
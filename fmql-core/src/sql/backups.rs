@@ -0,0 +1,541 @@
+//! Backup repositories as directories to query, for
+//! `FROM 'restic:/srv/restic'` and `FROM 'borg:/srv/borg'`.
+//!
+//! [`list`] asks the backup tool what each snapshot holds, through the
+//! JSON its CLI writes (`restic snapshots --json` and `restic ls --json`,
+//! or `borg list --json` and `borg list --json-lines`), and sets the
+//! listings out in a private temporary directory, a directory for each
+//! snapshot named by restic's short id or borg's archive name. Nothing is
+//! restored: each file is a sparse stand-in as long as the backed-up one,
+//! with its mode and modification time, so a query can ask which snapshot
+//! holds a file larger than some size without reading a byte of the
+//! repository's data. `content`, hashes and the other attributes read from
+//! a file's bytes see only zeros. A snapshot's directory was modified when
+//! the snapshot was taken.
+//!
+//! The query runs on that directory, and its results are given their paths
+//! in the repository, such as `restic:/srv/restic/1a2b3c4d/home/me/notes.txt`,
+//! so that nothing refers to the copy once it is gone. The tools read the
+//! repository's password from their own environment variables, such as
+//! `RESTIC_PASSWORD` and `BORG_PASSPHRASE`, and are never given a
+//! terminal to ask for it on.
+//!
+//! # Examples
+//!
+//! ```
+//! use std::path::Path;
+//!
+//! use fmql_core::sql::backups::{restic_entries, Listing};
+//!
+//! let ls = br#"{"struct_type":"snapshot","id":"1a2b3c4d5e6f","short_id":"1a2b3c4d"}
+//! {"struct_type":"node","type":"dir","path":"/home/me","size":0,"mode":2147484141}
+//! {"struct_type":"node","type":"file","path":"/home/me/disk.img","size":4294967296,"mode":420,"mtime":"2024-03-01T09:30:00+00:00"}"#;
+//!
+//! let mut listing = Listing::new("restic:/srv/restic".to_string()).unwrap();
+//! listing.add("1a2b3c4d", None, restic_entries(&ls[..])).unwrap();
+//! let disk = listing.root().join("1a2b3c4d/home/me/disk.img");
+//! assert_eq!(std::fs::metadata(&disk).unwrap().len(), 4294967296);
+//! assert_eq!(
+//!     listing.path_in_backup(&disk),
+//!     Path::new("restic:/srv/restic/1a2b3c4d/home/me/disk.img")
+//! );
+//! ```
+
+use std::collections::{BTreeMap, HashSet};
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader};
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Component, Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::time::SystemTime;
+
+use chrono::{DateTime, Local, NaiveDateTime};
+use serde_json::Value;
+use tempfile::TempDir;
+use walkdir::WalkDir;
+
+/// The Go `os.FileMode` bits restic records beside a file's permissions.
+const GO_SETUID: u64 = 1 << 23;
+const GO_SETGID: u64 = 1 << 22;
+const GO_STICKY: u64 = 1 << 20;
+
+/// Where a backup repository is, and which tool reads it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BackupSource {
+    /// A restic repository, as `restic --repo` takes it, such as
+    /// `/srv/restic` or `sftp:host:/srv/restic`.
+    Restic(String),
+    /// A borg repository, as `borg list` takes it, such as `/srv/borg` or
+    /// `ssh://host/srv/borg`.
+    Borg(String),
+}
+
+impl BackupSource {
+    /// Reads a FROM path naming a backup repository, `restic:/path/to/repo`
+    /// or `borg:/path/to/repo`, or returns None for any other path.
+    pub fn parse(from: &Path) -> Option<BackupSource> {
+        let from = from.to_str()?;
+        if let Some(repository) = from.strip_prefix("restic:") {
+            let repository = repository.trim_end_matches('/');
+            return (!repository.is_empty()).then(|| BackupSource::Restic(repository.to_string()));
+        }
+        let repository = from.strip_prefix("borg:")?.trim_end_matches('/');
+        (!repository.is_empty()).then(|| BackupSource::Borg(repository.to_string()))
+    }
+
+    /// How the repository's files are named in results: the FROM path,
+    /// which their snapshots and paths follow.
+    pub fn prefix(&self) -> String {
+        match self {
+            BackupSource::Restic(repository) => format!("restic:{}", repository),
+            BackupSource::Borg(repository) => format!("borg:{}", repository),
+        }
+    }
+
+    /// The repository, as its tool takes it.
+    pub fn repository(&self) -> &str {
+        match self {
+            BackupSource::Restic(repository) | BackupSource::Borg(repository) => repository,
+        }
+    }
+}
+
+/// What a snapshot's listing says about one of its entries.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    /// The entry's path in the snapshot.
+    pub path: PathBuf,
+    pub kind: EntryKind,
+    /// The size of a file, in bytes.
+    pub size: u64,
+    /// The permission bits, setuid, setgid and sticky included.
+    pub mode: u32,
+    pub modified: Option<SystemTime>,
+    /// Where a symlink leads.
+    pub link: Option<PathBuf>,
+}
+
+/// The kinds of entry a snapshot's listing has.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntryKind {
+    File,
+    Dir,
+    Symlink,
+    /// Devices, FIFOs and sockets, which get no stand-in.
+    Other,
+}
+
+/// The snapshots of a repository, or the files of a device, set out in a
+/// temporary directory, which is deleted when this is dropped.
+#[derive(Debug)]
+pub struct Listing {
+    dir: TempDir,
+    root: PathBuf,
+    prefix: String,
+}
+
+impl Listing {
+    /// Makes an empty temporary directory for the snapshots of the
+    /// repository `prefix` names.
+    pub fn new(prefix: String) -> io::Result<Listing> {
+        let dir = tempfile::Builder::new().prefix("fmql-backup-").tempdir()?;
+        let root = dir.path().join("snapshots");
+        fs::create_dir(&root)?;
+        Ok(Listing { dir, root, prefix })
+    }
+
+    /// The directory the snapshots are in.
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Sets out the entries of a snapshot, in a directory named `snapshot`
+    /// that was modified at `taken`.
+    pub fn add(
+        &mut self,
+        snapshot: &str,
+        taken: Option<SystemTime>,
+        entries: impl IntoIterator<Item = io::Result<Entry>>,
+    ) -> io::Result<()> {
+        let Some(name) = relative_path(Path::new(snapshot)).filter(|name| name.iter().count() == 1)
+        else {
+            return Err(invalid(format!("{:?} can't name a snapshot's directory", snapshot)));
+        };
+        let base = self.root.join(name);
+        fs::create_dir_all(&base)?;
+        set_out(&base, taken, entries)
+    }
+
+    /// Sets out the entries of a listing of one tree, such as a device's,
+    /// at the root of the directory rather than in a snapshot's.
+    pub fn add_root(
+        &mut self,
+        entries: impl IntoIterator<Item = io::Result<Entry>>,
+    ) -> io::Result<()> {
+        set_out(&self.root, None, entries)
+    }
+
+    /// Returns what a path in the temporary directory is called in
+    /// results: its snapshot and path after the repository's name.
+    pub fn path_in_backup(&self, path: &Path) -> PathBuf {
+        match path.strip_prefix(&self.root) {
+            Ok(relative) => PathBuf::from(format!("{}/{}", self.prefix, relative.display())),
+            Err(_) => path.to_path_buf(),
+        }
+    }
+
+    /// Returns `text` with the temporary directory's path at its start
+    /// replaced as [`path_in_backup`](Listing::path_in_backup) does, or
+    /// None if it doesn't start with it.
+    pub fn rewrite(&self, text: &str) -> Option<String> {
+        let root = self.root.to_str()?;
+        let rest = text.strip_prefix(root)?;
+        if rest.is_empty() || rest.starts_with('/') {
+            Some(format!("{}/{}", self.prefix, rest.trim_start_matches('/')))
+        } else {
+            None
+        }
+    }
+}
+
+impl Drop for Listing {
+    fn drop(&mut self) {
+        // Directories the snapshots made read-only must be writable again
+        // for their files to be deleted
+        for entry in WalkDir::new(self.dir.path()).into_iter().flatten() {
+            if entry.file_type().is_dir() {
+                let _ = fs::set_permissions(entry.path(), fs::Permissions::from_mode(0o700));
+            }
+        }
+    }
+}
+
+/// Sets out the entries of a listing under `base`, which was modified at
+/// `taken`. Entries with paths that climb out of it, or lead through one of
+/// its symlinks, are skipped.
+fn set_out(
+    base: &Path,
+    taken: Option<SystemTime>,
+    entries: impl IntoIterator<Item = io::Result<Entry>>,
+) -> io::Result<()> {
+    let mut links = HashSet::new();
+    let mut dirs = BTreeMap::new();
+    for entry in entries {
+        let entry = entry?;
+        let Some(relative) = relative_path(&entry.path) else {
+            continue;
+        };
+        if relative.ancestors().skip(1).any(|ancestor| links.contains(ancestor)) {
+            continue;
+        }
+        let target = base.join(&relative);
+        match entry.kind {
+            EntryKind::Dir => {
+                fs::create_dir_all(&target)?;
+                dirs.insert(target, (entry.mode, entry.modified));
+            }
+            EntryKind::File => {
+                if let Some(parent) = target.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                let file = File::create(&target)?;
+                // Sparse, so it takes no space however large it says it is
+                file.set_len(entry.size)?;
+                if let Some(modified) = entry.modified {
+                    file.set_modified(modified)?;
+                }
+                file.set_permissions(fs::Permissions::from_mode(entry.mode & 0o7777))?;
+            }
+            EntryKind::Symlink => {
+                let Some(link) = &entry.link else {
+                    continue;
+                };
+                if let Some(parent) = target.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                std::os::unix::fs::symlink(link, &target)?;
+                links.insert(relative);
+            }
+            EntryKind::Other => {}
+        }
+    }
+    // Directories get their modes last, so that read-only ones could be
+    // written into
+    dirs.insert(base.to_path_buf(), (0o755, taken));
+    for (path, (mode, modified)) in dirs.into_iter().rev() {
+        if let Some(modified) = modified {
+            let _ = File::open(&path).and_then(|dir| dir.set_modified(modified));
+        }
+        let _ = fs::set_permissions(&path, fs::Permissions::from_mode(mode & 0o7777));
+    }
+    Ok(())
+}
+
+/// Lists every snapshot of a repository into a new temporary directory.
+///
+/// # Errors
+///
+/// Fails if the tool isn't installed or can't read the repository, with
+/// `InvalidData` if what it writes isn't the JSON it should be.
+pub fn list(source: &BackupSource) -> io::Result<Listing> {
+    let mut listing = Listing::new(source.prefix())?;
+    match source {
+        BackupSource::Restic(repository) => {
+            let snapshots = run(restic(repository).args(["snapshots", "--json"]))?;
+            for snapshot in restic_snapshots(&snapshots)? {
+                let mut ls = spawn(restic(repository).args(["ls", "--json", &snapshot.id]))?;
+                let stdout = BufReader::new(ls.stdout.take().expect("stdout is piped"));
+                listing.add(&snapshot.name, snapshot.taken, restic_entries(stdout))?;
+                finish(ls, "restic ls")?;
+            }
+        }
+        BackupSource::Borg(repository) => {
+            let archives = run(Command::new("borg").args(["list", "--json", repository]))?;
+            for archive in borg_archives(&archives)? {
+                let location = format!("{}::{}", repository, archive.name);
+                let mut ls = spawn(Command::new("borg").args(["list", "--json-lines", &location]))?;
+                let stdout = BufReader::new(ls.stdout.take().expect("stdout is piped"));
+                listing.add(&archive.name, archive.taken, borg_entries(stdout))?;
+                finish(ls, "borg list")?;
+            }
+        }
+    }
+    Ok(listing)
+}
+
+/// A snapshot of a repository, as its tool lists them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snapshot {
+    /// What the tool calls the snapshot.
+    pub id: String,
+    /// What its directory is called: restic's short id or borg's archive
+    /// name.
+    pub name: String,
+    pub taken: Option<SystemTime>,
+}
+
+/// Reads what `restic snapshots --json` writes.
+pub fn restic_snapshots(json: &[u8]) -> io::Result<Vec<Snapshot>> {
+    let snapshots: Value = serde_json::from_slice(json)
+        .map_err(|err| invalid(format!("restic snapshots: {}", err)))?;
+    let snapshots = snapshots.as_array().ok_or_else(|| invalid("restic snapshots: not a list".to_string()))?;
+    snapshots
+        .iter()
+        .map(|snapshot| {
+            let id = snapshot.get("id").and_then(Value::as_str);
+            let id = id.ok_or_else(|| invalid("restic snapshots: a snapshot has no id".to_string()))?;
+            let short = snapshot.get("short_id").and_then(Value::as_str);
+            let name = short.unwrap_or(&id[..id.len().min(8)]).to_string();
+            let taken = snapshot.get("time").and_then(Value::as_str).and_then(rfc3339);
+            Ok(Snapshot { id: id.to_string(), name, taken })
+        })
+        .collect()
+}
+
+/// Reads what `borg list --json` writes of a repository's archives.
+pub fn borg_archives(json: &[u8]) -> io::Result<Vec<Snapshot>> {
+    let repository: Value =
+        serde_json::from_slice(json).map_err(|err| invalid(format!("borg list: {}", err)))?;
+    let archives = repository.get("archives").and_then(Value::as_array);
+    let archives = archives.ok_or_else(|| invalid("borg list: no archives".to_string()))?;
+    archives
+        .iter()
+        .map(|archive| {
+            let name = archive.get("name").or_else(|| archive.get("archive")).and_then(Value::as_str);
+            let name = name.ok_or_else(|| invalid("borg list: an archive has no name".to_string()))?;
+            let id = archive.get("id").and_then(Value::as_str).unwrap_or(name);
+            let taken = archive.get("start").and_then(Value::as_str).and_then(local_time);
+            Ok(Snapshot { id: id.to_string(), name: name.to_string(), taken })
+        })
+        .collect()
+}
+
+/// Reads the entries of what `restic ls --json` writes, a JSON object on
+/// each line, skipping the snapshot the first describes.
+pub fn restic_entries(listing: impl BufRead) -> impl Iterator<Item = io::Result<Entry>> {
+    json_lines(listing, "restic ls").filter_map(|node| {
+        let node = match node {
+            Ok(node) => node,
+            Err(err) => return Some(Err(err)),
+        };
+        let record = node.get("struct_type").or_else(|| node.get("message_type"));
+        if record.and_then(Value::as_str).is_some_and(|record| record != "node") {
+            return None;
+        }
+        let path = node.get("path").and_then(Value::as_str)?;
+        let kind = match node.get("type").and_then(Value::as_str) {
+            Some("file") => EntryKind::File,
+            Some("dir") => EntryKind::Dir,
+            Some("symlink") => EntryKind::Symlink,
+            _ => EntryKind::Other,
+        };
+        let go_mode = node.get("mode").and_then(Value::as_u64).unwrap_or(0o644);
+        let mut mode = (go_mode & 0o777) as u32;
+        for (go, unix) in [(GO_SETUID, 0o4000), (GO_SETGID, 0o2000), (GO_STICKY, 0o1000)] {
+            if go_mode & go != 0 {
+                mode |= unix;
+            }
+        }
+        Some(Ok(Entry {
+            path: PathBuf::from(path),
+            kind,
+            size: node.get("size").and_then(Value::as_u64).unwrap_or(0),
+            mode,
+            modified: node.get("mtime").and_then(Value::as_str).and_then(rfc3339),
+            link: node.get("linktarget").and_then(Value::as_str).map(PathBuf::from),
+        }))
+    })
+}
+
+/// Reads the entries of what `borg list --json-lines` writes of an
+/// archive, a JSON object on each line.
+pub fn borg_entries(listing: impl BufRead) -> impl Iterator<Item = io::Result<Entry>> {
+    json_lines(listing, "borg list").filter_map(|item| {
+        let item = match item {
+            Ok(item) => item,
+            Err(err) => return Some(Err(err)),
+        };
+        let path = item.get("path").and_then(Value::as_str)?;
+        let kind = match item.get("type").and_then(Value::as_str) {
+            // Borg 1.1 lists a hardlink's later names as `h`
+            Some("-" | "h") => EntryKind::File,
+            Some("d") => EntryKind::Dir,
+            Some("l") => EntryKind::Symlink,
+            _ => EntryKind::Other,
+        };
+        let mode = item.get("mode").and_then(Value::as_str).and_then(symbolic_mode);
+        let link = ["linktarget", "source"]
+            .iter()
+            .filter_map(|key| item.get(*key).and_then(Value::as_str))
+            .find(|link| !link.is_empty());
+        Some(Ok(Entry {
+            path: PathBuf::from(path),
+            kind,
+            size: item.get("size").and_then(Value::as_u64).unwrap_or(0),
+            mode: mode.unwrap_or(0o644),
+            modified: item.get("mtime").and_then(Value::as_str).and_then(local_time),
+            link: link.map(PathBuf::from),
+        }))
+    })
+}
+
+/// Reads a JSON object from each line that isn't blank.
+fn json_lines(listing: impl BufRead, tool: &'static str) -> impl Iterator<Item = io::Result<Value>> {
+    listing.lines().filter_map(move |line| match line {
+        Ok(line) if line.trim().is_empty() => None,
+        Ok(line) => Some(serde_json::from_str(&line).map_err(|err| invalid(format!("{}: {}", tool, err)))),
+        Err(err) => Some(Err(err)),
+    })
+}
+
+/// Reads a mode as `ls -l` writes it, such as `-rwsr-xr-x`.
+fn symbolic_mode(text: &str) -> Option<u32> {
+    let bits: Vec<char> = text.chars().collect();
+    let bits = bits.get(1..10)?;
+    let mut mode = 0;
+    for (index, &bit) in bits.iter().enumerate() {
+        let shift = 8 - index as u32;
+        let special = match index {
+            2 => 0o4000,
+            5 => 0o2000,
+            8 => 0o1000,
+            _ => 0,
+        };
+        match bit {
+            '-' => {}
+            'r' | 'w' | 'x' => mode |= 1 << shift,
+            's' | 't' => mode |= special | 1 << shift,
+            'S' | 'T' => mode |= special,
+            _ => return None,
+        }
+    }
+    Some(mode)
+}
+
+/// Reads a time restic writes, such as `2024-03-01T09:30:00.123456789+01:00`.
+fn rfc3339(text: &str) -> Option<SystemTime> {
+    DateTime::parse_from_rfc3339(text).ok().map(SystemTime::from)
+}
+
+/// Reads a time borg writes, such as `2024-03-01T09:30:00.123456`, which
+/// is in local time.
+fn local_time(text: &str) -> Option<SystemTime> {
+    if let Some(time) = rfc3339(text) {
+        return Some(time);
+    }
+    let time = NaiveDateTime::parse_from_str(text, "%Y-%m-%dT%H:%M:%S%.f").ok()?;
+    time.and_local_timezone(Local).earliest().map(SystemTime::from)
+}
+
+/// Returns a restic command for a repository, which doesn't lock it, so
+/// that read-only repositories can be listed too.
+fn restic(repository: &str) -> Command {
+    let mut command = Command::new("restic");
+    command.args(["--repo", repository, "--no-lock"]);
+    command
+}
+
+/// Runs a command and returns what it writes.
+fn run(command: &mut Command) -> io::Result<Vec<u8>> {
+    let output = command.stdin(Stdio::null()).output().map_err(|err| not_installed(command, err))?;
+    if !output.status.success() {
+        return Err(failed(command, &output.stderr));
+    }
+    Ok(output.stdout)
+}
+
+/// Starts a command whose output is read as it is written.
+fn spawn(command: &mut Command) -> io::Result<Child> {
+    command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|err| not_installed(command, err))
+}
+
+/// Waits for a command started by [`spawn`], failing if it did.
+fn finish(child: Child, name: &str) -> io::Result<()> {
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        let message = String::from_utf8_lossy(&output.stderr);
+        return Err(io::Error::other(format!("{} failed: {}", name, message.trim())));
+    }
+    Ok(())
+}
+
+fn not_installed(command: &Command, err: io::Error) -> io::Error {
+    match err.kind() {
+        io::ErrorKind::NotFound => io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("{} isn't installed", command.get_program().to_string_lossy()),
+        ),
+        _ => err,
+    }
+}
+
+fn failed(command: &Command, stderr: &[u8]) -> io::Error {
+    let message = String::from_utf8_lossy(stderr);
+    io::Error::other(format!(
+        "{} failed: {}",
+        command.get_program().to_string_lossy(),
+        message.trim()
+    ))
+}
+
+/// Returns a path relative to its snapshot, or None if it climbs out of it.
+fn relative_path(path: &Path) -> Option<PathBuf> {
+    let mut relative = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(part) => relative.push(part),
+            Component::CurDir | Component::RootDir => {}
+            Component::ParentDir | Component::Prefix(_) => return None,
+        }
+    }
+    (!relative.as_os_str().is_empty()).then_some(relative)
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
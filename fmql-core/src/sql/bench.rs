@@ -0,0 +1,380 @@
+//! Measuring how fast queries run on a file system, for `fmql bench`.
+//!
+//! [`run`] takes the entries under a directory, up to a limit, and times
+//! the stages of a query over them one at a time:
+//!
+//! - traversal: listing the directories, without reading any metadata
+//! - metadata: reading each entry's metadata once, as every query does,
+//!   timed one entry at a time for the spread of the latency
+//! - threads: reading it again with 1, 2, 4 and more threads, since network
+//!   file systems answer several requests at once faster than one by one
+//! - filters: a `SELECT` over the entries with each of a few conditions of
+//!   different cost, and the time each adds to one with no condition
+//!
+//! The [`Report`] has the measurements and suggestions drawn from them,
+//! such as a `--jobs` for file systems that reward threads, and ignore
+//! patterns for the `node_modules` and `.git` directories that make up
+//! much of many trees. Each stage sees the caches the ones before it
+//! warmed, so the traversal is the closest to a cold run; run it twice to
+//! see how much the caches help.
+//!
+//! # Examples
+//!
+//! ```
+//! use fmql_core::sql::bench::{run, BenchOptions};
+//!
+//! let dir = tempfile::tempdir().unwrap();
+//! for name in ["a.log", "b.log", "c.txt"] {
+//!     std::fs::write(dir.path().join(name), name).unwrap();
+//! }
+//! let options = BenchOptions {
+//!     conditions: vec!["extension = 'log'".to_string()],
+//!     ..BenchOptions::default()
+//! };
+//! let report = run(dir.path(), &options).unwrap();
+//! assert_eq!(report.entries, 3);
+//! let logs = report.filters.iter().find(|filter| filter.condition == "extension = 'log'").unwrap();
+//! assert_eq!(logs.matched, 2);
+//! assert!(logs.names_only);
+//! ```
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+use walkdir::WalkDir;
+
+use crate::error::FMQLError;
+use crate::sql::ast::{FileAttribute, FileQuery};
+use crate::sql::executor::{execute_query_with_options, ExecutionOptions};
+use crate::sql::parse_sql;
+use crate::sql::visit::Visitor;
+
+/// The conditions every run times, from cheap to dear: one on names, one
+/// on metadata and one that looks up owners' names.
+const CONDITIONS: [&str; 3] = ["name LIKE '%.log'", "size > 1048576", "owner = 'root'"];
+
+/// Directories that hold many files few queries are after.
+const NOISY: [&str; 6] = [".git", "node_modules", "target", "__pycache__", ".cache", ".venv"];
+
+/// What [`run`] measures.
+#[derive(Debug, Clone)]
+pub struct BenchOptions {
+    /// Take at most this many entries.
+    pub max_entries: usize,
+    /// Try at most this many threads.
+    pub max_threads: usize,
+    /// Conditions to time besides the standard ones, as WHERE clauses.
+    pub conditions: Vec<String>,
+    /// The options the filters run with, such as the encoding.
+    pub execution: ExecutionOptions,
+}
+
+impl Default for BenchOptions {
+    fn default() -> Self {
+        let cores = thread::available_parallelism().map_or(4, |cores| cores.get());
+        BenchOptions {
+            max_entries: 20_000,
+            max_threads: (cores * 2).min(32),
+            conditions: Vec::new(),
+            execution: ExecutionOptions::default(),
+        }
+    }
+}
+
+/// The measurements of a run of [`run`].
+#[derive(Debug, Clone, Serialize)]
+pub struct Report {
+    /// The directory measured.
+    pub path: PathBuf,
+    /// How many entries were taken.
+    pub entries: usize,
+    /// The directory has more entries than were taken.
+    pub truncated: bool,
+    /// How many entries a second were listed, without their metadata.
+    pub entries_per_second: f64,
+    /// How long reading an entry's metadata took.
+    pub metadata: Latency,
+    /// How many entries a second had their metadata read with each number
+    /// of threads.
+    pub threads: Vec<ThreadRun>,
+    /// How long each condition took over the entries.
+    pub filters: Vec<FilterCost>,
+    /// How many of the entries are in directories such as `node_modules`
+    /// and `.git`, by the name of the directory.
+    pub noisy: Vec<(String, usize)>,
+    /// What to change, drawn from the measurements.
+    pub suggestions: Vec<String>,
+}
+
+/// The spread of a latency, in microseconds.
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct Latency {
+    pub p50_us: f64,
+    pub p90_us: f64,
+    pub p99_us: f64,
+    pub max_us: f64,
+}
+
+/// How fast metadata was read with a number of threads.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct ThreadRun {
+    pub threads: usize,
+    pub entries_per_second: f64,
+}
+
+/// How long a query over the entries took with a condition.
+#[derive(Debug, Clone, Serialize)]
+pub struct FilterCost {
+    /// The condition, as a WHERE clause; empty for the query without one.
+    pub condition: String,
+    /// How many entries it matched.
+    pub matched: usize,
+    /// How long the query took, in seconds.
+    pub seconds: f64,
+    /// How long the condition added for each entry, over the query without
+    /// one, in microseconds.
+    pub us_per_entry: f64,
+    /// The condition reads only names and paths, which don't need an
+    /// entry's metadata.
+    pub names_only: bool,
+}
+
+/// Measures the file system under `path`; see the
+/// [module documentation](self).
+///
+/// # Errors
+///
+/// Fails if `path` can't be listed, or a condition doesn't parse or fails
+/// to run.
+pub fn run(path: &Path, options: &BenchOptions) -> Result<Report, FMQLError> {
+    if !path.is_dir() {
+        let message = format!("{} is not a directory", path.display());
+        return Err(std::io::Error::new(std::io::ErrorKind::NotADirectory, message).into());
+    }
+
+    let started = Instant::now();
+    let mut paths = Vec::new();
+    let mut truncated = false;
+    for entry in WalkDir::new(path).min_depth(1).follow_links(false) {
+        let Ok(entry) = entry else {
+            continue;
+        };
+        if paths.len() == options.max_entries {
+            truncated = true;
+            break;
+        }
+        paths.push(entry.into_path());
+    }
+    let entries_per_second = per_second(paths.len(), started.elapsed());
+
+    let mut latencies: Vec<Duration> = paths
+        .iter()
+        .map(|path| {
+            let started = Instant::now();
+            let _ = fs::symlink_metadata(path);
+            started.elapsed()
+        })
+        .collect();
+    latencies.sort();
+    let metadata = Latency {
+        p50_us: percentile(&latencies, 0.50),
+        p90_us: percentile(&latencies, 0.90),
+        p99_us: percentile(&latencies, 0.99),
+        max_us: percentile(&latencies, 1.0),
+    };
+
+    let threads = thread_counts(options.max_threads)
+        .into_iter()
+        .map(|threads| ThreadRun {
+            threads,
+            entries_per_second: per_second(paths.len(), stat_with(&paths, threads)),
+        })
+        .collect();
+
+    let mut conditions = vec![String::new()];
+    conditions.extend(CONDITIONS.iter().map(|condition| condition.to_string()));
+    conditions.extend(options.conditions.iter().cloned());
+    let mut filters = Vec::new();
+    for condition in conditions {
+        filters.push(time_filter(&paths, condition, &options.execution)?);
+    }
+    let baseline = filters[0].seconds;
+    for filter in &mut filters {
+        let added = (filter.seconds - baseline).max(0.0);
+        filter.us_per_entry = added * 1e6 / paths.len().max(1) as f64;
+    }
+
+    let noisy = NOISY
+        .iter()
+        .map(|name| {
+            let under = paths
+                .iter()
+                .filter(|entry| {
+                    let inside = entry.strip_prefix(path).unwrap_or(entry);
+                    inside.parent().is_some_and(|dir| dir.iter().any(|part| part == *name))
+                })
+                .count();
+            (name.to_string(), under)
+        })
+        .filter(|(_, under)| *under > 0)
+        .collect();
+
+    let mut report = Report {
+        path: path.to_path_buf(),
+        entries: paths.len(),
+        truncated,
+        entries_per_second,
+        metadata,
+        threads,
+        filters,
+        noisy,
+        suggestions: Vec::new(),
+    };
+    report.suggestions = suggest(&report);
+    Ok(report)
+}
+
+/// Returns the numbers of threads to try: 1, 2, 4 and so on up to `max`,
+/// and `max` itself.
+fn thread_counts(max: usize) -> Vec<usize> {
+    let max = max.max(1);
+    let mut counts: Vec<usize> = std::iter::successors(Some(1), |n| Some(n * 2))
+        .take_while(|n| *n < max)
+        .collect();
+    counts.push(max);
+    counts
+}
+
+/// Reads the metadata of every path with `threads` threads, and returns
+/// how long it took.
+fn stat_with(paths: &[PathBuf], threads: usize) -> Duration {
+    let started = Instant::now();
+    let chunk = paths.len().div_ceil(threads).max(1);
+    thread::scope(|scope| {
+        for part in paths.chunks(chunk) {
+            scope.spawn(move || {
+                for path in part {
+                    let _ = fs::symlink_metadata(path);
+                }
+            });
+        }
+    });
+    started.elapsed()
+}
+
+/// Runs a SELECT of `paths` with a condition, or without one if it is
+/// empty, and times it.
+fn time_filter(
+    paths: &[PathBuf],
+    condition: String,
+    execution: &ExecutionOptions,
+) -> Result<FilterCost, FMQLError> {
+    let sql = match condition.as_str() {
+        "" => "SELECT * FROM STDIN".to_string(),
+        condition => format!("SELECT * FROM STDIN WHERE {}", condition),
+    };
+    let query = parse_sql(&sql)?;
+    let mut names = NamesOnly(true);
+    if let FileQuery::Select { condition: Some(condition), .. } = &query {
+        names.visit_condition(condition);
+    }
+    let options = ExecutionOptions {
+        files_from: Some(paths.to_vec()),
+        ..execution.clone()
+    };
+    let started = Instant::now();
+    let matched = execute_query_with_options(&query, &options)?.len();
+    Ok(FilterCost {
+        condition,
+        matched,
+        seconds: started.elapsed().as_secs_f64(),
+        us_per_entry: 0.0,
+        names_only: names.0,
+    })
+}
+
+/// Finds whether a query reads nothing but names and paths.
+struct NamesOnly(bool);
+
+impl Visitor<'_> for NamesOnly {
+    fn visit_attribute(&mut self, attribute: &FileAttribute) {
+        if !matches!(
+            attribute,
+            FileAttribute::Name | FileAttribute::Path | FileAttribute::Extension
+        ) {
+            self.0 = false;
+        }
+    }
+}
+
+/// Draws suggestions from the measurements.
+fn suggest(report: &Report) -> Vec<String> {
+    let mut suggestions = Vec::new();
+    let single = report.threads.first().map_or(0.0, |run| run.entries_per_second);
+    let best = report
+        .threads
+        .iter()
+        .max_by(|a, b| a.entries_per_second.total_cmp(&b.entries_per_second));
+    if let Some(best) = best
+        && best.threads > 1
+        && best.entries_per_second > single * 1.3
+    {
+        suggestions.push(format!(
+            "Reading metadata with {} threads was {:.1} times as fast as with one: \
+             --jobs {} runs UPDATE, COPY and GROUP BY DIRNAME(path) with that many",
+            best.threads,
+            best.entries_per_second / single.max(f64::MIN_POSITIVE),
+            best.threads
+        ));
+    }
+    if report.metadata.p50_us > 1000.0 {
+        suggestions.push(format!(
+            "Reading an entry's metadata takes {:.1}ms, as on a network file system: \
+             --checkpoint lets a long scan resume where it stopped, and --io-retries \
+             rides out timeouts",
+            report.metadata.p50_us / 1000.0
+        ));
+    }
+    for filter in &report.filters {
+        let rejected = report.entries.saturating_sub(filter.matched);
+        if filter.names_only && report.entries >= 1000 && rejected * 10 >= report.entries * 9 {
+            suggestions.push(format!(
+                "{} matched {} of {} entries but reads only names: every entry's metadata \
+                 is read before a condition is checked, so a FROM glob or narrower \
+                 directory that lists fewer entries would be faster",
+                filter.condition, filter.matched, report.entries
+            ));
+        }
+    }
+    let noisy: usize = report.noisy.iter().map(|(_, under)| under).sum();
+    if noisy * 4 >= report.entries && noisy > 0 {
+        let names: Vec<&str> = report.noisy.iter().map(|(name, _)| name.as_str()).collect();
+        let quoted: Vec<String> = names.iter().map(|name| format!("\"{}\"", name)).collect();
+        suggestions.push(format!(
+            "{} of {} entries are inside {}: ignore = [{}] in a config profile leaves \
+             them out of every scan",
+            noisy,
+            report.entries,
+            names.join(", "),
+            quoted.join(", ")
+        ));
+    }
+    suggestions
+}
+
+/// Returns how many a second `count` in `elapsed` is.
+fn per_second(count: usize, elapsed: Duration) -> f64 {
+    count as f64 / elapsed.as_secs_f64().max(1e-9)
+}
+
+/// Returns a percentile of sorted durations, in microseconds.
+fn percentile(sorted: &[Duration], fraction: f64) -> f64 {
+    if sorted.is_empty() {
+        return 0.0;
+    }
+    let index = ((sorted.len() - 1) as f64 * fraction).round() as usize;
+    sorted[index].as_secs_f64() * 1e6
+}
@@ -0,0 +1,129 @@
+//! Time budgets for expensive predicates, so that the occasional huge file
+//! doesn't hold up a whole content scan.
+//!
+//! `CONTAINS_STR(content, 'secret') BUDGET '2s' PER FILE` gives the
+//! predicate two seconds for each file. Reading a file's contents, for
+//! `content` and `CONTAINS_BYTES`, checks the budget as it goes and gives
+//! up once it is spent. The file then doesn't match, and is logged with
+//! the query's other [faults](crate::sql::faults), or fails the query
+//! under strict evaluation. Predicates on names, sizes, dates and the
+//! other metadata never read a file, and finish well inside any budget.
+//!
+//! The budget is kept per thread, so files evaluated side by side each
+//! get their own.
+//!
+//! # Examples
+//!
+//! ```
+//! use std::io::Read;
+//! use std::time::Duration;
+//! use fmql_core::sql::budget::{spend, Budgeted};
+//!
+//! let read = |limit| {
+//!     spend(limit, || {
+//!         let mut text = String::new();
+//!         Budgeted::new("secret".as_bytes()).read_to_string(&mut text).map(|_| text)
+//!     })
+//! };
+//! assert_eq!(read(Duration::from_secs(2)).unwrap().unwrap(), "secret");
+//!
+//! // A budget spent before the first read gives up on it
+//! let exceeded = read(Duration::ZERO).unwrap_err();
+//! assert_eq!(exceeded.limit, Duration::ZERO);
+//! ```
+
+use std::cell::Cell;
+use std::fmt;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::time::{Duration, Instant};
+
+use crate::sql::meter;
+
+/// The most a [`Budgeted`] reader reads at once, so that the budget is
+/// checked every so often however much is asked for.
+const CHUNK: usize = 1024 * 1024;
+
+thread_local! {
+    /// When the budget being spent on this thread runs out.
+    static DEADLINE: Cell<Option<Instant>> = const { Cell::new(None) };
+    /// Set when a read found the budget spent, so that it counts even if
+    /// whoever read takes the failed read for a missing value.
+    static SPENT: Cell<bool> = const { Cell::new(false) };
+}
+
+/// A predicate gave up on a file when its budget ran out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Exceeded {
+    /// The budget for each file.
+    pub limit: Duration,
+}
+
+impl fmt::Display for Exceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "gave up after the condition's budget of {:?} for each file", self.limit)
+    }
+}
+
+impl std::error::Error for Exceeded {}
+
+/// Runs `evaluate` with `limit` to spend, and returns what it returns, or
+/// [`Exceeded`] if a [`Budgeted`] read inside it found the budget spent.
+/// A budget inside another ends when either does.
+pub fn spend<T>(limit: Duration, evaluate: impl FnOnce() -> T) -> Result<T, Exceeded> {
+    let outer = DEADLINE.get();
+    let deadline = Instant::now().checked_add(limit);
+    DEADLINE.set(match (outer, deadline) {
+        (Some(outer), Some(deadline)) => Some(outer.min(deadline)),
+        (outer, deadline) => deadline.or(outer),
+    });
+    let spent_before = SPENT.replace(false);
+    let value = evaluate();
+    let spent = SPENT.replace(spent_before);
+    DEADLINE.set(outer);
+    match spent {
+        true => Err(Exceeded { limit }),
+        false => Ok(value),
+    }
+}
+
+/// Fails if a budget is being spent on this thread and has run out.
+pub fn check() -> io::Result<()> {
+    match DEADLINE.get() {
+        Some(deadline) if Instant::now() >= deadline => {
+            SPENT.set(true);
+            Err(io::Error::other("the condition's budget for the file ran out"))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// A reader that checks the budget before every read, for the readers of
+/// file contents, and charges what it reads to the
+/// [meter](crate::sql::meter) of the run.
+#[derive(Debug)]
+pub struct Budgeted<R> {
+    inner: R,
+}
+
+impl<R> Budgeted<R> {
+    /// Wraps a reader.
+    pub fn new(inner: R) -> Self {
+        Budgeted { inner }
+    }
+}
+
+impl<R: Read> Read for Budgeted<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        check()?;
+        let len = buf.len().min(CHUNK);
+        let read = self.inner.read(&mut buf[..len])?;
+        meter::charge(read)?;
+        Ok(read)
+    }
+}
+
+impl<R: Seek> Seek for Budgeted<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.inner.seek(pos)
+    }
+}
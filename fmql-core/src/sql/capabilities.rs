@@ -0,0 +1,433 @@
+//! What the file system under a path can record, for `SHOW FILESYSTEM`.
+//!
+//! Attributes that fmql reads from every file are not recorded by every
+//! file system: a FAT-formatted USB stick has no symlinks and doesn't record
+//! creation times, and network shares often ignore the case of names.
+//! [`probe`] finds out what a file system can do, without writing to it:
+//!
+//! - `filesystem`: the kind of file system, such as `ext4` or `apfs`
+//! - `case_sensitive`: whether `a.txt` and `A.txt` are different files,
+//!   judged by looking up an existing name with its case changed, so an
+//!   empty directory can't tell
+//! - `symlinks` and `xattrs`: whether it supports symbolic links and
+//!   extended attributes
+//! - `birthtime`: whether it records when a file was created
+//! - `max_name_length` and `max_path_length`: the longest name and path,
+//!   in bytes
+//!
+//! Anything that can't be found out is NULL. Queries degrade rather than
+//! fail where a capability is missing (`created` is NULL, `is_symlink` is
+//! never true), and [`warnings`] says so before the query runs, the way
+//! [`lint`](crate::sql::lint::lint) does for the query text.
+//!
+//! # Examples
+//!
+//! ```
+//! use fmql_core::sql::capabilities::probe;
+//!
+//! let dir = tempfile::tempdir().unwrap();
+//! let capabilities = probe(dir.path()).unwrap();
+//! assert_eq!(capabilities.path, dir.path());
+//! assert!(capabilities.max_name_length.is_some_and(|max| max >= 14));
+//! ```
+
+use std::collections::HashSet;
+use std::ffi::CString;
+use std::fs;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+
+use crate::sql::ast::{EntryType, FileAttribute, FileCondition, FileQuery, FileValue};
+use crate::sql::executor::GroupRow;
+use crate::sql::chattr;
+use crate::sql::filecaps;
+use crate::sql::quarantine;
+use crate::sql::flags;
+use crate::sql::selinux;
+use crate::sql::extents;
+use crate::sql::lint::LintWarning;
+use crate::sql::paths::expand_targets;
+use crate::sql::users::group_exists;
+use crate::sql::visit::{walk_condition, Visitor};
+
+/// The entries of a directory looked at to tell whether names are case
+/// sensitive.
+const CASE_PROBES: usize = 64;
+
+/// What the file system holding a path supports. None means it couldn't be
+/// found out.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Capabilities {
+    /// The path probed.
+    pub path: PathBuf,
+    /// The kind of file system, such as `ext4`, or its magic number when
+    /// the kind isn't one fmql knows.
+    pub filesystem: Option<String>,
+    /// Whether names that differ only in case are different files.
+    pub case_sensitive: Option<bool>,
+    /// Whether symbolic links can be made.
+    pub symlinks: Option<bool>,
+    /// Whether files can have extended attributes.
+    pub xattrs: Option<bool>,
+    /// Whether the time a file was created is recorded.
+    pub birthtime: Option<bool>,
+    /// The longest file name, in bytes.
+    pub max_name_length: Option<u64>,
+    /// The longest relative path, in bytes.
+    pub max_path_length: Option<u64>,
+}
+
+impl Capabilities {
+    /// Returns the capabilities as a row of `SHOW FILESYSTEM`.
+    pub fn to_row(&self) -> GroupRow {
+        let text = |value: &Option<String>| {
+            value.clone().map_or(FileValue::Null, FileValue::String)
+        };
+        let flag = |value: Option<bool>| value.map_or(FileValue::Null, FileValue::Boolean);
+        let number = |value: Option<u64>| {
+            value.map_or(FileValue::Null, FileValue::from_u64)
+        };
+        GroupRow {
+            columns: vec![
+                ("path".to_string(), FileValue::String(self.path.display().to_string())),
+                ("filesystem".to_string(), text(&self.filesystem)),
+                ("case_sensitive".to_string(), flag(self.case_sensitive)),
+                ("symlinks".to_string(), flag(self.symlinks)),
+                ("xattrs".to_string(), flag(self.xattrs)),
+                ("birthtime".to_string(), flag(self.birthtime)),
+                ("max_name_length".to_string(), number(self.max_name_length)),
+                ("max_path_length".to_string(), number(self.max_path_length)),
+            ],
+        }
+    }
+}
+
+/// Finds out what the file system holding `path` supports. A file is
+/// probed through the directory it is in.
+///
+/// # Errors
+///
+/// Fails if `path` doesn't exist or can't be read.
+pub fn probe(path: &Path) -> io::Result<Capabilities> {
+    let dir = if fs::metadata(path)?.is_dir() {
+        path
+    } else {
+        path.parent().filter(|parent| !parent.as_os_str().is_empty()).unwrap_or(Path::new("."))
+    };
+    let c_path = CString::new(dir.as_os_str().as_bytes())?;
+    let filesystem = filesystem_name(&c_path);
+    let no_symlinks = filesystem.as_deref().is_some_and(|name| matches!(name, "vfat" | "exfat"));
+    let birthtime = match fs::metadata(dir)?.created() {
+        Ok(_) => Some(true),
+        Err(err) if err.kind() == io::ErrorKind::Unsupported => Some(false),
+        Err(_) => None,
+    };
+    Ok(Capabilities {
+        path: path.to_path_buf(),
+        case_sensitive: case_sensitive(dir),
+        symlinks: if no_symlinks {
+            Some(false)
+        } else {
+            path_limit(&c_path, libc::_PC_2_SYMLINKS).map(|supported| supported > 0)
+        },
+        xattrs: xattrs(&c_path),
+        birthtime,
+        max_name_length: path_limit(&c_path, libc::_PC_NAME_MAX),
+        max_path_length: path_limit(&c_path, libc::_PC_PATH_MAX),
+        filesystem,
+    })
+}
+
+/// Returns the kind of file system `path` is on, such as `ext4`, if it can
+/// be found out.
+pub fn filesystem(path: &Path) -> Option<String> {
+    filesystem_name(&CString::new(path.as_os_str().as_bytes()).ok()?)
+}
+
+/// Returns true if `path` is on a file system made for Windows, or shared
+/// from it, so that its names must be ones Windows can hold.
+pub fn holds_windows_names(path: &Path) -> bool {
+    cfg!(windows)
+        || filesystem(path).is_some_and(|name| {
+            matches!(name.as_str(), "vfat" | "exfat" | "ntfs" | "ntfs3" | "cifs" | "smb2" | "msdos")
+        })
+}
+
+/// Warns about attributes a query uses that the file systems it scans
+/// don't record, such as `created` on a FAT drive, or that this system
+/// doesn't have, such as `flags` on Linux. Each file system the
+/// query's paths are on is probed once; file systems mounted further down
+/// a recursive scan are not.
+///
+/// The warning code is `unsupported_attribute`, or `unknown_group` for an
+/// `OWNER_IN_GROUP` group the system group database doesn't have.
+pub fn warnings(query: &FileQuery) -> Vec<LintWarning> {
+    let (path, entry_type) = match query {
+        FileQuery::Select { path, entry_type, .. } | FileQuery::Update { path, entry_type, .. } => {
+            (path, *entry_type)
+        }
+        FileQuery::Copy { path, .. }
+        | FileQuery::Compress { path, .. }
+        | FileQuery::Extract { path, .. }
+        | FileQuery::Archive { path, .. }
+        | FileQuery::Restore { path, .. }
+        | FileQuery::ShowFilesystem { path } => (path, None),
+        // Nothing is scanned
+        FileQuery::Explain { .. } => return Vec::new(),
+    };
+    if query.lists_paths() || matches!(query, FileQuery::ShowFilesystem { .. }) {
+        return Vec::new();
+    }
+    let mut attributes = Attributes(Vec::new());
+    attributes.visit_query(query);
+    let attributes = attributes.0;
+    let symlinks_wanted =
+        attributes.contains(&FileAttribute::IsSymlink) || entry_type == Some(EntryType::Symlink);
+    let mut warnings = Vec::new();
+    let mut groups = Groups(Vec::new());
+    groups.visit_query(query);
+    for group in groups.0 {
+        if !group_exists(group) {
+            warnings.push(LintWarning {
+                code: "unknown_group",
+                message: format!("there is no group '{}' here, so OWNER_IN_GROUP is false", group),
+            });
+        }
+    }
+    if attributes.contains(&FileAttribute::Flags) && !flags::supported() {
+        warnings.push(LintWarning {
+            code: "unsupported_attribute",
+            message: "file flags are a macOS and BSD feature, so flags is NULL here".to_string(),
+        });
+    }
+    if attributes.contains(&FileAttribute::FsFlags) && !chattr::supported() {
+        warnings.push(LintWarning {
+            code: "unsupported_attribute",
+            message: "file attributes are a Linux feature, so fs_flags is NULL here".to_string(),
+        });
+    }
+    if attributes.contains(&FileAttribute::SecurityContext) && !selinux::supported() {
+        warnings.push(LintWarning {
+            code: "unsupported_attribute",
+            message: "security contexts are a Linux feature, so security_context is NULL here"
+                .to_string(),
+        });
+    }
+    let caps_wanted = attributes.contains(&FileAttribute::FileCapabilities)
+        || attributes.contains(&FileAttribute::HasFileCapabilities);
+    if caps_wanted && !filecaps::supported() {
+        warnings.push(LintWarning {
+            code: "unsupported_attribute",
+            message: "file capabilities are a Linux feature, so file_capabilities is NULL here"
+                .to_string(),
+        });
+    }
+    let quarantine_wanted = attributes.contains(&FileAttribute::Quarantine)
+        || attributes.contains(&FileAttribute::WhereFrom);
+    if quarantine_wanted && !quarantine::supported() {
+        warnings.push(LintWarning {
+            code: "unsupported_attribute",
+            message: "quarantine records are a macOS feature, so quarantine and where_from are \
+                      NULL here"
+                .to_string(),
+        });
+    }
+    if attributes.contains(&FileAttribute::SharedExtents) && !extents::supported() {
+        warnings.push(LintWarning {
+            code: "unsupported_attribute",
+            message: "fmql reads shared extents with a Linux ioctl, so shared_extents is NULL here"
+                .to_string(),
+        });
+    }
+    if !attributes.contains(&FileAttribute::Created) && !symlinks_wanted {
+        return warnings;
+    }
+
+    let mut devices = HashSet::new();
+    for target in expand_targets(path).unwrap_or_default() {
+        let Ok(metadata) = fs::metadata(&target) else {
+            continue;
+        };
+        if !devices.insert(metadata.dev()) {
+            continue;
+        }
+        let Ok(capabilities) = probe(&target) else {
+            continue;
+        };
+        let filesystem = capabilities.filesystem.as_deref().unwrap_or("file system");
+        let mut warn = |what: &str| {
+            warnings.push(LintWarning {
+                code: "unsupported_attribute",
+                message: format!("{} at {} {}", filesystem, target.display(), what),
+            });
+        };
+        if attributes.contains(&FileAttribute::Created) && capabilities.birthtime == Some(false) {
+            warn("doesn't record when files were created, so created is NULL");
+        }
+        if symlinks_wanted && capabilities.symlinks == Some(false) {
+            warn("has no symlinks, so none will be found");
+        }
+    }
+    warnings
+}
+
+/// Collects the attributes a query reads.
+struct Attributes(Vec<FileAttribute>);
+
+impl Visitor<'_> for Attributes {
+    fn visit_attribute(&mut self, attribute: &FileAttribute) {
+        self.0.push(attribute.clone());
+    }
+}
+
+/// Collects the groups a query's `OWNER_IN_GROUP` conditions name.
+struct Groups<'ast>(Vec<&'ast str>);
+
+impl<'ast> Visitor<'ast> for Groups<'ast> {
+    fn visit_condition(&mut self, condition: &'ast FileCondition) {
+        if let FileCondition::OwnerInGroup { group } = condition {
+            self.0.push(group);
+        }
+        walk_condition(self, condition);
+    }
+}
+
+/// Tells whether names in `dir` are case sensitive by looking up some of
+/// its entries with the case of their names changed. On a file system that
+/// ignores case, that finds the entry itself.
+fn case_sensitive(dir: &Path) -> Option<bool> {
+    let entries = fs::read_dir(dir).ok()?;
+    for entry in entries.flatten().take(CASE_PROBES) {
+        let name = entry.file_name();
+        let Some(name) = name.to_str() else {
+            continue;
+        };
+        let flipped: String = name
+            .chars()
+            .map(|c| {
+                if c.is_lowercase() {
+                    c.to_uppercase().next().unwrap_or(c)
+                } else {
+                    c.to_lowercase().next().unwrap_or(c)
+                }
+            })
+            .collect();
+        if flipped == name {
+            continue;
+        }
+        let Ok(original) = entry.metadata() else {
+            continue;
+        };
+        match dir.join(&flipped).symlink_metadata() {
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Some(true),
+            Err(_) => continue,
+            // A second name for the same file is told apart by listing it
+            Ok(found) if found.dev() == original.dev() && found.ino() == original.ino() => {
+                let listed = fs::read_dir(dir)
+                    .ok()?
+                    .flatten()
+                    .any(|entry| entry.file_name().to_str() == Some(flipped.as_str()));
+                return Some(listed);
+            }
+            Ok(_) => return Some(true),
+        }
+    }
+    None
+}
+
+/// Reads one of the limits `pathconf` reports for a path.
+fn path_limit(path: &CString, name: libc::c_int) -> Option<u64> {
+    // SAFETY: the path is a NUL-terminated string that outlives the call.
+    let limit = unsafe { libc::pathconf(path.as_ptr(), name) };
+    u64::try_from(limit).ok()
+}
+
+/// Tells whether a file system supports extended attributes, by listing
+/// those of the path.
+#[cfg(any(target_os = "linux", target_vendor = "apple"))]
+fn xattrs(path: &CString) -> Option<bool> {
+    // SAFETY: the path is a NUL-terminated string that outlives the call,
+    // and a null buffer of size zero only asks for the length.
+    #[cfg(target_os = "linux")]
+    let listed = unsafe { libc::llistxattr(path.as_ptr(), std::ptr::null_mut(), 0) };
+    // SAFETY: as above.
+    #[cfg(target_vendor = "apple")]
+    let listed = unsafe {
+        libc::listxattr(path.as_ptr(), std::ptr::null_mut(), 0, libc::XATTR_NOFOLLOW)
+    };
+    if listed >= 0 {
+        return Some(true);
+    }
+    match io::Error::last_os_error().raw_os_error() {
+        Some(libc::ENOTSUP) => Some(false),
+        _ => None,
+    }
+}
+
+/// Extended attributes aren't probed on this platform.
+#[cfg(not(any(target_os = "linux", target_vendor = "apple")))]
+fn xattrs(_path: &CString) -> Option<bool> {
+    None
+}
+
+/// Names the file system a path is on from its magic number.
+#[cfg(target_os = "linux")]
+fn filesystem_name(path: &CString) -> Option<String> {
+    // SAFETY: statfs is plain data, which the call fills in.
+    let mut info: libc::statfs = unsafe { std::mem::zeroed() };
+    // SAFETY: the path is a NUL-terminated string and `info` is a valid
+    // statfs, both outliving the call.
+    if unsafe { libc::statfs(path.as_ptr(), &mut info) } != 0 {
+        return None;
+    }
+    // The field's type differs between architectures; the magic fits in 32 bits
+    let magic = info.f_type as u64 & 0xffff_ffff;
+    let name = match magic {
+        0xef53 => "ext4",
+        0x9123_683e => "btrfs",
+        0x5846_5342 => "xfs",
+        0x2fc1_2fc1 => "zfs",
+        0xf2f5_2010 => "f2fs",
+        0x0102_1994 => "tmpfs",
+        0x794c_7630 => "overlay",
+        0x6969 => "nfs",
+        0xff53_4d42 => "cifs",
+        0xfe53_4d42 => "smb2",
+        0x4d44 => "vfat",
+        0x2011_bab0 => "exfat",
+        0x5346_544e => "ntfs",
+        0x7366_746e => "ntfs3",
+        0x6573_5546 => "fuse",
+        0x9fa0 => "proc",
+        0x6265_6572 => "sysfs",
+        0x4244 => "hfs",
+        0x482b => "hfsplus",
+        0x9660 => "iso9660",
+        0x1573_3105 => "udf",
+        _ => return Some(format!("{:#x}", magic)),
+    };
+    Some(name.to_string())
+}
+
+/// Names the file system a path is on, as the kernel does.
+#[cfg(target_vendor = "apple")]
+fn filesystem_name(path: &CString) -> Option<String> {
+    // SAFETY: statfs is plain data, which the call fills in.
+    let mut info: libc::statfs = unsafe { std::mem::zeroed() };
+    // SAFETY: the path is a NUL-terminated string and `info` is a valid
+    // statfs, both outliving the call.
+    if unsafe { libc::statfs(path.as_ptr(), &mut info) } != 0 {
+        return None;
+    }
+    // SAFETY: the kernel NUL-terminates the name within the array.
+    let name = unsafe { std::ffi::CStr::from_ptr(info.f_fstypename.as_ptr()) };
+    Some(name.to_string_lossy().into_owned())
+}
+
+/// File system names aren't looked up on this platform.
+#[cfg(not(any(target_os = "linux", target_vendor = "apple")))]
+fn filesystem_name(_path: &CString) -> Option<String> {
+    None
+}
@@ -0,0 +1,554 @@
+//! Introspection registry for the fmql query language.
+//!
+//! This module lists the attributes, functions and keywords that queries can
+//! use, together with their types and short descriptions. It is the single
+//! source of truth for interactive tooling: the REPL's `SHOW COLUMNS`
+//! command, syntax highlighting, and tab completion are all driven from here.
+//!
+//! # Examples
+//!
+//! ```
+//! use fmql_core::sql::catalog::{columns, ValueType};
+//!
+//! let size = columns().iter().find(|c| c.name == "size").unwrap();
+//! assert_eq!(size.value_type, ValueType::Number);
+//! ```
+
+use serde::Serialize;
+
+use crate::sql::ast::FileAttribute;
+use crate::sql::lexer::KEYWORDS;
+
+/// The type of value an attribute or function produces.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ValueType {
+    /// A string value.
+    String,
+    /// A numeric value.
+    Number,
+    /// A date/time value.
+    DateTime,
+    /// A length of time.
+    Duration,
+    /// A boolean value.
+    Boolean,
+}
+
+impl std::fmt::Display for ValueType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            ValueType::String => "string",
+            ValueType::Number => "number",
+            ValueType::DateTime => "datetime",
+            ValueType::Duration => "duration",
+            ValueType::Boolean => "boolean",
+        };
+        f.pad(name)
+    }
+}
+
+/// Describes a queryable file attribute.
+#[derive(Debug, Clone, Serialize)]
+pub struct ColumnInfo {
+    /// The attribute name as written in queries.
+    pub name: &'static str,
+    /// The type of the attribute's values.
+    pub value_type: ValueType,
+    /// A one-line description.
+    pub description: &'static str,
+}
+
+/// Describes a function that can be called in queries.
+#[derive(Debug, Clone, Serialize)]
+pub struct FunctionInfo {
+    /// The function name as written in queries.
+    pub name: &'static str,
+    /// The call signature, e.g. `REGEXP(attribute, pattern)`.
+    pub signature: &'static str,
+    /// The type of the function's result.
+    pub value_type: ValueType,
+    /// A one-line description.
+    pub description: &'static str,
+}
+
+const COLUMNS: &[ColumnInfo] = &[
+    ColumnInfo {
+        name: "name",
+        value_type: ValueType::String,
+        description: "The file name without path",
+    },
+    ColumnInfo {
+        name: "path",
+        value_type: ValueType::String,
+        description: "The full file path",
+    },
+    ColumnInfo {
+        name: "size",
+        value_type: ValueType::Number,
+        description: "The file size in bytes",
+    },
+    ColumnInfo {
+        name: "extension",
+        value_type: ValueType::String,
+        description: "The file extension (part after the last dot), NULL if there is none",
+    },
+    ColumnInfo {
+        name: "modified",
+        value_type: ValueType::DateTime,
+        description: "The file modification time",
+    },
+    ColumnInfo {
+        name: "created",
+        value_type: ValueType::DateTime,
+        description: "The file creation time, where the file system records it",
+    },
+    ColumnInfo {
+        name: "accessed",
+        value_type: ValueType::DateTime,
+        description: "The file access time",
+    },
+    ColumnInfo {
+        name: "age",
+        value_type: ValueType::Duration,
+        description: "How long ago the file was modified (compare with intervals, e.g. '90 days')",
+    },
+    ColumnInfo {
+        name: "source_root",
+        value_type: ValueType::String,
+        description: "The path after FROM that the file was found under (NULL for FROM STDIN)",
+    },
+    ColumnInfo {
+        name: "scan_id",
+        value_type: ValueType::String,
+        description: "An id for the query run that found the file, for telling merged runs apart",
+    },
+    ColumnInfo {
+        name: "permissions",
+        value_type: ValueType::Number,
+        description: "The file permissions (compare with octal strings, e.g. '644')",
+    },
+    ColumnInfo {
+        name: "owner",
+        value_type: ValueType::String,
+        description: "The file owner username",
+    },
+    ColumnInfo {
+        name: "group",
+        value_type: ValueType::String,
+        description: "The name of the file's group",
+    },
+    ColumnInfo {
+        name: "is_directory",
+        value_type: ValueType::Boolean,
+        description: "Whether the file is a directory",
+    },
+    ColumnInfo {
+        name: "is_symlink",
+        value_type: ValueType::Boolean,
+        description: "Whether the file is a symbolic link",
+    },
+    ColumnInfo {
+        name: "is_executable",
+        value_type: ValueType::Boolean,
+        description: "Whether the file is executable by its owner",
+    },
+    ColumnInfo {
+        name: "is_world_readable",
+        value_type: ValueType::Boolean,
+        description: "Whether anyone may read the file (o+r)",
+    },
+    ColumnInfo {
+        name: "is_group_writable",
+        value_type: ValueType::Boolean,
+        description: "Whether the file's group may write to it (g+w)",
+    },
+    ColumnInfo {
+        name: "is_other_executable",
+        value_type: ValueType::Boolean,
+        description: "Whether anyone may execute the file or search the directory (o+x)",
+    },
+    ColumnInfo {
+        name: "is_mine",
+        value_type: ValueType::Boolean,
+        description: "Whether the file belongs to the user fmql runs as",
+    },
+    ColumnInfo {
+        name: "preview",
+        value_type: ValueType::String,
+        description: "The first few lines of the file (a hex dump for binary files)",
+    },
+    ColumnInfo {
+        name: "content",
+        value_type: ValueType::String,
+        description: "The file's contents, for LIKE, REGEXP (line by line) and CONTAINS_STR",
+    },
+    ColumnInfo {
+        name: "matches",
+        value_type: ValueType::String,
+        description: "The numbered lines that matched the WHERE clause's content checks",
+    },
+    ColumnInfo {
+        name: "signature_extension",
+        value_type: ValueType::String,
+        description: "The extension implied by the file's magic bytes (NULL if unrecognised)",
+    },
+    ColumnInfo {
+        name: "extension_mismatch",
+        value_type: ValueType::Boolean,
+        description: "Whether the magic bytes name a format the extension doesn't (e.g. a PNG called .jpg)",
+    },
+    ColumnInfo {
+        name: "uncompressed_size",
+        value_type: ValueType::Number,
+        description: "The size of a .gz, .zst or .xz file's contents, if the format records it",
+    },
+    ColumnInfo {
+        name: "disk_usage",
+        value_type: ValueType::Number,
+        description: "The bytes allocated to the file on disk, as du counts them (size is ls -l's)",
+    },
+    ColumnInfo {
+        name: "is_sparse",
+        value_type: ValueType::Boolean,
+        description: "Whether the file has holes, with fewer bytes allocated on disk than its size",
+    },
+    ColumnInfo {
+        name: "shared_extents",
+        value_type: ValueType::Number,
+        description: "The bytes in extents shared with reflinked copies or snapshots (Linux only)",
+    },
+    ColumnInfo {
+        name: "flags",
+        value_type: ValueType::String,
+        description: "The file flags chflags sets, such as uchg or hidden (macOS and BSD only)",
+    },
+    ColumnInfo {
+        name: "fs_flags",
+        value_type: ValueType::String,
+        description: "The file attributes chattr sets, such as immutable or append (Linux only)",
+    },
+    ColumnInfo {
+        name: "security_context",
+        value_type: ValueType::String,
+        description: "The SELinux label ls -Z shows, such as system_u:object_r:etc_t:s0 (Linux only)",
+    },
+    ColumnInfo {
+        name: "file_capabilities",
+        value_type: ValueType::String,
+        description: "The capabilities getcap shows, such as cap_net_bind_service+ep (Linux only)",
+    },
+    ColumnInfo {
+        name: "has_file_capabilities",
+        value_type: ValueType::Boolean,
+        description: "Whether setcap gave the file capabilities (Linux only)",
+    },
+    ColumnInfo {
+        name: "quarantine",
+        value_type: ValueType::String,
+        description: "The app that downloaded the file, such as Safari (macOS only)",
+    },
+    ColumnInfo {
+        name: "where_from",
+        value_type: ValueType::String,
+        description: "The URLs the file was downloaded from, separated by spaces (macOS only)",
+    },
+    ColumnInfo {
+        name: "pdf_page_count",
+        value_type: ValueType::Number,
+        description: "How many pages a PDF has (NULL for other files)",
+    },
+    ColumnInfo {
+        name: "doc_title",
+        value_type: ValueType::String,
+        description: "The title a PDF, Word, Excel or PowerPoint file records",
+    },
+    ColumnInfo {
+        name: "doc_author",
+        value_type: ValueType::String,
+        description: "The author a PDF, Word, Excel or PowerPoint file records",
+    },
+    ColumnInfo {
+        name: "line_endings",
+        value_type: ValueType::String,
+        description: "lf, crlf, cr or mixed (NULL for binary files and files without line breaks)",
+    },
+    ColumnInfo {
+        name: "has_bom",
+        value_type: ValueType::Boolean,
+        description: "Whether a file starts with a UTF-8 or UTF-16 byte order mark",
+    },
+    ColumnInfo {
+        name: "binary_format",
+        value_type: ValueType::String,
+        description: "elf, pe or mach-o, from a binary's headers (NULL for other files)",
+    },
+    ColumnInfo {
+        name: "target_arch",
+        value_type: ValueType::String,
+        description: "The architecture a binary was built for, as uname -m names it",
+    },
+    ColumnInfo {
+        name: "is_stripped",
+        value_type: ValueType::Boolean,
+        description: "Whether a binary's symbols were stripped (NULL for other files)",
+    },
+    ColumnInfo {
+        name: "original_path",
+        value_type: ValueType::String,
+        description: "Where a file in the trash was before it was trashed (NULL for other files)",
+    },
+    ColumnInfo {
+        name: "deleted_at",
+        value_type: ValueType::DateTime,
+        description: "When a file in the trash was trashed (NULL for other files)",
+    },
+    ColumnInfo {
+        name: "exists",
+        value_type: ValueType::Boolean,
+        description: "Whether a path listed FROM STDIN or a table is on disk",
+    },
+    ColumnInfo {
+        name: "was_moved_hint",
+        value_type: ValueType::String,
+        description: "A file of the same name under the query's root, for a listed path that has gone",
+    },
+    ColumnInfo {
+        name: "metadata_error",
+        value_type: ValueType::String,
+        description: "Why a listed path's metadata couldn't be read (NULL when it could)",
+    },
+];
+
+const FUNCTIONS: &[FunctionInfo] = &[
+    FunctionInfo {
+        name: "REGEXP",
+        signature: "REGEXP(attribute, pattern)",
+        value_type: ValueType::Boolean,
+        description: "Whether the attribute matches a regular expression",
+    },
+    FunctionInfo {
+        name: "STARTS_WITH",
+        signature: "STARTS_WITH(attribute, 'text')",
+        value_type: ValueType::Boolean,
+        description: "Whether the attribute starts with the text",
+    },
+    FunctionInfo {
+        name: "ENDS_WITH",
+        signature: "ENDS_WITH(attribute, 'text')",
+        value_type: ValueType::Boolean,
+        description: "Whether the attribute ends with the text",
+    },
+    FunctionInfo {
+        name: "CONTAINS_STR",
+        signature: "CONTAINS_STR(attribute, 'text')",
+        value_type: ValueType::Boolean,
+        description: "Whether the attribute contains the text",
+    },
+    FunctionInfo {
+        name: "CONTAINS_BYTES",
+        signature: "CONTAINS_BYTES(x'hex'[, max_bytes])",
+        value_type: ValueType::Boolean,
+        description: "Whether the file's first 64 MiB (or max_bytes) contain the bytes",
+    },
+    FunctionInfo {
+        name: "ANY_OF",
+        signature: "ANY_OF(attribute, value, ...) or ANY_OF(condition, ...)",
+        value_type: ValueType::Boolean,
+        description: "Whether the attribute equals one of the values, or any condition holds",
+    },
+    FunctionInfo {
+        name: "ALL_OF",
+        signature: "ALL_OF(condition, ...)",
+        value_type: ValueType::Boolean,
+        description: "Whether every condition holds",
+    },
+    FunctionInfo {
+        name: "HAS_PERM",
+        signature: "HAS_PERM('g+w')",
+        value_type: ValueType::Boolean,
+        description: "Whether the permissions satisfy a chmod-style spec",
+    },
+    FunctionInfo {
+        name: "OWNER_IN_GROUP",
+        signature: "OWNER_IN_GROUP('developers')",
+        value_type: ValueType::Boolean,
+        description: "Whether the file's owner is in a group, as its primary group or a member",
+    },
+    FunctionInfo {
+        name: "PERM_AND",
+        signature: "PERM_AND(permissions, 0o022)",
+        value_type: ValueType::Number,
+        description: "The permission bits selected by a mask",
+    },
+    FunctionInfo {
+        name: "TAGGED",
+        signature: "TAGGED('project-x')",
+        value_type: ValueType::Boolean,
+        description: "Whether the file has been given a tag with fmql tag add",
+    },
+    FunctionInfo {
+        name: "PERM_MATCHES",
+        signature: "PERM_MATCHES('??5')",
+        value_type: ValueType::Boolean,
+        description: "Whether the octal permissions match a pattern (? is any digit)",
+    },
+    FunctionInfo {
+        name: "CONCAT",
+        signature: "CONCAT(value, ...)",
+        value_type: ValueType::String,
+        description: "Joins its arguments into one string",
+    },
+    FunctionInfo {
+        name: "STEM",
+        signature: "STEM(name)",
+        value_type: ValueType::String,
+        description: "The file name without its extension",
+    },
+    FunctionInfo {
+        name: "DIRNAME",
+        signature: "DIRNAME(path)",
+        value_type: ValueType::String,
+        description: "The directory a path is in",
+    },
+    FunctionInfo {
+        name: "LOWER",
+        signature: "LOWER(string)",
+        value_type: ValueType::String,
+        description: "The string in lower case",
+    },
+    FunctionInfo {
+        name: "UPPER",
+        signature: "UPPER(string)",
+        value_type: ValueType::String,
+        description: "The string in upper case",
+    },
+    FunctionInfo {
+        name: "YEAR",
+        signature: "YEAR(modified)",
+        value_type: ValueType::Number,
+        description: "The year of a date",
+    },
+    FunctionInfo {
+        name: "MONTH",
+        signature: "MONTH(modified)",
+        value_type: ValueType::Number,
+        description: "The month (1-12) of a date",
+    },
+    FunctionInfo {
+        name: "DAY",
+        signature: "DAY(modified)",
+        value_type: ValueType::Number,
+        description: "The day of the month of a date",
+    },
+    FunctionInfo {
+        name: "EPOCH_MS",
+        signature: "EPOCH_MS(1700000000000)",
+        value_type: ValueType::DateTime,
+        description: "The date a number of milliseconds since 1970 names",
+    },
+    FunctionInfo {
+        name: "STRPTIME",
+        signature: "STRPTIME('31/12/2024', '%d/%m/%Y')",
+        value_type: ValueType::DateTime,
+        description: "A date read with a strftime format, or NULL if the text doesn't match it",
+    },
+    FunctionInfo {
+        name: "CURRENT_USER",
+        signature: "CURRENT_USER()",
+        value_type: ValueType::String,
+        description: "The name of the user fmql runs as, for owner = CURRENT_USER()",
+    },
+    FunctionInfo {
+        name: "CURRENT_GROUP",
+        signature: "CURRENT_GROUP()",
+        value_type: ValueType::String,
+        description: "The name of the group fmql runs as, for group = CURRENT_GROUP()",
+    },
+    FunctionInfo {
+        name: "CAST",
+        signature: "CAST(value AS TEXT | NUMBER | INTEGER | DATE | BOOLEAN | OCTAL)",
+        value_type: ValueType::String,
+        description: "Converts a value, such as CAST(permissions AS OCTAL) for '755'",
+    },
+    FunctionInfo {
+        name: "COALESCE",
+        signature: "COALESCE(value, ...)",
+        value_type: ValueType::String,
+        description: "The first of its arguments that isn't NULL, or NULL if they all are",
+    },
+    FunctionInfo {
+        name: "NULLIF",
+        signature: "NULLIF(value, other)",
+        value_type: ValueType::String,
+        description: "NULL if value equals other, and otherwise value",
+    },
+    FunctionInfo {
+        name: "TRY",
+        signature: "TRY(value)",
+        value_type: ValueType::String,
+        description: "The value, or NULL where it can't be worked out for a file",
+    },
+    FunctionInfo {
+        name: "FREE_SPACE",
+        signature: "FREE_SPACE('/var')",
+        value_type: ValueType::Number,
+        description: "The bytes free to unprivileged users on the file system holding a path",
+    },
+    FunctionInfo {
+        name: "TOTAL_SPACE",
+        signature: "TOTAL_SPACE('/var')",
+        value_type: ValueType::Number,
+        description: "The size in bytes of the file system holding a path",
+    },
+    FunctionInfo {
+        name: "COUNT",
+        signature: "COUNT(*) or COUNT(value)",
+        value_type: ValueType::Number,
+        description: "The number of files in a group, or of its non-NULL values",
+    },
+    FunctionInfo {
+        name: "SUM",
+        signature: "SUM(size)",
+        value_type: ValueType::Number,
+        description: "The total of a value over a group",
+    },
+    FunctionInfo {
+        name: "AVG",
+        signature: "AVG(size)",
+        value_type: ValueType::Number,
+        description: "The mean of a value over a group",
+    },
+    FunctionInfo {
+        name: "MIN",
+        signature: "MIN(modified)",
+        value_type: ValueType::Number,
+        description: "The smallest value in a group",
+    },
+    FunctionInfo {
+        name: "MAX",
+        signature: "MAX(modified)",
+        value_type: ValueType::Number,
+        description: "The largest value in a group",
+    },
+];
+
+/// Returns every queryable attribute.
+pub fn columns() -> &'static [ColumnInfo] {
+    COLUMNS
+}
+
+/// Returns every function that can be called in queries.
+pub fn functions() -> &'static [FunctionInfo] {
+    FUNCTIONS
+}
+
+/// Returns the reserved words of the query language.
+pub fn keywords() -> &'static [&'static str] {
+    KEYWORDS
+}
+
+/// Looks up the column description for an attribute.
+pub fn column(attribute: &FileAttribute) -> Option<&'static ColumnInfo> {
+    COLUMNS.iter().find(|c| c.name == attribute.name())
+}
@@ -0,0 +1,257 @@
+//! Linux file attributes, as shown by `lsattr` and set by `chattr`.
+//!
+//! ext4, btrfs, XFS and other Linux file systems keep a set of flags on
+//! every inode besides its mode bits: `immutable` (`chattr +i`) stops
+//! anyone, root included, from changing, renaming or deleting a file,
+//! `append` (`+a`) only lets it grow, and `nodump` (`+d`) leaves it out of
+//! backups. The `fs_flags` attribute lists the flags a file has by name,
+//! comma-separated, so `fs_flags LIKE '%immutable%'` finds the files that
+//! are locked down, and `UPDATE ... SET fs_flags = '+immutable'` sets them
+//! the way `chattr` does. Symlinks, devices, file systems without such
+//! flags and systems other than Linux have a NULL `fs_flags`.
+//!
+//! Only a process with `CAP_LINUX_IMMUTABLE`, usually root, may set or
+//! clear `immutable` and `append`; [`may_protect`] tells whether this one
+//! can, so an UPDATE fails with a message that says so instead of a bare
+//! "operation not permitted".
+//!
+//! # Examples
+//!
+//! ```
+//! use fmql_core::sql::chattr::{format_fs_flags, parse_change};
+//!
+//! // immutable (0x10) and nodump (0x40)
+//! assert_eq!(format_fs_flags(0x50), "immutable,nodump");
+//!
+//! let change = parse_change("-immutable,+a").unwrap();
+//! assert_eq!(format_fs_flags(change.apply(0x50)), "append,nodump");
+//! assert_eq!(format_fs_flags(parse_change("=d").unwrap().apply(0x50)), "nodump");
+//! assert!(parse_change("+sticky").is_err());
+//! ```
+
+use std::io;
+use std::path::Path;
+
+use crate::sql::flags::FlagChange;
+
+/// The flags fmql knows, by name and `chattr` letter, in the order `lsattr`
+/// shows them. Some are set by the file system rather than by `chattr`,
+/// such as `extents` on ext4.
+const FS_FLAGS: &[(&str, char, u32)] = &[
+    ("secure_deletion", 's', 0x1),
+    ("undeletable", 'u', 0x2),
+    ("sync", 'S', 0x8),
+    ("dirsync", 'D', 0x1_0000),
+    ("immutable", 'i', 0x10),
+    ("append", 'a', 0x20),
+    ("nodump", 'd', 0x40),
+    ("noatime", 'A', 0x80),
+    ("compressed", 'c', 0x4),
+    ("encrypted", 'E', 0x800),
+    ("journal_data", 'j', 0x4000),
+    ("indexed", 'I', 0x1000),
+    ("notail", 't', 0x8000),
+    ("topdir", 'T', 0x2_0000),
+    ("extents", 'e', 0x8_0000),
+    ("nocow", 'C', 0x80_0000),
+    ("dax", 'x', 0x200_0000),
+    ("casefold", 'F', 0x4000_0000),
+    ("inline_data", 'N', 0x1000_0000),
+    ("project_inherit", 'P', 0x2000_0000),
+    ("verity", 'V', 0x10_0000),
+];
+
+/// The flags only a process with `CAP_LINUX_IMMUTABLE` may change:
+/// `immutable` and `append`.
+pub const PROTECTED: u32 = 0x10 | 0x20;
+
+/// Lists the flags in `bits` by name, comma-separated, in the order
+/// `lsattr` shows them. Unknown bits are shown in hex.
+pub fn format_fs_flags(bits: u32) -> String {
+    let mut names: Vec<String> = FS_FLAGS
+        .iter()
+        .filter(|(_, _, bit)| bits & bit != 0)
+        .map(|(name, _, _)| name.to_string())
+        .collect();
+    let known = FS_FLAGS.iter().fold(0, |known, (_, _, bit)| known | bit);
+    if bits & !known != 0 {
+        names.push(format!("{:#x}", bits & !known));
+    }
+    names.join(",")
+}
+
+/// Parses a `chattr`-style change: a comma-separated list of flags, each
+/// set with `+` (or no sign) or cleared with `-`, leaving the others as
+/// they are, or `=` and a list to give the file exactly those flags. A
+/// flag is named in full (`immutable`) or by its `chattr` letter (`i`),
+/// and a sign may be followed by several letters, as in `+ia`.
+pub fn parse_change(spec: &str) -> std::result::Result<FlagChange, String> {
+    let spec = spec.trim();
+    let (exact, spec) = match spec.strip_prefix('=') {
+        Some(rest) => (true, rest),
+        None => (false, spec),
+    };
+    let mut change = FlagChange { set: 0, clear: if exact { u32::MAX } else { 0 } };
+    for item in spec.split(',').map(str::trim).filter(|item| !item.is_empty()) {
+        let (clear, names) = match item.as_bytes()[0] {
+            b'-' if !exact => (true, &item[1..]),
+            b'+' => (false, &item[1..]),
+            _ => (false, item),
+        };
+        let bits = fs_flag(names).or_else(|| letters(names)).ok_or_else(|| {
+            let known: Vec<&str> = FS_FLAGS.iter().map(|(name, _, _)| *name).collect();
+            format!("Unknown file attribute '{}' (expected one of {})", item, known.join(", "))
+        })?;
+        if clear {
+            change.clear |= bits;
+        } else {
+            change.set |= bits;
+        }
+    }
+    Ok(change)
+}
+
+/// Looks up a flag by name, ignoring case.
+fn fs_flag(name: &str) -> Option<u32> {
+    FS_FLAGS
+        .iter()
+        .find(|(flag, _, _)| flag.eq_ignore_ascii_case(name))
+        .map(|(_, _, bit)| *bit)
+}
+
+/// Looks up a run of `chattr` letters such as `ia`, which are case
+/// sensitive.
+fn letters(letters: &str) -> Option<u32> {
+    if letters.is_empty() {
+        return None;
+    }
+    letters.chars().try_fold(0, |bits, letter| {
+        let (_, _, bit) = FS_FLAGS.iter().find(|(_, flag, _)| *flag == letter)?;
+        Some(bits | bit)
+    })
+}
+
+/// Returns true if this system has file attributes that can be read and
+/// set.
+pub fn supported() -> bool {
+    cfg!(target_os = "linux")
+}
+
+/// Returns true if this process may set and clear the [`PROTECTED`]
+/// flags: it has `CAP_LINUX_IMMUTABLE` in its effective capabilities.
+#[cfg(target_os = "linux")]
+pub fn may_protect() -> bool {
+    /// The capability's bit in the capability sets.
+    const CAP_LINUX_IMMUTABLE: u32 = 9;
+
+    let Ok(status) = std::fs::read_to_string("/proc/self/status") else {
+        // SAFETY: geteuid has no preconditions and can't fail
+        return unsafe { libc::geteuid() } == 0;
+    };
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix("CapEff:"))
+        .and_then(|caps| u64::from_str_radix(caps.trim(), 16).ok())
+        .is_some_and(|caps| caps & (1 << CAP_LINUX_IMMUTABLE) != 0)
+}
+
+/// Returns true if this process may set and clear the [`PROTECTED`]
+/// flags; this system has none.
+#[cfg(not(target_os = "linux"))]
+pub fn may_protect() -> bool {
+    false
+}
+
+/// Opens a regular file or directory for the attribute ioctls, without
+/// following a symlink, or returns None for anything else.
+#[cfg(target_os = "linux")]
+fn open(path: &Path) -> io::Result<Option<std::fs::File>> {
+    use std::os::unix::fs::OpenOptionsExt;
+
+    let metadata = path.symlink_metadata()?;
+    if !metadata.is_file() && !metadata.is_dir() {
+        return Ok(None);
+    }
+    let file = std::fs::File::options()
+        .read(true)
+        .custom_flags(libc::O_NOFOLLOW | libc::O_NONBLOCK)
+        .open(path)?;
+    Ok(Some(file))
+}
+
+/// Reads the attributes of a file, without following a symlink, or returns
+/// None where the file or its file system has none.
+#[cfg(target_os = "linux")]
+pub fn read_fs_flags(path: &Path) -> io::Result<Option<u32>> {
+    use std::os::fd::AsRawFd;
+
+    let Some(file) = open(path)? else {
+        return Ok(None);
+    };
+    let mut bits: libc::c_int = 0;
+    // SAFETY: the descriptor is open for the duration of the call, and
+    // FS_IOC_GETFLAGS writes an int to the pointer it is given.
+    if unsafe { libc::ioctl(file.as_raw_fd(), libc::FS_IOC_GETFLAGS, &mut bits) } != 0 {
+        let err = io::Error::last_os_error();
+        return match err.raw_os_error() {
+            Some(libc::ENOTTY | libc::EOPNOTSUPP | libc::EINVAL) => Ok(None),
+            _ => Err(err),
+        };
+    }
+    Ok(Some(bits as u32))
+}
+
+/// Reads the attributes of a file, or returns None where the system has no
+/// file attributes.
+#[cfg(not(target_os = "linux"))]
+pub fn read_fs_flags(_path: &Path) -> io::Result<Option<u32>> {
+    Ok(None)
+}
+
+/// Sets the attributes of a file, without following a symlink. Changing
+/// the [`PROTECTED`] flags without `CAP_LINUX_IMMUTABLE` fails with a
+/// message saying that is why.
+#[cfg(target_os = "linux")]
+pub fn write_fs_flags(path: &Path, bits: u32) -> io::Result<()> {
+    use std::os::fd::AsRawFd;
+
+    let Some(file) = open(path)? else {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("{} can't have file attributes", path.display()),
+        ));
+    };
+    let old = read_fs_flags(path)?.unwrap_or(0);
+    check_protected(path, old, bits)?;
+    let bits = bits as libc::c_int;
+    // SAFETY: the descriptor is open for the duration of the call, and
+    // FS_IOC_SETFLAGS reads an int from the pointer it is given.
+    if unsafe { libc::ioctl(file.as_raw_fd(), libc::FS_IOC_SETFLAGS, &bits) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Sets the attributes of a file; this system has none.
+#[cfg(not(target_os = "linux"))]
+pub fn write_fs_flags(_path: &Path, _bits: u32) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "file attributes are only supported on Linux",
+    ))
+}
+
+/// Fails, as the change would, if going from `old` to `new` flags changes
+/// `immutable` or `append` and this process lacks `CAP_LINUX_IMMUTABLE`.
+pub fn check_protected(path: &Path, old: u32, new: u32) -> io::Result<()> {
+    if (old ^ new) & PROTECTED == 0 || may_protect() {
+        return Ok(());
+    }
+    Err(io::Error::new(
+        io::ErrorKind::PermissionDenied,
+        format!(
+            "Cannot change immutable or append on {}: that needs CAP_LINUX_IMMUTABLE (root)",
+            path.display()
+        ),
+    ))
+}
@@ -0,0 +1,129 @@
+//! Checkpoints, for resuming a long scan where it stopped.
+//!
+//! A SELECT that hashes or searches the contents of every file on a NAS can
+//! run for hours, and without a checkpoint, anything that stops it
+//! (a dropped connection, a reboot, `--timeout`) means starting again.
+//! With `--checkpoint state.json`, the scan walks its directories in a fixed
+//! order and every few seconds saves how far it has got and the paths that
+//! have matched so far. Running the same query with the same checkpoint
+//! picks up after the last path it saved: directories wholly before that
+//! point aren't even listed, and the saved matches aren't checked again.
+//! Once the query finishes, the checkpoint is removed.
+//!
+//! A checkpoint belongs to the query that saved it; resuming a different
+//! query from it is an error rather than a silently wrong answer. Only
+//! SELECTs that return files can be checkpointed, since a group's running
+//! totals can't be saved as paths.
+//!
+//! # Examples
+//!
+//! ```
+//! use std::path::PathBuf;
+//!
+//! use fmql_core::sql::checkpoint::{Checkpoint, Position};
+//! use fmql_core::sql::parse_sql;
+//!
+//! let dir = tempfile::tempdir().unwrap();
+//! let state = dir.path().join("state.json");
+//! let query = parse_sql("WITH RECURSIVE SELECT * FROM /srv WHERE content LIKE '%TODO%'").unwrap();
+//! assert!(Checkpoint::load(&state, &query).unwrap().is_none());
+//!
+//! let mut checkpoint = Checkpoint::new(&query);
+//! checkpoint.position = Some(Position { target: 0, path: PathBuf::from("/srv/b/c.txt") });
+//! checkpoint.matches.push(PathBuf::from("/srv/a.txt"));
+//! checkpoint.save(&state).unwrap();
+//! assert_eq!(Checkpoint::load(&state, &query).unwrap(), Some(checkpoint));
+//!
+//! let other = parse_sql("WITH RECURSIVE SELECT * FROM /srv").unwrap();
+//! assert!(Checkpoint::load(&state, &other).is_err());
+//! ```
+
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::sql::ast::FileQuery;
+use crate::sql::executor::{ExecutorError, Result};
+
+/// How often a running scan saves its checkpoint.
+pub const SAVE_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How far a scan has got: the last path it visited, and which of the
+/// query's targets (its FROM paths, or the paths a glob matched) that path
+/// came from.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Position {
+    /// The index of the target being walked.
+    pub target: usize,
+    /// The last path visited, which is the target itself for a file target.
+    pub path: PathBuf,
+}
+
+/// The saved progress of a scan.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Checkpoint {
+    /// The query the checkpoint was saved for.
+    pub query: Value,
+    /// Where to resume from; None if nothing has been visited yet.
+    pub position: Option<Position>,
+    /// The paths that matched before the position.
+    pub matches: Vec<PathBuf>,
+}
+
+impl Checkpoint {
+    /// Returns a checkpoint for `query` that hasn't got anywhere yet.
+    pub fn new(query: &FileQuery) -> Checkpoint {
+        Checkpoint {
+            query: saved_query(query),
+            position: None,
+            matches: Vec::new(),
+        }
+    }
+
+    /// Reads the checkpoint saved at `path` for `query`, or returns None if
+    /// there is none yet.
+    pub fn load(path: &Path, query: &FileQuery) -> Result<Option<Checkpoint>> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+        let checkpoint: Checkpoint = serde_json::from_str(&text).map_err(|err| {
+            ExecutorError::InvalidCheckpoint(format!("{}: {}", path.display(), err))
+        })?;
+        if checkpoint.query != saved_query(query) {
+            return Err(ExecutorError::InvalidCheckpoint(format!(
+                "{} was saved for a different query; remove it to start again",
+                path.display()
+            )));
+        }
+        Ok(Some(checkpoint))
+    }
+
+    /// Writes the checkpoint to `path`. It is written under a temporary
+    /// name and renamed into place, so being stopped halfway through saving
+    /// leaves the previous checkpoint intact.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        let mut file = tempfile::NamedTempFile::new_in(dir)?;
+        serde_json::to_writer(&mut file, self).map_err(io::Error::from)?;
+        file.flush()?;
+        file.persist(path).map_err(|err| err.error)?;
+        Ok(())
+    }
+}
+
+/// Returns the query as a checkpoint saves it. It goes through JSON text
+/// and back, so that it compares equal to the same query read from a file.
+fn saved_query(query: &FileQuery) -> Value {
+    serde_json::to_string(query)
+        .and_then(|text| serde_json::from_str(&text))
+        .unwrap_or(Value::Null)
+}
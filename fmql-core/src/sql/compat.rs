@@ -0,0 +1,153 @@
+//! Query semantics versions, so scripts written for an older fmql keep
+//! meaning what they meant.
+//!
+//! A fix that changes what a query matches or does ships in a new
+//! [`Semantics`] version, and the old behaviour stays available under the
+//! version before it. [`ExecutionOptions::semantics`] picks the version a
+//! query runs with; `fmql --compat 0.3` and the `semantics` setting in
+//! fmql's config file pick it on the command line.
+//!
+//! Versions are named for the release that brought them, as the crate's
+//! version gives it, so [`Semantics::CURRENT`] is always this release's.
+//! The changes so far, each listed in [`CHANGES`]:
+//!
+//! - `0.4`: the FROM directory is no longer listed among its own entries
+//!   unless the query says `INCLUDE SELF`; under `0.3` every SELECT and
+//!   UPDATE lists it.
+//! - `0.4`: a file without an extension has a NULL `extension`; under `0.3`
+//!   it is an empty string, so `extension = ''` finds those files.
+//! - `0.4`: only `%` and `_` are wildcards in a LIKE pattern; under `0.3`
+//!   the rest of it is a regular expression, so `.` matches any character.
+//! - `0.4`: LIKE and REGEXP match nothing where the value is NULL; under
+//!   `0.3` evaluating them there is a type error, a fault for each file.
+//!
+//! [`ExecutionOptions::semantics`]: crate::sql::ExecutionOptions::semantics
+//!
+//! # Examples
+//!
+//! ```
+//! use fmql_core::sql::compat::Semantics;
+//!
+//! let old: Semantics = "0.3".parse().unwrap();
+//! assert!(old.lists_from_directory());
+//! assert!(!Semantics::CURRENT.lists_from_directory());
+//! assert!(old.blank_extensions());
+//!
+//! // The current semantics are this release's
+//! let release = env!("CARGO_PKG_VERSION").parse::<Semantics>().unwrap();
+//! assert_eq!(release, Semantics::CURRENT);
+//! assert!("0.1".parse::<Semantics>().is_err());
+//! ```
+
+use std::fmt;
+use std::str::FromStr;
+
+/// A version of the query semantics, named for the fmql release that
+/// brought it. Later versions compare greater.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub enum Semantics {
+    /// fmql 0.3, where the FROM directory is listed with its entries.
+    V0_3,
+    /// The semantics of this version of fmql.
+    #[default]
+    V0_4,
+}
+
+/// The behaviour changes between semantics versions: the version that
+/// brought each one, and what changed.
+pub const CHANGES: &[(Semantics, &str)] = &[
+    (
+        Semantics::V0_4,
+        "the FROM directory is only listed with INCLUDE SELF or --include-self",
+    ),
+    (
+        Semantics::V0_4,
+        "a file without an extension has a NULL extension, not an empty one",
+    ),
+    (
+        Semantics::V0_4,
+        "only % and _ are wildcards in LIKE patterns; . + [ and the like match themselves",
+    ),
+    (
+        Semantics::V0_4,
+        "LIKE and REGEXP match nothing on NULL values instead of being a type error there",
+    ),
+];
+
+impl Semantics {
+    /// The semantics queries run with unless told otherwise.
+    pub const CURRENT: Semantics = Semantics::V0_4;
+
+    /// Every version, oldest first.
+    pub const ALL: &[Semantics] = &[Semantics::V0_3, Semantics::V0_4];
+
+    /// Returns true if a SELECT or UPDATE lists the FROM directory itself
+    /// even without `INCLUDE SELF`, as fmql 0.3 did.
+    pub fn lists_from_directory(self) -> bool {
+        self < Semantics::V0_4
+    }
+
+    /// Returns true if a file without an extension has an empty `extension`
+    /// rather than a NULL one, as in fmql 0.3.
+    pub fn blank_extensions(self) -> bool {
+        self < Semantics::V0_4
+    }
+
+    /// Returns true if a LIKE pattern is a regular expression apart from
+    /// its `%` and `_`, as in fmql 0.3.
+    pub fn like_is_regex(self) -> bool {
+        self < Semantics::V0_4
+    }
+
+    /// Returns true if LIKE and REGEXP fail on a NULL value rather than not
+    /// match it, as in fmql 0.3.
+    pub fn patterns_fail_on_null(self) -> bool {
+        self < Semantics::V0_4
+    }
+
+    /// Returns what behaves differently under this version than under
+    /// the current one.
+    pub fn differences(self) -> Vec<&'static str> {
+        CHANGES
+            .iter()
+            .filter(|(version, _)| *version > self)
+            .map(|(_, change)| *change)
+            .collect()
+    }
+}
+
+impl fmt::Display for Semantics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let version = match self {
+            Semantics::V0_3 => "0.3",
+            Semantics::V0_4 => "0.4",
+        };
+        write!(f, "{}", version)
+    }
+}
+
+/// Reads a version such as `0.3`. A patch release such as `0.3.2` has the
+/// semantics of its minor version.
+impl FromStr for Semantics {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let text = text.trim();
+        let minor = match text.match_indices('.').nth(1) {
+            Some((end, _)) => &text[..end],
+            None => text,
+        };
+        Semantics::ALL
+            .iter()
+            .find(|version| version.to_string() == minor)
+            .copied()
+            .ok_or_else(|| {
+                let known: Vec<String> = Semantics::ALL.iter().map(ToString::to_string).collect();
+                format!(
+                    "unknown semantics version '{}'; the versions are {}",
+                    text,
+                    known.join(", ")
+                )
+            })
+    }
+}
@@ -0,0 +1,166 @@
+//! Queries parsed and planned once, to run again and again.
+//!
+//! A daemon or a loop that runs the same query every few seconds shouldn't
+//! parse it, simplify its conditions and compile its patterns on each run.
+//! [`CompiledQuery`] does that work once, up front, and keeps the result
+//! with the [`ExecutionOptions`] it runs with. [`CompiledQuery::execute_on`]
+//! runs it against another directory than the one its FROM names, so one
+//! compiled query can serve many roots.
+//!
+//! The regular expressions of its REGEXP and LIKE conditions are compiled
+//! when the query is, and shared with every run after; a pattern that
+//! doesn't compile is an error then rather than on the first run.
+//!
+//! # Examples
+//!
+//! ```
+//! use fmql_core::sql::compiled::CompiledQuery;
+//! use fmql_core::sql::ExecutionOptions;
+//!
+//! let logs = tempfile::tempdir().unwrap();
+//! let cache = tempfile::tempdir().unwrap();
+//! std::fs::write(logs.path().join("app.log"), "started").unwrap();
+//! std::fs::write(cache.path().join("old.log"), "").unwrap();
+//! std::fs::write(cache.path().join("index.db"), "").unwrap();
+//!
+//! let sql = "SELECT name FROM . WHERE name REGEXP '\\.log$'";
+//! let compiled = CompiledQuery::new(sql, &ExecutionOptions::default()).unwrap();
+//! assert_eq!(compiled.execute_on(logs.path()).unwrap()[0].name, "app.log");
+//! assert_eq!(compiled.execute_on(cache.path()).unwrap()[0].name, "old.log");
+//! assert_eq!(compiled.root(), Some(std::path::Path::new(".")));
+//! ```
+
+use std::path::{Path, PathBuf};
+
+use crate::error::Result;
+use crate::sql::ast::{FileCondition, FileQuery};
+use crate::sql::executor::{
+    execute_grouped_with_options, execute_query_with_options, like_regex, regexp_regex,
+    ExecutionOptions, ExecutorError, FileResult, GroupRow,
+};
+use crate::sql::optimize::optimize;
+use crate::sql::parse_sql;
+use crate::sql::visit::{walk_condition, Visitor};
+
+/// A query parsed, simplified and with its patterns compiled, and the
+/// options it runs with; see the [module documentation](self).
+#[derive(Debug, Clone)]
+pub struct CompiledQuery {
+    query: FileQuery,
+    options: ExecutionOptions,
+}
+
+impl CompiledQuery {
+    /// Parses and compiles a query.
+    pub fn new(sql: &str, options: &ExecutionOptions) -> Result<CompiledQuery> {
+        CompiledQuery::from_query(parse_sql(sql)?, options)
+    }
+
+    /// Compiles a query that has already been parsed.
+    pub fn from_query(query: FileQuery, options: &ExecutionOptions) -> Result<CompiledQuery> {
+        let query = optimize(query);
+        let mut patterns = Patterns(Ok(()));
+        patterns.visit_query(&query);
+        patterns.0.map_err(ExecutorError::from)?;
+        Ok(CompiledQuery {
+            query,
+            options: options.clone(),
+        })
+    }
+
+    /// Returns the query as it runs, after simplification.
+    pub fn query(&self) -> &FileQuery {
+        &self.query
+    }
+
+    /// Returns the path the query reads, which [`execute_on`](Self::execute_on)
+    /// replaces, or `None` for a query that reads `FROM STDIN`.
+    pub fn root(&self) -> Option<&Path> {
+        self.query.root()
+    }
+
+    /// Returns the options the query runs with.
+    pub fn options(&self) -> &ExecutionOptions {
+        &self.options
+    }
+
+    /// Returns the same compiled query, to run with other options.
+    pub fn with_options(&self, options: &ExecutionOptions) -> CompiledQuery {
+        CompiledQuery {
+            query: self.query.clone(),
+            options: options.clone(),
+        }
+    }
+
+    /// Runs the query against the path its FROM names.
+    pub fn execute(&self) -> Result<Vec<FileResult>> {
+        Ok(execute_query_with_options(&self.query, &self.options)?)
+    }
+
+    /// Runs the query against `root` in place of the path its FROM names.
+    /// A query that reads `FROM STDIN` has no root to replace, and fails.
+    pub fn execute_on(&self, root: impl AsRef<Path>) -> Result<Vec<FileResult>> {
+        let query = self.rooted(root.as_ref())?;
+        Ok(execute_query_with_options(&query, &self.options)?)
+    }
+
+    /// Runs a query that returns groups, such as one with GROUP BY, against
+    /// `root`; see [`execute_on`](Self::execute_on).
+    pub fn execute_grouped_on(&self, root: impl AsRef<Path>) -> Result<Vec<GroupRow>> {
+        let query = self.rooted(root.as_ref())?;
+        Ok(execute_grouped_with_options(&query, &self.options)?)
+    }
+
+    /// Returns the query with `root` as the path it reads.
+    fn rooted(&self, root: &Path) -> Result<FileQuery> {
+        if self.query.lists_paths() {
+            let err = ExecutorError::UnsupportedOperation(
+                "A query that reads FROM STDIN or a table can't run on another path".to_string(),
+            );
+            return Err(err.into());
+        }
+        let mut query = self.query.clone();
+        *source(&mut query) = root.to_path_buf();
+        Ok(query)
+    }
+}
+
+/// Returns the path a query reads: the one after FROM, or after UPDATE,
+/// COPY, COMPRESS, EXTRACT, ARCHIVE or RESTORE.
+fn source(query: &mut FileQuery) -> &mut PathBuf {
+    match query {
+        FileQuery::Select { path, .. }
+        | FileQuery::Update { path, .. }
+        | FileQuery::Copy { path, .. }
+        | FileQuery::Compress { path, .. }
+        | FileQuery::Extract { path, .. }
+        | FileQuery::Archive { path, .. }
+        | FileQuery::Restore { path, .. }
+        | FileQuery::ShowFilesystem { path } => path,
+        FileQuery::Explain { query } => source(query),
+    }
+}
+
+/// Compiles each REGEXP and LIKE pattern in a query, keeping the first
+/// error.
+struct Patterns(std::result::Result<(), regex::Error>);
+
+impl<'ast> Visitor<'ast> for Patterns {
+    fn visit_condition(&mut self, condition: &'ast FileCondition) {
+        let compiled = match condition {
+            FileCondition::Like {
+                pattern,
+                case_sensitive,
+                ..
+            } => like_regex(pattern, *case_sensitive).map(drop),
+            FileCondition::Regexp { attribute, pattern } => {
+                regexp_regex(attribute, pattern).map(drop)
+            }
+            _ => Ok(()),
+        };
+        if self.0.is_ok() {
+            self.0 = compiled;
+        }
+        walk_condition(self, condition);
+    }
+}
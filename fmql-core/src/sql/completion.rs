@@ -0,0 +1,114 @@
+//! Tab completion for partially typed queries.
+//!
+//! Completion is context sensitive: after `FROM`, `UPDATE`, `COPY` or `TO`
+//! the word under the cursor is completed as a filesystem path; everywhere else it is
+//! completed against the attributes, functions and keywords listed in the
+//! [`catalog`](crate::sql::catalog).
+//!
+//! # Examples
+//!
+//! ```
+//! use fmql_core::sql::completion::complete;
+//!
+//! let line = "SELECT * FROM . WHERE si";
+//! let completion = complete(line, line.len());
+//!
+//! assert_eq!(completion.start, 22);
+//! assert!(completion.candidates.contains(&"size".to_string()));
+//! ```
+
+use std::fs;
+use std::path::PathBuf;
+
+use crate::sql::catalog::{columns, functions, keywords};
+
+/// The result of completing the word under the cursor.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Completion {
+    /// Byte offset where the word being completed starts.
+    pub start: usize,
+    /// Replacement candidates for the text between `start` and the cursor.
+    pub candidates: Vec<String>,
+}
+
+/// Completes the word ending at `cursor` (a byte offset into `line`).
+pub fn complete(line: &str, cursor: usize) -> Completion {
+    let before = &line[..cursor];
+    let start = before
+        .rfind(|c: char| c.is_whitespace() || "(),=<>!'".contains(c))
+        .map(|i| i + 1)
+        .unwrap_or(0);
+    let word = &before[start..];
+
+    let previous = before[..start].split_whitespace().next_back().unwrap_or("");
+    let candidates = if ["FROM", "UPDATE", "COPY", "TO"]
+        .iter()
+        .any(|keyword| previous.eq_ignore_ascii_case(keyword))
+    {
+        complete_path(word)
+    } else {
+        complete_word(word)
+    };
+
+    Completion { start, candidates }
+}
+
+/// Completes an attribute, function or keyword.
+fn complete_word(word: &str) -> Vec<String> {
+    if word.is_empty() {
+        return Vec::new();
+    }
+    let prefix = word.to_lowercase();
+
+    let attributes = columns().iter().map(|c| c.name.to_string());
+    let functions = functions().iter().map(|f| format!("{}(", f.name));
+    let keywords = keywords().iter().map(|k| k.to_string());
+
+    attributes
+        .chain(functions)
+        .chain(keywords)
+        .filter(|candidate| candidate.to_lowercase().starts_with(&prefix))
+        .collect()
+}
+
+/// Completes a filesystem path, expanding a leading `~`.
+fn complete_path(word: &str) -> Vec<String> {
+    if word == "~" {
+        return vec!["~/".to_string()];
+    }
+
+    let (typed_dir, file_prefix) = match word.rfind('/') {
+        Some(i) => word.split_at(i + 1),
+        None => ("", word),
+    };
+
+    let dir = if typed_dir.is_empty() {
+        PathBuf::from(".")
+    } else if let Some(rest) = typed_dir.strip_prefix("~/") {
+        match dirs::home_dir() {
+            Some(home) => home.join(rest),
+            None => return Vec::new(),
+        }
+    } else {
+        PathBuf::from(typed_dir)
+    };
+
+    let Ok(entries) = fs::read_dir(&dir) else {
+        return Vec::new();
+    };
+
+    let mut candidates: Vec<String> = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if !name.starts_with(file_prefix) || (name.starts_with('.') && !file_prefix.starts_with('.')) {
+                return None;
+            }
+            let suffix = if entry.path().is_dir() { "/" } else { "" };
+            Some(format!("{}{}{}", typed_dir, name, suffix))
+        })
+        .collect();
+    candidates.sort();
+
+    candidates
+}
@@ -0,0 +1,456 @@
+//! Looking inside gzip, zstd and xz files.
+//!
+//! Rotated logs are usually compressed one file at a time (`app.log.1.gz`,
+//! `syslog.2.zst`), which hides their text from `content` checks. Files
+//! whose extension is `.gz`, `.zst` or `.xz` can be read through their
+//! decompressor instead: [`read_content`] does this when the query runs
+//! with a decompression limit (`--decompress` on the command line), and
+//! keeps only the first that many bytes of the decompressed text, so that
+//! a small archive can't expand into more than memory allows.
+//!
+//! [`uncompressed_size`] reads the size the format records, without
+//! decompressing anything: the trailer of a gzip file (modulo 4GiB, and for
+//! the last member only), the frame header of a zstd file when the encoder
+//! wrote it there, and the index of an xz file.
+//!
+//! Going the other way, [`compress_file`] writes a gzip or zstd copy of a
+//! file for `COMPRESS`, and only keeps it once it has read it back and found
+//! it decompresses to exactly the original, so that the original can then
+//! be removed safely. [`already_compressed`] tells the files not worth
+//! compressing again, by their extension or their contents.
+//!
+//! # Examples
+//!
+//! ```
+//! use std::io::Write;
+//!
+//! use flate2::write::GzEncoder;
+//! use flate2::Compression;
+//! use fmql_core::sql::compressed::{read_content, uncompressed_size};
+//!
+//! let dir = tempfile::tempdir().unwrap();
+//! let path = dir.path().join("app.log.1.gz");
+//! let mut encoder = GzEncoder::new(std::fs::File::create(&path).unwrap(), Compression::default());
+//! encoder.write_all(b"GET /health 200\nGET /login 500\n").unwrap();
+//! encoder.finish().unwrap();
+//!
+//! assert_eq!(uncompressed_size(&path).unwrap(), Some(31));
+//! assert_eq!(read_content(&path, Some(15)).unwrap(), b"GET /health 200");
+//! assert_ne!(read_content(&path, None).unwrap(), b"GET /health 200\nGET /login 500\n");
+//! ```
+
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use flate2::read::MultiGzDecoder;
+use flate2::write::GzEncoder;
+use ruzstd::decoding::StreamingDecoder;
+
+use crate::sql::ast::CompressFormat;
+use crate::sql::budget::Budgeted;
+use crate::sql::signatures::read_signature;
+use crate::sql::throttle::{throttled, Throttle};
+
+/// The formats, by the extension [`read_signature`] names, whose contents
+/// are compressed already, so that compressing them again gains little.
+const INCOMPRESSIBLE: &[&str] = &[
+    "gz", "bz2", "xz", "zst", "zip", "7z", "rar", "jpg", "png", "gif", "webp", "heic", "mp4",
+    "mov", "mkv", "mp3", "flac", "ogg", "woff", "woff2",
+];
+
+/// A single-file compression format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    Gzip,
+    Zstd,
+    Xz,
+}
+
+impl Compression {
+    /// Returns the format a file's extension names, if any.
+    pub fn of(path: &Path) -> Option<Compression> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "gz" => Some(Compression::Gzip),
+            "zst" => Some(Compression::Zstd),
+            "xz" => Some(Compression::Xz),
+            _ => None,
+        }
+    }
+}
+
+/// Reads a file's contents. With a limit, a compressed file is read
+/// through its decompressor and at most `limit` bytes of it are kept;
+/// without one, or for other files, the bytes are read as they are.
+/// Either way the read stops if a [budget](crate::sql::budget) runs out.
+///
+/// # Errors
+///
+/// Fails if the file can't be read, or with `InvalidData` if it isn't
+/// in the format its extension names.
+pub fn read_content(path: &Path, decompress: Option<u64>) -> io::Result<Vec<u8>> {
+    match (decompress, Compression::of(path)) {
+        (Some(limit), Some(compression)) => read_decompressed(path, compression, limit),
+        _ => {
+            let file = File::open(path)?;
+            let size = file.metadata().map_or(0, |metadata| metadata.len());
+            let mut content = Vec::with_capacity(usize::try_from(size).unwrap_or(0));
+            Budgeted::new(file).read_to_end(&mut content)?;
+            Ok(content)
+        }
+    }
+}
+
+/// Decompresses the start of a file, up to `limit` bytes.
+fn read_decompressed(path: &Path, compression: Compression, limit: u64) -> io::Result<Vec<u8>> {
+    let mut file = File::open(path)?;
+    let mut content = Vec::new();
+    match compression {
+        Compression::Gzip => {
+            let reader = BufReader::new(Budgeted::new(file));
+            MultiGzDecoder::new(reader).take(limit).read_to_end(&mut content)?;
+        }
+        Compression::Zstd => {
+            let mut reader = BufReader::new(Budgeted::new(file));
+            // A file can hold several frames, one after another
+            while (content.len() as u64) < limit && !reader.fill_buf()?.is_empty() {
+                let decoder = StreamingDecoder::new(&mut reader)
+                    .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err.to_string()))?;
+                decoder.take(limit - content.len() as u64).read_to_end(&mut content)?;
+            }
+        }
+        Compression::Xz => {
+            // The decoder reads one stream and rejects anything after it, so
+            // each stream is handed over on its own
+            let limit = usize::try_from(limit).unwrap_or(usize::MAX);
+            let streams = xz_streams(&mut file)?.ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidData, "not an xz file")
+            })?;
+            for stream in streams {
+                file.seek(SeekFrom::Start(stream.start))?;
+                let stream = (&mut file).take(stream.end - stream.start);
+                let mut input = BufReader::new(Budgeted::new(stream));
+                let mut writer = Capped {
+                    content: &mut content,
+                    limit,
+                };
+                let decoded = lzma_rs::xz_decompress(&mut input, &mut writer);
+                // Running out of room is how a capped read ends early
+                if content.len() >= limit {
+                    break;
+                }
+                decoded.map_err(|err| match err {
+                    lzma_rs::error::Error::IoError(err) => err,
+                    err => io::Error::new(io::ErrorKind::InvalidData, err.to_string()),
+                })?;
+            }
+        }
+    }
+    Ok(content)
+}
+
+/// A writer that keeps the first `limit` bytes and then refuses more.
+struct Capped<'a> {
+    content: &'a mut Vec<u8>,
+    limit: usize,
+}
+
+impl Write for Capped<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let room = self.limit - self.content.len();
+        if room == 0 && !buf.is_empty() {
+            return Err(io::Error::other("decompression limit reached"));
+        }
+        let n = buf.len().min(room);
+        self.content.extend_from_slice(&buf[..n]);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Returns the size of a compressed file's contents when the format
+/// records it, or None if it doesn't (or the file isn't compressed).
+///
+/// # Errors
+///
+/// Fails if the file can't be read. A file too short or malformed to hold
+/// the size has none, rather than failing.
+pub fn uncompressed_size(path: &Path) -> io::Result<Option<u64>> {
+    let Some(compression) = Compression::of(path) else {
+        return Ok(None);
+    };
+    let mut file = File::open(path)?;
+    let size = match compression {
+        Compression::Gzip => gzip_size(&mut file),
+        Compression::Zstd => zstd_size(&mut file),
+        Compression::Xz => xz_size(&mut file),
+    };
+    match size {
+        Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => Ok(None),
+        Err(err) if err.kind() == io::ErrorKind::InvalidData => Ok(None),
+        size => size,
+    }
+}
+
+/// Returns true if a file's contents are compressed already: it has the
+/// extension of a compressed format, or starts like an archive, a
+/// compressed file or compressed media.
+///
+/// # Errors
+///
+/// Fails if the file can't be read.
+pub fn already_compressed(path: &Path) -> io::Result<bool> {
+    if Compression::of(path).is_some() {
+        return Ok(true);
+    }
+    let signature = read_signature(path)?;
+    Ok(signature.is_some_and(|signature| INCOMPRESSIBLE.contains(&signature.extension)))
+}
+
+/// Writes a compressed copy of `path` at `target`, which must not exist,
+/// with the original's permissions and modification time, and returns its
+/// size. The copy is written under a temporary name, flushed to disk, and
+/// decompressed again to check it matches the original before it takes
+/// its name; the original is left as it is. Both reads go as fast as
+/// `throttle` allows.
+///
+/// # Errors
+///
+/// Fails if `target` exists, with `InvalidData` if the copy doesn't
+/// decompress to the original, or if the original changed while it was
+/// being read. Nothing is left at `target` when it fails.
+///
+/// # Examples
+///
+/// ```
+/// use fmql_core::sql::ast::CompressFormat;
+/// use fmql_core::sql::compressed::{compress_file, read_content};
+///
+/// let dir = tempfile::tempdir().unwrap();
+/// let log = dir.path().join("app.log");
+/// std::fs::write(&log, "GET /health 200\n".repeat(100)).unwrap();
+///
+/// let target = dir.path().join("app.log.zst");
+/// let size = compress_file(&log, &target, CompressFormat::Zstd, 9, None).unwrap();
+/// assert!(size < 1600);
+/// assert_eq!(read_content(&target, Some(16)).unwrap(), b"GET /health 200\n");
+/// assert!(compress_file(&log, &target, CompressFormat::Zstd, 9, None).is_err());
+/// ```
+pub fn compress_file(
+    path: &Path,
+    target: &Path,
+    format: CompressFormat,
+    level: u32,
+    throttle: Option<&Throttle>,
+) -> io::Result<u64> {
+    if target.symlink_metadata().is_ok() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("{} already exists", target.display()),
+        ));
+    }
+    let before = fs::metadata(path)?;
+    let dir = match target.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let mut temp = tempfile::NamedTempFile::new_in(dir)?;
+    let mut original = Hashed::new(throttled(File::open(path)?, throttle));
+    match format {
+        CompressFormat::Gzip => {
+            let level = flate2::Compression::new(level);
+            let mut encoder = GzEncoder::new(temp.as_file_mut(), level);
+            io::copy(&mut original, &mut encoder)?;
+            encoder.finish()?;
+        }
+        CompressFormat::Zstd => {
+            let level = i32::try_from(level).unwrap_or(i32::MAX);
+            let mut encoder = zstd::stream::Encoder::new(temp.as_file_mut(), level)?;
+            io::copy(&mut original, &mut encoder)?;
+            encoder.finish()?;
+        }
+    }
+    temp.as_file().sync_all()?;
+
+    let after = fs::metadata(path)?;
+    if (after.len(), after.modified()?) != (before.len(), before.modified()?) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{} changed while it was being compressed", path.display()),
+        ));
+    }
+    let mut compressed = BufReader::new(throttled(File::open(temp.path())?, throttle));
+    let mut decompressed = blake3::Hasher::new();
+    match format {
+        CompressFormat::Gzip => decompressed.update_reader(MultiGzDecoder::new(compressed))?,
+        CompressFormat::Zstd => {
+            decompressed.update_reader(zstd::stream::Decoder::with_buffer(&mut compressed)?)?
+        }
+    };
+    if decompressed.finalize() != original.hasher.finalize() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("the compressed copy of {} doesn't match it", path.display()),
+        ));
+    }
+
+    temp.as_file().set_permissions(before.permissions())?;
+    temp.as_file().set_modified(before.modified()?)?;
+    let size = temp.as_file().metadata()?.len();
+    temp.persist_noclobber(target).map_err(|err| err.error)?;
+    Ok(size)
+}
+
+/// A reader that hashes what is read through it.
+pub(crate) struct Hashed<R> {
+    inner: R,
+    pub(crate) hasher: blake3::Hasher,
+}
+
+impl<R: Read> Hashed<R> {
+    pub(crate) fn new(inner: R) -> Self {
+        Hashed {
+            inner,
+            hasher: blake3::Hasher::new(),
+        }
+    }
+}
+
+impl<R: Read> Read for Hashed<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.hasher.update(&buf[..read]);
+        Ok(read)
+    }
+}
+
+/// Reads the ISIZE field at the end of a gzip file.
+fn gzip_size(file: &mut File) -> io::Result<Option<u64>> {
+    let mut magic = [0; 2];
+    file.read_exact(&mut magic)?;
+    if magic != [0x1f, 0x8b] || file.metadata()?.len() < 18 {
+        return Ok(None);
+    }
+    file.seek(SeekFrom::End(-4))?;
+    let mut size = [0; 4];
+    file.read_exact(&mut size)?;
+    Ok(Some(u32::from_le_bytes(size).into()))
+}
+
+/// Reads the Frame_Content_Size field of a zstd file's first frame.
+fn zstd_size(file: &mut File) -> io::Result<Option<u64>> {
+    let mut header = [0; 18];
+    let read = file.read(&mut header)?;
+    let header = &header[..read];
+    if header.len() < 5 || header[..4] != [0x28, 0xb5, 0x2f, 0xfd] {
+        return Ok(None);
+    }
+    let descriptor = header[4];
+    let single_segment = descriptor & 0x20 != 0;
+    let field_size = match descriptor >> 6 {
+        0 if single_segment => 1,
+        0 => return Ok(None),
+        1 => 2,
+        2 => 4,
+        _ => 8,
+    };
+    let window_size = if single_segment { 0 } else { 1 };
+    let dictionary_size = [0, 1, 2, 4][usize::from(descriptor & 0x03)];
+    let start = 5 + window_size + dictionary_size;
+    let Some(field) = header.get(start..start + field_size) else {
+        return Ok(None);
+    };
+    let mut bytes = [0; 8];
+    bytes[..field_size].copy_from_slice(field);
+    let size = u64::from_le_bytes(bytes);
+    // Two-byte sizes are stored less 256
+    Ok(Some(if field_size == 2 { size + 256 } else { size }))
+}
+
+/// Adds up the uncompressed sizes in the indexes of an xz file's streams.
+fn xz_size(file: &mut File) -> io::Result<Option<u64>> {
+    let streams = xz_streams(file)?;
+    Ok(streams.map(|streams| streams.iter().map(|stream| stream.size).sum()))
+}
+
+/// Where one of the streams of an xz file lies, and how much it holds.
+struct XzStream {
+    start: u64,
+    end: u64,
+    size: u64,
+}
+
+/// Finds the streams of an xz file from their indexes, working back from
+/// the last, or returns None if the file doesn't end in a valid one.
+fn xz_streams(file: &mut File) -> io::Result<Option<Vec<XzStream>>> {
+    let mut end = file.metadata()?.len();
+    let mut streams = Vec::new();
+    loop {
+        // Streams can be followed by padding, in multiples of four zero bytes
+        let mut footer = [0; 12];
+        loop {
+            if end < 24 {
+                return Ok(None);
+            }
+            file.seek(SeekFrom::Start(end - 12))?;
+            file.read_exact(&mut footer)?;
+            if footer[8..] != [0; 4] {
+                break;
+            }
+            end -= 4;
+        }
+        if footer[10..] != *b"YZ" {
+            return Ok(None);
+        }
+        let backward_size = (u64::from(u32::from_le_bytes([
+            footer[4], footer[5], footer[6], footer[7],
+        ])) + 1)
+            * 4;
+        let Some(index_start) = (end - 12).checked_sub(backward_size) else {
+            return Ok(None);
+        };
+        file.seek(SeekFrom::Start(index_start))?;
+        let mut index = BufReader::new((&mut *file).take(backward_size));
+        let mut indicator = [0];
+        index.read_exact(&mut indicator)?;
+        if indicator[0] != 0 {
+            return Ok(None);
+        }
+        let mut blocks = 0u64;
+        let mut size = 0u64;
+        for _ in 0..read_varint(&mut index)? {
+            let unpadded = read_varint(&mut index)?;
+            size = size.saturating_add(read_varint(&mut index)?);
+            blocks = blocks.saturating_add(unpadded.div_ceil(4) * 4);
+        }
+        // The stream header, its blocks, then the index
+        let Some(start) = index_start.checked_sub(blocks.saturating_add(12)) else {
+            return Ok(None);
+        };
+        streams.push(XzStream { start, end, size });
+        if start == 0 {
+            streams.reverse();
+            return Ok(Some(streams));
+        }
+        end = start;
+    }
+}
+
+/// Reads one of the xz format's variable-length integers: seven bits a
+/// byte, least significant first, at most nine bytes.
+fn read_varint(reader: &mut impl Read) -> io::Result<u64> {
+    let mut value = 0u64;
+    for i in 0..9 {
+        let mut byte = [0];
+        reader.read_exact(&mut byte)?;
+        value |= u64::from(byte[0] & 0x7f) << (7 * i);
+        if byte[0] & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(io::Error::new(io::ErrorKind::InvalidData, "xz integer is too long"))
+}
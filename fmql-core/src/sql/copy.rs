@@ -0,0 +1,505 @@
+//! Making copies of files for `COPY`, by linking, cloning or copying bytes.
+//!
+//! `USING HARDLINK` gives the copy the original's inode, and `USING
+//! REFLINK` clones it on a copy-on-write file system (`FICLONE` on btrfs
+//! and XFS, `clonefile` on APFS), so a copy of any size takes no time and
+//! no space. Neither works across file systems, and some file systems can't
+//! do either, so every method falls back to copying the bytes, which the
+//! kernel still does without a round trip through user space where it can
+//! (`copy_file_range`). [`copy_file`] returns the method it ended up using.
+//!
+//! A copy keeps what its [`Preserve`] says of the original's metadata, and
+//! with `sync` it is on disk, and listed in its directory on disk, before
+//! [`copy_file`] returns. Byte copies are then made in chunks that are each
+//! flushed as they go, so that a large copy doesn't pile up gigabytes of
+//! unwritten data. [`verify_copy`] reads a copy and its original back and
+//! compares their checksums.
+//!
+//! Byte copies, the slow ones, are written to a partial file next to the
+//! target and renamed into place once complete, so the target is never seen
+//! half written. Their throughput can be capped with a [`Throttle`], and a
+//! copy that fails with an error that may pass, such as a network file
+//! system timing out, is tried again as its [`RetryPolicy`] says, carrying
+//! on from where the partial file stopped. If it keeps failing the partial
+//! file is kept, and the next copy to the same target resumes it, as long
+//! as the original hasn't changed since. [`copy_file`] reports what it took
+//! as a [`Transfer`].
+//!
+//! A copy never replaces an existing file: the caller decides what happens
+//! to a target that is in the way, and a target that is already there is
+//! left alone when the copy fails. A copy that fails once made is removed.
+//!
+//! # Examples
+//!
+//! ```
+//! use fmql_core::sql::ast::CopyMethod;
+//! use fmql_core::sql::copy::{copy_file, verify_copy, CopyOptions};
+//! use fmql_core::sql::retry::RetryPolicy;
+//!
+//! let dir = tempfile::tempdir().unwrap();
+//! let original = dir.path().join("disk.img");
+//! std::fs::write(&original, b"0123456789").unwrap();
+//!
+//! let options = CopyOptions::default();
+//! let copy = dir.path().join("disk-copy.img");
+//! let transfer = copy_file(&original, &copy, CopyMethod::Hardlink, &options).unwrap();
+//! assert_eq!((transfer.method, transfer.bytes), (CopyMethod::Hardlink, 0));
+//! assert_eq!(std::fs::read(&copy).unwrap(), b"0123456789");
+//! assert!(copy_file(&original, &copy, CopyMethod::Bytes, &options).is_err());
+//!
+//! let checked = dir.path().join("checked.img");
+//! let options = CopyOptions { sync: true, retry: RetryPolicy::default(), ..options };
+//! let transfer = copy_file(&original, &checked, CopyMethod::Bytes, &options).unwrap();
+//! assert_eq!((transfer.bytes, transfer.resumed, transfer.retries), (10, 0, 0));
+//! assert_eq!(verify_copy(&original, &checked, None).unwrap().len(), 64);
+//! ```
+
+use std::fs::{self, File, FileTimes, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use serde::Serialize;
+
+use crate::sql::ast::{CopyMethod, Preserve};
+use crate::sql::retry::RetryPolicy;
+use crate::sql::throttle::{throttled, Throttle};
+
+/// How much of a byte copy is written before it is flushed, with `sync`.
+const SYNC_CHUNK: u64 = 64 * 1024 * 1024;
+
+/// How [`copy_file`] makes a copy, whatever the method.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CopyOptions<'a> {
+    /// What the copy keeps of the original besides its contents.
+    pub preserve: Preserve,
+    /// Flush the copy and its directory entry to disk before returning.
+    pub sync: bool,
+    /// Caps how fast byte copies read, shared with whatever else uses it.
+    pub throttle: Option<&'a Throttle>,
+    /// How to try again a copy that fails with a transient error (see
+    /// [`is_transient`]).
+    pub retry: RetryPolicy,
+}
+
+/// What making a copy took.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Transfer {
+    /// How the copy was made; never `Auto`.
+    #[serde(skip)]
+    pub method: CopyMethod,
+    /// The bytes copied, not counting those an earlier, interrupted copy
+    /// had already written; nothing for a hardlink or a reflink.
+    pub bytes: u64,
+    /// The bytes an earlier, interrupted copy had already written.
+    pub resumed: u64,
+    /// How many times the copy was tried again after a transient error.
+    pub retries: u32,
+    /// How long the copy took, in seconds, pauses before retries included.
+    pub seconds: f64,
+}
+
+/// Makes `target`, which must not exist, a copy of `source`, and returns
+/// how: `Auto` tries a reflink, then a hardlink, and the method in the
+/// result is never `Auto` itself.
+///
+/// # Errors
+///
+/// Fails if `target` exists, if the copy can't be made even as bytes, or if
+/// what `options` asks to preserve can't be. A transient error only fails
+/// it once the retries of `options.retry` have failed too.
+pub fn copy_file(
+    source: &Path,
+    target: &Path,
+    method: CopyMethod,
+    options: &CopyOptions,
+) -> io::Result<Transfer> {
+    let started = Instant::now();
+    let mut tries = 0;
+    let mut resumed = None;
+    // A failed make leaves nothing behind, and the target may be someone
+    // else's, so only a copy it made is removed below
+    let made = options.retry.run(|| {
+        tries += 1;
+        make(source, target, method, options, &mut resumed)
+    })?;
+    let retries = tries - 1;
+    let finished = (|| {
+        // A hardlink is the original, and a clone on APFS keeps everything
+        let cloned = cfg!(target_vendor = "apple") && made == CopyMethod::Reflink;
+        if made != CopyMethod::Hardlink && !cloned {
+            keep_metadata(source, target, options.preserve)?;
+        }
+        if options.sync {
+            sync_copy(target)?;
+        }
+        let resumed = resumed.unwrap_or(0);
+        let bytes = match made {
+            CopyMethod::Bytes => target.metadata()?.len().saturating_sub(resumed),
+            _ => 0,
+        };
+        Ok(Transfer {
+            method: made,
+            bytes,
+            resumed,
+            retries,
+            seconds: started.elapsed().as_secs_f64(),
+        })
+    })();
+    if finished.is_err() {
+        let _ = fs::remove_file(target);
+    }
+    finished
+}
+
+/// Tells whether an I/O error may pass if the operation is tried again, as
+/// when a network file system's server is slow or briefly unreachable.
+pub fn is_transient(err: &io::Error) -> bool {
+    matches!(
+        err.kind(),
+        io::ErrorKind::Interrupted | io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock
+    ) || matches!(
+        err.raw_os_error(),
+        Some(
+            libc::EIO
+                | libc::ETIMEDOUT
+                | libc::ESTALE
+                | libc::ECONNRESET
+                | libc::ECONNABORTED
+                | libc::ENETUNREACH
+                | libc::EHOSTUNREACH
+        )
+    )
+}
+
+/// Makes the copy by the first method that works here. `resumed` is set,
+/// if it isn't already, to how much of a byte copy was already there.
+fn make(
+    source: &Path,
+    target: &Path,
+    method: CopyMethod,
+    options: &CopyOptions,
+    resumed: &mut Option<u64>,
+) -> io::Result<CopyMethod> {
+    if matches!(method, CopyMethod::Reflink | CopyMethod::Auto) {
+        match reflink(source, target) {
+            Ok(()) => return Ok(CopyMethod::Reflink),
+            Err(err) if !cannot_link(&err) => return Err(err),
+            Err(_) => {}
+        }
+    }
+    if matches!(method, CopyMethod::Hardlink | CopyMethod::Auto) {
+        match fs::hard_link(source, target) {
+            Ok(()) => return Ok(CopyMethod::Hardlink),
+            Err(err) if !cannot_link(&err) => return Err(err),
+            Err(_) => {}
+        }
+    }
+    let partial = copy_bytes(source, target, options)?;
+    resumed.get_or_insert(partial);
+    Ok(CopyMethod::Bytes)
+}
+
+/// Reads `target` and `source` back, as fast as `throttle` allows, and
+/// returns the BLAKE3 checksum they share, in hex.
+///
+/// # Errors
+///
+/// Fails with `InvalidData` if their contents differ, or if either can't
+/// be read.
+pub fn verify_copy(source: &Path, target: &Path, throttle: Option<&Throttle>) -> io::Result<String> {
+    let (original, copy) = (source.metadata()?, target.metadata()?);
+    let checksum = checksum(source, throttle)?;
+    // A hardlink is the same file, which needn't be read twice
+    let same_file = (original.dev(), original.ino()) == (copy.dev(), copy.ino());
+    if !same_file && checksum != self::checksum(target, throttle)? {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("the copy at {} doesn't match the original", target.display()),
+        ));
+    }
+    Ok(checksum.to_hex().to_string())
+}
+
+/// Returns the BLAKE3 checksum of a file's contents.
+fn checksum(path: &Path, throttle: Option<&Throttle>) -> io::Result<blake3::Hash> {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update_reader(throttled(File::open(path)?, throttle))?;
+    Ok(hasher.finalize())
+}
+
+/// Returns true if an error means this kind of link or clone isn't possible
+/// here, such as across devices, rather than that the copy can't be made.
+fn cannot_link(err: &io::Error) -> bool {
+    err.kind() == io::ErrorKind::Unsupported
+        || matches!(
+            err.raw_os_error(),
+            Some(
+                libc::EXDEV
+                    | libc::EOPNOTSUPP
+                    | libc::ENOTTY
+                    | libc::EINVAL
+                    | libc::ENOSYS
+                    | libc::EPERM
+                    | libc::EMLINK
+            )
+        )
+}
+
+/// Copies the contents of `source` into a new file by way of its partial
+/// file, carrying on with one an interrupted copy left, and returns how
+/// many bytes that already held. With `sync`, the copy is written in chunks
+/// that are each flushed to disk before the next. The partial file is kept
+/// if the copy fails with a transient error, for the next try to resume.
+fn copy_bytes(source: &Path, target: &Path, options: &CopyOptions) -> io::Result<u64> {
+    if target.symlink_metadata().is_ok() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("{} already exists", target.display()),
+        ));
+    }
+    let partial = partial_path(target);
+    let mut input = File::open(source)?;
+    let resumed = resumable(&partial, &input.metadata()?)?;
+    let mut output = OpenOptions::new().write(true).create(true).truncate(false).open(&partial)?;
+
+    let copied = (|| {
+        input.seek(SeekFrom::Start(resumed))?;
+        output.seek(SeekFrom::Start(resumed))?;
+        let mut input = throttled(&input, options.throttle);
+        if !options.sync {
+            return io::copy(&mut input, &mut output).map(|_| ());
+        }
+        while io::copy(&mut (&mut input).take(SYNC_CHUNK), &mut output)? > 0 {
+            output.sync_data()?;
+        }
+        Ok(())
+    })();
+    match copied.and_then(|()| publish(&partial, target)) {
+        Ok(()) => Ok(resumed),
+        Err(err) => {
+            if !is_transient(&err) {
+                let _ = fs::remove_file(&partial);
+            }
+            Err(err)
+        }
+    }
+}
+
+/// Gives the finished partial file its target's name, unless something has
+/// taken that name since the copy started. Linking fails rather than
+/// replace a file, where a rename would replace it; a file system that
+/// can't link gets a rename after one last look.
+fn publish(partial: &Path, target: &Path) -> io::Result<()> {
+    match fs::hard_link(partial, target) {
+        Ok(()) => {
+            // The copy is in place, so a partial file left over does no harm
+            let _ = fs::remove_file(partial);
+            Ok(())
+        }
+        Err(err) if !cannot_link(&err) => Err(err),
+        Err(_) if target.symlink_metadata().is_ok() => Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("{} already exists", target.display()),
+        )),
+        Err(_) => fs::rename(partial, target),
+    }
+}
+
+/// Returns where a byte copy to `target` is written until it is complete.
+fn partial_path(target: &Path) -> PathBuf {
+    let name = target.file_name().unwrap_or_default().to_string_lossy();
+    target.with_file_name(format!(".{}.fmql-partial", name))
+}
+
+/// Returns how much of a copy of the original with `metadata` the partial
+/// file at `partial` holds: all of it, unless it is longer than the
+/// original or the original was changed after it was last written, in which
+/// case it is removed and the copy starts afresh.
+fn resumable(partial: &Path, metadata: &fs::Metadata) -> io::Result<u64> {
+    let Ok(found) = partial.symlink_metadata() else {
+        return Ok(0);
+    };
+    let current = found.modified()? >= metadata.modified()?;
+    if found.is_file() && found.len() <= metadata.len() && current {
+        return Ok(found.len());
+    }
+    fs::remove_file(partial)?;
+    Ok(0)
+}
+
+/// Gives the new copy at `target` what `preserve` says to keep of the
+/// metadata of `source`. The permissions come after the owner, since
+/// changing the owner clears setuid and setgid, and the times last, since
+/// setting extended attributes may change them.
+fn keep_metadata(source: &Path, target: &Path, preserve: Preserve) -> io::Result<()> {
+    let metadata = source.metadata()?;
+    let copy = OpenOptions::new().write(true).open(target)?;
+    if preserve.xattrs {
+        copy_xattrs(source, &copy)?;
+    }
+    if preserve.owner {
+        std::os::unix::fs::fchown(&copy, Some(metadata.uid()), Some(metadata.gid()))?;
+    }
+    if preserve.permissions {
+        copy.set_permissions(metadata.permissions())?;
+    }
+    if preserve.times {
+        let times = FileTimes::new()
+            .set_accessed(metadata.accessed()?)
+            .set_modified(metadata.modified()?);
+        copy.set_times(times)?;
+    }
+    Ok(())
+}
+
+/// Flushes the copy at `target`, and the directory entry that names it, to
+/// disk.
+fn sync_copy(target: &Path) -> io::Result<()> {
+    File::open(target)?.sync_all()?;
+    let dir = match target.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    File::open(dir)?.sync_all()
+}
+
+/// Copies the extended attributes of `source` onto `target`.
+#[cfg(any(target_os = "linux", target_vendor = "apple"))]
+fn copy_xattrs(source: &Path, target: &File) -> io::Result<()> {
+    use std::ffi::{CStr, CString};
+    use std::os::fd::AsRawFd;
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = CString::new(source.as_os_str().as_bytes())?;
+    // SAFETY: the path is NUL-terminated, and the buffer is as long as the
+    // size passed.
+    let names = read_sized(|buffer, size| unsafe {
+        #[cfg(target_os = "linux")]
+        let listed = libc::llistxattr(path.as_ptr(), buffer.cast(), size);
+        #[cfg(target_vendor = "apple")]
+        let listed = libc::listxattr(path.as_ptr(), buffer.cast(), size, libc::XATTR_NOFOLLOW);
+        listed
+    })?;
+    for name in names.split(|&byte| byte == 0).filter(|name| !name.is_empty()) {
+        let name = CString::new(name)?;
+        let name: &CStr = &name;
+        // SAFETY: as above.
+        let value = read_sized(|buffer, size| unsafe {
+            #[cfg(target_os = "linux")]
+            let read = libc::lgetxattr(path.as_ptr(), name.as_ptr(), buffer, size);
+            #[cfg(target_vendor = "apple")]
+            let read = libc::getxattr(
+                path.as_ptr(),
+                name.as_ptr(),
+                buffer,
+                size,
+                0,
+                libc::XATTR_NOFOLLOW,
+            );
+            read
+        })?;
+        // SAFETY: the descriptor is open, the name is NUL-terminated and the
+        // value is as long as the size passed.
+        let written = unsafe {
+            #[cfg(target_os = "linux")]
+            let written = libc::fsetxattr(
+                target.as_raw_fd(),
+                name.as_ptr(),
+                value.as_ptr().cast(),
+                value.len(),
+                0,
+            );
+            #[cfg(target_vendor = "apple")]
+            let written = libc::fsetxattr(
+                target.as_raw_fd(),
+                name.as_ptr(),
+                value.as_ptr().cast(),
+                value.len(),
+                0,
+                0,
+            );
+            written
+        };
+        if written != 0 {
+            let err = io::Error::last_os_error();
+            return Err(io::Error::new(
+                err.kind(),
+                format!("can't copy the attribute {}: {}", name.to_string_lossy(), err),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Extended attributes can't be copied on this platform.
+#[cfg(not(any(target_os = "linux", target_vendor = "apple")))]
+fn copy_xattrs(_source: &Path, _target: &File) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "extended attributes can't be copied on this system",
+    ))
+}
+
+/// Calls a `listxattr`-style function, which fills a buffer of the size it
+/// is given and returns how much it wrote, or with an empty buffer how big
+/// one it needs, until the buffer is big enough.
+#[cfg(any(target_os = "linux", target_vendor = "apple"))]
+fn read_sized(mut call: impl FnMut(*mut libc::c_void, usize) -> isize) -> io::Result<Vec<u8>> {
+    loop {
+        let size = usize::try_from(call(std::ptr::null_mut(), 0))
+            .map_err(|_| io::Error::last_os_error())?;
+        let mut buffer = vec![0u8; size];
+        match usize::try_from(call(buffer.as_mut_ptr().cast(), buffer.len())) {
+            Ok(read) => {
+                buffer.truncate(read);
+                return Ok(buffer);
+            }
+            // It grew in between; ask again
+            Err(_) if io::Error::last_os_error().raw_os_error() == Some(libc::ERANGE) => {}
+            Err(_) => return Err(io::Error::last_os_error()),
+        }
+    }
+}
+
+/// Clones `source` into a new file sharing its data.
+#[cfg(target_os = "linux")]
+fn reflink(source: &Path, target: &Path) -> io::Result<()> {
+    use std::os::fd::AsRawFd;
+
+    let input = File::open(source)?;
+    let output = OpenOptions::new().write(true).create_new(true).open(target)?;
+    // SAFETY: both descriptors stay open for the duration of the call.
+    let result = unsafe { libc::ioctl(output.as_raw_fd(), libc::FICLONE, input.as_raw_fd()) };
+    let cloned = match result {
+        0 => Ok(()),
+        _ => Err(io::Error::last_os_error()),
+    };
+    if cloned.is_err() {
+        drop(output);
+        let _ = fs::remove_file(target);
+    }
+    cloned
+}
+
+/// Clones `source` into a new file sharing its data.
+#[cfg(target_vendor = "apple")]
+fn reflink(source: &Path, target: &Path) -> io::Result<()> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let source = CString::new(source.as_os_str().as_bytes())?;
+    let target = CString::new(target.as_os_str().as_bytes())?;
+    // SAFETY: both paths are NUL-terminated strings that outlive the call.
+    if unsafe { libc::clonefile(source.as_ptr(), target.as_ptr(), 0) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Reflinks aren't supported on this platform.
+#[cfg(not(any(target_os = "linux", target_vendor = "apple")))]
+fn reflink(_source: &Path, _target: &Path) -> io::Result<()> {
+    Err(io::Error::from(io::ErrorKind::Unsupported))
+}
@@ -39,7 +39,7 @@ use sqlparser::dialect::{Dialect, GenericDialect};
 /// The dialect can be used to parse SQL queries containing file paths:
 ///
 /// ```no_run
-/// use fmql::sql::dialect::FileDialect;
+/// use fmql_core::sql::dialect::FileDialect;
 /// use sqlparser::parser::Parser;
 ///
 /// let sql = "SELECT * FROM ~/Documents WHERE name LIKE '%.pdf'";
@@ -0,0 +1,173 @@
+//! Comparing two sets of results, for `fmql diff-results` and `--baseline`.
+//!
+//! A query saved with `--format json` today and run again tomorrow tells
+//! what drifted in between: [`diff_rows`] matches the rows of the two runs
+//! by `path` and reports the rows only the new run has, those only the old
+//! one had, and, for rows both have, each attribute whose value changed.
+//! The results may be a bare JSON array or wrapped by `--envelope` or
+//! `--schema`; [`parse_rows`] reads either. Rows are compared as JSON, so
+//! both runs should come from the same query: an attribute one run selected
+//! and the other didn't counts as a change.
+//!
+//! # Examples
+//!
+//! ```
+//! use fmql_core::sql::diff::{diff_rows, parse_rows};
+//!
+//! let old = r#"[{"path": "/srv/a", "size": 3}, {"path": "/srv/b", "size": 5}]"#;
+//! let new = r#"{"files": [{"path": "/srv/a", "size": 8}, {"path": "/srv/c", "size": 1}]}"#;
+//! let (old, new) = (parse_rows(old).unwrap(), parse_rows(new).unwrap());
+//!
+//! let diff = diff_rows(&old, &new, &[]).unwrap();
+//! assert_eq!(diff.added[0]["path"], "/srv/c");
+//! assert_eq!(diff.removed[0]["path"], "/srv/b");
+//! assert_eq!(diff.changed[0].path, "/srv/a");
+//! assert_eq!(diff.changed[0].changes[0].attribute, "size");
+//!
+//! // Attributes that change on their own can be left out
+//! let diff = diff_rows(&old, &new, &["size".to_string()]).unwrap();
+//! assert!(diff.changed.is_empty());
+//! ```
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::Path;
+
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::sql::executor::{ExecutorError, Result};
+
+/// One row of results, as its JSON object.
+pub type Row = serde_json::Map<String, Value>;
+
+/// What changed between two sets of results, each list in path order.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct RowDiff {
+    /// The rows only the new results have.
+    pub added: Vec<Row>,
+    /// The rows only the old results had.
+    pub removed: Vec<Row>,
+    /// The rows both have, with the attributes that differ.
+    pub changed: Vec<ChangedRow>,
+}
+
+impl RowDiff {
+    /// Returns true if the results were the same.
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+/// A row both sets of results have, whose attributes differ.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ChangedRow {
+    /// The path the row is for.
+    pub path: String,
+    /// Each attribute that differs, in name order.
+    pub changes: Vec<AttributeChange>,
+}
+
+/// An attribute whose value differs between two runs. A run that didn't
+/// give the attribute counts as having given null.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AttributeChange {
+    /// The name of the attribute.
+    pub attribute: String,
+    /// Its value in the old results.
+    pub old: Value,
+    /// Its value in the new results.
+    pub new: Value,
+}
+
+/// Reads the results fmql wrote to `path` as JSON.
+pub fn read_rows(path: &Path) -> Result<Vec<Row>> {
+    let text = fs::read_to_string(path)?;
+    parse_rows(&text).map_err(|err| match err {
+        ExecutorError::InvalidResults(message) => {
+            ExecutorError::InvalidResults(format!("{}: {}", path.display(), message))
+        }
+        err => err,
+    })
+}
+
+/// Parses results written as JSON: an array of rows, or a document that
+/// holds one under `files` or `groups`, as `--envelope` and `--schema` do.
+pub fn parse_rows(text: &str) -> Result<Vec<Row>> {
+    let invalid = |message: String| ExecutorError::InvalidResults(message);
+    let value: Value = serde_json::from_str(text).map_err(|err| invalid(err.to_string()))?;
+    let rows = match value {
+        Value::Array(rows) => rows,
+        Value::Object(mut document) => match ["files", "groups"]
+            .into_iter()
+            .find_map(|key| document.remove(key))
+        {
+            Some(Value::Array(rows)) => rows,
+            _ => return Err(invalid("expected a list of files or groups".to_string())),
+        },
+        _ => return Err(invalid("expected a list of results".to_string())),
+    };
+    rows.into_iter()
+        .map(|row| match row {
+            Value::Object(row) => Ok(row),
+            _ => Err(invalid("expected each result to be an object".to_string())),
+        })
+        .collect()
+}
+
+/// Turns results as fmql serializes them into rows to compare.
+pub fn to_rows<T: Serialize>(results: &[T]) -> Result<Vec<Row>> {
+    parse_rows(&serde_json::to_string(results).map_err(std::io::Error::from)?)
+}
+
+/// Compares `old` and `new` results by path, leaving the attributes named
+/// in `ignore` out of the comparison. Fails if a row has no path, or two
+/// rows of the same results have the same one.
+pub fn diff_rows(old: &[Row], new: &[Row], ignore: &[String]) -> Result<RowDiff> {
+    let old = by_path(old)?;
+    let mut new = by_path(new)?;
+    let mut diff = RowDiff::default();
+    for (path, before) in old {
+        let Some(after) = new.remove(path) else {
+            diff.removed.push(before.clone());
+            continue;
+        };
+        let names: BTreeSet<&String> = before.keys().chain(after.keys()).collect();
+        let changes: Vec<AttributeChange> = names
+            .into_iter()
+            .filter(|name| !ignore.contains(name))
+            .filter_map(|name| {
+                let (old, new) = (before.get(name), after.get(name));
+                (old != new).then(|| AttributeChange {
+                    attribute: name.clone(),
+                    old: old.cloned().unwrap_or(Value::Null),
+                    new: new.cloned().unwrap_or(Value::Null),
+                })
+            })
+            .collect();
+        if !changes.is_empty() {
+            diff.changed.push(ChangedRow {
+                path: path.to_string(),
+                changes,
+            });
+        }
+    }
+    diff.added = new.into_values().cloned().collect();
+    Ok(diff)
+}
+
+/// Indexes rows by their path.
+fn by_path(rows: &[Row]) -> Result<BTreeMap<&str, &Row>> {
+    let mut indexed = BTreeMap::new();
+    for row in rows {
+        let Some(Value::String(path)) = row.get("path") else {
+            let message = "every result must have a path to be compared, so groups can't be";
+            return Err(ExecutorError::InvalidResults(message.to_string()));
+        };
+        if indexed.insert(path.as_str(), row).is_some() {
+            let message = format!("{} is in the results more than once", path);
+            return Err(ExecutorError::InvalidResults(message));
+        }
+    }
+    Ok(indexed)
+}
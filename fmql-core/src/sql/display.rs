@@ -0,0 +1,184 @@
+//! Showing a file result as a line of text, the way people read it.
+//!
+//! A [`FileResult`] displays as its modification time, its size and its
+//! path, the line `fmql recent` prints:
+//! `2025-03-31 09:15:00            2048  /srv/logs/app.log`.
+//! [`DisplayOptions`] changes how each part is written: the date through a
+//! `strftime` format in a time zone, the size as bytes or in decimal or
+//! binary units, and the path in full, as just the name, or relative to a
+//! directory. [`FileResult::display_with`] applies them, writing the date
+//! in the order and the size with the separators of
+//! [`DisplayOptions::locale`] unless a date format is given.
+//!
+//! # Examples
+//!
+//! ```
+//! use fmql_core::sql::display::{format_size, DisplayOptions, PathStyle, SizeUnits};
+//! use fmql_core::sql::{execute_query, parse_sql};
+//!
+//! assert_eq!(format_size(512, SizeUnits::Binary), "512 B");
+//! assert_eq!(format_size(1536, SizeUnits::Binary), "1.5 KiB");
+//! assert_eq!(format_size(25_000_000, SizeUnits::Decimal), "25 MB");
+//! assert_eq!(format_size(1536, SizeUnits::Bytes), "1536");
+//!
+//! let dir = tempfile::tempdir().unwrap();
+//! let dir = dir.path().canonicalize().unwrap();
+//! std::fs::write(dir.join("app.log"), vec![b'x'; 2048]).unwrap();
+//! let query = parse_sql(&format!("SELECT * FROM '{}'", dir.display())).unwrap();
+//! let file = &execute_query(&query).unwrap()[0];
+//!
+//! let options = DisplayOptions {
+//!     date_format: "%Y".to_string(),
+//!     size_units: SizeUnits::Binary,
+//!     path_style: PathStyle::Relative(dir.clone()),
+//!     ..Default::default()
+//! };
+//! let line = file.display_with(&options).to_string();
+//! assert!(line.ends_with("2.0 KiB  app.log"));
+//! assert!(file.to_string().ends_with(&format!("2048  {}", dir.join("app.log").display())));
+//! ```
+
+use std::fmt;
+use std::path::PathBuf;
+
+use chrono::format::{Item, StrftimeItems};
+
+use crate::sql::executor::FileResult;
+use crate::sql::locale::Locale;
+use crate::sql::paths::display_path;
+use crate::sql::timezone::Tz;
+
+/// The date format results are shown with unless told otherwise, the one
+/// text output uses.
+pub const DEFAULT_DATE_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+/// How to write a file result's date, size and path.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DisplayOptions {
+    /// A `strftime` format for the modification time. One chrono can't read
+    /// falls back to [`DEFAULT_DATE_FORMAT`].
+    pub date_format: String,
+    /// The time zone the modification time is shown in.
+    pub time_zone: Tz,
+    /// How the size is written.
+    pub size_units: SizeUnits,
+    /// How the path is written.
+    pub path_style: PathStyle,
+    /// The locale the size's separators, and the date's order under the
+    /// default format, come from.
+    pub locale: Locale,
+}
+
+impl Default for DisplayOptions {
+    fn default() -> Self {
+        DisplayOptions {
+            date_format: DEFAULT_DATE_FORMAT.to_string(),
+            time_zone: Tz::UTC,
+            size_units: SizeUnits::Bytes,
+            path_style: PathStyle::Full,
+            locale: Locale::C,
+        }
+    }
+}
+
+/// How a size is written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SizeUnits {
+    /// A number of bytes, `1536`.
+    #[default]
+    Bytes,
+    /// Powers of 1000, as disks are sold: `1.5 kB`.
+    Decimal,
+    /// Powers of 1024, as `ls -h` and `du -h` count: `1.5 KiB`.
+    Binary,
+}
+
+/// How a path is written.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum PathStyle {
+    /// The whole path, as the query found it.
+    #[default]
+    Full,
+    /// Just the file name.
+    Name,
+    /// The path below this directory, or the whole path for a file that
+    /// isn't below it.
+    Relative(PathBuf),
+}
+
+/// Writes a size in `units`. Sizes of a unit or more are rounded to one
+/// decimal place below 10 of it, and to a whole number above.
+pub fn format_size(bytes: u64, units: SizeUnits) -> String {
+    let (base, names) = match units {
+        SizeUnits::Bytes => return bytes.to_string(),
+        SizeUnits::Decimal => (1000.0, ["B", "kB", "MB", "GB", "TB", "PB", "EB"]),
+        SizeUnits::Binary => (1024.0, ["B", "KiB", "MiB", "GiB", "TiB", "PiB", "EiB"]),
+    };
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= base && unit < names.len() - 1 {
+        size /= base;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else if size < 10.0 {
+        format!("{:.1} {}", size, names[unit])
+    } else {
+        format!("{:.0} {}", size, names[unit])
+    }
+}
+
+/// A file result with the options to show it with; see
+/// [`FileResult::display_with`].
+pub struct FileDisplay<'a> {
+    file: &'a FileResult,
+    options: &'a DisplayOptions,
+}
+
+impl FileResult {
+    /// Returns something that shows the file as [`DisplayOptions`] say.
+    pub fn display_with<'a>(&'a self, options: &'a DisplayOptions) -> FileDisplay<'a> {
+        FileDisplay { file: self, options }
+    }
+}
+
+impl fmt::Display for FileDisplay<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let options = self.options;
+        let format = &options.date_format;
+        let readable = !StrftimeItems::new(format).any(|item| matches!(item, Item::Error));
+        let modified = match format.as_str() {
+            format if readable && format != DEFAULT_DATE_FORMAT => {
+                self.file.modified.with_timezone(&options.time_zone).format(format).to_string()
+            }
+            _ => options.locale.format_datetime(&self.file.modified, options.time_zone),
+        };
+        let size = match options.size_units {
+            SizeUnits::Bytes => {
+                options.locale.format_integer(i64::try_from(self.file.size).unwrap_or(i64::MAX))
+            }
+            units => match format_size(self.file.size, units).split_once(' ') {
+                Some((number, unit)) => format!("{} {}", options.locale.localize(number), unit),
+                None => format_size(self.file.size, units),
+            },
+        };
+
+        let path = match &options.path_style {
+            PathStyle::Full => display_path(&self.file.path),
+            PathStyle::Name => self.file.name.clone(),
+            PathStyle::Relative(base) => match self.file.path.strip_prefix(base) {
+                Ok(below) if !below.as_os_str().is_empty() => display_path(below),
+                _ => display_path(&self.file.path),
+            },
+        };
+        write!(f, "{}  {:>14}  {}", modified, size, path)
+    }
+}
+
+impl fmt::Display for FileResult {
+    /// Shows the file with the default [`DisplayOptions`].
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.display_with(&DisplayOptions::default()).fmt(f)
+    }
+}
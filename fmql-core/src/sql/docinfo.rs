@@ -0,0 +1,173 @@
+//! What documents record about themselves: the title and author of a PDF
+//! or Office document and the page count of a PDF, for `doc_title`,
+//! `doc_author` and `pdf_page_count`.
+//!
+//! These come from the document's own records rather than its text, so no
+//! page is read and nothing is recognized: a PDF's from its information
+//! dictionary and page tree (see [`pdf`](crate::sql::pdf)), and a Word,
+//! Excel or PowerPoint file's from the `docProps/core.xml` part of its zip,
+//! which is unpacked on its own. That keeps them cheap enough for `WHERE`
+//! over a whole documents folder, such as
+//! `WHERE doc_author = 'Legal' AND pdf_page_count > 40`.
+//!
+//! A document that doesn't record a title or author, and every file that
+//! isn't a document, has NULL for them.
+//!
+//! # Examples
+//!
+//! ```
+//! use std::io::Write;
+//!
+//! use fmql_core::sql::docinfo::read_docinfo;
+//! use zip::write::SimpleFileOptions;
+//!
+//! let dir = tempfile::tempdir().unwrap();
+//! let path = dir.path().join("memo.docx");
+//! let mut docx = zip::ZipWriter::new(std::fs::File::create(&path).unwrap());
+//! docx.start_file("docProps/core.xml", SimpleFileOptions::default()).unwrap();
+//! docx.write_all(
+//!     br#"<cp:coreProperties xmlns:dc="http://purl.org/dc/elements/1.1/">
+//!       <dc:title>Q3 plan &amp; budget</dc:title><dc:creator>Ana Lima</dc:creator>
+//!     </cp:coreProperties>"#,
+//! )
+//! .unwrap();
+//! docx.finish().unwrap();
+//!
+//! let info = read_docinfo(&path).unwrap().unwrap();
+//! assert_eq!(info.title.as_deref(), Some("Q3 plan & budget"));
+//! assert_eq!(info.author.as_deref(), Some("Ana Lima"));
+//! assert_eq!(info.pages, None);
+//! assert_eq!(read_docinfo(&dir.path().join("notes.txt")).unwrap(), None);
+//! ```
+
+use std::fs::File;
+use std::io::{self, BufReader, Read};
+use std::path::Path;
+
+use crate::sql::budget::Budgeted;
+use crate::sql::pdf;
+
+/// The most of `docProps/core.xml` that is read.
+const MAX_CORE: u64 = 1024 * 1024;
+
+/// What a document records about itself.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DocInfo {
+    /// The document's title.
+    pub title: Option<String>,
+    /// Who wrote the document.
+    pub author: Option<String>,
+    /// How many pages a PDF has; None for other documents.
+    pub pages: Option<u64>,
+}
+
+/// Reads what a document records about itself, or returns None if the
+/// file's extension isn't that of a PDF or an Office document.
+///
+/// # Errors
+///
+/// Fails if the file can't be read, or with `InvalidData` if an Office
+/// document isn't a zip file.
+pub fn read_docinfo(path: &Path) -> io::Result<Option<DocInfo>> {
+    let extension = match path.extension().and_then(|extension| extension.to_str()) {
+        Some(extension) => extension.to_ascii_lowercase(),
+        None => return Ok(None),
+    };
+    match extension.as_str() {
+        "pdf" => {
+            let mut bytes = Vec::new();
+            Budgeted::new(File::open(path)?).read_to_end(&mut bytes)?;
+            Ok(Some(DocInfo {
+                title: pdf::info(&bytes, "Title"),
+                author: pdf::info(&bytes, "Author"),
+                pages: pdf::page_count(&bytes),
+            }))
+        }
+        "docx" | "docm" | "xlsx" | "xlsm" | "pptx" | "pptm" => read_core_properties(path).map(Some),
+        _ => Ok(None),
+    }
+}
+
+/// Reads the title and author from an Office document's core properties.
+fn read_core_properties(path: &Path) -> io::Result<DocInfo> {
+    let file = BufReader::new(File::open(path)?);
+    let mut package = zip::ZipArchive::new(file).map_err(zip_error)?;
+    let mut xml = String::new();
+    match package.by_name("docProps/core.xml") {
+        Ok(part) => {
+            // A part that isn't text is no title
+            let _ = Budgeted::new(part.take(MAX_CORE)).read_to_string(&mut xml);
+        }
+        Err(zip::result::ZipError::FileNotFound) => {}
+        Err(err) => return Err(zip_error(err)),
+    }
+    Ok(DocInfo {
+        title: element(&xml, "dc:title"),
+        author: element(&xml, "dc:creator"),
+        pages: None,
+    })
+}
+
+/// Returns the text of the first `name` element of some XML, or None if it
+/// has none or it is blank.
+fn element(xml: &str, name: &str) -> Option<String> {
+    let open = format!("<{}", name);
+    let close = format!("</{}>", name);
+    let (start, _) = xml.match_indices(&open).find(|(at, _)| {
+        xml[at + open.len()..].starts_with(|c: char| c == '>' || c == '/' || c.is_whitespace())
+    })?;
+    let tag_end = start + xml[start..].find('>')?;
+    if xml[..tag_end].ends_with('/') {
+        return None;
+    }
+    let text = &xml[tag_end + 1..];
+    let text = unescape(text[..text.find(&close)?].trim());
+    (!text.is_empty()).then_some(text)
+}
+
+/// Resolves the entities and character references of XML text.
+fn unescape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(at) = rest.find('&') {
+        out.push_str(&rest[..at]);
+        rest = &rest[at..];
+        let Some(end) = rest.find(';') else {
+            break;
+        };
+        let entity = &rest[1..end];
+        let resolved = match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ => match entity.strip_prefix("#x").or_else(|| entity.strip_prefix("#X")) {
+                Some(hex) => u32::from_str_radix(hex, 16).ok().and_then(char::from_u32),
+                None => {
+                    let decimal = entity.strip_prefix('#').and_then(|n| n.parse().ok());
+                    decimal.and_then(char::from_u32)
+                }
+            },
+        };
+        match resolved {
+            Some(c) => {
+                out.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+fn zip_error(err: zip::result::ZipError) -> io::Error {
+    match err {
+        zip::result::ZipError::Io(err) => err,
+        err => io::Error::new(io::ErrorKind::InvalidData, err.to_string()),
+    }
+}
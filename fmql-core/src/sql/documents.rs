@@ -0,0 +1,409 @@
+//! The text of PDFs and Word and Excel documents, for `content` checks.
+//!
+//! A documents folder is mostly PDFs, `.docx` and `.xlsx` files, whose
+//! text is compressed inside them where `content LIKE` can't see it. With
+//! the `documents` feature, [`extract_text`] pulls the text out, and
+//! `content` and `matches` see that text rather than the file's bytes, so
+//! that the contract mentioning Acme is one query:
+//! `WITH RECURSIVE SELECT path FROM ~/Documents WHERE CONTAINS_STR(content, 'Acme')`.
+//!
+//! - A Word document gives its paragraphs, one to a line.
+//! - An Excel workbook gives the rows of each sheet, one to a line, with
+//!   tabs between the cells.
+//! - A PDF gives the text its pages draw, a line for each line of text.
+//!   Fonts that number their own glyphs, as many CJK and scanned
+//!   documents' fonts do, and encrypted documents give no text.
+//!
+//! Documents are told by their extension, and `CONTAINS_BYTES` still
+//! searches a document's bytes.
+//!
+//! # Examples
+//!
+//! ```
+//! use std::io::{Cursor, Write};
+//!
+//! use fmql_core::sql::documents::{extract_text, Document};
+//! use zip::write::SimpleFileOptions;
+//!
+//! let mut docx = zip::ZipWriter::new(Cursor::new(Vec::new()));
+//! docx.start_file("word/document.xml", SimpleFileOptions::default()).unwrap();
+//! docx.write_all(
+//!     br#"<w:document xmlns:w="http://schemas.openxmlformats.org/wordprocessingml/2006/main">
+//!     <w:body>
+//!       <w:p><w:r><w:t>Supply agreement</w:t></w:r></w:p>
+//!       <w:p>
+//!         <w:r><w:t xml:space="preserve">between Acme </w:t></w:r>
+//!         <w:r><w:t>&amp; Co</w:t></w:r>
+//!       </w:p>
+//!     </w:body>
+//!   </w:document>"#,
+//! )
+//! .unwrap();
+//! let bytes = docx.finish().unwrap().into_inner();
+//!
+//! let document = Document::of("contract.docx".as_ref()).unwrap();
+//! let text = extract_text(document, &bytes).unwrap();
+//! assert_eq!(text.lines().collect::<Vec<_>>(), ["Supply agreement", "between Acme & Co"]);
+//! assert!(extract_text(Document::Pdf, &bytes).is_err());
+//! ```
+
+use std::io::{self, Cursor, Read};
+use std::path::Path;
+
+use memchr::memmem;
+use quick_xml::escape::resolve_predefined_entity;
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+
+use crate::sql::budget::Budgeted;
+use crate::sql::pdf::{hex_string, inflate, is_delimiter, literal_string, streams, text_string};
+
+/// The most of a part of a document that is unpacked, so that a small
+/// document can't expand into more than memory allows.
+const MAX_PART: u64 = 256 * 1024 * 1024;
+
+/// A document format whose text can be extracted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Document {
+    Pdf,
+    Docx,
+    Xlsx,
+}
+
+impl Document {
+    /// Returns the format a file's extension names, if any.
+    pub fn of(path: &Path) -> Option<Document> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "pdf" => Some(Document::Pdf),
+            "docx" => Some(Document::Docx),
+            "xlsx" => Some(Document::Xlsx),
+            _ => None,
+        }
+    }
+}
+
+/// Extracts the text of a document from its bytes.
+///
+/// # Errors
+///
+/// Fails with `InvalidData` if the bytes aren't a document in that format,
+/// or if a [budget](crate::sql::budget) runs out while unpacking it.
+pub fn extract_text(document: Document, bytes: &[u8]) -> io::Result<String> {
+    match document {
+        Document::Pdf => pdf_text(bytes),
+        Document::Docx => docx_text(&mut open_package(bytes)?),
+        Document::Xlsx => xlsx_text(&mut open_package(bytes)?),
+    }
+}
+
+/// An Office document: a zip file of XML parts.
+type Package<'a> = zip::ZipArchive<Cursor<&'a [u8]>>;
+
+fn open_package(bytes: &[u8]) -> io::Result<Package<'_>> {
+    zip::ZipArchive::new(Cursor::new(bytes)).map_err(zip_error)
+}
+
+/// Unpacks a part of an Office document, or returns None if it has no
+/// such part.
+fn read_part(package: &mut Package<'_>, name: &str) -> io::Result<Option<Vec<u8>>> {
+    let part = match package.by_name(name) {
+        Ok(part) => part,
+        Err(zip::result::ZipError::FileNotFound) => return Ok(None),
+        Err(err) => return Err(zip_error(err)),
+    };
+    let mut xml = Vec::new();
+    Budgeted::new(part.take(MAX_PART)).read_to_end(&mut xml)?;
+    Ok(Some(xml))
+}
+
+/// Returns the paragraphs of a Word document's body.
+fn docx_text(package: &mut Package<'_>) -> io::Result<String> {
+    let xml = read_part(package, "word/document.xml")?
+        .ok_or_else(|| invalid("not a Word document"))?;
+    let mut text = String::new();
+    let (mut in_run, mut in_text) = (false, false);
+    walk(&xml, |node| match node {
+        Node::Open("r", _) => in_run = true,
+        Node::Close("r") => in_run = false,
+        Node::Open("t", _) => in_text = true,
+        Node::Close("t") => in_text = false,
+        // Outside a run, `tab` is a tab stop rather than a tab
+        Node::Open("tab", _) if in_run => text.push('\t'),
+        Node::Open("br" | "cr", _) if in_run => text.push('\n'),
+        Node::Close("p") => text.push('\n'),
+        Node::Text(chunk) if in_text => text.push_str(chunk),
+        _ => {}
+    })?;
+    Ok(text)
+}
+
+/// Returns the rows of an Excel workbook's sheets, in the order of the
+/// sheets' numbers.
+fn xlsx_text(package: &mut Package<'_>) -> io::Result<String> {
+    // Text cells hold an index into the workbook's shared strings
+    let mut shared: Vec<String> = Vec::new();
+    if let Some(xml) = read_part(package, "xl/sharedStrings.xml")? {
+        let (mut in_text, mut phonetic) = (false, false);
+        walk(&xml, |node| match node {
+            Node::Open("si", _) => shared.push(String::new()),
+            Node::Open("rPh", _) => phonetic = true,
+            Node::Close("rPh") => phonetic = false,
+            Node::Open("t", _) => in_text = true,
+            Node::Close("t") => in_text = false,
+            Node::Text(chunk) if in_text && !phonetic => {
+                if let Some(string) = shared.last_mut() {
+                    string.push_str(chunk);
+                }
+            }
+            _ => {}
+        })?;
+    }
+    let mut sheets: Vec<String> = package
+        .file_names()
+        .filter(|name| name.starts_with("xl/worksheets/") && name.ends_with(".xml"))
+        .map(str::to_string)
+        .collect();
+    if sheets.is_empty() {
+        return Err(invalid("not an Excel workbook"));
+    }
+    // sheet2.xml before sheet10.xml
+    sheets.sort_by(|a, b| (a.len(), a).cmp(&(b.len(), b)));
+
+    let mut text = String::new();
+    for sheet in sheets {
+        let Some(xml) = read_part(package, &sheet)? else {
+            continue;
+        };
+        let mut row: Vec<String> = Vec::new();
+        let mut cell: Option<(Option<String>, String)> = None;
+        let mut in_value = false;
+        walk(&xml, |node| match node {
+            Node::Open("c", kind) => cell = Some((kind, String::new())),
+            Node::Open("v" | "t", _) => in_value = true,
+            Node::Close("v" | "t") => in_value = false,
+            Node::Text(chunk) if in_value => {
+                if let Some((_, value)) = &mut cell {
+                    value.push_str(chunk);
+                }
+            }
+            Node::Close("c") => {
+                if let Some((kind, value)) = cell.take() {
+                    let value = match kind.as_deref() {
+                        Some("s") => value
+                            .trim()
+                            .parse::<usize>()
+                            .ok()
+                            .and_then(|index| shared.get(index).cloned())
+                            .unwrap_or_default(),
+                        _ => value,
+                    };
+                    row.push(value);
+                }
+            }
+            Node::Close("row") => {
+                text.push_str(&row.join("\t"));
+                text.push('\n');
+                row.clear();
+            }
+            _ => {}
+        })?;
+    }
+    Ok(text)
+}
+
+/// A step through the XML of an Office document.
+enum Node<'a> {
+    /// An element starts, by its name without a namespace prefix, and its
+    /// `t` attribute, which says what an Excel cell holds.
+    Open(&'a str, Option<String>),
+    /// An element ends.
+    Close(&'a str),
+    /// Text, with its entities resolved.
+    Text(&'a str),
+}
+
+/// Calls `visit` for each element and piece of text of an XML part.
+fn walk(xml: &[u8], mut visit: impl FnMut(Node<'_>)) -> io::Result<()> {
+    let mut reader = Reader::from_reader(xml);
+    let mut buffer = Vec::new();
+    loop {
+        match reader.read_event_into(&mut buffer).map_err(|err| invalid(&err.to_string()))? {
+            Event::Start(element) => {
+                visit(Node::Open(element.local_name().as_ref(), kind(&element)))
+            }
+            Event::Empty(element) => {
+                let name = element.local_name();
+                visit(Node::Open(name.as_ref(), kind(&element)));
+                visit(Node::Close(name.as_ref()));
+            }
+            Event::End(element) => visit(Node::Close(element.local_name().as_ref())),
+            Event::Text(text) => visit(Node::Text(&text.xml10_content())),
+            Event::CData(text) => visit(Node::Text(&text)),
+            Event::GeneralRef(entity) => {
+                let resolved = match entity.resolve_char_ref() {
+                    Ok(Some(c)) => Some(c.to_string()),
+                    _ => resolve_predefined_entity(&entity).map(str::to_string),
+                };
+                if let Some(text) = resolved {
+                    visit(Node::Text(&text));
+                }
+            }
+            Event::Eof => return Ok(()),
+            _ => {}
+        }
+        buffer.clear();
+    }
+}
+
+/// Returns an element's `t` attribute.
+fn kind(element: &BytesStart<'_>) -> Option<String> {
+    let attribute = element.try_get_attribute("t").ok().flatten()?;
+    Some(attribute.value.into_owned())
+}
+
+/// Returns the text a PDF's content streams show.
+///
+/// Each stream is inflated if it is compressed with `FlateDecode`, and
+/// left alone if it is compressed some other way, as images are.
+fn pdf_text(bytes: &[u8]) -> io::Result<String> {
+    if !bytes.starts_with(b"%PDF") {
+        return Err(invalid("not a PDF"));
+    }
+    let mut text = String::new();
+    for (dictionary, data) in streams(bytes) {
+        if memmem::find(dictionary, b"/FlateDecode").is_some() {
+            show_text(&inflate(data)?, &mut text);
+        } else if memmem::find(dictionary, b"/Filter").is_none() {
+            show_text(data, &mut text);
+        }
+    }
+    Ok(text)
+}
+
+/// What an operator in a content stream works on.
+enum Operand {
+    String(Vec<u8>),
+    Number(f64),
+    ArrayStart,
+}
+
+/// Appends the text a content stream shows with `Tj`, `TJ`, `'` and `"`,
+/// starting a line where the stream moves down to the next one.
+fn show_text(content: &[u8], text: &mut String) {
+    let mut operands: Vec<Operand> = Vec::new();
+    // The height of the last line placed with Tm, which places words too
+    let mut line = None;
+    let mut i = 0;
+    while i < content.len() {
+        let byte = content[i];
+        match byte {
+            b'%' => {
+                i = memchr::memchr2(b'\n', b'\r', &content[i..]).map_or(content.len(), |n| i + n)
+            }
+            b'(' => {
+                let (string, next) = literal_string(content, i + 1);
+                operands.push(Operand::String(string));
+                i = next;
+            }
+            b'<' if content.get(i + 1) == Some(&b'<') => i += 2,
+            b'<' => {
+                let end = memchr::memchr(b'>', &content[i..]).map_or(content.len(), |n| i + n);
+                operands.push(Operand::String(hex_string(&content[i + 1..end])));
+                i = end + 1;
+            }
+            b'[' => {
+                operands.push(Operand::ArrayStart);
+                i += 1;
+            }
+            b'/' => {
+                i += 1;
+                while i < content.len() && !is_delimiter(content[i]) {
+                    i += 1;
+                }
+            }
+            _ if is_delimiter(byte) => i += 1,
+            _ => {
+                let start = i;
+                while i < content.len() && !is_delimiter(content[i]) {
+                    i += 1;
+                }
+                let word = &content[start..i];
+                if let Some(number) = std::str::from_utf8(word).ok().and_then(|w| w.parse().ok()) {
+                    operands.push(Operand::Number(number));
+                    continue;
+                }
+                let number = |back: usize| match operands.len().checked_sub(back) {
+                    Some(at) => match operands[at] {
+                        Operand::Number(number) => number,
+                        _ => 0.0,
+                    },
+                    None => 0.0,
+                };
+                match word {
+                    b"BT" if !text.ends_with(char::is_whitespace) && !text.is_empty() => {
+                        text.push(' ')
+                    }
+                    b"T*" => new_line(text),
+                    b"Td" | b"TD" if number(1) != 0.0 => new_line(text),
+                    b"Tm" => {
+                        let height = number(1);
+                        if line.is_some_and(|line| line != height) {
+                            new_line(text);
+                        }
+                        line = Some(height);
+                    }
+                    b"Tj" | b"'" | b"\"" => {
+                        if word != b"Tj" {
+                            new_line(text);
+                        }
+                        if let Some(Operand::String(string)) = operands.last() {
+                            text.push_str(&text_string(string));
+                        }
+                    }
+                    b"TJ" => {
+                        let start = operands
+                            .iter()
+                            .rposition(|operand| matches!(operand, Operand::ArrayStart))
+                            .map_or(0, |start| start + 1);
+                        for operand in &operands[start..] {
+                            match operand {
+                                Operand::String(string) => text.push_str(&text_string(string)),
+                                // A gap this wide, in thousandths of the font size, is a space
+                                Operand::Number(gap) if *gap < -250.0 => text.push(' '),
+                                _ => {}
+                            }
+                        }
+                    }
+                    // An inline image's data is not made of operators
+                    b"ID" => {
+                        i = memmem::find(&content[i..], b"EI").map_or(content.len(), |n| i + n + 2)
+                    }
+                    _ => {}
+                }
+                operands.clear();
+            }
+        }
+    }
+    new_line(text);
+}
+
+/// Ends the line of text, unless it is empty.
+fn new_line(text: &mut String) {
+    let trimmed = text.trim_end_matches(' ').len();
+    text.truncate(trimmed);
+    if !text.is_empty() && !text.ends_with('\n') {
+        text.push('\n');
+    }
+}
+
+fn zip_error(err: zip::result::ZipError) -> io::Error {
+    match err {
+        zip::result::ZipError::Io(err) => err,
+        err => invalid(&err.to_string()),
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
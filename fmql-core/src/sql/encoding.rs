@@ -0,0 +1,185 @@
+//! Reading text that isn't UTF-8, so that `content` checks find what they
+//! look for in files Windows wrote.
+//!
+//! Notepad, PowerShell and many Windows services write UTF-16, and older
+//! programs write Latin-1. Read as UTF-8, a UTF-16 file has a zero byte
+//! between every letter and a Latin-1 `é` is no character at all, so
+//! `content LIKE '%error%'` silently misses them. [`decode`] turns a file's
+//! bytes into text in the file's own encoding instead: with
+//! [`TextEncoding::Auto`], the default, a byte order mark names the
+//! encoding, UTF-16 without one is told by its zero bytes, and text that
+//! isn't UTF-8 is read as Latin-1. Naming an encoding reads every file in
+//! it, and [`TextEncoding::Utf8`] reads files as fmql did before it
+//! transcoded anything.
+//!
+//! `CONTAINS_BYTES` searches the bytes as they are, whatever the encoding.
+//!
+//! # Examples
+//!
+//! ```
+//! use fmql_core::sql::encoding::{decode, detect, TextEncoding};
+//!
+//! // "error" as PowerShell's Out-File writes it
+//! let utf16 = b"\xff\xfee\0r\0r\0o\0r\0";
+//! assert_eq!(detect(utf16), TextEncoding::Utf16Le);
+//! assert_eq!(decode(utf16, TextEncoding::Auto), "error");
+//!
+//! let latin1 = b"caf\xe9";
+//! assert_eq!(decode(latin1, TextEncoding::Auto), "café");
+//! assert_eq!(decode(latin1, TextEncoding::Utf8), "caf\u{fffd}");
+//!
+//! assert_eq!("utf-16le".parse::<TextEncoding>(), Ok(TextEncoding::Utf16Le));
+//! ```
+
+use std::fmt;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::sql::compressed::read_content;
+
+/// How much of the start of a file is looked at for the zero bytes of
+/// UTF-16 text.
+const SAMPLE: usize = 4096;
+
+/// The encoding a file's contents are read in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TextEncoding {
+    /// Whatever [`detect`] finds.
+    #[default]
+    Auto,
+    /// UTF-8, with anything that isn't replaced by `�`.
+    Utf8,
+    /// UTF-16, least significant byte first, as Windows writes it.
+    Utf16Le,
+    /// UTF-16, most significant byte first.
+    Utf16Be,
+    /// ISO 8859-1, one byte to each character.
+    Latin1,
+}
+
+impl fmt::Display for TextEncoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            TextEncoding::Auto => "auto",
+            TextEncoding::Utf8 => "utf-8",
+            TextEncoding::Utf16Le => "utf-16le",
+            TextEncoding::Utf16Be => "utf-16be",
+            TextEncoding::Latin1 => "latin1",
+        })
+    }
+}
+
+impl FromStr for TextEncoding {
+    type Err = String;
+
+    /// Reads an encoding's name, in any case: `auto`, `utf-8`, `utf-16le`,
+    /// `utf-16be` or `latin1`, with or without the dash, and `iso-8859-1`
+    /// for Latin-1.
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        let normalized = name.trim().to_ascii_lowercase().replace(['-', '_'], "");
+        match normalized.as_str() {
+            "auto" => Ok(TextEncoding::Auto),
+            "utf8" => Ok(TextEncoding::Utf8),
+            "utf16le" | "utf16" => Ok(TextEncoding::Utf16Le),
+            "utf16be" => Ok(TextEncoding::Utf16Be),
+            "latin1" | "iso88591" => Ok(TextEncoding::Latin1),
+            _ => Err(format!(
+                "Unknown encoding '{}' (expected auto, utf-8, utf-16le, utf-16be or latin1)",
+                name.trim()
+            )),
+        }
+    }
+}
+
+/// Returns the encoding a file's bytes are most likely in, never
+/// [`TextEncoding::Auto`].
+///
+/// A byte order mark decides. Without one, text whose every other byte is
+/// mostly zero is UTF-16, as ASCII letters in UTF-16 are. Otherwise text
+/// is UTF-8 unless it has more bytes UTF-8 can't read than characters it
+/// can, which is how Latin-1 text looks: a file that is UTF-8 but for a
+/// stray byte is still read as UTF-8.
+pub fn detect(bytes: &[u8]) -> TextEncoding {
+    if bytes.starts_with(b"\xef\xbb\xbf") {
+        return TextEncoding::Utf8;
+    }
+    if bytes.starts_with(b"\xff\xfe") {
+        return TextEncoding::Utf16Le;
+    }
+    if bytes.starts_with(b"\xfe\xff") {
+        return TextEncoding::Utf16Be;
+    }
+    let sample = &bytes[..bytes.len().min(SAMPLE)];
+    let units = sample.len() / 2;
+    let zeros = |offset| sample.chunks_exact(2).filter(|unit| unit[offset] == 0).count();
+    let (even, odd) = (zeros(0), zeros(1));
+    if units > 0 && odd * 5 >= units * 2 && even * 20 <= units {
+        return TextEncoding::Utf16Le;
+    }
+    if units > 0 && even * 5 >= units * 2 && odd * 20 <= units {
+        return TextEncoding::Utf16Be;
+    }
+    let (mut readable, mut unreadable) = (0, 0);
+    for chunk in bytes.utf8_chunks() {
+        readable += chunk.valid().chars().filter(|c| !c.is_ascii()).count();
+        unreadable += chunk.invalid().len();
+    }
+    if unreadable > readable {
+        TextEncoding::Latin1
+    } else {
+        TextEncoding::Utf8
+    }
+}
+
+/// Reads bytes as text in `encoding`, or in the one [`detect`] finds for
+/// [`TextEncoding::Auto`]. A byte order mark for the encoding is left
+/// out, and anything the encoding can't read becomes `�`.
+pub fn decode(bytes: &[u8], encoding: TextEncoding) -> String {
+    match encoding {
+        TextEncoding::Auto => decode(bytes, detect(bytes)),
+        TextEncoding::Utf8 => {
+            let bytes = bytes.strip_prefix(b"\xef\xbb\xbf").unwrap_or(bytes);
+            String::from_utf8_lossy(bytes).into_owned()
+        }
+        TextEncoding::Utf16Le => {
+            let bytes = bytes.strip_prefix(b"\xff\xfe").unwrap_or(bytes);
+            decode_utf16(bytes, u16::from_le_bytes)
+        }
+        TextEncoding::Utf16Be => {
+            let bytes = bytes.strip_prefix(b"\xfe\xff").unwrap_or(bytes);
+            decode_utf16(bytes, u16::from_be_bytes)
+        }
+        TextEncoding::Latin1 => bytes.iter().map(|&byte| char::from(byte)).collect(),
+    }
+}
+
+/// Reads a file's contents as text, for `content` and `matches`: what
+/// [`read_content`] reads, decoded in `encoding`. With the `documents`
+/// feature, the text of a PDF, Word or Excel document is what
+/// [`extract_text`](crate::sql::documents::extract_text) finds in it.
+pub fn read_text(
+    path: &Path,
+    decompress: Option<u64>,
+    encoding: TextEncoding,
+) -> io::Result<String> {
+    let content = read_content(path, decompress)?;
+    #[cfg(feature = "documents")]
+    if let Some(document) = crate::sql::documents::Document::of(path) {
+        return crate::sql::documents::extract_text(document, &content);
+    }
+    Ok(decode(&content, encoding))
+}
+
+/// Reads UTF-16 in the byte order `unit` puts its bytes together in.
+fn decode_utf16(bytes: &[u8], unit: fn([u8; 2]) -> u16) -> String {
+    let units = bytes.chunks_exact(2).map(|pair| unit([pair[0], pair[1]]));
+    let mut text: String = char::decode_utf16(units)
+        .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+        .collect();
+    // A read cut short can end half way through a character
+    if bytes.len() % 2 == 1 {
+        text.push(char::REPLACEMENT_CHARACTER);
+    }
+    text
+}
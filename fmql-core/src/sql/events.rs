@@ -0,0 +1,126 @@
+//! Progress events, for programs that show a query's progress as it runs.
+//!
+//! A long scan says nothing until it returns, so a GUI wrapping fmql would
+//! have to guess at its progress or parse what the CLI prints to stderr.
+//! Instead it can give the query an [`Events`] in
+//! [`ExecutionOptions::events`], and be handed an [`Event`] as each step
+//! happens: the scan starting, each directory it goes into, each entry that
+//! matches, each file an UPDATE, COPY, COMPRESS, EXTRACT or ARCHIVE has
+//! dealt with, each file left out with a warning, and the query finishing.
+//!
+//! Events are sent from the thread that did the work, which with
+//! [`ExecutionOptions::jobs`] above 1 is one of several, so a callback has
+//! to be quick and thread-safe. [`Events::channel`] passes them to another
+//! thread instead. Without either, no event is even made.
+//!
+//! [`ExecutionOptions::events`]: crate::sql::ExecutionOptions::events
+//! [`ExecutionOptions::jobs`]: crate::sql::ExecutionOptions::jobs
+//!
+//! # Examples
+//!
+//! ```
+//! use fmql_core::sql::events::{Event, Events};
+//! use fmql_core::sql::executor::{execute_query_with_options, ExecutionOptions};
+//! use fmql_core::sql::parse_sql;
+//!
+//! let dir = tempfile::tempdir().unwrap();
+//! std::fs::write(dir.path().join("notes.txt"), "").unwrap();
+//! std::fs::write(dir.path().join("photo.jpg"), "").unwrap();
+//!
+//! let (events, received) = Events::channel();
+//! let options = ExecutionOptions { events, ..Default::default() };
+//! let sql = format!("SELECT * FROM '{}' WHERE extension = 'txt'", dir.path().display());
+//! execute_query_with_options(&parse_sql(&sql).unwrap(), &options).unwrap();
+//! drop(options);
+//!
+//! let events: Vec<Event> = received.iter().collect();
+//! assert!(matches!(events[0], Event::ScanStarted { .. }));
+//! assert!(events.contains(&Event::EntryMatched { path: dir.path().join("notes.txt") }));
+//! assert!(matches!(events.last(), Some(Event::Finished { rows: 1, error: None, .. })));
+//! ```
+
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::sql::executor::OperationStatus;
+
+/// Something that happened while a query ran.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+    /// The query started listing its targets: the paths after `FROM`, as
+    /// they were expanded.
+    ScanStarted { targets: Vec<PathBuf> },
+    /// The scan went into a directory to list it.
+    DirectoryEntered { path: PathBuf },
+    /// An entry matched the query's condition.
+    EntryMatched { path: PathBuf },
+    /// A statement that changes files dealt with one, whether it changed,
+    /// skipped or failed to change it.
+    OperationApplied {
+        path: PathBuf,
+        status: OperationStatus,
+        /// Why it failed, if it did.
+        error: Option<String>,
+    },
+    /// An entry was left out because it couldn't be read, or the condition
+    /// couldn't be evaluated for it; see [`faults`](crate::sql::faults).
+    Warning { path: PathBuf, message: String },
+    /// The query finished, with this many rows, or failed with an error.
+    Finished {
+        rows: usize,
+        elapsed: Duration,
+        error: Option<String>,
+    },
+}
+
+/// Where a query sends its [`Event`]s, if anywhere. Clones send to the same
+/// place; the default sends nowhere.
+#[derive(Clone, Default)]
+pub struct Events {
+    callback: Option<Arc<dyn Fn(Event) + Send + Sync>>,
+}
+
+impl Events {
+    /// Returns events that are handed to `callback` as they happen.
+    pub fn new(callback: impl Fn(Event) + Send + Sync + 'static) -> Events {
+        Events {
+            callback: Some(Arc::new(callback)),
+        }
+    }
+
+    /// Returns events that are sent down a channel, and the receiving end.
+    /// The channel closes once every clone of the events has been dropped,
+    /// including those in the options the query ran with.
+    pub fn channel() -> (Events, mpsc::Receiver<Event>) {
+        let (sender, receiver) = mpsc::channel();
+        // Nobody listening is no reason to stop the query
+        let events = Events::new(move |event| {
+            let _ = sender.send(event);
+        });
+        (events, receiver)
+    }
+
+    /// Returns true if the events are sent anywhere.
+    pub fn is_enabled(&self) -> bool {
+        self.callback.is_some()
+    }
+
+    /// Sends the event `event` makes, which is only made if the events are
+    /// sent anywhere.
+    pub fn emit(&self, event: impl FnOnce() -> Event) {
+        if let Some(callback) = &self.callback {
+            callback(event());
+        }
+    }
+}
+
+impl fmt::Debug for Events {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Events")
+            .field("enabled", &self.is_enabled())
+            .finish()
+    }
+}
@@ -0,0 +1,399 @@
+//! The executable formats of binaries, for `binary_format`, `target_arch`
+//! and `is_stripped`.
+//!
+//! ELF files (Linux and the BSDs), PE files (Windows `.exe` and `.dll`)
+//! and Mach-O files (macOS, universal binaries included) are told by their
+//! headers, whatever they are named, and only the headers and the tables
+//! they point to are read. Architectures are named as `uname -m` names
+//! them on Linux, whichever format they come in, so `target_arch =
+//! 'aarch64'` finds the binaries built for Apple silicon as well as those
+//! for ARM servers. A universal binary's architectures are joined with
+//! commas, as in `x86_64,aarch64`.
+//!
+//! An ELF file is stripped when it has no `.symtab` section, as `file`
+//! says; a Mach-O file when it keeps no local symbols, as `strip` leaves
+//! it; and a PE file when it has neither a COFF symbol table nor a debug
+//! directory pointing to its PDB.
+//!
+//! # Examples
+//!
+//! ```
+//! use std::io::Cursor;
+//!
+//! use fmql_core::sql::executables::{identify, BinaryFormat};
+//!
+//! // The header of a 64-bit little-endian ELF file for AArch64
+//! let mut elf = vec![0; 64];
+//! elf[..6].copy_from_slice(b"\x7fELF\x02\x01");
+//! elf[18] = 0xb7;
+//!
+//! let binary = identify(&mut Cursor::new(elf)).unwrap().unwrap();
+//! assert_eq!(binary.format, BinaryFormat::Elf);
+//! assert_eq!(binary.arch.as_deref(), Some("aarch64"));
+//! // It has no section headers, so no symbol table
+//! assert_eq!(binary.stripped, Some(true));
+//!
+//! assert_eq!(identify(&mut Cursor::new(b"#!/bin/sh\n")).unwrap(), None);
+//! ```
+
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+
+use crate::sql::budget::Budgeted;
+
+/// The most of a table of section headers or load commands that is read.
+const MAX_TABLE: u64 = 16 * 1024 * 1024;
+
+/// The most architectures a universal binary is taken to have. Java class
+/// files start with the same magic number, followed by a version of 45 or
+/// more where a universal binary has its count.
+const MAX_FAT_ARCHES: u32 = 20;
+
+/// The format of an executable, library or object file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryFormat {
+    /// The Executable and Linkable Format of Linux and the BSDs.
+    Elf,
+    /// The Portable Executable format of Windows.
+    Pe,
+    /// The Mach-O format of macOS and iOS.
+    MachO,
+}
+
+impl fmt::Display for BinaryFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            BinaryFormat::Elf => "elf",
+            BinaryFormat::Pe => "pe",
+            BinaryFormat::MachO => "mach-o",
+        })
+    }
+}
+
+/// What a binary's headers say about it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Executable {
+    /// The binary's format.
+    pub format: BinaryFormat,
+    /// The architecture it was built for, or None for one not named here.
+    pub arch: Option<String>,
+    /// Whether its symbols were stripped, or None if its headers are cut
+    /// short.
+    pub stripped: Option<bool>,
+}
+
+/// Reads what a file's headers say about it, or returns None if it isn't
+/// an ELF, PE or Mach-O file.
+///
+/// # Errors
+///
+/// Fails if the file can't be read, such as when it is a directory.
+pub fn read_executable(path: &Path) -> io::Result<Option<Executable>> {
+    identify(&mut BufReader::new(Budgeted::new(File::open(path)?)))
+}
+
+/// Reads what the headers of a binary say about it, or returns None if it
+/// isn't an ELF, PE or Mach-O file.
+///
+/// # Errors
+///
+/// Fails if `reader` fails.
+pub fn identify<R: Read + Seek>(reader: &mut R) -> io::Result<Option<Executable>> {
+    let head = read_at(reader, 0, 64)?;
+    if head.starts_with(b"\x7fELF") {
+        return elf(reader, &head);
+    }
+    if head.starts_with(b"MZ") {
+        return pe(reader, &head);
+    }
+    macho(reader, &head)
+}
+
+/// Reads an ELF file's machine and whether it has a symbol table.
+fn elf<R: Read + Seek>(reader: &mut R, head: &[u8]) -> io::Result<Option<Executable>> {
+    let wide = head.get(4) == Some(&2);
+    let header = Fields { bytes: head, big: head.get(5) == Some(&2) };
+    let Some(machine) = header.u16(18) else {
+        return Ok(None);
+    };
+    let sections = match wide {
+        true => (header.u64(0x28), header.u16(0x3a), header.u16(0x3c)),
+        false => (header.u32(0x20).map(u64::from), header.u16(0x2e), header.u16(0x30)),
+    };
+    let stripped = match sections {
+        (Some(offset), Some(size), Some(count)) => {
+            elf_stripped(reader, header.big, wide, offset, size, count)?
+        }
+        _ => None,
+    };
+    Ok(Some(Executable {
+        format: BinaryFormat::Elf,
+        arch: elf_arch(machine, wide, header.big).map(str::to_string),
+        stripped,
+    }))
+}
+
+/// Whether none of an ELF file's sections is a symbol table.
+fn elf_stripped<R: Read + Seek>(
+    reader: &mut R,
+    big: bool,
+    wide: bool,
+    offset: u64,
+    size: u16,
+    count: u16,
+) -> io::Result<Option<bool>> {
+    if offset == 0 {
+        return Ok(Some(true));
+    }
+    let size = u64::from(size);
+    if size < 8 {
+        return Ok(None);
+    }
+    // A file with more sections than fit in e_shnum keeps the count in
+    // the size of the first
+    let mut count = u64::from(count);
+    if count == 0 {
+        let first = read_at(reader, offset, size)?;
+        let first = Fields { bytes: &first, big };
+        count = match wide {
+            true => first.u64(0x20),
+            false => first.u32(0x14).map(u64::from),
+        }
+        .unwrap_or(0);
+    }
+    let table = read_at(reader, offset, count.saturating_mul(size).min(MAX_TABLE))?;
+    let has_symbols = table
+        .chunks_exact(size as usize)
+        .any(|section| Fields { bytes: section, big }.u32(4) == Some(SHT_SYMTAB));
+    Ok(Some(!has_symbols))
+}
+
+const SHT_SYMTAB: u32 = 2;
+
+/// Names an ELF machine.
+fn elf_arch(machine: u16, wide: bool, big: bool) -> Option<&'static str> {
+    Some(match machine {
+        0x02 => "sparc",
+        0x03 => "x86",
+        0x08 if wide => "mips64",
+        0x08 => "mips",
+        0x14 => "ppc",
+        0x15 if big => "ppc64",
+        0x15 => "ppc64le",
+        0x16 => "s390x",
+        0x28 => "arm",
+        0x2b => "sparc64",
+        0x32 => "ia64",
+        0x3e => "x86_64",
+        0xb7 => "aarch64",
+        0xf3 if wide => "riscv64",
+        0xf3 => "riscv32",
+        0x102 => "loongarch64",
+        _ => return None,
+    })
+}
+
+/// Reads a PE file's machine and whether it keeps symbols or debug
+/// information. An `MZ` file without a PE header is a DOS program, and
+/// isn't one.
+fn pe<R: Read + Seek>(reader: &mut R, head: &[u8]) -> io::Result<Option<Executable>> {
+    let Some(offset) = (Fields { bytes: head, big: false }).u32(0x3c) else {
+        return Ok(None);
+    };
+    // The signature, the COFF header and an optional header with all 16
+    // data directories
+    let pe = read_at(reader, u64::from(offset), 4 + 20 + 240)?;
+    if !pe.starts_with(b"PE\0\0") {
+        return Ok(None);
+    }
+    let header = Fields { bytes: &pe, big: false };
+    let Some(machine) = header.u16(4) else {
+        return Ok(None);
+    };
+    let has_symbols = header.u32(12).is_some_and(|symbols| symbols != 0);
+    let directories = match header.u16(24) {
+        Some(0x10b) => Some((24 + 92, 24 + 96)),
+        Some(0x20b) => Some((24 + 108, 24 + 112)),
+        _ => None,
+    };
+    let stripped = directories.map(|(count, directories)| {
+        let has_debug = header.u32(count).is_some_and(|count| count > DEBUG_DIRECTORY)
+            && header
+                .u32(directories + DEBUG_DIRECTORY as usize * 8 + 4)
+                .is_some_and(|size| size != 0);
+        !has_symbols && !has_debug
+    });
+    Ok(Some(Executable {
+        format: BinaryFormat::Pe,
+        arch: pe_arch(machine).map(str::to_string),
+        stripped,
+    }))
+}
+
+/// The index of the debug directory among a PE file's data directories.
+const DEBUG_DIRECTORY: u32 = 6;
+
+/// Names a PE machine.
+fn pe_arch(machine: u16) -> Option<&'static str> {
+    Some(match machine {
+        0x014c => "x86",
+        0x0200 => "ia64",
+        0x01c0 | 0x01c2 | 0x01c4 => "arm",
+        0x5064 => "riscv64",
+        0x6264 => "loongarch64",
+        0x8664 => "x86_64",
+        0xaa64 => "aarch64",
+        _ => return None,
+    })
+}
+
+/// Reads a Mach-O file, or each of the files a universal binary holds.
+fn macho<R: Read + Seek>(reader: &mut R, head: &[u8]) -> io::Result<Option<Executable>> {
+    let Some(magic) = (Fields { bytes: head, big: true }).u32(0) else {
+        return Ok(None);
+    };
+    let entry = match magic {
+        0xcafebabe => 20,
+        0xcafebabf => 32,
+        _ => {
+            return Ok(macho_slice(reader, 0)?.map(|(arch, stripped)| Executable {
+                format: BinaryFormat::MachO,
+                arch: arch.map(str::to_string),
+                stripped,
+            }));
+        }
+    };
+    let count = Fields { bytes: head, big: true }.u32(4).unwrap_or(0);
+    if count == 0 || count > MAX_FAT_ARCHES {
+        return Ok(None);
+    }
+    let table = read_at(reader, 8, u64::from(count) * entry)?;
+    let (mut arches, mut stripped) = (Vec::new(), Some(true));
+    for arch in table.chunks_exact(entry as usize) {
+        let arch = Fields { bytes: arch, big: true };
+        let offset = match entry {
+            20 => arch.u32(8).map(u64::from),
+            _ => arch.u64(8),
+        };
+        let Some((name, slice_stripped)) = offset.map_or(Ok(None), |at| macho_slice(reader, at))?
+        else {
+            stripped = None;
+            continue;
+        };
+        if let Some(name) = name
+            && !arches.contains(&name)
+        {
+            arches.push(name);
+        }
+        // One slice that keeps its symbols is enough
+        stripped = match (stripped, slice_stripped) {
+            (Some(false), _) | (_, Some(false)) => Some(false),
+            (Some(true), Some(true)) => Some(true),
+            _ => None,
+        };
+    }
+    Ok(Some(Executable {
+        format: BinaryFormat::MachO,
+        arch: (!arches.is_empty()).then(|| arches.join(",")),
+        stripped,
+    }))
+}
+
+/// Reads the architecture of the Mach-O file at `offset` and whether it
+/// keeps local symbols, or returns None if there isn't one there.
+fn macho_slice<R: Read + Seek>(
+    reader: &mut R,
+    offset: u64,
+) -> io::Result<Option<(Option<&'static str>, Option<bool>)>> {
+    let header = read_at(reader, offset, 32)?;
+    let Some(magic) = (Fields { bytes: &header, big: false }).u32(0) else {
+        return Ok(None);
+    };
+    let (wide, big) = match magic {
+        0xfeedface => (false, false),
+        0xfeedfacf => (true, false),
+        0xcefaedfe => (false, true),
+        0xcffaedfe => (true, true),
+        _ => return Ok(None),
+    };
+    let header = Fields { bytes: &header, big };
+    let arch = header.u32(4).and_then(macho_arch);
+    let (Some(count), Some(size)) = (header.u32(16), header.u32(20)) else {
+        return Ok(Some((arch, None)));
+    };
+    let start = offset + if wide { 32 } else { 28 };
+    let commands = read_at(reader, start, u64::from(size).min(MAX_TABLE))?;
+    let commands = Fields { bytes: &commands, big };
+    let (mut symbols, mut locals) = (None, None);
+    let mut at = 0;
+    for _ in 0..count {
+        let (Some(command), Some(size)) = (commands.u32(at), commands.u32(at + 4)) else {
+            break;
+        };
+        match command {
+            LC_SYMTAB => symbols = commands.u32(at + 12),
+            LC_DYSYMTAB => locals = commands.u32(at + 12),
+            _ => {}
+        }
+        if size < 8 {
+            break;
+        }
+        at += size as usize;
+    }
+    let stripped = match (symbols, locals) {
+        (None | Some(0), _) => true,
+        (Some(_), Some(locals)) => locals == 0,
+        (Some(_), None) => false,
+    };
+    Ok(Some((arch, Some(stripped))))
+}
+
+const LC_SYMTAB: u32 = 0x2;
+const LC_DYSYMTAB: u32 = 0xb;
+
+/// Names a Mach-O CPU type.
+fn macho_arch(cpu: u32) -> Option<&'static str> {
+    const ABI64: u32 = 0x0100_0000;
+    Some(match (cpu & !ABI64, cpu & ABI64 != 0) {
+        (7, false) => "x86",
+        (7, true) => "x86_64",
+        (12, false) => "arm",
+        (12, true) => "aarch64",
+        (18, false) => "ppc",
+        (18, true) => "ppc64",
+        _ => return None,
+    })
+}
+
+/// Reads up to `len` bytes from `offset`, fewer if the file ends first.
+fn read_at<R: Read + Seek>(reader: &mut R, offset: u64, len: u64) -> io::Result<Vec<u8>> {
+    reader.seek(SeekFrom::Start(offset))?;
+    let mut bytes = Vec::new();
+    reader.take(len).read_to_end(&mut bytes)?;
+    Ok(bytes)
+}
+
+/// The fields of a header, in its byte order.
+struct Fields<'a> {
+    bytes: &'a [u8],
+    big: bool,
+}
+
+impl Fields<'_> {
+    fn u16(&self, at: usize) -> Option<u16> {
+        let bytes = self.bytes.get(at..at + 2)?.try_into().ok()?;
+        Some(if self.big { u16::from_be_bytes(bytes) } else { u16::from_le_bytes(bytes) })
+    }
+
+    fn u32(&self, at: usize) -> Option<u32> {
+        let bytes = self.bytes.get(at..at + 4)?.try_into().ok()?;
+        Some(if self.big { u32::from_be_bytes(bytes) } else { u32::from_le_bytes(bytes) })
+    }
+
+    fn u64(&self, at: usize) -> Option<u64> {
+        let bytes = self.bytes.get(at..at + 8)?.try_into().ok()?;
+        Some(if self.big { u64::from_be_bytes(bytes) } else { u64::from_le_bytes(bytes) })
+    }
+}
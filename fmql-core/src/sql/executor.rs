@@ -0,0 +1,5952 @@
+//! Executor for SQL-like file management commands.
+//!
+//! This module provides functionality to execute parsed SQL-like commands
+//! on the file system, such as querying files or updating file attributes.
+
+use chrono::{DateTime, Utc};
+use regex::Regex;
+use serde::Serialize;
+use std::borrow::Cow;
+use std::cmp::Reverse;
+use std::ops::ControlFlow;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::{self, Permissions};
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc, Mutex, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use walkdir::WalkDir;
+
+use crate::sql::ast::{
+    AggregateColumn, ArithmeticOperator, ComparisonOperator, CompressFormat, ComputedColumn,
+    ConflictPolicy, CopyMethod, EntryType, FileAttribute, FileCondition, FileExpr, FileQuery,
+    FileValue, OrderBy, RowSample, SortKey, SubstringPosition,
+};
+use crate::sql::aggregate::Accumulator;
+use crate::sql::functions::{self, to_text};
+use crate::sql::join::{JoinTable, PATH_COLUMN};
+use crate::sql::lexer::{parse_interval, parse_numeric};
+use crate::sql::paths::{
+    display_path, expand_targets, glob_base, read_path_list, windows_name_problem, PathError,
+};
+use crate::sql::matches::{LineMatch, LineMatcher};
+use crate::sql::optimize::{explain, never_matches, optimize};
+use crate::sql::preview::read_preview;
+use crate::sql::compressed::{
+    already_compressed, compress_file, uncompressed_size,
+};
+use crate::sql::encoding::{read_text, TextEncoding};
+use crate::sql::docinfo::read_docinfo;
+use crate::sql::copy::{copy_file, verify_copy, CopyOptions, Transfer};
+use crate::sql::extract::{archive_stem, extract_archive, ArchiveFormat, Extracted};
+use crate::sql::archive::{archive_path, check_archive, write_archive, Member};
+use crate::sql::chattr;
+use crate::sql::filecaps;
+use crate::sql::quarantine;
+use crate::sql::newlines;
+use crate::sql::trash;
+use crate::sql::executables;
+use crate::sql::images::{self, Image, ImageSource};
+use crate::sql::backups::{self, BackupSource, Listing};
+use crate::sql::android::{self, Device, DeviceSource};
+use crate::sql::trace::Replay;
+use crate::sql::reconcile::{Reconcile, Unread};
+use crate::sql::flags::{self, FlagChange};
+use crate::sql::selinux;
+use crate::sql::extents;
+use crate::sql::timezone::{self, Tz};
+use crate::sql::capabilities::{holds_windows_names, probe};
+use crate::sql::renames::plan_renames;
+use crate::sql::checkpoint::{self, Checkpoint, Position};
+use crate::sql::compat::Semantics;
+use crate::sql::events::{Event, Events};
+use crate::sql::protect::Protection;
+use crate::sql::ignore::Ignore;
+use crate::sql::locks::{FileLocks, Locking, RootLock};
+use crate::sql::meter::{self, Meter};
+use crate::sql::faults::Faults;
+use crate::sql::retry::{RetryPolicy, Transient};
+use crate::sql::intern::intern;
+use crate::sql::sample;
+use crate::sql::budget;
+use crate::sql::shard::{check_parts, PartialGroup, Shard, ShardResult};
+use crate::sql::signatures::{contains_bytes, read_signature};
+use crate::sql::space::{copy_needs, SpaceNeeds};
+use crate::sql::sort;
+use crate::sql::tags;
+use crate::sql::permissions::{matches_octal_pattern, parse_octal, PermissionSpec, PERMISSION_BITS};
+use crate::sql::throttle::{lower_io_priority, Throttle};
+use crate::sql::users::{current_user, group_name, in_group, user_name};
+
+/// Errors that can occur during query execution.
+#[derive(Error, Debug)]
+pub enum ExecutorError {
+    /// Error from std::io operations.
+    #[error("I/O error: {0}")]
+    IoError(#[from] std::io::Error),
+
+    /// Error when a file attribute is not supported.
+    #[error("Unsupported file attribute: {0}")]
+    UnsupportedAttribute(String),
+
+    /// Error when an operation is not supported.
+    #[error("Unsupported operation: {0}")]
+    UnsupportedOperation(String),
+
+    /// Error when a regular expression is invalid.
+    #[error("Invalid regular expression: {0}")]
+    InvalidRegex(#[from] regex::Error),
+
+    /// Error when a value is of the wrong type.
+    #[error("Type error: {0}")]
+    TypeError(String),
+
+    /// Error when the execution options forbid what the query asks for.
+    #[error("Not permitted: {0}")]
+    Denied(String),
+
+    /// Error when the query's path does not exist or cannot be listed.
+    #[error("Invalid path: {0}")]
+    InvalidPath(#[from] PathError),
+
+    /// Error when a query exceeds one of its resource limits.
+    #[error("Resource limit exceeded: {0}")]
+    LimitExceeded(#[from] LimitError),
+
+    /// Error when the table a query joins can't be used.
+    #[error("Invalid join: {0}")]
+    InvalidJoin(String),
+
+    /// Error when a retention policy can't be read or one of its rules is
+    /// malformed.
+    #[error("Invalid retention policy: {0}")]
+    InvalidPolicy(String),
+
+    /// Error when the renames of an UPDATE would give two files the same
+    /// name.
+    #[error("Conflicting renames: {0}")]
+    RenameConflict(String),
+
+    /// Error when a checkpoint can't be read or was saved for another
+    /// query.
+    #[error("Invalid checkpoint: {0}")]
+    InvalidCheckpoint(String),
+
+    /// Error when the shard results to merge aren't every shard of one
+    /// query.
+    #[error("Invalid shards: {0}")]
+    InvalidShards(String),
+
+    /// Error when the tag store can't be read.
+    #[error("Invalid tags: {0}")]
+    InvalidTags(String),
+
+    /// Error when saved results can't be read or compared.
+    #[error("Invalid results: {0}")]
+    InvalidResults(String),
+
+    /// Error when a destination hasn't room for what would be copied or
+    /// moved to it, found before anything is.
+    #[error("Not enough space: {0}")]
+    InsufficientSpace(String),
+
+    /// Error when the condition can't be evaluated for a file, under
+    /// [`Faults::strict`].
+    #[error("Cannot evaluate the condition for {}: {1}", .0.display())]
+    EvaluationFailed(PathBuf, String),
+
+    /// A condition with a `BUDGET` gave up on a file when the budget ran
+    /// out.
+    #[error("Over budget: {0}")]
+    OverBudget(#[from] budget::Exceeded),
+
+    /// Error when a container image can't be queried, such as when its
+    /// archive isn't one of an image or the statement would change it.
+    #[error("Invalid image: {0}")]
+    InvalidImage(String),
+
+    /// Error when a backup repository can't be listed, such as when its
+    /// tool writes something other than the JSON it should, or the
+    /// statement would change it.
+    #[error("Invalid backup: {0}")]
+    InvalidBackup(String),
+
+    /// Error when a device can't be listed, such as when it isn't
+    /// connected or the statement would change it.
+    #[error("Invalid device: {0}")]
+    InvalidDevice(String),
+
+    /// Error when a file given to replay isn't a trace this fmql can read.
+    #[error("Invalid trace: {0}")]
+    InvalidTrace(String),
+
+    /// Error when another run that changes files holds a lock on an
+    /// overlapping root; see [`locks`](crate::sql::locks).
+    #[error("Locked: {0}")]
+    Locked(String),
+}
+
+impl Transient for ExecutorError {
+    fn is_transient(&self) -> bool {
+        matches!(self, ExecutorError::IoError(err) if err.is_transient())
+    }
+}
+
+/// A resource limit that a query ran into; see [`ResourceLimits`].
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum LimitError {
+    /// More files were scanned than allowed.
+    #[error("scanned more than {0} files")]
+    FilesScanned(usize),
+
+    /// More files matched than allowed.
+    #[error("matched more than {0} files")]
+    Results(usize),
+
+    /// More file content was read than allowed.
+    #[error("read more than {0} bytes of file content")]
+    BytesRead(u64),
+
+    /// The query ran for longer than allowed.
+    #[error("ran for longer than {0:?}")]
+    Timeout(Duration),
+}
+
+/// Limits that stop runaway queries. `None` means unlimited.
+#[derive(Debug, Clone, Default)]
+pub struct ResourceLimits {
+    /// The most directory entries a query may visit.
+    pub max_files_scanned: Option<usize>,
+    /// The most files a query may return or update.
+    pub max_results: Option<usize>,
+    /// The most bytes a query may read from file contents, summed over all
+    /// files; see [`meter`](crate::sql::meter).
+    pub max_bytes_read: Option<u64>,
+    /// How many directory levels a recursive query descends. Deeper entries
+    /// are not visited, like `find -maxdepth`, rather than being an error.
+    pub max_depth: Option<usize>,
+    /// The longest a query may run. A query's own `WITHIN` clause can only
+    /// shorten this.
+    pub timeout: Option<Duration>,
+}
+
+/// Result type for executor operations.
+pub type Result<T> = std::result::Result<T, ExecutorError>;
+
+/// Represents a file that matches a query.
+#[derive(Debug, Clone, Serialize)]
+pub struct FileResult {
+    /// The file path.
+    pub path: PathBuf,
+    /// The file name.
+    pub name: String,
+    /// The file size in bytes.
+    pub size: u64,
+    /// Whether the file is a directory.
+    pub is_directory: bool,
+    /// Whether the path itself is a symbolic link.
+    pub is_symlink: bool,
+    /// The file extension, if any, shared with the other results that have
+    /// it: see [`intern`].
+    pub extension: Option<Arc<str>>,
+    /// The file permissions.
+    pub permissions: u32,
+    /// The file modification time.
+    #[serde(with = "chrono::serde::ts_seconds")]
+    pub modified: DateTime<Utc>,
+    /// When the file was last read. Mounts with `noatime` never update
+    /// this, and `relatime` (the Linux default) updates it at most daily.
+    /// Serialized only when the query selects it; see `selected`.
+    #[serde(skip)]
+    pub accessed: DateTime<Utc>,
+    /// The file owner, if available, shared like `extension`. Serialized
+    /// only when the query selects it; see `selected`.
+    #[serde(skip)]
+    pub owner: Option<Arc<str>>,
+    /// How a rename collision was resolved, for files an UPDATE renamed or skipped.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub conflict: Option<ConflictOutcome>,
+    /// Whether an UPDATE changed this file; None for SELECT results.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<OperationStatus>,
+    /// Why the update failed, when `status` is `Failed`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error_message: Option<String>,
+    /// The attribute values an UPDATE changed, old and new. A COPY reports
+    /// the copy, with the original's path as the old `path`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub changes: Vec<FileChange>,
+    /// How a COPY made this file: by copying bytes, a hardlink or a reflink.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub copied: Option<CopyMethod>,
+    /// The format COMPRESS compressed this file to.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub compressed: Option<CompressFormat>,
+    /// The BLAKE3 checksum, in hex, that VERIFY CHECKSUM found a copy to
+    /// share with its original.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub checksum: Option<String>,
+    /// What a COPY took to make this file: the bytes it copied, any it
+    /// resumed, its retries and how long it took.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub transfer: Option<Transfer>,
+    /// What an EXTRACT unpacked from this archive.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub extracted: Option<Extracted>,
+    /// The archive an ARCHIVE packed this file into.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub archived: Option<PathBuf>,
+    /// The parts of the WHERE clause the file satisfied, such as `size >
+    /// 1073741824 AND extension = 'log'`, when it was found by a dry run;
+    /// see [`ExecutionOptions::dry_run`].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub matched: Option<String>,
+    /// The values of the query's computed columns, by column name.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty", serialize_with = "serialize_columns")]
+    pub computed: BTreeMap<String, FileValue>,
+    /// The start of the file's contents, when the query selects `preview`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub preview: Option<String>,
+    /// The lines that matched the query's content predicates, when the
+    /// query selects `matches`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub matches: Vec<LineMatch>,
+    /// The optional attributes the query selected, such as `owner` or
+    /// `created`, which serialize as fields of their own.
+    #[serde(flatten)]
+    pub selected: SelectedAttributes,
+    /// The row of the joined table that matched this file, by column name;
+    /// empty without a JOIN. Only the columns the query selects appear in
+    /// the output, as computed columns.
+    #[serde(skip)]
+    pub joined: BTreeMap<String, FileValue>,
+    /// Read a compressed file's `content` through its decompressor, keeping
+    /// at most this many bytes: see [`ExecutionOptions::decompress`].
+    #[serde(skip)]
+    pub decompress: Option<u64>,
+    /// The encoding the file's `content` and `preview` are read in: see
+    /// [`ExecutionOptions::encoding`].
+    #[serde(skip)]
+    pub encoding: TextEncoding,
+    /// The time zone date literals and date functions are in: see
+    /// [`ExecutionOptions::time_zone`].
+    #[serde(skip)]
+    pub time_zone: Tz,
+    /// When the query started, which `age` is measured from; None measures
+    /// it from the moment it is asked for.
+    #[serde(skip)]
+    pub now: Option<DateTime<Utc>>,
+    /// The semantics the query that found the file runs with: see
+    /// [`ExecutionOptions::semantics`].
+    #[serde(skip)]
+    pub semantics: Semantics,
+    /// The query run that found the file, for `source_root` and
+    /// `scan_id`; None for a file no query found.
+    #[serde(skip)]
+    pub source: Option<Arc<ScanSource>>,
+    /// How reading the file's contents is tried again: see
+    /// [`ExecutionOptions::retry`].
+    #[serde(skip)]
+    pub retry: RetryPolicy,
+    /// What the reads of the query run that found the file have read, when
+    /// that is limited or throttled; see [`meter`](crate::sql::meter).
+    #[serde(skip)]
+    pub meter: Option<Arc<Meter>>,
+    /// Why a path the query listed couldn't be read, when it couldn't: see
+    /// [`reconcile`](crate::sql::reconcile).
+    #[serde(skip)]
+    pub unread: Option<Unread>,
+}
+
+/// Where the files of a query run come from: the `source_root` and
+/// `scan_id` of each.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScanSource {
+    /// The path the query reads, as its FROM gives it; None for
+    /// `FROM STDIN`.
+    pub root: Option<PathBuf>,
+    /// The id of the run: [`ExecutionOptions::scan_id`], or one made up
+    /// when it started.
+    pub id: String,
+}
+
+impl ScanSource {
+    /// Returns the source of a run of `query` starting `now`.
+    fn new(query: &FileQuery, options: &ExecutionOptions, now: DateTime<Utc>) -> Arc<ScanSource> {
+        let root = query.root().map(Path::to_path_buf);
+        let id = options.scan_id.clone().unwrap_or_else(|| ScanSource::new_id(now));
+        Arc::new(ScanSource { root, id })
+    }
+
+    /// Makes up the id of a run starting `now`, such as
+    /// `20250301T120000.000Z-4242-0`: the time, the process and how many
+    /// runs it started before, so that no two runs share one.
+    pub fn new_id(now: DateTime<Utc>) -> String {
+        static RUNS: AtomicUsize = AtomicUsize::new(0);
+
+        let run = RUNS.fetch_add(1, Ordering::Relaxed);
+        format!("{}-{}-{}", now.format("%Y%m%dT%H%M%S%.3fZ"), std::process::id(), run)
+    }
+}
+
+/// The optional attributes of a [`FileResult`] that its query selected,
+/// with their values.
+///
+/// Cheap metadata (path, name, size, type, extension, permissions and
+/// modification time) is always part of a result. Everything else is only
+/// looked up when a query asks for it, by name or with `*`, and then
+/// serializes as a field named after the attribute: timestamps as Unix
+/// seconds like `modified`, and unknown values as `null`. `*` selects
+/// `accessed` and `owner`; attributes that read the file's contents must be
+/// named.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SelectedAttributes(pub Vec<(FileAttribute, FileValue)>);
+
+impl SelectedAttributes {
+    /// Returns the value of a selected attribute.
+    pub fn get(&self, attribute: FileAttribute) -> Option<&FileValue> {
+        self.0
+            .iter()
+            .find(|(selected, _)| *selected == attribute)
+            .map(|(_, value)| value)
+    }
+
+    /// Returns true if the query selected no optional attributes.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl Serialize for SelectedAttributes {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        use serde::ser::SerializeMap;
+
+        let mut map = serializer.serialize_map(Some(self.0.len()))?;
+        for (attribute, value) in &self.0 {
+            map.serialize_entry(attribute.name(), &PlainValue(value))?;
+        }
+        map.end()
+    }
+}
+
+/// Serializes a value as itself rather than tagged with its type: strings,
+/// numbers (whole numbers without a fraction) and booleans as they are,
+/// timestamps as Unix seconds like `modified`, durations as seconds, and
+/// NULL as `null`. Result
+/// columns are typed by the query's [schema](crate::sql::schema) instead.
+pub struct PlainValue<'a>(pub &'a FileValue);
+
+impl Serialize for PlainValue<'_> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        match self.0 {
+            FileValue::String(s) => serializer.serialize_str(s),
+            FileValue::Integer(n) => serializer.serialize_i64(*n),
+            FileValue::Number(n) if n.fract() == 0.0 && n.abs() < 2f64.powi(53) => {
+                serializer.serialize_i64(*n as i64)
+            }
+            FileValue::Number(n) => serializer.serialize_f64(*n),
+            FileValue::DateTime(time) => serializer.serialize_i64(time.timestamp()),
+            FileValue::Duration(delta) if delta.subsec_nanos() == 0 => {
+                serializer.serialize_i64(delta.num_seconds())
+            }
+            FileValue::Duration(delta) => {
+                serializer.serialize_f64(delta.num_milliseconds() as f64 / 1000.0)
+            }
+            FileValue::Boolean(b) => serializer.serialize_bool(*b),
+            FileValue::Null => serializer.serialize_none(),
+        }
+    }
+}
+
+/// Serializes computed columns by name, with [`PlainValue`]s.
+fn serialize_columns<S: serde::Serializer>(
+    columns: &BTreeMap<String, FileValue>,
+    serializer: S,
+) -> std::result::Result<S::Ok, S::Error> {
+    serializer.collect_map(columns.iter().map(|(name, value)| (name, PlainValue(value))))
+}
+
+/// Lists the optional attributes a SELECT asks for, in the order of the
+/// select list: see [`SelectedAttributes`].
+pub(crate) fn optional_attributes(attributes: &[FileAttribute]) -> Vec<FileAttribute> {
+    let mut optional = Vec::new();
+    for attribute in attributes {
+        let wanted: &[FileAttribute] = match attribute {
+            FileAttribute::All => &[FileAttribute::Accessed, FileAttribute::Owner],
+            FileAttribute::Created
+            | FileAttribute::Accessed
+            | FileAttribute::Age
+            | FileAttribute::SourceRoot
+            | FileAttribute::ScanId
+            | FileAttribute::Owner
+            | FileAttribute::Group
+            | FileAttribute::IsMine
+            | FileAttribute::IsExecutable
+            | FileAttribute::IsWorldReadable
+            | FileAttribute::IsGroupWritable
+            | FileAttribute::IsOtherExecutable
+            | FileAttribute::SignatureExtension
+            | FileAttribute::ExtensionMismatch
+            | FileAttribute::UncompressedSize
+            | FileAttribute::DiskUsage
+            | FileAttribute::IsSparse
+            | FileAttribute::SharedExtents
+            | FileAttribute::Flags
+            | FileAttribute::FsFlags
+            | FileAttribute::SecurityContext
+            | FileAttribute::FileCapabilities
+            | FileAttribute::HasFileCapabilities
+            | FileAttribute::Quarantine
+            | FileAttribute::WhereFrom
+            | FileAttribute::PdfPageCount
+            | FileAttribute::DocTitle
+            | FileAttribute::DocAuthor
+            | FileAttribute::LineEndings
+            | FileAttribute::HasBom
+            | FileAttribute::BinaryFormat
+            | FileAttribute::TargetArch
+            | FileAttribute::IsStripped
+            | FileAttribute::OriginalPath
+            | FileAttribute::DeletedAt
+            | FileAttribute::Exists
+            | FileAttribute::WasMovedHint
+            | FileAttribute::MetadataError
+            | FileAttribute::Content => std::slice::from_ref(attribute),
+            _ => &[],
+        };
+        for attribute in wanted {
+            if !optional.contains(attribute) {
+                optional.push(attribute.clone());
+            }
+        }
+    }
+    optional
+}
+
+/// One row of a grouped SELECT: the group's GROUP BY values followed by its
+/// aggregates, in the order the query lists them.
+///
+/// Rows serialize as an object from column name to value, like the
+/// `computed` columns of a [`FileResult`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct GroupRow {
+    /// The column names and values.
+    pub columns: Vec<(String, FileValue)>,
+}
+
+impl GroupRow {
+    /// Returns the value of the named column.
+    pub fn get(&self, name: &str) -> Option<&FileValue> {
+        self.columns
+            .iter()
+            .find(|(column, _)| column == name)
+            .map(|(_, value)| value)
+    }
+}
+
+impl Serialize for GroupRow {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        use serde::ser::SerializeMap;
+
+        let mut map = serializer.serialize_map(Some(self.columns.len()))?;
+        for (name, value) in &self.columns {
+            map.serialize_entry(name, &PlainValue(value))?;
+        }
+        map.end()
+    }
+}
+
+/// One directory of a `ROLLUP BY path` query: its aggregates over every
+/// matching entry below it, and the same for each of its subdirectories.
+///
+/// Nodes serialize as an object with the `path`, then the aggregate columns
+/// by name, then the `children`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RollupNode {
+    /// The directory.
+    pub path: PathBuf,
+    /// The aggregate names and values, in the order the query lists them.
+    pub columns: Vec<(String, FileValue)>,
+    /// The subdirectories with matching entries, sorted by path.
+    pub children: Vec<RollupNode>,
+}
+
+impl RollupNode {
+    /// Returns the value of the named aggregate.
+    pub fn get(&self, name: &str) -> Option<&FileValue> {
+        self.columns
+            .iter()
+            .find(|(column, _)| column == name)
+            .map(|(_, value)| value)
+    }
+
+    /// Returns the child for a subdirectory, by file name.
+    pub fn child(&self, name: &str) -> Option<&RollupNode> {
+        self.children
+            .iter()
+            .find(|child| child.path.file_name().is_some_and(|n| n == name))
+    }
+}
+
+impl Serialize for RollupNode {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        use serde::ser::SerializeMap;
+
+        let mut map = serializer.serialize_map(Some(self.columns.len() + 2))?;
+        map.serialize_entry("path", &self.path)?;
+        for (name, value) in &self.columns {
+            map.serialize_entry(name, &PlainValue(value))?;
+        }
+        map.serialize_entry("children", &self.children)?;
+        map.end()
+    }
+}
+
+/// The outcome of a mutating query for a single file.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OperationStatus {
+    /// At least one attribute was changed.
+    Ok,
+    /// Nothing needed changing, or a conflict was skipped.
+    Skipped,
+    /// The file could not be updated; see `error_message`.
+    Failed,
+}
+
+/// One attribute changed by an UPDATE.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FileChange {
+    /// The attribute that was changed.
+    pub attribute: FileAttribute,
+    /// The value before the update.
+    pub old_value: FileValue,
+    /// The value after the update.
+    pub new_value: FileValue,
+}
+
+/// How a rename whose target already existed was handled.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConflictOutcome {
+    /// The file was left unchanged (`ON CONFLICT SKIP`).
+    Skipped,
+    /// The existing file was replaced (`ON CONFLICT OVERWRITE`).
+    Overwritten,
+    /// A numeric suffix was added to the new name (`ON CONFLICT RENAME`).
+    Renamed,
+}
+
+impl std::fmt::Display for ConflictOutcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let text = match self {
+            ConflictOutcome::Skipped => "skipped, target exists",
+            ConflictOutcome::Overwritten => "overwrote existing file",
+            ConflictOutcome::Renamed => "renamed to avoid existing file",
+        };
+        f.write_str(text)
+    }
+}
+
+/// Options controlling how a query is executed.
+///
+/// # Examples
+///
+/// ```no_run
+/// use fmql_core::sql::executor::{execute_query_with_options, ExecutionOptions};
+/// use fmql_core::sql::parse_sql;
+///
+/// let query = parse_sql("UPDATE . SET permissions = '644' WHERE extension = 'txt'").unwrap();
+/// let options = ExecutionOptions { jobs: 8, ..Default::default() };
+/// let results = execute_query_with_options(&query, &options).unwrap();
+/// ```
+#[derive(Debug, Clone)]
+pub struct ExecutionOptions {
+    /// The number of files to update concurrently (1 means sequentially),
+    /// and of workers to scan a query grouped by `DIRNAME(path)` with.
+    pub jobs: usize,
+    /// The most bytes per second that file contents may be read at, across
+    /// all workers (None means unlimited): by content search, COPY and its
+    /// VERIFY CHECKSUM, COMPRESS and ARCHIVE alike.
+    pub throttle: Option<u64>,
+    /// How many more times COPY tries a file that fails with an error that
+    /// may pass. COPY now tries again as [`retry`](Self::retry) says, and
+    /// this only overrides its retries when changed from their default.
+    #[deprecated(note = "COPY tries again as `retry` says; set `retry.retries` instead")]
+    pub copy_retries: u32,
+    /// Run at idle I/O and CPU priority. This affects the whole process for
+    /// the rest of its life; see [`lower_io_priority`].
+    pub nice_io: bool,
+    /// Reject statements that modify the file system.
+    pub read_only: bool,
+    /// Refuse to read or modify anything outside this directory, including
+    /// through symlinks. Use this when queries come from untrusted sources.
+    pub root: Option<PathBuf>,
+    /// Limits on how much work a query may do.
+    pub limits: ResourceLimits,
+    /// List the FROM directory itself, as if every query said `INCLUDE SELF`.
+    pub include_self: bool,
+    /// Only list this kind of entry, unless the query names a kind itself.
+    pub entry_type: Option<EntryType>,
+    /// The candidate paths for a query that says `FROM STDIN`. When this is
+    /// None, such a query reads the list from standard input; see
+    /// [`read_path_list`] for the format.
+    pub files_from: Option<Vec<PathBuf>>,
+    /// Read `.gz`, `.zst` and `.xz` files through their decompressors when
+    /// checking or selecting `content`, keeping at most this many bytes of
+    /// each (None means content is the compressed bytes).
+    pub decompress: Option<u64>,
+    /// The encoding `content`, `matches` and `preview` read files in,
+    /// which by default is found for each file; see
+    /// [`encoding`](crate::sql::encoding).
+    pub encoding: TextEncoding,
+    /// The time zone date literals such as `'2025-01-01'` are local times
+    /// in, which `YEAR`, `MONTH` and `DAY` take their parts from, and which
+    /// dates are shown in as text (UTC by default); see
+    /// [`timezone`](crate::sql::timezone).
+    pub time_zone: Tz,
+    /// Save the progress of a SELECT to this file as it goes, and resume
+    /// from it if it is already there; see [`checkpoint`].
+    pub checkpoint: Option<PathBuf>,
+    /// Only scan this shard of the tree; see [`shard`](crate::sql::shard).
+    pub shard: Option<Shard>,
+    /// Return results in order of path when the query has no ORDER BY.
+    ///
+    /// Without ORDER BY, files come in the order the directories list
+    /// them, which depends on the file system and can change between runs,
+    /// and UPDATE and COPY results in the order their workers finished.
+    /// With this set a SELECT without ORDER BY is sorted as if it said
+    /// `ORDER BY path`, so a LIMIT keeps the same files every time, and
+    /// UPDATE and COPY results are sorted by path too. A query's own ORDER
+    /// BY always wins.
+    pub stable_order: bool,
+    /// Work out what an UPDATE, COPY or COMPRESS would do without doing it.
+    ///
+    /// Every file comes back as it would after a real run, with the changes
+    /// it would get, or Failed with the reason the change couldn't be made:
+    /// a new name that is already taken or isn't allowed, a directory the
+    /// user can't write to, a file they can't read, or a file whose
+    /// permissions or flags only its owner may change. The checks are made
+    /// against the file system as it is now, so what happens to it before
+    /// the real run can still make that fail.
+    ///
+    /// Each file also records in [`FileResult::matched`] the parts of the
+    /// WHERE clause it satisfied, so a reviewer can check why it was picked.
+    /// A dry-run SELECT finds the same files as a real one, with the same
+    /// record.
+    pub dry_run: bool,
+    /// The semantics version to run with, for queries written for an older
+    /// fmql; see [`compat`](crate::sql::compat).
+    pub semantics: Semantics,
+    /// Whether a file the condition can't be evaluated for fails the query
+    /// or is left out, and the log of those left out; see [`faults`].
+    pub faults: Faults,
+    /// The id rows report as `scan_id`. None makes one up for each run
+    /// from the time it starts and the process, so every run has its own.
+    pub scan_id: Option<String>,
+    /// How reading a file's metadata or contents, or making a COPY, is
+    /// tried again after an error that may pass; see
+    /// [`retry`](crate::sql::retry).
+    pub retry: RetryPolicy,
+    /// Where to send progress events as the query runs, for a program that
+    /// shows its progress; see [`events`](crate::sql::events).
+    pub events: Events,
+    /// Whether an UPDATE may change the permissions or ownership of system
+    /// paths; see [`protect`](crate::sql::protect).
+    pub protection: Protection,
+    /// Entries scans leave out, and don't descend into if they are
+    /// directories; see [`ignore`](crate::sql::ignore).
+    pub ignore: Ignore,
+    /// Whether a statement that changes files locks its roots against other
+    /// runs, and what it does when one holds them; see
+    /// [`locks`](crate::sql::locks).
+    pub locking: Locking,
+}
+
+impl Default for ExecutionOptions {
+    // The deprecated copy_retries still needs a default
+    #[allow(deprecated)]
+    fn default() -> Self {
+        ExecutionOptions {
+            jobs: 1,
+            throttle: None,
+            copy_retries: RetryPolicy::default().retries,
+            nice_io: false,
+            read_only: false,
+            root: None,
+            limits: ResourceLimits::default(),
+            include_self: false,
+            entry_type: None,
+            files_from: None,
+            decompress: None,
+            encoding: TextEncoding::Auto,
+            time_zone: Tz::UTC,
+            checkpoint: None,
+            shard: None,
+            stable_order: false,
+            dry_run: false,
+            semantics: Semantics::CURRENT,
+            faults: Faults::default(),
+            scan_id: None,
+            retry: RetryPolicy::default(),
+            events: Events::default(),
+            protection: Protection::default(),
+            ignore: Ignore::default(),
+            locking: Locking::default(),
+        }
+    }
+}
+
+/// Sends the event for a query that ran from `started` and gave `outcome`.
+fn finished<T>(events: &Events, outcome: &Result<Vec<T>>, started: Instant) {
+    events.emit(|| Event::Finished {
+        rows: outcome.as_ref().map_or(0, Vec::len),
+        elapsed: started.elapsed(),
+        error: outcome.as_ref().err().map(ToString::to_string),
+    });
+}
+
+/// Executes a parsed FileQuery.
+///
+/// # Arguments
+///
+/// * `query` - The parsed FileQuery to execute.
+///
+/// # Returns
+///
+/// A Result containing a vector of FileResult instances or an ExecutorError.
+///
+/// # Examples
+///
+/// Example of using execute_query to process a parsed query:
+///
+/// ```no_run
+/// use fmql_core::sql::{parse_sql, execute_query};
+///
+/// // Parse a query
+/// let query = parse_sql("SELECT * FROM /var/log WHERE name LIKE '%.log'").unwrap();
+///
+/// // Execute the query to get matching files
+/// let results = execute_query(&query).unwrap();
+///
+/// // Process the results
+/// for file in results {
+///     println!("{}: {} bytes", file.name, file.size);
+/// }
+/// ```
+pub fn execute_query(query: &FileQuery) -> Result<Vec<FileResult>> {
+    execute_query_with_options(query, &ExecutionOptions::default())
+}
+
+/// Executes a parsed FileQuery with the given options.
+///
+/// The results come in the query's ORDER BY, or with
+/// [`ExecutionOptions::stable_order`] in order of path; otherwise their
+/// order isn't defined.
+pub fn execute_query_with_options(
+    query: &FileQuery,
+    options: &ExecutionOptions,
+) -> Result<Vec<FileResult>> {
+    let started = Instant::now();
+    let outcome = match elsewhere(query) {
+        Some(source) => run_elsewhere(query, &source, options),
+        None => run_query(query, options),
+    };
+    finished(&options.events, &outcome, started);
+    outcome
+}
+
+/// What a SELECT reads that isn't a directory, whose files are set out in
+/// a temporary copy for it.
+enum Elsewhere {
+    Image(ImageSource),
+    Backup(BackupSource),
+    Device(DeviceSource),
+}
+
+/// A temporary copy of the files of [`Elsewhere`].
+trait Copied {
+    /// The directory the copy is in.
+    fn root(&self) -> &Path;
+    /// Returns what a path in the copy is called in results.
+    fn rename(&self, path: &Path) -> PathBuf;
+    /// Returns `text` with the copy's path at its start renamed, or None if
+    /// it doesn't start with it.
+    fn rewrite(&self, text: &str) -> Option<String>;
+}
+
+impl Copied for Image {
+    fn root(&self) -> &Path {
+        Image::root(self)
+    }
+
+    fn rename(&self, path: &Path) -> PathBuf {
+        self.path_in_image(path)
+    }
+
+    fn rewrite(&self, text: &str) -> Option<String> {
+        Image::rewrite(self, text)
+    }
+}
+
+impl Copied for Listing {
+    fn root(&self) -> &Path {
+        Listing::root(self)
+    }
+
+    fn rename(&self, path: &Path) -> PathBuf {
+        self.path_in_backup(path)
+    }
+
+    fn rewrite(&self, text: &str) -> Option<String> {
+        Listing::rewrite(self, text)
+    }
+}
+
+impl Copied for Device {
+    fn root(&self) -> &Path {
+        Device::root(self)
+    }
+
+    fn rename(&self, path: &Path) -> PathBuf {
+        self.path_on_device(path)
+    }
+
+    fn rewrite(&self, text: &str) -> Option<String> {
+        Device::rewrite(self, text)
+    }
+}
+
+impl Copied for Replay {
+    fn root(&self) -> &Path {
+        Replay::root(self)
+    }
+
+    fn rename(&self, path: &Path) -> PathBuf {
+        self.recorded_path(path)
+    }
+
+    fn rewrite(&self, text: &str) -> Option<String> {
+        Replay::rewrite(self, text)
+    }
+}
+
+/// Returns the container image, backup repository or device a SELECT
+/// reads, if its FROM names one.
+fn elsewhere(query: &FileQuery) -> Option<Elsewhere> {
+    match query {
+        FileQuery::Select { path, .. } => ImageSource::parse(path)
+            .map(Elsewhere::Image)
+            .or_else(|| BackupSource::parse(path).map(Elsewhere::Backup))
+            .or_else(|| DeviceSource::parse(path).map(Elsewhere::Device)),
+        FileQuery::Explain { query } => elsewhere(query),
+        _ => None,
+    }
+}
+
+/// Runs a SELECT on the files of a container image, backup repository or
+/// device, which are copied for it, and gives the results their paths there.
+fn run_elsewhere(
+    query: &FileQuery,
+    source: &Elsewhere,
+    options: &ExecutionOptions,
+) -> Result<Vec<FileResult>> {
+    let copy = copy_of(source, options)?;
+    let options = ExecutionOptions { root: None, ..options.clone() };
+    run_in_copy(query, copy.as_ref(), &options)
+}
+
+/// Runs a SELECT on a copy's files for [`run_elsewhere`].
+fn run_in_copy(
+    query: &FileQuery,
+    copy: &dyn Copied,
+    options: &ExecutionOptions,
+) -> Result<Vec<FileResult>> {
+    let mut results = run_query(&in_copy(query, copy.root()), options)?;
+    for file in &mut results {
+        file.path = copy.rename(&file.path);
+        let selected = file.selected.0.iter_mut().map(|(_, value)| value);
+        rename_in_copy(copy, selected.chain(file.computed.values_mut()));
+    }
+    Ok(results)
+}
+
+/// Runs a grouped SELECT on the files of a container image, backup
+/// repository or device, as [`run_elsewhere`] does a plain one.
+fn run_grouped_elsewhere(
+    query: &FileQuery,
+    source: &Elsewhere,
+    options: &ExecutionOptions,
+) -> Result<Vec<GroupRow>> {
+    let copy = copy_of(source, options)?;
+    let options = ExecutionOptions { root: None, ..options.clone() };
+    run_grouped_in_copy(query, copy.as_ref(), &options)
+}
+
+/// Runs a grouped SELECT on a copy's files, as [`run_in_copy`] does a
+/// plain one.
+fn run_grouped_in_copy(
+    query: &FileQuery,
+    copy: &dyn Copied,
+    options: &ExecutionOptions,
+) -> Result<Vec<GroupRow>> {
+    let mut rows = run_grouped(&in_copy(query, copy.root()), options)?;
+    for row in &mut rows {
+        rename_in_copy(copy, row.columns.iter_mut().map(|(_, value)| value));
+    }
+    Ok(rows)
+}
+
+/// Runs a query on the entries a trace recorded, in place of the path its
+/// FROM names, and gives the results the paths they were recorded under;
+/// see [`trace`](crate::sql::trace). Statements that change files change
+/// the stand-ins, and may touch nothing outside them.
+pub fn execute_replay(
+    query: &FileQuery,
+    replay: &Replay,
+    options: &ExecutionOptions,
+) -> Result<Vec<FileResult>> {
+    let started = Instant::now();
+    let outcome = replay_options(query, replay, options)
+        .and_then(|options| run_in_copy(query, replay, &options));
+    finished(&options.events, &outcome, started);
+    outcome
+}
+
+/// Runs a query that returns groups on the entries a trace recorded; see
+/// [`execute_replay`].
+pub fn execute_grouped_replay(
+    query: &FileQuery,
+    replay: &Replay,
+    options: &ExecutionOptions,
+) -> Result<Vec<GroupRow>> {
+    let started = Instant::now();
+    let outcome = replay_options(query, replay, options)
+        .and_then(|options| run_grouped_in_copy(query, replay, &options));
+    finished(&options.events, &outcome, started);
+    outcome
+}
+
+/// Returns the options a replayed query runs with, confined to the
+/// stand-ins, or fails if the query reads no path the trace could stand
+/// in for.
+fn replay_options(
+    query: &FileQuery,
+    replay: &Replay,
+    options: &ExecutionOptions,
+) -> Result<ExecutionOptions> {
+    if query.root().is_none() {
+        return Err(ExecutorError::UnsupportedOperation(
+            "A query that reads FROM STDIN or a table can't be replayed".to_string(),
+        ));
+    }
+    if elsewhere(query).is_some() {
+        return Err(ExecutorError::InvalidTrace(
+            "A query that reads an image, a backup or a device can't be replayed".to_string(),
+        ));
+    }
+    Ok(ExecutionOptions {
+        root: Some(replay.base().to_path_buf()),
+        ..options.clone()
+    })
+}
+
+/// Copies the files of a container image, backup repository or device.
+fn copy_of(source: &Elsewhere, options: &ExecutionOptions) -> Result<Box<dyn Copied>> {
+    Ok(match source {
+        Elsewhere::Image(source) => Box::new(unpack_image(source, options)?),
+        Elsewhere::Backup(source) => Box::new(list_backup(source, options)?),
+        Elsewhere::Device(source) => Box::new(list_device(source, options)?),
+    })
+}
+
+/// Unpacks a container image. With a sandbox root, only an archive under
+/// it can be read, and no image of the Docker engine's.
+fn unpack_image(source: &ImageSource, options: &ExecutionOptions) -> Result<Image> {
+    if let Some(root) = &options.root {
+        let allowed = match source {
+            ImageSource::Docker(_) => false,
+            ImageSource::Archive(archive) => {
+                fs::canonicalize(archive)?.starts_with(fs::canonicalize(root)?)
+            }
+        };
+        if !allowed {
+            return Err(ExecutorError::Denied(format!(
+                "{} is outside the sandbox root",
+                source.prefix()
+            )));
+        }
+    }
+    images::unpack(source).map_err(|err| match err.kind() {
+        std::io::ErrorKind::InvalidData | std::io::ErrorKind::Other => {
+            ExecutorError::InvalidImage(format!("{}: {}", source.prefix(), err))
+        }
+        _ => ExecutorError::IoError(err),
+    })
+}
+
+/// Lists the snapshots of a backup repository. With a sandbox root, only a
+/// repository in a directory under it can be read.
+fn list_backup(source: &BackupSource, options: &ExecutionOptions) -> Result<Listing> {
+    if let Some(root) = &options.root {
+        let root = fs::canonicalize(root)?;
+        let repository = fs::canonicalize(source.repository());
+        if !repository.is_ok_and(|repository| repository.starts_with(&root)) {
+            return Err(ExecutorError::Denied(format!(
+                "{} is outside the sandbox root",
+                source.prefix()
+            )));
+        }
+    }
+    backups::list(source).map_err(|err| match err.kind() {
+        std::io::ErrorKind::InvalidData | std::io::ErrorKind::Other => {
+            ExecutorError::InvalidBackup(format!("{}: {}", source.prefix(), err))
+        }
+        _ => ExecutorError::IoError(err),
+    })
+}
+
+/// Lists a directory of a device. With a sandbox root, no device can be
+/// read, since none is under it.
+fn list_device(source: &DeviceSource, options: &ExecutionOptions) -> Result<Device> {
+    if options.root.is_some() {
+        return Err(ExecutorError::Denied(format!(
+            "{}{} is outside the sandbox root",
+            source.prefix(),
+            source.path
+        )));
+    }
+    android::list(source).map_err(|err| match err.kind() {
+        std::io::ErrorKind::Other => {
+            ExecutorError::InvalidDevice(format!("{}{}: {}", source.prefix(), source.path, err))
+        }
+        _ => ExecutorError::IoError(err),
+    })
+}
+
+/// Returns a query reading a copy's files from `root` instead.
+fn in_copy(query: &FileQuery, root: &Path) -> FileQuery {
+    let mut query = query.clone();
+    match &mut query {
+        FileQuery::Select { path, .. }
+        | FileQuery::Update { path, .. }
+        | FileQuery::Copy { path, .. }
+        | FileQuery::Compress { path, .. }
+        | FileQuery::Extract { path, .. }
+        | FileQuery::Archive { path, .. }
+        | FileQuery::Restore { path, .. }
+        | FileQuery::ShowFilesystem { path } => *path = root.to_path_buf(),
+        FileQuery::Explain { query } => **query = in_copy(query, root),
+    }
+    query
+}
+
+/// Gives the paths of a copy's files among `values`, such as `parent`,
+/// their names where they were copied from.
+fn rename_in_copy<'a>(copy: &dyn Copied, values: impl Iterator<Item = &'a mut FileValue>) {
+    for value in values {
+        if let FileValue::String(text) = value
+            && let Some(renamed) = copy.rewrite(text)
+        {
+            *text = renamed;
+        }
+    }
+}
+
+/// Runs a query for [`execute_query_with_options`], which reports when it finishes.
+fn run_query(query: &FileQuery, options: &ExecutionOptions) -> Result<Vec<FileResult>> {
+    let started = Instant::now();
+    let now = Utc::now();
+    reject_grouped(query)?;
+    let query = &optimize(query.clone());
+    if options.read_only {
+        let statement = match query {
+            FileQuery::Select { .. }
+            | FileQuery::ShowFilesystem { .. }
+            | FileQuery::Explain { .. } => None,
+            FileQuery::Update { .. } => Some("UPDATE"),
+            FileQuery::Copy { .. } => Some("COPY"),
+            FileQuery::Compress { .. } => Some("COMPRESS"),
+            FileQuery::Extract { .. } => Some("EXTRACT"),
+            FileQuery::Archive { .. } => Some("ARCHIVE"),
+            FileQuery::Restore { .. } => Some("RESTORE"),
+        };
+        if let Some(statement) = statement {
+            return Err(ExecutorError::Denied(format!(
+                "{} is not allowed in read-only mode",
+                statement
+            )));
+        }
+    }
+
+    let (targets, root) = resolve_targets(query, options)?;
+    let root = root.as_deref();
+
+    match query {
+        FileQuery::Select {
+            recursive,
+            include_self,
+            entry_type,
+            attributes,
+            computed,
+            condition,
+            order_by,
+            limit,
+            limit_size,
+            within,
+            sample,
+            sample_rows,
+            ..
+        } => {
+            let scan = Scan {
+                recursive: *recursive,
+                include_self: lists_self(*include_self, options),
+                listed: query.lists_paths(),
+                reconcile: Reconcile::for_query(query, &targets, root),
+                entry_type: entry_type.or(options.entry_type),
+                started,
+                now,
+                timeout: select_timeout(*within, &options.limits),
+                decompress: options.decompress,
+                encoding: options.encoding,
+                time_zone: options.time_zone,
+                semantics: options.semantics,
+                sample: *sample,
+                shard: options.shard,
+                ignore: options.ignore.clone(),
+                source: ScanSource::new(query, options, now),
+                retry: options.retry,
+                meter: content_meter(options),
+                faults: options.faults.clone(),
+                events: options.events.clone(),
+            };
+            let join = open_join(query, root)?;
+            let order_by = result_order(order_by, options);
+            let select = Select {
+                computed,
+                join: join.as_ref(),
+                condition: condition.as_ref(),
+                order_by: &order_by,
+                limit: *limit,
+                limit_size: *limit_size,
+                sample_rows: sample_rows.as_ref(),
+            };
+            let checkpoint = match &options.checkpoint {
+                Some(state) => {
+                    Some(Checkpoint::load(state, query)?.unwrap_or_else(|| Checkpoint::new(query)))
+                }
+                None => None,
+            };
+            execute_select(&targets, scan, attributes, select, options, root, checkpoint)
+        }
+        FileQuery::Update {
+            include_self,
+            entry_type,
+            updates,
+            condition,
+            on_conflict,
+            ..
+        } => {
+            reject_checkpoint(options, "UPDATE")?;
+            let access = updates.iter().find_map(|update| match update.attribute {
+                FileAttribute::Permissions => Some("permissions"),
+                FileAttribute::Owner => Some("owner"),
+                FileAttribute::Group => Some("group"),
+                _ => None,
+            });
+            if let Some(access) = access.filter(|_| !options.dry_run) {
+                options.protection.check(&targets, access)?;
+            }
+            let scan = Scan {
+                recursive: true,
+                include_self: lists_self(*include_self, options),
+                listed: query.lists_paths(),
+                reconcile: None,
+                entry_type: entry_type.or(options.entry_type),
+                started,
+                now,
+                timeout: options.limits.timeout,
+                decompress: options.decompress,
+                encoding: options.encoding,
+                time_zone: options.time_zone,
+                semantics: options.semantics,
+                sample: None,
+                shard: options.shard,
+                ignore: options.ignore.clone(),
+                source: ScanSource::new(query, options, now),
+                retry: options.retry,
+                meter: content_meter(options),
+                faults: options.faults.clone(),
+                events: options.events.clone(),
+            };
+            let updated = execute_update(
+                &targets, scan, updates, condition.as_ref(), *on_conflict, options, root,
+            );
+            in_result_order(updated, options)
+        }
+        FileQuery::Copy {
+            path,
+            recursive,
+            destination,
+            condition,
+            method,
+            preserve,
+            sync,
+            verify,
+            on_conflict,
+        } => {
+            reject_checkpoint(options, "COPY")?;
+            // COPY shares the throttle of the scan's content reads
+            let meter = content_meter(options);
+            let scan = Scan {
+                recursive: *recursive,
+                include_self: false,
+                listed: query.lists_paths(),
+                reconcile: None,
+                entry_type: Some(EntryType::File),
+                started,
+                now,
+                timeout: options.limits.timeout,
+                decompress: options.decompress,
+                encoding: options.encoding,
+                time_zone: options.time_zone,
+                semantics: options.semantics,
+                sample: None,
+                shard: options.shard,
+                ignore: options.ignore.clone(),
+                source: ScanSource::new(query, options, now),
+                retry: options.retry,
+                meter: meter.clone(),
+                faults: options.faults.clone(),
+                events: options.events.clone(),
+            };
+            let copy = CopyTo {
+                base: glob_base(path),
+                destination,
+                method: *method,
+                options: CopyOptions {
+                    preserve: *preserve,
+                    sync: *sync,
+                    throttle: meter.as_deref().and_then(Meter::throttle),
+                    retry: copy_retry(options),
+                },
+                verify: *verify,
+                on_conflict: *on_conflict,
+            };
+            let copied = execute_copy(&targets, scan, condition.as_ref(), copy, options, root);
+            in_result_order(copied, options)
+        }
+        FileQuery::Compress {
+            recursive,
+            condition,
+            format,
+            level,
+            delete_original,
+            ..
+        } => {
+            reject_checkpoint(options, "COMPRESS")?;
+            // COMPRESS shares the throttle of the scan's content reads
+            let meter = content_meter(options);
+            let scan = Scan {
+                recursive: *recursive,
+                include_self: false,
+                listed: query.lists_paths(),
+                reconcile: None,
+                entry_type: Some(EntryType::File),
+                started,
+                now,
+                timeout: options.limits.timeout,
+                decompress: options.decompress,
+                encoding: options.encoding,
+                time_zone: options.time_zone,
+                semantics: options.semantics,
+                sample: None,
+                shard: options.shard,
+                ignore: options.ignore.clone(),
+                source: ScanSource::new(query, options, now),
+                retry: options.retry,
+                meter: meter.clone(),
+                faults: options.faults.clone(),
+                events: options.events.clone(),
+            };
+            let compress = CompressTo {
+                format: *format,
+                level: level.unwrap_or_else(|| format.default_level()),
+                delete_original: *delete_original,
+                throttle: meter.as_deref().and_then(Meter::throttle),
+            };
+            let compressed =
+                execute_compress(&targets, scan, condition.as_ref(), compress, options, root);
+            in_result_order(compressed, options)
+        }
+        FileQuery::Extract {
+            recursive,
+            condition,
+            destination,
+            on_conflict,
+            ..
+        } => {
+            reject_checkpoint(options, "EXTRACT")?;
+            let scan = Scan {
+                recursive: *recursive,
+                include_self: false,
+                listed: query.lists_paths(),
+                reconcile: None,
+                entry_type: Some(EntryType::File),
+                started,
+                now,
+                timeout: options.limits.timeout,
+                decompress: options.decompress,
+                encoding: options.encoding,
+                time_zone: options.time_zone,
+                semantics: options.semantics,
+                sample: None,
+                shard: options.shard,
+                ignore: options.ignore.clone(),
+                source: ScanSource::new(query, options, now),
+                retry: options.retry,
+                meter: content_meter(options),
+                faults: options.faults.clone(),
+                events: options.events.clone(),
+            };
+            let extract = ExtractTo {
+                destination,
+                on_conflict: *on_conflict,
+            };
+            let extracted =
+                execute_extract(&targets, scan, condition.as_ref(), extract, options, root);
+            in_result_order(extracted, options)
+        }
+        FileQuery::Archive {
+            path,
+            recursive,
+            condition,
+            destination,
+            delete_original,
+        } => {
+            reject_checkpoint(options, "ARCHIVE")?;
+            // ARCHIVE shares the throttle of the scan's content reads
+            let meter = content_meter(options);
+            let scan = Scan {
+                recursive: *recursive,
+                include_self: false,
+                listed: query.lists_paths(),
+                reconcile: None,
+                entry_type: Some(EntryType::File),
+                started,
+                now,
+                timeout: options.limits.timeout,
+                decompress: options.decompress,
+                encoding: options.encoding,
+                time_zone: options.time_zone,
+                semantics: options.semantics,
+                sample: None,
+                shard: options.shard,
+                ignore: options.ignore.clone(),
+                source: ScanSource::new(query, options, now),
+                retry: options.retry,
+                meter: meter.clone(),
+                faults: options.faults.clone(),
+                events: options.events.clone(),
+            };
+            let archive = ArchiveTo {
+                base: glob_base(path),
+                destination,
+                delete_original: *delete_original,
+                throttle: meter.as_deref().and_then(Meter::throttle),
+            };
+            let archived =
+                execute_archive(&targets, scan, condition.as_ref(), archive, options, root);
+            in_result_order(archived, options)
+        }
+        FileQuery::Restore {
+            recursive,
+            condition,
+            on_conflict,
+            ..
+        } => {
+            reject_checkpoint(options, "RESTORE")?;
+            let scan = Scan {
+                recursive: *recursive,
+                include_self: false,
+                listed: query.lists_paths(),
+                reconcile: None,
+                entry_type: None,
+                started,
+                now,
+                timeout: options.limits.timeout,
+                decompress: options.decompress,
+                encoding: options.encoding,
+                time_zone: options.time_zone,
+                semantics: options.semantics,
+                sample: None,
+                shard: options.shard,
+                ignore: options.ignore.clone(),
+                source: ScanSource::new(query, options, now),
+                retry: options.retry,
+                meter: content_meter(options),
+                faults: options.faults.clone(),
+                events: options.events.clone(),
+            };
+            let restored =
+                execute_restore(&targets, scan, condition.as_ref(), *on_conflict, options, root);
+            in_result_order(restored, options)
+        }
+        FileQuery::ShowFilesystem { .. } | FileQuery::Explain { .. } => {
+            unreachable!("SHOW FILESYSTEM and EXPLAIN are rejected as grouped")
+        }
+    }
+}
+
+/// Counts the files that match a SELECT query.
+///
+/// This gives the same number as `execute_query(query)?.len()`, but no
+/// result list is kept: each file is checked against the WHERE clause and
+/// then dropped, computed columns are never evaluated, and without a WHERE
+/// clause the files are not even stat'ed. `max_results` does not apply,
+/// since there are no results to hold.
+///
+/// # Examples
+///
+/// ```no_run
+/// use fmql_core::sql::{parse_sql, execute_query_count};
+///
+/// let query = parse_sql("WITH RECURSIVE SELECT * FROM . WHERE extension = 'rs'").unwrap();
+/// println!("{} Rust files", execute_query_count(&query).unwrap());
+/// ```
+pub fn execute_query_count(query: &FileQuery) -> Result<usize> {
+    execute_query_count_with_options(query, &ExecutionOptions::default())
+}
+
+/// Counts the files that match a SELECT query with the given options; see
+/// [`execute_query_count`].
+pub fn execute_query_count_with_options(
+    query: &FileQuery,
+    options: &ExecutionOptions,
+) -> Result<usize> {
+    let started = Instant::now();
+    let now = Utc::now();
+    reject_checkpoint(options, "Counting")?;
+    let query = &optimize(query.clone());
+    let FileQuery::Select {
+        recursive,
+        include_self,
+        entry_type,
+        condition,
+        limit,
+        limit_size,
+        within,
+        sample,
+        sample_rows,
+        ..
+    } = query
+    else {
+        return Err(ExecutorError::UnsupportedOperation(
+            "only SELECT queries can be counted".to_string(),
+        ));
+    };
+    reject_grouped(query)?;
+    if sample_rows.is_some() {
+        // Which matches a sample picks depends on all of them
+        return Ok(execute_query_with_options(query, options)?.len());
+    }
+    let limit = limit.unwrap_or(usize::MAX);
+    if limit == 0 || *limit_size == Some(0) {
+        return Ok(0);
+    }
+
+    let (targets, root) = resolve_targets(query, options)?;
+    let scan = Scan {
+        recursive: *recursive,
+        include_self: lists_self(*include_self, options),
+        listed: query.lists_paths(),
+        reconcile: Reconcile::for_query(query, &targets, root.as_deref()),
+        entry_type: entry_type.or(options.entry_type),
+        started,
+        now,
+        timeout: select_timeout(*within, &options.limits),
+        decompress: options.decompress,
+        encoding: options.encoding,
+        time_zone: options.time_zone,
+        semantics: options.semantics,
+        sample: *sample,
+        shard: options.shard,
+        ignore: options.ignore.clone(),
+        source: ScanSource::new(query, options, now),
+        retry: options.retry,
+        meter: content_meter(options),
+        faults: options.faults.clone(),
+        events: options.events.clone(),
+    };
+
+    let join = open_join(query, root.as_deref())?;
+    let (mut count, mut size) = (0, 0u64);
+    visit_paths(&targets, scan.clone(), root.as_deref(), &options.limits, |path| {
+        // Only a condition, a join or a limit on size needs the metadata
+        let matched = match (condition, &join, limit_size) {
+            (None, None, None) => {
+                options.events.emit(|| Event::EntryMatched { path: path.to_path_buf() });
+                Some(0)
+            }
+            (condition, join, _) => match scan.open(path)? {
+                Some(mut file) => {
+                    let (faults, events) = (&options.faults, &options.events);
+                    keep(&mut file, join.as_ref(), condition.as_ref(), false, faults, events)?
+                        .then_some(file.size)
+                }
+                None => None,
+            },
+        };
+        if let Some(matched) = matched {
+            count += 1;
+            size = size.saturating_add(matched);
+        }
+        Ok(if count >= limit || limit_size.is_some_and(|limit_size| size >= limit_size) {
+            ControlFlow::Break(())
+        } else {
+            ControlFlow::Continue(())
+        })
+    })?;
+    Ok(count)
+}
+
+/// Fetches the results of a SELECT query a page at a time.
+///
+/// Files are found, filtered and built only as pages are asked for, so
+/// memory stays bounded by the page size however large the tree is. This
+/// suits interfaces that show results as the user scrolls. With `ORDER BY`
+/// the first page has to find and sort every match, and the rest are served
+/// from that sorted list. The query's
+/// `WITHIN` budget and `options.limits.timeout` count from when the cursor
+/// was opened, including time spent between pages.
+///
+/// # Examples
+///
+/// ```no_run
+/// use fmql_core::sql::{parse_sql, ExecutionOptions, QueryCursor};
+///
+/// let query = parse_sql("WITH RECURSIVE SELECT * FROM ~/Music WHERE extension = 'flac'").unwrap();
+/// let mut cursor = QueryCursor::new(&query, &ExecutionOptions::default()).unwrap();
+/// loop {
+///     let page = cursor.next_page(50).unwrap();
+///     if page.is_empty() {
+///         break;
+///     }
+///     for file in page {
+///         println!("{}", file.path.display());
+///     }
+/// }
+/// ```
+pub struct QueryCursor {
+    walk: Walk,
+    computed: Vec<ComputedColumn>,
+    /// The attributes the query selects, for the columns read per match.
+    attributes: Vec<FileAttribute>,
+    join: Option<Lookup>,
+    /// Finds the matching lines, when the query selects `matches`.
+    matcher: Option<LineMatcher>,
+    condition: Option<FileCondition>,
+    order_by: Vec<OrderBy>,
+    limit: Option<usize>,
+    limit_size: Option<u64>,
+    sample_rows: Option<RowSample>,
+    /// With ORDER BY or SAMPLE n ROWS, every match, picked and sorted;
+    /// filled by the first page.
+    sorted: Option<std::vec::IntoIter<FileResult>>,
+    matched: usize,
+    fetched: usize,
+    /// The total size of the results fetched so far.
+    fetched_size: u64,
+    faults: Faults,
+}
+
+impl QueryCursor {
+    /// Opens a cursor over a SELECT query. The path is resolved and checked
+    /// against the sandbox here, but no files are listed until the first
+    /// page is fetched.
+    pub fn new(query: &FileQuery, options: &ExecutionOptions) -> Result<Self> {
+        let started = Instant::now();
+        let now = Utc::now();
+        reject_checkpoint(options, "A cursor")?;
+        let query = &optimize(query.clone());
+        let FileQuery::Select {
+            recursive,
+            include_self,
+            entry_type,
+            attributes,
+            computed,
+            condition,
+            order_by,
+            limit,
+            limit_size,
+            within,
+            sample,
+            sample_rows,
+            ..
+        } = query
+        else {
+            return Err(ExecutorError::UnsupportedOperation(
+                "only SELECT queries can be fetched with a cursor".to_string(),
+            ));
+        };
+        reject_grouped(query)?;
+
+        let (targets, root) = resolve_targets(query, options)?;
+        let scan = Scan {
+            recursive: *recursive,
+            include_self: lists_self(*include_self, options),
+            listed: query.lists_paths(),
+            reconcile: Reconcile::for_query(query, &targets, root.as_deref()),
+            entry_type: entry_type.or(options.entry_type),
+            started,
+            now,
+            timeout: select_timeout(*within, &options.limits),
+            decompress: options.decompress,
+            encoding: options.encoding,
+            time_zone: options.time_zone,
+            semantics: options.semantics,
+            sample: *sample,
+            shard: options.shard,
+            ignore: options.ignore.clone(),
+            source: ScanSource::new(query, options, now),
+            retry: options.retry,
+            meter: content_meter(options),
+            faults: options.faults.clone(),
+            events: options.events.clone(),
+        };
+        let join = open_join(query, root.as_deref())?;
+        Ok(QueryCursor {
+            walk: Walk::new(targets, scan, root, options.limits.clone()),
+            computed: computed.clone(),
+            attributes: attributes.clone(),
+            join,
+            matcher: line_matcher(attributes, condition.as_ref())?,
+            condition: condition.clone(),
+            order_by: result_order(order_by, options).into_owned(),
+            limit: *limit,
+            limit_size: *limit_size,
+            sample_rows: sample_rows.clone(),
+            sorted: None,
+            matched: 0,
+            fetched: 0,
+            fetched_size: 0,
+            faults: options.faults.clone(),
+        })
+    }
+
+    /// Returns up to `n` more matches. A page shorter than `n` means the
+    /// results are exhausted, and every later page is empty.
+    pub fn next_page(&mut self, n: usize) -> Result<Vec<FileResult>> {
+        let n = match self.limit {
+            Some(limit) => n.min(limit.saturating_sub(self.fetched)),
+            None => n,
+        };
+        if (!self.order_by.is_empty() || self.sample_rows.is_some()) && self.sorted.is_none() {
+            let mut all = Vec::new();
+            while let Some(file) = self.next_match()? {
+                all.push(file);
+            }
+            if let Some(rows) = &self.sample_rows {
+                all = pick_rows(all, rows)?;
+            }
+            sort_results(&mut all, &self.order_by)?;
+            self.sorted = Some(all.into_iter());
+        }
+
+        let mut page = Vec::new();
+        while page.len() < n && self.limit_size.is_none_or(|size| self.fetched_size < size) {
+            let next = match &mut self.sorted {
+                Some(sorted) => sorted.next(),
+                None => self.next_match()?,
+            };
+            let Some(file) = next else {
+                break;
+            };
+            self.fetched += 1;
+            self.fetched_size = self.fetched_size.saturating_add(file.size);
+            page.push(file);
+        }
+        Ok(page)
+    }
+
+    /// Finds the next file that matches the condition, in traversal order.
+    fn next_match(&mut self) -> Result<Option<FileResult>> {
+        let scan = self.walk.scan.clone();
+        for path in self.walk.by_ref() {
+            let Some(mut file) = scan.open(&path?)? else {
+                continue;
+            };
+            let (join, condition) = (self.join.as_ref(), self.condition.as_ref());
+            if !keep(&mut file, join, condition, false, &self.faults, &scan.events)? {
+                continue;
+            }
+            self.matched += 1;
+            check_result_count(self.matched, &self.walk.limits)?;
+            add_columns(&mut file, &self.computed, &self.attributes, self.matcher.as_ref())?;
+            return Ok(Some(file));
+        }
+        Ok(None)
+    }
+
+    /// The number of results fetched so far.
+    pub fn fetched(&self) -> usize {
+        self.fetched
+    }
+}
+
+/// Expands the query's path into the targets to scan, and checks them
+/// against the sandbox root. Returns the targets and the canonical root.
+fn resolve_targets(
+    query: &FileQuery,
+    options: &ExecutionOptions,
+) -> Result<(Vec<PathBuf>, Option<PathBuf>)> {
+    let path = match query {
+        FileQuery::Select { path, .. }
+        | FileQuery::Update { path, .. }
+        | FileQuery::Copy { path, .. }
+        | FileQuery::Compress { path, .. }
+        | FileQuery::Extract { path, .. }
+        | FileQuery::Archive { path, .. }
+        | FileQuery::Restore { path, .. }
+        | FileQuery::ShowFilesystem { path } => path,
+        FileQuery::Explain { query } => return resolve_targets(query, options),
+    };
+    // Only a SELECT reads an image, a backup or a device, and only through
+    // the functions that copy it
+    if ImageSource::parse(path).is_some() {
+        return Err(ExecutorError::InvalidImage(format!(
+            "{} can only be read by SELECT",
+            path.display()
+        )));
+    }
+    if BackupSource::parse(path).is_some() {
+        return Err(ExecutorError::InvalidBackup(format!(
+            "{} can only be read by SELECT",
+            path.display()
+        )));
+    }
+    if DeviceSource::parse(path).is_some() {
+        return Err(ExecutorError::InvalidDevice(format!(
+            "{} can only be read by SELECT; mount the device over MTP to change its files",
+            path.display()
+        )));
+    }
+    let root = match &options.root {
+        Some(root) => Some(fs::canonicalize(root)?),
+        None => None,
+    };
+    let listed = query.lists_paths();
+    let targets = if query.reads_table() {
+        table_paths(path, root.as_deref())?
+    } else if !listed {
+        expand_targets(path)?
+    } else if let Some(files) = &options.files_from {
+        files.clone()
+    } else {
+        read_path_list(std::io::stdin().lock())?
+    };
+    if let Some(root) = &root {
+        for target in &targets {
+            let target_path = match fs::canonicalize(target) {
+                // Listed paths that no longer exist are skipped by the walk
+                Err(err) if listed && err.kind() == std::io::ErrorKind::NotFound => continue,
+                result => result?,
+            };
+            if !target_path.starts_with(root) {
+                return Err(ExecutorError::Denied(format!(
+                    "{} is outside the sandbox root",
+                    target.display()
+                )));
+            }
+        }
+    }
+
+    // A WHERE clause that can never hold needs no scan
+    if never_matches(query) {
+        return Ok((Vec::new(), root));
+    }
+    if options.nice_io {
+        lower_io_priority()?;
+    }
+
+    Ok((targets, root))
+}
+
+/// Reads the paths listed by a table read `FROM 'paths.csv' AS TABLE`. With
+/// a sandbox root, the table has to be inside it like the files it lists.
+fn table_paths(table: &Path, root: Option<&Path>) -> Result<Vec<PathBuf>> {
+    if table.exists() && !is_within_root(table, root) {
+        return Err(ExecutorError::Denied(format!(
+            "{} is outside the sandbox root",
+            table.display()
+        )));
+    }
+    let table = JoinTable::load(table, PATH_COLUMN)?;
+    Ok(table.keys().into_iter().map(PathBuf::from).collect())
+}
+
+/// The time budget for a SELECT: the shorter of its WITHIN clause and the
+/// caller's timeout.
+fn select_timeout(within: Option<Duration>, limits: &ResourceLimits) -> Option<Duration> {
+    match (limits.timeout, within) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    }
+}
+
+/// Executes a grouped SELECT, one with `GROUP BY` or aggregates such as
+/// `SUM(size)`, and returns one row per group.
+///
+/// Groups come out in order of their GROUP BY values unless the query has an
+/// ORDER BY, whose keys must then be GROUP BY columns. Without GROUP BY
+/// there is exactly one row, even when nothing matched. `max_results` limits
+/// the number of groups.
+///
+/// # Examples
+///
+/// ```no_run
+/// use fmql_core::sql::{parse_sql, execute_grouped};
+///
+/// let query = parse_sql("WITH RECURSIVE SELECT owner, SUM(size) FROM /srv GROUP BY owner").unwrap();
+/// for row in execute_grouped(&query).unwrap() {
+///     println!("{:?}", row.columns);
+/// }
+/// ```
+pub fn execute_grouped(query: &FileQuery) -> Result<Vec<GroupRow>> {
+    execute_grouped_with_options(query, &ExecutionOptions::default())
+}
+
+/// Executes a grouped SELECT with the given options; see [`execute_grouped`].
+pub fn execute_grouped_with_options(
+    query: &FileQuery,
+    options: &ExecutionOptions,
+) -> Result<Vec<GroupRow>> {
+    let started = Instant::now();
+    let outcome = match elsewhere(query) {
+        Some(source) => run_grouped_elsewhere(query, &source, options),
+        None => run_grouped(query, options),
+    };
+    finished(&options.events, &outcome, started);
+    outcome
+}
+
+/// Runs a query for [`execute_grouped_with_options`], which reports when it finishes.
+fn run_grouped(query: &FileQuery, options: &ExecutionOptions) -> Result<Vec<GroupRow>> {
+    reject_checkpoint(options, "A grouped query")?;
+    if let FileQuery::Explain { query } = query {
+        return Ok(explain(query));
+    }
+    if let FileQuery::ShowFilesystem { .. } = query {
+        let (targets, _) = resolve_targets(query, options)?;
+        return targets
+            .iter()
+            .map(|target| Ok(probe(target)?.to_row()))
+            .collect();
+    }
+    let groups = scan_groups(query, options)?;
+    finish_groups(query, groups)
+}
+
+/// Finds the groups of a grouped SELECT, with the running totals of their
+/// aggregates, in the order they were first seen.
+fn scan_groups(query: &FileQuery, options: &ExecutionOptions) -> Result<Vec<PartialGroup>> {
+    let started = Instant::now();
+    let now = Utc::now();
+    let query = &optimize(query.clone());
+    let FileQuery::Select {
+        recursive,
+        include_self,
+        entry_type,
+        aggregates,
+        condition,
+        group_by,
+        rollup,
+        order_by,
+        within,
+        sample,
+        ..
+    } = query
+    else {
+        return Err(ExecutorError::UnsupportedOperation(
+            "only SELECT queries can be grouped".to_string(),
+        ));
+    };
+    if *rollup {
+        return Err(ExecutorError::UnsupportedOperation(
+            "ROLLUP BY path returns a tree of directories; use execute_rollup".to_string(),
+        ));
+    }
+    // Check the ORDER BY before scanning rather than after
+    group_order(group_by, order_by)?;
+
+    let (targets, root) = resolve_targets(query, options)?;
+    let scan = Scan {
+        recursive: *recursive,
+        include_self: lists_self(*include_self, options),
+        listed: query.lists_paths(),
+        reconcile: Reconcile::for_query(query, &targets, root.as_deref()),
+        entry_type: entry_type.or(options.entry_type),
+        started,
+        now,
+        timeout: select_timeout(*within, &options.limits),
+        decompress: options.decompress,
+        encoding: options.encoding,
+        time_zone: options.time_zone,
+        semantics: options.semantics,
+        sample: *sample,
+        shard: options.shard,
+        ignore: options.ignore.clone(),
+        source: ScanSource::new(query, options, now),
+        retry: options.retry,
+        meter: content_meter(options),
+        faults: options.faults.clone(),
+        events: options.events.clone(),
+    };
+
+    let join = open_join(query, root.as_deref())?;
+    let add = |table: &mut GroupTable, mut file: FileResult| {
+        let (faults, events) = (&options.faults, &options.events);
+        if !keep(&mut file, join.as_ref(), condition.as_ref(), false, faults, events)? {
+            return Ok(());
+        }
+        let key = group_by
+            .iter()
+            .map(|key| evaluate_expr(&file, &key.expr))
+            .collect::<Result<Vec<_>>>()?;
+        table.add(&file, key, aggregates, &options.limits)
+    };
+    let table = match options.jobs > 1 && by_directory(group_by) {
+        true => scan_groups_in_parallel(&targets, scan, root.as_deref(), options, add)?,
+        false => {
+            let mut table = GroupTable::default();
+            visit_files(&targets, scan, root.as_deref(), &options.limits, |file| {
+                add(&mut table, file)?;
+                Ok(ControlFlow::Continue(()))
+            })?;
+            table
+        }
+    };
+    Ok(table.groups)
+}
+
+/// The groups of a grouped SELECT, in the order they were first seen.
+#[derive(Default)]
+struct GroupTable {
+    groups: Vec<PartialGroup>,
+    /// FileValue isn't hashable, so groups are found by their keys' debug
+    /// text.
+    index: HashMap<String, usize>,
+}
+
+impl GroupTable {
+    /// Adds `file` to the totals of the group with `key`.
+    fn add(
+        &mut self,
+        file: &FileResult,
+        key: Vec<FileValue>,
+        aggregates: &[AggregateColumn],
+        limits: &ResourceLimits,
+    ) -> Result<()> {
+        let slot = match self.index.get(&format!("{:?}", key)) {
+            Some(&slot) => slot,
+            None => {
+                self.index.insert(format!("{:?}", key), self.groups.len());
+                self.groups.push(PartialGroup {
+                    key,
+                    accumulators: new_group(aggregates),
+                });
+                check_result_count(self.groups.len(), limits)?;
+                self.groups.len() - 1
+            }
+        };
+
+        for (accumulator, column) in self.groups[slot].accumulators.iter_mut().zip(aggregates) {
+            let value = column
+                .argument
+                .as_ref()
+                .map(|argument| evaluate_expr(file, argument))
+                .transpose()?;
+            accumulator
+                .add(value.as_ref())
+                .map_err(ExecutorError::TypeError)?;
+        }
+        Ok(())
+    }
+
+    /// Adds the totals of `group`, found elsewhere, to the group with the
+    /// same key.
+    fn merge(&mut self, group: &PartialGroup) {
+        match self.index.get(&format!("{:?}", group.key)) {
+            Some(&slot) => {
+                let totals = self.groups[slot].accumulators.iter_mut().zip(&group.accumulators);
+                for (total, other) in totals {
+                    total.merge(other);
+                }
+            }
+            None => {
+                self.index.insert(format!("{:?}", group.key), self.groups.len());
+                self.groups.push(group.clone());
+            }
+        }
+    }
+}
+
+/// Returns true if a query is grouped by `DIRNAME(path)` alone, so that
+/// the entries of a directory all go to the same group.
+fn by_directory(group_by: &[ComputedColumn]) -> bool {
+    match group_by {
+        [column] => matches!(
+            &column.expr,
+            FileExpr::Function { name, args }
+                if name == "DIRNAME"
+                    && matches!(args[..], [FileExpr::Attribute(FileAttribute::Path)])
+        ),
+        _ => false,
+    }
+}
+
+/// How many entries of a directory a worker is handed at once.
+const DIRECTORY_BATCH: usize = 256;
+
+/// Scans a query grouped by `DIRNAME(path)` with `options.jobs` workers.
+///
+/// The walk stays on this thread, which only lists directories, and hands
+/// their entries to the workers in batches, each from one directory. Each
+/// worker reads, filters and totals its batches into groups of its own,
+/// with no lock to take for each entry, and the workers' groups are merged
+/// once the walk is done. A directory's entries only ever go to its own
+/// group, so each worker's groups stay few, and merging them is quick
+/// however many entries there were. The first error any worker finds stops
+/// the scan, though with workers running at once it may not be the first
+/// in the order of the walk.
+fn scan_groups_in_parallel(
+    targets: &[PathBuf],
+    scan: Scan,
+    root: Option<&Path>,
+    options: &ExecutionOptions,
+    add: impl Fn(&mut GroupTable, FileResult) -> Result<()> + Sync,
+) -> Result<GroupTable> {
+    let (sender, receiver) = mpsc::sync_channel::<Vec<PathBuf>>(options.jobs * 2);
+    let receiver = Mutex::new(receiver);
+    let failure: Mutex<Option<ExecutorError>> = Mutex::new(None);
+    let failed = AtomicBool::new(false);
+    let fail = |err| {
+        failure.lock().expect("worker panicked").get_or_insert(err);
+        failed.store(true, Ordering::Relaxed);
+    };
+
+    let tables: Vec<GroupTable> = thread::scope(|scope| {
+        let workers: Vec<_> = (0..options.jobs)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut table = GroupTable::default();
+                    loop {
+                        let Ok(batch) = receiver.lock().expect("worker panicked").recv() else {
+                            break;
+                        };
+                        // Keep taking batches after a failure, so that the
+                        // walk is never left waiting to hand one over
+                        if failed.load(Ordering::Relaxed) {
+                            continue;
+                        }
+                        for path in batch {
+                            let added = match scan.open(&path) {
+                                Ok(Some(file)) => add(&mut table, file),
+                                Ok(None) => Ok(()),
+                                Err(err) => Err(err),
+                            };
+                            if let Err(err) = added {
+                                fail(err);
+                                break;
+                            }
+                        }
+                    }
+                    table
+                })
+            })
+            .collect();
+
+        let walk = Walk::new(
+            targets.to_vec(),
+            scan.clone(),
+            root.map(Path::to_path_buf),
+            options.limits.clone(),
+        );
+        let mut batch: Vec<PathBuf> = Vec::new();
+        for path in walk {
+            if failed.load(Ordering::Relaxed) {
+                break;
+            }
+            let path = match path {
+                Ok(path) => path,
+                Err(err) => {
+                    fail(err);
+                    break;
+                }
+            };
+            let full = batch.len() >= DIRECTORY_BATCH;
+            if full || batch.last().is_some_and(|last| last.parent() != path.parent()) {
+                // Only fails once every worker has gone, which they don't
+                // before the channel closes
+                let _ = sender.send(std::mem::take(&mut batch));
+            }
+            batch.push(path);
+        }
+        if !batch.is_empty() {
+            let _ = sender.send(batch);
+        }
+        drop(sender);
+        workers
+            .into_iter()
+            .map(|worker| worker.join().expect("worker panicked"))
+            .collect()
+    });
+    if let Some(err) = failure.into_inner().expect("worker panicked") {
+        return Err(err);
+    }
+
+    let mut merged = GroupTable::default();
+    for table in &tables {
+        for group in &table.groups {
+            merged.merge(group);
+        }
+    }
+    check_result_count(merged.groups.len(), &options.limits)?;
+    Ok(merged)
+}
+
+/// Returns the running totals of a group that has seen no files yet.
+fn new_group(aggregates: &[AggregateColumn]) -> Vec<Accumulator> {
+    aggregates
+        .iter()
+        .map(|column| Accumulator::new(column.function))
+        .collect()
+}
+
+/// Finds the GROUP BY column each ORDER BY key sorts on. ORDER BY can only
+/// sort on what a group has: its GROUP BY values.
+fn group_order(group_by: &[ComputedColumn], order_by: &[OrderBy]) -> Result<Vec<usize>> {
+    order_by
+        .iter()
+        .map(|order| {
+            group_by
+                .iter()
+                .position(|key| match (&order.key, &key.expr) {
+                    (SortKey::Attribute(attribute), FileExpr::Attribute(key)) => attribute == key,
+                    (
+                        SortKey::Joined { column, .. },
+                        FileExpr::Joined { column: key, .. },
+                    ) => column == key,
+                    _ => false,
+                })
+                .ok_or_else(|| {
+                    ExecutorError::UnsupportedOperation(
+                        "ORDER BY in a grouped query must use GROUP BY columns".to_string(),
+                    )
+                })
+        })
+        .collect()
+}
+
+/// Turns the groups of a grouped SELECT into its rows: sorted, limited, and
+/// with the value of each aggregate.
+fn finish_groups(query: &FileQuery, mut groups: Vec<PartialGroup>) -> Result<Vec<GroupRow>> {
+    let FileQuery::Select {
+        aggregates,
+        group_by,
+        order_by,
+        limit,
+        sample,
+        ..
+    } = query
+    else {
+        return Err(ExecutorError::UnsupportedOperation(
+            "only SELECT queries can be grouped".to_string(),
+        ));
+    };
+    let order_columns = group_order(group_by, order_by)?;
+
+    if group_by.is_empty() && groups.is_empty() {
+        groups.push(PartialGroup {
+            key: Vec::new(),
+            accumulators: new_group(aggregates),
+        });
+    }
+
+    groups.sort_by(|a, b| {
+        a.key
+            .iter()
+            .zip(&b.key)
+            .map(|(a, b)| sort::compare_values(a, b, false))
+            .find(|ordering| ordering.is_ne())
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+    let mut keyed: Vec<_> = groups
+        .into_iter()
+        .map(|group| {
+            let keys = order_columns.iter().map(|&i| group.key[i].clone()).collect();
+            (keys, group)
+        })
+        .collect();
+    sort_keyed(&mut keyed, order_by)?;
+    if let Some(limit) = limit {
+        keyed.truncate(*limit);
+    }
+
+    Ok(keyed
+        .into_iter()
+        .map(|(_, group)| {
+            let mut columns: Vec<_> =
+                group_by.iter().map(|column| column.name.clone()).zip(group.key).collect();
+            for (column, accumulator) in aggregates.iter().zip(&group.accumulators) {
+                match sample {
+                    // Each estimate comes with the bounds of its confidence interval
+                    Some(fraction) => {
+                        let estimate = accumulator.estimate(*fraction);
+                        columns.push((column.name.clone(), estimate.value));
+                        columns.push((format!("{}_low", column.name), estimate.low));
+                        columns.push((format!("{}_high", column.name), estimate.high));
+                    }
+                    None => columns.push((column.name.clone(), accumulator.finish())),
+                }
+            }
+            GroupRow { columns }
+        })
+        .collect())
+}
+
+/// Runs one shard of a SELECT and returns what it found, for merging with
+/// the other shards by [`merge_files`] or [`merge_groups`]. The shard is
+/// `options.shard`, or the whole tree as the only shard if there is none.
+///
+/// # Examples
+///
+/// ```no_run
+/// use fmql_core::sql::executor::{execute_shard, merge_groups, ExecutionOptions};
+/// use fmql_core::sql::parse_sql;
+/// use fmql_core::sql::shard::Shard;
+///
+/// let query = parse_sql("WITH RECURSIVE SELECT extension, AVG(size) FROM /srv GROUP BY extension").unwrap();
+/// let parts: Vec<_> = (1..=2)
+///     .map(|index| {
+///         let shard = Some(Shard { index, count: 2 });
+///         execute_shard(&query, &ExecutionOptions { shard, ..Default::default() }).unwrap()
+///     })
+///     .collect();
+/// for row in merge_groups(&parts).unwrap() {
+///     println!("{:?}", row.columns);
+/// }
+/// ```
+pub fn execute_shard(query: &FileQuery, options: &ExecutionOptions) -> Result<ShardResult> {
+    if !matches!(query, FileQuery::Select { .. }) || query.is_rollup() {
+        return Err(ExecutorError::UnsupportedOperation(
+            "only SELECT results can be merged, not those of ROLLUP BY path, UPDATE or COPY"
+                .to_string(),
+        ));
+    }
+    reject_unseeded(query)?;
+    let mut part = ShardResult {
+        query: query.clone(),
+        shard: options.shard.unwrap_or(Shard { index: 1, count: 1 }),
+        matches: Vec::new(),
+        groups: Vec::new(),
+    };
+    if query.is_grouped() {
+        reject_checkpoint(options, "A grouped query")?;
+        part.groups = scan_groups(query, options)?;
+    } else {
+        // The merge picks a sample from every shard's picks, so none of
+        // them can be left out by LIMIT first
+        let mut query = query.clone();
+        if let FileQuery::Select {
+            limit,
+            limit_size,
+            sample_rows: Some(_),
+            ..
+        } = &mut query
+        {
+            (*limit, *limit_size) = (None, None);
+        }
+        let files = execute_query_with_options(&query, options)?;
+        part.matches = files.into_iter().map(|file| file.path).collect();
+    }
+    Ok(part)
+}
+
+/// Combines the shards of a SELECT that returns files into its results, as
+/// if one process had scanned the whole tree: sorted and limited as the
+/// query says, with its computed columns. The matches are read again, so
+/// the files have to be under the same paths here as where the shards ran,
+/// but the WHERE clause isn't checked again.
+pub fn merge_files(parts: &[ShardResult], options: &ExecutionOptions) -> Result<Vec<FileResult>> {
+    let query = check_parts(parts)?;
+    let FileQuery::Select {
+        attributes,
+        computed,
+        condition,
+        order_by,
+        limit,
+        limit_size,
+        sample_rows,
+        ..
+    } = query
+    else {
+        return Err(ExecutorError::UnsupportedOperation(
+            "only SELECT results can be merged".to_string(),
+        ));
+    };
+    reject_unseeded(query)?;
+    if query.is_grouped() {
+        return Err(ExecutorError::UnsupportedOperation(
+            "the shards of a grouped query are merged with merge_groups".to_string(),
+        ));
+    }
+
+    let root = match &options.root {
+        Some(root) => Some(fs::canonicalize(root)?),
+        None => None,
+    };
+    let now = Utc::now();
+    let scan = Scan {
+        recursive: false,
+        include_self: false,
+        listed: true,
+        reconcile: None,
+        entry_type: None,
+        started: Instant::now(),
+        now,
+        timeout: options.limits.timeout,
+        decompress: options.decompress,
+        encoding: options.encoding,
+        time_zone: options.time_zone,
+        semantics: options.semantics,
+        sample: None,
+        shard: None,
+        ignore: options.ignore.clone(),
+        source: ScanSource::new(query, options, now),
+        retry: options.retry,
+        meter: content_meter(options),
+        faults: options.faults.clone(),
+        events: options.events.clone(),
+    };
+    let join = open_join(query, root.as_deref())?;
+    let mut shards: Vec<&ShardResult> = parts.iter().collect();
+    shards.sort_by_key(|part| part.shard.index);
+    let paths: Vec<PathBuf> = shards
+        .iter()
+        .flat_map(|part| &part.matches)
+        .filter(|path| is_within_root(path, root.as_deref()))
+        .cloned()
+        .collect();
+    let mut files = reopen(&paths, &scan, join.as_ref())?;
+
+    // Each shard kept its own first few; these are the first few of all
+    if let Some(rows) = sample_rows {
+        files = pick_rows(files, rows)?;
+    }
+    let order_by = result_order(order_by, options);
+    if !order_by.is_empty() {
+        sort_results(&mut files, &order_by)?;
+    }
+    if let Some(limit) = limit {
+        files.truncate(*limit);
+    }
+    if let Some(size) = limit_size {
+        files.truncate(within_size(&files, *size));
+    }
+    let matcher = line_matcher(attributes, condition.as_ref())?;
+    for file in &mut files {
+        add_columns(file, computed, attributes, matcher.as_ref())?;
+    }
+    Ok(files)
+}
+
+/// Combines the shards of a grouped SELECT into its rows, merging the
+/// running totals of the groups more than one shard found.
+pub fn merge_groups(parts: &[ShardResult]) -> Result<Vec<GroupRow>> {
+    let query = check_parts(parts)?;
+    if !query.is_grouped() {
+        return Err(ExecutorError::UnsupportedOperation(
+            "the shards of a query that returns files are merged with merge_files".to_string(),
+        ));
+    }
+    let mut table = GroupTable::default();
+    for group in parts.iter().flat_map(|part| &part.groups) {
+        table.merge(group);
+    }
+    finish_groups(query, table.groups)
+}
+
+/// Executes a `ROLLUP BY path` query and returns one tree per directory
+/// target.
+///
+/// Each matching entry counts towards the aggregates of every directory
+/// above it, up to the target, like `du` does for sizes. Directories
+/// without matching entries are left out, except the targets themselves.
+/// `max_results` limits the number of directories.
+///
+/// # Examples
+///
+/// ```no_run
+/// use fmql_core::sql::{parse_sql, execute_rollup};
+///
+/// let query = parse_sql("WITH RECURSIVE SELECT SUM(size) FROM ~/projects TYPE f ROLLUP BY path").unwrap();
+/// for tree in execute_rollup(&query).unwrap() {
+///     println!("{}: {:?}", tree.path.display(), tree.columns);
+/// }
+/// ```
+pub fn execute_rollup(query: &FileQuery) -> Result<Vec<RollupNode>> {
+    execute_rollup_with_options(query, &ExecutionOptions::default())
+}
+
+/// Executes a `ROLLUP BY path` query with the given options; see
+/// [`execute_rollup`].
+pub fn execute_rollup_with_options(
+    query: &FileQuery,
+    options: &ExecutionOptions,
+) -> Result<Vec<RollupNode>> {
+    let started = Instant::now();
+    let outcome = run_rollup(query, options);
+    finished(&options.events, &outcome, started);
+    outcome
+}
+
+/// Runs a query for [`execute_rollup_with_options`], which reports when it finishes.
+fn run_rollup(query: &FileQuery, options: &ExecutionOptions) -> Result<Vec<RollupNode>> {
+    let started = Instant::now();
+    let now = Utc::now();
+    reject_checkpoint(options, "ROLLUP BY path")?;
+    let query = &optimize(query.clone());
+    let FileQuery::Select {
+        recursive,
+        include_self,
+        entry_type,
+        aggregates,
+        condition,
+        rollup: true,
+        within,
+        sample,
+        ..
+    } = query
+    else {
+        return Err(ExecutorError::UnsupportedOperation(
+            "only ROLLUP BY path queries can be rolled up".to_string(),
+        ));
+    };
+    if query.lists_paths() {
+        return Err(ExecutorError::UnsupportedOperation(
+            "ROLLUP BY path needs directories to scan, not a list of paths".to_string(),
+        ));
+    }
+    if sample.is_some() {
+        return Err(ExecutorError::UnsupportedOperation(
+            "ROLLUP BY path can't be combined with SAMPLE".to_string(),
+        ));
+    }
+
+    let (targets, root) = resolve_targets(query, options)?;
+    let tops: Vec<PathBuf> = targets.iter().filter(|target| target.is_dir()).cloned().collect();
+    let scan = Scan {
+        recursive: *recursive,
+        include_self: lists_self(*include_self, options),
+        listed: false,
+        reconcile: None,
+        entry_type: entry_type.or(options.entry_type),
+        started,
+        now,
+        timeout: select_timeout(*within, &options.limits),
+        decompress: options.decompress,
+        encoding: options.encoding,
+        time_zone: options.time_zone,
+        semantics: options.semantics,
+        sample: None,
+        shard: options.shard,
+        ignore: options.ignore.clone(),
+        source: ScanSource::new(query, options, now),
+        retry: options.retry,
+        meter: content_meter(options),
+        faults: options.faults.clone(),
+        events: options.events.clone(),
+    };
+
+    let new_node = || {
+        aggregates
+            .iter()
+            .map(|column| Accumulator::new(column.function))
+            .collect::<Vec<_>>()
+    };
+    let join = open_join(query, root.as_deref())?;
+    let mut nodes: HashMap<PathBuf, Vec<Accumulator>> = HashMap::new();
+    for top in &tops {
+        nodes.entry(top.clone()).or_insert_with(new_node);
+    }
+    visit_files(&tops, scan, root.as_deref(), &options.limits, |mut file| {
+        let (faults, events) = (&options.faults, &options.events);
+        if !keep(&mut file, join.as_ref(), condition.as_ref(), false, faults, events)? {
+            return Ok(ControlFlow::Continue(()));
+        }
+        let Some(top) = tops.iter().find(|top| file.path.starts_with(top)) else {
+            return Ok(ControlFlow::Continue(()));
+        };
+
+        let values = aggregates
+            .iter()
+            .map(|column| {
+                column
+                    .argument
+                    .as_ref()
+                    .map(|argument| evaluate_expr(&file, argument))
+                    .transpose()
+            })
+            .collect::<Result<Vec<_>>>()?;
+        for directory in file.path.ancestors().skip(1) {
+            if !directory.starts_with(top) {
+                break;
+            }
+            if !nodes.contains_key(directory) {
+                nodes.insert(directory.to_path_buf(), new_node());
+                check_result_count(nodes.len(), &options.limits)?;
+            }
+            let accumulators = nodes.get_mut(directory).expect("node was just inserted");
+            for (accumulator, value) in accumulators.iter_mut().zip(&values) {
+                accumulator
+                    .add(value.as_ref())
+                    .map_err(ExecutorError::TypeError)?;
+            }
+        }
+        Ok(ControlFlow::Continue(()))
+    })?;
+
+    // Hang each directory under its parent, deepest first
+    let mut paths: Vec<PathBuf> = nodes.keys().cloned().collect();
+    paths.sort_by_key(|path| Reverse(path.components().count()));
+    let mut built: HashMap<PathBuf, RollupNode> = HashMap::new();
+    for path in paths {
+        let accumulators = &nodes[&path];
+        let mut children: Vec<RollupNode> = built
+            .extract_if(|child, _| child.parent() == Some(path.as_path()))
+            .map(|(_, node)| node)
+            .collect();
+        children.sort_by(|a, b| a.path.cmp(&b.path));
+        let node = RollupNode {
+            columns: aggregates
+                .iter()
+                .map(|column| column.name.clone())
+                .zip(accumulators.iter().map(Accumulator::finish))
+                .collect(),
+            path: path.clone(),
+            children,
+        };
+        built.insert(path, node);
+    }
+
+    let mut trees = Vec::new();
+    for top in &tops {
+        if let Some(tree) = built.remove(top) {
+            trees.push(tree);
+        }
+    }
+    Ok(trees)
+}
+
+/// Fails for a grouped query, which returns groups rather than files.
+fn reject_grouped(query: &FileQuery) -> Result<()> {
+    if query.is_grouped() {
+        let rows = match query {
+            FileQuery::ShowFilesystem { .. } => "SHOW FILESYSTEM returns a row per path",
+            FileQuery::Explain { .. } => "EXPLAIN returns a row per step",
+            _ => "GROUP BY and aggregates return groups",
+        };
+        return Err(ExecutorError::UnsupportedOperation(format!(
+            "{}, not files; use execute_grouped",
+            rows
+        )));
+    }
+    Ok(())
+}
+
+/// Fails if the options ask for a checkpoint, which only a SELECT that
+/// returns files can keep; `statement` names what was run instead.
+fn reject_checkpoint(options: &ExecutionOptions, statement: &str) -> Result<()> {
+    if options.checkpoint.is_some() {
+        return Err(ExecutorError::UnsupportedOperation(format!(
+            "{} can't be checkpointed; only a SELECT that returns files can",
+            statement
+        )));
+    }
+    Ok(())
+}
+
+/// Fails if a query's shards would each draw at random differently: it
+/// samples or orders at random without a seed.
+fn reject_unseeded(query: &FileQuery) -> Result<()> {
+    let FileQuery::Select {
+        order_by,
+        sample_rows,
+        ..
+    } = query
+    else {
+        return Ok(());
+    };
+    let unseeded = order_by
+        .iter()
+        .any(|order| order.key == SortKey::Random { seed: None })
+        || sample_rows.as_ref().is_some_and(|rows| rows.seed.is_none());
+    if unseeded {
+        return Err(ExecutorError::UnsupportedOperation(
+            "a sharded query needs a seed for RANDOM() and SAMPLE n ROWS, so every shard draws the same way"
+                .to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Executes a SELECT query and returns its first match, if any.
+///
+/// The traversal stops as soon as a file matches, as if the query ended in
+/// `LIMIT 1`, so this is much cheaper than [`execute_query`] on a large tree
+/// when one path is all you need. UPDATE queries are rejected rather than
+/// applied to a single file.
+///
+/// # Examples
+///
+/// ```no_run
+/// use fmql_core::sql::{parse_sql, execute_query_first};
+///
+/// let query = parse_sql("WITH RECURSIVE SELECT * FROM . WHERE name = 'Cargo.toml'").unwrap();
+/// if let Some(file) = execute_query_first(&query).unwrap() {
+///     println!("found {}", file.path.display());
+/// }
+/// ```
+pub fn execute_query_first(query: &FileQuery) -> Result<Option<FileResult>> {
+    let FileQuery::Select { limit, .. } = query else {
+        return Err(ExecutorError::UnsupportedOperation(
+            "only SELECT queries can be run for their first match".to_string(),
+        ));
+    };
+    let mut first = query.clone();
+    if let FileQuery::Select { limit: first_limit, .. } = &mut first {
+        *first_limit = Some(limit.map_or(1, |limit| limit.min(1)));
+    }
+    Ok(execute_query(&first)?.into_iter().next())
+}
+
+/// Returns true if any file matches a SELECT query.
+///
+/// Like [`execute_query_first`], this stops at the first match.
+///
+/// ```no_run
+/// use fmql_core::sql::{parse_sql, exists};
+///
+/// let query = parse_sql("SELECT * FROM /tmp WHERE name LIKE '%.lock'").unwrap();
+/// if exists(&query).unwrap() {
+///     eprintln!("another instance is running");
+/// }
+/// ```
+pub fn exists(query: &FileQuery) -> Result<bool> {
+    Ok(execute_query_first(query)?.is_some())
+}
+
+/// Returns how COPY tries a copy again: as [`ExecutionOptions::retry`] says,
+/// with the deprecated `copy_retries` in place of its retries if that was
+/// changed from its default.
+#[allow(deprecated)]
+fn copy_retry(options: &ExecutionOptions) -> RetryPolicy {
+    match options.copy_retries {
+        retries if retries != RetryPolicy::default().retries => {
+            RetryPolicy { retries, ..options.retry }
+        }
+        _ => options.retry,
+    }
+}
+
+/// Returns true if a SELECT or UPDATE lists its FROM directory itself:
+/// when the query says `INCLUDE SELF`, the options ask for it, or the
+/// query runs with semantics from before it was left out.
+fn lists_self(include_self: bool, options: &ExecutionOptions) -> bool {
+    include_self || options.include_self || options.semantics.lists_from_directory()
+}
+
+/// How the targets of a query are traversed.
+#[derive(Debug, Clone)]
+struct Scan {
+    /// Descend into subdirectories of directory targets.
+    recursive: bool,
+    /// List directory targets themselves, not just their contents.
+    include_self: bool,
+    /// The targets came from `FROM STDIN` or a table: visit each as it is,
+    /// without listing directories, and skip any that no longer exist.
+    listed: bool,
+    /// Keep the listed targets that can't be read, rather than skip them.
+    reconcile: Option<Arc<Reconcile>>,
+    /// Only list entries of this kind.
+    entry_type: Option<EntryType>,
+    /// When the query started.
+    started: Instant,
+    /// When the query started, which each file's `age` is measured from.
+    now: DateTime<Utc>,
+    /// Fail if the scan runs for longer than this after `started`.
+    timeout: Option<Duration>,
+    /// Read compressed files' contents through their decompressors.
+    decompress: Option<u64>,
+    /// The encoding file contents are read in.
+    encoding: TextEncoding,
+    /// The time zone date literals and date functions are in.
+    time_zone: Tz,
+    /// The semantics the query runs with.
+    semantics: Semantics,
+    /// Only look at this fraction of the entries, picked by
+    /// [`sample::includes`].
+    sample: Option<f64>,
+    /// Only walk the entries of directory targets that this shard owns.
+    shard: Option<Shard>,
+    /// Leave out the entries below directory targets that match these.
+    ignore: Ignore,
+    /// The `source_root` and `scan_id` of the files found.
+    source: Arc<ScanSource>,
+    /// How reading metadata and contents is tried again.
+    retry: RetryPolicy,
+    /// What the run's reads of contents have read, when that is limited or
+    /// throttled.
+    meter: Option<Arc<Meter>>,
+    /// The log of files left out because they couldn't be read.
+    faults: Faults,
+    /// Where progress events go.
+    events: Events,
+}
+
+/// The parts of a SELECT that apply to each listed file.
+struct Select<'a> {
+    /// Expressions to compute for each match.
+    computed: &'a [ComputedColumn],
+    /// The joined table, if any.
+    join: Option<&'a Lookup>,
+    /// The WHERE condition, if any.
+    condition: Option<&'a FileCondition>,
+    /// How to sort the matches.
+    order_by: &'a [OrderBy],
+    /// Stop after this many matches.
+    limit: Option<usize>,
+    /// Stop once the matches add up to this many bytes.
+    limit_size: Option<u64>,
+    /// Keep a random few of the matches.
+    sample_rows: Option<&'a RowSample>,
+}
+
+/// Returns the meter a query run's reads of file contents share, if they
+/// are limited or throttled.
+fn content_meter(options: &ExecutionOptions) -> Option<Arc<Meter>> {
+    let (limit, throttle) = (options.limits.max_bytes_read, options.throttle);
+    if limit.is_none() && throttle.is_none() {
+        return None;
+    }
+    Some(Arc::new(Meter::new(limit, throttle.map(Throttle::new))))
+}
+
+impl Scan {
+    /// Returns true if an entry of this type should be listed. The check
+    /// uses the type from the directory listing, so skipped entries cost no
+    /// extra system calls.
+    fn wants(&self, file_type: fs::FileType) -> bool {
+        match self.entry_type {
+            None => true,
+            Some(EntryType::File) => file_type.is_file(),
+            Some(EntryType::Directory) => file_type.is_dir(),
+            Some(EntryType::Symlink) => file_type.is_symlink(),
+        }
+    }
+
+    /// Returns true if the entry at `path` is in the scan's sample. Entries
+    /// left out are still descended into, but their metadata is never read.
+    fn includes(&self, path: &Path) -> bool {
+        self.sample.is_none_or(|fraction| sample::includes(path, fraction))
+    }
+
+    /// Reads a file's metadata, for a query running with this scan. An
+    /// error that may pass is tried again; if it doesn't, the file is left
+    /// out and logged, unless the faults are strict. A listed file that
+    /// can't be read is kept as it is when the scan reconciles.
+    fn open(&self, path: &Path) -> Result<Option<FileResult>> {
+        let read = match (self.retry.run(|| create_file_result(path)), &self.reconcile) {
+            (Err(ExecutorError::IoError(err)), Some(reconcile)) => Ok(FileResult {
+                unread: Some(reconcile.unread(path, &err)),
+                ..bare_file_result(path)
+            }),
+            (read, _) => read,
+        };
+        let mut file = match read {
+            Err(err) if err.is_transient() && !self.faults.is_strict() => {
+                self.warn(path, err.to_string());
+                return Ok(None);
+            }
+            file => file?,
+        };
+        file.decompress = self.decompress;
+        file.encoding = self.encoding;
+        file.time_zone = self.time_zone;
+        file.now = Some(self.now);
+        file.semantics = self.semantics;
+        file.source = Some(Arc::clone(&self.source));
+        file.retry = self.retry;
+        file.meter = self.meter.clone();
+        Ok(Some(file))
+    }
+
+    /// Logs a file left out because it couldn't be read.
+    fn warn(&self, path: &Path, message: String) {
+        self.events.emit(|| Event::Warning {
+            path: path.to_path_buf(),
+            message: message.clone(),
+        });
+        self.faults.record(path, message);
+    }
+
+    /// Fails if the scan has run out of time.
+    fn check_time(&self) -> Result<()> {
+        match self.timeout {
+            Some(timeout) if self.started.elapsed() > timeout => {
+                Err(LimitError::Timeout(timeout).into())
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Executes a SELECT query. With a checkpoint, the walk resumes from it
+/// and it is saved to `options.checkpoint` as the walk goes.
+fn execute_select(
+    targets: &[PathBuf],
+    scan: Scan,
+    attributes: &[FileAttribute],
+    select: Select,
+    options: &ExecutionOptions,
+    root: Option<&Path>,
+    mut checkpoint: Option<Checkpoint>,
+) -> Result<Vec<FileResult>> {
+    let matcher = line_matcher(attributes, select.condition)?;
+    let mut filtered_files = Vec::new();
+    if select.limit == Some(0) || select.limit_size == Some(0) {
+        return Ok(filtered_files);
+    }
+
+    let root = root.map(Path::to_path_buf);
+    let mut walk = Walk::new(targets.to_vec(), scan.clone(), root, options.limits.clone());
+    if let Some(checkpoint) = &checkpoint {
+        filtered_files = reopen(&checkpoint.matches, &scan, select.join)?;
+        walk = walk.resume(checkpoint.position.as_ref());
+    }
+
+    // Filter while walking, so that LIMIT stops the traversal early. With
+    // ORDER BY or SAMPLE n ROWS every match has to be seen before the first
+    // few are known.
+    let early = select.order_by.is_empty() && select.sample_rows.is_none();
+    let stop_at = select.limit.filter(|_| early);
+    let stop_at_size = select.limit_size.filter(|_| early);
+    let mut matched_size = filtered_files.iter().map(|file| file.size).sum::<u64>();
+    let mut saved = Instant::now();
+    let walked = (|| -> Result<()> {
+        while let Some(path) = walk.next() {
+            let matched = match scan.open(&path?)? {
+                Some(mut file) => {
+                    let (faults, events) = (&options.faults, &options.events);
+                    let (join, condition) = (select.join, select.condition);
+                    keep(&mut file, join, condition, options.dry_run, faults, events)?
+                        .then_some(file)
+                }
+                None => None,
+            };
+            if let Some(checkpoint) = &mut checkpoint {
+                if let Some(file) = &matched {
+                    checkpoint.matches.push(file.path.clone());
+                }
+                checkpoint.position = walk.position().cloned();
+            }
+            if let Some(file) = matched {
+                matched_size = matched_size.saturating_add(file.size);
+                filtered_files.push(file);
+                check_result_count(filtered_files.len(), &options.limits)?;
+            }
+            if stop_at.is_some_and(|limit| filtered_files.len() >= limit)
+                || stop_at_size.is_some_and(|size| matched_size >= size)
+            {
+                break;
+            }
+            if let (Some(checkpoint), Some(state)) = (&checkpoint, &options.checkpoint)
+                && saved.elapsed() >= checkpoint::SAVE_INTERVAL
+            {
+                checkpoint.save(state)?;
+                saved = Instant::now();
+            }
+        }
+        Ok(())
+    })();
+    if let (Some(checkpoint), Some(state)) = (&checkpoint, &options.checkpoint) {
+        match walked {
+            // Keep what was done for the next run; the error that stopped
+            // the scan matters more than one saving it
+            Err(err) => {
+                let _ = checkpoint.save(state);
+                return Err(err);
+            }
+            Ok(()) => match fs::remove_file(state) {
+                Err(err) if err.kind() != std::io::ErrorKind::NotFound => return Err(err.into()),
+                _ => {}
+            },
+        }
+    }
+    walked?;
+
+    if let Some(rows) = select.sample_rows {
+        filtered_files = pick_rows(filtered_files, rows)?;
+    }
+    if !early {
+        sort_results(&mut filtered_files, select.order_by)?;
+        if let Some(limit) = select.limit {
+            filtered_files.truncate(limit);
+        }
+        if let Some(size) = select.limit_size {
+            filtered_files.truncate(within_size(&filtered_files, size));
+        }
+    }
+
+    for file in &mut filtered_files {
+        add_columns(file, select.computed, attributes, matcher.as_ref())?;
+    }
+
+    Ok(filtered_files)
+}
+
+/// Keeps the matches a `SAMPLE n ROWS` picks, in the order they were found.
+fn pick_rows(files: Vec<FileResult>, rows: &RowSample) -> Result<Vec<FileResult>> {
+    let seed = rows.seed.unwrap_or_else(sample::random_seed);
+    let items = files
+        .iter()
+        .map(|file| {
+            let weight = match &rows.weight {
+                None => 1.0,
+                Some(attribute) => match get_attribute_value(file, attribute)? {
+                    FileValue::Null => 0.0,
+                    value => value.as_f64().ok_or_else(|| {
+                        ExecutorError::TypeError(format!(
+                            "WEIGHTED BY {} needs a number, not {:?}",
+                            attribute.name(),
+                            value
+                        ))
+                    })?,
+                },
+            };
+            Ok((sample::draw(&file.path, seed), weight))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let mut files: Vec<Option<FileResult>> = files.into_iter().map(Some).collect();
+    Ok(sample::pick(&items, rows.rows)
+        .into_iter()
+        .filter_map(|index| files[index].take())
+        .collect())
+}
+
+/// Returns how many of `files`, in order, `LIMIT size BY size` keeps: each
+/// one until their sizes add up to `size`, the one that gets there included.
+fn within_size(files: &[FileResult], size: u64) -> usize {
+    let mut total: u64 = 0;
+    files
+        .iter()
+        .take_while(|file| {
+            let under = total < size;
+            total = total.saturating_add(file.size);
+            under
+        })
+        .count()
+}
+
+/// Reads the metadata of files that matched before, for a scan resumed from
+/// a checkpoint or the merge of a sharded one. Their rows of the joined
+/// table are looked up again, but the WHERE clause isn't checked; files that
+/// have gone since are left out.
+fn reopen(paths: &[PathBuf], scan: &Scan, join: Option<&Lookup>) -> Result<Vec<FileResult>> {
+    let mut files = Vec::new();
+    for path in paths {
+        let mut file = match scan.open(path) {
+            Err(ExecutorError::IoError(err)) if err.kind() == std::io::ErrorKind::NotFound => {
+                continue;
+            }
+            Ok(None) => continue,
+            file => file?.expect("Ok(None) is handled above"),
+        };
+        if keep(&mut file, join, None, false, &Faults::default(), &scan.events)? {
+            files.push(file);
+        }
+    }
+    Ok(files)
+}
+
+/// A query's JOIN, with its table read and ready for lookups.
+struct Lookup {
+    table: JoinTable,
+    /// The file's side of the ON condition.
+    key: FileExpr,
+    /// Keep files without a row (`LEFT JOIN`).
+    left: bool,
+}
+
+impl Lookup {
+    /// Puts the file's row of the table in `file.joined`. Returns false if
+    /// there is no row and the file should be left out.
+    fn attach(&self, file: &mut FileResult) -> Result<bool> {
+        let row = match evaluate_expr(file, &self.key)? {
+            FileValue::Null => None,
+            key => self.table.row(&to_text(&key)),
+        };
+        match row {
+            Some(row) => file.joined = row,
+            None if self.left => file.joined = self.table.empty_row(),
+            None => return Ok(false),
+        }
+        Ok(true)
+    }
+}
+
+/// Reads the table a query joins, if it has a JOIN. With a sandbox root,
+/// the table has to be inside it like everything else the query reads.
+fn open_join(query: &FileQuery, root: Option<&Path>) -> Result<Option<Lookup>> {
+    let FileQuery::Select {
+        join: Some(join), ..
+    } = query
+    else {
+        return Ok(None);
+    };
+    if join.source.exists() && !is_within_root(&join.source, root) {
+        return Err(ExecutorError::Denied(format!(
+            "{} is outside the sandbox root",
+            join.source.display()
+        )));
+    }
+    let table = JoinTable::load(&join.source, &join.column)?;
+    table.check_columns(query)?;
+    Ok(Some(Lookup {
+        table,
+        key: join.key.clone(),
+        left: join.left,
+    }))
+}
+
+/// Decides whether a file is a match: it needs a row in the joined table,
+/// unless the join is a LEFT JOIN, and has to satisfy the WHERE condition.
+/// A condition that fails to evaluate for a file doesn't match it; see
+/// [`holds`]. With `explain`, a match records why it matched; see
+/// [`satisfies`].
+fn keep(
+    file: &mut FileResult,
+    join: Option<&Lookup>,
+    condition: Option<&FileCondition>,
+    explain: bool,
+    faults: &Faults,
+    events: &Events,
+) -> Result<bool> {
+    if let Some(join) = join
+        && !join.attach(file)?
+    {
+        return Ok(false);
+    }
+    let kept = match condition {
+        Some(condition) => satisfies(file, condition, explain, faults, events)?,
+        None => true,
+    };
+    if kept {
+        events.emit(|| Event::EntryMatched { path: file.path.clone() });
+    }
+    Ok(kept)
+}
+
+/// Evaluates a condition for a file. If that fails, the file is logged in
+/// `faults` and doesn't match, unless they are strict. An invalid regular
+/// expression fails for every file, so it fails the query.
+fn holds(
+    file: &FileResult,
+    condition: &FileCondition,
+    faults: &Faults,
+    events: &Events,
+) -> Result<bool> {
+    tolerate(file, evaluate_condition(file, condition), faults, events)
+}
+
+/// Evaluates a condition for a file as [`holds`] does. With `explain`, a
+/// file that satisfies it records the parts it satisfied in
+/// [`FileResult::matched`], as [`matched_by`] finds them.
+fn satisfies(
+    file: &mut FileResult,
+    condition: &FileCondition,
+    explain: bool,
+    faults: &Faults,
+    events: &Events,
+) -> Result<bool> {
+    if !explain {
+        return holds(file, condition, faults, events);
+    }
+    let matched = tolerate(file, matched_by(file, condition), faults, events)?;
+    file.matched = matched.as_ref().map(ToString::to_string);
+    Ok(matched.is_some())
+}
+
+/// Settles the outcome of evaluating a condition for a file: an error is
+/// logged in `faults` and the file doesn't match, as for [`holds`].
+fn tolerate<T: Default>(
+    file: &FileResult,
+    outcome: Result<T>,
+    faults: &Faults,
+    events: &Events,
+) -> Result<T> {
+    match outcome {
+        Ok(outcome) => Ok(outcome),
+        Err(err @ (ExecutorError::InvalidRegex(_) | ExecutorError::LimitExceeded(_))) => Err(err),
+        Err(err) if faults.is_strict() => {
+            Err(ExecutorError::EvaluationFailed(file.path.clone(), err.to_string()))
+        }
+        Err(err) => {
+            let message = err.to_string();
+            events.emit(|| Event::Warning {
+                path: file.path.clone(),
+                message: message.clone(),
+            });
+            faults.record(&file.path, message);
+            Ok(T::default())
+        }
+    }
+}
+
+/// Keeps the files that satisfy a condition, as [`satisfies`] decides.
+fn filter_files(
+    files: Vec<FileResult>,
+    condition: Option<&FileCondition>,
+    explain: bool,
+    faults: &Faults,
+    events: &Events,
+) -> Result<Vec<FileResult>> {
+    let mut kept = Vec::new();
+    for mut file in files {
+        let satisfied = match condition {
+            Some(condition) => satisfies(&mut file, condition, explain, faults, events)?,
+            None => true,
+        };
+        if satisfied {
+            events.emit(|| Event::EntryMatched { path: file.path.clone() });
+            kept.push(file);
+        }
+    }
+    Ok(kept)
+}
+
+/// Returns a column of the file's joined row; NULL if it has none.
+fn joined_value(file: &FileResult, column: &str) -> FileValue {
+    file.joined.get(column).cloned().unwrap_or(FileValue::Null)
+}
+
+/// Fills in the columns a SELECT asks for beyond the file's metadata: its
+/// computed columns, its optional attributes, and the start of its contents
+/// or its matching lines if the query selects `preview` or `matches`. An
+/// unreadable file has no preview or matches rather than failing the query.
+fn add_columns(
+    file: &mut FileResult,
+    computed: &[ComputedColumn],
+    attributes: &[FileAttribute],
+    matcher: Option<&LineMatcher>,
+) -> Result<()> {
+    for column in computed {
+        let value = evaluate_expr(file, &column.expr)?;
+        file.computed.insert(column.name.clone(), value);
+    }
+    for attribute in optional_attributes(attributes) {
+        let value = get_attribute_value(file, &attribute)?;
+        file.selected.0.push((attribute, value));
+    }
+    if attributes.contains(&FileAttribute::Preview) {
+        let read = || Ok(read_preview(&file.path, file.encoding).ok().flatten());
+        file.preview = metered(file, read)?;
+    }
+    if let Some(matcher) = matcher {
+        let read = || Ok(matcher.read_matches(&file.path, file.decompress, file.encoding));
+        file.matches = metered(file, read)?.unwrap_or_default();
+    }
+    Ok(())
+}
+
+/// Builds the matcher for a SELECT of `matches`, which needs a content
+/// predicate in the WHERE clause to know which lines to return.
+fn line_matcher(
+    attributes: &[FileAttribute],
+    condition: Option<&FileCondition>,
+) -> Result<Option<LineMatcher>> {
+    if !attributes.contains(&FileAttribute::Matches) {
+        return Ok(None);
+    }
+    match condition.map(LineMatcher::from_condition).transpose()?.flatten() {
+        Some(matcher) => Ok(Some(matcher)),
+        None => Err(ExecutorError::UnsupportedOperation(
+            "selecting matches needs a WHERE clause that searches content, such as content LIKE '%TODO%'"
+                .to_string(),
+        )),
+    }
+}
+
+/// Sorts files by the keys of an ORDER BY clause. Files that tie on every
+/// key keep the order they were found in.
+/// Returns the order a SELECT's results are sorted in: its ORDER BY, or by
+/// path if it has none and the options ask for a stable order.
+fn result_order<'a>(order_by: &'a [OrderBy], options: &ExecutionOptions) -> Cow<'a, [OrderBy]> {
+    if !order_by.is_empty() || !options.stable_order {
+        return Cow::Borrowed(order_by);
+    }
+    Cow::Owned(vec![OrderBy {
+        key: SortKey::Attribute(FileAttribute::Path),
+        descending: false,
+        natural: false,
+        collation: None,
+        nulls_first: None,
+    }])
+}
+
+/// Sorts the results of an UPDATE or COPY by path, if the options ask for a
+/// stable order.
+fn in_result_order(
+    results: Result<Vec<FileResult>>,
+    options: &ExecutionOptions,
+) -> Result<Vec<FileResult>> {
+    let mut results = results?;
+    if options.stable_order {
+        results.sort_by(|a, b| a.path.cmp(&b.path));
+    }
+    Ok(results)
+}
+
+fn sort_results(files: &mut Vec<FileResult>, order_by: &[OrderBy]) -> Result<()> {
+    // RANDOM() without a seed shuffles differently each time
+    let seeds: Vec<u64> = order_by
+        .iter()
+        .map(|order| match order.key {
+            SortKey::Random { seed } => seed.unwrap_or_else(sample::random_seed),
+            _ => 0,
+        })
+        .collect();
+    let mut keyed = files
+        .drain(..)
+        .map(|file| {
+            let keys = order_by
+                .iter()
+                .zip(&seeds)
+                .map(|(order, seed)| match &order.key {
+                    SortKey::Attribute(attribute) => get_attribute_value(&file, attribute),
+                    SortKey::Depth => {
+                        Ok(FileValue::from_u64(file.path.components().count() as u64))
+                    }
+                    SortKey::Joined { column, .. } => Ok(joined_value(&file, column)),
+                    SortKey::Random { .. } => {
+                        Ok(FileValue::Number(sample::draw(&file.path, *seed)))
+                    }
+                })
+                .collect::<Result<Vec<_>>>()?;
+            Ok((keys, file))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    sort_keyed(&mut keyed, order_by)?;
+    files.extend(keyed.into_iter().map(|(_, file)| file));
+    Ok(())
+}
+
+/// Sorts items by precomputed ORDER BY keys, one value per key. The sort is
+/// stable.
+fn sort_keyed<T>(keyed: &mut [(Vec<FileValue>, T)], order_by: &[OrderBy]) -> Result<()> {
+    let collations = order_by
+        .iter()
+        .map(|order| {
+            order
+                .collation
+                .as_deref()
+                .map(|locale| sort::Collation::new(locale, order.natural))
+                .transpose()
+                .map_err(ExecutorError::UnsupportedOperation)
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    keyed.sort_by(|(a, _), (b, _)| {
+        order_by
+            .iter()
+            .zip(&collations)
+            .zip(a.iter().zip(b))
+            .map(|((order, collation), (a, b))| {
+                // NULLS FIRST and LAST place NULLs before the direction applies
+                let a_null = matches!(a, FileValue::Null);
+                if let Some(nulls_first) = order.nulls_first
+                    && a_null != matches!(b, FileValue::Null)
+                {
+                    return if a_null == nulls_first {
+                        std::cmp::Ordering::Less
+                    } else {
+                        std::cmp::Ordering::Greater
+                    };
+                }
+                let ordering = match collation {
+                    Some(collation) => collation.compare_values(a, b),
+                    None => sort::compare_values(a, b, order.natural),
+                };
+                if order.descending { ordering.reverse() } else { ordering }
+            })
+            .find(|ordering| ordering.is_ne())
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+    Ok(())
+}
+
+/// Executes an UPDATE query.
+fn execute_update(
+    targets: &[PathBuf],
+    scan: Scan,
+    updates: &[crate::sql::ast::FileAttributeUpdate],
+    condition: Option<&FileCondition>,
+    on_conflict: ConflictPolicy,
+    options: &ExecutionOptions,
+    root: Option<&Path>,
+) -> Result<Vec<FileResult>> {
+    let lock = lock_targets(targets, options)?;
+    let files = list_files(targets, scan, root, &options.limits)?;
+    let (faults, events) = (&options.faults, &options.events);
+    let filtered_files = filter_files(files, condition, options.dry_run, faults, events)?;
+    // Checked before touching anything, so a runaway UPDATE changes nothing
+    check_result_count(filtered_files.len(), &options.limits)?;
+
+    for update in updates {
+        match update.attribute {
+            FileAttribute::Flags if !flags::supported() => {
+                return Err(ExecutorError::UnsupportedOperation(
+                    "file flags can only be set on macOS and the BSDs".to_string(),
+                ));
+            }
+            FileAttribute::FsFlags if !chattr::supported() => {
+                return Err(ExecutorError::UnsupportedOperation(
+                    "file attributes can only be set on Linux".to_string(),
+                ));
+            }
+            FileAttribute::Permissions
+            | FileAttribute::Name
+            | FileAttribute::Flags
+            | FileAttribute::FsFlags => {}
+            FileAttribute::Owner => {
+                // Note: Changing ownership requires platform-specific code and often root privileges
+                // This is a simplified example
+                return Err(ExecutorError::UnsupportedOperation(
+                    "Changing file ownership is not implemented".to_string(),
+                ));
+            }
+            _ => {
+                return Err(ExecutorError::UnsupportedAttribute(format!(
+                    "Cannot update attribute: {:?}",
+                    update.attribute
+                )));
+            }
+        }
+    }
+
+    let renames = updates
+        .iter()
+        .find(|update| update.attribute == FileAttribute::Name);
+    let mut batches = update_batches(&filtered_files, renames.is_some());
+    let staged = match renames {
+        Some(rename) => plan_batches(&mut batches, &filtered_files, rename, on_conflict)?,
+        None => vec![false; filtered_files.len()],
+    };
+    // A dry run leaves every file where it is, so the names the renames
+    // give up still look taken
+    let vacated: Option<HashSet<PathBuf>> = options.dry_run.then(|| {
+        let Some(rename) = renames else {
+            return HashSet::new();
+        };
+        filtered_files
+            .iter()
+            .filter(|file| {
+                evaluate_expr(file, &rename.value)
+                    .ok()
+                    .filter(|value| !matches!(value, FileValue::Null))
+                    .and_then(|value| rename_target(&file.path, &value).ok())
+                    .is_some_and(|target| target != file.path)
+            })
+            .map(|file| file.path.clone())
+            .collect()
+    });
+    let mut results: Vec<Option<FileResult>> = vec![None; filtered_files.len()];
+
+    for batch in batches {
+        let updated = parallel_map(options.jobs, &batch, |group| {
+            let dry_run = vacated.as_ref();
+            let paths = group.iter().map(|&index| filtered_files[index].path.as_path());
+            let updated = match hold(lock.as_ref(), paths) {
+                Ok(_held) => {
+                    update_group(&filtered_files, group, &staged, updates, on_conflict, root, dry_run)
+                }
+                Err(err) => {
+                    let failed = |&index| (index, lock_failed(&filtered_files[index], &err));
+                    group.iter().map(failed).collect()
+                }
+            };
+            let applied = |(index, file)| (index, applied(&options.events, file));
+            updated.into_iter().map(applied).collect::<Vec<_>>()
+        });
+        for (index, result) in updated.into_iter().flatten() {
+            results[index] = Some(result);
+        }
+    }
+
+    Ok(results.into_iter().flatten().collect())
+}
+
+/// Takes the lock on the targets of a statement that changes files, unless
+/// it is a dry run: see [`locks`](crate::sql::locks).
+fn lock_targets(targets: &[PathBuf], options: &ExecutionOptions) -> Result<Option<RootLock>> {
+    if options.dry_run {
+        return Ok(None);
+    }
+    options.locking.acquire(targets, &options.events)
+}
+
+/// Takes the locks on the files at `paths` while they are changed, when
+/// the run's lock takes per-file locks.
+fn hold<'a>(
+    lock: Option<&RootLock>,
+    paths: impl IntoIterator<Item = &'a Path>,
+) -> std::io::Result<Option<FileLocks>> {
+    lock.map(|lock| lock.hold(paths)).transpose()
+}
+
+/// Fails a file whose lock couldn't be taken, which is left as it was.
+fn lock_failed(file: &FileResult, err: &std::io::Error) -> FileResult {
+    let mut failed = file.clone();
+    failed.status = Some(OperationStatus::Failed);
+    failed.error_message = Some(format!("Cannot lock {}: {}", file.path.display(), err));
+    failed
+}
+
+/// Sends the event for a file a statement has dealt with, and returns it.
+fn applied(events: &Events, file: FileResult) -> FileResult {
+    if let Some(status) = file.status {
+        events.emit(|| Event::OperationApplied {
+            path: file.path.clone(),
+            status,
+            error: file.error_message.clone(),
+        });
+    }
+    file
+}
+
+/// Splits the files of an UPDATE into batches that are run one after another.
+///
+/// Each batch is a list of groups of file indices. Groups within a batch may
+/// run concurrently; the files within a group run in order. Renames are
+/// batched by depth, deepest first, so that children are renamed before
+/// their parent directory moves, and grouped by directory so that two files
+/// never race for the same new name.
+fn update_batches(files: &[FileResult], renames: bool) -> Vec<Vec<Vec<usize>>> {
+    if !renames {
+        return vec![(0..files.len()).map(|index| vec![index]).collect()];
+    }
+
+    let mut by_depth: BTreeMap<Reverse<usize>, BTreeMap<&Path, Vec<usize>>> = BTreeMap::new();
+    for (index, file) in files.iter().enumerate() {
+        let depth = file.path.components().count();
+        let parent = file.path.parent().unwrap_or(Path::new(""));
+        by_depth
+            .entry(Reverse(depth))
+            .or_default()
+            .entry(parent)
+            .or_default()
+            .push(index);
+    }
+
+    by_depth
+        .into_values()
+        .map(|groups| groups.into_values().collect())
+        .collect()
+}
+
+/// Orders the renames of each group of an UPDATE with [`plan_renames`], and
+/// returns which files have to be moved aside to break a cycle.
+///
+/// Fails before anything is touched if two files would be given the same
+/// name and the conflict policy can't settle it: with FAIL one of them would
+/// be left behind half way through, and with OVERWRITE one would be lost.
+/// Files whose new name can't be worked out yet are left to fail later.
+fn plan_batches(
+    batches: &mut [Vec<Vec<usize>>],
+    files: &[FileResult],
+    rename: &crate::sql::ast::FileAttributeUpdate,
+    on_conflict: ConflictPolicy,
+) -> Result<Vec<bool>> {
+    let mut staged = vec![false; files.len()];
+    let mut collisions = Vec::new();
+    for group in batches.iter_mut().flatten() {
+        let (moving, staying): (Vec<_>, Vec<_>) = group
+            .iter()
+            .map(|&index| {
+                let file = &files[index];
+                let target = evaluate_expr(file, &rename.value)
+                    .ok()
+                    .filter(|value| !matches!(value, FileValue::Null))
+                    .and_then(|value| rename_target(&file.path, &value).ok());
+                (index, target)
+            })
+            .partition(|(_, target)| target.is_some());
+        let moves: Vec<(PathBuf, PathBuf)> = moving
+            .iter()
+            .map(|(index, target)| (files[*index].path.clone(), target.clone().unwrap_or_default()))
+            .collect();
+        let plan = plan_renames(&moves);
+        for &i in &plan.staged {
+            staged[moving[i].0] = true;
+        }
+        collisions.extend(plan.collisions);
+        *group = staying
+            .iter()
+            .map(|(index, _)| *index)
+            .chain(plan.order.iter().map(|&i| moving[i].0))
+            .collect();
+    }
+
+    let settled = matches!(on_conflict, ConflictPolicy::Skip | ConflictPolicy::Rename);
+    if !collisions.is_empty() && !settled {
+        let collisions: Vec<String> = collisions.iter().map(ToString::to_string).collect();
+        return Err(ExecutorError::RenameConflict(collisions.join("; ")));
+    }
+    Ok(staged)
+}
+
+/// Applies an UPDATE to one group of files, in order.
+///
+/// The files marked as staged are first moved to a temporary name next to
+/// them; if any of them can't be, the group is left as it was and all of
+/// its files fail. In a dry run, `dry_run` holds the paths the UPDATE's
+/// renames move files away from, and nothing is moved.
+fn update_group(
+    files: &[FileResult],
+    group: &[usize],
+    staged: &[bool],
+    updates: &[crate::sql::ast::FileAttributeUpdate],
+    on_conflict: ConflictPolicy,
+    root: Option<&Path>,
+    dry_run: Option<&HashSet<PathBuf>>,
+) -> Vec<(usize, FileResult)> {
+    let mut temporaries: HashMap<usize, PathBuf> = HashMap::new();
+    for &index in group.iter().filter(|&&index| staged[index] && dry_run.is_none()) {
+        let path = &files[index].path;
+        let temporary = aside(path);
+        if let Err(err) = fs::rename(path, &temporary) {
+            for (&index, temporary) in &temporaries {
+                let _ = fs::rename(temporary, &files[index].path);
+            }
+            let message = format!("Cannot move {} out of the way: {}", path.display(), err);
+            return group
+                .iter()
+                .map(|&index| {
+                    let mut file = files[index].clone();
+                    file.status = Some(OperationStatus::Failed);
+                    file.error_message = Some(message.clone());
+                    (index, file)
+                })
+                .collect();
+        }
+        temporaries.insert(index, temporary);
+    }
+
+    group
+        .iter()
+        .map(|&index| {
+            let file = &files[index];
+            let start = temporaries.get(&index).unwrap_or(&file.path);
+            (index, update_file(file, start, updates, on_conflict, root, dry_run))
+        })
+        .collect()
+}
+
+/// Applies `f` to every item using up to `jobs` threads, keeping the order.
+fn parallel_map<T, R, F>(jobs: usize, items: &[T], f: F) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T) -> R + Sync,
+{
+    if jobs <= 1 || items.len() <= 1 {
+        return items.iter().map(f).collect();
+    }
+
+    let next = AtomicUsize::new(0);
+    let results = Mutex::new(Vec::with_capacity(items.len()));
+    thread::scope(|scope| {
+        for _ in 0..jobs.min(items.len()) {
+            scope.spawn(|| {
+                loop {
+                    let index = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(item) = items.get(index) else {
+                        break;
+                    };
+                    let result = f(item);
+                    results.lock().expect("worker panicked").push((index, result));
+                }
+            });
+        }
+    });
+
+    let mut results = results.into_inner().expect("worker panicked");
+    results.sort_by_key(|(index, _)| *index);
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+/// Applies an UPDATE to one file, which is now at `start`, and reports what
+/// happened.
+fn update_file(
+    file: &FileResult,
+    start: &Path,
+    updates: &[crate::sql::ast::FileAttributeUpdate],
+    on_conflict: ConflictPolicy,
+    root: Option<&Path>,
+    dry_run: Option<&HashSet<PathBuf>>,
+) -> FileResult {
+    let mut applied = AppliedUpdates {
+        path: start.to_path_buf(),
+        changes: Vec::new(),
+        conflict: None,
+    };
+    let outcome = apply_updates(file, updates, on_conflict, root, dry_run, &mut applied);
+    // A file moved aside that didn't get its new name goes back to its old
+    // one, if nothing has taken it
+    if applied.path != file.path
+        && applied.path == start
+        && file.path.symlink_metadata().is_err()
+        && fs::rename(start, &file.path).is_ok()
+    {
+        applied.path = file.path.clone();
+    }
+
+    // Re-read the file info to get updated attributes
+    let mut updated_file = match dry_run {
+        // Nothing changed on disk, so only the name is new
+        Some(_) => {
+            let mut planned = file.clone();
+            if let Some(name) = applied.path.file_name() {
+                planned.name = name.to_string_lossy().to_string();
+            }
+            planned.path = applied.path.clone();
+            planned
+        }
+        None => create_file_result(&applied.path).unwrap_or_else(|_| file.clone()),
+    };
+    updated_file.status = Some(match &outcome {
+        Err(_) => OperationStatus::Failed,
+        Ok(()) if applied.changes.is_empty() => OperationStatus::Skipped,
+        Ok(()) => OperationStatus::Ok,
+    });
+    updated_file.error_message = outcome.err().map(|err| err.to_string());
+    updated_file.changes = applied.changes;
+    updated_file.conflict = applied.conflict;
+    updated_file
+}
+
+/// What has been done to a file so far by [`apply_updates`].
+struct AppliedUpdates {
+    /// The file's current path, which changes when it is renamed.
+    path: PathBuf,
+    /// The attribute changes made.
+    changes: Vec<FileChange>,
+    /// How a rename collision was resolved, if there was one.
+    conflict: Option<ConflictOutcome>,
+}
+
+/// Applies the SET clauses of an UPDATE to one file.
+///
+/// Progress is recorded in `applied` as it happens, so that a failure part
+/// way through still reports the changes that were made. In a dry run
+/// (`dry_run` holds the paths the UPDATE's renames give up) each change is
+/// checked and recorded but not made.
+fn apply_updates(
+    file: &FileResult,
+    updates: &[crate::sql::ast::FileAttributeUpdate],
+    on_conflict: ConflictPolicy,
+    root: Option<&Path>,
+    dry_run: Option<&HashSet<PathBuf>>,
+    applied: &mut AppliedUpdates,
+) -> Result<()> {
+    for update in updates {
+        // Re-checked for every update, since a rename moves the file
+        if !is_within_root(&applied.path, root) {
+            return Err(ExecutorError::Denied(format!(
+                "{} resolves outside the sandbox root",
+                applied.path.display()
+            )));
+        }
+
+        // Every expression sees the file as it was before the update
+        let value = evaluate_expr(file, &update.value)?;
+        if matches!(value, FileValue::Null) {
+            // e.g. a CASE without ELSE that matched no branch: leave the file alone
+            continue;
+        }
+
+        let old_value = get_attribute_value(file, &update.attribute)?;
+        let new_value = match update.attribute {
+            FileAttribute::Permissions => {
+                let perms = permissions_from_value(&value)?;
+
+                match dry_run {
+                    Some(_) => check_owner(&applied.path, "change the permissions of")?,
+                    None => fs::set_permissions(&applied.path, Permissions::from_mode(perms))?,
+                }
+                FileValue::Integer(perms.into())
+            }
+            FileAttribute::Flags => {
+                let FileValue::String(spec) = &value else {
+                    return Err(ExecutorError::TypeError(format!(
+                        "File flags must be a string such as 'uchg', not {:?}",
+                        value
+                    )));
+                };
+                let change = FlagChange::parse(spec).map_err(ExecutorError::TypeError)?;
+                let bits = change.apply(flags::read_flags(&applied.path)?.unwrap_or(0));
+                match dry_run {
+                    Some(_) => check_owner(&applied.path, "change the flags of")?,
+                    None => flags::write_flags(&applied.path, bits)?,
+                }
+                FileValue::String(flags::format_flags(bits))
+            }
+            FileAttribute::FsFlags => {
+                let FileValue::String(spec) = &value else {
+                    return Err(ExecutorError::TypeError(format!(
+                        "File attributes must be a string such as '+immutable', not {:?}",
+                        value
+                    )));
+                };
+                let change = chattr::parse_change(spec).map_err(ExecutorError::TypeError)?;
+                let old = chattr::read_fs_flags(&applied.path)?.unwrap_or(0);
+                let bits = change.apply(old);
+                match dry_run {
+                    Some(_) => {
+                        check_owner(&applied.path, "change the attributes of")?;
+                        chattr::check_protected(&applied.path, old, bits)?;
+                    }
+                    None => chattr::write_fs_flags(&applied.path, bits)?,
+                }
+                FileValue::String(chattr::format_fs_flags(bits))
+            }
+            FileAttribute::Name => {
+                let mut target = rename_target(&applied.path, &value)?;
+                if target == applied.path {
+                    continue;
+                }
+
+                let case_only = differs_only_in_case(&applied.path, &target);
+                let given_up = dry_run.is_some_and(|vacated| vacated.contains(&target));
+                if !case_only && !given_up && target.symlink_metadata().is_ok() {
+                    match on_conflict {
+                        ConflictPolicy::Fail => {
+                            return Err(ExecutorError::IoError(std::io::Error::new(
+                                std::io::ErrorKind::AlreadyExists,
+                                format!(
+                                    "Cannot rename {}: {} already exists",
+                                    applied.path.display(),
+                                    target.display()
+                                ),
+                            )));
+                        }
+                        ConflictPolicy::Skip => {
+                            applied.conflict = Some(ConflictOutcome::Skipped);
+                            continue;
+                        }
+                        ConflictPolicy::Overwrite => {
+                            applied.conflict = Some(ConflictOutcome::Overwritten);
+                        }
+                        ConflictPolicy::Rename => {
+                            target = free_name(&target);
+                            applied.conflict = Some(ConflictOutcome::Renamed);
+                        }
+                    }
+                }
+
+                if dry_run.is_some() {
+                    check_writable(target.parent().unwrap_or(Path::new(".")))?;
+                } else if case_only {
+                    // A file system that ignores case would see nothing to do,
+                    // so the file goes via a name of its own first
+                    let temporary = aside(&applied.path);
+                    fs::rename(&applied.path, &temporary)?;
+                    if let Err(err) = fs::rename(&temporary, &target) {
+                        let _ = fs::rename(&temporary, &applied.path);
+                        return Err(err.into());
+                    }
+                } else {
+                    fs::rename(&applied.path, &target)?;
+                }
+                let name = target
+                    .file_name()
+                    .map(|name| name.to_string_lossy().to_string())
+                    .unwrap_or_default();
+                applied.path = target;
+                FileValue::String(name)
+            }
+            _ => unreachable!("attributes are checked before any file is touched"),
+        };
+
+        applied.changes.push(FileChange {
+            attribute: update.attribute.clone(),
+            old_value,
+            new_value,
+        });
+    }
+
+    Ok(())
+}
+
+/// Where and how a COPY makes its copies.
+struct CopyTo<'a> {
+    /// The directory the files keep their paths below: the FROM path, up to
+    /// any wildcard.
+    base: PathBuf,
+    /// The directory the copies are made in.
+    destination: &'a Path,
+    method: CopyMethod,
+    options: CopyOptions<'a>,
+    /// Read each copy back and check it against its original.
+    verify: bool,
+    on_conflict: ConflictPolicy,
+}
+
+/// Executes a COPY query. Only regular files are copied; the directories
+/// they are in are created as needed.
+fn execute_copy(
+    targets: &[PathBuf],
+    scan: Scan,
+    condition: Option<&FileCondition>,
+    copy: CopyTo,
+    options: &ExecutionOptions,
+    root: Option<&Path>,
+) -> Result<Vec<FileResult>> {
+    if !lands_within_root(copy.destination, root) {
+        return Err(ExecutorError::Denied(format!(
+            "{} is outside the sandbox root",
+            copy.destination.display()
+        )));
+    }
+    let lock = lock_targets(targets, options)?;
+    let files: Vec<FileResult> = list_files(targets, scan, root, &options.limits)?
+        .into_iter()
+        .filter(|file| !file.is_directory && !file.is_symlink)
+        .collect();
+    let files = filter_files(files, condition, options.dry_run, &options.faults, &options.events)?;
+    // Checked before copying anything, like an UPDATE
+    check_result_count(files.len(), &options.limits)?;
+    if !options.dry_run {
+        let mut needs = SpaceNeeds::default();
+        for file in &files {
+            let target = copy_target(file, &copy);
+            // A target in the way that is to be kept takes no more space
+            if copy.on_conflict == ConflictPolicy::Skip && target.symlink_metadata().is_ok() {
+                continue;
+            }
+            needs.add(&target, copy_needs(&file.path, &target, copy.method)?)?;
+        }
+        check_space(&needs)?;
+    }
+
+    // A dry run makes no copies, so it keeps track of the targets it has
+    // handed out itself
+    let claimed = options.dry_run.then(|| Mutex::new(HashSet::new()));
+    Ok(parallel_map(options.jobs, &files, |file| {
+        let copied = match hold(lock.as_ref(), [file.path.as_path()]) {
+            Ok(_held) => copy_one(file, &copy, root, claimed.as_ref()),
+            Err(err) => lock_failed(file, &err),
+        };
+        applied(&options.events, copied)
+    }))
+}
+
+/// Copies one file for a COPY and reports what happened: the copy, or the
+/// original if it was skipped or failed. In a dry run, `dry_run` holds the
+/// targets given out so far, and nothing is copied.
+fn copy_one(
+    file: &FileResult,
+    copy: &CopyTo,
+    root: Option<&Path>,
+    dry_run: Option<&Mutex<HashSet<PathBuf>>>,
+) -> FileResult {
+    let mut target = copy_target(file, copy);
+    let mut made = MadeCopy::default();
+    let outcome = make_copy(&file.path, &mut target, copy, root, dry_run, &mut made);
+
+    let mut result = match &outcome {
+        Ok(Some(_)) if dry_run.is_none() => {
+            create_file_result(&target).unwrap_or_else(|_| file.clone())
+        }
+        _ => file.clone(),
+    };
+    result.status = Some(match &outcome {
+        Err(_) => OperationStatus::Failed,
+        Ok(None) => OperationStatus::Skipped,
+        Ok(Some(_)) => OperationStatus::Ok,
+    });
+    result.conflict = made.conflict;
+    match outcome {
+        Ok(Some(method)) => {
+            result.copied = Some(method);
+            result.checksum = made.checksum;
+            result.transfer = made.transfer;
+            result.changes.push(FileChange {
+                attribute: FileAttribute::Path,
+                old_value: FileValue::String(file.path.to_string_lossy().to_string()),
+                new_value: FileValue::String(target.to_string_lossy().to_string()),
+            });
+        }
+        Ok(None) => {}
+        Err(err) => result.error_message = Some(err.to_string()),
+    }
+    result
+}
+
+/// What became of one file of a COPY, besides how it was copied.
+#[derive(Debug, Default)]
+struct MadeCopy {
+    /// How a target that was in the way was dealt with.
+    conflict: Option<ConflictOutcome>,
+    /// The checksum VERIFY CHECKSUM found the copy to share with its original.
+    checksum: Option<String>,
+    /// What making the copy took.
+    transfer: Option<Transfer>,
+}
+
+/// Returns where a COPY puts its copy of `file`: at the file's path below
+/// the FROM path, in the destination.
+fn copy_target(file: &FileResult, copy: &CopyTo) -> PathBuf {
+    let relative = file
+        .path
+        .strip_prefix(&copy.base)
+        .ok()
+        .filter(|relative| !relative.as_os_str().is_empty())
+        .unwrap_or_else(|| Path::new(&file.name));
+    copy.destination.join(relative)
+}
+
+/// Fails, naming each one, if any file system hasn't the room `needs`
+/// says is needed on it.
+pub(crate) fn check_space(needs: &SpaceNeeds) -> Result<()> {
+    let shortfalls = needs.shortfalls()?;
+    if shortfalls.is_empty() {
+        return Ok(());
+    }
+    let shortfalls: Vec<String> = shortfalls.iter().map(ToString::to_string).collect();
+    Err(ExecutorError::InsufficientSpace(shortfalls.join("; ")))
+}
+
+/// Makes the copy of `source` at `target`, resolving a conflict with an
+/// existing file by the COPY's policy. Returns how the copy was made, or
+/// None if it was skipped; the rest of what happened goes in `made`. A
+/// verified copy that doesn't match its original is removed. A dry run only
+/// checks that it could be made, and returns the method asked for.
+fn make_copy(
+    source: &Path,
+    target: &mut PathBuf,
+    copy: &CopyTo,
+    root: Option<&Path>,
+    dry_run: Option<&Mutex<HashSet<PathBuf>>>,
+    made: &mut MadeCopy,
+) -> Result<Option<CopyMethod>> {
+    if !lands_within_root(target, root) {
+        return Err(ExecutorError::Denied(format!(
+            "{} resolves outside the sandbox root",
+            target.display()
+        )));
+    }
+
+    let mut replace = false;
+    let claimed = dry_run.is_some_and(|claimed| {
+        claimed.lock().expect("worker panicked").contains(&*target)
+    });
+    if claimed || target.symlink_metadata().is_ok() {
+        match copy.on_conflict {
+            ConflictPolicy::Fail => {
+                return Err(ExecutorError::IoError(std::io::Error::new(
+                    std::io::ErrorKind::AlreadyExists,
+                    format!(
+                        "Cannot copy {}: {} already exists",
+                        source.display(),
+                        target.display()
+                    ),
+                )));
+            }
+            ConflictPolicy::Skip => {
+                made.conflict = Some(ConflictOutcome::Skipped);
+                return Ok(None);
+            }
+            ConflictPolicy::Overwrite => {
+                if target.symlink_metadata().is_ok()
+                    && fs::canonicalize(source)? == fs::canonicalize(&*target)?
+                {
+                    return Err(ExecutorError::UnsupportedOperation(format!(
+                        "Cannot copy {} over itself",
+                        source.display()
+                    )));
+                }
+                made.conflict = Some(ConflictOutcome::Overwritten);
+                replace = true;
+            }
+            ConflictPolicy::Rename => {
+                *target = free_name(target);
+                made.conflict = Some(ConflictOutcome::Renamed);
+            }
+        }
+    }
+
+    if let Some(claimed) = dry_run {
+        check_readable(source)?;
+        check_writable(target.parent().unwrap_or(Path::new(".")))?;
+        claimed.lock().expect("worker panicked").insert(target.clone());
+        return Ok(Some(copy.method));
+    }
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    if !replace {
+        let transfer = copy_file(source, target, copy.method, &copy.options)?;
+        made.checksum = verified(source, target, copy)?;
+        made.transfer = Some(transfer);
+        return Ok(Some(transfer.method));
+    }
+    // Copy next to the file being replaced, then swap it in, so that a
+    // failed copy leaves it as it was
+    let name = target.file_name().unwrap_or_default().to_string_lossy();
+    let temporary = target.with_file_name(format!(".{}.fmql-copy", name));
+    let _ = fs::remove_file(&temporary);
+    let transfer = copy_file(source, &temporary, copy.method, &copy.options)?;
+    made.checksum = verified(source, &temporary, copy)?;
+    made.transfer = Some(transfer);
+    let renamed = fs::rename(&temporary, &*target);
+    // Renaming a hardlink over another link to the same file does nothing
+    let _ = fs::remove_file(&temporary);
+    renamed?;
+    Ok(Some(transfer.method))
+}
+
+/// Checks a new copy against its original if the COPY verifies, and
+/// returns their checksum; removes the copy if it doesn't match.
+fn verified(source: &Path, target: &Path, copy: &CopyTo) -> Result<Option<String>> {
+    if !copy.verify {
+        return Ok(None);
+    }
+    match verify_copy(source, target, copy.options.throttle) {
+        Ok(checksum) => Ok(Some(checksum)),
+        Err(err) => {
+            let _ = fs::remove_file(target);
+            Err(err.into())
+        }
+    }
+}
+
+/// How a COMPRESS compresses its files.
+struct CompressTo<'a> {
+    format: CompressFormat,
+    level: u32,
+    /// Remove each original once its compressed copy has been checked.
+    delete_original: bool,
+    /// Caps how fast the originals and compressed copies are read.
+    throttle: Option<&'a Throttle>,
+}
+
+/// Executes a COMPRESS query. Only regular files are compressed, and files
+/// whose contents are compressed already are left unchanged.
+fn execute_compress(
+    targets: &[PathBuf],
+    scan: Scan,
+    condition: Option<&FileCondition>,
+    compress: CompressTo<'_>,
+    options: &ExecutionOptions,
+    root: Option<&Path>,
+) -> Result<Vec<FileResult>> {
+    let lock = lock_targets(targets, options)?;
+    let files: Vec<FileResult> = list_files(targets, scan, root, &options.limits)?
+        .into_iter()
+        .filter(|file| !file.is_directory && !file.is_symlink)
+        .collect();
+    let files = filter_files(files, condition, options.dry_run, &options.faults, &options.events)?;
+    // Checked before compressing anything, like an UPDATE
+    check_result_count(files.len(), &options.limits)?;
+    Ok(parallel_map(options.jobs, &files, |file| {
+        let compressed = match hold(lock.as_ref(), [file.path.as_path()]) {
+            Ok(_held) => compress_one(file, &compress, root, options.dry_run),
+            Err(err) => lock_failed(file, &err),
+        };
+        applied(&options.events, compressed)
+    }))
+}
+
+/// Compresses one file for a COMPRESS and reports what happened: the
+/// compressed file, or the original if it was skipped or failed.
+fn compress_one(
+    file: &FileResult,
+    compress: &CompressTo<'_>,
+    root: Option<&Path>,
+    dry_run: bool,
+) -> FileResult {
+    let mut name = file.path.as_os_str().to_owned();
+    name.push(".");
+    name.push(compress.format.extension());
+    let target = PathBuf::from(name);
+    let outcome = make_compressed(&file.path, &target, compress, root, dry_run);
+
+    let mut result = match &outcome {
+        Ok(true) if !dry_run => create_file_result(&target).unwrap_or_else(|_| file.clone()),
+        _ => file.clone(),
+    };
+    result.status = Some(match &outcome {
+        Err(_) => OperationStatus::Failed,
+        Ok(false) => OperationStatus::Skipped,
+        Ok(true) => OperationStatus::Ok,
+    });
+    match outcome {
+        Ok(true) => {
+            result.compressed = Some(compress.format);
+            result.changes.push(FileChange {
+                attribute: FileAttribute::Path,
+                old_value: FileValue::String(file.path.to_string_lossy().to_string()),
+                new_value: FileValue::String(target.to_string_lossy().to_string()),
+            });
+            result.changes.push(FileChange {
+                attribute: FileAttribute::Size,
+                old_value: FileValue::from_u64(file.size),
+                new_value: FileValue::from_u64(result.size),
+            });
+        }
+        Ok(false) => {}
+        Err(err) => result.error_message = Some(err.to_string()),
+    }
+    result
+}
+
+/// Makes the compressed copy of `path` at `target`, and removes the
+/// original if the COMPRESS says to. Returns false if the file is
+/// compressed already, and so was left alone. A dry run only checks that
+/// the copy could be made.
+fn make_compressed(
+    path: &Path,
+    target: &Path,
+    compress: &CompressTo<'_>,
+    root: Option<&Path>,
+    dry_run: bool,
+) -> Result<bool> {
+    if !lands_within_root(target, root) {
+        return Err(ExecutorError::Denied(format!(
+            "{} resolves outside the sandbox root",
+            target.display()
+        )));
+    }
+    if already_compressed(path)? {
+        return Ok(false);
+    }
+    if target.symlink_metadata().is_ok() {
+        return Err(ExecutorError::IoError(std::io::Error::new(
+            std::io::ErrorKind::AlreadyExists,
+            format!("Cannot compress {}: {} already exists", path.display(), target.display()),
+        )));
+    }
+    let dir = target.parent().unwrap_or(Path::new("."));
+    if dry_run {
+        check_readable(path)?;
+        check_writable(dir)?;
+        return Ok(true);
+    }
+    compress_file(path, target, compress.format, compress.level, compress.throttle)?;
+    if compress.delete_original {
+        fs::remove_file(path)?;
+    }
+    Ok(true)
+}
+
+/// Where and how an EXTRACT unpacks its archives.
+struct ExtractTo<'a> {
+    /// The directory template, in which `{stem}` and `{name}` stand for
+    /// the archive's stem and name.
+    destination: &'a Path,
+    on_conflict: ConflictPolicy,
+}
+
+/// Executes an EXTRACT query. Matched files that aren't zip files or
+/// tarballs, by their names, are skipped.
+fn execute_extract(
+    targets: &[PathBuf],
+    scan: Scan,
+    condition: Option<&FileCondition>,
+    extract: ExtractTo,
+    options: &ExecutionOptions,
+    root: Option<&Path>,
+) -> Result<Vec<FileResult>> {
+    let lock = lock_targets(targets, options)?;
+    let files: Vec<FileResult> = list_files(targets, scan, root, &options.limits)?
+        .into_iter()
+        .filter(|file| !file.is_directory && !file.is_symlink)
+        .collect();
+    let files = filter_files(files, condition, options.dry_run, &options.faults, &options.events)?;
+    check_result_count(files.len(), &options.limits)?;
+    Ok(parallel_map(options.jobs, &files, |file| {
+        let extracted = match hold(lock.as_ref(), [file.path.as_path()]) {
+            Ok(_held) => extract_one(file, &extract, root, options.dry_run),
+            Err(err) => lock_failed(file, &err),
+        };
+        applied(&options.events, extracted)
+    }))
+}
+
+/// Unpacks one archive for an EXTRACT and reports what happened, with the
+/// directory it went to as the new `path`.
+fn extract_one(
+    file: &FileResult,
+    extract: &ExtractTo,
+    root: Option<&Path>,
+    dry_run: bool,
+) -> FileResult {
+    let mut result = file.clone();
+    if ArchiveFormat::of(&file.path).is_none() {
+        result.status = Some(OperationStatus::Skipped);
+        return result;
+    }
+    let destination = extract_destination(&file.path, extract.destination);
+    let outcome = if lands_within_root(&destination, root) {
+        extract_archive(&file.path, &destination, extract.on_conflict, dry_run).map_err(Into::into)
+    } else {
+        Err(ExecutorError::Denied(format!(
+            "{} resolves outside the sandbox root",
+            destination.display()
+        )))
+    };
+    match outcome {
+        Ok(extracted) => {
+            result.status = Some(OperationStatus::Ok);
+            result.extracted = Some(extracted);
+            result.changes.push(FileChange {
+                attribute: FileAttribute::Path,
+                old_value: FileValue::String(file.path.to_string_lossy().to_string()),
+                new_value: FileValue::String(destination.to_string_lossy().to_string()),
+            });
+        }
+        Err(err) => {
+            result.status = Some(OperationStatus::Failed);
+            result.error_message = Some(err.to_string());
+        }
+    }
+    result
+}
+
+/// Fills in the `{stem}` and `{name}` of an EXTRACT destination for one
+/// archive.
+fn extract_destination(archive: &Path, template: &Path) -> PathBuf {
+    let name = archive.file_name().unwrap_or_default().to_string_lossy();
+    let destination = template
+        .to_string_lossy()
+        .replace("{stem}", &archive_stem(archive))
+        .replace("{name}", &name);
+    PathBuf::from(destination)
+}
+
+/// Where an ARCHIVE packs its files.
+struct ArchiveTo<'a> {
+    /// The directory the paths in the archives are relative to.
+    base: PathBuf,
+    /// The archive template, in which `{YYYY}`, `{MM}` and `{DD}` stand
+    /// for the date each file was modified.
+    destination: &'a Path,
+    /// Remove each original once its archive has been checked.
+    delete_original: bool,
+    /// Caps how fast the originals are read.
+    throttle: Option<&'a Throttle>,
+}
+
+/// Executes an ARCHIVE query: the matched regular files are grouped by
+/// the archive the template names for each, and each archive is written
+/// in one go.
+fn execute_archive(
+    targets: &[PathBuf],
+    scan: Scan,
+    condition: Option<&FileCondition>,
+    archive: ArchiveTo,
+    options: &ExecutionOptions,
+    root: Option<&Path>,
+) -> Result<Vec<FileResult>> {
+    let lock = lock_targets(targets, options)?;
+    let files: Vec<FileResult> = list_files(targets, scan, root, &options.limits)?
+        .into_iter()
+        .filter(|file| !file.is_directory && !file.is_symlink)
+        .collect();
+    let files = filter_files(files, condition, options.dry_run, &options.faults, &options.events)?;
+    check_result_count(files.len(), &options.limits)?;
+    let mut batches: BTreeMap<PathBuf, Vec<FileResult>> = BTreeMap::new();
+    for file in files {
+        let target = archive_path(archive.destination, file.modified, file.time_zone);
+        batches.entry(target).or_default().push(file);
+    }
+    let batches: Vec<_> = batches.into_iter().collect();
+    let archived = parallel_map(options.jobs, &batches, |(target, files)| {
+        let archived = match hold(lock.as_ref(), files.iter().map(|file| file.path.as_path())) {
+            Ok(_held) => archive_batch(target, files, &archive, root, options.dry_run),
+            Err(err) => files.iter().map(|file| lock_failed(file, &err)).collect(),
+        };
+        archived.into_iter().map(|file| applied(&options.events, file)).collect::<Vec<_>>()
+    });
+    Ok(archived.into_iter().flatten().collect())
+}
+
+/// Packs the files bound for one archive and reports what happened to
+/// each, with the archive as its new `path`. If the archive can't be
+/// written, every file in it fails.
+fn archive_batch(
+    target: &Path,
+    files: &[FileResult],
+    archive: &ArchiveTo,
+    root: Option<&Path>,
+    dry_run: bool,
+) -> Vec<FileResult> {
+    let members: Vec<Member> = files
+        .iter()
+        .map(|file| Member {
+            path: file.path.clone(),
+            name: file
+                .path
+                .strip_prefix(&archive.base)
+                .ok()
+                .filter(|relative| !relative.as_os_str().is_empty())
+                .unwrap_or_else(|| Path::new(&file.name))
+                .to_path_buf(),
+        })
+        .collect();
+    let written = make_archive(target, &members, archive.throttle, root, dry_run);
+
+    let mut results = Vec::with_capacity(files.len());
+    for file in files {
+        let mut result = file.clone();
+        let outcome = match &written {
+            Ok(()) if archive.delete_original && !dry_run => {
+                fs::remove_file(&file.path).map_err(|err| {
+                    format!("archived in {}, but not removed: {}", target.display(), err)
+                })
+            }
+            Ok(()) => Ok(()),
+            Err(err) => Err(err.to_string()),
+        };
+        match outcome {
+            Ok(()) => {
+                result.status = Some(OperationStatus::Ok);
+                result.archived = Some(target.to_path_buf());
+                result.changes.push(FileChange {
+                    attribute: FileAttribute::Path,
+                    old_value: FileValue::String(file.path.to_string_lossy().to_string()),
+                    new_value: FileValue::String(target.to_string_lossy().to_string()),
+                });
+            }
+            Err(message) => {
+                result.status = Some(OperationStatus::Failed);
+                result.error_message = Some(message);
+            }
+        }
+        results.push(result);
+    }
+    results
+}
+
+/// Writes one archive of an ARCHIVE. A dry run only checks that it could
+/// be written.
+fn make_archive(
+    target: &Path,
+    members: &[Member],
+    throttle: Option<&Throttle>,
+    root: Option<&Path>,
+    dry_run: bool,
+) -> Result<()> {
+    if !lands_within_root(target, root) {
+        return Err(ExecutorError::Denied(format!(
+            "{} resolves outside the sandbox root",
+            target.display()
+        )));
+    }
+    if dry_run {
+        check_archive(target, members)?;
+        for member in members {
+            check_readable(&member.path)?;
+        }
+        return check_writable(target.parent().unwrap_or(Path::new(".")));
+    }
+    write_archive(target, members, throttle)?;
+    Ok(())
+}
+
+/// Executes a RESTORE query: each matched file in a trash goes back to
+/// its original path, one at a time so that two can't claim one name.
+/// Files inside a trashed directory that is restored go back with it.
+fn execute_restore(
+    targets: &[PathBuf],
+    scan: Scan,
+    condition: Option<&FileCondition>,
+    on_conflict: ConflictPolicy,
+    options: &ExecutionOptions,
+    root: Option<&Path>,
+) -> Result<Vec<FileResult>> {
+    let lock = lock_targets(targets, options)?;
+    let files = list_files(targets, scan, root, &options.limits)?;
+    let (faults, events) = (&options.faults, &options.events);
+    let mut files = filter_files(files, condition, options.dry_run, faults, events)?;
+    let matched: HashSet<PathBuf> = files.iter().map(|file| file.path.clone()).collect();
+    files.retain(|file| !file.path.ancestors().skip(1).any(|dir| matched.contains(dir)));
+    check_result_count(files.len(), &options.limits)?;
+    Ok(files
+        .iter()
+        .map(|file| {
+            let restored = match hold(lock.as_ref(), [file.path.as_path()]) {
+                Ok(_held) => restore_one(file, on_conflict, root, options.dry_run),
+                Err(err) => lock_failed(file, &err),
+            };
+            applied(&options.events, restored)
+        })
+        .collect())
+}
+
+/// Restores one trashed file for a RESTORE and reports what happened, with
+/// its original path as the new `path`.
+fn restore_one(
+    file: &FileResult,
+    on_conflict: ConflictPolicy,
+    root: Option<&Path>,
+    dry_run: bool,
+) -> FileResult {
+    let mut result = file.clone();
+    let outcome = put_back(file, on_conflict, root, dry_run, &mut result.conflict);
+    match outcome {
+        Ok(Some(target)) => {
+            if !dry_run && let Ok(moved) = create_file_result(&target) {
+                let conflict = result.conflict;
+                result = moved;
+                result.conflict = conflict;
+            }
+            result.status = Some(OperationStatus::Ok);
+            result.changes.push(FileChange {
+                attribute: FileAttribute::Path,
+                old_value: FileValue::String(file.path.to_string_lossy().to_string()),
+                new_value: FileValue::String(target.to_string_lossy().to_string()),
+            });
+        }
+        Ok(None) => result.status = Some(OperationStatus::Skipped),
+        Err(err) => {
+            result.status = Some(OperationStatus::Failed);
+            result.error_message = Some(err.to_string());
+        }
+    }
+    result
+}
+
+/// Moves a trashed file back to its original path, or to a free name
+/// beside it with `ON CONFLICT RENAME`, and returns where it went; None if
+/// it was left in the trash because the path is taken. A dry run only
+/// checks that it could be moved.
+fn put_back(
+    file: &FileResult,
+    on_conflict: ConflictPolicy,
+    root: Option<&Path>,
+    dry_run: bool,
+    conflict: &mut Option<ConflictOutcome>,
+) -> Result<Option<PathBuf>> {
+    let Some(info) = trash::read_info(&file.path)? else {
+        return Err(ExecutorError::UnsupportedOperation(format!(
+            "{} isn't in a trash that records where it came from",
+            file.path.display()
+        )));
+    };
+    let mut target = info.original_path.clone();
+    if !lands_within_root(&target, root) {
+        return Err(ExecutorError::Denied(format!(
+            "{} resolves outside the sandbox root",
+            target.display()
+        )));
+    }
+    if let Ok(existing) = target.symlink_metadata() {
+        match on_conflict {
+            ConflictPolicy::Fail => {
+                return Err(ExecutorError::IoError(std::io::Error::new(
+                    std::io::ErrorKind::AlreadyExists,
+                    format!(
+                        "Cannot restore {}: {} already exists",
+                        file.path.display(),
+                        target.display()
+                    ),
+                )));
+            }
+            ConflictPolicy::Skip => {
+                *conflict = Some(ConflictOutcome::Skipped);
+                return Ok(None);
+            }
+            ConflictPolicy::Overwrite => {
+                // A directory is never deleted to make way for a file
+                if existing.is_dir() {
+                    return Err(ExecutorError::UnsupportedOperation(format!(
+                        "Cannot restore {} over the directory {}",
+                        file.path.display(),
+                        target.display()
+                    )));
+                }
+                *conflict = Some(ConflictOutcome::Overwritten);
+            }
+            ConflictPolicy::Rename => {
+                target = free_name(&target);
+                *conflict = Some(ConflictOutcome::Renamed);
+            }
+        }
+    }
+    if dry_run {
+        check_readable(&file.path)?;
+        let dir = target.ancestors().skip(1).find(|dir| dir.exists());
+        check_writable(dir.unwrap_or(Path::new(".")))?;
+        return Ok(Some(target));
+    }
+    trash::restore(&file.path, &info, &target)?;
+    Ok(Some(target))
+}
+
+/// Evaluates a value expression for a file.
+fn evaluate_expr(file: &FileResult, expr: &FileExpr) -> Result<FileValue> {
+    match expr {
+        FileExpr::Literal(value) => Ok(value.clone()),
+        FileExpr::Attribute(attribute) => get_attribute_value(file, attribute),
+        FileExpr::Joined { column, .. } => Ok(joined_value(file, column)),
+        // An argument that fails for this file is NULL rather than an error
+        FileExpr::Function { name, args } if name.eq_ignore_ascii_case("TRY") => {
+            let args: Vec<_> = args
+                .iter()
+                .map(|arg| evaluate_expr(file, arg).unwrap_or(FileValue::Null))
+                .collect();
+            functions::call_in(name, &args, file.time_zone).map_err(ExecutorError::TypeError)
+        }
+        FileExpr::Function { name, args } => {
+            let args = args
+                .iter()
+                .map(|arg| evaluate_expr(file, arg))
+                .collect::<Result<Vec<_>>>()?;
+            functions::call_in(name, &args, file.time_zone).map_err(ExecutorError::TypeError)
+        }
+        FileExpr::Case {
+            branches,
+            otherwise,
+        } => {
+            for (condition, value) in branches {
+                if evaluate_condition(file, condition)? {
+                    return evaluate_expr(file, value);
+                }
+            }
+            match otherwise {
+                Some(value) => evaluate_expr(file, value),
+                None => Ok(FileValue::Null),
+            }
+        }
+        FileExpr::Binary {
+            left,
+            operator,
+            right,
+        } => {
+            let left = evaluate_expr(file, left)?;
+            let right = evaluate_expr(file, right)?;
+            apply_arithmetic(&left, *operator, &right)
+        }
+        FileExpr::Cast { expr, to } => {
+            let value = evaluate_expr(file, expr)?;
+            functions::cast(&value, *to, file.time_zone).map_err(ExecutorError::TypeError)
+        }
+    }
+}
+
+/// Applies an arithmetic operator to two values.
+///
+/// Numeric text (such as the `1024` in a SET value) is read as a number.
+/// Adding, subtracting, multiplying and taking the remainder of whole
+/// numbers gives an exact whole number, unless it would overflow; division
+/// keeps its fraction, so `size / 1024` is KiB rather than whole KiB.
+/// Subtracting two dates gives the duration between them, and adding or
+/// subtracting a duration, or a number of seconds, to a date gives a date.
+/// Durations add up and subtract to durations; anywhere else a duration
+/// is its seconds, so `(modified - created) / 86400` is a number of days.
+/// NULL operands and division by zero give NULL.
+pub(crate) fn apply_arithmetic(
+    left: &FileValue,
+    operator: ArithmeticOperator,
+    right: &FileValue,
+) -> Result<FileValue> {
+    match (left, operator, right) {
+        (FileValue::Null, _, _) | (_, _, FileValue::Null) => Ok(FileValue::Null),
+        (FileValue::DateTime(l), ArithmeticOperator::Sub, FileValue::DateTime(r)) => {
+            Ok(FileValue::Duration(*l - *r))
+        }
+        (FileValue::Duration(l), ArithmeticOperator::Add, FileValue::Duration(r)) => {
+            l.checked_add(r).map(FileValue::Duration).ok_or_else(duration_range)
+        }
+        (FileValue::Duration(l), ArithmeticOperator::Sub, FileValue::Duration(r)) => {
+            l.checked_sub(r).map(FileValue::Duration).ok_or_else(duration_range)
+        }
+        (
+            FileValue::DateTime(dt),
+            ArithmeticOperator::Add | ArithmeticOperator::Sub,
+            FileValue::Duration(delta),
+        )
+        | (FileValue::Duration(delta), ArithmeticOperator::Add, FileValue::DateTime(dt)) => {
+            let delta = if operator == ArithmeticOperator::Sub { -*delta } else { *delta };
+            dt.checked_add_signed(delta)
+                .map(FileValue::DateTime)
+                .ok_or_else(|| ExecutorError::TypeError("Date out of range".to_string()))
+        }
+        (FileValue::Duration(_), _, _) | (_, _, FileValue::Duration(_)) => {
+            apply_arithmetic(&as_seconds(left), operator, &as_seconds(right))
+        }
+        (FileValue::DateTime(dt), ArithmeticOperator::Add | ArithmeticOperator::Sub, other) => {
+            let seconds = arithmetic_operand(other)?.as_f64().unwrap_or_default();
+            let seconds = if operator == ArithmeticOperator::Sub { -seconds } else { seconds };
+            chrono::Duration::try_milliseconds((seconds * 1000.0) as i64)
+                .and_then(|delta| dt.checked_add_signed(delta))
+                .map(FileValue::DateTime)
+                .ok_or_else(|| ExecutorError::TypeError("Date out of range".to_string()))
+        }
+        _ => {
+            let l = arithmetic_operand(left)?;
+            let r = arithmetic_operand(right)?;
+            if let (FileValue::Integer(l), FileValue::Integer(r)) = (&l, &r) {
+                let exact = match operator {
+                    ArithmeticOperator::Add => l.checked_add(*r),
+                    ArithmeticOperator::Sub => l.checked_sub(*r),
+                    ArithmeticOperator::Mul => l.checked_mul(*r),
+                    ArithmeticOperator::Mod if *r == 0 => return Ok(FileValue::Null),
+                    ArithmeticOperator::Mod => l.checked_rem(*r),
+                    ArithmeticOperator::Div => None,
+                };
+                if let Some(n) = exact {
+                    return Ok(FileValue::Integer(n));
+                }
+            }
+            // Both are numbers, so neither is None
+            let (l, r) = (l.as_f64().unwrap_or_default(), r.as_f64().unwrap_or_default());
+            let result = match operator {
+                ArithmeticOperator::Add => l + r,
+                ArithmeticOperator::Sub => l - r,
+                ArithmeticOperator::Mul => l * r,
+                ArithmeticOperator::Div | ArithmeticOperator::Mod if r == 0.0 => {
+                    return Ok(FileValue::Null);
+                }
+                ArithmeticOperator::Div => l / r,
+                ArithmeticOperator::Mod => l % r,
+            };
+            Ok(FileValue::Number(result))
+        }
+    }
+}
+
+/// Reads a duration as its seconds, leaving anything else as it is.
+fn as_seconds(value: &FileValue) -> FileValue {
+    match value {
+        FileValue::Duration(delta) if delta.subsec_nanos() == 0 => {
+            FileValue::Integer(delta.num_seconds())
+        }
+        FileValue::Duration(delta) => FileValue::Number(delta.num_milliseconds() as f64 / 1000.0),
+        other => other.clone(),
+    }
+}
+
+/// Reports a duration too long to be represented.
+fn duration_range() -> ExecutorError {
+    ExecutorError::TypeError("Duration out of range".to_string())
+}
+
+/// Reads an arithmetic operand as a number, whole or not.
+fn arithmetic_operand(value: &FileValue) -> Result<FileValue> {
+    match value {
+        FileValue::Integer(_) | FileValue::Number(_) => Ok(value.clone()),
+        FileValue::String(s) => parse_numeric(s.trim()).ok_or_else(|| {
+            ExecutorError::TypeError(format!("Cannot use '{}' as a number", s))
+        }),
+        other => Err(ExecutorError::TypeError(format!(
+            "Arithmetic is not supported on {:?}",
+            other
+        ))),
+    }
+}
+
+/// Converts a SET value into permission bits.
+///
+/// Strings are read as octal (`'755'`); numbers are taken as mode bits.
+fn permissions_from_value(value: &FileValue) -> Result<u32> {
+    let perms = match value {
+        FileValue::String(s) => parse_octal(s.strip_prefix("0o").unwrap_or(s)),
+        FileValue::Integer(n) => u32::try_from(*n).ok().filter(|mode| *mode <= PERMISSION_BITS),
+        FileValue::Number(n) if n.fract() == 0.0 && *n >= 0.0 => {
+            Some(*n as u32).filter(|mode| *mode <= PERMISSION_BITS)
+        }
+        _ => None,
+    };
+
+    perms.ok_or_else(|| ExecutorError::TypeError(format!("Invalid permissions value: {:?}", value)))
+}
+
+/// Works out the new path for a file whose name is being SET.
+///
+/// The file stays in its directory; the new name may not contain a path
+/// separator.
+fn rename_target(path: &Path, value: &FileValue) -> Result<PathBuf> {
+    let FileValue::String(name) = value else {
+        return Err(ExecutorError::TypeError(format!("Invalid file name: {:?}", value)));
+    };
+    if name.is_empty() || name == "." || name == ".." || name.contains(['/', '\\']) {
+        return Err(ExecutorError::TypeError(format!("Invalid file name: '{}'", name)));
+    }
+    if let Some(problem) = windows_name_problem(name)
+        && path.parent().is_some_and(holds_windows_names)
+    {
+        return Err(ExecutorError::TypeError(format!(
+            "Invalid file name: '{}' {}",
+            name, problem
+        )));
+    }
+
+    Ok(path.with_file_name(name))
+}
+
+/// Finds a free name next to `target` by appending `_1`, `_2`, ... to its stem.
+pub(crate) fn free_name(target: &Path) -> PathBuf {
+    let stem = target
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let extension = target
+        .extension()
+        .map(|ext| format!(".{}", ext.to_string_lossy()))
+        .unwrap_or_default();
+
+    (1..)
+        .map(|n| target.with_file_name(format!("{}_{}{}", stem, n, extension)))
+        .find(|candidate| candidate.symlink_metadata().is_err())
+        .expect("some numeric suffix is free")
+}
+
+/// Returns a free temporary name next to `path`, to move it aside while
+/// renames are made.
+fn aside(path: &Path) -> PathBuf {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    free_name(&path.with_file_name(format!(".{}.fmql-rename", name)))
+}
+
+/// Returns true if renaming `path` to `target` only changes the case of its
+/// name, on a file system that ignores case.
+///
+/// There, `target` already seems to exist, since it names the file itself.
+/// That is told apart from a hard link under the other name, on a file
+/// system that doesn't ignore case, by checking that no entry in the
+/// directory has exactly the new name.
+fn differs_only_in_case(path: &Path, target: &Path) -> bool {
+    let (Some(name), Some(new_name)) = (path.file_name(), target.file_name()) else {
+        return false;
+    };
+    let (name, new_name) = (name.to_string_lossy(), new_name.to_string_lossy());
+    if name == new_name || name.to_lowercase() != new_name.to_lowercase() {
+        return false;
+    }
+    let same_file = match (path.symlink_metadata(), target.symlink_metadata()) {
+        (Ok(old), Ok(new)) => old.dev() == new.dev() && old.ino() == new.ino(),
+        _ => false,
+    };
+    same_file
+        && target.parent().and_then(|parent| fs::read_dir(parent).ok()).is_some_and(|entries| {
+            !entries
+                .flatten()
+                .any(|entry| entry.file_name() == target.file_name().unwrap_or_default())
+        })
+}
+
+/// Returns true if `path` resolves to a location inside `root`.
+///
+/// `root` must already be canonical. Symlinks in `path` are followed, so a
+/// link pointing out of the sandbox is outside it. Without a root every path
+/// is allowed.
+fn is_within_root(path: &Path, root: Option<&Path>) -> bool {
+    match root {
+        None => true,
+        Some(root) => fs::canonicalize(path).is_ok_and(|path| path.starts_with(root)),
+    }
+}
+
+/// Like [`is_within_root`], for a path that may not exist yet: the nearest
+/// ancestor of it that exists must be inside `root`.
+pub(crate) fn lands_within_root(path: &Path, root: Option<&Path>) -> bool {
+    match root {
+        None => true,
+        Some(root) => path
+            .ancestors()
+            .find_map(|ancestor| fs::canonicalize(ancestor).ok())
+            .is_some_and(|path| path.starts_with(root)),
+    }
+}
+
+/// Fails, as changing them would, unless the current user may change the
+/// mode and flags of the file at `path`: its owner and root may.
+fn check_owner(path: &Path, change: &str) -> Result<()> {
+    // SAFETY: geteuid has no preconditions and can't fail
+    let user = unsafe { libc::geteuid() };
+    if user == 0 || path.symlink_metadata()?.uid() == user {
+        return Ok(());
+    }
+    Err(ExecutorError::IoError(std::io::Error::new(
+        std::io::ErrorKind::PermissionDenied,
+        format!("Cannot {} {}: it belongs to another user", change, path.display()),
+    )))
+}
+
+/// Fails, as reading it would, unless the current user may read the file at
+/// `path`.
+fn check_readable(path: &Path) -> Result<()> {
+    if may_access(path, libc::R_OK) {
+        return Ok(());
+    }
+    Err(ExecutorError::IoError(std::io::Error::new(
+        std::io::ErrorKind::PermissionDenied,
+        format!("Cannot read {}", path.display()),
+    )))
+}
+
+/// Fails, as adding an entry to it would, unless the current user may write
+/// to the directory `dir`, or to its nearest ancestor that exists if it is
+/// yet to be made.
+fn check_writable(dir: &Path) -> Result<()> {
+    let dir = if dir.as_os_str().is_empty() { Path::new(".") } else { dir };
+    let existing = dir
+        .ancestors()
+        .find(|ancestor| ancestor.symlink_metadata().is_ok())
+        .unwrap_or(Path::new("."));
+    if !existing.is_dir() {
+        return Err(ExecutorError::IoError(std::io::Error::new(
+            std::io::ErrorKind::NotADirectory,
+            format!("Cannot write to {}: {} isn't a directory", dir.display(), existing.display()),
+        )));
+    }
+    if may_access(existing, libc::W_OK | libc::X_OK) {
+        return Ok(());
+    }
+    Err(ExecutorError::IoError(std::io::Error::new(
+        std::io::ErrorKind::PermissionDenied,
+        format!("Cannot write to {}", existing.display()),
+    )))
+}
+
+/// Returns true if the current user has the `mode` access (`libc::R_OK`
+/// and so on) to `path`, as the kernel would judge it, read-only mounts
+/// included.
+fn may_access(path: &Path, mode: libc::c_int) -> bool {
+    use std::os::unix::ffi::OsStrExt;
+
+    let Ok(path) = std::ffi::CString::new(path.as_os_str().as_bytes()) else {
+        return false;
+    };
+    // SAFETY: the path is a NUL-terminated string that outlives the call
+    unsafe { libc::faccessat(libc::AT_FDCWD, path.as_ptr(), mode, libc::AT_EACCESS) == 0 }
+}
+
+/// Fails if a query matched more files than its limits allow.
+fn check_result_count(count: usize, limits: &ResourceLimits) -> Result<()> {
+    match limits.max_results {
+        Some(max) if count > max => Err(LimitError::Results(max).into()),
+        _ => Ok(()),
+    }
+}
+
+/// Lists the files of each target, descending into directories (recursively
+/// if asked). A target that is a file contributes just itself; a directory
+/// target is only listed itself with `include_self`.
+///
+/// With a sandbox root, entries that resolve outside it are left out.
+fn list_files(
+    targets: &[PathBuf],
+    scan: Scan,
+    root: Option<&Path>,
+    limits: &ResourceLimits,
+) -> Result<Vec<FileResult>> {
+    let mut results = Vec::new();
+    visit_files(targets, scan, root, limits, |file| {
+        results.push(file);
+        Ok(ControlFlow::Continue(()))
+    })?;
+    Ok(results)
+}
+
+/// Like [`list_files`], but hands each file to `visit` as soon as it is
+/// found; `visit` can stop the traversal by returning `Break`.
+fn visit_files(
+    targets: &[PathBuf],
+    scan: Scan,
+    root: Option<&Path>,
+    limits: &ResourceLimits,
+    mut visit: impl FnMut(FileResult) -> Result<ControlFlow<()>>,
+) -> Result<()> {
+    visit_paths(targets, scan.clone(), root, limits, |path| match scan.open(path)? {
+        Some(file) => visit(file),
+        None => Ok(ControlFlow::Continue(())),
+    })
+}
+
+/// Like [`visit_files`], but hands over just the path, for callers that
+/// don't need the file's metadata.
+fn visit_paths(
+    targets: &[PathBuf],
+    scan: Scan,
+    root: Option<&Path>,
+    limits: &ResourceLimits,
+    mut visit: impl FnMut(&Path) -> Result<ControlFlow<()>>,
+) -> Result<()> {
+    let walk = Walk::new(targets.to_vec(), scan, root.map(Path::to_path_buf), limits.clone());
+    for path in walk {
+        if visit(&path?)?.is_break() {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// The paths of a query's targets, found one at a time. This is the
+/// traversal behind [`visit_paths`] and [`QueryCursor`]; it stops for good
+/// after the first error.
+struct Walk {
+    targets: std::vec::IntoIter<PathBuf>,
+    /// Glob targets can overlap (e.g. `FROM './**'`); list each file once.
+    overlapping: bool,
+    seen: HashSet<PathBuf>,
+    scan: Scan,
+    root: Option<PathBuf>,
+    limits: ResourceLimits,
+    /// The listing of the directory target being walked.
+    walker: Option<walkdir::IntoIter>,
+    /// List directories in name order, so that a checkpoint's position
+    /// means the same thing the next time.
+    sorted: bool,
+    /// How many targets have been taken.
+    taken: usize,
+    /// The next target was walked up to this path before a checkpoint was
+    /// saved, so everything up to it is skipped.
+    resume_after: Option<PathBuf>,
+    /// The last path returned.
+    position: Option<Position>,
+    scanned: usize,
+    failed: bool,
+}
+
+impl Walk {
+    fn new(targets: Vec<PathBuf>, scan: Scan, root: Option<PathBuf>, limits: ResourceLimits) -> Self {
+        scan.events.emit(|| Event::ScanStarted { targets: targets.clone() });
+        Walk {
+            overlapping: targets.len() > 1,
+            targets: targets.into_iter(),
+            seen: HashSet::new(),
+            scan,
+            root,
+            limits,
+            walker: None,
+            sorted: false,
+            taken: 0,
+            resume_after: None,
+            position: None,
+            scanned: 0,
+            failed: false,
+        }
+    }
+
+    /// Walks in an order that can be checkpointed, starting just after
+    /// `position` if there is one.
+    fn resume(mut self, position: Option<&Position>) -> Self {
+        self.sorted = true;
+        if let Some(position) = position {
+            self.targets.by_ref().take(position.target).for_each(drop);
+            self.taken = position.target;
+            self.resume_after = Some(position.path.clone());
+        }
+        self
+    }
+
+    /// Returns how far the walk has got, for saving in a checkpoint.
+    fn position(&self) -> Option<&Position> {
+        self.position.as_ref()
+    }
+
+    /// How deep below a directory target the walk goes.
+    fn max_depth(&self) -> usize {
+        if self.scan.recursive {
+            self.limits.max_depth.unwrap_or(usize::MAX)
+        } else {
+            1
+        }
+    }
+
+    /// Counts one more visited entry against the limits.
+    fn tick(&mut self) -> Result<()> {
+        self.scan.check_time()?;
+        if self.limits.max_files_scanned.is_some_and(|max| self.scanned >= max) {
+            return Err(LimitError::FilesScanned(self.scanned).into());
+        }
+        self.scanned += 1;
+        Ok(())
+    }
+
+    fn advance(&mut self) -> Result<Option<PathBuf>> {
+        loop {
+            let Some(walker) = self.walker.as_mut() else {
+                let Some(target) = self.targets.next() else {
+                    return Ok(None);
+                };
+                self.taken += 1;
+                if !self.scan.listed && target.is_dir() {
+                    let mut walker = WalkDir::new(&target)
+                        .min_depth(if self.scan.include_self { 0 } else { 1 })
+                        .max_depth(self.max_depth())
+                        .follow_links(false);
+                    if self.sorted {
+                        walker = walker.sort_by_file_name();
+                    }
+                    self.scan.events.emit(|| Event::DirectoryEntered { path: target.clone() });
+                    self.walker = Some(walker.into_iter());
+                    continue;
+                }
+                // A file target that a checkpoint was saved at is done
+                if self.resume_after.take().is_some() {
+                    continue;
+                }
+                if let (Some(shard), Some(name)) = (self.scan.shard, target.file_name())
+                    && !shard.owns(name)
+                {
+                    continue;
+                }
+
+                self.tick()?;
+                if !self.scan.includes(&target) {
+                    continue;
+                }
+                let file_type = match self.scan.retry.run(|| fs::symlink_metadata(&target)) {
+                    // Opening it again finds out why it can't be read
+                    Err(_) if self.scan.reconcile.is_some() => {
+                        if self.seen.insert(target.clone()) {
+                            return Ok(Some(target));
+                        }
+                        continue;
+                    }
+                    Err(err) if self.scan.listed && err.kind() == std::io::ErrorKind::NotFound => {
+                        continue;
+                    }
+                    Err(err) if err.is_transient() && !self.scan.faults.is_strict() => {
+                        self.scan.warn(&target, err.to_string());
+                        continue;
+                    }
+                    metadata => metadata?.file_type(),
+                };
+                if self.scan.wants(file_type) && self.seen.insert(target.clone()) {
+                    return Ok(Some(target));
+                }
+                continue;
+            };
+
+            let Some(entry) = walker.next() else {
+                self.walker = None;
+                self.resume_after = None;
+                continue;
+            };
+            // Paths sort in the order the walk lists them, so whatever sorts
+            // before the checkpoint's path was seen, including the contents
+            // of directories other than the ones it is in
+            if let Some(after) = &self.resume_after
+                && let Ok(entry) = &entry
+            {
+                if entry.path() <= after.as_path() {
+                    if entry.file_type().is_dir() && !after.starts_with(entry.path()) {
+                        walker.skip_current_dir();
+                    }
+                    continue;
+                }
+                self.resume_after = None;
+            }
+            // A shard walks the subtrees of the entries it owns, and the
+            // first shard lists the target itself
+            if let Some(shard) = self.scan.shard
+                && let Ok(entry) = &entry
+                && match entry.depth() {
+                    0 => shard.index != 1,
+                    1 => !shard.owns(entry.file_name()),
+                    _ => false,
+                }
+            {
+                if entry.depth() == 1 && entry.file_type().is_dir() {
+                    walker.skip_current_dir();
+                }
+                continue;
+            }
+            if let Ok(entry) = &entry
+                && entry.depth() > 0
+                && self.scan.ignore.is_ignored(entry.path())
+            {
+                if entry.file_type().is_dir() {
+                    walker.skip_current_dir();
+                }
+                continue;
+            }
+            self.tick()?;
+
+            let entry = entry.map_err(|e| {
+                let kind = e
+                    .io_error()
+                    .map_or(std::io::ErrorKind::Other, |err| err.kind());
+                ExecutorError::IoError(std::io::Error::new(
+                    kind,
+                    format!("Failed to read directory entry: {}", e),
+                ))
+            })?;
+            // walkdir lists a directory's entries right after it
+            if entry.depth() > 0 && entry.depth() < self.max_depth() && entry.file_type().is_dir() {
+                let path = entry.path();
+                self.scan.events.emit(|| Event::DirectoryEntered { path: path.to_path_buf() });
+            }
+
+            if !self.scan.wants(entry.file_type()) || !self.scan.includes(entry.path()) {
+                continue;
+            }
+            if entry.path_is_symlink() && !is_within_root(entry.path(), self.root.as_deref()) {
+                continue;
+            }
+            if self.overlapping && !self.seen.insert(entry.path().to_path_buf()) {
+                continue;
+            }
+            return Ok(Some(entry.into_path()));
+        }
+    }
+}
+
+impl Iterator for Walk {
+    type Item = Result<PathBuf>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        let next = self.advance().transpose();
+        self.failed = matches!(next, Some(Err(_)));
+        if self.sorted
+            && let Some(Ok(path)) = &next
+        {
+            self.position = Some(Position {
+                target: self.taken - 1,
+                path: path.clone(),
+            });
+        }
+        next
+    }
+}
+
+/// Creates a FileResult from a path.
+fn create_file_result(path: &Path) -> Result<FileResult> {
+    let metadata = fs::metadata(path)?;
+
+    let modified = metadata
+        .modified()
+        .map(DateTime::<Utc>::from)
+        .unwrap_or_else(|_| Utc::now());
+
+    // Without access times (some platforms and file systems), a file was
+    // last read no earlier than it was last written
+    let accessed = metadata
+        .accessed()
+        .map(DateTime::<Utc>::from)
+        .unwrap_or(modified);
+
+    let permissions = metadata.permissions().mode();
+
+    let owner = Some(intern(&user_name(metadata.uid())));
+
+    Ok(FileResult {
+        size: metadata.len(),
+        is_directory: metadata.is_dir(),
+        is_symlink: fs::symlink_metadata(path).is_ok_and(|m| m.file_type().is_symlink()),
+        permissions,
+        modified,
+        accessed,
+        owner,
+        ..bare_file_result(path)
+    })
+}
+
+/// Creates a FileResult that knows only what a path says: its name and
+/// extension. A listed file that couldn't be read is kept as one.
+fn bare_file_result(path: &Path) -> FileResult {
+    let name = path
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or("")
+        .to_string();
+
+    let extension = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(intern);
+
+    FileResult {
+        path: path.to_path_buf(),
+        name,
+        size: 0,
+        is_directory: false,
+        is_symlink: false,
+        extension,
+        permissions: 0,
+        modified: DateTime::UNIX_EPOCH,
+        accessed: DateTime::UNIX_EPOCH,
+        owner: None,
+        conflict: None,
+        status: None,
+        error_message: None,
+        changes: Vec::new(),
+        copied: None,
+        compressed: None,
+        checksum: None,
+        transfer: None,
+        extracted: None,
+        archived: None,
+        matched: None,
+        computed: BTreeMap::new(),
+        preview: None,
+        matches: Vec::new(),
+        selected: SelectedAttributes::default(),
+        joined: BTreeMap::new(),
+        decompress: None,
+        encoding: TextEncoding::Auto,
+        time_zone: Tz::UTC,
+        now: None,
+        semantics: Semantics::CURRENT,
+        source: None,
+        retry: RetryPolicy::NONE,
+        meter: None,
+        unread: None,
+    }
+}
+
+/// How many compiled regular expressions [`regex`] keeps.
+const COMPILED_REGEXES: usize = 256;
+
+/// Compiles a regular expression, or returns the one already compiled for
+/// the same pattern. A condition is evaluated once per file, and compiling
+/// its pattern each time would cost more than matching it.
+pub(crate) fn regex(pattern: &str) -> std::result::Result<Regex, regex::Error> {
+    static COMPILED: OnceLock<Mutex<HashMap<String, Regex>>> = OnceLock::new();
+
+    let compiled = COMPILED.get_or_init(|| Mutex::new(HashMap::new()));
+    let mut compiled = compiled.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    if let Some(regex) = compiled.get(pattern) {
+        return Ok(regex.clone());
+    }
+    let regex = Regex::new(pattern)?;
+    // A long-running process can see any number of patterns
+    if compiled.len() >= COMPILED_REGEXES {
+        compiled.clear();
+    }
+    compiled.insert(pattern.to_string(), regex.clone());
+    Ok(regex)
+}
+
+/// Compiles a LIKE pattern: `%` matches any run of characters (newlines
+/// included) and `_` any one character. Everything else matches itself,
+/// `.`, `+` and `[` included.
+pub(crate) fn like_regex(pattern: &str, case_sensitive: bool) -> std::result::Result<Regex, regex::Error> {
+    let mut translated = String::from(if case_sensitive { "(?s)^" } else { "(?si)^" });
+    let mut literal = [0u8; 4];
+    for c in pattern.chars() {
+        match c {
+            '%' => translated.push_str(".*"),
+            '_' => translated.push('.'),
+            c => translated.push_str(&regex::escape(c.encode_utf8(&mut literal))),
+        }
+    }
+    translated.push('$');
+    regex(&translated)
+}
+
+/// Compiles a LIKE pattern as fmql 0.3 did: `%` and `_` become `.*` and
+/// `.`, and the rest is read as a regular expression.
+fn like_regex_0_3(pattern: &str, case_sensitive: bool) -> std::result::Result<Regex, regex::Error> {
+    let translated = pattern.replace('%', ".*").replace('_', ".");
+    regex(&format!("{}^{}$", if case_sensitive { "" } else { "(?i)" }, translated))
+}
+
+/// Compiles the pattern of `attribute REGEXP pattern`. Like grep, `^` and
+/// `$` match at the start and end of each line of `content`.
+pub(crate) fn regexp_regex(
+    attribute: &FileAttribute,
+    pattern: &str,
+) -> std::result::Result<Regex, regex::Error> {
+    if *attribute == FileAttribute::Content {
+        regex(&format!("(?m){}", pattern))
+    } else {
+        regex(pattern)
+    }
+}
+
+/// Finds the parts of a condition a file satisfies, or None if it doesn't
+/// satisfy it: both sides of an AND, the first side of an OR that holds,
+/// and any other condition whole. It holds exactly when
+/// [`evaluate_condition`] does, and evaluates no more of the condition.
+fn matched_by(file: &FileResult, condition: &FileCondition) -> Result<Option<FileCondition>> {
+    match condition {
+        FileCondition::And(left, right) => {
+            let Some(left) = matched_by(file, left)? else {
+                return Ok(None);
+            };
+            let right = matched_by(file, right)?;
+            Ok(right.map(|right| FileCondition::And(Box::new(left), Box::new(right))))
+        }
+        FileCondition::Or(left, right) => match matched_by(file, left)? {
+            Some(left) => Ok(Some(left)),
+            None => matched_by(file, right),
+        },
+        condition => Ok(evaluate_condition(file, condition)?.then(|| condition.clone())),
+    }
+}
+
+/// Evaluates a condition against a file.
+pub(crate) fn evaluate_condition(file: &FileResult, condition: &FileCondition) -> Result<bool> {
+    match condition {
+        FileCondition::Compare {
+            attribute,
+            operator,
+            value,
+        } => {
+            let file_value = get_attribute_value(file, attribute)?;
+            let value = coerce_literal(attribute, value)?;
+            compare_values(&file_value, operator, &value, file.time_zone)
+        }
+        FileCondition::And(left, right) => {
+            let left_result = evaluate_condition(file, left)?;
+            if !left_result {
+                return Ok(false);
+            }
+            evaluate_condition(file, right)
+        }
+        FileCondition::Or(left, right) => {
+            let left_result = evaluate_condition(file, left)?;
+            if left_result {
+                return Ok(true);
+            }
+            evaluate_condition(file, right)
+        }
+        FileCondition::Not(inner) => {
+            // As in SQL, a NULL neither matches a pattern nor fails to
+            if let Some(matched) = match_pattern(file, inner)? {
+                return Ok(matched.is_some_and(|matched| !matched));
+            }
+            let inner_result = evaluate_condition(file, inner)?;
+            Ok(!inner_result)
+        }
+        FileCondition::Constant(value) => Ok(*value),
+        FileCondition::Like { .. } | FileCondition::Regexp { .. } => {
+            Ok(match_pattern(file, condition)?.flatten().unwrap_or(false))
+        }
+        FileCondition::Between {
+            attribute,
+            lower,
+            upper,
+        } => {
+            let file_value = get_attribute_value(file, attribute)?;
+            let lower = coerce_literal(attribute, lower)?;
+            let upper = coerce_literal(attribute, upper)?;
+
+            let zone = file.time_zone;
+            let greater_than_lower =
+                compare_values(&file_value, &ComparisonOperator::GtEq, &lower, zone)?;
+            let less_than_upper = compare_values(&file_value, &ComparisonOperator::LtEq, &upper, zone)?;
+
+            Ok(greater_than_lower && less_than_upper)
+        }
+        FileCondition::Expression {
+            left,
+            operator,
+            right,
+        } => {
+            let left = evaluate_expr(file, left)?;
+            let right = evaluate_expr(file, right)?;
+            compare_values(&left, operator, &right, file.time_zone)
+        }
+        FileCondition::Substring {
+            attribute,
+            position,
+            text,
+        } => match get_attribute_value(file, attribute)? {
+            FileValue::String(s) => Ok(match position {
+                SubstringPosition::Start => s.starts_with(text.as_str()),
+                SubstringPosition::End => s.ends_with(text.as_str()),
+                SubstringPosition::Anywhere => s.contains(text.as_str()),
+            }),
+            FileValue::Null => Ok(false),
+            other => Err(ExecutorError::TypeError(format!(
+                "Substring functions can only be used with string attributes, got {:?}",
+                other
+            ))),
+        },
+        FileCondition::ContainsBytes { bytes, max_bytes } => metered(file, || {
+            Ok(contains_bytes(&file.path, bytes, *max_bytes).unwrap_or(false))
+        }),
+        FileCondition::HasPerm { spec } => {
+            let spec = PermissionSpec::parse(spec).map_err(ExecutorError::TypeError)?;
+            Ok(spec.matches(file.permissions))
+        }
+        FileCondition::OwnerInGroup { group } => Ok(file
+            .owner
+            .as_ref()
+            .is_some_and(|owner| in_group(owner, group).unwrap_or(false))),
+        FileCondition::Tagged { tag, store } => tags::is_tagged(store, tag, &file.path),
+        FileCondition::PermMatches { pattern } => {
+            matches_octal_pattern(file.permissions, pattern).map_err(ExecutorError::TypeError)
+        }
+        FileCondition::PermMask {
+            mask,
+            operator,
+            value,
+        } => {
+            let masked = FileValue::Integer((file.permissions & mask & PERMISSION_BITS).into());
+            let value = coerce_literal(&FileAttribute::Permissions, value)?;
+            compare_values(&masked, operator, &value, file.time_zone)
+        }
+        FileCondition::Budget { condition, limit } => {
+            budget::spend(*limit, || evaluate_condition(file, condition))?
+        }
+    }
+}
+
+/// Matches a file against a LIKE or REGEXP condition: None if `condition`
+/// is neither, and Some(None) if the attribute is NULL, which matches no
+/// pattern. Other values that aren't text are an error.
+fn match_pattern(file: &FileResult, condition: &FileCondition) -> Result<Option<Option<bool>>> {
+    let (attribute, regex, keyword) = match condition {
+        FileCondition::Like {
+            attribute,
+            pattern,
+            case_sensitive,
+        } if file.semantics.like_is_regex() => {
+            (attribute, like_regex_0_3(pattern, *case_sensitive)?, "LIKE")
+        }
+        FileCondition::Like {
+            attribute,
+            pattern,
+            case_sensitive,
+        } => (attribute, like_regex(pattern, *case_sensitive)?, "LIKE"),
+        FileCondition::Regexp { attribute, pattern } => {
+            (attribute, regexp_regex(attribute, pattern)?, "REGEXP")
+        }
+        _ => return Ok(None),
+    };
+    match get_attribute_value(file, attribute)? {
+        FileValue::String(s) => Ok(Some(Some(regex.is_match(&s)))),
+        FileValue::Null if !file.semantics.patterns_fail_on_null() => Ok(Some(None)),
+        other => Err(ExecutorError::TypeError(format!(
+            "{} can only be used with string attributes, got {:?}",
+            keyword, other
+        ))),
+    }
+}
+
+/// Interprets a literal in the context of the attribute it is compared with.
+///
+/// Permissions are written as octal strings (`permissions = '644'`), so
+/// string literals compared against permissions are converted to the
+/// corresponding mode bits.
+fn coerce_literal(attribute: &FileAttribute, value: &FileValue) -> Result<FileValue> {
+    match (attribute, value) {
+        (FileAttribute::Permissions, FileValue::String(s)) => parse_octal(s)
+            .map(|mode| FileValue::Integer(mode.into()))
+            .ok_or_else(|| {
+                ExecutorError::TypeError(format!("Invalid permissions value: {}", s))
+            }),
+        _ => Ok(value.clone()),
+    }
+}
+
+/// Gets the value of an attribute of a listed file that couldn't be read:
+/// what its path says, and what is known of why it couldn't be read.
+fn unread_value(file: &FileResult, unread: &Unread, attribute: &FileAttribute) -> FileValue {
+    match attribute {
+        FileAttribute::Name => FileValue::String(file.name.clone()),
+        FileAttribute::Path => FileValue::String(file.path.to_string_lossy().to_string()),
+        FileAttribute::Extension => extension_value(file),
+        FileAttribute::SourceRoot => file
+            .source
+            .as_ref()
+            .and_then(|source| source.root.as_deref())
+            .map_or(FileValue::Null, |root| FileValue::String(display_path(root))),
+        FileAttribute::ScanId => file
+            .source
+            .as_ref()
+            .map_or(FileValue::Null, |source| FileValue::String(source.id.to_string())),
+        FileAttribute::Exists => FileValue::Boolean(unread.exists),
+        FileAttribute::WasMovedHint => unread
+            .moved_hint
+            .as_deref()
+            .map_or(FileValue::Null, |hint| FileValue::String(display_path(hint))),
+        FileAttribute::MetadataError => FileValue::String(unread.error.clone()),
+        _ => FileValue::Null,
+    }
+}
+
+/// Returns a file's `extension`: NULL if it has none, or an empty string
+/// under the semantics of fmql 0.3.
+fn extension_value(file: &FileResult) -> FileValue {
+    match file.extension.as_deref() {
+        Some(extension) => FileValue::String(extension.to_string()),
+        None if file.semantics.blank_extensions() => FileValue::String(String::new()),
+        None => FileValue::Null,
+    }
+}
+
+/// Gets the value of a file attribute.
+fn get_attribute_value(file: &FileResult, attribute: &FileAttribute) -> Result<FileValue> {
+    metered(file, || attribute_value(file, attribute))
+}
+
+/// Runs a read of a file's contents against the meter of the query run
+/// that found it, at the pace of [`ExecutionOptions::throttle`], and fails
+/// the query once the run's reads pass [`ResourceLimits::max_bytes_read`].
+fn metered<T>(file: &FileResult, read: impl FnOnce() -> Result<T>) -> Result<T> {
+    match &file.meter {
+        Some(meter) => meter::metered(meter, read)
+            .map_err(|overrun| LimitError::BytesRead(overrun.limit))?,
+        None => read(),
+    }
+}
+
+/// Returns the value of an attribute for a file, for [`get_attribute_value`].
+fn attribute_value(file: &FileResult, attribute: &FileAttribute) -> Result<FileValue> {
+    if let Some(unread) = &file.unread {
+        return Ok(unread_value(file, unread, attribute));
+    }
+    match attribute {
+        FileAttribute::Exists => Ok(FileValue::Boolean(true)),
+        FileAttribute::WasMovedHint | FileAttribute::MetadataError => Ok(FileValue::Null),
+        FileAttribute::Name => Ok(FileValue::String(file.name.clone())),
+        FileAttribute::Path => Ok(FileValue::String(file.path.to_string_lossy().to_string())),
+        FileAttribute::Size => Ok(FileValue::from_u64(file.size)),
+        FileAttribute::Extension => Ok(extension_value(file)),
+        FileAttribute::Modified => Ok(FileValue::DateTime(file.modified)),
+        FileAttribute::Accessed => Ok(FileValue::DateTime(file.accessed)),
+        FileAttribute::Age => {
+            Ok(FileValue::Duration(file.now.unwrap_or_else(Utc::now) - file.modified))
+        }
+        FileAttribute::SourceRoot => Ok(file
+            .source
+            .as_ref()
+            .and_then(|source| source.root.as_deref())
+            .map_or(FileValue::Null, |root| FileValue::String(display_path(root)))),
+        FileAttribute::ScanId => Ok(file
+            .source
+            .as_ref()
+            .map_or(FileValue::Null, |source| FileValue::String(source.id.to_string()))),
+        FileAttribute::Created => {
+            // Not every file system records when a file was created
+            let created = fs::metadata(&file.path).and_then(|metadata| metadata.created());
+            Ok(created.map_or(FileValue::Null, |time| FileValue::DateTime(time.into())))
+        }
+        FileAttribute::Permissions => Ok(FileValue::Integer(
+            (file.permissions & PERMISSION_BITS).into(),
+        )),
+        FileAttribute::IsDirectory => Ok(FileValue::Boolean(file.is_directory)),
+        FileAttribute::IsSymlink => Ok(FileValue::Boolean(file.is_symlink)),
+        FileAttribute::Owner => {
+            if let Some(owner) = &file.owner {
+                Ok(FileValue::String(owner.to_string()))
+            } else {
+                Ok(FileValue::Null)
+            }
+        }
+        FileAttribute::IsExecutable => {
+            // Check if file has executable bit set for user
+            let is_executable = file.permissions & 0o100 != 0;
+            Ok(FileValue::Boolean(is_executable))
+        }
+        FileAttribute::IsWorldReadable => Ok(FileValue::Boolean(file.permissions & 0o004 != 0)),
+        FileAttribute::IsGroupWritable => Ok(FileValue::Boolean(file.permissions & 0o020 != 0)),
+        FileAttribute::IsOtherExecutable => Ok(FileValue::Boolean(file.permissions & 0o001 != 0)),
+        FileAttribute::Group => {
+            let metadata = fs::symlink_metadata(&file.path).ok();
+            Ok(metadata.map_or(FileValue::Null, |m| FileValue::String(group_name(m.gid()))))
+        }
+        FileAttribute::IsMine => Ok(file.owner.as_ref().map_or(FileValue::Null, |owner| {
+            FileValue::Boolean(**owner == current_user())
+        })),
+        FileAttribute::Preview => {
+            let preview = match &file.preview {
+                Some(preview) => Some(preview.clone()),
+                None => read_preview(&file.path, file.encoding).ok().flatten(),
+            };
+            Ok(preview.map_or(FileValue::Null, FileValue::String))
+        }
+        FileAttribute::SignatureExtension => {
+            let signature = read_signature(&file.path).ok().flatten();
+            Ok(signature.map_or(FileValue::Null, |s| FileValue::String(s.extension.to_string())))
+        }
+        FileAttribute::ExtensionMismatch => {
+            let signature = read_signature(&file.path).ok().flatten();
+            let extension = file.extension.as_deref().unwrap_or_default();
+            Ok(FileValue::Boolean(signature.is_some_and(|s| !s.accepts(extension))))
+        }
+        FileAttribute::Flags => {
+            let bits = flags::read_flags(&file.path).ok().flatten();
+            Ok(bits.map_or(FileValue::Null, |bits| FileValue::String(flags::format_flags(bits))))
+        }
+        FileAttribute::FsFlags => {
+            let bits = chattr::read_fs_flags(&file.path).ok().flatten();
+            let text = bits.map(chattr::format_fs_flags);
+            Ok(text.map_or(FileValue::Null, FileValue::String))
+        }
+        FileAttribute::SecurityContext => {
+            let context = selinux::read_context(&file.path).ok().flatten();
+            Ok(context.map_or(FileValue::Null, FileValue::String))
+        }
+        FileAttribute::FileCapabilities => {
+            let caps = filecaps::read_capabilities(&file.path).ok().flatten();
+            Ok(caps.map_or(FileValue::Null, FileValue::String))
+        }
+        FileAttribute::HasFileCapabilities => {
+            if !filecaps::supported() {
+                return Ok(FileValue::Null);
+            }
+            let caps = filecaps::read_capabilities(&file.path);
+            Ok(caps.map_or(FileValue::Null, |caps| FileValue::Boolean(caps.is_some())))
+        }
+        FileAttribute::Quarantine => {
+            let record = quarantine::read_quarantine(&file.path).ok().flatten();
+            Ok(record.map_or(FileValue::Null, |record| FileValue::String(record.agent)))
+        }
+        FileAttribute::WhereFrom => {
+            let urls = quarantine::read_where_from(&file.path).ok().flatten();
+            Ok(urls.map_or(FileValue::Null, FileValue::String))
+        }
+        FileAttribute::PdfPageCount | FileAttribute::DocTitle | FileAttribute::DocAuthor => {
+            let info = read_docinfo(&file.path).ok().flatten().unwrap_or_default();
+            Ok(match attribute {
+                FileAttribute::PdfPageCount => {
+                    info.pages.map_or(FileValue::Null, FileValue::from_u64)
+                }
+                FileAttribute::DocTitle => info.title.map_or(FileValue::Null, FileValue::String),
+                _ => info.author.map_or(FileValue::Null, FileValue::String),
+            })
+        }
+        FileAttribute::LineEndings => {
+            let endings = newlines::read_line_endings(&file.path).ok().flatten();
+            Ok(endings.map_or(FileValue::Null, |endings| FileValue::String(endings.to_string())))
+        }
+        FileAttribute::HasBom => {
+            let bom = newlines::read_has_bom(&file.path).ok();
+            Ok(bom.map_or(FileValue::Null, FileValue::Boolean))
+        }
+        FileAttribute::BinaryFormat | FileAttribute::TargetArch | FileAttribute::IsStripped => {
+            let Some(binary) = executables::read_executable(&file.path).ok().flatten() else {
+                return Ok(FileValue::Null);
+            };
+            Ok(match attribute {
+                FileAttribute::BinaryFormat => FileValue::String(binary.format.to_string()),
+                FileAttribute::TargetArch => binary.arch.map_or(FileValue::Null, FileValue::String),
+                _ => binary.stripped.map_or(FileValue::Null, FileValue::Boolean),
+            })
+        }
+        FileAttribute::OriginalPath | FileAttribute::DeletedAt => {
+            let Some(info) = trash::read_info(&file.path).ok().flatten() else {
+                return Ok(FileValue::Null);
+            };
+            Ok(match attribute {
+                FileAttribute::OriginalPath => {
+                    FileValue::String(info.original_path.to_string_lossy().to_string())
+                }
+                _ => info.deleted_at.map_or(FileValue::Null, FileValue::DateTime),
+            })
+        }
+        FileAttribute::UncompressedSize => {
+            let size = uncompressed_size(&file.path).ok().flatten();
+            Ok(size.map_or(FileValue::Null, FileValue::from_u64))
+        }
+        FileAttribute::DiskUsage => {
+            // A symlink is counted as itself, as du does
+            let metadata = fs::symlink_metadata(&file.path).ok();
+            Ok(metadata.map_or(FileValue::Null, |m| FileValue::from_u64(disk_usage(&m))))
+        }
+        FileAttribute::IsSparse => {
+            let metadata = fs::symlink_metadata(&file.path).ok();
+            Ok(metadata.map_or(FileValue::Null, |m| {
+                FileValue::Boolean(m.is_file() && disk_usage(&m) < m.len())
+            }))
+        }
+        FileAttribute::SharedExtents => {
+            let shared = extents::shared_bytes(&file.path).ok().flatten();
+            Ok(shared.map_or(FileValue::Null, FileValue::from_u64))
+        }
+        FileAttribute::Content => {
+            let read = || match fs::metadata(&file.path)? {
+                metadata if metadata.is_file() => {
+                    read_text(&file.path, file.decompress, file.encoding).map(Some)
+                }
+                _ => Ok(None),
+            };
+            // A read that still fails with an error that may pass is a fault
+            // to log, not a file without contents
+            let content = match file.retry.run(read) {
+                Err(err) if err.is_transient() => return Err(err.into()),
+                read => read.ok().flatten(),
+            };
+            Ok(content.map_or(FileValue::Null, FileValue::String))
+        }
+        _ => Err(ExecutorError::UnsupportedAttribute(format!(
+            "Attribute not supported in conditions: {:?}",
+            attribute
+        ))),
+    }
+}
+
+/// Returns the bytes allocated to a file on disk. `st_blocks` counts
+/// 512-byte units whatever the file system's block size.
+fn disk_usage(metadata: &fs::Metadata) -> u64 {
+    metadata.blocks().saturating_mul(512)
+}
+
+/// Compares two values.
+pub(crate) fn compare_values(
+    left: &FileValue,
+    operator: &ComparisonOperator,
+    right: &FileValue,
+    zone: Tz,
+) -> Result<bool> {
+    match (left, right) {
+        (FileValue::String(l), FileValue::String(r)) => match operator {
+            ComparisonOperator::Eq => Ok(l == r),
+            ComparisonOperator::NotEq => Ok(l != r),
+            ComparisonOperator::Lt => Ok(l < r),
+            ComparisonOperator::LtEq => Ok(l <= r),
+            ComparisonOperator::Gt => Ok(l > r),
+            ComparisonOperator::GtEq => Ok(l >= r),
+        },
+        // Whole numbers compare exactly, with each other and with floats
+        (
+            FileValue::Integer(_) | FileValue::Number(_),
+            FileValue::Integer(_) | FileValue::Number(_),
+        ) => {
+            let ordering = sort::compare_numbers(left, right);
+            Ok(match operator {
+                ComparisonOperator::Eq => ordering.is_some_and(std::cmp::Ordering::is_eq),
+                ComparisonOperator::NotEq => !ordering.is_some_and(std::cmp::Ordering::is_eq),
+                ComparisonOperator::Lt => ordering.is_some_and(std::cmp::Ordering::is_lt),
+                ComparisonOperator::LtEq => ordering.is_some_and(std::cmp::Ordering::is_le),
+                ComparisonOperator::Gt => ordering.is_some_and(std::cmp::Ordering::is_gt),
+                ComparisonOperator::GtEq => ordering.is_some_and(std::cmp::Ordering::is_ge),
+            })
+        }
+        (FileValue::DateTime(l), FileValue::DateTime(r)) => match operator {
+            ComparisonOperator::Eq => Ok(l == r),
+            ComparisonOperator::NotEq => Ok(l != r),
+            ComparisonOperator::Lt => Ok(l < r),
+            ComparisonOperator::LtEq => Ok(l <= r),
+            ComparisonOperator::Gt => Ok(l > r),
+            ComparisonOperator::GtEq => Ok(l >= r),
+        },
+        (FileValue::Duration(l), FileValue::Duration(r)) => match operator {
+            ComparisonOperator::Eq => Ok(l == r),
+            ComparisonOperator::NotEq => Ok(l != r),
+            ComparisonOperator::Lt => Ok(l < r),
+            ComparisonOperator::LtEq => Ok(l <= r),
+            ComparisonOperator::Gt => Ok(l > r),
+            ComparisonOperator::GtEq => Ok(l >= r),
+        },
+        // Text compared with a duration is an interval, `age > '90 days'`,
+        // and a number is seconds
+        (FileValue::Duration(_), FileValue::String(s)) => {
+            let r = parse_interval(s).map_err(ExecutorError::TypeError)?;
+            compare_values(left, operator, &FileValue::Duration(r), zone)
+        }
+        (FileValue::String(s), FileValue::Duration(_)) => {
+            let l = parse_interval(s).map_err(ExecutorError::TypeError)?;
+            compare_values(&FileValue::Duration(l), operator, right, zone)
+        }
+        (FileValue::Duration(_), FileValue::Integer(_) | FileValue::Number(_)) => {
+            compare_values(&as_seconds(left), operator, right, zone)
+        }
+        (FileValue::Integer(_) | FileValue::Number(_), FileValue::Duration(_)) => {
+            compare_values(left, operator, &as_seconds(right), zone)
+        }
+        (FileValue::DateTime(_), FileValue::String(s)) => {
+            let r = parse_datetime_literal(s, zone)?;
+            compare_values(left, operator, &FileValue::DateTime(r), zone)
+        }
+        (FileValue::String(s), FileValue::DateTime(_)) => {
+            let l = parse_datetime_literal(s, zone)?;
+            compare_values(&FileValue::DateTime(l), operator, right, zone)
+        }
+        // A number compared with a date is Unix seconds: `modified > 1700000000`
+        (FileValue::DateTime(_), n @ (FileValue::Integer(_) | FileValue::Number(_))) => {
+            let r = epoch_datetime(n.as_f64().unwrap_or_default())?;
+            compare_values(left, operator, &FileValue::DateTime(r), zone)
+        }
+        (n @ (FileValue::Integer(_) | FileValue::Number(_)), FileValue::DateTime(_)) => {
+            let l = epoch_datetime(n.as_f64().unwrap_or_default())?;
+            compare_values(&FileValue::DateTime(l), operator, right, zone)
+        }
+        (FileValue::Integer(_) | FileValue::Number(_), FileValue::String(s)) => {
+            match parse_numeric(s.trim()) {
+                Some(r) => compare_values(left, operator, &r, zone),
+                None => Err(ExecutorError::TypeError(format!(
+                    "Cannot compare a number with '{}'",
+                    s
+                ))),
+            }
+        }
+        (FileValue::String(s), FileValue::Integer(_) | FileValue::Number(_)) => {
+            match parse_numeric(s.trim()) {
+                Some(l) => compare_values(&l, operator, right, zone),
+                None => Err(ExecutorError::TypeError(format!(
+                    "Cannot compare '{}' with a number",
+                    s
+                ))),
+            }
+        }
+        (FileValue::Boolean(l), FileValue::Boolean(r)) => match operator {
+            ComparisonOperator::Eq => Ok(l == r),
+            ComparisonOperator::NotEq => Ok(l != r),
+            _ => Err(ExecutorError::UnsupportedOperation(format!(
+                "Operator {:?} not supported for boolean values",
+                operator
+            ))),
+        },
+        // As in SQL, nothing is less or greater than a missing value
+        (FileValue::Null, FileValue::Null) => Ok(*operator == ComparisonOperator::Eq),
+        (_, FileValue::Null) | (FileValue::Null, _) => Ok(*operator == ComparisonOperator::NotEq),
+        _ => Err(ExecutorError::TypeError(format!(
+            "Cannot compare values of different types: {:?} and {:?}",
+            left, right
+        ))),
+    }
+}
+
+/// Parses a date literal such as `'2025-01-01'` or `'2025-01-01 12:30:00'`
+/// as a local time in `zone`.
+///
+/// Dates without a time are taken as midnight.
+fn parse_datetime_literal(s: &str, zone: Tz) -> Result<DateTime<Utc>> {
+    timezone::parse_datetime(s, zone).ok_or_else(|| {
+        ExecutorError::TypeError(format!("Cannot interpret '{}' as a date", s))
+    })
+}
+
+/// Returns the instant a number of seconds since the Unix epoch names.
+fn epoch_datetime(seconds: f64) -> Result<DateTime<Utc>> {
+    let millis = (seconds * 1000.0).round();
+    DateTime::from_timestamp_millis(millis as i64)
+        .filter(|_| millis.is_finite())
+        .ok_or_else(|| ExecutorError::TypeError(format!("{} seconds is not a date", seconds)))
+}
+
+// Include the tests module
+#[cfg(test)]
+#[path = "executor_tests.rs"]
+mod tests;
+
@@ -0,0 +1,5403 @@
+#[cfg(test)]
+use crate::sql::ast::{
+    ArithmeticOperator, ComparisonOperator, ComputedColumn, ConflictPolicy, EntryType,
+    FileAttribute, FileAttributeUpdate, FileCondition, FileExpr, FileQuery, FileValue,
+    SubstringPosition,
+};
+use crate::sql::executor::{
+    execute_grouped, execute_query, execute_query_count, execute_query_first, execute_query_with_options, exists,
+    ConflictOutcome, ExecutionOptions, ExecutorError,
+    FileChange, LimitError, OperationStatus, QueryCursor, ResourceLimits,
+};
+use std::fs::{self, File};
+use std::io::Result as IoResult;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use tempfile::{TempDir, tempdir};
+
+fn setup_test_directory() -> TempDir {
+    let dir = tempdir().unwrap();
+
+    // Create some test files
+    let _ = create_test_file(dir.path(), "file1.txt", "Hello, world!");
+    let _ = create_test_file(dir.path(), "file2.txt", "This is a test.");
+    let _ = create_test_file(dir.path(), "config.ini", "[section]\nkey=value");
+    let _ = create_test_file(dir.path(), "script.sh", "#!/bin/bash\necho 'Hello'");
+
+    // Create a subdirectory
+    let subdir_path = dir.path().join("subdir");
+    fs::create_dir(&subdir_path).unwrap();
+    let _ = create_test_file(dir.path(), "subdir/file3.txt", "Nested file.");
+    let _ = create_test_file(dir.path(), "subdir/config.xml", "<config></config>");
+
+    // Make script.sh executable
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let script_path = dir.path().join("script.sh");
+        let mut perms = fs::metadata(&script_path).unwrap().permissions();
+        perms.set_mode(0o755);
+        fs::set_permissions(&script_path, perms).unwrap();
+    }
+
+    dir
+}
+
+fn create_test_file(directory: &Path, filename: &str, content: &str) -> IoResult<PathBuf> {
+    let path = directory.join(filename);
+    let mut file = File::create(&path)?;
+    file.write_all(content.as_bytes())?;
+    Ok(path)
+}
+
+#[test]
+fn test_execute_select_all() {
+    let dir = setup_test_directory();
+    let _dir_path = dir.path().to_string_lossy().to_string();
+
+    let query = FileQuery::Select {
+        path: dir.path().to_path_buf(),
+        recursive: false,
+        include_self: false,
+        entry_type: None,
+        join: None,
+        attributes: vec![FileAttribute::All],
+        computed: vec![],
+        condition: None,
+        aggregates: vec![],
+        group_by: vec![],
+        rollup: false,
+        order_by: vec![],
+        limit: None,
+        limit_size: None,
+        within: None,
+        sample: None,
+        sample_rows: None,
+    };
+
+    let results = execute_query(&query).unwrap();
+
+    // There should be 5 entries; the directory itself is not listed
+    assert_eq!(results.len(), 5);
+    assert!(!results.iter().any(|f| f.path == dir.path()));
+
+    // Verify some basic properties
+    let files: Vec<_> = results
+        .iter()
+        .filter(|f| !f.is_directory)
+        .map(|f| f.name.clone())
+        .collect();
+
+    assert!(files.contains(&"file1.txt".to_string()));
+    assert!(files.contains(&"file2.txt".to_string()));
+    assert!(files.contains(&"config.ini".to_string()));
+    assert!(files.contains(&"script.sh".to_string()));
+}
+
+#[test]
+fn test_execute_select_recursive() {
+    let dir = setup_test_directory();
+
+    let query = FileQuery::Select {
+        path: dir.path().to_path_buf(),
+        recursive: true,
+        include_self: false,
+        entry_type: None,
+        join: None,
+        attributes: vec![FileAttribute::All],
+        computed: vec![],
+        condition: None,
+        aggregates: vec![],
+        group_by: vec![],
+        rollup: false,
+        order_by: vec![],
+        limit: None,
+        limit_size: None,
+        within: None,
+        sample: None,
+        sample_rows: None,
+    };
+
+    let results = execute_query(&query).unwrap();
+
+    // There should be 7 entries (including the subdirectories)
+    assert_eq!(results.len(), 7);
+
+    let all_files: Vec<_> = results
+        .iter()
+        .map(|f| f.path.to_string_lossy().to_string())
+        .collect();
+
+    // Check for the presence of subdirectory files
+    assert!(all_files.iter().any(|p| p.contains("subdir/file3.txt")));
+    assert!(all_files.iter().any(|p| p.contains("subdir/config.xml")));
+}
+
+#[test]
+fn test_execute_select_include_self() {
+    let dir = setup_test_directory();
+
+    let select = |include_self| FileQuery::Select {
+        path: dir.path().to_path_buf(),
+        recursive: false,
+        include_self,
+        entry_type: None,
+        join: None,
+        attributes: vec![FileAttribute::All],
+        computed: vec![],
+        condition: None,
+        aggregates: vec![],
+        group_by: vec![],
+        rollup: false,
+        order_by: vec![],
+        limit: None,
+        limit_size: None,
+        within: None,
+        sample: None,
+        sample_rows: None,
+    };
+
+    let results = execute_query(&select(true)).unwrap();
+    assert_eq!(results.len(), 6);
+    assert!(results.iter().any(|f| f.path == dir.path()));
+
+    // The option has the same effect as the clause
+    let options = ExecutionOptions {
+        include_self: true,
+        ..Default::default()
+    };
+    let results = execute_query_with_options(&select(false), &options).unwrap();
+    assert_eq!(results.len(), 6);
+}
+
+#[test]
+fn test_execute_select_by_entry_type() {
+    let dir = setup_test_directory();
+    std::os::unix::fs::symlink(dir.path().join("subdir"), dir.path().join("link")).unwrap();
+
+    let select = |entry_type| FileQuery::Select {
+        path: dir.path().to_path_buf(),
+        recursive: true,
+        include_self: false,
+        entry_type: Some(entry_type),
+        join: None,
+        attributes: vec![FileAttribute::All],
+        computed: vec![],
+        condition: None,
+        aggregates: vec![],
+        group_by: vec![],
+        rollup: false,
+        order_by: vec![],
+        limit: None,
+        limit_size: None,
+        within: None,
+        sample: None,
+        sample_rows: None,
+    };
+    let names = |entry_type| {
+        let mut names: Vec<_> = execute_query(&select(entry_type))
+            .unwrap()
+            .into_iter()
+            .map(|f| f.name)
+            .collect();
+        names.sort();
+        names
+    };
+
+    // Files in subdirectories are still found, and the symlink is neither
+    // a file nor a directory
+    assert_eq!(
+        names(EntryType::File),
+        vec!["config.ini", "config.xml", "file1.txt", "file2.txt", "file3.txt", "script.sh"]
+    );
+    assert_eq!(names(EntryType::Directory), vec!["subdir"]);
+    assert_eq!(names(EntryType::Symlink), vec!["link"]);
+
+    // A file target is filtered too
+    let mut single = select(EntryType::Directory);
+    if let FileQuery::Select { path, .. } = &mut single {
+        *path = dir.path().join("file1.txt");
+    }
+    assert!(execute_query(&single).unwrap().is_empty());
+}
+
+#[test]
+fn test_execute_select_with_boolean_attributes() {
+    let dir = setup_test_directory();
+    std::os::unix::fs::symlink(dir.path().join("file1.txt"), dir.path().join("link.txt")).unwrap();
+
+    let select = |condition| FileQuery::Select {
+        path: dir.path().to_path_buf(),
+        recursive: false,
+        include_self: false,
+        entry_type: None,
+        join: None,
+        attributes: vec![FileAttribute::All],
+        computed: vec![],
+        condition: Some(condition),
+        aggregates: vec![],
+        group_by: vec![],
+        rollup: false,
+        order_by: vec![],
+        limit: None,
+        limit_size: None,
+        within: None,
+        sample: None,
+        sample_rows: None,
+    };
+    let is_true = |attribute| FileCondition::Compare {
+        attribute,
+        operator: ComparisonOperator::Eq,
+        value: FileValue::Boolean(true),
+    };
+
+    let links = execute_query(&select(is_true(FileAttribute::IsSymlink))).unwrap();
+    assert_eq!(links.len(), 1);
+    assert_eq!(links[0].name, "link.txt");
+    assert!(links[0].is_symlink);
+
+    assert_eq!(execute_query(&select(FileCondition::Constant(true))).unwrap().len(), 6);
+    assert!(execute_query(&select(FileCondition::Constant(false))).unwrap().is_empty());
+}
+
+#[test]
+fn test_execute_select_with_any_of_and_xor() {
+    let dir = setup_test_directory();
+
+    let names = |sql: &str| {
+        let sql = sql.replace("DIR", &dir.path().to_string_lossy());
+        let mut names: Vec<_> = execute_query(&crate::sql::parse_sql(&sql).unwrap())
+            .unwrap()
+            .into_iter()
+            .map(|f| f.name)
+            .collect();
+        names.sort();
+        names
+    };
+
+    assert_eq!(
+        names("SELECT * FROM 'DIR' WHERE ANY_OF(extension, 'ini', 'sh')"),
+        vec!["config.ini", "script.sh"]
+    );
+    // Exactly one of: a .txt file, or larger than 14 bytes
+    assert_eq!(
+        names("SELECT FILES FROM 'DIR' WHERE extension = 'txt' XOR size > 14"),
+        vec!["config.ini", "file1.txt", "script.sh"]
+    );
+}
+
+#[test]
+fn test_execute_select_with_extension_filter() {
+    let dir = setup_test_directory();
+
+    let condition = FileCondition::Compare {
+        attribute: FileAttribute::Extension,
+        operator: ComparisonOperator::Eq,
+        value: FileValue::String("txt".to_string()),
+    };
+
+    let query = FileQuery::Select {
+        path: dir.path().to_path_buf(),
+        recursive: true,
+        include_self: false,
+        entry_type: None,
+        join: None,
+        attributes: vec![FileAttribute::All],
+        computed: vec![],
+        condition: Some(condition),
+        aggregates: vec![],
+        group_by: vec![],
+        rollup: false,
+        order_by: vec![],
+        limit: None,
+        limit_size: None,
+        within: None,
+        sample: None,
+        sample_rows: None,
+    };
+
+    let results = execute_query(&query).unwrap();
+
+    // There should be 3 .txt files
+    assert_eq!(results.len(), 3);
+
+    // Verify they all have .txt extension
+    for file in &results {
+        assert_eq!(file.extension.as_deref().unwrap(), "txt");
+    }
+}
+
+#[test]
+fn test_execute_select_with_name_like() {
+    let dir = setup_test_directory();
+
+    let condition = FileCondition::Like {
+        attribute: FileAttribute::Name,
+        pattern: "%config%".to_string(),
+        case_sensitive: false,
+    };
+
+    let query = FileQuery::Select {
+        path: dir.path().to_path_buf(),
+        recursive: true,
+        include_self: false,
+        entry_type: None,
+        join: None,
+        attributes: vec![FileAttribute::All],
+        computed: vec![],
+        condition: Some(condition),
+        aggregates: vec![],
+        group_by: vec![],
+        rollup: false,
+        order_by: vec![],
+        limit: None,
+        limit_size: None,
+        within: None,
+        sample: None,
+        sample_rows: None,
+    };
+
+    let results = execute_query(&query).unwrap();
+
+    // There should be 2 files with "config" in the name
+    assert_eq!(results.len(), 2);
+
+    let names: Vec<_> = results.iter().map(|f| f.name.clone()).collect();
+    assert!(names.contains(&"config.ini".to_string()));
+    assert!(names.contains(&"config.xml".to_string()));
+}
+
+#[test]
+fn test_execute_select_with_size_comparison() {
+    let dir = setup_test_directory();
+
+    let condition = FileCondition::Compare {
+        attribute: FileAttribute::Size,
+        operator: ComparisonOperator::Gt,
+        value: FileValue::Integer(10),
+    };
+
+    let query = FileQuery::Select {
+        path: dir.path().to_path_buf(),
+        recursive: false,
+        include_self: false,
+        entry_type: None,
+        join: None,
+        attributes: vec![FileAttribute::All],
+        computed: vec![],
+        condition: Some(condition),
+        aggregates: vec![],
+        group_by: vec![],
+        rollup: false,
+        order_by: vec![],
+        limit: None,
+        limit_size: None,
+        within: None,
+        sample: None,
+        sample_rows: None,
+    };
+
+    let results = execute_query(&query).unwrap();
+
+    // Check that we only got files larger than 10 bytes
+    for file in &results {
+        assert!(file.size > 10);
+    }
+}
+
+#[test]
+fn test_execute_select_with_complex_condition() {
+    let dir = setup_test_directory();
+
+    // Find .txt files with size > 5 bytes
+    let txt_condition = FileCondition::Compare {
+        attribute: FileAttribute::Extension,
+        operator: ComparisonOperator::Eq,
+        value: FileValue::String("txt".to_string()),
+    };
+
+    let size_condition = FileCondition::Compare {
+        attribute: FileAttribute::Size,
+        operator: ComparisonOperator::Gt,
+        value: FileValue::Integer(5),
+    };
+
+    let combined_condition = FileCondition::And(Box::new(txt_condition), Box::new(size_condition));
+
+    let query = FileQuery::Select {
+        path: dir.path().to_path_buf(),
+        recursive: true,
+        include_self: false,
+        entry_type: None,
+        join: None,
+        attributes: vec![FileAttribute::All],
+        computed: vec![],
+        condition: Some(combined_condition),
+        aggregates: vec![],
+        group_by: vec![],
+        rollup: false,
+        order_by: vec![],
+        limit: None,
+        limit_size: None,
+        within: None,
+        sample: None,
+        sample_rows: None,
+    };
+
+    let results = execute_query(&query).unwrap();
+
+    // Verify results
+    for file in &results {
+        assert_eq!(file.extension.as_deref().unwrap(), "txt");
+        assert!(file.size > 5);
+    }
+}
+
+#[test]
+fn test_execute_select_with_regexp() {
+    let dir = setup_test_directory();
+
+    let condition = FileCondition::Regexp {
+        attribute: FileAttribute::Name,
+        pattern: "^file[0-9]\\.txt$".to_string(),
+    };
+
+    let query = FileQuery::Select {
+        path: dir.path().to_path_buf(),
+        recursive: true,
+        include_self: false,
+        entry_type: None,
+        join: None,
+        attributes: vec![FileAttribute::All],
+        computed: vec![],
+        condition: Some(condition),
+        aggregates: vec![],
+        group_by: vec![],
+        rollup: false,
+        order_by: vec![],
+        limit: None,
+        limit_size: None,
+        within: None,
+        sample: None,
+        sample_rows: None,
+    };
+
+    let results = execute_query(&query).unwrap();
+
+    // There should be 3 files matching the pattern (file1.txt, file2.txt, and file3.txt)
+    assert_eq!(results.len(), 3);
+
+    let names: Vec<_> = results.iter().map(|f| f.name.clone()).collect();
+    assert!(names.contains(&"file1.txt".to_string()));
+    assert!(names.contains(&"file2.txt".to_string()));
+    assert!(names.contains(&"file3.txt".to_string()));
+}
+
+#[test]
+#[cfg(unix)]
+fn test_execute_update_permissions() {
+    let dir = setup_test_directory();
+
+    let condition = FileCondition::Compare {
+        attribute: FileAttribute::Extension,
+        operator: ComparisonOperator::Eq,
+        value: FileValue::String("txt".to_string()),
+    };
+
+    let updates = vec![FileAttributeUpdate {
+        attribute: FileAttribute::Permissions,
+        value: FileExpr::Literal(FileValue::String("644".to_string())),
+    }];
+
+    let query = FileQuery::Update {
+        path: dir.path().to_path_buf(),
+        include_self: false,
+        entry_type: None,
+        updates,
+        condition: Some(condition),
+        on_conflict: ConflictPolicy::Fail,
+    };
+
+    let updated_files = execute_query(&query).unwrap();
+
+    // Check that permissions were updated
+    for file in &updated_files {
+        assert_eq!(file.permissions & 0o777, 0o644);
+    }
+}
+
+
+#[test]
+#[cfg(unix)]
+fn test_execute_select_with_permission_helpers() {
+    let dir = setup_test_directory();
+
+    let select = |condition: FileCondition| FileQuery::Select {
+        path: dir.path().to_path_buf(),
+        recursive: false,
+        include_self: false,
+        entry_type: None,
+        join: None,
+        attributes: vec![FileAttribute::All],
+        computed: vec![],
+        condition: Some(condition),
+        aggregates: vec![],
+        group_by: vec![],
+        rollup: false,
+        order_by: vec![],
+        limit: None,
+        limit_size: None,
+        within: None,
+        sample: None,
+        sample_rows: None,
+    };
+
+    let octal = execute_query(&select(FileCondition::Compare {
+        attribute: FileAttribute::Permissions,
+        operator: ComparisonOperator::Eq,
+        value: FileValue::String("755".to_string()),
+    }))
+    .unwrap();
+    let names: Vec<_> = octal.iter().filter(|f| !f.is_directory).map(|f| f.name.clone()).collect();
+    assert_eq!(names, vec!["script.sh".to_string()]);
+
+    let has_perm = execute_query(&select(FileCondition::And(
+        Box::new(FileCondition::HasPerm { spec: "u+x,go-w".to_string() }),
+        Box::new(FileCondition::Compare {
+            attribute: FileAttribute::IsDirectory,
+            operator: ComparisonOperator::Eq,
+            value: FileValue::Boolean(false),
+        }),
+    )))
+    .unwrap();
+    assert_eq!(has_perm.len(), 1);
+    assert_eq!(has_perm[0].name, "script.sh");
+
+    let pattern = execute_query(&select(FileCondition::PermMatches {
+        pattern: "??5".to_string(),
+    }))
+    .unwrap();
+    assert!(pattern.iter().any(|f| f.name == "script.sh"));
+    assert!(!pattern.iter().any(|f| f.name == "file1.txt"));
+}
+
+#[test]
+fn test_execute_select_with_substring_functions() {
+    let dir = setup_test_directory();
+    let _ = create_test_file(dir.path(), "100%_done.txt", "");
+
+    let names = |attribute, position, text: &str| {
+        let query = FileQuery::Select {
+            path: dir.path().to_path_buf(),
+            recursive: true,
+            include_self: false,
+            entry_type: None,
+            join: None,
+            attributes: vec![FileAttribute::All],
+            computed: vec![],
+            condition: Some(FileCondition::Substring {
+                attribute,
+                position,
+                text: text.to_string(),
+            }),
+            aggregates: vec![],
+            group_by: vec![],
+            rollup: false,
+            order_by: vec![],
+            limit: None,
+            limit_size: None,
+            within: None,
+            sample: None,
+            sample_rows: None,
+        };
+        let mut names: Vec<_> = execute_query(&query).unwrap().into_iter().map(|f| f.name).collect();
+        names.sort();
+        names
+    };
+
+    assert_eq!(names(FileAttribute::Name, SubstringPosition::Start, "file"), vec!["file1.txt", "file2.txt", "file3.txt"]);
+    assert_eq!(names(FileAttribute::Name, SubstringPosition::End, ".ini"), vec!["config.ini"]);
+    assert_eq!(names(FileAttribute::Path, SubstringPosition::Anywhere, "/subdir/"), vec!["config.xml", "file3.txt"]);
+    // LIKE wildcards are plain characters here
+    assert_eq!(names(FileAttribute::Name, SubstringPosition::Anywhere, "%_"), vec!["100%_done.txt"]);
+    assert!(names(FileAttribute::Name, SubstringPosition::Start, "FILE").is_empty());
+}
+
+#[test]
+fn test_execute_select_with_arithmetic() {
+    let dir = setup_test_directory();
+    let size_div = |divisor: &str| FileExpr::Binary {
+        left: Box::new(FileExpr::Attribute(FileAttribute::Size)),
+        operator: ArithmeticOperator::Div,
+        right: Box::new(FileExpr::Literal(FileValue::String(divisor.to_string()))),
+    };
+
+    // size / 2 > 7 keeps files over 14 bytes
+    let query = FileQuery::Select {
+        path: dir.path().to_path_buf(),
+        recursive: false,
+        include_self: false,
+        entry_type: Some(EntryType::File),
+        join: None,
+        attributes: vec![FileAttribute::Name],
+        computed: vec![
+            ComputedColumn { name: "half".to_string(), expr: size_div("2") },
+            ComputedColumn { name: "broken".to_string(), expr: size_div("0") },
+        ],
+        condition: Some(FileCondition::Expression {
+            left: size_div("2"),
+            operator: ComparisonOperator::Gt,
+            right: FileExpr::Literal(FileValue::Integer(7)),
+        }),
+        aggregates: vec![],
+        group_by: vec![],
+        rollup: false,
+        order_by: vec![],
+        limit: None,
+        limit_size: None,
+        within: None,
+        sample: None,
+        sample_rows: None,
+    };
+
+    let mut results = execute_query(&query).unwrap();
+    results.sort_by(|a, b| a.name.cmp(&b.name));
+    let names: Vec<_> = results.iter().map(|f| f.name.as_str()).collect();
+    assert_eq!(names, vec!["config.ini", "file2.txt", "script.sh"]);
+
+    let config = &results[0];
+    assert_eq!(config.computed["half"], FileValue::Number(9.5));
+    // Division by zero gives NULL rather than failing the query
+    assert_eq!(config.computed["broken"], FileValue::Null);
+}
+
+#[test]
+#[cfg(unix)]
+fn test_execute_select_with_permission_mask() {
+    let dir = setup_test_directory();
+
+    // Group or other execute bits set
+    let query = FileQuery::Select {
+        path: dir.path().to_path_buf(),
+        recursive: false,
+        include_self: false,
+        entry_type: None,
+        join: None,
+        attributes: vec![FileAttribute::All],
+        computed: vec![],
+        condition: Some(FileCondition::PermMask {
+            mask: 0o011,
+            operator: ComparisonOperator::Eq,
+            value: FileValue::String("011".to_string()),
+        }),
+        aggregates: vec![],
+        group_by: vec![],
+        rollup: false,
+        order_by: vec![],
+        limit: None,
+        limit_size: None,
+        within: None,
+        sample: None,
+        sample_rows: None,
+    };
+
+    let results = execute_query(&query).unwrap();
+    let names: Vec<_> = results.iter().filter(|f| !f.is_directory).map(|f| f.name.clone()).collect();
+    assert_eq!(names, vec!["script.sh".to_string()]);
+}
+
+#[test]
+#[cfg(unix)]
+fn test_execute_update_with_case() {
+    let dir = setup_test_directory();
+
+    let updates = vec![FileAttributeUpdate {
+        attribute: FileAttribute::Permissions,
+        value: FileExpr::Case {
+            branches: vec![(
+                FileCondition::Compare {
+                    attribute: FileAttribute::IsDirectory,
+                    operator: ComparisonOperator::Eq,
+                    value: FileValue::Boolean(true),
+                },
+                FileExpr::Literal(FileValue::String("750".to_string())),
+            )],
+            otherwise: Some(Box::new(FileExpr::Literal(FileValue::String("640".to_string())))),
+        },
+    }];
+
+    let query = FileQuery::Update {
+        path: dir.path().to_path_buf(),
+        include_self: false,
+        entry_type: None,
+        updates,
+        condition: None,
+        on_conflict: ConflictPolicy::Fail,
+    };
+
+    let updated_files = execute_query(&query).unwrap();
+    assert_eq!(updated_files.len(), 7);
+
+    for file in &updated_files {
+        let expected = if file.is_directory { 0o750 } else { 0o640 };
+        assert_eq!(file.permissions & 0o777, expected, "{}", file.path.display());
+    }
+}
+
+#[test]
+fn test_execute_update_rename_with_expression() {
+    let dir = setup_test_directory();
+
+    let updates = vec![FileAttributeUpdate {
+        attribute: FileAttribute::Name,
+        value: FileExpr::Function {
+            name: "CONCAT".to_string(),
+            args: vec![
+                FileExpr::Function {
+                    name: "UPPER".to_string(),
+                    args: vec![FileExpr::Function {
+                        name: "STEM".to_string(),
+                        args: vec![FileExpr::Attribute(FileAttribute::Name)],
+                    }],
+                },
+                FileExpr::Literal(FileValue::String("_v".to_string())),
+                FileExpr::Literal(FileValue::Integer(2)),
+                FileExpr::Literal(FileValue::String(".".to_string())),
+                FileExpr::Attribute(FileAttribute::Extension),
+            ],
+        },
+    }];
+
+    let query = FileQuery::Update {
+        path: dir.path().to_path_buf(),
+        include_self: false,
+        entry_type: None,
+        updates,
+        condition: Some(FileCondition::Compare {
+            attribute: FileAttribute::Extension,
+            operator: ComparisonOperator::Eq,
+            value: FileValue::String("txt".to_string()),
+        }),
+        on_conflict: ConflictPolicy::Fail,
+    };
+
+    let updated_files = execute_query(&query).unwrap();
+    let mut names: Vec<_> = updated_files.iter().map(|f| f.name.clone()).collect();
+    names.sort();
+    assert_eq!(names, vec!["FILE1_v2.txt", "FILE2_v2.txt", "FILE3_v2.txt"]);
+
+    assert!(dir.path().join("subdir/FILE3_v2.txt").exists());
+    assert!(!dir.path().join("file1.txt").exists());
+}
+
+#[test]
+fn test_execute_update_rename_refuses_to_overwrite() {
+    let dir = setup_test_directory();
+
+    let query = FileQuery::Update {
+        path: dir.path().to_path_buf(),
+        include_self: false,
+        entry_type: None,
+        updates: vec![FileAttributeUpdate {
+            attribute: FileAttribute::Name,
+            value: FileExpr::Literal(FileValue::String("file2.txt".to_string())),
+        }],
+        condition: Some(FileCondition::Compare {
+            attribute: FileAttribute::Name,
+            operator: ComparisonOperator::Eq,
+            value: FileValue::String("file1.txt".to_string()),
+        }),
+        on_conflict: ConflictPolicy::Fail,
+    };
+
+    let results = execute_query(&query).unwrap();
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].status, Some(OperationStatus::Failed));
+    assert!(results[0].error_message.as_ref().unwrap().contains("already exists"));
+    assert!(dir.path().join("file1.txt").exists());
+    assert_eq!(fs::read_to_string(dir.path().join("file2.txt")).unwrap(), "This is a test.");
+}
+
+#[test]
+fn test_execute_update_rename_conflict_policies() {
+    let dir = setup_test_directory();
+
+    let rename_to_file2 = |on_conflict| FileQuery::Update {
+        path: dir.path().to_path_buf(),
+        include_self: false,
+        entry_type: None,
+        updates: vec![FileAttributeUpdate {
+            attribute: FileAttribute::Name,
+            value: FileExpr::Literal(FileValue::String("file2.txt".to_string())),
+        }],
+        condition: Some(FileCondition::Compare {
+            attribute: FileAttribute::Name,
+            operator: ComparisonOperator::Eq,
+            value: FileValue::String("file1.txt".to_string()),
+        }),
+        on_conflict,
+    };
+
+    let skipped = execute_query(&rename_to_file2(ConflictPolicy::Skip)).unwrap();
+    assert_eq!(skipped.len(), 1);
+    assert_eq!(skipped[0].name, "file1.txt");
+    assert_eq!(skipped[0].conflict, Some(ConflictOutcome::Skipped));
+
+    let renamed = execute_query(&rename_to_file2(ConflictPolicy::Rename)).unwrap();
+    assert_eq!(renamed[0].name, "file2_1.txt");
+    assert_eq!(renamed[0].conflict, Some(ConflictOutcome::Renamed));
+    assert_eq!(fs::read_to_string(dir.path().join("file2_1.txt")).unwrap(), "Hello, world!");
+    assert_eq!(fs::read_to_string(dir.path().join("file2.txt")).unwrap(), "This is a test.");
+
+    fs::rename(dir.path().join("file2_1.txt"), dir.path().join("file1.txt")).unwrap();
+    let overwritten = execute_query(&rename_to_file2(ConflictPolicy::Overwrite)).unwrap();
+    assert_eq!(overwritten[0].conflict, Some(ConflictOutcome::Overwritten));
+    assert!(!dir.path().join("file1.txt").exists());
+    assert_eq!(fs::read_to_string(dir.path().join("file2.txt")).unwrap(), "Hello, world!");
+}
+
+#[test]
+fn test_execute_update_reports_per_file_status() {
+    let dir = setup_test_directory();
+
+    // file1.txt cannot take file2.txt's name, but file2.txt can be renamed
+    let query = FileQuery::Update {
+        path: dir.path().to_path_buf(),
+        include_self: false,
+        entry_type: None,
+        updates: vec![FileAttributeUpdate {
+            attribute: FileAttribute::Name,
+            value: FileExpr::Case {
+                branches: vec![
+                    (
+                        FileCondition::Compare {
+                            attribute: FileAttribute::Name,
+                            operator: ComparisonOperator::Eq,
+                            value: FileValue::String("file1.txt".to_string()),
+                        },
+                        FileExpr::Literal(FileValue::String("config.ini".to_string())),
+                    ),
+                    (
+                        FileCondition::Compare {
+                            attribute: FileAttribute::Name,
+                            operator: ComparisonOperator::Eq,
+                            value: FileValue::String("file2.txt".to_string()),
+                        },
+                        FileExpr::Literal(FileValue::String("renamed.txt".to_string())),
+                    ),
+                ],
+                otherwise: None,
+            },
+        }],
+        condition: Some(FileCondition::Compare {
+            attribute: FileAttribute::Extension,
+            operator: ComparisonOperator::Eq,
+            value: FileValue::String("txt".to_string()),
+        }),
+        on_conflict: ConflictPolicy::Fail,
+    };
+
+    let results = execute_query(&query).unwrap();
+    assert_eq!(results.len(), 3);
+
+    let failed = results.iter().find(|f| f.name == "file1.txt").unwrap();
+    assert_eq!(failed.status, Some(OperationStatus::Failed));
+    assert!(failed.error_message.is_some());
+    assert!(failed.changes.is_empty());
+
+    let renamed = results.iter().find(|f| f.name == "renamed.txt").unwrap();
+    assert_eq!(renamed.status, Some(OperationStatus::Ok));
+    assert_eq!(renamed.error_message, None);
+    assert_eq!(
+        renamed.changes,
+        vec![FileChange {
+            attribute: FileAttribute::Name,
+            old_value: FileValue::String("file2.txt".to_string()),
+            new_value: FileValue::String("renamed.txt".to_string()),
+        }]
+    );
+
+    let unchanged = results.iter().find(|f| f.name == "file3.txt").unwrap();
+    assert_eq!(unchanged.status, Some(OperationStatus::Skipped));
+}
+
+#[test]
+fn test_execute_update_in_parallel_renames_children_first() {
+    let dir = setup_test_directory();
+
+    let query = FileQuery::Update {
+        path: dir.path().join("subdir"),
+        include_self: true,
+        entry_type: None,
+        updates: vec![FileAttributeUpdate {
+            attribute: FileAttribute::Name,
+            value: FileExpr::Function {
+                name: "UPPER".to_string(),
+                args: vec![FileExpr::Attribute(FileAttribute::Name)],
+            },
+        }],
+        condition: None,
+        on_conflict: ConflictPolicy::Fail,
+    };
+
+    let options = ExecutionOptions {
+        jobs: 4,
+        ..Default::default()
+    };
+    let results = execute_query_with_options(&query, &options).unwrap();
+
+    assert_eq!(results.len(), 3);
+    assert!(results.iter().all(|f| f.status == Some(OperationStatus::Ok)));
+    // Results keep the listing order: the directory comes first
+    assert_eq!(results[0].name, "SUBDIR");
+    assert!(dir.path().join("SUBDIR/FILE3.TXT").exists());
+    assert!(dir.path().join("SUBDIR/CONFIG.XML").exists());
+}
+
+#[test]
+fn test_throttle_limits_rate() {
+    use crate::sql::throttle::{parse_rate, Throttle};
+    use std::time::{Duration, Instant};
+
+    assert_eq!(parse_rate("50MB/s").unwrap(), 50_000_000);
+    assert_eq!(parse_rate("2 KiB/s").unwrap(), 2048);
+    assert_eq!(parse_rate("1.5k").unwrap(), 1500);
+    assert_eq!(parse_rate("4096").unwrap(), 4096);
+    assert!(parse_rate("fast").is_err());
+    assert!(parse_rate("10 furlongs/s").is_err());
+    assert!(parse_rate("0MB/s").is_err());
+
+    let throttle = Throttle::new(10_000);
+    let start = Instant::now();
+    throttle.consume(1_000);
+    throttle.consume(1_000);
+    assert!(start.elapsed() >= Duration::from_millis(190));
+}
+
+#[test]
+fn test_throttle_paces_content_reads() {
+    use std::time::{Duration, Instant};
+
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("a.log"), "a".repeat(2_000)).unwrap();
+    fs::write(dir.path().join("b.bin"), "b".repeat(2_000)).unwrap();
+    let options = ExecutionOptions {
+        throttle: Some(10_000),
+        ..Default::default()
+    };
+    let run = |sql: &str| {
+        let sql = sql.replace("DIR", &dir.path().display().to_string());
+        let start = Instant::now();
+        let results = execute_query_with_options(&crate::sql::parse_sql(&sql).unwrap(), &options);
+        (results.unwrap().len(), start.elapsed())
+    };
+
+    // 4000 bytes at 10000 a second, whichever reader reads them
+    for sql in [
+        "SELECT * FROM DIR WHERE content LIKE '%a%'",
+        "SELECT * FROM DIR WHERE CONTAINS_BYTES(x'61')",
+    ] {
+        let (found, took) = run(sql);
+        assert_eq!(found, 1, "{}", sql);
+        assert!(took >= Duration::from_millis(350), "{}: {:?}", sql, took);
+    }
+    // Metadata alone isn't held back
+    assert!(run("SELECT * FROM DIR").1 < Duration::from_millis(350));
+}
+
+#[test]
+fn test_throttle_paces_checksums_compress_and_archive() {
+    use std::time::{Duration, Instant};
+
+    let dir = tempdir().unwrap();
+    fs::create_dir(dir.path().join("src")).unwrap();
+    fs::write(dir.path().join("src/a.log"), "a".repeat(2_000)).unwrap();
+    let options = ExecutionOptions {
+        throttle: Some(10_000),
+        ..Default::default()
+    };
+    let run = |sql: &str| {
+        let sql = sql.replace("DIR", &dir.path().display().to_string());
+        let start = Instant::now();
+        let results = execute_query_with_options(&crate::sql::parse_sql(&sql).unwrap(), &options);
+        assert_eq!(results.unwrap()[0].status, Some(OperationStatus::Ok), "{}", sql);
+        start.elapsed()
+    };
+
+    // The copy, then both sides of its checksum: 6000 bytes
+    let took = run("COPY DIR/src TO DIR/copied USING BYTES VERIFY CHECKSUM");
+    assert!(took >= Duration::from_millis(550), "{:?}", took);
+    // The original, at least, for each
+    let took = run("ARCHIVE FROM DIR/copied INTO DIR/copied.tar");
+    assert!(took >= Duration::from_millis(150), "{:?}", took);
+    let took = run("COMPRESS DIR/src");
+    assert!(took >= Duration::from_millis(150), "{:?}", took);
+}
+
+#[test]
+fn test_execute_read_only_and_sandbox() {
+    let dir = setup_test_directory();
+
+    let update = FileQuery::Update {
+        path: dir.path().to_path_buf(),
+        include_self: false,
+        entry_type: None,
+        updates: vec![FileAttributeUpdate {
+            attribute: FileAttribute::Name,
+            value: FileExpr::Literal(FileValue::String("renamed.txt".to_string())),
+        }],
+        condition: Some(FileCondition::Compare {
+            attribute: FileAttribute::Name,
+            operator: ComparisonOperator::Eq,
+            value: FileValue::String("file1.txt".to_string()),
+        }),
+        on_conflict: ConflictPolicy::Fail,
+    };
+    let read_only = ExecutionOptions {
+        read_only: true,
+        ..Default::default()
+    };
+    assert!(matches!(
+        execute_query_with_options(&update, &read_only),
+        Err(ExecutorError::Denied(_))
+    ));
+    assert!(dir.path().join("file1.txt").exists());
+
+    let sandboxed = ExecutionOptions {
+        root: Some(dir.path().join("subdir")),
+        ..Default::default()
+    };
+    let select = |path: PathBuf| FileQuery::Select {
+        path,
+        recursive: true,
+        include_self: false,
+        entry_type: None,
+        join: None,
+        attributes: vec![FileAttribute::All],
+        computed: vec![],
+        condition: None,
+        aggregates: vec![],
+        group_by: vec![],
+        rollup: false,
+        order_by: vec![],
+        limit: None,
+        limit_size: None,
+        within: None,
+        sample: None,
+        sample_rows: None,
+    };
+    assert!(matches!(
+        execute_query_with_options(&select(dir.path().to_path_buf()), &sandboxed),
+        Err(ExecutorError::Denied(_))
+    ));
+    assert!(matches!(
+        execute_query_with_options(&select(dir.path().join("subdir/..")), &sandboxed),
+        Err(ExecutorError::Denied(_))
+    ));
+
+    // A symlink pointing out of the sandbox is neither listed nor followed
+    std::os::unix::fs::symlink(dir.path().join("file1.txt"), dir.path().join("subdir/escape"))
+        .unwrap();
+    let results =
+        execute_query_with_options(&select(dir.path().join("subdir")), &sandboxed).unwrap();
+    assert_eq!(results.len(), 2);
+    assert!(!results.iter().any(|f| f.name == "escape"));
+}
+
+#[test]
+fn test_execute_with_resource_limits() {
+    let dir = setup_test_directory();
+
+    let select = FileQuery::Select {
+        path: dir.path().to_path_buf(),
+        recursive: true,
+        include_self: false,
+        entry_type: None,
+        join: None,
+        attributes: vec![FileAttribute::All],
+        computed: vec![],
+        condition: None,
+        aggregates: vec![],
+        group_by: vec![],
+        rollup: false,
+        order_by: vec![],
+        limit: None,
+        limit_size: None,
+        within: None,
+        sample: None,
+        sample_rows: None,
+    };
+    let run = |limits: ResourceLimits| {
+        execute_query_with_options(
+            &select,
+            &ExecutionOptions {
+                limits,
+                ..Default::default()
+            },
+        )
+    };
+
+    let scanned = run(ResourceLimits {
+        max_files_scanned: Some(3),
+        ..Default::default()
+    });
+    assert!(matches!(
+        scanned,
+        Err(ExecutorError::LimitExceeded(LimitError::FilesScanned(3)))
+    ));
+
+    let results = run(ResourceLimits {
+        max_results: Some(5),
+        ..Default::default()
+    });
+    assert!(matches!(
+        results,
+        Err(ExecutorError::LimitExceeded(LimitError::Results(5)))
+    ));
+
+    // Depth limits prune instead of failing: the two files in subdir are skipped
+    let shallow = run(ResourceLimits {
+        max_depth: Some(1),
+        ..Default::default()
+    })
+    .unwrap();
+    assert_eq!(shallow.len(), 5);
+
+    assert_eq!(run(ResourceLimits::default()).unwrap().len(), 7);
+}
+
+#[test]
+fn test_content_reads_past_max_bytes_read_fail_the_query() {
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("big.log"), "a".repeat(100_000)).unwrap();
+    fs::write(dir.path().join("small.log"), "abc").unwrap();
+    let run = |sql: &str, max_bytes_read| {
+        let sql = sql.replace("DIR", &dir.path().display().to_string());
+        let options = ExecutionOptions {
+            limits: ResourceLimits {
+                max_bytes_read,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        execute_query_with_options(&crate::sql::parse_sql(&sql).unwrap(), &options)
+    };
+
+    let content = "SELECT * FROM DIR WHERE content LIKE '%aaa%'";
+    assert!(matches!(
+        run(content, Some(10)),
+        Err(ExecutorError::LimitExceeded(LimitError::BytesRead(10)))
+    ));
+    assert_eq!(run(content, Some(200_000)).unwrap().len(), 1);
+    assert_eq!(run(content, None).unwrap().len(), 1);
+
+    // Every reader of contents counts, and metadata alone reads nothing
+    for sql in [
+        "SELECT name, preview FROM DIR",
+        "SELECT * FROM DIR WHERE CONTAINS_BYTES(x'6162')",
+        "UPDATE DIR SET permissions = '600' WHERE content LIKE '%abc%'",
+    ] {
+        assert!(
+            matches!(run(sql, Some(2)), Err(ExecutorError::LimitExceeded(LimitError::BytesRead(2)))),
+            "{}",
+            sql
+        );
+    }
+    assert_eq!(run("SELECT * FROM DIR WHERE size > 10", Some(0)).unwrap().len(), 1);
+}
+
+#[test]
+fn test_a_read_that_panics_puts_back_the_meter_before_it() {
+    use crate::sql::meter::{charge, metered, Meter};
+    use std::sync::Arc;
+
+    let (outer, inner) = (Arc::new(Meter::new(None, None)), Arc::new(Meter::new(None, None)));
+    let read = metered(&outer, || {
+        let panicked = std::panic::catch_unwind(|| metered(&inner, || panic!("read failed")));
+        assert!(panicked.is_err());
+        // The outer read's meter counts again
+        charge(5).unwrap();
+    });
+    assert!(read.is_ok());
+    assert_eq!((outer.read(), inner.read()), (5, 0));
+    // Outside them both, nothing is charged
+    charge(7).unwrap();
+    assert_eq!((outer.read(), inner.read()), (5, 0));
+}
+
+#[test]
+fn test_execute_select_with_limit_and_within() {
+    use std::time::Duration;
+
+    let dir = setup_test_directory();
+    let select = |limit, within| FileQuery::Select {
+        path: dir.path().to_path_buf(),
+        recursive: true,
+        include_self: false,
+        entry_type: None,
+        join: None,
+        attributes: vec![FileAttribute::All],
+        computed: vec![],
+        condition: None,
+        aggregates: vec![],
+        group_by: vec![],
+        rollup: false,
+        order_by: vec![],
+        limit,
+        limit_size: None,
+        within,
+        sample: None,
+        sample_rows: None,
+    };
+
+    // LIMIT stops the walk at the first match, so a scan limit of one is enough
+    let options = ExecutionOptions {
+        limits: ResourceLimits {
+            max_files_scanned: Some(1),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let first = execute_query_with_options(&select(Some(1), None), &options).unwrap();
+    assert_eq!(first.len(), 1);
+    assert!(execute_query(&select(Some(0), None)).unwrap().is_empty());
+    assert_eq!(execute_query(&select(Some(100), None)).unwrap().len(), 7);
+
+    assert!(matches!(
+        execute_query(&select(None, Some(Duration::ZERO))),
+        Err(ExecutorError::LimitExceeded(LimitError::Timeout(_)))
+    ));
+    // The shorter of WITHIN and --timeout applies
+    let options = ExecutionOptions {
+        limits: ResourceLimits {
+            timeout: Some(Duration::ZERO),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    assert!(matches!(
+        execute_query_with_options(&select(None, Some(Duration::from_secs(60))), &options),
+        Err(ExecutorError::LimitExceeded(LimitError::Timeout(_)))
+    ));
+}
+
+#[test]
+fn test_limit_by_size_keeps_files_until_the_total_is_reached() {
+    let dir = tempdir().unwrap();
+    for (name, size, age) in [("a.log", 400, 40), ("b.log", 300, 30), ("c.log", 200, 20), ("d.log", 100, 10)] {
+        let path = dir.path().join(name);
+        fs::write(&path, vec![b'x'; size]).unwrap();
+        let modified = std::time::SystemTime::now() - std::time::Duration::from_secs(age * 86400);
+        File::options().write(true).open(&path).unwrap().set_modified(modified).unwrap();
+    }
+    let names = |sql: &str| -> Vec<String> {
+        let sql = sql.replace("{root}", &dir.path().display().to_string());
+        let results = crate::sql::run(&sql, &ExecutionOptions::default()).unwrap();
+        results.into_iter().map(|file| file.name).collect()
+    };
+
+    // The oldest files until 600 bytes are freed: the one that gets there is kept
+    assert_eq!(names("SELECT * FROM '{root}' ORDER BY modified LIMIT 600 BY size"), ["a.log", "b.log"]);
+    assert_eq!(names("SELECT * FROM '{root}' ORDER BY modified LIMIT 701 BY size"), ["a.log", "b.log", "c.log"]);
+    assert_eq!(names("SELECT * FROM '{root}' ORDER BY size LIMIT 1KB BY size").len(), 4);
+    assert!(names("SELECT * FROM '{root}' ORDER BY size LIMIT 0 BY size").is_empty());
+
+    // Without ORDER BY the walk stops once the total is reached
+    let options = ExecutionOptions { stable_order: false, ..Default::default() };
+    let sql = format!("SELECT * FROM '{}' LIMIT 1 BY size", dir.path().display());
+    let query = crate::sql::parse_sql(&sql).unwrap();
+    assert_eq!(execute_query_with_options(&query, &options).unwrap().len(), 1);
+    assert_eq!(execute_query_count(&query).unwrap(), 1);
+
+    let sql = format!("SELECT * FROM '{}' ORDER BY size DESC LIMIT 650 BY size", dir.path().display());
+    let mut cursor = QueryCursor::new(&crate::sql::parse_sql(&sql).unwrap(), &options).unwrap();
+    let page: Vec<String> = cursor.next_page(10).unwrap().into_iter().map(|file| file.name).collect();
+    assert_eq!(page, ["a.log", "b.log"]);
+    assert!(cursor.next_page(10).unwrap().is_empty());
+}
+
+#[test]
+fn test_random_order_and_row_samples_are_reproducible_with_a_seed() {
+    let dir = tempdir().unwrap();
+    for i in 0..20 {
+        fs::write(dir.path().join(format!("{:02}.dat", i)), vec![b'x'; i * 10]).unwrap();
+    }
+    let names = |sql: &str| -> Vec<String> {
+        let sql = sql.replace("{root}", &dir.path().display().to_string());
+        let options = ExecutionOptions { stable_order: false, ..Default::default() };
+        let results = crate::sql::run(&sql, &options).unwrap();
+        results.into_iter().map(|file| file.name).collect()
+    };
+
+    let shuffled = names("SELECT * FROM '{root}' ORDER BY RANDOM(7)");
+    assert_eq!(shuffled.len(), 20);
+    assert_eq!(shuffled, names("SELECT * FROM '{root}' ORDER BY RANDOM(7)"));
+    assert_ne!(shuffled, names("SELECT * FROM '{root}' ORDER BY name"));
+    assert_eq!(names("SELECT * FROM '{root}' ORDER BY RANDOM() LIMIT 5").len(), 5);
+
+    // A sample is picked from every match, then sorted and limited
+    let sample = names("SELECT * FROM '{root}' ORDER BY name SAMPLE 6 ROWS SEED 3");
+    assert_eq!(sample.len(), 6);
+    assert!(sample.is_sorted());
+    assert_eq!(sample, names("SELECT * FROM '{root}' ORDER BY name SAMPLE 6 ROWS SEED 3"));
+    assert_eq!(names("SELECT * FROM '{root}' ORDER BY name SAMPLE 6 ROWS SEED 3 LIMIT 2"), sample[..2]);
+    assert_eq!(names("SELECT * FROM '{root}' WHERE size < 50 SAMPLE 10 ROWS").len(), 5);
+
+    // Files with no weight are only picked when there aren't enough others
+    let weighted = names("SELECT * FROM '{root}' SAMPLE 19 ROWS WEIGHTED BY size");
+    assert!(!weighted.contains(&"00.dat".to_string()));
+
+    let sql = format!("SELECT * FROM '{}' SAMPLE 4 ROWS SEED 1", dir.path().display());
+    let query = crate::sql::parse_sql(&sql).unwrap();
+    assert_eq!(execute_query_count(&query).unwrap(), 4);
+    let mut cursor = QueryCursor::new(&query, &ExecutionOptions::default()).unwrap();
+    let page: Vec<String> = cursor.next_page(10).unwrap().into_iter().map(|file| file.name).collect();
+    assert_eq!(page, names("SELECT * FROM '{root}' SAMPLE 4 ROWS SEED 1"));
+}
+
+#[test]
+fn test_execute_query_first_and_exists() {
+    let dir = setup_test_directory();
+    let select = |name: &str| FileQuery::Select {
+        path: dir.path().to_path_buf(),
+        recursive: true,
+        include_self: false,
+        entry_type: None,
+        join: None,
+        attributes: vec![FileAttribute::All],
+        computed: vec![],
+        condition: Some(FileCondition::Compare {
+            attribute: FileAttribute::Name,
+            operator: ComparisonOperator::Eq,
+            value: FileValue::String(name.to_string()),
+        }),
+        aggregates: vec![],
+        group_by: vec![],
+        rollup: false,
+        order_by: vec![],
+        limit: None,
+        limit_size: None,
+        within: None,
+        sample: None,
+        sample_rows: None,
+    };
+
+    let first = execute_query_first(&select("file3.txt")).unwrap().unwrap();
+    assert_eq!(first.path, dir.path().join("subdir/file3.txt"));
+    assert!(exists(&select("config.xml")).unwrap());
+    assert!(!exists(&select("missing.txt")).unwrap());
+    assert!(execute_query_first(&select("missing.txt")).unwrap().is_none());
+
+    let update = FileQuery::Update {
+        path: dir.path().to_path_buf(),
+        include_self: false,
+        entry_type: None,
+        updates: vec![],
+        condition: None,
+        on_conflict: ConflictPolicy::Fail,
+    };
+    assert!(matches!(
+        execute_query_first(&update),
+        Err(ExecutorError::UnsupportedOperation(_))
+    ));
+}
+
+#[test]
+fn test_execute_query_count() {
+    let dir = setup_test_directory();
+    let select = |condition, limit| FileQuery::Select {
+        path: dir.path().to_path_buf(),
+        recursive: true,
+        include_self: false,
+        entry_type: None,
+        join: None,
+        attributes: vec![FileAttribute::All],
+        computed: vec![],
+        condition,
+        aggregates: vec![],
+        group_by: vec![],
+        rollup: false,
+        order_by: vec![],
+        limit,
+        limit_size: None,
+        within: None,
+        sample: None,
+        sample_rows: None,
+    };
+    let txt = || {
+        Some(FileCondition::Compare {
+            attribute: FileAttribute::Extension,
+            operator: ComparisonOperator::Eq,
+            value: FileValue::String("txt".to_string()),
+        })
+    };
+
+    assert_eq!(execute_query_count(&select(None, None)).unwrap(), 7);
+    assert_eq!(execute_query_count(&select(txt(), None)).unwrap(), 3);
+    assert_eq!(
+        execute_query_count(&select(txt(), None)).unwrap(),
+        execute_query(&select(txt(), None)).unwrap().len()
+    );
+    assert_eq!(execute_query_count(&select(None, Some(2))).unwrap(), 2);
+    assert_eq!(execute_query_count(&select(txt(), Some(0))).unwrap(), 0);
+}
+
+#[test]
+fn test_query_cursor_pages() {
+    let dir = setup_test_directory();
+    let select = |limit| FileQuery::Select {
+        path: dir.path().to_path_buf(),
+        recursive: true,
+        include_self: false,
+        entry_type: None,
+        join: None,
+        attributes: vec![FileAttribute::All],
+        computed: vec![],
+        condition: None,
+        aggregates: vec![],
+        group_by: vec![],
+        rollup: false,
+        order_by: vec![],
+        limit,
+        limit_size: None,
+        within: None,
+        sample: None,
+        sample_rows: None,
+    };
+
+    let mut cursor = QueryCursor::new(&select(None), &ExecutionOptions::default()).unwrap();
+    let mut paths = Vec::new();
+    for expected in [3, 3, 1, 0, 0] {
+        let page = cursor.next_page(3).unwrap();
+        assert_eq!(page.len(), expected);
+        paths.extend(page.into_iter().map(|file| file.path));
+    }
+    assert_eq!(cursor.fetched(), 7);
+    let mut all: Vec<_> = execute_query(&select(None))
+        .unwrap()
+        .into_iter()
+        .map(|file| file.path)
+        .collect();
+    paths.sort();
+    all.sort();
+    assert_eq!(paths, all);
+
+    // LIMIT caps the pages too
+    let mut cursor = QueryCursor::new(&select(Some(4)), &ExecutionOptions::default()).unwrap();
+    assert_eq!(cursor.next_page(3).unwrap().len(), 3);
+    assert_eq!(cursor.next_page(3).unwrap().len(), 1);
+    assert!(cursor.next_page(3).unwrap().is_empty());
+}
+
+#[test]
+fn test_execute_select_order_by() {
+    use crate::sql::ast::{OrderBy, SortKey};
+
+    let dir = tempdir().unwrap();
+    for name in ["file10.txt", "file2.txt", "file1.txt", "nested/file3.txt"] {
+        if let Some(parent) = Path::new(name).parent() {
+            fs::create_dir_all(dir.path().join(parent)).unwrap();
+        }
+        create_test_file(dir.path(), name, "x").unwrap();
+    }
+    let select = |order_by, limit| FileQuery::Select {
+        path: dir.path().to_path_buf(),
+        recursive: true,
+        include_self: false,
+        entry_type: Some(EntryType::File),
+        join: None,
+        attributes: vec![FileAttribute::All],
+        computed: vec![],
+        condition: None,
+        aggregates: vec![],
+        group_by: vec![],
+        rollup: false,
+        order_by,
+        limit,
+        limit_size: None,
+        within: None,
+        sample: None,
+        sample_rows: None,
+    };
+    let order = |key, descending, natural| OrderBy {
+        key,
+        descending,
+        natural,
+        collation: None,
+        nulls_first: None,
+    };
+    let names = |results: Vec<crate::sql::executor::FileResult>| {
+        results.into_iter().map(|file| file.name).collect::<Vec<_>>()
+    };
+
+    let natural = vec![order(SortKey::Attribute(FileAttribute::Name), false, true)];
+    assert_eq!(
+        names(execute_query(&select(natural, None)).unwrap()),
+        ["file1.txt", "file2.txt", "file3.txt", "file10.txt"]
+    );
+    let plain = vec![order(SortKey::Attribute(FileAttribute::Name), false, false)];
+    assert_eq!(
+        names(execute_query(&select(plain, None)).unwrap()),
+        ["file1.txt", "file10.txt", "file2.txt", "file3.txt"]
+    );
+
+    // Deepest first, then natural descending; LIMIT applies after sorting
+    let by_depth = vec![
+        order(SortKey::Depth, true, false),
+        order(SortKey::Attribute(FileAttribute::Name), true, true),
+    ];
+    assert_eq!(
+        names(execute_query(&select(by_depth.clone(), Some(2))).unwrap()),
+        ["file3.txt", "file10.txt"]
+    );
+
+    let mut cursor = QueryCursor::new(&select(by_depth, None), &ExecutionOptions::default()).unwrap();
+    assert_eq!(names(cursor.next_page(2).unwrap()), ["file3.txt", "file10.txt"]);
+    assert_eq!(names(cursor.next_page(5).unwrap()), ["file2.txt", "file1.txt"]);
+}
+
+#[test]
+fn test_execute_select_order_by_collate() {
+    use crate::sql::ast::{OrderBy, SortKey};
+
+    let dir = tempdir().unwrap();
+    for name in ["Zebra", "Äpfel", "Apfel", "Birne"] {
+        create_test_file(dir.path(), name, "x").unwrap();
+    }
+    let query = FileQuery::Select {
+        path: dir.path().to_path_buf(),
+        recursive: false,
+        include_self: false,
+        entry_type: None,
+        join: None,
+        attributes: vec![FileAttribute::All],
+        computed: vec![],
+        condition: None,
+        aggregates: vec![],
+        group_by: vec![],
+        rollup: false,
+        order_by: vec![OrderBy {
+            key: SortKey::Attribute(FileAttribute::Name),
+            descending: false,
+            natural: false,
+            collation: Some("de_DE.UTF-8".to_string()),
+            nulls_first: None,
+        }],
+        limit: None,
+        limit_size: None,
+        within: None,
+        sample: None,
+        sample_rows: None,
+    };
+
+    #[cfg(feature = "collation")]
+    {
+        let names: Vec<_> = execute_query(&query).unwrap().into_iter().map(|f| f.name).collect();
+        assert_eq!(names, ["Apfel", "Äpfel", "Birne", "Zebra"]);
+    }
+    #[cfg(not(feature = "collation"))]
+    assert!(matches!(
+        execute_query(&query),
+        Err(ExecutorError::UnsupportedOperation(_))
+    ));
+}
+
+#[test]
+fn test_execute_grouped() {
+    use crate::sql::ast::{AggregateColumn, AggregateFunction, OrderBy, SortKey};
+
+    let dir = setup_test_directory();
+    let aggregate = |name: &str, function, attribute: Option<FileAttribute>| AggregateColumn {
+        name: name.to_string(),
+        function,
+        argument: attribute.map(FileExpr::Attribute),
+    };
+    let grouped = |group_by: Vec<FileAttribute>, condition, order_by| FileQuery::Select {
+        path: dir.path().to_path_buf(),
+        recursive: true,
+        include_self: false,
+        entry_type: Some(EntryType::File),
+        join: None,
+        attributes: vec![],
+        computed: vec![],
+        aggregates: vec![
+            aggregate("files", AggregateFunction::Count, None),
+            aggregate("bytes", AggregateFunction::Sum, Some(FileAttribute::Size)),
+            aggregate("smallest", AggregateFunction::Min, Some(FileAttribute::Size)),
+            aggregate("mean", AggregateFunction::Avg, Some(FileAttribute::Size)),
+        ],
+        condition,
+        group_by: group_by
+            .into_iter()
+            .map(|attribute| ComputedColumn {
+                name: attribute.name().to_string(),
+                expr: FileExpr::Attribute(attribute),
+            })
+            .collect(),
+        rollup: false,
+        order_by,
+        limit: None,
+        limit_size: None,
+        within: None,
+        sample: None,
+        sample_rows: None,
+    };
+    let number = |n: i64| FileValue::Integer(n);
+
+    // Sizes: file1.txt 13, file2.txt 15, subdir/file3.txt 12, config.ini 19,
+    // config.xml 17, script.sh 24
+    let rows = execute_grouped(&grouped(vec![FileAttribute::Extension], None, vec![])).unwrap();
+    let extensions: Vec<_> = rows.iter().map(|row| row.get("extension").unwrap().clone()).collect();
+    assert_eq!(
+        extensions,
+        ["ini", "sh", "txt", "xml"].map(|ext| FileValue::String(ext.to_string()))
+    );
+    let txt = &rows[2];
+    assert_eq!(txt.get("files"), Some(&number(3)));
+    assert_eq!(txt.get("bytes"), Some(&number(40)));
+    assert_eq!(txt.get("smallest"), Some(&number(12)));
+    assert_eq!(txt.columns[0].0, "extension");
+
+    let descending = vec![OrderBy {
+        key: SortKey::Attribute(FileAttribute::Extension),
+        descending: true,
+        natural: false,
+        collation: None,
+        nulls_first: None,
+    }];
+    let rows = execute_grouped(&grouped(vec![FileAttribute::Extension], None, descending)).unwrap();
+    assert_eq!(rows[0].get("extension"), Some(&FileValue::String("xml".to_string())));
+
+    // Owners are looked up by name
+    let rows = execute_grouped(&grouped(vec![FileAttribute::Owner], None, vec![])).unwrap();
+    let total: i64 = rows
+        .iter()
+        .map(|row| match row.get("files") {
+            Some(FileValue::Integer(n)) => *n,
+            _ => 0,
+        })
+        .sum();
+    assert_eq!(total, 6);
+    assert!(rows.iter().all(|row| matches!(row.get("owner"), Some(FileValue::String(_)))));
+
+    // Without GROUP BY there is one row, even when nothing matches
+    let nothing = Some(FileCondition::Constant(false));
+    let rows = execute_grouped(&grouped(vec![], nothing, vec![])).unwrap();
+    assert_eq!(rows.len(), 1);
+    assert_eq!(rows[0].get("files"), Some(&number(0)));
+    assert_eq!(rows[0].get("bytes"), Some(&FileValue::Null));
+
+    let query = grouped(vec![FileAttribute::Extension], None, vec![]);
+    assert!(matches!(execute_query(&query), Err(ExecutorError::UnsupportedOperation(_))));
+}
+
+#[test]
+fn test_execute_select_by_accessed() {
+    let dir = setup_test_directory();
+    let old = filetime::FileTime::from_unix_time(1_577_836_800, 0); // 2020-01-01
+    filetime::set_file_atime(dir.path().join("file1.txt"), old).unwrap();
+
+    let query = FileQuery::Select {
+        path: dir.path().to_path_buf(),
+        recursive: true,
+        include_self: false,
+        entry_type: None,
+        join: None,
+        attributes: vec![FileAttribute::All],
+        computed: vec![],
+        aggregates: vec![],
+        condition: Some(FileCondition::Compare {
+            attribute: FileAttribute::Accessed,
+            operator: ComparisonOperator::Lt,
+            value: FileValue::String("2021-01-01".to_string()),
+        }),
+        group_by: vec![],
+        rollup: false,
+        order_by: vec![],
+        limit: None,
+        limit_size: None,
+        within: None,
+        sample: None,
+        sample_rows: None,
+    };
+    let results = execute_query(&query).unwrap();
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].name, "file1.txt");
+    assert_eq!(results[0].accessed.timestamp(), 1_577_836_800);
+}
+
+#[test]
+fn test_execute_select_preview() {
+    use crate::sql::encoding::TextEncoding;
+
+    let dir = setup_test_directory();
+    fs::write(dir.path().join("image.png"), b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR").unwrap();
+    let run = |sql: &str| {
+        let sql = sql.replace("DIR", &dir.path().to_string_lossy());
+        execute_query(&crate::sql::parse_sql(&sql).unwrap()).unwrap()
+    };
+
+    let results = run(
+        "SELECT name, preview FROM 'DIR' WHERE preview LIKE '%bash%' OR extension = 'png' ORDER BY name",
+    );
+    let previews: Vec<_> = results
+        .iter()
+        .map(|f| (f.name.as_str(), f.preview.as_deref()))
+        .collect();
+    assert_eq!(
+        previews,
+        [
+            ("image.png", Some("<binary> 89 50 4e 47 0d 0a 1a 0a 00 00 00 0d 49 48 44 52")),
+            ("script.sh", Some("#!/bin/bash ⏎ echo 'Hello'")),
+        ]
+    );
+
+    // Contents are only read when the query asks for them
+    assert!(run("SELECT * FROM 'DIR'").iter().all(|f| f.preview.is_none()));
+
+    // UTF-16 is read as text, with a byte order mark or without
+    let utf16 = |text: &str, unit: fn(u16) -> [u8; 2]| text.encode_utf16().flat_map(unit).collect::<Vec<u8>>();
+    let mut le = vec![0xff, 0xfe];
+    le.extend(utf16("Résumé\r\nline two\r\n", u16::to_le_bytes));
+    fs::write(dir.path().join("windows.log"), le).unwrap();
+    fs::write(dir.path().join("big_endian.txt"), utf16("hello world\n", u16::to_be_bytes)).unwrap();
+    let sql = format!("SELECT name, preview FROM '{}' WHERE ANY_OF(name, 'windows.log', 'big_endian.txt') ORDER BY name", dir.path().display());
+    let query = crate::sql::parse_sql(&sql).unwrap();
+    let previews = |encoding| {
+        let options = ExecutionOptions { encoding, ..ExecutionOptions::default() };
+        execute_query_with_options(&query, &options).unwrap().into_iter().map(|f| f.preview.unwrap()).collect::<Vec<_>>()
+    };
+    assert_eq!(previews(TextEncoding::Auto), ["hello world", "Résumé ⏎ line two"]);
+    // Read as UTF-8, as --encoding utf-8 says, they look binary
+    assert!(previews(TextEncoding::Utf8).iter().all(|preview| preview.starts_with("<binary>")));
+}
+
+#[test]
+fn test_execute_select_content_matches() {
+    let dir = setup_test_directory();
+    create_test_file(dir.path(), "notes.md", "# Notes\nTODO: call Bob\ndone\n// todo later\n").unwrap();
+    let run = |sql: &str| {
+        let sql = sql.replace("DIR", &dir.path().to_string_lossy());
+        execute_query(&crate::sql::parse_sql(&sql).unwrap())
+    };
+
+    // LIKE spans lines; REGEXP anchors match at each line
+    let results = run("WITH RECURSIVE SELECT name FROM 'DIR' WHERE content LIKE '%todo%'").unwrap();
+    assert_eq!(results.len(), 1);
+    assert!(results[0].matches.is_empty());
+    let results = run("WITH RECURSIVE SELECT name FROM 'DIR' WHERE content REGEXP '^echo'").unwrap();
+    assert_eq!(results[0].name, "script.sh");
+
+    let results = run(
+        "SELECT name, matches FROM 'DIR' WHERE content LIKE '%todo%' OR CONTAINS_STR(content, 'world')",
+    )
+    .unwrap();
+    let mut found: Vec<_> = results
+        .iter()
+        .flat_map(|f| f.matches.iter().map(|m| (f.name.as_str(), m.line, m.text.as_str())))
+        .collect();
+    found.sort();
+    assert_eq!(
+        found,
+        [
+            ("file1.txt", 1, "Hello, world!"),
+            ("notes.md", 2, "TODO: call Bob"),
+            ("notes.md", 4, "// todo later"),
+        ]
+    );
+
+    assert!(matches!(
+        run("SELECT matches FROM 'DIR' WHERE name = 'notes.md'"),
+        Err(ExecutorError::UnsupportedOperation(_))
+    ));
+}
+
+#[test]
+fn test_execute_select_contains_bytes() {
+    let dir = setup_test_directory();
+    fs::write(dir.path().join("photo.jpg"), b"\x7fELF\x02\x01\x01\x00").unwrap();
+    // A signature that straddles two of the blocks the search reads
+    let mut padded = vec![b'.'; 64 * 1024 - 2];
+    padded.extend_from_slice(b"PK\x03\x04");
+    fs::write(dir.path().join("padded.bin"), &padded).unwrap();
+    let names = |sql: &str| {
+        let sql = sql.replace("DIR", &dir.path().to_string_lossy());
+        let mut names: Vec<_> = execute_query(&crate::sql::parse_sql(&sql).unwrap())
+            .unwrap()
+            .into_iter()
+            .map(|f| f.name)
+            .collect();
+        names.sort();
+        names
+    };
+
+    assert_eq!(names("WITH RECURSIVE SELECT * FROM 'DIR' WHERE CONTAINS_BYTES(x'7f454c46')"), ["photo.jpg"]);
+    assert_eq!(names("SELECT * FROM 'DIR' WHERE CONTAINS_BYTES(x'504B0304')"), ["padded.bin"]);
+    assert!(names("SELECT * FROM 'DIR' WHERE CONTAINS_BYTES(x'504b0304', 4096)").is_empty());
+    assert_eq!(
+        names("SELECT * FROM 'DIR' WHERE NOT CONTAINS_BYTES(x'00') AND extension = 'txt'"),
+        ["file1.txt", "file2.txt"]
+    );
+}
+
+#[test]
+fn test_execute_select_by_signature() {
+    let dir = setup_test_directory();
+    let png = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR";
+    fs::write(dir.path().join("holiday.jpg"), png).unwrap();
+    fs::write(dir.path().join("logo.PNG"), png).unwrap();
+    fs::write(dir.path().join("report.docx"), b"PK\x03\x04\x14\0\x06\0").unwrap();
+    fs::write(dir.path().join("tool"), b"\x7fELF\x02\x01\x01\0").unwrap();
+    fs::write(dir.path().join("invoice.pdf"), b"\x7fELF\x02\x01\x01\0").unwrap();
+    let names = |sql: &str| {
+        let sql = sql.replace("DIR", &dir.path().to_string_lossy());
+        let mut names: Vec<_> = execute_query(&crate::sql::parse_sql(&sql).unwrap())
+            .unwrap()
+            .into_iter()
+            .map(|f| f.name)
+            .collect();
+        names.sort();
+        names
+    };
+
+    assert_eq!(names("SELECT * FROM 'DIR' WHERE extension_mismatch"), ["holiday.jpg", "invoice.pdf"]);
+    assert_eq!(
+        names("SELECT * FROM 'DIR' WHERE signature_extension != extension AND signature_extension != NULL"),
+        ["holiday.jpg", "invoice.pdf", "logo.PNG", "report.docx", "tool"]
+    );
+    assert_eq!(names("SELECT * FROM 'DIR' WHERE signature_extension = 'elf'"), ["invoice.pdf", "tool"]);
+}
+
+#[test]
+fn test_execute_select_from_stdin_list() {
+    let dir = setup_test_directory();
+    let query = crate::sql::parse_sql(
+        "SELECT * FROM STDIN WHERE name LIKE '%.txt' OR name = 'subdir' ORDER BY name",
+    )
+    .unwrap();
+    assert!(query.reads_stdin());
+
+    // Directories are candidates themselves, not listed; missing paths and
+    // duplicates are skipped
+    let options = ExecutionOptions {
+        files_from: Some(vec![
+            dir.path().join("subdir/file3.txt"),
+            dir.path().join("subdir"),
+            dir.path().join("config.ini"),
+            dir.path().join("deleted.txt"),
+            dir.path().join("file1.txt"),
+            dir.path().join("file1.txt"),
+        ]),
+        ..Default::default()
+    };
+    let results = execute_query_with_options(&query, &options).unwrap();
+    let names: Vec<_> = results.iter().map(|f| f.name.as_str()).collect();
+    assert_eq!(names, ["file1.txt", "file3.txt", "subdir"]);
+
+    let count = crate::sql::parse_sql("SELECT * FROM STDIN").unwrap();
+    assert_eq!(
+        crate::sql::execute_query_count_with_options(&count, &options).unwrap(),
+        4
+    );
+
+    // Outside the sandbox root
+    let sandboxed = ExecutionOptions {
+        root: Some(dir.path().join("subdir")),
+        ..options
+    };
+    assert!(matches!(
+        execute_query_with_options(&query, &sandboxed),
+        Err(ExecutorError::Denied(_))
+    ));
+}
+
+#[test]
+fn test_execute_reports_invalid_paths_before_scanning() {
+    use crate::sql::paths::{PathError, PathProblem};
+
+    let dir = setup_test_directory();
+    let select = |path: PathBuf| FileQuery::Select {
+        path,
+        recursive: false,
+        include_self: false,
+        entry_type: None,
+        join: None,
+        attributes: vec![FileAttribute::All],
+        computed: vec![],
+        condition: None,
+        aggregates: vec![],
+        group_by: vec![],
+        rollup: false,
+        order_by: vec![],
+        limit: None,
+        limit_size: None,
+        within: None,
+        sample: None,
+        sample_rows: None,
+    };
+
+    let typo = dir.path().join("subdri");
+    match execute_query(&select(typo.clone())) {
+        Err(ExecutorError::InvalidPath(PathError { path, problem, suggestion })) => {
+            assert_eq!(path, typo);
+            assert_eq!(problem, PathProblem::NotFound);
+            assert_eq!(suggestion, Some(dir.path().join("subdir")));
+        }
+        other => panic!("Expected an invalid path error, got {:?}", other),
+    }
+
+    // The suggestion keeps the components after the typo
+    let nested = dir.path().join("SubDir/deeper");
+    match execute_query(&select(nested)) {
+        Err(ExecutorError::InvalidPath(err)) => {
+            assert_eq!(err.suggestion, Some(dir.path().join("subdir/deeper")));
+        }
+        other => panic!("Expected an invalid path error, got {:?}", other),
+    }
+
+    match execute_query(&select(dir.path().join("nothing_like_it"))) {
+        Err(ExecutorError::InvalidPath(err)) => assert_eq!(err.suggestion, None),
+        other => panic!("Expected an invalid path error, got {:?}", other),
+    }
+
+    match execute_query(&select(dir.path().join("file1.txt/deeper"))) {
+        Err(ExecutorError::InvalidPath(err)) => {
+            assert_eq!(err.problem, PathProblem::NotADirectory);
+        }
+        other => panic!("Expected an invalid path error, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_execute_select_from_file_and_glob_targets() {
+    use crate::sql::paths::PathProblem;
+
+    let dir = setup_test_directory();
+    let select = |path: PathBuf, recursive| FileQuery::Select {
+        path,
+        recursive,
+        include_self: false,
+        entry_type: None,
+        join: None,
+        attributes: vec![FileAttribute::All],
+        computed: vec![],
+        condition: None,
+        aggregates: vec![],
+        group_by: vec![],
+        rollup: false,
+        order_by: vec![],
+        limit: None,
+        limit_size: None,
+        within: None,
+        sample: None,
+        sample_rows: None,
+    };
+
+    let single = execute_query(&select(dir.path().join("config.ini"), true)).unwrap();
+    assert_eq!(single.len(), 1);
+    assert_eq!(single[0].name, "config.ini");
+    assert_eq!(single[0].size, 19);
+
+    let globbed = execute_query(&select(dir.path().join("file*.txt"), false)).unwrap();
+    let mut names: Vec<_> = globbed.iter().map(|f| f.name.clone()).collect();
+    names.sort();
+    assert_eq!(names, vec!["file1.txt", "file2.txt"]);
+
+    // Matched directories are scanned like a plain FROM directory
+    let nested = execute_query(&select(dir.path().join("sub*"), false)).unwrap();
+    assert_eq!(nested.len(), 2);
+
+    match execute_query(&select(dir.path().join("*.md"), false)) {
+        Err(ExecutorError::InvalidPath(err)) => assert_eq!(err.problem, PathProblem::NoMatches),
+        other => panic!("Expected no matches, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_execute_select_with_directory_glob_roots() {
+    let dir = tempdir().unwrap();
+    for project in ["alpha", "beta", "gamma"] {
+        fs::create_dir_all(dir.path().join(project).join("target/debug")).unwrap();
+        create_test_file(dir.path(), &format!("{}/target/debug/app", project), "binary").unwrap();
+        create_test_file(dir.path(), &format!("{}/README.md", project), "docs").unwrap();
+    }
+
+    let query = FileQuery::Select {
+        path: dir.path().join("*/target"),
+        recursive: true,
+        include_self: false,
+        entry_type: None,
+        join: None,
+        attributes: vec![FileAttribute::All],
+        computed: vec![],
+        condition: Some(FileCondition::Compare {
+            attribute: FileAttribute::Name,
+            operator: ComparisonOperator::Eq,
+            value: FileValue::String("app".to_string()),
+        }),
+        aggregates: vec![],
+        group_by: vec![],
+        rollup: false,
+        order_by: vec![],
+        limit: None,
+        limit_size: None,
+        within: None,
+        sample: None,
+        sample_rows: None,
+    };
+    let results = execute_query(&query).unwrap();
+    assert_eq!(results.len(), 3);
+    assert!(results.iter().all(|f| f.path.ends_with("target/debug/app")));
+
+    // Overlapping roots list each file only once
+    let overlapping = FileQuery::Select {
+        path: dir.path().join("alpha/**"),
+        recursive: true,
+        include_self: false,
+        entry_type: None,
+        join: None,
+        attributes: vec![FileAttribute::All],
+        computed: vec![],
+        condition: None,
+        aggregates: vec![],
+        group_by: vec![],
+        rollup: false,
+        order_by: vec![],
+        limit: None,
+        limit_size: None,
+        within: None,
+        sample: None,
+        sample_rows: None,
+    };
+    let results = execute_query(&overlapping).unwrap();
+    let mut paths: Vec<_> = results.iter().map(|f| f.path.clone()).collect();
+    let count = paths.len();
+    paths.sort();
+    paths.dedup();
+    assert_eq!(paths.len(), count);
+    assert!(paths.contains(&dir.path().join("alpha/target/debug/app")));
+}
+
+#[test]
+fn test_execute_rollup_by_path() {
+    use crate::sql::executor::execute_rollup;
+
+    let dir = tempfile::tempdir().unwrap();
+    fs::create_dir_all(dir.path().join("a/b")).unwrap();
+    fs::create_dir(dir.path().join("c")).unwrap();
+    fs::create_dir(dir.path().join("empty")).unwrap();
+    fs::write(dir.path().join("top.txt"), b"1234").unwrap();
+    fs::write(dir.path().join("a/one.txt"), b"12").unwrap();
+    fs::write(dir.path().join("a/b/two.txt"), b"123456").unwrap();
+    fs::write(dir.path().join("c/skip.log"), b"1").unwrap();
+
+    let sql = format!(
+        "WITH RECURSIVE SELECT SUM(size) AS bytes, COUNT(*) FROM '{}' TYPE f WHERE extension = 'txt' ROLLUP BY path",
+        dir.path().display()
+    );
+    let query = crate::sql::parse_sql(&sql).unwrap();
+    let trees = execute_rollup(&query).unwrap();
+    assert_eq!(trees.len(), 1);
+    let root = &trees[0];
+    assert_eq!(root.path, dir.path());
+    assert_eq!(root.get("bytes"), Some(&FileValue::Integer(12)));
+    assert_eq!(root.get("COUNT(*)"), Some(&FileValue::Integer(3)));
+
+    // Only directories with matching files appear, sorted by path
+    assert_eq!(root.children.len(), 1);
+    let a = root.child("a").unwrap();
+    assert_eq!(a.get("bytes"), Some(&FileValue::Integer(8)));
+    let b = a.child("b").unwrap();
+    assert_eq!(b.get("COUNT(*)"), Some(&FileValue::Integer(1)));
+    assert!(b.children.is_empty());
+
+    let json = serde_json::to_value(root).unwrap();
+    assert_eq!(json["children"][0]["children"][0]["path"], b.path.display().to_string());
+    assert_eq!(json["bytes"], 12);
+
+    // A rollup tree isn't a list of files or groups
+    assert!(execute_query(&query).is_err());
+    assert!(execute_grouped(&query).is_err());
+}
+
+#[test]
+fn test_execute_json_has_selected_attributes_only() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("photo.jpg"), b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR").unwrap();
+
+    let select = |columns: &str| {
+        let sql = format!("SELECT {} FROM '{}'", columns, dir.path().display());
+        let results = execute_query(&crate::sql::parse_sql(&sql).unwrap()).unwrap();
+        assert_eq!(results.len(), 1);
+        serde_json::to_value(&results[0]).unwrap()
+    };
+
+    // Cheap metadata is always there; the rest only when asked for
+    let json = select("name");
+    assert_eq!(json["name"], "photo.jpg");
+    assert_eq!(json["size"], 16);
+    for field in ["owner", "accessed", "created", "is_executable", "signature_extension"] {
+        assert!(json.get(field).is_none(), "{} was not selected", field);
+    }
+
+    let json = select("*");
+    assert!(json["owner"].is_string());
+    assert!(json["accessed"].is_i64());
+    assert!(json.get("signature_extension").is_none());
+
+    let json = select("name, signature_extension, extension_mismatch, is_executable");
+    assert_eq!(json["signature_extension"], "png");
+    assert_eq!(json["extension_mismatch"], true);
+    assert_eq!(json["is_executable"], false);
+    assert!(json.get("owner").is_none());
+}
+
+#[test]
+fn test_results_serialize_as_yaml_and_toml() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("notes.txt"), b"plain text").unwrap();
+    let sql = format!(
+        "SELECT name, owner, signature_extension, size * 2 AS double FROM '{}'",
+        dir.path().display()
+    );
+    let results = execute_query(&crate::sql::parse_sql(&sql).unwrap()).unwrap();
+
+    let yaml = serde_yaml::to_string(&results).unwrap();
+    assert!(yaml.contains("name: notes.txt"));
+    assert!(yaml.contains("signature_extension: null"));
+
+    // TOML has no null, so unknown values are left out
+    let toml = toml::to_string(&std::collections::BTreeMap::from([("files", &results)])).unwrap();
+    assert!(toml.contains("[[files]]"));
+    assert!(toml.contains("name = \"notes.txt\""));
+    assert!(toml.contains("owner = "));
+    assert!(!toml.contains("signature_extension"));
+}
+
+#[test]
+fn test_execute_select_with_join() {
+    let dir = setup_test_directory();
+    let tables = tempdir().unwrap();
+    let inventory = create_test_file(
+        tables.path(),
+        "inventory.csv",
+        "filename,team,retention_days\nfile1.txt,docs,30\nconfig.ini,ops,365\nmissing.txt,docs,\n",
+    )
+    .unwrap();
+    let run = |sql: String| execute_query(&crate::sql::parse_sql(&sql).unwrap());
+    let from = format!("FROM '{}' TYPE f", dir.path().display());
+    let join = format!("JOIN '{}' ON name = inventory.filename", inventory.display());
+
+    let results = run(format!(
+        "SELECT name, inventory.team AS team, inventory.retention_days AS days {} {} \
+         ORDER BY inventory.retention_days DESC",
+        from, join
+    ))
+    .unwrap();
+    let rows: Vec<_> = results
+        .iter()
+        .map(|file| (file.name.as_str(), file.computed["team"].clone(), file.computed["days"].clone()))
+        .collect();
+    assert_eq!(
+        rows,
+        vec![
+            ("config.ini", FileValue::String("ops".to_string()), FileValue::Integer(365)),
+            ("file1.txt", FileValue::String("docs".to_string()), FileValue::Integer(30)),
+        ]
+    );
+
+    // A LEFT JOIN keeps every file, with NULLs where the table has no row
+    let results = run(format!("SELECT name, inventory.team {} LEFT {} WHERE inventory.team = NULL", from, join));
+    let mut names: Vec<_> = results.unwrap().into_iter().map(|file| file.name).collect();
+    names.sort();
+    assert_eq!(names, vec!["file2.txt", "script.sh"]);
+
+    let groups = execute_grouped(
+        &crate::sql::parse_sql(&format!(
+            "SELECT inventory.team, COUNT(*) AS n {} LEFT {} GROUP BY inventory.team",
+            from, join
+        ))
+        .unwrap(),
+    )
+    .unwrap();
+    // docs, ops, and the files without a row
+    assert_eq!(groups.len(), 3);
+
+    // JSON tables, keyed by any attribute
+    let owners = create_test_file(tables.path(), "kinds.json", r#"[{"ext": "txt", "kind": "text"}]"#).unwrap();
+    let results = run(format!(
+        "SELECT name, kinds.kind {} JOIN '{}' ON extension = kinds.ext",
+        from,
+        owners.display()
+    ))
+    .unwrap();
+    assert_eq!(results.len(), 2);
+
+    let err = run(format!("SELECT inventory.owner {} {}", from, join)).unwrap_err();
+    assert!(err.to_string().contains("there is no column 'owner'"));
+    let duplicate = create_test_file(tables.path(), "dup.csv", "filename\na.txt\na.txt\n").unwrap();
+    let err = run(format!("SELECT * {} JOIN '{}' ON name = dup.filename", from, duplicate.display())).unwrap_err();
+    assert!(err.to_string().contains("more than one row has 'a.txt' as its key"));
+}
+
+#[test]
+fn test_select_from_a_table_of_paths() {
+    let dir = tempdir().unwrap();
+    let root = dir.path().display();
+    fs::write(dir.path().join("same.txt"), "12345").unwrap();
+    fs::write(dir.path().join("grown.txt"), "1234567890").unwrap();
+    fs::write(dir.path().join("unlisted.txt"), "x").unwrap();
+    let inventory = create_test_file(
+        dir.path(),
+        "inventory.csv",
+        &format!("path,size,team\n{root}/same.txt,5,ops\n{root}/grown.txt,4,docs\n{root}/gone.txt,1,ops\n"),
+    )
+    .unwrap();
+    let run = |sql: String| execute_query(&crate::sql::parse_sql(&sql).unwrap());
+    let names = |sql: String| -> Vec<String> {
+        let mut names: Vec<_> = run(sql).unwrap().into_iter().map(|file| file.name).collect();
+        names.sort();
+        names
+    };
+
+    // Only the listed files that still exist are read
+    assert_eq!(names(format!("SELECT * FROM '{}' AS TABLE", inventory.display())), ["grown.txt", "same.txt"]);
+    // Each is joined to its row, so drift from the inventory shows
+    let drifted = run(format!(
+        "SELECT name, inv.size AS listed FROM '{}' AS TABLE inv WHERE size != inv.size",
+        inventory.display()
+    ))
+    .unwrap();
+    assert_eq!(drifted.len(), 1);
+    assert_eq!(drifted[0].name, "grown.txt");
+    assert_eq!(drifted[0].computed["listed"], FileValue::Integer(4));
+    let query = crate::sql::parse_sql(&format!(
+        "SELECT COUNT(*) AS n FROM '{}' AS TABLE WHERE inventory.team = 'ops'",
+        inventory.display()
+    ))
+    .unwrap();
+    assert_eq!(execute_grouped(&query).unwrap()[0].columns[0].1, FileValue::Integer(1));
+
+    let json = create_test_file(dir.path(), "paths.json", &format!(r#"[{{"path": "{root}/same.txt"}}]"#)).unwrap();
+    assert_eq!(names(format!("SELECT * FROM '{}' AS TABLE", json.display())), ["same.txt"]);
+
+    let unkeyed = create_test_file(dir.path(), "names.csv", "name\nsame.txt\n").unwrap();
+    let err = run(format!("SELECT * FROM '{}' AS TABLE", unkeyed.display())).unwrap_err();
+    assert!(err.to_string().contains("there is no column 'path'"));
+    let sandbox = tempdir().unwrap();
+    let options = ExecutionOptions { root: Some(sandbox.path().to_path_buf()), ..Default::default() };
+    let query = crate::sql::parse_sql(&format!("SELECT * FROM '{}' AS TABLE", inventory.display())).unwrap();
+    assert!(matches!(execute_query_with_options(&query, &options), Err(ExecutorError::Denied(_))));
+}
+
+#[test]
+fn test_listed_paths_that_cant_be_read_are_kept_when_reconciling() {
+    let dir = tempdir().unwrap();
+    fs::create_dir(dir.path().join("archive")).unwrap();
+    fs::write(dir.path().join("here.txt"), "12345").unwrap();
+    fs::write(dir.path().join("archive/moved.txt"), "x").unwrap();
+    let listed = ["here.txt", "moved.txt", "lost.txt"];
+    let options = ExecutionOptions {
+        files_from: Some(listed.iter().map(|name| dir.path().join(name)).collect()),
+        ..Default::default()
+    };
+    let run = |sql: &str| execute_query_with_options(&crate::sql::parse_sql(sql).unwrap(), &options);
+
+    // Without the attributes, paths that can't be read are left out
+    assert_eq!(run("SELECT * FROM STDIN").unwrap().len(), 1);
+
+    let files = run("SELECT path, exists, was_moved_hint, metadata_error FROM STDIN ORDER BY path").unwrap();
+    let value = |file: &super::FileResult, attribute| super::get_attribute_value(file, &attribute).unwrap();
+    let rows: Vec<_> = files
+        .iter()
+        .map(|file| (file.name.as_str(), value(file, FileAttribute::Exists), value(file, FileAttribute::WasMovedHint)))
+        .collect();
+    let hint = FileValue::String(dir.path().join("archive/moved.txt").display().to_string());
+    assert_eq!(
+        rows,
+        [
+            ("here.txt", FileValue::Boolean(true), FileValue::Null),
+            ("lost.txt", FileValue::Boolean(false), FileValue::Null),
+            ("moved.txt", FileValue::Boolean(false), hint),
+        ]
+    );
+    assert_eq!(value(&files[0], FileAttribute::MetadataError), FileValue::Null);
+    assert!(matches!(value(&files[1], FileAttribute::MetadataError), FileValue::String(error) if error.contains("No such file")));
+    // What a path can't say about itself is unknown
+    assert_eq!(value(&files[1], FileAttribute::Size), FileValue::Null);
+
+    let gone = run("SELECT name FROM STDIN WHERE NOT exists").unwrap();
+    assert_eq!(gone.len(), 2);
+    let query = crate::sql::parse_sql("SELECT exists, COUNT(*) AS n FROM STDIN GROUP BY exists ORDER BY exists").unwrap();
+    let groups = super::execute_grouped_with_options(&query, &options).unwrap();
+    assert_eq!(groups[1].columns[1].1, FileValue::Integer(1));
+}
+
+#[test]
+fn test_changes_over_a_locked_tree_wait_fail_or_lock_each_file() {
+    use crate::sql::events::Events;
+    use crate::sql::locks::{LockMode, Locking};
+    use std::time::Duration;
+
+    let dir = setup_test_directory();
+    let locks = tempdir().unwrap();
+    let locking = |mode| Locking { mode, dir: Some(locks.path().to_path_buf()), timeout: Some(Duration::ZERO) };
+    let sql = format!("UPDATE {} SET permissions = '600' WHERE name = 'file1.txt'", dir.path().display());
+    let query = crate::sql::parse_sql(&sql).unwrap();
+    let run = |mode, dry_run| {
+        let options = ExecutionOptions { locking: locking(mode), dry_run, ..Default::default() };
+        execute_query_with_options(&query, &options)
+    };
+
+    // Another run changing the tree the UPDATE is in
+    let held = locking(LockMode::Wait).acquire(&[dir.path().join("subdir")], &Events::default()).unwrap();
+    assert!(matches!(run(LockMode::Fail, false), Err(ExecutorError::Locked(message)) if message.contains("subdir")));
+    // Waiting gives up after the timeout
+    assert!(matches!(run(LockMode::Wait, false), Err(ExecutorError::Locked(_))));
+    // A run that locks each file still waits for one that doesn't
+    assert!(matches!(run(LockMode::PerFile, false), Err(ExecutorError::Locked(_))));
+    // Dry runs and runs with locking off take no locks
+    assert_eq!(run(LockMode::Fail, true).unwrap().len(), 1);
+    assert_eq!(run(LockMode::None, false).unwrap().len(), 1);
+    drop(held);
+
+    let held = locking(LockMode::PerFile).acquire(&[dir.path().to_path_buf()], &Events::default()).unwrap();
+    let updated = run(LockMode::PerFile, false).unwrap();
+    assert_eq!(updated[0].status, Some(OperationStatus::Ok));
+    assert!(matches!(run(LockMode::Fail, false), Err(ExecutorError::Locked(_))));
+    drop(held);
+    assert_eq!(run(LockMode::Fail, false).unwrap().len(), 1);
+    // Nothing is left behind but the registry and the per-file lock directory
+    let mut left: Vec<_> = fs::read_dir(locks.path()).unwrap().map(|entry| entry.unwrap().file_name()).collect();
+    left.sort();
+    assert_eq!(left, ["files", "registry"]);
+    assert_eq!(fs::read_dir(locks.path().join("files")).unwrap().count(), 0);
+}
+
+#[test]
+fn test_lock_directory_must_be_the_users_own() {
+    use crate::sql::locks::private_dir;
+    use std::os::unix::fs::PermissionsExt;
+
+    let tmp = tempdir().unwrap();
+    // Made if missing, private to the user
+    let made = private_dir(&tmp.path().join("fmql-locks")).unwrap();
+    assert_eq!(fs::metadata(&made).unwrap().permissions().mode() & 0o777, 0o700);
+    assert!(private_dir(&made).is_ok());
+
+    // One that others may write to is refused
+    let open = tmp.path().join("open");
+    fs::create_dir(&open).unwrap();
+    fs::set_permissions(&open, fs::Permissions::from_mode(0o777)).unwrap();
+    let err = private_dir(&open).unwrap_err();
+    assert!(err.to_string().contains("others may write to it"), "{}", err);
+    fs::set_permissions(&open, fs::Permissions::from_mode(0o775)).unwrap();
+    assert!(private_dir(&open).is_err());
+
+    // As is a symlink to one of the user's own
+    let link = tmp.path().join("link");
+    std::os::unix::fs::symlink(&made, &link).unwrap();
+    assert!(private_dir(&link).unwrap_err().to_string().contains("isn't a directory"));
+
+    // And, where the test may hand it over, one another user owns
+    let theirs = tmp.path().join("theirs");
+    fs::create_dir(&theirs).unwrap();
+    fs::set_permissions(&theirs, fs::Permissions::from_mode(0o700)).unwrap();
+    if std::os::unix::fs::chown(&theirs, Some(65534), None).is_ok() {
+        assert!(private_dir(&theirs).unwrap_err().to_string().contains("another user"));
+    }
+}
+
+#[test]
+fn test_retention_policy_plans_and_applies() {
+    use crate::sql::retention::{apply, plan, Action, Policy};
+
+    let dir = setup_test_directory();
+    let archive = tempdir().unwrap();
+    let old = filetime::FileTime::from_unix_time(1_577_836_800, 0); // 2020-01-01
+    for name in ["file1.txt", "config.ini", "subdir/file3.txt"] {
+        filetime::set_file_mtime(dir.path().join(name), old).unwrap();
+    }
+    let audit = archive.path().join("audit.jsonl");
+    let policy = Policy::from_toml(&format!(
+        r#"
+        audit_log = '{audit}'
+
+        [[rule]]
+        name = "old text"
+        query = "SELECT * FROM '{dir}' WHERE extension = 'txt'"
+        after = "30d"
+        action = "compress"
+
+        [[rule]]
+        name = "everything old"
+        query = "WITH RECURSIVE SELECT * FROM '{dir}'"
+        after = "1y"
+        action = "archive"
+        archive_to = '{archive}'
+        "#,
+        audit = audit.display(),
+        dir = dir.path().display(),
+        archive = archive.path().join("cold").display(),
+    ))
+    .unwrap();
+
+    // The first rule takes file1.txt, so the second only archives the rest
+    let planned = plan(&policy, &ExecutionOptions::default()).unwrap();
+    let mut actions: Vec<_> = planned.iter().map(|outcome| (outcome.action, outcome.path.clone())).collect();
+    actions.sort_by(|a, b| a.1.cmp(&b.1));
+    assert_eq!(
+        actions,
+        vec![
+            (Action::Archive, dir.path().join("config.ini")),
+            (Action::Compress, dir.path().join("file1.txt")),
+            (Action::Archive, dir.path().join("subdir/file3.txt")),
+        ]
+    );
+    assert!(planned.iter().all(|outcome| outcome.status.is_none()));
+    assert!(dir.path().join("file1.txt").exists());
+
+    let applied = apply(&policy, &ExecutionOptions::default()).unwrap();
+    assert!(applied.iter().all(|outcome| outcome.status == Some(OperationStatus::Ok)));
+    assert!(!dir.path().join("file1.txt").exists());
+    let compressed = fs::metadata(dir.path().join("file1.txt.gz")).unwrap();
+    assert_eq!(filetime::FileTime::from_last_modification_time(&compressed), old);
+    assert!(archive.path().join("cold/subdir/file3.txt").exists());
+    assert!(dir.path().join("file2.txt").exists());
+    assert_eq!(fs::read_to_string(&audit).unwrap().lines().count(), 3);
+
+    let read_only = ExecutionOptions {
+        read_only: true,
+        ..ExecutionOptions::default()
+    };
+    assert!(matches!(apply(&policy, &read_only), Err(ExecutorError::Denied(_))));
+
+    let err = Policy::from_toml(
+        "[[rule]]\nname = 'x'\nquery = 'SELECT * FROM .'\nafter = '1d'\naction = 'archive'\n",
+    )
+    .unwrap_err();
+    assert!(err.to_string().contains("rule 'x': archive needs archive_to"));
+    let err = Policy::from_toml(
+        "[[rule]]\nname = 'x'\nquery = 'UPDATE . SET name = 1'\nafter = '1d'\naction = 'delete'\n",
+    )
+    .unwrap_err();
+    assert!(err.to_string().contains("must be a SELECT"));
+}
+
+#[test]
+fn test_alert_thresholds() {
+    use crate::sql::alert::{summarise, Threshold};
+
+    let dir = setup_test_directory();
+    let threshold = |text: &str| text.parse::<Threshold>().unwrap();
+    assert_eq!(threshold("bytes>=1.5KB").value, 1500.0);
+    assert_eq!(threshold("count != 0").operator, ComparisonOperator::NotEq);
+    assert!("count 0".parse::<Threshold>().is_err());
+    assert!("> 0".parse::<Threshold>().is_err());
+    assert!("count >= lots".parse::<Threshold>().is_err());
+
+    let query = crate::sql::parse_sql(&format!("SELECT * FROM '{}' TYPE f", dir.path().display())).unwrap();
+    let summary = [summarise(&execute_query(&query).unwrap())];
+    assert!(threshold("count = 4").holds(&summary).unwrap());
+    assert!(threshold("bytes > 50").holds(&summary).unwrap());
+    assert!(!threshold("bytes > 1MB").holds(&summary).unwrap());
+    assert!(threshold("size > 0").holds(&summary).is_err());
+
+    // Any group crossing the threshold fires it; NULLs never do
+    let sql = format!(
+        "WITH RECURSIVE SELECT extension, COUNT(*) AS n, MAX(owner) AS top FROM '{}' TYPE f GROUP BY extension",
+        dir.path().display()
+    );
+    let groups = execute_grouped(&crate::sql::parse_sql(&sql).unwrap()).unwrap();
+    assert!(threshold("n >= 3").holds(&groups).unwrap());
+    assert!(!threshold("n > 3").holds(&groups).unwrap());
+    assert!(threshold("rows = 4").holds(&groups).unwrap());
+    assert!(!threshold("top > 0").holds(&groups).unwrap());
+}
+
+#[test]
+fn test_group_by_alias_groups_by_its_expression() {
+    let dir = tempdir().unwrap();
+    for name in ["a.TXT", "b.txt", "c.md"] {
+        create_test_file(dir.path(), name, "").unwrap();
+    }
+    let sql = format!(
+        "SELECT LOWER(extension) AS kind, COUNT(*) AS n FROM '{}' GROUP BY kind",
+        dir.path().display()
+    );
+    let mut groups = execute_grouped(&crate::sql::parse_sql(&sql).unwrap()).unwrap();
+    groups.sort_by_key(|group| format!("{:?}", group.get("kind")));
+    let counts: Vec<_> = groups
+        .iter()
+        .map(|group| (group.get("kind").cloned(), group.get("n").cloned()))
+        .collect();
+    assert_eq!(
+        counts,
+        [
+            (Some(FileValue::String("md".to_string())), Some(FileValue::Integer(1))),
+            (Some(FileValue::String("txt".to_string())), Some(FileValue::Integer(2))),
+        ]
+    );
+}
+
+#[test]
+fn test_compressed_files_read_through_decompressors() {
+    use flate2::write::GzEncoder;
+
+    let dir = tempdir().unwrap();
+    let log = "INFO started\nERROR disk full\nINFO stopped\n";
+    create_test_file(dir.path(), "app.log", log).unwrap();
+    let mut gz = GzEncoder::new(File::create(dir.path().join("app.log.1.gz")).unwrap(), Default::default());
+    gz.write_all(log.as_bytes()).unwrap();
+    gz.finish().unwrap();
+    // A zstd frame with its content size in the header and one raw block
+    let mut zst = vec![0x28, 0xb5, 0x2f, 0xfd, 0x20, log.len() as u8];
+    zst.extend_from_slice(&((log.len() as u32) << 3 | 1).to_le_bytes()[..3]);
+    zst.extend_from_slice(log.as_bytes());
+    fs::write(dir.path().join("app.log.2.zst"), zst).unwrap();
+    let mut xz = Vec::new();
+    lzma_rs::xz_compress(&mut log.as_bytes(), &mut xz).unwrap();
+    fs::write(dir.path().join("app.log.3.xz"), xz).unwrap();
+
+    let run = |sql: &str, decompress: Option<u64>| {
+        let query = crate::sql::parse_sql(&sql.replace("DIR", &dir.path().display().to_string())).unwrap();
+        let options = ExecutionOptions { decompress, ..Default::default() };
+        let mut results = execute_query_with_options(&query, &options).unwrap();
+        results.sort_by(|a, b| a.name.cmp(&b.name));
+        results
+    };
+    let names = |results: Vec<crate::sql::executor::FileResult>| {
+        results.into_iter().map(|f| f.name).collect::<Vec<_>>()
+    };
+
+    let sizes: Vec<_> = run("SELECT name, uncompressed_size FROM 'DIR'", None)
+        .iter()
+        .map(|f| f.selected.get(FileAttribute::UncompressedSize).cloned())
+        .collect();
+    let size = Some(FileValue::Integer(log.len() as i64));
+    assert_eq!(sizes, [Some(FileValue::Null), size.clone(), size.clone(), size]);
+
+    // Content is the compressed bytes unless the query decompresses
+    assert_eq!(names(run("SELECT * FROM 'DIR' WHERE content LIKE 'INFO%'", None)), ["app.log"]);
+    let sql = "SELECT name, matches FROM 'DIR' WHERE content LIKE '%ERROR%'";
+    let results = run(sql, Some(1024));
+    assert!(results.iter().all(|f| f.matches.len() == 1 && f.matches[0].line == 2));
+    assert_eq!(names(results), ["app.log", "app.log.1.gz", "app.log.2.zst", "app.log.3.xz"]);
+
+    // Only the first LIMIT bytes of each are read
+    assert_eq!(names(run(sql, Some(12))), ["app.log"]);
+    assert_eq!(names(run("SELECT * FROM 'DIR' WHERE content = 'INFO started'", Some(12))).len(), 3);
+}
+
+#[test]
+fn test_execute_copy() {
+    use crate::sql::ast::CopyMethod;
+    use std::os::unix::fs::MetadataExt;
+
+    let dir = setup_test_directory();
+    let backup = tempdir().unwrap();
+    let run = |sql: &str, options: &ExecutionOptions| {
+        let sql = sql
+            .replace("DIR", &dir.path().display().to_string())
+            .replace("BACKUP", &backup.path().display().to_string());
+        execute_query_with_options(&crate::sql::parse_sql(&sql).unwrap(), options)
+    };
+    let options = ExecutionOptions::default();
+    let inode = |path: &Path| fs::metadata(path).unwrap().ino();
+
+    let mut results = run("WITH RECURSIVE COPY 'DIR' TO 'BACKUP' WHERE extension = 'txt' USING HARDLINK", &options)
+        .unwrap();
+    results.sort_by(|a, b| a.path.cmp(&b.path));
+    let copies: Vec<_> = results.iter().map(|f| f.path.strip_prefix(backup.path()).unwrap()).collect();
+    assert_eq!(copies, [Path::new("file1.txt"), Path::new("file2.txt"), Path::new("subdir/file3.txt")]);
+    assert!(results.iter().all(|f| f.copied == Some(CopyMethod::Hardlink)));
+    assert_eq!(results[2].changes[0].old_value, FileValue::String(dir.path().join("subdir/file3.txt").display().to_string()));
+    assert_eq!(inode(&backup.path().join("file1.txt")), inode(&dir.path().join("file1.txt")));
+
+    // Existing copies fail by default, and are replaced with fresh bytes on request
+    let failed = run("COPY 'DIR' TO 'BACKUP' WHERE name = 'file1.txt'", &options).unwrap();
+    assert_eq!(failed[0].status, Some(OperationStatus::Failed));
+    let skipped = run("COPY 'DIR' TO 'BACKUP' WHERE name = 'file1.txt' ON CONFLICT SKIP", &options).unwrap();
+    assert_eq!(skipped[0].conflict, Some(ConflictOutcome::Skipped));
+    let replaced = run("COPY 'DIR' TO 'BACKUP' WHERE name = 'file1.txt' ON CONFLICT OVERWRITE", &options).unwrap();
+    assert_eq!(replaced[0].copied, Some(CopyMethod::Bytes));
+    assert_ne!(inode(&backup.path().join("file1.txt")), inode(&dir.path().join("file1.txt")));
+    assert_eq!(fs::read_to_string(backup.path().join("file1.txt")).unwrap(), "Hello, world!");
+
+    // A glob keeps the paths below the directory before the wildcard
+    let globbed = run("COPY 'DIR/sub*' TO 'BACKUP/glob' USING AUTO", &options).unwrap();
+    assert_eq!(globbed.len(), 2);
+    assert!(backup.path().join("glob/subdir/config.xml").exists());
+    assert!(globbed.iter().all(|f| f.copied.is_some_and(|method| method != CopyMethod::Auto)));
+
+    let read_only = ExecutionOptions { read_only: true, ..Default::default() };
+    assert!(matches!(run("COPY 'DIR' TO 'BACKUP'", &read_only), Err(ExecutorError::Denied(_))));
+    let sandboxed = ExecutionOptions { root: Some(dir.path().to_path_buf()), ..Default::default() };
+    assert!(matches!(run("COPY 'DIR' TO 'BACKUP'", &sandboxed), Err(ExecutorError::Denied(_))));
+}
+
+#[test]
+fn test_execute_update_renames_chains_and_swaps() {
+    let dir = tempdir().unwrap();
+    for (name, content) in [("a.txt", "a"), ("b.txt", "b"), ("v1.md", "1"), ("v2.md", "2")] {
+        fs::write(dir.path().join(name), content).unwrap();
+    }
+    let sql = format!(
+        "UPDATE '{}' SET name = CASE WHEN name = 'a.txt' THEN 'b.txt' WHEN name = 'b.txt' THEN 'a.txt' \
+         WHEN name = 'v1.md' THEN 'v2.md' WHEN name = 'v2.md' THEN 'v3.md' END",
+        dir.path().display()
+    );
+    let results = crate::sql::run(&sql, &ExecutionOptions::default()).unwrap();
+
+    assert_eq!(results.len(), 4);
+    assert!(results.iter().all(|file| file.status == Some(OperationStatus::Ok)), "{:?}", results);
+    let read = |name: &str| fs::read_to_string(dir.path().join(name)).unwrap();
+    assert_eq!((read("a.txt"), read("b.txt")), ("b".to_string(), "a".to_string()));
+    assert_eq!((read("v2.md"), read("v3.md")), ("1".to_string(), "2".to_string()));
+    assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 4);
+    let swapped = results.iter().find(|file| file.name == "b.txt").unwrap();
+    assert_eq!(swapped.changes[0].old_value, FileValue::String("a.txt".to_string()));
+}
+
+#[test]
+fn test_execute_update_rename_collisions_abort() {
+    let dir = tempdir().unwrap();
+    for name in ["a.txt", "b.txt", "c.txt"] {
+        fs::write(dir.path().join(name), name).unwrap();
+    }
+    let sql = |on_conflict: &str| {
+        format!(
+            "UPDATE '{}' SET name = 'merged.txt' WHERE name <> 'c.txt' ON CONFLICT {}",
+            dir.path().display(),
+            on_conflict
+        )
+    };
+
+    let err = crate::sql::run(&sql("OVERWRITE"), &ExecutionOptions::default()).unwrap_err();
+    assert_eq!(err.code(), "exec.rename_conflict");
+    assert!(err.to_string().contains("would both be renamed to"), "{}", err);
+    assert!(dir.path().join("a.txt").exists() && dir.path().join("b.txt").exists());
+
+    let results = crate::sql::run(&sql("RENAME"), &ExecutionOptions::default()).unwrap();
+    let mut names: Vec<_> = results.iter().map(|file| file.name.as_str()).collect();
+    names.sort();
+    assert_eq!(names, ["merged.txt", "merged_1.txt"]);
+}
+
+#[test]
+fn test_execute_update_case_only_rename() {
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("readme.md"), "# fmql").unwrap();
+    let sql = format!("UPDATE '{}' SET name = CONCAT(UPPER(STEM(name)), '.md')", dir.path().display());
+    let results = crate::sql::run(&sql, &ExecutionOptions::default()).unwrap();
+    assert_eq!(results[0].status, Some(OperationStatus::Ok));
+    assert_eq!(results[0].name, "README.md");
+    let names: Vec<_> = fs::read_dir(dir.path()).unwrap().map(|entry| entry.unwrap().file_name()).collect();
+    assert_eq!(names, ["README.md"]);
+
+    // A second name for the same file is a real conflict where case matters
+    fs::hard_link(dir.path().join("README.md"), dir.path().join("readme.md")).unwrap();
+    assert!(!super::differs_only_in_case(&dir.path().join("readme.md"), &dir.path().join("README.md")));
+    let sql = format!("UPDATE '{}' SET name = 'README.md' WHERE name = 'readme.md'", dir.path().display());
+    let results = crate::sql::run(&sql, &ExecutionOptions::default()).unwrap();
+    assert_eq!(results[0].status, Some(OperationStatus::Failed));
+    assert!(dir.path().join("readme.md").exists());
+}
+
+#[test]
+fn test_show_filesystem() {
+    let dir = setup_test_directory();
+    let query = crate::sql::parse_sql(&format!("SHOW FILESYSTEM FROM '{}'", dir.path().display())).unwrap();
+
+    let rows = execute_grouped(&query).unwrap();
+    assert_eq!(rows.len(), 1);
+    let names: Vec<_> = rows[0].columns.iter().map(|(name, _)| name.as_str()).collect();
+    let schema: Vec<_> = crate::sql::schema::schema(&query).into_iter().map(|column| column.name).collect();
+    assert_eq!(names, schema);
+    assert_eq!(rows[0].get("path"), Some(&FileValue::String(dir.path().display().to_string())));
+    assert!(matches!(rows[0].get("max_name_length"), Some(FileValue::Integer(n)) if *n >= 14));
+
+    let capabilities = crate::sql::capabilities::probe(&dir.path().join("file1.txt")).unwrap();
+    // The directory holds file1.txt, so there is a name to try in upper case
+    assert!(capabilities.case_sensitive.is_some());
+    assert!(execute_query(&query).is_err());
+    // Where creation times are recorded there is nothing to warn about
+    let select = crate::sql::parse_sql(&format!("SELECT created FROM '{}'", dir.path().display())).unwrap();
+    let warnings = crate::sql::capabilities::warnings(&select);
+    assert_eq!(warnings.is_empty(), capabilities.birthtime != Some(false), "{:?}", warnings);
+}
+
+#[test]
+fn test_schema_names_the_fields_of_each_row() {
+    use crate::sql::catalog::ValueType;
+    use crate::sql::schema::schema;
+
+    let dir = setup_test_directory();
+    let sql = format!("SELECT * FROM '{}'", dir.path().display());
+    let query = crate::sql::parse_sql(&sql).unwrap();
+    let columns = schema(&query);
+    let mut names: Vec<_> = columns.iter().map(|column| column.name.as_str()).collect();
+    names.sort_unstable();
+    let results = crate::sql::run(&sql, &ExecutionOptions::default()).unwrap();
+    for result in &results {
+        let json = serde_json::to_value(result).unwrap();
+        // The object's keys come out sorted
+        let keys: Vec<_> = json.as_object().unwrap().keys().map(String::as_str).collect();
+        assert_eq!(keys, names);
+    }
+    let modified = columns.iter().find(|column| column.name == "modified").unwrap();
+    assert_eq!(modified.value_type, ValueType::DateTime);
+    // Rows carry dates as Unix seconds, which the schema says
+    let json = serde_json::to_value(modified).unwrap();
+    assert_eq!(json, serde_json::json!({"name": "modified", "type": "datetime", "unit": "seconds"}));
+
+    // Just what was selected, attributes before expressions
+    let sql = format!("SELECT name, size / 1024 AS kib, is_mine FROM '{}'", dir.path().display());
+    let query = crate::sql::parse_sql(&sql).unwrap();
+    let names: Vec<_> = schema(&query).into_iter().map(|column| column.name).collect();
+    assert_eq!(names, ["name", "is_mine", "kib"]);
+    let results = crate::sql::run(&sql, &ExecutionOptions::default()).unwrap();
+    for result in &results {
+        assert!(result.selected.get(FileAttribute::IsMine).is_some());
+        assert!(result.computed.contains_key("kib"));
+    }
+}
+
+#[test]
+fn test_rename_target_checks_windows_names_only_where_windows_reads_them() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("notes.txt");
+    let target = super::rename_target(&path, &FileValue::String("aux.txt".to_string()));
+    if crate::sql::capabilities::holds_windows_names(dir.path()) {
+        assert!(target.unwrap_err().to_string().contains("device name"));
+    } else {
+        assert_eq!(target.unwrap(), dir.path().join("aux.txt"));
+    }
+}
+
+#[test]
+fn test_flags_attribute() {
+    let dir = setup_test_directory();
+    let sql = format!("SELECT name, flags FROM '{}' WHERE name = 'file1.txt'", dir.path().display());
+    let results = crate::sql::run(&sql, &ExecutionOptions::default()).unwrap();
+    let flags = super::get_attribute_value(&results[0], &FileAttribute::Flags).unwrap();
+    assert_eq!(flags == FileValue::Null, !crate::sql::flags::supported());
+
+    let sql = format!("UPDATE '{}' SET flags = 'nodump' WHERE name = 'file1.txt'", dir.path().display());
+    let result = crate::sql::run(&sql, &ExecutionOptions::default());
+    if crate::sql::flags::supported() {
+        let results = result.unwrap();
+        assert_eq!(results[0].status, Some(OperationStatus::Ok));
+        let bits = crate::sql::flags::read_flags(&dir.path().join("file1.txt")).unwrap().unwrap();
+        assert_ne!(bits & 0x1, 0);
+    } else {
+        assert!(matches!(
+            result,
+            Err(crate::error::FMQLError::Execution(ExecutorError::UnsupportedOperation(_)))
+        ));
+    }
+}
+
+#[test]
+fn test_flag_changes() {
+    use crate::sql::flags::FlagChange;
+
+    assert_eq!(FlagChange::parse("0").unwrap().apply(0x8002), 0);
+    assert_eq!(FlagChange::parse("100000").unwrap().apply(0x2), 0x8000);
+    assert_eq!(FlagChange::parse("dump, uchange").unwrap(), FlagChange { set: 0x2, clear: 0x1 });
+    assert!(FlagChange::parse("nosticky").is_err());
+}
+
+#[test]
+fn test_security_context_attribute() {
+    let dir = setup_test_directory();
+    let context = crate::sql::selinux::read_context(&dir.path().join("file1.txt")).unwrap();
+    let sql = format!("SELECT name, security_context FROM '{}' WHERE name = 'file1.txt'", dir.path().display());
+    let results = crate::sql::run(&sql, &ExecutionOptions::default()).unwrap();
+    assert_eq!(
+        super::get_attribute_value(&results[0], &FileAttribute::SecurityContext).unwrap(),
+        context.clone().map_or(FileValue::Null, FileValue::String)
+    );
+
+    // Unlabeled files don't match a LIKE on the label
+    let sql = format!("SELECT name FROM '{}' WHERE security_context LIKE '%:object_r:%'", dir.path().display());
+    let results = crate::sql::run(&sql, &ExecutionOptions::default()).unwrap();
+    if context.is_none_or(|context| !context.contains(":object_r:")) {
+        assert!(results.iter().all(|file| file.name != "file1.txt"));
+    } else {
+        assert!(results.iter().any(|file| file.name == "file1.txt"));
+    }
+}
+
+#[test]
+fn test_time_zone_option() {
+    use crate::sql::timezone::{parse_datetime, parse_time_zone};
+
+    let dir = setup_test_directory();
+    let late = filetime::FileTime::from_unix_time(1_751_326_200, 0); // 2025-06-30 23:30 UTC
+    filetime::set_file_mtime(dir.path().join("file1.txt"), late).unwrap();
+    let sql = format!(
+        "SELECT name, DAY(modified) AS day FROM '{}' WHERE name = 'file1.txt' AND modified >= '2025-07-01'",
+        dir.path().display()
+    );
+
+    // In UTC the file was changed on the 30th; in London it was the 1st
+    assert!(crate::sql::run(&sql, &ExecutionOptions::default()).unwrap().is_empty());
+    let london = parse_time_zone("Europe/London").unwrap();
+    let options = ExecutionOptions { time_zone: london, ..ExecutionOptions::default() };
+    let results = crate::sql::run(&sql, &options).unwrap();
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].computed.get("day"), Some(&FileValue::Integer(1)));
+
+    // 01:30 never happened in London on 2025-03-30; it is read as 01:30 GMT
+    let gap = parse_datetime("2025-03-30 01:30:00", london).unwrap();
+    assert_eq!(gap.to_rfc3339(), "2025-03-30T01:30:00+00:00");
+    // An explicit offset means the same instant in any zone
+    let explicit = parse_datetime("2025-07-01T00:00:00+02:00", london).unwrap();
+    assert_eq!(explicit.to_rfc3339(), "2025-06-30T22:00:00+00:00");
+}
+
+#[test]
+fn test_epoch_and_strptime_dates() {
+    let dir = setup_test_directory();
+    let mtime = filetime::FileTime::from_unix_time(1_735_603_200, 0); // 2024-12-31 00:00 UTC
+    filetime::set_file_mtime(dir.path().join("file1.txt"), mtime).unwrap();
+    let matches = |condition: &str| {
+        let sql = format!("SELECT name FROM '{}' WHERE name = 'file1.txt' AND {}", dir.path().display(), condition);
+        crate::sql::run(&sql, &ExecutionOptions::default()).unwrap().len() == 1
+    };
+
+    assert!(matches("modified = 1735603200"));
+    assert!(matches("modified BETWEEN 1735603199 AND 1735603201"));
+    assert!(!matches("modified > 1735603200"));
+    assert!(matches("modified = EPOCH_MS(1735603200000)"));
+    assert!(matches("modified = STRPTIME('31/12/2024', '%d/%m/%Y')"));
+    assert!(matches("modified < STRPTIME('2024-12-31 01:00 +0000', '%Y-%m-%d %H:%M %z')"));
+
+    let text = |s: &str| FileValue::String(s.to_string());
+    let call = |name: &str, args: &[FileValue]| crate::sql::functions::call(name, args);
+    assert_eq!(call("STRPTIME", &[text("IMG_2024"), text("%Y%m%d")]), Ok(FileValue::Null));
+    assert!(call("STRPTIME", &[text("2024"), text("%Q")]).is_err());
+    assert_eq!(call("EPOCH_MS", &[FileValue::Null]), Ok(FileValue::Null));
+    assert!(call("EPOCH_MS", &[FileValue::Number(1e300)]).is_err());
+}
+
+#[test]
+fn test_sample_estimates_aggregates() {
+    let dir = setup_test_directory();
+    let run = |sample: &str| {
+        let sql = format!("SELECT COUNT(*), SUM(size) FROM '{}' TYPE f SAMPLE {}", dir.path().display(), sample);
+        execute_grouped(&crate::sql::parse_sql(&sql).unwrap()).unwrap().remove(0)
+    };
+
+    // Sampling everything is exact
+    let all = run("100%");
+    let names: Vec<_> = all.columns.iter().map(|(name, _)| name.as_str()).collect();
+    assert_eq!(names, ["COUNT(*)", "COUNT(*)_low", "COUNT(*)_high", "SUM(size)", "SUM(size)_low", "SUM(size)_high"]);
+    assert_eq!(all.columns[0].1, FileValue::Integer(4));
+    assert_eq!(all.columns[1].1, FileValue::Integer(4));
+    assert_eq!(all.columns[2].1, FileValue::Integer(4));
+
+    // A smaller sample brackets its estimate, and the same query picks the same sample
+    let half = run("50%");
+    let number = |index: usize| match half.columns[index].1 {
+        FileValue::Number(n) => n,
+        ref other => panic!("Expected a number, got {:?}", other),
+    };
+    assert!(number(1) <= number(0) && number(0) <= number(2));
+    assert_eq!(run("50%").columns, half.columns);
+}
+
+#[test]
+fn test_sample_selects_a_subset() {
+    let dir = setup_test_directory();
+    let sql = |sample: &str| format!("SELECT * FROM '{}' TYPE f {}", dir.path().display(), sample);
+    let all = crate::sql::run(&sql(""), &ExecutionOptions::default()).unwrap();
+    let some = crate::sql::run(&sql("SAMPLE 30%"), &ExecutionOptions::default()).unwrap();
+    assert!(some.len() <= all.len());
+    assert!(some.iter().all(|file| all.iter().any(|other| other.path == file.path)));
+    for file in &all {
+        let picked = some.iter().any(|other| other.path == file.path);
+        assert_eq!(picked, crate::sql::sample::includes(&file.path, 0.3));
+    }
+
+    let rollup = format!("SELECT SUM(size) FROM '{}' ROLLUP BY path", dir.path().display());
+    let mut query = crate::sql::parse_sql(&rollup).unwrap();
+    if let FileQuery::Select { sample, .. } = &mut query {
+        *sample = Some(0.5);
+    }
+    assert!(crate::sql::executor::execute_rollup(&query).is_err());
+}
+
+#[test]
+fn test_checkpoint_resumes_after_saved_position() {
+    use crate::sql::checkpoint::{Checkpoint, Position};
+
+    let dir = tempdir().unwrap();
+    for name in ["a/1.txt", "a/2.txt", "b/1.txt", "b/2.txt", "c/1.txt"] {
+        let path = dir.path().join(name);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, "x").unwrap();
+    }
+    let state = dir.path().join("state.json");
+    let query = crate::sql::parse_sql(&format!(
+        "WITH RECURSIVE SELECT * FROM '{}' WHERE extension = 'txt'",
+        dir.path().display()
+    ))
+    .unwrap();
+
+    // As if a run had matched a/1.txt and stopped after b/1.txt
+    let mut checkpoint = Checkpoint::new(&query);
+    checkpoint.position = Some(Position { target: 0, path: dir.path().join("b/1.txt") });
+    checkpoint.matches.push(dir.path().join("a/1.txt"));
+    checkpoint.save(&state).unwrap();
+
+    let options = ExecutionOptions { checkpoint: Some(state.clone()), ..ExecutionOptions::default() };
+    let mut names: Vec<_> = execute_query_with_options(&query, &options)
+        .unwrap()
+        .iter()
+        .map(|file| file.path.strip_prefix(dir.path()).unwrap().display().to_string())
+        .collect();
+    names.sort();
+    assert_eq!(names, ["a/1.txt", "b/2.txt", "c/1.txt"]);
+    // A finished query leaves no checkpoint behind
+    assert!(!state.exists());
+}
+
+#[test]
+fn test_checkpoint_saved_when_scan_stops() {
+    let dir = tempdir().unwrap();
+    for name in ["a/1.txt", "a/2.log", "b/1.txt", "b/2.txt", "c/1.txt", "c/2.log"] {
+        let path = dir.path().join(name);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, "x").unwrap();
+    }
+    let states = tempdir().unwrap();
+    let state = states.path().join("state.json");
+    let sql = format!("WITH RECURSIVE SELECT * FROM '{}' WHERE extension = 'txt'", dir.path().display());
+    let query = crate::sql::parse_sql(&sql).unwrap();
+    let everything = execute_query(&query).unwrap().len();
+
+    let limits = ResourceLimits { max_files_scanned: Some(4), ..ResourceLimits::default() };
+    let stopped = ExecutionOptions { checkpoint: Some(state.clone()), limits, ..ExecutionOptions::default() };
+    assert!(execute_query_with_options(&query, &stopped).is_err());
+    assert!(state.exists());
+
+    let resumed = ExecutionOptions { checkpoint: Some(state.clone()), ..ExecutionOptions::default() };
+    let results = execute_query_with_options(&query, &resumed).unwrap();
+    assert_eq!(results.len(), everything);
+    assert!(!state.exists());
+
+    // A checkpoint belongs to one query, and only plain SELECTs keep one
+    crate::sql::checkpoint::Checkpoint::new(&query).save(&state).unwrap();
+    let other = crate::sql::parse_sql(&format!("SELECT * FROM '{}'", dir.path().display())).unwrap();
+    assert!(matches!(
+        execute_query_with_options(&other, &resumed),
+        Err(ExecutorError::InvalidCheckpoint(_))
+    ));
+    let grouped = crate::sql::parse_sql(&format!("SELECT COUNT(*) FROM '{}'", dir.path().display())).unwrap();
+    assert!(matches!(
+        crate::sql::executor::execute_grouped_with_options(&grouped, &resumed),
+        Err(ExecutorError::UnsupportedOperation(_))
+    ));
+}
+
+#[test]
+fn test_shards_visit_each_entry_once() {
+    use crate::sql::shard::Shard;
+
+    let dir = tempdir().unwrap();
+    for name in ["a/1.txt", "a/deep/2.txt", "b/3.txt", "c/4.txt", "d/5.txt", "6.txt", "7.txt"] {
+        let path = dir.path().join(name);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, name).unwrap();
+    }
+    let query = crate::sql::parse_sql(&format!("WITH RECURSIVE SELECT * FROM '{}'", dir.path().display())).unwrap();
+    let mut everything: Vec<_> = execute_query(&query).unwrap().into_iter().map(|file| file.path).collect();
+    everything.sort();
+
+    let mut sharded = Vec::new();
+    for index in 1..=3 {
+        let shard = Some(Shard { index, count: 3 });
+        let options = ExecutionOptions { shard, ..ExecutionOptions::default() };
+        let files = execute_query_with_options(&query, &options).unwrap();
+        sharded.extend(files.into_iter().map(|file| file.path));
+    }
+    sharded.sort();
+    assert_eq!(sharded, everything);
+}
+
+#[test]
+fn test_bench_times_conditions_and_suggests_ignores() {
+    use crate::sql::bench::{run, BenchOptions};
+
+    let dir = tempdir().unwrap();
+    for index in 0..30 {
+        let path = dir.path().join(format!("node_modules/pkg{}/index.js", index));
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, "module.exports = {}").unwrap();
+    }
+    fs::write(dir.path().join("app.log"), "started").unwrap();
+    let options = BenchOptions {
+        max_threads: 2,
+        conditions: vec!["content LIKE '%started%'".to_string()],
+        ..BenchOptions::default()
+    };
+    let report = run(dir.path(), &options).unwrap();
+    assert_eq!(report.entries, 62);
+    assert!(!report.truncated);
+    assert_eq!(report.threads.iter().map(|run| run.threads).collect::<Vec<_>>(), [1, 2]);
+    let matched: Vec<_> = report.filters.iter().map(|filter| (filter.condition.as_str(), filter.matched)).collect();
+    assert_eq!(
+        matched,
+        [("", 62), ("name LIKE '%.log'", 1), ("size > 1048576", 0), ("owner = 'root'", matched[3].1), ("content LIKE '%started%'", 1)]
+    );
+    assert!(report.filters[1].names_only && !report.filters[4].names_only);
+    assert_eq!(report.noisy, [("node_modules".to_string(), 60)]);
+    assert!(report.suggestions.iter().any(|suggestion| suggestion.contains(r#"ignore = ["node_modules"]"#)));
+
+    let options = BenchOptions { max_entries: 10, ..options };
+    assert!(run(dir.path(), &options).unwrap().truncated);
+    let options = BenchOptions { conditions: vec!["size >".to_string()], ..options };
+    assert!(run(dir.path(), &options).is_err());
+}
+
+#[test]
+fn test_replay_runs_on_a_recorded_trace() {
+    use crate::sql::events::Events;
+    use crate::sql::executor::{execute_grouped_replay, execute_replay};
+    use crate::sql::trace::{replay, Recorder, Trace};
+    use std::sync::Arc;
+
+    let dir = tempdir().unwrap();
+    let dir = dir.path().canonicalize().unwrap();
+    fs::create_dir(dir.join("logs")).unwrap();
+    fs::write(dir.join("logs/app.log"), vec![b'x'; 3000]).unwrap();
+    fs::write(dir.join("logs/.hidden.log"), "x").unwrap();
+    fs::write(dir.join("notes.txt"), "x").unwrap();
+    std::os::unix::fs::symlink(dir.join("logs/app.log"), dir.join("latest")).unwrap();
+    let sql = format!("WITH RECURSIVE SELECT * FROM '{}' WHERE size > 1000 AND name != 'logs' ORDER BY path", dir.display());
+    let query = crate::sql::parse_sql(&sql).unwrap();
+
+    let recorder = Arc::new(Recorder::new().unwrap());
+    let seen = Arc::clone(&recorder);
+    let options = ExecutionOptions {
+        events: Events::new(move |event| seen.observe(&event)),
+        ..Default::default()
+    };
+    let live = execute_query_with_options(&query, &options).unwrap();
+    let trace = recorder.trace(&sql, &dir);
+    assert_eq!(trace.entries.len(), 6);
+    let file = dir.join("case.fmqltrace");
+    trace.write(File::create(&file).unwrap()).unwrap();
+
+    // The files are gone, but the trace still has them
+    fs::remove_dir_all(dir.join("logs")).unwrap();
+    let stand_ins = replay(&file).unwrap();
+    let other = crate::sql::parse_sql(&sql.replace(&*dir.to_string_lossy(), "/elsewhere")).unwrap();
+    let replayed = execute_replay(&other, &stand_ins, &ExecutionOptions::default()).unwrap();
+    let paths = |results: &[super::FileResult]| -> Vec<PathBuf> {
+        results.iter().map(|file| file.path.clone()).collect()
+    };
+    assert_eq!(paths(&replayed), paths(&live));
+    assert_eq!(paths(&replayed), [dir.join("latest"), dir.join("logs/app.log")]);
+    assert_eq!(replayed[1].size, 3000);
+
+    let grouped = format!("WITH RECURSIVE SELECT extension, COUNT(*) FROM '{}' GROUP BY extension", dir.display());
+    let grouped = crate::sql::parse_sql(&grouped).unwrap();
+    let rows = execute_grouped_replay(&grouped, &stand_ins, &ExecutionOptions::default()).unwrap();
+    assert_eq!(rows.len(), 3);
+
+    // Changes are made to the stand-ins, not the files
+    let update = format!("UPDATE '{}' SET name = 'renamed.txt' WHERE name = 'notes.txt'", dir.display());
+    let update = crate::sql::parse_sql(&update).unwrap();
+    let updated = execute_replay(&update, &stand_ins, &ExecutionOptions::default()).unwrap();
+    assert_eq!(updated[0].path, dir.join("renamed.txt"));
+    assert!(dir.join("notes.txt").exists());
+
+    let mut anonymized = trace.clone();
+    anonymized.anonymize();
+    assert_eq!(anonymized.query, None);
+    let names: Vec<String> = anonymized
+        .entries
+        .iter()
+        .map(|entry| entry.path.file_name().unwrap().to_string_lossy().into_owned())
+        .collect();
+    assert!(names.iter().all(|name| !name.contains("app") && !name.contains("notes")));
+    assert!(names.iter().any(|name| name.starts_with('.') && name.ends_with(".log")));
+    let newer = Trace::read(&br#"{"version": 2, "root": "/", "entries": []}"#[..]);
+    assert_eq!(newer.map_err(|err| err.kind()), Err(std::io::ErrorKind::InvalidData));
+    fs::write(&file, "not a trace").unwrap();
+    assert!(matches!(replay(&file), Err(ExecutorError::InvalidTrace(_))));
+}
+
+#[test]
+fn test_ignored_entries_are_left_out_of_scans() {
+    use crate::sql::ignore::Ignore;
+
+    let dir = tempdir().unwrap();
+    for name in ["app/main.rs", "app/draft.tmp", "app/node_modules/left/index.js", "app/build/cache/x", "app/build/out"] {
+        let path = dir.path().join(name);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, name).unwrap();
+    }
+    let ignore = Ignore::new(&["node_modules", "*.tmp", "*/build/cache"]).unwrap();
+    let options = ExecutionOptions { ignore, ..ExecutionOptions::default() };
+    let names = |sql: String| {
+        let query = crate::sql::parse_sql(&sql).unwrap();
+        let mut names: Vec<_> =
+            execute_query_with_options(&query, &options).unwrap().into_iter().map(|file| file.name).collect();
+        names.sort();
+        names
+    };
+    let sql = format!("WITH RECURSIVE SELECT * FROM '{}/app' TYPE f", dir.path().display());
+    assert_eq!(names(sql), ["main.rs", "out"]);
+    // A target named in FROM is listed even if it matches
+    let sql = format!("SELECT * FROM '{}/app/node_modules'", dir.path().display());
+    assert_eq!(names(sql), ["left"]);
+}
+
+#[test]
+fn test_merge_shards() {
+    use crate::sql::executor::{execute_shard, merge_files, merge_groups};
+    use crate::sql::shard::Shard;
+
+    let dir = setup_test_directory();
+    fs::create_dir(dir.path().join("nested")).unwrap();
+    create_test_file(&dir.path().join("nested"), "more.txt", "a longer line of text").unwrap();
+    let run_shards = |sql: &str| -> Vec<_> {
+        let query = crate::sql::parse_sql(sql).unwrap();
+        (1..=3)
+            .map(|index| {
+                let shard = Some(Shard { index, count: 3 });
+                execute_shard(&query, &ExecutionOptions { shard, ..ExecutionOptions::default() }).unwrap()
+            })
+            .collect()
+    };
+
+    let grouped = format!(
+        "WITH RECURSIVE SELECT extension, COUNT(*), AVG(size), MIN(name) FROM '{}' TYPE f GROUP BY extension",
+        dir.path().display()
+    );
+    let merged = merge_groups(&run_shards(&grouped)).unwrap();
+    let whole = execute_grouped(&crate::sql::parse_sql(&grouped).unwrap()).unwrap();
+    assert_eq!(
+        merged.iter().map(|row| &row.columns).collect::<Vec<_>>(),
+        whole.iter().map(|row| &row.columns).collect::<Vec<_>>()
+    );
+
+    let top = format!("WITH RECURSIVE SELECT * FROM '{}' TYPE f ORDER BY size DESC LIMIT 2", dir.path().display());
+    let parts = run_shards(&top);
+    let options = ExecutionOptions::default();
+    let paths = |files: Vec<crate::sql::executor::FileResult>| files.into_iter().map(|file| file.path).collect::<Vec<_>>();
+    let whole = execute_query(&crate::sql::parse_sql(&top).unwrap()).unwrap();
+    assert_eq!(paths(merge_files(&parts, &options).unwrap()), paths(whole));
+
+    // Every shard has to be there, once
+    assert!(matches!(merge_files(&parts[..2], &options), Err(ExecutorError::InvalidShards(_))));
+    let twice = [parts[0].clone(), parts[0].clone(), parts[1].clone()];
+    assert!(matches!(merge_files(&twice, &options), Err(ExecutorError::InvalidShards(_))));
+    assert!(merge_groups(&parts).is_err());
+}
+
+#[test]
+fn test_stats_view_reports_each_extension() {
+    let dir = setup_test_directory();
+    fs::create_dir(dir.path().join("nested")).unwrap();
+    create_test_file(&dir.path().join("nested"), "more.txt", "a longer line of text").unwrap();
+    let sql = format!("SELECT * FROM STATS('{}')", dir.path().display());
+    let rows = execute_grouped(&crate::sql::parse_sql(&sql).unwrap()).unwrap();
+
+    let txt = rows
+        .iter()
+        .find(|row| row.columns[0] == ("extension".to_string(), FileValue::String("txt".to_string())))
+        .expect("a row for .txt files");
+    let names: Vec<&str> = txt.columns.iter().map(|(name, _)| name.as_str()).collect();
+    assert_eq!(names, ["extension", "count", "total_size", "avg_size", "newest_modified"]);
+    let txt_files = execute_query(&crate::sql::parse_sql(&format!(
+        "WITH RECURSIVE SELECT * FROM '{}' TYPE f WHERE extension = 'txt'",
+        dir.path().display()
+    )).unwrap()).unwrap();
+    assert_eq!(txt.columns[1].1, FileValue::Integer(txt_files.len() as i64));
+    let total: u64 = txt_files.iter().map(|file| file.size).sum();
+    assert_eq!(txt.columns[2].1, FileValue::from_u64(total));
+
+    let extensions: Vec<&str> = rows
+        .iter()
+        .filter_map(|row| match &row.columns[0].1 {
+            FileValue::String(extension) => Some(extension.as_str()),
+            _ => None,
+        })
+        .collect();
+    let mut sorted = extensions.clone();
+    sorted.sort();
+    assert_eq!(extensions, sorted);
+}
+
+#[test]
+fn test_recent_lists_newest_first() {
+    use crate::sql::recent::recent_query;
+
+    let dir = tempdir().unwrap();
+    fs::create_dir(dir.path().join("nested")).unwrap();
+    let now = chrono::Utc::now().timestamp();
+    for (name, age) in [("a.txt", 3600), ("nested/b.txt", 60), ("c.txt", 600), ("old.txt", 86_400)] {
+        create_test_file(dir.path(), name, "text").unwrap();
+        let modified = filetime::FileTime::from_unix_time(now - age, 0);
+        filetime::set_file_mtime(dir.path().join(name), modified).unwrap();
+    }
+
+    let since = chrono::Utc::now() - chrono::Duration::hours(2);
+    let names = |limit| -> Vec<String> {
+        let query = recent_query(dir.path().to_path_buf(), since, limit);
+        execute_query(&query).unwrap().into_iter().map(|file| file.name).collect()
+    };
+    assert_eq!(names(None), ["b.txt", "c.txt", "a.txt"]);
+    assert_eq!(names(Some(2)), ["b.txt", "c.txt"]);
+}
+
+#[test]
+fn test_tagged_follows_renames() {
+    use crate::sql::tags::TagStore;
+
+    let dir = setup_test_directory();
+    let store = dir.path().join("tags.json");
+    let mut tags = TagStore::default();
+    tags.add("keep", &dir.path().join("file1.txt")).unwrap();
+    tags.add("keep", &dir.path().join("subdir/file3.txt")).unwrap();
+    tags.save(&store).unwrap();
+
+    let mut query = crate::sql::parse_sql(&format!(
+        "WITH RECURSIVE SELECT * FROM '{}' WHERE TAGGED('keep')",
+        dir.path().display()
+    ))
+    .unwrap();
+    if let FileQuery::Select { condition: Some(FileCondition::Tagged { store: path, .. }), .. } = &mut query {
+        *path = store.clone();
+    }
+    let names = |query: &FileQuery| {
+        let mut names: Vec<String> = execute_query(query).unwrap().into_iter().map(|f| f.name).collect();
+        names.sort();
+        names
+    };
+    assert_eq!(names(&query), ["file1.txt", "file3.txt"]);
+
+    // The tag goes with the file, and a changed store is read again
+    fs::rename(dir.path().join("file1.txt"), dir.path().join("renamed.txt")).unwrap();
+    assert_eq!(names(&query), ["file3.txt", "renamed.txt"]);
+    tags.remove("keep", &dir.path().join("subdir/file3.txt"));
+    tags.save(&store).unwrap();
+    assert_eq!(names(&query), ["renamed.txt"]);
+}
+
+#[cfg(feature = "fuse")]
+#[test]
+fn test_mount_entries_tell_same_names_apart() {
+    use crate::sql::mount::entries;
+
+    let dir = tempdir().unwrap();
+    for sub in ["a", "b", "c"] {
+        fs::create_dir(dir.path().join(sub)).unwrap();
+        fs::write(dir.path().join(sub).join("notes.txt"), sub).unwrap();
+    }
+    let query = crate::sql::parse_sql(&format!(
+        "WITH RECURSIVE SELECT * FROM {} WHERE extension = 'txt' ORDER BY path",
+        dir.path().display()
+    ))
+    .unwrap();
+    let results = execute_query(&query).unwrap();
+    let entries = entries(&results);
+    let names: Vec<_> = entries.iter().map(|entry| entry.name.to_str().unwrap()).collect();
+    assert_eq!(names, ["notes.txt", "notes (2).txt", "notes (3).txt"]);
+    assert_eq!(entries[2].target, dir.path().join("c").join("notes.txt"));
+}
+
+#[test]
+fn test_diagnostic_names_the_missing_path() {
+    use crate::error::{Diagnostic, FMQLError};
+
+    let dir = tempdir().unwrap();
+    let missing = dir.path().join("nope");
+    let sql = format!("SELECT * FROM {}", missing.display());
+    let err = FMQLError::from(execute_query(&crate::sql::parse_sql(&sql).unwrap()).unwrap_err());
+    let diagnostic = Diagnostic::from(&err);
+    assert_eq!(diagnostic.code, "exec.path_not_found");
+    assert_eq!(diagnostic.path.as_deref(), Some(missing.as_path()));
+    assert_eq!(diagnostic.span, None);
+}
+
+#[test]
+fn test_stable_order_sorts_by_path_before_limit() {
+    let dir = tempdir().unwrap();
+    for name in ["m.txt", "z.txt", "a.txt", "q.txt", "c.txt"] {
+        fs::write(dir.path().join(name), name).unwrap();
+    }
+    let options = ExecutionOptions {
+        stable_order: true,
+        ..ExecutionOptions::default()
+    };
+    let names = |sql: String| -> Vec<String> {
+        let query = crate::sql::parse_sql(&sql).unwrap();
+        execute_query_with_options(&query, &options)
+            .unwrap()
+            .into_iter()
+            .map(|file| file.name)
+            .collect()
+    };
+    let from = dir.path().display();
+    assert_eq!(names(format!("SELECT * FROM {} LIMIT 3", from)), ["a.txt", "c.txt", "m.txt"]);
+    // The query's own ORDER BY wins
+    assert_eq!(
+        names(format!("SELECT * FROM {} ORDER BY name DESC LIMIT 2", from)),
+        ["z.txt", "q.txt"]
+    );
+}
+
+#[test]
+fn test_disk_usage_and_is_sparse() {
+    let dir = tempdir().unwrap();
+    File::create(dir.path().join("holes.img")).unwrap().set_len(4 << 20).unwrap();
+    fs::write(dir.path().join("dense.bin"), vec![7u8; 64 * 1024]).unwrap();
+    let sql = format!("SELECT * FROM {} ORDER BY name", dir.path().display());
+    let results = execute_query(&crate::sql::parse_sql(&sql).unwrap()).unwrap();
+    let value = |index: usize, attribute| super::get_attribute_value(&results[index], &attribute).unwrap();
+
+    let FileValue::Integer(dense) = value(0, FileAttribute::DiskUsage) else { panic!() };
+    assert!(dense >= 64 * 1024);
+    assert_eq!(value(0, FileAttribute::IsSparse), FileValue::Boolean(false));
+    let FileValue::Integer(holes) = value(1, FileAttribute::DiskUsage) else { panic!() };
+    assert!(holes < 4 << 20);
+    assert_eq!(value(1, FileAttribute::IsSparse), FileValue::Boolean(true));
+
+    let sql = format!("SELECT * FROM {} WHERE is_sparse", dir.path().display());
+    let sparse = execute_query(&crate::sql::parse_sql(&sql).unwrap()).unwrap();
+    assert_eq!(sparse.len(), 1);
+    assert_eq!(sparse[0].name, "holes.img");
+}
+
+#[test]
+fn test_shared_extents_of_unshared_files() {
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("own.bin"), vec![3u8; 16 * 1024]).unwrap();
+    fs::create_dir(dir.path().join("sub")).unwrap();
+    let sql = format!("SELECT * FROM {} ORDER BY name", dir.path().display());
+    let results = execute_query(&crate::sql::parse_sql(&sql).unwrap()).unwrap();
+    let shared = super::get_attribute_value(&results[0], &FileAttribute::SharedExtents).unwrap();
+    // 0 where the file system answers FIEMAP, NULL where it doesn't
+    assert!(matches!(shared, FileValue::Integer(0) | FileValue::Null));
+    // Directories have no extents to share
+    let shared = super::get_attribute_value(&results[1], &FileAttribute::SharedExtents).unwrap();
+    assert_eq!(shared, FileValue::Null);
+}
+
+#[test]
+fn test_dry_run_reports_changes_without_making_them() {
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("a.txt"), "a").unwrap();
+    fs::write(dir.path().join("b.txt"), "b").unwrap();
+    fs::create_dir(dir.path().join("out")).unwrap();
+    fs::write(dir.path().join("out/b.txt"), "old").unwrap();
+    let options = ExecutionOptions {
+        dry_run: true,
+        stable_order: true,
+        ..Default::default()
+    };
+
+    // a.txt can take b.txt's name, since b.txt gives it up
+    let sql = format!(
+        "UPDATE {} SET name = CASE WHEN name = 'a.txt' THEN 'b.txt' ELSE 'c.txt' END, \
+         permissions = '600' WHERE extension = 'txt'",
+        dir.path().join("[ab].txt").display()
+    );
+    let query = crate::sql::parse_sql(&sql).unwrap();
+    let results = execute_query_with_options(&query, &options).unwrap();
+    let names: Vec<&str> = results.iter().map(|r| r.name.as_str()).collect();
+    assert_eq!(names, ["b.txt", "c.txt"]);
+    assert!(results.iter().all(|r| r.status == Some(OperationStatus::Ok) && r.changes.len() == 2));
+    assert!(dir.path().join("a.txt").exists());
+    assert!(!dir.path().join("c.txt").exists());
+
+    let sql = format!(
+        "COPY {} TO {}",
+        dir.path().join("[ab].txt").display(),
+        dir.path().join("out").display()
+    );
+    let query = crate::sql::parse_sql(&sql).unwrap();
+    let results = execute_query_with_options(&query, &options).unwrap();
+    assert_eq!(results[0].status, Some(OperationStatus::Ok));
+    assert_eq!(results[1].status, Some(OperationStatus::Failed));
+    assert!(results[1].error_message.as_deref().unwrap().contains("already exists"));
+    assert!(!dir.path().join("out/a.txt").exists());
+    assert_eq!(fs::read_to_string(dir.path().join("out/b.txt")).unwrap(), "old");
+}
+
+#[test]
+fn test_dry_runs_record_the_predicates_each_file_matched() {
+    use crate::sql::retention::{apply, plan, Policy};
+
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("big.log"), "x".repeat(2048)).unwrap();
+    fs::write(dir.path().join("old.log"), "x").unwrap();
+    fs::write(dir.path().join("notes.txt"), "x").unwrap();
+    let old = filetime::FileTime::from_unix_time(1_577_836_800, 0); // 2020-01-01
+    filetime::set_file_mtime(dir.path().join("old.log"), old).unwrap();
+    let options = ExecutionOptions {
+        dry_run: true,
+        stable_order: true,
+        ..Default::default()
+    };
+
+    // Only the side of the OR that held is reported, under both sides of the AND
+    let sql = format!(
+        "UPDATE {} SET permissions = '600' WHERE extension = 'log' \
+         AND (size > 1024 OR modified < '2021-01-01')",
+        dir.path().display()
+    );
+    let query = crate::sql::parse_sql(&sql).unwrap();
+    let results = execute_query_with_options(&query, &options).unwrap();
+    let matched: Vec<_> = results.iter().map(|r| (r.name.as_str(), r.matched.as_deref())).collect();
+    assert_eq!(
+        matched,
+        [
+            ("big.log", Some("extension = 'log' AND size > 1024")),
+            ("old.log", Some("extension = 'log' AND modified < '2021-01-01'")),
+        ]
+    );
+    let json = serde_json::to_value(&results[0]).unwrap();
+    assert_eq!(json["matched"], "extension = 'log' AND size > 1024");
+
+    // A real run doesn't say, and neither does a query without a condition
+    let sql = format!("SELECT * FROM {} WHERE extension = 'log'", dir.path().display());
+    let query = crate::sql::parse_sql(&sql).unwrap();
+    let results = execute_query_with_options(&query, &ExecutionOptions::default()).unwrap();
+    assert!(results.iter().all(|r| r.matched.is_none()));
+    let query = crate::sql::parse_sql(&format!("SELECT * FROM {}", dir.path().display())).unwrap();
+    let results = execute_query_with_options(&query, &options).unwrap();
+    assert!(results.iter().all(|r| r.matched.is_none()));
+
+    // A retention plan and its audit log give the rule's age as well
+    let audit = dir.path().join("audit.jsonl");
+    let policy = Policy::from_toml(&format!(
+        r#"
+        audit_log = '{audit}'
+
+        [[rule]]
+        name = "old logs"
+        query = "SELECT * FROM '{dir}' WHERE extension = 'log' OR name = 'notes.txt'"
+        after = "1y"
+        action = "delete"
+        "#,
+        audit = audit.display(),
+        dir = dir.path().display(),
+    ))
+    .unwrap();
+    let planned = plan(&policy, &ExecutionOptions::default()).unwrap();
+    assert_eq!(planned.len(), 1);
+    let matched = planned[0].matched.as_deref().unwrap();
+    assert!(matched.starts_with("extension = 'log' AND modified < '"), "{}", matched);
+    apply(&policy, &ExecutionOptions::default()).unwrap();
+    let line: serde_json::Value =
+        serde_json::from_str(fs::read_to_string(&audit).unwrap().trim()).unwrap();
+    assert_eq!(line["matched"], matched);
+    assert!(!dir.path().join("old.log").exists());
+}
+
+#[test]
+fn test_split_statements_ignores_quoted_and_commented_semicolons() {
+    use crate::sql::script::split_statements;
+
+    let script = "-- tidy up; carefully\n\
+                  UPDATE . SET name = 'a;b' WHERE name = \"x;y\";\n\
+                  ;\n\
+                  /* one;\n two */ SELECT * FROM .\n";
+    let statements = split_statements(script);
+    let lines: Vec<u64> = statements.iter().map(|s| s.line).collect();
+    assert_eq!(lines, [2, 5]);
+    assert!(statements[0].text.ends_with("name = \"x;y\""));
+    assert!(crate::sql::parse_sql(&statements[1].text).is_ok());
+}
+
+#[test]
+fn test_validate_catches_statements_that_clash() {
+    use crate::sql::script::validate;
+
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("report.txt"), "r").unwrap();
+    let path = dir.path().display();
+    let script = [
+        format!("UPDATE {} SET name = 'final.txt' WHERE name = 'report.txt'", path),
+        format!("COPY {} TO {}/backup WHERE name = 'report.txt'", path, path),
+        format!("SELECT * FROM {}", path),
+    ];
+    let queries: Vec<FileQuery> =
+        script.iter().map(|sql| crate::sql::parse_sql(sql).unwrap()).collect();
+    let problems = validate(&queries, &ExecutionOptions::default());
+    assert_eq!(problems.len(), 1);
+    assert_eq!(problems[0].statement, Some(2));
+    assert!(problems[0].to_string().contains("statement 1 renames it away first"));
+    assert!(validate(&queries[1..], &ExecutionOptions::default()).is_empty());
+    assert!(!dir.path().join("backup").exists());
+}
+
+#[test]
+fn test_copy_fails_fast_without_space() {
+    use crate::sql::space::available_bytes;
+
+    let dir = tempdir().unwrap();
+    // Sparse, so it takes no space itself, but a byte copy would
+    let big = fs::File::create(dir.path().join("big.img")).unwrap();
+    let free = available_bytes(dir.path()).unwrap();
+    if big.set_len(free + (1 << 30)).is_err() {
+        return; // the file system can't hold a file that size at all
+    }
+    fs::write(dir.path().join("small.txt"), "small").unwrap();
+
+    let sql = format!("COPY {} TO {}", dir.path().display(), dir.path().join("out").display());
+    let query = crate::sql::parse_sql(&sql).unwrap();
+    match execute_query(&query) {
+        Err(ExecutorError::InsufficientSpace(message)) => {
+            assert!(message.contains("but only"), "{}", message)
+        }
+        other => panic!("expected a space error, got {:?}", other),
+    }
+    // Nothing was copied, not even the file that would have fit
+    assert!(!dir.path().join("out").exists());
+
+    // Hardlinks on the same file system take no space
+    let sql = format!("{} USING HARDLINK", sql);
+    let results = execute_query(&crate::sql::parse_sql(&sql).unwrap()).unwrap();
+    assert!(results.iter().all(|r| r.status == Some(OperationStatus::Ok)));
+}
+
+#[test]
+fn test_copy_preserves_metadata_and_verifies() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = tempdir().unwrap();
+    let original = dir.path().join("src/report.txt");
+    fs::create_dir(dir.path().join("src")).unwrap();
+    fs::write(&original, "quarterly numbers").unwrap();
+    fs::set_permissions(&original, fs::Permissions::from_mode(0o640)).unwrap();
+    let old = std::time::SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_000_000_000);
+    fs::File::options().write(true).open(&original).unwrap().set_modified(old).unwrap();
+
+    let sql = format!(
+        "COPY {} TO {} VERIFY CHECKSUM",
+        dir.path().join("src").display(),
+        dir.path().join("kept").display()
+    );
+    let results = execute_query(&crate::sql::parse_sql(&sql).unwrap()).unwrap();
+    assert_eq!(results[0].status, Some(OperationStatus::Ok));
+    let checksum = results[0].checksum.as_deref().unwrap();
+    assert_eq!(checksum, blake3::hash(b"quarterly numbers").to_hex().as_str());
+    let kept = fs::metadata(dir.path().join("kept/report.txt")).unwrap();
+    assert_eq!(kept.modified().unwrap(), old);
+    assert_eq!(kept.permissions().mode() & 0o777, 0o640);
+
+    let sql = format!(
+        "COPY {} TO {} PRESERVE NONE SYNC",
+        dir.path().join("src").display(),
+        dir.path().join("fresh").display()
+    );
+    let results = execute_query(&crate::sql::parse_sql(&sql).unwrap()).unwrap();
+    assert_eq!(results[0].status, Some(OperationStatus::Ok));
+    assert_eq!(results[0].checksum, None);
+    let fresh = fs::metadata(dir.path().join("fresh/report.txt")).unwrap();
+    assert_ne!(fresh.modified().unwrap(), old);
+    let copied = fs::read_to_string(dir.path().join("fresh/report.txt")).unwrap();
+    assert_eq!(copied, "quarterly numbers");
+}
+
+#[test]
+fn test_copy_resumes_partial_file() {
+    use crate::sql::copy::is_transient;
+
+    let dir = tempdir().unwrap();
+    fs::create_dir_all(dir.path().join("src")).unwrap();
+    fs::create_dir_all(dir.path().join("dst")).unwrap();
+    let contents: Vec<u8> = (0..10_000u32).map(|i| (i % 251) as u8).collect();
+    let original = dir.path().join("src/disk.img");
+    fs::write(&original, &contents).unwrap();
+    let old = std::time::SystemTime::now() - std::time::Duration::from_secs(3600);
+    File::options().write(true).open(&original).unwrap().set_modified(old).unwrap();
+    // What an interrupted copy left, written since the original last changed
+    let partial = dir.path().join("dst/.disk.img.fmql-partial");
+    fs::write(&partial, &contents[..4000]).unwrap();
+
+    let sql = format!(
+        "COPY {} TO {}",
+        dir.path().join("src").display(),
+        dir.path().join("dst").display()
+    );
+    let results = execute_query(&crate::sql::parse_sql(&sql).unwrap()).unwrap();
+    assert_eq!(results[0].status, Some(OperationStatus::Ok));
+    let transfer = results[0].transfer.unwrap();
+    assert_eq!((transfer.resumed, transfer.bytes, transfer.retries), (4000, 6000, 0));
+    assert_eq!(fs::read(dir.path().join("dst/disk.img")).unwrap(), contents);
+    assert!(!partial.exists());
+
+    // A partial file older than the original is of some other version of it
+    fs::write(dir.path().join("dst/.copy.img.fmql-partial"), b"stale").unwrap();
+    let stale = dir.path().join("dst/.copy.img.fmql-partial");
+    File::options().write(true).open(&stale).unwrap().set_modified(old).unwrap();
+    fs::write(&original, b"fresh").unwrap();
+    fs::rename(&original, dir.path().join("src/copy.img")).unwrap();
+    let results = execute_query(&crate::sql::parse_sql(&sql).unwrap()).unwrap();
+    let copied = results.iter().find(|r| r.name == "copy.img").unwrap();
+    assert_eq!(copied.transfer.unwrap().resumed, 0);
+    assert_eq!(fs::read(dir.path().join("dst/copy.img")).unwrap(), b"fresh");
+
+    assert!(is_transient(&std::io::Error::from_raw_os_error(libc::ETIMEDOUT)));
+    assert!(!is_transient(&std::io::Error::from_raw_os_error(libc::ENOENT)));
+}
+
+#[test]
+fn test_failed_copy_leaves_an_existing_target_alone() {
+    use crate::sql::ast::CopyMethod;
+    use crate::sql::copy::{copy_file, CopyOptions};
+
+    let dir = tempdir().unwrap();
+    let original = dir.path().join("a.txt");
+    let target = dir.path().join("b.txt");
+    fs::write(&original, "new").unwrap();
+    fs::write(&target, "old").unwrap();
+
+    let methods = [CopyMethod::Bytes, CopyMethod::Reflink, CopyMethod::Hardlink, CopyMethod::Auto];
+    for method in methods {
+        let err = copy_file(&original, &target, method, &CopyOptions::default()).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::AlreadyExists, "{:?}", method);
+        assert_eq!(fs::read_to_string(&target).unwrap(), "old", "{:?}", method);
+    }
+    assert!(!dir.path().join(".b.txt.fmql-partial").exists());
+}
+
+#[test]
+fn test_compress_checks_copy_before_deleting_original() {
+    use crate::sql::compressed::read_content;
+
+    let dir = tempdir().unwrap();
+    let log = dir.path().join("app.log");
+    let text = "GET /health 200\n".repeat(1000);
+    fs::write(&log, &text).unwrap();
+    // Compressed already, whatever its name says
+    fs::write(dir.path().join("photo.dat"), b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR").unwrap();
+
+    let sql = format!("COMPRESS FROM {} USING ZSTD LEVEL 9 DELETE ORIGINAL", dir.path().display());
+    let results = execute_query(&crate::sql::parse_sql(&sql).unwrap()).unwrap();
+    assert_eq!(results.len(), 2);
+    let compressed = results.iter().find(|r| r.name == "app.log.zst").unwrap();
+    assert_eq!(compressed.status, Some(OperationStatus::Ok));
+    assert!(compressed.size < text.len() as u64 / 10);
+    assert!(!log.exists());
+    let target = dir.path().join("app.log.zst");
+    assert_eq!(read_content(&target, Some(1 << 20)).unwrap(), text.as_bytes());
+    let photo = results.iter().find(|r| r.name == "photo.dat").unwrap();
+    assert_eq!(photo.status, Some(OperationStatus::Skipped));
+    assert!(!dir.path().join("photo.dat.zst").exists());
+
+    // The compressed file is skipped in turn, and a target in the way fails
+    fs::write(&log, &text).unwrap();
+    fs::write(dir.path().join("app.log.gz"), b"in the way").unwrap();
+    let sql = format!("COMPRESS {} WHERE name = 'app.log'", dir.path().display());
+    let results = execute_query(&crate::sql::parse_sql(&sql).unwrap()).unwrap();
+    assert_eq!(results[0].status, Some(OperationStatus::Failed));
+    assert!(log.exists());
+}
+
+#[test]
+fn test_extract_unpacks_archives_inside_destination() {
+    use std::os::unix::fs::PermissionsExt;
+    use zip::write::SimpleFileOptions;
+
+    let dir = tempdir().unwrap();
+    let downloads = dir.path().join("downloads");
+    fs::create_dir(&downloads).unwrap();
+    let mut tarball = tar::Builder::new(flate2::write::GzEncoder::new(
+        File::create(downloads.join("site.tar.gz")).unwrap(),
+        flate2::Compression::default(),
+    ));
+    let mut header = tar::Header::new_gnu();
+    header.set_size(5);
+    header.set_mode(0o755);
+    tarball.append_data(&mut header, "./bin/run", &b"hello"[..]).unwrap();
+    tarball.into_inner().unwrap().finish().unwrap();
+    let mut evil = zip::ZipWriter::new(File::create(downloads.join("evil.zip")).unwrap());
+    evil.start_file("ok.txt", SimpleFileOptions::default()).unwrap();
+    evil.write_all(b"fine").unwrap();
+    evil.start_file("../../escaped.txt", SimpleFileOptions::default()).unwrap();
+    evil.write_all(b"gotcha").unwrap();
+    evil.finish().unwrap();
+    fs::write(downloads.join("notes.txt"), "not an archive").unwrap();
+
+    let sql = format!(
+        "EXTRACT FROM {} TO {}/unpacked/{{stem}}",
+        downloads.display(),
+        dir.path().display()
+    );
+    let results = execute_query(&crate::sql::parse_sql(&sql).unwrap()).unwrap();
+    let status = |name: &str| results.iter().find(|r| r.name == name).unwrap().status;
+    assert_eq!(status("site.tar.gz"), Some(OperationStatus::Ok));
+    assert_eq!(status("notes.txt"), Some(OperationStatus::Skipped));
+    assert_eq!(status("evil.zip"), Some(OperationStatus::Failed));
+    let run = dir.path().join("unpacked/site/bin/run");
+    assert_eq!(fs::read(&run).unwrap(), b"hello");
+    assert_eq!(run.metadata().unwrap().permissions().mode() & 0o777, 0o755);
+    // Nothing of the zip slip archive was written, not even its safe entry
+    assert!(!dir.path().join("unpacked/evil").exists());
+    assert!(!dir.path().join("escaped.txt").exists());
+
+    // Extracting again collides with the first run
+    let sql = format!("{} WHERE name = 'site.tar.gz'", sql.replace("EXTRACT FROM", "EXTRACT"));
+    let results = execute_query(&crate::sql::parse_sql(&sql).unwrap()).unwrap();
+    assert_eq!(results[0].status, Some(OperationStatus::Failed));
+    let sql = format!("{} ON CONFLICT SKIP", sql);
+    let results = execute_query(&crate::sql::parse_sql(&sql).unwrap()).unwrap();
+    assert_eq!(results[0].status, Some(OperationStatus::Ok));
+    let extracted = results[0].extracted.unwrap();
+    assert_eq!((extracted.files, extracted.skipped), (0, 1));
+}
+
+#[test]
+fn test_conditions_are_simplified_before_they_run() {
+    let dir = setup_test_directory();
+    let run = |condition: &str| {
+        let sql = format!("SELECT * FROM '{}' WHERE name LIKE 'file%' AND {}", dir.path().display(), condition);
+        let mut names: Vec<_> = execute_query(&crate::sql::parse_sql(&sql).unwrap())
+            .unwrap()
+            .into_iter()
+            .map(|file| file.name)
+            .collect();
+        names.sort();
+        names
+    };
+
+    // file1.txt has 13 bytes and file2.txt 15
+    assert_eq!(run("size > 1 AND size > 2 * 7 AND NOT NOT is_directory = FALSE"), vec!["file2.txt"]);
+    assert_eq!(run("size >= 13 AND size <= 13 AND size < 100"), vec!["file1.txt"]);
+    assert_eq!(run("NOT (size < 14 OR name LIKE '%.sh') AND is_directory = FALSE"), vec!["file2.txt"]);
+    // A contradiction matches nothing, even where the rest would fail
+    assert!(run("size > 100 AND size < 10 AND name > 3").is_empty());
+    assert!(run("size = 13 AND size = 15").is_empty());
+
+    let sql = format!("EXPLAIN SELECT * FROM '{}' WHERE size > 10 AND size > 1024 * 1024", dir.path().display());
+    let query = crate::sql::parse_sql(&sql).unwrap();
+    assert!(execute_query(&query).is_err());
+    let rows = execute_grouped(&query).unwrap();
+    let steps: Vec<_> = rows
+        .iter()
+        .map(|row| match (row.get("step"), row.get("detail")) {
+            (Some(FileValue::String(step)), Some(FileValue::String(detail))) => format!("{}: {}", step, detail),
+            other => panic!("Expected a step and its detail, got {:?}", other),
+        })
+        .collect();
+    assert_eq!(
+        steps,
+        vec![
+            "statement: SELECT".to_string(),
+            format!("scan: the entries of {}", dir.path().display()),
+            "where: size > 10 AND size > 1024 * 1024".to_string(),
+            "simplified: size > 1048576".to_string(),
+        ]
+    );
+    let sql = format!("EXPLAIN UPDATE '{}' SET name = 'x' WHERE size < 0 AND size > 0", dir.path().display());
+    let rows = execute_grouped(&crate::sql::parse_sql(&sql).unwrap()).unwrap();
+    assert_eq!(rows[1].get("detail"), Some(&FileValue::String("skipped: the WHERE clause can never hold".to_string())));
+    assert!(dir.path().join("file1.txt").exists());
+}
+
+#[test]
+fn test_generated_queries_match_what_their_conditions_hold_for() {
+    use crate::sql::optimize::simplify;
+    use crate::testing::{arb_select, assert_matches, Fixture};
+    use proptest::test_runner::{Config, TestRunner};
+
+    let fixture = Fixture::builder()
+        .file("a.txt", "")
+        .file("b.c", vec![b'b'; 1024])
+        .file("abc/ca.b", vec![b'c'; 3000])
+        .dir("c.a")
+        .build()
+        .unwrap();
+    assert_matches(&fixture, "WITH RECURSIVE SELECT * FROM {root} WHERE name LIKE '%b%'", &["abc", "abc/ca.b", "b.c"]);
+
+    let mut runner = TestRunner::new(Config::with_cases(128));
+    runner
+        .run(&arb_select(fixture.path()), |query| {
+            let FileQuery::Select { recursive, condition, .. } = &query else {
+                unreachable!()
+            };
+            let condition = condition.clone().unwrap_or(FileCondition::Constant(true));
+            // Some arithmetic divides by zero, and comparing its NULL fails
+            let Ok(mut expected) = fixture.filter(&condition) else {
+                return Ok(());
+            };
+            let simplified = fixture.filter(&simplify(condition.clone())).unwrap();
+            proptest::prop_assert_eq!(&simplified, &expected, "{}", condition);
+            if !recursive {
+                expected.retain(|path| !path.contains('/'));
+            }
+            let mut matched: Vec<String> = execute_query(&query)
+                .unwrap()
+                .into_iter()
+                .map(|file| file.path.strip_prefix(fixture.path()).unwrap().to_string_lossy().replace('\\', "/"))
+                .collect();
+            matched.sort();
+            proptest::prop_assert_eq!(matched, expected, "{}", condition);
+            Ok(())
+        })
+        .unwrap();
+}
+
+#[test]
+fn test_older_semantics_list_the_from_directory() {
+    use crate::sql::compat::Semantics;
+
+    let dir = setup_test_directory();
+    let query = crate::sql::parse_sql(&format!("SELECT * FROM '{}' WHERE is_directory = TRUE", dir.path().display())).unwrap();
+    let names = |semantics| {
+        let options = ExecutionOptions { semantics, stable_order: true, ..ExecutionOptions::default() };
+        execute_query_with_options(&query, &options).unwrap().into_iter().map(|file| file.path).collect::<Vec<_>>()
+    };
+    assert_eq!(names(Semantics::CURRENT), vec![dir.path().join("subdir")]);
+    assert_eq!(names(Semantics::V0_3), vec![dir.path().to_path_buf(), dir.path().join("subdir")]);
+    assert_eq!(Semantics::V0_3.differences().len(), 4);
+    assert!(Semantics::CURRENT.differences().is_empty());
+    assert_eq!("0.3.7".parse::<Semantics>(), Ok(Semantics::V0_3));
+}
+
+#[test]
+fn test_older_semantics_read_like_patterns_as_fmql_0_3_did() {
+    use crate::sql::compat::Semantics;
+    use crate::sql::faults::Faults;
+    use crate::testing::Fixture;
+
+    let fixture = Fixture::builder().file("abc", "plain").file("a.c", "plain").build().unwrap();
+    let run = |semantics, sql: &str| {
+        let options = ExecutionOptions { semantics, faults: Faults::strict(), ..ExecutionOptions::default() };
+        crate::sql::run(&fixture.sql(sql), &options).map(|files| {
+            let mut names: Vec<_> = files.into_iter().map(|file| file.name).collect();
+            names.sort();
+            names
+        })
+    };
+    let like = "SELECT * FROM {root} WHERE name LIKE 'A.C'";
+    assert_eq!(run(Semantics::CURRENT, like).unwrap(), vec!["a.c"]);
+    assert_eq!(run(Semantics::V0_3, like).unwrap(), vec!["a.c", "abc"]);
+
+    let null = "SELECT * FROM {root} WHERE signature_extension LIKE '%'";
+    assert!(run(Semantics::CURRENT, null).unwrap().is_empty());
+    assert!(run(Semantics::V0_3, null).unwrap_err().to_string().contains("LIKE can only be used"));
+    assert_eq!(Semantics::CURRENT.to_string().parse(), env!("CARGO_PKG_VERSION").parse::<Semantics>());
+}
+
+#[test]
+fn test_current_user_owns_the_files_it_creates() {
+    use crate::sql::users::current_user;
+    use crate::testing::{assert_matches, Fixture};
+
+    let fixture = Fixture::builder().file("a.txt", "").file("sub/b.txt", "").build().unwrap();
+    let mine = ["a.txt", "sub", "sub/b.txt"];
+    assert_matches(&fixture, "WITH RECURSIVE SELECT * FROM {root} WHERE owner = CURRENT_USER()", &mine);
+    assert_matches(&fixture, "WITH RECURSIVE SELECT * FROM {root} WHERE is_mine = TRUE", &mine);
+    assert_matches(&fixture, "WITH RECURSIVE SELECT * FROM {root} WHERE is_mine = FALSE", &[]);
+
+    let sql = fixture.sql("SELECT name, CURRENT_USER() AS me FROM {root} WHERE name = 'a.txt'");
+    let results = execute_query(&crate::sql::parse_sql(&sql).unwrap()).unwrap();
+    assert_eq!(results[0].computed["me"], FileValue::String(current_user()));
+    let sql = fixture.sql("SELECT CURRENT_USER('root') AS me FROM {root}");
+    assert!(execute_query(&crate::sql::parse_sql(&sql).unwrap()).is_err());
+}
+
+#[test]
+fn test_group_membership_of_owners() {
+    use crate::sql::users::current_group;
+    use crate::testing::{assert_matches, Fixture};
+
+    let fixture = Fixture::builder().file("a.txt", "").file("b.txt", "").build().unwrap();
+    let all = ["a.txt", "b.txt"];
+    assert_matches(&fixture, "SELECT * FROM {root} WHERE group = CURRENT_GROUP()", &all);
+    let sql = format!("SELECT * FROM {{root}} WHERE OWNER_IN_GROUP('{}')", current_group());
+    assert_matches(&fixture, &sql, &all);
+    assert_matches(&fixture, "SELECT * FROM {root} WHERE OWNER_IN_GROUP('no-such-group')", &[]);
+
+    let sql = fixture.sql("SELECT group, COUNT(*) FROM {root} GROUP BY group");
+    let rows = execute_grouped(&crate::sql::parse_sql(&sql).unwrap()).unwrap();
+    assert_eq!(rows.len(), 1);
+    assert_eq!(rows[0].columns[0], ("group".to_string(), FileValue::String(current_group())));
+    let warnings = crate::sql::capabilities::warnings(&crate::sql::parse_sql(&fixture.sql(
+        "SELECT * FROM {root} WHERE OWNER_IN_GROUP('no-such-group')",
+    )).unwrap());
+    assert_eq!(warnings.iter().map(|w| w.code).collect::<Vec<_>>(), ["unknown_group"]);
+}
+
+#[test]
+fn test_permission_booleans() {
+    use crate::testing::{assert_matches, Fixture};
+
+    let fixture = Fixture::builder()
+        .file("private", "")
+        .permissions("private", 0o600)
+        .file("shared", "")
+        .permissions("shared", 0o664)
+        .file("tool", "")
+        .permissions("tool", 0o755)
+        .build()
+        .unwrap();
+    assert_matches(&fixture, "SELECT * FROM {root} WHERE is_world_readable", &["shared", "tool"]);
+    assert_matches(&fixture, "SELECT * FROM {root} WHERE is_group_writable = TRUE", &["shared"]);
+    assert_matches(&fixture, "SELECT * FROM {root} WHERE is_other_executable", &["tool"]);
+    assert_matches(&fixture, "SELECT * FROM {root} WHERE NOT is_world_readable", &["private"]);
+}
+
+#[test]
+fn test_set_and_filter_on_fs_flags() {
+    use crate::sql::chattr::{check_protected, read_fs_flags};
+    use crate::testing::{assert_matches, Fixture};
+
+    let fixture = Fixture::builder().file("a.txt", "").file("b.txt", "").build().unwrap();
+    // Not every file system has attributes (tmpfs before Linux 6.0, for one)
+    if read_fs_flags(&fixture.path().join("a.txt")).ok().flatten().is_none() {
+        return;
+    }
+    let sql = fixture.sql("UPDATE {root} SET fs_flags = '+nodump' WHERE name = 'a.txt'");
+    let results = execute_query(&crate::sql::parse_sql(&sql).unwrap()).unwrap();
+    assert_eq!(results[0].status, Some(OperationStatus::Ok));
+    assert_matches(&fixture, "SELECT * FROM {root} WHERE fs_flags LIKE '%nodump%'", &["a.txt"]);
+
+    let sql = fixture.sql("UPDATE {root} SET fs_flags = '+sticky'");
+    let results = execute_query(&crate::sql::parse_sql(&sql).unwrap()).unwrap();
+    assert!(results.iter().all(|r| r.status == Some(OperationStatus::Failed)));
+    // Only immutable and append need CAP_LINUX_IMMUTABLE
+    assert!(check_protected(fixture.path(), 0x40, 0x0).is_ok());
+}
+
+#[test]
+fn test_file_capabilities() {
+    use crate::sql::filecaps::{decode, supported};
+    use crate::testing::{assert_matches, Fixture};
+
+    // Revision 1: cap_chown (0) permitted and inheritable, not effective
+    let rev1 = [0, 0, 0, 1, 1, 0, 0, 0, 1, 0, 0, 0];
+    assert_eq!(decode(&rev1).as_deref(), Some("cap_chown+ip"));
+    // Revision 3, effective: cap_net_raw (13) and cap_bpf (39), set in a namespace owned by 1000
+    let mut rev3 = vec![1, 0, 0, 3, 0, 0x20, 0, 0, 0, 0, 0, 0, 0x80, 0, 0, 0, 0, 0, 0, 0];
+    rev3.extend(1000u32.to_le_bytes());
+    assert_eq!(decode(&rev3).as_deref(), Some("cap_net_raw,cap_bpf+ep [rootid=1000]"));
+    assert_eq!(decode(&rev3[..20]), None);
+
+    let fixture = Fixture::builder().file("tool", "").permissions("tool", 0o755).build().unwrap();
+    if supported() {
+        assert_matches(&fixture, "SELECT * FROM {root} WHERE has_file_capabilities = FALSE", &["tool"]);
+    }
+    assert_matches(&fixture, "SELECT * FROM {root} WHERE file_capabilities != NULL", &[]);
+}
+
+#[test]
+fn test_quarantine_and_where_from() {
+    use crate::sql::quarantine::{parse_quarantine, parse_where_from, supported};
+    use crate::testing::{assert_matches, Fixture};
+
+    // Opened since the download, with no time or agent recorded
+    let record = parse_quarantine("01c1;;;").unwrap();
+    assert!(record.opened());
+    assert_eq!((record.downloaded, record.agent.as_str()), (None, ""));
+    assert_eq!(parse_quarantine("not hex;65a1b2c3;Chrome"), None);
+
+    let xml = br#"<?xml version="1.0" encoding="UTF-8"?>
+<plist version="1.0"><array>
+<string>https://github.com/a/b.zip</string><string></string>
+</array></plist>"#;
+    assert_eq!(parse_where_from(xml).as_deref(), Some("https://github.com/a/b.zip"));
+    assert_eq!(parse_where_from(b"<plist version=\"1.0\"><array/></plist>"), None);
+    assert_eq!(parse_where_from(b"garbage"), None);
+
+    let fixture = Fixture::builder().file("download.zip", "").build().unwrap();
+    if !supported() {
+        assert_matches(&fixture, "SELECT * FROM {root} WHERE quarantine != NULL", &[]);
+        assert_matches(&fixture, "SELECT * FROM {root} WHERE where_from = NULL", &["download.zip"]);
+    }
+}
+
+#[test]
+fn test_order_by_nulls_first_and_last() {
+    use crate::testing::Fixture;
+
+    // Text has no signature, so its signature_extension is NULL
+    let fixture = Fixture::builder()
+        .file("logo.png", b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR".to_vec())
+        .file("tool", b"\x7fELF\x02\x01\x01\0".to_vec())
+        .file("notes.txt", "short")
+        .file("readme.txt", "a little longer")
+        .build()
+        .unwrap();
+    let names = |order: &str| {
+        let sql = format!("SELECT * FROM {{root}} ORDER BY {}", order);
+        let results = fixture.run(&sql).unwrap();
+        results.into_iter().map(|file| file.name).collect::<Vec<_>>()
+    };
+
+    assert_eq!(
+        names("signature_extension NULLS FIRST, size DESC"),
+        ["readme.txt", "notes.txt", "tool", "logo.png"]
+    );
+    assert_eq!(
+        names("signature_extension DESC NULLS LAST, size"),
+        ["logo.png", "tool", "notes.txt", "readme.txt"]
+    );
+    // Without NULLS, they follow the direction: last ascending, first descending
+    assert_eq!(names("signature_extension, size")[3], "readme.txt");
+    assert_eq!(names("signature_extension DESC, size")[0], "notes.txt");
+}
+
+#[test]
+fn test_coalesce_and_nullif() {
+    use crate::sql::compat::Semantics;
+    use crate::testing::{assert_matches, Fixture};
+
+    let fixture = Fixture::builder()
+        .file("Makefile", "all:")
+        .file("LICENSE", "MIT")
+        .file("main.rs", "fn main() {}")
+        .build()
+        .unwrap();
+    let kind = "COALESCE(extension, '(none)')";
+    let sql = format!("SELECT {kind}, COUNT(*) FROM {{root}} GROUP BY {kind}");
+    let mut rows: Vec<_> = execute_grouped(&crate::sql::parse_sql(&fixture.sql(&sql)).unwrap())
+        .unwrap()
+        .into_iter()
+        .map(|row| (row.get(kind).cloned(), row.get("COUNT(*)").cloned()))
+        .collect();
+    rows.sort_by_key(|(kind, _)| format!("{:?}", kind));
+    let row = |kind: &str, count: i64| {
+        (Some(FileValue::String(kind.to_string())), Some(FileValue::Integer(count)))
+    };
+    assert_eq!(rows, [row("(none)", 2), row("rs", 1)]);
+
+    assert_matches(&fixture, "SELECT * FROM {root} WHERE extension IS NULL", &["LICENSE", "Makefile"]);
+    assert_matches(&fixture, "SELECT * FROM {root} WHERE extension IS NOT NULL", &["main.rs"]);
+    assert_matches(&fixture, "SELECT * FROM {root} WHERE extension = ''", &[]);
+    assert_matches(&fixture, "SELECT * FROM {root} WHERE NULLIF(name, 'LICENSE') IS NULL", &["LICENSE"]);
+    assert_matches(&fixture, "SELECT * FROM {root} WHERE size / 2 IS NOT NULL", &["LICENSE", "Makefile", "main.rs"]);
+
+    // As fmql 0.3 had it, a missing extension is an empty one
+    let options = ExecutionOptions { semantics: Semantics::V0_3, ..ExecutionOptions::default() };
+    let sql = fixture.sql("SELECT * FROM {root} WHERE extension = '' AND NOT is_directory");
+    let mut names: Vec<_> = crate::sql::run(&sql, &options).unwrap().into_iter().map(|file| file.name).collect();
+    names.sort();
+    assert_eq!(names, ["LICENSE", "Makefile"]);
+    assert_matches(&fixture, "SELECT * FROM {root} WHERE COALESCE(NULL, size, 0) > 5", &["main.rs"]);
+    assert!(fixture.run("SELECT NULLIF(name) AS n FROM {root}").is_err());
+}
+
+#[test]
+fn test_cast() {
+    use crate::sql::ast::CastType;
+    use crate::sql::functions::cast;
+    use crate::sql::timezone::Tz;
+    use crate::testing::{assert_matches, Fixture};
+
+    let text = |s: &str| FileValue::String(s.to_string());
+    let to = |value: FileValue, to| cast(&value, to, Tz::UTC);
+    assert_eq!(to(FileValue::Number(0o755 as f64), CastType::Octal), Ok(text("755")));
+    assert_eq!(to(text("0o4755"), CastType::Octal), Ok(FileValue::Integer(0o4755)));
+    assert_eq!(to(text(" 12.7 "), CastType::Integer), Ok(FileValue::Integer(12)));
+    assert_eq!(to(text("yes"), CastType::Boolean), Ok(FileValue::Boolean(true)));
+    assert_eq!(to(FileValue::Integer(86400), CastType::Date), to(text("1970-01-02"), CastType::Date));
+    assert_eq!(to(FileValue::Null, CastType::Number), Ok(FileValue::Null));
+    assert_eq!(to(text("abc"), CastType::Number), Err("Cannot convert abc to NUMBER".to_string()));
+    assert!(to(text("9"), CastType::Octal).is_err());
+
+    let fixture = Fixture::builder()
+        .file("run.sh", "#!/bin/sh")
+        .file("notes.txt", "hello")
+        .permissions("run.sh", 0o755)
+        .permissions("notes.txt", 0o644)
+        .build()
+        .unwrap();
+    assert_matches(&fixture, "SELECT * FROM {root} WHERE CAST(permissions AS OCTAL) = '755'", &["run.sh"]);
+    assert_matches(&fixture, "SELECT * FROM {root} WHERE permissions = CAST(644 AS OCTAL)", &["notes.txt"]);
+    assert_matches(&fixture, "SELECT * FROM {root} WHERE CAST(size AS TEXT) = '5'", &["notes.txt"]);
+    assert_matches(&fixture, "SELECT * FROM {root} WHERE modified > CAST('2000-01-01' AS DATE)", &["notes.txt", "run.sh"]);
+    assert!(fixture.run("SELECT CAST(name AS NUMBER) AS n FROM {root}").is_err());
+}
+
+#[test]
+fn test_integers_stay_exact() {
+    use crate::sql::aggregate::Accumulator;
+    use crate::sql::ast::{AggregateFunction, ArithmeticOperator};
+    use crate::sql::sort::compare_numbers;
+    use crate::testing::{assert_matches, Fixture};
+    use std::cmp::Ordering;
+
+    // 2^53 + 1 is the first integer a float can't hold
+    let big = 1i64 << 53;
+    assert_eq!(compare_numbers(&FileValue::Integer(big + 1), &FileValue::Number(big as f64)), Some(Ordering::Greater));
+    assert_eq!(
+        super::apply_arithmetic(&FileValue::Integer(big), ArithmeticOperator::Add, &FileValue::Integer(1)).unwrap(),
+        FileValue::Integer(big + 1)
+    );
+    assert_eq!(
+        super::apply_arithmetic(&FileValue::Integer(7), ArithmeticOperator::Div, &FileValue::Integer(2)).unwrap(),
+        FileValue::Number(3.5)
+    );
+    let mut sum = Accumulator::new(AggregateFunction::Sum);
+    for value in [FileValue::Integer(big), FileValue::Integer(1), FileValue::String("1".to_string())] {
+        sum.add(Some(&value)).unwrap();
+    }
+    assert_eq!(sum.finish(), FileValue::Integer(big + 2));
+
+    let fixture = Fixture::builder().file("a.txt", "hello").file("b.txt", "hi").build().unwrap();
+    assert_matches(&fixture, &format!("SELECT * FROM {{root}} WHERE size + {big} = {}", big + 5), &["a.txt"]);
+    let results = fixture.run(&format!("SELECT name, size + {big} AS big FROM {{root}} WHERE name = 'a.txt'")).unwrap();
+    let json = serde_json::to_value(&results[0]).unwrap();
+    assert_eq!(json["computed"]["big"], big as u64 + 5);
+    assert!(json["size"].is_u64());
+}
+
+#[test]
+fn test_durations() {
+    use crate::testing::{assert_matches, Fixture};
+    use chrono::{TimeDelta, Utc};
+
+    let now = Utc::now();
+    let fixture = Fixture::builder()
+        .file("old.log", "old")
+        .file("new.log", "new")
+        .modified("old.log", now - TimeDelta::days(100))
+        .modified("new.log", now - TimeDelta::hours(2))
+        .build()
+        .unwrap();
+    assert_matches(&fixture, "SELECT * FROM {root} WHERE age > INTERVAL '90 days'", &["old.log"]);
+    assert_matches(&fixture, "SELECT * FROM {root} WHERE age < '1d'", &["new.log"]);
+    assert_matches(&fixture, "SELECT * FROM {root} WHERE age > 86400", &["old.log"]);
+    assert_matches(&fixture, "SELECT * FROM {root} WHERE modified + INTERVAL '1w' < accessed", &["old.log"]);
+    assert_matches(&fixture, "SELECT * FROM {root} WHERE (accessed - modified) / 86400 > 99", &["old.log"]);
+
+    let day = FileValue::Duration(TimeDelta::days(1));
+    let add = |l: &FileValue, r: &FileValue| super::apply_arithmetic(l, ArithmeticOperator::Add, r).unwrap();
+    assert_eq!(add(&day, &FileValue::Duration(TimeDelta::hours(6))), FileValue::Duration(TimeDelta::hours(30)));
+    assert_eq!(add(&day, &FileValue::Integer(1)), FileValue::Integer(86401));
+
+    // Durations show as intervals, and as seconds in JSON
+    let results = fixture.run("SELECT name, age, accessed - modified AS idle FROM {root} WHERE name = 'new.log'").unwrap();
+    let idle = &results[0].computed["idle"];
+    assert!(matches!(idle, FileValue::Duration(delta) if *delta >= TimeDelta::hours(2)));
+    assert!(crate::sql::functions::to_text(idle).starts_with("2h"));
+    let json = serde_json::to_value(&results[0]).unwrap();
+    assert!(json["age"].as_f64().is_some_and(|seconds| seconds >= 7200.0));
+}
+
+#[test]
+fn test_try_gives_null_instead_of_failing() {
+    use crate::testing::Fixture;
+
+    let fixture = Fixture::builder().file("2024.txt", "").file("notes.txt", "").build().unwrap();
+    // One file whose name isn't a number fails the whole SELECT...
+    assert!(fixture.run("SELECT name, CAST(STEM(name) AS INTEGER) AS year FROM {root}").is_err());
+
+    // ...unless TRY turns its failure into NULL
+    let mut results = fixture.run("SELECT name, TRY(CAST(STEM(name) AS INTEGER)) AS year FROM {root}").unwrap();
+    results.sort_by(|a, b| a.name.cmp(&b.name));
+    let years: Vec<_> = results.iter().map(|file| file.computed["year"].clone()).collect();
+    assert_eq!(years, [FileValue::Integer(2024), FileValue::Null]);
+
+    assert!(fixture.run("SELECT name, TRY(size, name) AS both FROM {root}").is_err());
+}
+
+#[test]
+fn test_evaluation_faults_are_logged_or_fail_fast() {
+    use crate::sql::faults::Faults;
+    use crate::testing::Fixture;
+
+    let fixture = Fixture::builder().file("2024.txt", "").file("notes.txt", "").build().unwrap();
+    let sql = fixture.sql("SELECT * FROM {root} WHERE CAST(STEM(name) AS INTEGER) > 2000");
+
+    // The file the condition fails for is left out, and logged
+    let options = ExecutionOptions::default();
+    let results = crate::sql::run(&sql, &options).unwrap();
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].name, "2024.txt");
+    assert_eq!(options.faults.count(), 1);
+    assert_eq!(options.faults.kept()[0].path, fixture.path().join("notes.txt"));
+
+    // Strict, it fails the query instead
+    let strict = ExecutionOptions { faults: Faults::strict(), ..Default::default() };
+    let err = crate::sql::run(&sql, &strict).unwrap_err();
+    assert_eq!(err.code(), "exec.evaluation_failed");
+    assert_eq!(strict.faults.count(), 0);
+
+    // A missing value is neither less nor greater than anything, not a fault
+    let options = ExecutionOptions::default();
+    let sql = fixture.sql("SELECT * FROM {root} WHERE NULLIF(size, 0) > 10");
+    assert!(crate::sql::run(&sql, &options).unwrap().is_empty());
+    assert_eq!(options.faults.count(), 0);
+}
+
+#[test]
+fn test_like_matches_regex_metacharacters_literally() {
+    use crate::testing::Fixture;
+
+    let fixture = Fixture::builder()
+        .file("a.txt", "")
+        .file("aa.txt", "")
+        .file("a+.txt", "")
+        .file("atxt", "")
+        .file("[draft].md", "")
+        .file("d.md", "")
+        .file("notes (1).md", "")
+        .file("$HOME.env", "")
+        .file("HOME.env", "")
+        .build()
+        .unwrap();
+    for (pattern, expected) in [
+        ("a+.txt", vec!["a+.txt"]),
+        ("a.txt", vec!["a.txt"]),
+        ("%[%", vec!["[draft].md"]),
+        ("[d]%", vec![]),
+        ("%(1)%", vec!["notes (1).md"]),
+        ("$%", vec!["$HOME.env"]),
+        ("_.txt", vec!["a.txt"]),
+    ] {
+        let sql = fixture.sql(&format!("SELECT * FROM {{root}} WHERE name LIKE '{}'", pattern));
+        let mut names: Vec<_> =
+            crate::sql::run(&sql, &ExecutionOptions::default()).unwrap().into_iter().map(|file| file.name).collect();
+        names.sort();
+        assert_eq!(names, expected, "LIKE '{}'", pattern);
+    }
+}
+
+#[test]
+fn test_patterns_on_null_values_match_nothing() {
+    use crate::sql::faults::Faults;
+    use crate::testing::Fixture;
+
+    let fixture = Fixture::builder()
+        .file("notes.txt", "a todo list")
+        .file("sub/other.txt", "nothing")
+        .build()
+        .unwrap();
+    // A directory has no content: it neither matches nor fails to match
+    for (sql, expected) in [
+        ("WITH RECURSIVE SELECT * FROM {root} WHERE content LIKE '%todo%'", vec!["notes.txt"]),
+        ("WITH RECURSIVE SELECT * FROM {root} WHERE content NOT LIKE '%todo%'", vec!["other.txt"]),
+        ("WITH RECURSIVE SELECT * FROM {root} WHERE content REGEXP 'to+do'", vec!["notes.txt"]),
+        ("WITH RECURSIVE SELECT * FROM {root} WHERE content NOT REGEXP 'todo'", vec!["other.txt"]),
+    ] {
+        let sql = fixture.sql(sql);
+        let strict = ExecutionOptions { faults: Faults::strict(), ..Default::default() };
+        for options in [ExecutionOptions::default(), strict] {
+            let results = crate::sql::run(&sql, &options).unwrap();
+            let names: Vec<_> = results.iter().map(|file| file.name.as_str()).collect();
+            assert_eq!(names, expected, "{}", sql);
+            assert_eq!(options.faults.count(), 0, "{}", sql);
+        }
+    }
+
+    // Values that aren't text are still a mistake
+    let options = ExecutionOptions::default();
+    let sql = fixture.sql("SELECT * FROM {root} WHERE size LIKE '1%'");
+    assert!(crate::sql::run(&sql, &options).unwrap().is_empty());
+    assert!(options.faults.count() > 0);
+}
+
+#[test]
+fn test_invalid_regex_fails_the_query() {
+    use crate::testing::Fixture;
+
+    let fixture = Fixture::builder().file("notes.txt", "").build().unwrap();
+    let err = fixture.run("SELECT * FROM {root} WHERE name REGEXP '('").unwrap_err();
+    assert_eq!(err.code(), "parse.invalid_pattern");
+
+    // A condition built without the parser fails when it's run instead
+    let mut query = crate::sql::parse_sql(&fixture.sql("SELECT * FROM {root} WHERE name REGEXP 'a'")).unwrap();
+    if let FileQuery::Select { condition: Some(FileCondition::Regexp { pattern, .. }), .. } = &mut query {
+        *pattern = "(".to_string();
+    }
+    let err = execute_query(&query).unwrap_err();
+    assert!(matches!(err, ExecutorError::InvalidRegex(_)));
+}
+
+#[test]
+fn test_compiled_query_runs_on_other_roots() {
+    use crate::sql::CompiledQuery;
+    use crate::testing::Fixture;
+
+    let small = Fixture::builder().file("a.txt", "1").file("b.md", "22").build().unwrap();
+    let large = Fixture::builder().file("c.txt", "333").file("d.txt", "4444").build().unwrap();
+    let options = ExecutionOptions::default();
+
+    let sql = "SELECT extension, COUNT(*) AS n FROM . WHERE name LIKE '%.txt' GROUP BY extension";
+    let compiled = CompiledQuery::new(sql, &options).unwrap();
+    for (fixture, n) in [(&small, 1), (&large, 2), (&small, 1)] {
+        let rows = compiled.execute_grouped_on(fixture.path()).unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].get("n"), Some(&FileValue::Integer(n)));
+    }
+    assert!(compiled.execute_on(small.path()).is_err());
+
+    let compiled = CompiledQuery::new("SELECT * FROM STDIN", &options).unwrap();
+    assert!(compiled.execute_on(small.path()).is_err());
+}
+
+#[test]
+fn test_source_root_and_scan_id() {
+    use crate::testing::Fixture;
+
+    let fixture = Fixture::builder().file("a.txt", "").file("b.txt", "").build().unwrap();
+    let root = FileValue::String(fixture.path().display().to_string());
+    let run = |options: &ExecutionOptions| {
+        let sql = fixture.sql("SELECT name, source_root, scan_id FROM {root}");
+        let files = crate::sql::run(&sql, options).unwrap();
+        let ids: Vec<_> = files.iter().map(|file| file.selected.get(FileAttribute::ScanId)).collect();
+        assert_eq!(files[0].selected.get(FileAttribute::SourceRoot), Some(&root));
+        assert_eq!(ids[0], ids[1]);
+        ids[0].cloned().unwrap()
+    };
+
+    // Every run has an id of its own, unless the options give one
+    let options = ExecutionOptions::default();
+    assert_ne!(run(&options), run(&options));
+    let options = ExecutionOptions { scan_id: Some("nightly".to_string()), ..Default::default() };
+    assert_eq!(run(&options), FileValue::String("nightly".to_string()));
+
+    let options = ExecutionOptions { files_from: Some(vec![fixture.path().join("a.txt")]), ..Default::default() };
+    let files = crate::sql::run("SELECT name, source_root FROM STDIN", &options).unwrap();
+    assert_eq!(files[0].selected.get(FileAttribute::SourceRoot), Some(&FileValue::Null));
+}
+
+#[test]
+fn test_retry_policy_tries_transient_errors_again() {
+    use crate::sql::retry::RetryPolicy;
+    use std::io::ErrorKind;
+    use std::time::Duration;
+
+    let policy = RetryPolicy { retries: 3, delay: Duration::ZERO };
+    let fail = |kind: ErrorKind| {
+        let mut tries = 0;
+        let result: Result<(), ExecutorError> = policy.run(|| {
+            tries += 1;
+            Err(ExecutorError::IoError(std::io::Error::from(kind)))
+        });
+        assert!(result.is_err());
+        tries
+    };
+    assert_eq!(fail(ErrorKind::TimedOut), 4);
+    assert_eq!(fail(ErrorKind::PermissionDenied), 1);
+    assert_eq!(RetryPolicy { retries: 40, delay: Duration::from_secs(1) }.backoff(30), Duration::from_secs(10));
+
+    // Files that read fine are untouched by the policy, however strict
+    let dir = setup_test_directory();
+    let sql = format!("SELECT name FROM {} WHERE name LIKE '%.txt' AND content LIKE '%test%'", dir.path().display());
+    for retry in [RetryPolicy::NONE, RetryPolicy::default()] {
+        let options = ExecutionOptions { retry, ..Default::default() };
+        let files = crate::sql::run(&sql, &options).unwrap();
+        assert_eq!(files.iter().map(|file| file.name.as_str()).collect::<Vec<_>>(), ["file2.txt"]);
+        assert_eq!(options.faults.count(), 0);
+    }
+}
+
+#[test]
+#[allow(deprecated)]
+fn test_copy_retries_still_set_how_often_copy_tries_again() {
+    use crate::sql::retry::RetryPolicy;
+    use std::time::Duration;
+
+    let retry = RetryPolicy { retries: 1, delay: Duration::from_millis(5) };
+    let options = ExecutionOptions { retry, ..Default::default() };
+    assert_eq!(super::copy_retry(&options), retry);
+    let options = ExecutionOptions { copy_retries: 3, ..options };
+    assert_eq!(super::copy_retry(&options), RetryPolicy { retries: 3, ..retry });
+}
+
+#[test]
+fn test_free_and_total_space_of_a_file_system() {
+    use crate::sql::space::{available_bytes, total_bytes};
+    use crate::testing::{assert_matches, Fixture};
+
+    let fixture = Fixture::builder().file("a.txt", "").build().unwrap();
+    let sql = fixture.sql("SELECT FREE_SPACE(path) AS free, TOTAL_SPACE({root}) AS total FROM {root}");
+    let results = execute_query(&crate::sql::parse_sql(&sql).unwrap()).unwrap();
+    let total = total_bytes(fixture.path()).unwrap();
+    assert_eq!(results[0].computed["total"], FileValue::from_u64(total));
+    let free = results[0].computed["free"].as_f64().unwrap();
+    assert!(free > 0.0 && free <= total as f64);
+    assert!(available_bytes(fixture.path()).unwrap() <= total);
+
+    // There's never more free than there is in all
+    assert_matches(&fixture, "SELECT * FROM {root} WHERE FREE_SPACE({root}) > TOTAL_SPACE({root})", &[]);
+    assert_matches(&fixture, "SELECT * FROM {root} WHERE FREE_SPACE({root}) <= TOTAL_SPACE({root})", &["a.txt"]);
+    let sql = fixture.sql("SELECT FREE_SPACE(TRUE) AS free FROM {root}");
+    assert!(execute_query(&crate::sql::parse_sql(&sql).unwrap()).is_err());
+}
+
+#[test]
+fn test_archive_packs_files_by_date_and_checks_them() {
+    use crate::sql::extract::extract_archive;
+    use crate::sql::ast::ConflictPolicy;
+    use filetime::{set_file_mtime, FileTime};
+
+    let dir = tempdir().unwrap();
+    let reports = dir.path().join("reports");
+    fs::create_dir_all(reports.join("q")).unwrap();
+    let dated = |name: &str, contents: &str, seconds: i64| {
+        fs::write(reports.join(name), contents).unwrap();
+        set_file_mtime(reports.join(name), FileTime::from_unix_time(seconds, 0)).unwrap();
+    };
+    dated("a.csv", "2023 a", 1_684_000_000);
+    dated("q/b.csv", "2023 b", 1_690_000_000);
+    dated("c.csv", "2024 c", 1_710_000_000);
+
+    let sql = format!(
+        "WITH RECURSIVE ARCHIVE FROM {} INTO {}/archive/reports-{{YYYY}}.tar.gz DELETE ORIGINAL",
+        reports.display(),
+        dir.path().display()
+    );
+    let results = execute_query(&crate::sql::parse_sql(&sql).unwrap()).unwrap();
+    assert_eq!(results.len(), 3);
+    assert!(results.iter().all(|r| r.status == Some(OperationStatus::Ok)));
+    assert!(!reports.join("a.csv").exists() && !reports.join("q/b.csv").exists());
+
+    // Each year has an archive of its own, with the paths below FROM
+    let unpacked = dir.path().join("unpacked");
+    let archive = dir.path().join("archive/reports-2023.tar.gz");
+    let extracted = extract_archive(&archive, &unpacked, ConflictPolicy::Fail, false).unwrap();
+    assert_eq!(extracted.files, 2);
+    assert_eq!(fs::read_to_string(unpacked.join("q/b.csv")).unwrap(), "2023 b");
+    assert!(dir.path().join("archive/reports-2024.tar.gz").exists());
+
+    // An archive already there is never added to, and its files are kept
+    dated("d.csv", "2023 d", 1_684_000_000);
+    let results = execute_query(&crate::sql::parse_sql(&sql).unwrap()).unwrap();
+    assert_eq!(results[0].status, Some(OperationStatus::Failed));
+    assert!(reports.join("d.csv").exists());
+}
+
+#[test]
+fn test_diff_rows_of_two_runs_by_path() {
+    use crate::sql::diff::{diff_rows, parse_rows, to_rows};
+    use crate::testing::Fixture;
+
+    let fixture = Fixture::builder()
+        .file("kept.txt", "same")
+        .file("grown.txt", "a")
+        .file("gone.txt", "")
+        .build()
+        .unwrap();
+    let sql = fixture.sql("SELECT path, size FROM {root}");
+    let query = crate::sql::parse_sql(&sql).unwrap();
+    let old = to_rows(&execute_query(&query).unwrap()).unwrap();
+    fs::write(fixture.path().join("grown.txt"), "abc").unwrap();
+    fs::remove_file(fixture.path().join("gone.txt")).unwrap();
+    fs::write(fixture.path().join("new.txt"), "").unwrap();
+    let new = to_rows(&execute_query(&query).unwrap()).unwrap();
+
+    let diff = diff_rows(&old, &new, &["modified".to_string()]).unwrap();
+    let path = |name: &str| serde_json::Value::from(fixture.path().join(name).to_str().unwrap());
+    assert_eq!(diff.added.iter().map(|row| &row["path"]).collect::<Vec<_>>(), [&path("new.txt")]);
+    assert_eq!(diff.removed.iter().map(|row| &row["path"]).collect::<Vec<_>>(), [&path("gone.txt")]);
+    assert_eq!(diff.changed.len(), 1);
+    assert_eq!(serde_json::Value::from(diff.changed[0].path.as_str()), path("grown.txt"));
+    let change = &diff.changed[0].changes[0];
+    assert_eq!((change.attribute.as_str(), &change.old, &change.new), ("size", &1.into(), &3.into()));
+    assert!(diff_rows(&new, &new, &[]).unwrap().is_empty());
+
+    // Only rows with a path, once each, can be compared
+    let twice = parse_rows(r#"[{"path": "/a"}, {"path": "/a"}]"#).unwrap();
+    assert!(matches!(diff_rows(&twice, &new, &[]), Err(ExecutorError::InvalidResults(_))));
+    let groups = parse_rows(r#"{"groups": [{"extension": "txt", "COUNT(*)": 2}]}"#).unwrap();
+    assert!(diff_rows(&groups, &groups, &[]).is_err());
+    assert!(parse_rows(r#"{"files": 3}"#).is_err());
+}
+
+#[test]
+fn test_progress_events_follow_the_query() {
+    use crate::sql::events::{Event, Events};
+    use crate::testing::Fixture;
+    use std::sync::{Arc, Mutex};
+
+    let fixture = Fixture::builder()
+        .file("2024.txt", "")
+        .file("notes.txt", "")
+        .file("old/2019.txt", "")
+        .build()
+        .unwrap();
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let events = Events::new({
+        let seen = Arc::clone(&seen);
+        move |event| seen.lock().unwrap().push(event)
+    });
+    let options = ExecutionOptions { events, ..Default::default() };
+    let sql = "WITH RECURSIVE SELECT * FROM {root} WHERE CAST(STEM(name) AS INTEGER) > 2000";
+    crate::sql::run(&fixture.sql(sql), &options).unwrap();
+
+    let events = std::mem::take(&mut *seen.lock().unwrap());
+    assert_eq!(events[0], Event::ScanStarted { targets: vec![fixture.path().to_path_buf()] });
+    assert_eq!(events[1], Event::DirectoryEntered { path: fixture.path().to_path_buf() });
+    let entered = Event::DirectoryEntered { path: fixture.path().join("old") };
+    let matched = |name: &str| Event::EntryMatched { path: fixture.path().join(name) };
+    assert!(events.contains(&entered));
+    assert!(events.contains(&matched("2024.txt")) && events.contains(&matched("old/2019.txt")));
+    assert!(!events.contains(&matched("notes.txt")));
+    // notes.txt, and the directory old, have names that aren't numbers
+    let warned = events.iter().filter(|event| matches!(event, Event::Warning { .. })).count();
+    assert_eq!(warned, 2);
+    assert!(matches!(events.last(), Some(Event::Finished { rows: 2, error: None, .. })));
+
+    // Each file a statement deals with is reported as it is done
+    let sql = fixture.sql("UPDATE {root} SET name = 'done.txt' WHERE name = 'notes.txt'");
+    crate::sql::run(&sql, &options).unwrap();
+    let events = std::mem::take(&mut *seen.lock().unwrap());
+    assert!(events.contains(&Event::OperationApplied {
+        path: fixture.path().join("done.txt"),
+        status: OperationStatus::Ok,
+        error: None,
+    }));
+
+    // A query that fails still finishes
+    let limits = ResourceLimits { max_files_scanned: Some(1), ..Default::default() };
+    let limited = ExecutionOptions { limits, ..options.clone() };
+    assert!(crate::sql::run(&fixture.sql("SELECT * FROM {root}"), &limited).is_err());
+    let events = std::mem::take(&mut *seen.lock().unwrap());
+    assert!(matches!(events.last(), Some(Event::Finished { rows: 0, error: Some(_), .. })));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_access_changes_to_system_paths_must_be_privileged() {
+    use crate::sql::protect::Protection;
+
+    // Matches nothing, so nothing would change even if the check let it by
+    let sql = "UPDATE '/etc' SET permissions = '600' WHERE name = 'fmql-no-such-file'";
+    let query = crate::sql::parse_sql(sql).unwrap();
+    let err = execute_query_with_options(&query, &ExecutionOptions::default()).unwrap_err();
+    match err {
+        ExecutorError::Denied(message) => assert!(message.contains("system path"), "{}", message),
+        other => panic!("Expected the UPDATE to be denied, got {:?}", other),
+    }
+    // Walking all of /etc may still fail on what the test can't read, but
+    // not for want of privilege
+    let allowed = |query: &FileQuery, options: &ExecutionOptions| {
+        !matches!(execute_query_with_options(query, options), Err(ExecutorError::Denied(_)))
+    };
+    // A rename or a dry run doesn't need it
+    let rename = "UPDATE '/etc' SET name = 'x' WHERE name = 'fmql-no-such-file'";
+    let rename = crate::sql::parse_sql(rename).unwrap();
+    assert!(allowed(&rename, &ExecutionOptions::default()));
+    assert!(allowed(&query, &ExecutionOptions { dry_run: true, ..Default::default() }));
+
+    for protection in [
+        Protection { privileged: true, ..Default::default() },
+        Protection { allowed: vec![PathBuf::from("/etc")], ..Default::default() },
+    ] {
+        assert!(allowed(&query, &ExecutionOptions { protection, ..Default::default() }));
+    }
+
+    // Anywhere else is as it was
+    let dir = tempdir().unwrap();
+    create_test_file(dir.path(), "notes.txt", "").unwrap();
+    let sql = format!("UPDATE '{}' SET permissions = '600'", dir.path().display());
+    let results = execute_query(&crate::sql::parse_sql(&sql).unwrap()).unwrap();
+    assert_eq!(results.len(), 1);
+}
+
+#[test]
+fn test_group_by_dirname_in_parallel_matches_one_job() {
+    use crate::sql::executor::execute_grouped_with_options;
+
+    let dir = tempdir().unwrap();
+    for (sub, files) in [("a", 300), ("a/b", 5), ("c", 40), ("d", 0)] {
+        fs::create_dir_all(dir.path().join(sub)).unwrap();
+        for i in 0..files {
+            let body = "x".repeat(i % 7);
+            create_test_file(&dir.path().join(sub), &format!("{}.txt", i), &body).unwrap();
+        }
+    }
+    create_test_file(dir.path(), "top.log", "top").unwrap();
+
+    let sql = format!(
+        "WITH RECURSIVE SELECT DIRNAME(path), COUNT(*), SUM(size), MAX(name) FROM '{}' \
+         WHERE NOT is_directory GROUP BY DIRNAME(path)",
+        dir.path().display()
+    );
+    let query = crate::sql::parse_sql(&sql).unwrap();
+    let one = execute_grouped_with_options(&query, &ExecutionOptions::default()).unwrap();
+    let four = ExecutionOptions { jobs: 4, ..Default::default() };
+    let parallel = execute_grouped_with_options(&query, &four).unwrap();
+    assert_eq!(parallel, one);
+
+    // Each directory is a group, however many batches its files came in
+    assert_eq!(one.len(), 4);
+    let a = dir.path().join("a").display().to_string();
+    let group = one.iter().find(|row| row.get("DIRNAME(path)") == Some(&FileValue::String(a.clone())));
+    assert_eq!(group.unwrap().get("COUNT(*)"), Some(&FileValue::Integer(300)));
+
+    // A limit stops the workers as it stops one
+    let limited = ExecutionOptions {
+        jobs: 4,
+        limits: ResourceLimits { max_results: Some(2), ..Default::default() },
+        ..Default::default()
+    };
+    assert!(matches!(
+        execute_grouped_with_options(&query, &limited),
+        Err(ExecutorError::LimitExceeded(_))
+    ));
+}
+
+#[test]
+fn test_results_share_their_extensions_and_owners() {
+    use std::sync::Arc;
+
+    let dir = setup_test_directory();
+    let sql = format!(
+        "WITH RECURSIVE SELECT * FROM '{}' WHERE extension = 'txt'",
+        dir.path().display()
+    );
+    let results = execute_query(&crate::sql::parse_sql(&sql).unwrap()).unwrap();
+    assert_eq!(results.len(), 3);
+
+    // One copy of "txt" and of the owner's name, however many files have them
+    let first = &results[0];
+    for file in &results[1..] {
+        assert!(Arc::ptr_eq(file.extension.as_ref().unwrap(), first.extension.as_ref().unwrap()));
+        assert!(Arc::ptr_eq(file.owner.as_ref().unwrap(), first.owner.as_ref().unwrap()));
+    }
+
+    // They still serialize as plain strings
+    let json = serde_json::to_value(first).unwrap();
+    assert_eq!(json["extension"], "txt");
+
+    // Files read by other threads share the same copies
+    let options = ExecutionOptions { jobs: 4, ..ExecutionOptions::default() };
+    let parallel = crate::sql::run(&sql, &options).unwrap();
+    for file in &parallel {
+        assert!(Arc::ptr_eq(file.extension.as_ref().unwrap(), first.extension.as_ref().unwrap()));
+        assert!(Arc::ptr_eq(file.owner.as_ref().unwrap(), first.owner.as_ref().unwrap()));
+    }
+}
+
+#[test]
+fn test_display_file_results_with_options() {
+    use crate::sql::display::{DisplayOptions, PathStyle, SizeUnits};
+
+    let dir = setup_test_directory();
+    let sql = format!("SELECT * FROM '{}' WHERE name = 'file1.txt'", dir.path().display());
+    let results = execute_query(&crate::sql::parse_sql(&sql).unwrap()).unwrap();
+    let file = &results[0];
+    let modified = file.modified.format("%Y-%m-%d %H:%M:%S");
+    let path = file.path.display();
+    assert_eq!(file.to_string(), format!("{}  {:>14}  {}", modified, file.size, path));
+
+    // A date format chrono can't read falls back to the default
+    let options = DisplayOptions {
+        date_format: "%Q".to_string(),
+        size_units: SizeUnits::Decimal,
+        path_style: PathStyle::Name,
+        ..Default::default()
+    };
+    let size = format!("{} B", file.size);
+    let expected = format!("{}  {:>14}  file1.txt", modified, size);
+    assert_eq!(file.display_with(&options).to_string(), expected);
+
+    // A path outside the directory is shown in full
+    let options = DisplayOptions {
+        path_style: PathStyle::Relative(PathBuf::from("/elsewhere")),
+        ..Default::default()
+    };
+    assert!(file.display_with(&options).to_string().ends_with(&path.to_string()));
+}
+
+#[test]
+fn test_display_file_results_in_locale() {
+    use crate::sql::display::{DisplayOptions, SizeUnits};
+    use crate::sql::locale::Locale;
+    use crate::sql::timezone::Tz;
+    use chrono::{TimeZone, Utc};
+
+    let dir = tempdir().unwrap();
+    let file = create_test_file(dir.path(), "report.pdf", &"x".repeat(1536)).unwrap();
+    let modified = Utc.with_ymd_and_hms(2025, 3, 31, 9, 15, 0).unwrap();
+    File::options().write(true).open(&file).unwrap().set_modified(modified.into()).unwrap();
+    let sql = format!("SELECT * FROM '{}'", dir.path().display());
+    let results = execute_query(&crate::sql::parse_sql(&sql).unwrap()).unwrap();
+
+    let german: Locale = "de_DE.UTF-8".parse().unwrap();
+    let options = DisplayOptions { locale: german, ..Default::default() };
+    let line = results[0].display_with(&options).to_string();
+    assert!(line.starts_with(&format!("31.03.2025 09:15:00  {:>14}  ", "1.536")), "{}", line);
+    let options = DisplayOptions { size_units: SizeUnits::Binary, ..options };
+    assert!(results[0].display_with(&options).to_string().contains(" 1,5 KiB  "));
+
+    // A date format of the user's own wins over the locale's order
+    let options = DisplayOptions { date_format: "%Y".to_string(), ..options };
+    assert!(results[0].display_with(&options).to_string().starts_with("2025  "));
+
+    assert_eq!("C.UTF-8".parse::<Locale>(), Ok(Locale::C));
+    let canadian: Locale = "fr-CA".parse().unwrap();
+    assert_eq!(canadian.format_date(&modified, Tz::UTC), "2025-03-31");
+    assert_eq!(canadian.format_number(1234.5), "1\u{a0}234,5");
+    assert!("".parse::<Locale>().is_err());
+}
+
+#[test]
+fn test_budget_gives_up_on_slow_files() {
+    use crate::sql::faults::Faults;
+    use crate::testing::Fixture;
+
+    // Big enough to take several reads, each of which checks the budget
+    let big = "x".repeat(4 * 1024 * 1024) + "secret";
+    let fixture = Fixture::builder().file("big.log", big).file("small.log", "secret").build().unwrap();
+    let query = |budget: &str| {
+        let condition = format!("CONTAINS_STR(content, 'secret') BUDGET {} PER FILE", budget);
+        fixture.sql(&format!("SELECT * FROM {{root}} WHERE {} ORDER BY name", condition))
+    };
+
+    let options = ExecutionOptions::default();
+    let results = crate::sql::run(&query("'10s'"), &options).unwrap();
+    assert_eq!(results.len(), 2);
+    assert_eq!(options.faults.count(), 0);
+
+    // The big file runs out of time, doesn't match and is logged
+    let options = ExecutionOptions::default();
+    let results = crate::sql::run(&query("0.000001s"), &options).unwrap();
+    assert!(results.iter().all(|file| file.name != "big.log"));
+    let faults = options.faults.kept();
+    let fault = faults.iter().find(|fault| fault.path.ends_with("big.log")).unwrap();
+    assert!(fault.message.starts_with("Over budget"), "{}", fault.message);
+
+    let strict = ExecutionOptions { faults: Faults::strict(), ..Default::default() };
+    let err = crate::sql::run(&query("0.000001s"), &strict).unwrap_err();
+    assert_eq!(err.code(), "exec.evaluation_failed");
+}
+
+#[test]
+fn test_content_reads_utf16_and_latin1_files() {
+    use crate::sql::encoding::TextEncoding;
+    use crate::testing::Fixture;
+
+    let utf16 = |text: &str| text.encode_utf16().flat_map(u16::to_le_bytes).collect::<Vec<u8>>();
+    let fixture = Fixture::builder()
+        .file("notepad.log", [b"\xff\xfe".to_vec(), utf16("Service error\r\nCafé closed\r\n")].concat())
+        .file("service.log", utf16("Retrying\r\nFatal error\r\n"))
+        .file("legacy.txt", b"Caf\xe9 error\n".to_vec())
+        .file("plain.log", "Café error\n")
+        .build()
+        .unwrap();
+    let names = |condition: &str, encoding: TextEncoding| {
+        let sql = format!("SELECT name, matches FROM {{root}} WHERE {} ORDER BY name", condition);
+        let options = ExecutionOptions { encoding, ..Default::default() };
+        let results = crate::sql::run(&fixture.sql(&sql), &options).unwrap();
+        results.into_iter().map(|file| (file.name, file.matches)).collect::<Vec<_>>()
+    };
+
+    let found = names("CONTAINS_STR(content, 'error')", TextEncoding::Auto);
+    let found: Vec<(&str, Vec<&str>)> = found
+        .iter()
+        .map(|(name, matches)| (name.as_str(), matches.iter().map(|m| m.text.as_str()).collect()))
+        .collect();
+    assert_eq!(
+        found,
+        vec![
+            ("legacy.txt", vec!["Café error"]),
+            ("notepad.log", vec!["Service error"]),
+            ("plain.log", vec!["Café error"]),
+            ("service.log", vec!["Fatal error"]),
+        ]
+    );
+    let cafes = names("content LIKE '%Café%'", TextEncoding::Auto);
+    assert_eq!(cafes.len(), 3);
+
+    // Reading everything as UTF-8 misses what Windows wrote
+    let found = names("CONTAINS_STR(content, 'error')", TextEncoding::Utf8);
+    let found: Vec<&str> = found.iter().map(|(name, _)| name.as_str()).collect();
+    assert_eq!(found, ["legacy.txt", "plain.log"]);
+    assert_eq!(names("content LIKE '%Café%'", TextEncoding::Latin1).len(), 1);
+}
+
+#[test]
+fn test_document_metadata_attributes() {
+    use crate::testing::Fixture;
+    use zip::write::SimpleFileOptions;
+
+    let report = b"%PDF-1.4\n1 0 obj << /Type /Catalog /Pages 2 0 R >> endobj\n\
+        2 0 obj << /Type /Pages /Kids [] /Count 12 >> endobj\n\
+        3 0 obj << /Title (Annual report) /Author (Legal) >> endobj\n\
+        trailer << /Root 1 0 R /Info 3 0 R >>\n%%EOF\n";
+
+    // PDF 1.5 can pack the catalog and page tree into a compressed object stream
+    let (catalog, pages) = ("<< /Type /Catalog /Pages 2 0 R >>", "<< /Type /Pages /Count 3 >>");
+    let header = format!("1 0 2 {} ", catalog.len());
+    let mut encoder = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(format!("{}{}{}", header, catalog, pages).as_bytes()).unwrap();
+    let objects = encoder.finish().unwrap();
+    let mut packed = format!(
+        "%PDF-1.5\n5 0 obj\n<< /Type /ObjStm /N 2 /First {} /Length {} /Filter /FlateDecode >>\nstream\n",
+        header.len(),
+        objects.len()
+    )
+    .into_bytes();
+    packed.extend_from_slice(&objects);
+    packed.extend_from_slice(b"\nendstream\nendobj\ntrailer << /Root 1 0 R >>\n%%EOF\n");
+
+    let mut memo = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+    memo.start_file("docProps/core.xml", SimpleFileOptions::default()).unwrap();
+    memo.write_all(b"<cp:coreProperties><dc:title/><dc:creator>Ana Lima</dc:creator></cp:coreProperties>")
+        .unwrap();
+    let memo = memo.finish().unwrap().into_inner();
+
+    let fixture = Fixture::builder()
+        .file("report.pdf", report.to_vec())
+        .file("packed.pdf", packed)
+        .file("memo.docx", memo)
+        .file("notes.txt", "Annual report by Legal")
+        .build()
+        .unwrap();
+    let sql = fixture.sql("SELECT name, pdf_page_count, doc_title, doc_author FROM {root} ORDER BY name");
+    let results = crate::sql::run(&sql, &ExecutionOptions::default()).unwrap();
+    let rows: Vec<_> = results
+        .iter()
+        .map(|file| {
+            let value = |attribute| file.selected.get(attribute).cloned().unwrap();
+            (
+                file.name.as_str(),
+                value(FileAttribute::PdfPageCount),
+                value(FileAttribute::DocTitle),
+                value(FileAttribute::DocAuthor),
+            )
+        })
+        .collect();
+    let text = |text: &str| FileValue::String(text.to_string());
+    assert_eq!(
+        rows,
+        vec![
+            ("memo.docx", FileValue::Null, FileValue::Null, text("Ana Lima")),
+            ("notes.txt", FileValue::Null, FileValue::Null, FileValue::Null),
+            ("packed.pdf", FileValue::Integer(3), FileValue::Null, FileValue::Null),
+            ("report.pdf", FileValue::Integer(12), text("Annual report"), text("Legal")),
+        ]
+    );
+
+    let sql = fixture.sql("SELECT name FROM {root} WHERE doc_author = 'Legal' AND pdf_page_count > 10");
+    let results = crate::sql::run(&sql, &ExecutionOptions::default()).unwrap();
+    assert_eq!(results.iter().map(|file| file.name.as_str()).collect::<Vec<_>>(), ["report.pdf"]);
+}
+
+#[test]
+fn test_line_endings_and_bom_attributes() {
+    use crate::testing::Fixture;
+
+    // The \r\n straddles the end of the first 64 KiB read
+    let mut straddling = "x".repeat(64 * 1024 - 1).into_bytes();
+    straddling.extend_from_slice(b"\r\nlast\r\n");
+    let fixture = Fixture::builder()
+        .file("unix.rs", "fn main() {}\n")
+        .file("windows.bat", "@echo off\r\necho hi\r\n")
+        .file("mixed.md", "# Title\r\n\nText\n")
+        .file("notepad.txt", b"\xff\xfeh\0i\0\r\0\n\0".to_vec())
+        .file("excel.csv", "\u{feff}name,size\r\n")
+        .file("oneline.txt", "no newline at the end")
+        .file("image.png", b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR\n".to_vec())
+        .file("straddling.log", straddling)
+        .build()
+        .unwrap();
+    let sql = fixture.sql("SELECT name, line_endings, has_bom FROM {root} ORDER BY name");
+    let results = crate::sql::run(&sql, &ExecutionOptions::default()).unwrap();
+    let rows: Vec<_> = results
+        .iter()
+        .map(|file| {
+            let value = |attribute| file.selected.get(attribute).cloned().unwrap();
+            (file.name.as_str(), value(FileAttribute::LineEndings), value(FileAttribute::HasBom))
+        })
+        .collect();
+    let text = |text: &str| FileValue::String(text.to_string());
+    assert_eq!(
+        rows,
+        vec![
+            ("excel.csv", text("crlf"), FileValue::Boolean(true)),
+            ("image.png", FileValue::Null, FileValue::Boolean(false)),
+            ("mixed.md", text("mixed"), FileValue::Boolean(false)),
+            ("notepad.txt", text("crlf"), FileValue::Boolean(true)),
+            ("oneline.txt", FileValue::Null, FileValue::Boolean(false)),
+            ("straddling.log", text("crlf"), FileValue::Boolean(false)),
+            ("unix.rs", text("lf"), FileValue::Boolean(false)),
+            ("windows.bat", text("crlf"), FileValue::Boolean(false)),
+        ]
+    );
+
+    let sql = fixture.sql(
+        "SELECT name FROM {root} WHERE (line_endings = 'crlf' OR line_endings = 'mixed') AND NOT has_bom ORDER BY name",
+    );
+    let results = crate::sql::run(&sql, &ExecutionOptions::default()).unwrap();
+    let names: Vec<_> = results.iter().map(|file| file.name.as_str()).collect();
+    assert_eq!(names, ["mixed.md", "straddling.log", "windows.bat"]);
+}
+
+#[test]
+fn test_executable_format_attributes() {
+    use crate::testing::Fixture;
+
+    fn put(bytes: &mut Vec<u8>, at: usize, field: &[u8]) {
+        if bytes.len() < at + field.len() {
+            bytes.resize(at + field.len(), 0);
+        }
+        bytes[at..at + field.len()].copy_from_slice(field);
+    }
+
+    // A 64-bit x86 ELF file whose second section is its symbol table
+    let mut server = b"\x7fELF\x02\x01\x01".to_vec();
+    put(&mut server, 18, &0x3eu16.to_le_bytes());
+    put(&mut server, 0x28, &64u64.to_le_bytes());
+    put(&mut server, 0x3a, &64u16.to_le_bytes());
+    put(&mut server, 0x3c, &2u16.to_le_bytes());
+    put(&mut server, 64 + 64 + 4, &2u32.to_le_bytes());
+    put(&mut server, 64 * 3 - 1, &[0]);
+
+    // A 32-bit ARM ELF file with no section headers left
+    let mut firmware = b"\x7fELF\x01\x01\x01".to_vec();
+    put(&mut firmware, 18, &0x28u16.to_le_bytes());
+    put(&mut firmware, 52, &[0]);
+
+    // A 64-bit Windows program with a debug directory
+    let mut tool = b"MZ".to_vec();
+    put(&mut tool, 0x3c, &64u32.to_le_bytes());
+    put(&mut tool, 64, b"PE\0\0");
+    put(&mut tool, 68, &0x8664u16.to_le_bytes());
+    put(&mut tool, 64 + 24, &0x20bu16.to_le_bytes());
+    put(&mut tool, 64 + 24 + 108, &16u32.to_le_bytes());
+    put(&mut tool, 64 + 24 + 112 + 6 * 8 + 4, &28u32.to_le_bytes());
+
+    // A universal binary for Intel and Apple silicon, stripped for neither
+    let mut universal = 0xcafebabeu32.to_be_bytes().to_vec();
+    put(&mut universal, 4, &2u32.to_be_bytes());
+    for (index, cpu) in [0x0100_0007u32, 0x0100_000c].into_iter().enumerate() {
+        let offset = 4096 * (index + 1);
+        put(&mut universal, 8 + index * 20, &cpu.to_be_bytes());
+        put(&mut universal, 8 + index * 20 + 8, &(offset as u32).to_be_bytes());
+        put(&mut universal, offset, &0xfeedfacfu32.to_le_bytes());
+        put(&mut universal, offset + 4, &cpu.to_le_bytes());
+        put(&mut universal, offset + 16, &1u32.to_le_bytes());
+        put(&mut universal, offset + 20, &24u32.to_le_bytes());
+        // LC_SYMTAB with 5 symbols
+        put(&mut universal, offset + 32, &2u32.to_le_bytes());
+        put(&mut universal, offset + 36, &24u32.to_le_bytes());
+        put(&mut universal, offset + 44, &5u32.to_le_bytes());
+    }
+
+    let fixture = Fixture::builder()
+        .file("server", server)
+        .file("firmware.bin", firmware)
+        .file("tool.exe", tool)
+        .file("app", universal)
+        .file("Main.class", b"\xca\xfe\xba\xbe\0\0\0\x34".to_vec())
+        .file("run.sh", "#!/bin/sh\n")
+        .build()
+        .unwrap();
+    let sql = fixture.sql("SELECT name, binary_format, target_arch, is_stripped FROM {root} ORDER BY name");
+    let results = crate::sql::run(&sql, &ExecutionOptions::default()).unwrap();
+    let rows: Vec<_> = results
+        .iter()
+        .map(|file| {
+            let value = |attribute| file.selected.get(attribute).cloned().unwrap();
+            (
+                file.name.as_str(),
+                value(FileAttribute::BinaryFormat),
+                value(FileAttribute::TargetArch),
+                value(FileAttribute::IsStripped),
+            )
+        })
+        .collect();
+    let text = |text: &str| FileValue::String(text.to_string());
+    assert_eq!(
+        rows,
+        vec![
+            ("Main.class", FileValue::Null, FileValue::Null, FileValue::Null),
+            ("app", text("mach-o"), text("x86_64,aarch64"), FileValue::Boolean(false)),
+            ("firmware.bin", text("elf"), text("arm"), FileValue::Boolean(true)),
+            ("run.sh", FileValue::Null, FileValue::Null, FileValue::Null),
+            ("server", text("elf"), text("x86_64"), FileValue::Boolean(false)),
+            ("tool.exe", text("pe"), text("x86_64"), FileValue::Boolean(false)),
+        ]
+    );
+
+    let sql = fixture.sql("SELECT name FROM {root} WHERE binary_format = 'elf' AND NOT is_stripped");
+    let results = crate::sql::run(&sql, &ExecutionOptions::default()).unwrap();
+    assert_eq!(results.iter().map(|file| file.name.as_str()).collect::<Vec<_>>(), ["server"]);
+}
+
+#[test]
+fn test_select_from_a_container_image() {
+    use crate::testing::Fixture;
+
+    fn layer(entries: &[(&str, tar::EntryType, u32, &str)]) -> Vec<u8> {
+        let mut layer = tar::Builder::new(Vec::new());
+        for &(path, kind, mode, data) in entries {
+            let mut header = tar::Header::new_gnu();
+            header.set_entry_type(kind);
+            header.set_mode(mode);
+            header.set_mtime(1_700_000_000);
+            if kind == tar::EntryType::Symlink {
+                header.set_size(0);
+                layer.append_link(&mut header, path, data).unwrap();
+            } else {
+                header.set_size(data.len() as u64);
+                layer.append_data(&mut header, path, data.as_bytes()).unwrap();
+            }
+        }
+        layer.into_inner().unwrap()
+    }
+    let (file, dir, link) = (tar::EntryType::Regular, tar::EntryType::Directory, tar::EntryType::Symlink);
+    let base = layer(&[
+        ("bin/", dir, 0o755, ""),
+        ("sbin", link, 0o777, "/bin"),
+        ("etc/passwd", file, 0o644, "root:x:0:0::/root:/bin/sh\n"),
+        ("etc/secret.key", file, 0o600, "hunter2"),
+        ("app/config/old.yml", file, 0o644, "debug: true\n"),
+        ("tmp/", dir, 0o1777, ""),
+    ]);
+    let mut app = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    app.write_all(&layer(&[
+        ("etc/.wh.secret.key", file, 0o644, ""),
+        ("app/config/.wh..wh..opq", file, 0o644, ""),
+        ("app/config/new.yml", file, 0o644, "debug: false\n"),
+        ("app/cache.db", file, 0o666, "cache"),
+        // Written through the symlink, into /bin
+        ("sbin/tool", file, 0o4755, "#!/bin/sh\n"),
+    ]))
+    .unwrap();
+    let app = app.finish().unwrap();
+
+    let mut image = tar::Builder::new(Vec::new());
+    let manifest = br#"[{"Config": "config.json", "Layers": ["base/layer.tar", "app/layer.tar.gz"]}]"#;
+    for (name, data) in [
+        ("base/layer.tar", &base[..]),
+        ("app/layer.tar.gz", &app[..]),
+        ("manifest.json", &manifest[..]),
+    ] {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(data.len() as u64);
+        header.set_mode(0o644);
+        image.append_data(&mut header, name, data).unwrap();
+    }
+    let fixture = Fixture::builder().file("image.tar", image.into_inner().unwrap()).build().unwrap();
+    let from = format!("docker-archive:{}/image.tar", fixture.path().display());
+
+    let sql = format!("WITH RECURSIVE SELECT path FROM '{}' TYPE file ORDER BY path", from);
+    let results = crate::sql::run(&sql, &ExecutionOptions::default()).unwrap();
+    let paths: Vec<_> = results.iter().map(|file| file.path.display().to_string()).collect();
+    let in_image = |path: &str| format!("{}{}", from, path);
+    assert_eq!(
+        paths,
+        [
+            in_image("/app/cache.db"),
+            in_image("/app/config/new.yml"),
+            in_image("/bin/tool"),
+            in_image("/etc/passwd"),
+        ]
+    );
+
+    // The layers' modes come through, so world-writable files can be found
+    let sql = format!(
+        "WITH RECURSIVE SELECT path, source_root FROM '{}' WHERE CAST(permissions AS OCTAL) = '666'",
+        from
+    );
+    let results = crate::sql::run(&sql, &ExecutionOptions::default()).unwrap();
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].path, PathBuf::from(in_image("/app/cache.db")));
+    let source_root = results[0].selected.get(FileAttribute::SourceRoot);
+    assert_eq!(source_root, Some(&FileValue::String(in_image("/"))));
+
+    let sql = format!("UPDATE '{}' SET permissions = '600'", from);
+    let update = crate::sql::run(&sql, &ExecutionOptions::default());
+    assert!(
+        matches!(update, Err(crate::error::FMQLError::Execution(ExecutorError::InvalidImage(_)))),
+        "{:?}",
+        update
+    );
+}
+
+#[test]
+fn test_select_from_a_backup_listing() {
+    use crate::sql::backups::{Listing, borg_archives, borg_entries, restic_entries, restic_snapshots};
+
+    let snapshots = br#"[
+        {"time": "2024-03-01T09:30:00+00:00", "id": "1a2b3c4d5e6f7a8b", "short_id": "1a2b3c4d"},
+        {"time": "2024-03-08T09:30:00+00:00", "id": "9f8e7d6c5b4a3928"}
+    ]"#;
+    let snapshots = restic_snapshots(snapshots).unwrap();
+    assert_eq!(
+        snapshots.iter().map(|snapshot| snapshot.name.as_str()).collect::<Vec<_>>(),
+        ["1a2b3c4d", "9f8e7d6c"]
+    );
+    let first = br#"{"struct_type":"snapshot","id":"1a2b3c4d5e6f7a8b","short_id":"1a2b3c4d"}
+{"struct_type":"node","type":"dir","path":"/home","mode":2147484141}
+{"struct_type":"node","type":"file","path":"/home/notes.txt","size":120,"mode":420}
+{"struct_type":"node","type":"file","path":"/home/disk.img","size":2000000000,"mode":384}
+{"struct_type":"node","type":"symlink","path":"/home/out","linktarget":"/etc","mode":134218239}
+{"struct_type":"node","type":"file","path":"/home/out/passwd","size":9,"mode":420}
+{"struct_type":"node","type":"file","path":"/../escaped","size":9,"mode":420}
+"#;
+    let second = br#"{"message_type":"snapshot","id":"9f8e7d6c5b4a3928"}
+{"message_type":"node","type":"file","path":"/home/notes.txt","size":240,"mode":420}
+{"message_type":"node","type":"file","path":"/usr/bin/tool","size":64,"mode":8389101}
+"#;
+    let mut restic = Listing::new("restic:/srv/restic".to_string()).unwrap();
+    restic.add(&snapshots[0].name, snapshots[0].taken, restic_entries(&first[..])).unwrap();
+    restic.add(&snapshots[1].name, snapshots[1].taken, restic_entries(&second[..])).unwrap();
+
+    // Which snapshot has a file larger than 1MB
+    let sql = "WITH RECURSIVE SELECT path FROM 'restic:/srv/restic' TYPE file WHERE size > 1000000";
+    let results = super::run_in_copy(
+        &crate::sql::parse_sql(sql).unwrap(),
+        &restic,
+        &ExecutionOptions::default(),
+    )
+    .unwrap();
+    let paths: Vec<_> = results.iter().map(|file| file.path.clone()).collect();
+    assert_eq!(paths, [PathBuf::from("restic:/srv/restic/1a2b3c4d/home/disk.img")]);
+
+    // Nothing is written through a symlink, nor outside the snapshot
+    let sql = "WITH RECURSIVE SELECT path, source_root FROM 'restic:/srv/restic' TYPE file ORDER BY path";
+    let results = super::run_in_copy(
+        &crate::sql::parse_sql(sql).unwrap(),
+        &restic,
+        &ExecutionOptions::default(),
+    )
+    .unwrap();
+    let paths: Vec<_> = results.iter().map(|file| file.path.display().to_string()).collect();
+    assert_eq!(
+        paths,
+        [
+            "restic:/srv/restic/1a2b3c4d/home/disk.img",
+            "restic:/srv/restic/1a2b3c4d/home/notes.txt",
+            "restic:/srv/restic/9f8e7d6c/home/notes.txt",
+            "restic:/srv/restic/9f8e7d6c/usr/bin/tool",
+        ]
+    );
+    let source_root = results[3].selected.get(FileAttribute::SourceRoot);
+    assert_eq!(source_root, Some(&FileValue::String("restic:/srv/restic/".to_string())));
+    let tool = fs::metadata(restic.root().join("9f8e7d6c/usr/bin/tool")).unwrap();
+    assert_eq!(std::os::unix::fs::PermissionsExt::mode(&tool.permissions()) & 0o7777, 0o4755);
+
+    let archives = br#"{"archives": [{"name": "host-2024-03-01", "id": "abc", "start": "2024-03-01T09:30:00.000000"}]}"#;
+    let archives = borg_archives(archives).unwrap();
+    let items = br#"{"type":"d","mode":"drwxr-xr-x","path":"home","size":0}
+{"type":"-","mode":"-rw-------","path":"home/disk.img","size":3000000000,"mtime":"2024-02-28T18:00:00.000000"}
+{"type":"-","mode":"-rwxrwxrwt","path":"home/shared","size":1,"mtime":"2024-02-28T18:00:00.000000"}
+"#;
+    let mut borg = Listing::new("borg:/srv/borg".to_string()).unwrap();
+    borg.add(&archives[0].name, archives[0].taken, borg_entries(&items[..])).unwrap();
+    let sql = "WITH RECURSIVE SELECT path FROM 'borg:/srv/borg' WHERE name = 'disk.img' AND size > 1000000";
+    let results = super::run_in_copy(
+        &crate::sql::parse_sql(sql).unwrap(),
+        &borg,
+        &ExecutionOptions::default(),
+    )
+    .unwrap();
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].path, PathBuf::from("borg:/srv/borg/host-2024-03-01/home/disk.img"));
+    let shared = fs::metadata(borg.root().join("host-2024-03-01/home/shared")).unwrap();
+    assert_eq!(std::os::unix::fs::PermissionsExt::mode(&shared.permissions()) & 0o7777, 0o1777);
+
+    let update = crate::sql::run("UPDATE 'borg:/srv/borg' SET permissions = '600'", &ExecutionOptions::default());
+    assert!(
+        matches!(update, Err(crate::error::FMQLError::Execution(ExecutorError::InvalidBackup(_)))),
+        "{:?}",
+        update
+    );
+}
+
+#[test]
+fn test_select_from_an_android_device() {
+    use crate::sql::android::{Device, DeviceSource, stat_entries};
+
+    let source = DeviceSource::parse(Path::new("adb://R58M12345/sdcard/DCIM")).unwrap();
+    assert_eq!(source.serial.as_deref(), Some("R58M12345"));
+    assert_eq!(source.path, "/sdcard/DCIM");
+    let stat = b"41f9 3452 1709285400 /sdcard/DCIM
+41f9 3452 1709285400 /sdcard/DCIM/Camera
+81b0 734003200 1709285400 /sdcard/DCIM/Camera/VID_0001.mp4
+81b0 52428800 1709285400 /sdcard/DCIM/Camera/VID_0002.mp4
+81b0 3145728 1709285400 /sdcard/DCIM/Camera/IMG_0003.jpg
+81b0 900000000 1709285400 /sdcard/DCIM/.thumbnails/big.mp4
+stat: '/sdcard/DCIM/broken': No such file or directory
+";
+    let device = Device::new(&source, stat_entries(&stat[..])).unwrap();
+
+    let sql = "WITH RECURSIVE SELECT path FROM 'adb://R58M12345/sdcard/DCIM' \
+               WHERE extension = 'mp4' AND size > 500000000 AND NOT path LIKE '%/.thumbnails/%'";
+    let results =
+        super::run_in_copy(&crate::sql::parse_sql(sql).unwrap(), &device, &ExecutionOptions::default())
+            .unwrap();
+    let paths: Vec<_> = results.iter().map(|file| file.path.clone()).collect();
+    assert_eq!(paths, [PathBuf::from("adb://R58M12345/sdcard/DCIM/Camera/VID_0001.mp4")]);
+
+    let sql = "UPDATE 'adb:///sdcard/DCIM' SET name = 'x'";
+    let update = crate::sql::run(sql, &ExecutionOptions::default());
+    assert!(
+        matches!(update, Err(crate::error::FMQLError::Execution(ExecutorError::InvalidDevice(_)))),
+        "{:?}",
+        update
+    );
+}
+
+#[test]
+fn test_restore_puts_trashed_files_back() {
+    let dir = tempdir().unwrap();
+    let trash = dir.path().join("Trash");
+    let thesis = dir.path().join("thesis");
+    fs::create_dir_all(trash.join("files/figures")).unwrap();
+    fs::create_dir_all(trash.join("info")).unwrap();
+    fs::create_dir(&thesis).unwrap();
+    let trashed = |name: &str, original: &str, date: &str| {
+        fs::write(
+            trash.join("info").join(format!("{}.trashinfo", name)),
+            format!("[Trash Info]\nPath={}/{}\nDeletionDate={}\n", dir.path().display(), original, date),
+        )
+        .unwrap();
+    };
+    fs::write(trash.join("files/chapter 1.tex"), "draft").unwrap();
+    trashed("chapter 1.tex", "thesis/chapter%201.tex", "2024-03-01T09:30:00");
+    fs::write(trash.join("files/figures/plot.png"), "png").unwrap();
+    trashed("figures", "thesis/figures", "2024-03-02T10:00:00");
+    fs::write(trash.join("files/notes.txt"), "old notes").unwrap();
+    trashed("notes.txt", "thesis/notes.txt", "2024-03-03T11:00:00");
+    fs::write(thesis.join("notes.txt"), "new notes").unwrap();
+    fs::write(trash.join("files/stray.txt"), "no record").unwrap();
+
+    let sql = format!(
+        "WITH RECURSIVE SELECT name, original_path, deleted_at FROM '{}/files' ORDER BY name",
+        trash.display()
+    );
+    let results = execute_query(&crate::sql::parse_sql(&sql).unwrap()).unwrap();
+    let original = |name: &str| {
+        let file = results.iter().find(|file| file.name == name).unwrap();
+        super::get_attribute_value(file, &FileAttribute::OriginalPath).unwrap()
+    };
+    assert_eq!(
+        original("plot.png"),
+        FileValue::String(thesis.join("figures/plot.png").to_string_lossy().to_string())
+    );
+    assert_eq!(original("stray.txt"), FileValue::Null);
+    let deleted = results.iter().find(|file| file.name == "chapter 1.tex").unwrap();
+    assert!(matches!(
+        super::get_attribute_value(deleted, &FileAttribute::DeletedAt).unwrap(),
+        FileValue::DateTime(_)
+    ));
+
+    let sql = format!(
+        "RESTORE FROM '{}/files' WHERE original_path LIKE '{}/%' ON CONFLICT RENAME",
+        trash.display(),
+        thesis.display()
+    );
+    let results = execute_query(&crate::sql::parse_sql(&sql).unwrap()).unwrap();
+    assert_eq!(results.len(), 3);
+    assert!(results.iter().all(|file| file.status == Some(OperationStatus::Ok)), "{:?}", results);
+    assert_eq!(fs::read_to_string(thesis.join("chapter 1.tex")).unwrap(), "draft");
+    assert_eq!(fs::read_to_string(thesis.join("figures/plot.png")).unwrap(), "png");
+    // The file in the way is kept, and the restored one put beside it
+    assert_eq!(fs::read_to_string(thesis.join("notes.txt")).unwrap(), "new notes");
+    assert_eq!(fs::read_to_string(thesis.join("notes_1.txt")).unwrap(), "old notes");
+    let notes = results.iter().find(|file| file.name == "notes_1.txt").unwrap();
+    assert_eq!(notes.conflict, Some(ConflictOutcome::Renamed));
+    assert!(!trash.join("info/figures.trashinfo").exists());
+    assert!(!trash.join("info/chapter 1.tex.trashinfo").exists());
+
+    // A file the trash has no record of can't be restored
+    let sql = format!("RESTORE FROM '{}/files'", trash.display());
+    let results = execute_query(&crate::sql::parse_sql(&sql).unwrap()).unwrap();
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].status, Some(OperationStatus::Failed));
+    assert!(trash.join("files/stray.txt").exists());
+}
+
+#[cfg(feature = "documents")]
+#[test]
+fn test_content_reads_the_text_of_documents() {
+    use std::io::Cursor;
+
+    use crate::testing::Fixture;
+    use zip::write::SimpleFileOptions;
+
+    let page = b"BT /F1 12 Tf 72 712 Td (Supply agreement) Tj 0 -14 Td \
+                 [(between Ac) -20 (me) -300 (Corp)] TJ ET";
+    let mut encoder = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(page).unwrap();
+    let page = encoder.finish().unwrap();
+    let mut pdf = format!("%PDF-1.4\n4 0 obj\n<< /Length {} /Filter /FlateDecode >>\nstream\n", page.len())
+        .into_bytes();
+    pdf.extend_from_slice(&page);
+    pdf.extend_from_slice(b"\nendstream\nendobj\n%%EOF\n");
+
+    let mut xlsx = zip::ZipWriter::new(Cursor::new(Vec::new()));
+    xlsx.start_file("xl/sharedStrings.xml", SimpleFileOptions::default()).unwrap();
+    xlsx.write_all(b"<sst><si><t>Customer</t></si><si><r><t>Acme</t></r><r><t> Corp</t></r></si></sst>")
+        .unwrap();
+    xlsx.start_file("xl/worksheets/sheet1.xml", SimpleFileOptions::default()).unwrap();
+    xlsx.write_all(
+        b"<worksheet><sheetData>\
+          <row><c t=\"s\"><v>0</v></c><c t=\"inlineStr\"><is><t>Total</t></is></c></row>\
+          <row><c t=\"s\"><v>1</v></c><c><v>99.5</v></c></row>\
+          </sheetData></worksheet>",
+    )
+    .unwrap();
+    let xlsx = xlsx.finish().unwrap().into_inner();
+
+    let fixture = Fixture::builder()
+        .file("contract.pdf", pdf)
+        .file("invoices.xlsx", xlsx)
+        .file("broken.pdf", "Acme, but not a PDF")
+        .file("notes.txt", "Call Acme")
+        .build()
+        .unwrap();
+    let sql = fixture.sql("SELECT name, matches FROM {root} WHERE content LIKE '%Acme Corp%' ORDER BY name");
+    let results = crate::sql::run(&sql, &ExecutionOptions::default()).unwrap();
+    let found: Vec<(&str, Vec<&str>)> = results
+        .iter()
+        .map(|file| (file.name.as_str(), file.matches.iter().map(|m| m.text.as_str()).collect()))
+        .collect();
+    assert_eq!(
+        found,
+        vec![("contract.pdf", vec!["between Acme Corp"]), ("invoices.xlsx", vec!["Acme Corp 99.5"])]
+    );
+
+    // A file that isn't the document its name says has no content
+    let sql = fixture.sql("SELECT name FROM {root} WHERE CONTAINS_STR(content, 'Acme') ORDER BY name");
+    let names: Vec<String> = crate::sql::run(&sql, &ExecutionOptions::default())
+        .unwrap()
+        .into_iter()
+        .map(|file| file.name)
+        .collect();
+    assert_eq!(names, ["contract.pdf", "invoices.xlsx", "notes.txt"]);
+}
+
+#[cfg(feature = "sqlite")]
+#[test]
+fn test_sqlite_joins_files_with_csv() {
+    use crate::sql::sqlite::{connect, select};
+
+    let dir = setup_test_directory();
+    let inventory = create_test_file(dir.path(), "kinds.csv", "ext,kind\ntxt,text\nini,config\n").unwrap();
+    let conn = connect(&ExecutionOptions::default()).unwrap();
+    conn.execute_batch(&format!(
+        "CREATE VIRTUAL TABLE temp.kinds USING csv(filename='{}', header=yes)",
+        inventory.display()
+    ))
+    .unwrap();
+
+    let sql = format!(
+        "SELECT k.kind, COUNT(*) AS n, SUM(f.size) AS bytes \
+         FROM fmql('WITH RECURSIVE SELECT * FROM ''{}''') AS f \
+         JOIN kinds AS k ON k.ext = f.extension GROUP BY k.kind ORDER BY k.kind",
+        dir.path().display()
+    );
+    let rows = select(&conn, &sql).unwrap();
+    assert_eq!(rows.len(), 2);
+    assert_eq!(rows[0].get("kind"), Some(&FileValue::String("config".to_string())));
+    assert_eq!(rows[0].get("n"), Some(&FileValue::Integer(1)));
+    assert_eq!(rows[1].get("n"), Some(&FileValue::Integer(3)));
+    assert_eq!(rows[1].get("bytes"), Some(&FileValue::Integer(40)));
+
+    // The fmql query only reads
+    let update = format!("SELECT * FROM fmql('UPDATE ''{}'' SET name = ''x''')", dir.path().display());
+    assert!(select(&conn, &update).is_err());
+}
@@ -0,0 +1,135 @@
+//! Shared extents: the parts of a file that reflinked copies share on disk.
+//!
+//! On btrfs, XFS with reflink and other copy-on-write file systems, `cp
+//! --reflink`, snapshots and deduplication tools leave several files
+//! pointing at the same blocks. Such copies look like duplicates by name,
+//! size and content, but they take the space only once, so counting them as
+//! wasted overstates what deduplicating would save. The `shared_extents`
+//! attribute is how many of a file's bytes are in extents it shares with
+//! another file or snapshot: 0 for a file with its own blocks, and up to its
+//! size for a reflinked copy nobody has written to since. `disk_usage -
+//! shared_extents` is about what deleting the file would free.
+//!
+//! The sharing is read with Linux's `FIEMAP` ioctl, which asks the file
+//! system for the file's extents and their flags. File systems that don't
+//! answer it, such as tmpfs and most network file systems, directories and
+//! other non-regular files, and every file on systems other than Linux,
+//! have a NULL `shared_extents`. macOS keeps APFS clone information private
+//! to the file system, so it is NULL there too.
+//!
+//! # Examples
+//!
+//! ```
+//! use std::fs;
+//!
+//! use fmql_core::sql::extents::shared_bytes;
+//!
+//! let dir = tempfile::tempdir().unwrap();
+//! let path = dir.path().join("own.bin");
+//! fs::write(&path, vec![1u8; 8192]).unwrap();
+//! // A freshly written file shares nothing, if the file system can say
+//! assert!(matches!(shared_bytes(&path).unwrap(), None | Some(0)));
+//! ```
+
+use std::io;
+use std::path::Path;
+
+/// Returns how many bytes of the file at `path` are in extents shared with
+/// other files, without following a symlink, or None if it can't be told.
+#[cfg(target_os = "linux")]
+pub fn shared_bytes(path: &Path) -> io::Result<Option<u64>> {
+    use std::fs::File;
+    use std::os::fd::AsRawFd;
+    use std::os::unix::fs::OpenOptionsExt;
+
+    /// `_IOWR('f', 11, struct fiemap)`
+    const FS_IOC_FIEMAP: libc::c_ulong = 0xC020_660B;
+    /// Write out delayed allocations first, so their extents are known.
+    const FIEMAP_FLAG_SYNC: u32 = 0x1;
+    const FIEMAP_EXTENT_LAST: u32 = 0x1;
+    const FIEMAP_EXTENT_SHARED: u32 = 0x2000;
+    /// How many extents each call asks for.
+    const BATCH: usize = 64;
+
+    #[repr(C)]
+    #[derive(Clone, Copy, Default)]
+    struct Extent {
+        logical: u64,
+        physical: u64,
+        length: u64,
+        reserved64: [u64; 2],
+        flags: u32,
+        reserved: [u32; 3],
+    }
+
+    #[repr(C)]
+    struct Request {
+        start: u64,
+        length: u64,
+        flags: u32,
+        mapped_extents: u32,
+        extent_count: u32,
+        reserved: u32,
+        extents: [Extent; BATCH],
+    }
+
+    if !path.symlink_metadata()?.is_file() {
+        return Ok(None);
+    }
+    let file = File::options()
+        .read(true)
+        .custom_flags(libc::O_NOFOLLOW | libc::O_NONBLOCK)
+        .open(path)?;
+    let size = file.metadata()?.len();
+    let mut shared = 0u64;
+    let mut start = 0u64;
+    while start < size {
+        let mut request = Request {
+            start,
+            length: u64::MAX - start,
+            flags: FIEMAP_FLAG_SYNC,
+            mapped_extents: 0,
+            extent_count: BATCH as u32,
+            reserved: 0,
+            extents: [Extent::default(); BATCH],
+        };
+        // SAFETY: the request is laid out as struct fiemap followed by
+        // room for the BATCH extents it says it has.
+        if unsafe { libc::ioctl(file.as_raw_fd(), FS_IOC_FIEMAP, &mut request) } != 0 {
+            let err = io::Error::last_os_error();
+            return match err.raw_os_error() {
+                Some(libc::EOPNOTSUPP | libc::ENOTTY | libc::EINVAL) => Ok(None),
+                _ => Err(err),
+            };
+        }
+        let mapped = (request.mapped_extents as usize).min(BATCH);
+        if mapped == 0 {
+            break;
+        }
+        for extent in &request.extents[..mapped] {
+            if extent.flags & FIEMAP_EXTENT_SHARED != 0 {
+                // The last extent can run past the end of the file
+                let end = extent.logical.saturating_add(extent.length).min(size);
+                shared += end.saturating_sub(extent.logical);
+            }
+        }
+        let last = &request.extents[mapped - 1];
+        if last.flags & FIEMAP_EXTENT_LAST != 0 {
+            break;
+        }
+        start = last.logical.saturating_add(last.length);
+    }
+    Ok(Some(shared))
+}
+
+/// Returns how many bytes of a file are in shared extents; only Linux can
+/// tell.
+#[cfg(not(target_os = "linux"))]
+pub fn shared_bytes(_path: &Path) -> io::Result<Option<u64>> {
+    Ok(None)
+}
+
+/// Returns true if this system can tell which extents are shared.
+pub fn supported() -> bool {
+    cfg!(target_os = "linux")
+}
@@ -0,0 +1,381 @@
+//! Unpacking archives for `EXTRACT`.
+//!
+//! [`extract_archive`] unpacks a zip file or a tarball (plain, gzipped or
+//! zstd-compressed, told apart by extension) into a directory. Archives
+//! come from anywhere, so nothing in one is trusted:
+//!
+//! - an entry whose path is absolute or climbs out with `..` fails the
+//!   whole archive before anything is written ("zip slip"), as does one
+//!   that would be written through a symlink already in the destination;
+//! - symlinks, hardlinks and device files in an archive are not created,
+//!   only counted as skipped, so no later entry can be led outside the
+//!   destination through one;
+//! - files get their permissions from the archive without setuid, setgid
+//!   or sticky bits.
+//!
+//! An entry whose path is already taken is dealt with by a
+//! [`ConflictPolicy`], and with `FAIL` every entry is checked first, so an
+//! archive that would collide writes nothing. Each file is written under a
+//! temporary name and renamed into place once complete.
+//!
+//! # Examples
+//!
+//! ```
+//! use std::io::Write;
+//!
+//! use fmql_core::sql::ast::ConflictPolicy;
+//! use fmql_core::sql::extract::{archive_stem, extract_archive};
+//!
+//! let dir = tempfile::tempdir().unwrap();
+//! let archive = dir.path().join("site.zip");
+//! let mut zip = zip::ZipWriter::new(std::fs::File::create(&archive).unwrap());
+//! zip.start_file("docs/index.html", zip::write::SimpleFileOptions::default()).unwrap();
+//! zip.write_all(b"<h1>hello</h1>").unwrap();
+//! zip.finish().unwrap();
+//!
+//! assert_eq!(archive_stem(&archive), "site");
+//! let unpacked = dir.path().join("site");
+//! let extracted = extract_archive(&archive, &unpacked, ConflictPolicy::Fail, false).unwrap();
+//! assert_eq!((extracted.files, extracted.bytes), (1, 14));
+//! assert!(unpacked.join("docs/index.html").exists());
+//!
+//! // Everything is there already, so with FAIL nothing is written
+//! assert!(extract_archive(&archive, &unpacked, ConflictPolicy::Fail, false).is_err());
+//! ```
+
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{self, BufReader, Read};
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Component, Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use chrono::{Local, NaiveDate, TimeZone};
+use serde::Serialize;
+
+use crate::sql::ast::ConflictPolicy;
+use crate::sql::executor::free_name;
+
+/// An archive format `EXTRACT` can unpack and `ARCHIVE` can write.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    Zip,
+    Tar,
+    TarGz,
+    TarZst,
+}
+
+/// The extensions of each format, longest first, so that `.tar.gz` wins
+/// over `.gz`.
+const EXTENSIONS: &[(&str, ArchiveFormat)] = &[
+    (".tar.gz", ArchiveFormat::TarGz),
+    (".tar.zst", ArchiveFormat::TarZst),
+    (".tgz", ArchiveFormat::TarGz),
+    (".tzst", ArchiveFormat::TarZst),
+    (".tar", ArchiveFormat::Tar),
+    (".zip", ArchiveFormat::Zip),
+];
+
+impl ArchiveFormat {
+    /// Returns the format a file's name says it is in, if any.
+    pub fn of(path: &Path) -> Option<ArchiveFormat> {
+        let name = path.file_name()?.to_str()?.to_ascii_lowercase();
+        EXTENSIONS
+            .iter()
+            .find(|(extension, _)| name.ends_with(extension) && name.len() > extension.len())
+            .map(|(_, format)| *format)
+    }
+}
+
+/// What unpacking an archive did.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct Extracted {
+    /// The files written.
+    pub files: u64,
+    /// The bytes in them.
+    pub bytes: u64,
+    /// The entries left out: links, device files, and under `SKIP`, files
+    /// whose paths were taken.
+    pub skipped: u64,
+}
+
+/// Returns an archive's name without its archive extension, such as
+/// `backup` for `backup.tar.gz`, for the `{stem}` of an EXTRACT
+/// destination.
+pub fn archive_stem(path: &Path) -> String {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let lower = name.to_ascii_lowercase();
+    EXTENSIONS
+        .iter()
+        .find(|(extension, _)| lower.ends_with(extension) && lower.len() > extension.len())
+        .map_or_else(
+            || name.to_string(),
+            |(extension, _)| name[..name.len() - extension.len()].to_string(),
+        )
+}
+
+/// Unpacks `archive` into `destination`, creating it as needed. A dry run
+/// checks every entry, and that nothing collides under `FAIL`, but writes
+/// nothing, and returns what would be written.
+///
+/// # Errors
+///
+/// Fails if the archive isn't in a format its name says it is in, or is
+/// damaged; if an entry would land outside `destination`; or, under `FAIL`,
+/// if an entry's path is taken. Files written before a later failure stay.
+pub fn extract_archive(
+    archive: &Path,
+    destination: &Path,
+    on_conflict: ConflictPolicy,
+    dry_run: bool,
+) -> io::Result<Extracted> {
+    let format = ArchiveFormat::of(archive).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} is not a zip file or a tarball", archive.display()),
+        )
+    })?;
+
+    // Every path is checked before anything is written
+    let mut planned = Extracted::default();
+    for_each_entry(archive, format, |entry| {
+        let target = safe_target(destination, &entry.path, entry.kind)?;
+        let taken = target.symlink_metadata().is_ok();
+        match entry.kind {
+            Kind::File if taken && on_conflict == ConflictPolicy::Fail => Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{} already exists", target.display()),
+            )),
+            Kind::File => {
+                planned.files += 1;
+                planned.bytes += entry.size;
+                Ok(())
+            }
+            Kind::Directory => Ok(()),
+            Kind::Other => {
+                planned.skipped += 1;
+                Ok(())
+            }
+        }
+    })?;
+    if dry_run {
+        return Ok(planned);
+    }
+
+    let mut extracted = Extracted::default();
+    fs::create_dir_all(destination)?;
+    for_each_entry(archive, format, |entry| {
+        let mut target = safe_target(destination, &entry.path, entry.kind)?;
+        match entry.kind {
+            Kind::Directory => return fs::create_dir_all(&target),
+            Kind::Other => {
+                extracted.skipped += 1;
+                return Ok(());
+            }
+            Kind::File => {}
+        }
+        let replace = match (target.symlink_metadata().is_ok(), on_conflict) {
+            (false, _) => false,
+            (true, ConflictPolicy::Skip) => {
+                extracted.skipped += 1;
+                return Ok(());
+            }
+            (true, ConflictPolicy::Overwrite) => true,
+            (true, ConflictPolicy::Rename) => {
+                target = free_name(&target);
+                false
+            }
+            (true, ConflictPolicy::Fail) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    format!("{} already exists", target.display()),
+                ));
+            }
+        };
+        let dir = target.parent().unwrap_or(destination);
+        fs::create_dir_all(dir)?;
+        let mut temp = tempfile::NamedTempFile::new_in(dir)?;
+        let bytes = io::copy(entry.reader, temp.as_file_mut())?;
+        let mode = entry.mode.unwrap_or(0o644) & 0o777;
+        temp.as_file().set_permissions(fs::Permissions::from_mode(mode))?;
+        if let Some(modified) = entry.modified {
+            temp.as_file().set_modified(modified)?;
+        }
+        if replace {
+            temp.persist(&target).map_err(|err| err.error)?;
+        } else {
+            temp.persist_noclobber(&target).map_err(|err| err.error)?;
+        }
+        extracted.files += 1;
+        extracted.bytes += bytes;
+        Ok(())
+    })?;
+    Ok(extracted)
+}
+
+/// Returns the BLAKE3 checksum of each file in an archive in `format`, by
+/// its path there, for `ARCHIVE` to check what it wrote.
+pub(crate) fn entry_checksums(
+    archive: &Path,
+    format: ArchiveFormat,
+) -> io::Result<BTreeMap<PathBuf, blake3::Hash>> {
+    let mut checksums = BTreeMap::new();
+    for_each_entry(archive, format, |entry| {
+        if entry.kind == Kind::File {
+            let mut hasher = blake3::Hasher::new();
+            hasher.update_reader(entry.reader)?;
+            checksums.insert(entry.path, hasher.finalize());
+        }
+        Ok(())
+    })?;
+    Ok(checksums)
+}
+
+/// What an archive entry is, as far as extracting goes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    File,
+    Directory,
+    /// A link or a device file, which is never created.
+    Other,
+}
+
+/// One entry of an archive, with its contents ready to read.
+struct Entry<'a> {
+    path: PathBuf,
+    kind: Kind,
+    size: u64,
+    mode: Option<u32>,
+    modified: Option<SystemTime>,
+    reader: &'a mut dyn Read,
+}
+
+/// Calls `f` for each entry of an archive, in order.
+fn for_each_entry(
+    archive: &Path,
+    format: ArchiveFormat,
+    mut f: impl FnMut(Entry<'_>) -> io::Result<()>,
+) -> io::Result<()> {
+    let file = BufReader::new(File::open(archive)?);
+    let tarball: Box<dyn Read> = match format {
+        ArchiveFormat::Zip => return for_each_zip_entry(archive, f),
+        ArchiveFormat::Tar => Box::new(file),
+        ArchiveFormat::TarGz => Box::new(flate2::read::MultiGzDecoder::new(file)),
+        ArchiveFormat::TarZst => Box::new(zstd::stream::Decoder::with_buffer(file)?),
+    };
+    let mut tarball = tar::Archive::new(tarball);
+    for entry in tarball.entries()? {
+        let mut entry = entry?;
+        let header = entry.header();
+        let kind = match header.entry_type() {
+            tar::EntryType::Regular | tar::EntryType::Continuous => Kind::File,
+            tar::EntryType::Directory => Kind::Directory,
+            // Metadata for the entries after them, which tar applies itself
+            tar::EntryType::XGlobalHeader | tar::EntryType::XHeader => continue,
+            tar::EntryType::GNULongName | tar::EntryType::GNULongLink => continue,
+            _ => Kind::Other,
+        };
+        let mode = header.mode().ok();
+        let modified = header
+            .mtime()
+            .ok()
+            .map(|secs| SystemTime::UNIX_EPOCH + Duration::from_secs(secs));
+        let path = entry.path()?.into_owned();
+        let size = entry.size();
+        f(Entry {
+            path,
+            kind,
+            size,
+            mode,
+            modified,
+            reader: &mut entry,
+        })?;
+    }
+    Ok(())
+}
+
+/// Calls `f` for each entry of a zip file, in order.
+fn for_each_zip_entry(
+    archive: &Path,
+    mut f: impl FnMut(Entry<'_>) -> io::Result<()>,
+) -> io::Result<()> {
+    let mut zip = zip::ZipArchive::new(BufReader::new(File::open(archive)?)).map_err(zip_error)?;
+    for index in 0..zip.len() {
+        let mut entry = zip.by_index(index).map_err(zip_error)?;
+        let kind = if entry.is_dir() {
+            Kind::Directory
+        } else if entry.is_symlink() {
+            Kind::Other
+        } else {
+            Kind::File
+        };
+        // Zip files record local times, to the even second
+        let modified = entry.last_modified().and_then(|time| {
+            let (year, month, day) = (time.year().into(), time.month().into(), time.day().into());
+            let date = NaiveDate::from_ymd_opt(year, month, day)?;
+            let time = date.and_hms_opt(
+                time.hour().into(),
+                time.minute().into(),
+                time.second().into(),
+            )?;
+            Local.from_local_datetime(&time).earliest().map(SystemTime::from)
+        });
+        f(Entry {
+            // Checked by safe_target, like a tar entry's path
+            path: PathBuf::from(entry.name()),
+            kind,
+            size: entry.size(),
+            mode: entry.unix_mode(),
+            modified,
+            reader: &mut entry,
+        })?;
+    }
+    Ok(())
+}
+
+/// Turns a zip error into an I/O error.
+fn zip_error(err: zip::result::ZipError) -> io::Error {
+    match err {
+        zip::result::ZipError::Io(err) => err,
+        err => io::Error::new(io::ErrorKind::InvalidData, err.to_string()),
+    }
+}
+
+/// Returns where an entry with the path `entry` goes in `destination`.
+/// Only a directory, such as the `./` many tarballs start with, may be
+/// the destination itself.
+///
+/// # Errors
+///
+/// Fails if the path is absolute, climbs out with `..`, or passes through
+/// a symlink already in the destination.
+fn safe_target(destination: &Path, entry: &Path, kind: Kind) -> io::Result<PathBuf> {
+    let unsafe_path = || {
+        io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!("the entry {} would land outside the destination", entry.display()),
+        )
+    };
+    let mut target = destination.to_path_buf();
+    for component in entry.components() {
+        match component {
+            Component::Normal(part) => {
+                // What's already there may not lead elsewhere
+                if target.symlink_metadata().is_ok_and(|metadata| metadata.is_symlink())
+                    && target != destination
+                {
+                    return Err(unsafe_path());
+                }
+                target.push(part);
+            }
+            Component::CurDir => {}
+            Component::ParentDir | Component::RootDir | Component::Prefix(_) => {
+                return Err(unsafe_path());
+            }
+        }
+    }
+    if target == destination && kind != Kind::Directory {
+        return Err(unsafe_path());
+    }
+    Ok(target)
+}
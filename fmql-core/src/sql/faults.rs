@@ -0,0 +1,101 @@
+//! The files a query's condition couldn't be evaluated for.
+//!
+//! A condition can fail for one file and not the others: text that isn't a
+//! number compared with one, or an attribute that can't be read for that
+//! file. Such a file doesn't match, and the failure is logged in the
+//! [`Faults`] of [`ExecutionOptions::faults`] rather than dropped, so `fmql
+//! sql` can warn about it after the query. With [`Faults::strict`] the
+//! first failure fails the whole query instead (`--strict-eval`).
+//!
+//! A mistake in the query itself, such as an invalid regular expression,
+//! fails the query either way, since it would fail for every file.
+//!
+//! [`ExecutionOptions::faults`]: crate::sql::ExecutionOptions::faults
+//!
+//! # Examples
+//!
+//! ```
+//! use fmql_core::sql::faults::Faults;
+//! use std::path::Path;
+//!
+//! let faults = Faults::default();
+//! let shared = faults.clone();
+//! shared.record(Path::new("notes.txt"), "Cannot compare 'notes' with a number".to_string());
+//! assert_eq!(faults.count(), 1);
+//! assert_eq!(faults.kept()[0].path, Path::new("notes.txt"));
+//! assert!(!faults.is_strict() && Faults::strict().is_strict());
+//! ```
+
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// A file a condition couldn't be evaluated for, and why.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Fault {
+    /// The file.
+    pub path: PathBuf,
+    /// What went wrong.
+    pub message: String,
+}
+
+/// How a query treats files its condition can't be evaluated for, and the
+/// log of those it found. Clones share the log, so a caller can keep one
+/// and read it once the query has run.
+#[derive(Debug, Clone, Default)]
+pub struct Faults {
+    strict: bool,
+    log: Arc<Mutex<Log>>,
+}
+
+/// The first faults found, and how many there were in all.
+#[derive(Debug, Default)]
+struct Log {
+    kept: Vec<Fault>,
+    count: usize,
+}
+
+impl Faults {
+    /// How many faults are kept; the rest are only counted.
+    pub const KEPT: usize = 100;
+
+    /// Returns faults that fail the query at the first file.
+    pub fn strict() -> Faults {
+        Faults {
+            strict: true,
+            ..Faults::default()
+        }
+    }
+
+    /// Returns true if the first fault fails the query.
+    pub fn is_strict(&self) -> bool {
+        self.strict
+    }
+
+    /// Logs a file whose condition couldn't be evaluated.
+    pub fn record(&self, path: &Path, message: String) {
+        let mut log = self.log();
+        log.count += 1;
+        if log.kept.len() < Self::KEPT {
+            log.kept.push(Fault {
+                path: path.to_path_buf(),
+                message,
+            });
+        }
+    }
+
+    /// Returns how many faults were logged.
+    pub fn count(&self) -> usize {
+        self.log().count
+    }
+
+    /// Returns the first [`Faults::KEPT`] faults, in the order found.
+    pub fn kept(&self) -> Vec<Fault> {
+        self.log().kept.clone()
+    }
+
+    /// Locks the log. A worker that panicked while holding it left it
+    /// whole, since each change is a single push or increment.
+    fn log(&self) -> MutexGuard<'_, Log> {
+        self.log.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
@@ -0,0 +1,183 @@
+//! File capabilities, as shown by `getcap` and set by `setcap`.
+//!
+//! On Linux a binary can be given some of root's powers without being
+//! setuid: `setcap cap_net_bind_service+ep /usr/bin/caddy` lets a web
+//! server bind port 443 as an ordinary user. Those grants are kept in the
+//! file's `security.capability` extended attribute, where `ls -l` doesn't
+//! show them, so they are easy to forget in a security review. The
+//! `file_capabilities` attribute decodes them into `getcap`'s text, such as
+//! `cap_net_bind_service+ep`, and `has_file_capabilities` says whether a
+//! file has any, so `WHERE has_file_capabilities` inventories the
+//! privileged executables on a system.
+//!
+//! A file without capabilities has a NULL `file_capabilities`; on systems
+//! other than Linux both attributes are NULL.
+//!
+//! # Examples
+//!
+//! ```
+//! use fmql_core::sql::filecaps::decode;
+//!
+//! // Revision 2, effective, with cap_net_bind_service (10) permitted
+//! let mut xattr = vec![0x01, 0, 0, 0x02];
+//! xattr.extend([0x00, 0x04, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+//! assert_eq!(decode(&xattr).as_deref(), Some("cap_net_bind_service+ep"));
+//! assert_eq!(decode(&[1, 2, 3]), None);
+//! ```
+
+use std::io;
+use std::path::Path;
+
+/// The names of the capabilities, by number, without their `cap_` prefix.
+const NAMES: &[&str] = &[
+    "chown",
+    "dac_override",
+    "dac_read_search",
+    "fowner",
+    "fsetid",
+    "kill",
+    "setgid",
+    "setuid",
+    "setpcap",
+    "linux_immutable",
+    "net_bind_service",
+    "net_broadcast",
+    "net_admin",
+    "net_raw",
+    "ipc_lock",
+    "ipc_owner",
+    "sys_module",
+    "sys_rawio",
+    "sys_chroot",
+    "sys_ptrace",
+    "sys_pacct",
+    "sys_admin",
+    "sys_boot",
+    "sys_nice",
+    "sys_resource",
+    "sys_time",
+    "sys_tty_config",
+    "mknod",
+    "lease",
+    "audit_write",
+    "audit_control",
+    "setfcap",
+    "mac_override",
+    "mac_admin",
+    "syslog",
+    "wake_alarm",
+    "block_suspend",
+    "audit_read",
+    "perfmon",
+    "bpf",
+    "checkpoint_restore",
+];
+
+/// The revision of the attribute's layout is in its first word's top byte.
+const REVISION_MASK: u32 = 0xFF00_0000;
+/// Revision 1 has 32 capabilities; 2 and 3 have 64, and 3 adds the root
+/// user ID of the user namespace that set them.
+const REVISIONS: &[(u32, usize)] = &[(0x0100_0000, 12), (0x0200_0000, 20), (0x0300_0000, 24)];
+/// The capabilities are effective as soon as the program runs.
+const EFFECTIVE: u32 = 0x1;
+
+/// Decodes a `security.capability` attribute into `getcap`'s text, such as
+/// `cap_net_bind_service+ep`. Capabilities with the same flags are listed
+/// together (`cap_chown,cap_kill+ep`), and groups with different ones are
+/// separated by spaces. A revision 3 attribute set inside a user namespace
+/// ends with `[rootid=N]`. Returns None if the attribute is malformed.
+pub fn decode(xattr: &[u8]) -> Option<String> {
+    let word = |index: usize| -> u32 {
+        let bytes = &xattr[index * 4..index * 4 + 4];
+        u32::from_le_bytes(bytes.try_into().expect("a slice of four bytes"))
+    };
+    if xattr.len() < 4 {
+        return None;
+    }
+    let magic = word(0);
+    let (revision, length) = REVISIONS
+        .iter()
+        .find(|(revision, _)| magic & REVISION_MASK == *revision)?;
+    if xattr.len() != *length {
+        return None;
+    }
+    // Pairs of permitted and inheritable words, low 32 capabilities first
+    let (mut permitted, mut inheritable) = (u64::from(word(1)), u64::from(word(2)));
+    if *revision != REVISIONS[0].0 {
+        permitted |= u64::from(word(3)) << 32;
+        inheritable |= u64::from(word(4)) << 32;
+    }
+    let effective = magic & EFFECTIVE != 0;
+
+    let mut groups: Vec<(String, Vec<String>)> = Vec::new();
+    for cap in 0..64 {
+        let (p, i) = (permitted & 1 << cap != 0, inheritable & 1 << cap != 0);
+        if !p && !i {
+            continue;
+        }
+        let flags: String = [(effective, 'e'), (i, 'i'), (p, 'p')]
+            .iter()
+            .filter(|(set, _)| *set)
+            .map(|(_, flag)| flag)
+            .collect();
+        let name = match NAMES.get(cap) {
+            Some(name) => format!("cap_{}", name),
+            None => format!("cap_{}", cap),
+        };
+        match groups.iter_mut().find(|(group, _)| *group == flags) {
+            Some((_, names)) => names.push(name),
+            None => groups.push((flags, vec![name])),
+        }
+    }
+    let mut text: Vec<String> = groups
+        .into_iter()
+        .map(|(flags, names)| format!("{}+{}", names.join(","), flags))
+        .collect();
+    if *revision == REVISIONS[2].0 && word(5) != 0 {
+        text.push(format!("[rootid={}]", word(5)));
+    }
+    Some(text.join(" "))
+}
+
+/// Reads and decodes the capabilities of a file, without following a
+/// symlink, or returns None if it has none.
+#[cfg(target_os = "linux")]
+pub fn read_capabilities(path: &Path) -> io::Result<Option<String>> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = CString::new(path.as_os_str().as_bytes())?;
+    let name = c"security.capability";
+    // No revision is longer than this
+    let mut buffer = [0u8; 24];
+    // SAFETY: both strings are NUL-terminated and outlive the call, and the
+    // buffer is as long as the size passed.
+    let size = unsafe {
+        libc::lgetxattr(path.as_ptr(), name.as_ptr(), buffer.as_mut_ptr().cast(), buffer.len())
+    };
+    let Ok(size) = usize::try_from(size) else {
+        let err = io::Error::last_os_error();
+        return match err.raw_os_error() {
+            Some(libc::ENODATA | libc::ENOTSUP) => Ok(None),
+            Some(libc::ERANGE) => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "the file's security.capability attribute is too long",
+            )),
+            _ => Err(err),
+        };
+    };
+    decode(&buffer[..size]).map(Some).ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidData, "malformed security.capability attribute")
+    })
+}
+
+/// Reads the capabilities of a file; only Linux has them.
+#[cfg(not(target_os = "linux"))]
+pub fn read_capabilities(_path: &Path) -> io::Result<Option<String>> {
+    Ok(None)
+}
+
+/// Returns true if this system can have file capabilities.
+pub fn supported() -> bool {
+    cfg!(target_os = "linux")
+}
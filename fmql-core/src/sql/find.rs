@@ -0,0 +1,693 @@
+//! Translating `find` expressions into queries, for `fmql translate`.
+//!
+//! Cron jobs and scripts full of `find` lines are the usual thing to move
+//! over to fmql, and rewriting each one by hand is where mistakes creep in.
+//! [`translate_find`] reads the start directory and expression of a `find`
+//! command line and builds the [`FileQuery`] that picks the same entries,
+//! which [`Translation::sql`] writes out as query text.
+//!
+//! The tests keep find's meaning rather than the nearest look-alike: find
+//! rounds sizes up to whole units and ages down to whole days, so
+//! `-size -1M` only matches empty files and `-mtime +30` files at least 31
+//! days old, and the query says so. `-name` is case-sensitive where SQL's
+//! LIKE isn't, so it becomes `=`, `STARTS_WITH`, `ENDS_WITH`, `CONTAINS_STR`
+//! or an anchored `REGEXP`. The tests translated are `-name`, `-iname`,
+//! `-path`, `-ipath`, `-wholename`, `-regex`, `-iregex`, `-type`, `-size`,
+//! `-mtime`, `-mmin`, `-empty`, `-user`, `-group`, `-perm` with an octal
+//! mode, `-executable`, `-true` and `-false`, with `!`, `-a`, `-o` and
+//! parentheses; `-maxdepth 1`, `-mindepth` of 0 or 1, and `-print`.
+//! Anything else, such as `-exec` or `-delete`, fails rather than being
+//! dropped, since a query that silently does less than the script it
+//! replaces is worse than none. Where a test is close but not exact, the
+//! translation carries a note saying how.
+//!
+//! # Examples
+//!
+//! ```
+//! use fmql_core::sql::find::translate_find;
+//! use fmql_core::sql::parse_sql;
+//!
+//! let translation = translate_find(r#"/var/log -name "*.log" -size +1M -mtime +30"#).unwrap();
+//! assert_eq!(
+//!     translation.sql(),
+//!     "WITH RECURSIVE SELECT * FROM '/var/log' INCLUDE SELF \
+//!      WHERE ENDS_WITH(name, '.log') AND size > 1048576 AND age >= INTERVAL '31d'"
+//! );
+//! assert!(parse_sql(&translation.sql()).is_ok());
+//!
+//! // What is done with the files is not for a query to say
+//! assert!(translate_find("/tmp -name '*.tmp' -delete").is_err());
+//! ```
+
+use std::path::PathBuf;
+
+use chrono::TimeDelta;
+
+use crate::sql::ast::{
+    ComparisonOperator, EntryType, FileAttribute, FileCondition, FileQuery, FileValue,
+    SubstringPosition,
+};
+use crate::sql::parser::{ParserError, Result};
+
+/// A `find` command line translated into a query.
+#[derive(Debug, Clone)]
+pub struct Translation {
+    path: String,
+    recursive: bool,
+    include_self: bool,
+    entry_type: Option<EntryType>,
+    condition: Option<FileCondition>,
+    notes: Vec<String>,
+}
+
+impl Translation {
+    /// Returns the query, which [`sql`](Translation::sql) writes out.
+    pub fn query(&self) -> FileQuery {
+        FileQuery::Select {
+            path: PathBuf::from(&self.path),
+            recursive: self.recursive,
+            include_self: self.include_self,
+            entry_type: self.entry_type,
+            join: None,
+            attributes: vec![FileAttribute::All],
+            computed: Vec::new(),
+            aggregates: Vec::new(),
+            condition: self.condition.clone(),
+            group_by: Vec::new(),
+            rollup: false,
+            order_by: Vec::new(),
+            limit: None,
+            limit_size: None,
+            within: None,
+            sample: None,
+            sample_rows: None,
+        }
+    }
+
+    /// Returns the query as text.
+    pub fn sql(&self) -> String {
+        let mut sql = String::new();
+        if self.recursive {
+            sql += "WITH RECURSIVE ";
+        }
+        sql += &format!("SELECT * FROM '{}'", self.path.replace('\'', "''"));
+        if self.include_self {
+            sql += " INCLUDE SELF";
+        }
+        match self.entry_type {
+            Some(EntryType::File) => sql += " TYPE f",
+            Some(EntryType::Directory) => sql += " TYPE d",
+            Some(EntryType::Symlink) => sql += " TYPE symlink",
+            None => {}
+        }
+        if let Some(condition) = &self.condition {
+            sql += &format!(" WHERE {}", condition);
+        }
+        sql
+    }
+
+    /// Returns how the query differs from what find would do, if it does.
+    pub fn notes(&self) -> &[String] {
+        &self.notes
+    }
+}
+
+/// Translates a `find` command line, such as
+/// `find /var/log -name '*.log' -mtime +30`, quoted as a shell would be.
+/// The leading `find` may be left out, and so may the start directory,
+/// which is then `.` as it is for GNU find.
+pub fn translate_find(command: &str) -> Result<Translation> {
+    let words = split_words(command)?;
+    let words = match words.split_first() {
+        Some((first, rest)) if first == "find" => rest,
+        _ => &words[..],
+    };
+    let starts = words
+        .iter()
+        .take_while(|word| !word.starts_with('-') && !matches!(word.as_str(), "(" | "!"))
+        .count();
+    let path = match &words[..starts] {
+        [] => ".".to_string(),
+        [path] => path.clone(),
+        paths => {
+            let message = format!(
+                "a query reads one directory, so translate {} separately",
+                paths.join(", ")
+            );
+            return Err(ParserError::Find(message));
+        }
+    };
+
+    let mut parser = FindParser {
+        words: &words[starts..],
+        position: 0,
+        max_depth: None,
+        min_depth: 0,
+        notes: Vec::new(),
+    };
+    let node = match parser.words.is_empty() {
+        true => Node::True,
+        false => parser.parse_or()?,
+    };
+    if let Some(word) = parser.words.get(parser.position) {
+        return Err(ParserError::Find(format!("unexpected {}", word)));
+    }
+
+    let mut conjuncts = Vec::new();
+    node.conjuncts(&mut conjuncts);
+    let mut entry_type = None;
+    let mut condition: Option<FileCondition> = None;
+    for node in conjuncts {
+        match node {
+            Node::Type(kind) if entry_type.is_some_and(|other| other != kind) => {
+                return Err(ParserError::Find("-type is given twice".to_string()));
+            }
+            Node::Type(kind) => entry_type = Some(kind),
+            node => condition = and(condition, node.condition()?),
+        }
+    }
+
+    let recursive = match parser.max_depth {
+        None => true,
+        Some(1) => false,
+        Some(depth) => {
+            let message = format!(
+                "a query lists one level or all of them, so -maxdepth {} can't be translated",
+                depth
+            );
+            return Err(ParserError::Find(message));
+        }
+    };
+    // The start directory is never a file or a symlink, so saying it may be
+    // listed would only add noise
+    let include_self = parser.min_depth == 0
+        && matches!(entry_type, None | Some(EntryType::Directory));
+    Ok(Translation {
+        path,
+        recursive,
+        include_self,
+        entry_type,
+        condition,
+        notes: parser.notes,
+    })
+}
+
+/// A parsed find expression. `-type` is kept apart so that it can become
+/// the query's `TYPE`, and tests that are always true, such as `-print`,
+/// so that they can be left out.
+#[derive(Debug)]
+enum Node {
+    Test(FileCondition),
+    Type(EntryType),
+    True,
+    And(Box<Node>, Box<Node>),
+    Or(Box<Node>, Box<Node>),
+    Not(Box<Node>),
+}
+
+impl Node {
+    /// Collects the nodes that are ANDed together at the top.
+    fn conjuncts(self, into: &mut Vec<Node>) {
+        match self {
+            Node::And(left, right) => {
+                left.conjuncts(into);
+                right.conjuncts(into);
+            }
+            node => into.push(node),
+        }
+    }
+
+    /// Returns the condition, or None if it is always true.
+    fn condition(self) -> Result<Option<FileCondition>> {
+        Ok(match self {
+            Node::Test(condition) => Some(condition),
+            Node::True => None,
+            Node::Type(_) => {
+                let message = "-type can only be translated when it applies to every entry, \
+                               not under ! or -o";
+                return Err(ParserError::Find(message.to_string()));
+            }
+            Node::And(left, right) => and(left.condition()?, right.condition()?),
+            Node::Or(left, right) => match (left.condition()?, right.condition()?) {
+                (Some(left), Some(right)) => {
+                    Some(FileCondition::Or(Box::new(left), Box::new(right)))
+                }
+                _ => None,
+            },
+            Node::Not(inner) => Some(match inner.condition()? {
+                Some(FileCondition::Not(inner)) => *inner,
+                Some(inner) => FileCondition::Not(Box::new(inner)),
+                None => FileCondition::Constant(false),
+            }),
+        })
+    }
+}
+
+fn and(left: Option<FileCondition>, right: Option<FileCondition>) -> Option<FileCondition> {
+    match (left, right) {
+        (Some(left), Some(right)) => Some(FileCondition::And(Box::new(left), Box::new(right))),
+        (left, right) => left.or(right),
+    }
+}
+
+/// Whether a number was given as `+n`, `-n` or `n`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Sign {
+    More,
+    Less,
+    Exactly,
+}
+
+struct FindParser<'a> {
+    words: &'a [String],
+    position: usize,
+    max_depth: Option<u64>,
+    min_depth: u64,
+    notes: Vec<String>,
+}
+
+impl FindParser<'_> {
+    fn peek(&self) -> Option<&str> {
+        self.words.get(self.position).map(String::as_str)
+    }
+
+    fn next(&mut self) -> Option<&str> {
+        let word = self.words.get(self.position)?;
+        self.position += 1;
+        Some(word)
+    }
+
+    /// Takes the argument of `test`.
+    fn argument(&mut self, test: &str) -> Result<String> {
+        match self.next() {
+            Some(argument) => Ok(argument.to_string()),
+            None => Err(ParserError::Find(format!("{} needs an argument", test))),
+        }
+    }
+
+    fn parse_or(&mut self) -> Result<Node> {
+        let mut node = self.parse_and()?;
+        while matches!(self.peek(), Some("-o" | "-or")) {
+            self.position += 1;
+            let right = self.parse_and()?;
+            node = Node::Or(Box::new(node), Box::new(right));
+        }
+        Ok(node)
+    }
+
+    /// Parses tests joined by `-a`, or by nothing at all, which means the
+    /// same.
+    fn parse_and(&mut self) -> Result<Node> {
+        let mut node = self.parse_unary()?;
+        loop {
+            match self.peek() {
+                None | Some("-o" | "-or" | ")") => return Ok(node),
+                Some("-a" | "-and") => self.position += 1,
+                Some(_) => {}
+            }
+            let right = self.parse_unary()?;
+            node = Node::And(Box::new(node), Box::new(right));
+        }
+    }
+
+    fn parse_unary(&mut self) -> Result<Node> {
+        match self.next() {
+            Some("!" | "-not") => Ok(Node::Not(Box::new(self.parse_unary()?))),
+            Some("(") => {
+                let node = self.parse_or()?;
+                match self.next() {
+                    Some(")") => Ok(node),
+                    _ => Err(ParserError::Find("( is never closed".to_string())),
+                }
+            }
+            Some(test) => {
+                let test = test.to_string();
+                self.parse_test(&test)
+            }
+            None => Err(ParserError::Find("the expression ends too soon".to_string())),
+        }
+    }
+
+    fn parse_test(&mut self, test: &str) -> Result<Node> {
+        let node = match test {
+            "-name" => glob(FileAttribute::Name, &self.argument(test)?, true)?,
+            "-iname" => glob(FileAttribute::Name, &self.argument(test)?, false)?,
+            "-path" | "-wholename" => glob(FileAttribute::Path, &self.argument(test)?, true)?,
+            "-ipath" | "-iwholename" => {
+                glob(FileAttribute::Path, &self.argument(test)?, false)?
+            }
+            "-regex" | "-iregex" => {
+                let pattern = self.argument(test)?;
+                self.notes.push(format!(
+                    "{} is translated as written, but find reads it as an Emacs regular \
+                     expression, where \\( \\) and \\| group and alternate",
+                    test
+                ));
+                let flags = if test == "-iregex" { "(?i)" } else { "" };
+                let pattern = match pattern.contains('|') {
+                    true => format!("{}^(?:{})$", flags, pattern),
+                    false => format!("{}^{}$", flags, pattern),
+                };
+                Node::Test(FileCondition::Regexp {
+                    attribute: FileAttribute::Path,
+                    pattern,
+                })
+            }
+            "-type" => match self.argument(test)?.as_str() {
+                "f" => Node::Type(EntryType::File),
+                "d" => Node::Type(EntryType::Directory),
+                "l" => Node::Type(EntryType::Symlink),
+                kind => {
+                    let message = format!("-type {} has no equivalent; only f, d and l do", kind);
+                    return Err(ParserError::Find(message));
+                }
+            },
+            "-size" => size(&self.argument(test)?)?,
+            "-mtime" => age(test, &self.argument(test)?, TimeDelta::days(1), true)?,
+            "-mmin" => age(test, &self.argument(test)?, TimeDelta::minutes(1), false)?,
+            "-empty" => {
+                self.notes.push(
+                    "-empty also matches empty directories, which a query can't tell from \
+                     their size, so only empty files are found"
+                        .to_string(),
+                );
+                let empty = compare(FileAttribute::Size, ComparisonOperator::Eq, integer(0));
+                let no = FileValue::Boolean(false);
+                let file = compare(FileAttribute::IsDirectory, ComparisonOperator::Eq, no);
+                Node::Test(FileCondition::And(Box::new(empty), Box::new(file)))
+            }
+            "-user" => {
+                let user = FileValue::String(self.argument(test)?);
+                Node::Test(compare(FileAttribute::Owner, ComparisonOperator::Eq, user))
+            }
+            "-group" => {
+                let group = FileValue::String(self.argument(test)?);
+                Node::Test(compare(FileAttribute::Group, ComparisonOperator::Eq, group))
+            }
+            "-perm" => perm(&self.argument(test)?)?,
+            "-executable" => Node::Test(flag(FileAttribute::IsExecutable)),
+            "-true" | "-print" => Node::True,
+            "-false" => Node::Test(FileCondition::Constant(false)),
+            // find prints the contents of a directory before the directory
+            // itself, which only changes the order
+            "-depth" => Node::True,
+            "-maxdepth" | "-mindepth" => {
+                let argument = self.argument(test)?;
+                let depth = argument.parse().map_err(|_| {
+                    ParserError::Find(format!("{} {} is not a depth", test, argument))
+                })?;
+                match test {
+                    "-maxdepth" => self.max_depth = Some(depth),
+                    _ if depth > 1 => {
+                        let message = format!("-mindepth {} can't be translated", depth);
+                        return Err(ParserError::Find(message));
+                    }
+                    _ => self.min_depth = depth,
+                }
+                Node::True
+            }
+            "-exec" | "-execdir" | "-ok" | "-okdir" | "-delete" | "-ls" | "-fls" | "-print0"
+            | "-printf" | "-fprint" | "-fprint0" | "-fprintf" | "-prune" | "-quit" => {
+                let message = format!(
+                    "{} can't be translated: a query picks the files, so run what it did \
+                     on the query's results instead",
+                    test
+                );
+                return Err(ParserError::Find(message));
+            }
+            test => return Err(ParserError::Find(format!("{} has no equivalent", test))),
+        };
+        Ok(node)
+    }
+}
+
+fn compare(
+    attribute: FileAttribute,
+    operator: ComparisonOperator,
+    value: FileValue,
+) -> FileCondition {
+    FileCondition::Compare {
+        attribute,
+        operator,
+        value,
+    }
+}
+
+/// A boolean attribute that is true.
+fn flag(attribute: FileAttribute) -> FileCondition {
+    compare(attribute, ComparisonOperator::Eq, FileValue::Boolean(true))
+}
+
+fn integer(n: u64) -> FileValue {
+    FileValue::from_u64(n)
+}
+
+/// Splits a number given as `+n`, `-n` or `n` from its sign.
+fn signed(argument: &str) -> (Sign, &str) {
+    if let Some(rest) = argument.strip_prefix('+') {
+        (Sign::More, rest)
+    } else if let Some(rest) = argument.strip_prefix('-') {
+        (Sign::Less, rest)
+    } else {
+        (Sign::Exactly, argument)
+    }
+}
+
+/// Translates `-size`. find rounds a file's size up to whole units before
+/// comparing it, so `-size 2M` is anything over 1 MiB up to 2 MiB.
+fn size(argument: &str) -> Result<Node> {
+    let invalid = || ParserError::Find(format!("-size {} is not a size", argument));
+    let (sign, number) = signed(argument);
+    let (number, unit) = match number.char_indices().last() {
+        Some((at, 'c')) => (&number[..at], 1),
+        Some((at, 'w')) => (&number[..at], 2),
+        Some((at, 'b')) => (&number[..at], 512),
+        Some((at, 'k')) => (&number[..at], 1 << 10),
+        Some((at, 'M')) => (&number[..at], 1 << 20),
+        Some((at, 'G')) => (&number[..at], 1 << 30),
+        _ => (number, 512),
+    };
+    let n: u64 = number.parse().map_err(|_| invalid())?;
+    let bytes = |n: u64| n.checked_mul(unit).map(integer).ok_or_else(invalid);
+    let size = |operator, value| Node::Test(compare(FileAttribute::Size, operator, value));
+    Ok(match sign {
+        Sign::More => size(ComparisonOperator::Gt, bytes(n)?),
+        Sign::Less if n == 0 => Node::Test(FileCondition::Constant(false)),
+        Sign::Less if n == 1 => size(ComparisonOperator::Eq, integer(0)),
+        Sign::Less => size(ComparisonOperator::LtEq, bytes(n - 1)?),
+        Sign::Exactly if n == 0 || unit == 1 => size(ComparisonOperator::Eq, bytes(n)?),
+        Sign::Exactly => {
+            let upper = bytes(n)?;
+            Node::Test(FileCondition::Between {
+                attribute: FileAttribute::Size,
+                lower: integer((n - 1) * unit + 1),
+                upper,
+            })
+        }
+    })
+}
+
+/// Translates `-mtime` and `-mmin`. find counts `-mtime` in whole days,
+/// dropping any part of a day, so `-mtime +1` is two days or more; it
+/// counts `-mmin` in minutes rounded up.
+fn age(test: &str, argument: &str, unit: TimeDelta, whole: bool) -> Result<Node> {
+    let invalid = || ParserError::Find(format!("{} {} is not a number", test, argument));
+    let (sign, number) = signed(argument);
+    let n: i32 = number.parse().ok().filter(|n| *n >= 0).ok_or_else(invalid)?;
+    let units = |n: i32| {
+        unit.checked_mul(n)
+            .map(FileValue::Duration)
+            .ok_or_else(invalid)
+    };
+    let age = |operator, value| compare(FileAttribute::Age, operator, value);
+    let (lower, upper) = match whole {
+        true => (ComparisonOperator::GtEq, ComparisonOperator::Lt),
+        false => (ComparisonOperator::Gt, ComparisonOperator::LtEq),
+    };
+    // The edges of the range of ages that count as n
+    let (from, to) = match whole {
+        true => (n, n.checked_add(1).ok_or_else(invalid)?),
+        false => (n - 1, n),
+    };
+    Ok(Node::Test(match sign {
+        Sign::More => age(lower, units(to)?),
+        Sign::Less => age(ComparisonOperator::Lt, units(n)?),
+        Sign::Exactly => FileCondition::And(
+            Box::new(age(lower, units(from)?)),
+            Box::new(age(upper, units(to)?)),
+        ),
+    }))
+}
+
+/// Translates `-perm` with an octal mode: exactly these bits, `-mode` for
+/// all of them, or `/mode` for any of them.
+fn perm(argument: &str) -> Result<Node> {
+    let (mode, mask) = match argument.strip_prefix(['-', '/']) {
+        Some(mode) => (mode, None),
+        None => (argument, Some(0o7777)),
+    };
+    let mode = u32::from_str_radix(mode, 8)
+        .ok()
+        .filter(|mode| *mode <= 0o7777)
+        .ok_or_else(|| {
+            let message = format!("-perm {} can't be translated; only octal modes can", argument);
+            ParserError::Find(message)
+        })?;
+    let (mask, operator, value) = match (mask, argument.starts_with('/')) {
+        (Some(mask), _) => (mask, ComparisonOperator::Eq, mode),
+        // No bits to look for matches everything, as it does for find
+        (None, true) if mode == 0 => return Ok(Node::True),
+        (None, true) => (mode, ComparisonOperator::NotEq, 0),
+        (None, false) => (mode, ComparisonOperator::Eq, mode),
+    };
+    Ok(Node::Test(FileCondition::PermMask {
+        mask,
+        operator,
+        value: integer(value.into()),
+    }))
+}
+
+/// Translates a `-name` or `-path` glob, into the plainest test that
+/// matches exactly the same names.
+fn glob(attribute: FileAttribute, glob: &str, case_sensitive: bool) -> Result<Node> {
+    let literal = |text: &str| !text.is_empty() && !text.contains(['*', '?', '[', '\\']);
+    if case_sensitive {
+        let substring = |position, text: &str| FileCondition::Substring {
+            attribute: attribute.clone(),
+            position,
+            text: text.to_string(),
+        };
+        if literal(glob) {
+            let value = FileValue::String(glob.to_string());
+            return Ok(Node::Test(compare(attribute, ComparisonOperator::Eq, value)));
+        }
+        let inner = glob.strip_prefix('*').and_then(|rest| rest.strip_suffix('*'));
+        if let Some(text) = inner.filter(|text| literal(text)) {
+            return Ok(Node::Test(substring(SubstringPosition::Anywhere, text)));
+        }
+        if let Some(text) = glob.strip_prefix('*').filter(|text| literal(text)) {
+            return Ok(Node::Test(substring(SubstringPosition::End, text)));
+        }
+        if let Some(text) = glob.strip_suffix('*').filter(|text| literal(text)) {
+            return Ok(Node::Test(substring(SubstringPosition::Start, text)));
+        }
+    }
+    let flags = if case_sensitive { "" } else { "(?i)" };
+    Ok(Node::Test(FileCondition::Regexp {
+        attribute,
+        pattern: format!("{}^{}$", flags, glob_regex(glob)),
+    }))
+}
+
+/// Turns a shell glob into the regular expression that matches the same
+/// text. As for find, `*` matches `/` too.
+fn glob_regex(glob: &str) -> String {
+    let chars: Vec<char> = glob.chars().collect();
+    let mut regex = String::new();
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            '*' => regex += ".*",
+            '?' => regex += ".",
+            '\\' if i + 1 < chars.len() => {
+                i += 1;
+                regex += &regex::escape(&chars[i].to_string());
+            }
+            '[' => match bracket(&chars[i..]) {
+                Some((class, length)) => {
+                    regex += &class;
+                    i += length - 1;
+                }
+                None => regex += "\\[",
+            },
+            c => regex += &regex::escape(&c.to_string()),
+        }
+        i += 1;
+    }
+    regex
+}
+
+/// Translates the bracket expression `chars` starts with, such as `[!a-z]`,
+/// returning it and how many characters it took, or None if it is never
+/// closed.
+fn bracket(chars: &[char]) -> Option<(String, usize)> {
+    let mut class = String::from("[");
+    let mut i = 1;
+    if matches!(chars.get(i), Some('!' | '^')) {
+        class.push('^');
+        i += 1;
+    }
+    // A ] straight after the opening is one of the characters
+    let start = i;
+    loop {
+        match *chars.get(i)? {
+            ']' if i > start => return Some((class + "]", i + 1)),
+            '\\' => {
+                i += 1;
+                class.push('\\');
+                class.push(*chars.get(i)?);
+            }
+            // Characters a regex class would read as nesting or set
+            // operations
+            c @ ('[' | ']' | '&' | '~') => {
+                class.push('\\');
+                class.push(c);
+            }
+            c => class.push(c),
+        }
+        i += 1;
+    }
+}
+
+/// Splits a command line into words as a POSIX shell would: by spaces,
+/// with single quotes keeping everything, and double quotes and
+/// backslashes keeping what they quote.
+fn split_words(command: &str) -> Result<Vec<String>> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut chars = command.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => words.extend(word.take()),
+            '\'' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => word.push(c),
+                        None => return Err(unclosed('\'')),
+                    }
+                }
+            }
+            '"' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(c @ ('"' | '\\' | '$' | '`')) => word.push(c),
+                            Some(c) => {
+                                word.push('\\');
+                                word.push(c);
+                            }
+                            None => return Err(unclosed('"')),
+                        },
+                        Some(c) => word.push(c),
+                        None => return Err(unclosed('"')),
+                    }
+                }
+            }
+            '\\' => {
+                let word = word.get_or_insert_with(String::new);
+                word.extend(chars.next());
+            }
+            c => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    words.extend(word);
+    Ok(words)
+}
+
+fn unclosed(quote: char) -> ParserError {
+    ParserError::Find(format!("a {} quote is never closed", quote))
+}
@@ -0,0 +1,205 @@
+//! BSD file flags, as shown by `ls -lO` and set by `chflags`.
+//!
+//! macOS and the BSDs keep a set of flags on every file besides its mode
+//! bits: `uchg` makes it immutable, `hidden` hides it from the Finder,
+//! `nodump` leaves it out of backups. The `flags` attribute lists the flags
+//! a file has by these names, comma-separated, so `flags LIKE '%uchg%'`
+//! finds the files nobody can change, and `UPDATE ... SET flags = 'uchg'`
+//! sets flags the way `chflags` does. Other systems have no such flags;
+//! there, `flags` is NULL and can't be set.
+//!
+//! # Examples
+//!
+//! ```
+//! use fmql_core::sql::flags::{format_flags, FlagChange};
+//!
+//! // user immutable (0x2) and hidden (0x8000)
+//! assert_eq!(format_flags(0x8002), "uchg,hidden");
+//!
+//! let change = FlagChange::parse("nouchg,nodump").unwrap();
+//! assert_eq!(format_flags(change.apply(0x8002)), "nodump,hidden");
+//! assert!(FlagChange::parse("sticky").is_err());
+//! ```
+
+use std::io;
+use std::path::Path;
+
+/// The flags fmql knows, by their `chflags` names. The values are the same
+/// on macOS, FreeBSD, NetBSD and OpenBSD, though not every system has every
+/// flag.
+const FLAGS: &[(&str, u32)] = &[
+    ("nodump", 0x1),
+    ("uchg", 0x2),
+    ("uappnd", 0x4),
+    ("opaque", 0x8),
+    ("hidden", 0x8000),
+    ("arch", 0x1_0000),
+    ("schg", 0x2_0000),
+    ("sappnd", 0x4_0000),
+    ("sunlnk", 0x10_0000),
+];
+
+/// Other names `chflags` accepts for some of the flags.
+const ALIASES: &[(&str, u32)] = &[
+    ("dump", 0x1),
+    ("uchange", 0x2),
+    ("uimmutable", 0x2),
+    ("uappend", 0x4),
+    ("archived", 0x1_0000),
+    ("schange", 0x2_0000),
+    ("simmutable", 0x2_0000),
+    ("sappend", 0x4_0000),
+    ("sunlink", 0x10_0000),
+];
+
+/// Lists the flags in `bits` by name, comma-separated, in the order `ls
+/// -lO` shows them. Unknown bits are shown in octal.
+pub fn format_flags(bits: u32) -> String {
+    let mut names: Vec<String> = FLAGS
+        .iter()
+        .filter(|(_, bit)| bits & bit != 0)
+        .map(|(name, _)| name.to_string())
+        .collect();
+    let known = FLAGS.iter().fold(0, |known, (_, bit)| known | bit);
+    if bits & !known != 0 {
+        names.push(format!("{:o}", bits & !known));
+    }
+    names.join(",")
+}
+
+/// A change to a file's flags, parsed from a `chflags`-style spec.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FlagChange {
+    /// The flags to set.
+    pub set: u32,
+    /// The flags to clear.
+    pub clear: u32,
+}
+
+impl FlagChange {
+    /// Parses a comma-separated list of flag names, each set as it is or
+    /// cleared with a `no` in front (`nouchg`), leaving the other flags as
+    /// they are. An octal number sets the flags to exactly that, so `'0'`
+    /// clears them all. `nodump` is a flag in its own right, and `dump`
+    /// clears it.
+    pub fn parse(spec: &str) -> std::result::Result<FlagChange, String> {
+        let spec = spec.trim();
+        if !spec.is_empty() && spec.bytes().all(|b| b.is_ascii_digit()) {
+            let bits = u32::from_str_radix(spec, 8)
+                .map_err(|_| format!("'{}' is not an octal number of flags", spec))?;
+            return Ok(FlagChange { set: bits, clear: u32::MAX });
+        }
+
+        let mut change = FlagChange { set: 0, clear: 0 };
+        for name in spec.split(',').map(str::trim).filter(|name| !name.is_empty()) {
+            let lower = name.to_ascii_lowercase();
+            if let Some(bit) = flag(&lower) {
+                // "dump" is the opposite of the "nodump" flag
+                if lower == "dump" {
+                    change.clear |= bit;
+                } else {
+                    change.set |= bit;
+                }
+            } else if let Some(bit) = lower.strip_prefix("no").and_then(flag) {
+                change.clear |= bit;
+            } else {
+                return Err(format!(
+                    "Unknown file flag '{}' (expected one of {})",
+                    name,
+                    FLAGS.iter().map(|(name, _)| *name).collect::<Vec<_>>().join(", ")
+                ));
+            }
+        }
+        Ok(change)
+    }
+
+    /// Returns the flags a file with `bits` has after the change.
+    pub fn apply(&self, bits: u32) -> u32 {
+        (bits & !self.clear) | self.set
+    }
+}
+
+/// Looks up a flag by name or alias.
+fn flag(name: &str) -> Option<u32> {
+    FLAGS
+        .iter()
+        .chain(ALIASES)
+        .find(|(flag, _)| *flag == name)
+        .map(|(_, bit)| *bit)
+}
+
+/// Reads the flags of a file, without following a symlink, or returns
+/// None where the system has no file flags.
+#[cfg(any(
+    target_vendor = "apple",
+    target_os = "freebsd",
+    target_os = "netbsd",
+    target_os = "openbsd"
+))]
+pub fn read_flags(path: &Path) -> io::Result<Option<u32>> {
+    #[cfg(target_vendor = "apple")]
+    use std::os::macos::fs::MetadataExt;
+    #[cfg(target_os = "freebsd")]
+    use std::os::freebsd::fs::MetadataExt;
+    #[cfg(target_os = "netbsd")]
+    use std::os::netbsd::fs::MetadataExt;
+    #[cfg(target_os = "openbsd")]
+    use std::os::openbsd::fs::MetadataExt;
+
+    Ok(Some(path.symlink_metadata()?.st_flags()))
+}
+
+/// Reads the flags of a file, without following a symlink, or returns
+/// None where the system has no file flags.
+#[cfg(not(any(
+    target_vendor = "apple",
+    target_os = "freebsd",
+    target_os = "netbsd",
+    target_os = "openbsd"
+)))]
+pub fn read_flags(_path: &Path) -> io::Result<Option<u32>> {
+    Ok(None)
+}
+
+/// Returns true if this system has file flags that can be set.
+pub fn supported() -> bool {
+    cfg!(any(
+        target_vendor = "apple",
+        target_os = "freebsd",
+        target_os = "netbsd",
+        target_os = "openbsd"
+    ))
+}
+
+/// Sets the flags of a file, following a symlink as `chflags` does.
+#[cfg(any(
+    target_vendor = "apple",
+    target_os = "freebsd",
+    target_os = "netbsd",
+    target_os = "openbsd"
+))]
+pub fn write_flags(path: &Path, bits: u32) -> io::Result<()> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = CString::new(path.as_os_str().as_bytes())?;
+    // SAFETY: the path is a NUL-terminated string that outlives the call.
+    if unsafe { libc::chflags(path.as_ptr(), bits as _) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Sets the flags of a file; this system has none.
+#[cfg(not(any(
+    target_vendor = "apple",
+    target_os = "freebsd",
+    target_os = "netbsd",
+    target_os = "openbsd"
+)))]
+pub fn write_flags(_path: &Path, _bits: u32) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "file flags are only supported on macOS and the BSDs",
+    ))
+}
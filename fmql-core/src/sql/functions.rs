@@ -0,0 +1,328 @@
+//! Scalar functions that can be used in SET expressions.
+//!
+//! Scalar functions compute a new value from their arguments, for example
+//! `CONCAT(STEM(name), '_', YEAR(modified), '.', ext)`. Each function is
+//! evaluated once per file after its arguments have been resolved against
+//! that file's attributes.
+//!
+//! `CURRENT_USER()` and `CURRENT_GROUP()` take no arguments and name the
+//! user and group fmql runs as, so `owner = CURRENT_USER()` finds your own
+//! files without spelling out who you are.
+//!
+//! `COALESCE` and `NULLIF` deal with missing values: `COALESCE(extension,
+//! '(none)')` gives a placeholder where there is none, and `NULLIF(owner,
+//! 'root')` turns a value you'd rather not see into NULL.
+//!
+//! `TRY(value)` is NULL where working out its argument fails for a file,
+//! such as `TRY(CAST(stem AS INTEGER))` for a file whose name isn't a
+//! number, so one file that can't be read doesn't fail a whole SELECT. The
+//! executor catches the error; called here, `TRY` just returns its argument.
+//!
+//! [`cast`] does the conversions of `CAST(value AS type)`, for when a
+//! comparison needs one side to change type, as in `CAST(permissions AS
+//! OCTAL) = '755'`.
+//!
+//! `FREE_SPACE(path)` and `TOTAL_SPACE(path)` are the bytes free for
+//! unprivileged users and in all on the file system that holds a path, as
+//! `statvfs` gives them, so an alert can say `FREE_SPACE('/var') * 10 <
+//! TOTAL_SPACE('/var')` for a file system that is more than 90% full.
+//!
+//! `DIRNAME(path)` is the directory a path is in, as `dirname` gives it,
+//! so `GROUP BY DIRNAME(path)` totals each directory's own files; with
+//! more than one job, such a query reads and totals directories on all of
+//! them at once.
+//!
+//! `EPOCH_MS` and `STRPTIME` turn other tools' timestamps into dates, so
+//! `modified > EPOCH_MS(1700000000000)` and `modified <
+//! STRPTIME('31/12/2024', '%d/%m/%Y')` compare like any other date.
+//!
+//! # Examples
+//!
+//! ```
+//! use fmql_core::sql::ast::FileValue;
+//! use fmql_core::sql::functions::{call, to_text};
+//!
+//! let stem = call("STEM", &[FileValue::String("report.txt".to_string())]).unwrap();
+//! assert_eq!(stem, FileValue::String("report".to_string()));
+//!
+//! let text = |s: &str| FileValue::String(s.to_string());
+//! let date = call("STRPTIME", &[text("31/12/2024"), text("%d/%m/%Y")]).unwrap();
+//! assert_eq!(to_text(&date), "2024-12-31 00:00:00");
+//!
+//! let first = call("COALESCE", &[FileValue::Null, text("(none)")]).unwrap();
+//! assert_eq!(first, text("(none)"));
+//! assert_eq!(call("NULLIF", &[text("root"), text("root")]).unwrap(), FileValue::Null);
+//! ```
+
+use chrono::format::ParseErrorKind;
+use std::cmp::Ordering;
+use chrono::{DateTime, Datelike, NaiveDate, NaiveDateTime, NaiveTime};
+use std::path::Path;
+
+use crate::sql::ast::{CastType, FileValue};
+use crate::sql::lexer::{format_interval, parse_number, parse_numeric};
+use crate::sql::sort::compare_numbers;
+use crate::sql::space::{available_bytes, total_bytes};
+use crate::sql::timezone::{format_datetime, from_local, parse_datetime, Tz};
+use crate::sql::users::{current_group, current_user};
+
+/// Names of the scalar functions understood by [`call`].
+pub const SCALAR_FUNCTIONS: &[&str] = &[
+    "CONCAT", "STEM", "DIRNAME", "LOWER", "UPPER", "YEAR", "MONTH", "DAY", "EPOCH_MS", "STRPTIME",
+    "CURRENT_USER", "CURRENT_GROUP", "COALESCE", "NULLIF", "TRY", "FREE_SPACE", "TOTAL_SPACE",
+];
+
+/// Returns true if `name` is a scalar function.
+pub fn is_scalar(name: &str) -> bool {
+    SCALAR_FUNCTIONS.contains(&name.to_uppercase().as_str())
+}
+
+/// Calls a scalar function with already evaluated arguments.
+///
+/// Returns a message describing the problem if the function is unknown or
+/// the arguments have the wrong number or type.
+pub fn call(name: &str, args: &[FileValue]) -> std::result::Result<FileValue, String> {
+    call_in(name, args, Tz::UTC)
+}
+
+/// Calls a scalar function, taking the parts of dates (`YEAR`, `MONTH`,
+/// `DAY`) and their text (`CONCAT`) from their local time in `zone`.
+pub fn call_in(name: &str, args: &[FileValue], zone: Tz) -> std::result::Result<FileValue, String> {
+    let name = name.to_uppercase();
+    match name.as_str() {
+        "CONCAT" => Ok(FileValue::String(args.iter().map(|arg| to_text_in(arg, zone)).collect())),
+        "STEM" => {
+            let s = string_arg(&name, args)?;
+            let stem = Path::new(s)
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().to_string())
+                .unwrap_or_default();
+            Ok(FileValue::String(stem))
+        }
+        "DIRNAME" => {
+            let s = string_arg(&name, args)?;
+            // As dirname(1) does: a bare name is in `.`, and `/` is its own
+            let dir = match Path::new(s).parent() {
+                Some(parent) if parent.as_os_str().is_empty() => ".".to_string(),
+                Some(parent) => parent.to_string_lossy().to_string(),
+                None if s.starts_with('/') => "/".to_string(),
+                None => ".".to_string(),
+            };
+            Ok(FileValue::String(dir))
+        }
+        "LOWER" => Ok(FileValue::String(string_arg(&name, args)?.to_lowercase())),
+        "UPPER" => Ok(FileValue::String(string_arg(&name, args)?.to_uppercase())),
+        "YEAR" | "MONTH" | "DAY" => {
+            let [value] = args else {
+                return Err(format!("{} expects 1 argument, got {}", name, args.len()));
+            };
+            let FileValue::DateTime(dt) = value else {
+                return Err(format!("{} expects a date, got {:?}", name, value));
+            };
+            let dt = dt.with_timezone(&zone);
+            let part = match name.as_str() {
+                "YEAR" => dt.year(),
+                "MONTH" => dt.month() as i32,
+                _ => dt.day() as i32,
+            };
+            Ok(FileValue::Integer(part.into()))
+        }
+        "EPOCH_MS" => {
+            let millis = match args {
+                [FileValue::Null] => return Ok(FileValue::Null),
+                [FileValue::Integer(n)] => Some(*n),
+                [FileValue::Number(n)] => Some(*n as i64),
+                [FileValue::String(s)] => parse_number(s.trim()).map(|n| n as i64),
+                [value] => return Err(format!("EPOCH_MS expects a number, got {:?}", value)),
+                _ => return Err(format!("EPOCH_MS expects 1 argument, got {}", args.len())),
+            };
+            millis
+                .and_then(DateTime::from_timestamp_millis)
+                .map(FileValue::DateTime)
+                .ok_or_else(|| format!("EPOCH_MS can't make a date of {:?}", args[0]))
+        }
+        "STRPTIME" => {
+            let [text, format] = args else {
+                return Err(format!("STRPTIME expects 2 arguments, got {}", args.len()));
+            };
+            let (FileValue::String(text), FileValue::String(format)) = (text, format) else {
+                if *text == FileValue::Null {
+                    return Ok(FileValue::Null);
+                }
+                return Err(format!(
+                    "STRPTIME expects two strings, got {:?} and {:?}",
+                    text, format
+                ));
+            };
+            strptime(text, format, zone)
+        }
+        "CURRENT_USER" | "CURRENT_GROUP" => match args {
+            [] if name == "CURRENT_USER" => Ok(FileValue::String(current_user())),
+            [] => Ok(FileValue::String(current_group())),
+            _ => Err(format!("{} expects no arguments, got {}", name, args.len())),
+        },
+        "COALESCE" if args.is_empty() => Err("COALESCE expects at least 1 argument".to_string()),
+        "COALESCE" => {
+            let first = args.iter().find(|arg| !matches!(arg, FileValue::Null));
+            Ok(first.cloned().unwrap_or(FileValue::Null))
+        }
+        "NULLIF" => match args {
+            [value, other] if same_value(value, other) => Ok(FileValue::Null),
+            [value, _] => Ok(value.clone()),
+            _ => Err(format!("NULLIF expects 2 arguments, got {}", args.len())),
+        },
+        "TRY" => match args {
+            [value] => Ok(value.clone()),
+            _ => Err(format!("TRY expects 1 argument, got {}", args.len())),
+        },
+        "FREE_SPACE" | "TOTAL_SPACE" => {
+            let path = match args {
+                [FileValue::Null] => return Ok(FileValue::Null),
+                [FileValue::String(path)] => Path::new(path),
+                [value] => return Err(format!("{} expects a path, got {:?}", name, value)),
+                _ => return Err(format!("{} expects 1 argument, got {}", name, args.len())),
+            };
+            let bytes = match name.as_str() {
+                "FREE_SPACE" => available_bytes(path),
+                _ => total_bytes(path),
+            };
+            bytes
+                .map(FileValue::from_u64)
+                .map_err(|err| format!("{} can't read {}: {}", name, path.display(), err))
+        }
+        _ => Err(format!("Unknown function '{}'", name)),
+    }
+}
+
+/// Converts a value for `CAST(value AS type)`, reading dates in text and
+/// writing them as text as local times in `zone`. NULL stays NULL, and a
+/// value that can't be converted, such as the text `'abc'` to a number, is
+/// an error naming it.
+pub fn cast(value: &FileValue, to: CastType, zone: Tz) -> std::result::Result<FileValue, String> {
+    let fail = || format!("Cannot convert {} to {}", to_text_in(value, zone), to.name());
+    let number = match (value, to) {
+        (FileValue::Null, _) => return Ok(FileValue::Null),
+        (_, CastType::Text) => return Ok(FileValue::String(to_text_in(value, zone))),
+        (FileValue::Integer(_) | FileValue::Number(_), _) => Some(value.clone()),
+        (FileValue::String(s), CastType::Octal) => {
+            let digits = s.trim();
+            let octal = digits.strip_prefix("0o").unwrap_or(digits);
+            return i64::from_str_radix(octal, 8).map(FileValue::Integer).map_err(|_| fail());
+        }
+        (FileValue::String(s), CastType::Date) => {
+            return parse_datetime(s, zone).map(FileValue::DateTime).ok_or_else(fail);
+        }
+        (FileValue::String(s), CastType::Boolean) => {
+            return match s.trim().to_lowercase().as_str() {
+                "true" | "yes" | "1" => Ok(FileValue::Boolean(true)),
+                "false" | "no" | "0" => Ok(FileValue::Boolean(false)),
+                _ => Err(fail()),
+            };
+        }
+        (FileValue::String(s), _) => parse_numeric(s.trim()),
+        (FileValue::DateTime(dt), CastType::Date) => return Ok(FileValue::DateTime(*dt)),
+        (FileValue::DateTime(dt), CastType::Number | CastType::Integer) => {
+            Some(FileValue::Number(dt.timestamp_millis() as f64 / 1000.0))
+        }
+        (FileValue::Boolean(b), CastType::Boolean) => return Ok(FileValue::Boolean(*b)),
+        (FileValue::Boolean(b), CastType::Number | CastType::Integer) => {
+            Some(FileValue::Integer(i64::from(*b)))
+        }
+        _ => None,
+    };
+    let number = number.filter(|n| n.as_f64().is_some_and(f64::is_finite)).ok_or_else(fail)?;
+    // Whole floats within the range of an integer, such as 12.0 from 12.7
+    let whole = |n: f64| (n.abs() < 2f64.powi(63)).then_some(n as i64).ok_or_else(fail);
+    match (to, number) {
+        (CastType::Integer, FileValue::Integer(n)) => Ok(FileValue::Integer(n)),
+        (CastType::Integer, FileValue::Number(n)) => whole(n.trunc()).map(FileValue::Integer),
+        (CastType::Octal, FileValue::Integer(n)) if n >= 0 => {
+            Ok(FileValue::String(format!("{:o}", n)))
+        }
+        (CastType::Octal, FileValue::Number(n)) if n >= 0.0 && n.fract() == 0.0 => {
+            whole(n).map(|n| FileValue::String(format!("{:o}", n)))
+        }
+        (CastType::Number, n) => Ok(FileValue::Number(n.as_f64().unwrap_or_default())),
+        (CastType::Boolean, n) => Ok(FileValue::Boolean(n.as_f64() != Some(0.0))),
+        (CastType::Date, FileValue::Integer(n)) => {
+            DateTime::from_timestamp(n, 0).map(FileValue::DateTime).ok_or_else(fail)
+        }
+        (CastType::Date, FileValue::Number(n)) => {
+            DateTime::from_timestamp_millis((n * 1000.0).round() as i64)
+                .map(FileValue::DateTime)
+                .ok_or_else(fail)
+        }
+        _ => Err(fail()),
+    }
+}
+
+/// Returns true if two values are the same for `NULLIF`: equal numbers,
+/// whole or not, and numbers equal to numeric text, such as the `0` in
+/// `NULLIF(size, 0)`, count as the same.
+fn same_value(a: &FileValue, b: &FileValue) -> bool {
+    let numeric = |value: &FileValue| match value {
+        FileValue::String(s) => parse_numeric(s.trim()),
+        value => Some(value.clone()),
+    };
+    match (a, b) {
+        (FileValue::String(_), FileValue::String(_)) => a == b,
+        _ => match (numeric(a), numeric(b)) {
+            (Some(a), Some(b)) => compare_numbers(&a, &b).map_or(a == b, Ordering::is_eq),
+            _ => a == b,
+        },
+    }
+}
+
+/// Renders a value as text, as string functions and text output see it.
+/// NULL becomes empty, and dates are in UTC.
+pub fn to_text(value: &FileValue) -> String {
+    to_text_in(value, Tz::UTC)
+}
+
+/// Renders a value as text, with dates as local times in `zone`.
+pub fn to_text_in(value: &FileValue, zone: Tz) -> String {
+    match value {
+        FileValue::String(s) => s.clone(),
+        FileValue::Integer(n) => n.to_string(),
+        FileValue::Number(n) if n.fract() == 0.0 => format!("{}", *n as i64),
+        FileValue::Number(n) => n.to_string(),
+        FileValue::DateTime(dt) => format_datetime(dt, zone),
+        FileValue::Duration(delta) => format_interval(*delta),
+        FileValue::Boolean(b) => b.to_string(),
+        FileValue::Null => String::new(),
+    }
+}
+
+/// Reads a date written in a `strftime` format, as a local time in `zone`
+/// unless the format has an offset (`%z`). A format without a time gives
+/// midnight. Text that doesn't match the format is NULL, so that
+/// `STRPTIME(STEM(name), '%Y%m%d')` skips files not named by date, but a
+/// format that can't be used at all is an error.
+fn strptime(text: &str, format: &str, zone: Tz) -> std::result::Result<FileValue, String> {
+    if let Ok(time) = DateTime::parse_from_str(text, format) {
+        return Ok(FileValue::DateTime(time.to_utc()));
+    }
+    let local = match NaiveDateTime::parse_from_str(text, format) {
+        // A format with only a date is midnight
+        Err(err) if err.kind() == ParseErrorKind::NotEnough => {
+            NaiveDate::parse_from_str(text, format).map(|date| date.and_time(NaiveTime::MIN))
+        }
+        local => local,
+    };
+    match local {
+        Ok(local) => Ok(FileValue::DateTime(from_local(&local, zone))),
+        Err(err) if err.kind() == ParseErrorKind::BadFormat => {
+            Err(format!("Invalid date format '{}'", format))
+        }
+        Err(_) => Ok(FileValue::Null),
+    }
+}
+
+/// Extracts the single string argument of a function.
+fn string_arg<'a>(name: &str, args: &'a [FileValue]) -> std::result::Result<&'a str, String> {
+    match args {
+        [FileValue::String(s)] => Ok(s),
+        [value] => Err(format!("{} expects a string, got {:?}", name, value)),
+        _ => Err(format!("{} expects 1 argument, got {}", name, args.len())),
+    }
+}
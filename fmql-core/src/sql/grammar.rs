@@ -0,0 +1,544 @@
+//! The query language written out as a grammar, and queries generated from
+//! it.
+//!
+//! [`GRAMMAR`] is the syntax [`parse_sql`](crate::sql::parse_sql) accepts,
+//! in EBNF: `rule = ... ;` defines a rule, `"..."` is text written as it
+//! stands, `[ ... ]` is optional, `{ ... }` repeats any number of times,
+//! `( ... )` groups, `|` separates alternatives and `(* ... *)` is a
+//! comment. A `? name ?` is text described in words rather than rules, such
+//! as a path or a string literal. Keywords match ignoring case, and a
+//! function's `(` follows its name with no space between them.
+//!
+//! The grammar is sound rather than complete: every query it describes
+//! parses, but a few spellings the parser also accepts, such as a
+//! `JOIN`ed table's columns outside its `ON`, are left out, as are the
+//! checks made after parsing, such as that a grouped query selects only its
+//! keys and aggregates. [`Grammar::sentence`] writes out queries from it,
+//! which is how the parser is tested against it.
+//!
+//! # Examples
+//!
+//! ```
+//! use fmql_core::sql::grammar::Grammar;
+//! use fmql_core::sql::parse_sql;
+//!
+//! let grammar = Grammar::standard();
+//! for seed in 0..20 {
+//!     let sql = grammar.sentence("query", seed).unwrap();
+//!     assert!(parse_sql(&sql).is_ok(), "{}", sql);
+//! }
+//!
+//! let grammar = Grammar::parse(r#"greeting = "hello" [ "world" ] ;"#).unwrap();
+//! assert!(grammar.sentence("greeting", 1).unwrap().starts_with("hello"));
+//! assert!(Grammar::parse("greeting = farewell ;").is_err());
+//! ```
+
+use std::collections::{BTreeMap, BTreeSet};
+
+/// The syntax of fmql queries; see the [module documentation](self).
+pub const GRAMMAR: &str = r#"
+query = [ "EXPLAIN" ] statement ;
+statement = select | recursive | update | copy | compress | extract | archive | restore | show ;
+recursive = "WITH" "RECURSIVE" ( select | copy | compress | extract | archive | restore ) ;
+
+(* SELECT, with plain columns, aggregates, or a built-in view *)
+select = "SELECT" ( listing | typed_listing | view_listing | grouped | rolled_up ) ;
+listing = ( "*" | column { "," column } ) "FROM" from [ where ] [ order_by ] bounds ;
+typed_listing = ( "FILES" | "DIRS" ) "FROM" source [ "INCLUDE" "SELF" ] [ join ] [ where ]
+    [ order_by ] bounds ;
+view_listing = "*" "FROM" ( "STATS(" | "STATS_BY_EXTENSION(" ) ? quoted path ? ")"
+    [ where ] [ order_by ] group_bounds ;
+grouped = aggregate_column { "," aggregate_column } "FROM" from [ where ]
+    [ "GROUP" "BY" group_key { "," group_key } ] [ order_by ] group_bounds ;
+(* A key reads the file, or every file would be in one group; the alias of
+   a computed column is a key too *)
+group_key = ( ? attribute ? | scalar ? attribute ? ")" | "CAST(" ? attribute ? "AS" cast_type ")" )
+    { arithmetic operand } ;
+rolled_up = aggregate_column { "," aggregate_column } "FROM" source modifiers [ where ]
+    "ROLLUP" "BY" "path" [ "WITHIN" ? duration ? ] ;
+
+column = ? attribute ? | expression "AS" column_name ;
+column_name = ? identifier ? | ? attribute ? | ? string ? ;
+aggregate_column = ( "COUNT(" ( "*" | expression )
+    | ( "SUM(" | "MIN(" | "MAX(" | "AVG(" ) expression ) ")" [ "AS" column_name ] ;
+source = ? path ? | "STDIN" | "TRASH" ;
+(* AS TABLE reads the files the table's path column lists, joined to their rows *)
+from = source modifiers [ join ] | ? table file ? "AS" "TABLE" [ ? table ? ] modifiers ;
+(* INCLUDE SELF and TYPE may come in either order *)
+modifiers = [ "INCLUDE" "SELF" ] [ "TYPE" entry_type ] ;
+entry_type = "file" | "f" | "dir" | "directory" | "d" | "symlink" | "link" | "l" ;
+(* The table is named by AS, or else after its file *)
+join = [ "LEFT" ] "JOIN" ? table file ? [ "AS" ? table ? ] "ON"
+    ( expression "=" ? table column ? | ? table column ? "=" expression ) ;
+where = "WHERE" condition ;
+order_by = "ORDER" "BY" sort_key { "," sort_key } ;
+(* The words after a key may come in any order, each at most once *)
+sort_key = ( "DEPTH" | "RANDOM(" [ ? seed ? ] ")" | ? attribute ? ) [ "NATURAL" ] [ "COLLATE" ? collation ? ] [ "ASC" | "DESC" ]
+    [ "NULLS" ( "FIRST" | "LAST" ) ] ;
+(* LIMIT, WITHIN and SAMPLE may come in any order, each at most once; groups
+   have no size to LIMIT and can't be sampled by the row *)
+bounds = [ "LIMIT" ( ? count ? | ? size ? "BY" "size" ) ] [ "WITHIN" ? duration ? ]
+    [ "SAMPLE" ( ? percentage ? "%" | row_sample ) ] ;
+row_sample = ? count ? "ROWS" [ "WEIGHTED" "BY" ? numeric attribute ? ] [ "SEED" ? seed ? ] ;
+group_bounds = [ "LIMIT" ? count ? ] [ "WITHIN" ? duration ? ] [ "SAMPLE" ? percentage ? "%" ] ;
+
+(* Statements that change files *)
+update = "UPDATE" source modifiers "SET" assignment { "," assignment } [ where ] [ on_conflict ] ;
+assignment = ? attribute ? "=" expression ;
+on_conflict = "ON" "CONFLICT" ( "SKIP" | "OVERWRITE" | "RENAME" | "FAIL" ) ;
+copy = "COPY" source "TO" ? path ? [ where ]
+    [ "USING" ( "BYTES" | "HARDLINK" | "REFLINK" | "AUTO" ) ] [ preserve ] [ "SYNC" ]
+    [ "VERIFY" "CHECKSUM" ] [ on_conflict ] ;
+preserve = "PRESERVE" ( "ALL" | "NONE" | "(" preserved { "," preserved } ")" ) ;
+preserved = "times" | "permissions" | "owner" | "xattrs" ;
+compress = "COMPRESS" [ "FROM" ] source [ where ] [ "USING" ( "GZIP" | "ZSTD" ) ]
+    [ "LEVEL" ? level ? ] [ "DELETE" "ORIGINAL" ] ;
+extract = "EXTRACT" [ "FROM" ] source ( [ where ] "TO" ? path ? | "TO" ? path ? where )
+    [ on_conflict ] ;
+archive = "ARCHIVE" [ "FROM" ] source ( [ where ] "INTO" ? archive ? | "INTO" ? archive ? where )
+    [ "DELETE" "ORIGINAL" ] ;
+restore = "RESTORE" [ "FROM" ] source [ where ] [ on_conflict ] ;
+show = "SHOW" "FILESYSTEM" [ "FROM" source ] ;
+
+(* Conditions, loosest first *)
+condition = exclusive { "OR" exclusive } ;
+exclusive = conjunction { "XOR" conjunction } ;
+conjunction = negation { "AND" negation } ;
+negation = { "NOT" } primary [ budget ] ;
+(* The duration of a budget may be left unquoted *)
+budget = "BUDGET" ( ? duration ? | ? bare duration ? ) "PER" "FILE" ;
+primary = "(" condition ")" | "TRUE" | "FALSE" | ? flag ? | ? attribute ? [ "NOT" ] test
+    | ? attribute ? is_null | "permissions" [ "&" ? mask ? ] comparison mode
+    | leading { arithmetic operand } ( comparison expression | is_null ) | predicate ;
+is_null = "IS" [ "NOT" ] "NULL" ;
+test = "LIKE" ? like pattern ? | "REGEXP" ? regex ? | "BETWEEN" value "AND" value
+    | arithmetic operand { arithmetic operand } comparison expression
+    | comparison comparand ;
+comparand = ( value | ? attribute ? | call | "(" expression ")" ) { arithmetic operand } ;
+leading = ? number ? | "INTERVAL" ? interval ? | call | "(" expression ")" ;
+predicate = "REGEXP(" ? attribute ? "," ? regex ? ")"
+    | "PERM_AND(" "permissions" "," ? mask ? ")" comparison mode
+    | ( "STARTS_WITH(" | "ENDS_WITH(" | "CONTAINS_STR(" ) ? attribute ? "," ? string ? ")"
+    | "CONTAINS_BYTES(" ? bytes ? [ "," ? count ? ] ")"
+    | "HAS_PERM(" ? permission spec ? ")"
+    | "PERM_MATCHES(" ? octal pattern ? ")"
+    | "OWNER_IN_GROUP(" ? string ? ")"
+    | "TAGGED(" ? string ? ")"
+    | "ANY_OF(" ( ? attribute ? "," literal { "," literal } | condition { "," condition } ) ")"
+    | "ALL_OF(" condition { "," condition } ")" ;
+comparison = "=" | "!=" | "<>" | "<" | "<=" | ">" | ">=" ;
+(* Permissions are compared with octal strings or numbers *)
+mode = ? octal ? | ? number ? ;
+
+(* Expressions, as in SELECT lists, SET and comparisons *)
+expression = operand { arithmetic operand } ;
+arithmetic = "+" | "-" | "*" | "/" | "%" ;
+operand = "(" expression ")" | "-" operand | case | "INTERVAL" ? interval ? | call
+    | ? attribute ? | ? string ? | ? number ? | "TRUE" | "FALSE" | "NULL" ;
+case = "CASE" "WHEN" condition "THEN" expression { "WHEN" condition "THEN" expression }
+    [ "ELSE" expression ] "END" ;
+call = scalar [ expression { "," expression } ] ")" | "CAST(" expression "AS" cast_type ")" ;
+scalar = "CONCAT(" | "STEM(" | "DIRNAME(" | "LOWER(" | "UPPER(" | "YEAR(" | "MONTH(" | "DAY("
+    | "EPOCH_MS(" | "STRPTIME(" | "CURRENT_USER(" | "CURRENT_GROUP(" | "COALESCE(" | "NULLIF("
+    | "TRY(" | "FREE_SPACE(" | "TOTAL_SPACE(" ;
+cast_type = "TEXT" | "NUMBER" | "INTEGER" | "DATE" | "BOOLEAN" | "OCTAL" ;
+value = literal | "INTERVAL" ? interval ? ;
+literal = ? string ? | ? number ? | "-" ? number ? | "TRUE" | "FALSE" | "NULL" ;
+"#;
+
+/// Examples of the text each `? name ?` in [`GRAMMAR`] stands for, which
+/// sentences are written with.
+const SPECIALS: &[(&str, &[&str])] = &[
+    ("path", &[".", "/tmp", "~/Documents", "./logs", "src/*.rs", "'/srv/shared files'"]),
+    ("quoted path", &["'.'", "'/srv/src'", "'~/Documents'"]),
+    ("archive", &["backup.tar", "/tmp/logs.tar.gz", "site.tar.zst", "'/tmp/old files.zip'"]),
+    ("table file", &["'inventory.csv'", "'/srv/data/inventory.json'"]),
+    ("table", &["inventory"]),
+    ("table column", &["inventory.team", "inventory.filename"]),
+    (
+        "attribute",
+        &[
+            "name", "path", "size", "extension", "ext", "modified", "created", "accessed", "age",
+            "owner", "group", "is_directory", "content", "disk_usage",
+        ],
+    ),
+    ("flag", &["is_directory", "is_symlink", "is_executable", "is_mine", "is_sparse"]),
+    ("identifier", &["total", "misc"]),
+    ("string", &["'report'", "'it''s'", "''", "'2024-01-01'", "'.txt'"]),
+    ("number", &["0", "42", "1024", "3.5", "0o755", "0b101"]),
+    ("count", &["1", "10", "512"]),
+    ("size", &["10GB", "1.5MiB", "4096"]),
+    ("level", &["1", "3", "9"]),
+    ("percentage", &["1", "12.5", "100"]),
+    ("numeric attribute", &["size", "disk_usage"]),
+    ("seed", &["0", "42"]),
+    ("duration", &["'10s'", "'2m'", "'1h30m'"]),
+    ("bare duration", &["2s", "500ms", "1.5s", "3"]),
+    ("interval", &["'90 days'", "'2h30m'", "'-1 day'"]),
+    ("like pattern", &["'%.txt'", "'report_2024%'", "'%'"]),
+    ("regex", &["'^a'", r"'\.rs$'", "'[0-9]+'"]),
+    ("mask", &["0o022", "18", "'022'"]),
+    ("bytes", &["x'7f454c46'", "X'25504446'"]),
+    ("permission spec", &["'g+w'", "'u+x'"]),
+    ("octal pattern", &["'??5'", "'644'"]),
+    ("octal", &["'644'", "'0755'", "'022'"]),
+    ("collation", &["'en'", "'de'"]),
+];
+
+/// How many rules deep a sentence goes before every choice takes the
+/// shortest way to finish.
+const DEPTH: usize = 16;
+
+/// A part of a rule.
+#[derive(Debug, Clone, PartialEq)]
+enum Expr {
+    Terminal(String),
+    Special(String),
+    Rule(String),
+    Sequence(Vec<Expr>),
+    Choice(Vec<Expr>),
+    Optional(Box<Expr>),
+    Repeat(Box<Expr>),
+}
+
+/// A grammar read from EBNF, which can write out the sentences it describes.
+#[derive(Debug, Clone)]
+pub struct Grammar {
+    rules: BTreeMap<String, Expr>,
+    /// For each rule, how many rules deep its shortest sentence goes.
+    heights: BTreeMap<String, usize>,
+}
+
+impl Grammar {
+    /// Returns the grammar of fmql queries, [`GRAMMAR`].
+    pub fn standard() -> Grammar {
+        Grammar::parse(GRAMMAR).expect("the query grammar is valid")
+    }
+
+    /// Reads a grammar written in EBNF.
+    ///
+    /// # Errors
+    ///
+    /// Returns a message describing the problem if the text isn't EBNF, a
+    /// rule is defined twice, a rule or a `? name ?` is used but not
+    /// defined, or a rule can never finish.
+    pub fn parse(text: &str) -> Result<Grammar, String> {
+        let mut reader = Reader { text, position: 0 };
+        let mut rules = BTreeMap::new();
+        while reader.skip_space() {
+            let name = reader.name()?;
+            reader.expect('=')?;
+            let expr = reader.alternatives()?;
+            reader.expect(';')?;
+            if rules.insert(name.clone(), expr).is_some() {
+                return Err(format!("the rule '{}' is defined twice", name));
+            }
+        }
+
+        for expr in rules.values() {
+            check(expr, &rules)?;
+        }
+        let heights = heights(&rules);
+        if let Some(name) = rules.keys().find(|name| !heights.contains_key(*name)) {
+            return Err(format!("the rule '{}' never finishes", name));
+        }
+        Ok(Grammar { rules, heights })
+    }
+
+    /// Returns the names of the rules, in alphabetical order.
+    pub fn rules(&self) -> impl Iterator<Item = &str> {
+        self.rules.keys().map(String::as_str)
+    }
+
+    /// Returns every piece of text sentences are made of: the quoted text
+    /// of the rules and the examples of each `? name ?`.
+    pub fn terminals(&self) -> BTreeSet<&str> {
+        fn collect<'a>(expr: &'a Expr, terminals: &mut BTreeSet<&'a str>) {
+            match expr {
+                Expr::Terminal(text) => {
+                    terminals.insert(text);
+                }
+                Expr::Special(name) => terminals.extend(samples(name).iter().copied()),
+                Expr::Rule(_) => {}
+                Expr::Sequence(items) | Expr::Choice(items) => {
+                    items.iter().for_each(|item| collect(item, terminals));
+                }
+                Expr::Optional(inner) | Expr::Repeat(inner) => collect(inner, terminals),
+            }
+        }
+
+        let mut terminals = BTreeSet::new();
+        self.rules.values().for_each(|expr| collect(expr, &mut terminals));
+        terminals
+    }
+
+    /// Writes out a sentence of `rule`, making its choices from `seed`, so
+    /// the same seed always gives the same sentence. Returns None if there
+    /// is no such rule.
+    pub fn sentence(&self, rule: &str, seed: u64) -> Option<String> {
+        let expr = self.rules.get(rule)?;
+        let mut random = Random::new(seed);
+        let mut pieces = Vec::new();
+        self.write(expr, 0, &mut random, &mut pieces);
+
+        let mut sentence = String::new();
+        for piece in pieces {
+            if !sentence.is_empty() && !sentence.ends_with('(') {
+                sentence.push(' ');
+            }
+            sentence.push_str(piece);
+        }
+        Some(sentence)
+    }
+
+    /// Appends the pieces of a sentence of `expr`, `depth` rules down.
+    fn write<'a>(
+        &'a self,
+        expr: &'a Expr,
+        depth: usize,
+        random: &mut Random,
+        out: &mut Vec<&'a str>,
+    ) {
+        let deep = depth >= DEPTH;
+        match expr {
+            Expr::Terminal(text) => out.push(text),
+            Expr::Special(name) => out.push(*random.pick(samples(name))),
+            Expr::Rule(name) => self.write(&self.rules[name], depth + 1, random, out),
+            Expr::Sequence(items) => {
+                for item in items {
+                    self.write(item, depth, random, out);
+                }
+            }
+            Expr::Choice(alternatives) => {
+                let choice = if deep {
+                    alternatives
+                        .iter()
+                        .min_by_key(|alternative| height(alternative, &self.heights))
+                        .expect("a choice has alternatives")
+                } else {
+                    random.pick(alternatives)
+                };
+                self.write(choice, depth, random, out);
+            }
+            Expr::Optional(inner) => {
+                if !deep && random.below(2) == 0 {
+                    self.write(inner, depth, random, out);
+                }
+            }
+            Expr::Repeat(inner) => {
+                if !deep {
+                    for _ in 0..random.below(3) {
+                        self.write(inner, depth, random, out);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Returns the examples of a `? name ?`.
+fn samples(name: &str) -> &'static [&'static str] {
+    SPECIALS
+        .iter()
+        .find(|(special, _)| *special == name)
+        .map(|(_, samples)| *samples)
+        .unwrap_or_default()
+}
+
+/// Checks that every rule and `? name ?` that `expr` uses is defined.
+fn check(expr: &Expr, rules: &BTreeMap<String, Expr>) -> Result<(), String> {
+    match expr {
+        Expr::Terminal(_) => Ok(()),
+        Expr::Special(name) if samples(name).is_empty() => {
+            Err(format!("'? {} ?' has no examples", name))
+        }
+        Expr::Special(_) => Ok(()),
+        Expr::Rule(name) if !rules.contains_key(name) => {
+            Err(format!("the rule '{}' is used but not defined", name))
+        }
+        Expr::Rule(_) => Ok(()),
+        Expr::Sequence(items) | Expr::Choice(items) => {
+            items.iter().try_for_each(|item| check(item, rules))
+        }
+        Expr::Optional(inner) | Expr::Repeat(inner) => check(inner, rules),
+    }
+}
+
+/// Works out how many rules deep the shortest sentence of each rule goes.
+/// Rules that never finish are left out.
+fn heights(rules: &BTreeMap<String, Expr>) -> BTreeMap<String, usize> {
+    let mut heights = BTreeMap::new();
+    loop {
+        let mut changed = false;
+        for (name, expr) in rules {
+            let found = height(expr, &heights);
+            if found != usize::MAX && heights.get(name) != Some(&found) {
+                heights.insert(name.clone(), found);
+                changed = true;
+            }
+        }
+        if !changed {
+            return heights;
+        }
+    }
+}
+
+/// Returns how many rules deep the shortest sentence of `expr` goes, given
+/// the heights of the rules known so far, or `usize::MAX` if it can't
+/// finish yet.
+fn height(expr: &Expr, heights: &BTreeMap<String, usize>) -> usize {
+    match expr {
+        Expr::Terminal(_) | Expr::Special(_) | Expr::Optional(_) | Expr::Repeat(_) => 0,
+        Expr::Rule(name) => heights.get(name).map_or(usize::MAX, |height| height + 1),
+        Expr::Sequence(items) => items.iter().map(|item| height(item, heights)).max().unwrap_or(0),
+        Expr::Choice(items) => {
+            items.iter().map(|item| height(item, heights)).min().unwrap_or(usize::MAX)
+        }
+    }
+}
+
+/// Reads EBNF text.
+struct Reader<'a> {
+    text: &'a str,
+    position: usize,
+}
+
+impl Reader<'_> {
+    /// Skips whitespace and comments, and returns true if there is more.
+    fn skip_space(&mut self) -> bool {
+        loop {
+            let rest = &self.text[self.position..];
+            let trimmed = rest.trim_start();
+            self.position += rest.len() - trimmed.len();
+            if !trimmed.starts_with("(*") {
+                return !trimmed.is_empty();
+            }
+            match trimmed.find("*)") {
+                Some(end) => self.position += end + 2,
+                None => self.position = self.text.len(),
+            }
+        }
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.skip_space();
+        self.text[self.position..].chars().next()
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        match self.peek() {
+            Some(found) if found == expected => {
+                self.position += 1;
+                Ok(())
+            }
+            Some(found) => Err(format!(
+                "expected '{}' at offset {}, found '{}'",
+                expected, self.position, found
+            )),
+            None => Err(format!("expected '{}' at the end", expected)),
+        }
+    }
+
+    /// Reads a rule name: letters, digits and underscores.
+    fn name(&mut self) -> Result<String, String> {
+        self.skip_space();
+        let rest = &self.text[self.position..];
+        let end = rest
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .unwrap_or(rest.len());
+        if end == 0 {
+            return Err(format!("expected a rule name at offset {}", self.position));
+        }
+        self.position += end;
+        Ok(rest[..end].to_string())
+    }
+
+    /// Reads the text up to the next `close`, after the opening character.
+    fn quoted(&mut self, close: char) -> Result<String, String> {
+        let start = self.position;
+        self.position += 1;
+        let rest = &self.text[self.position..];
+        let end = rest
+            .find(close)
+            .ok_or_else(|| format!("'{}' at offset {} isn't closed", close, start))?;
+        self.position += end + 1;
+        Ok(rest[..end].to_string())
+    }
+
+    /// Reads alternatives separated by `|`.
+    fn alternatives(&mut self) -> Result<Expr, String> {
+        let mut alternatives = vec![self.sequence()?];
+        while self.peek() == Some('|') {
+            self.position += 1;
+            alternatives.push(self.sequence()?);
+        }
+        Ok(match alternatives.len() {
+            1 => alternatives.remove(0),
+            _ => Expr::Choice(alternatives),
+        })
+    }
+
+    /// Reads the items of one alternative.
+    fn sequence(&mut self) -> Result<Expr, String> {
+        let mut items = Vec::new();
+        loop {
+            let item = match self.peek() {
+                Some('"') => Expr::Terminal(self.quoted('"')?),
+                Some('?') => Expr::Special(self.quoted('?')?.trim().to_string()),
+                Some(open @ ('(' | '[' | '{')) => {
+                    self.position += 1;
+                    let inner = self.alternatives()?;
+                    match open {
+                        '(' => {
+                            self.expect(')')?;
+                            inner
+                        }
+                        '[' => {
+                            self.expect(']')?;
+                            Expr::Optional(Box::new(inner))
+                        }
+                        _ => {
+                            self.expect('}')?;
+                            Expr::Repeat(Box::new(inner))
+                        }
+                    }
+                }
+                Some(c) if c.is_ascii_alphabetic() => Expr::Rule(self.name()?),
+                _ => break,
+            };
+            items.push(item);
+        }
+        match items.len() {
+            0 => Err(format!("expected a rule's text at offset {}", self.position)),
+            1 => Ok(items.remove(0)),
+            _ => Ok(Expr::Sequence(items)),
+        }
+    }
+}
+
+/// A small pseudo-random number generator (xorshift64*), so that the same
+/// seed always makes the same choices.
+struct Random(u64);
+
+impl Random {
+    fn new(seed: u64) -> Self {
+        // Spread nearby seeds apart; xorshift needs a state other than zero
+        Random(seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    /// Returns a number from 0 up to but not including `bound`.
+    fn below(&mut self, bound: usize) -> usize {
+        (self.next() % bound as u64) as usize
+    }
+
+    fn pick<'a, T>(&mut self, items: &'a [T]) -> &'a T {
+        &items[self.below(items.len())]
+    }
+}
@@ -0,0 +1,84 @@
+//! Patterns for entries a scan leaves out, such as `node_modules` and
+//! `.git`, as a config profile's `ignore` lists them.
+//!
+//! A pattern without a `/` is a glob matched against each entry's name, so
+//! `*.tmp` leaves out temporary files wherever they are; one with a `/` is
+//! matched against the whole path, as in `*/build/cache`. An ignored
+//! directory isn't descended into, so nothing below it is listed either.
+//! The targets a query names are never ignored: `FROM ./node_modules`
+//! lists what is in it, though the patterns still apply below.
+//!
+//! # Examples
+//!
+//! ```
+//! use std::path::Path;
+//!
+//! use fmql_core::sql::ignore::Ignore;
+//!
+//! let ignore = Ignore::new(&["node_modules", "*.tmp", "*/build/cache"]).unwrap();
+//! assert!(ignore.is_ignored(Path::new("app/node_modules")));
+//! assert!(ignore.is_ignored(Path::new("app/src/draft.tmp")));
+//! assert!(ignore.is_ignored(Path::new("app/build/cache")));
+//! assert!(!ignore.is_ignored(Path::new("app/src/main.rs")));
+//! assert!(Ignore::new(&["[unclosed"]).is_err());
+//! ```
+
+use std::path::Path;
+use std::sync::Arc;
+
+use glob::{MatchOptions, Pattern, PatternError};
+
+/// The patterns of entries to leave out of scans; empty by default.
+#[derive(Debug, Clone, Default)]
+pub struct Ignore {
+    /// Patterns matched against an entry's name.
+    names: Arc<[Pattern]>,
+    /// Patterns matched against an entry's whole path.
+    paths: Arc<[Pattern]>,
+}
+
+impl Ignore {
+    /// Compiles a list of patterns.
+    ///
+    /// # Errors
+    ///
+    /// Fails on the first pattern that isn't a valid glob.
+    pub fn new<S: AsRef<str>>(patterns: &[S]) -> Result<Ignore, PatternError> {
+        let mut names = Vec::new();
+        let mut paths = Vec::new();
+        for pattern in patterns {
+            let pattern = pattern.as_ref();
+            let compiled = Pattern::new(pattern.trim_end_matches('/'))?;
+            if pattern.trim_end_matches('/').contains('/') {
+                paths.push(compiled);
+            } else {
+                names.push(compiled);
+            }
+        }
+        Ok(Ignore {
+            names: names.into(),
+            paths: paths.into(),
+        })
+    }
+
+    /// Returns true if there are no patterns.
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty() && self.paths.is_empty()
+    }
+
+    /// Returns true if the entry at `path` is to be left out.
+    pub fn is_ignored(&self, path: &Path) -> bool {
+        let options = MatchOptions {
+            require_literal_separator: true,
+            ..MatchOptions::new()
+        };
+        let named = path.file_name().is_some_and(|name| {
+            let name = name.to_string_lossy();
+            self.names.iter().any(|pattern| pattern.matches_with(&name, options))
+        });
+        named || {
+            let path = path.to_string_lossy();
+            self.paths.iter().any(|pattern| pattern.matches(&path))
+        }
+    }
+}
@@ -0,0 +1,488 @@
+//! Container images as directories to query, for
+//! `FROM 'docker://alpine:3.19'`.
+//!
+//! An image is a stack of layers, each a tarball of the files it adds,
+//! changes or deletes. [`unpack`] gets the image, through `docker image
+//! save` for `docker://image:tag` or from an archive `docker save` or
+//! `skopeo` already wrote for `docker-archive:/path/to/image.tar`, and
+//! applies its layers in order to a private temporary directory the way a
+//! container runtime does: a later layer's file replaces an earlier one's,
+//! a `.wh.name` whiteout deletes `name`, and `.wh..wh..opq` empties its
+//! directory of what the layers below put there. Both the layouts of
+//! `docker save`, `manifest.json` and OCI's `index.json`, are read, with
+//! layers plain, gzipped or zstd-compressed.
+//!
+//! The query runs on that directory, and its results are given their paths
+//! in the image, such as `docker://alpine:3.19/etc/passwd`, so that nothing
+//! refers to the copy once it is gone. Files keep their modes, setuid and
+//! world-writable bits included, and their modification times, so either
+//! can be audited; they belong to whoever runs the query, since only root
+//! could give them the image's owners. Device files aren't created, and a
+//! symlink on the way to an entry, such as `/bin` on images where it leads
+//! to `/usr/bin`, is followed within the image as the runtime follows it,
+//! never out of the copy. While the query runs, `path` is a file's path in
+//! the copy, so a condition on it should match its end, as in `path LIKE
+//! '%/etc/shadow'`.
+//!
+//! # Examples
+//!
+//! ```
+//! use std::path::Path;
+//!
+//! use fmql_core::sql::images::{unpack, ImageSource};
+//!
+//! // An image of one layer, as docker save writes it
+//! let layer = {
+//!     let mut layer = tar::Builder::new(Vec::new());
+//!     let mut header = tar::Header::new_gnu();
+//!     header.set_size(5);
+//!     header.set_mode(0o644);
+//!     layer.append_data(&mut header, "etc/motd", &b"hello"[..]).unwrap();
+//!     layer.into_inner().unwrap()
+//! };
+//! let dir = tempfile::tempdir().unwrap();
+//! let archive = dir.path().join("image.tar");
+//! let mut image = tar::Builder::new(std::fs::File::create(&archive).unwrap());
+//! let manifest = br#"[{"Layers": ["layer.tar"]}]"#.to_vec();
+//! for (name, data) in [("layer.tar", layer), ("manifest.json", manifest)] {
+//!     let mut header = tar::Header::new_gnu();
+//!     header.set_size(data.len() as u64);
+//!     header.set_mode(0o644);
+//!     image.append_data(&mut header, name, &data[..]).unwrap();
+//! }
+//! image.finish().unwrap();
+//!
+//! let from = format!("docker-archive:{}", archive.display());
+//! let source = ImageSource::parse(Path::new(&from)).unwrap();
+//! let image = unpack(&source).unwrap();
+//! let motd = image.root().join("etc/motd");
+//! assert_eq!(std::fs::read(&motd).unwrap(), b"hello");
+//! assert_eq!(image.path_in_image(&motd), Path::new(&format!("{}/etc/motd", from)));
+//! ```
+
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom};
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Component, Path, PathBuf};
+use std::process::Command;
+use std::time::{Duration, SystemTime};
+
+use serde_json::Value;
+use tempfile::TempDir;
+use walkdir::WalkDir;
+
+/// The name of a whiteout that empties its directory.
+const OPAQUE: &str = ".wh..wh..opq";
+
+/// The most symlinks followed on the way to one entry, as Linux allows.
+const MAX_LINKS: usize = 40;
+
+/// The most of an image's `manifest.json`, `index.json` or other JSON
+/// that is read.
+const MAX_JSON: u64 = 16 * 1024 * 1024;
+
+/// Where a container image comes from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ImageSource {
+    /// An image the local Docker engine has, such as `alpine:3.19`, saved
+    /// with `docker image save`.
+    Docker(String),
+    /// An archive of an image that `docker save` or `skopeo` wrote.
+    Archive(PathBuf),
+}
+
+impl ImageSource {
+    /// Reads a FROM path naming an image, `docker://image:tag` or
+    /// `docker-archive:/path/to/image.tar`, or returns None for any other
+    /// path.
+    pub fn parse(from: &Path) -> Option<ImageSource> {
+        let from = from.to_str()?;
+        if let Some(reference) = from.strip_prefix("docker://") {
+            let reference = reference.trim_end_matches('/');
+            return (!reference.is_empty()).then(|| ImageSource::Docker(reference.to_string()));
+        }
+        let archive = from.strip_prefix("docker-archive:")?;
+        (!archive.is_empty()).then(|| ImageSource::Archive(PathBuf::from(archive)))
+    }
+
+    /// How the image's files are named in results: the FROM path, which
+    /// their paths in the image follow.
+    pub fn prefix(&self) -> String {
+        match self {
+            ImageSource::Docker(reference) => format!("docker://{}", reference),
+            ImageSource::Archive(archive) => format!("docker-archive:{}", archive.display()),
+        }
+    }
+}
+
+/// An image unpacked into a temporary directory, which is deleted when
+/// this is dropped.
+#[derive(Debug)]
+pub struct Image {
+    dir: TempDir,
+    root: PathBuf,
+    prefix: String,
+}
+
+impl Image {
+    /// The directory the image's files are in.
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Returns what a path in the unpacked copy is called in results: its
+    /// path in the image after the image's name.
+    pub fn path_in_image(&self, path: &Path) -> PathBuf {
+        match path.strip_prefix(&self.root) {
+            Ok(relative) => PathBuf::from(format!("{}/{}", self.prefix, relative.display())),
+            Err(_) => path.to_path_buf(),
+        }
+    }
+
+    /// Returns `text` with the unpacked copy's path at its start replaced
+    /// as [`path_in_image`](Image::path_in_image) does, or None if it
+    /// doesn't start with it.
+    pub fn rewrite(&self, text: &str) -> Option<String> {
+        let root = self.root.to_str()?;
+        let rest = text.strip_prefix(root)?;
+        if rest.is_empty() || rest.starts_with('/') {
+            Some(format!("{}/{}", self.prefix, rest.trim_start_matches('/')))
+        } else {
+            None
+        }
+    }
+}
+
+impl Drop for Image {
+    fn drop(&mut self) {
+        // Directories the image made read-only must be writable again for
+        // their files to be deleted
+        for entry in WalkDir::new(self.dir.path()).into_iter().flatten() {
+            if entry.file_type().is_dir() {
+                let _ = fs::set_permissions(entry.path(), fs::Permissions::from_mode(0o700));
+            }
+        }
+    }
+}
+
+/// Unpacks an image into a new temporary directory.
+///
+/// # Errors
+///
+/// Fails if the image can't be saved or read, with `InvalidData` if the
+/// archive isn't one of an image.
+pub fn unpack(source: &ImageSource) -> io::Result<Image> {
+    let dir = tempfile::Builder::new().prefix("fmql-image-").tempdir()?;
+    let root = dir.path().join("rootfs");
+    fs::create_dir(&root)?;
+    let saved;
+    let archive = match source {
+        ImageSource::Docker(reference) => {
+            saved = dir.path().join("image.tar");
+            save(reference, &saved)?;
+            &saved
+        }
+        ImageSource::Archive(archive) => archive,
+    };
+    let mut file = File::open(archive)
+        .map_err(|err| io::Error::new(err.kind(), format!("{}: {}", archive.display(), err)))?;
+    let members = index(&mut file)?;
+    let layers = layers(&mut file, &members)?;
+    let mut dirs = BTreeMap::new();
+    for layer in layers {
+        let &(offset, size) = members.get(&layer).ok_or_else(|| {
+            invalid(format!("the image has no layer {}", layer))
+        })?;
+        file.seek(SeekFrom::Start(offset))?;
+        apply_layer(&root, decompress(BufReader::new((&mut file).take(size)))?, &mut dirs)?;
+    }
+    // Directories get their modes last, so that read-only ones could be
+    // written into
+    for (path, (mode, modified)) in dirs {
+        if let Some(modified) = modified {
+            let _ = File::open(&path).and_then(|dir| dir.set_modified(modified));
+        }
+        let _ = fs::set_permissions(&path, fs::Permissions::from_mode(mode & 0o7777));
+    }
+    if let ImageSource::Docker(_) = source {
+        fs::remove_file(archive)?;
+    }
+    Ok(Image { dir, root, prefix: source.prefix() })
+}
+
+/// Saves an image the local Docker engine has to an archive.
+fn save(reference: &str, archive: &Path) -> io::Result<()> {
+    let output = Command::new("docker")
+        .args(["image", "save", "--output"])
+        .arg(archive)
+        .arg(reference)
+        .output()
+        .map_err(|err| match err.kind() {
+            io::ErrorKind::NotFound => io::Error::new(
+                io::ErrorKind::NotFound,
+                "docker isn't installed; save the image with docker save and query \
+                 docker-archive:/path/to/image.tar instead",
+            ),
+            _ => err,
+        })?;
+    if !output.status.success() {
+        let message = String::from_utf8_lossy(&output.stderr);
+        return Err(io::Error::other(format!(
+            "docker image save {} failed: {}",
+            reference,
+            message.trim()
+        )));
+    }
+    Ok(())
+}
+
+/// Finds where each member of an image archive is, as the offset and
+/// length of its data.
+fn index(file: &mut File) -> io::Result<HashMap<String, (u64, u64)>> {
+    file.seek(SeekFrom::Start(0))?;
+    let mut members = HashMap::new();
+    let mut archive = tar::Archive::new(BufReader::new(&mut *file));
+    for entry in archive.entries()? {
+        let entry = entry?;
+        if entry.header().entry_type().is_file() {
+            let name = entry.path()?.to_string_lossy().trim_start_matches("./").to_string();
+            members.insert(name, (entry.raw_file_position(), entry.size()));
+        }
+    }
+    Ok(members)
+}
+
+/// Returns the members an image's layers are in, bottom first.
+fn layers(file: &mut File, members: &HashMap<String, (u64, u64)>) -> io::Result<Vec<String>> {
+    if members.contains_key("manifest.json") {
+        let manifest = read_json(file, members, "manifest.json")?;
+        let layers = manifest.get(0).and_then(|image| image.get("Layers"));
+        return strings(layers).ok_or_else(|| invalid("manifest.json lists no layers".to_string()));
+    }
+    if !members.contains_key("index.json") {
+        return Err(invalid("neither manifest.json nor index.json is in the archive".to_string()));
+    }
+    // An index can name further indexes, one for each platform
+    let mut document = read_json(file, members, "index.json")?;
+    for _ in 0..4 {
+        if let Some(layers) = document.get("layers").and_then(Value::as_array) {
+            let digests = layers.iter().map(|layer| layer.get("digest"));
+            return digests
+                .map(|digest| digest.and_then(Value::as_str).and_then(blob))
+                .collect::<Option<_>>()
+                .ok_or_else(|| invalid("a layer has no digest".to_string()));
+        }
+        let manifest = document
+            .get("manifests")
+            .and_then(|manifests| manifests.get(0))
+            .and_then(|manifest| manifest.get("digest"))
+            .and_then(Value::as_str)
+            .and_then(blob)
+            .ok_or_else(|| invalid("index.json names no manifest".to_string()))?;
+        document = read_json(file, members, &manifest)?;
+    }
+    Err(invalid("index.json names indexes too deep to follow".to_string()))
+}
+
+/// Returns the member a blob is in, from its digest.
+fn blob(digest: &str) -> Option<String> {
+    let (algorithm, hex) = digest.split_once(':')?;
+    Some(format!("blobs/{}/{}", algorithm, hex))
+}
+
+/// Returns the strings of a JSON array.
+fn strings(array: Option<&Value>) -> Option<Vec<String>> {
+    array?.as_array()?.iter().map(|value| value.as_str().map(str::to_string)).collect()
+}
+
+/// Reads a member of an image archive as JSON.
+fn read_json(
+    file: &mut File,
+    members: &HashMap<String, (u64, u64)>,
+    name: &str,
+) -> io::Result<Value> {
+    let &(offset, size) =
+        members.get(name).ok_or_else(|| invalid(format!("the image has no {}", name)))?;
+    file.seek(SeekFrom::Start(offset))?;
+    let mut json = Vec::new();
+    (&mut *file).take(size.min(MAX_JSON)).read_to_end(&mut json)?;
+    serde_json::from_slice(&json).map_err(|err| invalid(format!("{}: {}", name, err)))
+}
+
+/// Reads a layer through its decompressor, told by its magic number.
+fn decompress<'a, R: BufRead + 'a>(mut layer: R) -> io::Result<Box<dyn Read + 'a>> {
+    let head = layer.fill_buf()?;
+    Ok(if head.starts_with(b"\x1f\x8b") {
+        Box::new(flate2::bufread::MultiGzDecoder::new(layer))
+    } else if head.starts_with(b"\x28\xb5\x2f\xfd") {
+        Box::new(zstd::stream::Decoder::with_buffer(layer)?)
+    } else {
+        Box::new(layer)
+    })
+}
+
+/// Applies a layer to the files below it in `root`, noting the mode and
+/// modification time of each directory in `dirs` to be set once all the
+/// layers are.
+fn apply_layer(
+    root: &Path,
+    layer: impl Read,
+    dirs: &mut BTreeMap<PathBuf, (u32, Option<SystemTime>)>,
+) -> io::Result<()> {
+    // What this layer made, which its opaque whiteouts leave alone
+    let mut made = HashSet::new();
+    let mut archive = tar::Archive::new(layer);
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let Some(relative) = relative_path(&entry.path()?) else {
+            continue;
+        };
+        let Some(target) = inside(root, &relative) else {
+            continue;
+        };
+        let name = relative.file_name().map(|name| name.to_string_lossy().into_owned());
+        let name = name.unwrap_or_default();
+        if name == OPAQUE {
+            let dir = target.parent().unwrap_or(root);
+            for child in fs::read_dir(dir).into_iter().flatten().flatten() {
+                let relative = relative.with_file_name(child.file_name());
+                if !made.contains(&relative) {
+                    remove(&child.path())?;
+                }
+            }
+            continue;
+        }
+        if let Some(hidden) = name.strip_prefix(".wh.") {
+            remove(&target.with_file_name(hidden))?;
+            continue;
+        }
+        let header = entry.header();
+        let mode = header.mode().unwrap_or(0o644);
+        let modified = header.mtime().ok();
+        let modified = modified.map(|secs| SystemTime::UNIX_EPOCH + Duration::from_secs(secs));
+        match header.entry_type() {
+            tar::EntryType::Directory => {
+                if !target.symlink_metadata().is_ok_and(|metadata| metadata.is_dir()) {
+                    remove(&target)?;
+                }
+                fs::create_dir_all(&target)?;
+                dirs.insert(target, (mode, modified));
+            }
+            tar::EntryType::Regular | tar::EntryType::Continuous => {
+                replace(&target)?;
+                let mut file = File::create(&target)?;
+                io::copy(&mut entry, &mut file)?;
+                file.set_permissions(fs::Permissions::from_mode(mode & 0o7777))?;
+                if let Some(modified) = modified {
+                    file.set_modified(modified)?;
+                }
+            }
+            tar::EntryType::Symlink => {
+                let Some(link) = entry.link_name()? else {
+                    continue;
+                };
+                replace(&target)?;
+                std::os::unix::fs::symlink(link, &target)?;
+            }
+            tar::EntryType::Link => {
+                let linked = entry.link_name()?;
+                let linked = linked.as_deref().and_then(relative_path);
+                let Some(linked) = linked.and_then(|linked| inside(root, &linked)) else {
+                    continue;
+                };
+                replace(&target)?;
+                if fs::hard_link(&linked, &target).is_err() {
+                    fs::copy(&linked, &target)?;
+                }
+            }
+            // Devices and FIFOs
+            _ => continue,
+        }
+        made.extend(relative.ancestors().map(Path::to_path_buf));
+    }
+    Ok(())
+}
+
+/// Returns the path of a layer's entry relative to the image's root, or
+/// None if it climbs out of it.
+fn relative_path(path: &Path) -> Option<PathBuf> {
+    let mut relative = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(part) => relative.push(part),
+            Component::CurDir | Component::RootDir => {}
+            Component::ParentDir | Component::Prefix(_) => return None,
+        }
+    }
+    (!relative.as_os_str().is_empty()).then_some(relative)
+}
+
+/// Returns where a path relative to the image's root is in `root`. The
+/// symlinks on the way are followed as if `root` were `/`, so none leads
+/// out of it; the last component is left as it is. Returns None if there
+/// are too many links to follow.
+fn inside(root: &Path, relative: &Path) -> Option<PathBuf> {
+    let mut resolved = PathBuf::new();
+    let mut pending: VecDeque<PathBuf> = components(relative);
+    let mut links = 0;
+    while let Some(part) = pending.pop_front() {
+        match part.components().next() {
+            Some(Component::Normal(_)) => {}
+            Some(Component::ParentDir) => {
+                resolved.pop();
+                continue;
+            }
+            Some(Component::RootDir) => {
+                resolved.clear();
+                continue;
+            }
+            _ => continue,
+        }
+        let candidate = root.join(&resolved).join(&part);
+        if !pending.is_empty() && candidate.is_symlink() {
+            links += 1;
+            if links > MAX_LINKS {
+                return None;
+            }
+            // Where the link leads comes before what is left of the path
+            for part in components(&fs::read_link(&candidate).ok()?).into_iter().rev() {
+                pending.push_front(part);
+            }
+            continue;
+        }
+        resolved.push(part);
+    }
+    Some(root.join(resolved))
+}
+
+/// Splits a path into its components, each a path of its own.
+fn components(path: &Path) -> VecDeque<PathBuf> {
+    path.components().map(|component| PathBuf::from(component.as_os_str())).collect()
+}
+
+/// Makes way for a new entry at `path`, creating its directory and
+/// removing whatever is there.
+fn replace(path: &Path) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    remove(path)
+}
+
+/// Removes whatever is at `path`, if anything is.
+fn remove(path: &Path) -> io::Result<()> {
+    let removed = match path.symlink_metadata() {
+        Ok(metadata) if metadata.is_dir() => fs::remove_dir_all(path),
+        Ok(_) => fs::remove_file(path),
+        Err(_) => return Ok(()),
+    };
+    match removed {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+        _ => Ok(()),
+    }
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
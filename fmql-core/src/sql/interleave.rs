@@ -0,0 +1,123 @@
+//! Writes the output of parallel workers without mixing it up.
+//!
+//! Workers that print as they go can interleave their lines, or even the
+//! halves of one line. Instead, each worker renders its part of the output
+//! whole and hands it to one [`OutputSerializer`], which writes each part in
+//! one piece, either in the order of the parts or as they come:
+//!
+//! - [`OutputOrder::Ordered`] holds a part back until every part before it
+//!   is written, so the output is the same however the work was shared out.
+//!   `fmql sql --each-root` numbers its parts by root, so the rows of each
+//!   directory come together, in the order of the list.
+//! - [`OutputOrder::Unordered`] writes each part as soon as it is done, for
+//!   the least wait before the first rows.
+//!
+//! Either way, each part is flushed once written, so whatever reads the
+//! output sees it straight away.
+//!
+//! # Examples
+//!
+//! ```
+//! use fmql_core::sql::interleave::{OutputOrder, OutputSerializer};
+//!
+//! let mut serializer = OutputSerializer::new(Vec::new(), OutputOrder::Ordered);
+//! serializer.write(1, b"second\n".to_vec()).unwrap();
+//! assert_eq!(serializer.held(), 1);
+//! serializer.write(0, b"first\n".to_vec()).unwrap();
+//! assert_eq!(serializer.into_inner().unwrap(), b"first\nsecond\n");
+//!
+//! let mut serializer = OutputSerializer::new(Vec::new(), OutputOrder::Unordered);
+//! serializer.write(1, b"second\n".to_vec()).unwrap();
+//! serializer.write(0, b"first\n".to_vec()).unwrap();
+//! assert_eq!(serializer.into_inner().unwrap(), b"second\nfirst\n");
+//! ```
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::{self, Write};
+
+/// The order an [`OutputSerializer`] writes its parts in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutputOrder {
+    /// In the order of the parts, holding back any that finish early.
+    #[default]
+    Ordered,
+    /// As each part finishes.
+    Unordered,
+}
+
+impl std::str::FromStr for OutputOrder {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "ordered" => Ok(OutputOrder::Ordered),
+            "unordered" => Ok(OutputOrder::Unordered),
+            _ => Err(format!("Unknown output order '{}' (expected ordered or unordered)", s)),
+        }
+    }
+}
+
+impl fmt::Display for OutputOrder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            OutputOrder::Ordered => "ordered",
+            OutputOrder::Unordered => "unordered",
+        })
+    }
+}
+
+/// Writes whole parts of the output, numbered from 0, to one writer: see
+/// the [module documentation](self).
+#[derive(Debug)]
+pub struct OutputSerializer<W: Write> {
+    out: W,
+    order: OutputOrder,
+    /// The part to write next, when ordered.
+    next: usize,
+    /// The parts that finished before the ones ahead of them.
+    held: BTreeMap<usize, Vec<u8>>,
+}
+
+impl<W: Write> OutputSerializer<W> {
+    /// Creates a serializer that writes to `out` in `order`.
+    pub fn new(out: W, order: OutputOrder) -> Self {
+        OutputSerializer {
+            out,
+            order,
+            next: 0,
+            held: BTreeMap::new(),
+        }
+    }
+
+    /// Takes the whole output of part `part`: writes it now, or when ordered
+    /// holds it until the parts before it are written. A part with no output
+    /// still has to be given, or the parts after it wait for it.
+    pub fn write(&mut self, part: usize, output: Vec<u8>) -> io::Result<()> {
+        if self.order == OutputOrder::Unordered {
+            self.out.write_all(&output)?;
+            return self.out.flush();
+        }
+        self.held.insert(part, output);
+        while let Some(output) = self.held.remove(&self.next) {
+            self.out.write_all(&output)?;
+            self.next += 1;
+        }
+        self.out.flush()
+    }
+
+    /// How many parts are held back, waiting for the ones before them.
+    pub fn held(&self) -> usize {
+        self.held.len()
+    }
+
+    /// Writes any parts still held back, in order, and returns the writer.
+    /// Parts are only left over if one before them was never given.
+    pub fn into_inner(mut self) -> io::Result<W> {
+        for output in std::mem::take(&mut self.held).into_values() {
+            self.out.write_all(&output)?;
+        }
+        self.out.flush()?;
+        Ok(self.out)
+    }
+}
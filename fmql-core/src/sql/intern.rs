@@ -0,0 +1,77 @@
+//! Shared copies of the strings many results repeat.
+//!
+//! A tree of a few million files has only a few hundred extensions and a
+//! handful of owners, yet each result would own a copy of both. [`intern`]
+//! hands out one reference-counted copy of each distinct string instead, so
+//! a result holds a pointer where it held an allocation, and cloning a
+//! result, as sorting and grouping do, copies no text.
+//!
+//! Every thread keeps the strings it has seen in a table of its own, so
+//! the threads of a `--jobs` scan look them up without waiting on each
+//! other; only a thread's first sight of a string goes to the table they
+//! share, which makes sure each thread gets the same copy. Both tables are
+//! bounded, since a long-running process can see any number of distinct
+//! strings: past the bound, new strings are handed out unshared, and those
+//! already shared stay so.
+//!
+//! # Examples
+//!
+//! ```
+//! use std::sync::Arc;
+//!
+//! use fmql_core::sql::intern::intern;
+//!
+//! let first = intern("txt");
+//! let second = intern(&String::from("txt"));
+//! assert_eq!(&*first, "txt");
+//! assert!(Arc::ptr_eq(&first, &second));
+//!
+//! // Another thread is handed the same copy
+//! let other = std::thread::spawn(|| intern("txt")).join().unwrap();
+//! assert!(Arc::ptr_eq(&first, &other));
+//! ```
+
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex, OnceLock};
+
+/// How many distinct strings the shared table keeps.
+const INTERNED: usize = 65_536;
+
+/// How many distinct strings each thread's table keeps.
+const INTERNED_PER_THREAD: usize = 4_096;
+
+/// Returns the shared copy of `text`, making it if there is none yet.
+pub fn intern(text: &str) -> Arc<str> {
+    thread_local! {
+        static SEEN: RefCell<HashSet<Arc<str>>> = RefCell::new(HashSet::new());
+    }
+
+    SEEN.with_borrow_mut(|seen| {
+        if let Some(shared) = seen.get(text) {
+            return Arc::clone(shared);
+        }
+        let shared = intern_shared(text);
+        if seen.len() < INTERNED_PER_THREAD {
+            seen.insert(Arc::clone(&shared));
+        }
+        shared
+    })
+}
+
+/// Returns the copy of `text` in the table every thread shares, for a
+/// thread that hasn't seen it before.
+fn intern_shared(text: &str) -> Arc<str> {
+    static TABLE: OnceLock<Mutex<HashSet<Arc<str>>>> = OnceLock::new();
+
+    let table = TABLE.get_or_init(|| Mutex::new(HashSet::new()));
+    let mut table = table.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    if let Some(shared) = table.get(text) {
+        return Arc::clone(shared);
+    }
+    let shared: Arc<str> = Arc::from(text);
+    if table.len() < INTERNED {
+        table.insert(Arc::clone(&shared));
+    }
+    shared
+}
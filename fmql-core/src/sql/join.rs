@@ -0,0 +1,297 @@
+//! External tables for `JOIN`, read from CSV or JSON files.
+//!
+//! A query can enrich its files with data kept elsewhere, such as the team
+//! that owns each share or the retention class of each folder:
+//!
+//! ```sql
+//! SELECT name, inventory.team FROM /srv JOIN 'inventory.csv' ON name = inventory.filename
+//! ```
+//!
+//! The table is read into memory and indexed by its key column before the
+//! scan starts, so it should be small next to the tree being scanned. A
+//! `.json` file is an array of objects, one per row; anything else is read
+//! as CSV with a header row. CSV cells are text, except that a column whose
+//! every non-empty cell is a number holds numbers, and empty cells are NULL.
+//!
+//! Rows are matched on the text of the key, so `7` in a JSON file matches a
+//! file named `7`. Each file is matched to at most one row: a key that
+//! appears twice is an error rather than a reason to list a file twice.
+//!
+//! A table can also be what a query reads, with `FROM 'inventory.csv' AS
+//! TABLE`: its [`PATH_COLUMN`] lists the files, and each is joined to its
+//! row, so an inventory can be checked against the files as they are now.
+//!
+//! # Examples
+//!
+//! ```
+//! use fmql_core::sql::ast::FileValue;
+//! use fmql_core::sql::join::JoinTable;
+//!
+//! let csv = "filename,team,retention_days\nreport.pdf,finance,3650\nlogo.png,design,\n";
+//! let table = JoinTable::from_csv(csv.as_bytes(), "filename").unwrap();
+//! let row = table.row("report.pdf").unwrap();
+//! assert_eq!(row.get("team"), Some(&FileValue::String("finance".to_string())));
+//! assert_eq!(row.get("retention_days"), Some(&FileValue::Integer(3650)));
+//! assert_eq!(table.row("logo.png").unwrap().get("retention_days"), Some(&FileValue::Null));
+//! assert!(table.row("notes.txt").is_none());
+//! ```
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::{self, BufReader, Read};
+use std::path::Path;
+
+use crate::sql::ast::{FileExpr, FileQuery, FileValue, SortKey};
+use crate::sql::executor::{ExecutorError, Result};
+use crate::sql::functions::to_text;
+use crate::sql::lexer::parse_numeric;
+use crate::sql::visit::{walk_expr, Visitor};
+
+/// The column of a table read `FROM 'paths.csv' AS TABLE` that lists the
+/// files to query.
+pub const PATH_COLUMN: &str = "path";
+
+/// A table read from a file, indexed by its key column.
+#[derive(Debug, Clone)]
+pub struct JoinTable {
+    columns: Vec<String>,
+    rows: Vec<Vec<FileValue>>,
+    /// The row for each key, by the key's text.
+    index: HashMap<String, usize>,
+}
+
+impl JoinTable {
+    /// Reads a table from a file: JSON if its extension is `.json`, CSV
+    /// otherwise. `key` is the column rows are looked up by.
+    pub fn load(path: &Path, key: &str) -> Result<Self> {
+        let file = File::open(path).map_err(|err| {
+            io::Error::new(err.kind(), format!("can't read {}: {}", path.display(), err))
+        })?;
+        let reader = BufReader::new(file);
+        let is_json = path
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("json"));
+        let table = if is_json {
+            JoinTable::from_json(reader, key)
+        } else {
+            JoinTable::from_csv(reader, key)
+        };
+        table.map_err(|err| match err {
+            ExecutorError::InvalidJoin(message) => {
+                ExecutorError::InvalidJoin(format!("{}: {}", path.display(), message))
+            }
+            err => err,
+        })
+    }
+
+    /// Reads a CSV table with a header row.
+    pub fn from_csv(reader: impl Read, key: &str) -> Result<Self> {
+        let mut reader = csv::Reader::from_reader(reader);
+        let columns: Vec<String> = reader
+            .headers()
+            .map_err(csv_error)?
+            .iter()
+            .map(str::to_string)
+            .collect();
+        let mut cells = Vec::new();
+        for record in reader.records() {
+            let record = record.map_err(csv_error)?;
+            cells.push(record.iter().map(str::to_string).collect::<Vec<_>>());
+        }
+
+        let numeric: Vec<bool> = (0..columns.len())
+            .map(|i| {
+                cells
+                    .iter()
+                    .filter_map(|row| row.get(i).filter(|cell| !cell.is_empty()))
+                    .all(|cell| number(cell).is_some())
+            })
+            .collect();
+        let i = key_index(&columns, key)?;
+        let keys = cells
+            .iter()
+            .map(|row| row.get(i).filter(|cell| !cell.is_empty()).cloned())
+            .collect();
+        let rows = cells
+            .iter()
+            .map(|row| {
+                row.iter()
+                    .zip(&numeric)
+                    .map(|(cell, &numeric)| match number(cell) {
+                        _ if cell.is_empty() => FileValue::Null,
+                        Some(_) if numeric => parse_numeric(cell.trim())
+                            .unwrap_or_else(|| FileValue::String(cell.clone())),
+                        _ => FileValue::String(cell.clone()),
+                    })
+                    .collect()
+            })
+            .collect();
+        JoinTable::new(columns, rows, keys)
+    }
+
+    /// Reads a JSON table: an array of objects, whose keys are the columns.
+    /// A row without one of the columns has NULL there.
+    pub fn from_json(reader: impl Read, key: &str) -> Result<Self> {
+        let objects: Vec<serde_json::Map<String, serde_json::Value>> =
+            serde_json::from_reader(reader).map_err(|err| {
+                ExecutorError::InvalidJoin(format!("expected an array of objects ({})", err))
+            })?;
+        let mut columns: Vec<String> = Vec::new();
+        for object in &objects {
+            for column in object.keys() {
+                if !columns.contains(column) {
+                    columns.push(column.clone());
+                }
+            }
+        }
+        let rows: Vec<Vec<FileValue>> = objects
+            .iter()
+            .map(|object| {
+                columns
+                    .iter()
+                    .map(|column| object.get(column).map_or(FileValue::Null, json_value))
+                    .collect()
+            })
+            .collect();
+        let i = key_index(&columns, key)?;
+        let keys = rows
+            .iter()
+            .map(|row| match &row[i] {
+                FileValue::Null => None,
+                value => Some(to_text(value)),
+            })
+            .collect();
+        JoinTable::new(columns, rows, keys)
+    }
+
+    /// Indexes the rows by their keys, checking that no key repeats. Rows
+    /// without a key can't be matched.
+    fn new(
+        columns: Vec<String>,
+        rows: Vec<Vec<FileValue>>,
+        keys: Vec<Option<String>>,
+    ) -> Result<Self> {
+        let mut index = HashMap::new();
+        for (i, key) in keys.into_iter().enumerate() {
+            let Some(key) = key else {
+                continue;
+            };
+            if index.insert(key.clone(), i).is_some() {
+                return Err(ExecutorError::InvalidJoin(format!(
+                    "more than one row has '{}' as its key",
+                    key
+                )));
+            }
+        }
+        Ok(JoinTable {
+            columns,
+            rows,
+            index,
+        })
+    }
+
+    /// Returns the keys of the rows that have one, in the order of the rows.
+    pub fn keys(&self) -> Vec<&str> {
+        let mut keys: Vec<(usize, &str)> =
+            self.index.iter().map(|(key, &i)| (i, key.as_str())).collect();
+        keys.sort_unstable();
+        keys.into_iter().map(|(_, key)| key).collect()
+    }
+
+    /// Returns the column names, in order.
+    pub fn columns(&self) -> &[String] {
+        &self.columns
+    }
+
+    /// Returns the row whose key has this text, by column name.
+    pub fn row(&self, key: &str) -> Option<BTreeMap<String, FileValue>> {
+        let row = &self.rows[*self.index.get(key)?];
+        Some(
+            self.columns
+                .iter()
+                .cloned()
+                .zip(row.iter().cloned().chain(std::iter::repeat(FileValue::Null)))
+                .collect(),
+        )
+    }
+
+    /// Returns a row of NULLs, for a file a `LEFT JOIN` found no row for.
+    pub fn empty_row(&self) -> BTreeMap<String, FileValue> {
+        self.columns
+            .iter()
+            .map(|column| (column.clone(), FileValue::Null))
+            .collect()
+    }
+
+    /// Fails if the query uses a column the table doesn't have.
+    pub fn check_columns(&self, query: &FileQuery) -> Result<()> {
+        let mut used = Columns(Vec::new());
+        used.visit_query(query);
+        if let FileQuery::Select { order_by, .. } = query {
+            for order in order_by {
+                if let SortKey::Joined { column, .. } = &order.key {
+                    used.0.push(column);
+                }
+            }
+        }
+        match used.0.into_iter().find(|column| !self.columns.contains(column)) {
+            Some(column) => Err(ExecutorError::InvalidJoin(format!(
+                "there is no column '{}' (the columns are {})",
+                column,
+                self.columns.join(", ")
+            ))),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Returns the position of the key column.
+fn key_index(columns: &[String], key: &str) -> Result<usize> {
+    columns.iter().position(|column| column == key).ok_or_else(|| {
+        ExecutorError::InvalidJoin(format!(
+            "there is no column '{}' to join on (the columns are {})",
+            key,
+            columns.join(", ")
+        ))
+    })
+}
+
+/// Collects the joined columns a query reads.
+struct Columns<'a>(Vec<&'a String>);
+
+impl<'a> Visitor<'a> for Columns<'a> {
+    fn visit_expr(&mut self, expr: &'a FileExpr) {
+        if let FileExpr::Joined { column, .. } = expr {
+            self.0.push(column);
+        }
+        walk_expr(self, expr);
+    }
+}
+
+/// Reads a CSV cell as a number, if it is a finite one.
+fn number(cell: &str) -> Option<f64> {
+    cell.trim().parse::<f64>().ok().filter(|n| n.is_finite())
+}
+
+/// Converts a JSON value. Arrays and objects are kept as JSON text.
+fn json_value(value: &serde_json::Value) -> FileValue {
+    match value {
+        serde_json::Value::Null => FileValue::Null,
+        serde_json::Value::Bool(b) => FileValue::Boolean(*b),
+        serde_json::Value::Number(n) => match n.as_i64() {
+            Some(n) => FileValue::Integer(n),
+            None => n.as_f64().map_or(FileValue::Null, FileValue::Number),
+        },
+        serde_json::Value::String(s) => FileValue::String(s.clone()),
+        other => FileValue::String(other.to_string()),
+    }
+}
+
+/// Reports a malformed CSV file.
+fn csv_error(err: csv::Error) -> ExecutorError {
+    let message = err.to_string();
+    match err.into_kind() {
+        csv::ErrorKind::Io(err) => ExecutorError::IoError(err),
+        _ => ExecutorError::InvalidJoin(message),
+    }
+}
@@ -0,0 +1,570 @@
+//! Tokenization of SQL-like file management commands.
+//!
+//! This module turns query text into a stream of classified tokens with
+//! source spans. The parser consumes this stream, and it is also exposed
+//! publicly so editor plugins can implement syntax highlighting, linting,
+//! and completion without re-implementing fmql's lexical rules.
+//!
+//! Tokenization is built on the sqlparser tokenizer driven by
+//! [`FileDialect`]. On top of that, the path following `FROM`, `UPDATE`,
+//! `COPY`, `COMPRESS`, `EXTRACT`, `ARCHIVE`, `RESTORE`, `TO` or `INTO` is
+//! glued back into a single [`TokenKind::Path`] token, so `~/Documents` is
+//! reported as one token rather than `~`, `/` and `Documents`.
+//!
+//! # Examples
+//!
+//! ```
+//! use fmql_core::sql::lexer::{tokenize, TokenKind};
+//!
+//! let tokens = tokenize("SELECT * FROM ~/Documents WHERE size > 1000").unwrap();
+//!
+//! assert_eq!(tokens[0].kind, TokenKind::Keyword);
+//! assert_eq!(tokens[3].kind, TokenKind::Path);
+//! assert_eq!(tokens[3].text, "~/Documents");
+//! assert_eq!(tokens[5].kind, TokenKind::Attribute);
+//! ```
+
+use chrono::TimeDelta;
+use serde::Serialize;
+use std::time::Duration;
+use sqlparser::tokenizer::{Token as SqlToken, Tokenizer, Whitespace};
+
+use crate::sql::ast::{FileAttribute, FileValue};
+use crate::sql::dialect::FileDialect;
+use crate::sql::parser::{ParserError, Result};
+use crate::sql::views::view;
+
+/// Reserved words of the fmql query language.
+pub const KEYWORDS: &[&str] = &[
+    "SELECT",
+    "FROM",
+    "WHERE",
+    "UPDATE",
+    "SET",
+    "WITH",
+    "RECURSIVE",
+    "AND",
+    "OR",
+    "NOT",
+    "LIKE",
+    "BETWEEN",
+    "REGEXP",
+    "IS",
+    "TRUE",
+    "FALSE",
+    "NULL",
+    "CASE",
+    "WHEN",
+    "THEN",
+    "ELSE",
+    "END",
+    "ON",
+    "CONFLICT",
+    "SKIP",
+    "OVERWRITE",
+    "RENAME",
+    "FAIL",
+    "INCLUDE",
+    "SELF",
+    "FILES",
+    "DIRS",
+    "TYPE",
+    "AS",
+    "XOR",
+    "LIMIT",
+    "WITHIN",
+    "BUDGET",
+    "PER",
+    "SAMPLE",
+    "ROWS",
+    "WEIGHTED",
+    "SEED",
+    "ORDER",
+    "BY",
+    "ASC",
+    "DESC",
+    "NATURAL",
+    "NULLS",
+    "FIRST",
+    "LAST",
+    "DEPTH",
+    "COLLATE",
+    "GROUP",
+    "STDIN",
+    "TRASH",
+    "ROLLUP",
+    "JOIN",
+    "TABLE",
+    "LEFT",
+    "COPY",
+    "TO",
+    "USING",
+    "HARDLINK",
+    "REFLINK",
+    "AUTO",
+    "PRESERVE",
+    "SYNC",
+    "VERIFY",
+    "CHECKSUM",
+    "COMPRESS",
+    "GZIP",
+    "ZSTD",
+    "LEVEL",
+    "DELETE",
+    "ORIGINAL",
+    "EXTRACT",
+    "ARCHIVE",
+    "INTO",
+    "RESTORE",
+    "SHOW",
+    "FILESYSTEM",
+    "EXPLAIN",
+    "INTERVAL",
+];
+
+/// The lexical category of a token.
+///
+/// Categories are chosen to be useful for syntax highlighting: an editor can
+/// map each kind directly onto a highlight group.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TokenKind {
+    /// A reserved word such as `SELECT` or `WHERE`.
+    Keyword,
+    /// A known file attribute such as `size` or `modified`.
+    Attribute,
+    /// A name immediately followed by `(`, such as `REGEXP(`.
+    Function,
+    /// Any other bare or quoted identifier.
+    Identifier,
+    /// The target path of a `FROM`, `UPDATE`, `COPY` or `TO` clause.
+    Path,
+    /// A single-quoted string literal.
+    String,
+    /// A hex byte-string literal such as `x'7f454c46'`; the text is the
+    /// hex digits.
+    Bytes,
+    /// A numeric literal.
+    Number,
+    /// A comparison or arithmetic operator such as `>=` or `*`.
+    Operator,
+    /// Punctuation such as `(`, `)` and `,`.
+    Punctuation,
+    /// A `--` or `/* */` comment.
+    Comment,
+}
+
+/// A position in the query text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Position {
+    /// Line number, starting from 1.
+    pub line: u64,
+    /// Column number, starting from 1.
+    pub column: u64,
+}
+
+impl Position {
+    /// Converts the position into a byte offset within `text`.
+    ///
+    /// Positions past the end of the text map to `text.len()`.
+    pub fn offset_in(&self, text: &str) -> usize {
+        let mut offset = 0;
+        for (index, line) in text.split_inclusive('\n').enumerate() {
+            if index as u64 + 1 == self.line {
+                let column = self.column.saturating_sub(1) as usize;
+                return offset
+                    + line
+                        .char_indices()
+                        .nth(column)
+                        .map(|(i, _)| i)
+                        .unwrap_or(line.len());
+            }
+            offset += line.len();
+        }
+        text.len()
+    }
+}
+
+/// The source range covered by a token, from `start` (inclusive) to `end`
+/// (exclusive).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Span {
+    /// Where the token starts.
+    pub start: Position,
+    /// Where the token ends.
+    pub end: Position,
+}
+
+/// A classified token with its source span.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Token {
+    /// The lexical category of the token.
+    pub kind: TokenKind,
+    /// The token value. String literals are unquoted.
+    pub text: String,
+    /// Where the token appears in the query text.
+    pub span: Span,
+}
+
+impl Token {
+    /// Returns true if the token is the given keyword (case-insensitive).
+    pub fn is_keyword(&self, keyword: &str) -> bool {
+        self.kind == TokenKind::Keyword && self.text.eq_ignore_ascii_case(keyword)
+    }
+}
+
+/// Tokenizes a query into classified tokens with spans.
+///
+/// Whitespace is dropped; comments are kept so that editors can highlight
+/// them.
+///
+/// # Errors
+///
+/// Returns `ParserError::Sql` if the text cannot be tokenized, for example
+/// because of an unterminated string literal.
+pub fn tokenize(sql: &str) -> Result<Vec<Token>> {
+    let dialect = FileDialect::default();
+    let raw = Tokenizer::new(&dialect, sql)
+        .tokenize_with_location()
+        .map_err(|e| ParserError::Sql(e.into()))?;
+
+    let mut tokens: Vec<Token> = Vec::new();
+    let mut index = 0;
+
+    while index < raw.len() {
+        let current = &raw[index];
+        let span = convert_span(&current.span);
+
+        match &current.token {
+            SqlToken::EOF | SqlToken::Whitespace(Whitespace::Space) => {}
+            SqlToken::Whitespace(Whitespace::Newline | Whitespace::Tab) => {}
+            SqlToken::Whitespace(comment) => tokens.push(Token {
+                kind: TokenKind::Comment,
+                text: comment.to_string(),
+                span,
+            }),
+            token
+                if expects_path(&tokens)
+                    && !is_view_call(token, raw.get(index + 1))
+                    && !is_optional_from(&tokens, token) =>
+            {
+                // Glue everything up to the next whitespace into one path
+                let mut text = match token {
+                    SqlToken::SingleQuotedString(s) => s.clone(),
+                    other => other.to_string(),
+                };
+                let mut end = span.end;
+                while let Some(next) = raw.get(index + 1) {
+                    if matches!(next.token, SqlToken::Whitespace(_) | SqlToken::EOF)
+                        || matches!(token, SqlToken::SingleQuotedString(_))
+                    {
+                        break;
+                    }
+                    text.push_str(&next.token.to_string());
+                    end = convert_span(&next.span).end;
+                    index += 1;
+                }
+                // A bare STDIN or TRASH is the keyword; './stdin' or 'stdin'
+                // is a path
+                let (kind, text) = match token {
+                    SqlToken::Word(_) if text.eq_ignore_ascii_case("STDIN") => {
+                        (TokenKind::Keyword, "STDIN".to_string())
+                    }
+                    SqlToken::Word(_) if text.eq_ignore_ascii_case("TRASH") => {
+                        (TokenKind::Keyword, "TRASH".to_string())
+                    }
+                    _ => (TokenKind::Path, text),
+                };
+                tokens.push(Token {
+                    kind,
+                    text,
+                    span: Span {
+                        start: span.start,
+                        end,
+                    },
+                });
+            }
+            SqlToken::Number(zero, _) if zero == "0" && radix_suffix(raw.get(index + 1)).is_some() => {
+                // sqlparser splits 0o755 into `0` and `o755`; rejoin them
+                let suffix = radix_suffix(raw.get(index + 1)).expect("checked above");
+                tokens.push(Token {
+                    kind: TokenKind::Number,
+                    text: format!("0{}", suffix),
+                    span: Span {
+                        start: span.start,
+                        end: convert_span(&raw[index + 1].span).end,
+                    },
+                });
+                index += 1;
+            }
+            SqlToken::Word(word)
+                if word.quote_style.is_none()
+                    && word.value.eq_ignore_ascii_case("GROUP")
+                    && !starts_group_by(&raw[index + 1..]) =>
+            {
+                // `group` is the attribute unless it starts GROUP BY
+                tokens.push(Token {
+                    kind: TokenKind::Attribute,
+                    text: "group".to_string(),
+                    span,
+                });
+            }
+            token => {
+                let followed_by_paren =
+                    matches!(raw.get(index + 1).map(|t| &t.token), Some(SqlToken::LParen));
+                let (kind, text) = classify(token, followed_by_paren);
+                tokens.push(Token { kind, text, span });
+            }
+        }
+
+        index += 1;
+    }
+
+    Ok(tokens)
+}
+
+/// Returns true if the next token is the target path of FROM, UPDATE or
+/// COPY, or the destination after TO or INTO.
+fn expects_path(tokens: &[Token]) -> bool {
+    const BEFORE_PATHS: &[&str] =
+        &["FROM", "UPDATE", "COPY", "COMPRESS", "EXTRACT", "ARCHIVE", "RESTORE", "TO", "INTO"];
+    tokens
+        .iter()
+        .rev()
+        .find(|t| t.kind != TokenKind::Comment)
+        .is_some_and(|t| BEFORE_PATHS.iter().any(|k| t.is_keyword(k)))
+}
+
+/// Returns true if the tokens after a `GROUP` start with `BY`, so that it
+/// is the keyword rather than the `group` attribute.
+fn starts_group_by(rest: &[sqlparser::tokenizer::TokenWithSpan]) -> bool {
+    rest.iter()
+        .find(|t| !matches!(t.token, SqlToken::Whitespace(_)))
+        .is_some_and(|t| {
+            matches!(&t.token, SqlToken::Word(word) if word.value.eq_ignore_ascii_case("BY"))
+        })
+}
+
+/// Returns true if `token` is the optional FROM of `COMPRESS FROM <path>`,
+/// `EXTRACT FROM <path>`, `ARCHIVE FROM <path>` or `RESTORE FROM <path>`,
+/// rather than a path.
+fn is_optional_from(tokens: &[Token], token: &SqlToken) -> bool {
+    let from_optional = tokens
+        .iter()
+        .rev()
+        .find(|t| t.kind != TokenKind::Comment)
+        .is_some_and(|t| {
+            ["COMPRESS", "EXTRACT", "ARCHIVE", "RESTORE"].iter().any(|k| t.is_keyword(k))
+        });
+    from_optional && matches!(token, SqlToken::Word(word)
+        if word.quote_style.is_none() && word.value.eq_ignore_ascii_case("FROM"))
+}
+
+/// Returns true if `token` names a built-in view and is followed by its
+/// `(`, as in `FROM STATS('~/src')`, rather than starting a path.
+fn is_view_call(token: &SqlToken, next: Option<&sqlparser::tokenizer::TokenWithSpan>) -> bool {
+    let named = matches!(token, SqlToken::Word(word)
+        if word.quote_style.is_none() && view(&word.value).is_some());
+    named && matches!(next.map(|t| &t.token), Some(SqlToken::LParen))
+}
+
+/// Returns the word following a `0` if it forms a radix-prefixed literal
+/// such as `0o755` or `0x1f`.
+fn radix_suffix(token: Option<&sqlparser::tokenizer::TokenWithSpan>) -> Option<&str> {
+    match token.map(|t| &t.token) {
+        Some(SqlToken::Word(word)) if word.quote_style.is_none() => {
+            let mut chars = word.value.chars();
+            let valid = match chars.next() {
+                Some('o') => chars.all(|c| c.is_digit(8)),
+                Some('x') => chars.all(|c| c.is_ascii_hexdigit()),
+                Some('b') => chars.all(|c| c.is_digit(2)),
+                _ => false,
+            };
+            (valid && word.value.len() > 1).then_some(word.value.as_str())
+        }
+        _ => None,
+    }
+}
+
+/// Parses a numeric literal as a value: a whole number such as `1024` or
+/// `0o755` is an exact [`FileValue::Integer`], and anything else, such as
+/// `1.5` or `1e9`, a [`FileValue::Number`].
+pub fn parse_numeric(text: &str) -> Option<FileValue> {
+    let radix = match text.get(..2) {
+        Some("0o") => 8,
+        Some("0x") => 16,
+        Some("0b") => 2,
+        _ => match text.parse::<i64>() {
+            Ok(n) => return Some(FileValue::Integer(n)),
+            Err(_) => return text.parse::<f64>().ok().map(FileValue::Number),
+        },
+    };
+    i64::from_str_radix(&text[2..], radix).ok().map(FileValue::Integer)
+}
+
+/// Parses a numeric literal, including `0o`, `0x` and `0b` prefixed integers.
+pub fn parse_number(text: &str) -> Option<f64> {
+    let radix = match text.get(..2) {
+        Some("0o") => 8,
+        Some("0x") => 16,
+        Some("0b") => 2,
+        _ => return text.parse::<f64>().ok(),
+    };
+    u64::from_str_radix(&text[2..], radix).ok().map(|n| n as f64)
+}
+
+/// Parses a duration such as `10s`, `500ms`, `2m`, `1h`, `30d` or `1y`,
+/// or several added up, as in `2h30m` or `1 day 6 hours`.
+///
+/// A bare number is taken as seconds. Calendar units are approximate: a
+/// week is 7 days, a month (`mo`) 30 days and a year 365 days.
+///
+/// # Examples
+///
+/// ```
+/// use fmql_core::sql::lexer::parse_duration;
+/// use std::time::Duration;
+///
+/// assert_eq!(parse_duration("10s"), Ok(Duration::from_secs(10)));
+/// assert_eq!(parse_duration("1.5m"), Ok(Duration::from_secs(90)));
+/// assert_eq!(parse_duration("2w"), Ok(Duration::from_secs(14 * 86400)));
+/// assert_eq!(parse_duration("2h30m"), Ok(Duration::from_secs(9000)));
+/// assert_eq!(parse_duration("90 days"), Ok(Duration::from_secs(90 * 86400)));
+/// assert!(parse_duration("soon").is_err());
+/// ```
+pub fn parse_duration(text: &str) -> std::result::Result<Duration, String> {
+    let invalid = || format!("Invalid duration '{}'", text);
+    let mut rest = text.trim();
+    if rest.is_empty() {
+        return Err(invalid());
+    }
+    let mut seconds = 0.0;
+    while !rest.is_empty() {
+        let split = rest
+            .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+            .unwrap_or(rest.len());
+        let (number, tail) = rest.split_at(split);
+        let number: f64 = number.parse().map_err(|_| invalid())?;
+        let tail = tail.trim_start();
+        let split = tail
+            .find(|c: char| !c.is_ascii_alphabetic())
+            .unwrap_or(tail.len());
+        let (unit, tail) = tail.split_at(split);
+        let unit_seconds = match unit.to_lowercase().as_str() {
+            "ms" => 0.001,
+            "" | "s" | "sec" | "secs" | "second" | "seconds" => 1.0,
+            "m" | "min" | "mins" | "minute" | "minutes" => 60.0,
+            "h" | "hr" | "hrs" | "hour" | "hours" => 3600.0,
+            "d" | "day" | "days" => 86400.0,
+            "w" | "week" | "weeks" => 7.0 * 86400.0,
+            "mo" | "month" | "months" => 30.0 * 86400.0,
+            "y" | "yr" | "year" | "years" => 365.0 * 86400.0,
+            other => return Err(format!("Unknown unit '{}' in duration '{}'", other, text)),
+        };
+        seconds += number * unit_seconds;
+        rest = tail.trim_start_matches([' ', ',']);
+    }
+    Duration::try_from_secs_f64(seconds).map_err(|_| invalid())
+}
+
+/// Parses the text of an `INTERVAL` literal: a duration as
+/// [`parse_duration`] reads it, negative if it starts with `-`.
+///
+/// # Examples
+///
+/// ```
+/// use fmql_core::sql::lexer::parse_interval;
+/// use chrono::TimeDelta;
+///
+/// assert_eq!(parse_interval("90 days"), Ok(TimeDelta::days(90)));
+/// assert_eq!(parse_interval("-1h"), Ok(TimeDelta::hours(-1)));
+/// ```
+pub fn parse_interval(text: &str) -> std::result::Result<TimeDelta, String> {
+    let trimmed = text.trim();
+    let (negative, magnitude) = match trimmed.strip_prefix('-') {
+        Some(magnitude) => (true, magnitude),
+        None => (false, trimmed),
+    };
+    let delta = TimeDelta::from_std(parse_duration(magnitude)?)
+        .map_err(|_| format!("Interval '{}' is too long", text))?;
+    Ok(if negative { -delta } else { delta })
+}
+
+/// Writes a duration the way [`parse_interval`] reads it, in days, hours,
+/// minutes, seconds and milliseconds, leaving out those that are zero.
+///
+/// # Examples
+///
+/// ```
+/// use fmql_core::sql::lexer::format_interval;
+/// use chrono::TimeDelta;
+///
+/// assert_eq!(format_interval(TimeDelta::minutes(150)), "2h30m");
+/// assert_eq!(format_interval(TimeDelta::days(-90)), "-90d");
+/// assert_eq!(format_interval(TimeDelta::zero()), "0s");
+/// ```
+pub fn format_interval(delta: TimeDelta) -> String {
+    if delta < TimeDelta::zero() {
+        return format!("-{}", format_interval(delta.abs()));
+    }
+    let millis = delta.num_milliseconds();
+    let parts = [
+        (millis / 86_400_000, "d"),
+        (millis / 3_600_000 % 24, "h"),
+        (millis / 60_000 % 60, "m"),
+        (millis / 1000 % 60, "s"),
+        (millis % 1000, "ms"),
+    ];
+    let text: String = parts
+        .iter()
+        .filter(|(count, _)| *count != 0)
+        .map(|(count, unit)| format!("{}{}", count, unit))
+        .collect();
+    if text.is_empty() { "0s".to_string() } else { text }
+}
+
+/// Classifies a single sqlparser token.
+fn classify(token: &SqlToken, followed_by_paren: bool) -> (TokenKind, String) {
+    match token {
+        SqlToken::Word(word) if word.quote_style.is_some() => {
+            (TokenKind::Identifier, word.value.clone())
+        }
+        SqlToken::Word(word) if followed_by_paren => (TokenKind::Function, word.value.clone()),
+        SqlToken::Word(word) => {
+            let upper = word.value.to_uppercase();
+            if KEYWORDS.contains(&upper.as_str()) {
+                (TokenKind::Keyword, upper)
+            } else if FileAttribute::from_name(&word.value).is_some() {
+                (TokenKind::Attribute, word.value.to_lowercase())
+            } else {
+                (TokenKind::Identifier, word.value.clone())
+            }
+        }
+        SqlToken::SingleQuotedString(s)
+        | SqlToken::DoubleQuotedString(s)
+        | SqlToken::NationalStringLiteral(s)
+        | SqlToken::EscapedStringLiteral(s) => (TokenKind::String, s.clone()),
+        SqlToken::HexStringLiteral(digits) => (TokenKind::Bytes, digits.clone()),
+        SqlToken::Number(n, _) => (TokenKind::Number, n.clone()),
+        SqlToken::LParen
+        | SqlToken::RParen
+        | SqlToken::Comma
+        | SqlToken::SemiColon
+        | SqlToken::Period => (TokenKind::Punctuation, token.to_string()),
+        other => (TokenKind::Operator, other.to_string()),
+    }
+}
+
+/// Converts a sqlparser span into an fmql span.
+fn convert_span(span: &sqlparser::tokenizer::Span) -> Span {
+    Span {
+        start: Position {
+            line: span.start.line,
+            column: span.start.column,
+        },
+        end: Position {
+            line: span.end.line,
+            column: span.end.column,
+        },
+    }
+}
@@ -0,0 +1,143 @@
+//! Warnings about queries that are valid but probably not what was meant.
+//!
+//! Linting looks only at the parsed query, never at the file system, so it
+//! is cheap enough to run before every query. `fmql sql` prints the warnings
+//! to stderr before running the query, and `fmql check` reports them
+//! alongside the parse result.
+//!
+//! # Examples
+//!
+//! ```
+//! use fmql_core::sql::{lint::lint, parse_sql};
+//!
+//! let query = parse_sql("UPDATE . SET permissions = '644'").unwrap();
+//! let warnings = lint(&query);
+//! assert_eq!(warnings[0].code, "update_without_where");
+//! ```
+
+use serde::Serialize;
+use std::path::Path;
+
+use crate::sql::ast::{FileCondition, FileQuery};
+use crate::sql::visit::{walk_condition, Visitor};
+
+/// A warning about a query.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct LintWarning {
+    /// A stable, machine-readable name for the warning, such as
+    /// `update_without_where`.
+    pub code: &'static str,
+    /// A description of the problem for people.
+    pub message: String,
+}
+
+impl std::fmt::Display for LintWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} [{}]", self.message, self.code)
+    }
+}
+
+/// Checks a query for patterns that are legal but risky or slow.
+///
+/// The warnings are:
+/// - `update_without_where`: an UPDATE that changes every file under its path
+/// - `recursive_from_root`: a recursive query starting at `/`
+/// - `leading_wildcard`: a LIKE pattern starting with `%` in a recursive
+///   query, which must check every name in the tree
+pub fn lint(query: &FileQuery) -> Vec<LintWarning> {
+    let mut warnings = Vec::new();
+
+    let (path, recursive, condition) = match query {
+        FileQuery::Select {
+            path,
+            recursive,
+            condition,
+            ..
+        } => (path, *recursive, condition.as_ref()),
+        FileQuery::Update {
+            path, condition, ..
+        } => {
+            if condition.is_none() {
+                warnings.push(LintWarning {
+                    code: "update_without_where",
+                    message: format!(
+                        "UPDATE has no WHERE clause and will change every file under {}",
+                        path.display()
+                    ),
+                });
+            }
+            (path, true, condition.as_ref())
+        }
+        FileQuery::Copy {
+            path,
+            recursive,
+            condition,
+            ..
+        }
+        | FileQuery::Compress {
+            path,
+            recursive,
+            condition,
+            ..
+        }
+        | FileQuery::Extract {
+            path,
+            recursive,
+            condition,
+            ..
+        }
+        | FileQuery::Archive {
+            path,
+            recursive,
+            condition,
+            ..
+        }
+        | FileQuery::Restore {
+            path,
+            recursive,
+            condition,
+            ..
+        } => (path, *recursive, condition.as_ref()),
+        FileQuery::ShowFilesystem { .. } | FileQuery::Explain { .. } => return warnings,
+    };
+
+    if recursive && path == Path::new("/") {
+        warnings.push(LintWarning {
+            code: "recursive_from_root",
+            message: "Recursive query from / will scan the whole file system".to_string(),
+        });
+    }
+
+    if recursive && let Some(condition) = condition {
+        let mut patterns = LeadingWildcards(Vec::new());
+        patterns.visit_condition(condition);
+        for pattern in patterns.0 {
+            warnings.push(LintWarning {
+                code: "leading_wildcard",
+                message: format!(
+                    "LIKE '{}' starts with %, so every name in the tree must be checked; \
+                     consider ENDS_WITH or CONTAINS_STR",
+                    pattern
+                ),
+            });
+        }
+    }
+
+    warnings
+}
+
+/// Collects the distinct LIKE patterns in a condition that start with `%`.
+struct LeadingWildcards<'a>(Vec<&'a str>);
+
+impl<'a> Visitor<'a> for LeadingWildcards<'a> {
+    fn visit_condition(&mut self, condition: &'a FileCondition) {
+        match condition {
+            FileCondition::Like { pattern, .. }
+                if pattern.starts_with('%') && !self.0.contains(&pattern.as_str()) =>
+            {
+                self.0.push(pattern)
+            }
+            _ => walk_condition(self, condition),
+        }
+    }
+}
@@ -0,0 +1,237 @@
+//! Numbers and dates written the way a locale writes them, for the text,
+//! Markdown and HTML output people read and paste into documents.
+//!
+//! A [`Locale`] knows three things: the decimal separator, the separator
+//! between groups of thousands, and the order and separator of a date's
+//! day, month and year. `de_DE` writes `1.234.567,5` and `31.03.2025`,
+//! `en_US` writes `1,234,567.5` and `03/31/2025`, and the `C` locale, the
+//! default, writes `1234567.5` and `2025-03-31`, as fmql always has.
+//! Times stay on the 24-hour clock in every locale.
+//!
+//! [`Locale::from_env`] reads the locale the environment asks for, through
+//! `LC_ALL`, `LC_NUMERIC` and `LC_TIME`, and `LANG`, as other command-line
+//! tools do. CSV, JSON, YAML and TOML are for other programs, and are
+//! never localized.
+//!
+//! # Examples
+//!
+//! ```
+//! use chrono::{TimeZone, Utc};
+//! use fmql_core::sql::ast::FileValue;
+//! use fmql_core::sql::locale::Locale;
+//! use fmql_core::sql::timezone::Tz;
+//!
+//! let german: Locale = "de_DE.UTF-8".parse().unwrap();
+//! assert_eq!(german.format_integer(1234567), "1.234.567");
+//! assert_eq!(german.format_number(-1234.5), "-1.234,5");
+//!
+//! let time = Utc.with_ymd_and_hms(2025, 3, 31, 9, 15, 0).unwrap();
+//! let us: Locale = "en_US".parse().unwrap();
+//! assert_eq!(us.format_datetime(&time, Tz::UTC), "03/31/2025 09:15:00");
+//! assert_eq!(german.format_date(&time, Tz::UTC), "31.03.2025");
+//! assert_eq!(Locale::C.format_value(&FileValue::Integer(2048), Tz::UTC), "2048");
+//!
+//! assert!("xx_XX".parse::<Locale>().is_err());
+//! ```
+
+use std::str::FromStr;
+
+use chrono::{DateTime, Utc};
+
+use crate::sql::ast::FileValue;
+use crate::sql::functions::to_text_in;
+use crate::sql::timezone::Tz;
+
+/// The order a date's parts are written in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DateOrder {
+    /// `2025-03-31`, as ISO 8601 and East Asian locales write dates.
+    YearMonthDay,
+    /// `31/03/2025`, as most of Europe and the world does.
+    DayMonthYear,
+    /// `03/31/2025`, as the United States does.
+    MonthDayYear,
+}
+
+/// How a locale writes numbers and dates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Locale {
+    /// Separates the whole part of a number from its fraction.
+    decimal: char,
+    /// Separates groups of three digits, or nothing.
+    group: Option<char>,
+    /// The order of a date's parts.
+    date_order: DateOrder,
+    /// Separates a date's parts.
+    date_separator: char,
+}
+
+impl Default for Locale {
+    fn default() -> Self {
+        Locale::C
+    }
+}
+
+impl Locale {
+    /// The `C` and `POSIX` locale: no grouping, a decimal point and ISO
+    /// dates, which is what fmql writes unless told otherwise.
+    pub const C: Locale = Locale {
+        decimal: '.',
+        group: None,
+        date_order: DateOrder::YearMonthDay,
+        date_separator: '-',
+    };
+
+    /// Returns the locale the environment asks for: numbers as `LC_ALL`,
+    /// `LC_NUMERIC` or `LANG` say, the first that is set, and dates as
+    /// `LC_ALL`, `LC_TIME` or `LANG` say. A locale fmql doesn't know is
+    /// read as `C`.
+    pub fn from_env() -> Locale {
+        let category = |name| {
+            ["LC_ALL", name, "LANG"]
+                .iter()
+                .filter_map(|variable| std::env::var(variable).ok())
+                .find(|value| !value.is_empty())
+                .and_then(|value| value.parse::<Locale>().ok())
+                .unwrap_or(Locale::C)
+        };
+        let (numbers, dates) = (category("LC_NUMERIC"), category("LC_TIME"));
+        Locale {
+            date_order: dates.date_order,
+            date_separator: dates.date_separator,
+            ..numbers
+        }
+    }
+
+    /// Writes a whole number with the locale's thousands separator.
+    pub fn format_integer(&self, n: i64) -> String {
+        self.localize(&n.to_string())
+    }
+
+    /// Writes a number with the locale's separators, as many decimal
+    /// places as it needs and none for a whole number.
+    pub fn format_number(&self, n: f64) -> String {
+        if n.fract() == 0.0 && n.abs() < i64::MAX as f64 {
+            self.format_integer(n as i64)
+        } else {
+            self.localize(&n.to_string())
+        }
+    }
+
+    /// Writes a date and time as a local time in `zone`: the date in the
+    /// locale's order, then the time on the 24-hour clock.
+    pub fn format_datetime(&self, time: &DateTime<Utc>, zone: Tz) -> String {
+        let time = time.with_timezone(&zone);
+        format!("{} {}", self.write_date(&time), time.format("%H:%M:%S"))
+    }
+
+    /// Writes just the date of a time, in the locale's order, as a local
+    /// date in `zone`.
+    pub fn format_date(&self, time: &DateTime<Utc>, zone: Tz) -> String {
+        self.write_date(&time.with_timezone(&zone))
+    }
+
+    /// Renders a value as text the way [`to_text_in`] does, with its
+    /// numbers and dates written in the locale's way.
+    pub fn format_value(&self, value: &FileValue, zone: Tz) -> String {
+        match value {
+            FileValue::Integer(n) => self.format_integer(*n),
+            FileValue::Number(n) => self.format_number(*n),
+            FileValue::DateTime(time) => self.format_datetime(time, zone),
+            value => to_text_in(value, zone),
+        }
+    }
+
+    /// Swaps the separators of a number written the Rust way, `-1234.5`,
+    /// for the locale's.
+    pub(crate) fn localize(&self, number: &str) -> String {
+        let (sign, digits) = match number.strip_prefix('-') {
+            Some(digits) => ("-", digits),
+            None => ("", number),
+        };
+        let (whole, fraction) = match digits.split_once('.') {
+            Some((whole, fraction)) => (whole, Some(fraction)),
+            None => (digits, None),
+        };
+        let mut out = String::from(sign);
+        for (i, digit) in whole.chars().enumerate() {
+            if let Some(group) = self.group
+                && i > 0
+                && (whole.len() - i) % 3 == 0
+            {
+                out.push(group);
+            }
+            out.push(digit);
+        }
+        if let Some(fraction) = fraction {
+            out.push(self.decimal);
+            out.push_str(fraction);
+        }
+        out
+    }
+
+    fn write_date(&self, date: &DateTime<Tz>) -> String {
+        let format = match self.date_order {
+            DateOrder::YearMonthDay => "%Y{}%m{}%d",
+            DateOrder::DayMonthYear => "%d{}%m{}%Y",
+            DateOrder::MonthDayYear => "%m{}%d{}%Y",
+        };
+        let format = format.replace("{}", &self.date_separator.to_string());
+        date.format(&format).to_string()
+    }
+}
+
+impl FromStr for Locale {
+    type Err = String;
+
+    /// Reads a locale name as the environment writes them, `de_DE.UTF-8`
+    /// or `fr_CA`, or as a language tag, `pt-BR`. A language alone, such
+    /// as `de`, takes the conventions of the country it is mostly spoken
+    /// in.
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        let trimmed = name.trim();
+        let tag = trimmed.split(['.', '@']).next().unwrap_or_default();
+        if tag.eq_ignore_ascii_case("c") || tag.eq_ignore_ascii_case("posix") {
+            return Ok(Locale::C);
+        }
+        let (language, region) = match tag.split_once(['_', '-']) {
+            Some((language, region)) => (language, region),
+            None => (tag, ""),
+        };
+        let (language, region) = (language.to_ascii_lowercase(), region.to_ascii_uppercase());
+        let (decimal, group, date_order, date_separator) = conventions(&language, &region)
+            .ok_or_else(|| {
+                format!("Unknown locale '{}' (expected a name such as 'de_DE' or 'en_GB')", trimmed)
+            })?;
+        Ok(Locale { decimal, group: Some(group), date_order, date_separator })
+    }
+}
+
+/// Returns how a language, as spoken in a region, writes numbers and
+/// dates: the decimal separator, the thousands separator, and the order
+/// and separator of a date's parts.
+fn conventions(language: &str, region: &str) -> Option<(char, char, DateOrder, char)> {
+    use DateOrder::*;
+    const NBSP: char = '\u{a0}';
+    Some(match (language, region) {
+        ("en", "US" | "PH" | "") => ('.', ',', MonthDayYear, '/'),
+        ("en", "CA") => ('.', ',', YearMonthDay, '-'),
+        ("en", "ZA") => (',', NBSP, YearMonthDay, '/'),
+        ("en", _) => ('.', ',', DayMonthYear, '/'),
+        ("de" | "it", "CH" | "LI") => ('.', '\'', DayMonthYear, '.'),
+        ("fr", "CA") => (',', NBSP, YearMonthDay, '-'),
+        ("fr", "CH") => (',', NBSP, DayMonthYear, '.'),
+        ("fr", _) => (',', NBSP, DayMonthYear, '/'),
+        ("es" | "it" | "pt" | "el" | "ca" | "vi" | "id", _) => (',', '.', DayMonthYear, '/'),
+        ("de" | "da" | "tr" | "ro" | "hr" | "sl" | "sr", _) => (',', '.', DayMonthYear, '.'),
+        ("nl", _) => (',', '.', DayMonthYear, '-'),
+        ("ru" | "uk" | "bg" | "pl" | "cs" | "sk", _) => (',', NBSP, DayMonthYear, '.'),
+        ("fi" | "nb" | "nn" | "no" | "et" | "lv", _) => (',', NBSP, DayMonthYear, '.'),
+        ("sv" | "lt", _) => (',', NBSP, YearMonthDay, '-'),
+        ("hu", _) => (',', NBSP, YearMonthDay, '.'),
+        ("ja" | "zh", _) => ('.', ',', YearMonthDay, '/'),
+        ("ko", _) => ('.', ',', YearMonthDay, '.'),
+        ("he" | "th" | "hi" | "ms", _) => ('.', ',', DayMonthYear, '/'),
+        _ => return None,
+    })
+}
@@ -0,0 +1,422 @@
+//! Keeping concurrent runs that change files out of each other's way.
+//!
+//! Two batch jobs that UPDATE, COPY, COMPRESS, EXTRACT, ARCHIVE or RESTORE
+//! over the same tree can race: one renames a file the other is about to
+//! delete, or both pick the same free name. With [`Locking`] on, a run that
+//! changes files first takes an advisory lock on its roots, the paths its
+//! FROM names, and a run whose roots overlap another's (one holds the other,
+//! or they are the same) does what its [`LockMode`] says:
+//!
+//! - `wait` queues until the other run is done, and then lists its files,
+//!   so it sees what the other run left behind.
+//! - `fail` fails straight away, saying which process holds the lock.
+//! - `per-file` goes ahead alongside other `per-file` runs, each taking a
+//!   lock on each file just while it changes it. A run in another mode
+//!   doesn't take those, so a `per-file` run waits for it like `wait`.
+//!
+//! The locks are files in a directory of the user's own, by default in the
+//! runtime directory (`$XDG_RUNTIME_DIR/fmql-locks`), or else the temporary
+//! directory. Since the temporary directory is shared, a default directory
+//! that some other user made first, or that others may write to, is refused
+//! rather than trusted. Each run's file lists its roots and is locked with `flock` for
+//! as long as the run holds it, so a run that crashed leaves nothing held:
+//! its file is cleared away by the next run to look. The locks are only
+//! advisory; other programs, and fmql runs with locking off, aren't kept
+//! out. Dry runs change nothing and take no locks.
+//!
+//! # Examples
+//!
+//! ```
+//! use std::path::PathBuf;
+//!
+//! use fmql_core::sql::events::Events;
+//! use fmql_core::sql::locks::{LockMode, Locking};
+//!
+//! let dir = tempfile::tempdir().unwrap();
+//! let fail = Locking { mode: LockMode::Fail, dir: Some(dir.path().to_path_buf()), timeout: None };
+//!
+//! let held = fail.acquire(&[PathBuf::from("/srv/share")], &Events::default()).unwrap();
+//! assert!(held.is_some());
+//! // A tree inside the locked one overlaps it, a tree beside it doesn't
+//! assert!(fail.acquire(&[PathBuf::from("/srv/share/team")], &Events::default()).is_err());
+//! assert!(fail.acquire(&[PathBuf::from("/srv/other")], &Events::default()).is_ok());
+//!
+//! drop(held);
+//! assert!(fail.acquire(&[PathBuf::from("/srv/share/team")], &Events::default()).is_ok());
+//! ```
+
+use std::env;
+use std::ffi::OsStr;
+use std::fmt;
+use std::fs::{self, File, OpenOptions, TryLockError};
+use std::io::{self, Read, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{DirBuilderExt, MetadataExt};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::sql::events::{Event, Events};
+use crate::sql::executor::{ExecutorError, Result};
+use crate::sql::users::current_user;
+
+/// How often a queued run looks again whether it may go ahead.
+const POLL: Duration = Duration::from_millis(200);
+
+/// What a run that changes files does when another holds an overlapping
+/// root: see the [module documentation](self).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LockMode {
+    /// Take no locks, and heed none.
+    #[default]
+    None,
+    /// Queue until the other run is done.
+    Wait,
+    /// Fail straight away.
+    Fail,
+    /// Go ahead alongside other `per-file` runs, locking each file while
+    /// changing it.
+    PerFile,
+}
+
+impl std::str::FromStr for LockMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "none" => Ok(LockMode::None),
+            "wait" => Ok(LockMode::Wait),
+            "fail" => Ok(LockMode::Fail),
+            "per-file" => Ok(LockMode::PerFile),
+            _ => Err(format!(
+                "Unknown lock mode '{}' (expected none, wait, fail or per-file)",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for LockMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            LockMode::None => "none",
+            LockMode::Wait => "wait",
+            LockMode::Fail => "fail",
+            LockMode::PerFile => "per-file",
+        })
+    }
+}
+
+/// How runs that change files keep out of each other's way.
+#[derive(Debug, Clone, Default)]
+pub struct Locking {
+    /// What to do when another run holds an overlapping root.
+    pub mode: LockMode,
+    /// Where the lock files are kept; None means [`Locking::default_dir`].
+    pub dir: Option<PathBuf>,
+    /// How long a queued run waits before it fails; None waits for as long
+    /// as it takes.
+    pub timeout: Option<Duration>,
+}
+
+impl Locking {
+    /// Returns where lock files are kept by default: `fmql-locks` in the
+    /// runtime directory, or else a directory of the user's own in the
+    /// temporary directory.
+    pub fn default_dir() -> PathBuf {
+        match dirs::runtime_dir() {
+            Some(dir) => dir.join("fmql-locks"),
+            None => env::temp_dir().join(format!("fmql-locks-{}", current_user())),
+        }
+    }
+
+    /// Takes the lock on `roots` for a run that changes files, waiting or
+    /// failing as the mode says while another run holds an overlapping
+    /// one; returns None when locking is off. The lock is let go when the
+    /// [`RootLock`] is dropped.
+    ///
+    /// # Errors
+    ///
+    /// [`ExecutorError::Locked`] when the mode is `fail` and a run holds an
+    /// overlapping root, or a queued run has waited for longer than the
+    /// timeout; an I/O error if the lock files can't be written.
+    pub fn acquire(&self, roots: &[PathBuf], events: &Events) -> Result<Option<RootLock>> {
+        if self.mode == LockMode::None {
+            return Ok(None);
+        }
+        let dir = match &self.dir {
+            Some(dir) => {
+                fs::DirBuilder::new().recursive(true).mode(0o700).create(dir)?;
+                dir.clone()
+            }
+            None => private_dir(&Locking::default_dir())?,
+        };
+        let roots: Vec<PathBuf> = roots.iter().map(absolute).collect();
+        let started = Instant::now();
+        let mut warned = false;
+        loop {
+            // Looking and taking happen under the registry's lock, so two
+            // runs can't both find the way clear
+            let registry = open_lock(&dir.join("registry"))?;
+            registry.lock()?;
+            let Some(holder) = self.blocker(&dir, &roots)? else {
+                return Ok(Some(RootLock::take(&dir, &roots, self.mode)?));
+            };
+            drop(registry);
+            let held = format!(
+                "{} is being changed by fmql process {}",
+                holder.root.display(),
+                holder.pid
+            );
+            if self.mode == LockMode::Fail {
+                return Err(ExecutorError::Locked(held));
+            }
+            if let Some(timeout) = self.timeout
+                && started.elapsed() >= timeout
+            {
+                return Err(ExecutorError::Locked(format!(
+                    "{}, still after {:?}",
+                    held, timeout
+                )));
+            }
+            if !warned {
+                events.emit(|| Event::Warning {
+                    path: holder.root.clone(),
+                    message: format!("{}; waiting for it to finish", held),
+                });
+                warned = true;
+            }
+            thread::sleep(POLL);
+        }
+    }
+
+    /// Finds a run holding a root that overlaps one of `roots`, and that
+    /// this run may not go ahead alongside. Lock files no run holds are
+    /// cleared away.
+    fn blocker(&self, dir: &Path, roots: &[PathBuf]) -> Result<Option<Holder>> {
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.extension() != Some(OsStr::new("lock")) {
+                continue;
+            }
+            let file = match File::open(&path) {
+                Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
+                file => file?,
+            };
+            match file.try_lock_shared() {
+                // Nobody holds it: the run that wrote it is gone
+                Ok(()) => {
+                    let _ = fs::remove_file(&path);
+                    continue;
+                }
+                Err(TryLockError::WouldBlock) => {}
+                Err(TryLockError::Error(err)) => return Err(err.into()),
+            }
+            let holder = Holder::read(file)?;
+            if self.mode == LockMode::PerFile && holder.mode == LockMode::PerFile {
+                continue;
+            }
+            let overlap = holder.roots.iter().find(|held| {
+                roots.iter().any(|root| root.starts_with(held) || held.starts_with(root))
+            });
+            if let Some(root) = overlap {
+                return Ok(Some(Holder {
+                    root: root.clone(),
+                    ..holder
+                }));
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// Makes the directory `dir` of the current user's own if there is none,
+/// and returns it, failing unless the one there belongs to the user and
+/// nobody else may write to it. Anyone can make a directory by that name in
+/// the temporary directory first, to read or plant lock files.
+pub(crate) fn private_dir(dir: &Path) -> Result<PathBuf> {
+    fs::DirBuilder::new().recursive(true).mode(0o700).create(dir)?;
+    let metadata = dir.symlink_metadata()?;
+    // SAFETY: geteuid has no preconditions and can't fail
+    let user = unsafe { libc::geteuid() };
+    let problem = if !metadata.is_dir() {
+        "it isn't a directory"
+    } else if metadata.uid() != user {
+        "it belongs to another user"
+    } else if metadata.mode() & 0o022 != 0 {
+        "others may write to it"
+    } else {
+        return Ok(dir.to_path_buf());
+    };
+    Err(ExecutorError::IoError(io::Error::new(
+        io::ErrorKind::PermissionDenied,
+        format!("Cannot keep locks in {}: {}", dir.display(), problem),
+    )))
+}
+
+/// The run holding a lock file, as the file says.
+struct Holder {
+    pid: u32,
+    mode: LockMode,
+    roots: Vec<PathBuf>,
+    /// The root that overlaps the one looking, once one is found.
+    root: PathBuf,
+}
+
+impl Holder {
+    /// Reads a lock file: the process id and the mode on the first line,
+    /// then the roots, each ended by a NUL byte, since a path may hold any
+    /// other byte.
+    fn read(mut file: File) -> Result<Holder> {
+        let mut bytes = Vec::new();
+        file.read_to_end(&mut bytes)?;
+        let (head, roots) = bytes.split_at(bytes.iter().position(|&b| b == b'\n').unwrap_or(0));
+        let head = String::from_utf8_lossy(head);
+        let mut head = head.split_whitespace();
+        let pid = head.next().and_then(|pid| pid.parse().ok()).unwrap_or(0);
+        let mode = head.next().and_then(|mode| mode.parse().ok()).unwrap_or(LockMode::Wait);
+        let roots = roots
+            .get(1..)
+            .unwrap_or_default()
+            .split(|&b| b == 0)
+            .filter(|root| !root.is_empty())
+            .map(|root| PathBuf::from(OsStr::from_bytes(root)))
+            .collect();
+        Ok(Holder { pid, mode, roots, root: PathBuf::new() })
+    }
+}
+
+/// A run's lock on its roots, let go when dropped.
+#[derive(Debug)]
+pub struct RootLock {
+    /// The run's lock file.
+    path: PathBuf,
+    /// The lock file, open and locked.
+    file: File,
+    /// Where per-file locks are kept, when the run takes them.
+    files: Option<PathBuf>,
+}
+
+impl RootLock {
+    /// Writes and locks a lock file for `roots`.
+    fn take(dir: &Path, roots: &[PathBuf], mode: LockMode) -> Result<RootLock> {
+        static RUNS: AtomicUsize = AtomicUsize::new(0);
+
+        let pid = std::process::id();
+        let run = RUNS.fetch_add(1, Ordering::Relaxed);
+        let path = dir.join(format!("{}-{}.lock", pid, run));
+        let mut file = open_lock(&path)?;
+        file.lock()?;
+        file.set_len(0)?;
+        let mut text = format!("{} {}\n", pid, mode).into_bytes();
+        for root in roots {
+            text.extend_from_slice(root.as_os_str().as_bytes());
+            text.push(0);
+        }
+        file.write_all(&text)?;
+        let files = match mode {
+            LockMode::PerFile => {
+                let files = dir.join("files");
+                fs::DirBuilder::new().recursive(true).mode(0o700).create(&files)?;
+                Some(files)
+            }
+            _ => None,
+        };
+        Ok(RootLock { path, file, files })
+    }
+
+    /// Locks each of `paths` while the run changes it, in order of path so
+    /// that two runs never each wait for the other, when the run takes
+    /// per-file locks; otherwise holds nothing. The locks are let go when
+    /// the [`FileLocks`] are dropped.
+    pub fn hold<'a>(&self, paths: impl IntoIterator<Item = &'a Path>) -> io::Result<FileLocks> {
+        let Some(files) = &self.files else {
+            return Ok(FileLocks { _held: Vec::new() });
+        };
+        let mut paths: Vec<PathBuf> = paths.into_iter().map(absolute).collect();
+        paths.sort();
+        paths.dedup();
+        let mut held = Vec::with_capacity(paths.len());
+        for path in paths {
+            let name = blake3::hash(path.as_os_str().as_bytes()).to_hex();
+            held.push(FileLock::take(files.join(format!("{}.lock", &name[..32])))?);
+        }
+        Ok(FileLocks { _held: held })
+    }
+}
+
+impl Drop for RootLock {
+    fn drop(&mut self) {
+        // Removed before it is let go, so nobody takes a lock on a file
+        // that is about to go
+        let _ = fs::remove_file(&self.path);
+        let _ = self.file.unlock();
+    }
+}
+
+/// The per-file locks a run holds while it changes some files, let go
+/// when dropped.
+#[derive(Debug)]
+pub struct FileLocks {
+    /// Kept only to be dropped.
+    _held: Vec<FileLock>,
+}
+
+/// A lock on one file being changed.
+#[derive(Debug)]
+struct FileLock {
+    path: PathBuf,
+    file: File,
+}
+
+impl FileLock {
+    /// Locks the lock file at `path`, waiting for whoever holds it. A lock
+    /// file can be removed by its holder between being opened and being
+    /// locked here, so the lock is only taken once it is on the file still
+    /// at `path`.
+    fn take(path: PathBuf) -> io::Result<FileLock> {
+        loop {
+            let file = open_lock(&path)?;
+            file.lock()?;
+            let current = match fs::metadata(&path) {
+                Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
+                metadata => metadata?,
+            };
+            let opened = file.metadata()?;
+            if (current.dev(), current.ino()) == (opened.dev(), opened.ino()) {
+                return Ok(FileLock { path, file });
+            }
+        }
+    }
+}
+
+impl Drop for FileLock {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+        let _ = self.file.unlock();
+    }
+}
+
+/// Opens a lock file, making it if it isn't there, readable only by the
+/// user.
+fn open_lock(path: &Path) -> io::Result<File> {
+    use std::os::unix::fs::OpenOptionsExt;
+
+    OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .mode(0o600)
+        .open(path)
+}
+
+/// Returns a path as it is from the root, resolving symlinks when it can,
+/// so two names for one tree overlap.
+fn absolute(path: impl AsRef<Path>) -> PathBuf {
+    let path = path.as_ref();
+    fs::canonicalize(path)
+        .or_else(|_| std::path::absolute(path))
+        .unwrap_or_else(|_| path.to_path_buf())
+}
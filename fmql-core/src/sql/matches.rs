@@ -0,0 +1,143 @@
+//! Matched lines for content searches, for the `matches` attribute.
+//!
+//! A query that filters on `content` (with LIKE, REGEXP or CONTAINS_STR) can
+//! also select `matches` to get the lines that matched, with their line
+//! numbers, much like `grep -n`. Each predicate is applied to one line at a
+//! time: `content LIKE '%TODO%'` picks out the lines containing `TODO`.
+//! Predicates under a NOT find no lines, since they describe what a file
+//! lacks.
+//!
+//! # Examples
+//!
+//! ```
+//! use fmql_core::sql::ast::{FileAttribute, FileCondition};
+//! use fmql_core::sql::matches::LineMatcher;
+//!
+//! let condition = FileCondition::Regexp {
+//!     attribute: FileAttribute::Content,
+//!     pattern: "fn \\w+".to_string(),
+//! };
+//! let matcher = LineMatcher::from_condition(&condition).unwrap().unwrap();
+//! let found = matcher.find("use std::fs;\n\nfn main() {\n}\n");
+//! assert_eq!(found.len(), 1);
+//! assert_eq!((found[0].line, found[0].text.as_str()), (3, "fn main() {"));
+//! ```
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use regex::Regex;
+use serde::Serialize;
+
+use crate::sql::ast::{FileAttribute, FileCondition, SubstringPosition};
+use crate::sql::encoding::{read_text, TextEncoding};
+use crate::sql::executor::like_regex;
+use crate::sql::preview::sanitize;
+
+/// The most matched lines kept for one file.
+pub const MAX_MATCHES: usize = 10;
+
+/// The most characters of a matched line that are kept.
+pub const MAX_LINE_CHARS: usize = 200;
+
+/// One matched line.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct LineMatch {
+    /// The line number, counting from 1.
+    pub line: usize,
+    /// The line, cleaned up for display and cut to [`MAX_LINE_CHARS`].
+    pub text: String,
+}
+
+/// Finds the lines of a file that match a query's content predicates.
+#[derive(Debug, Clone)]
+pub struct LineMatcher {
+    tests: Vec<LineTest>,
+}
+
+/// One content predicate, applied to a line.
+#[derive(Debug, Clone)]
+enum LineTest {
+    Regex(Regex),
+    Substring(String),
+}
+
+impl LineMatcher {
+    /// Collects the content predicates of a WHERE clause, or returns None
+    /// if it has none.
+    pub fn from_condition(condition: &FileCondition) -> Result<Option<Self>, regex::Error> {
+        let mut tests = Vec::new();
+        collect_tests(condition, &mut tests)?;
+        Ok((!tests.is_empty()).then_some(LineMatcher { tests }))
+    }
+
+    /// Returns the lines of `text` that match any of the predicates, up to
+    /// [`MAX_MATCHES`].
+    pub fn find(&self, text: &str) -> Vec<LineMatch> {
+        text.lines()
+            .enumerate()
+            .filter(|(_, line)| {
+                self.tests.iter().any(|test| match test {
+                    LineTest::Regex(regex) => regex.is_match(line),
+                    LineTest::Substring(needle) => line.contains(needle.as_str()),
+                })
+            })
+            .take(MAX_MATCHES)
+            .map(|(index, line)| {
+                let mut text = sanitize(line);
+                if text.chars().count() > MAX_LINE_CHARS {
+                    text = text.chars().take(MAX_LINE_CHARS - 1).collect();
+                    text.push('…');
+                }
+                LineMatch {
+                    line: index + 1,
+                    text,
+                }
+            })
+            .collect()
+    }
+
+    /// Reads a file and returns its matching lines. Directories and other
+    /// non-files have none. With `decompress`, a compressed file's lines are
+    /// those of its contents, and the lines are read in `encoding`: see
+    /// [`read_text`].
+    pub fn read_matches(
+        &self,
+        path: &Path,
+        decompress: Option<u64>,
+        encoding: TextEncoding,
+    ) -> io::Result<Vec<LineMatch>> {
+        if !fs::metadata(path)?.is_file() {
+            return Ok(Vec::new());
+        }
+        Ok(self.find(&read_text(path, decompress, encoding)?))
+    }
+}
+
+/// Adds a line test for each content predicate that a match depends on.
+fn collect_tests(condition: &FileCondition, tests: &mut Vec<LineTest>) -> Result<(), regex::Error> {
+    match condition {
+        FileCondition::And(left, right) | FileCondition::Or(left, right) => {
+            collect_tests(left, tests)?;
+            collect_tests(right, tests)?;
+        }
+        FileCondition::Budget { condition, .. } => collect_tests(condition, tests)?,
+        FileCondition::Like {
+            attribute: FileAttribute::Content,
+            pattern,
+            case_sensitive,
+        } => tests.push(LineTest::Regex(like_regex(pattern, *case_sensitive)?)),
+        FileCondition::Regexp {
+            attribute: FileAttribute::Content,
+            pattern,
+        } => tests.push(LineTest::Regex(Regex::new(pattern)?)),
+        FileCondition::Substring {
+            attribute: FileAttribute::Content,
+            position: SubstringPosition::Anywhere,
+            text,
+        } => tests.push(LineTest::Substring(text.clone())),
+        _ => {}
+    }
+    Ok(())
+}
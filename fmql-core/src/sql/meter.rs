@@ -0,0 +1,152 @@
+//! Counting and pacing the bytes a query reads from file contents.
+//!
+//! A query that reads contents, for `content`, `CONTAINS_BYTES`, `preview`
+//! and the like, can read far more than it lists. A [`Meter`] is shared by
+//! all the reads of one query run, on every worker, and counts their bytes,
+//! so that [`ResourceLimits::max_bytes_read`] can stop the run once they
+//! pass it. With a [`Throttle`], it also holds them to its rate, together
+//! with whatever else of the run the throttle paces.
+//!
+//! The readers of file contents are [`Budgeted`] readers, which charge the
+//! meter of the file being read: [`metered`] sets it for the thread while
+//! a file is read, as [`spend`](crate::sql::budget::spend) sets a time
+//! budget, and reports whether the run went over.
+//!
+//! [`ResourceLimits::max_bytes_read`]: crate::sql::executor::ResourceLimits::max_bytes_read
+//! [`Budgeted`]: crate::sql::budget::Budgeted
+//!
+//! # Examples
+//!
+//! ```
+//! use std::io::Read;
+//! use std::sync::Arc;
+//! use fmql_core::sql::budget::Budgeted;
+//! use fmql_core::sql::meter::{metered, Meter};
+//!
+//! let meter = Arc::new(Meter::new(Some(4), None));
+//! let read = |text: &'static str| {
+//!     metered(&meter, || {
+//!         let mut read = String::new();
+//!         Budgeted::new(text.as_bytes()).read_to_string(&mut read).map(|_| read)
+//!     })
+//! };
+//! assert_eq!(read("abc").unwrap().unwrap(), "abc");
+//! assert_eq!(meter.read(), 3);
+//!
+//! // Once the reads pass the limit, the run is over it
+//! assert_eq!(read("de").unwrap_err().limit, 4);
+//! ```
+
+use std::cell::RefCell;
+use std::fmt;
+use std::io;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::sql::throttle::Throttle;
+
+thread_local! {
+    /// The meter of the file being read on this thread.
+    static METER: RefCell<Option<Arc<Meter>>> = const { RefCell::new(None) };
+}
+
+/// Counts the bytes the reads of one query run have read, and paces them.
+#[derive(Debug, Default)]
+pub struct Meter {
+    /// The most bytes the run may read, if there is a limit.
+    limit: Option<u64>,
+    /// The bytes read so far, by every worker.
+    read: AtomicU64,
+    /// Caps how fast the run reads.
+    throttle: Option<Throttle>,
+}
+
+/// The reads of a query run went over its limit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Overrun {
+    /// The most bytes the run could read.
+    pub limit: u64,
+}
+
+impl fmt::Display for Overrun {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "read more than {} bytes of file content", self.limit)
+    }
+}
+
+impl std::error::Error for Overrun {}
+
+impl Meter {
+    /// Creates a meter for a run that may read at most `limit` bytes, as
+    /// fast as `throttle` allows.
+    pub fn new(limit: Option<u64>, throttle: Option<Throttle>) -> Meter {
+        Meter {
+            limit,
+            read: AtomicU64::new(0),
+            throttle,
+        }
+    }
+
+    /// Returns how many bytes have been read.
+    pub fn read(&self) -> u64 {
+        self.read.load(Ordering::Relaxed)
+    }
+
+    /// Returns the throttle that paces the run, if there is one.
+    pub fn throttle(&self) -> Option<&Throttle> {
+        self.throttle.as_ref()
+    }
+
+    /// Returns the limit, if the reads have passed it.
+    fn overrun(&self) -> Option<Overrun> {
+        self.limit
+            .filter(|&limit| self.read() > limit)
+            .map(|limit| Overrun { limit })
+    }
+
+    /// Counts `bytes` as read, waiting if they put the run ahead of its
+    /// throttle, and fails once the reads pass the limit.
+    fn charge(&self, bytes: u64) -> io::Result<()> {
+        self.read.fetch_add(bytes, Ordering::Relaxed);
+        if let Some(throttle) = &self.throttle {
+            throttle.consume(bytes);
+        }
+        match self.overrun() {
+            Some(overrun) => Err(io::Error::other(overrun)),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Runs `read` with `meter` counting what it reads, and returns what it
+/// returns, or [`Overrun`] if the run's reads have passed their limit.
+pub fn metered<T>(meter: &Arc<Meter>, read: impl FnOnce() -> T) -> Result<T, Overrun> {
+    let value = {
+        let _set = MeterSet(METER.replace(Some(Arc::clone(meter))));
+        read()
+    };
+    match meter.overrun() {
+        Some(overrun) => Err(overrun),
+        None => Ok(value),
+    }
+}
+
+/// Puts back the meter that was set for the thread before [`metered`] set
+/// its own, when dropped, so a read that panics doesn't leave the thread
+/// charging a meter that is done.
+struct MeterSet(Option<Arc<Meter>>);
+
+impl Drop for MeterSet {
+    fn drop(&mut self) {
+        METER.set(self.0.take());
+    }
+}
+
+/// Counts `bytes` as read against the meter set for this thread, if any,
+/// waiting for its throttle; fails once the reads pass its limit.
+pub fn charge(bytes: usize) -> io::Result<()> {
+    METER.with_borrow(|meter| match meter {
+        Some(meter) => meter.charge(bytes as u64),
+        None => Ok(()),
+    })
+}
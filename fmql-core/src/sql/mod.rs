@@ -0,0 +1,241 @@
+//! SQL-like parsing and execution for file management operations.
+//!
+//! This module provides functionality to parse and execute SQL-like commands for managing
+//! files and directories. It uses the sqlparser crate to parse SQL syntax and adapts it to
+//! file system operations.
+//!
+//! # Components
+//! 
+//! - `dialect`: Defines a custom SQL dialect for file operations
+//! - `lexer`: Splits SQL strings into classified tokens with source spans
+//! - `parser`: Parses SQL strings into abstract syntax tree (AST) structures
+//! - `executor`: Executes the parsed queries against the file system
+//! - `ast`: Defines the abstract syntax tree data structures
+//! - `permissions`: Interprets symbolic and octal permission specs
+//! - `functions`: Scalar functions usable in SET expressions
+//! - `aggregate`: Aggregate functions such as SUM for grouped queries
+//! - `sort`: Orderings used by ORDER BY, including natural sorting
+//! - `throttle`: Rate limiting and I/O priority for heavy operations
+//! - `paths`: Validates query paths and suggests near misses
+//! - `lint`: Warns about risky or slow queries before they run
+//! - `catalog`: Lists the attributes, functions and keywords queries can use
+//! - `completion`: Completes partially typed queries for interactive use
+//! - `users`: Looks up the user and group names of file owners
+//! - `join`: Reads the CSV and JSON tables a query joins
+//! - `retention`: Deletes, archives or compresses files by age, following a policy file
+//! - `alert`: Thresholds on query results, for monitoring
+//! - `compressed`: Reads gzip, zstd and xz files through their decompressors, and writes them
+//! - `copy`: Makes the copies of `COPY`, by hardlink, reflink or byte copy
+//! - `extract`: Unpacks zip files and tarballs for `EXTRACT`, keeping them inside the destination
+//! - `archive`: Packs files into dated archives for `ARCHIVE`, checking each before it is kept
+//! - `renames`: Orders the renames of an UPDATE so that chains and swaps work
+//! - `space`: Adds up the space copies need and checks it against what is free
+//! - `script`: Splits scripts of several statements and validates them before they run
+//! - `capabilities`: Probes what a file system supports, for `SHOW FILESYSTEM`
+//! - `flags`: Reads and sets BSD file flags such as `uchg` and `hidden`
+//! - `chattr`: Reads and sets Linux file attributes such as `immutable` and `append`
+//! - `extents`: Tells how much of a file shares its blocks with reflinked copies
+//! - `selinux`: Reads SELinux security contexts
+//! - `filecaps`: Reads the capabilities `setcap` gives binaries
+//! - `quarantine`: Reads where macOS says a downloaded file came from
+//! - `timezone`: Reads date literals and writes dates in a chosen time zone
+//! - `sample`: Picks the entries of `SAMPLE n%` and the rows of `SAMPLE n ROWS`, and bounds the estimates made from a sample
+//! - `checkpoint`: Saves a long scan's progress so it can resume where it stopped
+//! - `find`: Translates `find` command lines into queries, for moving scripts over to fmql
+//! - `diff`: Compares two runs' results by path, for drift detection
+//! - `shard`: Splits a scan between processes and checks their results before merging
+//! - `views`: Built-in views such as `STATS(path)`, the per-extension statistics
+//! - `recent`: The recently modified files under a directory, once or as a live feed
+//! - `tags`: Keeps the tags of `fmql tag` and answers `TAGGED('name')`
+//! - `visit`: Walks and rewrites parsed queries, for analyses and transformations
+//! - `optimize`: Simplifies conditions before they run, and describes queries for `EXPLAIN`
+//! - `compat`: Semantics versions, for running queries as an older fmql did
+//! - `events`: Progress events a query sends as it runs, for GUIs that embed fmql
+//! - `protect`: Keeps ownership and permission changes away from system paths unless privileged
+//! - `faults`: Logs the files a condition couldn't be evaluated for, or fails on the first
+//! - `compiled`: Queries parsed and planned once, to run again on any directory
+//! - `retry`: Tries reads again after errors that may pass, as on network file systems
+//! - `intern`: Shares one copy of the extensions and owner names many results repeat
+//! - `display`: Shows file results as lines of text, with configurable dates, sizes and paths
+//! - `aliases`: Expands attribute aliases and `@snippets` in query text before it is parsed
+//! - `grammar`: The query language as an EBNF grammar, and queries generated from it
+//! - `locale`: Writes numbers and dates the way a locale does, for output people read
+//! - `budget`: Caps the time expensive predicates such as content scans take for each file
+//! - `encoding`: Reads file contents written in UTF-16 or Latin-1 as text
+//! - `pdf`: Reads the page tree, information and streams of PDF files
+//! - `docinfo`: The title, author and page count documents record about themselves
+//! - `newlines`: The line endings and byte order marks of text files
+//! - `executables`: The format, architecture and stripping of ELF, PE and Mach-O binaries
+//! - `images`: Unpacks the layers of container images so queries can read their files
+//! - `backups`: Lists the snapshots of restic and borg repositories so queries can read them
+//! - `android`: Lists the files of Android devices over adb so queries can read them
+//! - `trash`: Reads where trashed files came from, and puts them back, for `RESTORE`
+//! - `ignore`: Patterns for entries scans leave out, such as a profile's `node_modules`
+//! - `bench`: Times traversal, metadata reads and conditions on a file system, for `fmql bench`
+//! - `trace`: Records the entries a query sees, and replays queries on them, for bug reports
+//! - `reconcile`: Keeps the listed paths a query can't read, with `exists`, `was_moved_hint` and `metadata_error`
+//! - `interleave`: Writes the output of parallel workers whole, in order or as it comes
+//! - `locks`: Keeps concurrent runs that change files over overlapping roots out of each other's way
+//! - `meter`: Counts and paces the bytes a query reads from file contents, for `--max-bytes-read` and `--throttle`
+//! - `mount`: Serves query results as a read-only FUSE folder (`fuse` feature)
+//! - `sqlite`: Full SQL over the file scan in an embedded SQLite (`sqlite` feature)
+//! - `documents`: The text of PDFs and Word and Excel documents (`documents` feature)
+//!
+//! # Examples
+//!
+//! Querying files using SQL-like syntax:
+//!
+//! ```no_run
+//! use fmql_core::sql::{parse_sql, execute_query};
+//!
+//! // Find all text files in the current directory
+//! let query = parse_sql("SELECT * FROM . WHERE name LIKE '%.txt'").unwrap();
+//! let results = execute_query(&query).unwrap();
+//!
+//! // Display results
+//! for file in results {
+//!     println!("{}: {} bytes", file.name, file.size);
+//! }
+//! ```
+//!
+//! Using a more complex query with conditions:
+//!
+//! ```no_run
+//! use fmql_core::sql::{parse_sql, execute_query};
+//!
+//! // Find large image files, recursively
+//! let sql = "WITH RECURSIVE SELECT * FROM ~/Pictures WHERE \
+//!            (extension = 'jpg' OR extension = 'png') AND size > 1000000";
+//!            
+//! let query = parse_sql(sql).unwrap();
+//! let results = execute_query(&query).unwrap();
+//!
+//! println!("Found {} large image files", results.len());
+//! ```
+//!
+//! Updating file permissions:
+//!
+//! ```no_run
+//! use fmql_core::sql::{parse_sql, execute_query};
+//!
+//! // Make all shell scripts executable
+//! let sql = "UPDATE . SET permissions = '755' WHERE extension = 'sh'";
+//! let query = parse_sql(sql).unwrap();
+//! let updated_files = execute_query(&query).unwrap();
+//!
+//! println!("Updated permissions for {} files", updated_files.len());
+//! ```
+
+pub mod dialect;
+pub mod lexer;
+pub mod parser;
+pub mod executor;
+pub mod ast;
+pub mod catalog;
+pub mod lint;
+pub mod permissions;
+pub mod functions;
+pub mod aggregate;
+pub mod sort;
+pub mod throttle;
+pub mod paths;
+pub mod matches;
+pub mod signatures;
+pub mod preview;
+pub mod schema;
+pub mod completion;
+pub mod users;
+pub mod join;
+pub mod retention;
+pub mod alert;
+pub mod compressed;
+pub mod copy;
+pub mod extract;
+pub mod archive;
+pub mod renames;
+pub mod space;
+pub mod script;
+pub mod capabilities;
+pub mod flags;
+pub mod chattr;
+pub mod extents;
+pub mod selinux;
+pub mod filecaps;
+pub mod quarantine;
+pub mod timezone;
+pub mod sample;
+pub mod checkpoint;
+pub mod diff;
+pub mod find;
+pub mod shard;
+pub mod recent;
+pub mod tags;
+pub mod views;
+pub mod visit;
+pub mod optimize;
+pub mod compat;
+pub mod events;
+pub mod faults;
+pub mod protect;
+pub mod compiled;
+pub mod retry;
+pub mod intern;
+pub mod display;
+pub mod aliases;
+pub mod grammar;
+pub mod locale;
+pub mod budget;
+pub mod encoding;
+pub mod pdf;
+pub mod docinfo;
+pub mod newlines;
+pub mod executables;
+pub mod images;
+pub mod backups;
+pub mod android;
+pub mod trash;
+pub mod ignore;
+pub mod bench;
+pub mod trace;
+pub mod reconcile;
+pub mod interleave;
+pub mod locks;
+pub mod meter;
+#[cfg(feature = "fuse")]
+pub mod mount;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+#[cfg(feature = "documents")]
+pub mod documents;
+
+// Re-exports for convenience
+pub use lexer::tokenize;
+pub use parser::{parse_sql, parse_to_ast_json};
+pub use compiled::CompiledQuery;
+pub use executor::{
+    execute_grouped, execute_grouped_with_options, execute_query, execute_query_count,
+    execute_query_count_with_options, execute_query_first, execute_query_with_options,
+    execute_rollup, execute_rollup_with_options, exists, ExecutionOptions, QueryCursor,
+    RollupNode,
+};
+
+use crate::error::Result;
+use executor::FileResult;
+
+/// Parses and executes a query in one step.
+///
+/// Parse and execution failures are both reported as
+/// [`FMQLError`](crate::error::FMQLError).
+///
+/// # Examples
+///
+/// ```no_run
+/// use fmql_core::sql::{run, ExecutionOptions};
+///
+/// let results = run("SELECT * FROM . WHERE size > 1000", &ExecutionOptions::default()).unwrap();
+/// println!("{} large files", results.len());
+/// ```
+pub fn run(sql: &str, options: &ExecutionOptions) -> Result<Vec<FileResult>> {
+    let query = parse_sql(sql)?;
+    Ok(execute_query_with_options(&query, options)?)
+} 
\ No newline at end of file
@@ -0,0 +1,785 @@
+//! Query results as a folder, for `fmql mount` (`fuse` feature).
+//!
+//! [`mount`] serves a read-only FUSE directory whose entries are the files
+//! a SELECT finds, so that a file manager, an image viewer or a backup tool
+//! can browse the "folder" a query defines. By default each entry is a
+//! symlink to the file it stands for; with [`MountOptions::passthrough`]
+//! the files appear as regular files whose contents are read from the
+//! originals, for programs that don't follow symlinks. Directories in the
+//! results are always symlinks.
+//!
+//! The folder is live: the query runs again when the folder is listed or a
+//! name is looked up, once [`MountOptions::refresh`] has passed since it
+//! last ran. Files from different directories with the same name are told
+//! apart as `name (2).ext`, `name (3).ext` and so on, in result order.
+//!
+//! fmql speaks the FUSE protocol to the kernel itself, so nothing beyond
+//! the kernel's FUSE support is needed to run as root. Other users mount
+//! through `fusermount3` (or `fusermount`), which FUSE packages install
+//! setuid. [`mount`] serves requests until the folder is unmounted, or the
+//! process gets SIGINT or SIGTERM, when it unmounts the folder itself. A
+//! refresh that fails keeps the entries from before, and is sent to the
+//! query's [`Events`](crate::sql::events::Events) as a warning.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use std::path::Path;
+//!
+//! use fmql_core::sql::mount::{mount, MountOptions};
+//! use fmql_core::sql::{parse_sql, ExecutionOptions};
+//!
+//! let sql = "WITH RECURSIVE SELECT * FROM ~/Pictures WHERE extension = 'raw'";
+//! let query = parse_sql(sql).unwrap();
+//! let options = ExecutionOptions { read_only: true, ..ExecutionOptions::default() };
+//! mount(&query, &options, Path::new("/mnt/raw"), &MountOptions::default()).unwrap();
+//! ```
+
+use std::collections::{HashMap, HashSet};
+use std::ffi::{CString, OsStr, OsString};
+use std::fs::{self, File};
+use std::io;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{FileExt, MetadataExt};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::sql::ast::FileQuery;
+use crate::sql::events::Event;
+use crate::sql::executor::{
+    execute_query_with_options, ExecutionOptions, ExecutorError, FileResult, Result,
+};
+
+/// The FUSE protocol version spoken, 7.31.
+const KERNEL_MAJOR: u32 = 7;
+const KERNEL_MINOR: u32 = 31;
+
+/// The largest read the kernel is told to ask for.
+const MAX_READ: u32 = 128 * 1024;
+
+/// How long the kernel may cache names and attributes before asking again.
+const VALID_SECONDS: u64 = 1;
+
+/// The inode of the folder itself.
+const ROOT: u64 = 1;
+
+/// The request opcodes fmql answers.
+const LOOKUP: u32 = 1;
+const FORGET: u32 = 2;
+const GETATTR: u32 = 3;
+const READLINK: u32 = 5;
+const OPEN: u32 = 14;
+const READ: u32 = 15;
+const STATFS: u32 = 17;
+const RELEASE: u32 = 18;
+const INIT: u32 = 26;
+const OPENDIR: u32 = 27;
+const READDIR: u32 = 28;
+const RELEASEDIR: u32 = 29;
+const ACCESS: u32 = 34;
+const INTERRUPT: u32 = 36;
+const DESTROY: u32 = 38;
+const BATCH_FORGET: u32 = 42;
+
+/// The size of a request's header.
+const IN_HEADER: usize = 40;
+
+/// Set by SIGINT and SIGTERM, to unmount and stop.
+static STOP: AtomicBool = AtomicBool::new(false);
+
+/// A reply's payload, or the errno it fails with.
+type Reply = std::result::Result<Vec<u8>, i32>;
+
+/// How a query's results are shown.
+#[derive(Debug, Clone)]
+pub struct MountOptions {
+    /// Show files as regular files with the originals' contents, rather
+    /// than symlinks to them.
+    pub passthrough: bool,
+    /// How long the results stay current before the query runs again.
+    pub refresh: Duration,
+}
+
+impl Default for MountOptions {
+    fn default() -> Self {
+        MountOptions {
+            passthrough: false,
+            refresh: Duration::from_secs(5),
+        }
+    }
+}
+
+/// One entry of the folder.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    /// The entry's name in the folder.
+    pub name: OsString,
+    /// The file it stands for.
+    pub target: PathBuf,
+    /// Whether that file is a directory, which is always shown as a symlink.
+    pub is_directory: bool,
+}
+
+/// Names the entries for `results`: each file by its own name, with
+/// ` (2)`, ` (3)` and so on before the extension of later files whose name
+/// is taken.
+pub fn entries(results: &[FileResult]) -> Vec<Entry> {
+    let mut taken = HashSet::new();
+    let mut entries = Vec::with_capacity(results.len());
+    for result in results {
+        let name = result
+            .path
+            .file_name()
+            .map(OsStr::to_os_string)
+            .unwrap_or_else(|| OsString::from(&result.name));
+        let mut unique = name.clone();
+        let mut copy = 1;
+        while taken.contains(&unique) {
+            copy += 1;
+            let path = Path::new(&name);
+            unique = path.file_stem().unwrap_or(&name).to_os_string();
+            unique.push(format!(" ({})", copy));
+            if let Some(extension) = path.extension() {
+                unique.push(".");
+                unique.push(extension);
+            }
+        }
+        taken.insert(unique.clone());
+        entries.push(Entry {
+            name: unique,
+            target: result.path.clone(),
+            is_directory: result.is_directory,
+        });
+    }
+    entries
+}
+
+/// Shows the results of `query` as a read-only folder at `mountpoint`, and
+/// serves it until it is unmounted or the process is told to stop.
+pub fn mount(
+    query: &FileQuery,
+    options: &ExecutionOptions,
+    mountpoint: &Path,
+    mount_options: &MountOptions,
+) -> Result<()> {
+    if !matches!(query, FileQuery::Select { .. }) || query.is_grouped() || query.is_rollup() {
+        return Err(ExecutorError::UnsupportedOperation(
+            "a mounted folder needs a SELECT that lists files".to_string(),
+        ));
+    }
+    let options = ExecutionOptions {
+        read_only: true,
+        ..options.clone()
+    };
+    let mut view = View::new(query, &options, mountpoint, mount_options);
+    view.refresh()?;
+
+    let (device, unmount) = connect(mountpoint)?;
+    catch_signals();
+    let served = serve(&device, &mut view);
+    let unmounted = match served {
+        Ok(Served::Unmounted) => Ok(()),
+        _ => unmount.run(mountpoint),
+    };
+    served?;
+    unmounted.map_err(|err| {
+        let message = format!("can't unmount {}: {}", mountpoint.display(), err);
+        ExecutorError::from(io::Error::new(err.kind(), message))
+    })
+}
+
+/// Why serving stopped.
+enum Served {
+    /// The folder was unmounted from outside.
+    Unmounted,
+    /// The process was told to stop.
+    Stopped,
+}
+
+/// Answers requests from the kernel until the folder goes away.
+fn serve(device: &OwnedFd, view: &mut View) -> io::Result<Served> {
+    let mut buffer = vec![0u8; MAX_READ as usize + 4096];
+    loop {
+        if STOP.load(Ordering::Relaxed) {
+            return Ok(Served::Stopped);
+        }
+        // SAFETY: the buffer is valid for writes of its whole length.
+        let read = unsafe {
+            libc::read(device.as_raw_fd(), buffer.as_mut_ptr().cast(), buffer.len())
+        };
+        if read < 0 {
+            let err = io::Error::last_os_error();
+            match err.raw_os_error() {
+                // A signal, or a request the kernel took back
+                Some(libc::EINTR | libc::ENOENT | libc::EAGAIN) => continue,
+                Some(libc::ENODEV) => return Ok(Served::Unmounted),
+                _ => return Err(err),
+            }
+        }
+        let request = &buffer[..read as usize];
+        if request.len() < IN_HEADER {
+            continue;
+        }
+        let opcode = u32_at(request, 4);
+        let unique = u64_at(request, 8);
+        let node = u64_at(request, 16);
+        let body = &request[IN_HEADER..];
+        let reply = match opcode {
+            INIT => Some(init(body)),
+            _ => view.handle(opcode, node, body),
+        };
+        if let Some(reply) = reply {
+            send(device, unique, reply)?;
+        }
+    }
+}
+
+/// Writes the reply to a request.
+fn send(device: &OwnedFd, unique: u64, reply: Reply) -> io::Result<()> {
+    let (error, payload) = match reply {
+        Ok(payload) => (0, payload),
+        Err(errno) => (-errno, Vec::new()),
+    };
+    let mut message = Vec::with_capacity(16 + payload.len());
+    push_u32(&mut message, (16 + payload.len()) as u32);
+    push_i32(&mut message, error);
+    push_u64(&mut message, unique);
+    message.extend_from_slice(&payload);
+    // SAFETY: the message is valid for reads of its whole length.
+    let written =
+        unsafe { libc::write(device.as_raw_fd(), message.as_ptr().cast(), message.len()) };
+    if written < 0 {
+        let err = io::Error::last_os_error();
+        // The request was interrupted and the kernel no longer wants it
+        if err.raw_os_error() != Some(libc::ENOENT) {
+            return Err(err);
+        }
+    }
+    Ok(())
+}
+
+/// Answers the kernel's opening INIT with the protocol version and limits.
+fn init(body: &[u8]) -> Reply {
+    if body.len() < 16 || u32_at(body, 0) != KERNEL_MAJOR {
+        return Err(libc::EPROTO);
+    }
+    let minor = u32_at(body, 4).min(KERNEL_MINOR);
+    let mut reply = Vec::with_capacity(64);
+    push_u32(&mut reply, KERNEL_MAJOR);
+    push_u32(&mut reply, minor);
+    push_u32(&mut reply, u32_at(body, 8)); // max_readahead
+    push_u32(&mut reply, 0); // flags
+    push_u16(&mut reply, 16); // max_background
+    push_u16(&mut reply, 12); // congestion_threshold
+    push_u32(&mut reply, MAX_READ); // max_write
+    push_u32(&mut reply, 1); // time_gran
+    reply.resize(64, 0);
+    // Kernels before 7.23 expect the reply as it was then
+    if minor < 23 {
+        reply.truncate(24);
+    }
+    Ok(reply)
+}
+
+/// The folder's state: the entries of the last run of the query and the
+/// files opened through it.
+struct View<'a> {
+    query: &'a FileQuery,
+    options: &'a ExecutionOptions,
+    mountpoint: &'a Path,
+    mount_options: &'a MountOptions,
+    /// The entries, by inode.
+    entries: HashMap<u64, Entry>,
+    /// The inodes of the entries, in result order.
+    listing: Vec<u64>,
+    /// Inodes stay the same for the same name and file across runs.
+    inodes: HashMap<(OsString, PathBuf), u64>,
+    next_inode: u64,
+    refreshed: Option<Instant>,
+    handles: HashMap<u64, File>,
+    next_handle: u64,
+    mounted: SystemTime,
+}
+
+impl<'a> View<'a> {
+    fn new(
+        query: &'a FileQuery,
+        options: &'a ExecutionOptions,
+        mountpoint: &'a Path,
+        mount_options: &'a MountOptions,
+    ) -> Self {
+        View {
+            query,
+            options,
+            mountpoint,
+            mount_options,
+            entries: HashMap::new(),
+            listing: Vec::new(),
+            inodes: HashMap::new(),
+            next_inode: ROOT + 1,
+            refreshed: None,
+            handles: HashMap::new(),
+            next_handle: 1,
+            mounted: SystemTime::now(),
+        }
+    }
+
+    /// Runs the query and replaces the entries with its results.
+    fn refresh(&mut self) -> Result<()> {
+        let results = execute_query_with_options(self.query, self.options)?;
+        self.refreshed = Some(Instant::now());
+        self.entries.clear();
+        self.listing.clear();
+        for entry in entries(&results) {
+            let key = (entry.name.clone(), entry.target.clone());
+            let next_inode = &mut self.next_inode;
+            let inode = *self.inodes.entry(key).or_insert_with(|| {
+                *next_inode += 1;
+                *next_inode - 1
+            });
+            self.listing.push(inode);
+            self.entries.insert(inode, entry);
+        }
+        Ok(())
+    }
+
+    /// Runs the query again if its results are older than the refresh
+    /// interval. A run that fails keeps the previous entries, with a
+    /// warning.
+    fn refresh_if_stale(&mut self) {
+        if self.refreshed.is_some_and(|at| at.elapsed() < self.mount_options.refresh) {
+            return;
+        }
+        if let Err(err) = self.refresh() {
+            self.options.events.emit(|| Event::Warning {
+                path: self.mountpoint.to_path_buf(),
+                message: format!("can't refresh the folder: {}", err),
+            });
+            self.refreshed = Some(Instant::now());
+        }
+    }
+
+    /// Answers one request, or returns None for those that get no answer.
+    fn handle(&mut self, opcode: u32, node: u64, body: &[u8]) -> Option<Reply> {
+        let reply = match opcode {
+            FORGET | BATCH_FORGET | INTERRUPT => return None,
+            LOOKUP => self.lookup(node, body),
+            GETATTR => self.attr(node).map(|attr| {
+                let mut reply = Vec::with_capacity(104);
+                push_u64(&mut reply, VALID_SECONDS);
+                push_u32(&mut reply, 0);
+                push_u32(&mut reply, 0);
+                reply.extend_from_slice(&attr);
+                reply
+            }),
+            READLINK => match self.entries.get(&node) {
+                Some(entry) if self.is_symlink(entry) => {
+                    Ok(entry.target.as_os_str().as_bytes().to_vec())
+                }
+                _ => Err(libc::EINVAL),
+            },
+            OPEN => self.open(node, body),
+            READ => self.read(body),
+            RELEASE => {
+                self.handles.remove(&u64_at(body, 0));
+                Ok(Vec::new())
+            }
+            OPENDIR if node == ROOT => {
+                self.refresh_if_stale();
+                Ok(open_reply(0))
+            }
+            READDIR if node == ROOT => Ok(self.read_dir(body)),
+            OPENDIR | READDIR => Err(libc::ENOTDIR),
+            RELEASEDIR | ACCESS | DESTROY => Ok(Vec::new()),
+            STATFS => {
+                let mut reply = vec![0u8; 80];
+                reply[40..44].copy_from_slice(&4096u32.to_ne_bytes()); // bsize
+                reply[44..48].copy_from_slice(&255u32.to_ne_bytes()); // namelen
+                reply[48..52].copy_from_slice(&4096u32.to_ne_bytes()); // frsize
+                Ok(reply)
+            }
+            _ => Err(libc::ENOSYS),
+        };
+        Some(reply)
+    }
+
+    fn lookup(&mut self, parent: u64, body: &[u8]) -> Reply {
+        if parent != ROOT {
+            return Err(libc::ENOTDIR);
+        }
+        let name = OsStr::from_bytes(body.split(|&b| b == 0).next().unwrap_or_default());
+        self.refresh_if_stale();
+        let inode = self
+            .listing
+            .iter()
+            .copied()
+            .find(|inode| self.entries[inode].name == name)
+            .ok_or(libc::ENOENT)?;
+        let attr = self.attr(inode)?;
+        let mut reply = Vec::with_capacity(128);
+        push_u64(&mut reply, inode);
+        push_u64(&mut reply, 0); // generation
+        push_u64(&mut reply, VALID_SECONDS);
+        push_u64(&mut reply, VALID_SECONDS);
+        push_u32(&mut reply, 0);
+        push_u32(&mut reply, 0);
+        reply.extend_from_slice(&attr);
+        Ok(reply)
+    }
+
+    fn open(&mut self, node: u64, body: &[u8]) -> Reply {
+        let entry = self.entries.get(&node).ok_or(libc::ENOENT)?;
+        if self.is_symlink(entry) {
+            return Err(libc::ELOOP);
+        }
+        if u32_at(body, 0) as i32 & libc::O_ACCMODE != libc::O_RDONLY {
+            return Err(libc::EROFS);
+        }
+        let file = File::open(&entry.target).map_err(|err| errno(&err))?;
+        let handle = self.next_handle;
+        self.next_handle += 1;
+        self.handles.insert(handle, file);
+        Ok(open_reply(handle))
+    }
+
+    fn read(&mut self, body: &[u8]) -> Reply {
+        let file = self.handles.get(&u64_at(body, 0)).ok_or(libc::EBADF)?;
+        let offset = u64_at(body, 8);
+        let mut data = vec![0u8; u32_at(body, 16).min(MAX_READ) as usize];
+        let mut filled = 0;
+        while filled < data.len() {
+            match file.read_at(&mut data[filled..], offset + filled as u64) {
+                Ok(0) => break,
+                Ok(read) => filled += read,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => return Err(errno(&err)),
+            }
+        }
+        data.truncate(filled);
+        Ok(data)
+    }
+
+    /// Lists the folder from the entry after `offset`, as much as fits in
+    /// the size asked for.
+    fn read_dir(&self, body: &[u8]) -> Vec<u8> {
+        let offset = u64_at(body, 8) as usize;
+        let size = u32_at(body, 16) as usize;
+        let dots = [(ROOT, OsStr::new("."), libc::DT_DIR), (ROOT, OsStr::new(".."), libc::DT_DIR)];
+        let listed = self.listing.iter().map(|inode| {
+            let entry = &self.entries[inode];
+            let kind = if self.is_symlink(entry) { libc::DT_LNK } else { libc::DT_REG };
+            (*inode, entry.name.as_os_str(), kind)
+        });
+        let mut reply = Vec::new();
+        let all = dots.into_iter().chain(listed).enumerate().skip(offset);
+        for (index, (inode, name, kind)) in all {
+            let name = name.as_bytes();
+            let length = (24 + name.len()).next_multiple_of(8);
+            if reply.len() + length > size {
+                break;
+            }
+            push_u64(&mut reply, inode);
+            push_u64(&mut reply, index as u64 + 1); // the offset of the next entry
+            push_u32(&mut reply, name.len() as u32);
+            push_u32(&mut reply, u32::from(kind));
+            reply.extend_from_slice(name);
+            reply.resize(reply.len().next_multiple_of(8), 0);
+        }
+        reply
+    }
+
+    /// Returns true if the entry is shown as a symlink.
+    fn is_symlink(&self, entry: &Entry) -> bool {
+        !self.mount_options.passthrough || entry.is_directory
+    }
+
+    /// Returns the attributes of an inode, laid out as `fuse_attr`.
+    fn attr(&self, inode: u64) -> Reply {
+        // SAFETY: getuid and getgid can't fail.
+        let (uid, gid) = unsafe { (libc::getuid(), libc::getgid()) };
+        let mounted = seconds(self.mounted);
+        let attr = if inode == ROOT {
+            Attr {
+                inode,
+                mode: libc::S_IFDIR | 0o555,
+                nlink: 2,
+                uid,
+                gid,
+                times: [mounted; 3],
+                ..Attr::default()
+            }
+        } else {
+            let entry = self.entries.get(&inode).ok_or(libc::ENOENT)?;
+            let metadata = fs::metadata(&entry.target);
+            if self.is_symlink(entry) {
+                let modified = metadata.and_then(|m| m.modified()).map_or(mounted, seconds);
+                Attr {
+                    inode,
+                    size: entry.target.as_os_str().len() as u64,
+                    mode: libc::S_IFLNK | 0o777,
+                    nlink: 1,
+                    uid,
+                    gid,
+                    times: [modified; 3],
+                    ..Attr::default()
+                }
+            } else {
+                let metadata = metadata.map_err(|err| errno(&err))?;
+                Attr {
+                    inode,
+                    size: metadata.size(),
+                    blocks: metadata.blocks(),
+                    mode: libc::S_IFREG | (metadata.mode() & 0o555),
+                    nlink: 1,
+                    uid: metadata.uid(),
+                    gid: metadata.gid(),
+                    times: [
+                        (metadata.atime(), metadata.atime_nsec() as u32),
+                        (metadata.mtime(), metadata.mtime_nsec() as u32),
+                        (metadata.ctime(), metadata.ctime_nsec() as u32),
+                    ],
+                }
+            }
+        };
+        Ok(attr.encode())
+    }
+}
+
+/// The attributes the kernel is told about an inode.
+#[derive(Default)]
+struct Attr {
+    inode: u64,
+    size: u64,
+    blocks: u64,
+    mode: u32,
+    nlink: u32,
+    uid: u32,
+    gid: u32,
+    /// Access, modification and change times, in seconds and nanoseconds.
+    times: [(i64, u32); 3],
+}
+
+impl Attr {
+    /// Lays the attributes out as `fuse_attr`.
+    fn encode(&self) -> Vec<u8> {
+        let mut attr = Vec::with_capacity(88);
+        push_u64(&mut attr, self.inode);
+        push_u64(&mut attr, self.size);
+        push_u64(&mut attr, self.blocks);
+        for (seconds, _) in self.times {
+            push_u64(&mut attr, seconds as u64);
+        }
+        for (_, nanoseconds) in self.times {
+            push_u32(&mut attr, nanoseconds);
+        }
+        push_u32(&mut attr, self.mode);
+        push_u32(&mut attr, self.nlink);
+        push_u32(&mut attr, self.uid);
+        push_u32(&mut attr, self.gid);
+        push_u32(&mut attr, 0); // rdev
+        push_u32(&mut attr, 4096); // blksize
+        push_u32(&mut attr, 0); // flags
+        attr
+    }
+}
+
+/// Returns the errno of an error, for a reply.
+fn errno(err: &io::Error) -> i32 {
+    err.raw_os_error().unwrap_or(libc::EIO)
+}
+
+/// Returns an OPEN or OPENDIR reply with the handle.
+fn open_reply(handle: u64) -> Vec<u8> {
+    let mut reply = Vec::with_capacity(16);
+    push_u64(&mut reply, handle);
+    push_u64(&mut reply, 0); // open_flags and padding
+    reply
+}
+
+/// Returns a time as seconds and nanoseconds since the epoch.
+fn seconds(time: SystemTime) -> (i64, u32) {
+    time.duration_since(UNIX_EPOCH)
+        .map_or((0, 0), |since| (since.as_secs() as i64, since.subsec_nanos()))
+}
+
+fn u32_at(bytes: &[u8], at: usize) -> u32 {
+    bytes
+        .get(at..at + 4)
+        .map_or(0, |b| u32::from_ne_bytes(b.try_into().expect("four bytes")))
+}
+
+fn u64_at(bytes: &[u8], at: usize) -> u64 {
+    bytes
+        .get(at..at + 8)
+        .map_or(0, |b| u64::from_ne_bytes(b.try_into().expect("eight bytes")))
+}
+
+fn push_u16(bytes: &mut Vec<u8>, value: u16) {
+    bytes.extend_from_slice(&value.to_ne_bytes());
+}
+
+fn push_u32(bytes: &mut Vec<u8>, value: u32) {
+    bytes.extend_from_slice(&value.to_ne_bytes());
+}
+
+fn push_i32(bytes: &mut Vec<u8>, value: i32) {
+    bytes.extend_from_slice(&value.to_ne_bytes());
+}
+
+fn push_u64(bytes: &mut Vec<u8>, value: u64) {
+    bytes.extend_from_slice(&value.to_ne_bytes());
+}
+
+/// How to take the folder down again.
+enum Unmount {
+    /// It was mounted with mount(2), so umount(2) it.
+    Direct,
+    /// It was mounted by this fusermount program, which unmounts it too.
+    Fusermount(&'static str),
+}
+
+impl Unmount {
+    fn run(&self, mountpoint: &Path) -> io::Result<()> {
+        match self {
+            Unmount::Direct => CString::new(mountpoint.as_os_str().as_bytes())
+                .map_err(io::Error::from)
+                .and_then(|path| {
+                    // SAFETY: path is a valid NUL-terminated string.
+                    match unsafe { libc::umount2(path.as_ptr(), libc::MNT_DETACH) } {
+                        0 => Ok(()),
+                        _ => Err(io::Error::last_os_error()),
+                    }
+                }),
+            Unmount::Fusermount(program) => Command::new(program)
+                .args(["-u", "-z"])
+                .arg(mountpoint)
+                .status()
+                .and_then(|status| match status.success() {
+                    true => Ok(()),
+                    false => Err(io::Error::other(format!("{} -u failed", program))),
+                }),
+        }
+    }
+}
+
+/// Mounts an empty FUSE file system at `mountpoint` and returns the
+/// connection to the kernel that serves it.
+fn connect(mountpoint: &Path) -> Result<(OwnedFd, Unmount)> {
+    let target = CString::new(mountpoint.as_os_str().as_bytes()).map_err(io::Error::from)?;
+    // SAFETY: the path is a valid NUL-terminated string.
+    let fd = unsafe { libc::open(c"/dev/fuse".as_ptr(), libc::O_RDWR | libc::O_CLOEXEC) };
+    if fd >= 0 {
+        // SAFETY: open just returned this descriptor and nothing else owns it.
+        let device = unsafe { OwnedFd::from_raw_fd(fd) };
+        // SAFETY: getuid and getgid can't fail.
+        let (uid, gid) = unsafe { (libc::getuid(), libc::getgid()) };
+        let data = format!(
+            "fd={},rootmode=40000,user_id={},group_id={},default_permissions",
+            fd, uid, gid
+        );
+        let data = CString::new(data).map_err(io::Error::from)?;
+        // SAFETY: every string is valid and NUL-terminated.
+        let mounted = unsafe {
+            libc::mount(
+                c"fmql".as_ptr(),
+                target.as_ptr(),
+                c"fuse.fmql".as_ptr(),
+                libc::MS_NOSUID | libc::MS_NODEV | libc::MS_RDONLY,
+                data.as_ptr().cast(),
+            )
+        };
+        if mounted == 0 {
+            return Ok((device, Unmount::Direct));
+        }
+        let err = io::Error::last_os_error();
+        if !matches!(err.raw_os_error(), Some(libc::EPERM | libc::EACCES)) {
+            return Err(err.into());
+        }
+    }
+    fusermount(mountpoint)
+}
+
+/// Mounts through the setuid fusermount helper, which passes back the
+/// connection over a socket.
+fn fusermount(mountpoint: &Path) -> Result<(OwnedFd, Unmount)> {
+    let mut sockets: [RawFd; 2] = [-1; 2];
+    // SAFETY: sockets has room for the two descriptors.
+    if unsafe { libc::socketpair(libc::AF_UNIX, libc::SOCK_STREAM, 0, sockets.as_mut_ptr()) } != 0 {
+        return Err(io::Error::last_os_error().into());
+    }
+    // SAFETY: socketpair just returned these and nothing else owns them.
+    let (ours, theirs) =
+        unsafe { (OwnedFd::from_raw_fd(sockets[0]), OwnedFd::from_raw_fd(sockets[1])) };
+    for program in ["fusermount3", "fusermount"] {
+        let status = Command::new(program)
+            .args(["-o", "ro,nosuid,nodev,default_permissions,subtype=fmql", "--"])
+            .arg(mountpoint)
+            .env("_FUSE_COMMFD", theirs.as_raw_fd().to_string())
+            .status();
+        match status {
+            Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
+            Err(err) => return Err(err.into()),
+            Ok(status) if !status.success() => {
+                let err = io::Error::other(format!("{} couldn't mount the folder", program));
+                return Err(err.into());
+            }
+            Ok(_) => return Ok((receive_fd(&ours)?, Unmount::Fusermount(program))),
+        }
+    }
+    Err(ExecutorError::Denied(
+        "mounting needs root, or fusermount3 from the FUSE package".to_string(),
+    ))
+}
+
+/// Receives the descriptor fusermount sends over `socket`.
+fn receive_fd(socket: &OwnedFd) -> io::Result<OwnedFd> {
+    let mut byte = [0u8; 1];
+    let mut iov = libc::iovec {
+        iov_base: byte.as_mut_ptr().cast(),
+        iov_len: 1,
+    };
+    // Room for one descriptor, aligned for a cmsghdr
+    let mut control = [0u64; 8];
+    // SAFETY: an all-zero msghdr is valid, and the fields set point at
+    // buffers that outlive the call.
+    let mut message: libc::msghdr = unsafe { std::mem::zeroed() };
+    message.msg_iov = &mut iov;
+    message.msg_iovlen = 1;
+    message.msg_control = control.as_mut_ptr().cast();
+    message.msg_controllen = std::mem::size_of_val(&control) as _;
+    // SAFETY: message describes valid buffers.
+    if unsafe { libc::recvmsg(socket.as_raw_fd(), &mut message, 0) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // SAFETY: recvmsg filled in the control buffer message points at.
+    let header = unsafe { libc::CMSG_FIRSTHDR(&message) };
+    // SAFETY: a non-null header points into the control buffer.
+    if header.is_null() || unsafe { (*header).cmsg_type } != libc::SCM_RIGHTS {
+        return Err(io::Error::other("fusermount didn't pass back a connection"));
+    }
+    // SAFETY: an SCM_RIGHTS message carries a descriptor, which is now ours.
+    let fd = unsafe { std::ptr::read_unaligned(libc::CMSG_DATA(header).cast::<RawFd>()) };
+    // SAFETY: the descriptor was just received and nothing else owns it.
+    Ok(unsafe { OwnedFd::from_raw_fd(fd) })
+}
+
+/// Makes SIGINT and SIGTERM set [`STOP`] and interrupt the read that waits
+/// for requests, rather than end the process with the folder mounted.
+fn catch_signals() {
+    extern "C" fn stop(_: libc::c_int) {
+        STOP.store(true, Ordering::Relaxed);
+    }
+    for signal in [libc::SIGINT, libc::SIGTERM] {
+        // SAFETY: an all-zero sigaction with a handler and no SA_RESTART is
+        // valid, and the handler only stores to an atomic.
+        unsafe {
+            let mut action: libc::sigaction = std::mem::zeroed();
+            action.sa_sigaction = stop as *const () as libc::sighandler_t;
+            libc::sigemptyset(&mut action.sa_mask);
+            libc::sigaction(signal, &action, std::ptr::null_mut());
+        }
+    }
+}
@@ -0,0 +1,153 @@
+//! The line endings and byte order marks of text files, so that a
+//! repository can be audited for both with one query.
+//!
+//! `line_endings` is `lf` for the line endings of Unix, `crlf` for those
+//! of Windows, `cr` for those of classic Mac OS, and `mixed` for a file
+//! with more than one kind, which is usually the one worth fixing. A file
+//! with no line breaks at all, and a binary file, has NULL. UTF-16 files
+//! are read a character at a time, so the UTF-16 file Notepad writes is
+//! `crlf` like any other. `has_bom` is whether a file starts with a UTF-8
+//! or UTF-16 byte order mark.
+//!
+//! # Examples
+//!
+//! ```
+//! use fmql_core::sql::newlines::{has_bom, line_endings, LineEndings};
+//!
+//! assert_eq!(line_endings(b"one\ntwo\n"), Some(LineEndings::Lf));
+//! assert_eq!(line_endings(b"one\r\ntwo\n"), Some(LineEndings::Mixed));
+//! assert_eq!(line_endings(b"\xff\xfeo\0k\0\r\0\n\0"), Some(LineEndings::Crlf));
+//! assert_eq!(line_endings(b"no breaks"), None);
+//! assert_eq!(line_endings(b"\x7fELF\x02\x01\x01\0\n"), None);
+//!
+//! assert!(has_bom(b"\xef\xbb\xbfname,size\n"));
+//! assert!(!has_bom(b"name,size\n"));
+//! ```
+
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::Path;
+
+use crate::sql::budget::Budgeted;
+use crate::sql::encoding::{detect, TextEncoding};
+
+/// How much of the start of a file is looked at to tell text from binary
+/// and UTF-16 from the rest.
+const SAMPLE: usize = 4096;
+
+/// The kind of line endings a text file has.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineEndings {
+    /// `\n`, as Unix writes lines.
+    Lf,
+    /// `\r\n`, as Windows writes lines.
+    Crlf,
+    /// `\r` alone, as classic Mac OS wrote lines.
+    Cr,
+    /// More than one kind.
+    Mixed,
+}
+
+impl fmt::Display for LineEndings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            LineEndings::Lf => "lf",
+            LineEndings::Crlf => "crlf",
+            LineEndings::Cr => "cr",
+            LineEndings::Mixed => "mixed",
+        })
+    }
+}
+
+/// Returns the line endings of some text, or None if it has no line
+/// breaks or isn't text.
+pub fn line_endings(bytes: &[u8]) -> Option<LineEndings> {
+    let sample = &bytes[..bytes.len().min(SAMPLE)];
+    // Bytes already read can't fail to be read
+    scan(sample, bytes.iter().map(|&byte| Ok(byte))).ok().flatten()
+}
+
+/// Reads a file's line endings, stopping as soon as it finds a second
+/// kind.
+///
+/// # Errors
+///
+/// Fails if the file can't be read, such as when it is a directory.
+pub fn read_line_endings(path: &Path) -> io::Result<Option<LineEndings>> {
+    let file = Budgeted::new(File::open(path)?);
+    let mut reader = BufReader::with_capacity(64 * 1024, file);
+    let sample = reader.fill_buf()?;
+    let sample = sample[..sample.len().min(SAMPLE)].to_vec();
+    scan(&sample, reader.bytes())
+}
+
+/// Whether some bytes start with a UTF-8 or UTF-16 byte order mark.
+pub fn has_bom(bytes: &[u8]) -> bool {
+    [&b"\xef\xbb\xbf"[..], b"\xff\xfe", b"\xfe\xff"]
+        .iter()
+        .any(|bom| bytes.starts_with(bom))
+}
+
+/// Reads whether a file starts with a byte order mark.
+///
+/// # Errors
+///
+/// Fails if the file can't be read, such as when it is a directory.
+pub fn read_has_bom(path: &Path) -> io::Result<bool> {
+    let mut start = Vec::with_capacity(3);
+    File::open(path)?.take(3).read_to_end(&mut start)?;
+    Ok(has_bom(&start))
+}
+
+/// Finds the line endings of `bytes`, whose start is `sample`.
+fn scan(
+    sample: &[u8],
+    mut bytes: impl Iterator<Item = io::Result<u8>>,
+) -> io::Result<Option<LineEndings>> {
+    let little_endian = match detect(sample) {
+        TextEncoding::Utf16Le => Some(true),
+        TextEncoding::Utf16Be => Some(false),
+        // Text other than UTF-16 has no zero bytes
+        _ if sample.contains(&0) => return Ok(None),
+        _ => None,
+    };
+    let (mut lf, mut crlf, mut cr) = (false, false, false);
+    let mut after_cr = false;
+    loop {
+        let unit = match little_endian {
+            None => match bytes.next() {
+                Some(byte) => u16::from(byte?),
+                None => break,
+            },
+            Some(little_endian) => {
+                let (Some(first), Some(second)) = (bytes.next(), bytes.next()) else {
+                    break;
+                };
+                let pair = [first?, second?];
+                match little_endian {
+                    true => u16::from_le_bytes(pair),
+                    false => u16::from_be_bytes(pair),
+                }
+            }
+        };
+        match (after_cr, unit) {
+            (true, 0x0a) => crlf = true,
+            (true, _) => cr = true,
+            (false, 0x0a) => lf = true,
+            _ => {}
+        }
+        after_cr = unit == 0x0d;
+        if usize::from(lf) + usize::from(crlf) + usize::from(cr) > 1 {
+            return Ok(Some(LineEndings::Mixed));
+        }
+    }
+    cr |= after_cr;
+    Ok(match (lf, crlf, cr) {
+        (false, false, false) => None,
+        (true, false, false) => Some(LineEndings::Lf),
+        (false, true, false) => Some(LineEndings::Crlf),
+        (false, false, true) => Some(LineEndings::Cr),
+        _ => Some(LineEndings::Mixed),
+    })
+}
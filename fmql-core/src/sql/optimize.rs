@@ -0,0 +1,528 @@
+//! Simplifying queries before they run, and `EXPLAIN`.
+//!
+//! [`optimize`] rewrites a query's conditions into simpler ones that hold
+//! for the same files:
+//!
+//! - constants are folded: `x AND TRUE` is `x`, `x OR TRUE` is `TRUE`,
+//!   `NOT FALSE` is `TRUE`, and arithmetic on numbers such as
+//!   `1024 * 1024` is worked out once;
+//! - NOT is pushed down through AND and OR by De Morgan's laws, and
+//!   `NOT NOT x` is `x`. It stops at comparisons, since with NULLs and
+//!   values of other types `NOT a > b` and `a <= b` can disagree;
+//! - comparisons of one numeric attribute with numbers in a conjunction
+//!   are merged into the tightest range: `size > 10 AND size > 100` is
+//!   `size > 100`, and `size = 5 AND size < 10` is `size = 5`;
+//! - a conjunction that no file can satisfy, such as
+//!   `size > 100 AND size < 10`, or a BETWEEN whose bounds are the wrong
+//!   way round, is `FALSE`.
+//!
+//! The executor runs every query through it, and doesn't scan at all for a
+//! query whose WHERE clause comes out `FALSE`. Errors that a dropped
+//! predicate would have raised, such as comparing a number with text
+//! under `AND FALSE`, go with it.
+//!
+//! `EXPLAIN <statement>` shows the outcome without running anything; see
+//! [`explain`].
+//!
+//! # Examples
+//!
+//! ```
+//! use fmql_core::sql::optimize::simplify;
+//! use fmql_core::sql::parse_sql;
+//! use fmql_core::sql::ast::FileQuery;
+//!
+//! let sql = "SELECT * FROM . WHERE NOT (size <= 10 OR TRUE AND name = 'a') \
+//!            AND size > 100 AND size > 1024 * 1024";
+//! let FileQuery::Select { condition: Some(condition), .. } = parse_sql(sql).unwrap() else {
+//!     unreachable!()
+//! };
+//! assert_eq!(
+//!     simplify(condition).to_string(),
+//!     "NOT size <= 10 AND NOT name = 'a' AND size > 1048576"
+//! );
+//! ```
+
+use std::cmp::Ordering;
+
+use crate::sql::ast::{
+    ComparisonOperator, FileAttribute, FileCondition, FileExpr, FileQuery, FileValue,
+};
+use crate::sql::catalog::{column, ValueType};
+use crate::sql::executor::{apply_arithmetic, compare_values, GroupRow};
+use crate::sql::lexer::parse_numeric;
+use crate::sql::sort::compare_numbers;
+use crate::sql::timezone::Tz;
+use crate::sql::visit::{rewrite_condition, rewrite_expr, Rewriter};
+
+/// Simplifies a query's WHERE clause and the conditions and expressions
+/// in the rest of it; see the [module documentation](self).
+pub fn optimize(query: FileQuery) -> FileQuery {
+    Simplify.rewrite_query(query)
+}
+
+/// Simplifies a condition; see the [module documentation](self).
+pub fn simplify(condition: FileCondition) -> FileCondition {
+    Simplify.rewrite_condition(condition)
+}
+
+/// Returns true if no file can match the query, because its WHERE clause
+/// is `FALSE` once [`optimize`]d.
+pub fn never_matches(query: &FileQuery) -> bool {
+    let condition = match query {
+        FileQuery::Select { condition, .. }
+        | FileQuery::Update { condition, .. }
+        | FileQuery::Copy { condition, .. }
+        | FileQuery::Compress { condition, .. }
+        | FileQuery::Extract { condition, .. }
+        | FileQuery::Archive { condition, .. }
+        | FileQuery::Restore { condition, .. } => condition,
+        FileQuery::ShowFilesystem { .. } | FileQuery::Explain { .. } => return false,
+    };
+    matches!(condition, Some(FileCondition::Constant(false)))
+}
+
+/// Describes how a query would run, as rows of a `step` and its `detail`:
+/// the statement, the scan, and the WHERE clause as written and as
+/// simplified.
+pub fn explain(query: &FileQuery) -> Vec<GroupRow> {
+    let optimized = optimize(query.clone());
+    let (statement, path, recursive, condition) = match query {
+        FileQuery::Select {
+            path,
+            recursive,
+            condition,
+            ..
+        } => ("SELECT", path, *recursive, condition),
+        FileQuery::Update {
+            path, condition, ..
+        } => ("UPDATE", path, true, condition),
+        FileQuery::Copy {
+            path,
+            recursive,
+            condition,
+            ..
+        } => ("COPY", path, *recursive, condition),
+        FileQuery::Compress {
+            path,
+            recursive,
+            condition,
+            ..
+        } => ("COMPRESS", path, *recursive, condition),
+        FileQuery::Extract {
+            path,
+            recursive,
+            condition,
+            ..
+        } => ("EXTRACT", path, *recursive, condition),
+        FileQuery::Archive {
+            path,
+            recursive,
+            condition,
+            ..
+        } => ("ARCHIVE", path, *recursive, condition),
+        FileQuery::Restore {
+            path,
+            recursive,
+            condition,
+            ..
+        } => ("RESTORE", path, *recursive, condition),
+        FileQuery::ShowFilesystem { path } => ("SHOW FILESYSTEM", path, false, &None),
+        FileQuery::Explain { query } => return explain(query),
+    };
+
+    let scan = if never_matches(&optimized) {
+        "skipped: the WHERE clause can never hold".to_string()
+    } else if matches!(query, FileQuery::ShowFilesystem { .. }) {
+        format!("probe the file system of {}", path.display())
+    } else if query.reads_stdin() {
+        "the paths listed on stdin".to_string()
+    } else if query.reads_table() {
+        format!("the paths listed in the path column of {}", path.display())
+    } else if recursive {
+        format!("{} and everything below it", path.display())
+    } else {
+        format!("the entries of {}", path.display())
+    };
+    let mut steps = vec![("statement", statement.to_string()), ("scan", scan)];
+    if let Some(condition) = condition {
+        steps.push(("where", condition.to_string()));
+        let simplified = match &optimized {
+            FileQuery::Select { condition, .. }
+            | FileQuery::Update { condition, .. }
+            | FileQuery::Copy { condition, .. }
+            | FileQuery::Compress { condition, .. }
+            | FileQuery::Extract { condition, .. }
+            | FileQuery::Archive { condition, .. }
+            | FileQuery::Restore { condition, .. } => condition.as_ref(),
+            FileQuery::ShowFilesystem { .. } | FileQuery::Explain { .. } => None,
+        };
+        let simplified = simplified.map_or_else(|| "TRUE".to_string(), ToString::to_string);
+        steps.push(("simplified", simplified));
+    }
+    steps
+        .into_iter()
+        .map(|(step, detail)| GroupRow {
+            columns: vec![
+                ("step".to_string(), FileValue::String(step.to_string())),
+                ("detail".to_string(), FileValue::String(detail)),
+            ],
+        })
+        .collect()
+}
+
+/// The rewriter behind [`optimize`]. Each node's children are simplified
+/// before the node itself.
+struct Simplify;
+
+impl Rewriter for Simplify {
+    fn rewrite_where(&mut self, condition: Option<FileCondition>) -> Option<FileCondition> {
+        // A WHERE clause that always holds is no WHERE clause
+        match condition.map(|condition| self.rewrite_condition(condition)) {
+            Some(FileCondition::Constant(true)) | None => None,
+            condition => condition,
+        }
+    }
+
+    fn rewrite_condition(&mut self, condition: FileCondition) -> FileCondition {
+        match rewrite_condition(self, condition) {
+            FileCondition::Not(inner) => negate(*inner, self),
+            FileCondition::And(left, right) => conjunction(flatten_and(*left, *right)),
+            FileCondition::Or(left, right) => disjunction(flatten_or(*left, *right)),
+            FileCondition::Between { lower, upper, .. }
+                if matches!(compare_numbers(&lower, &upper), Some(Ordering::Greater)) =>
+            {
+                FileCondition::Constant(false)
+            }
+            condition @ FileCondition::Expression { .. } => comparison(condition),
+            // A constant costs nothing to evaluate, so its budget can go
+            FileCondition::Budget { condition, .. }
+                if matches!(*condition, FileCondition::Constant(_)) =>
+            {
+                *condition
+            }
+            condition => condition,
+        }
+    }
+
+    fn rewrite_expr(&mut self, expr: FileExpr) -> FileExpr {
+        match rewrite_expr(self, expr) {
+            FileExpr::Binary {
+                left,
+                operator,
+                right,
+            } => match (*left, *right) {
+                // Numbers in expressions are parsed as text, which the
+                // arithmetic reads as numbers
+                (FileExpr::Literal(left), FileExpr::Literal(right)) => {
+                    match apply_arithmetic(&left, operator, &right) {
+                    Ok(value) => FileExpr::Literal(value),
+                        Err(_) => FileExpr::Binary {
+                            left: Box::new(FileExpr::Literal(left)),
+                            operator,
+                            right: Box::new(FileExpr::Literal(right)),
+                        },
+                    }
+                }
+                (left, right) => FileExpr::Binary {
+                    left: Box::new(left),
+                    operator,
+                    right: Box::new(right),
+                },
+            },
+            expr => expr,
+        }
+    }
+}
+
+/// Simplifies `NOT condition`, where `condition` is simplified already.
+fn negate(condition: FileCondition, simplify: &mut Simplify) -> FileCondition {
+    match condition {
+        FileCondition::Constant(value) => FileCondition::Constant(!value),
+        FileCondition::Not(inner) => *inner,
+        FileCondition::And(left, right) => {
+            let left = simplify.rewrite_condition(FileCondition::Not(left));
+            let right = simplify.rewrite_condition(FileCondition::Not(right));
+            disjunction(flatten_or(left, right))
+        }
+        FileCondition::Or(left, right) => {
+            let left = simplify.rewrite_condition(FileCondition::Not(left));
+            let right = simplify.rewrite_condition(FileCondition::Not(right));
+            conjunction(flatten_and(left, right))
+        }
+        condition => FileCondition::Not(Box::new(condition)),
+    }
+}
+
+/// Lists the operands of a chain of ANDs, in order.
+fn flatten_and(left: FileCondition, right: FileCondition) -> Vec<FileCondition> {
+    let mut operands = Vec::new();
+    for side in [left, right] {
+        match side {
+            FileCondition::And(left, right) => operands.extend(flatten_and(*left, *right)),
+            side => operands.push(side),
+        }
+    }
+    operands
+}
+
+/// Lists the operands of a chain of ORs, in order.
+fn flatten_or(left: FileCondition, right: FileCondition) -> Vec<FileCondition> {
+    let mut operands = Vec::new();
+    for side in [left, right] {
+        match side {
+            FileCondition::Or(left, right) => operands.extend(flatten_or(*left, *right)),
+            side => operands.push(side),
+        }
+    }
+    operands
+}
+
+/// Builds the simplest AND of `operands`, each simplified already.
+fn conjunction(operands: Vec<FileCondition>) -> FileCondition {
+    let mut kept = Vec::new();
+    for operand in operands {
+        match operand {
+            FileCondition::Constant(true) => {}
+            FileCondition::Constant(false) => return FileCondition::Constant(false),
+            operand => kept.push(operand),
+        }
+    }
+    match merge_ranges(kept) {
+        Some(kept) => chain(kept, FileCondition::And).unwrap_or(FileCondition::Constant(true)),
+        None => FileCondition::Constant(false),
+    }
+}
+
+/// Builds the simplest OR of `operands`, each simplified already.
+fn disjunction(operands: Vec<FileCondition>) -> FileCondition {
+    let mut kept = Vec::new();
+    for operand in operands {
+        match operand {
+            FileCondition::Constant(false) => {}
+            FileCondition::Constant(true) => return FileCondition::Constant(true),
+            operand => kept.push(operand),
+        }
+    }
+    chain(kept, FileCondition::Or).unwrap_or(FileCondition::Constant(false))
+}
+
+/// Joins conditions left to right with AND or OR; None if there are none.
+fn chain(
+    operands: Vec<FileCondition>,
+    join: fn(Box<FileCondition>, Box<FileCondition>) -> FileCondition,
+) -> Option<FileCondition> {
+    operands
+        .into_iter()
+        .reduce(|left, right| join(Box::new(left), Box::new(right)))
+}
+
+/// The values a conjunction allows one attribute: between two bounds, each
+/// inclusive or not, or exactly one value.
+#[derive(Default)]
+struct Range {
+    lower: Option<(f64, bool)>,
+    upper: Option<(f64, bool)>,
+    equal: Option<f64>,
+    /// Two different values were required.
+    conflicting: bool,
+}
+
+impl Range {
+    fn narrow(&mut self, operator: ComparisonOperator, value: f64) {
+        match operator {
+            ComparisonOperator::Eq => {
+                if self.equal.is_some_and(|equal| equal != value) {
+                    self.conflicting = true;
+                }
+                self.equal = Some(value);
+            }
+            ComparisonOperator::Gt | ComparisonOperator::GtEq => {
+                let inclusive = operator == ComparisonOperator::GtEq;
+                let tighter = self.lower.is_none_or(|(lower, lower_inclusive)| {
+                    value > lower || (value == lower && lower_inclusive && !inclusive)
+                });
+                if tighter {
+                    self.lower = Some((value, inclusive));
+                }
+            }
+            ComparisonOperator::Lt | ComparisonOperator::LtEq => {
+                let inclusive = operator == ComparisonOperator::LtEq;
+                let tighter = self.upper.is_none_or(|(upper, upper_inclusive)| {
+                    value < upper || (value == upper && upper_inclusive && !inclusive)
+                });
+                if tighter {
+                    self.upper = Some((value, inclusive));
+                }
+            }
+            ComparisonOperator::NotEq => unreachable!("!= is not merged"),
+        }
+    }
+
+    /// Returns the comparisons that say the same as the range, or None if
+    /// no value is in it.
+    fn conditions(&self, attribute: &FileAttribute) -> Option<Vec<FileCondition>> {
+        let above = |value: f64| match self.lower {
+            Some((lower, inclusive)) => value > lower || (inclusive && value == lower),
+            None => true,
+        };
+        let below = |value: f64| match self.upper {
+            Some((upper, inclusive)) => value < upper || (inclusive && value == upper),
+            None => true,
+        };
+        let compare = |operator, value| FileCondition::Compare {
+            attribute: attribute.clone(),
+            operator,
+            value: number(value),
+        };
+        if self.conflicting {
+            return None;
+        }
+        if let Some(equal) = self.equal {
+            return (above(equal) && below(equal))
+                .then(|| vec![compare(ComparisonOperator::Eq, equal)]);
+        }
+        if let (Some((lower, _)), Some((upper, _))) = (self.lower, self.upper)
+            && (!above(upper) || !below(lower) || lower > upper)
+        {
+            return None;
+        }
+        let mut conditions = Vec::new();
+        if let Some((lower, inclusive)) = self.lower {
+            let operator = match inclusive {
+                true => ComparisonOperator::GtEq,
+                false => ComparisonOperator::Gt,
+            };
+            conditions.push(compare(operator, lower));
+        }
+        if let Some((upper, inclusive)) = self.upper {
+            let operator = match inclusive {
+                true => ComparisonOperator::LtEq,
+                false => ComparisonOperator::Lt,
+            };
+            conditions.push(compare(operator, upper));
+        }
+        Some(conditions)
+    }
+}
+
+/// Merges the comparisons of each numeric attribute with numbers into its
+/// range, kept where the first of them was. Returns None if some range is
+/// empty, so the conjunction can never hold.
+fn merge_ranges(operands: Vec<FileCondition>) -> Option<Vec<FileCondition>> {
+    let mut ranges: Vec<(FileAttribute, Range, usize)> = Vec::new();
+    let mut kept: Vec<Option<FileCondition>> = Vec::new();
+    for operand in operands {
+        match mergeable(&operand) {
+            Some((attribute, operator, value)) => {
+                match ranges.iter_mut().find(|(merged, ..)| merged == attribute) {
+                    Some((_, range, _)) => range.narrow(operator, value),
+                    None => {
+                        let mut range = Range::default();
+                        range.narrow(operator, value);
+                        ranges.push((attribute.clone(), range, kept.len()));
+                        kept.push(None);
+                    }
+                }
+            }
+            None => kept.push(Some(operand)),
+        }
+    }
+
+    let mut merged: Vec<Vec<FileCondition>> =
+        kept.into_iter().map(|kept| kept.into_iter().collect()).collect();
+    for (attribute, range, slot) in ranges {
+        merged[slot] = range.conditions(&attribute)?;
+    }
+    Some(merged.into_iter().flatten().collect())
+}
+
+/// Returns the parts of a comparison that [`merge_ranges`] can merge: a
+/// numeric attribute compared with a number by anything but `!=`. An
+/// integer too large to be a float exactly is left alone.
+fn mergeable(condition: &FileCondition) -> Option<(&FileAttribute, ComparisonOperator, f64)> {
+    let FileCondition::Compare {
+        attribute,
+        operator,
+        value,
+    } = condition
+    else {
+        return None;
+    };
+    let value = match *value {
+        FileValue::Number(n) => n,
+        FileValue::Integer(n) if n as f64 as i64 == n => n as f64,
+        _ => return None,
+    };
+    let numeric = column(attribute).is_some_and(|column| column.value_type == ValueType::Number);
+    (numeric && *operator != ComparisonOperator::NotEq && !value.is_nan())
+        .then(|| (attribute, operator.clone(), value))
+}
+
+/// Returns a merged bound as a value, whole numbers as integers.
+fn number(value: f64) -> FileValue {
+    match value.fract() == 0.0 && value.abs() < i64::MAX as f64 {
+        true => FileValue::Integer(value as i64),
+        false => FileValue::Number(value),
+    }
+}
+
+/// Simplifies a comparison of expressions, simplified already: two values
+/// compare once, and an attribute compared with a number needs no
+/// expression.
+fn comparison(condition: FileCondition) -> FileCondition {
+    let FileCondition::Expression {
+        left,
+        operator,
+        right,
+    } = condition
+    else {
+        return condition;
+    };
+    match (left, right) {
+        // Comparing text with a date reads it in the query's time zone
+        (FileExpr::Literal(left), FileExpr::Literal(right))
+            if !matches!(left, FileValue::DateTime(_))
+                && !matches!(right, FileValue::DateTime(_)) =>
+        {
+            match compare_values(&left, &operator, &right, Tz::UTC) {
+                Ok(holds) => FileCondition::Constant(holds),
+                // Left to fail when the query runs
+                Err(_) => FileCondition::Expression {
+                    left: FileExpr::Literal(left),
+                    operator,
+                    right: FileExpr::Literal(right),
+                },
+            }
+        }
+        (FileExpr::Attribute(attribute), FileExpr::Literal(value)) => {
+            let number = match &value {
+                FileValue::Integer(_) | FileValue::Number(_) => Some(value.clone()),
+                // Compared with a numeric attribute, numeric text is a number
+                FileValue::String(text)
+                    if column(&attribute)
+                        .is_some_and(|column| column.value_type == ValueType::Number) =>
+                {
+                    parse_numeric(text.trim())
+                }
+                _ => None,
+            };
+            match number {
+                Some(number) => FileCondition::Compare {
+                    attribute,
+                    operator,
+                    value: number,
+                },
+                None => FileCondition::Expression {
+                    left: FileExpr::Attribute(attribute),
+                    operator,
+                    right: FileExpr::Literal(value),
+                },
+            }
+        }
+        (left, right) => FileCondition::Expression {
+            left,
+            operator,
+            right,
+        },
+    }
+}
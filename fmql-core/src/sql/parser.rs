@@ -0,0 +1,2439 @@
+//! Parser for SQL-like file management commands.
+//!
+//! This module provides functionality to parse SQL-like statements into
+//! file management operations. It transforms text-based SQL queries into
+//! structured `FileQuery` objects that can be executed by the executor.
+//!
+//! # Supported SQL Syntax
+//!
+//! The parser supports a subset of SQL syntax specifically adapted for file operations:
+//!
+//! ## SELECT Queries
+//! ```sql
+//! -- Basic select
+//! SELECT * FROM /path/to/directory WHERE extension = 'txt'
+//!
+//! -- Recursive select (includes subdirectories)
+//! WITH RECURSIVE SELECT * FROM ~/Documents WHERE size > 1000000
+//!
+//! -- A single file, or every path matching a glob
+//! SELECT * FROM ./Cargo.toml
+//! WITH RECURSIVE SELECT * FROM '~/projects/*/target' WHERE size > 1000000
+//!
+//! -- List the FROM directory itself as well as its contents
+//! SELECT * FROM ~/Documents INCLUDE SELF
+//!
+//! -- Only files, only directories, or any one kind of entry
+//! SELECT FILES FROM ~/Documents
+//! SELECT DIRS FROM ~/projects
+//! SELECT * FROM ~/projects TYPE symlink
+//!
+//! -- Totals per owner, or for the whole tree
+//! WITH RECURSIVE SELECT owner, SUM(size), COUNT(*) FROM /srv GROUP BY owner
+//! WITH RECURSIVE SELECT COUNT(*), AVG(size) AS mean FROM ~/Photos
+//!
+//! -- Sort by depth, then by path with numbers compared by value (file2 before file10)
+//! SELECT * FROM ~/Music ORDER BY depth, path NATURAL
+//! SELECT name, size FROM ~/Downloads ORDER BY size DESC LIMIT 10
+//!
+//! -- Files without an extension after those with one, whichever the direction
+//! SELECT * FROM ~/src ORDER BY extension DESC NULLS LAST, size DESC
+//!
+//! -- Sort names the way a German speaker expects (needs the `collation` feature)
+//! SELECT * FROM ~/Dokumente ORDER BY name COLLATE 'de_DE'
+//!
+//! -- Stop at the first match, and give up after ten seconds
+//! SELECT * FROM /var/log WHERE name = 'panic.log' LIMIT 1 WITHIN '10s'
+//!
+//! -- Arithmetic, in conditions and as computed columns
+//! SELECT name, size / 1024 AS kib FROM ~/Downloads WHERE size / 1048576 > 100
+//!
+//! -- Look up each file in a CSV or JSON table, keeping only those it lists
+//! SELECT name, inventory.team FROM /srv JOIN 'inventory.csv' ON name = inventory.filename
+//! SELECT name FROM /srv LEFT JOIN 'retention.json' AS r ON path = r.path WHERE r.class = NULL
+//! ```
+//!
+//! ## UPDATE Queries
+//! ```sql
+//! -- Update permissions
+//! UPDATE /path/to/scripts SET permissions = '755' WHERE extension = 'sh'
+//!
+//! -- Different values per file
+//! UPDATE ~/site SET permissions = CASE WHEN is_directory = TRUE THEN '755' ELSE '644' END
+//! UPDATE ~/Photos SET name = CONCAT(STEM(name), '_', YEAR(modified), '.', ext)
+//!
+//! -- Keep both files when a new name is already taken
+//! UPDATE ~/Photos SET name = LOWER(name) ON CONFLICT RENAME
+//! ```
+//!
+//! ## Condition Types
+//! - Comparisons: `=`, `!=`, `<`, `<=`, `>`, `>=`
+//! - Pattern matching: `LIKE`, `REGEXP`
+//! - Substrings: `STARTS_WITH(name, 'IMG_')`, `ENDS_WITH(name, '.bak')`, `CONTAINS_STR(path, '/cache/')`
+//! - Permission checks: `HAS_PERM('g+w')`, `PERM_MATCHES('??5')`
+//! - Byte signatures: `CONTAINS_BYTES(x'7f454c46')`, `CONTAINS_BYTES(x'504b0304', 4)`
+//! - Permission masks: `PERM_AND(permissions, 0o022) != 0`, `permissions & 0o022 != 0`
+//! - Range checking: `BETWEEN`
+//! - Missing values: `extension IS NULL`, `owner IS NOT NULL`
+//! - Arithmetic on either side of a comparison: `+`, `-`, `*`, `/`, `%`
+//! - Logical operations: `AND`, `OR`, `NOT`, `XOR`
+//! - Membership and grouping: `ANY_OF(extension, 'jpg', 'png')`, `ALL_OF(cond, cond, ...)`
+//! - Group membership of the owner: `OWNER_IN_GROUP('developers')`
+//! - Tags given with `fmql tag add`: `TAGGED('project-x')`
+//! - Boolean attributes on their own (`WHERE is_directory`) and `TRUE` / `FALSE`
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use fmql_core::sql::parse_sql;
+//!
+//! // Parse a SELECT query
+//! let select_query = parse_sql("SELECT * FROM . WHERE size > 1000000").unwrap();
+//!
+//! // Parse an UPDATE query
+//! let update_query = parse_sql("UPDATE ~/scripts SET permissions = '755' WHERE extension = 'sh'").unwrap();
+//!
+//! // Parse a complex query with pattern matching
+//! let pattern_query = parse_sql("SELECT * FROM ~/logs WHERE name LIKE '%.log' AND size > 1000").unwrap();
+//! ```
+
+use regex::Regex;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use thiserror::Error;
+
+use crate::sql::ast::{
+    AggregateColumn, AggregateFunction, ArithmeticOperator, CastType, ComparisonOperator, ComputedColumn, ConflictPolicy,
+    CompressFormat, CopyMethod, EntryType, FileAttribute, FileAttributeUpdate, FileCondition, FileExpr, FileQuery, FileValue, Join,
+    OrderBy, Preserve, RowSample, SortKey, SubstringPosition, STDIN_PATH,
+};
+use crate::sql::catalog::{column, ValueType};
+use crate::sql::executor::like_regex;
+use crate::sql::extract::ArchiveFormat;
+use crate::sql::join::PATH_COLUMN;
+use crate::sql::trash::trash_files;
+use crate::sql::functions::is_scalar;
+use crate::sql::lint::lint;
+use crate::sql::lexer::{
+    parse_duration, parse_interval, parse_number, parse_numeric, tokenize, Position, Span, Token,
+    TokenKind,
+};
+use crate::sql::paths::is_glob;
+use crate::sql::permissions::{matches_octal_pattern, parse_octal, PermissionSpec};
+use crate::sql::sample::parse_sample;
+use crate::sql::signatures::parse_hex;
+use crate::sql::tags::TagStore;
+use crate::sql::throttle::parse_size;
+use crate::sql::views::view;
+
+/// The items of a SELECT list.
+#[derive(Default)]
+struct Projection {
+    attributes: Vec<FileAttribute>,
+    computed: Vec<ComputedColumn>,
+    aggregates: Vec<AggregateColumn>,
+    /// Every item that isn't an aggregate, in order.
+    columns: Vec<ProjectedColumn>,
+}
+
+/// An item of a SELECT list that isn't an aggregate.
+struct ProjectedColumn {
+    /// The attribute, for a lone attribute name.
+    attribute: Option<FileAttribute>,
+    /// The attribute's name, or the expression's column name.
+    name: String,
+    span: Span,
+}
+
+/// The `LIMIT`, `WITHIN` and `SAMPLE` clauses that end a SELECT.
+#[derive(Default)]
+struct Bounds {
+    limit: Option<usize>,
+    /// The size of `LIMIT 10GB BY size`, in bytes.
+    limit_size: Option<u64>,
+    /// The LIMIT keyword, so later checks can point at it.
+    limit_keyword: Option<Token>,
+    within: Option<Duration>,
+    sample: Option<f64>,
+    sample_rows: Option<RowSample>,
+    /// The SAMPLE keyword of `SAMPLE n ROWS`, so later checks can point at it.
+    sample_keyword: Option<Token>,
+}
+
+/// Errors that can occur during SQL parsing.
+///
+/// This enum represents all the potential errors that might arise during
+/// the parsing of SQL-like statements into file queries.
+///
+/// # Examples
+///
+/// ```
+/// use fmql_core::sql::parser::ParserError;
+///
+/// // Creating a parser error
+/// let error = ParserError::InvalidPath("~/invalid/path".to_string());
+/// assert!(format!("{}", error).contains("Invalid file path"));
+/// ```
+#[derive(Error, Debug)]
+pub enum ParserError {
+    /// Error from the underlying SQL parser library.
+    #[error("SQL parser error: {0}")]
+    Sql(#[from] sqlparser::parser::ParserError),
+
+    /// Error when the SQL statement type is not supported (e.g., not SELECT/UPDATE).
+    #[error("Unsupported SQL statement: {0}")]
+    UnsupportedStatement(String),
+
+    /// Error when a path in the query is invalid or cannot be resolved.
+    #[error("Invalid file path: {0}")]
+    InvalidPath(String),
+
+    #[error("Missing required clause: {0}")]
+    MissingClause(String),
+
+    /// Error when the query text does not match the expected grammar.
+    #[error("Syntax error at line {}, column {}: {message}", span.start.line, span.start.column)]
+    Syntax {
+        /// A description of what was expected.
+        message: String,
+        /// Where in the query the error was detected.
+        span: Span,
+    },
+
+    /// Error when a query refers to an attribute that does not exist.
+    #[error("Unknown attribute '{name}' at line {}, column {}", span.start.line, span.start.column)]
+    UnknownAttribute {
+        /// The name that was not recognised.
+        name: String,
+        /// Where in the query the name appears.
+        span: Span,
+    },
+
+    /// Error when a REGEXP or LIKE pattern, or a glob in a path, can't be
+    /// compiled.
+    #[error("Invalid pattern at line {}, column {}: {message}", span.start.line, span.start.column)]
+    InvalidPattern {
+        /// Why the pattern is invalid.
+        message: String,
+        /// Where in the query the pattern appears.
+        span: Span,
+    },
+
+    /// Error when a `find` expression can't be translated into a query.
+    #[error("Can't translate the find expression: {0}")]
+    Find(String),
+
+    /// Error when a `@snippet` can't be expanded: see [`Aliases`].
+    ///
+    /// [`Aliases`]: crate::sql::aliases::Aliases
+    #[error(
+        "Can't expand the snippet at line {}, column {}: {message}",
+        span.start.line,
+        span.start.column
+    )]
+    Snippet {
+        /// Why the snippet can't be expanded.
+        message: String,
+        /// Where in the query the snippet is used.
+        span: Span,
+    },
+}
+
+impl ParserError {
+    /// Returns the source span the error refers to, if known.
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            ParserError::Syntax { span, .. }
+            | ParserError::UnknownAttribute { span, .. }
+            | ParserError::InvalidPattern { span, .. }
+            | ParserError::Snippet { span, .. } => Some(*span),
+            _ => None,
+        }
+    }
+}
+
+/// Result type for parser operations.
+///
+/// This is a specialized Result type that uses `ParserError` as its error type.
+pub type Result<T> = std::result::Result<T, ParserError>;
+
+/// Parses a SQL-like statement into a structured FileQuery object.
+///
+/// This function is the main entry point for SQL parsing. It takes a SQL string
+/// and transforms it into a `FileQuery` that can be executed by the executor.
+///
+/// # Arguments
+///
+/// * `sql` - The SQL-like statement to parse as a string
+///
+/// # Returns
+///
+/// A `Result` containing either:
+/// - A parsed `FileQuery` object representing the query
+/// - A `ParserError` if parsing fails
+///
+/// # Examples
+///
+/// ```no_run
+/// use fmql_core::sql::parse_sql;
+///
+/// // Parse a basic SELECT query
+/// let query = parse_sql("SELECT * FROM ~/Documents WHERE extension = 'txt'").unwrap();
+///
+/// // Parse a recursive query
+/// let recursive = parse_sql("WITH RECURSIVE SELECT * FROM . WHERE size > 1000000").unwrap();
+///
+/// // Parse an UPDATE query
+/// let update = parse_sql("UPDATE ~/scripts SET permissions = '755' WHERE extension = 'sh'").unwrap();
+/// ```
+///
+/// # Errors
+///
+/// This function will return an error if:
+/// - The SQL syntax is invalid
+/// - The statement type is not supported (only SELECT and UPDATE are supported)
+/// - Required clauses are missing (e.g., FROM in a SELECT query)
+/// - Path resolution fails (e.g., home directory cannot be determined)
+pub fn parse_sql(sql: &str) -> Result<FileQuery> {
+    let tokens: Vec<Token> = tokenize(sql)?
+        .into_iter()
+        .filter(|t| t.kind != TokenKind::Comment)
+        .collect();
+
+    let mut parser = Parser::new(sql, tokens);
+    let query = parser.parse_query()?;
+    parser.expect_end()?;
+
+    Ok(query)
+}
+
+/// Parses a query and returns the result as a JSON document.
+///
+/// This is intended for editor tooling and other non-Rust consumers. On
+/// success the document has the form `{"ok": true, "ast": ..., "warnings":
+/// [...]}`, with the warnings from [`lint`](crate::sql::lint::lint); on failure
+/// it has the form `{"ok": false, "error": {"message": ..., "span": ...}}`,
+/// where `span` is `null` when the error has no source location.
+///
+/// # Examples
+///
+/// ```
+/// use fmql_core::sql::parse_to_ast_json;
+///
+/// let json = parse_to_ast_json("SELECT * FROM . WHERE size > 10");
+/// assert!(json.contains("\"ok\":true"));
+///
+/// let json = parse_to_ast_json("SELECT * FROM . WHERE");
+/// assert!(json.contains("\"ok\":false"));
+/// ```
+pub fn parse_to_ast_json(sql: &str) -> String {
+    let document = match parse_sql(sql) {
+        Ok(query) => serde_json::json!({ "ok": true, "warnings": lint(&query), "ast": query }),
+        Err(err) => serde_json::json!({
+            "ok": false,
+            "error": { "message": err.to_string(), "span": err.span() },
+        }),
+    };
+
+    document.to_string()
+}
+
+/// A recursive-descent parser over the token stream produced by the lexer.
+struct Parser<'a> {
+    sql: &'a str,
+    tokens: Vec<Token>,
+    position: usize,
+    /// The table names of the qualified columns read so far, such as
+    /// `inventory` in `inventory.team`, checked against the JOIN once it
+    /// has been read.
+    tables: Vec<Token>,
+}
+
+impl<'a> Parser<'a> {
+    fn new(sql: &'a str, tokens: Vec<Token>) -> Self {
+        Parser {
+            sql,
+            tokens,
+            position: 0,
+            tables: Vec::new(),
+        }
+    }
+
+    /// Parses a complete SELECT, UPDATE, COPY, COMPRESS, EXTRACT, ARCHIVE or
+    /// RESTORE statement, or an EXPLAIN of one.
+    fn parse_query(&mut self) -> Result<FileQuery> {
+        if self.consume_keyword("EXPLAIN") {
+            if self.consume_keyword("EXPLAIN") {
+                return Err(ParserError::UnsupportedStatement(
+                    "EXPLAIN can't explain another EXPLAIN".to_string(),
+                ));
+            }
+            let query = self.parse_query()?;
+            return Ok(FileQuery::Explain {
+                query: Box::new(query),
+            });
+        }
+        if self.consume_keyword("WITH") {
+            self.expect_keyword("RECURSIVE")?;
+            if self.consume_keyword("COPY") {
+                return self.parse_copy(true);
+            }
+            if self.consume_keyword("COMPRESS") {
+                return self.parse_compress(true);
+            }
+            if self.consume_keyword("EXTRACT") {
+                return self.parse_extract(true);
+            }
+            if self.consume_keyword("ARCHIVE") {
+                return self.parse_archive(true);
+            }
+            if self.consume_keyword("RESTORE") {
+                return self.parse_restore(true);
+            }
+            self.expect_keyword("SELECT")?;
+            return self.parse_select(true);
+        }
+        if self.consume_keyword("SELECT") {
+            return self.parse_select(false);
+        }
+        if self.consume_keyword("UPDATE") {
+            return self.parse_update();
+        }
+        if self.consume_keyword("COPY") {
+            return self.parse_copy(false);
+        }
+        if self.consume_keyword("COMPRESS") {
+            return self.parse_compress(false);
+        }
+        if self.consume_keyword("EXTRACT") {
+            return self.parse_extract(false);
+        }
+        if self.consume_keyword("ARCHIVE") {
+            return self.parse_archive(false);
+        }
+        if self.consume_keyword("RESTORE") {
+            return self.parse_restore(false);
+        }
+        if self.consume_keyword("SHOW") {
+            self.expect_keyword("FILESYSTEM")?;
+            let path = if self.consume_keyword("FROM") {
+                self.parse_source("Missing path after FROM")?
+            } else {
+                PathBuf::from(".")
+            };
+            return Ok(FileQuery::ShowFilesystem { path });
+        }
+
+        Err(ParserError::UnsupportedStatement(format!(
+            "Unsupported SQL statement: {}",
+            self.sql
+        )))
+    }
+
+    /// Parses the remainder of a SELECT statement.
+    fn parse_select(&mut self, recursive: bool) -> Result<FileQuery> {
+        let shorthand = if self.consume_keyword("FILES") {
+            Some(EntryType::File)
+        } else if self.consume_keyword("DIRS") {
+            Some(EntryType::Directory)
+        } else {
+            None
+        };
+        let projection = match shorthand {
+            Some(_) => Projection {
+                attributes: vec![FileAttribute::All],
+                ..Default::default()
+            },
+            None => self.parse_projection()?,
+        };
+
+        if !self.consume_keyword("FROM") {
+            return Err(ParserError::MissingClause(
+                "Missing FROM clause".to_string(),
+            ));
+        }
+        if let Some(token) = self.peek().filter(|t| t.kind == TokenKind::Function).cloned()
+            && let Some(build) = view(&token.text)
+        {
+            let star = shorthand.is_none()
+                && projection.computed.is_empty()
+                && projection.aggregates.is_empty()
+                && matches!(projection.attributes[..], [FileAttribute::All]);
+            if !star {
+                return Err(ParserError::Syntax {
+                    message: format!("Only SELECT * can be taken from {}()", token.text),
+                    span: token.span,
+                });
+            }
+            self.position += 1;
+            return self.parse_view(build);
+        }
+        let path = self.parse_source("Missing path after FROM")?;
+        let table = self.parse_table_source(&path)?;
+        let (include_self, entry_type) = self.parse_path_modifiers(shorthand)?;
+        let join = match table {
+            Some(table) => {
+                if let Some(token) = self.peek().filter(|t| t.is_keyword("JOIN") || t.is_keyword("LEFT")) {
+                    return Err(syntax_error("A query that reads FROM a table can't also JOIN one", token));
+                }
+                Some(table)
+            }
+            None => self.parse_join()?,
+        };
+        let condition = self.parse_where()?;
+        let group_by = self.parse_group_by(&projection)?;
+        let rollup = self.parse_rollup()?;
+        if let Some(keyword) = &rollup {
+            check_rollup(keyword, &projection, &group_by)?;
+        } else if !projection.aggregates.is_empty() || !group_by.is_empty() {
+            check_grouped_columns(&projection.columns, &group_by)?;
+        }
+        let order_by = self.parse_order_by()?;
+        if let (Some(keyword), false) = (&rollup, order_by.is_empty()) {
+            return Err(rollup_error("ROLLUP BY path can't be combined with ORDER BY", keyword));
+        }
+        let Bounds {
+            limit,
+            limit_size,
+            limit_keyword,
+            within,
+            sample,
+            sample_rows,
+            sample_keyword,
+        } = self.parse_select_bounds()?;
+        if let (Some(keyword), Some(_)) = (&rollup, &limit_keyword) {
+            return Err(rollup_error("ROLLUP BY path can't be combined with LIMIT", keyword));
+        }
+        if let (Some(keyword), true) = (&rollup, sample.is_some() || sample_rows.is_some()) {
+            return Err(rollup_error("ROLLUP BY path can't be combined with SAMPLE", keyword));
+        }
+        let grouped = !projection.aggregates.is_empty() || !group_by.is_empty();
+        if let (Some(keyword), Some(_), true) = (&limit_keyword, limit_size, grouped) {
+            return Err(files_only_error("LIMIT ... BY size", keyword));
+        }
+        if let (Some(keyword), true) = (&sample_keyword, grouped) {
+            return Err(files_only_error("SAMPLE n ROWS", keyword));
+        }
+        self.check_tables(join.as_deref())?;
+
+        Ok(FileQuery::Select {
+            path,
+            recursive,
+            include_self,
+            entry_type,
+            join,
+            attributes: projection.attributes,
+            computed: projection.computed,
+            aggregates: projection.aggregates,
+            condition,
+            group_by,
+            rollup: rollup.is_some(),
+            order_by,
+            limit,
+            limit_size,
+            within,
+            sample,
+            sample_rows,
+        })
+    }
+
+    /// Parses the `(path)` of a built-in view and the clauses that can follow
+    /// it, and returns the view's query narrowed by them.
+    fn parse_view(&mut self, build: fn(PathBuf) -> FileQuery) -> Result<FileQuery> {
+        self.expect_punctuation("(")?;
+        let path = self.parse_source("Missing path in the view's ()")?;
+        self.expect_punctuation(")")?;
+        let mut query = build(path);
+        let where_clause = self.parse_where()?;
+        let sort = self.parse_order_by()?;
+        let bounds = self.parse_select_bounds()?;
+        if let (Some(keyword), Some(_), true) =
+            (&bounds.limit_keyword, bounds.limit_size, query.is_grouped())
+        {
+            return Err(files_only_error("LIMIT ... BY size", keyword));
+        }
+        if let (Some(keyword), true) = (&bounds.sample_keyword, query.is_grouped()) {
+            return Err(files_only_error("SAMPLE n ROWS", keyword));
+        }
+        if let FileQuery::Select {
+            condition,
+            order_by,
+            limit,
+            limit_size,
+            within,
+            sample,
+            sample_rows,
+            ..
+        } = &mut query
+        {
+            *condition = where_clause;
+            if !sort.is_empty() {
+                *order_by = sort;
+            }
+            (*limit, *limit_size, *within, *sample) =
+                (bounds.limit, bounds.limit_size, bounds.within, bounds.sample);
+            *sample_rows = bounds.sample_rows;
+        }
+        Ok(query)
+    }
+
+    /// Parses the optional `AS TABLE [name]` after the file a SELECT reads,
+    /// which makes the file a table whose `path` column lists the files to
+    /// query. Each file is joined to its row, as if by
+    /// `JOIN 'file' AS name ON path = name.path`.
+    fn parse_table_source(&mut self, path: &Path) -> Result<Option<Box<Join>>> {
+        if !self.peek().is_some_and(|t| t.is_keyword("AS")) {
+            return Ok(None);
+        }
+        let keyword = self.next_token("AS")?;
+        self.expect_keyword("TABLE")?;
+        if path.as_os_str() == STDIN_PATH || is_glob(path) {
+            return Err(syntax_error("Only a CSV or JSON file can be read AS TABLE", &keyword));
+        }
+        let alias = match self.peek() {
+            Some(name) if name.kind == TokenKind::Identifier => self.next_token("a table name")?.text,
+            _ => path
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+        };
+        Ok(Some(Box::new(Join {
+            source: path.to_path_buf(),
+            alias,
+            key: FileExpr::Attribute(FileAttribute::Path),
+            column: PATH_COLUMN.to_string(),
+            left: false,
+            lists_files: true,
+        })))
+    }
+
+    /// Parses an optional `[LEFT] JOIN 'file' [AS name] ON key = name.column`
+    /// clause. The key can be on either side of the `=`.
+    fn parse_join(&mut self) -> Result<Option<Box<Join>>> {
+        let left = self.consume_keyword("LEFT");
+        if !left && !self.peek().is_some_and(|t| t.is_keyword("JOIN")) {
+            return Ok(None);
+        }
+        self.expect_keyword("JOIN")?;
+        let token = self.next_token("a file to join")?;
+        if token.kind != TokenKind::String {
+            return Err(syntax_error("Expected a file to join, such as 'inventory.csv'", &token));
+        }
+        let source = resolve_path(&token.text)?;
+        let alias = if self.consume_keyword("AS") {
+            let name = self.next_token("a table name")?;
+            if name.kind != TokenKind::Identifier {
+                return Err(syntax_error("Expected a table name", &name));
+            }
+            name.text
+        } else {
+            source
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+                .unwrap_or_default()
+        };
+
+        let on = self.next_token("ON")?;
+        if !on.is_keyword("ON") {
+            return Err(syntax_error("Expected ON", &on));
+        }
+        let first = self.parse_set_value()?;
+        self.expect_operator("=")?;
+        let second = self.parse_set_value()?;
+        let (key, column) = match (first, second) {
+            (FileExpr::Joined { column, .. }, key) | (key, FileExpr::Joined { column, .. })
+                if !matches!(key, FileExpr::Joined { .. }) =>
+            {
+                (key, column)
+            }
+            _ => {
+                return Err(ParserError::Syntax {
+                    message: format!(
+                        "ON must compare the file with a column of the table, as in ON name = {}.filename",
+                        alias
+                    ),
+                    span: on.span,
+                });
+            }
+        };
+        Ok(Some(Box::new(Join {
+            source,
+            alias,
+            key,
+            column,
+            left,
+            lists_files: false,
+        })))
+    }
+
+    /// Checks that every qualified column names the joined table.
+    fn check_tables(&self, join: Option<&Join>) -> Result<()> {
+        for table in &self.tables {
+            match join {
+                None => {
+                    return Err(syntax_error("Columns of another table need a JOIN clause", table));
+                }
+                Some(join)
+                    if !qualified(&table.text)
+                        .is_some_and(|(name, _)| name.eq_ignore_ascii_case(&join.alias)) =>
+                {
+                    return Err(syntax_error(
+                        &format!("Unknown table; the joined table is '{}'", join.alias),
+                        table,
+                    ));
+                }
+                Some(_) => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns true if the next token is a qualified column such as
+    /// `inventory.team`.
+    fn peek_qualified(&self) -> bool {
+        self.peek().is_some_and(|t| t.kind == TokenKind::Identifier && qualified(&t.text).is_some())
+    }
+
+    /// Parses a qualified column such as `inventory.team` into its table and
+    /// column names.
+    fn parse_qualified(&mut self) -> Result<(String, String)> {
+        let token = self.next_token("a table column")?;
+        let Some((table, column)) = qualified(&token.text) else {
+            return Err(syntax_error("Expected a column such as inventory.team", &token));
+        };
+        let names = (table.to_string(), column.to_string());
+        self.tables.push(token);
+        Ok(names)
+    }
+
+    /// Parses an optional `ROLLUP BY path` clause, returning the ROLLUP
+    /// keyword so later checks can point at it.
+    fn parse_rollup(&mut self) -> Result<Option<Token>> {
+        if !self.peek().is_some_and(|t| t.is_keyword("ROLLUP")) {
+            return Ok(None);
+        }
+        let keyword = self.next_token("ROLLUP")?;
+        self.expect_keyword("BY")?;
+        let key = self.next_token("path")?;
+        if FileAttribute::from_name(&key.text) != Some(FileAttribute::Path) {
+            return Err(syntax_error("Only ROLLUP BY path is supported", &key));
+        }
+        Ok(Some(keyword))
+    }
+
+    /// Parses an optional `GROUP BY expression, ...` clause. Each key is
+    /// named by its source text, so `GROUP BY YEAR(modified)` matches
+    /// `YEAR(modified)` in the SELECT list, except that a key that is just
+    /// the alias of a computed column, as in `YEAR(modified) AS year ...
+    /// GROUP BY year`, is that column. A key that doesn't depend on the
+    /// file, such as `'x'`, would put every file in one group, and is an
+    /// error.
+    fn parse_group_by(&mut self, projection: &Projection) -> Result<Vec<ComputedColumn>> {
+        if !self.consume_keyword("GROUP") {
+            return Ok(Vec::new());
+        }
+        self.expect_keyword("BY")?;
+
+        let mut keys = Vec::new();
+        loop {
+            if let Some(column) = self.parse_group_alias(projection) {
+                keys.push(column);
+            } else {
+                let start = self.next_span_start("an expression")?;
+                let expr = self.parse_set_value()?;
+                let end = self.tokens[self.position - 1].span.end;
+                let span = Span { start, end };
+                if !depends_on_file(&expr) {
+                    return Err(ParserError::Syntax {
+                        message: "GROUP BY needs an attribute, a column alias or an expression \
+                                  of attributes"
+                            .to_string(),
+                        span,
+                    });
+                }
+                let name = self.sql[start.offset_in(self.sql)..end.offset_in(self.sql)].to_string();
+                keys.push(ComputedColumn { name, expr });
+            }
+            if !self.consume_punctuation(",") {
+                return Ok(keys);
+            }
+        }
+    }
+
+    /// Parses a GROUP BY key that is just the alias of a computed column in
+    /// the SELECT list, and returns that column; parses nothing otherwise.
+    fn parse_group_alias(&mut self, projection: &Projection) -> Option<ComputedColumn> {
+        let token = self.peek()?;
+        let word = matches!(
+            token.kind,
+            TokenKind::Identifier | TokenKind::Attribute | TokenKind::String
+        );
+        // The alias is the whole key, not the start of an expression
+        let lone = self.tokens.get(self.position + 1).is_none_or(|next| {
+            next.kind == TokenKind::Keyword
+                || (next.kind == TokenKind::Punctuation && next.text == ",")
+        });
+        if !word || !lone {
+            return None;
+        }
+        let column = projection
+            .computed
+            .iter()
+            .find(|column| column.name.eq_ignore_ascii_case(&token.text))?
+            .clone();
+        self.position += 1;
+        Some(column)
+    }
+
+    /// Parses an optional `ORDER BY key [NATURAL] [COLLATE 'locale']
+    /// [ASC | DESC] [NULLS FIRST | NULLS LAST], ...` clause. A key is an
+    /// attribute or `depth`.
+    fn parse_order_by(&mut self) -> Result<Vec<OrderBy>> {
+        if !self.consume_keyword("ORDER") {
+            return Ok(Vec::new());
+        }
+        self.expect_keyword("BY")?;
+
+        let mut order_by = Vec::new();
+        loop {
+            let random = self.peek().is_some_and(|t| {
+                t.kind == TokenKind::Function && t.text.eq_ignore_ascii_case("RANDOM")
+            });
+            let key = if random {
+                self.position += 1;
+                self.expect_punctuation("(")?;
+                let seed = if self.consume_punctuation(")") {
+                    None
+                } else {
+                    let seed = self.parse_seed()?;
+                    self.expect_punctuation(")")?;
+                    Some(seed)
+                };
+                SortKey::Random { seed }
+            } else if self.consume_keyword("DEPTH") {
+                SortKey::Depth
+            } else if self.peek_qualified() {
+                let (table, column) = self.parse_qualified()?;
+                SortKey::Joined { table, column }
+            } else {
+                SortKey::Attribute(self.parse_attribute()?)
+            };
+            let mut natural = false;
+            let mut collation = None;
+            let mut direction = None;
+            let mut nulls_first = None;
+            loop {
+                if !natural && self.consume_keyword("NATURAL") {
+                    natural = true;
+                } else if collation.is_none() && self.consume_keyword("COLLATE") {
+                    collation = Some(self.expect_string()?);
+                } else if direction.is_none() && self.consume_keyword("ASC") {
+                    direction = Some(false);
+                } else if direction.is_none() && self.consume_keyword("DESC") {
+                    direction = Some(true);
+                } else if nulls_first.is_none() && self.consume_keyword("NULLS") {
+                    if self.consume_keyword("FIRST") {
+                        nulls_first = Some(true);
+                    } else {
+                        self.expect_keyword("LAST")?;
+                        nulls_first = Some(false);
+                    }
+                } else {
+                    break;
+                }
+            }
+            order_by.push(OrderBy {
+                key,
+                descending: direction.unwrap_or(false),
+                natural,
+                collation,
+                nulls_first,
+            });
+            if !self.consume_punctuation(",") {
+                return Ok(order_by);
+            }
+        }
+    }
+
+    /// Parses the optional `LIMIT n` or `LIMIT <size> BY size`,
+    /// `WITHIN '<duration>'` and `SAMPLE n%` or `SAMPLE n ROWS` clauses that
+    /// end a SELECT, in any order.
+    fn parse_select_bounds(&mut self) -> Result<Bounds> {
+        let mut bounds = Bounds::default();
+        loop {
+            if bounds.limit_keyword.is_none() && self.peek().is_some_and(|t| t.is_keyword("LIMIT")) {
+                bounds.limit_keyword = Some(self.next_token("LIMIT")?);
+                let token = self.next_token("a row count")?;
+                if token.kind != TokenKind::Number {
+                    return Err(syntax_error("Expected a row count", &token));
+                }
+                // A unit, as in `LIMIT 10GB BY size`, makes it a size
+                let unit = self.peek().filter(|unit| {
+                    matches!(unit.kind, TokenKind::Identifier | TokenKind::Keyword)
+                        && parse_size(&format!("1{}", unit.text)).is_ok()
+                });
+                let unit = unit.map(|unit| unit.text.clone());
+                if unit.is_some() {
+                    self.position += 1;
+                }
+                if self.consume_keyword("BY") {
+                    let key = self.next_token("size")?;
+                    if FileAttribute::from_name(&key.text) != Some(FileAttribute::Size) {
+                        return Err(syntax_error("Only LIMIT ... BY size is supported", &key));
+                    }
+                    let size = format!("{}{}", token.text, unit.unwrap_or_default());
+                    let size = parse_size(&size).map_err(|message| syntax_error(&message, &token))?;
+                    bounds.limit_size = Some(size);
+                } else if unit.is_some() {
+                    let next = self.next_token("BY size")?;
+                    return Err(syntax_error("Expected BY size after a size to LIMIT", &next));
+                } else {
+                    let count = token.text.parse::<usize>().ok();
+                    bounds.limit =
+                        Some(count.ok_or_else(|| syntax_error("Expected a row count", &token))?);
+                }
+            } else if bounds.within.is_none() && self.consume_keyword("WITHIN") {
+                let token = self.next_token("a duration")?;
+                if token.kind != TokenKind::String {
+                    return Err(syntax_error("Expected a duration such as '10s'", &token));
+                }
+                let duration =
+                    parse_duration(&token.text).map_err(|message| syntax_error(&message, &token))?;
+                bounds.within = Some(duration);
+            } else if bounds.sample.is_none()
+                && bounds.sample_rows.is_none()
+                && self.peek().is_some_and(|t| t.is_keyword("SAMPLE"))
+            {
+                let keyword = self.next_token("SAMPLE")?;
+                let token = self.next_token("a percentage")?;
+                if token.kind == TokenKind::Number && self.consume_keyword("ROWS") {
+                    let rows = token.text.parse::<usize>().ok();
+                    let rows = rows.ok_or_else(|| syntax_error("Expected a row count", &token))?;
+                    let weight = if self.consume_keyword("WEIGHTED") {
+                        self.expect_keyword("BY")?;
+                        let weight = self.parse_attribute()?;
+                        if !column(&weight).is_some_and(|c| c.value_type == ValueType::Number) {
+                            return Err(syntax_error(
+                                "WEIGHTED BY needs a numeric attribute, such as size",
+                                &self.tokens[self.position - 1],
+                            ));
+                        }
+                        Some(weight)
+                    } else {
+                        None
+                    };
+                    let seed = if self.consume_keyword("SEED") {
+                        Some(self.parse_seed()?)
+                    } else {
+                        None
+                    };
+                    bounds.sample_rows = Some(RowSample { rows, weight, seed });
+                    bounds.sample_keyword = Some(keyword);
+                    continue;
+                }
+                let percent = match token.kind {
+                    TokenKind::Number if self.consume_operator("%") => Some(token.text.as_str()),
+                    _ => None,
+                };
+                let fraction = percent
+                    .ok_or_else(|| {
+                        syntax_error("Expected a percentage such as 1%, or n ROWS", &token)
+                    })
+                    .and_then(|percent| {
+                        parse_sample(percent).map_err(|message| syntax_error(&message, &token))
+                    })?;
+                bounds.sample = Some(fraction);
+            } else {
+                return Ok(bounds);
+            }
+        }
+    }
+
+    /// Parses the seed of `RANDOM(42)` or `SEED 42`.
+    fn parse_seed(&mut self) -> Result<u64> {
+        let token = self.next_token("a seed")?;
+        match token.kind {
+            TokenKind::Number => token.text.parse().ok(),
+            _ => None,
+        }
+        .ok_or_else(|| syntax_error("Expected a seed, a whole number such as 42", &token))
+    }
+
+    /// Parses the remainder of an UPDATE statement.
+    fn parse_update(&mut self) -> Result<FileQuery> {
+        let path = self.parse_source("Missing path in UPDATE statement")?;
+        let (include_self, entry_type) = self.parse_path_modifiers(None)?;
+
+        if !self.consume_keyword("SET") {
+            return Err(ParserError::MissingClause("Missing SET clause".to_string()));
+        }
+
+        let mut updates = vec![self.parse_assignment()?];
+        while self.consume_punctuation(",") {
+            updates.push(self.parse_assignment()?);
+        }
+
+        let condition = self.parse_where()?;
+        let on_conflict = self.parse_on_conflict()?;
+        self.check_tables(None)?;
+
+        Ok(FileQuery::Update {
+            path,
+            include_self,
+            entry_type,
+            updates,
+            condition,
+            on_conflict,
+        })
+    }
+
+    /// Parses the remainder of a COPY statement.
+    fn parse_copy(&mut self, recursive: bool) -> Result<FileQuery> {
+        let path = self.parse_source("Missing path in COPY statement")?;
+        if !self.consume_keyword("TO") {
+            return Err(ParserError::MissingClause(
+                "Missing TO clause with the directory to copy into".to_string(),
+            ));
+        }
+        let destination = self.parse_path("Missing destination after TO")?;
+        if destination.as_os_str() == STDIN_PATH {
+            return Err(ParserError::InvalidPath("COPY can't copy to STDIN".to_string()));
+        }
+
+        let condition = self.parse_where()?;
+        let method = if self.consume_keyword("USING") {
+            let token = self.next_token("a copy method")?;
+            match token.text.to_uppercase().as_str() {
+                "BYTES" => CopyMethod::Bytes,
+                "HARDLINK" => CopyMethod::Hardlink,
+                "REFLINK" => CopyMethod::Reflink,
+                "AUTO" => CopyMethod::Auto,
+                _ => {
+                    return Err(syntax_error(
+                        "Expected HARDLINK, REFLINK, AUTO or BYTES",
+                        &token,
+                    ));
+                }
+            }
+        } else {
+            CopyMethod::default()
+        };
+        let preserve = self.parse_preserve()?;
+        let sync = self.consume_keyword("SYNC");
+        let verify = self.consume_keyword("VERIFY");
+        if verify {
+            self.expect_keyword("CHECKSUM")?;
+        }
+        let on_conflict = self.parse_on_conflict()?;
+        self.check_tables(None)?;
+
+        Ok(FileQuery::Copy {
+            path,
+            recursive,
+            destination,
+            condition,
+            method,
+            preserve,
+            sync,
+            verify,
+            on_conflict,
+        })
+    }
+
+    /// Parses the remainder of a COMPRESS statement, whose FROM is optional.
+    fn parse_compress(&mut self, recursive: bool) -> Result<FileQuery> {
+        self.consume_keyword("FROM");
+        let path = self.parse_source("Missing path in COMPRESS statement")?;
+        let condition = self.parse_where()?;
+        let format = if self.consume_keyword("USING") {
+            let token = self.next_token("a compression format")?;
+            match token.text.to_uppercase().as_str() {
+                "GZIP" => CompressFormat::Gzip,
+                "ZSTD" => CompressFormat::Zstd,
+                _ => return Err(syntax_error("Expected GZIP or ZSTD", &token)),
+            }
+        } else {
+            CompressFormat::default()
+        };
+        let level = if self.consume_keyword("LEVEL") {
+            let token = self.next_token("a compression level")?;
+            let level = match token.kind {
+                TokenKind::Number => token.text.parse::<u32>().ok(),
+                _ => None,
+            };
+            let levels = format.levels();
+            if !level.is_some_and(|level| levels.contains(&level)) {
+                let message = format!(
+                    "Expected a {} level from {} to {}",
+                    format,
+                    levels.start(),
+                    levels.end()
+                );
+                return Err(syntax_error(&message, &token));
+            }
+            level
+        } else {
+            None
+        };
+        let delete_original = self.consume_keyword("DELETE");
+        if delete_original {
+            self.expect_keyword("ORIGINAL")?;
+        }
+        self.check_tables(None)?;
+
+        Ok(FileQuery::Compress {
+            path,
+            recursive,
+            condition,
+            format,
+            level,
+            delete_original,
+        })
+    }
+
+    /// Parses the remainder of an EXTRACT statement, whose FROM is optional
+    /// and whose WHERE clause may come before or after its TO.
+    fn parse_extract(&mut self, recursive: bool) -> Result<FileQuery> {
+        self.consume_keyword("FROM");
+        let path = self.parse_source("Missing path in EXTRACT statement")?;
+        let mut condition = self.parse_where()?;
+        if !self.consume_keyword("TO") {
+            return Err(ParserError::MissingClause(
+                "Missing TO clause with the directory to extract into".to_string(),
+            ));
+        }
+        let destination = self.parse_path("Missing destination after TO")?;
+        if destination.as_os_str() == STDIN_PATH {
+            return Err(ParserError::InvalidPath("EXTRACT can't extract to STDIN".to_string()));
+        }
+        if condition.is_none() {
+            condition = self.parse_where()?;
+        }
+        let on_conflict = self.parse_on_conflict()?;
+        self.check_tables(None)?;
+
+        Ok(FileQuery::Extract {
+            path,
+            recursive,
+            condition,
+            destination,
+            on_conflict,
+        })
+    }
+
+    /// Parses the remainder of an ARCHIVE statement, whose FROM is optional
+    /// and whose WHERE clause may come before or after its INTO.
+    fn parse_archive(&mut self, recursive: bool) -> Result<FileQuery> {
+        self.consume_keyword("FROM");
+        let path = self.parse_source("Missing path in ARCHIVE statement")?;
+        let mut condition = self.parse_where()?;
+        if !self.consume_keyword("INTO") {
+            return Err(ParserError::MissingClause(
+                "Missing INTO clause with the archive to write".to_string(),
+            ));
+        }
+        let destination = self.parse_path("Missing archive after INTO")?;
+        if ArchiveFormat::of(&destination).is_none() {
+            return Err(ParserError::InvalidPath(format!(
+                "ARCHIVE can only write .tar, .tar.gz, .tar.zst and .zip files, not {}",
+                destination.display()
+            )));
+        }
+        if condition.is_none() {
+            condition = self.parse_where()?;
+        }
+        let delete_original = self.consume_keyword("DELETE");
+        if delete_original {
+            self.expect_keyword("ORIGINAL")?;
+        }
+        self.check_tables(None)?;
+
+        Ok(FileQuery::Archive {
+            path,
+            recursive,
+            condition,
+            destination,
+            delete_original,
+        })
+    }
+
+    /// Parses the remainder of a RESTORE statement, whose FROM is optional.
+    fn parse_restore(&mut self, recursive: bool) -> Result<FileQuery> {
+        self.consume_keyword("FROM");
+        let path = self.parse_source("Missing path in RESTORE statement")?;
+        let condition = self.parse_where()?;
+        let on_conflict = self.parse_on_conflict()?;
+        self.check_tables(None)?;
+
+        Ok(FileQuery::Restore {
+            path,
+            recursive,
+            condition,
+            on_conflict,
+        })
+    }
+
+    /// Parses an optional `PRESERVE (times, permissions, owner, xattrs)`,
+    /// `PRESERVE ALL` or `PRESERVE NONE` clause.
+    fn parse_preserve(&mut self) -> Result<Preserve> {
+        if !self.consume_keyword("PRESERVE") {
+            return Ok(Preserve::default());
+        }
+        let token = self.next_token("what to preserve")?;
+        match token.text.to_uppercase().as_str() {
+            "ALL" => return Ok(Preserve::ALL),
+            "NONE" => return Ok(Preserve::NONE),
+            "(" => {}
+            _ => {
+                return Err(syntax_error("Expected (, ALL or NONE after PRESERVE", &token));
+            }
+        }
+
+        let mut preserve = Preserve::NONE;
+        loop {
+            let token = self.next_token("what to preserve")?;
+            if !preserve.add(&token.text) {
+                let message = format!("Expected one of {}", Preserve::NAMES.join(", "));
+                return Err(syntax_error(&message, &token));
+            }
+            if !self.consume_punctuation(",") {
+                break;
+            }
+        }
+        self.expect_punctuation(")")?;
+        Ok(preserve)
+    }
+
+    /// Parses the optional `INCLUDE SELF` and `TYPE <kind>` modifiers after
+    /// the path, in either order. `entry_type` is the kind already chosen by
+    /// `SELECT FILES` or `SELECT DIRS`, if any.
+    fn parse_path_modifiers(
+        &mut self,
+        mut entry_type: Option<EntryType>,
+    ) -> Result<(bool, Option<EntryType>)> {
+        let mut include_self = false;
+        loop {
+            if !include_self && self.consume_keyword("INCLUDE") {
+                self.expect_keyword("SELF")?;
+                include_self = true;
+            } else if self.peek().is_some_and(|t| t.is_keyword("TYPE")) {
+                let keyword = self.next_token("TYPE")?;
+                if entry_type.is_some() {
+                    return Err(syntax_error("The entry type is already set", &keyword));
+                }
+                let token = self.next_token("an entry type")?;
+                let kind = token
+                    .text
+                    .parse::<EntryType>()
+                    .map_err(|message| syntax_error(&message, &token))?;
+                entry_type = Some(kind);
+            } else {
+                return Ok((include_self, entry_type));
+            }
+        }
+    }
+
+    /// Parses an optional `ON CONFLICT <policy>` clause.
+    fn parse_on_conflict(&mut self) -> Result<ConflictPolicy> {
+        if !self.consume_keyword("ON") {
+            return Ok(ConflictPolicy::default());
+        }
+        self.expect_keyword("CONFLICT")?;
+
+        let token = self.next_token("a conflict policy")?;
+        let policy = match token.text.to_uppercase().as_str() {
+            "SKIP" => ConflictPolicy::Skip,
+            "OVERWRITE" => ConflictPolicy::Overwrite,
+            "RENAME" => ConflictPolicy::Rename,
+            "FAIL" => ConflictPolicy::Fail,
+            _ => {
+                return Err(syntax_error(
+                    "Expected SKIP, OVERWRITE, RENAME or FAIL",
+                    &token,
+                ));
+            }
+        };
+        Ok(policy)
+    }
+
+    /// Parses the list of selected attributes, computed columns and aggregates.
+    fn parse_projection(&mut self) -> Result<Projection> {
+        let mut projection = Projection::default();
+        let start = self.next_span_start("a column")?;
+        if self.consume_operator("*") {
+            projection.attributes.push(FileAttribute::All);
+            projection.columns.push(self.projected(None, "*".to_string(), start));
+            return Ok(projection);
+        }
+
+        loop {
+            let start = self.next_span_start("a column")?;
+            let aggregate = self
+                .peek()
+                .filter(|t| t.kind == TokenKind::Function)
+                .and_then(|t| AggregateFunction::from_name(&t.text));
+            // A lone name is an attribute; anything longer, or a column of
+            // the joined table, is an expression
+            let lone = !self.peek_qualified()
+                && self.tokens.get(self.position + 1).is_some_and(|t| {
+                    t.is_keyword("FROM") || (t.kind == TokenKind::Punctuation && t.text == ",")
+                });
+            if let Some(function) = aggregate {
+                projection.aggregates.push(self.parse_aggregate_column(function, start)?);
+            } else if lone {
+                let attribute = self.parse_attribute()?;
+                let name = attribute.name().to_string();
+                projection.columns.push(self.projected(Some(attribute.clone()), name, start));
+                projection.attributes.push(attribute);
+            } else {
+                let column = self.parse_computed_column()?;
+                projection.columns.push(self.projected(None, column.name.clone(), start));
+                projection.computed.push(column);
+            }
+            if !self.consume_punctuation(",") {
+                break;
+            }
+        }
+
+        Ok(projection)
+    }
+
+    /// Describes the SELECT list item that started at `start` and ended with
+    /// the last token consumed.
+    fn projected(
+        &self,
+        attribute: Option<FileAttribute>,
+        name: String,
+        start: Position,
+    ) -> ProjectedColumn {
+        let end = self.tokens[self.position - 1].span.end;
+        ProjectedColumn {
+            attribute,
+            name,
+            span: Span { start, end },
+        }
+    }
+
+    /// Parses an expression in a SELECT list, with an optional `AS` alias.
+    fn parse_computed_column(&mut self) -> Result<ComputedColumn> {
+        let start = self.next_span_start("an expression")?;
+        let expr = self.parse_set_value()?;
+        let name = self.parse_column_name(start)?;
+        Ok(ComputedColumn { name, expr })
+    }
+
+    /// Parses an aggregate such as `SUM(size)` or `COUNT(*)` in a SELECT
+    /// list, with an optional `AS` alias.
+    fn parse_aggregate_column(
+        &mut self,
+        function: AggregateFunction,
+        start: Position,
+    ) -> Result<AggregateColumn> {
+        self.position += 1;
+        self.expect_punctuation("(")?;
+        let argument = if function == AggregateFunction::Count && self.consume_operator("*") {
+            None
+        } else {
+            Some(self.parse_set_value()?)
+        };
+        self.expect_punctuation(")")?;
+        if let Some(token) = self.peek().filter(|_| self.peek_arithmetic()) {
+            return Err(syntax_error(
+                "Arithmetic on aggregates is not supported",
+                token,
+            ));
+        }
+        let name = self.parse_column_name(start)?;
+        Ok(AggregateColumn {
+            name,
+            function,
+            argument,
+        })
+    }
+
+    /// Parses an optional `AS` alias for the SELECT list item that started at
+    /// `start`. Without one the column is named by its source text.
+    fn parse_column_name(&mut self, start: Position) -> Result<String> {
+        let end = self.tokens[self.position - 1].span.end;
+        if self.consume_keyword("AS") {
+            let token = self.next_token("a column name")?;
+            if !matches!(
+                token.kind,
+                TokenKind::Identifier | TokenKind::Attribute | TokenKind::String
+            ) {
+                return Err(syntax_error("Expected a column name", &token));
+            }
+            Ok(token.text)
+        } else {
+            Ok(self.sql[start.offset_in(self.sql)..end.offset_in(self.sql)].to_string())
+        }
+    }
+
+    /// Parses a single `attribute = value` assignment in a SET clause.
+    fn parse_assignment(&mut self) -> Result<FileAttributeUpdate> {
+        let attribute = self.parse_attribute()?;
+        self.expect_operator("=")?;
+        let value = self.parse_set_value()?;
+
+        Ok(FileAttributeUpdate { attribute, value })
+    }
+
+    /// Parses the value of a SET assignment, which is also the expression
+    /// syntax used for computed columns and arithmetic in conditions.
+    ///
+    /// Literals are kept as text (so `755` and `'755'` are equivalent),
+    /// leaving their interpretation to the attribute being set; arithmetic
+    /// reads text as a number.
+    fn parse_set_value(&mut self) -> Result<FileExpr> {
+        self.parse_sum(None)
+    }
+
+    /// Parses `+` and `-` chains. `first`, if given, is an operand that has
+    /// already been parsed.
+    fn parse_sum(&mut self, first: Option<FileExpr>) -> Result<FileExpr> {
+        let mut left = self.parse_product(first)?;
+        while let Some(operator) = self.consume_arithmetic(false) {
+            let right = self.parse_product(None)?;
+            left = FileExpr::Binary {
+                left: Box::new(left),
+                operator,
+                right: Box::new(right),
+            };
+        }
+        Ok(left)
+    }
+
+    /// Parses `*`, `/` and `%` chains.
+    fn parse_product(&mut self, first: Option<FileExpr>) -> Result<FileExpr> {
+        let mut left = match first {
+            Some(expr) => expr,
+            None => self.parse_operand()?,
+        };
+        while let Some(operator) = self.consume_arithmetic(true) {
+            let right = self.parse_operand()?;
+            left = FileExpr::Binary {
+                left: Box::new(left),
+                operator,
+                right: Box::new(right),
+            };
+        }
+        Ok(left)
+    }
+
+    /// Consumes the next token if it is an arithmetic operator of the given
+    /// precedence.
+    fn consume_arithmetic(&mut self, multiplicative: bool) -> Option<ArithmeticOperator> {
+        let operator = self
+            .peek()
+            .filter(|t| t.kind == TokenKind::Operator)
+            .and_then(|t| ArithmeticOperator::from_symbol(&t.text))
+            .filter(|op| op.is_multiplicative() == multiplicative)?;
+        self.position += 1;
+        Some(operator)
+    }
+
+    /// Parses a single operand: a literal, attribute, function call, CASE
+    /// expression, negation or parenthesised expression.
+    fn parse_operand(&mut self) -> Result<FileExpr> {
+        if self.consume_punctuation("(") {
+            let expr = self.parse_set_value()?;
+            self.expect_punctuation(")")?;
+            return Ok(expr);
+        }
+
+        if self.consume_operator("-") {
+            let operand = self.parse_operand()?;
+            return Ok(FileExpr::Binary {
+                left: Box::new(FileExpr::Literal(FileValue::Integer(0))),
+                operator: ArithmeticOperator::Sub,
+                right: Box::new(operand),
+            });
+        }
+
+        if self.consume_keyword("CASE") {
+            let mut branches = Vec::new();
+            while self.consume_keyword("WHEN") {
+                let condition = self.parse_or()?;
+                self.expect_keyword("THEN")?;
+                branches.push((condition, self.parse_set_value()?));
+            }
+            if branches.is_empty() {
+                self.expect_keyword("WHEN")?;
+            }
+
+            let otherwise = if self.consume_keyword("ELSE") {
+                Some(Box::new(self.parse_set_value()?))
+            } else {
+                None
+            };
+            self.expect_keyword("END")?;
+
+            return Ok(FileExpr::Case {
+                branches,
+                otherwise,
+            });
+        }
+
+        if self.peek_qualified() {
+            let (table, column) = self.parse_qualified()?;
+            return Ok(FileExpr::Joined { table, column });
+        }
+
+        if self.consume_keyword("INTERVAL") {
+            return Ok(FileExpr::Literal(self.parse_interval()?));
+        }
+
+        let token = self.next_token("a value")?;
+        match token.kind {
+            TokenKind::String | TokenKind::Number => {
+                Ok(FileExpr::Literal(FileValue::String(token.text)))
+            }
+            // A word that names no attribute is a misspelling, not a value
+            TokenKind::Identifier => Err(ParserError::UnknownAttribute {
+                name: token.text,
+                span: token.span,
+            }),
+            TokenKind::Attribute => {
+                let attribute = FileAttribute::from_name(&token.text).ok_or(
+                    ParserError::UnknownAttribute {
+                        name: token.text.clone(),
+                        span: token.span,
+                    },
+                )?;
+                Ok(FileExpr::Attribute(attribute))
+            }
+            TokenKind::Function if token.text.eq_ignore_ascii_case("CAST") => {
+                self.expect_punctuation("(")?;
+                let expr = Box::new(self.parse_set_value()?);
+                self.expect_keyword("AS")?;
+                let name = self.next_token("a type such as TEXT")?;
+                let to = CastType::from_name(&name.text).ok_or_else(|| {
+                    let expected = "TEXT, NUMBER, INTEGER, DATE, BOOLEAN or OCTAL";
+                    let message = format!("Unknown type '{}' (expected {})", name.text, expected);
+                    syntax_error(&message, &name)
+                })?;
+                self.expect_punctuation(")")?;
+                Ok(FileExpr::Cast { expr, to })
+            }
+            TokenKind::Function => {
+                if !is_scalar(&token.text) {
+                    return Err(syntax_error(
+                        &format!("Unknown function '{}'", token.text),
+                        &token,
+                    ));
+                }
+                self.expect_punctuation("(")?;
+                let mut args = Vec::new();
+                if !self.consume_punctuation(")") {
+                    loop {
+                        args.push(self.parse_set_value()?);
+                        if !self.consume_punctuation(",") {
+                            break;
+                        }
+                    }
+                    self.expect_punctuation(")")?;
+                }
+                Ok(FileExpr::Function {
+                    name: token.text.to_uppercase(),
+                    args,
+                })
+            }
+            _ if token.is_keyword("NULL") => Ok(FileExpr::Literal(FileValue::Null)),
+            _ if token.is_keyword("TRUE") => Ok(FileExpr::Literal(FileValue::Boolean(true))),
+            _ if token.is_keyword("FALSE") => Ok(FileExpr::Literal(FileValue::Boolean(false))),
+            _ => Err(syntax_error("Expected a value", &token)),
+        }
+    }
+
+    /// Parses the path following FROM or UPDATE. `STDIN` becomes
+    /// [`STDIN_PATH`].
+    fn parse_path(&mut self, missing: &str) -> Result<PathBuf> {
+        if self.consume_keyword("STDIN") {
+            return Ok(PathBuf::from(STDIN_PATH));
+        }
+        match self.peek() {
+            Some(token) if matches!(token.kind, TokenKind::Path | TokenKind::String) => {
+                let text = token.text.clone();
+                self.position += 1;
+                resolve_path(&text)
+            }
+            _ => Err(ParserError::MissingClause(missing.to_string())),
+        }
+    }
+
+    /// Parses the path of the files a statement reads, which may be a glob
+    /// pattern; see [`parse_path`](Self::parse_path). `TRASH` becomes the
+    /// directory the user's trashed files are in.
+    fn parse_source(&mut self, missing: &str) -> Result<PathBuf> {
+        if self.consume_keyword("TRASH") {
+            return trash_files().ok_or_else(|| {
+                ParserError::InvalidPath("Could not determine the trash directory".to_string())
+            });
+        }
+        let span = self.peek().map(|token| token.span);
+        let path = self.parse_path(missing)?;
+        if let Some(span) = span
+            && is_glob(&path)
+            && let Err(err) = glob::Pattern::new(&path.to_string_lossy())
+        {
+            return Err(ParserError::InvalidPattern {
+                message: err.msg.to_string(),
+                span,
+            });
+        }
+        Ok(path)
+    }
+
+    /// Parses an optional WHERE clause.
+    fn parse_where(&mut self) -> Result<Option<FileCondition>> {
+        if self.consume_keyword("WHERE") {
+            Ok(Some(self.parse_or()?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn parse_or(&mut self) -> Result<FileCondition> {
+        let mut condition = self.parse_xor()?;
+        while self.consume_keyword("OR") {
+            let right = self.parse_xor()?;
+            condition = FileCondition::Or(Box::new(condition), Box::new(right));
+        }
+        Ok(condition)
+    }
+
+    /// Parses `a XOR b`, which binds more tightly than OR and less tightly
+    /// than AND, and is rewritten as `(a AND NOT b) OR (NOT a AND b)`.
+    fn parse_xor(&mut self) -> Result<FileCondition> {
+        let mut condition = self.parse_and()?;
+        while self.consume_keyword("XOR") {
+            let right = self.parse_and()?;
+            let not = |c: &FileCondition| Box::new(FileCondition::Not(Box::new(c.clone())));
+            condition = FileCondition::Or(
+                Box::new(FileCondition::And(Box::new(condition.clone()), not(&right))),
+                Box::new(FileCondition::And(not(&condition), Box::new(right))),
+            );
+        }
+        Ok(condition)
+    }
+
+    fn parse_and(&mut self) -> Result<FileCondition> {
+        let mut condition = self.parse_not()?;
+        while self.consume_keyword("AND") {
+            let right = self.parse_not()?;
+            condition = FileCondition::And(Box::new(condition), Box::new(right));
+        }
+        Ok(condition)
+    }
+
+    fn parse_not(&mut self) -> Result<FileCondition> {
+        if self.consume_keyword("NOT") {
+            let inner = self.parse_not()?;
+            return Ok(FileCondition::Not(Box::new(inner)));
+        }
+        let condition = self.parse_primary()?;
+        self.parse_budget(condition)
+    }
+
+    /// Parses the optional `BUDGET '<duration>' PER FILE` after a
+    /// predicate, which caps the time it may take for each file. The
+    /// duration can be left unquoted, as in `BUDGET 2s PER FILE`.
+    fn parse_budget(&mut self, condition: FileCondition) -> Result<FileCondition> {
+        if !self.consume_keyword("BUDGET") {
+            return Ok(condition);
+        }
+        let token = self.next_token("a duration")?;
+        let text = match token.kind {
+            TokenKind::String => token.text.clone(),
+            // `2s` is lexed as a number and then its unit
+            TokenKind::Number => match self.peek() {
+                Some(unit)
+                    if unit.kind == TokenKind::Identifier && unit.span.start == token.span.end =>
+                {
+                    let text = format!("{}{}", token.text, unit.text);
+                    self.position += 1;
+                    text
+                }
+                _ => token.text.clone(),
+            },
+            _ => return Err(syntax_error("Expected a duration such as '2s'", &token)),
+        };
+        let limit = parse_duration(&text).map_err(|message| syntax_error(&message, &token))?;
+        if limit.is_zero() {
+            return Err(syntax_error("A budget must be longer than no time at all", &token));
+        }
+        let per_file = self.consume_keyword("PER")
+            && self.consume_if(|t| {
+                matches!(t.kind, TokenKind::Identifier | TokenKind::Keyword)
+                    && t.text.eq_ignore_ascii_case("FILE")
+            });
+        if !per_file {
+            let token = self.next_token("PER FILE")?;
+            return Err(syntax_error("Expected PER FILE after the budget", &token));
+        }
+        Ok(FileCondition::Budget {
+            condition: Box::new(condition),
+            limit,
+        })
+    }
+
+    /// Parses a parenthesised condition, a function-style predicate, or a
+    /// predicate on an attribute.
+    fn parse_primary(&mut self) -> Result<FileCondition> {
+        if self.peek().is_some_and(|t| t.kind == TokenKind::Punctuation && t.text == "(") {
+            return self.parse_parenthesised();
+        }
+
+        // Expressions that cannot start with an attribute: `YEAR(modified) = 2024`
+        let starts_expression = self.peek_qualified()
+            || self.peek().is_some_and(|t| {
+                t.kind == TokenKind::Number || t.is_keyword("INTERVAL") || starts_value_function(t)
+            });
+        if starts_expression {
+            let left = self.parse_set_value()?;
+            return self.parse_expression_comparison(left);
+        }
+
+        if self.peek().is_some_and(|t| t.kind == TokenKind::Function) {
+            return self.parse_function_predicate();
+        }
+
+        if self.consume_keyword("TRUE") {
+            return Ok(FileCondition::Constant(true));
+        }
+        if self.consume_keyword("FALSE") {
+            return Ok(FileCondition::Constant(false));
+        }
+
+        let attribute = self.parse_attribute()?;
+
+        // A bare boolean attribute is a condition by itself: `WHERE is_directory`
+        let is_boolean = column(&attribute).is_some_and(|c| c.value_type == ValueType::Boolean);
+        if is_boolean && !self.peek().is_some_and(starts_predicate) {
+            return Ok(FileCondition::Compare {
+                attribute,
+                operator: ComparisonOperator::Eq,
+                value: FileValue::Boolean(true),
+            });
+        }
+
+        if let Some(operator) = self.parse_is_null()? {
+            return Ok(FileCondition::Compare {
+                attribute,
+                operator,
+                value: FileValue::Null,
+            });
+        }
+
+        if self.consume_keyword("NOT") {
+            let inner = self.parse_attribute_predicate(attribute)?;
+            return Ok(FileCondition::Not(Box::new(inner)));
+        }
+
+        self.parse_attribute_predicate(attribute)
+    }
+
+    /// Parses `IS NULL` or `IS NOT NULL`, if that comes next, as the
+    /// comparison with NULL it stands for: `= NULL` or `!= NULL`.
+    fn parse_is_null(&mut self) -> Result<Option<ComparisonOperator>> {
+        if !self.consume_keyword("IS") {
+            return Ok(None);
+        }
+        let operator = if self.consume_keyword("NOT") {
+            ComparisonOperator::NotEq
+        } else {
+            ComparisonOperator::Eq
+        };
+        self.expect_keyword("NULL")?;
+        Ok(Some(operator))
+    }
+
+    /// Parses a predicate that follows an attribute name.
+    fn parse_attribute_predicate(&mut self, attribute: FileAttribute) -> Result<FileCondition> {
+        if self.consume_keyword("LIKE") {
+            let pattern = self.expect_pattern(|pattern| like_regex(pattern, false))?;
+            return Ok(FileCondition::Like {
+                attribute,
+                pattern,
+                case_sensitive: false,
+            });
+        }
+
+        if self.consume_keyword("REGEXP") {
+            let pattern = self.expect_pattern(Regex::new)?;
+            return Ok(FileCondition::Regexp { attribute, pattern });
+        }
+
+        if attribute == FileAttribute::Permissions && self.consume_operator("&") {
+            let mask = self.parse_perm_mask()?;
+            return self.parse_perm_mask_comparison(mask);
+        }
+
+        if self.consume_keyword("BETWEEN") {
+            let lower = self.parse_value_of(&attribute)?;
+            self.expect_keyword("AND")?;
+            let upper = self.parse_value_of(&attribute)?;
+            return Ok(FileCondition::Between {
+                attribute,
+                lower,
+                upper,
+            });
+        }
+
+        if self.peek_arithmetic() {
+            let left = self.parse_sum(Some(FileExpr::Attribute(attribute)))?;
+            return self.parse_expression_comparison(left);
+        }
+
+        let operator = self.parse_comparison_operator()?;
+
+        // `signature_extension != extension`: one attribute against another,
+        // against a column of the joined table such as `size != inv.size`,
+        // against a function such as `modified > EPOCH_MS(...)`, or against
+        // a parenthesised expression such as `size > (4 + 4) * 512`
+        if self.peek_qualified()
+            || self.peek().is_some_and(|t| {
+                t.kind == TokenKind::Attribute
+                    || starts_value_function(t)
+                    || (t.kind == TokenKind::Punctuation && t.text == "(")
+            })
+        {
+            let right = self.parse_set_value()?;
+            return Ok(FileCondition::Expression {
+                left: FileExpr::Attribute(attribute),
+                operator,
+                right,
+            });
+        }
+
+        let start = self.position;
+        let value = self.parse_value_of(&attribute)?;
+
+        // `size > 100 * 1024`: the right-hand side is an expression after all
+        if self.peek_arithmetic() {
+            self.position = start;
+            let right = self.parse_set_value()?;
+            return Ok(FileCondition::Expression {
+                left: FileExpr::Attribute(attribute),
+                operator,
+                right,
+            });
+        }
+
+        Ok(FileCondition::Compare {
+            attribute,
+            operator,
+            value,
+        })
+    }
+
+    /// Parses a parenthesised condition, or a comparison whose left-hand
+    /// side starts with a parenthesised expression, as in `(size - 10) % 2 = 0`.
+    fn parse_parenthesised(&mut self) -> Result<FileCondition> {
+        let start = self.position;
+        self.position += 1;
+        let grouped = self.parse_or().and_then(|condition| {
+            self.expect_punctuation(")")?;
+            Ok(condition)
+        });
+        let continues = self.peek_arithmetic()
+            || self.peek().is_some_and(|t| {
+                t.is_keyword("IS")
+                    || t.kind == TokenKind::Operator
+                        && matches!(t.text.as_str(), "=" | "!=" | "<>" | "<" | "<=" | ">" | ">=")
+            });
+        if grouped.is_ok() && !continues {
+            return grouped;
+        }
+
+        let end = self.position;
+        self.position = start;
+        match self
+            .parse_set_value()
+            .and_then(|left| self.parse_expression_comparison(left))
+        {
+            Ok(condition) => Ok(condition),
+            Err(err) => {
+                // Report the error from reading it as a condition, if it was one
+                self.position = end;
+                grouped.and(Err(err))
+            }
+        }
+    }
+
+    /// Parses the comparison operator and right-hand expression, or the
+    /// `IS NULL`, that follow an arithmetic expression.
+    fn parse_expression_comparison(&mut self, left: FileExpr) -> Result<FileCondition> {
+        if let Some(operator) = self.parse_is_null()? {
+            return Ok(FileCondition::Expression {
+                left,
+                operator,
+                right: FileExpr::Literal(FileValue::Null),
+            });
+        }
+        let operator = self.parse_comparison_operator()?;
+        let right = self.parse_set_value()?;
+        Ok(FileCondition::Expression {
+            left,
+            operator,
+            right,
+        })
+    }
+
+    /// Returns true if the next token is an arithmetic operator.
+    fn peek_arithmetic(&self) -> bool {
+        self.peek().is_some_and(|t| {
+            t.kind == TokenKind::Operator && ArithmeticOperator::from_symbol(&t.text).is_some()
+        })
+    }
+
+    /// Parses a function-style predicate such as `REGEXP(name, '^a')`.
+    fn parse_function_predicate(&mut self) -> Result<FileCondition> {
+        let token = self.next_token("a function")?;
+        self.expect_punctuation("(")?;
+
+        let condition = match token.text.to_uppercase().as_str() {
+            "REGEXP" => {
+                let attribute = self.parse_attribute()?;
+                self.expect_punctuation(",")?;
+                let pattern = self.expect_pattern(Regex::new)?;
+                FileCondition::Regexp { attribute, pattern }
+            }
+            "PERM_AND" => {
+                let attribute = self.parse_attribute()?;
+                if attribute != FileAttribute::Permissions {
+                    return Err(syntax_error("PERM_AND expects permissions", &token));
+                }
+                self.expect_punctuation(",")?;
+                let mask = self.parse_perm_mask()?;
+                self.expect_punctuation(")")?;
+                return self.parse_perm_mask_comparison(mask);
+            }
+            name @ ("STARTS_WITH" | "ENDS_WITH" | "CONTAINS_STR") => {
+                let position = match name {
+                    "STARTS_WITH" => SubstringPosition::Start,
+                    "ENDS_WITH" => SubstringPosition::End,
+                    _ => SubstringPosition::Anywhere,
+                };
+                let attribute = self.parse_attribute()?;
+                self.expect_punctuation(",")?;
+                let text = self.expect_string()?;
+                FileCondition::Substring {
+                    attribute,
+                    position,
+                    text,
+                }
+            }
+            "CONTAINS_BYTES" => {
+                let literal = self.next_token("a hex literal")?;
+                if literal.kind != TokenKind::Bytes {
+                    return Err(syntax_error("Expected a hex literal such as x'7f454c46'", &literal));
+                }
+                let bytes = parse_hex(&literal.text).map_err(|message| syntax_error(&message, &literal))?;
+                let max_bytes = if self.consume_punctuation(",") {
+                    let limit = self.next_token("a byte count")?;
+                    let count = limit
+                        .text
+                        .parse::<u64>()
+                        .ok()
+                        .filter(|_| limit.kind == TokenKind::Number)
+                        .ok_or_else(|| syntax_error("Expected a whole number of bytes", &limit))?;
+                    Some(count)
+                } else {
+                    None
+                };
+                FileCondition::ContainsBytes { bytes, max_bytes }
+            }
+            "HAS_PERM" => {
+                let spec = self.expect_string()?;
+                PermissionSpec::parse(&spec).map_err(|message| ParserError::Syntax {
+                    message,
+                    span: token.span,
+                })?;
+                FileCondition::HasPerm { spec }
+            }
+            "OWNER_IN_GROUP" => FileCondition::OwnerInGroup {
+                group: self.expect_string()?,
+            },
+            name @ ("ANY_OF" | "ALL_OF") => {
+                let any = name == "ANY_OF";
+                let conditions = if any && self.at_value_list() {
+                    self.parse_membership()?
+                } else {
+                    let mut conditions = vec![self.parse_or()?];
+                    while self.consume_punctuation(",") {
+                        conditions.push(self.parse_or()?);
+                    }
+                    conditions
+                };
+                let combine = |left, right| {
+                    if any {
+                        FileCondition::Or(Box::new(left), Box::new(right))
+                    } else {
+                        FileCondition::And(Box::new(left), Box::new(right))
+                    }
+                };
+                conditions
+                    .into_iter()
+                    .reduce(combine)
+                    .expect("at least one condition is parsed")
+            }
+            "TAGGED" => {
+                let tag = self.expect_string()?;
+                let store = TagStore::default_path().ok_or_else(|| ParserError::Syntax {
+                    message: "TAGGED needs a configuration directory to keep tags in".to_string(),
+                    span: token.span,
+                })?;
+                FileCondition::Tagged { tag, store }
+            }
+            "PERM_MATCHES" => {
+                let pattern = self.expect_string()?;
+                matches_octal_pattern(0, &pattern).map_err(|message| ParserError::Syntax {
+                    message,
+                    span: token.span,
+                })?;
+                FileCondition::PermMatches { pattern }
+            }
+            _ => {
+                return Err(syntax_error(
+                    &format!("Unknown function '{}'", token.text),
+                    &token,
+                ));
+            }
+        };
+
+        self.expect_punctuation(")")?;
+        Ok(condition)
+    }
+
+    /// Returns true if the arguments ahead are an attribute followed by
+    /// literal values, as in `ANY_OF(extension, 'jpg', 'png')`, rather than
+    /// a list of conditions, as in `ANY_OF(TRUE, FALSE)` or
+    /// `ANY_OF(is_mine, 1024 < size)`.
+    fn at_value_list(&self) -> bool {
+        let token = |offset| self.tokens.get(self.position + offset);
+        let is_comma = |t: &Token| t.kind == TokenKind::Punctuation && t.text == ",";
+        let is_negative = |t: &Token| t.kind == TokenKind::Operator && t.text == "-";
+        let is_literal = |t: &Token| {
+            matches!(t.kind, TokenKind::String | TokenKind::Number)
+                || ["TRUE", "FALSE", "NULL"].iter().any(|k| t.is_keyword(k))
+        };
+        let ends_value =
+            |t: &Token| is_comma(t) || (t.kind == TokenKind::Punctuation && t.text == ")");
+        let value = if token(2).is_some_and(is_negative) { 3 } else { 2 };
+        token(0).is_some_and(|t| matches!(t.kind, TokenKind::Attribute | TokenKind::Identifier))
+            && token(1).is_some_and(is_comma)
+            && token(value).is_some_and(is_literal)
+            && token(value + 1).is_some_and(ends_value)
+    }
+
+    /// Parses `attribute, value, ...` into one equality test per value.
+    fn parse_membership(&mut self) -> Result<Vec<FileCondition>> {
+        let attribute = self.parse_attribute()?;
+        let mut conditions = Vec::new();
+        while self.consume_punctuation(",") {
+            conditions.push(FileCondition::Compare {
+                attribute: attribute.clone(),
+                operator: ComparisonOperator::Eq,
+                value: self.parse_value_of(&attribute)?,
+            });
+        }
+        Ok(conditions)
+    }
+
+    /// Parses a permission mask, written as a number (`0o022`, `18`) or an
+    /// octal string (`'022'`).
+    fn parse_perm_mask(&mut self) -> Result<u32> {
+        let token = self.next_token("a permission mask")?;
+        let mask = match token.kind {
+            TokenKind::Number => parse_number(&token.text)
+                .filter(|n| n.fract() == 0.0 && (0.0..=0o7777 as f64).contains(n))
+                .map(|n| n as u32),
+            TokenKind::String => parse_octal(&token.text),
+            _ => None,
+        };
+        mask.ok_or_else(|| syntax_error("Expected a permission mask", &token))
+    }
+
+    /// Parses the comparison that follows a masked permissions expression.
+    fn parse_perm_mask_comparison(&mut self, mask: u32) -> Result<FileCondition> {
+        let operator = self.parse_comparison_operator()?;
+        let value = self.parse_value_of(&FileAttribute::Permissions)?;
+        Ok(FileCondition::PermMask {
+            mask,
+            operator,
+            value,
+        })
+    }
+
+    fn parse_attribute(&mut self) -> Result<FileAttribute> {
+        let token = self.next_token("an attribute")?;
+        match token.kind {
+            TokenKind::Attribute | TokenKind::Identifier | TokenKind::Keyword => {
+                FileAttribute::from_name(&token.text).ok_or(ParserError::UnknownAttribute {
+                    name: token.text,
+                    span: token.span,
+                })
+            }
+            _ => Err(syntax_error("Expected an attribute", &token)),
+        }
+    }
+
+    fn parse_comparison_operator(&mut self) -> Result<ComparisonOperator> {
+        let token = self.next_token("a comparison operator")?;
+        let operator = match (token.kind, token.text.as_str()) {
+            (TokenKind::Operator, "=") => ComparisonOperator::Eq,
+            (TokenKind::Operator, "!=" | "<>") => ComparisonOperator::NotEq,
+            (TokenKind::Operator, "<") => ComparisonOperator::Lt,
+            (TokenKind::Operator, "<=") => ComparisonOperator::LtEq,
+            (TokenKind::Operator, ">") => ComparisonOperator::Gt,
+            (TokenKind::Operator, ">=") => ComparisonOperator::GtEq,
+            _ => return Err(syntax_error("Expected a comparison operator", &token)),
+        };
+        Ok(operator)
+    }
+
+    /// Parses a literal value.
+    /// Parses a literal compared with `attribute`. Permissions are written
+    /// as octal strings, so a string that isn't one is an error here rather
+    /// than on every file the condition is evaluated for.
+    fn parse_value_of(&mut self, attribute: &FileAttribute) -> Result<FileValue> {
+        let token = self.peek().cloned();
+        let value = self.parse_value()?;
+        match (&value, token) {
+            (FileValue::String(text), Some(token))
+                if *attribute == FileAttribute::Permissions && parse_octal(text).is_none() =>
+            {
+                Err(syntax_error("Invalid permissions value", &token))
+            }
+            _ => Ok(value),
+        }
+    }
+
+    fn parse_value(&mut self) -> Result<FileValue> {
+        let token = self.next_token("a value")?;
+        match token.kind {
+            TokenKind::String => Ok(FileValue::String(token.text)),
+            TokenKind::Number => {
+                parse_numeric(&token.text).ok_or_else(|| syntax_error("Invalid number", &token))
+            }
+            TokenKind::Operator if token.text == "-" => {
+                let number = self.next_token("a number")?;
+                match parse_numeric(&number.text) {
+                    Some(FileValue::Integer(n)) if number.kind == TokenKind::Number => Ok(n
+                        .checked_neg()
+                        .map_or(FileValue::Number(-(n as f64)), FileValue::Integer)),
+                    Some(FileValue::Number(n)) if number.kind == TokenKind::Number => {
+                        Ok(FileValue::Number(-n))
+                    }
+                    _ => Err(syntax_error("Expected a number", &number)),
+                }
+            }
+            TokenKind::Keyword if token.is_keyword("INTERVAL") => self.parse_interval(),
+            TokenKind::Keyword if token.is_keyword("TRUE") => Ok(FileValue::Boolean(true)),
+            TokenKind::Keyword if token.is_keyword("FALSE") => Ok(FileValue::Boolean(false)),
+            TokenKind::Keyword if token.is_keyword("NULL") => Ok(FileValue::Null),
+            _ => Err(syntax_error("Expected a value", &token)),
+        }
+    }
+
+    /// Parses the string after `INTERVAL`, such as `'90 days'` or `'2h30m'`.
+    fn parse_interval(&mut self) -> Result<FileValue> {
+        let token = self.next_token("an interval such as '90 days'")?;
+        if token.kind != TokenKind::String {
+            return Err(syntax_error("Expected an interval such as '90 days'", &token));
+        }
+        parse_interval(&token.text)
+            .map(FileValue::Duration)
+            .map_err(|message| syntax_error(&message, &token))
+    }
+
+    fn expect_string(&mut self) -> Result<String> {
+        let token = self.next_token("a string")?;
+        match token.kind {
+            TokenKind::String => Ok(token.text),
+            _ => Err(syntax_error("Expected a string literal", &token)),
+        }
+    }
+
+    /// Parses a string literal that `compile` has to accept, so that a bad
+    /// pattern is reported before any file is read.
+    fn expect_pattern<T>(
+        &mut self,
+        compile: impl Fn(&str) -> std::result::Result<T, regex::Error>,
+    ) -> Result<String> {
+        let token = self.next_token("a string")?;
+        if token.kind != TokenKind::String {
+            return Err(syntax_error("Expected a string literal", &token));
+        }
+        match compile(&token.text) {
+            Ok(_) => Ok(token.text),
+            Err(err) => Err(ParserError::InvalidPattern {
+                message: regex_message(&err),
+                span: token.span,
+            }),
+        }
+    }
+
+    fn expect_keyword(&mut self, keyword: &str) -> Result<()> {
+        if self.consume_keyword(keyword) {
+            return Ok(());
+        }
+        let token = self.next_token(keyword)?;
+        Err(syntax_error(&format!("Expected {}", keyword), &token))
+    }
+
+    fn expect_operator(&mut self, operator: &str) -> Result<()> {
+        if self.consume_operator(operator) {
+            return Ok(());
+        }
+        let token = self.next_token(operator)?;
+        Err(syntax_error(&format!("Expected '{}'", operator), &token))
+    }
+
+    fn expect_punctuation(&mut self, punctuation: &str) -> Result<()> {
+        if self.consume_punctuation(punctuation) {
+            return Ok(());
+        }
+        let token = self.next_token(punctuation)?;
+        Err(syntax_error(&format!("Expected '{}'", punctuation), &token))
+    }
+
+    /// Fails if any tokens remain after a complete statement.
+    fn expect_end(&mut self) -> Result<()> {
+        self.consume_punctuation(";");
+        match self.peek() {
+            Some(token) => Err(syntax_error("Unexpected trailing input", token)),
+            None => Ok(()),
+        }
+    }
+
+    fn consume_keyword(&mut self, keyword: &str) -> bool {
+        self.consume_if(|t| t.is_keyword(keyword))
+    }
+
+    fn consume_operator(&mut self, operator: &str) -> bool {
+        self.consume_if(|t| t.kind == TokenKind::Operator && t.text == operator)
+    }
+
+    fn consume_punctuation(&mut self, punctuation: &str) -> bool {
+        self.consume_if(|t| t.kind == TokenKind::Punctuation && t.text == punctuation)
+    }
+
+    fn consume_if(&mut self, predicate: impl Fn(&Token) -> bool) -> bool {
+        match self.peek() {
+            Some(token) if predicate(token) => {
+                self.position += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    /// Returns where the next token starts, or a syntax error at the end of
+    /// the input.
+    fn next_span_start(&mut self, expected: &str) -> Result<Position> {
+        match self.peek() {
+            Some(token) => Ok(token.span.start),
+            None => self.next_token(expected).map(|t| t.span.start),
+        }
+    }
+
+    /// Returns the next token, or a syntax error at the end of the input.
+    fn next_token(&mut self, expected: &str) -> Result<Token> {
+        match self.tokens.get(self.position) {
+            Some(token) => {
+                self.position += 1;
+                Ok(token.clone())
+            }
+            None => {
+                let end = self
+                    .tokens
+                    .last()
+                    .map(|t| t.span.end)
+                    .unwrap_or(Position { line: 1, column: 1 });
+                Err(ParserError::Syntax {
+                    message: format!("Expected {} but reached the end of the query", expected),
+                    span: Span { start: end, end },
+                })
+            }
+        }
+    }
+}
+
+/// Returns true if `token` starts a function that gives a value, such as
+/// `YEAR(` or `CAST(`, rather than a predicate such as `HAS_PERM(`.
+fn starts_value_function(token: &Token) -> bool {
+    token.kind == TokenKind::Function
+        && (is_scalar(&token.text) || token.text.eq_ignore_ascii_case("CAST"))
+}
+
+/// Returns true if `token` can continue a predicate on an attribute, as
+/// `=` or `LIKE` can, rather than end it, as `AND` or `)` do.
+fn starts_predicate(token: &Token) -> bool {
+    token.kind == TokenKind::Operator
+        || ["NOT", "LIKE", "REGEXP", "BETWEEN", "IS"]
+            .iter()
+            .any(|keyword| token.is_keyword(keyword))
+}
+
+/// Checks that every plain column in a grouped SELECT is one of its GROUP BY
+/// keys, since other values differ between the files of a group.
+fn check_grouped_columns(columns: &[ProjectedColumn], group_by: &[ComputedColumn]) -> Result<()> {
+    for column in columns {
+        let grouped = group_by.iter().any(|key| match (&column.attribute, &key.expr) {
+            (Some(attribute), FileExpr::Attribute(key)) => attribute == key,
+            // A computed key named like an attribute is a different value
+            (Some(_), _) => false,
+            (None, _) => key.name.eq_ignore_ascii_case(&column.name),
+        });
+        if !grouped {
+            let message = if column.name == "*" {
+                "SELECT * can't be used with GROUP BY or aggregates; list the grouped columns instead"
+                    .to_string()
+            } else {
+                format!(
+                    "'{}' must be in GROUP BY or inside an aggregate such as SUM()",
+                    column.name
+                )
+            };
+            return Err(ParserError::Syntax {
+                message,
+                span: column.span,
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Returns true if `expr` can differ between files: it reads an attribute
+/// or a joined column somewhere, rather than being a constant.
+fn depends_on_file(expr: &FileExpr) -> bool {
+    match expr {
+        FileExpr::Literal(_) => false,
+        FileExpr::Attribute(_) | FileExpr::Joined { .. } => true,
+        FileExpr::Function { args, .. } => args.iter().any(depends_on_file),
+        // Its conditions may be about the file
+        FileExpr::Case { .. } => true,
+        FileExpr::Binary { left, right, .. } => depends_on_file(left) || depends_on_file(right),
+        FileExpr::Cast { expr, .. } => depends_on_file(expr),
+    }
+}
+
+/// Checks a `ROLLUP BY path` query: it lists aggregates for each
+/// directory, so there is nothing else to select or group by.
+fn check_rollup(keyword: &Token, projection: &Projection, group_by: &[ComputedColumn]) -> Result<()> {
+    if !group_by.is_empty() {
+        return Err(rollup_error("ROLLUP BY path can't be combined with GROUP BY", keyword));
+    }
+    if let Some(column) = projection.columns.first() {
+        return Err(ParserError::Syntax {
+            message: format!(
+                "ROLLUP BY path lists each directory with its aggregates; '{}' can't be selected",
+                column.name
+            ),
+            span: column.span,
+        });
+    }
+    if projection.aggregates.is_empty() {
+        return Err(rollup_error("ROLLUP BY path needs aggregates such as SUM(size)", keyword));
+    }
+    Ok(())
+}
+
+/// The error for a clause that only makes sense on files, such as
+/// `LIMIT ... BY size`, on a query that returns groups.
+fn files_only_error(clause: &str, keyword: &Token) -> ParserError {
+    ParserError::Syntax {
+        message: format!("{} needs a query that returns files, not groups", clause),
+        span: keyword.span,
+    }
+}
+
+/// Builds an error about a ROLLUP clause, located at its keyword.
+fn rollup_error(message: &str, keyword: &Token) -> ParserError {
+    ParserError::Syntax {
+        message: message.to_string(),
+        span: keyword.span,
+    }
+}
+
+/// Splits a qualified column such as `inventory.team` into its table and
+/// column. The dialect reads both halves and the dot as one identifier.
+fn qualified(text: &str) -> Option<(&str, &str)> {
+    let (table, column) = text.split_once('.')?;
+    let is_name = |name: &str| {
+        name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+    };
+    (is_name(table) && is_name(column)).then_some((table, column))
+}
+
+/// Builds a syntax error located at a token.
+fn syntax_error(message: &str, token: &Token) -> ParserError {
+    ParserError::Syntax {
+        message: format!("{}, found '{}'", message, token.text),
+        span: token.span,
+    }
+}
+
+/// Returns the reason a regular expression didn't compile, without the
+/// copy of the pattern the regex crate prints above it.
+fn regex_message(err: &regex::Error) -> String {
+    match err {
+        regex::Error::Syntax(text) => text
+            .lines()
+            .rfind(|line| line.starts_with("error: "))
+            .map_or(text.as_str(), |line| &line["error: ".len()..])
+            .to_string(),
+        _ => err.to_string(),
+    }
+}
+
+/// Resolves the path text from a FROM or UPDATE clause.
+///
+/// # Path Resolution
+///
+/// The function handles several special cases:
+/// - `~` expands to the user's home directory
+/// - `~/path` expands to a path within the home directory
+/// - Relative paths are preserved as-is
+/// - Absolute paths are preserved as-is
+fn resolve_path(path_str: &str) -> Result<PathBuf> {
+    // Handle home directory expansion
+    let path = if path_str.starts_with("~/") {
+        if let Some(home_dir) = dirs::home_dir() {
+            home_dir.join(
+                path_str
+                    .strip_prefix("~/")
+                    .expect(r#"We've already checked path_str.starts_with("~/")"#),
+            )
+        } else {
+            return Err(ParserError::InvalidPath(
+                "Could not determine home directory".to_string(),
+            ));
+        }
+    } else if path_str.starts_with('~') {
+        if let Some(home_dir) = dirs::home_dir() {
+            home_dir
+        } else {
+            return Err(ParserError::InvalidPath(
+                "Could not determine home directory".to_string(),
+            ));
+        }
+    } else {
+        PathBuf::from(path_str)
+    };
+
+    Ok(path)
+}
+
+// Include the tests module
+#[cfg(test)]
+#[path = "parser_tests.rs"]
+mod tests;
+
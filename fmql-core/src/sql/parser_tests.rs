@@ -0,0 +1,1866 @@
+#[cfg(test)]
+use crate::sql::ast::{
+    ArithmeticOperator, ComparisonOperator, CompressFormat, ConflictPolicy, EntryType, FileAttribute,
+    FileCondition, FileExpr, FileQuery, FileValue, Preserve, SubstringPosition,
+};
+use crate::sql::parser::{parse_sql, ParserError};
+
+#[test]
+fn test_parse_select_all() {
+    let sql = "SELECT * FROM ~/Documents";
+    let query = parse_sql(sql).unwrap();
+    
+    match query {
+        FileQuery::Select { path, recursive, attributes, condition, .. } => {
+            assert!(path.ends_with("Documents"));
+            assert!(!recursive);
+            assert_eq!(attributes.len(), 1);
+            assert!(matches!(attributes[0], FileAttribute::All));
+            assert!(condition.is_none());
+        },
+        _ => panic!("Expected SELECT query"),
+    }
+}
+
+#[test]
+fn test_parse_select_with_condition() {
+    let sql = "SELECT * FROM ~/Documents WHERE extension = '.txt'";
+    let query = parse_sql(sql).unwrap();
+    
+    match query {
+        FileQuery::Select { path, recursive, attributes, condition, .. } => {
+            assert!(path.ends_with("Documents"));
+            assert!(!recursive);
+            assert_eq!(attributes.len(), 1);
+            assert!(matches!(attributes[0], FileAttribute::All));
+            
+            match condition {
+                Some(FileCondition::Compare { attribute, operator, value }) => {
+                    assert!(matches!(attribute, FileAttribute::Extension));
+                    assert!(matches!(operator, ComparisonOperator::Eq));
+                    assert!(matches!(value, FileValue::String(s) if s == ".txt"));
+                },
+                _ => panic!("Expected Compare condition"),
+            }
+        },
+        _ => panic!("Expected SELECT query"),
+    }
+}
+
+#[test]
+fn test_parse_select_with_complex_condition() {
+    let sql = "SELECT * FROM ~/Documents WHERE extension = '.txt' AND size > 1000";
+    let query = parse_sql(sql).unwrap();
+    
+    match query {
+        FileQuery::Select { path, recursive, attributes, condition, .. } => {
+            assert!(path.ends_with("Documents"));
+            assert!(!recursive);
+            assert_eq!(attributes.len(), 1);
+            assert!(matches!(attributes[0], FileAttribute::All));
+            
+            match condition {
+                Some(FileCondition::And(left, right)) => {
+                    match (*left, *right) {
+                        (
+                            FileCondition::Compare { 
+                                attribute: attr1, 
+                                operator: op1, 
+                                value: val1 
+                            },
+                            FileCondition::Compare { 
+                                attribute: attr2, 
+                                operator: op2, 
+                                value: val2 
+                            }
+                        ) => {
+                            assert!(matches!(attr1, FileAttribute::Extension));
+                            assert!(matches!(op1, ComparisonOperator::Eq));
+                            assert!(matches!(val1, FileValue::String(s) if s == ".txt"));
+                            
+                            assert!(matches!(attr2, FileAttribute::Size));
+                            assert!(matches!(op2, ComparisonOperator::Gt));
+                            assert!(matches!(val2, FileValue::Integer(1000)));
+                        },
+                        _ => panic!("Expected two Compare conditions"),
+                    }
+                },
+                _ => panic!("Expected AND condition"),
+            }
+        },
+        _ => panic!("Expected SELECT query"),
+    }
+}
+
+#[test]
+fn test_parse_select_with_between() {
+    let sql = "SELECT * FROM ~/Documents WHERE modified BETWEEN '2025-01-01' AND '2025-03-31'";
+    let query = parse_sql(sql).unwrap();
+    
+    match query {
+        FileQuery::Select { path, recursive, attributes, condition, .. } => {
+            assert!(path.ends_with("Documents"));
+            assert!(!recursive);
+            assert_eq!(attributes.len(), 1);
+            assert!(matches!(attributes[0], FileAttribute::All));
+            
+            match condition {
+                Some(FileCondition::Between { attribute, lower, upper }) => {
+                    assert!(matches!(attribute, FileAttribute::Modified));
+                    assert!(matches!(lower, FileValue::String(s) if s == "2025-01-01"));
+                    assert!(matches!(upper, FileValue::String(s) if s == "2025-03-31"));
+                },
+                _ => panic!("Expected BETWEEN condition"),
+            }
+        },
+        _ => panic!("Expected SELECT query"),
+    }
+}
+
+#[test]
+fn test_parse_select_with_like() {
+    let sql = "SELECT * FROM ~/Projects WHERE name LIKE '%config%'";
+    let query = parse_sql(sql).unwrap();
+    
+    match query {
+        FileQuery::Select { path, recursive, attributes, condition, .. } => {
+            assert!(path.ends_with("Projects"));
+            assert!(!recursive);
+            assert_eq!(attributes.len(), 1);
+            assert!(matches!(attributes[0], FileAttribute::All));
+            
+            match condition {
+                Some(FileCondition::Like { attribute, pattern, case_sensitive }) => {
+                    assert!(matches!(attribute, FileAttribute::Name));
+                    assert_eq!(pattern, "%config%");
+                    assert!(!case_sensitive);
+                },
+                _ => panic!("Expected LIKE condition"),
+            }
+        },
+        _ => panic!("Expected SELECT query"),
+    }
+}
+
+#[test]
+fn test_parse_select_with_like_pattern_variations() {
+    let test_cases = vec![
+        ("SELECT * FROM ~/Downloads WHERE name LIKE '%meme'", "%meme"),
+        ("SELECT * FROM ~/Documents WHERE name LIKE 'meme%'", "meme%"),
+        ("SELECT * FROM ~/Pictures WHERE name LIKE '%meme%'", "%meme%"),
+        ("SELECT * FROM ~/Videos WHERE name LIKE 'm_me'", "m_me"),
+        ("SELECT * FROM ~/Music WHERE name LIKE '_e%e_'", "_e%e_"),
+    ];
+
+    for (sql, expected_pattern) in test_cases {
+        let query = parse_sql(sql).unwrap();
+        match query {
+            FileQuery::Select { condition, .. } => {
+                match condition {
+                    Some(FileCondition::Like { attribute, pattern, case_sensitive }) => {
+                        assert!(matches!(attribute, FileAttribute::Name));
+                        assert_eq!(pattern, expected_pattern);
+                        assert!(!case_sensitive, "Expected case_sensitive to be false for: {}", sql);
+                    },
+                    _ => panic!("Expected LIKE condition for SQL: {}", sql),
+                }
+            },
+            _ => panic!("Expected SELECT query for SQL: {}", sql),
+        }
+    }
+}
+
+#[test]
+fn test_parse_select_with_regexp() {
+    let sql = "SELECT * FROM ~/logs WHERE REGEXP(name, '^server_[0-9]+\\.log$')";
+    let query = parse_sql(sql).unwrap();
+    
+    match query {
+        FileQuery::Select { path, recursive, attributes, condition, .. } => {
+            assert!(path.ends_with("logs"));
+            assert!(!recursive);
+            assert_eq!(attributes.len(), 1);
+            assert!(matches!(attributes[0], FileAttribute::All));
+            
+            match condition {
+                Some(FileCondition::Regexp { attribute, pattern }) => {
+                    assert!(matches!(attribute, FileAttribute::Name));
+                    assert_eq!(pattern, "^server_[0-9]+\\.log$");
+                },
+                _ => panic!("Expected REGEXP condition"),
+            }
+        },
+        _ => panic!("Expected SELECT query"),
+    }
+}
+
+#[test]
+fn test_parse_select_with_recursive() {
+    let sql = "WITH RECURSIVE SELECT * FROM ~/Projects WHERE name LIKE '%config%'";
+    let query = parse_sql(sql).unwrap();
+    
+    match query {
+        FileQuery::Select { path, recursive, attributes, condition, .. } => {
+            assert!(path.ends_with("Projects"));
+            assert!(recursive);
+            assert_eq!(attributes.len(), 1);
+            assert!(matches!(attributes[0], FileAttribute::All));
+            
+            match condition {
+                Some(FileCondition::Like { attribute, pattern, case_sensitive }) => {
+                    assert!(matches!(attribute, FileAttribute::Name));
+                    assert_eq!(pattern, "%config%");
+                    assert!(!case_sensitive);
+                },
+                _ => panic!("Expected LIKE condition"),
+            }
+        },
+        _ => panic!("Expected SELECT query"),
+    }
+}
+
+#[test]
+fn test_parse_update() {
+    let sql = "UPDATE ~/executables SET permissions = '755' WHERE extension = '.bin'";
+    let query = parse_sql(sql).unwrap();
+    
+    match query {
+        FileQuery::Update { path, updates, condition, .. } => {
+            assert!(path.ends_with("executables"));
+            assert_eq!(updates.len(), 1);
+            
+            let update = &updates[0];
+            assert!(matches!(update.attribute, FileAttribute::Permissions));
+            assert!(matches!(&update.value, FileExpr::Literal(FileValue::String(s)) if s == "755"));
+            
+            match condition {
+                Some(FileCondition::Compare { attribute, operator, value }) => {
+                    assert!(matches!(attribute, FileAttribute::Extension));
+                    assert!(matches!(operator, ComparisonOperator::Eq));
+                    assert!(matches!(value, FileValue::String(s) if s == ".bin"));
+                },
+                _ => panic!("Expected Compare condition"),
+            }
+        },
+        _ => panic!("Expected UPDATE query"),
+    }
+}
+
+#[test]
+fn test_parse_update_multiple_attributes() {
+    let sql = "UPDATE ~/executables SET owner = 'admin', permissions = '755' WHERE extension = '.bin'";
+    let query = parse_sql(sql).unwrap();
+    
+    match query {
+        FileQuery::Update { path, updates, condition, .. } => {
+            assert!(path.ends_with("executables"));
+            assert_eq!(updates.len(), 2);
+            
+            assert!(matches!(updates[0].attribute, FileAttribute::Owner));
+            assert!(matches!(&updates[0].value, FileExpr::Literal(FileValue::String(s)) if s == "admin"));
+            
+            assert!(matches!(updates[1].attribute, FileAttribute::Permissions));
+            assert!(matches!(&updates[1].value, FileExpr::Literal(FileValue::String(s)) if s == "755"));
+            
+            match condition {
+                Some(FileCondition::Compare { attribute, operator, value }) => {
+                    assert!(matches!(attribute, FileAttribute::Extension));
+                    assert!(matches!(operator, ComparisonOperator::Eq));
+                    assert!(matches!(value, FileValue::String(s) if s == ".bin"));
+                },
+                _ => panic!("Expected Compare condition"),
+            }
+        },
+        _ => panic!("Expected UPDATE query"),
+    }
+}
+
+#[test]
+fn test_parse_invalid_sql() {
+    let sql = "INSERT INTO ~/Documents VALUES ('file.txt')";
+    let result = parse_sql(sql);
+    
+    assert!(result.is_err());
+    match result {
+        Err(ParserError::UnsupportedStatement(_)) => {},
+        _ => panic!("Expected UnsupportedStatement error"),
+    }
+}
+
+#[test]
+fn test_parse_syntax_error_has_span() {
+    let sql = "SELECT * FROM ~/Documents WHERE size >";
+    let err = parse_sql(sql).unwrap_err();
+
+    match err {
+        ParserError::Syntax { span, .. } => {
+            assert_eq!(span.start.line, 1);
+            assert_eq!(span.start.column, 39);
+        },
+        other => panic!("Expected Syntax error, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_parse_unknown_attribute() {
+    let sql = "SELECT * FROM . WHERE colour = 'red'";
+    let err = parse_sql(sql).unwrap_err();
+
+    match err {
+        ParserError::UnknownAttribute { name, span } => {
+            assert_eq!(name, "colour");
+            assert_eq!(span.start.column, 23);
+        },
+        other => panic!("Expected UnknownAttribute error, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_unknown_words_in_expressions_are_unknown_attributes() {
+    // A misspelled column is an error, not a string constant
+    for (sql, word) in [
+        ("SELECT UPPER(nmae) AS n FROM .", "nmae"),
+        ("SELECT COUNT(*) FROM . GROUP BY extention", "extention"),
+        ("UPDATE . SET name = CONCAT(stem, '.txt')", "stem"),
+    ] {
+        match parse_sql(sql).unwrap_err() {
+            ParserError::UnknownAttribute { name, .. } => assert_eq!(name, word, "{}", sql),
+            other => panic!("{}: expected UnknownAttribute error, got {:?}", sql, other),
+        }
+    }
+}
+
+#[test]
+fn test_tokenize_classifies_tokens() {
+    use crate::sql::lexer::{tokenize, TokenKind};
+
+    let tokens = tokenize("SELECT * FROM ~/logs WHERE REGEXP(name, 'a') -- note").unwrap();
+    let kinds: Vec<TokenKind> = tokens.iter().map(|t| t.kind).collect();
+
+    assert_eq!(kinds, vec![
+        TokenKind::Keyword,
+        TokenKind::Operator,
+        TokenKind::Keyword,
+        TokenKind::Path,
+        TokenKind::Keyword,
+        TokenKind::Function,
+        TokenKind::Punctuation,
+        TokenKind::Attribute,
+        TokenKind::Punctuation,
+        TokenKind::String,
+        TokenKind::Punctuation,
+        TokenKind::Comment,
+    ]);
+    assert_eq!(tokens[3].text, "~/logs");
+    assert_eq!(tokens[3].span.start.column, 15);
+    assert_eq!(tokens[3].span.end.column, 21);
+}
+
+#[test]
+fn test_catalog_columns_match_attributes() {
+    use crate::sql::catalog::columns;
+
+    for column in columns() {
+        let attribute = FileAttribute::from_name(column.name)
+            .unwrap_or_else(|| panic!("Catalog column {} is not an attribute", column.name));
+        assert_eq!(attribute.name(), column.name);
+    }
+}
+
+#[test]
+fn test_complete_words_and_paths() {
+    use crate::sql::completion::complete;
+
+    let line = "SELECT * FROM . WHERE is_d";
+    let completion = complete(line, line.len());
+    assert_eq!(completion.start, 22);
+    assert_eq!(completion.candidates, vec!["is_directory".to_string()]);
+
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::create_dir(dir.path().join("reports")).unwrap();
+    std::fs::write(dir.path().join("readme.txt"), "").unwrap();
+
+    let line = format!("SELECT * FROM {}/re", dir.path().display());
+    let completion = complete(&line, line.len());
+    assert_eq!(completion.start, 14);
+    assert_eq!(completion.candidates, vec![
+        format!("{}/readme.txt", dir.path().display()),
+        format!("{}/reports/", dir.path().display()),
+    ]);
+}
+
+#[test]
+fn test_parse_permission_helpers() {
+    let query = parse_sql("SELECT * FROM . WHERE HAS_PERM('g+w') OR PERM_MATCHES('??5')").unwrap();
+
+    match query {
+        FileQuery::Select { condition: Some(FileCondition::Or(left, right)), .. } => {
+            assert!(matches!(*left, FileCondition::HasPerm { spec } if spec == "g+w"));
+            assert!(matches!(*right, FileCondition::PermMatches { pattern } if pattern == "??5"));
+        },
+        _ => panic!("Expected OR of permission helpers"),
+    }
+
+    assert!(matches!(
+        parse_sql("SELECT * FROM . WHERE HAS_PERM('q+w')"),
+        Err(ParserError::Syntax { .. })
+    ));
+    assert!(matches!(
+        parse_sql("SELECT * FROM . WHERE PERM_MATCHES('9')"),
+        Err(ParserError::Syntax { .. })
+    ));
+}
+
+#[test]
+fn test_parse_permission_mask() {
+    for sql in [
+        "SELECT * FROM . WHERE PERM_AND(permissions, 0o022) != 0",
+        "SELECT * FROM . WHERE permissions & 0o022 != 0",
+        "SELECT * FROM . WHERE permissions & '022' != 0",
+    ] {
+        match parse_sql(sql).unwrap() {
+            FileQuery::Select { condition: Some(FileCondition::PermMask { mask, operator, value }), .. } => {
+                assert_eq!(mask, 0o022, "{}", sql);
+                assert_eq!(operator, ComparisonOperator::NotEq);
+                assert_eq!(value, FileValue::Integer(0));
+            },
+            other => panic!("Expected PermMask condition for {}, got {:?}", sql, other),
+        }
+    }
+
+    assert!(parse_sql("SELECT * FROM . WHERE PERM_AND(size, 0o022) != 0").is_err());
+}
+
+#[test]
+fn test_parse_invalid_permissions_value() {
+    for sql in [
+        "SELECT * FROM . WHERE permissions = '9'",
+        "SELECT * FROM . WHERE permissions BETWEEN '600' AND '9'",
+        "SELECT * FROM . WHERE ANY_OF(permissions, '644', 'rw')",
+        "SELECT * FROM . WHERE permissions & '022' = '9'",
+    ] {
+        match parse_sql(sql) {
+            Err(ParserError::Syntax { message, span }) => {
+                assert!(message.starts_with("Invalid permissions value"), "{}: {}", sql, message);
+                assert!(sql[span.start.column as usize - 1..].starts_with('\''), "{}", sql);
+            },
+            other => panic!("Expected Syntax error for {}, got {:?}", sql, other),
+        }
+    }
+
+    // Octal strings and numbers are fine, and other attributes take any string
+    assert!(parse_sql("SELECT * FROM . WHERE permissions = '0644' OR permissions = 420").is_ok());
+    assert!(parse_sql("SELECT * FROM . WHERE name = '9'").is_ok());
+}
+
+#[test]
+fn test_parse_update_with_case() {
+    let sql = "UPDATE ~/site SET permissions = CASE WHEN is_directory = TRUE THEN '755' ELSE '644' END";
+
+    match parse_sql(sql).unwrap() {
+        FileQuery::Update { updates, condition, .. } => {
+            assert!(condition.is_none());
+            match &updates[0].value {
+                FileExpr::Case { branches, otherwise } => {
+                    assert_eq!(branches.len(), 1);
+                    assert!(matches!(&branches[0].0, FileCondition::Compare { attribute: FileAttribute::IsDirectory, .. }));
+                    assert!(matches!(&branches[0].1, FileExpr::Literal(FileValue::String(s)) if s == "755"));
+                    assert!(matches!(otherwise.as_deref(), Some(FileExpr::Literal(FileValue::String(s))) if s == "644"));
+                },
+                other => panic!("Expected CASE expression, got {:?}", other),
+            }
+        },
+        _ => panic!("Expected UPDATE query"),
+    }
+
+    assert!(parse_sql("UPDATE . SET permissions = CASE ELSE '644' END").is_err());
+    assert!(parse_sql("UPDATE . SET permissions = CASE WHEN size > 1 THEN '600'").is_err());
+}
+
+#[test]
+fn test_parse_update_with_function_expression() {
+    let sql = "UPDATE ~/Photos SET name = CONCAT(STEM(name), '_', YEAR(modified), '.', ext) WHERE extension = 'jpg'";
+
+    match parse_sql(sql).unwrap() {
+        FileQuery::Update { updates, .. } => {
+            assert!(matches!(updates[0].attribute, FileAttribute::Name));
+            match &updates[0].value {
+                FileExpr::Function { name, args } => {
+                    assert_eq!(name, "CONCAT");
+                    assert_eq!(args.len(), 5);
+                    assert!(matches!(&args[0], FileExpr::Function { name, .. } if name == "STEM"));
+                    assert!(matches!(&args[2], FileExpr::Function { name, args } if name == "YEAR" && matches!(args[..], [FileExpr::Attribute(FileAttribute::Modified)])));
+                    assert!(matches!(&args[4], FileExpr::Attribute(FileAttribute::Extension)));
+                },
+                other => panic!("Expected function call, got {:?}", other),
+            }
+        },
+        _ => panic!("Expected UPDATE query"),
+    }
+
+    assert!(parse_sql("UPDATE . SET name = FROBNICATE(name)").is_err());
+    assert!(parse_sql("UPDATE . SET name = CONCAT(name, '.bak'").is_err());
+}
+
+#[test]
+fn test_parse_update_on_conflict() {
+    let policy = |sql: &str| match parse_sql(sql).unwrap() {
+        FileQuery::Update { on_conflict, .. } => on_conflict,
+        _ => panic!("Expected UPDATE query"),
+    };
+
+    assert_eq!(policy("UPDATE . SET name = LOWER(name)"), ConflictPolicy::Fail);
+    assert_eq!(policy("UPDATE . SET name = LOWER(name) WHERE size > 0 ON CONFLICT SKIP"), ConflictPolicy::Skip);
+    assert_eq!(policy("UPDATE . SET name = LOWER(name) ON CONFLICT overwrite"), ConflictPolicy::Overwrite);
+    assert_eq!(policy("UPDATE . SET name = LOWER(name) ON CONFLICT RENAME"), ConflictPolicy::Rename);
+
+    assert!(parse_sql("UPDATE . SET name = LOWER(name) ON CONFLICT IGNORE").is_err());
+    assert!(parse_sql("UPDATE . SET name = LOWER(name) ON SKIP").is_err());
+}
+
+#[test]
+fn test_parse_glob_paths() {
+    let home = dirs::home_dir().unwrap();
+
+    for sql in [
+        "SELECT * FROM '~/projects/*/target'",
+        "SELECT * FROM ~/projects/*/target",
+    ] {
+        match parse_sql(sql).unwrap() {
+            FileQuery::Select { path, .. } => assert_eq!(path, home.join("projects/*/target")),
+            _ => panic!("Expected SELECT query"),
+        }
+    }
+
+    match parse_sql("SELECT * FROM ./logs/*.log WHERE size > 0").unwrap() {
+        FileQuery::Select { path, condition, .. } => {
+            assert_eq!(path, std::path::PathBuf::from("./logs/*.log"));
+            assert!(condition.is_some());
+        },
+        _ => panic!("Expected SELECT query"),
+    }
+}
+
+#[test]
+fn test_parse_include_self() {
+    match parse_sql("SELECT * FROM . INCLUDE SELF WHERE size > 0").unwrap() {
+        FileQuery::Select { include_self, condition, .. } => {
+            assert!(include_self);
+            assert!(condition.is_some());
+        },
+        _ => panic!("Expected SELECT query"),
+    }
+
+    match parse_sql("SELECT * FROM .").unwrap() {
+        FileQuery::Select { include_self, .. } => assert!(!include_self),
+        _ => panic!("Expected SELECT query"),
+    }
+
+    match parse_sql("UPDATE ./site INCLUDE SELF SET permissions = '755'").unwrap() {
+        FileQuery::Update { include_self, .. } => assert!(include_self),
+        _ => panic!("Expected UPDATE query"),
+    }
+
+    assert!(parse_sql("SELECT * FROM . INCLUDE").is_err());
+}
+
+#[test]
+fn test_parse_entry_type() {
+    let entry_type = |sql| match parse_sql(sql).unwrap() {
+        FileQuery::Select { entry_type, attributes, .. } => {
+            assert_eq!(attributes, vec![FileAttribute::All]);
+            entry_type
+        },
+        FileQuery::Update { entry_type, .. } => entry_type,
+        other => panic!("Expected a SELECT or UPDATE, got {:?}", other),
+    };
+
+    assert_eq!(entry_type("SELECT FILES FROM ."), Some(EntryType::File));
+    assert_eq!(entry_type("WITH RECURSIVE SELECT DIRS FROM . INCLUDE SELF"), Some(EntryType::Directory));
+    assert_eq!(entry_type("SELECT * FROM . TYPE l WHERE size > 0"), Some(EntryType::Symlink));
+    assert_eq!(entry_type("UPDATE . TYPE dir SET permissions = '755'"), Some(EntryType::Directory));
+    assert_eq!(entry_type("SELECT * FROM ."), None);
+
+    assert!(parse_sql("SELECT * FROM . TYPE socket").is_err());
+    assert!(parse_sql("SELECT FILES FROM . TYPE dir").is_err());
+}
+
+#[test]
+fn test_parse_substring_functions() {
+    let query = parse_sql(
+        "SELECT * FROM . WHERE STARTS_WITH(name, 'IMG_') AND NOT ENDS_WITH(name, '.bak') OR CONTAINS_STR(path, '/cache/')",
+    )
+    .unwrap();
+
+    match query {
+        FileQuery::Select { condition: Some(FileCondition::Or(left, right)), .. } => {
+            match *left {
+                FileCondition::And(starts, not_ends) => {
+                    assert!(matches!(
+                        *starts,
+                        FileCondition::Substring { attribute: FileAttribute::Name, position: SubstringPosition::Start, text } if text == "IMG_"
+                    ));
+                    assert!(matches!(*not_ends, FileCondition::Not(_)));
+                },
+                other => panic!("Expected AND, got {:?}", other),
+            }
+            assert!(matches!(
+                *right,
+                FileCondition::Substring { attribute: FileAttribute::Path, position: SubstringPosition::Anywhere, text } if text == "/cache/"
+            ));
+        },
+        _ => panic!("Expected OR of substring checks"),
+    }
+
+    assert!(parse_sql("SELECT * FROM . WHERE STARTS_WITH(name)").is_err());
+}
+
+#[test]
+fn test_parse_arithmetic() {
+    let query = parse_sql("SELECT name, size / 1024 AS kib, size + 2 * 3 FROM . WHERE size / 1048576 > 100").unwrap();
+
+    match query {
+        FileQuery::Select { attributes, computed, condition, .. } => {
+            assert_eq!(attributes, vec![FileAttribute::Name]);
+            assert_eq!(computed.len(), 2);
+            assert_eq!(computed[0].name, "kib");
+            assert!(matches!(
+                &computed[0].expr,
+                FileExpr::Binary { operator: ArithmeticOperator::Div, .. }
+            ));
+            // Without an alias the column is named after its source text;
+            // multiplication binds more tightly than addition
+            assert_eq!(computed[1].name, "size + 2 * 3");
+            match &computed[1].expr {
+                FileExpr::Binary { operator: ArithmeticOperator::Add, right, .. } => {
+                    assert!(matches!(**right, FileExpr::Binary { operator: ArithmeticOperator::Mul, .. }));
+                },
+                other => panic!("Expected addition, got {:?}", other),
+            }
+            assert!(matches!(
+                condition,
+                Some(FileCondition::Expression { operator: ComparisonOperator::Gt, .. })
+            ));
+        },
+        _ => panic!("Expected SELECT query"),
+    }
+
+    for sql in [
+        "SELECT * FROM . WHERE size > 100 * 1024",
+        "SELECT * FROM . WHERE YEAR(modified) = 2024",
+        "SELECT * FROM . WHERE (size - 10) % 2 = 0 AND name = 'a'",
+    ] {
+        assert!(parse_sql(sql).is_ok(), "{}", sql);
+    }
+
+    // Plain comparisons keep their simple form
+    assert!(matches!(
+        parse_sql("SELECT * FROM . WHERE size > -5").unwrap(),
+        FileQuery::Select { condition: Some(FileCondition::Compare { .. }), .. }
+    ));
+    assert!(parse_sql("SELECT size / FROM .").is_err());
+}
+
+#[test]
+fn test_parse_bare_boolean_attributes() {
+    let is_true = |attribute| FileCondition::Compare {
+        attribute,
+        operator: ComparisonOperator::Eq,
+        value: FileValue::Boolean(true),
+    };
+
+    match parse_sql("SELECT * FROM . WHERE is_directory AND NOT is_symlink").unwrap() {
+        FileQuery::Select { condition: Some(FileCondition::And(left, right)), .. } => {
+            assert_eq!(format!("{:?}", left), format!("{:?}", is_true(FileAttribute::IsDirectory)));
+            assert_eq!(
+                format!("{:?}", right),
+                format!("{:?}", FileCondition::Not(Box::new(is_true(FileAttribute::IsSymlink))))
+            );
+        },
+        _ => panic!("Expected AND of bare booleans"),
+    }
+
+    for sql in [
+        "SELECT * FROM . WHERE is_executable = TRUE",
+        "SELECT * FROM . WHERE (is_executable)",
+        "SELECT * FROM . WHERE TRUE",
+        "UPDATE . SET permissions = CASE WHEN is_directory THEN '755' ELSE '644' END",
+    ] {
+        assert!(parse_sql(sql).is_ok(), "{}", sql);
+    }
+
+    assert!(matches!(
+        parse_sql("SELECT * FROM . WHERE FALSE").unwrap(),
+        FileQuery::Select { condition: Some(FileCondition::Constant(false)), .. }
+    ));
+    // Only boolean attributes can stand alone
+    assert!(parse_sql("SELECT * FROM . WHERE size").is_err());
+}
+
+#[test]
+fn test_parse_any_of_all_of_and_xor() {
+    let condition = |sql| match parse_sql(sql).unwrap() {
+        FileQuery::Select { condition: Some(condition), .. } => format!("{:?}", condition),
+        _ => panic!("Expected SELECT with a condition"),
+    };
+
+    assert_eq!(
+        condition("SELECT * FROM . WHERE ANY_OF(extension, 'jpg', 'png', 'gif')"),
+        condition("SELECT * FROM . WHERE extension = 'jpg' OR extension = 'png' OR extension = 'gif'")
+    );
+    assert_eq!(
+        condition("SELECT * FROM . WHERE ANY_OF(is_directory, size > 10)"),
+        condition("SELECT * FROM . WHERE is_directory OR size > 10")
+    );
+    assert_eq!(
+        condition("SELECT * FROM . WHERE ALL_OF(size > 10, name LIKE 'a%', NOT is_symlink)"),
+        condition("SELECT * FROM . WHERE size > 10 AND name LIKE 'a%' AND NOT is_symlink")
+    );
+    // XOR binds between AND and OR
+    assert_eq!(
+        condition("SELECT * FROM . WHERE is_directory XOR size > 10 AND is_symlink OR FALSE"),
+        condition(
+            "SELECT * FROM . WHERE ((is_directory AND NOT (size > 10 AND is_symlink)) \
+             OR (NOT is_directory AND (size > 10 AND is_symlink))) OR FALSE"
+        )
+    );
+
+    assert!(parse_sql("SELECT * FROM . WHERE ANY_OF()").is_err());
+    assert!(parse_sql("SELECT * FROM . WHERE ANY_OF(extension, 'jpg',)").is_err());
+}
+
+#[test]
+fn test_lint_warnings() {
+    use crate::sql::lint::lint;
+
+    let codes = |sql| -> Vec<&'static str> {
+        lint(&parse_sql(sql).unwrap()).into_iter().map(|w| w.code).collect()
+    };
+
+    assert_eq!(codes("UPDATE . SET permissions = '644'"), vec!["update_without_where"]);
+    assert!(codes("UPDATE . SET permissions = '644' WHERE extension = 'sh'").is_empty());
+    assert_eq!(codes("WITH RECURSIVE SELECT * FROM / WHERE size > 0"), vec!["recursive_from_root"]);
+    assert!(codes("SELECT * FROM / WHERE size > 0").is_empty());
+    assert_eq!(
+        codes("WITH RECURSIVE SELECT * FROM . WHERE NOT name LIKE '%.log' OR name LIKE '%.log'"),
+        vec!["leading_wildcard"]
+    );
+    assert!(codes("SELECT * FROM . WHERE name LIKE '%.log'").is_empty());
+
+    let json = crate::sql::parse_to_ast_json("UPDATE . SET permissions = '644'");
+    assert!(json.contains("\"warnings\":[{\"code\":\"update_without_where\""));
+}
+
+#[test]
+fn test_parse_limit_and_within() {
+    use std::time::Duration;
+
+    match parse_sql("SELECT * FROM . WHERE size > 0 LIMIT 1 WITHIN '10s'").unwrap() {
+        FileQuery::Select { limit, within, condition, .. } => {
+            assert_eq!(limit, Some(1));
+            assert_eq!(within, Some(Duration::from_secs(10)));
+            assert!(condition.is_some());
+        },
+        _ => panic!("Expected SELECT query"),
+    }
+
+    match parse_sql("SELECT * FROM . WITHIN '250ms' LIMIT 5").unwrap() {
+        FileQuery::Select { limit, within, .. } => {
+            assert_eq!(limit, Some(5));
+            assert_eq!(within, Some(Duration::from_millis(250)));
+        },
+        _ => panic!("Expected SELECT query"),
+    }
+
+    assert!(parse_sql("SELECT * FROM . LIMIT -1").is_err());
+    assert!(parse_sql("SELECT * FROM . LIMIT 1.5").is_err());
+    assert!(parse_sql("SELECT * FROM . WITHIN 'forever'").is_err());
+    assert!(parse_sql("SELECT * FROM . LIMIT 1 LIMIT 2").is_err());
+}
+
+#[test]
+fn test_parse_limit_by_size() {
+    match parse_sql("SELECT * FROM /var/log ORDER BY modified LIMIT 50GB BY size").unwrap() {
+        FileQuery::Select { limit, limit_size, .. } => {
+            assert_eq!(limit, None);
+            assert_eq!(limit_size, Some(50_000_000_000));
+        },
+        _ => panic!("Expected SELECT query"),
+    }
+    match parse_sql("SELECT * FROM . LIMIT 1.5 MiB BY size WITHIN '10s'").unwrap() {
+        FileQuery::Select { limit_size, within, .. } => {
+            assert_eq!(limit_size, Some(1_572_864));
+            assert!(within.is_some());
+        },
+        _ => panic!("Expected SELECT query"),
+    }
+    match parse_sql("SELECT * FROM . LIMIT 4096 BY size").unwrap() {
+        FileQuery::Select { limit_size, .. } => assert_eq!(limit_size, Some(4096)),
+        _ => panic!("Expected SELECT query"),
+    }
+
+    assert!(parse_sql("SELECT * FROM . LIMIT 10GB").is_err());
+    assert!(parse_sql("SELECT * FROM . LIMIT 10GB BY modified").is_err());
+    assert!(parse_sql("SELECT * FROM . LIMIT 10GB BY size LIMIT 5").is_err());
+    assert!(parse_sql("SELECT extension, COUNT(*) FROM . GROUP BY extension LIMIT 1GB BY size").is_err());
+    assert!(parse_sql("SELECT * FROM STATS('.') LIMIT 1GB BY size").is_err());
+}
+
+#[test]
+fn test_parse_random_order_and_row_sample() {
+    use crate::sql::ast::{RowSample, SortKey};
+
+    match parse_sql("SELECT * FROM . ORDER BY RANDOM() LIMIT 10").unwrap() {
+        FileQuery::Select { order_by, limit, .. } => {
+            assert_eq!(order_by[0].key, SortKey::Random { seed: None });
+            assert_eq!(limit, Some(10));
+        },
+        _ => panic!("Expected SELECT query"),
+    }
+    match parse_sql("SELECT * FROM . ORDER BY random(42), name").unwrap() {
+        FileQuery::Select { order_by, .. } => assert_eq!(order_by[0].key, SortKey::Random { seed: Some(42) }),
+        _ => panic!("Expected SELECT query"),
+    }
+    match parse_sql("SELECT * FROM /backups SAMPLE 100 ROWS WEIGHTED BY size SEED 7 LIMIT 5").unwrap() {
+        FileQuery::Select { sample, sample_rows, limit, .. } => {
+            assert_eq!(sample, None);
+            assert_eq!(sample_rows, Some(RowSample { rows: 100, weight: Some(FileAttribute::Size), seed: Some(7) }));
+            assert_eq!(limit, Some(5));
+        },
+        _ => panic!("Expected SELECT query"),
+    }
+    match parse_sql("SELECT * FROM . SAMPLE 3 ROWS").unwrap() {
+        FileQuery::Select { sample_rows, .. } => {
+            assert_eq!(sample_rows, Some(RowSample { rows: 3, weight: None, seed: None }));
+        },
+        _ => panic!("Expected SELECT query"),
+    }
+
+    assert!(parse_sql("SELECT * FROM . ORDER BY RANDOM('x')").is_err());
+    assert!(parse_sql("SELECT * FROM . SAMPLE 1.5 ROWS").is_err());
+    assert!(parse_sql("SELECT * FROM . SAMPLE 10 ROWS WEIGHTED BY name").is_err());
+    assert!(parse_sql("SELECT * FROM . SAMPLE 10 ROWS SAMPLE 1%").is_err());
+    assert!(parse_sql("SELECT extension, COUNT(*) FROM . GROUP BY extension SAMPLE 2 ROWS").is_err());
+}
+
+#[test]
+fn test_parse_order_by() {
+    use crate::sql::ast::{OrderBy, SortKey};
+
+    match parse_sql("SELECT * FROM . WHERE size > 0 ORDER BY depth, path NATURAL DESC, size LIMIT 3").unwrap() {
+        FileQuery::Select { order_by, limit, .. } => {
+            assert_eq!(
+                order_by,
+                vec![
+                    OrderBy {
+                        key: SortKey::Depth,
+                        descending: false,
+                        natural: false,
+                        collation: None,
+                        nulls_first: None,
+                    },
+                    OrderBy {
+                        key: SortKey::Attribute(FileAttribute::Path),
+                        descending: true,
+                        natural: true,
+                        collation: None,
+                        nulls_first: None,
+                    },
+                    OrderBy {
+                        key: SortKey::Attribute(FileAttribute::Size),
+                        descending: false,
+                        natural: false,
+                        collation: None,
+                        nulls_first: None,
+                    },
+                ]
+            );
+            assert_eq!(limit, Some(3));
+        },
+        _ => panic!("Expected SELECT query"),
+    }
+
+    assert!(parse_sql("SELECT * FROM . ORDER BY name ASC NATURAL").is_ok());
+    assert!(parse_sql("SELECT * FROM . ORDER name").is_err());
+    assert!(parse_sql("SELECT * FROM . ORDER BY").is_err());
+    assert!(parse_sql("SELECT * FROM . ORDER BY colour").is_err());
+    assert!(parse_sql("SELECT * FROM . ORDER BY name ASC DESC").is_err());
+}
+
+#[test]
+fn test_parse_order_by_collate() {
+    match parse_sql("SELECT * FROM . ORDER BY name COLLATE 'de_DE' DESC, path").unwrap() {
+        FileQuery::Select { order_by, .. } => {
+            assert_eq!(order_by[0].collation.as_deref(), Some("de_DE"));
+            assert!(order_by[0].descending);
+            assert_eq!(order_by[1].collation, None);
+        },
+        _ => panic!("Expected SELECT query"),
+    }
+
+    assert!(parse_sql("SELECT * FROM . ORDER BY name COLLATE de_DE").is_err());
+    assert!(parse_sql("SELECT * FROM . ORDER BY name COLLATE 'de' COLLATE 'fr'").is_err());
+}
+
+#[test]
+fn test_parse_group_by() {
+    use crate::sql::ast::AggregateFunction;
+
+    let sql = "WITH RECURSIVE SELECT owner, SUM(size), COUNT(*) AS file_count FROM . WHERE size > 0 GROUP BY owner ORDER BY owner";
+    let query = parse_sql(sql).unwrap();
+    assert!(query.is_grouped());
+    match query {
+        FileQuery::Select { attributes, aggregates, group_by, order_by, .. } => {
+            assert_eq!(attributes, vec![FileAttribute::Owner]);
+            assert_eq!(aggregates.len(), 2);
+            assert_eq!(aggregates[0].name, "SUM(size)");
+            assert_eq!(aggregates[0].function, AggregateFunction::Sum);
+            assert!(matches!(aggregates[0].argument, Some(FileExpr::Attribute(FileAttribute::Size))));
+            assert_eq!(aggregates[1].name, "file_count");
+            assert!(aggregates[1].argument.is_none());
+            assert_eq!(group_by.len(), 1);
+            assert_eq!(group_by[0].name, "owner");
+            assert_eq!(order_by.len(), 1);
+        },
+        _ => panic!("Expected SELECT query"),
+    }
+
+    // Aggregates without GROUP BY form a single group
+    assert!(parse_sql("SELECT COUNT(*), MAX(modified) FROM .").unwrap().is_grouped());
+    assert!(parse_sql("SELECT YEAR(modified), COUNT(*) FROM . GROUP BY YEAR(modified)").is_ok());
+    assert!(parse_sql("SELECT ext, COUNT(*) FROM . GROUP BY extension").is_ok());
+    assert!(!parse_sql("SELECT * FROM .").unwrap().is_grouped());
+
+    let err = parse_sql("SELECT owner, name, SUM(size) FROM . GROUP BY owner").unwrap_err();
+    assert!(err.to_string().contains("'name' must be in GROUP BY"));
+    assert_eq!(err.span().unwrap().start.column, 15);
+    assert!(parse_sql("SELECT * FROM . GROUP BY owner").is_err());
+    assert!(parse_sql("SELECT SUM(*) FROM .").is_err());
+    assert!(parse_sql("SELECT SUM(size) / 2 FROM .").is_err());
+    assert!(parse_sql("SELECT owner FROM . GROUP owner").is_err());
+
+    // An alias groups by its column's expression, under its own name
+    let sql = "SELECT LOWER(extension) AS kind, COUNT(*) FROM . GROUP BY kind";
+    match parse_sql(sql).unwrap() {
+        FileQuery::Select { group_by, .. } => {
+            assert_eq!(group_by[0].name, "kind");
+            assert!(matches!(&group_by[0].expr, FileExpr::Function { name, .. } if name == "LOWER"));
+        },
+        _ => panic!("Expected SELECT query"),
+    }
+    // A key that is the same for every file is a mistake
+    for sql in [
+        "SELECT COUNT(*) FROM . GROUP BY 'kind'",
+        "SELECT COUNT(*) FROM . GROUP BY 1 + 2",
+        "SELECT COUNT(*) FROM . GROUP BY UPPER('x')",
+    ] {
+        let err = parse_sql(sql).unwrap_err();
+        assert!(err.to_string().contains("GROUP BY needs an attribute"), "{}: {}", sql, err);
+    }
+    assert!(parse_sql("SELECT LOWER(name) AS kind, COUNT(*) FROM . GROUP BY kinds").is_err());
+}
+
+#[test]
+fn test_parse_contains_bytes() {
+    match parse_sql("SELECT * FROM . WHERE CONTAINS_BYTES(X'7F454c46', 512)").unwrap() {
+        FileQuery::Select { condition, .. } => match condition {
+            Some(FileCondition::ContainsBytes { bytes, max_bytes }) => {
+                assert_eq!(bytes, b"\x7fELF");
+                assert_eq!(max_bytes, Some(512));
+            },
+            other => panic!("Expected CONTAINS_BYTES, got {:?}", other),
+        },
+        _ => panic!("Expected SELECT query"),
+    }
+
+    assert!(parse_sql("SELECT * FROM . WHERE CONTAINS_BYTES('ELF')").is_err());
+    assert!(parse_sql("SELECT * FROM . WHERE CONTAINS_BYTES(x'7f4')").is_err());
+    assert!(parse_sql("SELECT * FROM . WHERE CONTAINS_BYTES(x'zz')").is_err());
+    assert!(parse_sql("SELECT * FROM . WHERE CONTAINS_BYTES(x'')").is_err());
+    assert!(parse_sql("SELECT * FROM . WHERE CONTAINS_BYTES(x'00', 1.5)").is_err());
+}
+
+#[test]
+fn test_parse_attribute_comparison() {
+    match parse_sql("SELECT * FROM . WHERE signature_extension != extension").unwrap() {
+        FileQuery::Select { condition, .. } => match condition {
+            Some(FileCondition::Expression { left, operator, right }) => {
+                assert!(matches!(left, FileExpr::Attribute(FileAttribute::SignatureExtension)));
+                assert_eq!(operator, ComparisonOperator::NotEq);
+                assert!(matches!(right, FileExpr::Attribute(FileAttribute::Extension)));
+            },
+            other => panic!("Expected an expression comparison, got {:?}", other),
+        },
+        _ => panic!("Expected SELECT query"),
+    }
+
+    assert!(parse_sql("SELECT * FROM . WHERE extension_mismatch AND size > 0").is_ok());
+}
+
+#[test]
+fn test_parse_from_stdin() {
+    let query = parse_sql("SELECT name FROM stdin WHERE size > 0").unwrap();
+    assert!(query.reads_stdin());
+    match query {
+        FileQuery::Select { path, condition, .. } => {
+            assert_eq!(path, std::path::Path::new("-"));
+            assert!(condition.is_some());
+        },
+        _ => panic!("Expected SELECT query"),
+    }
+
+    assert!(parse_sql("SELECT * FROM '-'").unwrap().reads_stdin());
+    assert!(parse_sql("UPDATE STDIN SET permissions = '644'").unwrap().reads_stdin());
+    assert!(!parse_sql("SELECT * FROM ./stdin").unwrap().reads_stdin());
+}
+
+#[test]
+fn test_parse_rollup_by_path() {
+    let query = parse_sql("WITH RECURSIVE SELECT SUM(size), COUNT(*) FROM . TYPE f WHERE size > 0 ROLLUP BY path").unwrap();
+    assert!(query.is_rollup());
+    assert!(query.is_grouped());
+    assert!(!parse_sql("SELECT COUNT(*) FROM .").unwrap().is_rollup());
+
+    let err = parse_sql("SELECT name, SUM(size) FROM . ROLLUP BY path").unwrap_err();
+    assert!(err.to_string().contains("'name' can't be selected"));
+    assert_eq!(err.span().unwrap().start.column, 8);
+    let err = parse_sql("SELECT SUM(size) FROM . ROLLUP BY owner").unwrap_err();
+    assert!(err.to_string().contains("Only ROLLUP BY path"));
+    assert!(parse_sql("SELECT * FROM . ROLLUP BY path").is_err());
+    assert!(parse_sql("SELECT owner, SUM(size) FROM . GROUP BY owner ROLLUP BY path").is_err());
+    assert!(parse_sql("SELECT SUM(size) FROM . ROLLUP BY path ORDER BY name").is_err());
+    assert!(parse_sql("SELECT SUM(size) FROM . ROLLUP BY path LIMIT 5").is_err());
+    assert!(parse_sql("SELECT SUM(size) FROM . ROLLUP path").is_err());
+}
+
+#[test]
+fn test_parse_join() {
+    let query = parse_sql(
+        "SELECT name, inv.team FROM . LEFT JOIN 'data/inventory.csv' AS inv ON inv.filename = name \
+         WHERE inv.retention_days > 30 ORDER BY inv.team",
+    )
+    .unwrap();
+    match query {
+        FileQuery::Select { join: Some(join), computed, order_by, .. } => {
+            assert!(join.source.ends_with("data/inventory.csv"));
+            assert_eq!(join.alias, "inv");
+            assert_eq!(join.column, "filename");
+            assert!(join.left);
+            assert!(matches!(join.key, FileExpr::Attribute(FileAttribute::Name)));
+            assert!(matches!(&computed[0].expr, FileExpr::Joined { column, .. } if column == "team"));
+            assert!(matches!(&order_by[0].key, crate::sql::ast::SortKey::Joined { column, .. } if column == "team"));
+        },
+        other => panic!("Expected a SELECT with a JOIN, got {:?}", other),
+    }
+
+    // The alias defaults to the file's stem
+    match parse_sql("SELECT * FROM . JOIN 'owners.json' ON owners.login = owner").unwrap() {
+        FileQuery::Select { join: Some(join), .. } => {
+            assert_eq!(join.alias, "owners");
+            assert!(!join.left);
+        },
+        other => panic!("Expected a SELECT with a JOIN, got {:?}", other),
+    }
+
+    let err = parse_sql("SELECT inventory.team FROM .").unwrap_err();
+    assert!(err.to_string().contains("need a JOIN clause"));
+    let err = parse_sql("SELECT other.team FROM . JOIN 'inventory.csv' ON name = inventory.filename").unwrap_err();
+    assert!(err.to_string().contains("the joined table is 'inventory'"));
+    assert_eq!(err.span().unwrap().start.column, 8);
+    let err = parse_sql("SELECT * FROM . JOIN 'inventory.csv' ON name = size").unwrap_err();
+    assert!(err.to_string().contains("ON must compare the file with a column of the table"));
+    assert!(parse_sql("SELECT * FROM . JOIN inventory ON name = inventory.filename").is_err());
+}
+
+#[test]
+fn test_parse_from_table() {
+    let query = parse_sql("SELECT path, inv.size FROM 'data/inventory.csv' AS TABLE inv TYPE f WHERE size != inv.size").unwrap();
+    assert!(query.reads_table());
+    assert!(query.lists_paths());
+    assert!(!query.reads_stdin());
+    assert_eq!(query.root(), None);
+    match query {
+        FileQuery::Select { path, join: Some(join), entry_type, .. } => {
+            assert!(path.ends_with("data/inventory.csv"));
+            assert_eq!(join.source, path);
+            assert_eq!(join.alias, "inv");
+            assert_eq!(join.column, "path");
+            assert!(join.lists_files);
+            assert!(!join.left);
+            assert!(matches!(join.key, FileExpr::Attribute(FileAttribute::Path)));
+            assert_eq!(entry_type, Some(crate::sql::ast::EntryType::File));
+        },
+        other => panic!("Expected a SELECT from a table, got {:?}", other),
+    }
+
+    // The name defaults to the file's stem
+    match parse_sql("SELECT COUNT(*) FROM 'paths.json' AS TABLE WHERE paths.team = 'ops'").unwrap() {
+        FileQuery::Select { join: Some(join), .. } => assert_eq!(join.alias, "paths"),
+        other => panic!("Expected a SELECT from a table, got {:?}", other),
+    }
+    assert!(!parse_sql("SELECT * FROM . JOIN 'inventory.csv' ON name = inventory.filename").unwrap().reads_table());
+
+    let err = parse_sql("SELECT * FROM 'a.csv' AS TABLE JOIN 'b.csv' ON name = b.filename").unwrap_err();
+    assert!(err.to_string().contains("can't also JOIN"));
+    assert!(parse_sql("SELECT * FROM STDIN AS TABLE").is_err());
+    assert!(parse_sql("SELECT * FROM '*.csv' AS TABLE").is_err());
+    assert!(parse_sql("SELECT * FROM 'a.csv' AS inventory").is_err());
+}
+
+#[test]
+fn test_query_schema_types() {
+    use crate::sql::catalog::ValueType;
+    use crate::sql::schema::schema;
+
+    let types = |sql: &str| -> Vec<(String, ValueType)> {
+        schema(&parse_sql(sql).unwrap())
+            .into_iter()
+            .map(|column| (column.name, column.value_type))
+            .collect()
+    };
+    let column = |name: &str, value_type| (name.to_string(), value_type);
+
+    assert_eq!(
+        types("SELECT owner, MAX(modified) AS newest, SUM(size) FROM . GROUP BY owner"),
+        vec![
+            column("owner", ValueType::String),
+            column("newest", ValueType::DateTime),
+            column("SUM(size)", ValueType::Number),
+        ]
+    );
+    assert_eq!(
+        types("WITH RECURSIVE SELECT COUNT(*) AS entries FROM . ROLLUP BY path"),
+        vec![column("path", ValueType::String), column("entries", ValueType::Number)]
+    );
+
+    let select = types(
+        "SELECT created, modified + 3600 AS later, modified - accessed AS idle, \
+         CASE WHEN size > 0 THEN 'full' ELSE NULL END AS state, \
+         CASE WHEN size > 0 THEN 1 ELSE 'none' END AS mixed, preview FROM .",
+    );
+    assert_eq!(
+        select,
+        [
+            column("created", ValueType::DateTime),
+            column("preview", ValueType::String),
+            column("later", ValueType::DateTime),
+            column("idle", ValueType::Duration),
+            column("state", ValueType::String),
+            column("mixed", ValueType::String),
+        ]
+    );
+}
+
+#[test]
+fn test_parse_copy() {
+    use crate::sql::ast::CopyMethod;
+
+    let sql = "WITH RECURSIVE COPY ~/Videos TO /mnt/archive WHERE size > 1000 USING REFLINK ON CONFLICT SKIP";
+    match parse_sql(sql).unwrap() {
+        FileQuery::Copy {
+            path,
+            recursive,
+            destination,
+            condition,
+            method,
+            preserve,
+            sync,
+            verify,
+            on_conflict,
+        } => {
+            assert_eq!(path, dirs::home_dir().unwrap().join("Videos"));
+            assert!(recursive);
+            assert_eq!(destination.to_str(), Some("/mnt/archive"));
+            assert!(condition.is_some());
+            assert_eq!(method, CopyMethod::Reflink);
+            assert_eq!(preserve, Preserve::default());
+            assert!(!sync && !verify);
+            assert_eq!(on_conflict, ConflictPolicy::Skip);
+        },
+        other => panic!("Expected a COPY query, got {:?}", other),
+    }
+
+    match parse_sql("COPY . TO 'backup dir'").unwrap() {
+        FileQuery::Copy { recursive, destination, method, .. } => {
+            assert!(!recursive);
+            assert_eq!(destination.to_str(), Some("backup dir"));
+            assert_eq!(method, CopyMethod::Bytes);
+        },
+        other => panic!("Expected a COPY query, got {:?}", other),
+    }
+
+    assert!(matches!(parse_sql("COPY . WHERE size > 0"), Err(ParserError::MissingClause(_))));
+    assert!(parse_sql("COPY . TO STDIN").is_err());
+    let err = parse_sql("COPY . TO /tmp USING SYMLINK").unwrap_err();
+    assert!(err.to_string().contains("Expected HARDLINK, REFLINK, AUTO or BYTES"));
+}
+
+#[test]
+fn test_parse_show_filesystem() {
+    match parse_sql("SHOW FILESYSTEM FROM ~/mnt").unwrap() {
+        FileQuery::ShowFilesystem { path } => assert_eq!(path, dirs::home_dir().unwrap().join("mnt")),
+        other => panic!("Expected SHOW FILESYSTEM, got {:?}", other),
+    }
+    let query = parse_sql("show filesystem;").unwrap();
+    assert!(query.is_grouped());
+    assert!(matches!(query, FileQuery::ShowFilesystem { path } if path.as_os_str() == "."));
+    assert!(parse_sql("SHOW FILES FROM .").is_err());
+    assert!(parse_sql("SHOW FILESYSTEM FROM . WHERE size > 0").is_err());
+}
+
+#[test]
+fn test_parse_date_functions_in_comparisons() {
+    match parse_sql("SELECT * FROM . WHERE modified > STRPTIME('31/12/2024', '%d/%m/%Y')").unwrap() {
+        FileQuery::Select { condition, .. } => match condition {
+            Some(FileCondition::Expression { left, operator, right }) => {
+                assert!(matches!(left, FileExpr::Attribute(FileAttribute::Modified)));
+                assert_eq!(operator, ComparisonOperator::Gt);
+                assert!(matches!(right, FileExpr::Function { name, args } if name == "STRPTIME" && args.len() == 2));
+            },
+            other => panic!("Expected an expression comparison, got {:?}", other),
+        },
+        _ => panic!("Expected SELECT query"),
+    }
+
+    assert!(parse_sql("SELECT * FROM . WHERE modified BETWEEN 1700000000 AND 1800000000").is_ok());
+    assert!(parse_sql("SELECT * FROM . WHERE EPOCH_MS(1700000000000) < modified").is_ok());
+}
+
+#[test]
+fn test_parse_sample() {
+    match parse_sql("SELECT COUNT(*) FROM / SAMPLE 1% LIMIT 10").unwrap() {
+        FileQuery::Select { sample, limit, .. } => {
+            assert_eq!(sample, Some(0.01));
+            assert_eq!(limit, Some(10));
+        },
+        _ => panic!("Expected SELECT query"),
+    }
+    assert!(matches!(parse_sql("SELECT * FROM .").unwrap(), FileQuery::Select { sample: None, .. }));
+
+    assert!(parse_sql("SELECT * FROM . SAMPLE 0%").is_err());
+    assert!(parse_sql("SELECT * FROM . SAMPLE 150%").is_err());
+    let err = parse_sql("SELECT * FROM . SAMPLE 'some'").unwrap_err();
+    assert!(err.to_string().contains("Expected a percentage such as 1%"));
+    assert!(parse_sql("SELECT SUM(size) FROM . ROLLUP BY path SAMPLE 5%").is_err());
+}
+
+#[test]
+fn test_parse_stats_view() {
+    let query = parse_sql("SELECT * FROM STATS('/srv/src') WHERE size > 0 LIMIT 5").unwrap();
+    let written = parse_sql(
+        "WITH RECURSIVE SELECT extension, COUNT(*) AS count, SUM(size) AS total_size, \
+         AVG(size) AS avg_size, MAX(modified) AS newest_modified FROM '/srv/src' TYPE f \
+         WHERE size > 0 GROUP BY extension ORDER BY extension LIMIT 5",
+    )
+    .unwrap();
+    assert_eq!(serde_json::to_value(&query).unwrap(), serde_json::to_value(&written).unwrap());
+    assert!(parse_sql("select * from stats_by_extension('.') ORDER BY extension DESC").is_ok());
+
+    let err = parse_sql("SELECT name FROM STATS('.')").unwrap_err();
+    assert!(err.to_string().contains("Only SELECT * can be taken from STATS()"));
+    assert!(parse_sql("SELECT * FROM STATS('.') GROUP BY name").is_err());
+    // Without the parentheses it is just a directory called stats
+    match parse_sql("SELECT * FROM stats").unwrap() {
+        FileQuery::Select { path, group_by, .. } => {
+            assert!(path.ends_with("stats"));
+            assert!(group_by.is_empty());
+        },
+        _ => panic!("Expected SELECT query"),
+    }
+}
+
+#[test]
+fn test_parse_tagged() {
+    match parse_sql("SELECT * FROM . WHERE TAGGED('project-x') AND size > 0").unwrap() {
+        FileQuery::Select { condition: Some(FileCondition::And(left, _)), .. } => match *left {
+            FileCondition::Tagged { tag, store } => {
+                assert_eq!(tag, "project-x");
+                assert!(store.ends_with("fmql/tags.json"));
+            },
+            other => panic!("Expected TAGGED, got {:?}", other),
+        },
+        _ => panic!("Expected SELECT query with AND"),
+    }
+    assert!(parse_sql("SELECT * FROM . WHERE TAGGED(name)").is_err());
+}
+
+#[test]
+fn test_parse_copy_preserve_sync_and_verify() {
+    let sql = "COPY . TO /backup PRESERVE (owner, XATTRS) SYNC VERIFY CHECKSUM ON CONFLICT SKIP";
+    match parse_sql(sql).unwrap() {
+        FileQuery::Copy { preserve, sync, verify, on_conflict, .. } => {
+            assert_eq!(preserve, Preserve { owner: true, xattrs: true, ..Preserve::NONE });
+            assert!(sync && verify);
+            assert_eq!(on_conflict, ConflictPolicy::Skip);
+        },
+        other => panic!("Expected a COPY query, got {:?}", other),
+    }
+    match parse_sql("COPY . TO /backup USING AUTO PRESERVE ALL").unwrap() {
+        FileQuery::Copy { preserve, sync, verify, .. } => {
+            assert_eq!(preserve, Preserve::ALL);
+            assert!(!sync && !verify);
+        },
+        other => panic!("Expected a COPY query, got {:?}", other),
+    }
+
+    let err = parse_sql("COPY . TO /backup PRESERVE (times, acls)").unwrap_err();
+    assert!(err.to_string().contains("Expected one of times, permissions, owner, xattrs"));
+    assert!(parse_sql("COPY . TO /backup PRESERVE times").is_err());
+    assert!(parse_sql("COPY . TO /backup VERIFY").is_err());
+}
+
+#[test]
+fn test_parse_compress() {
+    let sql = "WITH RECURSIVE COMPRESS FROM /var/log WHERE extension = 'log' USING zstd LEVEL 9 DELETE ORIGINAL";
+    match parse_sql(sql).unwrap() {
+        FileQuery::Compress { path, recursive, condition, format, level, delete_original } => {
+            assert_eq!(path.to_str(), Some("/var/log"));
+            assert!(recursive && delete_original);
+            assert!(condition.is_some());
+            assert_eq!((format, level), (CompressFormat::Zstd, Some(9)));
+        },
+        other => panic!("Expected a COMPRESS query, got {:?}", other),
+    }
+    match parse_sql("COMPRESS logs").unwrap() {
+        FileQuery::Compress { recursive, format, level, delete_original, .. } => {
+            assert!(!recursive && !delete_original);
+            assert_eq!((format, level), (CompressFormat::Gzip, None));
+        },
+        other => panic!("Expected a COMPRESS query, got {:?}", other),
+    }
+
+    let err = parse_sql("COMPRESS logs USING GZIP LEVEL 12").unwrap_err();
+    assert!(err.to_string().contains("Expected a GZIP level from 1 to 9"));
+    let err = parse_sql("COMPRESS logs USING BROTLI").unwrap_err();
+    assert!(err.to_string().contains("Expected GZIP or ZSTD"));
+    assert!(parse_sql("COMPRESS logs DELETE").is_err());
+}
+
+#[test]
+fn test_parse_extract() {
+    let sql = "EXTRACT FROM ~/downloads WHERE extension = 'zip' OR extension = 'gz' TO /tmp/unpacked/{stem}/";
+    match parse_sql(sql).unwrap() {
+        FileQuery::Extract { path, recursive, condition, destination, on_conflict } => {
+            assert!(path.ends_with("downloads"));
+            assert!(!recursive && condition.is_some());
+            assert_eq!(destination.to_str(), Some("/tmp/unpacked/{stem}/"));
+            assert_eq!(on_conflict, ConflictPolicy::Fail);
+        },
+        other => panic!("Expected an EXTRACT query, got {:?}", other),
+    }
+    // The WHERE clause may come after TO as well
+    let sql = "WITH RECURSIVE EXTRACT archives TO out WHERE size > 0 ON CONFLICT SKIP";
+    match parse_sql(sql).unwrap() {
+        FileQuery::Extract { recursive, condition, on_conflict, .. } => {
+            assert!(recursive && condition.is_some());
+            assert_eq!(on_conflict, ConflictPolicy::Skip);
+        },
+        other => panic!("Expected an EXTRACT query, got {:?}", other),
+    }
+
+    assert!(parse_sql("EXTRACT FROM archives").is_err());
+    assert!(parse_sql("EXTRACT FROM archives TO STDIN").is_err());
+}
+
+#[test]
+fn test_visit_and_rewrite_parsed_query() {
+    use crate::sql::visit::{rewrite_condition, Rewriter, Visitor};
+
+    struct Read(Vec<FileAttribute>);
+    impl Visitor<'_> for Read {
+        fn visit_attribute(&mut self, attribute: &FileAttribute) {
+            self.0.push(attribute.clone());
+        }
+    }
+    let sql = "SELECT name, CASE WHEN is_directory THEN 0 ELSE size END AS bytes FROM . \
+               WHERE owner = 'root' AND YEAR(modified) = 2024 ORDER BY accessed";
+    let query = parse_sql(sql).unwrap();
+    let mut read = Read(Vec::new());
+    read.visit_query(&query);
+    for attribute in [
+        FileAttribute::Name,
+        FileAttribute::IsDirectory,
+        FileAttribute::Size,
+        FileAttribute::Owner,
+        FileAttribute::Modified,
+        FileAttribute::Accessed,
+    ] {
+        assert!(read.0.contains(&attribute), "{:?} was not visited", attribute);
+    }
+
+    // Rewriting nothing gives the query back as it was
+    struct Unchanged;
+    impl Rewriter for Unchanged {}
+    let rewritten = Unchanged.rewrite_query(query.clone());
+    assert_eq!(format!("{:?}", rewritten), format!("{:?}", query));
+
+    // Negating every comparison reaches the one inside NOT too
+    struct Negate;
+    impl Rewriter for Negate {
+        fn rewrite_condition(&mut self, condition: FileCondition) -> FileCondition {
+            match condition {
+                FileCondition::Compare { .. } => FileCondition::Not(Box::new(condition)),
+                condition => rewrite_condition(self, condition),
+            }
+        }
+    }
+    let query = parse_sql("COPY . TO /backup WHERE NOT size > 10").unwrap();
+    match Negate.rewrite_query(query) {
+        FileQuery::Copy { condition: Some(FileCondition::Not(inner)), .. } => {
+            assert!(matches!(*inner, FileCondition::Not(_)));
+        },
+        other => panic!("Expected a negated COPY, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_parse_explain_and_write_conditions_back() {
+    let query = parse_sql("EXPLAIN WITH RECURSIVE SELECT * FROM . WHERE size > 10").unwrap();
+    match query {
+        FileQuery::Explain { query } => {
+            assert!(matches!(*query, FileQuery::Select { recursive: true, .. }));
+        }
+        other => panic!("Expected EXPLAIN, got {:?}", other),
+    }
+    assert!(parse_sql("EXPLAIN EXPLAIN SELECT * FROM .").is_err());
+
+    // Written back, a condition parses to the same thing
+    let sql = "SELECT * FROM . WHERE NOT (name LIKE '%.tmp' OR size / 1024 > 3 * (2 + 1)) \
+               AND (owner = 'o''brien' OR permissions & 0o022 != 0) AND TRUE";
+    let FileQuery::Select { condition: Some(condition), .. } = parse_sql(sql).unwrap() else {
+        panic!("Expected a SELECT with a WHERE clause");
+    };
+    let written = condition.to_string();
+    assert_eq!(
+        written,
+        "NOT (name LIKE '%.tmp' OR size / 1024 > 3 * (2 + 1)) \
+         AND (owner = 'o''brien' OR permissions & 0o022 != 0) AND TRUE"
+    );
+    let FileQuery::Select { condition: Some(reparsed), .. } =
+        parse_sql(&format!("SELECT * FROM . WHERE {}", written)).unwrap()
+    else {
+        panic!("Expected a SELECT with a WHERE clause");
+    };
+    assert_eq!(format!("{:?}", reparsed), format!("{:?}", condition));
+}
+
+#[test]
+fn test_generated_conditions_parse_back_from_their_text() {
+    use crate::testing::arb_condition;
+    use proptest::test_runner::{Config, TestRunner};
+
+    let mut runner = TestRunner::new(Config::with_cases(256));
+    runner
+        .run(&arb_condition(), |condition| {
+            let written = condition.to_string();
+            let sql = format!("SELECT * FROM . WHERE {}", written);
+            let reparsed = match parse_sql(&sql) {
+                Ok(FileQuery::Select { condition: Some(reparsed), .. }) => reparsed,
+                other => panic!("{} parsed as {:?}", sql, other),
+            };
+            // Numbers compared with an attribute come back as plain comparisons
+            proptest::prop_assert_eq!(reparsed.to_string(), written);
+            Ok(())
+        })
+        .unwrap();
+}
+
+#[test]
+fn test_parse_current_user_and_is_mine() {
+    let query = parse_sql("SELECT * FROM /tmp WHERE owner = CURRENT_USER() OR is_mine = TRUE").unwrap();
+    let FileQuery::Select { condition: Some(condition), .. } = query else {
+        panic!("Expected a SELECT with a WHERE clause");
+    };
+    assert_eq!(condition.to_string(), "owner = CURRENT_USER() OR is_mine = TRUE");
+    assert!(parse_sql("SELECT * FROM /tmp WHERE owner = CURRENT_USER").is_err());
+}
+
+#[test]
+fn test_group_is_an_attribute_unless_it_starts_group_by() {
+    use crate::sql::lexer::{tokenize, TokenKind};
+
+    let sql = "SELECT group, COUNT(*) FROM /srv WHERE group != 'staff' GROUP BY group";
+    let kinds: Vec<_> = tokenize(sql).unwrap().into_iter().filter(|t| t.text.eq_ignore_ascii_case("group")).map(|t| t.kind).collect();
+    assert_eq!(kinds, [TokenKind::Attribute, TokenKind::Attribute, TokenKind::Keyword, TokenKind::Attribute]);
+    assert!(parse_sql(sql).is_ok());
+
+    let query = parse_sql("SELECT * FROM /srv WHERE OWNER_IN_GROUP('dev''s') AND group = CURRENT_GROUP()").unwrap();
+    let FileQuery::Select { condition: Some(condition), .. } = query else {
+        panic!("Expected a SELECT with a WHERE clause");
+    };
+    assert_eq!(condition.to_string(), "OWNER_IN_GROUP('dev''s') AND group = CURRENT_GROUP()");
+    assert!(parse_sql("SELECT * FROM /srv WHERE OWNER_IN_GROUP(staff)").is_err());
+}
+
+#[test]
+fn test_parse_order_by_nulls() {
+    match parse_sql("SELECT * FROM . ORDER BY extension ASC NULLS LAST, size DESC, owner NULLS FIRST").unwrap() {
+        FileQuery::Select { order_by, .. } => {
+            let placement: Vec<_> = order_by.iter().map(|order| (order.descending, order.nulls_first)).collect();
+            assert_eq!(placement, [(false, Some(false)), (true, None), (false, Some(true))]);
+        },
+        _ => panic!("Expected SELECT query"),
+    }
+
+    assert!(parse_sql("SELECT * FROM . ORDER BY size NULLS LAST DESC NATURAL").is_ok());
+    assert!(parse_sql("SELECT * FROM . ORDER BY size NULLS").is_err());
+    assert!(parse_sql("SELECT * FROM . ORDER BY size NULLS FIRST NULLS LAST").is_err());
+}
+
+#[test]
+fn test_parse_is_null() {
+    let condition = |sql: &str| match parse_sql(sql).unwrap() {
+        FileQuery::Select { condition, .. } => condition.unwrap().to_string(),
+        _ => panic!("Expected SELECT query"),
+    };
+    // The comparisons with NULL they stand for
+    assert_eq!(
+        condition("SELECT * FROM . WHERE extension IS NULL"),
+        condition("SELECT * FROM . WHERE extension = NULL")
+    );
+    assert_eq!(
+        condition("SELECT * FROM . WHERE extension IS NOT NULL"),
+        condition("SELECT * FROM . WHERE extension != NULL")
+    );
+    assert_eq!(
+        condition("SELECT * FROM . WHERE COALESCE(owner, group) IS NULL"),
+        condition("SELECT * FROM . WHERE COALESCE(owner, group) = NULL")
+    );
+    assert!(parse_sql("SELECT * FROM . WHERE (size / 2) IS NOT NULL").is_ok());
+    assert!(parse_sql("SELECT * FROM . WHERE is_mine IS NULL").is_ok());
+    assert!(parse_sql("SELECT * FROM . WHERE extension IS 'txt'").is_err());
+    assert!(parse_sql("SELECT * FROM . WHERE extension IS").is_err());
+}
+
+#[test]
+fn test_parse_cast() {
+    use crate::sql::ast::CastType;
+
+    match parse_sql("SELECT name, CAST(permissions AS octal) AS mode FROM . WHERE CAST(size AS TEXT) = '4096'").unwrap() {
+        FileQuery::Select { computed, condition, .. } => {
+            assert_eq!(computed[0].name, "mode");
+            assert_eq!(computed[0].expr.to_string(), "CAST(permissions AS OCTAL)");
+            match &computed[0].expr {
+                FileExpr::Cast { expr, to: CastType::Octal } => {
+                    assert!(matches!(**expr, FileExpr::Attribute(FileAttribute::Permissions)));
+                },
+                other => panic!("Expected a cast, got {:?}", other),
+            }
+            assert!(condition.is_some());
+        },
+        _ => panic!("Expected SELECT query"),
+    }
+
+    assert!(parse_sql("SELECT * FROM . WHERE modified > CAST('2024-01-01' AS TIMESTAMP)").is_ok());
+    let err = parse_sql("SELECT CAST(size AS BLOB) FROM .").unwrap_err();
+    assert!(err.to_string().contains("Unknown type 'BLOB'"));
+    assert!(parse_sql("SELECT CAST(size) FROM .").is_err());
+}
+
+#[test]
+fn test_parse_interval() {
+    use chrono::TimeDelta;
+
+    match parse_sql("SELECT * FROM . WHERE age > INTERVAL '2h30m'").unwrap() {
+        FileQuery::Select { condition: Some(FileCondition::Compare { attribute, value, .. }), .. } => {
+            assert_eq!(attribute, FileAttribute::Age);
+            assert_eq!(value, FileValue::Duration(TimeDelta::minutes(150)));
+        }
+        other => panic!("Expected a comparison, got {:?}", other),
+    }
+    match parse_sql("SELECT name, modified - INTERVAL '1 day 6 hours' AS earlier FROM .").unwrap() {
+        FileQuery::Select { computed, .. } => {
+            assert_eq!(computed[0].expr.to_string(), "modified - INTERVAL '1d6h'");
+        }
+        other => panic!("Expected a SELECT, got {:?}", other),
+    }
+    assert!(parse_sql("SELECT * FROM . WHERE age > INTERVAL 'soon'").is_err());
+    assert!(parse_sql("SELECT * FROM . WHERE age > INTERVAL 90").is_err());
+}
+
+#[test]
+fn test_invalid_patterns_are_reported_with_their_span() {
+    let err = parse_sql("SELECT * FROM . WHERE name REGEXP '(' AND size > 0").unwrap_err();
+    match &err {
+        ParserError::InvalidPattern { message, span } => {
+            assert_eq!(message, "unclosed group");
+            assert_eq!(span.start.column, 35);
+        }
+        other => panic!("Expected InvalidPattern error, got {:?}", other),
+    }
+    assert!(err.to_string().starts_with("Invalid pattern at line 1, column 35"));
+
+    // Globs in FROM are compiled too; a LIKE pattern has no syntax to get
+    // wrong, since only % and _ mean anything in it
+    assert!(parse_sql("SELECT * FROM . WHERE name LIKE '%[%'").is_ok());
+    let err = parse_sql("SELECT * FROM './logs/[a-' WHERE REGEXP(name, 'a')").unwrap_err();
+    assert_eq!(err.span().unwrap().start.column, 15);
+    assert!(parse_sql("SELECT * FROM . WHERE REGEXP(name, '[')").is_err());
+
+    assert!(parse_sql("SELECT * FROM './logs/[a-c]*' WHERE name REGEXP '^a.*\\.log$'").is_ok());
+}
+
+#[test]
+fn test_parse_archive() {
+    let sql = "ARCHIVE FROM ~/reports WHERE age > '1 year' INTO ~/archive/reports-{YYYY}.tar.zst DELETE ORIGINAL";
+    match parse_sql(sql).unwrap() {
+        FileQuery::Archive { path, recursive, condition, destination, delete_original } => {
+            assert!(path.ends_with("reports"));
+            assert!(!recursive && condition.is_some() && delete_original);
+            assert!(destination.ends_with("archive/reports-{YYYY}.tar.zst"));
+        },
+        other => panic!("Expected an ARCHIVE query, got {:?}", other),
+    }
+    // The WHERE clause may come after INTO as well
+    let sql = "WITH RECURSIVE ARCHIVE logs INTO old/{YYYY}-{MM}.zip WHERE size > 0";
+    match parse_sql(sql).unwrap() {
+        FileQuery::Archive { recursive, condition, delete_original, .. } => {
+            assert!(recursive && condition.is_some() && !delete_original);
+        },
+        other => panic!("Expected an ARCHIVE query, got {:?}", other),
+    }
+
+    assert!(parse_sql("ARCHIVE FROM logs").is_err());
+    assert!(matches!(parse_sql("ARCHIVE FROM logs INTO old/logs.rar"), Err(ParserError::InvalidPath(_))));
+}
+
+#[test]
+fn test_parse_restore() {
+    let sql = "RESTORE FROM TRASH WHERE original_path LIKE '/home/me/thesis/%' ON CONFLICT RENAME";
+    match parse_sql(sql).unwrap() {
+        FileQuery::Restore { path, recursive, condition, on_conflict } => {
+            assert_eq!(Some(path), crate::sql::trash::trash_files());
+            assert!(!recursive && condition.is_some());
+            assert_eq!(on_conflict, ConflictPolicy::Rename);
+        },
+        other => panic!("Expected a RESTORE query, got {:?}", other),
+    }
+    // Any trash can be named by its path, and nothing is overwritten unless asked
+    match parse_sql("WITH RECURSIVE RESTORE '/mnt/usb/.Trash-1000/files'").unwrap() {
+        FileQuery::Restore { path, recursive, condition, on_conflict } => {
+            assert_eq!(path, std::path::PathBuf::from("/mnt/usb/.Trash-1000/files"));
+            assert!(recursive && condition.is_none());
+            assert_eq!(on_conflict, ConflictPolicy::Fail);
+        },
+        other => panic!("Expected a RESTORE query, got {:?}", other),
+    }
+    // TRASH is a source anywhere a path is
+    assert!(matches!(parse_sql("SELECT name, deleted_at FROM TRASH").unwrap(), FileQuery::Select { .. }));
+}
+
+#[test]
+fn test_translate_find_matches_the_parsed_query() {
+    use crate::sql::find::translate_find;
+
+    let command = r#"find /var/log -maxdepth 1 -type f ! \( -iname '*.gz' -o -path '*/old/*' \) -size 2k -mtime 3 -perm -022"#;
+    let translation = translate_find(command).unwrap();
+    let sql = translation.sql();
+    assert_eq!(
+        sql,
+        "SELECT * FROM '/var/log' TYPE f WHERE NOT (name REGEXP '(?i)^.*\\.gz$' OR CONTAINS_STR(path, '/old/')) \
+         AND size BETWEEN 1025 AND 2048 AND age >= INTERVAL '3d' AND age < INTERVAL '4d' \
+         AND permissions & 0o022 = 18"
+    );
+    // The query built is the one its text parses to
+    match (translation.query(), parse_sql(&sql).unwrap()) {
+        (
+            FileQuery::Select { path, recursive, include_self, entry_type, condition, .. },
+            FileQuery::Select {
+                path: parsed_path,
+                recursive: parsed_recursive,
+                include_self: parsed_include_self,
+                entry_type: parsed_entry_type,
+                condition: parsed_condition,
+                ..
+            },
+        ) => {
+            assert_eq!(path, parsed_path);
+            assert_eq!((recursive, include_self), (parsed_recursive, parsed_include_self));
+            assert_eq!(entry_type, parsed_entry_type);
+            assert_eq!(condition.unwrap().to_string(), parsed_condition.unwrap().to_string());
+        },
+        other => panic!("Expected two SELECT queries, got {:?}", other),
+    }
+
+    // Case-sensitive names don't become LIKE, which isn't
+    let translation = translate_find("-name 'report*' -o -name core -o ! -executable").unwrap();
+    assert_eq!(
+        translation.sql(),
+        "WITH RECURSIVE SELECT * FROM '.' INCLUDE SELF \
+         WHERE STARTS_WITH(name, 'report') OR name = 'core' OR NOT is_executable = TRUE"
+    );
+    assert!(translation.notes().is_empty());
+    // find rounds sizes up, so less than one megabyte is only what is empty
+    let translation = translate_find(". -type d -size -1M").unwrap();
+    assert_eq!(translation.sql(), "WITH RECURSIVE SELECT * FROM '.' INCLUDE SELF TYPE d WHERE size = 0");
+}
+
+#[test]
+fn test_translate_find_rejects_what_it_cant_translate() {
+    use crate::sql::find::translate_find;
+
+    for command in [
+        "/tmp -name '*.tmp' -delete",
+        "/tmp -newer stamp",
+        "/tmp /var/tmp -name '*.tmp'",
+        "/tmp -maxdepth 2",
+        "/tmp -type f -o -type d",
+        "/tmp ( -name a",
+        "/tmp -name 'a",
+        "/tmp -perm g+w",
+        "/tmp -size lots",
+    ] {
+        assert!(
+            matches!(translate_find(command), Err(ParserError::Find(_))),
+            "{} should not translate",
+            command
+        );
+    }
+}
+
+#[test]
+fn test_aliases_expand_to_the_query_written_out() {
+    use crate::sql::aliases::Aliases;
+
+    let mut aliases = Aliases::default();
+    aliases.attributes.insert("mtime".to_string(), "modified".to_string());
+    aliases.snippets.insert("big".to_string(), "size > 1000".to_string());
+    aliases.snippets.insert("stale".to_string(), "MTIME < '2024-01-01' AND @big".to_string());
+
+    let expanded = aliases
+        .expand("SELECT name, size AS mtime FROM ~/data WHERE NOT @Stale ORDER BY mtime")
+        .unwrap();
+    let written = "SELECT name, size AS mtime FROM ~/data \
+                   WHERE NOT (modified < '2024-01-01' AND (size > 1000)) ORDER BY modified";
+    assert_eq!(expanded, written);
+    assert_eq!(
+        format!("{:?}", parse_sql(&expanded).unwrap()),
+        format!("{:?}", parse_sql(written).unwrap())
+    );
+
+    // Nothing to expand leaves the text as it is
+    let plain = "SELECT * FROM '/srv/@big' WHERE name = 'mtime'";
+    assert_eq!(aliases.expand(plain).unwrap(), plain);
+    assert_eq!(Aliases::default().expand("SELECT @big").unwrap(), "SELECT @big");
+}
+
+#[test]
+fn test_aliases_reject_unknown_and_circular_snippets() {
+    use crate::sql::aliases::Aliases;
+
+    let mut aliases = Aliases::default();
+    aliases.snippets.insert("a".to_string(), "size > 1 AND @b".to_string());
+    aliases.snippets.insert("b".to_string(), "NOT @a".to_string());
+
+    let err = aliases.expand("SELECT * FROM . WHERE @a").unwrap_err();
+    assert!(matches!(&err, ParserError::Snippet { message, .. } if message.contains("@a is used")));
+    // Reported where the query uses the outermost snippet
+    assert_eq!(err.span().unwrap().start.column, 23);
+
+    let err = aliases.expand("SELECT * FROM . WHERE @c").unwrap_err();
+    assert!(matches!(err, ParserError::Snippet { .. }));
+
+    aliases.attributes.insert("when".to_string(), "no_such_attribute".to_string());
+    assert!(aliases.check().is_err());
+    let mut spaced = Aliases::default();
+    spaced.snippets.insert("big files".to_string(), "size > 1".to_string());
+    assert!(spaced.check().is_err());
+}
+
+#[test]
+fn test_every_query_the_grammar_describes_parses() {
+    use crate::sql::grammar::Grammar;
+    use std::collections::BTreeSet;
+
+    let grammar = Grammar::standard();
+    let mut statements = BTreeSet::new();
+    for seed in 0..2000 {
+        let sql = grammar.sentence("query", seed).unwrap();
+        if let Err(err) = parse_sql(&sql) {
+            panic!("seed {}: {}\n{}", seed, sql, err);
+        }
+        statements.insert(sql.split(' ').find(|word| !["EXPLAIN", "WITH", "RECURSIVE"].contains(word)).unwrap().to_string());
+    }
+    // Every kind of statement came up
+    let expected = ["ARCHIVE", "COMPRESS", "COPY", "EXTRACT", "RESTORE", "SELECT", "SHOW", "UPDATE"];
+    assert_eq!(statements.iter().map(String::as_str).collect::<Vec<_>>(), expected);
+    assert!(grammar.sentence("no_such_rule", 0).is_none());
+}
+
+#[test]
+fn test_any_of_tells_values_from_conditions() {
+    let condition = |sql: &str| match parse_sql(sql).unwrap() {
+        FileQuery::Select { condition: Some(condition), .. } => condition.to_string(),
+        other => panic!("Expected a SELECT with a WHERE clause, got {:?}", other),
+    };
+    assert_eq!(condition("SELECT * FROM . WHERE ANY_OF(size, -1, 0)"), "size = -1 OR size = 0");
+    assert_eq!(condition("SELECT * FROM . WHERE ANY_OF(TRUE, FALSE)"), "TRUE OR FALSE");
+    assert_eq!(condition("SELECT * FROM . WHERE ANY_OF(is_mine, 1024 < size)"), "is_mine = TRUE OR 1024 < size");
+}
+
+#[test]
+fn test_malformed_queries_never_panic() {
+    use crate::sql::grammar::Grammar;
+    use proptest::prelude::*;
+    use proptest::test_runner::{Config, TestRunner};
+
+    let grammar = Grammar::standard();
+    let terminals: Vec<String> = grammar.terminals().into_iter().map(str::to_string).collect();
+    // Pieces of queries in any order, queries with a stretch cut out, and any text at all
+    let pieces = proptest::collection::vec(proptest::sample::select(terminals), 0..24)
+        .prop_map(|pieces| pieces.join(" "));
+    let cut = (0..500u64, any::<prop::sample::Index>(), 0..12usize).prop_map(move |(seed, at, length)| {
+        let sql = grammar.sentence("query", seed).unwrap();
+        let start = at.index(sql.len() + 1);
+        let end = (start + length).min(sql.len());
+        format!("{}{}", sql.get(..start).unwrap_or(&sql), sql.get(end..).unwrap_or_default())
+    });
+    let text = prop_oneof![pieces, cut, any::<String>()];
+
+    let mut runner = TestRunner::new(Config::with_cases(2048));
+    runner
+        .run(&text, |sql| {
+            let _ = parse_sql(&sql);
+            Ok(())
+        })
+        .unwrap();
+}
+
+#[test]
+fn test_budget_applies_to_the_predicate_before_it() {
+    let condition = |sql: &str| match parse_sql(&format!("SELECT * FROM . WHERE {}", sql)) {
+        Ok(FileQuery::Select { condition: Some(condition), .. }) => Ok(condition),
+        Ok(other) => panic!("Expected a SELECT with a WHERE clause, got {:?}", other),
+        Err(err) => Err(err),
+    };
+
+    let budgeted = condition("CONTAINS_STR(content, 'secret') BUDGET 2s PER FILE").unwrap();
+    match &budgeted {
+        FileCondition::Budget { condition, limit } => {
+            assert_eq!(*limit, std::time::Duration::from_secs(2));
+            assert!(matches!(**condition, FileCondition::Substring { .. }));
+        }
+        other => panic!("Expected a budget, got {:?}", other),
+    }
+    assert_eq!(budgeted.to_string(), "CONTAINS_STR(content, 'secret') BUDGET '2s' PER FILE");
+
+    // NOT and AND bind around it, and parentheses put a budget on a group
+    for (sql, written) in [
+        (
+            "NOT content LIKE '%key%' BUDGET '1.5s' per file AND size > 0",
+            "NOT content LIKE '%key%' BUDGET '1.5s' PER FILE AND size > 0",
+        ),
+        (
+            "(content REGEXP 'a' OR CONTAINS_BYTES(x'00')) BUDGET 500ms PER FILE",
+            "(content REGEXP 'a' OR CONTAINS_BYTES(x'00')) BUDGET '0.5s' PER FILE",
+        ),
+    ] {
+        let parsed = condition(sql).unwrap();
+        assert_eq!(parsed.to_string(), written);
+        assert_eq!(condition(written).unwrap().to_string(), written);
+    }
+
+    for sql in ["size > 0 BUDGET 2s", "size > 0 BUDGET 0s PER FILE", "size > 0 BUDGET 'soon' PER FILE"] {
+        assert!(condition(sql).is_err(), "{} should not parse", sql);
+    }
+}
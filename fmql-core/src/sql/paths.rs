@@ -0,0 +1,396 @@
+//! Validation and expansion of the paths that queries run against.
+//!
+//! The FROM (or UPDATE) path is checked before any traversal starts, so a
+//! typo produces one clear error, with a suggestion when a similarly named
+//! sibling exists, rather than an I/O error part way through a query.
+//!
+//! A path may name a directory, a single file, or a glob pattern such as
+//! `./logs/*.log` or `~/projects/*/target`; patterns are expanded into the
+//! matching paths first, and each match is scanned as if it had been named
+//! on its own. Wildcards may appear in any component. To match a literal
+//! `*`, `?` or `[`, wrap it in brackets (`[*]`).
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use fmql_core::sql::paths::expand_targets;
+//! use std::path::Path;
+//!
+//! match expand_targets(Path::new("/usr/lcoal")) {
+//!     Ok(targets) => println!("{} targets", targets.len()),
+//!     // "/usr/lcoal: no such file or directory (did you mean /usr/local?)"
+//!     Err(err) => eprintln!("{}", err),
+//! }
+//! ```
+
+use std::ffi::OsStr;
+use std::fs;
+use std::io::{ErrorKind, Read, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Component, Path, PathBuf};
+use thiserror::Error;
+
+/// What is wrong with a path.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PathProblem {
+    /// Nothing exists at the path.
+    NotFound,
+    /// The path exists but is not a directory.
+    NotADirectory,
+    /// The path exists but cannot be read.
+    NotReadable,
+    /// The path is a glob pattern that matches nothing.
+    NoMatches,
+    /// The path is a malformed glob pattern.
+    InvalidPattern,
+}
+
+impl std::fmt::Display for PathProblem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let text = match self {
+            PathProblem::NotFound => "no such file or directory",
+            PathProblem::NotADirectory => "not a directory",
+            PathProblem::NotReadable => "permission denied",
+            PathProblem::NoMatches => "pattern matches nothing",
+            PathProblem::InvalidPattern => "invalid glob pattern",
+        };
+        f.write_str(text)
+    }
+}
+
+/// Error when a query's path cannot be used.
+#[derive(Error, Debug, Clone, PartialEq)]
+#[error("{}: {problem}{}", path.display(), hint(suggestion))]
+pub struct PathError {
+    /// The path from the query.
+    pub path: PathBuf,
+    /// What is wrong with it.
+    pub problem: PathProblem,
+    /// A similarly named path that does exist, if one was found.
+    pub suggestion: Option<PathBuf>,
+}
+
+/// Formats the "did you mean" part of a [`PathError`].
+fn hint(suggestion: &Option<PathBuf>) -> String {
+    suggestion
+        .as_ref()
+        .map(|s| format!(" (did you mean {}?)", s.display()))
+        .unwrap_or_default()
+}
+
+/// Expands a query path into the files and directories to scan.
+///
+/// Plain paths must exist; glob patterns must match at least one path.
+pub fn expand_targets(path: &Path) -> std::result::Result<Vec<PathBuf>, PathError> {
+    if !is_glob(path) {
+        validate_target(path)?;
+        return Ok(vec![path.to_path_buf()]);
+    }
+
+    let error = |problem| PathError {
+        path: path.to_path_buf(),
+        problem,
+        suggestion: None,
+    };
+    let pattern = path.to_string_lossy();
+    let targets: Vec<PathBuf> = glob::glob(&pattern)
+        .map_err(|_| error(PathProblem::InvalidPattern))?
+        .filter_map(|entry| entry.ok())
+        .collect();
+
+    if targets.is_empty() {
+        return Err(error(PathProblem::NoMatches));
+    }
+    Ok(targets)
+}
+
+/// Returns the part of a path before its first component with a wildcard,
+/// such as `./logs` for `./logs/*/app.log`: the directory every match of
+/// the pattern is below. A plain path is returned as it is.
+pub fn glob_base(path: &Path) -> PathBuf {
+    path.components()
+        .take_while(|component| {
+            !matches!(component, Component::Normal(name)
+                if name.to_string_lossy().contains(['*', '?', '[']))
+        })
+        .collect()
+}
+
+/// Returns true if the path contains glob wildcards.
+pub fn is_glob(path: &Path) -> bool {
+    path.to_string_lossy().contains(['*', '?', '['])
+}
+
+/// The device names Windows reserves in every directory, extension or not.
+const WINDOWS_RESERVED: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// Returns why Windows can't hold a file under this name, if it can't.
+///
+/// A reserved device name such as `NUL` or `con.txt` opens the device
+/// rather than a file, a trailing dot or space is silently dropped (so
+/// `report.` becomes `report`), and `<>:"|?*` and control characters aren't
+/// allowed at all. The names are fine on Linux and macOS, but not on a FAT,
+/// exFAT or NTFS drive or a share that Windows will read too.
+///
+/// # Examples
+///
+/// ```
+/// use fmql_core::sql::paths::windows_name_problem;
+///
+/// assert_eq!(windows_name_problem("notes.txt"), None);
+/// assert!(windows_name_problem("aux.c").is_some());
+/// assert!(windows_name_problem("draft. ").is_some());
+/// assert!(windows_name_problem("a:b").is_some());
+/// ```
+pub fn windows_name_problem(name: &str) -> Option<&'static str> {
+    let stem = name.split('.').next().unwrap_or(name).trim_end();
+    if WINDOWS_RESERVED.iter().any(|reserved| stem.eq_ignore_ascii_case(reserved)) {
+        return Some("is a device name on Windows");
+    }
+    if name.ends_with(['.', ' ']) {
+        return Some("ends in a dot or space, which Windows drops");
+    }
+    if name.contains(['<', '>', ':', '"', '|', '?', '*']) || name.chars().any(char::is_control) {
+        return Some("has a character Windows doesn't allow in names");
+    }
+    None
+}
+
+/// Returns a path as it should be shown to people.
+///
+/// On Windows, canonical paths are verbatim paths, written `\\?\C:\...` so
+/// that they can be longer than 260 characters; the prefix is dropped for
+/// display. Other paths are shown as they are.
+pub fn display_path(path: &Path) -> String {
+    let text = path.display().to_string();
+    if cfg!(windows) {
+        strip_verbatim(&text).into_owned()
+    } else {
+        text
+    }
+}
+
+/// Turns a Windows verbatim path back into the path people would write:
+/// `\\?\C:\x` into `C:\x` and `\\?\UNC\server\share` into `\\server\share`.
+///
+/// # Examples
+///
+/// ```
+/// use fmql_core::sql::paths::strip_verbatim;
+///
+/// assert_eq!(strip_verbatim(r"\\?\C:\node_modules\a"), r"C:\node_modules\a");
+/// assert_eq!(strip_verbatim(r"\\?\UNC\nas\share"), r"\\nas\share");
+/// assert_eq!(strip_verbatim("/home/me"), "/home/me");
+/// ```
+pub fn strip_verbatim(path: &str) -> std::borrow::Cow<'_, str> {
+    match path.strip_prefix(r"\\?\") {
+        Some(rest) => match rest.strip_prefix(r"UNC\") {
+            Some(share) => format!(r"\\{}", share).into(),
+            None => rest.into(),
+        },
+        None => path.into(),
+    }
+}
+
+/// Checks that `path` is a readable file or directory.
+pub fn validate_target(path: &Path) -> std::result::Result<(), PathError> {
+    let error = |problem, suggestion| PathError {
+        path: path.to_path_buf(),
+        problem,
+        suggestion,
+    };
+
+    match fs::metadata(path) {
+        Err(err) if err.kind() == ErrorKind::NotFound => {
+            Err(error(PathProblem::NotFound, suggest_similar(path)))
+        }
+        Err(err) if err.kind() == ErrorKind::NotADirectory => {
+            Err(error(PathProblem::NotADirectory, None))
+        }
+        Err(err) if err.kind() == ErrorKind::PermissionDenied => {
+            Err(error(PathProblem::NotReadable, None))
+        }
+        Err(_) => Err(error(PathProblem::NotFound, None)),
+        Ok(metadata) if metadata.is_dir() => match fs::read_dir(path) {
+            Ok(_) => Ok(()),
+            Err(_) => Err(error(PathProblem::NotReadable, None)),
+        },
+        Ok(_) => Ok(()),
+    }
+}
+
+/// Finds an existing path that differs from `path` by a small typo in its
+/// first missing component.
+pub fn suggest_similar(path: &Path) -> Option<PathBuf> {
+    let mut existing = path;
+    let mut missing = Vec::new();
+    while fs::symlink_metadata(existing).is_err() {
+        missing.push(existing.file_name()?);
+        existing = existing.parent()?;
+    }
+    let wanted = missing.pop()?.to_string_lossy().to_lowercase();
+    let dir = if existing.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        existing
+    };
+
+    let max_distance = (wanted.chars().count() / 3).max(1);
+    let best = fs::read_dir(dir)
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.file_name())
+        .map(|name| {
+            let distance = edit_distance(&wanted, &name.to_string_lossy().to_lowercase());
+            (distance, name)
+        })
+        .filter(|(distance, _)| *distance <= max_distance)
+        .min_by(|(a, a_name), (b, b_name)| a.cmp(b).then_with(|| a_name.cmp(b_name)))?;
+
+    let mut suggestion = existing.join(best.1);
+    for component in missing.into_iter().rev() {
+        suggestion.push(component);
+    }
+    Some(suggestion)
+}
+
+/// How a list of paths is written, for `FROM STDIN` and for passing results
+/// from one query to the next.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PathListFormat {
+    /// One path per line. Paths that contain a newline can't be represented.
+    Paths,
+    /// Each path followed by a NUL byte, as with `find -print0`. Any path
+    /// can be represented.
+    Paths0,
+}
+
+impl std::str::FromStr for PathListFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "paths" => Ok(PathListFormat::Paths),
+            "paths0" => Ok(PathListFormat::Paths0),
+            _ => Err(format!("Unknown path list format '{}' (expected paths or paths0)", s)),
+        }
+    }
+}
+
+impl PathListFormat {
+    /// The byte that ends each path.
+    fn separator(self) -> u8 {
+        match self {
+            PathListFormat::Paths => b'\n',
+            PathListFormat::Paths0 => b'\0',
+        }
+    }
+}
+
+/// Reads a list of paths, such as the output of `find` or `fd`, for a query
+/// that says `FROM STDIN`.
+///
+/// Paths are separated by NUL bytes if the list contains any (as written by
+/// `find -print0`), and by newlines otherwise. Empty entries are skipped.
+///
+/// # Examples
+///
+/// ```
+/// use fmql_core::sql::paths::read_path_list;
+/// use std::path::PathBuf;
+///
+/// let paths = read_path_list("a.txt\r\nlogs/b.log\n\n".as_bytes()).unwrap();
+/// assert_eq!(paths, [PathBuf::from("a.txt"), PathBuf::from("logs/b.log")]);
+///
+/// let paths = read_path_list("with\nnewline\0c.txt\0".as_bytes()).unwrap();
+/// assert_eq!(paths, [PathBuf::from("with\nnewline"), PathBuf::from("c.txt")]);
+/// ```
+pub fn read_path_list<R: Read>(mut reader: R) -> std::io::Result<Vec<PathBuf>> {
+    let mut list = Vec::new();
+    reader.read_to_end(&mut list)?;
+
+    let format = if list.contains(&0) {
+        PathListFormat::Paths0
+    } else {
+        PathListFormat::Paths
+    };
+    Ok(split_path_list(&list, format))
+}
+
+/// Reads a list of paths written in a known format, such as the output of
+/// [`write_paths`]. Unlike [`read_path_list`], a `paths0` list is never
+/// mistaken for a one-line list, so every path survives the trip.
+pub fn read_paths<R: Read>(mut reader: R, format: PathListFormat) -> std::io::Result<Vec<PathBuf>> {
+    let mut list = Vec::new();
+    reader.read_to_end(&mut list)?;
+    Ok(split_path_list(&list, format))
+}
+
+/// Writes paths as a list in `format`, byte for byte.
+///
+/// # Examples
+///
+/// ```
+/// use fmql_core::sql::paths::{read_paths, write_paths, PathListFormat};
+/// use std::path::{Path, PathBuf};
+///
+/// let mut list = Vec::new();
+/// write_paths(&mut list, [Path::new("a b.txt"), Path::new("odd\nname")], PathListFormat::Paths0).unwrap();
+/// assert_eq!(list, b"a b.txt\0odd\nname\0");
+///
+/// let paths = read_paths(list.as_slice(), PathListFormat::Paths0).unwrap();
+/// assert_eq!(paths, [PathBuf::from("a b.txt"), PathBuf::from("odd\nname")]);
+/// ```
+pub fn write_paths<'a, W, I>(mut writer: W, paths: I, format: PathListFormat) -> std::io::Result<()>
+where
+    W: Write,
+    I: IntoIterator<Item = &'a Path>,
+{
+    for path in paths {
+        writer.write_all(path.as_os_str().as_bytes())?;
+        writer.write_all(&[format.separator()])?;
+    }
+    writer.flush()
+}
+
+/// Splits a path list into its non-empty entries. Newline-separated lists
+/// may have Windows line endings.
+fn split_path_list(list: &[u8], format: PathListFormat) -> Vec<PathBuf> {
+    list.split(|&byte| byte == format.separator())
+        .map(|entry| match format {
+            PathListFormat::Paths => entry.strip_suffix(b"\r").unwrap_or(entry),
+            PathListFormat::Paths0 => entry,
+        })
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| PathBuf::from(OsStr::from_bytes(entry)))
+        .collect()
+}
+
+/// Counts the single-character edits (insertions, deletions, substitutions
+/// and swaps of neighbouring characters) needed to turn `a` into `b`.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut d = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in d.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in d[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            d[i][j] = (d[i - 1][j] + 1).min(d[i][j - 1] + 1).min(d[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                d[i][j] = d[i][j].min(d[i - 2][j - 2] + 1);
+            }
+        }
+    }
+
+    d[a.len()][b.len()]
+}
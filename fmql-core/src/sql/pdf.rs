@@ -0,0 +1,289 @@
+//! Reading the objects of PDF files: how many pages a document has, the
+//! title and author it records, and the streams and strings its text is
+//! drawn from.
+//!
+//! Objects are found by looking for `12 0 obj` in the file, the last one
+//! winning as it does when a document is edited and saved again, rather
+//! than through the cross-reference table, so a damaged table doesn't
+//! matter. Objects packed into compressed object streams, as PDF 1.5 and
+//! later allow, are found in those streams. The title and author of an
+//! encrypted document are encrypted too, so they are never read.
+//!
+//! # Examples
+//!
+//! ```
+//! use fmql_core::sql::pdf::{info, page_count};
+//!
+//! let pdf = b"%PDF-1.4
+//! 1 0 obj << /Type /Catalog /Pages 2 0 R >> endobj
+//! 2 0 obj << /Type /Pages /Kids [3 0 R 4 0 R] /Count 2 >> endobj
+//! 5 0 obj << /Title (Supply agreement \\(draft\\)) /Author <FEFF00C500730061> >> endobj
+//! trailer << /Size 6 /Root 1 0 R /Info 5 0 R >>
+//! %%EOF";
+//! assert_eq!(page_count(pdf), Some(2));
+//! assert_eq!(info(pdf, "Title").as_deref(), Some("Supply agreement (draft)"));
+//! assert_eq!(info(pdf, "Author").as_deref(), Some("Åsa"));
+//! assert_eq!(info(pdf, "Subject"), None);
+//! ```
+
+use std::borrow::Cow;
+use std::io::{self, Read};
+
+use flate2::read::ZlibDecoder;
+use memchr::memmem;
+
+use crate::sql::budget::Budgeted;
+use crate::sql::encoding::{decode, TextEncoding};
+
+/// The most a stream is inflated to, so that a small file can't expand
+/// into more than memory allows.
+const MAX_STREAM: u64 = 256 * 1024 * 1024;
+
+/// Returns how many pages a PDF has, from the page tree its catalog names,
+/// or None if there isn't one to be found.
+pub fn page_count(bytes: &[u8]) -> Option<u64> {
+    let catalog = object(bytes, trailer_reference(bytes, b"/Root")?)?;
+    let pages = object(bytes, reference(&catalog, b"/Pages")?)?;
+    integer(&pages, b"/Count")
+}
+
+/// Returns an entry of a PDF's document information dictionary as text,
+/// such as `Title` or `Author`, or None if it has no such entry, the entry
+/// is blank, or the document is encrypted.
+pub fn info(bytes: &[u8], key: &str) -> Option<String> {
+    if trailer_reference(bytes, b"/Encrypt").is_some() {
+        return None;
+    }
+    let info = object(bytes, trailer_reference(bytes, b"/Info")?)?;
+    let key = format!("/{}", key);
+    let mut value = Cow::Borrowed(value(&info, key.as_bytes())?);
+    // The string can be an object of its own
+    if let Some(number) = parse_reference(&value) {
+        value = object(bytes, number)?;
+    }
+    let value = value.trim_ascii_start();
+    let string = match value.first()? {
+        b'(' => literal_string(value, 1).0,
+        b'<' => hex_string(&value[1..memchr::memchr(b'>', value)?]),
+        _ => return None,
+    };
+    let text = text_string(&string);
+    let text = text.trim();
+    (!text.is_empty()).then(|| text.to_string())
+}
+
+/// The streams of a PDF, as the dictionary before each and its data, in
+/// the order they come in the file.
+pub(crate) struct Streams<'a> {
+    bytes: &'a [u8],
+    at: usize,
+}
+
+/// Returns the streams of a PDF.
+pub(crate) fn streams(bytes: &[u8]) -> Streams<'_> {
+    Streams { bytes, at: 0 }
+}
+
+impl<'a> Iterator for Streams<'a> {
+    type Item = (&'a [u8], &'a [u8]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let bytes = self.bytes;
+        while let Some(found) = memmem::find(&bytes[self.at..], b"stream") {
+            let keyword = self.at + found;
+            self.at = keyword + b"stream".len();
+            // `endstream` ends a stream, and `stream` must end its line
+            let start = match &bytes[self.at..] {
+                [b'\r', b'\n', ..] => self.at + 2,
+                [b'\n' | b'\r', ..] => self.at + 1,
+                _ => continue,
+            };
+            if bytes[..keyword].ends_with(b"end") {
+                continue;
+            }
+            let end = memmem::find(&bytes[start..], b"endstream")
+                .map_or(bytes.len(), |end| start + end);
+            let object = memmem::rfind(&bytes[..keyword], b"obj").unwrap_or(0);
+            self.at = end;
+            return Some((&bytes[object..keyword], &bytes[start..end]));
+        }
+        None
+    }
+}
+
+/// Inflates a stream compressed with `FlateDecode`. A stream cut short or
+/// damaged gives what it holds before the damage.
+///
+/// # Errors
+///
+/// Fails only if a [budget](crate::sql::budget) runs out.
+pub(crate) fn inflate(data: &[u8]) -> io::Result<Vec<u8>> {
+    let mut content = Vec::new();
+    let inflated = Budgeted::new(ZlibDecoder::new(data).take(MAX_STREAM)).read_to_end(&mut content);
+    match inflated {
+        Err(err)
+            if err.kind() != io::ErrorKind::InvalidInput
+                && err.kind() != io::ErrorKind::InvalidData =>
+        {
+            Err(err)
+        }
+        _ => Ok(content),
+    }
+}
+
+/// Returns the body of object `number`, between `obj` and `endobj`.
+fn object(bytes: &[u8], number: u64) -> Option<Cow<'_, [u8]>> {
+    let header = format!("{} 0 obj", number);
+    let start = memmem::find_iter(bytes, header.as_bytes())
+        .filter(|&at| at == 0 || is_delimiter(bytes[at - 1]))
+        .last();
+    if let Some(start) = start {
+        let body = &bytes[start + header.len()..];
+        let end = memmem::find(body, b"endobj").unwrap_or(body.len());
+        return Some(Cow::Borrowed(&body[..end]));
+    }
+    // An object stream starts with the number and offset of each object
+    // in it, and the objects follow from /First on
+    for (dictionary, data) in streams(bytes) {
+        if !has_key(dictionary, b"/ObjStm") {
+            continue;
+        }
+        let (Some(first), Ok(content)) = (integer(dictionary, b"/First"), inflate(data)) else {
+            continue;
+        };
+        let first = usize::try_from(first).ok()?;
+        let header: Vec<usize> = content
+            .get(..first)?
+            .split(u8::is_ascii_whitespace)
+            .filter_map(|word| std::str::from_utf8(word).ok()?.parse().ok())
+            .collect();
+        for (index, pair) in header.chunks_exact(2).enumerate() {
+            if pair[0] as u64 == number {
+                let end = header.get(index * 2 + 3).map_or(content.len(), |next| first + next);
+                return content.get(first + pair[1]..end).map(|body| Cow::Owned(body.to_vec()));
+            }
+        }
+    }
+    None
+}
+
+/// Returns the number of the object the last trailer's `key` refers to,
+/// as a document saved again after editing appends a newer trailer.
+fn trailer_reference(bytes: &[u8], key: &[u8]) -> Option<u64> {
+    memmem::rfind_iter(bytes, key)
+        .filter(|&at| bytes.get(at + key.len()).is_none_or(|&next| is_delimiter(next)))
+        .find_map(|at| parse_reference(&bytes[at + key.len()..]))
+}
+
+/// Returns the number of the object a dictionary's `key` refers to.
+fn reference(dictionary: &[u8], key: &[u8]) -> Option<u64> {
+    parse_reference(value(dictionary, key)?)
+}
+
+/// Reads a reference to an object, `12 0 R`, from the start of `text`.
+fn parse_reference(text: &[u8]) -> Option<u64> {
+    let mut words = text.trim_ascii_start().splitn(4, |byte| byte.is_ascii_whitespace());
+    let number = std::str::from_utf8(words.next()?).ok()?.parse().ok()?;
+    let generation = words.next()?;
+    let keyword = words.next()?;
+    let is_reference = generation.iter().all(u8::is_ascii_digit)
+        && (keyword == b"R" || keyword.starts_with(b"R") && is_delimiter(keyword[1]));
+    is_reference.then_some(number)
+}
+
+/// Returns a dictionary's whole number for `key`.
+fn integer(dictionary: &[u8], key: &[u8]) -> Option<u64> {
+    let value = value(dictionary, key)?;
+    let digits = value.iter().take_while(|byte| byte.is_ascii_digit()).count();
+    std::str::from_utf8(&value[..digits]).ok()?.parse().ok()
+}
+
+/// Returns what follows `key` in a dictionary.
+fn value<'a>(dictionary: &'a [u8], key: &[u8]) -> Option<&'a [u8]> {
+    memmem::find_iter(dictionary, key)
+        .map(|at| at + key.len())
+        .find(|&end| dictionary.get(end).is_none_or(|&next| is_delimiter(next)))
+        .map(|end| dictionary[end..].trim_ascii_start())
+}
+
+/// Whether a dictionary has `key`, as a name of its own.
+fn has_key(dictionary: &[u8], key: &[u8]) -> bool {
+    value(dictionary, key).is_some()
+}
+
+/// Whether a byte ends a word of a PDF.
+pub(crate) fn is_delimiter(byte: u8) -> bool {
+    byte.is_ascii_whitespace()
+        || byte == 0
+        || matches!(byte, b'(' | b')' | b'<' | b'>' | b'[' | b']' | b'{' | b'}' | b'/' | b'%')
+}
+
+/// Reads a string in parentheses from just after its `(`, and returns its
+/// bytes and where it ends.
+pub(crate) fn literal_string(content: &[u8], mut i: usize) -> (Vec<u8>, usize) {
+    let mut string = Vec::new();
+    let mut depth = 0;
+    while i < content.len() {
+        let byte = content[i];
+        i += 1;
+        match byte {
+            b'(' => depth += 1,
+            b')' if depth == 0 => break,
+            b')' => depth -= 1,
+            b'\\' if i < content.len() => {
+                let escaped = content[i];
+                i += 1;
+                match escaped {
+                    b'n' => string.push(b'\n'),
+                    b'r' => string.push(b'\r'),
+                    b't' => string.push(b'\t'),
+                    b'b' => string.push(0x08),
+                    b'f' => string.push(0x0c),
+                    b'0'..=b'7' => {
+                        let mut code = u32::from(escaped - b'0');
+                        for _ in 0..2 {
+                            match content.get(i) {
+                                Some(&digit @ b'0'..=b'7') => {
+                                    code = code * 8 + u32::from(digit - b'0');
+                                    i += 1;
+                                }
+                                _ => break,
+                            }
+                        }
+                        string.push(code as u8);
+                    }
+                    // A backslash at the end of a line continues the string
+                    b'\r' if content.get(i) == Some(&b'\n') => i += 1,
+                    b'\r' | b'\n' => {}
+                    escaped => string.push(escaped),
+                }
+                continue;
+            }
+            _ => {}
+        }
+        string.push(byte);
+    }
+    (string, i)
+}
+
+/// Reads the hex digits of a string in angle brackets.
+pub(crate) fn hex_string(digits: &[u8]) -> Vec<u8> {
+    let digits: Vec<u8> = digits
+        .iter()
+        .filter_map(|&digit| (digit as char).to_digit(16).map(|value| value as u8))
+        .collect();
+    // A missing last digit is 0
+    digits.chunks(2).map(|pair| pair[0] << 4 | pair.get(1).copied().unwrap_or(0)).collect()
+}
+
+/// Reads a PDF string as text: UTF-16 or UTF-8 if it starts with a byte
+/// order mark, and otherwise one byte to each character.
+pub(crate) fn text_string(string: &[u8]) -> String {
+    if string.starts_with(b"\xfe\xff") {
+        decode(string, TextEncoding::Utf16Be)
+    } else if string.starts_with(b"\xef\xbb\xbf") {
+        decode(string, TextEncoding::Utf8)
+    } else {
+        decode(string, TextEncoding::Latin1)
+    }
+}
@@ -0,0 +1,141 @@
+//! Helpers for working with Unix permission bits in queries.
+//!
+//! Permissions are stored as raw mode integers, which are awkward to compare
+//! directly. This module interprets the chmod-style symbolic specs used by
+//! `HAS_PERM('g+w')`, the octal wildcard patterns used by
+//! `PERM_MATCHES('??5')`, and octal string literals such as `'644'`.
+//!
+//! # Examples
+//!
+//! ```
+//! use fmql_core::sql::permissions::{PermissionSpec, matches_octal_pattern};
+//!
+//! let spec = PermissionSpec::parse("g+w").unwrap();
+//! assert!(spec.matches(0o664));
+//! assert!(!spec.matches(0o644));
+//!
+//! assert!(matches_octal_pattern(0o755, "??5").unwrap());
+//! ```
+
+/// Mask selecting the permission bits of a mode (excluding file-type bits).
+pub const PERMISSION_BITS: u32 = 0o7777;
+
+/// A single clause of a symbolic permission spec, e.g. `g+w`.
+#[derive(Debug, Clone, PartialEq)]
+struct Clause {
+    /// The bits the clause refers to.
+    mask: u32,
+    /// Which classes (user/group/other) the clause covers.
+    scope: u32,
+    /// `+` (all bits set), `-` (no bits set) or `=` (exactly these bits).
+    op: char,
+}
+
+/// A parsed chmod-style symbolic permission spec such as `u+x,go-w`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PermissionSpec {
+    clauses: Vec<Clause>,
+}
+
+impl PermissionSpec {
+    /// Parses a symbolic spec.
+    ///
+    /// Each comma-separated clause has the form `[ugoa]*[+-=][rwxst]*`. An
+    /// empty class list means `a`. `+` requires all listed bits to be set,
+    /// `-` requires them all to be clear, and `=` requires the class to have
+    /// exactly those bits.
+    pub fn parse(spec: &str) -> std::result::Result<PermissionSpec, String> {
+        let mut clauses = Vec::new();
+
+        for clause in spec.split(',') {
+            let op_index = clause
+                .find(['+', '-', '='])
+                .ok_or_else(|| format!("Permission spec '{}' is missing +, - or =", clause))?;
+            let (who, rest) = clause.split_at(op_index);
+            let op = rest.chars().next().expect("found an operator at op_index");
+            let perms = &rest[1..];
+
+            let mut classes = 0;
+            for c in who.chars() {
+                classes |= match c {
+                    'u' => 0o4,
+                    'g' => 0o2,
+                    'o' => 0o1,
+                    'a' => 0o7,
+                    _ => return Err(format!("Unknown permission class '{}' in '{}'", c, spec)),
+                };
+            }
+            if classes == 0 {
+                classes = 0o7;
+            }
+
+            let mut scope = 0;
+            for (class, shift) in [(0o4, 6), (0o2, 3), (0o1, 0)] {
+                if classes & class != 0 {
+                    scope |= 0o7 << shift;
+                }
+            }
+
+            let mut mask = 0;
+            for c in perms.chars() {
+                mask |= match c {
+                    'r' => 0o444 & scope,
+                    'w' => 0o222 & scope,
+                    'x' => 0o111 & scope,
+                    's' => (if classes & 0o4 != 0 { 0o4000 } else { 0 })
+                        | (if classes & 0o2 != 0 { 0o2000 } else { 0 }),
+                    't' => 0o1000,
+                    _ => return Err(format!("Unknown permission '{}' in '{}'", c, spec)),
+                };
+            }
+
+            clauses.push(Clause { mask, scope, op });
+        }
+
+        Ok(PermissionSpec { clauses })
+    }
+
+    /// Returns true if the mode satisfies every clause of the spec.
+    pub fn matches(&self, mode: u32) -> bool {
+        self.clauses.iter().all(|clause| match clause.op {
+            '+' => mode & clause.mask == clause.mask,
+            '-' => mode & clause.mask == 0,
+            _ => mode & clause.scope == clause.mask & clause.scope,
+        })
+    }
+}
+
+/// Matches a mode against an octal pattern such as `'??5'` or `'0755'`.
+///
+/// Each character is an octal digit or `?`, which matches any digit. Three
+/// character patterns cover the user/group/other bits; four character
+/// patterns also cover the setuid/setgid/sticky digit.
+pub fn matches_octal_pattern(mode: u32, pattern: &str) -> std::result::Result<bool, String> {
+    if !(3..=4).contains(&pattern.len()) {
+        return Err(format!(
+            "Permission pattern '{}' must have 3 or 4 digits",
+            pattern
+        ));
+    }
+
+    let digits = format!("{:04o}", mode & PERMISSION_BITS);
+    let digits = &digits[4 - pattern.len()..];
+
+    for (expected, actual) in pattern.chars().zip(digits.chars()) {
+        if !matches!(expected, '?' | '0'..='7') {
+            return Err(format!("Invalid character '{}' in permission pattern '{}'", expected, pattern));
+        }
+        if expected != '?' && expected != actual {
+            return Ok(false);
+        }
+    }
+
+    Ok(true)
+}
+
+/// Parses an octal permissions string such as `'644'` or `'0755'`.
+pub fn parse_octal(value: &str) -> Option<u32> {
+    u32::from_str_radix(value, 8)
+        .ok()
+        .filter(|mode| *mode <= PERMISSION_BITS)
+}
@@ -0,0 +1,110 @@
+//! Short previews of file contents, for the `preview` attribute.
+//!
+//! A preview is the first few non-blank lines of a file, cleaned up so that
+//! it fits on one line of a terminal: control characters are replaced, and
+//! lines are joined with `⏎`. The text is read in the file's encoding, as
+//! `content` reads it (see [`encoding`](crate::sql::encoding)), so a UTF-16
+//! file shows its text. Files that look binary (they contain a NUL near the
+//! start) are shown as a hex dump of their first bytes, which is usually
+//! enough to recognise the format.
+//!
+//! # Examples
+//!
+//! ```
+//! use fmql_core::sql::encoding::TextEncoding;
+//! use fmql_core::sql::preview::preview;
+//!
+//! let auto = TextEncoding::Auto;
+//! assert_eq!(preview(b"\n# Notes\r\n\tbuy milk\x07\n", auto), "# Notes ⏎  buy milk\u{FFFD}");
+//! assert_eq!(preview(b"\xff\xfeo\0k\0\n\0", auto), "ok");
+//! assert_eq!(preview(b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR", auto), "<binary> 89 50 4e 47 0d 0a 1a 0a 00 00 00 0d 49 48 44 52");
+//! ```
+
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::Path;
+
+use crate::sql::budget::Budgeted;
+use crate::sql::encoding::{decode, detect, TextEncoding};
+
+/// The most bytes read from the start of a file.
+pub const PREVIEW_BYTES: usize = 256;
+
+/// The most lines shown in a preview.
+pub const PREVIEW_LINES: usize = 3;
+
+/// The number of bytes shown in the hex dump of a binary file.
+const BINARY_BYTES: usize = 16;
+
+/// Reads the preview of a file in `encoding`, or in its own for
+/// [`TextEncoding::Auto`]. Directories and other non-files have none.
+pub fn read_preview(path: &Path, encoding: TextEncoding) -> io::Result<Option<String>> {
+    if !fs::metadata(path)?.is_file() {
+        return Ok(None);
+    }
+    let mut head = Vec::with_capacity(PREVIEW_BYTES);
+    Budgeted::new(File::open(path)?)
+        .take(PREVIEW_BYTES as u64)
+        .read_to_end(&mut head)?;
+
+    let encoding = match encoding {
+        TextEncoding::Auto => detect(&head),
+        encoding => encoding,
+    };
+    // Don't show a character cut in half by the byte limit
+    if head.len() == PREVIEW_BYTES {
+        let last = match encoding {
+            TextEncoding::Utf16Le => head[PREVIEW_BYTES - 1],
+            TextEncoding::Utf16Be => head[PREVIEW_BYTES - 2],
+            _ => 0,
+        };
+        if (0xd8..0xdc).contains(&last) {
+            // The first half of a surrogate pair
+            head.truncate(PREVIEW_BYTES - 2);
+        } else if encoding == TextEncoding::Utf8
+            && let Err(err) = std::str::from_utf8(&head)
+            && err.error_len().is_none()
+        {
+            head.truncate(err.valid_up_to());
+        }
+    }
+    Ok(Some(preview(&head, encoding)))
+}
+
+/// Builds the preview of the start of a file, read in `encoding` or in
+/// the one [`detect`] finds for [`TextEncoding::Auto`].
+pub fn preview(head: &[u8], encoding: TextEncoding) -> String {
+    let encoding = match encoding {
+        TextEncoding::Auto => detect(head),
+        encoding => encoding,
+    };
+    let text = decode(head, encoding);
+    if text.contains('\0') {
+        let hex: Vec<String> = head
+            .iter()
+            .take(BINARY_BYTES)
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+        return format!("<binary> {}", hex.join(" "));
+    }
+
+    text.lines()
+        .filter(|line| !line.trim().is_empty())
+        .take(PREVIEW_LINES)
+        .map(sanitize)
+        .collect::<Vec<_>>()
+        .join(" ⏎ ")
+}
+
+/// Makes one line safe to print: tabs become spaces and other control
+/// characters, which could move the cursor or change colours, become `�`.
+pub(crate) fn sanitize(line: &str) -> String {
+    line.trim_end()
+        .chars()
+        .map(|c| match c {
+            '\t' => ' ',
+            c if c.is_control() => char::REPLACEMENT_CHARACTER,
+            c => c,
+        })
+        .collect()
+}
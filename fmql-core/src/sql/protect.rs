@@ -0,0 +1,151 @@
+//! Keeping ownership and permission changes away from system paths.
+//!
+//! An UPDATE that sets `permissions`, `owner` or `group` walks everything
+//! below its FROM directory, so one typo, `/` for `./`, or `/etc` in place
+//! of `etc`, recursively chmods or chowns the system, which no undo will
+//! bring back. [`Protection::check`] refuses such an UPDATE when a target
+//! is a system path, lies inside one, or holds one: `/` and the system
+//! directories such as `/etc`, `/usr` and `/var`, and the home directories
+//! of other users. Running privileged lifts the check, as does allowing a
+//! path, such as `/var/www`, that is meant to be changed this way.
+//!
+//! Other UPDATEs, renames and touches, are left alone, as are dry runs,
+//! which change nothing.
+//!
+//! # Examples
+//!
+//! ```
+//! use std::path::{Path, PathBuf};
+//!
+//! use fmql_core::sql::protect::{system_path, Protection};
+//!
+//! assert_eq!(system_path(Path::new("/etc/nginx")), Some(PathBuf::from("/etc")));
+//! assert_eq!(system_path(Path::new("/")), Some(PathBuf::from("/")));
+//! assert_eq!(system_path(Path::new("/srv/www")), None);
+//!
+//! let protection = Protection::default();
+//! assert!(protection.check(&[PathBuf::from("/usr/local/bin")], "permissions").is_err());
+//!
+//! let allowed = Protection { allowed: vec![PathBuf::from("/usr/local")], ..Default::default() };
+//! assert!(allowed.check(&[PathBuf::from("/usr/local/bin")], "permissions").is_ok());
+//! ```
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::sql::executor::{ExecutorError, Result};
+
+/// The directories the operating system lives in. A few hold user data on
+/// some systems, such as `/var/www`; those can be allowed.
+const SYSTEM_DIRS: &[&str] = &[
+    "/bin",
+    "/boot",
+    "/dev",
+    "/etc",
+    "/lib",
+    "/lib32",
+    "/lib64",
+    "/proc",
+    "/root",
+    "/sbin",
+    "/sys",
+    "/usr",
+    "/var",
+    "/Applications",
+    "/Library",
+    "/System",
+    "/private",
+];
+
+/// The directories users' home directories are kept in.
+const HOME_DIRS: &[&str] = &["/home", "/Users"];
+
+/// Whether ownership and permission changes may reach system paths.
+#[derive(Debug, Clone, Default)]
+pub struct Protection {
+    /// Allow them everywhere.
+    pub privileged: bool,
+    /// Allow them inside these paths even when they are system paths.
+    pub allowed: Vec<PathBuf>,
+}
+
+impl Protection {
+    /// Checks that changing `what` of the entries under `targets` stays
+    /// clear of system paths, unless privileged or allowed.
+    pub fn check(&self, targets: &[PathBuf], what: &str) -> Result<()> {
+        if self.privileged {
+            return Ok(());
+        }
+        let allowed: Vec<PathBuf> = self.allowed.iter().map(|path| canonical(path)).collect();
+        for target in targets {
+            let path = canonical(target);
+            if allowed.iter().any(|allowed| path.starts_with(allowed)) {
+                continue;
+            }
+            if let Some(system) = system_path(&path) {
+                let place = if system == path {
+                    "is".to_string()
+                } else if path.starts_with(&system) {
+                    format!("is inside {},", system.display())
+                } else {
+                    format!("holds {},", system.display())
+                };
+                return Err(ExecutorError::Denied(format!(
+                    "{} {} a system path, so changing {} there must be privileged (--privileged)",
+                    target.display(),
+                    place,
+                    what
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Returns the system path that `path`, or anything below it, is in or is:
+/// `/`, a system directory, or another user's home directory. The user's
+/// own home and the temporary directory are never system paths. The path is
+/// taken as it is; resolve symlinks first to catch those that lead into a
+/// system path.
+pub fn system_path(path: &Path) -> Option<PathBuf> {
+    if path == Path::new("/") {
+        return Some(path.to_path_buf());
+    }
+    let home = dirs::home_dir()
+        .map(|home| canonical(&home))
+        .filter(|home| home != Path::new("/"));
+    // The temporary directory is under /var on macOS, but belongs to no one
+    let temp = Some(canonical(&env::temp_dir())).filter(|temp| temp != Path::new("/"));
+    if [&home, &temp]
+        .into_iter()
+        .flatten()
+        .any(|mine| path.starts_with(mine))
+    {
+        return None;
+    }
+    let system = SYSTEM_DIRS.iter().map(Path::new).find(|dir| {
+        // Inside the directory, or holding it
+        path.starts_with(dir) || dir.starts_with(path)
+    });
+    if let Some(dir) = system {
+        return Some(dir.to_path_buf());
+    }
+
+    // Wherever this user's home is kept, other users' are kept too
+    let parent = home.as_deref().and_then(Path::parent);
+    let mut homes: Vec<&Path> = HOME_DIRS.iter().map(Path::new).collect();
+    homes.extend(parent.filter(|parent| *parent != Path::new("/")));
+    homes.into_iter().find_map(|dir| {
+        if dir.starts_with(path) {
+            return Some(dir.to_path_buf());
+        }
+        let user = path.strip_prefix(dir).ok()?.components().next()?;
+        Some(dir.join(user))
+    })
+}
+
+/// Resolves the symlinks in `path`, or returns it as it is if it can't be.
+fn canonical(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
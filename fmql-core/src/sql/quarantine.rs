@@ -0,0 +1,168 @@
+//! Where downloaded files came from, as macOS records it.
+//!
+//! When a browser, mail client or chat app saves a file on macOS, it marks
+//! the file with two extended attributes. `com.apple.quarantine` makes
+//! Gatekeeper ask before the file is first opened, and names the app that
+//! downloaded it; `com.apple.metadata:kMDItemWhereFroms` lists the URL it
+//! came from and the page that linked to it, which Finder shows under
+//! "Where from". The `quarantine` attribute is the downloading app, such as
+//! `Safari`, or NULL if the file isn't quarantined, and `where_from` is the
+//! URLs, separated by spaces, so
+//! `where_from LIKE '%://github.com/%'` finds what came from GitHub.
+//!
+//! Files without the attributes, and every file on systems other than
+//! macOS, have NULL for both.
+//!
+//! # Examples
+//!
+//! ```
+//! use fmql_core::sql::quarantine::{parse_quarantine, parse_where_from};
+//!
+//! let record = parse_quarantine("0083;65a1b2c3;Safari;5B1C8A5E-2C9D").unwrap();
+//! assert_eq!(record.agent, "Safari");
+//! assert!(!record.opened());
+//! assert_eq!(record.downloaded.unwrap().timestamp(), 0x65a1b2c3);
+//!
+//! let urls = plist::Value::Array(vec![
+//!     "https://example.com/report.pdf".into(),
+//!     "https://example.com/".into(),
+//! ]);
+//! let mut xattr = Vec::new();
+//! urls.to_writer_binary(&mut xattr).unwrap();
+//! assert_eq!(
+//!     parse_where_from(&xattr).as_deref(),
+//!     Some("https://example.com/report.pdf https://example.com/")
+//! );
+//! ```
+
+use std::io;
+use std::path::Path;
+
+use chrono::{DateTime, Utc};
+
+/// The quarantine flag set once the user has opened the file and
+/// Gatekeeper let it run.
+const USER_APPROVED: u32 = 0x40;
+
+/// A `com.apple.quarantine` record: `flags;time;agent;id`, with the flags
+/// and the time in hex.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Quarantine {
+    /// The record's flags.
+    pub flags: u32,
+    /// When the file was downloaded, if the record says.
+    pub downloaded: Option<DateTime<Utc>>,
+    /// The app that downloaded the file, such as `Safari`.
+    pub agent: String,
+}
+
+impl Quarantine {
+    /// Returns true if the user has opened the file since it was
+    /// downloaded, and so approved it.
+    pub fn opened(&self) -> bool {
+        self.flags & USER_APPROVED != 0
+    }
+}
+
+/// Reads a `com.apple.quarantine` record, or returns None if it is
+/// malformed.
+pub fn parse_quarantine(record: &str) -> Option<Quarantine> {
+    let mut fields = record.trim_end_matches('\0').split(';');
+    let flags = u32::from_str_radix(fields.next()?, 16).ok()?;
+    let downloaded = fields
+        .next()
+        .and_then(|time| i64::from_str_radix(time, 16).ok())
+        .and_then(|time| DateTime::from_timestamp(time, 0));
+    let agent = fields.next().unwrap_or_default().to_string();
+    Some(Quarantine {
+        flags,
+        downloaded,
+        agent,
+    })
+}
+
+/// Reads a `kMDItemWhereFroms` property list, binary or XML, into its URLs
+/// separated by spaces, or returns None if it is malformed or empty.
+pub fn parse_where_from(xattr: &[u8]) -> Option<String> {
+    let value = plist::Value::from_reader(io::Cursor::new(xattr)).ok()?;
+    let urls: Vec<&str> = value
+        .as_array()?
+        .iter()
+        .filter_map(plist::Value::as_string)
+        .filter(|url| !url.is_empty())
+        .collect();
+    (!urls.is_empty()).then(|| urls.join(" "))
+}
+
+/// Reads the app that quarantined a file, without following a symlink, or
+/// returns None if the file isn't quarantined.
+pub fn read_quarantine(path: &Path) -> io::Result<Option<Quarantine>> {
+    let Some(record) = read_xattr(path, c"com.apple.quarantine")? else {
+        return Ok(None);
+    };
+    Ok(parse_quarantine(&String::from_utf8_lossy(&record)))
+}
+
+/// Reads the URLs a file was downloaded from, without following a symlink,
+/// or returns None if they weren't recorded.
+pub fn read_where_from(path: &Path) -> io::Result<Option<String>> {
+    let xattr = read_xattr(path, c"com.apple.metadata:kMDItemWhereFroms")?;
+    Ok(xattr.as_deref().and_then(parse_where_from))
+}
+
+/// Reads an extended attribute of a file, without following a symlink, or
+/// returns None if it doesn't have it.
+#[cfg(target_vendor = "apple")]
+fn read_xattr(path: &Path, name: &std::ffi::CStr) -> io::Result<Option<Vec<u8>>> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = CString::new(path.as_os_str().as_bytes())?;
+    loop {
+        // SAFETY: both strings are NUL-terminated and outlive the call, and
+        // a null buffer of size zero only asks for the length.
+        let size = unsafe {
+            let (path, name) = (path.as_ptr(), name.as_ptr());
+            libc::getxattr(path, name, std::ptr::null_mut(), 0, 0, libc::XATTR_NOFOLLOW)
+        };
+        let Ok(size) = usize::try_from(size) else {
+            let err = io::Error::last_os_error();
+            return match err.raw_os_error() {
+                Some(libc::ENOATTR | libc::ENOTSUP) => Ok(None),
+                _ => Err(err),
+            };
+        };
+        let mut buffer = vec![0u8; size];
+        // SAFETY: as above, and the buffer is as long as the size passed.
+        let read = unsafe {
+            libc::getxattr(
+                path.as_ptr(),
+                name.as_ptr(),
+                buffer.as_mut_ptr().cast(),
+                buffer.len(),
+                0,
+                libc::XATTR_NOFOLLOW,
+            )
+        };
+        match usize::try_from(read) {
+            Ok(read) => {
+                buffer.truncate(read);
+                return Ok(Some(buffer));
+            }
+            // The attribute grew between the calls; ask again
+            Err(_) if io::Error::last_os_error().raw_os_error() == Some(libc::ERANGE) => {}
+            Err(_) => return Err(io::Error::last_os_error()),
+        }
+    }
+}
+
+/// Reads an extended attribute of a file; only macOS has these.
+#[cfg(not(target_vendor = "apple"))]
+fn read_xattr(_path: &Path, _name: &std::ffi::CStr) -> io::Result<Option<Vec<u8>>> {
+    Ok(None)
+}
+
+/// Returns true if this system records where files came from.
+pub fn supported() -> bool {
+    cfg!(target_vendor = "apple")
+}
@@ -0,0 +1,125 @@
+//! The recently modified files under a directory, for `fmql recent`.
+//!
+//! `fmql recent ~/src --since 2h` is a canned query: the files below the
+//! directory modified in the last two hours, newest first. With `--follow`
+//! it keeps going like `tail -f`, printing each file as it appears or
+//! changes. A [`Feed`] polls for it: each poll looks for files modified
+//! since the previous one and reports those it hasn't already reported with
+//! the same modification time. There is no file system notification behind
+//! it, so a poll rescans the tree; pick the interval to suit its size. Files
+//! are recognised as changed by their modification time, so one moved in
+//! with an old time, as `cp -p` and `mv` leave it, isn't reported.
+//!
+//! # Examples
+//!
+//! ```
+//! use std::fs;
+//! use std::time::Duration;
+//!
+//! use chrono::Utc;
+//! use fmql_core::sql::executor::ExecutionOptions;
+//! use fmql_core::sql::recent::Feed;
+//!
+//! let dir = tempfile::tempdir().unwrap();
+//! let mut feed = Feed::new(dir.path().to_path_buf(), Utc::now() - Duration::from_secs(60));
+//! let options = ExecutionOptions::default();
+//! assert!(feed.poll(&options).unwrap().is_empty());
+//!
+//! fs::write(dir.path().join("new.txt"), "hello").unwrap();
+//! let files = feed.poll(&options).unwrap();
+//! assert_eq!(files.len(), 1);
+//! assert_eq!(files[0].name, "new.txt");
+//! // Reported once, until it changes again
+//! assert!(feed.poll(&options).unwrap().is_empty());
+//! ```
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+
+use crate::sql::ast::{
+    ComparisonOperator, EntryType, FileAttribute, FileCondition, FileQuery, FileValue, OrderBy,
+    SortKey,
+};
+use crate::sql::executor::{execute_query_with_options, ExecutionOptions, FileResult, Result};
+
+/// How far back each poll of a [`Feed`] looks before the previous poll
+/// started, for file systems that keep modification times to the second
+/// or coarser.
+const OVERLAP: Duration = Duration::from_secs(2);
+
+/// Returns the query behind `fmql recent`: the files below `path` modified
+/// after `since`, newest first, at most `limit` of them.
+pub fn recent_query(path: PathBuf, since: DateTime<Utc>, limit: Option<usize>) -> FileQuery {
+    FileQuery::Select {
+        path,
+        recursive: true,
+        include_self: false,
+        entry_type: Some(EntryType::File),
+        join: None,
+        attributes: vec![FileAttribute::All],
+        computed: Vec::new(),
+        aggregates: Vec::new(),
+        condition: Some(FileCondition::Compare {
+            attribute: FileAttribute::Modified,
+            operator: ComparisonOperator::Gt,
+            value: FileValue::DateTime(since),
+        }),
+        group_by: Vec::new(),
+        rollup: false,
+        order_by: vec![OrderBy {
+            key: SortKey::Attribute(FileAttribute::Modified),
+            descending: true,
+            natural: false,
+            collation: None,
+            nulls_first: None,
+        }],
+        limit,
+        limit_size: None,
+        within: None,
+        sample: None,
+        sample_rows: None,
+    }
+}
+
+/// A running feed of the files that appear or change below a directory.
+#[derive(Debug, Clone)]
+pub struct Feed {
+    path: PathBuf,
+    since: DateTime<Utc>,
+    /// The files reported that could turn up again in the next poll, with
+    /// the modification time they were reported with.
+    reported: HashMap<PathBuf, DateTime<Utc>>,
+}
+
+impl Feed {
+    /// Returns a feed of the files below `path` modified after `since`.
+    pub fn new(path: PathBuf, since: DateTime<Utc>) -> Feed {
+        Feed {
+            path,
+            since,
+            reported: HashMap::new(),
+        }
+    }
+
+    /// Returns the files modified since the last poll that haven't been
+    /// reported yet, oldest first.
+    pub fn poll(&mut self, options: &ExecutionOptions) -> Result<Vec<FileResult>> {
+        let started = Utc::now();
+        let query = recent_query(self.path.clone(), self.since, None);
+        let mut files = execute_query_with_options(&query, options)?;
+        files.retain(|file| self.reported.get(&file.path) != Some(&file.modified));
+        files.reverse();
+
+        self.since = self.since.max(started - OVERLAP);
+        self.reported.retain(|_, modified| *modified > self.since);
+        for file in &files {
+            if file.modified > self.since {
+                self.reported.insert(file.path.clone(), file.modified);
+            }
+        }
+        Ok(files)
+    }
+}
@@ -0,0 +1,171 @@
+//! What became of the paths a query lists, for reconciliation reports.
+//!
+//! A query that reads `FROM STDIN` or `FROM 'inventory.csv' AS TABLE`
+//! checks a list of paths against the disk, and the paths that are gone
+//! matter as much as the ones that are there. A query that selects or tests
+//! `exists`, `was_moved_hint` or `metadata_error` keeps every listed path,
+//! readable or not, instead of leaving out the ones it can't read:
+//!
+//! ```sql
+//! SELECT path, exists, was_moved_hint, metadata_error
+//! FROM 'inventory.csv' AS TABLE WHERE NOT exists
+//! ```
+//!
+//! A path whose metadata can't be read has only its `name`, `path` and
+//! `extension`; its other attributes are NULL. `was_moved_hint` is a file
+//! of the same name elsewhere under the query's root: the `--root` it is
+//! confined to, or else the directory the listed paths share. The tree is
+//! only walked once some path is found to be gone, and never from `/`.
+//!
+//! # Examples
+//!
+//! ```
+//! use fmql_core::sql::reconcile::Reconcile;
+//! use std::path::PathBuf;
+//!
+//! let dir = tempfile::tempdir().unwrap();
+//! std::fs::create_dir(dir.path().join("archive")).unwrap();
+//! std::fs::write(dir.path().join("archive/report.pdf"), "").unwrap();
+//!
+//! let gone = dir.path().join("report.pdf");
+//! let reconcile = Reconcile::new(&[gone.clone()], Some(dir.path()));
+//! let error = std::fs::metadata(&gone).unwrap_err();
+//! let unread = reconcile.unread(&gone, &error);
+//! assert!(!unread.exists);
+//! assert_eq!(unread.moved_hint, Some(dir.path().join("archive/report.pdf")));
+//! ```
+
+use std::collections::{HashMap, HashSet};
+use std::ffi::OsString;
+use std::io::{self, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock};
+
+use walkdir::WalkDir;
+
+use crate::sql::ast::{FileAttribute, FileQuery};
+use crate::sql::visit::Visitor;
+
+/// What is known of a listed path whose metadata couldn't be read.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Unread {
+    /// Why the metadata couldn't be read.
+    pub error: String,
+    /// Whether the path is there all the same, as it is when it can't be
+    /// read for want of permission.
+    pub exists: bool,
+    /// A file of the same name elsewhere under the query's root, for a
+    /// path that is gone.
+    pub moved_hint: Option<PathBuf>,
+}
+
+/// Accounts for the listed paths of a query that can't be read.
+#[derive(Debug)]
+pub struct Reconcile {
+    /// Where to look for moved files; None if there is nowhere to look.
+    root: Option<PathBuf>,
+    /// The file names of the listed paths, the only ones worth indexing.
+    names: HashSet<OsString>,
+    /// The first file under `root` with each of `names`, in the order a
+    /// sorted walk lists them; walked when first needed.
+    found: OnceLock<HashMap<OsString, PathBuf>>,
+}
+
+impl Reconcile {
+    /// Accounts for the paths `targets` lists, looking for moved files
+    /// under `root` or, without one, the directory the targets share.
+    pub fn new(targets: &[PathBuf], root: Option<&Path>) -> Reconcile {
+        let root = root.map(Path::to_path_buf).or_else(|| shared_directory(targets));
+        Reconcile {
+            // Walking the whole file system for a hint would cost far more
+            // than the hint is worth
+            root: root.filter(|root| root.parent().is_some()),
+            names: targets
+                .iter()
+                .filter_map(|target| target.file_name())
+                .map(|name| name.to_os_string())
+                .collect(),
+            found: OnceLock::new(),
+        }
+    }
+
+    /// Returns the accounting for a query, if it lists paths and reads any
+    /// of `exists`, `was_moved_hint` or `metadata_error`; other queries
+    /// leave out the paths they can't read.
+    pub fn for_query(
+        query: &FileQuery,
+        targets: &[PathBuf],
+        root: Option<&Path>,
+    ) -> Option<Arc<Reconcile>> {
+        if !matches!(query, FileQuery::Select { .. }) || !query.lists_paths() {
+            return None;
+        }
+        let mut reads = ReadsReconcile(false);
+        reads.visit_query(query);
+        reads.0.then(|| Arc::new(Reconcile::new(targets, root)))
+    }
+
+    /// Describes a listed path whose metadata couldn't be read.
+    pub fn unread(&self, path: &Path, error: &io::Error) -> Unread {
+        let exists = !matches!(error.kind(), ErrorKind::NotFound | ErrorKind::NotADirectory);
+        let moved_hint = if exists {
+            None
+        } else {
+            path.file_name().and_then(|name| self.found().get(name)).cloned()
+        };
+        Unread {
+            error: error.to_string(),
+            exists,
+            moved_hint,
+        }
+    }
+
+    /// Indexes the files under the root that have a listed name.
+    fn found(&self) -> &HashMap<OsString, PathBuf> {
+        self.found.get_or_init(|| {
+            let mut found = HashMap::new();
+            let Some(root) = &self.root else {
+                return found;
+            };
+            let entries = WalkDir::new(root)
+                .min_depth(1)
+                .follow_links(false)
+                .sort_by_file_name()
+                .into_iter()
+                .filter_map(|entry| entry.ok());
+            for entry in entries {
+                if self.names.contains(entry.file_name()) {
+                    found
+                        .entry(entry.file_name().to_os_string())
+                        .or_insert_with(|| entry.into_path());
+                }
+            }
+            found
+        })
+    }
+}
+
+/// The deepest directory that holds all of `targets`.
+fn shared_directory(targets: &[PathBuf]) -> Option<PathBuf> {
+    let mut shared = targets.first()?.parent()?.to_path_buf();
+    for target in &targets[1..] {
+        while !target.starts_with(&shared) {
+            if !shared.pop() {
+                return None;
+            }
+        }
+    }
+    (!shared.as_os_str().is_empty()).then_some(shared)
+}
+
+/// Finds whether a query reads any of the reconciliation attributes.
+struct ReadsReconcile(bool);
+
+impl<'ast> Visitor<'ast> for ReadsReconcile {
+    fn visit_attribute(&mut self, attribute: &'ast FileAttribute) {
+        self.0 |= matches!(
+            attribute,
+            FileAttribute::Exists | FileAttribute::WasMovedHint | FileAttribute::MetadataError
+        );
+    }
+}
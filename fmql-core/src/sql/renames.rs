@@ -0,0 +1,162 @@
+//! Planning a batch of renames so that they can all be made.
+//!
+//! Renaming files one after another goes wrong when a file's new name is
+//! the old name of another file in the same batch. `UPDATE . SET name =
+//! REPLACE(name, 'v1', 'v2')` over `v1.txt` and `v2.txt` must move `v2.txt`
+//! away before `v1.txt` can take its name, and a swap (`a` to `b` and `b`
+//! to `a`) can't be made in any order without one file first going through
+//! a temporary name.
+//!
+//! [`plan_renames`] orders the renames so that every file's new name has
+//! been given up by the time it is renamed, and picks one file in each
+//! cycle to be moved aside first. Because each file has one old and one new
+//! name, the renames form separate chains and cycles, and the plan is made
+//! in a single pass over them. Two files that would be given the same name
+//! are reported as a [`Collision`], since no order makes both renames.
+//!
+//! # Examples
+//!
+//! ```
+//! use std::path::PathBuf;
+//!
+//! use fmql_core::sql::renames::plan_renames;
+//!
+//! let rename = |from: &str, to: &str| (PathBuf::from(from), PathBuf::from(to));
+//! // v1 becomes v2 and v2 becomes v3; a and b swap names
+//! let renames = [rename("v1", "v2"), rename("a", "b"), rename("v2", "v3"), rename("b", "a")];
+//! let plan = plan_renames(&renames);
+//! assert_eq!(plan.order, [2, 0, 3, 1]);
+//! assert_eq!(plan.staged, [1]);
+//! assert!(plan.collisions.is_empty());
+//!
+//! let plan = plan_renames(&[rename("a", "c"), rename("b", "c")]);
+//! assert_eq!(plan.collisions[0].to_string(), "a and b would both be renamed to c");
+//! ```
+
+use std::collections::HashMap;
+use std::fmt;
+use std::path::PathBuf;
+
+/// The order in which a batch of renames can be made.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RenamePlan {
+    /// Every rename, by its index in the batch, in the order to make them.
+    pub order: Vec<usize>,
+    /// The renames whose file must be moved to a temporary name before any
+    /// of the others are made, one for each cycle.
+    pub staged: Vec<usize>,
+    /// The names that more than one rename would give. Only the first of
+    /// these renames is ordered with the others; the rest come last, and
+    /// will find the name taken.
+    pub collisions: Vec<Collision>,
+}
+
+/// Files that a batch would all rename to the same path.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Collision {
+    /// The path they would be given.
+    pub target: PathBuf,
+    /// The files, in batch order.
+    pub sources: Vec<PathBuf>,
+}
+
+impl fmt::Display for Collision {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sources: Vec<String> = self
+            .sources
+            .iter()
+            .map(|source| source.display().to_string())
+            .collect();
+        let (last, rest) = sources.split_last().expect("a collision has sources");
+        let quantifier = if rest.len() == 1 { "both" } else { "all" };
+        write!(
+            f,
+            "{} and {} would {} be renamed to {}",
+            rest.join(", "),
+            last,
+            quantifier,
+            self.target.display()
+        )
+    }
+}
+
+/// Plans a batch of renames, each from an old path to a new one. The old
+/// paths must all be different; a rename to the path it comes from is left
+/// where it is in the order.
+pub fn plan_renames(renames: &[(PathBuf, PathBuf)]) -> RenamePlan {
+    let mut plan = RenamePlan::default();
+
+    // The first rename to each new name keeps it; the rest are collisions
+    let mut claimed: HashMap<&PathBuf, usize> = HashMap::new();
+    let mut lost = vec![false; renames.len()];
+    for (index, (_, to)) in renames.iter().enumerate() {
+        match claimed.get(to) {
+            Some(&first) => {
+                lost[index] = true;
+                match plan.collisions.iter_mut().find(|collision| &collision.target == to) {
+                    Some(collision) => collision.sources.push(renames[index].0.clone()),
+                    None => plan.collisions.push(Collision {
+                        target: to.clone(),
+                        sources: vec![renames[first].0.clone(), renames[index].0.clone()],
+                    }),
+                }
+            }
+            None => {
+                claimed.insert(to, index);
+            }
+        }
+    }
+
+    // `waited_on[i]` is the rename that can only be made after rename i has
+    // freed its old name; no rename waits on itself
+    let sources: HashMap<&PathBuf, usize> = renames
+        .iter()
+        .enumerate()
+        .map(|(index, (from, _))| (from, index))
+        .collect();
+    let mut waited_on = vec![None; renames.len()];
+    let mut waits = vec![false; renames.len()];
+    for (&to, &index) in &claimed {
+        if let Some(&blocker) = sources.get(to)
+            && blocker != index
+        {
+            waited_on[blocker] = Some(index);
+            waits[index] = true;
+        }
+    }
+
+    // A chain starts with a rename whose new name is free, then follows
+    // the renames waiting on each old name in turn
+    let mut placed = vec![false; renames.len()];
+    for start in 0..renames.len() {
+        if waits[start] || lost[start] {
+            continue;
+        }
+        let mut next = Some(start);
+        while let Some(index) = next {
+            plan.order.push(index);
+            placed[index] = true;
+            next = waited_on[index];
+        }
+    }
+
+    // What is left is cycles. Moving one file aside frees its name, after
+    // which the cycle is a chain that ends with that file
+    for start in 0..renames.len() {
+        if placed[start] || lost[start] {
+            continue;
+        }
+        plan.staged.push(start);
+        let mut next = waited_on[start];
+        while let Some(index) = next.filter(|&index| index != start) {
+            plan.order.push(index);
+            placed[index] = true;
+            next = waited_on[index];
+        }
+        plan.order.push(start);
+        placed[start] = true;
+    }
+
+    plan.order.extend((0..renames.len()).filter(|&index| lost[index]));
+    plan
+}
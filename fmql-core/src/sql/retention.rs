@@ -0,0 +1,419 @@
+//! Retention policies: what to delete, archive or compress, and when.
+//!
+//! A policy is a TOML file of rules. Each rule pairs a SELECT query, which
+//! picks the candidates, with an action and an age: files the query finds
+//! that haven't been modified for that long are deleted, moved under an
+//! archive directory, or gzipped in place.
+//!
+//! ```toml
+//! audit_log = "/var/log/fmql-retention.jsonl"
+//!
+//! [[rule]]
+//! name = "app logs"
+//! query = "WITH RECURSIVE SELECT * FROM /var/log/app WHERE extension = 'log'"
+//! after = "30d"
+//! action = "compress"
+//!
+//! [[rule]]
+//! name = "old exports"
+//! query = "WITH RECURSIVE SELECT * FROM /srv/exports"
+//! after = "1y"
+//! action = "archive"
+//! archive_to = "/mnt/cold/exports"
+//! ```
+//!
+//! Rules act on files and symlinks; directories are never touched. A file
+//! that several rules match is handled by the first of them. [`plan`] lists
+//! what a policy would do without doing it, and [`apply`] does it, carrying
+//! on past files that fail the way UPDATE does, and appending a line of JSON
+//! per action to the audit log as it goes. Both say which of a rule's
+//! conditions each file matched, so a batch of deletions can be checked
+//! against the intent of its rule at a glance.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use fmql_core::sql::retention::{apply, plan, Policy};
+//! use fmql_core::sql::ExecutionOptions;
+//!
+//! let policy = Policy::load("retention.toml".as_ref()).unwrap();
+//! let options = ExecutionOptions::default();
+//! for outcome in plan(&policy, &options).unwrap() {
+//!     println!("would {} {}", outcome.action, outcome.path.display());
+//! }
+//! apply(&policy, &options).unwrap();
+//! ```
+
+use std::collections::HashSet;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::sql::ast::{
+    ComparisonOperator, CompressFormat, FileAttribute, FileCondition, FileQuery, FileValue,
+};
+use crate::sql::compressed::compress_file;
+use crate::sql::executor::{
+    check_space, execute_query_with_options, lands_within_root, ExecutionOptions, ExecutorError,
+    OperationStatus, Result,
+};
+use crate::sql::lexer::parse_duration;
+use crate::sql::parser::parse_sql;
+use crate::sql::paths::glob_base;
+use crate::sql::space::{move_needs, SpaceNeeds};
+use crate::sql::throttle::Throttle;
+
+/// A set of retention rules, as read from a policy file.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Policy {
+    /// Where [`apply`] appends a JSON line for every action it takes.
+    #[serde(default)]
+    pub audit_log: Option<PathBuf>,
+    /// The rules, in the order they are applied.
+    #[serde(rename = "rule", default)]
+    pub rules: Vec<Rule>,
+}
+
+/// One retention rule.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Rule {
+    /// A name for reports and the audit log.
+    pub name: String,
+    /// A SELECT query for the files the rule covers.
+    pub query: String,
+    /// How long a file must go unmodified before the action applies, such
+    /// as `30d` or `1y`.
+    #[serde(deserialize_with = "duration")]
+    pub after: Duration,
+    /// What to do with the file.
+    pub action: Action,
+    /// The directory archived files are moved under, keeping their paths
+    /// below the query's FROM directory. Only for `archive`.
+    #[serde(default)]
+    pub archive_to: Option<PathBuf>,
+}
+
+/// What a rule does to the files it matches.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    /// Remove the file.
+    Delete,
+    /// Move the file under the rule's `archive_to` directory.
+    Archive,
+    /// Replace the file with a gzipped copy named `<name>.gz`.
+    Compress,
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let verb = match self {
+            Action::Delete => "delete",
+            Action::Archive => "archive",
+            Action::Compress => "compress",
+        };
+        f.write_str(verb)
+    }
+}
+
+/// An action a policy takes, or would take, on one file.
+#[derive(Debug, Clone, Serialize)]
+pub struct Outcome {
+    /// The name of the rule that matched the file.
+    pub rule: String,
+    /// What the rule does to it.
+    pub action: Action,
+    /// The file.
+    pub path: PathBuf,
+    /// The file size in bytes.
+    pub size: u64,
+    /// When the file was last modified.
+    #[serde(with = "chrono::serde::ts_seconds")]
+    pub modified: DateTime<Utc>,
+    /// Where the file ends up: the archived or compressed copy.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target: Option<PathBuf>,
+    /// How the action went; None when it was only planned.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<OperationStatus>,
+    /// Why the file was skipped or the action failed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    /// The parts of the rule's condition the file satisfied, its age
+    /// included, such as `extension = 'log' AND modified < '2026-01-01
+    /// 00:00:00'`, so a reviewer can see why the rule picked it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub matched: Option<String>,
+}
+
+/// A line of the audit log.
+#[derive(Serialize)]
+struct AuditEntry<'a> {
+    time: DateTime<Utc>,
+    #[serde(flatten)]
+    outcome: &'a Outcome,
+}
+
+impl Policy {
+    /// Reads a policy file.
+    pub fn load(path: &Path) -> Result<Policy> {
+        let text = fs::read_to_string(path).map_err(|err| {
+            io::Error::new(err.kind(), format!("can't read {}: {}", path.display(), err))
+        })?;
+        Policy::from_toml(&text)
+    }
+
+    /// Parses a policy, checking that every rule's query is a SELECT that
+    /// lists files and that archive rules say where to.
+    pub fn from_toml(text: &str) -> Result<Policy> {
+        let policy: Policy =
+            toml::from_str(text).map_err(|err| ExecutorError::InvalidPolicy(err.to_string()))?;
+        for rule in &policy.rules {
+            rule.select()?;
+            match (rule.action, &rule.archive_to) {
+                (Action::Archive, None) => return Err(rule.error("archive needs archive_to")),
+                (Action::Delete | Action::Compress, Some(_)) => {
+                    return Err(rule.error("archive_to is only for archive"));
+                }
+                _ => {}
+            }
+        }
+        Ok(policy)
+    }
+}
+
+impl Rule {
+    /// Parses the rule's query.
+    fn select(&self) -> Result<FileQuery> {
+        let query = parse_sql(&self.query).map_err(|err| self.error(&err.to_string()))?;
+        if !matches!(query, FileQuery::Select { .. }) || query.is_grouped() {
+            return Err(self.error("the query must be a SELECT that lists files"));
+        }
+        if query.reads_stdin() {
+            return Err(self.error("the query can't read FROM STDIN"));
+        }
+        Ok(query)
+    }
+
+    /// Builds an error about this rule.
+    fn error(&self, message: &str) -> ExecutorError {
+        ExecutorError::InvalidPolicy(format!("rule '{}': {}", self.name, message))
+    }
+}
+
+/// Lists what a policy would do, without changing anything.
+///
+/// Each rule's query runs with `options`, narrowed to files modified longer
+/// ago than the rule's `after`, as a dry run, so each outcome says which of
+/// the rule's conditions its file matched.
+pub fn plan(policy: &Policy, options: &ExecutionOptions) -> Result<Vec<Outcome>> {
+    let options = ExecutionOptions {
+        dry_run: true,
+        ..options.clone()
+    };
+    let now = Utc::now();
+    let mut seen = HashSet::new();
+    let mut planned = Vec::new();
+    for rule in &policy.rules {
+        let mut query = rule.select()?;
+        let from = match &mut query {
+            FileQuery::Select {
+                path, condition, ..
+            } => {
+                let cutoff = chrono::Duration::from_std(rule.after)
+                    .ok()
+                    .and_then(|age| now.checked_sub_signed(age))
+                    .unwrap_or(DateTime::<Utc>::MIN_UTC);
+                let old = FileCondition::Compare {
+                    attribute: FileAttribute::Modified,
+                    operator: ComparisonOperator::Lt,
+                    value: FileValue::DateTime(cutoff),
+                };
+                *condition = Some(match condition.take() {
+                    Some(condition) => FileCondition::And(Box::new(condition), Box::new(old)),
+                    None => old,
+                });
+                glob_base(path)
+            }
+            FileQuery::Update { .. }
+            | FileQuery::Copy { .. }
+            | FileQuery::Compress { .. }
+            | FileQuery::Extract { .. }
+            | FileQuery::Archive { .. }
+            | FileQuery::Restore { .. }
+            | FileQuery::ShowFilesystem { .. }
+            | FileQuery::Explain { .. } => {
+                unreachable!("rules are checked to be SELECTs")
+            }
+        };
+        for file in execute_query_with_options(&query, &options)? {
+            if file.is_directory || !seen.insert(file.path.clone()) {
+                continue;
+            }
+            let target = match (rule.action, &rule.archive_to) {
+                (Action::Archive, Some(archive_to)) => {
+                    let relative = file.path.strip_prefix(&from).unwrap_or(Path::new(&file.name));
+                    Some(archive_to.join(relative))
+                }
+                (Action::Compress, _) => Some(gzip_name(&file.path)),
+                _ => None,
+            };
+            planned.push(Outcome {
+                rule: rule.name.clone(),
+                action: rule.action,
+                path: file.path,
+                size: file.size,
+                modified: file.modified,
+                target,
+                status: None,
+                message: None,
+                matched: file.matched,
+            });
+        }
+    }
+    Ok(planned)
+}
+
+/// Carries out a policy and returns what happened to each file.
+///
+/// A file that can't be handled is reported as failed and the rest carry
+/// on. With an audit log, each action is appended to it as soon as it has
+/// been taken. Read-only options refuse to apply a policy at all, and a
+/// sandbox root applies to archive directories too. Nothing is done if an
+/// archive directory on another file system hasn't room for every file to
+/// be archived there. Files are compressed at the pace of
+/// [`ExecutionOptions::throttle`].
+pub fn apply(policy: &Policy, options: &ExecutionOptions) -> Result<Vec<Outcome>> {
+    if options.read_only {
+        return Err(ExecutorError::Denied(
+            "retention policies can't be applied in read-only mode".to_string(),
+        ));
+    }
+    if let Some(root) = &options.root {
+        let root = fs::canonicalize(root)?;
+        for archive_to in policy.rules.iter().filter_map(|rule| rule.archive_to.as_ref()) {
+            if !lands_within_root(archive_to, Some(&root)) {
+                return Err(ExecutorError::Denied(format!(
+                    "{} is outside the sandbox root",
+                    archive_to.display()
+                )));
+            }
+        }
+    }
+    let mut audit = match &policy.audit_log {
+        Some(path) => Some(OpenOptions::new().create(true).append(true).open(path)?),
+        None => None,
+    };
+
+    let mut outcomes = plan(policy, options)?;
+    // Archives on another file system are copied, so there must be room
+    // for all of them before the first one moves
+    let mut needs = SpaceNeeds::default();
+    for outcome in &outcomes {
+        if let (Action::Archive, Some(target), None) =
+            (outcome.action, &outcome.target, skip_reason(outcome))
+        {
+            needs.add(target, move_needs(&outcome.path, target)?)?;
+        }
+    }
+    check_space(&needs)?;
+
+    let throttle = options.throttle.map(Throttle::new);
+    for outcome in &mut outcomes {
+        if let Some(reason) = skip_reason(outcome) {
+            outcome.status = Some(OperationStatus::Skipped);
+            outcome.message = Some(reason);
+        } else {
+            let result = match (outcome.action, &outcome.target) {
+                (Action::Delete, _) => fs::remove_file(&outcome.path),
+                (Action::Archive, Some(target)) => archive(&outcome.path, target),
+                (Action::Compress, Some(target)) => {
+                    compress(&outcome.path, target, throttle.as_ref())
+                }
+                (_, None) => unreachable!("archive and compress always have a target"),
+            };
+            match result {
+                Ok(()) => outcome.status = Some(OperationStatus::Ok),
+                Err(err) => {
+                    outcome.status = Some(OperationStatus::Failed);
+                    outcome.message = Some(err.to_string());
+                }
+            }
+        }
+        if let Some(log) = &mut audit {
+            let entry = AuditEntry {
+                time: Utc::now(),
+                outcome,
+            };
+            let line = serde_json::to_string(&entry).map_err(io::Error::other)?;
+            writeln!(log, "{}", line)?;
+        }
+    }
+    Ok(outcomes)
+}
+
+/// Returns the name a file is compressed to.
+fn gzip_name(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".gz");
+    PathBuf::from(name)
+}
+
+/// Says why an action should be left undone: its target is taken, or the
+/// file is a symlink or already gzipped and so not worth compressing.
+fn skip_reason(outcome: &Outcome) -> Option<String> {
+    if outcome.action == Action::Compress {
+        if !fs::symlink_metadata(&outcome.path).is_ok_and(|metadata| metadata.is_file()) {
+            return Some("not a regular file".to_string());
+        }
+        if outcome.path.extension().is_some_and(|extension| extension == "gz") {
+            return Some("already compressed".to_string());
+        }
+    }
+    outcome
+        .target
+        .as_ref()
+        .filter(|target| target.symlink_metadata().is_ok())
+        .map(|target| format!("{} already exists", target.display()))
+}
+
+/// Moves a file to `target`, creating its directory. Across file systems
+/// the file is copied and then removed.
+fn archive(path: &Path, target: &Path) -> io::Result<()> {
+    if let Some(dir) = target.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    match fs::rename(path, target) {
+        Err(err) if err.kind() == io::ErrorKind::CrossesDevices => {
+            fs::copy(path, target)?;
+            File::options()
+                .write(true)
+                .open(target)?
+                .set_modified(fs::metadata(path)?.modified()?)?;
+            fs::remove_file(path)
+        }
+        result => result,
+    }
+}
+
+/// Replaces a file with a gzipped copy at `target`, keeping its
+/// permissions and modification time. The file is only removed once the
+/// copy has been checked; see [`compress_file`].
+fn compress(path: &Path, target: &Path, throttle: Option<&Throttle>) -> io::Result<()> {
+    let format = CompressFormat::Gzip;
+    compress_file(path, target, format, format.default_level(), throttle)?;
+    fs::remove_file(path)
+}
+
+/// Reads a duration such as `30d` from a policy file.
+fn duration<'de, D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Duration, D::Error> {
+    let text = String::deserialize(deserializer)?;
+    parse_duration(&text).map_err(serde::de::Error::custom)
+}
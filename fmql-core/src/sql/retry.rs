@@ -0,0 +1,116 @@
+//! Trying again after errors that may pass, as on network file systems.
+//!
+//! An NFS or SMB mount whose server is slow or briefly unreachable fails
+//! a `stat` or a read with `EIO` or `ETIMEDOUT` that works a moment later.
+//! A scan of a million files on such a mount would otherwise stop at the
+//! first one. A [`RetryPolicy`] runs an operation again after each
+//! [`Transient`] error, pausing twice as long each time, up to a number of
+//! retries; an error that doesn't pass, or any other error, is returned.
+//!
+//! [`ExecutionOptions::retry`] is the policy a query reads metadata and
+//! contents with, and a COPY makes its copies with. A file that still
+//! fails after the retries is left out and logged in
+//! [`ExecutionOptions::faults`], so that one bad file doesn't stop the
+//! scan; with [`Faults::strict`] it fails the query instead.
+//!
+//! [`ExecutionOptions::retry`]: crate::sql::ExecutionOptions::retry
+//! [`ExecutionOptions::faults`]: crate::sql::ExecutionOptions::faults
+//! [`Faults::strict`]: crate::sql::faults::Faults::strict
+//!
+//! # Examples
+//!
+//! ```
+//! use fmql_core::sql::retry::RetryPolicy;
+//! use std::io;
+//! use std::time::Duration;
+//!
+//! let policy = RetryPolicy { retries: 2, delay: Duration::from_millis(1) };
+//! let mut tries = 0;
+//! let read = policy.run(|| {
+//!     tries += 1;
+//!     match tries {
+//!         1 => Err(io::Error::from(io::ErrorKind::TimedOut)),
+//!         _ => Ok("contents"),
+//!     }
+//! });
+//! assert_eq!((read.unwrap(), tries), ("contents", 2));
+//!
+//! // Only errors that may pass are tried again
+//! let mut tries = 0;
+//! let missing = policy.run(|| -> io::Result<()> {
+//!     tries += 1;
+//!     Err(io::Error::from(io::ErrorKind::NotFound))
+//! });
+//! assert!(missing.is_err() && tries == 1);
+//! assert_eq!(policy.backoff(1), Duration::from_millis(2));
+//! ```
+
+use std::io;
+use std::thread;
+use std::time::Duration;
+
+use crate::sql::copy::is_transient;
+
+/// The longest pause between two tries, however many there were before.
+const MAX_DELAY: Duration = Duration::from_secs(10);
+
+/// How many times to try an operation again after an error that may pass,
+/// and how long to wait first; see the [module documentation](self).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// How many more times to try after the first failure.
+    pub retries: u32,
+    /// The pause before the first retry; each one after waits twice as
+    /// long as the one before, up to 10 seconds.
+    pub delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            retries: 2,
+            delay: Duration::from_millis(100),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// A policy that never tries again.
+    pub const NONE: RetryPolicy = RetryPolicy {
+        retries: 0,
+        delay: Duration::ZERO,
+    };
+
+    /// Runs `operation`, and runs it again after each transient error
+    /// until it succeeds, fails otherwise, or has been retried
+    /// [`retries`](Self::retries) times.
+    pub fn run<T, E: Transient>(&self, mut operation: impl FnMut() -> Result<T, E>) -> Result<T, E> {
+        let mut retries = 0;
+        loop {
+            match operation() {
+                Err(err) if err.is_transient() && retries < self.retries => {
+                    thread::sleep(self.backoff(retries));
+                    retries += 1;
+                }
+                done => return done,
+            }
+        }
+    }
+
+    /// Returns how long to wait before the retry after `retries` others.
+    pub fn backoff(&self, retries: u32) -> Duration {
+        self.delay.saturating_mul(1 << retries.min(16)).min(MAX_DELAY)
+    }
+}
+
+/// An error that may pass if the operation is tried again.
+pub trait Transient {
+    /// Returns true if trying again may succeed.
+    fn is_transient(&self) -> bool;
+}
+
+impl Transient for io::Error {
+    fn is_transient(&self) -> bool {
+        is_transient(self)
+    }
+}
@@ -0,0 +1,139 @@
+//! Sampled execution, for `SELECT ... SAMPLE 1%`.
+//!
+//! Counting the files on an enormous file system means reading the
+//! metadata of every one of them. A sampled query still lists every
+//! directory, since there is no telling what is below one without looking,
+//! but reads the metadata of only a fraction of the entries and runs the
+//! query on those. Which entries is decided by a hash of their path, so a
+//! query looks at the same sample every time it runs, and the sample is
+//! spread over the whole tree rather than taken from the first directories
+//! listed.
+//!
+//! The aggregates of a sampled query are estimates of what the whole query
+//! would give, each followed by the bounds of its 95% confidence interval
+//! in columns named after it with `_low` and `_high`:
+//!
+//! - `COUNT` and `SUM` are the sample's, scaled up by the sampling rate
+//! - `AVG` is the sample's mean
+//! - `MIN` and `MAX` are the sample's, which only bound the true value from
+//!   one side: the true `MAX` is at least the sample's, and its `_high` is
+//!   NULL
+//!
+//! Without aggregates, `SAMPLE` just returns the files it picked.
+//!
+//! `SAMPLE 100 ROWS` is another kind of sample: every match is found, and
+//! a random 100 of them are returned. `WEIGHTED BY size` makes a match more
+//! likely to be picked the larger it is, and `SEED 42` picks the same ones
+//! each run; `ORDER BY RANDOM(42)` shuffles the same way. Each match's
+//! chance comes from a hash of its path and the seed, so which are picked
+//! doesn't depend on the order they were found in.
+//!
+//! # Examples
+//!
+//! ```
+//! use fmql_core::sql::aggregate::Accumulator;
+//! use fmql_core::sql::ast::{AggregateFunction, FileValue};
+//! use fmql_core::sql::sample::parse_sample;
+//!
+//! let fraction = parse_sample("10%").unwrap();
+//! // 250 of the sampled files matched
+//! let mut count = Accumulator::new(AggregateFunction::Count);
+//! for _ in 0..250 {
+//!     count.add(None).unwrap();
+//! }
+//! let estimate = count.estimate(fraction);
+//! assert_eq!(estimate.value, FileValue::Number(2500.0));
+//! assert_eq!(estimate.low, FileValue::Number(2206.0));
+//! assert_eq!(estimate.high, FileValue::Number(2794.0));
+//! ```
+//!
+//! ```
+//! use std::path::Path;
+//! use fmql_core::sql::sample::{draw, pick};
+//!
+//! let paths = ["a.txt", "b.txt", "c.txt", "d.txt"];
+//! let draws: Vec<(f64, f64)> =
+//!     paths.iter().map(|path| (draw(Path::new(path), 42), 1.0)).collect();
+//! let picked = pick(&draws, 2);
+//! assert_eq!(picked.len(), 2);
+//! // The same seed picks the same rows
+//! assert_eq!(picked, pick(&draws, 2));
+//! ```
+
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, DefaultHasher, Hash, Hasher};
+use std::path::Path;
+
+use crate::sql::ast::FileValue;
+
+/// How many standard errors either side of an estimate its 95% confidence
+/// interval reaches.
+pub const Z_95: f64 = 1.959_964;
+
+/// An aggregate estimated from a sample, with the bounds of its 95%
+/// confidence interval. A bound is NULL where the sample says nothing
+/// about it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Estimate {
+    /// The best guess at the value.
+    pub value: FileValue,
+    /// The value is probably no less than this.
+    pub low: FileValue,
+    /// The value is probably no more than this.
+    pub high: FileValue,
+}
+
+/// Reads a sampling rate written as a percentage, `1%` or just `1`, as a
+/// fraction.
+pub fn parse_sample(text: &str) -> Result<f64, String> {
+    let number = text.trim().trim_end_matches('%').trim();
+    match number.parse::<f64>() {
+        Ok(percent) if percent > 0.0 && percent <= 100.0 => Ok(percent / 100.0),
+        _ => Err(format!("Expected a percentage between 0 and 100, such as 1%, not '{}'", text)),
+    }
+}
+
+/// Returns true if the entry at `path` is part of a sample of `fraction` of
+/// the entries.
+pub fn includes(path: &Path, fraction: f64) -> bool {
+    if fraction >= 1.0 {
+        return true;
+    }
+    let mut hasher = DefaultHasher::new();
+    path.hash(&mut hasher);
+    (hasher.finish() as f64) < fraction * u64::MAX as f64
+}
+
+/// Returns a number from 0 up to 1 for the entry at `path`: the same for
+/// the same path and seed, and unrelated from one seed to another.
+pub fn draw(path: &Path, seed: u64) -> f64 {
+    let mut hasher = DefaultHasher::new();
+    seed.hash(&mut hasher);
+    path.hash(&mut hasher);
+    (hasher.finish() >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// Returns a seed for a random order or sample that differs each run.
+pub fn random_seed() -> u64 {
+    RandomState::new().build_hasher().finish()
+}
+
+/// Picks `rows` of the items of a `SAMPLE n ROWS`, given each one's
+/// [`draw`] and weight, and returns their indices in order. An item's
+/// chance of being picked is in proportion to its weight (Efraimidis and
+/// Spirakis's method: the items with the highest `draw^(1/weight)` win).
+/// Items with no weight are only picked when there aren't enough others.
+pub fn pick(items: &[(f64, f64)], rows: usize) -> Vec<usize> {
+    if items.len() <= rows {
+        return (0..items.len()).collect();
+    }
+    // Compared as logarithms, which small weights don't round to 0
+    let key = |(draw, weight): (f64, f64)| {
+        if weight > 0.0 { draw.ln() / weight } else { f64::NEG_INFINITY }
+    };
+    let mut indices: Vec<usize> = (0..items.len()).collect();
+    indices.sort_by(|&a, &b| key(items[b]).total_cmp(&key(items[a])));
+    indices.truncate(rows);
+    indices.sort_unstable();
+    indices
+}
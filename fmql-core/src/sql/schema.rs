@@ -0,0 +1,316 @@
+//! The columns of a query's output, with their types.
+//!
+//! Loaders such as pandas or DuckDB guess column types by sniffing the
+//! first rows, which goes wrong on a column that starts out empty or holds
+//! numeric-looking names. A query already knows its types, so fmql can say
+//! them up front: `--csv-types` adds them to CSV output and `--schema` adds
+//! a `schema` block to JSON, YAML and TOML.
+//!
+//! The columns are the ones the table formats (CSV, Markdown, HTML) show,
+//! and the fields of each row that JSON, YAML and TOML write with their
+//! schema: for a SELECT, the attributes and expressions it lists, with `*`
+//! standing for the attributes every file has; for grouped queries, the
+//! GROUP BY columns and the aggregates; for `ROLLUP BY path`, the directory
+//! and its aggregates. Those formats write dates as Unix seconds and
+//! durations as seconds, so their schema gives those columns a `unit`.
+//!
+//! # Examples
+//!
+//! ```
+//! use fmql_core::sql::catalog::ValueType;
+//! use fmql_core::sql::parse_sql;
+//! use fmql_core::sql::schema::schema;
+//!
+//! let query = parse_sql("SELECT owner, size / 1024 AS kib FROM .").unwrap();
+//! let columns: Vec<_> = schema(&query).into_iter().map(|c| (c.name, c.value_type)).collect();
+//! assert_eq!(
+//!     columns,
+//!     vec![
+//!         ("owner".to_string(), ValueType::String),
+//!         ("kib".to_string(), ValueType::Number),
+//!     ]
+//! );
+//! ```
+
+use serde::Serialize;
+
+use crate::sql::ast::{
+    AggregateColumn, AggregateFunction, ArithmeticOperator, CastType, FileAttribute, FileExpr,
+    FileQuery, FileValue,
+};
+use crate::sql::catalog::{column, functions, ValueType};
+
+/// One output column.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SchemaColumn {
+    /// The column name, as it appears in the output.
+    pub name: String,
+    /// The type of the column's values.
+    pub value_type: ValueType,
+}
+
+impl SchemaColumn {
+    fn new(name: impl Into<String>, value_type: ValueType) -> Self {
+        SchemaColumn {
+            name: name.into(),
+            value_type,
+        }
+    }
+}
+
+impl Serialize for SchemaColumn {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+
+        let unit = matches!(self.value_type, ValueType::DateTime | ValueType::Duration);
+        let mut column = serializer.serialize_struct("SchemaColumn", 2 + usize::from(unit))?;
+        column.serialize_field("name", &self.name)?;
+        column.serialize_field("type", &self.value_type.to_string())?;
+        // Dates are written as seconds since the Unix epoch
+        if unit {
+            column.serialize_field("unit", "seconds")?;
+        }
+        column.end()
+    }
+}
+
+/// Returns the output columns of a query.
+pub fn schema(query: &FileQuery) -> Vec<SchemaColumn> {
+    let mut columns = vec![SchemaColumn::new("path", ValueType::String)];
+    match query {
+        FileQuery::Select {
+            aggregates,
+            rollup: true,
+            ..
+        } => {
+            columns.extend(aggregates.iter().map(aggregate_column));
+        }
+        FileQuery::Select {
+            aggregates,
+            group_by,
+            sample,
+            ..
+        } if query.is_grouped() => {
+            columns = group_by
+                .iter()
+                .map(|key| SchemaColumn::new(&key.name, expr_type(&key.expr)))
+                .collect();
+            for aggregate in aggregates {
+                let column = aggregate_column(aggregate);
+                // A sampled query bounds each estimate
+                if sample.is_some() {
+                    let bound = |suffix| {
+                        SchemaColumn::new(format!("{}_{}", column.name, suffix), column.value_type)
+                    };
+                    let (low, high) = (bound("low"), bound("high"));
+                    columns.extend([column, low, high]);
+                } else {
+                    columns.push(column);
+                }
+            }
+        }
+        FileQuery::Select {
+            attributes,
+            computed,
+            ..
+        } => {
+            // A query built without a select list returns everything
+            columns = match (&attributes[..], &computed[..]) {
+                ([], []) => selected_columns(&[FileAttribute::All]),
+                _ => selected_columns(attributes),
+            };
+            columns.extend(
+                computed
+                    .iter()
+                    .map(|column| SchemaColumn::new(&column.name, expr_type(&column.expr))),
+            );
+        }
+        FileQuery::Update { .. } => {
+            columns.push(SchemaColumn::new("size", ValueType::Number));
+            columns.push(SchemaColumn::new("modified", ValueType::DateTime));
+            columns.push(SchemaColumn::new("status", ValueType::String));
+        }
+        FileQuery::Copy { verify, .. } => {
+            columns.push(SchemaColumn::new("size", ValueType::Number));
+            columns.push(SchemaColumn::new("modified", ValueType::DateTime));
+            columns.push(SchemaColumn::new("source", ValueType::String));
+            if *verify {
+                columns.push(SchemaColumn::new("checksum", ValueType::String));
+            }
+            for stat in ["transferred", "resumed", "retries", "seconds"] {
+                columns.push(SchemaColumn::new(stat, ValueType::Number));
+            }
+            columns.push(SchemaColumn::new("status", ValueType::String));
+        }
+        FileQuery::Compress { .. } => {
+            columns.push(SchemaColumn::new("size", ValueType::Number));
+            columns.push(SchemaColumn::new("modified", ValueType::DateTime));
+            columns.push(SchemaColumn::new("source", ValueType::String));
+            columns.push(SchemaColumn::new("original_size", ValueType::Number));
+            columns.push(SchemaColumn::new("status", ValueType::String));
+        }
+        FileQuery::Extract { .. } => {
+            columns.push(SchemaColumn::new("size", ValueType::Number));
+            columns.push(SchemaColumn::new("modified", ValueType::DateTime));
+            columns.push(SchemaColumn::new("destination", ValueType::String));
+            for stat in ["files", "bytes", "skipped"] {
+                columns.push(SchemaColumn::new(stat, ValueType::Number));
+            }
+            columns.push(SchemaColumn::new("status", ValueType::String));
+        }
+        FileQuery::Archive { .. } => {
+            columns.push(SchemaColumn::new("size", ValueType::Number));
+            columns.push(SchemaColumn::new("modified", ValueType::DateTime));
+            columns.push(SchemaColumn::new("destination", ValueType::String));
+            columns.push(SchemaColumn::new("status", ValueType::String));
+        }
+        FileQuery::Restore { .. } => {
+            columns.push(SchemaColumn::new("size", ValueType::Number));
+            columns.push(SchemaColumn::new("modified", ValueType::DateTime));
+            columns.push(SchemaColumn::new("source", ValueType::String));
+            columns.push(SchemaColumn::new("status", ValueType::String));
+        }
+        FileQuery::ShowFilesystem { .. } => {
+            columns.push(SchemaColumn::new("filesystem", ValueType::String));
+            for flag in ["case_sensitive", "symlinks", "xattrs", "birthtime"] {
+                columns.push(SchemaColumn::new(flag, ValueType::Boolean));
+            }
+            columns.push(SchemaColumn::new("max_name_length", ValueType::Number));
+            columns.push(SchemaColumn::new("max_path_length", ValueType::Number));
+        }
+        FileQuery::Explain { .. } => {
+            columns = vec![
+                SchemaColumn::new("step", ValueType::String),
+                SchemaColumn::new("detail", ValueType::String),
+            ];
+        }
+    }
+    columns
+}
+
+/// The attributes `*` selects: those every file result carries, then the
+/// optional ones it adds.
+const ALL_COLUMNS: [FileAttribute; 10] = [
+    FileAttribute::Path,
+    FileAttribute::Name,
+    FileAttribute::Size,
+    FileAttribute::IsDirectory,
+    FileAttribute::IsSymlink,
+    FileAttribute::Extension,
+    FileAttribute::Permissions,
+    FileAttribute::Modified,
+    FileAttribute::Accessed,
+    FileAttribute::Owner,
+];
+
+/// Returns the columns of the attributes a SELECT lists, in its order, with
+/// `*` standing for [`ALL_COLUMNS`].
+fn selected_columns(attributes: &[FileAttribute]) -> Vec<SchemaColumn> {
+    let mut columns: Vec<SchemaColumn> = Vec::new();
+    for attribute in attributes {
+        let listed = match attribute {
+            FileAttribute::All => &ALL_COLUMNS[..],
+            attribute => std::slice::from_ref(attribute),
+        };
+        for attribute in listed {
+            if !columns.iter().any(|column| column.name == attribute.name()) {
+                columns.push(SchemaColumn::new(attribute.name(), attribute_type(attribute)));
+            }
+        }
+    }
+    columns
+}
+
+/// Returns the type of an expression's values, as far as it is known
+/// before the query runs. Anything that can't be told, such as a CASE
+/// whose branches disagree, is a string.
+pub fn expr_type(expr: &FileExpr) -> ValueType {
+    match expr {
+        FileExpr::Literal(value) => match value {
+            FileValue::Integer(_) | FileValue::Number(_) => ValueType::Number,
+            FileValue::DateTime(_) => ValueType::DateTime,
+            FileValue::Duration(_) => ValueType::Duration,
+            FileValue::Boolean(_) => ValueType::Boolean,
+            FileValue::String(_) | FileValue::Null => ValueType::String,
+        },
+        FileExpr::Attribute(attribute) => attribute_type(attribute),
+        // Only known once the table has been read
+        FileExpr::Joined { .. } => ValueType::String,
+        // The type of the values they may return
+        FileExpr::Function { name, args } if name.eq_ignore_ascii_case("COALESCE") => {
+            common_type(args)
+        }
+        FileExpr::Function { name, args }
+            if name.eq_ignore_ascii_case("NULLIF") || name.eq_ignore_ascii_case("TRY") =>
+        {
+            args.first().map_or(ValueType::String, expr_type)
+        }
+        FileExpr::Function { name, .. } => functions()
+            .iter()
+            .find(|function| function.name == name)
+            .map_or(ValueType::String, |function| function.value_type),
+        FileExpr::Case {
+            branches,
+            otherwise,
+        } => common_type(branches.iter().map(|(_, value)| value).chain(otherwise.as_deref())),
+        // Dates subtract to a duration and move by one, or by a number of
+        // seconds; anything else is arithmetic
+        FileExpr::Binary {
+            left,
+            operator,
+            right,
+        } => match (expr_type(left), operator, expr_type(right)) {
+            (ValueType::DateTime, ArithmeticOperator::Sub, ValueType::DateTime) => {
+                ValueType::Duration
+            }
+            (ValueType::DateTime, ArithmeticOperator::Add | ArithmeticOperator::Sub, _)
+            | (ValueType::Duration, ArithmeticOperator::Add, ValueType::DateTime) => {
+                ValueType::DateTime
+            }
+            (
+                ValueType::Duration,
+                ArithmeticOperator::Add | ArithmeticOperator::Sub,
+                ValueType::Duration,
+            ) => ValueType::Duration,
+            _ => ValueType::Number,
+        },
+        // Octal digits are read into a number, and a number written as them
+        FileExpr::Cast { expr, to } => match to {
+            CastType::Octal if expr_type(expr) == ValueType::String => ValueType::Number,
+            CastType::Text | CastType::Octal => ValueType::String,
+            CastType::Number | CastType::Integer => ValueType::Number,
+            CastType::Date => ValueType::DateTime,
+            CastType::Boolean => ValueType::Boolean,
+        },
+    }
+}
+
+/// Returns the type every one of some expressions has, leaving out NULLs,
+/// or string if they disagree.
+fn common_type<'a>(exprs: impl IntoIterator<Item = &'a FileExpr>) -> ValueType {
+    let mut types = exprs
+        .into_iter()
+        .filter(|expr| !matches!(expr, FileExpr::Literal(FileValue::Null)))
+        .map(expr_type);
+    let first = types.next().unwrap_or(ValueType::String);
+    if types.all(|other| other == first) {
+        first
+    } else {
+        ValueType::String
+    }
+}
+
+/// Returns the type of an attribute's values.
+fn attribute_type(attribute: &FileAttribute) -> ValueType {
+    column(attribute).map_or(ValueType::String, |column| column.value_type)
+}
+
+/// Returns the column for an aggregate: counts, sums and averages are
+/// numbers, and MIN and MAX have the type of what they compare.
+fn aggregate_column(aggregate: &AggregateColumn) -> SchemaColumn {
+    let value_type = match (aggregate.function, &aggregate.argument) {
+        (AggregateFunction::Min | AggregateFunction::Max, Some(argument)) => expr_type(argument),
+        _ => ValueType::Number,
+    };
+    SchemaColumn::new(&aggregate.name, value_type)
+}
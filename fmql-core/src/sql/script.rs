@@ -0,0 +1,282 @@
+//! Scripts of several statements, for `fmql apply`.
+//!
+//! A script is statements separated by semicolons, with `--` and `/* */`
+//! comments anywhere. [`split_statements`] cuts it into its statements
+//! without parsing them, so that each is parsed, and its errors reported,
+//! on its own. A semicolon inside quotes or a comment doesn't end a
+//! statement; one in an unquoted path does, so such a path must be quoted.
+//!
+//! [`validate`] dry-runs every statement of a script (see
+//! [`ExecutionOptions::dry_run`]) and reports every change that would fail,
+//! so that a script can be refused before it changes anything. Each
+//! statement is checked against the file system as it is before the script
+//! runs, so on top of what each statement would do on its own, a statement
+//! that writes a path an earlier one writes, or works on a file an earlier
+//! one renames away, is a problem, as is a file system without room for
+//! the copies of every statement together.
+//!
+//! # Examples
+//!
+//! ```
+//! use std::fs;
+//!
+//! use fmql_core::sql::executor::ExecutionOptions;
+//! use fmql_core::sql::parse_sql;
+//! use fmql_core::sql::script::{split_statements, validate};
+//!
+//! let dir = tempfile::tempdir().unwrap();
+//! fs::write(dir.path().join("a.txt"), "a").unwrap();
+//! fs::write(dir.path().join("b.txt"), "b").unwrap();
+//! let script = format!(
+//!     "-- Both statements want the name c.txt\n\
+//!      UPDATE {0} SET name = 'c.txt' WHERE name = 'a.txt';\n\
+//!      UPDATE {0} SET name = 'c.txt' WHERE name = 'b.txt';\n",
+//!     dir.path().display()
+//! );
+//! let statements = split_statements(&script);
+//! assert_eq!(statements.len(), 2);
+//! assert_eq!(statements[1].line, 3);
+//!
+//! let queries: Vec<_> = statements.iter().map(|s| parse_sql(&s.text).unwrap()).collect();
+//! let problems = validate(&queries, &ExecutionOptions::default());
+//! assert_eq!(problems.len(), 1);
+//! assert_eq!(problems[0].statement, Some(2));
+//! // Nothing was renamed
+//! assert!(dir.path().join("a.txt").exists());
+//! ```
+
+use std::collections::HashMap;
+use std::fmt;
+use std::path::PathBuf;
+
+use crate::sql::ast::{FileAttribute, FileQuery, FileValue};
+use crate::sql::executor::{
+    execute_query_with_options, ExecutionOptions, FileResult, OperationStatus,
+};
+use crate::sql::space::{copy_needs, SpaceNeeds};
+
+/// One statement of a script.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Statement {
+    /// The statement, without the semicolon that ends it.
+    pub text: String,
+    /// The line of the script its first word is on, from 1.
+    pub line: u64,
+}
+
+/// Splits a script into its statements, leaving out those that are empty
+/// or only comments.
+pub fn split_statements(script: &str) -> Vec<Statement> {
+    let mut statements = Vec::new();
+    let mut text = String::new();
+    // The line the statement's first word is on, once it has one
+    let mut start = None;
+    let mut line = 1;
+    let mut chars = script.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            ';' => {
+                if let Some(line) = start.take() {
+                    statements.push(Statement {
+                        text: std::mem::take(&mut text),
+                        line,
+                    });
+                }
+                text.clear();
+                continue;
+            }
+            '\'' | '"' => {
+                start.get_or_insert(line);
+                text.push(c);
+                for quoted in chars.by_ref() {
+                    text.push(quoted);
+                    if quoted == '\n' {
+                        line += 1;
+                    }
+                    if quoted == c {
+                        break;
+                    }
+                }
+                continue;
+            }
+            '-' if chars.peek() == Some(&'-') => {
+                text.push(c);
+                while let Some(&next) = chars.peek() {
+                    if next == '\n' {
+                        break;
+                    }
+                    text.push(next);
+                    chars.next();
+                }
+                continue;
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                text.push(c);
+                text.push(chars.next().expect("peeked"));
+                let mut previous = ' ';
+                for commented in chars.by_ref() {
+                    text.push(commented);
+                    if commented == '\n' {
+                        line += 1;
+                    }
+                    if previous == '*' && commented == '/' {
+                        break;
+                    }
+                    previous = commented;
+                }
+                continue;
+            }
+            '\n' => line += 1,
+            c if c.is_whitespace() => {}
+            _ => {
+                start.get_or_insert(line);
+            }
+        }
+        text.push(c);
+    }
+    if let Some(line) = start {
+        statements.push(Statement { text, line });
+    }
+    statements
+}
+
+/// A change a script would fail to make.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Problem {
+    /// The statement that would fail, from 1, or None for a problem with
+    /// the script as a whole, such as running out of space.
+    pub statement: Option<usize>,
+    /// The file it would fail on, if it is about one file.
+    pub path: Option<PathBuf>,
+    /// What would go wrong.
+    pub message: String,
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(statement) = self.statement {
+            write!(f, "statement {}: ", statement)?;
+        }
+        if let Some(path) = &self.path {
+            write!(f, "{}: ", path.display())?;
+        }
+        write!(f, "{}", self.message)
+    }
+}
+
+/// Dry-runs every UPDATE, COPY and COMPRESS of a script, in order, and
+/// returns what would go wrong, or nothing if the whole script can be run.
+/// SELECT and SHOW statements change nothing, and are not run.
+pub fn validate(queries: &[FileQuery], options: &ExecutionOptions) -> Vec<Problem> {
+    let options = ExecutionOptions {
+        dry_run: true,
+        ..options.clone()
+    };
+    let mut problems = Vec::new();
+    // The paths earlier statements rename files away from, and the paths
+    // they give files or copies, by statement
+    let mut moved: HashMap<PathBuf, usize> = HashMap::new();
+    let mut written: HashMap<PathBuf, usize> = HashMap::new();
+    let mut needs = SpaceNeeds::default();
+
+    for (index, query) in queries.iter().enumerate() {
+        let statement = index + 1;
+        let problem = |path: Option<PathBuf>, message: String| Problem {
+            statement: Some(statement),
+            path,
+            message,
+        };
+        let keeps_originals = match query {
+            FileQuery::Update { .. } => false,
+            FileQuery::Copy { .. } => true,
+            FileQuery::Compress { delete_original, .. } => !delete_original,
+            FileQuery::Extract { .. } => true,
+            FileQuery::Archive { delete_original, .. } => !delete_original,
+            FileQuery::Restore { .. } => false,
+            FileQuery::Select { .. }
+            | FileQuery::ShowFilesystem { .. }
+            | FileQuery::Explain { .. } => continue,
+        };
+        let results = match execute_query_with_options(query, &options) {
+            Ok(results) => results,
+            Err(err) => {
+                problems.push(problem(None, err.to_string()));
+                continue;
+            }
+        };
+
+        for result in &results {
+            match result.status {
+                Some(OperationStatus::Failed) => {
+                    let message = result.error_message.clone().unwrap_or_default();
+                    problems.push(problem(Some(result.path.clone()), message));
+                    continue;
+                }
+                Some(OperationStatus::Ok) => {}
+                _ => continue,
+            }
+            let (source, target) = planned_paths(result);
+            // Archives may share a directory to extract into, and files an
+            // archive to be written into
+            let target = target.filter(|_| {
+                !matches!(query, FileQuery::Extract { .. } | FileQuery::Archive { .. })
+            });
+            if let Some(&earlier) = moved.get(&source) {
+                let message = format!("statement {} renames it away first", earlier);
+                problems.push(problem(Some(source.clone()), message));
+            }
+            if let Some(target) = &target
+                && let Some(&earlier) = written.get(target)
+            {
+                let message = format!("statement {} writes this path too", earlier);
+                problems.push(problem(Some(target.clone()), message));
+            }
+
+            if let (Some(method), Some(target)) = (result.copied, &target) {
+                let needed = copy_needs(&source, target, method)
+                    .and_then(|bytes| needs.add(target, bytes));
+                if let Err(err) = needed {
+                    problems.push(problem(Some(target.clone()), err.to_string()));
+                }
+            } else if target.is_some() && !keeps_originals {
+                moved.entry(source).or_insert(statement);
+            }
+            if let Some(target) = target {
+                written.entry(target).or_insert(statement);
+            }
+        }
+    }
+
+    match needs.shortfalls() {
+        Ok(shortfalls) => problems.extend(shortfalls.into_iter().map(|shortfall| Problem {
+            statement: None,
+            path: None,
+            message: shortfall.to_string(),
+        })),
+        Err(err) => problems.push(Problem {
+            statement: None,
+            path: None,
+            message: format!("cannot tell how much space is free: {}", err),
+        }),
+    }
+    problems
+}
+
+/// Returns the path a dry-run result starts from, and the path it would
+/// write, if any: the new name of a renamed file, or the copy of a copied
+/// one.
+fn planned_paths(result: &FileResult) -> (PathBuf, Option<PathBuf>) {
+    for change in &result.changes {
+        match (&change.attribute, &change.old_value, &change.new_value) {
+            (FileAttribute::Path, FileValue::String(from), FileValue::String(to)) => {
+                return (PathBuf::from(from), Some(PathBuf::from(to)));
+            }
+            (FileAttribute::Name, FileValue::String(from), _) => {
+                return (result.path.with_file_name(from), Some(result.path.clone()));
+            }
+            _ => {}
+        }
+    }
+    (result.path.clone(), None)
+}
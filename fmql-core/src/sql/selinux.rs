@@ -0,0 +1,80 @@
+//! SELinux security contexts, as shown by `ls -Z`.
+//!
+//! On an SELinux system every file carries a label in its
+//! `security.selinux` extended attribute, such as
+//! `system_u:object_r:httpd_sys_content_t:s0`, and a file copied or moved
+//! into place keeps the label it had before, so a web root full of
+//! `user_home_t` files can't be served. The `security_context` attribute
+//! reads that label, which makes finding mislabeled files before running
+//! `restorecon` a query: `security_context NOT LIKE '%:httpd_sys_content_t:%'`.
+//! Files without a label, and every file on systems other than Linux, have
+//! a NULL context.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use std::path::Path;
+//!
+//! use fmql_core::sql::selinux::read_context;
+//!
+//! let context = read_context(Path::new("/var/www/html/index.html")).unwrap();
+//! assert_eq!(context.as_deref(), Some("system_u:object_r:httpd_sys_content_t:s0"));
+//! ```
+
+use std::io;
+use std::path::Path;
+
+/// Reads the security context of a file, without following a symlink, or
+/// returns None if it has none.
+#[cfg(target_os = "linux")]
+pub fn read_context(path: &Path) -> io::Result<Option<String>> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = CString::new(path.as_os_str().as_bytes())?;
+    let name = c"security.selinux";
+    let mut buffer = vec![0u8; 256];
+    loop {
+        // SAFETY: both strings are NUL-terminated and outlive the call, and
+        // the buffer is as long as the size passed.
+        let size = unsafe {
+            libc::lgetxattr(path.as_ptr(), name.as_ptr(), buffer.as_mut_ptr().cast(), buffer.len())
+        };
+        if let Ok(size) = usize::try_from(size) {
+            buffer.truncate(size);
+            break;
+        }
+        let err = io::Error::last_os_error();
+        match err.raw_os_error() {
+            // The label is longer than the buffer; ask how long it is
+            Some(libc::ERANGE) => {
+                // SAFETY: as above, and a null buffer of size zero only asks
+                // for the length.
+                let size = unsafe {
+                    libc::lgetxattr(path.as_ptr(), name.as_ptr(), std::ptr::null_mut(), 0)
+                };
+                let size = usize::try_from(size).map_err(|_| io::Error::last_os_error())?;
+                buffer.resize(size.max(buffer.len() * 2), 0);
+            }
+            Some(libc::ENODATA | libc::ENOTSUP) => return Ok(None),
+            _ => return Err(err),
+        }
+    }
+
+    // The kernel keeps the terminating NUL as part of the label
+    while buffer.last() == Some(&0) {
+        buffer.pop();
+    }
+    Ok(Some(String::from_utf8_lossy(&buffer).into_owned()))
+}
+
+/// Reads the security context of a file; only Linux has them.
+#[cfg(not(target_os = "linux"))]
+pub fn read_context(_path: &Path) -> io::Result<Option<String>> {
+    Ok(None)
+}
+
+/// Returns true if this system can have security contexts.
+pub fn supported() -> bool {
+    cfg!(target_os = "linux")
+}
@@ -0,0 +1,159 @@
+//! Sharded scans, for splitting one query over many fmql processes.
+//!
+//! Scanning a shared file system of billions of files from one client takes
+//! days, however fast the client is; the server can answer many clients at
+//! once. With `--shard 3/8`, a process only looks at the third of eight
+//! shares of the tree: the entries directly inside each FROM directory are
+//! dealt out between the shards by a hash of their name, and each shard
+//! walks everything below the entries it was dealt and skips the rest
+//! without listing it. The eight shards, run anywhere that sees the tree
+//! under the same path, together visit each entry exactly once.
+//!
+//! A shard run with `--partial` writes a [`ShardResult`] instead of its
+//! results: the paths it matched, or for a grouped query the running totals
+//! of its groups. `fmql merge` checks that it was given every shard of the
+//! same query and combines them into the query's results, sorting and
+//! limiting them as the query says and merging the groups' totals, so
+//! `AVG` over eight shards is the average of everything, not of eight
+//! averages.
+//!
+//! ```text
+//! for i in $(seq 1 8); do
+//!     ssh "nfs-client-$i" fmql sql --shard "$i/8" --partial "$QUERY" > "part-$i.json" &
+//! done
+//! wait
+//! fmql merge part-*.json
+//! ```
+//!
+//! # Examples
+//!
+//! ```
+//! use std::ffi::OsStr;
+//!
+//! use fmql_core::sql::shard::Shard;
+//!
+//! let shards: Vec<Shard> = (1..=4).map(|i| Shard::parse(&format!("{}/4", i)).unwrap()).collect();
+//! // Every name belongs to exactly one shard
+//! let owners = shards.iter().filter(|shard| shard.owns(OsStr::new("src"))).count();
+//! assert_eq!(owners, 1);
+//! assert_eq!(shards[2].to_string(), "3/4");
+//!
+//! assert!(Shard::parse("5/4").is_err());
+//! assert!(Shard::parse("0/4").is_err());
+//! ```
+
+use std::ffi::OsStr;
+use std::fmt;
+use std::os::unix::ffi::OsStrExt;
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use crate::sql::aggregate::Accumulator;
+use crate::sql::ast::{FileQuery, FileValue};
+use crate::sql::executor::{ExecutorError, Result};
+
+/// One of the shares a sharded scan is split into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Shard {
+    /// Which share this is, counting from 1.
+    pub index: usize,
+    /// How many shares there are.
+    pub count: usize,
+}
+
+impl Shard {
+    /// Reads a shard written as `3/8`: the third of eight.
+    pub fn parse(text: &str) -> std::result::Result<Shard, String> {
+        let invalid = || format!("Expected a shard such as 3/8, not '{}'", text);
+        let (index, count) = text.trim().split_once('/').ok_or_else(invalid)?;
+        let index: usize = index.trim().parse().map_err(|_| invalid())?;
+        let count: usize = count.trim().parse().map_err(|_| invalid())?;
+        if index == 0 || index > count {
+            return Err(format!("Shard {} is not between 1 and {}", index, count));
+        }
+        Ok(Shard { index, count })
+    }
+
+    /// Returns true if the entry called `name`, directly inside a FROM
+    /// directory, is in this shard.
+    ///
+    /// The hash is FNV-1a, written out here rather than taken from the
+    /// standard library, so that every build of fmql on every machine deals
+    /// out the entries the same way.
+    pub fn owns(&self, name: &OsStr) -> bool {
+        let hash = name
+            .as_bytes()
+            .iter()
+            .fold(0xcbf2_9ce4_8422_2325_u64, |hash, &byte| {
+                (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+            });
+        hash % self.count as u64 == (self.index - 1) as u64
+    }
+}
+
+impl fmt::Display for Shard {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.index, self.count)
+    }
+}
+
+/// One group of a grouped query, with the running totals of its aggregates.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PartialGroup {
+    /// The group's GROUP BY values.
+    pub key: Vec<FileValue>,
+    /// The state of each aggregate, in the order the query lists them.
+    pub accumulators: Vec<Accumulator>,
+}
+
+/// What one shard of a query found, for merging with the other shards.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ShardResult {
+    /// The query the shard ran.
+    pub query: FileQuery,
+    /// Which shard this is.
+    pub shard: Shard,
+    /// The paths of the matching files, for a query that returns files.
+    #[serde(default)]
+    pub matches: Vec<PathBuf>,
+    /// The groups found, for a grouped query.
+    #[serde(default)]
+    pub groups: Vec<PartialGroup>,
+}
+
+/// Checks that `parts` are every shard of one query, each once, and returns
+/// the query.
+pub fn check_parts(parts: &[ShardResult]) -> Result<&FileQuery> {
+    let invalid = |message: String| Err(ExecutorError::InvalidShards(message));
+    let Some(first) = parts.first() else {
+        return invalid("there are no shards to merge".to_string());
+    };
+    let query = serde_json::to_value(&first.query).ok();
+    let mut seen = vec![false; first.shard.count];
+    for part in parts {
+        if serde_json::to_value(&part.query).ok() != query {
+            return invalid(format!("shard {} ran a different query", part.shard));
+        }
+        if part.shard.index == 0 || part.shard.index > part.shard.count {
+            return invalid(format!("{} is not a shard", part.shard));
+        }
+        if part.shard.count != first.shard.count {
+            return invalid(format!(
+                "shards {} and {} split the tree differently",
+                first.shard, part.shard
+            ));
+        }
+        if std::mem::replace(&mut seen[part.shard.index - 1], true) {
+            return invalid(format!("shard {} was given twice", part.shard));
+        }
+    }
+    let missing: Vec<String> = (1..=first.shard.count)
+        .filter(|index| !seen[index - 1])
+        .map(|index| Shard { index, count: first.shard.count }.to_string())
+        .collect();
+    if !missing.is_empty() {
+        return invalid(format!("missing shards {}", missing.join(", ")));
+    }
+    Ok(&first.query)
+}
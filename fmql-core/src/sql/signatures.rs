@@ -0,0 +1,193 @@
+//! Binary signatures in file contents.
+//!
+//! `CONTAINS_BYTES(x'7f454c46')` finds files that contain a byte sequence
+//! anywhere in their first [`CONTAINS_BYTES_LIMIT`] bytes (or fewer, if the
+//! query gives its own cap), whatever their name or extension says. Files
+//! are searched a block at a time, so large files are never held in memory.
+//!
+//! The `signature_extension` attribute goes the other way: it recognises
+//! common formats by the magic bytes at the start of the file and names the
+//! extension they usually have, so that mislabelled files stand out.
+//!
+//! # Examples
+//!
+//! ```
+//! use fmql_core::sql::signatures::{contains_bytes, parse_hex};
+//!
+//! let elf = parse_hex("7f454c46").unwrap();
+//! assert_eq!(elf, b"\x7fELF");
+//!
+//! let file = tempfile::NamedTempFile::new().unwrap();
+//! std::fs::write(file.path(), b"\x7fELF\x02\x01\x01").unwrap();
+//! assert!(contains_bytes(file.path(), &elf, None).unwrap());
+//! assert!(!contains_bytes(file.path(), b"ELF", Some(3)).unwrap());
+//! ```
+//!
+//! ```
+//! use fmql_core::sql::signatures::identify;
+//!
+//! let png = identify(b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR").unwrap();
+//! assert_eq!(png.extension, "png");
+//! assert!(!png.accepts("jpg"));
+//!
+//! let docx = identify(b"PK\x03\x04\x14\0\x06\0").unwrap();
+//! assert_eq!(docx.extension, "zip");
+//! assert!(docx.accepts("DOCX"));
+//! ```
+
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::Path;
+
+use memchr::memmem::Finder;
+
+use crate::sql::budget::Budgeted;
+
+/// The most bytes `CONTAINS_BYTES` searches in each file by default.
+pub const CONTAINS_BYTES_LIMIT: u64 = 64 * 1024 * 1024;
+
+/// The size of each block read while searching.
+const BLOCK_SIZE: usize = 64 * 1024;
+
+/// The most bytes from the start of a file that any signature looks at.
+const SIGNATURE_BYTES: usize = 512;
+
+/// A file format recognised by its magic bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Signature {
+    /// The byte sequences that identify the format, each at its offset.
+    magic: &'static [(usize, &'static [u8])],
+    /// The extension files of this format usually have.
+    pub extension: &'static str,
+    /// Other extensions that are just as correct, including formats that
+    /// are built on this one (a `.docx` is a zip file). An empty alias means
+    /// files of this format often have no extension at all.
+    pub aliases: &'static [&'static str],
+}
+
+impl Signature {
+    /// Returns true if `extension` (without the dot, in any case) is a
+    /// proper extension for files of this format.
+    pub fn accepts(&self, extension: &str) -> bool {
+        let extension = extension.to_lowercase();
+        self.extension == extension || self.aliases.contains(&extension.as_str())
+    }
+
+    fn matches(&self, head: &[u8]) -> bool {
+        self.magic
+            .iter()
+            .all(|(offset, bytes)| head.get(*offset..offset + bytes.len()) == Some(*bytes))
+    }
+}
+
+/// The formats `signature_extension` recognises. More specific signatures
+/// come before the ones they share a prefix with.
+const SIGNATURES: &[Signature] = &[
+    Signature { magic: &[(0, b"\x89PNG\r\n\x1a\n")], extension: "png", aliases: &[] },
+    Signature { magic: &[(0, b"\xff\xd8\xff")], extension: "jpg", aliases: &["jpeg", "jpe", "jfif"] },
+    Signature { magic: &[(0, b"GIF87a")], extension: "gif", aliases: &[] },
+    Signature { magic: &[(0, b"GIF89a")], extension: "gif", aliases: &[] },
+    Signature { magic: &[(0, b"RIFF"), (8, b"WEBP")], extension: "webp", aliases: &[] },
+    Signature { magic: &[(0, b"RIFF"), (8, b"WAVE")], extension: "wav", aliases: &[] },
+    Signature { magic: &[(0, b"RIFF"), (8, b"AVI ")], extension: "avi", aliases: &[] },
+    Signature { magic: &[(0, b"II*\0")], extension: "tiff", aliases: &["tif", "dng", "nef", "cr2", "arw"] },
+    Signature { magic: &[(0, b"MM\0*")], extension: "tiff", aliases: &["tif", "dng", "nef", "cr2", "arw"] },
+    Signature { magic: &[(0, b"BM"), (6, b"\0\0\0\0")], extension: "bmp", aliases: &["dib"] },
+    Signature { magic: &[(0, b"\0\0\x01\0")], extension: "ico", aliases: &[] },
+    Signature { magic: &[(0, b"8BPS")], extension: "psd", aliases: &["psb"] },
+    Signature { magic: &[(4, b"ftypheic")], extension: "heic", aliases: &["heif"] },
+    Signature { magic: &[(4, b"ftypqt")], extension: "mov", aliases: &["qt"] },
+    Signature { magic: &[(4, b"ftyp")], extension: "mp4", aliases: &["m4a", "m4v", "m4b", "3gp", "mov", "heic", "avif"] },
+    Signature { magic: &[(0, b"ID3")], extension: "mp3", aliases: &[] },
+    Signature { magic: &[(0, b"fLaC")], extension: "flac", aliases: &[] },
+    Signature { magic: &[(0, b"OggS")], extension: "ogg", aliases: &["oga", "ogv", "opus"] },
+    Signature { magic: &[(0, b"\x1a\x45\xdf\xa3")], extension: "mkv", aliases: &["webm", "mka", "mk3d"] },
+    Signature { magic: &[(0, b"%PDF-")], extension: "pdf", aliases: &["ai"] },
+    Signature {
+        magic: &[(0, b"PK\x03\x04")],
+        extension: "zip",
+        aliases: &["docx", "xlsx", "pptx", "odt", "ods", "odp", "epub", "jar", "apk", "ipa", "whl", "xpi", "kmz", "cbz"],
+    },
+    Signature { magic: &[(0, b"PK\x05\x06")], extension: "zip", aliases: &[] },
+    Signature { magic: &[(0, b"Rar!\x1a\x07")], extension: "rar", aliases: &["cbr"] },
+    Signature { magic: &[(0, b"7z\xbc\xaf\x27\x1c")], extension: "7z", aliases: &[] },
+    Signature { magic: &[(0, b"\x1f\x8b")], extension: "gz", aliases: &["tgz", "gzip"] },
+    Signature { magic: &[(0, b"BZh")], extension: "bz2", aliases: &["tbz2", "tbz"] },
+    Signature { magic: &[(0, b"\xfd7zXZ\0")], extension: "xz", aliases: &["txz"] },
+    Signature { magic: &[(0, b"\x28\xb5\x2f\xfd")], extension: "zst", aliases: &["tzst"] },
+    Signature { magic: &[(257, b"ustar")], extension: "tar", aliases: &[] },
+    Signature { magic: &[(0, b"SQLite format 3\0")], extension: "sqlite", aliases: &["db", "sqlite3", "db3"] },
+    Signature { magic: &[(0, b"\0asm")], extension: "wasm", aliases: &[] },
+    Signature { magic: &[(0, b"\xca\xfe\xba\xbe")], extension: "class", aliases: &[] },
+    Signature { magic: &[(0, b"\x7fELF")], extension: "elf", aliases: &["", "so", "o", "ko", "bin", "out", "axf"] },
+    Signature { magic: &[(0, b"MZ")], extension: "exe", aliases: &["dll", "sys", "scr", "efi"] },
+    Signature { magic: &[(0, b"wOFF")], extension: "woff", aliases: &[] },
+    Signature { magic: &[(0, b"wOF2")], extension: "woff2", aliases: &[] },
+];
+
+/// Recognises a file format from the start of a file.
+pub fn identify(head: &[u8]) -> Option<&'static Signature> {
+    SIGNATURES.iter().find(|signature| signature.matches(head))
+}
+
+/// Reads the start of a file and recognises its format. Directories and
+/// other non-files have no format.
+pub fn read_signature(path: &Path) -> io::Result<Option<&'static Signature>> {
+    if !fs::metadata(path)?.is_file() {
+        return Ok(None);
+    }
+    let mut head = Vec::with_capacity(SIGNATURE_BYTES);
+    Budgeted::new(File::open(path)?)
+        .take(SIGNATURE_BYTES as u64)
+        .read_to_end(&mut head)?;
+    Ok(identify(&head))
+}
+
+/// Decodes the digits of a hex literal such as `x'7f454c46'`.
+pub fn parse_hex(digits: &str) -> Result<Vec<u8>, String> {
+    if digits.is_empty() {
+        return Err("A byte pattern needs at least one byte".to_string());
+    }
+    if !digits.len().is_multiple_of(2) {
+        return Err(format!("Hex literal x'{}' has an odd number of digits", digits));
+    }
+    (0..digits.len())
+        .step_by(2)
+        .map(|i| {
+            digits
+                .get(i..i + 2)
+                .and_then(|pair| u8::from_str_radix(pair, 16).ok())
+                .ok_or_else(|| format!("Invalid hex literal x'{}'", digits))
+        })
+        .collect()
+}
+
+/// Returns true if `needle` occurs in the first `max_bytes` bytes of the
+/// file (by default [`CONTAINS_BYTES_LIMIT`]), or fails if a
+/// [budget](crate::sql::budget) runs out first. Directories and other
+/// non-files contain nothing.
+pub fn contains_bytes(path: &Path, needle: &[u8], max_bytes: Option<u64>) -> io::Result<bool> {
+    if !fs::metadata(path)?.is_file() {
+        return Ok(false);
+    }
+    let finder = Finder::new(needle);
+    let file = Budgeted::new(File::open(path)?);
+    let mut reader = file.take(max_bytes.unwrap_or(CONTAINS_BYTES_LIMIT));
+
+    // Keep the tail of each block so matches that straddle two blocks are found
+    let overlap = needle.len().saturating_sub(1);
+    let mut buffer = vec![0; overlap + BLOCK_SIZE];
+    let mut kept = 0;
+    loop {
+        let read = reader.read(&mut buffer[kept..])?;
+        if read == 0 {
+            return Ok(false);
+        }
+        let filled = kept + read;
+        if finder.find(&buffer[..filled]).is_some() {
+            return Ok(true);
+        }
+        kept = overlap.min(filled);
+        buffer.copy_within(filled - kept..filled, 0);
+    }
+}
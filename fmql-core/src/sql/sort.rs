@@ -0,0 +1,191 @@
+//! Orderings used by `ORDER BY`.
+//!
+//! Values of the same type compare the obvious way; strings can also be
+//! compared "naturally", treating each run of digits as a number so that
+//! numbered files sort the way people count. With the `collation` feature,
+//! strings can be compared by the rules of a locale instead, so that `Äpfel`
+//! sorts next to `Apfel` rather than after `Zebra`.
+//!
+//! # Examples
+//!
+//! ```
+//! use fmql_core::sql::sort::natural_cmp;
+//! use std::cmp::Ordering;
+//!
+//! assert_eq!(natural_cmp("file2.txt", "file10.txt"), Ordering::Less);
+//! assert_eq!("file2.txt".cmp("file10.txt"), Ordering::Greater);
+//! ```
+
+use std::cmp::Ordering;
+use std::iter::Peekable;
+use std::str::Chars;
+
+use crate::sql::ast::FileValue;
+
+/// Compares two strings, treating runs of ASCII digits as numbers.
+///
+/// `v1.9` sorts before `v1.10`. Strings that differ only in leading zeros,
+/// like `img007` and `img7`, fall back to a plain comparison so that the
+/// order stays total.
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let mut left = a.chars().peekable();
+    let mut right = b.chars().peekable();
+    loop {
+        match (left.peek().copied(), right.peek().copied()) {
+            (None, None) => return a.cmp(b),
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(l), Some(r)) if l.is_ascii_digit() && r.is_ascii_digit() => {
+                let l = digit_run(&mut left);
+                let r = digit_run(&mut right);
+                let (l, r) = (l.trim_start_matches('0'), r.trim_start_matches('0'));
+                let ordering = l.len().cmp(&r.len()).then_with(|| l.cmp(r));
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+            }
+            (Some(l), Some(r)) => {
+                if l != r {
+                    return l.cmp(&r);
+                }
+                left.next();
+                right.next();
+            }
+        }
+    }
+}
+
+/// Consumes and returns the run of digits at the front of `chars`.
+fn digit_run(chars: &mut Peekable<Chars>) -> String {
+    let mut run = String::new();
+    while let Some(c) = chars.next_if(char::is_ascii_digit) {
+        run.push(c);
+    }
+    run
+}
+
+/// Compares two values for sorting, in ascending order.
+///
+/// NULL sorts after everything else, and values of different types are
+/// grouped by type. With `natural`, strings are compared by [`natural_cmp`].
+pub fn compare_values(a: &FileValue, b: &FileValue, natural: bool) -> Ordering {
+    match (a, b) {
+        (FileValue::String(a), FileValue::String(b)) if natural => natural_cmp(a, b),
+        (FileValue::String(a), FileValue::String(b)) => a.cmp(b),
+        (FileValue::Number(a), FileValue::Number(b)) => a.total_cmp(b),
+        (FileValue::DateTime(a), FileValue::DateTime(b)) => a.cmp(b),
+        (FileValue::Duration(a), FileValue::Duration(b)) => a.cmp(b),
+        (FileValue::Boolean(a), FileValue::Boolean(b)) => a.cmp(b),
+        _ => compare_numbers(a, b).unwrap_or_else(|| rank(a).cmp(&rank(b))),
+    }
+}
+
+/// Compares two numbers, whole or not, exactly: a size beyond 2^53 bytes
+/// still differs from one a byte smaller. Returns None if either value
+/// isn't a number, or is NaN.
+pub fn compare_numbers(a: &FileValue, b: &FileValue) -> Option<Ordering> {
+    match (a, b) {
+        (FileValue::Integer(a), FileValue::Integer(b)) => Some(a.cmp(b)),
+        (FileValue::Number(a), FileValue::Number(b)) => a.partial_cmp(b),
+        (FileValue::Integer(a), FileValue::Number(b)) => compare_integer(*a, *b),
+        (FileValue::Number(a), FileValue::Integer(b)) => {
+            compare_integer(*b, *a).map(Ordering::reverse)
+        }
+        _ => None,
+    }
+}
+
+/// Compares a whole number with a float without rounding the whole number
+/// to the float's precision.
+fn compare_integer(a: i64, b: f64) -> Option<Ordering> {
+    // 2^63, the first float beyond every i64
+    const LIMIT: f64 = 9_223_372_036_854_775_808.0;
+    if b.is_nan() {
+        return None;
+    }
+    if b >= LIMIT {
+        return Some(Ordering::Less);
+    }
+    if b < -LIMIT {
+        return Some(Ordering::Greater);
+    }
+    let whole = b.trunc();
+    // Equal whole parts leave the fraction to decide
+    Some(a.cmp(&(whole as i64)).then_with(|| 0f64.total_cmp(&(b - whole))))
+}
+
+/// The position of each type when values of different types are sorted.
+fn rank(value: &FileValue) -> u8 {
+    match value {
+        FileValue::Boolean(_) => 0,
+        FileValue::Integer(_) | FileValue::Number(_) => 1,
+        FileValue::DateTime(_) => 2,
+        FileValue::Duration(_) => 3,
+        FileValue::String(_) => 4,
+        FileValue::Null => 5,
+    }
+}
+
+/// A locale's rules for ordering text, for `ORDER BY ... COLLATE`.
+///
+/// Locales are written `de_DE`, `de-DE` or just `de`; an encoding suffix
+/// such as `.UTF-8` is ignored, so `$LANG` can be used as is. Without the
+/// `collation` feature every locale is rejected.
+pub struct Collation {
+    #[cfg(feature = "collation")]
+    collator: icu_collator::Collator,
+    #[cfg(not(feature = "collation"))]
+    never: std::convert::Infallible,
+}
+
+impl Collation {
+    /// Loads the rules for `locale`. With `natural`, runs of digits compare
+    /// by value as they do in [`natural_cmp`].
+    #[cfg(feature = "collation")]
+    pub fn new(locale: &str, natural: bool) -> Result<Self, String> {
+        use icu_collator::{Collator, CollatorOptions, Numeric};
+
+        let tag = locale.split('.').next().unwrap_or_default().replace('_', "-");
+        let parsed: icu_locid::Locale = tag
+            .parse()
+            .map_err(|_| format!("Unknown locale '{}'", locale))?;
+        let mut options = CollatorOptions::new();
+        if natural {
+            options.numeric = Some(Numeric::On);
+        }
+        let collator = Collator::try_new(&(&parsed).into(), options)
+            .map_err(|e| format!("No collation rules for locale '{}': {}", locale, e))?;
+        Ok(Collation { collator })
+    }
+
+    /// Loads the rules for `locale`. With `natural`, runs of digits compare
+    /// by value as they do in [`natural_cmp`].
+    #[cfg(not(feature = "collation"))]
+    pub fn new(locale: &str, _natural: bool) -> Result<Self, String> {
+        Err(format!(
+            "COLLATE '{}' needs fmql to be built with the `collation` feature",
+            locale
+        ))
+    }
+
+    /// Compares two strings by this locale's rules.
+    #[cfg(feature = "collation")]
+    pub fn compare(&self, a: &str, b: &str) -> Ordering {
+        self.collator.compare(a, b)
+    }
+
+    /// Compares two strings by this locale's rules.
+    #[cfg(not(feature = "collation"))]
+    pub fn compare(&self, _a: &str, _b: &str) -> Ordering {
+        match self.never {}
+    }
+
+    /// Like [`compare_values`], but strings are compared by this locale's
+    /// rules.
+    pub fn compare_values(&self, a: &FileValue, b: &FileValue) -> Ordering {
+        match (a, b) {
+            (FileValue::String(a), FileValue::String(b)) => self.compare(a, b),
+            _ => compare_values(a, b, false),
+        }
+    }
+}
@@ -0,0 +1,163 @@
+//! Free space on the file systems that copies are made on.
+//!
+//! A batch of copies that runs out of space part way through leaves some
+//! files copied and the rest not, and a nearly full disk besides. This
+//! module adds up what a batch will write on each file system, so it can be
+//! checked against the free space there before the first byte is copied.
+//!
+//! [`copy_needs`] and [`move_needs`] tell how many bytes one copy or move
+//! takes on its target's file system, [`SpaceNeeds`] adds them up by file
+//! system, and
+//! [`SpaceNeeds::shortfalls`] names each file system that hasn't room for
+//! its share, going by `statvfs`.
+//!
+//! # Examples
+//!
+//! ```
+//! use fmql_core::sql::space::SpaceNeeds;
+//!
+//! let dir = tempfile::tempdir().unwrap();
+//! let mut needs = SpaceNeeds::default();
+//! needs.add(&dir.path().join("copies/a.bin"), 1000).unwrap();
+//! assert!(needs.shortfalls().unwrap().is_empty());
+//!
+//! needs.add(&dir.path().join("copies/b.bin"), u64::MAX / 2).unwrap();
+//! let shortfalls = needs.shortfalls().unwrap();
+//! assert_eq!(shortfalls[0].needed, u64::MAX / 2 + 1000);
+//! ```
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+
+use crate::sql::ast::CopyMethod;
+
+/// Returns how many bytes copying `source` to `target` with `method` takes
+/// on the target's file system.
+///
+/// A hardlink takes none, nor does `Auto`, which ends up a link or a clone,
+/// as long as the target is on the source's file system; anywhere else
+/// they copy bytes. A reflink is counted in full, since a file system that
+/// can't clone makes a byte copy instead.
+pub fn copy_needs(source: &Path, target: &Path, method: CopyMethod) -> io::Result<u64> {
+    let metadata = source.symlink_metadata()?;
+    let linked = matches!(method, CopyMethod::Hardlink | CopyMethod::Auto)
+        && device_of(target)? == metadata.dev();
+    Ok(if linked { 0 } else { metadata.len() })
+}
+
+/// Returns how many bytes moving `source` to `target` takes on the target's
+/// file system: none within one file system, where it is a rename, and its
+/// size across file systems, where it is copied and then removed.
+pub fn move_needs(source: &Path, target: &Path) -> io::Result<u64> {
+    let metadata = source.symlink_metadata()?;
+    let renamed = device_of(target)? == metadata.dev();
+    Ok(if renamed { 0 } else { metadata.len() })
+}
+
+/// Returns how many bytes an unprivileged user can still write on the file
+/// system that holds `path`, or would hold it once made.
+pub fn available_bytes(path: &Path) -> io::Result<u64> {
+    let stats = statvfs(path)?;
+    Ok((stats.f_bavail as u64).saturating_mul(stats.f_frsize as u64))
+}
+
+/// Returns the size in bytes of the file system that holds `path`, or
+/// would hold it once made.
+pub fn total_bytes(path: &Path) -> io::Result<u64> {
+    let stats = statvfs(path)?;
+    Ok((stats.f_blocks as u64).saturating_mul(stats.f_frsize as u64))
+}
+
+/// Returns what `statvfs` says of the file system that holds `path`.
+fn statvfs(path: &Path) -> io::Result<libc::statvfs> {
+    let existing = nearest_existing(path)?;
+    let path = std::ffi::CString::new(existing.as_os_str().as_bytes())
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+    // SAFETY: statvfs is a plain C struct, for which all zeroes is valid
+    let mut stats: libc::statvfs = unsafe { std::mem::zeroed() };
+    // SAFETY: the path is NUL-terminated, and both pointers outlive the call
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stats) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(stats)
+}
+
+/// The bytes a batch of copies will write, by file system.
+#[derive(Debug, Clone, Default)]
+pub struct SpaceNeeds {
+    /// By device: the first target on it, and the bytes needed there.
+    devices: BTreeMap<u64, (PathBuf, u64)>,
+}
+
+impl SpaceNeeds {
+    /// Counts `bytes` against the file system that `target` will be on.
+    pub fn add(&mut self, target: &Path, bytes: u64) -> io::Result<()> {
+        let device = device_of(target)?;
+        let (_, needed) = self
+            .devices
+            .entry(device)
+            .or_insert_with(|| (target.to_path_buf(), 0));
+        *needed = needed.saturating_add(bytes);
+        Ok(())
+    }
+
+    /// Returns the file systems that have less space free than the copies
+    /// on them need.
+    pub fn shortfalls(&self) -> io::Result<Vec<Shortfall>> {
+        let mut shortfalls = Vec::new();
+        for (path, needed) in self.devices.values() {
+            let available = available_bytes(path)?;
+            if *needed > available {
+                shortfalls.push(Shortfall {
+                    path: path.clone(),
+                    needed: *needed,
+                    available,
+                });
+            }
+        }
+        Ok(shortfalls)
+    }
+}
+
+/// A file system without room for the copies meant for it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Shortfall {
+    /// One of the targets on the file system.
+    pub path: PathBuf,
+    /// The bytes the copies need there.
+    pub needed: u64,
+    /// The bytes free there.
+    pub available: u64,
+}
+
+impl fmt::Display for Shortfall {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "the copies need {} bytes on the file system holding {}, but only {} are free",
+            self.needed,
+            self.path.display(),
+            self.available
+        )
+    }
+}
+
+/// Returns the device of the file system that holds `path`, or would hold
+/// it once made.
+fn device_of(path: &Path) -> io::Result<u64> {
+    Ok(nearest_existing(path)?.metadata()?.dev())
+}
+
+/// Returns `path`, or its nearest ancestor that exists.
+fn nearest_existing(path: &Path) -> io::Result<&Path> {
+    path.ancestors()
+        .find(|ancestor| ancestor.symlink_metadata().is_ok())
+        .or_else(|| path.is_relative().then_some(Path::new(".")))
+        .ok_or_else(|| {
+            io::Error::new(io::ErrorKind::NotFound, format!("{} doesn't exist", path.display()))
+        })
+}
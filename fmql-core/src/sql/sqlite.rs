@@ -0,0 +1,267 @@
+//! Full SQL over the file scan, in an embedded SQLite.
+//!
+//! fmql's own dialect covers what a file query usually needs, but not
+//! window functions, subqueries or joins with data from elsewhere. With the
+//! `sqlite` feature, [`connect`] opens an in-memory SQLite database in which
+//! the table-valued function `fmql(query)` lists the files an fmql SELECT
+//! finds, one row per file, so that any SQL SQLite understands can run over
+//! them. The `csv` virtual table module is loaded too, for joining the
+//! files with a spreadsheet or an inventory.
+//!
+//! The rows have these columns: `path`, `name`, `size`, `is_directory`,
+//! `is_symlink`, `extension`, `permissions`, `modified`, `accessed` and
+//! `owner`. Booleans are 0 or 1, and times are UTC text such as
+//! `2024-01-31 09:30:00`, which SQLite's date functions read as they are.
+//!
+//! Files are listed as SQLite asks for them, so a `LIMIT` stops the scan
+//! early. The fmql query can only read: grouped queries and UPDATE are
+//! rejected, whatever the options say.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use fmql_core::sql::ast::FileValue;
+//! use fmql_core::sql::sqlite::{connect, select};
+//! use fmql_core::sql::ExecutionOptions;
+//!
+//! let conn = connect(&ExecutionOptions::default()).unwrap();
+//! let rows = select(
+//!     &conn,
+//!     "SELECT extension, name, size, \
+//!             RANK() OVER (PARTITION BY extension ORDER BY size DESC) AS rank \
+//!      FROM fmql('WITH RECURSIVE SELECT * FROM ~/Documents') \
+//!      WHERE extension IS NOT NULL",
+//! )
+//! .unwrap();
+//! for row in rows.iter().filter(|row| row.get("rank") == Some(&FileValue::Integer(1))) {
+//!     println!("{:?}", row.columns);
+//! }
+//! ```
+
+use std::os::raw::c_int;
+
+use chrono::{DateTime, Utc};
+use rusqlite::types::{Value, ValueRef};
+use rusqlite::vtab::{
+    csvtab, eponymous_only_module, Context, IndexConstraintOp, IndexInfo, VTab, VTabConfig,
+    VTabConnection, VTabCursor, Values,
+};
+use rusqlite::{ffi, Connection, Error, Result};
+
+use crate::sql::ast::FileValue;
+use crate::sql::executor::{ExecutionOptions, FileResult, GroupRow, QueryCursor};
+use crate::sql::parser::parse_sql;
+
+/// The columns of `fmql()`, in order; the hidden `query` column holds the
+/// function's argument.
+const SCHEMA: &str = "CREATE TABLE x(path, name, size, is_directory, is_symlink, extension, \
+                      permissions, modified, accessed, owner, query HIDDEN)";
+
+/// The index of the hidden `query` column.
+const QUERY_COLUMN: c_int = 10;
+
+/// How many files the cursor fetches at a time.
+const PAGE_SIZE: usize = 256;
+
+/// Opens an in-memory SQLite database with `fmql()` and the `csv` module
+/// registered; see [`register`].
+pub fn connect(options: &ExecutionOptions) -> Result<Connection> {
+    let conn = Connection::open_in_memory()?;
+    register(&conn, options)?;
+    Ok(conn)
+}
+
+/// Registers the table-valued function `fmql(query)` and the `csv` virtual
+/// table module on a connection. Queries run with `options`, in read-only
+/// mode.
+pub fn register(conn: &Connection, options: &ExecutionOptions) -> Result<()> {
+    let options = ExecutionOptions {
+        read_only: true,
+        ..options.clone()
+    };
+    conn.create_module("fmql", eponymous_only_module::<FmqlTab>(), Some(options))?;
+    csvtab::load_module(conn)
+}
+
+/// Runs a statement and returns its rows, each column under the name
+/// SQLite gives it. Integers and reals become numbers, text becomes
+/// strings, and blobs are written out in hex.
+pub fn select(conn: &Connection, sql: &str) -> Result<Vec<GroupRow>> {
+    let mut statement = conn.prepare(sql)?;
+    let names: Vec<String> = statement
+        .column_names()
+        .into_iter()
+        .map(str::to_string)
+        .collect();
+    let mut rows = statement.query([])?;
+    let mut out = Vec::new();
+    while let Some(row) = rows.next()? {
+        let mut columns = Vec::with_capacity(names.len());
+        for (i, name) in names.iter().enumerate() {
+            columns.push((name.clone(), file_value(row.get_ref(i)?)));
+        }
+        out.push(GroupRow { columns });
+    }
+    Ok(out)
+}
+
+/// Converts a SQLite value.
+fn file_value(value: ValueRef<'_>) -> FileValue {
+    match value {
+        ValueRef::Null => FileValue::Null,
+        ValueRef::Integer(n) => FileValue::Integer(n),
+        ValueRef::Real(n) => FileValue::Number(n),
+        ValueRef::Text(text) => FileValue::String(String::from_utf8_lossy(text).into_owned()),
+        ValueRef::Blob(bytes) => {
+            FileValue::String(bytes.iter().map(|byte| format!("{:02x}", byte)).collect())
+        }
+    }
+}
+
+/// The `fmql()` table.
+#[repr(C)]
+struct FmqlTab {
+    base: ffi::sqlite3_vtab,
+    options: ExecutionOptions,
+}
+
+unsafe impl<'vtab> VTab<'vtab> for FmqlTab {
+    type Aux = ExecutionOptions;
+    type Cursor = FmqlCursor<'vtab>;
+
+    fn connect(
+        db: &mut VTabConnection,
+        aux: Option<&ExecutionOptions>,
+        _args: &[&[u8]],
+    ) -> Result<(String, FmqlTab)> {
+        let table = FmqlTab {
+            base: ffi::sqlite3_vtab::default(),
+            options: aux.cloned().unwrap_or_default(),
+        };
+        db.config(VTabConfig::DirectOnly)?;
+        Ok((SCHEMA.to_string(), table))
+    }
+
+    /// Passes the query to `filter` when SQLite has it. Without one, the
+    /// plan is costed out of reach and `filter` reports the mistake.
+    fn best_index(&self, info: &mut IndexInfo) -> Result<()> {
+        let found = info.constraints().position(|constraint| {
+            constraint.column() == QUERY_COLUMN
+                && constraint.is_usable()
+                && constraint.operator() == IndexConstraintOp::SQLITE_INDEX_CONSTRAINT_EQ
+        });
+        match found {
+            Some(i) => {
+                let mut usage = info.constraint_usage(i);
+                usage.set_argv_index(1);
+                usage.set_omit(true);
+                info.set_idx_num(1);
+                info.set_estimated_cost(1_000_000.0);
+            }
+            None => {
+                info.set_idx_num(0);
+                info.set_estimated_cost(f64::MAX);
+            }
+        }
+        Ok(())
+    }
+
+    fn open(&'vtab mut self) -> Result<FmqlCursor<'vtab>> {
+        Ok(FmqlCursor {
+            base: ffi::sqlite3_vtab_cursor::default(),
+            options: &self.options,
+            cursor: None,
+            page: Vec::new().into_iter(),
+            current: None,
+            row_id: 0,
+        })
+    }
+}
+
+/// A scan of `fmql()`, fetching files from a [`QueryCursor`] a page at a
+/// time.
+#[repr(C)]
+struct FmqlCursor<'vtab> {
+    base: ffi::sqlite3_vtab_cursor,
+    options: &'vtab ExecutionOptions,
+    /// The fmql query's cursor, until its results run out.
+    cursor: Option<QueryCursor>,
+    page: std::vec::IntoIter<FileResult>,
+    current: Option<FileResult>,
+    row_id: i64,
+}
+
+impl FmqlCursor<'_> {
+    /// Moves to the next file, fetching another page when this one is used up.
+    fn advance(&mut self) -> Result<()> {
+        self.current = self.page.next();
+        if let (None, Some(cursor)) = (&self.current, &mut self.cursor) {
+            let page = cursor.next_page(PAGE_SIZE).map_err(module_error)?;
+            if page.len() < PAGE_SIZE {
+                self.cursor = None;
+            }
+            self.page = page.into_iter();
+            self.current = self.page.next();
+        }
+        self.row_id += 1;
+        Ok(())
+    }
+}
+
+unsafe impl VTabCursor for FmqlCursor<'_> {
+    fn filter(&mut self, idx_num: c_int, _idx_str: Option<&str>, args: &Values<'_>) -> Result<()> {
+        if idx_num == 0 {
+            return Err(Error::ModuleError(
+                "fmql() needs a query, as in SELECT * FROM fmql('SELECT * FROM .')".to_string(),
+            ));
+        }
+        self.cursor = match args.get::<Option<String>>(0)? {
+            Some(sql) => {
+                let query = parse_sql(&sql).map_err(module_error)?;
+                Some(QueryCursor::new(&query, self.options).map_err(module_error)?)
+            }
+            None => None,
+        };
+        self.page = Vec::new().into_iter();
+        self.row_id = 0;
+        self.advance()
+    }
+
+    fn next(&mut self) -> Result<()> {
+        self.advance()
+    }
+
+    fn eof(&self) -> bool {
+        self.current.is_none()
+    }
+
+    fn column(&self, ctx: &mut Context, i: c_int) -> Result<()> {
+        let Some(file) = &self.current else {
+            return ctx.set_result(&Value::Null);
+        };
+        let time = |time: &DateTime<Utc>| time.format("%Y-%m-%d %H:%M:%S").to_string();
+        let value = match i {
+            0 => Value::Text(file.path.display().to_string()),
+            1 => Value::Text(file.name.clone()),
+            2 => Value::Integer(file.size as i64),
+            3 => Value::Integer(file.is_directory.into()),
+            4 => Value::Integer(file.is_symlink.into()),
+            5 => file.extension.as_deref().map_or(Value::Null, |ext| Value::Text(ext.to_string())),
+            6 => Value::Integer(file.permissions.into()),
+            7 => Value::Text(time(&file.modified)),
+            8 => Value::Text(time(&file.accessed)),
+            9 => file.owner.as_deref().map_or(Value::Null, |owner| Value::Text(owner.to_string())),
+            _ => Value::Null,
+        };
+        ctx.set_result(&value)
+    }
+
+    fn rowid(&self) -> Result<i64> {
+        Ok(self.row_id)
+    }
+}
+
+/// Reports an fmql error through SQLite.
+fn module_error(err: impl std::fmt::Display) -> Error {
+    Error::ModuleError(format!("fmql: {}", err))
+}
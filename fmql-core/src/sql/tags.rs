@@ -0,0 +1,195 @@
+//! Tags: named collections of files, for `fmql tag` and `TAGGED('name')`.
+//!
+//! A tag gathers files from anywhere into one collection without moving
+//! them: `fmql tag add project-x "<query>"` tags the files a query finds,
+//! and `WHERE TAGGED('project-x')` finds them again. Tags are kept in a
+//! small JSON file in the user's configuration directory
+//! (`~/.config/fmql/tags.json` on Linux). Each tagged file is remembered by
+//! its device and inode, so a tag follows the file when it is renamed or
+//! moved within its file system, and by its path, so it survives an editor
+//! that saves by writing a new file over the old one. A file matches if
+//! either is the same; an inode freed by deleting a tagged file and reused
+//! for a new one carries the tag over to it.
+//!
+//! # Examples
+//!
+//! ```
+//! use std::fs;
+//!
+//! use fmql_core::sql::tags::TagStore;
+//!
+//! let dir = tempfile::tempdir().unwrap();
+//! let report = dir.path().join("report.pdf");
+//! fs::write(&report, "%PDF").unwrap();
+//!
+//! let mut store = TagStore::default();
+//! assert!(store.add("project-x", &report).unwrap());
+//! // Renamed, it is still the same file
+//! let renamed = dir.path().join("final.pdf");
+//! fs::rename(&report, &renamed).unwrap();
+//! assert!(store.is_tagged("project-x", &renamed));
+//! assert!(!store.is_tagged("project-y", &renamed));
+//!
+//! let saved = dir.path().join("tags.json");
+//! store.save(&saved).unwrap();
+//! assert_eq!(TagStore::load(&saved).unwrap().files("project-x").len(), 1);
+//! ```
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io::{self, Write};
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::SystemTime;
+
+use serde::{Deserialize, Serialize};
+
+use crate::sql::executor::{ExecutorError, Result};
+
+/// A file as a tag remembers it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TaggedFile {
+    /// Where the file was when it was tagged.
+    pub path: PathBuf,
+    /// The device the file is on.
+    pub device: u64,
+    /// The file's inode on that device.
+    pub inode: u64,
+}
+
+/// The tags and the files each one holds.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TagStore {
+    tags: BTreeMap<String, Vec<TaggedFile>>,
+}
+
+impl TagStore {
+    /// Returns where the tags are kept: `fmql/tags.json` in the user's
+    /// configuration directory, or None if the system has none.
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("fmql").join("tags.json"))
+    }
+
+    /// Reads the tags saved at `path`; there are none if it doesn't exist.
+    pub fn load(path: &Path) -> Result<TagStore> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(TagStore::default()),
+            Err(err) => return Err(err.into()),
+        };
+        serde_json::from_str(&text)
+            .map_err(|err| ExecutorError::InvalidTags(format!("{}: {}", path.display(), err)))
+    }
+
+    /// Writes the tags to `path`, creating its directory if need be. The
+    /// file is replaced in one step, so a tag command that is stopped
+    /// halfway leaves the previous tags intact.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        fs::create_dir_all(dir)?;
+        let mut file = tempfile::NamedTempFile::new_in(dir)?;
+        serde_json::to_writer_pretty(&mut file, self).map_err(io::Error::from)?;
+        file.flush()?;
+        file.persist(path).map_err(|err| err.error)?;
+        Ok(())
+    }
+
+    /// Tags the file at `path`. Returns false if it already had the tag,
+    /// in which case where it is now replaces where it was.
+    pub fn add(&mut self, tag: &str, path: &Path) -> io::Result<bool> {
+        let metadata = fs::symlink_metadata(path)?;
+        let file = TaggedFile {
+            path: std::path::absolute(path)?,
+            device: metadata.dev(),
+            inode: metadata.ino(),
+        };
+        let files = self.tags.entry(tag.to_string()).or_default();
+        let before = files.len();
+        files.retain(|tagged| {
+            tagged.path != file.path && (tagged.device, tagged.inode) != (file.device, file.inode)
+        });
+        let added = files.len() == before;
+        files.push(file);
+        Ok(added)
+    }
+
+    /// Takes the tag off the file at `path`. Returns false if it didn't
+    /// have it. A tag left with no files is forgotten.
+    pub fn remove(&mut self, tag: &str, path: &Path) -> bool {
+        let Some(files) = self.tags.get_mut(tag) else {
+            return false;
+        };
+        let (path, identity) = locate(path);
+        let before = files.len();
+        files.retain(|tagged| !is_same(tagged, &path, identity));
+        let removed = files.len() != before;
+        if files.is_empty() {
+            self.tags.remove(tag);
+        }
+        removed
+    }
+
+    /// Returns true if the file at `path` has the tag.
+    pub fn is_tagged(&self, tag: &str, path: &Path) -> bool {
+        self.tags.get(tag).is_some_and(|files| {
+            let (path, identity) = locate(path);
+            files.iter().any(|tagged| is_same(tagged, &path, identity))
+        })
+    }
+
+    /// Returns the tags, in order of name, with how many files each holds.
+    pub fn tags(&self) -> impl Iterator<Item = (&str, usize)> {
+        self.tags.iter().map(|(tag, files)| (tag.as_str(), files.len()))
+    }
+
+    /// Returns the files with the tag, as they were when tagged.
+    pub fn files(&self, tag: &str) -> &[TaggedFile] {
+        self.tags.get(tag).map_or(&[], Vec::as_slice)
+    }
+}
+
+/// Returns true if the file at `path` has the tag in the store at `store`.
+///
+/// The store is read once and kept, and read again only when the file
+/// changes, so checking every file of a scan costs a lookup rather than a
+/// parse.
+pub fn is_tagged(store: &Path, tag: &str, path: &Path) -> Result<bool> {
+    type Loaded = HashMap<PathBuf, (Option<SystemTime>, Arc<TagStore>)>;
+    static LOADED: OnceLock<Mutex<Loaded>> = OnceLock::new();
+
+    let modified = fs::metadata(store).and_then(|metadata| metadata.modified()).ok();
+    let loaded = LOADED.get_or_init(|| Mutex::new(HashMap::new()));
+    let tags = {
+        let mut loaded = loaded.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        match loaded.get(store) {
+            Some((when, tags)) if *when == modified => Arc::clone(tags),
+            _ => {
+                let tags = Arc::new(TagStore::load(store)?);
+                loaded.insert(store.to_path_buf(), (modified, Arc::clone(&tags)));
+                tags
+            }
+        }
+    };
+    Ok(tags.is_tagged(tag, path))
+}
+
+/// Returns the absolute path of the file at `path`, and its device and
+/// inode if it exists.
+fn locate(path: &Path) -> (PathBuf, Option<(u64, u64)>) {
+    let identity = fs::symlink_metadata(path)
+        .ok()
+        .map(|metadata| (metadata.dev(), metadata.ino()));
+    let path = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+    (path, identity)
+}
+
+/// Returns true if `tagged` is the file at the absolute `path`, whose
+/// device and inode are `identity`: it is if either they or the path are
+/// the same.
+fn is_same(tagged: &TaggedFile, path: &Path, identity: Option<(u64, u64)>) -> bool {
+    identity == Some((tagged.device, tagged.inode)) || tagged.path == path
+}
@@ -0,0 +1,181 @@
+//! Rate limiting and I/O priority for heavy operations.
+//!
+//! Operations that read or write file contents (content search, copying,
+//! checksums, compressing and archiving) can saturate a disk. A
+//! [`Throttle`] caps their combined throughput, and [`lower_io_priority`]
+//! asks the kernel to serve other processes first, so that fmql can run on
+//! busy servers.
+//!
+//! # Examples
+//!
+//! ```
+//! use fmql_core::sql::throttle::{parse_rate, Throttle};
+//!
+//! let rate = parse_rate("50MB/s").unwrap();
+//! assert_eq!(rate, 50 * 1000 * 1000);
+//!
+//! let throttle = Throttle::new(rate);
+//! throttle.consume(4096);
+//! ```
+
+use std::io::{self, Read};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Limits throughput to a fixed number of bytes per second.
+///
+/// A throttle can be shared between threads; the limit applies to the total.
+#[derive(Debug)]
+pub struct Throttle {
+    bytes_per_second: u64,
+    state: Mutex<(Instant, u64)>,
+}
+
+impl Throttle {
+    /// Creates a throttle allowing `bytes_per_second`.
+    pub fn new(bytes_per_second: u64) -> Self {
+        Throttle {
+            bytes_per_second: bytes_per_second.max(1),
+            state: Mutex::new((Instant::now(), 0)),
+        }
+    }
+
+    /// Records that `bytes` were transferred, sleeping if that puts the
+    /// caller ahead of the allowed rate.
+    pub fn consume(&self, bytes: u64) {
+        let wait = {
+            let mut state = self.state.lock().expect("throttle lock poisoned");
+            let (start, consumed) = &mut *state;
+            *consumed += bytes;
+            let due = Duration::from_secs_f64(*consumed as f64 / self.bytes_per_second as f64);
+            due.saturating_sub(start.elapsed())
+        };
+
+        if !wait.is_zero() {
+            std::thread::sleep(wait);
+        }
+    }
+}
+
+/// A reader whose throughput is limited by a [`Throttle`].
+pub struct ThrottledReader<'a, R> {
+    inner: R,
+    throttle: &'a Throttle,
+}
+
+impl<'a, R: Read> ThrottledReader<'a, R> {
+    /// Wraps `inner` so that reads are limited by `throttle`.
+    pub fn new(inner: R, throttle: &'a Throttle) -> Self {
+        ThrottledReader { inner, throttle }
+    }
+}
+
+impl<R: Read> Read for ThrottledReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.throttle.consume(read as u64);
+        Ok(read)
+    }
+}
+
+/// Wraps `inner` in a [`ThrottledReader`] when there is a throttle.
+pub fn throttled<'a, R: Read + 'a>(
+    inner: R,
+    throttle: Option<&'a Throttle>,
+) -> Box<dyn Read + 'a> {
+    match throttle {
+        Some(throttle) => Box::new(ThrottledReader::new(inner, throttle)),
+        None => Box::new(inner),
+    }
+}
+
+/// Parses a rate such as `50MB/s`, `512KiB/s` or `1048576` into bytes per second.
+///
+/// Decimal (`KB`, `MB`, `GB`) and binary (`KiB`, `MiB`, `GiB`) units are
+/// accepted; the `/s` suffix is optional.
+pub fn parse_rate(rate: &str) -> std::result::Result<u64, String> {
+    let text = rate.trim();
+    let text = text.strip_suffix("/s").unwrap_or(text).trim();
+    let bytes = parse_bytes(text).ok_or_else(|| format!("Invalid rate '{}'", rate))?;
+    if bytes == 0 {
+        return Err(format!("Rate '{}' must be greater than zero", rate));
+    }
+    Ok(bytes)
+}
+
+/// Parses a size such as `100MB`, `1GiB` or `4096` into bytes, with the
+/// same units as [`parse_rate`].
+///
+/// ```
+/// use fmql_core::sql::throttle::parse_size;
+///
+/// assert_eq!(parse_size("1.5KB"), Ok(1500));
+/// assert!(parse_size("big").is_err());
+/// ```
+pub fn parse_size(size: &str) -> std::result::Result<u64, String> {
+    parse_bytes(size.trim()).ok_or_else(|| format!("Invalid size '{}'", size))
+}
+
+/// Parses a number of bytes with an optional unit.
+fn parse_bytes(text: &str) -> Option<u64> {
+    let split = text
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(text.len());
+    let (number, unit) = text.split_at(split);
+
+    let number: f64 = number.parse().ok()?;
+    let multiplier: u64 = match unit.trim().to_uppercase().as_str() {
+        "" | "B" => 1,
+        "K" | "KB" => 1000,
+        "M" | "MB" => 1000 * 1000,
+        "G" | "GB" => 1000 * 1000 * 1000,
+        "KIB" => 1 << 10,
+        "MIB" => 1 << 20,
+        "GIB" => 1 << 30,
+        _ => return None,
+    };
+    Some((number * multiplier as f64) as u64)
+}
+
+/// Lowers the I/O and CPU priority of the current process.
+///
+/// On Linux this selects the idle I/O class (like `ionice -c3`) and the
+/// `SCHED_IDLE` scheduler; elsewhere it sets the lowest `nice` value. The
+/// change applies to the whole process and cannot be undone.
+pub fn lower_io_priority() -> io::Result<()> {
+    #[cfg(target_os = "linux")]
+    {
+        const IOPRIO_WHO_PROCESS: libc::c_long = 1;
+        const IOPRIO_CLASS_IDLE: libc::c_long = 3;
+        const IOPRIO_CLASS_SHIFT: libc::c_long = 13;
+
+        // SAFETY: ioprio_set takes plain integers and only affects this process.
+        let result = unsafe {
+            libc::syscall(
+                libc::SYS_ioprio_set,
+                IOPRIO_WHO_PROCESS,
+                0 as libc::c_long,
+                IOPRIO_CLASS_IDLE << IOPRIO_CLASS_SHIFT,
+            )
+        };
+        if result != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let param = libc::sched_param { sched_priority: 0 };
+        // SAFETY: param is a valid sched_param for the duration of the call.
+        if unsafe { libc::sched_setscheduler(0, libc::SCHED_IDLE, &param) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    {
+        // SAFETY: setpriority takes plain integers and only affects this process.
+        if unsafe { libc::setpriority(libc::PRIO_PROCESS, 0, 19) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+
+    Ok(())
+}
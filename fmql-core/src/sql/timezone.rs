@@ -0,0 +1,103 @@
+//! Time zones for reading the dates a query is written with and writing the
+//! dates it returns.
+//!
+//! File times are instants, kept in UTC. A date literal such as
+//! `'2025-03-30'` is not: it means midnight somewhere. By default that is
+//! midnight UTC, which is an hour or more off for anyone who isn't there.
+//! With a time zone (`--tz Europe/London`, or `SET TIME ZONE` in the
+//! REPL), literals are read as local times in that zone, the date
+//! functions (`YEAR`, `MONTH`, `DAY`) take their parts from the local date,
+//! and text output shows local times. A literal with an explicit offset,
+//! like `'2025-03-30T09:00:00+02:00'`, means what it says in any zone.
+//! JSON, YAML and TOML output keeps Unix seconds, which have no zone.
+//!
+//! # Examples
+//!
+//! ```
+//! use fmql_core::sql::timezone::{format_datetime, parse_datetime, parse_time_zone};
+//!
+//! let london = parse_time_zone("Europe/London").unwrap();
+//! // Midnight in London during summer time is 23:00 UTC the day before
+//! let midnight = parse_datetime("2025-07-01", london).unwrap();
+//! assert_eq!(midnight.to_rfc3339(), "2025-06-30T23:00:00+00:00");
+//! assert_eq!(format_datetime(&midnight, london), "2025-07-01 00:00:00");
+//!
+//! assert!(parse_time_zone("Mars/Olympus_Mons").is_err());
+//! ```
+
+use std::fs;
+
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Offset, TimeZone, Utc};
+
+pub use chrono_tz::Tz;
+
+/// Looks up a time zone by its IANA name, such as `Europe/London` or
+/// `America/New_York`. `UTC` is UTC, and `LOCAL` is the zone the system is
+/// set to.
+pub fn parse_time_zone(name: &str) -> std::result::Result<Tz, String> {
+    let name = name.trim();
+    if name.eq_ignore_ascii_case("local") {
+        return local_time_zone().ok_or_else(|| {
+            "Can't tell which time zone this system is in; name one, such as 'Europe/London'"
+                .to_string()
+        });
+    }
+    if name.eq_ignore_ascii_case("utc") || name.eq_ignore_ascii_case("z") {
+        return Ok(Tz::UTC);
+    }
+    name.parse::<Tz>()
+        .map_err(|_| format!("Unknown time zone '{}' (expected a name such as 'Europe/London')", name))
+}
+
+/// Returns the time zone the system is set to: the one `TZ` names, or else
+/// the zone `/etc/localtime` links to.
+pub fn local_time_zone() -> Option<Tz> {
+    if let Ok(name) = std::env::var("TZ")
+        && !name.is_empty()
+    {
+        return name.trim_start_matches(':').parse().ok();
+    }
+    let target = fs::read_link("/etc/localtime").ok()?;
+    let target = target.to_string_lossy();
+    let (_, name) = target.split_once("zoneinfo/")?;
+    name.parse().ok()
+}
+
+/// Reads a date literal such as `'2025-01-01'` or `'2025-01-01 12:30:00'`
+/// as a local time in `zone`, or returns None if it isn't a date. A date
+/// without a time is midnight. A time that happens twice, when the clocks
+/// go back, is the first of the two; one that never happens, when they go
+/// forward, is read as if the clocks hadn't changed yet.
+pub fn parse_datetime(s: &str, zone: Tz) -> Option<DateTime<Utc>> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(s) {
+        return Some(time.with_timezone(&Utc));
+    }
+    let local = ["%Y-%m-%d %H:%M:%S", "%Y-%m-%dT%H:%M:%S"]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(s, format).ok())
+        .or_else(|| {
+            NaiveDate::parse_from_str(s, "%Y-%m-%d")
+                .ok()
+                .and_then(|date| date.and_hms_opt(0, 0, 0))
+        })?;
+    Some(from_local(&local, zone))
+}
+
+/// Returns the instant a local time in `zone` names, settling times the
+/// clocks skip or repeat as [`parse_datetime`] does.
+pub fn from_local(local: &NaiveDateTime, zone: Tz) -> DateTime<Utc> {
+    match zone.from_local_datetime(local).earliest() {
+        Some(time) => time.with_timezone(&Utc),
+        // In a gap, the offset from before the gap still applies
+        None => {
+            let before = zone.from_utc_datetime(&(*local - chrono::Duration::days(1)));
+            (*local - before.offset().fix()).and_utc()
+        }
+    }
+}
+
+/// Writes an instant as a local time in `zone`, the way text output shows
+/// dates.
+pub fn format_datetime(time: &DateTime<Utc>, zone: Tz) -> String {
+    time.with_timezone(&zone).format("%Y-%m-%d %H:%M:%S").to_string()
+}
@@ -0,0 +1,391 @@
+//! Traces of the entries a query saw, for `fmql sql --record` and
+//! `--replay`, so that a bug a user hit on their files can be run again on
+//! another machine.
+//!
+//! A [`Recorder`] is handed a query's [`Event`]s as it runs, and notes the
+//! name, kind, size, permissions, modification time and symlink target of
+//! each entry in every directory the scan goes into. Nothing is read from a
+//! file's contents. [`Recorder::trace`] returns what it saw as a [`Trace`],
+//! which is written as JSON, and [`Trace::anonymize`] replaces each name in
+//! it with a keyed hash, keeping extensions and leading dots, before it is
+//! shared. An anonymized trace leaves out the query's text, since that
+//! names files too.
+//!
+//! [`replay`] sets a trace's entries out in a private temporary directory
+//! as the [`backups`](crate::sql::backups) module does a snapshot's: each
+//! file a sparse stand-in with the size, mode and modification time that
+//! was recorded. A query run on it with
+//! [`execute_replay`](crate::sql::executor::execute_replay) reads the
+//! recorded path whatever its FROM names, and its results are given the
+//! paths they were recorded under. Owners, extended attributes and
+//! contents aren't recorded, so conditions on them see the stand-ins'.
+//!
+//! # Examples
+//!
+//! ```
+//! use fmql_core::sql::events::Events;
+//! use fmql_core::sql::executor::{execute_query_with_options, execute_replay, ExecutionOptions};
+//! use fmql_core::sql::parse_sql;
+//! use fmql_core::sql::trace::{Recorder, Trace};
+//! use std::sync::Arc;
+//!
+//! let dir = tempfile::tempdir().unwrap();
+//! let dir = dir.path().canonicalize().unwrap();
+//! std::fs::write(dir.join("report.pdf"), vec![0; 4096]).unwrap();
+//! let sql = format!("SELECT * FROM '{}' WHERE size > 1000", dir.display());
+//! let query = parse_sql(&sql).unwrap();
+//!
+//! let recorder = Arc::new(Recorder::new().unwrap());
+//! let seen = Arc::clone(&recorder);
+//! let options = ExecutionOptions {
+//!     events: Events::new(move |event| seen.observe(&event)),
+//!     ..Default::default()
+//! };
+//! execute_query_with_options(&query, &options).unwrap();
+//! let mut trace = recorder.trace(&sql, &dir);
+//!
+//! let mut json = Vec::new();
+//! trace.write(&mut json).unwrap();
+//! let replay = Trace::read(&json[..]).unwrap().replay().unwrap();
+//! let results = execute_replay(&query, &replay, &ExecutionOptions::default()).unwrap();
+//! assert_eq!(results[0].path, dir.join("report.pdf"));
+//!
+//! trace.anonymize();
+//! assert_eq!(trace.query, None);
+//! assert!(trace.entries.iter().all(|entry| !entry.path.ends_with("report.pdf")));
+//! assert!(trace.entries.iter().any(|entry| entry.path.extension().is_some_and(|e| e == "pdf")));
+//! ```
+
+use std::collections::hash_map::RandomState;
+use std::collections::BTreeMap;
+use std::env;
+use std::fs::{self, File};
+use std::hash::{BuildHasher, Hasher};
+use std::io::{self, BufReader, Read, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::sql::backups::{Entry, EntryKind, Listing};
+use crate::sql::events::Event;
+use crate::sql::executor::ExecutorError;
+use crate::sql::paths::is_glob;
+
+/// The version of the trace format this fmql writes and reads.
+pub const VERSION: u32 = 1;
+
+/// The entries a query saw, as `--record` writes them.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Trace {
+    /// The version of the format, [`VERSION`].
+    pub version: u32,
+    /// The query that was run, unless the trace was anonymized.
+    pub query: Option<String>,
+    /// The absolute path the query read.
+    pub root: PathBuf,
+    /// Whether the names were replaced with hashes.
+    #[serde(default)]
+    pub anonymized: bool,
+    /// The entries, parents before their children.
+    pub entries: Vec<TraceEntry>,
+}
+
+/// One entry a query saw.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TraceEntry {
+    /// The entry's absolute path.
+    pub path: PathBuf,
+    pub kind: TraceKind,
+    /// The size of a file, in bytes.
+    #[serde(default)]
+    pub size: u64,
+    /// The permission bits, setuid, setgid and sticky included.
+    pub mode: u32,
+    #[serde(default)]
+    pub modified: Option<DateTime<Utc>>,
+    /// Where a symlink leads.
+    #[serde(default)]
+    pub link: Option<PathBuf>,
+}
+
+/// The kinds of entry a trace has.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TraceKind {
+    File,
+    Dir,
+    Symlink,
+    /// Devices, FIFOs and sockets, which get no stand-in.
+    Other,
+}
+
+impl Trace {
+    /// Reads a trace written by [`write`](Trace::write).
+    ///
+    /// # Errors
+    ///
+    /// Fails with `InvalidData` if it isn't a trace, or one of a version
+    /// this fmql doesn't read.
+    pub fn read(reader: impl Read) -> io::Result<Trace> {
+        let trace: Trace = serde_json::from_reader(reader)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        if trace.version != VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("version {} traces can't be read, only version {}", trace.version, VERSION),
+            ));
+        }
+        Ok(trace)
+    }
+
+    /// Writes the trace as a line of JSON.
+    pub fn write(&self, mut writer: impl Write) -> io::Result<()> {
+        serde_json::to_writer(&mut writer, self)?;
+        writeln!(writer)
+    }
+
+    /// Replaces every name in the trace, the root's and symlinks' targets
+    /// included, with a hash keyed for this trace alone, and leaves out the
+    /// query. A name hashes the same wherever it is, its extension and a
+    /// leading dot are kept, and glob wildcards in the root are left as
+    /// they are.
+    pub fn anonymize(&mut self) {
+        if self.anonymized {
+            return;
+        }
+        let key = random_key();
+        let hide = |path: &Path| anonymize_path(path, &key);
+        self.root = hide(&self.root);
+        for entry in &mut self.entries {
+            entry.path = hide(&entry.path);
+            entry.link = entry.link.as_deref().map(hide);
+        }
+        self.query = None;
+        self.anonymized = true;
+    }
+
+    /// Sets the entries out in a new temporary directory to run a query on.
+    pub fn replay(&self) -> io::Result<Replay> {
+        let mut listing = Listing::new(String::new())?;
+        let base = listing.root().to_path_buf();
+        listing.add_root(self.entries.iter().map(|entry| Ok(entry.stand_in(&base))))?;
+        let root = base.join(self.root.strip_prefix("/").unwrap_or(&self.root));
+        if !is_glob(&root) && fs::symlink_metadata(&root).is_err() {
+            fs::create_dir_all(&root)?;
+        }
+        Ok(Replay { listing, root })
+    }
+}
+
+impl TraceEntry {
+    /// Returns the entry a stand-in is set out for under `base`, where a
+    /// symlink to an absolute path leads to the stand-in of that path
+    /// rather than out of it.
+    fn stand_in(&self, base: &Path) -> Entry {
+        let link = self.link.as_ref().map(|link| match link.strip_prefix("/") {
+            Ok(relative) => base.join(relative),
+            Err(_) => link.clone(),
+        });
+        Entry {
+            path: self.path.clone(),
+            kind: match self.kind {
+                TraceKind::File => EntryKind::File,
+                TraceKind::Dir => EntryKind::Dir,
+                TraceKind::Symlink => EntryKind::Symlink,
+                TraceKind::Other => EntryKind::Other,
+            },
+            size: self.size,
+            mode: self.mode,
+            modified: self.modified.map(SystemTime::from),
+            link,
+        }
+    }
+}
+
+/// Notes the entries a query sees, from its events.
+#[derive(Debug)]
+pub struct Recorder {
+    /// The directory relative paths are in.
+    cwd: PathBuf,
+    entries: Mutex<BTreeMap<PathBuf, TraceEntry>>,
+}
+
+impl Recorder {
+    /// Returns a recorder that has seen nothing yet.
+    pub fn new() -> io::Result<Recorder> {
+        Ok(Recorder {
+            cwd: env::current_dir()?,
+            entries: Mutex::new(BTreeMap::new()),
+        })
+    }
+
+    /// Notes the targets a scan starts with, and what is in each directory
+    /// it goes into, as it is before the query changes anything there.
+    pub fn observe(&self, event: &Event) {
+        match event {
+            Event::ScanStarted { targets } => {
+                for target in targets {
+                    self.note(target);
+                }
+            }
+            Event::DirectoryEntered { path } => {
+                for entry in fs::read_dir(path).into_iter().flatten().flatten() {
+                    self.note(&entry.path());
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Returns what has been seen, for the query `sql` that read `root`.
+    pub fn trace(&self, sql: &str, root: &Path) -> Trace {
+        let entries = self.entries.lock().unwrap_or_else(|err| err.into_inner());
+        Trace {
+            version: VERSION,
+            query: Some(sql.to_string()),
+            root: self.absolute(root),
+            anonymized: false,
+            entries: entries.values().cloned().collect(),
+        }
+    }
+
+    /// Notes the entry at `path`, if it can still be read.
+    fn note(&self, path: &Path) {
+        let Ok(metadata) = fs::symlink_metadata(path) else {
+            return;
+        };
+        let file_type = metadata.file_type();
+        let kind = if file_type.is_symlink() {
+            TraceKind::Symlink
+        } else if file_type.is_dir() {
+            TraceKind::Dir
+        } else if file_type.is_file() {
+            TraceKind::File
+        } else {
+            TraceKind::Other
+        };
+        let link = fs::read_link(path).ok().map(|link| lossy(&link));
+        let path = self.absolute(path);
+        let entry = TraceEntry {
+            path: path.clone(),
+            kind,
+            size: if kind == TraceKind::File { metadata.len() } else { 0 },
+            mode: metadata.permissions().mode() & 0o7777,
+            modified: metadata.modified().ok().map(DateTime::from),
+            link,
+        };
+        let mut entries = self.entries.lock().unwrap_or_else(|err| err.into_inner());
+        entries.entry(path).or_insert(entry);
+    }
+
+    /// Returns `path` as an absolute path without `.` or `..`, with any
+    /// name that isn't UTF-8 made so, as JSON needs.
+    fn absolute(&self, path: &Path) -> PathBuf {
+        let mut absolute = PathBuf::new();
+        for component in self.cwd.join(path).components() {
+            match component {
+                Component::ParentDir => {
+                    absolute.pop();
+                }
+                Component::CurDir => {}
+                component => absolute.push(component),
+            }
+        }
+        lossy(&absolute)
+    }
+}
+
+/// A trace's entries set out in a temporary directory, which is deleted
+/// when this is dropped.
+#[derive(Debug)]
+pub struct Replay {
+    listing: Listing,
+    root: PathBuf,
+}
+
+impl Replay {
+    /// The stand-in of the path the recorded query read.
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// The directory every stand-in is under, which a replayed query
+    /// isn't let out of.
+    pub fn base(&self) -> &Path {
+        self.listing.root()
+    }
+
+    /// Returns the path a stand-in was recorded under.
+    pub fn recorded_path(&self, path: &Path) -> PathBuf {
+        self.listing.path_in_backup(path)
+    }
+
+    /// Returns `text` with the temporary directory's path at its start
+    /// replaced as [`recorded_path`](Replay::recorded_path) does, or None
+    /// if it doesn't start with it.
+    pub fn rewrite(&self, text: &str) -> Option<String> {
+        self.listing.rewrite(text)
+    }
+}
+
+/// Reads the trace at `path` and sets its entries out to run a query on.
+///
+/// # Errors
+///
+/// Fails with [`ExecutorError::InvalidTrace`] if the file isn't a trace
+/// this fmql can read.
+pub fn replay(path: &Path) -> Result<Replay, ExecutorError> {
+    let invalid = |err: io::Error| match err.kind() {
+        io::ErrorKind::InvalidData => {
+            ExecutorError::InvalidTrace(format!("{}: {}", path.display(), err))
+        }
+        _ => ExecutorError::IoError(err),
+    };
+    let trace = Trace::read(BufReader::new(File::open(path)?)).map_err(invalid)?;
+    trace.replay().map_err(invalid)
+}
+
+/// Returns `path` with each name hashed with `key`; see
+/// [`Trace::anonymize`].
+fn anonymize_path(path: &Path, key: &[u8; 32]) -> PathBuf {
+    path.components()
+        .map(|component| match component {
+            Component::Normal(name) if !is_glob(Path::new(name)) => {
+                let name = name.to_string_lossy();
+                let hidden = if name.starts_with('.') { "." } else { "" };
+                let extension = match name.trim_start_matches('.').rsplit_once('.') {
+                    Some((_, extension)) => format!(".{}", extension),
+                    None => String::new(),
+                };
+                let hash = blake3::keyed_hash(key, name.as_bytes()).to_hex();
+                PathBuf::from(format!("{}{}{}", hidden, &hash[..12], extension))
+            }
+            component => PathBuf::from(component.as_os_str()),
+        })
+        .collect()
+}
+
+/// Returns a key no one else has, so that hashed names can't be looked up
+/// in a table of the hashes of common ones.
+fn random_key() -> [u8; 32] {
+    let mut key = [0; 32];
+    for chunk in key.chunks_mut(8) {
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_u128(
+            SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .map_or(0, |elapsed| elapsed.as_nanos()),
+        );
+        chunk.copy_from_slice(&hasher.finish().to_le_bytes());
+    }
+    key
+}
+
+/// Returns `path` with any part that isn't UTF-8 made so.
+fn lossy(path: &Path) -> PathBuf {
+    PathBuf::from(path.to_string_lossy().into_owned())
+}
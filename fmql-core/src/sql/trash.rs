@@ -0,0 +1,223 @@
+//! The trash, for `FROM TRASH`, the `original_path` and `deleted_at`
+//! attributes and `RESTORE`.
+//!
+//! On Linux and the BSDs the trash is the freedesktop.org one file managers
+//! share, `$XDG_DATA_HOME/Trash` (`~/.local/share/Trash` by default): each
+//! trashed file or directory is moved into its `files` directory, and a
+//! `.trashinfo` file of the same name in its `info` directory records where
+//! it was and when it was deleted. `FROM TRASH` reads `files`. The trashes
+//! of other volumes, `$topdir/.Trash-$uid` and `$topdir/.Trash/$uid`, have
+//! the same layout and can be read by their paths; their items record paths
+//! relative to the volume.
+//!
+//! A file inside a trashed directory has the directory's deletion date and
+//! its original path under the directory's. [`restore`] moves a file back
+//! to where it was and, once the trashed item itself is back, removes its
+//! `.trashinfo`.
+//!
+//! macOS keeps its trash in `~/.Trash`, which `FROM TRASH` reads, but it
+//! records where files came from in the Finder's own database rather than
+//! beside them, so they have NULL original paths and can't be restored.
+//!
+//! # Examples
+//!
+//! ```
+//! use std::fs;
+//!
+//! use fmql_core::sql::trash::{read_info, restore};
+//!
+//! let dir = tempfile::tempdir().unwrap();
+//! let trash = dir.path().join("Trash");
+//! fs::create_dir_all(trash.join("files")).unwrap();
+//! fs::create_dir_all(trash.join("info")).unwrap();
+//! let original = dir.path().join("notes/todo list.txt");
+//! fs::write(trash.join("files/todo list.txt"), "milk").unwrap();
+//! fs::write(
+//!     trash.join("info/todo list.txt.trashinfo"),
+//!     format!(
+//!         "[Trash Info]\nPath={}/notes/todo%20list.txt\nDeletionDate=2024-03-01T09:30:00\n",
+//!         dir.path().display()
+//!     ),
+//! )
+//! .unwrap();
+//!
+//! let trashed = trash.join("files/todo list.txt");
+//! let info = read_info(&trashed).unwrap().unwrap();
+//! assert_eq!(info.original_path, original);
+//! assert!(info.deleted_at.is_some());
+//!
+//! restore(&trashed, &info, &original).unwrap();
+//! assert_eq!(fs::read_to_string(&original).unwrap(), "milk");
+//! assert!(!trash.join("info/todo list.txt.trashinfo").exists());
+//! ```
+
+use std::ffi::OsString;
+use std::fs;
+use std::io::{self, Read};
+use std::os::unix::ffi::OsStringExt;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Local, NaiveDateTime, Utc};
+
+/// The most of a `.trashinfo` file that is read.
+const MAX_INFO: u64 = 64 * 1024;
+
+/// Returns the directory the user's trashed files are in, which `FROM
+/// TRASH` reads, or None if there is no home directory to find it in.
+pub fn trash_files() -> Option<PathBuf> {
+    if cfg!(target_os = "macos") {
+        return dirs::home_dir().map(|home| home.join(".Trash"));
+    }
+    dirs::data_dir().map(|data| data.join("Trash").join("files"))
+}
+
+/// What a trash records about a trashed file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TrashInfo {
+    /// Where the file was before it was trashed.
+    pub original_path: PathBuf,
+    /// When it was trashed, if the trash says.
+    pub deleted_at: Option<DateTime<Utc>>,
+    /// The item in the trash's `files` directory that holds the file: the
+    /// file itself, or the trashed directory it is in.
+    pub item: PathBuf,
+    /// The item's `.trashinfo` file.
+    pub info_file: PathBuf,
+}
+
+/// Reads what the trash records about a file in it, or returns None for a
+/// file that isn't in a trash or whose trash doesn't say.
+///
+/// # Errors
+///
+/// Fails if the `.trashinfo` file can't be read.
+pub fn read_info(path: &Path) -> io::Result<Option<TrashInfo>> {
+    for item in path.ancestors() {
+        let Some(files) = item.parent() else {
+            break;
+        };
+        if files.file_name().is_none_or(|name| name != "files") {
+            continue;
+        }
+        let (Some(trash), Some(name)) = (files.parent(), item.file_name()) else {
+            continue;
+        };
+        let mut info_name = name.to_owned();
+        info_name.push(".trashinfo");
+        let info_file = trash.join("info").join(info_name);
+        if !info_file.is_file() {
+            continue;
+        }
+        let mut text = String::new();
+        fs::File::open(&info_file)?.take(MAX_INFO).read_to_string(&mut text)?;
+        let Some((original, deleted_at)) = parse_info(&text) else {
+            return Ok(None);
+        };
+        // The trashes of other volumes record paths relative to the volume
+        let original = if original.is_relative() {
+            topdir(trash).join(original)
+        } else {
+            original
+        };
+        let inside = path.strip_prefix(item).unwrap_or(Path::new(""));
+        let original_path = if inside.as_os_str().is_empty() {
+            original
+        } else {
+            original.join(inside)
+        };
+        return Ok(Some(TrashInfo {
+            original_path,
+            deleted_at,
+            item: item.to_path_buf(),
+            info_file,
+        }));
+    }
+    Ok(None)
+}
+
+/// Moves a trashed file to `target`, and removes its item's `.trashinfo`
+/// if the file is the item. The directory `target` is in is made if it
+/// has to be; whatever is at `target` already is replaced if it is a file.
+///
+/// # Errors
+///
+/// Fails if the file can't be moved, as when `target` is on another file
+/// system or is a directory.
+pub fn restore(path: &Path, info: &TrashInfo, target: &Path) -> io::Result<()> {
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::rename(path, target)?;
+    if path == info.item {
+        match fs::remove_file(&info.info_file) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Reads the original path and deletion date of a `.trashinfo` file.
+fn parse_info(text: &str) -> Option<(PathBuf, Option<DateTime<Utc>>)> {
+    let mut section = false;
+    let mut path = None;
+    let mut deleted_at = None;
+    for line in text.lines() {
+        let line = line.trim();
+        if line.starts_with('[') {
+            section = line == "[Trash Info]";
+            continue;
+        }
+        if !section {
+            continue;
+        }
+        match line.split_once('=') {
+            Some(("Path", value)) => path = Some(percent_decode(value)),
+            Some(("DeletionDate", value)) => deleted_at = local_time(value),
+            _ => {}
+        }
+    }
+    Some((path?, deleted_at))
+}
+
+/// Reads a deletion date, which is in local time, such as
+/// `2024-03-01T09:30:00`.
+fn local_time(text: &str) -> Option<DateTime<Utc>> {
+    let time = NaiveDateTime::parse_from_str(text.trim(), "%Y-%m-%dT%H:%M:%S%.f").ok()?;
+    let time = time.and_local_timezone(Local).earliest()?;
+    Some(time.with_timezone(&Utc))
+}
+
+/// Decodes the `%20`s of a path a `.trashinfo` records.
+fn percent_decode(text: &str) -> PathBuf {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        let escaped = bytes.get(index + 1..index + 3).filter(|_| bytes[index] == b'%');
+        let byte = escaped
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match byte {
+            Some(byte) => {
+                decoded.push(byte);
+                index += 3;
+            }
+            None => {
+                decoded.push(bytes[index]);
+                index += 1;
+            }
+        }
+    }
+    PathBuf::from(OsString::from_vec(decoded))
+}
+
+/// Returns the volume a trash is on, which the paths it records are
+/// relative to: the directory `.Trash-$uid` is in, or the one `.Trash` is.
+fn topdir(trash: &Path) -> PathBuf {
+    let parent = trash.parent().unwrap_or(Path::new("/"));
+    if parent.file_name().is_some_and(|name| name == ".Trash") {
+        return parent.parent().unwrap_or(Path::new("/")).to_path_buf();
+    }
+    parent.to_path_buf()
+}
@@ -0,0 +1,236 @@
+//! User and group name lookups for file owners.
+//!
+//! Files record their owner and group as numeric IDs; queries and reports
+//! want the names. Lookups go through the system user and group databases
+//! (`getpwuid_r`, `getgrgid_r` and friends), so NSS sources such as LDAP
+//! work, and each ID is looked up only once per process because a large
+//! tree typically has only a handful of owners.
+//!
+//! [`current_user`] and [`current_group`] name the user and group fmql runs
+//! as, for `CURRENT_USER()`, `CURRENT_GROUP()` and `is_mine`, and
+//! [`in_group`] answers `OWNER_IN_GROUP('developers')`.
+//!
+//! # Examples
+//!
+//! ```
+//! use fmql_core::sql::users::{current_group, current_user, group_name, in_group, user_name};
+//!
+//! assert_eq!(user_name(0), "root");
+//! assert_eq!(group_name(0), "root");
+//! assert!(!current_user().is_empty());
+//! assert_eq!(in_group(&current_user(), &current_group()), Some(true));
+//! assert_eq!(in_group("root", "no-such-group"), None);
+//! ```
+
+use std::collections::HashMap;
+use std::ffi::{CStr, CString};
+use std::sync::{Mutex, OnceLock};
+
+/// Returns the name of the user with ID `uid`, or the ID itself as text if
+/// the user database has no entry for it (as `ls -l` does).
+pub fn user_name(uid: u32) -> String {
+    static NAMES: OnceLock<Mutex<HashMap<u32, String>>> = OnceLock::new();
+
+    let names = NAMES.get_or_init(|| Mutex::new(HashMap::new()));
+    let mut names = names.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    names
+        .entry(uid)
+        .or_insert_with(|| lookup_user(uid).unwrap_or_else(|| uid.to_string()))
+        .clone()
+}
+
+/// Returns the name of the group with ID `gid`, or the ID itself as text if
+/// the group database has no entry for it.
+pub fn group_name(gid: u32) -> String {
+    static NAMES: OnceLock<Mutex<HashMap<u32, String>>> = OnceLock::new();
+
+    let names = NAMES.get_or_init(|| Mutex::new(HashMap::new()));
+    let mut names = names.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    names
+        .entry(gid)
+        .or_insert_with(|| lookup_group_name(gid).unwrap_or_else(|| gid.to_string()))
+        .clone()
+}
+
+/// Returns the name of the user fmql runs as: its effective user, so under
+/// `sudo` it is root.
+pub fn current_user() -> String {
+    // SAFETY: geteuid has no preconditions and can't fail
+    user_name(unsafe { libc::geteuid() })
+}
+
+/// Returns the name of the group fmql runs as: its effective group, the
+/// one new files get unless their directory says otherwise.
+pub fn current_group() -> String {
+    // SAFETY: getegid has no preconditions and can't fail
+    group_name(unsafe { libc::getegid() })
+}
+
+/// Returns whether the user named `user` is in the group named `group`,
+/// as its primary group or as a listed member, or None if there is no such
+/// group.
+pub fn in_group(user: &str, group: &str) -> Option<bool> {
+    let group = group_entry(group)?;
+    let listed = group.members.iter().any(|member| member == user);
+    Some(listed || primary_group(user) == Some(group.gid))
+}
+
+/// Returns whether the system group database has a group named `group`.
+pub fn group_exists(group: &str) -> bool {
+    group_entry(group).is_some()
+}
+
+/// A group's entry in the group database.
+#[derive(Clone)]
+struct Group {
+    gid: u32,
+    /// The users listed as members; those whose primary group it is are
+    /// usually not.
+    members: Vec<String>,
+}
+
+/// Returns a group's entry, looked up once per process.
+fn group_entry(group: &str) -> Option<Group> {
+    static GROUPS: OnceLock<Mutex<HashMap<String, Option<Group>>>> = OnceLock::new();
+
+    let groups = GROUPS.get_or_init(|| Mutex::new(HashMap::new()));
+    let mut groups = groups.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    groups
+        .entry(group.to_string())
+        .or_insert_with(|| lookup_group(group))
+        .clone()
+}
+
+/// Returns the ID of a user's primary group, looked up once per process.
+fn primary_group(user: &str) -> Option<u32> {
+    static GROUPS: OnceLock<Mutex<HashMap<String, Option<u32>>>> = OnceLock::new();
+
+    let groups = GROUPS.get_or_init(|| Mutex::new(HashMap::new()));
+    let mut groups = groups.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    *groups
+        .entry(user.to_string())
+        .or_insert_with(|| lookup_primary_group(user))
+}
+
+/// Looks up a user name in the system user database.
+fn lookup_user(uid: u32) -> Option<String> {
+    lookup(|buffer| {
+        // SAFETY: an all-zero passwd is a valid value for getpwuid_r to fill in.
+        let mut passwd: libc::passwd = unsafe { std::mem::zeroed() };
+        let mut result = std::ptr::null_mut();
+        // SAFETY: every pointer is valid for the duration of the call and
+        // `buffer.len()` is the true size of the buffer.
+        let status = unsafe {
+            libc::getpwuid_r(uid, &mut passwd, buffer.as_mut_ptr(), buffer.len(), &mut result)
+        };
+        if status != 0 {
+            return Err(status);
+        }
+        if result.is_null() || passwd.pw_name.is_null() {
+            return Ok(None);
+        }
+        // SAFETY: on success pw_name points to a NUL-terminated string in `buffer`.
+        Ok(Some(unsafe { text(passwd.pw_name) }))
+    })
+}
+
+/// Looks up the ID of a user's primary group in the system user database.
+fn lookup_primary_group(user: &str) -> Option<u32> {
+    let name = CString::new(user).ok()?;
+    lookup(|buffer| {
+        // SAFETY: an all-zero passwd is a valid value for getpwnam_r to fill in.
+        let mut passwd: libc::passwd = unsafe { std::mem::zeroed() };
+        let mut result = std::ptr::null_mut();
+        // SAFETY: every pointer is valid for the duration of the call and
+        // `buffer.len()` is the true size of the buffer.
+        let status = unsafe {
+            let (buffer, len) = (buffer.as_mut_ptr(), buffer.len());
+            libc::getpwnam_r(name.as_ptr(), &mut passwd, buffer, len, &mut result)
+        };
+        if status != 0 {
+            return Err(status);
+        }
+        Ok((!result.is_null()).then_some(passwd.pw_gid))
+    })
+}
+
+/// Looks up a group name in the system group database.
+fn lookup_group_name(gid: u32) -> Option<String> {
+    lookup(|buffer| {
+        // SAFETY: an all-zero group is a valid value for getgrgid_r to fill in.
+        let mut group: libc::group = unsafe { std::mem::zeroed() };
+        let mut result = std::ptr::null_mut();
+        // SAFETY: every pointer is valid for the duration of the call and
+        // `buffer.len()` is the true size of the buffer.
+        let status = unsafe {
+            libc::getgrgid_r(gid, &mut group, buffer.as_mut_ptr(), buffer.len(), &mut result)
+        };
+        if status != 0 {
+            return Err(status);
+        }
+        if result.is_null() || group.gr_name.is_null() {
+            return Ok(None);
+        }
+        // SAFETY: on success gr_name points to a NUL-terminated string in `buffer`.
+        Ok(Some(unsafe { text(group.gr_name) }))
+    })
+}
+
+/// Looks up a group's entry in the system group database.
+fn lookup_group(name: &str) -> Option<Group> {
+    let name = CString::new(name).ok()?;
+    lookup(|buffer| {
+        // SAFETY: an all-zero group is a valid value for getgrnam_r to fill in.
+        let mut group: libc::group = unsafe { std::mem::zeroed() };
+        let mut result = std::ptr::null_mut();
+        // SAFETY: every pointer is valid for the duration of the call and
+        // `buffer.len()` is the true size of the buffer.
+        let status = unsafe {
+            let (buffer, len) = (buffer.as_mut_ptr(), buffer.len());
+            libc::getgrnam_r(name.as_ptr(), &mut group, buffer, len, &mut result)
+        };
+        if status != 0 {
+            return Err(status);
+        }
+        if result.is_null() {
+            return Ok(None);
+        }
+        let mut members = Vec::new();
+        let mut member = group.gr_mem;
+        // SAFETY: on success gr_mem is a NULL-terminated array of
+        // NUL-terminated strings in `buffer`.
+        while !member.is_null() && unsafe { !(*member).is_null() } {
+            members.push(unsafe { text(*member) });
+            member = unsafe { member.add(1) };
+        }
+        Ok(Some(Group { gid: group.gr_gid, members }))
+    })
+}
+
+/// Calls a reentrant user or group database lookup with a buffer for the
+/// strings it returns, growing the buffer while the lookup says it is too
+/// small. The lookup returns its error number on failure.
+fn lookup<T>(
+    mut call: impl FnMut(&mut [libc::c_char]) -> Result<Option<T>, libc::c_int>,
+) -> Option<T> {
+    let mut buffer = vec![0 as libc::c_char; 1024];
+    loop {
+        match call(&mut buffer) {
+            Err(libc::ERANGE) if buffer.len() < 1 << 20 => {
+                buffer.resize(buffer.len() * 2, 0);
+            }
+            Err(_) => return None,
+            Ok(found) => return found,
+        }
+    }
+}
+
+/// Copies a NUL-terminated string out of a lookup's buffer.
+///
+/// # Safety
+///
+/// `ptr` must point to a NUL-terminated string.
+unsafe fn text(ptr: *const libc::c_char) -> String {
+    // SAFETY: the caller promises a NUL-terminated string.
+    unsafe { CStr::from_ptr(ptr) }.to_string_lossy().into_owned()
+}
@@ -0,0 +1,96 @@
+//! Built-in views: common reports that can be selected from like a table.
+//!
+//! A view is a name that takes a path, such as `STATS('~/src')`, and stands
+//! for a query that would otherwise have to be written out in full. Only
+//! `SELECT *` can be taken from a view, but the view's query can still be
+//! narrowed with WHERE, which filters the files before they are counted,
+//! and bounded with ORDER BY, LIMIT, WITHIN and SAMPLE.
+//!
+//! - `STATS(path)`, also called `STATS_BY_EXTENSION(path)`: one row per file
+//!   extension below `path`, with its `count`, `total_size`, `avg_size` and
+//!   `newest_modified`
+//!
+//! # Examples
+//!
+//! ```
+//! use std::path::PathBuf;
+//!
+//! use fmql_core::sql::ast::FileQuery;
+//! use fmql_core::sql::views::view;
+//!
+//! let stats = view("stats").unwrap();
+//! let FileQuery::Select { recursive, group_by, aggregates, .. } = stats(PathBuf::from("/src"))
+//! else {
+//!     unreachable!()
+//! };
+//! assert!(recursive);
+//! assert_eq!(group_by[0].name, "extension");
+//! let columns: Vec<&str> = aggregates.iter().map(|a| a.name.as_str()).collect();
+//! assert_eq!(columns, ["count", "total_size", "avg_size", "newest_modified"]);
+//!
+//! assert!(view("tables").is_none());
+//! ```
+
+use std::path::PathBuf;
+
+use crate::sql::ast::{
+    AggregateColumn, AggregateFunction, ComputedColumn, EntryType, FileAttribute, FileExpr,
+    FileQuery, OrderBy, SortKey,
+};
+
+/// The names of the built-in views, as written in queries.
+pub const VIEWS: &[&str] = &["STATS", "STATS_BY_EXTENSION"];
+
+/// Returns the function that builds the query of the view called `name`,
+/// or None if there is no such view. Names are matched ignoring case.
+pub fn view(name: &str) -> Option<fn(PathBuf) -> FileQuery> {
+    if VIEWS.iter().any(|view| view.eq_ignore_ascii_case(name)) {
+        Some(stats_by_extension)
+    } else {
+        None
+    }
+}
+
+/// Returns the query behind `STATS(path)`: the files below `path` grouped
+/// by extension, with how many there are, their total and average size, and
+/// when the newest of them was modified, in order of extension.
+pub fn stats_by_extension(path: PathBuf) -> FileQuery {
+    let aggregate = |name: &str, function, attribute: Option<FileAttribute>| AggregateColumn {
+        name: name.to_string(),
+        function,
+        argument: attribute.map(FileExpr::Attribute),
+    };
+    FileQuery::Select {
+        path,
+        recursive: true,
+        include_self: false,
+        entry_type: Some(EntryType::File),
+        join: None,
+        attributes: vec![FileAttribute::Extension],
+        computed: Vec::new(),
+        aggregates: vec![
+            aggregate("count", AggregateFunction::Count, None),
+            aggregate("total_size", AggregateFunction::Sum, Some(FileAttribute::Size)),
+            aggregate("avg_size", AggregateFunction::Avg, Some(FileAttribute::Size)),
+            aggregate("newest_modified", AggregateFunction::Max, Some(FileAttribute::Modified)),
+        ],
+        condition: None,
+        group_by: vec![ComputedColumn {
+            name: "extension".to_string(),
+            expr: FileExpr::Attribute(FileAttribute::Extension),
+        }],
+        rollup: false,
+        order_by: vec![OrderBy {
+            key: SortKey::Attribute(FileAttribute::Extension),
+            descending: false,
+            natural: false,
+            collation: None,
+            nulls_first: None,
+        }],
+        limit: None,
+        limit_size: None,
+        within: None,
+        sample: None,
+        sample_rows: None,
+    }
+}
@@ -0,0 +1,397 @@
+//! Walking and rewriting parsed queries.
+//!
+//! [`Visitor`] looks at every condition, expression and attribute a query
+//! reads, without the recursion having to be written out again for each
+//! analysis: implement the methods for the nodes of interest and leave the
+//! rest to their defaults, which walk on into the children. An overriding
+//! method calls [`walk_condition`] or [`walk_expr`] itself to go further
+//! down.
+//!
+//! [`Rewriter`] does the same for transformations. It takes the query by
+//! value and rebuilds it from what each method returns, so a method can
+//! replace a node with something else entirely. [`Rewriter::rewrite_where`]
+//! sees each WHERE clause whole, present or not, for adding conditions a
+//! query must always have.
+//!
+//! # Examples
+//!
+//! Finding the LIKE patterns in a query:
+//!
+//! ```
+//! use fmql_core::sql::ast::FileCondition;
+//! use fmql_core::sql::parse_sql;
+//! use fmql_core::sql::visit::{walk_condition, Visitor};
+//!
+//! struct Patterns<'ast>(Vec<&'ast str>);
+//!
+//! impl<'ast> Visitor<'ast> for Patterns<'ast> {
+//!     fn visit_condition(&mut self, condition: &'ast FileCondition) {
+//!         if let FileCondition::Like { pattern, .. } = condition {
+//!             self.0.push(pattern);
+//!         }
+//!         walk_condition(self, condition);
+//!     }
+//! }
+//!
+//! let query = parse_sql("SELECT * FROM . WHERE name LIKE 'a%' OR NOT name LIKE '%z'").unwrap();
+//! let mut patterns = Patterns(Vec::new());
+//! patterns.visit_query(&query);
+//! assert_eq!(patterns.0, ["a%", "%z"]);
+//! ```
+//!
+//! Keeping a query away from hidden files, and dropping its content
+//! searches:
+//!
+//! ```
+//! use fmql_core::sql::ast::{FileAttribute, FileCondition, FileQuery};
+//! use fmql_core::sql::parse_sql;
+//! use fmql_core::sql::visit::{rewrite_condition, Rewriter};
+//!
+//! struct Guard;
+//!
+//! impl Rewriter for Guard {
+//!     fn rewrite_where(&mut self, condition: Option<FileCondition>) -> Option<FileCondition> {
+//!         let visible = FileCondition::Not(Box::new(FileCondition::Like {
+//!             attribute: FileAttribute::Name,
+//!             pattern: ".%".to_string(),
+//!             case_sensitive: true,
+//!         }));
+//!         Some(match condition.map(|condition| self.rewrite_condition(condition)) {
+//!             Some(condition) => FileCondition::And(Box::new(condition), Box::new(visible)),
+//!             None => visible,
+//!         })
+//!     }
+//!
+//!     fn rewrite_condition(&mut self, condition: FileCondition) -> FileCondition {
+//!         match condition {
+//!             FileCondition::Like { attribute: FileAttribute::Content, .. } => {
+//!                 FileCondition::Constant(true)
+//!             }
+//!             condition => rewrite_condition(self, condition),
+//!         }
+//!     }
+//! }
+//!
+//! let query = parse_sql("UPDATE . SET permissions = '600' WHERE content LIKE '%key%'").unwrap();
+//! let FileQuery::Update { condition: Some(FileCondition::And(left, _)), .. } =
+//!     Guard.rewrite_query(query)
+//! else {
+//!     panic!("expected the guarded condition");
+//! };
+//! assert!(matches!(*left, FileCondition::Constant(true)));
+//! ```
+
+use std::mem;
+
+use crate::sql::ast::{FileAttribute, FileCondition, FileExpr, FileQuery, FileValue, SortKey};
+
+/// Looks at the parts of a query. Each method's default walks on into the
+/// node's children; see the [module documentation](self).
+pub trait Visitor<'ast> {
+    /// Visits a whole query. The default visits everything it reads.
+    fn visit_query(&mut self, query: &'ast FileQuery) {
+        walk_query(self, query);
+    }
+
+    /// Visits a condition, and by default the conditions and expressions
+    /// inside it.
+    fn visit_condition(&mut self, condition: &'ast FileCondition) {
+        walk_condition(self, condition);
+    }
+
+    /// Visits an expression, and by default the expressions and conditions
+    /// inside it.
+    fn visit_expr(&mut self, expr: &'ast FileExpr) {
+        walk_expr(self, expr);
+    }
+
+    /// Visits an attribute that the query reads: one it selects, sorts on,
+    /// or that a condition or an expression uses. The attributes an UPDATE
+    /// sets are not visited, since it doesn't read them.
+    fn visit_attribute(&mut self, _attribute: &'ast FileAttribute) {}
+}
+
+/// Visits everything a query reads: its selected attributes and sort keys,
+/// the expressions it computes, groups by, aggregates, joins on or sets,
+/// and its WHERE clause.
+pub fn walk_query<'ast, V: Visitor<'ast> + ?Sized>(visitor: &mut V, query: &'ast FileQuery) {
+    match query {
+        FileQuery::Select {
+            join,
+            attributes,
+            computed,
+            aggregates,
+            condition,
+            group_by,
+            order_by,
+            sample_rows,
+            ..
+        } => {
+            for attribute in attributes {
+                visitor.visit_attribute(attribute);
+            }
+            for column in computed.iter().chain(group_by) {
+                visitor.visit_expr(&column.expr);
+            }
+            for argument in aggregates.iter().filter_map(|aggregate| aggregate.argument.as_ref()) {
+                visitor.visit_expr(argument);
+            }
+            if let Some(join) = join {
+                visitor.visit_expr(&join.key);
+            }
+            if let Some(condition) = condition {
+                visitor.visit_condition(condition);
+            }
+            for order in order_by {
+                if let SortKey::Attribute(attribute) = &order.key {
+                    visitor.visit_attribute(attribute);
+                }
+            }
+            if let Some(weight) = sample_rows.as_ref().and_then(|rows| rows.weight.as_ref()) {
+                visitor.visit_attribute(weight);
+            }
+        }
+        FileQuery::Update {
+            updates, condition, ..
+        } => {
+            for update in updates {
+                visitor.visit_expr(&update.value);
+            }
+            if let Some(condition) = condition {
+                visitor.visit_condition(condition);
+            }
+        }
+        FileQuery::Copy { condition, .. }
+        | FileQuery::Compress { condition, .. }
+        | FileQuery::Extract { condition, .. }
+        | FileQuery::Archive { condition, .. }
+        | FileQuery::Restore { condition, .. } => {
+            if let Some(condition) = condition {
+                visitor.visit_condition(condition);
+            }
+        }
+        FileQuery::ShowFilesystem { .. } => {}
+        FileQuery::Explain { query } => visitor.visit_query(query),
+    }
+}
+
+/// Visits the conditions, expressions and attributes directly inside a
+/// condition.
+pub fn walk_condition<'ast, V: Visitor<'ast> + ?Sized>(
+    visitor: &mut V,
+    condition: &'ast FileCondition,
+) {
+    match condition {
+        FileCondition::And(left, right) | FileCondition::Or(left, right) => {
+            visitor.visit_condition(left);
+            visitor.visit_condition(right);
+        }
+        FileCondition::Not(inner) | FileCondition::Budget { condition: inner, .. } => {
+            visitor.visit_condition(inner)
+        }
+        FileCondition::Compare { attribute, .. }
+        | FileCondition::Like { attribute, .. }
+        | FileCondition::Between { attribute, .. }
+        | FileCondition::Regexp { attribute, .. }
+        | FileCondition::Substring { attribute, .. } => visitor.visit_attribute(attribute),
+        FileCondition::Expression { left, right, .. } => {
+            visitor.visit_expr(left);
+            visitor.visit_expr(right);
+        }
+        FileCondition::Constant(_)
+        | FileCondition::HasPerm { .. }
+        | FileCondition::OwnerInGroup { .. }
+        | FileCondition::Tagged { .. }
+        | FileCondition::PermMatches { .. }
+        | FileCondition::ContainsBytes { .. }
+        | FileCondition::PermMask { .. } => {}
+    }
+}
+
+/// Visits the expressions, conditions and attributes directly inside an
+/// expression.
+pub fn walk_expr<'ast, V: Visitor<'ast> + ?Sized>(visitor: &mut V, expr: &'ast FileExpr) {
+    match expr {
+        FileExpr::Attribute(attribute) => visitor.visit_attribute(attribute),
+        FileExpr::Function { args, .. } => {
+            for arg in args {
+                visitor.visit_expr(arg);
+            }
+        }
+        FileExpr::Case {
+            branches,
+            otherwise,
+        } => {
+            for (condition, value) in branches {
+                visitor.visit_condition(condition);
+                visitor.visit_expr(value);
+            }
+            if let Some(otherwise) = otherwise {
+                visitor.visit_expr(otherwise);
+            }
+        }
+        FileExpr::Binary { left, right, .. } => {
+            visitor.visit_expr(left);
+            visitor.visit_expr(right);
+        }
+        FileExpr::Cast { expr, .. } => visitor.visit_expr(expr),
+        FileExpr::Literal(_) | FileExpr::Joined { .. } => {}
+    }
+}
+
+/// Transforms the parts of a query. Each method's default rebuilds the
+/// node from its rewritten children; see the [module documentation](self).
+pub trait Rewriter {
+    /// Rewrites a whole query. The default rewrites its WHERE clause and
+    /// the expressions it computes, groups by, aggregates, joins on or sets.
+    fn rewrite_query(&mut self, query: FileQuery) -> FileQuery {
+        rewrite_query(self, query)
+    }
+
+    /// Rewrites the WHERE clause of a query that has one, or can have one:
+    /// `None` means there is none, and returning `None` removes it. The
+    /// default rewrites the condition, if any.
+    fn rewrite_where(&mut self, condition: Option<FileCondition>) -> Option<FileCondition> {
+        condition.map(|condition| self.rewrite_condition(condition))
+    }
+
+    /// Rewrites a condition. The default rewrites the conditions and
+    /// expressions inside it.
+    fn rewrite_condition(&mut self, condition: FileCondition) -> FileCondition {
+        rewrite_condition(self, condition)
+    }
+
+    /// Rewrites an expression. The default rewrites the expressions and
+    /// conditions inside it.
+    fn rewrite_expr(&mut self, expr: FileExpr) -> FileExpr {
+        rewrite_expr(self, expr)
+    }
+}
+
+/// Rebuilds a query from its rewritten WHERE clause and expressions.
+/// SHOW FILESYSTEM has neither, and comes back as it was.
+pub fn rewrite_query<R: Rewriter + ?Sized>(rewriter: &mut R, mut query: FileQuery) -> FileQuery {
+    match &mut query {
+        FileQuery::Select {
+            join,
+            computed,
+            aggregates,
+            condition,
+            group_by,
+            ..
+        } => {
+            for column in computed.iter_mut().chain(group_by) {
+                rewrite_in_place(rewriter, &mut column.expr);
+            }
+            for aggregate in aggregates {
+                if let Some(argument) = &mut aggregate.argument {
+                    rewrite_in_place(rewriter, argument);
+                }
+            }
+            if let Some(join) = join {
+                rewrite_in_place(rewriter, &mut join.key);
+            }
+            *condition = rewriter.rewrite_where(condition.take());
+        }
+        FileQuery::Update {
+            updates, condition, ..
+        } => {
+            for update in updates {
+                rewrite_in_place(rewriter, &mut update.value);
+            }
+            *condition = rewriter.rewrite_where(condition.take());
+        }
+        FileQuery::Copy { condition, .. }
+        | FileQuery::Compress { condition, .. }
+        | FileQuery::Extract { condition, .. }
+        | FileQuery::Archive { condition, .. }
+        | FileQuery::Restore { condition, .. } => {
+            *condition = rewriter.rewrite_where(condition.take());
+        }
+        FileQuery::ShowFilesystem { .. } => {}
+        FileQuery::Explain { query } => {
+            let placeholder = FileQuery::ShowFilesystem {
+                path: std::path::PathBuf::new(),
+            };
+            let explained = mem::replace(query.as_mut(), placeholder);
+            **query = rewriter.rewrite_query(explained);
+        }
+    }
+    query
+}
+
+/// Rebuilds a condition from its rewritten conditions and expressions.
+pub fn rewrite_condition<R: Rewriter + ?Sized>(
+    rewriter: &mut R,
+    condition: FileCondition,
+) -> FileCondition {
+    match condition {
+        FileCondition::And(left, right) => FileCondition::And(
+            Box::new(rewriter.rewrite_condition(*left)),
+            Box::new(rewriter.rewrite_condition(*right)),
+        ),
+        FileCondition::Or(left, right) => FileCondition::Or(
+            Box::new(rewriter.rewrite_condition(*left)),
+            Box::new(rewriter.rewrite_condition(*right)),
+        ),
+        FileCondition::Not(inner) => {
+            FileCondition::Not(Box::new(rewriter.rewrite_condition(*inner)))
+        }
+        FileCondition::Budget { condition, limit } => FileCondition::Budget {
+            condition: Box::new(rewriter.rewrite_condition(*condition)),
+            limit,
+        },
+        FileCondition::Expression {
+            left,
+            operator,
+            right,
+        } => FileCondition::Expression {
+            left: rewriter.rewrite_expr(left),
+            operator,
+            right: rewriter.rewrite_expr(right),
+        },
+        condition => condition,
+    }
+}
+
+/// Rebuilds an expression from its rewritten expressions and conditions.
+pub fn rewrite_expr<R: Rewriter + ?Sized>(rewriter: &mut R, expr: FileExpr) -> FileExpr {
+    match expr {
+        FileExpr::Function { name, args } => FileExpr::Function {
+            name,
+            args: args.into_iter().map(|arg| rewriter.rewrite_expr(arg)).collect(),
+        },
+        FileExpr::Case {
+            branches,
+            otherwise,
+        } => FileExpr::Case {
+            branches: branches
+                .into_iter()
+                .map(|(condition, value)| {
+                    (rewriter.rewrite_condition(condition), rewriter.rewrite_expr(value))
+                })
+                .collect(),
+            otherwise: otherwise.map(|otherwise| Box::new(rewriter.rewrite_expr(*otherwise))),
+        },
+        FileExpr::Binary {
+            left,
+            operator,
+            right,
+        } => FileExpr::Binary {
+            left: Box::new(rewriter.rewrite_expr(*left)),
+            operator,
+            right: Box::new(rewriter.rewrite_expr(*right)),
+        },
+        FileExpr::Cast { expr, to } => FileExpr::Cast {
+            expr: Box::new(rewriter.rewrite_expr(*expr)),
+            to,
+        },
+        expr => expr,
+    }
+}
+
+/// Rewrites an expression that is borrowed from the query being rebuilt.
+fn rewrite_in_place<R: Rewriter + ?Sized>(rewriter: &mut R, expr: &mut FileExpr) {
+    let taken = mem::replace(expr, FileExpr::Literal(FileValue::Null));
+    *expr = rewriter.rewrite_expr(taken);
+}
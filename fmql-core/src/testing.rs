@@ -0,0 +1,421 @@
+//! Fixtures, assertions and property-test generators for testing code
+//! built on fmql-core (`testing` feature).
+//!
+//! A [`Fixture`] is a small directory tree declared in memory with
+//! [`FixtureBuilder`] and written into a temporary directory, which is
+//! removed when the fixture is dropped. Queries run against it with the
+//! `{root}` placeholder standing for its path, and
+//! [`assert_matches`] checks which files a query matched by their paths
+//! below the root, so a test reads the same on every machine.
+//!
+//! The `arb_*` functions are [proptest] strategies for conditions and
+//! SELECT queries over the attributes every file has. Running a generated
+//! query through [`Fixture::filter`], which evaluates its condition
+//! directly, and through the executor checks that a backend, a rewrite
+//! or an optimization keeps the query's meaning.
+//!
+//! # Examples
+//!
+//! ```
+//! use fmql_core::testing::{assert_matches, Fixture};
+//!
+//! let fixture = Fixture::builder()
+//!     .file("notes.txt", "hello")
+//!     .file("logs/app.log", vec![b'x'; 2048])
+//!     .dir("empty")
+//!     .build()
+//!     .unwrap();
+//!
+//! let sql = "WITH RECURSIVE SELECT FILES FROM {root} WHERE size > 1000";
+//! assert_matches(&fixture, sql, &["logs/app.log"]);
+//! assert_matches(&fixture, "SELECT DIRS FROM {root}", &["empty", "logs"]);
+//! ```
+//!
+//! [proptest]: https://docs.rs/proptest
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use chrono::{DateTime, Utc};
+use proptest::prelude::*;
+use tempfile::TempDir;
+
+use crate::error::Result;
+use crate::sql::ast::{
+    ArithmeticOperator, ComparisonOperator, FileAttribute, FileCondition, FileExpr, FileQuery,
+    FileValue,
+};
+use crate::sql::executor::{evaluate_condition, FileResult};
+use crate::sql::{parse_sql, run, ExecutionOptions};
+
+/// One entry of a [`FixtureBuilder`]'s tree.
+#[derive(Debug, Clone)]
+enum Entry {
+    File(PathBuf, Vec<u8>),
+    Dir(PathBuf),
+    Symlink(PathBuf, PathBuf),
+}
+
+/// Declares the tree of a [`Fixture`]; see the [module documentation](self).
+///
+/// Paths are relative to the fixture's root, with `/` between their
+/// components, and the directories above a file are created for it.
+/// Entries are written in the order they were declared, and then their
+/// permissions and modification times are set.
+#[derive(Debug, Clone, Default)]
+pub struct FixtureBuilder {
+    entries: Vec<Entry>,
+    modes: Vec<(PathBuf, u32)>,
+    modified: Vec<(PathBuf, DateTime<Utc>)>,
+}
+
+impl FixtureBuilder {
+    /// Adds a file holding `contents`.
+    pub fn file(mut self, path: impl AsRef<Path>, contents: impl Into<Vec<u8>>) -> Self {
+        self.entries
+            .push(Entry::File(path.as_ref().to_path_buf(), contents.into()));
+        self
+    }
+
+    /// Adds an empty directory.
+    pub fn dir(mut self, path: impl AsRef<Path>) -> Self {
+        self.entries.push(Entry::Dir(path.as_ref().to_path_buf()));
+        self
+    }
+
+    /// Adds a symlink to `target`, which is written as given, so a
+    /// relative target is relative to the link's directory. Symlinks need
+    /// a Unix system; elsewhere [`build`](Self::build) fails.
+    pub fn symlink(mut self, path: impl AsRef<Path>, target: impl AsRef<Path>) -> Self {
+        self.entries.push(Entry::Symlink(
+            path.as_ref().to_path_buf(),
+            target.as_ref().to_path_buf(),
+        ));
+        self
+    }
+
+    /// Sets the permissions of an entry declared before, such as `0o600`.
+    /// Outside Unix only the write bits count: without any, the entry is
+    /// read-only.
+    pub fn permissions(mut self, path: impl AsRef<Path>, mode: u32) -> Self {
+        self.modes.push((path.as_ref().to_path_buf(), mode));
+        self
+    }
+
+    /// Sets the modification time of a file declared before.
+    pub fn modified(mut self, path: impl AsRef<Path>, time: DateTime<Utc>) -> Self {
+        self.modified.push((path.as_ref().to_path_buf(), time));
+        self
+    }
+
+    /// Writes the tree into a new temporary directory.
+    pub fn build(self) -> io::Result<Fixture> {
+        let dir = tempfile::tempdir()?;
+        let root = dir.path();
+        for entry in &self.entries {
+            match entry {
+                Entry::File(path, contents) => {
+                    create_parent(root, path)?;
+                    fs::write(root.join(path), contents)?;
+                }
+                Entry::Dir(path) => fs::create_dir_all(root.join(path))?,
+                Entry::Symlink(path, target) => {
+                    create_parent(root, path)?;
+                    symlink(target, &root.join(path))?;
+                }
+            }
+        }
+        for (path, time) in &self.modified {
+            let file = fs::File::options().write(true).open(root.join(path))?;
+            file.set_modified(SystemTime::from(*time))?;
+        }
+        for (path, mode) in &self.modes {
+            set_mode(&root.join(path), *mode)?;
+        }
+        Ok(Fixture { dir })
+    }
+}
+
+/// Creates the directories above a fixture entry.
+fn create_parent(root: &Path, path: &Path) -> io::Result<()> {
+    match root.join(path).parent() {
+        Some(parent) => fs::create_dir_all(parent),
+        None => Ok(()),
+    }
+}
+
+#[cfg(unix)]
+fn symlink(target: &Path, link: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+#[cfg(not(unix))]
+fn symlink(_target: &Path, link: &Path) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        format!("can't create the symlink {}: fixtures only have symlinks on Unix", link.display()),
+    ))
+}
+
+#[cfg(unix)]
+fn set_mode(path: &Path, mode: u32) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(path, fs::Permissions::from_mode(mode))
+}
+
+#[cfg(not(unix))]
+fn set_mode(path: &Path, mode: u32) -> io::Result<()> {
+    let mut permissions = fs::metadata(path)?.permissions();
+    permissions.set_readonly(mode & 0o222 == 0);
+    fs::set_permissions(path, permissions)
+}
+
+/// A directory tree to run queries against, removed when dropped; see the
+/// [module documentation](self).
+#[derive(Debug)]
+pub struct Fixture {
+    dir: TempDir,
+}
+
+impl Fixture {
+    /// Starts declaring a fixture.
+    pub fn builder() -> FixtureBuilder {
+        FixtureBuilder::default()
+    }
+
+    /// Returns the fixture's root directory.
+    pub fn path(&self) -> &Path {
+        self.dir.path()
+    }
+
+    /// Puts the fixture's root, quoted, in place of each `{root}` in a
+    /// query.
+    pub fn sql(&self, sql: &str) -> String {
+        let root = self.path().display().to_string().replace('\'', "''");
+        sql.replace("{root}", &format!("'{}'", root))
+    }
+
+    /// Runs a query with `{root}` standing for the fixture's root.
+    pub fn run(&self, sql: &str) -> Result<Vec<FileResult>> {
+        run(&self.sql(sql), &ExecutionOptions::default())
+    }
+
+    /// Runs a query with `{root}` standing for the fixture's root, and
+    /// returns the paths it matched below the root, sorted.
+    pub fn matches(&self, sql: &str) -> Result<Vec<String>> {
+        Ok(self.relative_paths(self.run(sql)?))
+    }
+
+    /// Returns the paths below the root of the entries that `condition`
+    /// holds for, sorted, by evaluating it on each entry directly rather
+    /// than running a query, so nothing rewrites it first. Only the
+    /// attributes `SELECT *` reads are set on the entries.
+    pub fn filter(&self, condition: &FileCondition) -> Result<Vec<String>> {
+        let mut matched = Vec::new();
+        for file in self.run("WITH RECURSIVE SELECT * FROM {root}")? {
+            if evaluate_condition(&file, condition)? {
+                matched.push(file);
+            }
+        }
+        Ok(self.relative_paths(matched))
+    }
+
+    fn relative_paths(&self, files: Vec<FileResult>) -> Vec<String> {
+        let mut paths: Vec<String> = files
+            .into_iter()
+            .map(|file| match file.path.strip_prefix(self.path()) {
+                Ok(relative) => relative
+                    .components()
+                    .map(|component| component.as_os_str().to_string_lossy())
+                    .collect::<Vec<_>>()
+                    .join("/"),
+                Err(_) => file.path.display().to_string(),
+            })
+            .collect();
+        paths.sort();
+        paths
+    }
+}
+
+/// Asserts that a query, with `{root}` standing for the fixture's root,
+/// matches exactly the entries at `expected` below the root, in any order.
+///
+/// # Panics
+///
+/// Panics, naming the query and what it matched, if the query fails or
+/// matches anything else.
+#[track_caller]
+pub fn assert_matches(fixture: &Fixture, sql: &str, expected: &[&str]) {
+    let mut expected: Vec<String> = expected.iter().map(|path| path.to_string()).collect();
+    expected.sort();
+    match fixture.matches(sql) {
+        Ok(matched) => assert_eq!(
+            matched, expected,
+            "{} matched {:?} rather than {:?}",
+            sql, matched, expected
+        ),
+        Err(err) => panic!("{} failed: {}", sql, err),
+    }
+}
+
+/// Asserts that two queries, with `{root}` standing for the fixture's
+/// root, match the same entries.
+///
+/// # Panics
+///
+/// Panics, naming both queries and what they matched, if either fails or
+/// they match different entries.
+#[track_caller]
+pub fn assert_same_matches(fixture: &Fixture, sql: &str, other: &str) {
+    let matches = |sql| match fixture.matches(sql) {
+        Ok(matched) => matched,
+        Err(err) => panic!("{} failed: {}", sql, err),
+    };
+    let (left, right) = (matches(sql), matches(other));
+    assert_eq!(
+        left, right,
+        "{} matched {:?} but {} matched {:?}",
+        sql, left, other, right
+    );
+}
+
+/// Asserts that a query parses, and returns it.
+///
+/// # Panics
+///
+/// Panics with the parse error if it doesn't.
+#[track_caller]
+pub fn assert_parses(sql: &str) -> FileQuery {
+    match parse_sql(sql) {
+        Ok(query) => query,
+        Err(err) => panic!("{} doesn't parse: {}", sql, err),
+    }
+}
+
+/// Generates sizes, favouring the edges.
+fn arb_size() -> impl Strategy<Value = f64> {
+    prop_oneof![Just(0.0), Just(1.0), Just(1024.0), (0u32..4096).prop_map(f64::from)]
+}
+
+fn arb_operator() -> impl Strategy<Value = ComparisonOperator> {
+    prop_oneof![
+        Just(ComparisonOperator::Eq),
+        Just(ComparisonOperator::NotEq),
+        Just(ComparisonOperator::Lt),
+        Just(ComparisonOperator::LtEq),
+        Just(ComparisonOperator::Gt),
+        Just(ComparisonOperator::GtEq),
+    ]
+}
+
+/// Generates text for names and patterns from a few characters, so that
+/// generated names and patterns often match each other.
+fn arb_text() -> impl Strategy<Value = String> {
+    "[abc.]{0,4}"
+}
+
+/// Generates arithmetic on sizes, such as `size / 2` or `1024 * 4`, with
+/// numbers written as text as the parser writes them.
+fn arb_size_expr() -> impl Strategy<Value = FileExpr> {
+    let leaf = prop_oneof![
+        Just(FileExpr::Attribute(FileAttribute::Size)),
+        (1u32..2048).prop_map(|n| FileExpr::Literal(FileValue::String(n.to_string()))),
+    ];
+    leaf.prop_recursive(2, 4, 2, |inner| {
+        let operator = prop_oneof![
+            Just(ArithmeticOperator::Add),
+            Just(ArithmeticOperator::Sub),
+            Just(ArithmeticOperator::Mul),
+            Just(ArithmeticOperator::Div),
+        ];
+        (inner.clone(), operator, inner).prop_map(|(left, operator, right)| FileExpr::Binary {
+            left: Box::new(left),
+            operator,
+            right: Box::new(right),
+        })
+    })
+}
+
+/// Generates a condition that holds or doesn't for every entry, without
+/// raising an error: comparisons, LIKE and BETWEEN on the name, extension,
+/// size and `is_directory`, arithmetic on sizes, and constants, joined by
+/// AND, OR and NOT.
+pub fn arb_condition() -> impl Strategy<Value = FileCondition> {
+    let leaf = prop_oneof![
+        (arb_operator(), arb_size()).prop_map(|(operator, size)| FileCondition::Compare {
+            attribute: FileAttribute::Size,
+            operator,
+            value: FileValue::Number(size),
+        }),
+        (
+            prop_oneof![Just(FileAttribute::Name), Just(FileAttribute::Extension)],
+            arb_operator(),
+            arb_text(),
+        )
+            .prop_map(|(attribute, operator, text)| FileCondition::Compare {
+                attribute,
+                operator,
+                value: FileValue::String(text),
+            }),
+        (arb_text(), "[%_]?").prop_map(|(text, wildcard)| FileCondition::Like {
+            attribute: FileAttribute::Name,
+            pattern: format!("{}{}", wildcard, text),
+            case_sensitive: false,
+        }),
+        (arb_size(), arb_size()).prop_map(|(lower, upper)| FileCondition::Between {
+            attribute: FileAttribute::Size,
+            lower: FileValue::Number(lower),
+            upper: FileValue::Number(upper),
+        }),
+        any::<bool>().prop_map(|is_directory| FileCondition::Compare {
+            attribute: FileAttribute::IsDirectory,
+            operator: ComparisonOperator::Eq,
+            value: FileValue::Boolean(is_directory),
+        }),
+        (arb_size_expr(), arb_operator(), arb_size_expr()).prop_map(|(left, operator, right)| {
+            FileCondition::Expression {
+                left,
+                operator,
+                right,
+            }
+        }),
+        any::<bool>().prop_map(FileCondition::Constant),
+    ];
+    leaf.prop_recursive(4, 24, 2, |inner| {
+        prop_oneof![
+            (inner.clone(), inner.clone())
+                .prop_map(|(left, right)| FileCondition::And(Box::new(left), Box::new(right))),
+            (inner.clone(), inner.clone())
+                .prop_map(|(left, right)| FileCondition::Or(Box::new(left), Box::new(right))),
+            inner.prop_map(|condition| FileCondition::Not(Box::new(condition))),
+        ]
+    })
+}
+
+/// Generates `SELECT * FROM path` queries, recursive or not, with a WHERE
+/// clause from [`arb_condition`] or none.
+pub fn arb_select(path: impl AsRef<Path>) -> impl Strategy<Value = FileQuery> {
+    let path = path.as_ref().to_path_buf();
+    let conditions = proptest::option::of(arb_condition());
+    (any::<bool>(), conditions).prop_map(move |(recursive, condition)| FileQuery::Select {
+        path: path.clone(),
+        recursive,
+        include_self: false,
+        entry_type: None,
+        join: None,
+        attributes: Vec::new(),
+        computed: Vec::new(),
+        aggregates: Vec::new(),
+        condition,
+        group_by: Vec::new(),
+        rollup: false,
+        order_by: Vec::new(),
+        limit: None,
+        limit_size: None,
+        within: None,
+        sample: None,
+        sample_rows: None,
+    })
+}
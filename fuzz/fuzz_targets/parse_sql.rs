@@ -0,0 +1,11 @@
+//! Feeds arbitrary text to the parser: it may reject it, but must never
+//! panic. Run with `cargo +nightly fuzz run parse_sql` from the repository
+//! root.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|sql: &str| {
+    let _ = fmql_core::sql::parse_sql(sql);
+});
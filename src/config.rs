@@ -0,0 +1,293 @@
+//! fmql's config file, `config.toml` in its configuration directory
+//! (`~/.config/fmql` on Linux, `~/Library/Application Support/fmql` on
+//! macOS). Every setting is optional, and so is the file:
+//!
+//! ```toml
+//! # Run queries as fmql 0.3 did, until the scripts here are updated
+//! semantics = "0.3"
+//!
+//! # Write numbers and dates as in Germany, whatever LANG says
+//! locale = "de_DE"
+//!
+//! # Read every file as UTF-8, rather than finding each file's encoding
+//! encoding = "utf-8"
+//!
+//! # System paths whose permissions and ownership UPDATE may change without
+//! # --privileged
+//! allowed_system_paths = ["/var/www", "/usr/local/share/app"]
+//!
+//! # What a change does when another fmql run is changing an overlapping
+//! # tree, unless --lock says: wait (the default), fail, per-file or none
+//! lock = "fail"
+//!
+//! # Other names for attributes
+//! [aliases]
+//! mtime = "modified"
+//!
+//! # Conditions a query can use as @name
+//! [snippets]
+//! big = "size > 100 * 1024 * 1024"
+//! stale = "mtime < '2024-01-01'"
+//!
+//! # Settings for `--profile work` (or FMQL_PROFILE=work), over the ones above
+//! [profiles.work]
+//! # Refuse to read or change anything outside this directory, as --root does
+//! root = "~/work"
+//! # Leave these out of every scan, and don't descend into them
+//! ignore = ["node_modules", ".git", "target", "*.tmp"]
+//! # Reject queries that would modify files, as --read-only does
+//! read_only = true
+//! # Output format and time zone, as --format and --tz give them
+//! format = "json"
+//! tz = "Europe/London"
+//!
+//! [profiles.work.snippets]
+//! mine = "owner = 'me'"
+//! ```
+//!
+//! A profile can set any of the settings above as well as its own, and its
+//! aliases and snippets are added to the file's. Command-line options win
+//! over the profile, and the profile over the rest of the file.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use serde::Deserialize;
+
+use fmql_core::sql::aliases::Aliases;
+use fmql_core::sql::compat::Semantics;
+use fmql_core::sql::encoding::TextEncoding;
+use fmql_core::sql::ignore::Ignore;
+use fmql_core::sql::locale::Locale;
+use fmql_core::sql::locks::LockMode;
+
+/// The settings of the config file.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// The semantics version queries run with, such as `"0.3"`, unless
+    /// `--compat` names one.
+    semantics: Option<String>,
+    /// The locale output people read is written in, such as `"de_DE"`,
+    /// unless `--locale` names one.
+    locale: Option<String>,
+    /// The encoding file contents are read in, such as `"utf-16le"`,
+    /// unless `--encoding` names one.
+    encoding: Option<String>,
+    /// System paths an UPDATE may change the permissions and ownership of
+    /// without `--privileged`, and everything below them.
+    allowed_system_paths: Vec<PathBuf>,
+    /// What a statement that changes files does when another run holds an
+    /// overlapping tree, such as `"fail"`, unless `--lock` says.
+    lock: Option<String>,
+    /// Other names for attributes, such as `mtime` for `modified`.
+    aliases: BTreeMap<String, String>,
+    /// Conditions by name, which queries use as `@name`.
+    snippets: BTreeMap<String, String>,
+    /// Named sets of settings, one of which `--profile` picks.
+    profiles: BTreeMap<String, Profile>,
+    /// The settings of the profile picked, applied over the ones above.
+    #[serde(skip)]
+    profile: Profile,
+}
+
+/// A named set of settings in the config file, such as `[profiles.work]`.
+/// Settings it leaves out are the file's.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Profile {
+    semantics: Option<String>,
+    locale: Option<String>,
+    encoding: Option<String>,
+    allowed_system_paths: Option<Vec<PathBuf>>,
+    lock: Option<String>,
+    aliases: BTreeMap<String, String>,
+    snippets: BTreeMap<String, String>,
+    /// The directory commands with `--root` are confined to unless it is
+    /// given.
+    root: Option<PathBuf>,
+    /// Patterns of entries scans leave out.
+    ignore: Vec<String>,
+    /// Reject queries that would modify files, for commands with
+    /// `--read-only`.
+    read_only: bool,
+    /// The output format of commands with `--format`, unless it is given.
+    format: Option<String>,
+    /// The time zone of commands with `--tz`, unless it is given.
+    tz: Option<String>,
+}
+
+impl Config {
+    /// Returns where the config file is, if there is a configuration
+    /// directory.
+    pub fn path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("fmql").join("config.toml"))
+    }
+
+    /// Reads the config file, or returns the defaults if there isn't one.
+    pub fn load() -> io::Result<Config> {
+        let Some(path) = Config::path() else {
+            return Ok(Config::default());
+        };
+        let text = match fs::read_to_string(&path) {
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Config::default()),
+            result => result?,
+        };
+        toml::from_str(&text).map_err(|err| {
+            let message = format!("{}: {}", path.display(), err.message());
+            io::Error::new(io::ErrorKind::InvalidData, message)
+        })
+    }
+
+    /// Reads the config file with the settings of the profile `name`
+    /// applied over the rest of it.
+    ///
+    /// # Errors
+    ///
+    /// Fails as [`load`](Config::load) does, or if the file has no profile
+    /// by that name.
+    pub fn load_profile(name: &str) -> io::Result<Config> {
+        let mut config = Config::load()?;
+        let Some(profile) = config.profiles.remove(name) else {
+            let path = Config::path().unwrap_or_default();
+            let names: Vec<&str> = config.profiles.keys().map(String::as_str).collect();
+            let known = if names.is_empty() {
+                "it has none".to_string()
+            } else {
+                format!("it has {}", names.join(", "))
+            };
+            let message = format!("{}: there is no profile named '{}'; {}", path.display(), name, known);
+            return Err(io::Error::new(io::ErrorKind::NotFound, message));
+        };
+        config.profile = profile;
+        Ok(config)
+    }
+
+    /// Returns the semantics version the file sets, if it sets one.
+    pub fn semantics(&self) -> io::Result<Option<Semantics>> {
+        self.profile
+            .semantics
+            .as_ref()
+            .or(self.semantics.as_ref())
+            .map(String::as_str)
+            .map(|version| {
+                version.parse().map_err(|err| {
+                    let path = Config::path().unwrap_or_default();
+                    let message = format!("{}: {}", path.display(), err);
+                    io::Error::new(io::ErrorKind::InvalidData, message)
+                })
+            })
+            .transpose()
+    }
+
+    /// Returns the locale the file sets, if it sets one.
+    pub fn locale(&self) -> io::Result<Option<Locale>> {
+        self.profile
+            .locale
+            .as_ref()
+            .or(self.locale.as_ref())
+            .map(String::as_str)
+            .map(|name| {
+                name.parse().map_err(|err| {
+                    let path = Config::path().unwrap_or_default();
+                    let message = format!("{}: {}", path.display(), err);
+                    io::Error::new(io::ErrorKind::InvalidData, message)
+                })
+            })
+            .transpose()
+    }
+
+    /// Returns the encoding the file sets, if it sets one.
+    pub fn encoding(&self) -> io::Result<Option<TextEncoding>> {
+        self.profile
+            .encoding
+            .as_ref()
+            .or(self.encoding.as_ref())
+            .map(String::as_str)
+            .map(|name| {
+                name.parse().map_err(|err| {
+                    let path = Config::path().unwrap_or_default();
+                    let message = format!("{}: {}", path.display(), err);
+                    io::Error::new(io::ErrorKind::InvalidData, message)
+                })
+            })
+            .transpose()
+    }
+
+    /// Returns the system paths the file allows changes to.
+    pub fn allowed_system_paths(&self) -> &[PathBuf] {
+        self.profile
+            .allowed_system_paths
+            .as_deref()
+            .unwrap_or(&self.allowed_system_paths)
+    }
+
+    /// Returns the lock mode the file sets, if it sets one.
+    pub fn lock(&self) -> io::Result<Option<LockMode>> {
+        self.profile
+            .lock
+            .as_ref()
+            .or(self.lock.as_ref())
+            .map(String::as_str)
+            .map(|mode| {
+                mode.parse().map_err(|err| {
+                    let path = Config::path().unwrap_or_default();
+                    let message = format!("{}: {}", path.display(), err);
+                    io::Error::new(io::ErrorKind::InvalidData, message)
+                })
+            })
+            .transpose()
+    }
+
+    /// Returns the attribute aliases and snippets the file defines.
+    pub fn aliases(&self) -> io::Result<Aliases> {
+        let mut aliases = Aliases {
+            attributes: self.aliases.clone(),
+            snippets: self.snippets.clone(),
+        };
+        aliases.attributes.extend(self.profile.aliases.clone());
+        aliases.snippets.extend(self.profile.snippets.clone());
+        aliases.check().map_err(|err| {
+            let path = Config::path().unwrap_or_default();
+            let message = format!("{}: {}", path.display(), err);
+            io::Error::new(io::ErrorKind::InvalidData, message)
+        })?;
+        Ok(aliases)
+    }
+
+    /// Returns the directory the profile confines commands to, with a
+    /// leading `~` expanded.
+    pub fn root(&self) -> Option<PathBuf> {
+        let root = self.profile.root.as_ref()?;
+        match (root.strip_prefix("~"), dirs::home_dir()) {
+            (Ok(rest), Some(home)) => Some(home.join(rest)),
+            _ => Some(root.clone()),
+        }
+    }
+
+    /// Returns the entries the profile leaves out of scans.
+    pub fn ignore(&self) -> io::Result<Ignore> {
+        Ignore::new(&self.profile.ignore).map_err(|err| {
+            let path = Config::path().unwrap_or_default();
+            let message = format!("{}: ignore pattern: {}", path.display(), err);
+            io::Error::new(io::ErrorKind::InvalidData, message)
+        })
+    }
+
+    /// Returns whether the profile rejects queries that modify files.
+    pub fn read_only(&self) -> bool {
+        self.profile.read_only
+    }
+
+    /// Returns the output format the profile sets, if it sets one.
+    pub fn format(&self) -> Option<&str> {
+        self.profile.format.as_deref()
+    }
+
+    /// Returns the time zone the profile sets, if it sets one.
+    pub fn tz(&self) -> Option<&str> {
+        self.profile.tz.as_deref()
+    }
+}
@@ -2,15 +2,81 @@
 //!
 //! This crate provides a command-line tool for managing files using a SQL-like query language.
 
-// mod display;
-mod error;
-// mod file;
-mod sql;
+mod config;
+mod notify;
+mod open;
+mod pick;
+mod repl;
+mod report;
+mod settings;
+mod wizard;
 
-use clap::{Parser, Subcommand};
+use clap::builder::PossibleValuesParser;
+use clap::{CommandFactory, FromArgMatches, Parser, Subcommand};
+use serde::Serialize;
+use std::ffi::OsString;
+use std::fs;
+use std::io::{self, IsTerminal, Write};
+use std::path::{Path, PathBuf};
 use std::process;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{mpsc, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant};
 
-use crate::sql::{parse_sql, execute_query};
+use chrono::{DateTime, Utc};
+use fmql_core::sql::alert::{summarise, Threshold};
+use fmql_core::sql::bench::{self, BenchOptions};
+use fmql_core::sql::capabilities;
+use fmql_core::sql::catalog::ValueType;
+use fmql_core::sql::aliases::Aliases;
+use fmql_core::sql::compat::Semantics;
+use fmql_core::sql::diff::{diff_rows, read_rows, to_rows, RowDiff};
+use fmql_core::sql::display::DisplayOptions;
+use fmql_core::sql::events::{Event, Events};
+use fmql_core::sql::faults::Faults;
+use fmql_core::sql::find::translate_find;
+use fmql_core::sql::ast::{
+    AggregateColumn, AggregateFunction, ComparisonOperator, ComputedColumn, EntryType,
+    FileAttribute, FileCondition, FileExpr, FileQuery, FileValue, OrderBy, SortKey,
+};
+use fmql_core::sql::executor::{
+    execute_grouped_replay, execute_replay, execute_shard, merge_files, merge_groups,
+    ExecutorError, FileResult, GroupRow, OperationStatus, PlainValue, ResourceLimits, RollupNode,
+    ScanSource,
+};
+use fmql_core::sql::functions::to_text;
+use fmql_core::sql::locale::Locale;
+use fmql_core::sql::lexer::parse_duration;
+use fmql_core::sql::parser::ParserError;
+use fmql_core::sql::retry::RetryPolicy;
+use fmql_core::sql::lint::{lint, LintWarning};
+use fmql_core::sql::protect::Protection;
+use fmql_core::sql::ignore::Ignore;
+use fmql_core::sql::interleave::{OutputOrder, OutputSerializer};
+use fmql_core::sql::locks::{LockMode, Locking};
+use fmql_core::sql::schema::{schema, SchemaColumn};
+use fmql_core::sql::paths::{display_path, read_path_list, read_paths, write_paths, PathListFormat};
+use fmql_core::sql::throttle::{parse_rate, parse_size};
+use fmql_core::sql::recent::{recent_query, Feed};
+use fmql_core::sql::sample::parse_sample;
+use fmql_core::sql::space::{available_bytes, total_bytes};
+use fmql_core::sql::script::{self, split_statements};
+use fmql_core::sql::shard::{Shard, ShardResult};
+use fmql_core::sql::tags::TagStore;
+use fmql_core::sql::trace::{self, Recorder};
+use fmql_core::sql::timezone::{parse_time_zone, Tz};
+use fmql_core::sql::encoding::TextEncoding;
+use fmql_core::error::{Diagnostic, FMQLError};
+use config::Config;
+use settings::Settings;
+use notify::{Sink, Summary};
+use open::Action;
+use report::{file_value, is_table_format, Table};
+use fmql_core::sql::{
+    execute_grouped_with_options, execute_query, execute_query_with_options,
+    execute_rollup_with_options, parse_sql, parse_to_ast_json, CompiledQuery, ExecutionOptions,
+};
 
 /// Command-line arguments for the SQL mode
 #[derive(Parser, Debug)]
@@ -19,68 +85,3200 @@ struct SqlCommand {
     /// SQL query to execute
     #[arg(help = "SQL query to execute (e.g., \"SELECT * FROM ~/Documents WHERE extension = '.txt'\"")]
     query: String,
-    
+
+    /// Output format (text, json, ndjson, yaml, toml, csv, markdown or html)
+    #[arg(short, long, default_value = "text")]
+    format: String,
+
+    /// Number of files to update, or directories to group by DIRNAME(path),
+    /// concurrently
+    #[arg(short, long, default_value_t = 1)]
+    jobs: usize,
+
+    /// Limit file content reads (content search, COPY, VERIFY CHECKSUM,
+    /// COMPRESS and ARCHIVE) to a rate such as 50MB/s
+    #[arg(long, visible_alias = "bwlimit", value_parser = parse_rate)]
+    throttle: Option<u64>,
+
+    /// Run at idle I/O and CPU priority so other workloads come first
+    #[arg(long)]
+    nice_io: bool,
+
+    /// Reject queries that would modify files
+    #[arg(long)]
+    read_only: bool,
+
+    /// Print every result to a terminal, rather than stopping after 100,000
+    #[arg(long)]
+    no_cap: bool,
+
+    /// Sort results by path when the query has no ORDER BY, so that runs
+    /// agree; on by default when the output is a terminal
+    #[arg(long)]
+    stable_order: bool,
+
+    /// After the results, print how many rows there were, how long the query
+    /// took and how full the file system it read is, to stderr
+    #[arg(long)]
+    stats: bool,
+
+    /// After the results, send a summary of them to webhook:URL or
+    /// email:ADDRESS; may be given more than once
+    #[arg(long, value_name = "SINK", conflicts_with = "partial")]
+    notify: Vec<Sink>,
+
+    /// How many rows a --notify summary includes
+    #[arg(long, value_name = "N", default_value_t = 10)]
+    notify_top: usize,
+
+    /// Instead of the results, print how they differ from those saved in
+    /// this JSON file by an earlier run, and exit 1 if they do
+    #[arg(
+        long,
+        value_name = "FILE",
+        conflicts_with_all = ["partial", "each_root", "emit", "open", "reveal", "copy_paths"]
+    )]
+    baseline: Option<PathBuf>,
+
+    /// An attribute whose changes --baseline leaves out, such as accessed;
+    /// may be given more than once
+    #[arg(long, value_name = "ATTRIBUTE", requires = "baseline")]
+    ignore: Vec<String>,
+
+    /// Fail the query at the first file its condition can't be evaluated
+    /// for, rather than leaving the file out with a warning
+    #[arg(long)]
+    strict_eval: bool,
+
+    /// Try a stat, read or COPY again this many times when it fails with an
+    /// error that may pass, such as a timeout on an NFS or SMB mount
+    #[arg(long, visible_alias = "retries", default_value_t = 2)]
+    io_retries: u32,
+
+    /// Wait this long before the first retry of a stat or read, and twice as
+    /// long before each one after (e.g. 100ms, 1s)
+    #[arg(long, value_parser = parse_duration, default_value = "100ms")]
+    io_backoff: Duration,
+
+    /// Refuse to touch anything outside this directory
+    #[arg(long)]
+    root: Option<PathBuf>,
+
+    /// Give up after visiting this many files
+    #[arg(long)]
+    max_files: Option<usize>,
+
+    /// Give up if more than this many files match
+    #[arg(long)]
+    max_results: Option<usize>,
+
+    /// Give up after reading this many bytes of file content
+    #[arg(long)]
+    max_bytes_read: Option<u64>,
+
+    /// Descend at most this many directory levels
+    #[arg(long)]
+    max_depth: Option<usize>,
+
+    /// Give up if the query runs longer than this (e.g. 30s, 5m), like WITHIN
+    #[arg(long, value_parser = parse_duration)]
+    timeout: Option<Duration>,
+
+    /// List the FROM directory itself, like INCLUDE SELF
+    #[arg(long)]
+    include_self: bool,
+
+    /// Only list files (f), directories (d) or symlinks (l), like TYPE
+    #[arg(long = "type", value_name = "f|d|l")]
+    entry_type: Option<EntryType>,
+
+    /// Check the content of .gz, .zst and .xz files through their
+    /// decompressors, reading at most this much of each (default 256MB)
+    #[arg(long, value_name = "LIMIT", num_args = 0..=1, require_equals = true,
+          default_missing_value = "256MB",
+          value_parser = parse_size)]
+    decompress: Option<u64>,
+
+    /// Read date literals and show dates as local times in this time zone,
+    /// such as Europe/London or LOCAL (default UTC)
+    #[arg(long, value_name = "ZONE", value_parser = parse_time_zone)]
+    tz: Option<Tz>,
+
+    /// Estimate aggregates from this percentage of the entries, like SAMPLE
+    #[arg(long, value_name = "PERCENT", value_parser = parse_sample)]
+    sample: Option<f64>,
+
+    /// Save the scan's progress to this file as it goes, and resume from it
+    /// if the same query was stopped before finishing
+    #[arg(long, value_name = "FILE")]
+    checkpoint: Option<PathBuf>,
+
+    /// Only scan this shard of the tree, such as 3/8, for splitting a query
+    /// between processes
+    #[arg(long, value_name = "I/N", value_parser = Shard::parse)]
+    shard: Option<Shard>,
+
+    /// Write what this shard found as JSON, for fmql merge to combine with
+    /// the other shards, instead of the results
+    #[arg(long)]
+    partial: bool,
+
+    /// Read the candidates for FROM STDIN from this file (- for stdin), one
+    /// path per line or NUL-separated
+    #[arg(long, value_name = "FILE")]
+    files_from: Option<PathBuf>,
+
+    /// Read the candidates for FROM STDIN as exactly this format: paths0 for
+    /// the output of --emit paths0
+    #[arg(long, value_name = "paths|paths0")]
+    read: Option<PathListFormat>,
+
+    /// Run the query once for each directory listed in this file, one per
+    /// line, with the directory in place of $ROOT in its FROM; each row
+    /// gets source_root and scan_id columns saying which run found it
+    #[arg(long, value_name = "FILE", conflicts_with_all = [
+        "partial", "checkpoint", "files_from", "open", "reveal", "copy_paths",
+    ])]
+    each_root: Option<PathBuf>,
+
+    /// With --each-root, how many roots to scan at once
+    #[arg(long, value_name = "N", default_value_t = 1, requires = "each_root")]
+    root_jobs: usize,
+
+    /// With --each-root, print each root's rows as soon as they are ready
+    /// rather than all at the end: ordered keeps the roots in the order of
+    /// the list, unordered prints each as it finishes. Needs --format
+    /// ndjson or --emit
+    #[arg(long, value_name = "ordered|unordered", requires = "each_root")]
+    output_order: Option<OutputOrder>,
+
+    /// Write only the paths of the results, for another fmql (--read) or
+    /// xargs -0 (paths0)
+    #[arg(long, value_name = "paths|paths0")]
+    emit: Option<PathListFormat>,
+
+    /// Open each result with the system's default application, after
+    /// printing them. At most 20 results; add a LIMIT for more
+    #[arg(long, conflicts_with_all = ["reveal", "partial"])]
+    open: bool,
+
+    /// Show each result in the system file manager, after printing them
+    #[arg(long, conflicts_with = "partial")]
+    reveal: bool,
+
+    /// Put the paths of the results on the system clipboard, one per line
+    /// (needs the clipboard feature)
+    #[arg(long, conflicts_with = "partial")]
+    copy_paths: bool,
+
+    /// Put JSON output on a single line instead of pretty-printing it
+    #[arg(long)]
+    compact: bool,
+
+    /// Add the type of each column: a schema block in JSON, YAML and TOML,
+    /// whose rows then hold just those columns, and a row under the header
+    /// in CSV
+    #[arg(long)]
+    schema: bool,
+
+    /// Add a row of column types under the CSV header, like --schema
+    #[arg(long)]
+    csv_types: bool,
+
+    /// Wrap JSON, YAML and TOML output in a record of the run: the fmql
+    /// version, the query, when it started and finished, the host, the
+    /// working directory and whether rows were left out
+    #[arg(long, conflicts_with = "partial")]
+    envelope: bool,
+
+    /// Write the output to this file instead of stdout. The file is only
+    /// replaced once the whole output has been written
+    #[arg(long, value_name = "FILE")]
+    out: Option<PathBuf>,
+
+    /// Write the names, sizes, permissions and times of the entries the
+    /// query saw, but none of their contents, to this trace file, for
+    /// reproducing a bug with --replay
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["each_root", "partial", "files_from", "read"])]
+    record: Option<PathBuf>,
+
+    /// With --record, replace every name in the trace with a hash, keeping
+    /// extensions, and leave the query out of it
+    #[arg(long, requires = "record")]
+    anonymize: bool,
+
+    /// Run the query on the entries recorded in this trace file instead of
+    /// the path its FROM names; changes are made to stand-ins of the files
+    #[arg(
+        long,
+        value_name = "FILE",
+        conflicts_with_all = ["record", "each_root", "partial", "files_from", "read", "checkpoint"]
+    )]
+    replay: Option<PathBuf>,
+}
+
+/// Command-line arguments for the check mode
+#[derive(Parser, Debug)]
+struct CheckCommand {
+    /// SQL query to validate
+    query: String,
+
+    /// Output format (text, or json for the parsed AST or error with its span)
+    #[arg(short, long, default_value = "text")]
+    format: String,
+}
+
+/// Command-line arguments for running a script
+#[derive(Parser, Debug)]
+struct ApplyCommand {
+    /// Script of statements separated by semicolons (- for stdin)
+    script: PathBuf,
+
+    /// Dry-run every statement first, checking permissions, name collisions
+    /// and free space for copies, and change nothing unless all would succeed
+    #[arg(long)]
+    validate_first: bool,
+
+    /// Output format (text, json, ndjson, yaml, toml, csv, markdown or html), one
+    /// document per statement
+    #[arg(short, long, default_value = "text")]
+    format: String,
+
+    /// Number of files to update concurrently
+    #[arg(short, long, default_value_t = 1)]
+    jobs: usize,
+
+    /// Limit file content reads (content search, COPY, VERIFY CHECKSUM,
+    /// COMPRESS and ARCHIVE) to a rate such as 50MB/s
+    #[arg(long, visible_alias = "bwlimit", value_parser = parse_rate)]
+    throttle: Option<u64>,
+
+    /// Try a stat, read or COPY again this many times when it fails with an
+    /// error that may pass, such as a timeout on an NFS or SMB mount
+    #[arg(long, visible_alias = "retries", default_value_t = 2)]
+    io_retries: u32,
+
+    /// Refuse to touch anything outside this directory
+    #[arg(long)]
+    root: Option<PathBuf>,
+
+    /// Read date literals in this time zone, such as Europe/London or LOCAL
+    /// (default UTC)
+    #[arg(long, value_name = "ZONE", value_parser = parse_time_zone)]
+    tz: Option<Tz>,
+}
+
+/// Command-line arguments for the interactive mode
+#[derive(Parser, Debug)]
+struct ReplCommand {
+    /// Output format (text, json, ndjson, yaml, toml, csv, markdown or html)
+    #[arg(short, long, default_value = "text")]
+    format: String,
+
+    /// The time zone to start in, like SET TIME ZONE (default UTC)
+    #[arg(long, value_name = "ZONE", value_parser = parse_time_zone)]
+    tz: Option<Tz>,
+}
+
+/// Command-line arguments for the usage report
+#[derive(Parser, Debug)]
+struct UsageCommand {
+    /// Directory to report on (searched recursively)
+    path: PathBuf,
+
+    /// One line per file owner, biggest first
+    #[arg(long)]
+    by_owner: bool,
+
+    /// Count the space files take on disk, as du does, rather than their
+    /// size as ls -l shows it; sparse files count only what's allocated
+    #[arg(long)]
+    disk_usage: bool,
+
+    /// Output format (text, json, ndjson, yaml, toml, csv, markdown or html)
+    #[arg(short, long, default_value = "text")]
+    format: String,
+}
+
+/// Command-line arguments for the cold data report
+#[derive(Parser, Debug)]
+struct ColdCommand {
+    /// Directory to report on (searched recursively)
+    path: PathBuf,
+
+    /// Only files not read for this long (e.g. 90d, 1y). Access times are
+    /// approximate on relatime mounts and frozen on noatime mounts
+    #[arg(long, value_parser = parse_duration, default_value = "1y")]
+    older_than: Duration,
+
+    /// Only files at least this big (e.g. 100MB)
+    #[arg(long, value_parser = parse_size, default_value = "1MB")]
+    min_size: u64,
+
+    /// Output format (text, json, ndjson, yaml, toml, csv, markdown or html)
+    #[arg(short, long, default_value = "text")]
+    format: String,
+}
+
+/// Command-line arguments for the recently modified files
+#[derive(Parser, Debug)]
+struct RecentCommand {
+    /// Directory to look in (searched recursively)
+    path: PathBuf,
+
+    /// Only files modified within this long (e.g. 2h, 7d)
+    #[arg(long, value_parser = parse_duration, default_value = "24h")]
+    since: Duration,
+
+    /// Show at most this many files, the most recently modified
+    #[arg(long, default_value_t = 50)]
+    limit: usize,
+
+    /// Keep watching, printing files as they appear or change, oldest
+    /// first, until interrupted
+    #[arg(short = 'F', long)]
+    follow: bool,
+
+    /// How often --follow rescans the directory (e.g. 2s, 1m)
+    #[arg(long, value_parser = parse_duration, default_value = "2s")]
+    interval: Duration,
+
+    /// Output format (text, json, ndjson, yaml, toml, csv, markdown or html). With
+    /// --follow, text, or json or ndjson, which print a line of JSON per file
+    #[arg(short, long, default_value = "text")]
+    format: String,
+
+    /// Show dates as local times in this time zone (default UTC)
+    #[arg(long, value_name = "ZONE", value_parser = parse_time_zone)]
+    tz: Option<Tz>,
+
+    /// Refuse to look outside this directory
+    #[arg(long)]
+    root: Option<PathBuf>,
+}
+
+/// Command-line arguments for the alert mode
+#[derive(Parser, Debug)]
+struct AlertCommand {
+    /// Query to check; a SELECT listing files, or a grouped SELECT
+    #[arg(long)]
+    query: String,
+
+    /// Threshold that fires the alert, such as 'count > 0' or 'bytes > 10GB'.
+    /// A grouped query can name any of its columns
+    #[arg(long)]
+    when: Threshold,
+
+    /// Shell command to run when the alert fires. It gets the results as
+    /// JSON on stdin and FMQL_ALERT_* variables in its environment
+    #[arg(long, value_name = "COMMAND")]
+    exec: Option<String>,
+
+    /// Refuse to look outside this directory
+    #[arg(long)]
+    root: Option<PathBuf>,
+
+    /// Give up if the query runs longer than this (e.g. 30s, 5m)
+    #[arg(long, value_parser = parse_duration)]
+    timeout: Option<Duration>,
+}
+
+/// Command-line arguments for the retention commands
+#[derive(Parser, Debug)]
+struct RetentionCommand {
+    #[command(subcommand)]
+    action: RetentionAction,
+}
+
+#[derive(Subcommand, Debug)]
+enum RetentionAction {
+    /// Delete, archive or compress the files a policy's rules match
+    Apply(RetentionApplyCommand),
+}
+
+/// Command-line arguments for applying a retention policy
+#[derive(Parser, Debug)]
+struct RetentionApplyCommand {
+    /// Policy file: TOML with a [[rule]] table per rule
+    policy: PathBuf,
+
+    /// List what the policy would do without changing anything
+    #[arg(long)]
+    dry_run: bool,
+
+    /// Append a line of JSON per action to this file, instead of the
+    /// policy's audit_log
+    #[arg(long, value_name = "FILE")]
+    audit_log: Option<PathBuf>,
+
+    /// Refuse to touch anything outside this directory
+    #[arg(long)]
+    root: Option<PathBuf>,
+
+    /// Output format (text, json, ndjson, yaml or toml)
+    #[arg(short, long, default_value = "text")]
+    format: String,
+}
+
+/// Command-line arguments for the tag commands
+#[derive(Parser, Debug)]
+struct TagCommand {
+    #[command(subcommand)]
+    action: TagAction,
+}
+
+#[derive(Subcommand, Debug)]
+enum TagAction {
+    /// Tag the files a query finds
+    Add(TagQueryCommand),
+    /// Take a tag off the files a query finds
+    Remove(TagQueryCommand),
+    /// List the tags and how many files each has, or the files with one tag
+    List(TagListCommand),
+}
+
+/// Command-line arguments for tagging or untagging the results of a query
+#[derive(Parser, Debug)]
+struct TagQueryCommand {
+    /// The tag, such as project-x
+    tag: String,
+
+    /// A SELECT listing the files
+    query: String,
+
+    /// Refuse to look outside this directory
+    #[arg(long)]
+    root: Option<PathBuf>,
+}
+
+/// Command-line arguments for listing tags
+#[derive(Parser, Debug)]
+struct TagListCommand {
+    /// List the files with this tag, where they were when tagged
+    tag: Option<String>,
+}
+
+/// Command-line arguments for picking one result interactively
+#[derive(Parser, Debug)]
+struct PickCommand {
+    /// SQL query whose results to choose from
+    /// (e.g. "WITH RECURSIVE SELECT * FROM ~/src WHERE name = 'Cargo.toml'")
+    query: String,
+
+    /// Choose in fzf, in the built-in finder, or in fzf if it's installed
+    #[arg(long, default_value = "auto", value_parser = PossibleValuesParser::new(pick::PICKERS))]
+    picker: String,
+
+    /// Print the directory of the chosen file rather than its path, for cd;
+    /// a chosen directory is printed as it is
+    #[arg(long)]
+    dir: bool,
+
+    /// Refuse to look outside this directory
+    #[arg(long)]
+    root: Option<PathBuf>,
+}
+
+/// Command-line arguments for printing the shell integration
+#[derive(Parser, Debug)]
+struct ShellInitCommand {
+    /// The shell to print functions for
+    #[arg(value_parser = PossibleValuesParser::new(pick::SHELLS))]
+    shell: String,
+
+    /// What to call the function that picks a result and changes to its directory
+    #[arg(long, default_value = "fcd")]
+    name: String,
+}
+
+/// Command-line arguments for mounting a query's results as a folder
+#[cfg(feature = "fuse")]
+#[derive(Parser, Debug)]
+struct MountCommand {
+    /// SQL query whose files the folder holds
+    /// (e.g. "WITH RECURSIVE SELECT * FROM ~/Pictures WHERE extension = 'raw'")
+    query: String,
+
+    /// The empty directory to mount the folder on
+    mountpoint: PathBuf,
+
+    /// Show files as read-only regular files rather than symlinks, for
+    /// programs that don't follow symlinks
+    #[arg(long)]
+    passthrough: bool,
+
+    /// Run the query again when the folder is listed, at most this often
+    /// (e.g. 5s, 1m)
+    #[arg(long, value_parser = parse_duration, default_value = "5s")]
+    refresh: Duration,
+
+    /// Refuse to look outside this directory
+    #[arg(long)]
+    root: Option<PathBuf>,
+}
+
+/// Command-line arguments for merging the shards of a query
+#[derive(Parser, Debug)]
+struct MergeCommand {
+    /// The files fmql sql --shard I/N --partial wrote, one for each shard
+    #[arg(required = true)]
+    parts: Vec<PathBuf>,
+
+    /// Output format (text, json, ndjson, yaml, toml, csv, markdown or html)
+    #[arg(short, long, default_value = "text")]
+    format: String,
+
+    /// Put JSON output on a single line instead of pretty-printing it
+    #[arg(long)]
+    compact: bool,
+
+    /// Show dates as local times in this time zone (default UTC)
+    #[arg(long, value_name = "ZONE", value_parser = parse_time_zone)]
+    tz: Option<Tz>,
+
+    /// Refuse to read anything outside this directory
+    #[arg(long)]
+    root: Option<PathBuf>,
+
+    /// Write the output to this file instead of stdout, replacing it only
+    /// once the whole output has been written
+    #[arg(long, value_name = "FILE")]
+    out: Option<PathBuf>,
+}
+
+/// Command-line arguments for comparing the results of two runs
+#[derive(Parser, Debug)]
+struct DiffResultsCommand {
+    /// Results an earlier run saved with --format json
+    old: PathBuf,
+
+    /// Results a later run saved with --format json
+    new: PathBuf,
+
+    /// Leave out the changes of this attribute, such as accessed; may be
+    /// given more than once
+    #[arg(long, value_name = "ATTRIBUTE")]
+    ignore: Vec<String>,
+
+    /// Output format (text, json or yaml)
+    #[arg(short, long, default_value = "text")]
+    format: String,
+
+    /// Put JSON output on a single line instead of pretty-printing it
+    #[arg(long)]
+    compact: bool,
+
+    /// Write the output to this file instead of stdout, replacing it only
+    /// once the whole output has been written
+    #[arg(long, value_name = "FILE")]
+    out: Option<PathBuf>,
+}
+
+/// Command-line arguments for building a query with the wizard
+#[derive(Parser, Debug)]
+struct WizardCommand {
+    /// Output format for the results, if the query is run (text, json, ndjson,
+    /// yaml, toml, csv, markdown or html)
+    #[arg(short, long, default_value = "text")]
+    format: String,
+}
+
+/// Command-line arguments for measuring how fast queries run on a file system
+#[derive(Parser, Debug)]
+struct BenchCommand {
+    /// Directory to measure (searched recursively)
+    path: PathBuf,
+
+    /// Take at most this many entries
+    #[arg(long, default_value_t = 20_000)]
+    max_entries: usize,
+
+    /// Try at most this many threads (default twice the CPUs, up to 32)
+    #[arg(long)]
+    max_threads: Option<usize>,
+
+    /// A condition to time besides the standard ones, as a WHERE clause
+    /// (e.g. "content LIKE '%TODO%'"); may be given more than once
+    #[arg(long = "where", value_name = "CONDITION")]
+    conditions: Vec<String>,
+
     /// Output format (text or json)
     #[arg(short, long, default_value = "text")]
     format: String,
+
+    /// Put JSON output on a single line instead of pretty-printing it
+    #[arg(long)]
+    compact: bool,
+}
+
+/// Command-line arguments for translating another tool's search into a query
+#[derive(Parser, Debug)]
+struct TranslateCommand {
+    /// A find command line to translate, quoted as the shell would quote it
+    /// (e.g. "/var/log -name '*.log' -size +1M -mtime +30")
+    #[arg(long, value_name = "EXPRESSION", allow_hyphen_values = true)]
+    from_find: String,
+}
+
+/// Command-line arguments for the SQLite mode
+#[cfg(feature = "sqlite")]
+#[derive(Parser, Debug)]
+struct SqliteCommand {
+    /// SQLite SQL to run; fmql('<fmql query>') lists the files the query finds
+    /// (e.g. "SELECT name, size FROM fmql('SELECT * FROM .') ORDER BY size DESC")
+    sql: String,
+
+    /// Make a CSV file with a header row queryable as a table
+    #[arg(long, value_name = "NAME=FILE")]
+    csv: Vec<String>,
+
+    /// Output format (text, json, ndjson, yaml, toml, csv, markdown or html)
+    #[arg(short, long, default_value = "text")]
+    format: String,
+
+    /// Refuse to look outside this directory
+    #[arg(long)]
+    root: Option<PathBuf>,
+
+    /// Give up after visiting this many files
+    #[arg(long)]
+    max_files: Option<usize>,
+
+    /// Give up if an fmql() scan runs longer than this (e.g. 30s, 5m)
+    #[arg(long, value_parser = parse_duration)]
+    timeout: Option<Duration>,
+
+    /// Put JSON output on a single line instead of pretty-printing it
+    #[arg(long)]
+    compact: bool,
+
+    /// Add the type of each column, as for the sql command
+    #[arg(long)]
+    schema: bool,
+
+    /// Write the output to this file instead of stdout, replacing it only
+    /// once the whole output has been written
+    #[arg(long, value_name = "FILE")]
+    out: Option<PathBuf>,
+}
+
+/// Command-line arguments for the main application
+#[derive(Parser, Debug)]
+#[command(author, version, about)]
+struct AppArgs {
+    #[command(subcommand)]
+    command: Command,
+
+    /// Report errors and warnings on stderr as a line of JSON each, with their
+    /// code, message, span and path; --format json turns this on too
+    #[arg(long, global = true)]
+    json_errors: bool,
+
+    /// Run queries with the semantics of an older fmql, such as 0.3, so that
+    /// scripts written for it mean what they did (overrides the config file)
+    #[arg(long, global = true, value_name = "VERSION")]
+    compat: Option<Semantics>,
+
+    /// Write numbers and dates in text, Markdown and HTML output as this
+    /// locale does, such as de_DE or en_GB (default from LC_ALL, LC_NUMERIC,
+    /// LC_TIME or LANG; C for plain numbers and ISO dates)
+    #[arg(long, global = true, value_name = "LOCALE")]
+    locale: Option<Locale>,
+
+    /// Read file contents for content and matches in this encoding: auto
+    /// (the default) follows a byte order mark and otherwise tells UTF-16,
+    /// UTF-8 and Latin-1 apart; utf-8 reads every file as UTF-8
+    #[arg(long, global = true, value_name = "ENCODING")]
+    encoding: Option<TextEncoding>,
+
+    /// Let UPDATE change the permissions, owner or group of system paths:
+    /// /, directories such as /etc and /usr, and other users' homes
+    #[arg(long, global = true)]
+    privileged: bool,
+
+    /// What a statement that changes files does when another fmql run is
+    /// changing an overlapping tree: wait for it (the default), fail, go
+    /// ahead locking each file as it is changed (per-file, alongside other
+    /// per-file runs), or take no locks (none)
+    #[arg(long, global = true, value_name = "wait|fail|per-file|none")]
+    lock: Option<LockMode>,
+
+    /// With --lock wait, fail after waiting this long for another run
+    #[arg(long, global = true, value_name = "DURATION", value_parser = parse_duration)]
+    lock_timeout: Option<Duration>,
+
+    /// Use the settings of this profile in the config file, such as work;
+    /// its root, ignore patterns, format and time zone are defaults that
+    /// options given here override (default from FMQL_PROFILE)
+    #[arg(long, global = true, value_name = "NAME")]
+    profile: Option<String>,
+
+    /// Answer yes to questions rather than asking, such as the wizard's
+    /// whether to run its query (default from FMQL_ASSUME_YES)
+    #[arg(short = 'y', long, global = true)]
+    yes: bool,
+}
+
+/// Set when errors and warnings go to stderr as JSON
+static JSON_ERRORS: AtomicBool = AtomicBool::new(false);
+
+/// The semantics queries run with, from --compat or the config file
+static SEMANTICS: OnceLock<Semantics> = OnceLock::new();
+
+/// Returns the semantics queries run with.
+fn semantics() -> Semantics {
+    SEMANTICS.get().copied().unwrap_or_default()
+}
+
+/// How output people read writes numbers and dates, from --locale, the
+/// config file or the environment
+static LOCALE: OnceLock<Locale> = OnceLock::new();
+
+/// Returns the locale text, Markdown and HTML output are written in.
+fn locale() -> Locale {
+    LOCALE.get().copied().unwrap_or_default()
+}
+
+/// The encoding file contents are read in, from --encoding or the config
+/// file
+static ENCODING: OnceLock<TextEncoding> = OnceLock::new();
+
+/// Returns the encoding file contents are read in.
+fn encoding() -> TextEncoding {
+    ENCODING.get().copied().unwrap_or_default()
+}
+
+/// Whether UPDATE may change the access to system paths, from --privileged
+/// and the config file
+static PROTECTION: OnceLock<Protection> = OnceLock::new();
+
+/// Returns whether UPDATE may change the access to system paths.
+fn protection() -> Protection {
+    PROTECTION.get().cloned().unwrap_or_default()
+}
+
+/// How statements that change files lock their trees against other runs,
+/// from --lock and the config file
+static LOCKING: OnceLock<Locking> = OnceLock::new();
+
+/// Returns how statements that change files lock their trees.
+fn locking() -> Locking {
+    LOCKING.get().cloned().unwrap_or_default()
+}
+
+/// The settings the command line left out, from the environment and the
+/// config file
+static SETTINGS: OnceLock<Settings> = OnceLock::new();
+
+/// Returns the settings the command line left out.
+fn settings() -> &'static Settings {
+    SETTINGS.get_or_init(Settings::default)
+}
+
+/// The entries scans leave out, from the config file's profile
+static IGNORE: OnceLock<Ignore> = OnceLock::new();
+
+/// Returns the entries scans leave out.
+fn ignore() -> Ignore {
+    IGNORE.get().cloned().unwrap_or_default()
+}
+
+/// The attribute aliases and snippets of the config file
+static ALIASES: OnceLock<Aliases> = OnceLock::new();
+
+/// Returns a query the user wrote with the config file's attribute aliases
+/// and snippets expanded
+fn expand_query(sql: &str) -> Result<String, ParserError> {
+    match ALIASES.get() {
+        Some(aliases) => aliases.expand(sql),
+        None => Ok(sql.to_string()),
+    }
+}
+
+/// Parses a query the user wrote, after expanding the config file's
+/// attribute aliases and snippets
+fn parse_query(sql: &str) -> Result<FileQuery, ParserError> {
+    parse_sql(&expand_query(sql)?)
+}
+
+/// Returns the default options, with the semantics queries run with, the
+/// protection of system paths, the encoding file contents are read in, the
+/// entries scans leave out and how changes lock their trees.
+fn default_options() -> ExecutionOptions {
+    ExecutionOptions {
+        semantics: semantics(),
+        protection: protection(),
+        encoding: encoding(),
+        ignore: ignore(),
+        locking: locking(),
+        ..ExecutionOptions::default()
+    }
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Query files using SQL-like syntax
+    Sql(Box<SqlCommand>),
+    /// Validate a query without executing it
+    Check(CheckCommand),
+    /// Run a script of statements in order, optionally checking them all first
+    Apply(ApplyCommand),
+    /// Start an interactive query prompt
+    Repl(ReplCommand),
+    /// Choose one of a query's results interactively and print its path
+    Pick(PickCommand),
+    /// Print shell functions that use fmql pick, such as one to cd to a result
+    ShellInit(ShellInitCommand),
+    /// Report how many files and bytes a directory holds, optionally per owner
+    Usage(UsageCommand),
+    /// List large files that haven't been read in a long time, biggest first
+    Cold(ColdCommand),
+    /// Apply retention policies: age-based delete, archive and compress rules
+    Retention(RetentionCommand),
+    /// List the files modified recently, newest first, or follow them as they change
+    Recent(RecentCommand),
+    /// Check a query's results against a threshold and run a command if it's crossed
+    Alert(AlertCommand),
+    /// Tag files into collections that WHERE TAGGED('name') finds again
+    Tag(TagCommand),
+    /// Show a query's results as a read-only folder until it is unmounted
+    #[cfg(feature = "fuse")]
+    Mount(MountCommand),
+    /// Combine the results of the shards of a query run with --shard and --partial
+    Merge(MergeCommand),
+    /// Compare the results of two runs by path: rows added, removed and changed
+    DiffResults(DiffResultsCommand),
+    /// Build a query by answering questions, then print it and offer to run it
+    Wizard(WizardCommand),
+    /// Print the query that finds what a find command line finds
+    Translate(TranslateCommand),
+    /// Time listing, metadata reads and conditions on a directory, and suggest settings
+    Bench(BenchCommand),
+    /// Run full SQL over the file scan in an embedded SQLite
+    #[cfg(feature = "sqlite")]
+    Sqlite(SqliteCommand),
+}
+
+/// The query a bare `fmql` runs: the entries of the current directory
+const DEFAULT_QUERY: &str = "SELECT * FROM .";
+
+/// Puts the `sql` subcommand into a command line that leaves it out, so that
+/// `fmql "SELECT * FROM . WHERE size > 1MB"` runs the query and a bare `fmql`
+/// lists the current directory. The first argument after the global options
+/// gets `sql` in front of it when it is an option of its own, or has spaces
+/// in it as queries do. A subcommand, help and the version are left alone,
+/// and so is a single word, which is more likely a mistyped subcommand than
+/// a query
+fn with_default_command(mut args: Vec<OsString>) -> Vec<OsString> {
+    let mut at = 1;
+    while let Some(arg) = args.get(at).and_then(|arg| arg.to_str()) {
+        match arg {
+            "--json-errors" | "--privileged" | "--yes" | "-y" => at += 1,
+            "--compat" | "--locale" | "--encoding" | "--profile" | "--lock" | "--lock-timeout" => {
+                at += 2
+            }
+            _ if ["--compat=", "--locale=", "--encoding=", "--profile=", "--lock=", "--lock-timeout="]
+                .iter()
+                .any(|flag| arg.starts_with(flag)) =>
+            {
+                at += 1
+            }
+            _ => break,
+        }
+    }
+    if at > args.len() {
+        return args;
+    }
+    let Some(first) = args.get(at).map(|arg| arg.to_string_lossy().into_owned()) else {
+        args.extend([OsString::from("sql"), OsString::from(DEFAULT_QUERY)]);
+        return args;
+    };
+    let command = AppArgs::command();
+    let subcommand = first == "help"
+        || command.get_subcommands().any(|subcommand| {
+            subcommand.get_name() == first
+                || subcommand.get_all_aliases().any(|alias| alias == first)
+        });
+    let asks = matches!(first.as_str(), "-h" | "--help" | "-V" | "--version");
+    if !subcommand && !asks && (first.starts_with('-') || first.contains(char::is_whitespace)) {
+        args.insert(at, OsString::from("sql"));
+    }
+    args
+}
+
+fn main() {
+    catch_panics();
+    if let Err(payload) = std::panic::catch_unwind(run_command) {
+        report_panic(payload);
+    }
+}
+
+/// What the first panic said and where, kept by the hook [`catch_panics`]
+/// installs so it can be reported as an error instead
+static PANIC: OnceLock<String> = OnceLock::new();
+
+/// Keep panic messages to report as errors rather than printing them. With
+/// RUST_BACKTRACE set they are printed as well, backtrace and all, for
+/// debugging
+fn catch_panics() {
+    let print = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        let payload = info.payload();
+        let message = payload
+            .downcast_ref::<&str>()
+            .copied()
+            .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+            .unwrap_or("no message");
+        let _ = match info.location() {
+            Some(at) => PANIC.set(format!("{} at {}:{}", message, at.file(), at.line())),
+            None => PANIC.set(message.to_string()),
+        };
+        if std::env::var_os("RUST_BACKTRACE").is_some() {
+            print(info);
+        }
+    }));
+}
+
+/// Report a panic that reached main as an internal error. One from
+/// printing to a closed pipe, as when the output goes to `head`, just ends
+/// the run
+fn report_panic(payload: Box<dyn std::any::Any + Send>) -> ! {
+    let message = PANIC
+        .get()
+        .cloned()
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "no message".to_string());
+    if message.contains("failed printing to stdout") && message.contains("Broken pipe") {
+        process::exit(fmql_core::error::exit_code("io.broken_pipe"));
+    }
+    fail(
+        "internal.panic",
+        &format!(
+            "fmql hit a bug and stopped ({}); please report it at \
+             https://github.com/chriswmann/fmql/issues",
+            message
+        ),
+    )
+}
+
+/// Parses the command line again with the defaults of `settings`, such as
+/// the format FMQL_FORMAT or the profile gives, for the commands that take
+/// them.
+fn with_defaults(argv: Vec<OsString>, settings: &Settings) -> AppArgs {
+    let defaults = settings.defaults();
+    let mut command = AppArgs::command();
+    let names: Vec<String> = command.get_subcommands().map(|sub| sub.get_name().to_string()).collect();
+    for name in names {
+        command = command.mut_subcommand(name, |mut subcommand| {
+            for (id, value) in &defaults {
+                if subcommand.get_arguments().any(|arg| arg.get_id() == id) {
+                    subcommand = subcommand.mut_arg(id, |arg| arg.default_value(value.clone()));
+                }
+            }
+            subcommand
+        });
+    }
+    let matches = command.get_matches_from(argv);
+    AppArgs::from_arg_matches(&matches).unwrap_or_else(|err| err.exit())
+}
+
+/// Parse the command line and run the command
+fn run_command() {
+    let argv = with_default_command(std::env::args_os().collect());
+    let args = AppArgs::parse_from(argv.clone());
+    if args.json_errors || command_format(&args.command) == Some("json") {
+        JSON_ERRORS.store(true, Ordering::Relaxed);
+    }
+    let config = match Settings::profile(args.profile.as_deref()) {
+        Some(name) => Config::load_profile(&name),
+        None => Config::load(),
+    };
+    let config = config.unwrap_or_else(|err| exit_with_error(&err.into()));
+    let settings = SETTINGS.get_or_init(|| Settings::resolve(&config, args.yes));
+    let args = if settings.defaults().is_empty() { args } else { with_defaults(argv, settings) };
+    if command_format(&args.command) == Some("json") {
+        JSON_ERRORS.store(true, Ordering::Relaxed);
+    }
+    let semantics = match args.compat {
+        Some(semantics) => semantics,
+        None => config
+            .semantics()
+            .unwrap_or_else(|err| exit_with_error(&err.into()))
+            .unwrap_or_default(),
+    };
+    SEMANTICS.get_or_init(|| semantics);
+    let locale = match args.locale {
+        Some(locale) => locale,
+        None => config
+            .locale()
+            .unwrap_or_else(|err| exit_with_error(&err.into()))
+            .unwrap_or_else(Locale::from_env),
+    };
+    LOCALE.get_or_init(|| locale);
+    let encoding = match args.encoding {
+        Some(encoding) => encoding,
+        None => config
+            .encoding()
+            .unwrap_or_else(|err| exit_with_error(&err.into()))
+            .unwrap_or_default(),
+    };
+    ENCODING.get_or_init(|| encoding);
+    let aliases = config.aliases().unwrap_or_else(|err| exit_with_error(&err.into()));
+    ALIASES.get_or_init(|| aliases);
+    let ignore = config.ignore().unwrap_or_else(|err| exit_with_error(&err.into()));
+    IGNORE.get_or_init(|| ignore);
+    PROTECTION.get_or_init(|| Protection {
+        privileged: args.privileged,
+        allowed: config.allowed_system_paths().to_vec(),
+    });
+    let lock = match args.lock {
+        Some(mode) => mode,
+        None => config
+            .lock()
+            .unwrap_or_else(|err| exit_with_error(&err.into()))
+            .unwrap_or(LockMode::Wait),
+    };
+    LOCKING.get_or_init(|| Locking {
+        mode: lock,
+        dir: None,
+        timeout: args.lock_timeout,
+    });
+
+    match args.command {
+        Command::Sql(sql_args) => {
+            // Run in SQL mode
+            run_sql_mode(&sql_args);
+        },
+        Command::Check(check_args) => {
+            run_check_mode(&check_args);
+        },
+        Command::Apply(apply_args) => {
+            run_apply_mode(&apply_args);
+        },
+        Command::Repl(repl_args) => {
+            let options = ExecutionOptions {
+                time_zone: repl_args.tz.unwrap_or(Tz::UTC),
+                ..default_options()
+            };
+            let run = repl::run(&repl_args.format, options, settings.color());
+            if let Err(err) = run {
+                fail("io.other", &format!("can't run the REPL: {}", err));
+            }
+        },
+        Command::Pick(pick_args) => {
+            run_pick_mode(&pick_args);
+        },
+        Command::ShellInit(shell_args) => {
+            print!("{}", pick::shell_init(&shell_args.shell, &shell_args.name));
+        },
+        Command::Usage(usage_args) => {
+            run_usage_mode(&usage_args);
+        },
+        Command::Cold(cold_args) => {
+            run_cold_mode(&cold_args);
+        },
+        Command::Retention(retention_args) => match retention_args.action {
+            RetentionAction::Apply(apply_args) => run_retention_apply(&apply_args),
+        },
+        Command::Recent(recent_args) => {
+            run_recent_mode(&recent_args);
+        },
+        Command::Alert(alert_args) => {
+            run_alert_mode(&alert_args);
+        },
+        Command::Tag(tag_args) => {
+            run_tag_mode(&tag_args);
+        },
+        #[cfg(feature = "fuse")]
+        Command::Mount(mount_args) => {
+            run_mount_mode(&mount_args);
+        },
+        Command::Merge(merge_args) => {
+            run_merge_mode(&merge_args);
+        },
+        Command::DiffResults(diff_args) => {
+            run_diff_results_mode(&diff_args);
+        },
+        Command::Wizard(wizard_args) => {
+            run_wizard_mode(&wizard_args);
+        },
+        Command::Translate(translate_args) => {
+            run_translate_mode(&translate_args);
+        },
+        Command::Bench(bench_args) => {
+            run_bench_mode(&bench_args);
+        },
+        #[cfg(feature = "sqlite")]
+        Command::Sqlite(sqlite_args) => {
+            run_sqlite_mode(&sqlite_args);
+        },
+    }
+}
+
+/// Run the application in SQL mode
+fn run_sql_mode(args: &SqlCommand) {
+    let files_from = if args.files_from.is_some() || args.read.is_some() {
+        let list = args.files_from.as_deref().unwrap_or(Path::new("-"));
+        match read_files_from(list, args.read) {
+            Ok(files) => Some(files),
+            Err(err) => exit_with_error(&err.into()),
+        }
+    } else {
+        None
+    };
+    let mut options = ExecutionOptions {
+        jobs: args.jobs.max(1),
+        throttle: args.throttle,
+        nice_io: args.nice_io,
+        read_only: args.read_only,
+        root: args.root.clone(),
+        limits: ResourceLimits {
+            max_files_scanned: args.max_files,
+            max_results: args.max_results,
+            max_bytes_read: args.max_bytes_read,
+            max_depth: args.max_depth,
+            timeout: args.timeout,
+        },
+        include_self: args.include_self,
+        entry_type: args.entry_type,
+        files_from,
+        decompress: args.decompress,
+        encoding: encoding(),
+        time_zone: args.tz.unwrap_or(Tz::UTC),
+        checkpoint: args.checkpoint.clone(),
+        shard: args.shard,
+        stable_order: args.stable_order
+            || settings().ci()
+            || (args.out.is_none() && io::stdout().is_terminal()),
+        dry_run: false,
+        semantics: semantics(),
+        faults: if args.strict_eval { Faults::strict() } else { Faults::default() },
+        scan_id: None,
+        retry: RetryPolicy {
+            retries: args.io_retries,
+            delay: args.io_backoff,
+        },
+        events: Events::default(),
+        protection: protection(),
+        ignore: ignore(),
+        locking: locking(),
+        ..ExecutionOptions::default()
+    };
+    if let Some(list) = &args.each_root {
+        run_each_root(args, &options, list);
+        return;
+    }
+
+    let mut query = match parse_query(&args.query) {
+        Ok(query) => query,
+        Err(err) => exit_with_error(&err.into()),
+    };
+    // A SAMPLE in the query wins over --sample
+    if let (Some(fraction), FileQuery::Select { sample, .. }) = (args.sample, &mut query) {
+        sample.get_or_insert(fraction);
+    }
+    let mut output = Output {
+        format: args.format.clone(),
+        compact: args.compact,
+        schema: schema(&query),
+        with_schema: args.schema || args.csv_types,
+        time_zone: options.time_zone,
+        locale: locale(),
+        envelope: args.envelope.then(|| Envelope::start(&args.query)),
+    };
+    print_warnings(&query);
+    // Unlike the lints, these look at the file system the query runs on,
+    // which a replayed one doesn't
+    if args.replay.is_none() {
+        for warning in capabilities::warnings(&query) {
+            report_warning(&warning);
+        }
+    }
+    if options.files_from.is_some() && !query.reads_stdin() {
+        let err = ExecutorError::UnsupportedOperation(
+            "--files-from and --read need a query that says FROM STDIN".to_string(),
+        );
+        exit_with_error(&err.into());
+    }
+    if query.is_grouped() && args.emit.is_some() {
+        let err = ExecutorError::UnsupportedOperation(
+            "--emit needs a query that returns files, not groups".to_string(),
+        );
+        exit_with_error(&err.into());
+    }
+    let action = match (args.open, args.reveal) {
+        (true, _) => Some(Action::Open),
+        (_, true) => Some(Action::Reveal),
+        _ => None,
+    };
+    if let Some(action) = action
+        && (query.is_grouped() || query.is_rollup())
+    {
+        let err = ExecutorError::UnsupportedOperation(format!(
+            "--{} needs a query that returns files, not groups",
+            action.verb()
+        ));
+        exit_with_error(&err.into());
+    }
+    if args.baseline.is_some() && (query.is_grouped() || query.is_rollup()) {
+        let err = ExecutorError::UnsupportedOperation(
+            "--baseline needs a query that returns files, not groups".to_string(),
+        );
+        exit_with_error(&err.into());
+    }
+    if args.copy_paths && (query.is_grouped() || query.is_rollup()) {
+        let err = ExecutorError::UnsupportedOperation(
+            "--copy-paths needs a query that returns files, not groups".to_string(),
+        );
+        exit_with_error(&err.into());
+    }
+    if (args.record.is_some() || args.replay.is_some()) && query.is_rollup() {
+        let err = ExecutorError::UnsupportedOperation(
+            "--record and --replay need a query that returns files or groups, not a rollup"
+                .to_string(),
+        );
+        exit_with_error(&err.into());
+    }
+    if let Some(path) = &args.record {
+        options.events = record(path, &args.query, &query, args.anonymize);
+    }
+    let replay = args
+        .replay
+        .as_deref()
+        .map(|path| trace::replay(path).unwrap_or_else(|err| exit_with_error(&err.into())));
+    let run = |query: &FileQuery| match &replay {
+        Some(replay) => execute_replay(query, replay, &options),
+        None => execute_query_with_options(query, &options),
+    };
+    if args.partial {
+        match execute_shard(&query, &options) {
+            Ok(part) => write_output(args.out.as_deref(), |out| {
+                serde_json::to_writer(&mut *out, &part)?;
+                writeln!(out)
+            }),
+            Err(err) => exit_with_error(&err.into()),
+        }
+        return;
+    }
+    if query.is_rollup() {
+        match execute_rollup_with_options(&query, &options) {
+            Ok(trees) => {
+                report_faults(&options.faults);
+                output.finish(false);
+                write_output(args.out.as_deref(), |out| print_rollup(&trees, &output, out));
+            }
+            Err(err) => exit_with_error(&err.into()),
+        }
+        return;
+    }
+    // Only a terminal is capped: files and pipes asked for everything
+    let cap = !args.no_cap && args.out.is_none() && io::stdout().is_terminal();
+    let started = Instant::now();
+    let stats = |rows: usize| {
+        if args.stats {
+            report_stats(&query, rows, started);
+        }
+    };
+    if let Some(baseline) = &args.baseline {
+        match run(&query) {
+            Ok(results) => {
+                report_faults(&options.faults);
+                let diff = read_rows(baseline)
+                    .and_then(|old| diff_rows(&old, &to_rows(&results)?, &args.ignore))
+                    .unwrap_or_else(|err| exit_with_error(&err.into()));
+                let color = args.out.is_none() && use_color();
+                write_output(args.out.as_deref(), |out| {
+                    print_diff(&diff, &args.format, args.compact, color, out)
+                });
+                stats(results.len());
+                if !diff.is_empty() {
+                    process::exit(1);
+                }
+            }
+            Err(err) => exit_with_error(&err.into()),
+        }
+        return;
+    }
+    if query.is_grouped() {
+        let rows = match &replay {
+            Some(replay) => execute_grouped_replay(&query, replay, &options),
+            None => execute_grouped_with_options(&query, &options),
+        };
+        match rows {
+            Ok(rows) => {
+                report_faults(&options.faults);
+                let (shown, left_out) = cap_rows(&rows, cap);
+                output.finish(left_out > 0);
+                write_output(args.out.as_deref(), |out| print_groups(shown, &output, out));
+                report_capped(shown.len(), left_out, CAP_HINT);
+                stats(rows.len());
+                let summary = || Summary::of_groups(&args.query, &rows, args.notify_top);
+                if !notify(&args.notify, summary) {
+                    process::exit(1);
+                }
+            }
+            Err(err) => exit_with_error(&err.into()),
+        }
+        return;
+    }
+
+    match run(&query) {
+        Ok(results) => {
+            report_faults(&options.faults);
+            let (shown, left_out) = cap_rows(&results, cap);
+            output.finish(left_out > 0);
+            write_output(args.out.as_deref(), |out| match args.emit {
+                Some(emit) => write_paths(out, shown.iter().map(|r| r.path.as_path()), emit),
+                None => print_results(shown, &output, out),
+            });
+            report_capped(shown.len(), left_out, CAP_HINT);
+            stats(results.len());
+            let notified =
+                notify(&args.notify, || Summary::of_files(&args.query, &results, args.notify_top));
+            if args.copy_paths {
+                let paths: Vec<&Path> = results.iter().map(|r| r.path.as_path()).collect();
+                if let Err(message) = open::copy_paths(&paths) {
+                    fail("io.other", &message);
+                }
+            }
+            if let Some(action) = action {
+                match action.apply(&results) {
+                    Ok(0) => {}
+                    Ok(_) => process::exit(1),
+                    Err(message) => fail("exec.limit_exceeded", &message),
+                }
+            }
+            let failed = results
+                .iter()
+                .filter(|r| r.status == Some(OperationStatus::Failed))
+                .count();
+            if failed > 0 {
+                let verb = match query {
+                    FileQuery::Copy { .. } => "copied",
+                    FileQuery::Compress { .. } => "compressed",
+                    FileQuery::Extract { .. } => "extracted",
+                    FileQuery::Archive { .. } => "archived",
+                    FileQuery::Restore { .. } => "restored",
+                    _ => "updated",
+                };
+                eprintln!("{} of {} files could not be {}", failed, results.len(), verb);
+                process::exit(1);
+            }
+            if !notified {
+                process::exit(1);
+            }
+        },
+        Err(err) => exit_with_error(&err.into()),
+    }
+}
+
+/// Returns events that note the entries the query sees, and write them to
+/// `path` as a trace once it finishes, whether it failed or not
+fn record(path: &Path, sql: &str, query: &FileQuery, anonymize: bool) -> Events {
+    let Some(root) = query.root().map(Path::to_path_buf) else {
+        let err = ExecutorError::UnsupportedOperation(
+            "--record needs a query that reads a path, not STDIN".to_string(),
+        );
+        exit_with_error(&err.into());
+    };
+    let recorder = Recorder::new().unwrap_or_else(|err| exit_with_error(&err.into()));
+    let (path, sql) = (path.to_path_buf(), sql.to_string());
+    Events::new(move |event| {
+        recorder.observe(&event);
+        if let Event::Finished { .. } = event {
+            let mut trace = recorder.trace(&sql, &root);
+            if anonymize {
+                trace.anonymize();
+            }
+            write_output(Some(&path), |out| trace.write(out));
+        }
+    })
+}
+
+/// What one root of --each-root gave: files, or the rows of a grouped query
+enum RootRows {
+    Files(Vec<FileResult>),
+    Groups(Vec<GroupRow>),
+}
+
+/// Run the query once for each root listed in `list`, in place of $ROOT,
+/// and print what they found together, each row tagged with its source_root
+/// and scan_id
+fn run_each_root(args: &SqlCommand, options: &ExecutionOptions, list: &Path) {
+    let roots = read_files_from(list, None).unwrap_or_else(|err| exit_with_error(&err.into()));
+    // $ROOT stands for the path the query reads, quoted or not
+    let sql = args.query.replace("'$ROOT'", "$ROOT").replace("$ROOT", "'$ROOT'");
+    let query = parse_query(&sql).unwrap_or_else(|err| exit_with_error(&err.into()));
+    if query.is_rollup() {
+        let err = ExecutorError::UnsupportedOperation(
+            "--each-root needs a query that returns files or groups, not a rollup".to_string(),
+        );
+        exit_with_error(&err.into());
+    }
+    print_warnings(&query);
+    let compiled =
+        CompiledQuery::from_query(query, options).unwrap_or_else(|err| exit_with_error(&err));
+    if compiled.root() != Some(Path::new("$ROOT")) {
+        let err = ExecutorError::UnsupportedOperation(
+            "--each-root needs a query that reads FROM $ROOT".to_string(),
+        );
+        exit_with_error(&err.into());
+    }
+    let grouped = compiled.query().is_grouped();
+    // Unless the query already has them
+    let mut schema = schema(compiled.query());
+    let implicit: Vec<&str> = ["source_root", "scan_id"]
+        .into_iter()
+        .filter(|name| !schema.iter().any(|column| column.name == *name))
+        .collect();
+    schema.extend(implicit.iter().map(|name| SchemaColumn {
+        name: name.to_string(),
+        value_type: ValueType::String,
+    }));
+    let mut output = Output {
+        format: args.format.clone(),
+        compact: args.compact,
+        schema,
+        with_schema: args.schema || args.csv_types,
+        time_zone: options.time_zone,
+        locale: locale(),
+        envelope: args.envelope.then(|| Envelope::start(&args.query)),
+    };
+    let run = |root: &Path| {
+        // Each root is a run of its own, so the rows say which
+        let scan_id = ScanSource::new_id(Utc::now());
+        let options = ExecutionOptions { scan_id: Some(scan_id.clone()), ..options.clone() };
+        let compiled = compiled.with_options(&options);
+        let tags: Vec<(String, FileValue)> = [
+            ("source_root", FileValue::String(display_path(root))),
+            ("scan_id", FileValue::String(scan_id)),
+        ]
+        .into_iter()
+        .filter(|(name, _)| implicit.contains(name))
+        .map(|(name, value)| (name.to_string(), value))
+        .collect();
+        if grouped {
+            let mut rows = compiled.execute_grouped_on(root)?;
+            for row in &mut rows {
+                row.columns.extend(tags.iter().cloned());
+            }
+            Ok(RootRows::Groups(rows))
+        } else {
+            let mut files = compiled.execute_on(root)?;
+            for file in &mut files {
+                file.computed.extend(tags.iter().cloned());
+            }
+            Ok(RootRows::Files(files))
+        }
+    };
+    let jobs = args.root_jobs.max(1);
+    let outcomes = match args.output_order {
+        Some(order) => stream_roots(args, &roots, jobs, order, &output, run),
+        None => for_each_root(&roots, jobs, run, |_, _| {}),
+    };
+
+    // A root that can't be scanned doesn't stop the others
+    let mut failure = None;
+    let (mut files, mut groups) = (Vec::new(), Vec::new());
+    for outcome in outcomes {
+        match outcome {
+            Ok(RootRows::Files(found)) => files.extend(found),
+            Ok(RootRows::Groups(rows)) => groups.extend(rows),
+            Err(err) => {
+                report_error(&err);
+                failure.get_or_insert(err.exit_code());
+            }
+        }
+    }
+    report_faults(&options.faults);
+
+    let cap = !args.no_cap && args.out.is_none() && io::stdout().is_terminal();
+    if args.output_order.is_some() {
+        // The rows were printed as each root finished
+        if files.iter().any(|r| r.status == Some(OperationStatus::Failed)) {
+            failure.get_or_insert(1);
+        }
+    } else if grouped {
+        let (shown, left_out) = cap_rows(&groups, cap);
+        output.finish(left_out > 0);
+        write_output(args.out.as_deref(), |out| print_groups(shown, &output, out));
+        report_capped(shown.len(), left_out, CAP_HINT);
+    } else {
+        let (shown, left_out) = cap_rows(&files, cap);
+        output.finish(left_out > 0);
+        write_output(args.out.as_deref(), |out| match args.emit {
+            Some(emit) => write_paths(out, shown.iter().map(|r| r.path.as_path()), emit),
+            None => print_results(shown, &output, out),
+        });
+        report_capped(shown.len(), left_out, CAP_HINT);
+        if files.iter().any(|r| r.status == Some(OperationStatus::Failed)) {
+            failure.get_or_insert(1);
+        }
+    }
+    let notified = match grouped {
+        true => notify(&args.notify, || Summary::of_groups(&args.query, &groups, args.notify_top)),
+        false => notify(&args.notify, || Summary::of_files(&args.query, &files, args.notify_top)),
+    };
+    if !notified {
+        failure.get_or_insert(1);
+    }
+    if let Some(code) = failure {
+        process::exit(code);
+    }
+}
+
+/// Run the roots of --each-root with --output-order, printing each root's
+/// rows as it finishes: rendered whole, so no two roots' lines mix, and
+/// written by this thread alone, in the order asked for
+fn stream_roots(
+    args: &SqlCommand,
+    roots: &[PathBuf],
+    jobs: usize,
+    order: OutputOrder,
+    output: &Output,
+    run: impl Fn(&Path) -> fmql_core::error::Result<RootRows> + Sync,
+) -> Vec<fmql_core::error::Result<RootRows>> {
+    if args.emit.is_none() && output.format != "ndjson" {
+        let err = ExecutorError::UnsupportedOperation(
+            "--output-order prints the rows as they come, a line each, with --format ndjson or --emit"
+                .to_string(),
+        );
+        exit_with_error(&err.into());
+    }
+    let render = |rows: &RootRows| -> io::Result<Vec<u8>> {
+        let mut part = Vec::new();
+        match (rows, args.emit) {
+            (RootRows::Files(files), Some(emit)) => {
+                write_paths(&mut part, files.iter().map(|r| r.path.as_path()), emit)?
+            }
+            (RootRows::Files(files), None) => print_results(files, output, &mut part)?,
+            (RootRows::Groups(rows), _) => print_groups(rows, output, &mut part)?,
+        }
+        Ok(part)
+    };
+    let mut outcomes = Vec::new();
+    write_output(args.out.as_deref(), |out| {
+        let mut serializer = OutputSerializer::new(out, order);
+        let mut written = Ok(());
+        outcomes = for_each_root(roots, jobs, run, |i, outcome| {
+            // A root that failed still has its turn, with nothing to print
+            let part = match outcome {
+                Ok(rows) => render(rows),
+                Err(_) => Ok(Vec::new()),
+            };
+            if written.is_ok() {
+                written = part.and_then(|part| serializer.write(i, part));
+            }
+        });
+        written?;
+        serializer.into_inner().map(drop)
+    });
+    outcomes
+}
+
+/// Run `run` for each root, `jobs` at a time, and return what each gave in
+/// the order of the roots. `done` is given each root's number and what it
+/// gave as soon as it finishes, on the calling thread
+fn for_each_root<T: Send>(
+    roots: &[PathBuf],
+    jobs: usize,
+    run: impl Fn(&Path) -> T + Sync,
+    mut done: impl FnMut(usize, &T),
+) -> Vec<T> {
+    let next = AtomicUsize::new(0);
+    let mut found: Vec<Option<T>> = roots.iter().map(|_| None).collect();
+    thread::scope(|scope| {
+        let (sender, finished) = mpsc::channel();
+        for _ in 0..jobs.min(roots.len()) {
+            let (sender, next, run) = (sender.clone(), &next, &run);
+            scope.spawn(move || loop {
+                let i = next.fetch_add(1, Ordering::Relaxed);
+                let Some(root) = roots.get(i) else { break };
+                if sender.send((i, run(root))).is_err() {
+                    break;
+                }
+            });
+        }
+        // The workers hold the only senders left, so this ends when they do
+        drop(sender);
+        for (i, outcome) in finished {
+            done(i, &outcome);
+            found[i] = Some(outcome);
+        }
+    });
+    found.into_iter().map(|outcome| outcome.expect("every root is run")).collect()
+}
+
+/// Read a list of paths from a file, or from stdin if the file is `-`,
+/// guessing the separator unless the format is given
+fn read_files_from(list: &Path, format: Option<PathListFormat>) -> std::io::Result<Vec<PathBuf>> {
+    let reader: Box<dyn std::io::Read> = if list == Path::new("-") {
+        Box::new(std::io::stdin().lock())
+    } else {
+        Box::new(std::fs::File::open(list)?)
+    };
+    match format {
+        Some(format) => read_paths(reader, format),
+        None => read_path_list(reader),
+    }
+}
+
+/// The most rows printed to a terminal, so that a recursive query over `/`
+/// can't flood it; the library itself never caps results
+const OUTPUT_CAP: usize = 100_000;
+
+/// How to see the rows left out by the cap, for fmql sql
+const CAP_HINT: &str = "add a LIMIT to narrow the query, or --no-cap to print them all";
+
+/// Returns the first OUTPUT_CAP rows if `cap` is set, with how many were
+/// left out
+fn cap_rows<T>(rows: &[T], cap: bool) -> (&[T], usize) {
+    match cap && rows.len() > OUTPUT_CAP {
+        true => (&rows[..OUTPUT_CAP], rows.len() - OUTPUT_CAP),
+        false => (rows, 0),
+    }
+}
+
+/// Print the --stats footer: the rows a query gave, how long it took and
+/// the space on the file system it read
+fn report_stats(query: &FileQuery, rows: usize, started: Instant) {
+    eprintln!("Stats: {} rows in {:.3}s", rows, started.elapsed().as_secs_f64());
+    let Some(root) = query.root() else {
+        return;
+    };
+    match (available_bytes(root), total_bytes(root)) {
+        (Ok(free), Ok(total)) => eprintln!(
+            "Stats: {} of {} bytes free ({:.1}%) on the file system holding {}",
+            free,
+            total,
+            free as f64 * 100.0 / total.max(1) as f64,
+            display_path(root)
+        ),
+        (Err(err), _) | (_, Err(err)) => {
+            eprintln!("Stats: cannot read the space free on {}: {}", display_path(root), err)
+        }
+    }
+}
+
+/// Send the summary `summary` makes to each of `sinks`, reporting those it
+/// can't be sent to. Returns false if any failed
+fn notify(sinks: &[Sink], summary: impl FnOnce() -> Summary) -> bool {
+    if sinks.is_empty() {
+        return true;
+    }
+    let summary = summary();
+    let mut sent = true;
+    for sink in sinks {
+        if let Err(message) = notify::send(sink, &summary) {
+            eprintln!("Error: can't notify {}: {}", sink, message);
+            sent = false;
+        }
+    }
+    sent
+}
+
+/// Tell the user, on stderr, that rows were left out and how to see them
+fn report_capped(shown: usize, left_out: usize, hint: &str) {
+    if left_out > 0 {
+        eprintln!(
+            "Output truncated: showed the first {} of {} rows; {}",
+            shown,
+            shown + left_out,
+            hint
+        );
+    }
+}
+
+/// Returns the output format the command was given, if it takes one
+fn command_format(command: &Command) -> Option<&str> {
+    let format = match command {
+        Command::Sql(args) => &args.format,
+        Command::Check(args) => &args.format,
+        Command::Apply(args) => &args.format,
+        Command::Repl(args) => &args.format,
+        Command::Usage(args) => &args.format,
+        Command::Cold(args) => &args.format,
+        Command::Recent(args) => &args.format,
+        Command::Retention(args) => match &args.action {
+            RetentionAction::Apply(apply) => &apply.format,
+        },
+        Command::Merge(args) => &args.format,
+        Command::DiffResults(args) => &args.format,
+        Command::Wizard(args) => &args.format,
+        Command::Bench(args) => &args.format,
+        #[cfg(feature = "sqlite")]
+        Command::Sqlite(args) => &args.format,
+        _ => return None,
+    };
+    Some(format)
+}
+
+/// Report an error on stderr, as a line of JSON if asked for
+fn report_error(err: &FMQLError) {
+    if JSON_ERRORS.load(Ordering::Relaxed) {
+        print_diagnostic(&Diagnostic::from(err));
+    } else {
+        eprintln!("Error: {}", err);
+    }
+}
+
+/// Report a warning on stderr, as a line of JSON if asked for
+fn report_warning(warning: &LintWarning) {
+    if JSON_ERRORS.load(Ordering::Relaxed) {
+        print_diagnostic(&Diagnostic::from(warning));
+    } else {
+        eprintln!("warning: {}", warning);
+    }
+}
+
+/// Warn about the files the query's condition couldn't be evaluated for,
+/// one warning each up to those kept, then how many more there were
+fn report_faults(faults: &Faults) {
+    for fault in faults.kept() {
+        report_warning(&LintWarning {
+            code: "evaluation_error",
+            message: format!("{}: {}", fault.path.display(), fault.message),
+        });
+    }
+    let more = faults.count().saturating_sub(Faults::KEPT);
+    if more > 0 {
+        report_warning(&LintWarning {
+            code: "evaluation_error",
+            message: format!("and {} more files the condition couldn't be evaluated for", more),
+        });
+    }
+}
+
+fn print_diagnostic(diagnostic: &Diagnostic) {
+    match serde_json::to_string(diagnostic) {
+        Ok(line) => eprintln!("{}", line),
+        Err(_) => eprintln!("{}: {}", diagnostic.level, diagnostic.message),
+    }
+}
+
+/// Report an error and exit with the code it maps to. A closed output
+/// pipe isn't worth a message: whoever closed it has what they wanted
+fn exit_with_error(err: &FMQLError) -> ! {
+    if err.code() != "io.broken_pipe" {
+        report_error(err);
+    }
+    process::exit(err.exit_code());
+}
+
+/// Report a failure that isn't one of the engine's errors, with its error
+/// code, and exit with the code that maps to
+fn fail(code: &'static str, message: &str) -> ! {
+    if JSON_ERRORS.load(Ordering::Relaxed) {
+        print_diagnostic(&Diagnostic {
+            level: "error",
+            code,
+            message: message.to_string(),
+            span: None,
+            path: None,
+        });
+    } else {
+        eprintln!("Error: {}", message);
+    }
+    process::exit(fmql_core::error::exit_code(code));
+}
+
+/// Returns true for the formats that serialize results rather than
+/// printing them as text
+fn is_structured(format: &str) -> bool {
+    matches!(format, "json" | "ndjson" | "yaml" | "toml")
+}
+
+/// Run `write` against the output: the file given by --out, or stdout.
+///
+/// A file is written next to its destination under a temporary name and
+/// renamed over it once complete, so a query that fails halfway never
+/// leaves a truncated report behind. A report that replaces another keeps
+/// its permissions, and a new one gets those of any new file.
+fn write_output(out: Option<&Path>, write: impl FnOnce(&mut dyn Write) -> io::Result<()>) {
+    let result = match out {
+        None => {
+            // Stdout flushes at every newline, which pretty JSON has plenty of
+            let mut stdout = io::BufWriter::new(io::stdout().lock());
+            write(&mut stdout).and_then(|()| stdout.flush())
+        }
+        Some(path) => (|| {
+            let dir = match path.parent() {
+                Some(dir) if !dir.as_os_str().is_empty() => dir,
+                _ => Path::new("."),
+            };
+            let mut file = new_report(path, dir)?;
+            let mut writer = io::BufWriter::new(file.as_file_mut());
+            write(&mut writer)?;
+            writer.flush()?;
+            drop(writer);
+            file.as_file().sync_all()?;
+            file.persist(path).map_err(|err| err.error)?;
+            Ok(())
+        })(),
+    };
+    if let Err(err) = result {
+        exit_with_error(&err.into());
+    }
+}
+
+/// Create the temporary file a report to `path` is written to, in `dir`.
+/// It has the permissions of the report it replaces, if there is one, and
+/// otherwise 0666 less the umask, as a file created in place would.
+fn new_report(path: &Path, dir: &Path) -> io::Result<tempfile::NamedTempFile> {
+    let existing = fs::metadata(path).ok().map(|metadata| metadata.permissions());
+    let mut builder = tempfile::Builder::new();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        // Created with the mode given, which the umask then narrows
+        builder.permissions(fs::Permissions::from_mode(0o666));
+    }
+    let file = builder.tempfile_in(dir)?;
+    if let Some(permissions) = existing {
+        file.as_file().set_permissions(permissions)?;
+    }
+    Ok(file)
+}
+
+/// How to print the results of a query
+struct Output {
+    /// The output format, such as text or json
+    format: String,
+    /// Put JSON on a single line
+    compact: bool,
+    /// The query's result columns and their types
+    schema: Vec<SchemaColumn>,
+    /// Include the types: a schema block in JSON, YAML and TOML, and a row
+    /// under the CSV header
+    with_schema: bool,
+    /// The time zone text and table output show dates in
+    time_zone: Tz,
+    /// How text, Markdown and HTML output write numbers and dates
+    locale: Locale,
+    /// The record of the run JSON, YAML and TOML output is wrapped in
+    envelope: Option<Envelope>,
 }
 
-/// Command-line arguments for the main application
-#[derive(Parser, Debug)]
-#[command(author, version, about)]
-struct AppArgs {
-    #[command(subcommand)]
-    command: Command,
+/// What an archived result needs to say about the run that made it
+#[derive(Serialize)]
+struct Envelope {
+    fmql_version: &'static str,
+    query: String,
+    started: DateTime<Utc>,
+    finished: Option<DateTime<Utc>>,
+    host: String,
+    cwd: Option<PathBuf>,
+    /// Some rows were left out of the output, by the terminal cap
+    truncated: bool,
 }
 
-#[derive(Subcommand, Debug)]
-enum Command {
-    /// Query files using SQL-like syntax
-    Sql(SqlCommand),
+impl Envelope {
+    /// Starts the record of running a query now
+    fn start(query: &str) -> Self {
+        Envelope {
+            fmql_version: env!("CARGO_PKG_VERSION"),
+            query: query.to_string(),
+            started: Utc::now(),
+            finished: None,
+            host: gethostname::gethostname().to_string_lossy().into_owned(),
+            cwd: std::env::current_dir().ok(),
+            truncated: false,
+        }
+    }
+
+    /// Records that the query has finished, and whether rows were left out
+    fn finish(&mut self, truncated: bool) {
+        self.finished = Some(Utc::now());
+        self.truncated = truncated;
+    }
 }
 
-fn main() {
-    let args = AppArgs::parse();
-    
-    match args.command {
-        Command::Sql(sql_args) => {
-            // Run in SQL mode
-            run_sql_mode(&sql_args);
-        },
+impl Output {
+    /// Records that the query has finished, if the output has an envelope
+    fn finish(&mut self, truncated: bool) {
+        if let Some(envelope) = &mut self.envelope {
+            envelope.finish(truncated);
+        }
+    }
+
+    /// Prints a query's results in a format, without the extras of the sql
+    /// command
+    fn new(format: &str, query: &FileQuery) -> Self {
+        Output {
+            format: format.to_string(),
+            compact: false,
+            schema: schema(query),
+            with_schema: false,
+            time_zone: Tz::UTC,
+            locale: locale(),
+            envelope: None,
+        }
     }
 }
 
-/// Run the application in SQL mode
-fn run_sql_mode(args: &SqlCommand) {
-    match parse_sql(&args.query) {
-        Ok(query) => {
-            match execute_query(&query) {
-                Ok(results) => {
-                    match args.format.as_str() {
-                        "json" => {
-                            println!("{}", serde_json::to_string_pretty(&results).unwrap_or_else(|e| {
-                                eprintln!("Error serializing results: {}", e);
-                                process::exit(1);
-                            }));
-                        },
-                        _ => {
-                            // Default to text output
-                            println!("{} results found:", results.len());
-                            for result in &results {
-                                println!("{}: {} bytes", result.path.display(), result.size);
+/// A list of results under a name, after their schema if there is one
+struct Document<'a, T> {
+    key: &'a str,
+    items: &'a [T],
+    schema: Option<&'a [SchemaColumn]>,
+    envelope: Option<&'a Envelope>,
+}
+
+impl<T: Serialize> Serialize for Document<'_, T> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeMap;
+
+        let mut map = serializer.serialize_map(None)?;
+        if let Some(envelope) = self.envelope {
+            map.serialize_entry("envelope", envelope)?;
+        }
+        if let Some(schema) = self.schema {
+            map.serialize_entry("schema", schema)?;
+        }
+        map.serialize_entry(self.key, self.items)?;
+        map.end()
+    }
+}
+
+/// Print a list of results as JSON, NDJSON, YAML or TOML. JSON and YAML
+/// are a bare list unless the schema or the envelope is wanted, when they
+/// become an object with those and the list under `key`. A TOML document
+/// can't be a bare list, so there the list is always an array of tables
+/// named `key`. NDJSON is one compact object per line, and nothing else.
+///
+/// JSON, NDJSON and YAML are written as they are serialized, so millions of
+/// results never wait in memory as one string; TOML has to be built first.
+fn print_structured<T: Serialize>(
+    items: &[T],
+    key: &str,
+    output: &Output,
+    out: &mut dyn Write,
+) -> io::Result<()> {
+    let document = Document {
+        key,
+        items,
+        schema: output.with_schema.then_some(output.schema.as_slice()),
+        envelope: output.envelope.as_ref(),
+    };
+    let wrapped = output.with_schema || output.envelope.is_some();
+    let json = |err: serde_json::Error| match err.io_error_kind() {
+        Some(_) => io::Error::from(err),
+        None => io::Error::other(format!("can't serialize results: {}", err)),
+    };
+    let yaml =
+        |err: serde_yaml::Error| io::Error::other(format!("can't serialize results: {}", err));
+    match (output.format.as_str(), wrapped) {
+        ("ndjson", _) => {
+            for item in items {
+                serde_json::to_writer(&mut *out, item).map_err(json)?;
+                writeln!(out)?;
+            }
+            return Ok(());
+        }
+        ("yaml", false) => return serde_yaml::to_writer(&mut *out, items).map_err(yaml),
+        ("yaml", true) => return serde_yaml::to_writer(&mut *out, &document).map_err(yaml),
+        ("toml", _) => {
+            let text = toml::to_string_pretty(&document)
+                .map_err(|e| io::Error::other(format!("can't serialize results: {}", e)))?;
+            return writeln!(out, "{}", text.trim_end());
+        }
+        (_, false) if output.compact => serde_json::to_writer(&mut *out, items),
+        (_, false) => serde_json::to_writer_pretty(&mut *out, items),
+        (_, true) if output.compact => serde_json::to_writer(&mut *out, &document),
+        (_, true) => serde_json::to_writer_pretty(&mut *out, &document),
+    }
+    .map_err(json)?;
+    writeln!(out)
+}
+
+/// A file as a row of its query's schema, with a field for each column and
+/// no others, for structured output that carries the schema
+struct SchemaRow<'a> {
+    file: &'a FileResult,
+    columns: &'a [SchemaColumn],
+}
+
+impl Serialize for SchemaRow<'_> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeMap;
+
+        let mut map = serializer.serialize_map(Some(self.columns.len()))?;
+        for column in self.columns {
+            let value = file_value(self.file, &column.name);
+            map.serialize_entry(&column.name, &PlainValue(&value))?;
+        }
+        map.end()
+    }
+}
+
+/// Print query results in the requested format
+fn print_results(results: &[FileResult], output: &Output, out: &mut dyn Write) -> io::Result<()> {
+    match output.format.as_str() {
+        format if is_structured(format) && output.with_schema => {
+            let rows: Vec<SchemaRow> = results
+                .iter()
+                .map(|file| SchemaRow { file, columns: &output.schema })
+                .collect();
+            print_structured(&rows, "files", output, out)
+        }
+        format if is_structured(format) => print_structured(results, "files", output, out),
+        format if is_table_format(format) => {
+            let table = Table::from_results(results, &output.schema)
+                .in_zone(output.time_zone)
+                .in_locale(output.locale);
+            table.write(format, output.with_schema, out)
+        }
+        _ => {
+            // Default to text output
+            let text = |value: &FileValue| output.locale.format_value(value, output.time_zone);
+            let count = |n: u64| text(&FileValue::from_u64(n));
+            writeln!(out, "{} results found:", results.len())?;
+            for result in results {
+                let note = match (result.status, &result.error_message, result.conflict) {
+                    (_, Some(message), _) => Some(format!("failed: {}", message)),
+                    (_, None, Some(conflict)) => Some(conflict.to_string()),
+                    (Some(OperationStatus::Skipped), None, None) => Some("unchanged".to_string()),
+                    _ => result.copied.zip(result.changes.first()).map(|(method, change)| {
+                        let from = text(&change.old_value);
+                        let mut note = format!("{} from {}", method.verb(), from);
+                        if let Some(transfer) = result.transfer {
+                            if transfer.resumed > 0 {
+                                note += &format!(", resumed at {} bytes", count(transfer.resumed));
                             }
+                            if transfer.retries > 0 {
+                                note += &format!(", after {} retries", transfer.retries);
+                            }
+                        }
+                        if result.checksum.is_some() {
+                            note += ", verified";
                         }
+                        note
+                    }).or_else(|| {
+                        let (from, size) = (result.changes.first()?, result.changes.get(1)?);
+                        result.compressed?;
+                        Some(format!(
+                            "compressed from {}, {} bytes before",
+                            text(&from.old_value),
+                            text(&size.old_value)
+                        ))
+                    }).or_else(|| {
+                        let (extracted, to) = (result.extracted?, result.changes.first()?);
+                        Some(format!(
+                            "extracted {} files ({} bytes) to {}",
+                            count(extracted.files),
+                            count(extracted.bytes),
+                            text(&to.new_value)
+                        ))
+                    }).or_else(|| {
+                        let archive = result.archived.as_deref()?;
+                        Some(format!("archived in {}", display_path(archive)))
+                    }),
+                };
+                let columns: String = result
+                    .computed
+                    .iter()
+                    .map(|(name, value)| {
+                        format!(", {} = {}", name, text(value))
+                    })
+                    .collect();
+                match note {
+                    Some(note) => writeln!(
+                        out,
+                        "{}: {} bytes{} ({})",
+                        display_path(&result.path),
+                        count(result.size),
+                        columns,
+                        note
+                    )?,
+                    None => writeln!(
+                        out,
+                        "{}: {} bytes{}",
+                        display_path(&result.path),
+                        count(result.size),
+                        columns
+                    )?,
+                }
+                if let Some(preview) = &result.preview {
+                    writeln!(out, "    {}", preview)?;
+                }
+                for found in &result.matches {
+                    writeln!(out, "    {}: {}", found.line, found.text)?;
+                }
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Print the rows of a grouped query in the requested format
+fn print_groups(rows: &[GroupRow], output: &Output, out: &mut dyn Write) -> io::Result<()> {
+    match output.format.as_str() {
+        format if is_structured(format) => print_structured(rows, "groups", output, out),
+        format if is_table_format(format) => {
+            let table = Table::from_groups(rows, &output.schema)
+                .in_zone(output.time_zone)
+                .in_locale(output.locale);
+            table.write(format, output.with_schema, out)
+        }
+        _ => {
+            writeln!(out, "{} groups found:", rows.len())?;
+            for row in rows {
+                let columns: Vec<String> = row
+                    .columns
+                    .iter()
+                    .map(|(name, value)| {
+                        let value = output.locale.format_value(value, output.time_zone);
+                        format!("{} = {}", name, value)
+                    })
+                    .collect();
+                writeln!(out, "{}", columns.join(", "))?;
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Print the directory trees of a ROLLUP BY path query in the requested format
+fn print_rollup(trees: &[RollupNode], output: &Output, out: &mut dyn Write) -> io::Result<()> {
+    match output.format.as_str() {
+        format if is_structured(format) => print_structured(trees, "directories", output, out),
+        format if is_table_format(format) => {
+            let table = Table::from_rollup(trees, &output.schema)
+                .in_zone(output.time_zone)
+                .in_locale(output.locale);
+            table.write(format, output.with_schema, out)
+        }
+        _ => {
+            for tree in trees {
+                print_rollup_node(tree, &display_path(&tree.path), 0, output, out)?;
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Print one directory of a rollup tree, then its subdirectories indented
+/// below it
+fn print_rollup_node(
+    node: &RollupNode,
+    name: &str,
+    depth: usize,
+    output: &Output,
+    out: &mut dyn Write,
+) -> io::Result<()> {
+    let columns: Vec<String> = node
+        .columns
+        .iter()
+        .map(|(name, value)| {
+            format!("{} = {}", name, output.locale.format_value(value, output.time_zone))
+        })
+        .collect();
+    writeln!(out, "{}{}: {}", "  ".repeat(depth), name, columns.join(", "))?;
+    for child in &node.children {
+        let name = child
+            .path
+            .file_name()
+            .map(|name| format!("{}/", name.to_string_lossy()))
+            .unwrap_or_else(|| display_path(&child.path));
+        print_rollup_node(child, &name, depth + 1, output, out)?;
+    }
+    Ok(())
+}
+
+/// Report the files and bytes under a directory, in total or per owner
+fn run_usage_mode(args: &UsageCommand) {
+    let aggregate = |name: &str, function, argument| AggregateColumn {
+        name: name.to_string(),
+        function,
+        argument,
+    };
+    let measure = match args.disk_usage {
+        true => FileAttribute::DiskUsage,
+        false => FileAttribute::Size,
+    };
+    let group_by = if args.by_owner {
+        vec![ComputedColumn {
+            name: "owner".to_string(),
+            expr: FileExpr::Attribute(FileAttribute::Owner),
+        }]
+    } else {
+        Vec::new()
+    };
+    let query = FileQuery::Select {
+        path: args.path.clone(),
+        recursive: true,
+        include_self: false,
+        entry_type: Some(EntryType::File),
+        join: None,
+        attributes: Vec::new(),
+        computed: Vec::new(),
+        aggregates: vec![
+            aggregate("files", AggregateFunction::Count, None),
+            aggregate("bytes", AggregateFunction::Sum, Some(FileExpr::Attribute(measure))),
+        ],
+        condition: None,
+        group_by,
+        rollup: false,
+        order_by: Vec::new(),
+        limit: None,
+        limit_size: None,
+        within: None,
+        sample: None,
+        sample_rows: None,
+    };
+
+    let mut rows = match execute_grouped_with_options(&query, &default_options()) {
+        Ok(rows) => rows,
+        Err(err) => exit_with_error(&err.into()),
+    };
+    let number = |row: &GroupRow, name| match row.get(name) {
+        Some(value) => value.as_f64().map_or(0, |n| n as u64),
+        None => 0,
+    };
+    rows.sort_by_key(|row| std::cmp::Reverse(number(row, "bytes")));
+
+    if is_structured(&args.format) || is_table_format(&args.format) {
+        write_output(None, |out| print_groups(&rows, &Output::new(&args.format, &query), out));
+        return;
+    }
+    let bytes = if args.disk_usage { "BYTES ON DISK" } else { "BYTES" };
+    println!("{:<20} {:>10} {:>16}", "OWNER", "FILES", bytes);
+    let locale = locale();
+    let count = |row: &GroupRow, name| {
+        locale.format_value(&FileValue::from_u64(number(row, name)), Tz::UTC)
+    };
+    for row in &rows {
+        let owner = row.get("owner").map_or("(all)".to_string(), to_text);
+        println!("{:<20} {:>10} {:>16}", owner, count(row, "files"), count(row, "bytes"));
+    }
+}
+
+/// List large files not read since a cutoff, to decide what to archive
+fn run_cold_mode(args: &ColdCommand) {
+    let cutoff = chrono::Duration::from_std(args.older_than)
+        .ok()
+        .and_then(|age| Utc::now().checked_sub_signed(age))
+        .unwrap_or(DateTime::<Utc>::MIN_UTC);
+    let compare = |attribute, operator, value| FileCondition::Compare {
+        attribute,
+        operator,
+        value,
+    };
+    let query = FileQuery::Select {
+        path: args.path.clone(),
+        recursive: true,
+        include_self: false,
+        entry_type: Some(EntryType::File),
+        join: None,
+        attributes: vec![FileAttribute::All],
+        computed: Vec::new(),
+        aggregates: Vec::new(),
+        condition: Some(FileCondition::And(
+            Box::new(compare(
+                FileAttribute::Accessed,
+                ComparisonOperator::Lt,
+                FileValue::DateTime(cutoff),
+            )),
+            Box::new(compare(
+                FileAttribute::Size,
+                ComparisonOperator::GtEq,
+                FileValue::from_u64(args.min_size),
+            )),
+        )),
+        group_by: Vec::new(),
+        rollup: false,
+        order_by: vec![OrderBy {
+            key: SortKey::Attribute(FileAttribute::Size),
+            descending: true,
+            natural: false,
+            collation: None,
+            nulls_first: None,
+        }],
+        limit: None,
+        limit_size: None,
+        within: None,
+        sample: None,
+        sample_rows: None,
+    };
+
+    let results = match execute_query(&query) {
+        Ok(results) => results,
+        Err(err) => exit_with_error(&err.into()),
+    };
+    if is_structured(&args.format) || is_table_format(&args.format) {
+        write_output(None, |out| {
+            print_results(&results, &Output::new(&args.format, &query), out)
+        });
+        return;
+    }
+    let locale = locale();
+    let count = |n: u64| locale.format_value(&FileValue::from_u64(n), Tz::UTC);
+    for file in &results {
+        println!(
+            "{}  {:>14}  {}",
+            locale.format_date(&file.accessed, Tz::UTC),
+            count(file.size),
+            file.path.display()
+        );
+    }
+    let total: u64 = results.iter().map(|file| file.size).sum();
+    println!(
+        "{} files, {} bytes not read since {}",
+        count(results.len() as u64),
+        count(total),
+        locale.format_date(&cutoff, Tz::UTC)
+    );
+}
+
+/// List the files modified recently, and with --follow keep printing them
+/// as they appear or change
+fn run_recent_mode(args: &RecentCommand) {
+    let since = chrono::Duration::from_std(args.since)
+        .ok()
+        .and_then(|age| Utc::now().checked_sub_signed(age))
+        .unwrap_or(DateTime::<Utc>::MIN_UTC);
+    let options = ExecutionOptions {
+        read_only: true,
+        root: args.root.clone(),
+        time_zone: args.tz.unwrap_or(Tz::UTC),
+        ..default_options()
+    };
+    let display = DisplayOptions {
+        time_zone: options.time_zone,
+        locale: locale(),
+        ..Default::default()
+    };
+    let print_line = |file: &FileResult, out: &mut dyn Write| match args.format.as_str() {
+        "json" | "ndjson" => {
+            let line = serde_json::to_string(file).map_err(io::Error::from)?;
+            writeln!(out, "{}", line)
+        }
+        _ => writeln!(out, "{}", file.display_with(&display)),
+    };
+
+    if !args.follow {
+        let query = recent_query(args.path.clone(), since, Some(args.limit));
+        let results = execute_query_with_options(&query, &options)
+            .unwrap_or_else(|err| exit_with_error(&err.into()));
+        if is_structured(&args.format) || is_table_format(&args.format) {
+            let output = Output {
+                time_zone: options.time_zone,
+                ..Output::new(&args.format, &query)
+            };
+            write_output(None, |out| print_results(&results, &output, out));
+            return;
+        }
+        write_output(None, |out| {
+            for file in &results {
+                print_line(file, out)?;
+            }
+            writeln!(
+                out,
+                "{} files modified since {}",
+                results.len(),
+                display.locale.format_datetime(&since, options.time_zone)
+            )
+        });
+        return;
+    }
+
+    if !matches!(args.format.as_str(), "text" | "json" | "ndjson") {
+        let err = ExecutorError::UnsupportedOperation(
+            "--follow prints text, or a line of JSON per file with --format json or ndjson"
+                .to_string(),
+        );
+        exit_with_error(&err.into());
+    }
+    let mut feed = Feed::new(args.path.clone(), since);
+    let mut files = feed.poll(&options).unwrap_or_else(|err| exit_with_error(&err.into()));
+    files.drain(..files.len().saturating_sub(args.limit));
+    loop {
+        write_output(None, |out| {
+            for file in &files {
+                print_line(file, out)?;
+            }
+            out.flush()
+        });
+        std::thread::sleep(args.interval);
+        // A directory that is briefly unreadable, or being replaced, is
+        // reported but doesn't stop the feed
+        files = feed.poll(&options).unwrap_or_else(|err| {
+            eprintln!("Error: {}", err);
+            Vec::new()
+        });
+    }
+}
+
+/// Let the user choose one of a query's results and print its path. Exits
+/// with 1 if there was nothing to choose from and 130 if they cancelled,
+/// so that shell functions can tell
+fn run_pick_mode(args: &PickCommand) {
+    let query = parse_query(&args.query).unwrap_or_else(|err| exit_with_error(&err.into()));
+    if !matches!(query, FileQuery::Select { .. }) || query.is_grouped() || query.is_rollup() {
+        let err = ExecutorError::UnsupportedOperation(
+            "picking needs a SELECT that lists files".to_string(),
+        );
+        exit_with_error(&err.into());
+    }
+    let options = ExecutionOptions {
+        read_only: true,
+        root: args.root.clone(),
+        ..default_options()
+    };
+    let results = execute_query_with_options(&query, &options)
+        .unwrap_or_else(|err| exit_with_error(&err.into()));
+    if results.is_empty() {
+        eprintln!("No files to pick from");
+        process::exit(1);
+    }
+    let chosen = match pick::pick(&results, &args.picker) {
+        Ok(Some(chosen)) => chosen,
+        Ok(None) => process::exit(130),
+        Err(err) => exit_with_error(&err.into()),
+    };
+    let chosen = match args.dir && !chosen.is_dir() {
+        true => match chosen.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+            _ => PathBuf::from("."),
+        },
+        false => chosen,
+    };
+    println!("{}", display_path(&chosen));
+}
+
+/// Add or remove tags, or list them
+fn run_tag_mode(args: &TagCommand) {
+    let Some(store_path) = TagStore::default_path() else {
+        let err = io::Error::other("there is no configuration directory to keep tags in");
+        exit_with_error(&err.into());
+    };
+    let mut store = TagStore::load(&store_path).unwrap_or_else(|err| exit_with_error(&err.into()));
+    let (add, command) = match &args.action {
+        TagAction::Add(command) => (true, command),
+        TagAction::Remove(command) => (false, command),
+        TagAction::List(list) => {
+            match &list.tag {
+                Some(tag) => {
+                    for file in store.files(tag) {
+                        println!("{}", display_path(&file.path));
                     }
-                },
-                Err(err) => {
-                    eprintln!("Error executing query: {}", err);
-                    process::exit(1);
                 }
+                None => {
+                    for (tag, count) in store.tags() {
+                        println!("{:<24} {} files", tag, count);
+                    }
+                }
+            }
+            return;
+        }
+    };
+
+    if command.tag.trim().is_empty() {
+        exit_with_error(&io::Error::other("a tag needs a name").into());
+    }
+    let query = parse_query(&command.query).unwrap_or_else(|err| exit_with_error(&err.into()));
+    if !matches!(query, FileQuery::Select { .. }) || query.is_grouped() || query.is_rollup() {
+        let err = ExecutorError::UnsupportedOperation(
+            "tags need a SELECT that lists files".to_string(),
+        );
+        exit_with_error(&err.into());
+    }
+    let options = ExecutionOptions {
+        read_only: true,
+        root: command.root.clone(),
+        ..default_options()
+    };
+    let results = execute_query_with_options(&query, &options)
+        .unwrap_or_else(|err| exit_with_error(&err.into()));
+    let mut changed = 0;
+    for result in &results {
+        let done = if add {
+            store.add(&command.tag, &result.path)
+        } else {
+            Ok(store.remove(&command.tag, &result.path))
+        };
+        match done {
+            Ok(true) => changed += 1,
+            Ok(false) => {}
+            Err(err) => eprintln!("Error: can't tag {}: {}", result.path.display(), err),
+        }
+    }
+    if let Err(err) = store.save(&store_path) {
+        exit_with_error(&err.into());
+    }
+    let unchanged = results.len() - changed;
+    if add {
+        println!("{} files tagged '{}', {} already were", changed, command.tag, unchanged);
+    } else {
+        println!("{} files untagged '{}', {} weren't tagged", changed, command.tag, unchanged);
+    }
+}
+
+/// Serve a query's results as a folder until it is unmounted
+#[cfg(feature = "fuse")]
+fn run_mount_mode(args: &MountCommand) {
+    use fmql_core::sql::mount::{mount, MountOptions};
+
+    let query = parse_query(&args.query).unwrap_or_else(|err| exit_with_error(&err.into()));
+    // The folder is served until it is unmounted, so warn as refreshes go
+    // rather than after
+    let events = Events::new(|event| {
+        if let Event::Warning { path, message } = event {
+            report_warning(&LintWarning {
+                code: "mount_warning",
+                message: format!("{}: {}", path.display(), message),
+            });
+        }
+    });
+    let options = ExecutionOptions {
+        read_only: true,
+        root: args.root.clone(),
+        events,
+        ..default_options()
+    };
+    let mount_options = MountOptions {
+        passthrough: args.passthrough,
+        refresh: args.refresh,
+    };
+    if let Err(err) = mount(&query, &options, &args.mountpoint, &mount_options) {
+        exit_with_error(&err.into());
+    }
+}
+
+/// Run a query and, if its results cross the threshold, report it and run
+/// the alert command. Exits with 1 when the alert fires, so scripts can
+/// use it as a check without a command
+fn run_alert_mode(args: &AlertCommand) {
+    let query = match parse_query(&args.query) {
+        Ok(query) => query,
+        Err(err) => exit_with_error(&err.into()),
+    };
+    if query.is_rollup() {
+        let err = ExecutorError::UnsupportedOperation(
+            "alerts need a query that lists files or groups, not ROLLUP BY path".to_string(),
+        );
+        exit_with_error(&err.into());
+    }
+    let options = ExecutionOptions {
+        read_only: true,
+        root: args.root.clone(),
+        limits: ResourceLimits {
+            timeout: args.timeout,
+            ..ResourceLimits::default()
+        },
+        ..default_options()
+    };
+    // The rows the threshold is checked against, how many files or groups
+    // there were, and the results for the alert command
+    let (rows, count, json) = if query.is_grouped() {
+        execute_grouped_with_options(&query, &options).map(|rows| {
+            let json = serde_json::to_string(&rows);
+            let count = rows.len();
+            (rows, count, json)
+        })
+    } else {
+        execute_query_with_options(&query, &options).map(|results| {
+            let json = serde_json::to_string(&results);
+            (vec![summarise(&results)], results.len(), json)
+        })
+    }
+    .unwrap_or_else(|err| exit_with_error(&err.into()));
+    let fired = match args.when.holds(&rows) {
+        Ok(fired) => fired,
+        Err(err) => exit_with_error(&err.into()),
+    };
+    if !fired {
+        return;
+    }
+
+    let values: Vec<String> = rows
+        .iter()
+        .filter_map(|row| row.get(&args.when.column))
+        .filter(|value| args.when.crossed_by(value))
+        .map(to_text)
+        .collect();
+    if values.is_empty() {
+        println!("ALERT: {} ({} rows)", args.when, rows.len());
+    } else {
+        println!("ALERT: {} ({} = {})", args.when, args.when.column, values.join(", "));
+    }
+    if let Some(command) = &args.exec {
+        let json = json.unwrap_or_else(|err| {
+            exit_with_error(&io::Error::other(format!("can't serialize results: {}", err)).into())
+        });
+        if let Err(err) = run_alert_command(command, args, count, &json) {
+            eprintln!("Error: alert command failed: {}", err);
+        }
+    }
+    process::exit(1);
+}
+
+/// Run the alert command through the shell, with the results on stdin
+fn run_alert_command(command: &str, args: &AlertCommand, count: usize, json: &str) -> io::Result<()> {
+    use std::process::{Command, Stdio};
+
+    let mut shell = if cfg!(windows) {
+        let mut shell = Command::new("cmd");
+        shell.arg("/C");
+        shell
+    } else {
+        let mut shell = Command::new("sh");
+        shell.arg("-c");
+        shell
+    };
+    let mut child = shell
+        .arg(command)
+        .env("FMQL_ALERT_QUERY", &args.query)
+        .env("FMQL_ALERT_WHEN", args.when.to_string())
+        .env("FMQL_ALERT_COUNT", count.to_string())
+        .stdin(Stdio::piped())
+        .spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        // A command that doesn't read its input is fine
+        match stdin.write_all(json.as_bytes()) {
+            Err(err) if err.kind() != io::ErrorKind::BrokenPipe => return Err(err),
+            _ => {}
+        }
+    }
+    let status = child.wait()?;
+    if !status.success() {
+        return Err(io::Error::other(format!("exited with {}", status)));
+    }
+    Ok(())
+}
+
+/// Apply a retention policy, or with --dry-run list what it would do
+fn run_retention_apply(args: &RetentionApplyCommand) {
+    use fmql_core::sql::retention::{apply, plan, Action, Policy};
+
+    let mut policy = match Policy::load(&args.policy) {
+        Ok(policy) => policy,
+        Err(err) => exit_with_error(&err.into()),
+    };
+    if args.audit_log.is_some() {
+        policy.audit_log = args.audit_log.clone();
+    }
+    let options = ExecutionOptions {
+        root: args.root.clone(),
+        ..default_options()
+    };
+    let outcomes = match if args.dry_run { plan(&policy, &options) } else { apply(&policy, &options) } {
+        Ok(outcomes) => outcomes,
+        Err(err) => exit_with_error(&err.into()),
+    };
+
+    if is_structured(&args.format) {
+        let output = Output {
+            format: args.format.clone(),
+            compact: false,
+            schema: Vec::new(),
+            with_schema: false,
+            time_zone: Tz::UTC,
+            locale: locale(),
+            envelope: None,
+        };
+        write_output(None, |out| print_structured(&outcomes, "actions", &output, out));
+    } else {
+        for outcome in &outcomes {
+            let done = match outcome.action {
+                Action::Delete => "deleted",
+                Action::Archive => "archived",
+                Action::Compress => "compressed",
+            };
+            let target = outcome
+                .target
+                .as_ref()
+                .map_or(String::new(), |target| format!(" -> {}", target.display()));
+            let verb = match outcome.status {
+                None => format!("would {}", outcome.action),
+                Some(OperationStatus::Ok) => done.to_string(),
+                Some(OperationStatus::Skipped) => "skipped".to_string(),
+                Some(OperationStatus::Failed) => "failed".to_string(),
+            };
+            match &outcome.message {
+                Some(message) => {
+                    println!("[{}] {} {}: {}", outcome.rule, verb, outcome.path.display(), message);
+                }
+                None => println!(
+                    "[{}] {} {}{} ({} bytes)",
+                    outcome.rule,
+                    verb,
+                    outcome.path.display(),
+                    target,
+                    outcome.size
+                ),
+            }
+            if let Some(matched) = &outcome.matched {
+                println!("    matched: {}", matched);
+            }
+        }
+        let bytes: u64 = outcomes.iter().map(|outcome| outcome.size).sum();
+        let verb = if args.dry_run { "would be handled" } else { "handled" };
+        println!("{} files, {} bytes {}", outcomes.len(), bytes, verb);
+    }
+
+    let failed = outcomes
+        .iter()
+        .filter(|outcome| outcome.status == Some(OperationStatus::Failed))
+        .count();
+    if failed > 0 {
+        eprintln!("{} of {} files could not be handled", failed, outcomes.len());
+        process::exit(1);
+    }
+}
+
+/// Combine the shards of a query into its results, printed as the sql
+/// command would print them
+fn run_merge_mode(args: &MergeCommand) {
+    let parts = args
+        .parts
+        .iter()
+        .map(|path| {
+            let file = std::fs::File::open(path)?;
+            serde_json::from_reader(io::BufReader::new(file)).map_err(|err| {
+                ExecutorError::InvalidShards(format!("{}: {}", path.display(), err)).into()
+            })
+        })
+        .collect::<Result<Vec<ShardResult>, FMQLError>>()
+        .unwrap_or_else(|err| exit_with_error(&err));
+    let options = ExecutionOptions {
+        root: args.root.clone(),
+        time_zone: args.tz.unwrap_or(Tz::UTC),
+        ..default_options()
+    };
+    let output = Output {
+        format: args.format.clone(),
+        compact: args.compact,
+        schema: schema(&parts[0].query),
+        with_schema: false,
+        time_zone: options.time_zone,
+        locale: locale(),
+        envelope: None,
+    };
+
+    if parts[0].query.is_grouped() {
+        match merge_groups(&parts) {
+            Ok(rows) => write_output(args.out.as_deref(), |out| print_groups(&rows, &output, out)),
+            Err(err) => exit_with_error(&err.into()),
+        }
+    } else {
+        match merge_files(&parts, &options) {
+            Ok(results) => write_output(args.out.as_deref(), |out| {
+                print_results(&results, &output, out)
+            }),
+            Err(err) => exit_with_error(&err.into()),
+        }
+    }
+}
+
+/// Compare two saved result sets, and exit 1 if they differ
+fn run_diff_results_mode(args: &DiffResultsCommand) {
+    let diff = read_rows(&args.old)
+        .and_then(|old| diff_rows(&old, &read_rows(&args.new)?, &args.ignore))
+        .unwrap_or_else(|err| exit_with_error(&err.into()));
+    let color = args.out.is_none() && use_color();
+    write_output(args.out.as_deref(), |out| {
+        print_diff(&diff, &args.format, args.compact, color, out)
+    });
+    if !diff.is_empty() {
+        process::exit(1);
+    }
+}
+
+/// Ask what to look for and do, print the query that does it, and run it
+/// if the user wants
+fn run_wizard_mode(args: &WizardCommand) {
+    let answers = match wizard::ask() {
+        Ok(Some(answers)) => answers,
+        Ok(None) => process::exit(130),
+        Err(err) => exit_with_error(&err.into()),
+    };
+    let sql = answers.sql();
+    // Built from answers that were each checked, so this is a bug if it fails
+    if let Err(err) = parse_sql(&sql) {
+        exit_with_error(&err.into());
+    }
+    println!("{}", sql);
+    let run = match settings().answer(!answers.action.changes_files()) {
+        Some(answer) => Ok(answer),
+        None => wizard::confirm_run(&answers.action),
+    };
+    if run.unwrap_or_else(|err| exit_with_error(&err.into())) {
+        let command = ["fmql sql", "--format", &args.format, &sql];
+        match SqlCommand::try_parse_from(command) {
+            Ok(sql_args) => run_sql_mode(&sql_args),
+            Err(err) => err.exit(),
+        }
+    }
+}
+
+/// Measure a directory's file system and print what was found, with
+/// suggestions
+fn run_bench_mode(args: &BenchCommand) {
+    let defaults = BenchOptions::default();
+    let options = BenchOptions {
+        max_entries: args.max_entries,
+        max_threads: args.max_threads.unwrap_or(defaults.max_threads),
+        conditions: args.conditions.clone(),
+        execution: default_options(),
+    };
+    let report = bench::run(&args.path, &options).unwrap_or_else(|err| exit_with_error(&err));
+    if args.format == "json" {
+        let json = if args.compact {
+            serde_json::to_string(&report)
+        } else {
+            serde_json::to_string_pretty(&report)
+        };
+        write_output(None, |out| writeln!(out, "{}", json.map_err(io::Error::from)?));
+        return;
+    }
+    let duration = |us: f64| match us {
+        us if us >= 1000.0 => format!("{:.1}ms", us / 1000.0),
+        us => format!("{:.0}µs", us),
+    };
+    write_output(None, |out| {
+        let taken = if report.truncated { " (the first ones)" } else { "" };
+        writeln!(out, "{} entries under {}{}", report.entries, report.path.display(), taken)?;
+        writeln!(out, "Listing:   {:.0} entries/s", report.entries_per_second)?;
+        let latency = &report.metadata;
+        writeln!(
+            out,
+            "Metadata:  {} median, {} p90, {} p99, {} max",
+            duration(latency.p50_us),
+            duration(latency.p90_us),
+            duration(latency.p99_us),
+            duration(latency.max_us)
+        )?;
+        let threads: Vec<String> = report
+            .threads
+            .iter()
+            .map(|run| format!("{}: {:.0}/s", run.threads, run.entries_per_second))
+            .collect();
+        writeln!(out, "Threads:   {}", threads.join(", "))?;
+        writeln!(out, "Conditions (over a query with none):")?;
+        for filter in &report.filters {
+            let condition = if filter.condition.is_empty() { "(none)" } else { &filter.condition };
+            let added = if filter.condition.is_empty() {
+                String::new()
+            } else {
+                format!(" (+{:.1}µs an entry)", filter.us_per_entry)
+            };
+            writeln!(
+                out,
+                "  {:<30} {:.3}s{}, {} matched",
+                condition, filter.seconds, added, filter.matched
+            )?;
+        }
+        if !report.suggestions.is_empty() {
+            writeln!(out, "Suggestions:")?;
+        }
+        for suggestion in &report.suggestions {
+            writeln!(out, "  - {}", suggestion)?;
+        }
+        Ok(())
+    });
+}
+
+fn run_translate_mode(args: &TranslateCommand) {
+    let translation =
+        translate_find(&args.from_find).unwrap_or_else(|err| exit_with_error(&err.into()));
+    let sql = translation.sql();
+    // Written from a query that was built already, so this is a bug if it fails
+    if let Err(err) = parse_sql(&sql) {
+        exit_with_error(&err.into());
+    }
+    for note in translation.notes() {
+        eprintln!("note: {}", note);
+    }
+    println!("{}", sql);
+}
+
+/// Whether to color what is written to stdout: only for a terminal, and
+/// not if NO_COLOR or FMQL_NO_COLOR is set or in CI
+fn use_color() -> bool {
+    io::stdout().is_terminal() && settings().color()
+}
+
+/// Print how two result sets differ: in text, a line per row added (+),
+/// removed (-) or changed (~), and under each changed row the attributes
+/// that changed, highlighted if `color`
+fn print_diff(
+    diff: &RowDiff,
+    format: &str,
+    compact: bool,
+    color: bool,
+    out: &mut dyn Write,
+) -> io::Result<()> {
+    let text = match format {
+        "json" if compact => serde_json::to_string(diff).map_err(|e| e.to_string()),
+        "json" => serde_json::to_string_pretty(diff).map_err(|e| e.to_string()),
+        "yaml" => serde_yaml::to_string(diff).map_err(|e| e.to_string()),
+        _ => {
+            let paint = |code: &str, text: String| match color {
+                true => format!("\x1b[{}m{}\x1b[0m", code, text),
+                false => text,
+            };
+            // Strings without their quotes, anything else as JSON
+            let show = |value: &serde_json::Value| match value {
+                serde_json::Value::String(text) => text.clone(),
+                value => value.to_string(),
+            };
+            let path = |row: &serde_json::Map<String, serde_json::Value>| show(&row["path"]);
+            for row in &diff.added {
+                writeln!(out, "{}", paint("32", format!("+ {}", path(row))))?;
+            }
+            for row in &diff.removed {
+                writeln!(out, "{}", paint("31", format!("- {}", path(row))))?;
+            }
+            for row in &diff.changed {
+                writeln!(out, "{}", paint("33", format!("~ {}", row.path)))?;
+                for change in &row.changes {
+                    let attribute = paint("1", change.attribute.clone());
+                    let (old, new) = (show(&change.old), show(&change.new));
+                    writeln!(out, "    {}: {} -> {}", attribute, old, new)?;
+                }
+            }
+            return writeln!(
+                out,
+                "{} added, {} removed, {} changed",
+                diff.added.len(),
+                diff.removed.len(),
+                diff.changed.len()
+            );
+        }
+    };
+    let text =
+        text.map_err(|e| io::Error::other(format!("can't serialize the differences: {}", e)))?;
+    writeln!(out, "{}", text.trim_end())
+}
+
+/// Run SQLite SQL over the file scan, with any CSV files as tables
+#[cfg(feature = "sqlite")]
+fn run_sqlite_mode(args: &SqliteCommand) {
+    use fmql_core::sql::catalog::ValueType;
+    use fmql_core::sql::sqlite::{connect, select};
+
+    let fail = |err: &dyn std::fmt::Display| -> ! { fail("sqlite.error", &err.to_string()) };
+    let options = ExecutionOptions {
+        root: args.root.clone(),
+        limits: ResourceLimits {
+            max_files_scanned: args.max_files,
+            timeout: args.timeout,
+            ..ResourceLimits::default()
+        },
+        ..default_options()
+    };
+    let conn = connect(&options).unwrap_or_else(|err| fail(&err));
+    for table in &args.csv {
+        let Some((name, file)) = table.split_once('=') else {
+            fail(&format!("--csv expects NAME=FILE, got '{}'", table));
+        };
+        // The csv module can't unquote a file name holding a quote or an =
+        if file.contains(['\'', '=']) {
+            fail(&format!("--csv can't read '{}': rename it without quotes or '='", file));
+        }
+        let sql = format!(
+            "CREATE VIRTUAL TABLE temp.\"{}\" USING csv(filename='{}', header=yes)",
+            name.replace('"', "\"\""),
+            file
+        );
+        conn.execute_batch(&sql).unwrap_or_else(|err| fail(&err));
+    }
+
+    let names: Vec<String> = match conn.prepare(&args.sql) {
+        Ok(statement) => statement.column_names().into_iter().map(str::to_string).collect(),
+        Err(err) => fail(&err),
+    };
+    let rows = select(&conn, &args.sql).unwrap_or_else(|err| fail(&err));
+    // SQLite columns have no declared type, so go by the first value
+    let schema = names
+        .into_iter()
+        .map(|name| {
+            let first = rows.iter().filter_map(|row| row.get(&name)).find(|v| **v != FileValue::Null);
+            let value_type = match first {
+                Some(FileValue::Integer(_) | FileValue::Number(_)) => ValueType::Number,
+                _ => ValueType::String,
+            };
+            SchemaColumn { name, value_type }
+        })
+        .collect();
+    let output = Output {
+        format: args.format.clone(),
+        compact: args.compact,
+        schema,
+        with_schema: args.schema,
+        time_zone: Tz::UTC,
+        locale: locale(),
+        envelope: None,
+    };
+    write_output(args.out.as_deref(), |out| {
+        if is_structured(&output.format) {
+            return print_structured(&rows, "rows", &output, out);
+        }
+        if is_table_format(&output.format) {
+            return print_groups(&rows, &output, out);
+        }
+        writeln!(out, "{} rows:", rows.len())?;
+        for row in &rows {
+            let columns: Vec<String> = row
+                .columns
+                .iter()
+                .map(|(name, value)| {
+                    format!("{} = {}", name, locale().format_value(value, Tz::UTC))
+                })
+                .collect();
+            writeln!(out, "{}", columns.join(", "))?;
+        }
+        Ok(())
+    });
+}
+
+/// Print lint warnings for a query to stderr
+fn print_warnings(query: &FileQuery) {
+    for warning in lint(query) {
+        report_warning(&warning);
+    }
+}
+
+/// Run the statements of a script in order, stopping at the first that fails
+fn run_apply_mode(args: &ApplyCommand) {
+    let script = match args.script.as_path() {
+        path if path == Path::new("-") => io::read_to_string(io::stdin()),
+        path => std::fs::read_to_string(path),
+    };
+    let script = script.unwrap_or_else(|err| exit_with_error(&err.into()));
+    let statements = split_statements(&script);
+    if statements.is_empty() {
+        fail("parse.empty_script", &format!("{} has no statements", display_path(&args.script)));
+    }
+
+    // Every statement is parsed before any of them runs
+    let mut queries = Vec::with_capacity(statements.len());
+    for (index, statement) in statements.iter().enumerate() {
+        let query = match parse_query(&statement.text) {
+            Ok(query) if query.reads_stdin() => Err(FMQLError::from(
+                ExecutorError::UnsupportedOperation("a script can't read FROM STDIN".to_string()),
+            )),
+            parsed => parsed.map_err(FMQLError::from),
+        };
+        match query {
+            Ok(query) => queries.push(query),
+            Err(err) => {
+                if !JSON_ERRORS.load(Ordering::Relaxed) {
+                    eprintln!("In statement {} (line {}):", index + 1, statement.line);
+                }
+                exit_with_error(&err);
             }
+        }
+    }
+    for query in &queries {
+        print_warnings(query);
+    }
+
+    let time_zone = args.tz.unwrap_or(Tz::UTC);
+    let options = ExecutionOptions {
+        jobs: args.jobs.max(1),
+        throttle: args.throttle,
+        retry: RetryPolicy {
+            retries: args.io_retries,
+            ..RetryPolicy::default()
         },
-        Err(err) => {
-            eprintln!("Error parsing SQL query: {}", err);
+        root: args.root.clone(),
+        time_zone,
+        stable_order: true,
+        ..default_options()
+    };
+    if args.validate_first {
+        let problems = script::validate(&queries, &options);
+        if !problems.is_empty() {
+            for problem in &problems {
+                if JSON_ERRORS.load(Ordering::Relaxed) {
+                    print_diagnostic(&Diagnostic::from(problem));
+                } else {
+                    eprintln!("Error: {}", problem);
+                }
+            }
+            let noun = if problems.len() == 1 { "problem" } else { "problems" };
+            eprintln!("{} {} found; nothing was changed", problems.len(), noun);
             process::exit(1);
         }
     }
-} 
\ No newline at end of file
+
+    for (index, (statement, query)) in statements.iter().zip(&queries).enumerate() {
+        let output = Output {
+            time_zone,
+            ..Output::new(&args.format, query)
+        };
+        if !is_structured(&args.format) && args.format != "csv" {
+            let text = statement.text.split_whitespace().collect::<Vec<_>>().join(" ");
+            println!("-- {}: {}", index + 1, text);
+        }
+        let outcome = if query.is_rollup() {
+            execute_rollup_with_options(query, &options).map(|trees| {
+                write_output(None, |out| print_rollup(&trees, &output, out));
+                0
+            })
+        } else if query.is_grouped() {
+            execute_grouped_with_options(query, &options).map(|rows| {
+                write_output(None, |out| print_groups(&rows, &output, out));
+                0
+            })
+        } else {
+            execute_query_with_options(query, &options).map(|results| {
+                write_output(None, |out| print_results(&results, &output, out));
+                results
+                    .iter()
+                    .filter(|r| r.status == Some(OperationStatus::Failed))
+                    .count()
+            })
+        };
+        let stopped = match outcome {
+            Ok(0) => continue,
+            Ok(failed) => {
+                eprintln!("{} files could not be changed by statement {}", failed, index + 1);
+                1
+            }
+            Err(err) => {
+                let err = FMQLError::from(err);
+                report_error(&err);
+                err.exit_code()
+            }
+        };
+        if index + 1 < statements.len() {
+            eprintln!(
+                "Stopped after statement {} of {}; the statements after it were not run",
+                index + 1,
+                statements.len()
+            );
+        }
+        process::exit(stopped);
+    }
+}
+
+/// Validate a query without touching the file system
+fn run_check_mode(args: &CheckCommand) {
+    let valid = parse_query(&args.query).map_err(FMQLError::from);
+
+    match args.format.as_str() {
+        "json" => {
+            let sql = expand_query(&args.query).unwrap_or_else(|_| args.query.clone());
+            println!("{}", parse_to_ast_json(&sql))
+        }
+        _ => {
+            match &valid {
+                Err(FMQLError::Parse(err)) if !JSON_ERRORS.load(Ordering::Relaxed) => {
+                    eprintln!("Error parsing SQL query: {}", err)
+                }
+                Err(err) => report_error(err),
+                Ok(query) => {
+                    println!("Query is valid");
+                    print_warnings(query);
+                }
+            }
+        }
+    }
+
+    if let Err(err) = valid {
+        process::exit(err.exit_code());
+    }
+}
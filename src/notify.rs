@@ -0,0 +1,193 @@
+//! Sending a summary of a query's results somewhere, for `--notify`.
+//!
+//! A scheduled query can report what it found without a wrapper script.
+//! `webhook:URL` POSTs the summary as JSON to the URL with `curl`, and
+//! `email:ADDRESS` mails it as plain text through the local `sendmail`,
+//! which mail servers and relays such as Postfix and msmtp install. The
+//! summary is the query, where and when it ran, how many rows it gave, the
+//! total size of the files it found, and its first few rows.
+
+use std::fmt;
+use std::io::{self, Write};
+use std::process::{Command, Stdio};
+use std::str::FromStr;
+
+use chrono::{SecondsFormat, Utc};
+use serde::Serialize;
+
+use fmql_core::sql::executor::{FileResult, GroupRow};
+use fmql_core::sql::functions::to_text;
+use fmql_core::sql::paths::display_path;
+
+/// Where to send a summary.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Sink {
+    /// POST it as JSON to an http or https URL.
+    Webhook(String),
+    /// Mail it to an address.
+    Email(String),
+}
+
+impl FromStr for Sink {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Sink, String> {
+        match s.split_once(':') {
+            Some(("webhook", url)) if url.starts_with("https://") || url.starts_with("http://") => {
+                Ok(Sink::Webhook(url.to_string()))
+            }
+            Some(("webhook", url)) => Err(format!("{} is not an http or https URL", url)),
+            // Anything else would let the address add headers to the mail
+            Some(("email", address))
+                if address.contains('@') && !address.contains(|c: char| c.is_whitespace()) =>
+            {
+                Ok(Sink::Email(address.to_string()))
+            }
+            Some(("email", address)) => Err(format!("{} is not an email address", address)),
+            _ => Err(format!("{} is not webhook:URL or email:ADDRESS", s)),
+        }
+    }
+}
+
+impl fmt::Display for Sink {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Sink::Webhook(url) => write!(f, "webhook:{}", url),
+            Sink::Email(address) => write!(f, "email:{}", address),
+        }
+    }
+}
+
+/// What a query found, as it is sent.
+#[derive(Debug, Serialize)]
+pub struct Summary {
+    query: String,
+    host: String,
+    finished: String,
+    count: usize,
+    /// The total size of the files, for a query that returns files
+    #[serde(skip_serializing_if = "Option::is_none")]
+    bytes: Option<u64>,
+    rows: Vec<serde_json::Value>,
+    /// The same rows, one line each, for an email
+    #[serde(skip)]
+    lines: Vec<String>,
+}
+
+impl Summary {
+    /// Summarises the files a query returned, with the first `top` of them.
+    pub fn of_files(query: &str, results: &[FileResult], top: usize) -> Summary {
+        let shown = &results[..top.min(results.len())];
+        Summary {
+            bytes: Some(results.iter().map(|file| file.size).sum()),
+            rows: shown.iter().map(json).collect(),
+            lines: shown
+                .iter()
+                .map(|file| format!("{} ({} bytes)", display_path(&file.path), file.size))
+                .collect(),
+            ..Summary::new(query, results.len())
+        }
+    }
+
+    /// Summarises the rows a grouped query returned, with the first `top`.
+    pub fn of_groups(query: &str, rows: &[GroupRow], top: usize) -> Summary {
+        let shown = &rows[..top.min(rows.len())];
+        let line = |row: &GroupRow| {
+            let columns: Vec<String> = row
+                .columns
+                .iter()
+                .map(|(name, value)| format!("{} = {}", name, to_text(value)))
+                .collect();
+            columns.join(", ")
+        };
+        Summary {
+            rows: shown.iter().map(json).collect(),
+            lines: shown.iter().map(line).collect(),
+            ..Summary::new(query, rows.len())
+        }
+    }
+
+    fn new(query: &str, count: usize) -> Summary {
+        Summary {
+            query: query.to_string(),
+            host: gethostname::gethostname().to_string_lossy().into_owned(),
+            finished: Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true),
+            count,
+            bytes: None,
+            rows: Vec::new(),
+            lines: Vec::new(),
+        }
+    }
+
+    /// The summary as the text of an email.
+    fn text(&self) -> String {
+        let mut text = format!("{}\n\nRan on {} at {}.\n", self.query, self.host, self.finished);
+        match self.bytes {
+            Some(bytes) => text += &format!("{} rows, {} bytes in all.\n", self.count, bytes),
+            None => text += &format!("{} rows.\n", self.count),
+        }
+        if !self.lines.is_empty() {
+            text += "\n";
+            for line in &self.lines {
+                text += &format!("{}\n", line);
+            }
+            if self.lines.len() < self.count {
+                text += &format!("... and {} more\n", self.count - self.lines.len());
+            }
+        }
+        text
+    }
+}
+
+fn json(row: &impl Serialize) -> serde_json::Value {
+    serde_json::to_value(row).unwrap_or(serde_json::Value::Null)
+}
+
+/// Sends `summary` to `sink`, returning why it couldn't be sent.
+pub fn send(sink: &Sink, summary: &Summary) -> Result<(), String> {
+    match sink {
+        Sink::Webhook(url) => {
+            let body = serde_json::to_vec(summary).map_err(|err| err.to_string())?;
+            let mut curl = Command::new("curl");
+            curl.args(["--silent", "--show-error", "--fail", "--max-time", "30"])
+                .args(["-X", "POST", "-H", "Content-Type: application/json"])
+                .args(["--data-binary", "@-", url.as_str()]);
+            pipe(curl, "curl", &body)
+        }
+        Sink::Email(address) => {
+            let subject = format!("fmql on {}: {} rows", summary.host, summary.count);
+            let mail = format!(
+                "To: {}\nSubject: {}\nContent-Type: text/plain; charset=utf-8\n\n{}",
+                address,
+                subject,
+                summary.text()
+            );
+            let mut sendmail = Command::new("sendmail");
+            sendmail.args(["-t", "-i"]);
+            pipe(sendmail, "sendmail", mail.as_bytes())
+        }
+    }
+}
+
+/// Runs `command` with `input` on its stdin, failing with what it said on
+/// stderr if it fails.
+fn pipe(mut command: Command, name: &str, input: &[u8]) -> Result<(), String> {
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|err| match err.kind() {
+            io::ErrorKind::NotFound => format!("{} is not installed", name),
+            _ => format!("can't run {}: {}", name, err),
+        })?;
+    let written = child.stdin.take().expect("piped").write_all(input);
+    let output = child
+        .wait_with_output()
+        .map_err(|err| format!("can't run {}: {}", name, err))?;
+    if !output.status.success() {
+        let said = String::from_utf8_lossy(&output.stderr);
+        return Err(format!("{} failed: {}", name, said.trim()));
+    }
+    written.map_err(|err| format!("can't write to {}: {}", name, err))
+}
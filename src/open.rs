@@ -0,0 +1,165 @@
+//! Handing query results to the desktop, for `--open`, `--reveal` and
+//! `--copy-paths` and the REPL's `OPEN` and `REVEAL`.
+//!
+//! Opening hands a file to whatever the system opens it with by default:
+//! `open` on macOS, `start` on Windows and `xdg-open` elsewhere. Revealing
+//! shows it selected in the system file manager: Finder, Explorer, or on
+//! Linux the file manager listening on the freedesktop `FileManager1` bus
+//! interface, falling back to opening the directory it is in. Copying puts
+//! the paths on the clipboard, one per line, which needs fmql to be built
+//! with the `clipboard` feature.
+
+use std::io;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use fmql_core::sql::executor::FileResult;
+
+/// The most results one command will open. Opening a window per file is
+/// fine for a handful and a mess for a thousand, so more than this asks for
+/// a LIMIT instead.
+pub const MAX_OPEN: usize = 20;
+
+/// What to do with each result.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    /// Open it with the default application.
+    Open,
+    /// Show it in the file manager.
+    Reveal,
+}
+
+impl Action {
+    /// The action as a verb, for messages.
+    pub fn verb(self) -> &'static str {
+        match self {
+            Action::Open => "open",
+            Action::Reveal => "reveal",
+        }
+    }
+
+    /// Opens or reveals each of `results`, reporting those that fail and
+    /// carrying on with the rest. Returns how many failed, or an error
+    /// without touching any if there are more than [`MAX_OPEN`].
+    pub fn apply(self, results: &[FileResult]) -> Result<usize, String> {
+        if results.len() > MAX_OPEN {
+            return Err(format!(
+                "won't {} {} results at once; add a LIMIT of at most {}",
+                self.verb(),
+                results.len(),
+                MAX_OPEN
+            ));
+        }
+        let mut failed = 0;
+        for result in results {
+            let done = match self {
+                Action::Open => open(&result.path),
+                Action::Reveal => reveal(&result.path),
+            };
+            if let Err(err) = done {
+                eprintln!("Error: can't {} {}: {}", self.verb(), result.path.display(), err);
+                failed += 1;
+            }
+        }
+        Ok(failed)
+    }
+}
+
+/// Opens `path` with the system's default application for it.
+pub fn open(path: &Path) -> io::Result<()> {
+    if cfg!(target_os = "macos") {
+        run(Command::new("open").arg(path))
+    } else if cfg!(windows) {
+        // start treats its first quoted argument as a window title
+        run(Command::new("cmd").args(["/C", "start", ""]).arg(path))
+    } else {
+        run(Command::new("xdg-open").arg(path))
+    }
+}
+
+/// Shows `path` selected in the system file manager.
+pub fn reveal(path: &Path) -> io::Result<()> {
+    if cfg!(target_os = "macos") {
+        return run(Command::new("open").arg("-R").arg(path));
+    }
+    if cfg!(windows) {
+        let mut select = std::ffi::OsString::from("/select,");
+        select.push(path);
+        // Explorer exits with 1 even when it worked
+        return Command::new("explorer").arg(select).status().map(drop);
+    }
+    let path = std::path::absolute(path)?;
+    let shown = run(Command::new("dbus-send")
+        .args([
+            "--session",
+            "--print-reply",
+            "--dest=org.freedesktop.FileManager1",
+            "--type=method_call",
+            "/org/freedesktop/FileManager1",
+            "org.freedesktop.FileManager1.ShowItems",
+        ])
+        .arg(format!("array:string:{}", file_uri(&path)))
+        .arg("string:"));
+    match (shown, path.parent()) {
+        (Ok(()), _) => Ok(()),
+        (Err(_), Some(dir)) => open(dir),
+        (Err(err), None) => Err(err),
+    }
+}
+
+/// Puts `paths` on the system clipboard, one per line.
+///
+/// On Linux the clipboard belongs to the program that set it, so once fmql
+/// exits the paths stay pasteable only if a clipboard manager has taken
+/// them over, as most desktops' do.
+#[cfg(feature = "clipboard")]
+pub fn copy_paths(paths: &[&Path]) -> Result<(), String> {
+    let text: Vec<String> = paths.iter().map(|path| path.display().to_string()).collect();
+    arboard::Clipboard::new()
+        .and_then(|mut clipboard| clipboard.set_text(text.join("\n")))
+        .map_err(|err| format!("can't copy to the clipboard: {}", err))
+}
+
+/// Puts `paths` on the system clipboard, one per line.
+#[cfg(not(feature = "clipboard"))]
+pub fn copy_paths(_paths: &[&Path]) -> Result<(), String> {
+    Err("--copy-paths needs fmql to be built with the `clipboard` feature".to_string())
+}
+
+/// Runs a launcher quietly and checks that it succeeded.
+fn run(command: &mut Command) -> io::Result<()> {
+    let status = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .map_err(|err| match err.kind() {
+            io::ErrorKind::NotFound => io::Error::new(
+                err.kind(),
+                format!("{} isn't installed", command.get_program().to_string_lossy()),
+            ),
+            _ => err,
+        })?;
+    if status.success() {
+        Ok(())
+    } else {
+        let program = command.get_program().to_string_lossy();
+        Err(io::Error::other(format!("{} failed ({})", program, status)))
+    }
+}
+
+/// Returns the `file://` URI of an absolute path, percent-encoding the bytes
+/// a URI can't hold as they are.
+fn file_uri(path: &Path) -> String {
+    use std::os::unix::ffi::OsStrExt;
+
+    let mut uri = String::from("file://");
+    for &byte in path.as_os_str().as_bytes() {
+        if byte.is_ascii_alphanumeric() || b"/-_.~".contains(&byte) {
+            uri.push(byte as char);
+        } else {
+            uri.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    uri
+}
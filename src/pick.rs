@@ -0,0 +1,113 @@
+//! Choosing one result interactively, for `fmql pick`, and the shell
+//! functions `fmql shell-init` prints to go with it.
+//!
+//! The choice is made in fzf when it is installed, which gives the full fzf
+//! experience with the user's own settings (`FZF_DEFAULT_OPTS` and so on),
+//! or else in a built-in fuzzy finder. Either way the list is drawn on the
+//! terminal rather than stdout, so `$(fmql pick ...)` captures just the
+//! chosen path.
+
+use std::io::{self, Read, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+use dialoguer::console::Term;
+use dialoguer::FuzzySelect;
+use fmql_core::sql::executor::FileResult;
+use fmql_core::sql::paths::display_path;
+
+/// The names `--picker` accepts.
+pub const PICKERS: &[&str] = &["auto", "fzf", "builtin"];
+
+/// The shells `fmql shell-init` knows.
+pub const SHELLS: &[&str] = &["bash", "zsh", "fish"];
+
+/// Lets the user choose one of `results` with `picker`, and returns its
+/// path, or None if they cancelled.
+pub fn pick(results: &[FileResult], picker: &str) -> io::Result<Option<PathBuf>> {
+    match picker {
+        "fzf" => fzf(results),
+        "builtin" => builtin(results),
+        _ => match fzf(results) {
+            Err(err) if err.kind() == io::ErrorKind::NotFound => builtin(results),
+            picked => picked,
+        },
+    }
+}
+
+/// Picks in fzf, handing it the paths separated by NULs so that any path,
+/// even one with a newline, comes back whole.
+fn fzf(results: &[FileResult]) -> io::Result<Option<PathBuf>> {
+    let mut child = Command::new("fzf")
+        .args(["--read0", "--print0", "--no-multi", "--prompt", "fmql> "])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .map_err(|err| match err.kind() {
+            io::ErrorKind::NotFound => io::Error::new(err.kind(), "fzf isn't installed"),
+            _ => err,
+        })?;
+    let mut input = child.stdin.take().expect("stdin is piped");
+    for result in results {
+        let written = input
+            .write_all(result.path.as_os_str().as_bytes())
+            .and_then(|()| input.write_all(b"\0"));
+        // fzf stops reading once the choice is made
+        if written.is_err() {
+            break;
+        }
+    }
+    drop(input);
+    let mut chosen = Vec::new();
+    child.stdout.take().expect("stdout is piped").read_to_end(&mut chosen)?;
+    let status = child.wait()?;
+    // 1 is no match and 130 is cancelled
+    match status.code() {
+        Some(0) => {}
+        Some(1 | 130) => return Ok(None),
+        _ => return Err(io::Error::other(format!("fzf failed ({})", status))),
+    }
+    if chosen.last() == Some(&0) {
+        chosen.pop();
+    }
+    Ok(Some(PathBuf::from(std::ffi::OsStr::from_bytes(&chosen))))
+}
+
+/// Picks in the built-in fuzzy finder, drawn on stderr.
+fn builtin(results: &[FileResult]) -> io::Result<Option<PathBuf>> {
+    let term = Term::stderr();
+    if !term.is_term() {
+        return Err(io::Error::other("picking needs a terminal"));
+    }
+    let items: Vec<String> = results.iter().map(|result| display_path(&result.path)).collect();
+    let chosen = FuzzySelect::new()
+        .with_prompt("fmql")
+        .items(&items)
+        .max_length(20)
+        .interact_on_opt(&term)
+        .map_err(|dialoguer::Error::IO(err)| err)?;
+    Ok(chosen.map(|index| results[index].path.clone()))
+}
+
+/// Returns the shell code that defines `name`, a function that picks one
+/// of the results of a query and changes to its directory.
+pub fn shell_init(shell: &str, name: &str) -> String {
+    match shell {
+        "fish" => format!(
+            "# Added by `fmql shell-init fish`: {name} \"<query>\" picks one of the query's\n\
+             # results and changes to its directory\n\
+             function {name} --description 'Pick a file fmql finds and change to its directory'\n\
+             \x20   set -l dir (command fmql pick --dir $argv); and cd -- $dir\n\
+             end\n"
+        ),
+        _ => format!(
+            "# Added by `fmql shell-init {shell}`: {name} \"<query>\" picks one of the query's\n\
+             # results and changes to its directory\n\
+             {name}() {{\n\
+             \x20   local dir\n\
+             \x20   dir=\"$(command fmql pick --dir \"$@\")\" && cd -- \"$dir\"\n\
+             }}\n"
+        ),
+    }
+}
@@ -0,0 +1,278 @@
+//! Interactive read-eval-print loop for fmql queries.
+//!
+//! The REPL highlights keywords, attributes and strings as you type and
+//! completes attribute names, functions, keywords and paths (after `FROM`).
+//! Both features are driven by the library's tokenizer and catalog, the same
+//! registry that backs the `SHOW COLUMNS` command. `SET TIME ZONE
+//! 'Europe/London'` reads and shows dates in that zone for the rest of the
+//! session, and `SHOW TIME ZONE` says which zone that is. `SET COMPAT 0.3`
+//! runs the queries after it with the semantics of fmql 0.3, and `SHOW
+//! COMPAT` says which semantics they run with. `OPEN 3` opens
+//! the third file the last query listed with its default application, and
+//! `REVEAL 3` shows it in the file manager; without a number they take
+//! every file the query listed.
+
+use std::borrow::Cow;
+
+use rustyline::completion::Completer;
+use rustyline::error::ReadlineError;
+use rustyline::highlight::{CmdKind, Highlighter};
+use rustyline::hint::Hinter;
+use rustyline::validate::Validator;
+use rustyline::config::{ColorMode, Config};
+use rustyline::{Context, Editor, Helper};
+
+use fmql_core::sql::ast::FileQuery;
+use fmql_core::sql::catalog::{columns, functions};
+use fmql_core::sql::completion::complete;
+use fmql_core::sql::executor::FileResult;
+use fmql_core::sql::lexer::{tokenize, TokenKind};
+use fmql_core::sql::timezone::parse_time_zone;
+use fmql_core::sql::{
+    execute_grouped_with_options, execute_query_with_options, execute_rollup_with_options,
+    ExecutionOptions,
+};
+
+use crate::open::Action;
+
+const PROMPT: &str = "fmql> ";
+
+/// rustyline helper providing highlighting and completion.
+struct QueryHelper;
+
+impl Completer for QueryHelper {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        let completion = complete(line, pos);
+        Ok((completion.start, completion.candidates))
+    }
+}
+
+impl Hinter for QueryHelper {
+    type Hint = String;
+}
+
+impl Validator for QueryHelper {}
+
+impl Highlighter for QueryHelper {
+    fn highlight<'l>(&self, line: &'l str, _pos: usize) -> Cow<'l, str> {
+        // Partial input (e.g. an unterminated string) is shown unhighlighted
+        let Ok(tokens) = tokenize(line) else {
+            return Cow::Borrowed(line);
+        };
+
+        let mut highlighted = String::with_capacity(line.len() * 2);
+        let mut last = 0;
+        for token in tokens {
+            let start = token.span.start.offset_in(line);
+            let end = token.span.end.offset_in(line);
+            if start < last || end < start {
+                continue;
+            }
+            highlighted.push_str(&line[last..start]);
+            match colour(token.kind) {
+                Some(code) => {
+                    highlighted.push_str(code);
+                    highlighted.push_str(&line[start..end]);
+                    highlighted.push_str("\x1b[0m");
+                }
+                None => highlighted.push_str(&line[start..end]),
+            }
+            last = end;
+        }
+        highlighted.push_str(&line[last..]);
+
+        Cow::Owned(highlighted)
+    }
+
+    fn highlight_char(&self, _line: &str, _pos: usize, _kind: CmdKind) -> bool {
+        true
+    }
+}
+
+impl Helper for QueryHelper {}
+
+/// Returns the ANSI colour sequence for a token kind.
+fn colour(kind: TokenKind) -> Option<&'static str> {
+    match kind {
+        TokenKind::Keyword => Some("\x1b[1;34m"),
+        TokenKind::Attribute => Some("\x1b[36m"),
+        TokenKind::Function => Some("\x1b[35m"),
+        TokenKind::String | TokenKind::Bytes => Some("\x1b[32m"),
+        TokenKind::Number => Some("\x1b[33m"),
+        TokenKind::Path => Some("\x1b[4m"),
+        TokenKind::Comment => Some("\x1b[90m"),
+        _ => None,
+    }
+}
+
+/// Runs the REPL until end of input or an `exit` command, running queries
+/// with `options`: reading and showing dates in its time zone until a
+/// `SET TIME ZONE` changes it, and with its semantics until a `SET COMPAT`
+/// does. Queries are highlighted as they are typed if `color`.
+pub fn run(format: &str, options: ExecutionOptions, color: bool) -> rustyline::Result<()> {
+    let color_mode = if color { ColorMode::Enabled } else { ColorMode::Disabled };
+    let config = Config::builder().color_mode(color_mode).build();
+    let mut editor: Editor<QueryHelper, _> = Editor::with_config(config)?;
+    editor.set_helper(Some(QueryHelper));
+    let mut options = ExecutionOptions {
+        stable_order: true,
+        ..options
+    };
+    // The files the last query listed, for OPEN and REVEAL
+    let mut last: Vec<FileResult> = Vec::new();
+
+    loop {
+        let line = match editor.readline(PROMPT) {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break,
+            Err(err) => return Err(err),
+        };
+
+        let input = line.trim().trim_end_matches(';');
+        if input.is_empty() {
+            continue;
+        }
+        editor.add_history_entry(line.as_str())?;
+
+        if let Some(name) = setting(input, "SET TIME ZONE") {
+            match parse_time_zone(name) {
+                Ok(zone) => options.time_zone = zone,
+                Err(err) => eprintln!("Error: {}", err),
+            }
+            continue;
+        }
+        if let Some(version) = setting(input, "SET COMPAT") {
+            match version.parse() {
+                Ok(semantics) => options.semantics = semantics,
+                Err(err) => eprintln!("Error: {}", err),
+            }
+            continue;
+        }
+        if let Some((action, index)) = action_command(input) {
+            let chosen = match index {
+                None => &last[..],
+                Some(index) => match last.get(index.wrapping_sub(1)) {
+                    Some(result) => std::slice::from_ref(result),
+                    None => {
+                        eprintln!("Error: the last query listed {} files", last.len());
+                        continue;
+                    }
+                },
+            };
+            if let Err(message) = action.apply(chosen) {
+                eprintln!("Error: {}", message);
+            }
+            continue;
+        }
+
+        match input.to_uppercase().as_str() {
+            "EXIT" | "QUIT" => break,
+            "SHOW COLUMNS" => show_columns(),
+            "SHOW FUNCTIONS" => show_functions(),
+            "SHOW TIME ZONE" => println!("{}", options.time_zone),
+            "SHOW COMPAT" => println!("{}", options.semantics),
+            _ => match crate::parse_query(input) {
+                Ok(query) => {
+                    let output = crate::Output {
+                        time_zone: options.time_zone,
+                        ..crate::Output::new(format, &query)
+                    };
+                    if let Some(results) = run_query(&query, &options, &output) {
+                        last = results;
+                    }
+                }
+                Err(err) => eprintln!("Error parsing SQL query: {}", err),
+            },
+        }
+    }
+
+    Ok(())
+}
+
+/// How to see the rows left out by the output cap
+const CAP_HINT: &str = "add a LIMIT to narrow the query";
+
+/// Runs a query and prints its results. Returns the files, for a query
+/// that lists them.
+fn run_query(
+    query: &FileQuery,
+    options: &ExecutionOptions,
+    output: &crate::Output,
+) -> Option<Vec<FileResult>> {
+    if query.is_rollup() {
+        match execute_rollup_with_options(query, options) {
+            Ok(trees) => crate::write_output(None, |out| crate::print_rollup(&trees, output, out)),
+            Err(err) => eprintln!("Error executing query: {}", err),
+        }
+    } else if query.is_grouped() {
+        match execute_grouped_with_options(query, options) {
+            Ok(rows) => {
+                let (shown, left_out) = crate::cap_rows(&rows, true);
+                crate::write_output(None, |out| crate::print_groups(shown, output, out));
+                crate::report_capped(shown.len(), left_out, CAP_HINT);
+            }
+            Err(err) => eprintln!("Error executing query: {}", err),
+        }
+    } else {
+        match execute_query_with_options(query, options) {
+            Ok(results) => {
+                let (shown, left_out) = crate::cap_rows(&results, true);
+                crate::write_output(None, |out| crate::print_results(shown, output, out));
+                crate::report_capped(shown.len(), left_out, CAP_HINT);
+                return Some(results);
+            }
+            Err(err) => eprintln!("Error executing query: {}", err),
+        }
+    }
+    None
+}
+
+/// Returns the action and result number of an `OPEN 3` or `REVEAL 3`
+/// command, or None if the input is something else. The number is None
+/// when the command leaves it out.
+fn action_command(input: &str) -> Option<(Action, Option<usize>)> {
+    let mut words = input.split_whitespace();
+    let action = match words.next()?.to_uppercase().as_str() {
+        "OPEN" => Action::Open,
+        "REVEAL" => Action::Reveal,
+        _ => return None,
+    };
+    let index = match (words.next(), words.next()) {
+        (None, _) => None,
+        (Some(number), None) => Some(number.parse().ok()?),
+        _ => return None,
+    };
+    Some((action, index))
+}
+
+/// Returns the value a setting command such as `SET TIME ZONE
+/// 'Europe/London'` gives, without its quotes, or None if the input is
+/// some other command.
+fn setting<'a>(input: &'a str, command: &str) -> Option<&'a str> {
+    let name = input
+        .get(..command.len())
+        .filter(|start| start.eq_ignore_ascii_case(command))
+        .and_then(|_| input.get(command.len()..))?;
+    let name = name.trim();
+    Some(name.strip_prefix('\'').and_then(|name| name.strip_suffix('\'')).unwrap_or(name))
+}
+
+fn show_columns() {
+    for column in columns() {
+        println!("{:<16} {:<10} {}", column.name, column.value_type, column.description);
+    }
+}
+
+fn show_functions() {
+    for function in functions() {
+        println!("{:<32} {:<10} {}", function.signature, function.value_type, function.description);
+    }
+}
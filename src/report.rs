@@ -0,0 +1,360 @@
+//! Tables of query results, for `--format csv`, `--format markdown` and
+//! `--format html`.
+//!
+//! All three show the same columns, the query's
+//! [schema](fmql_core::sql::schema): for files, what the query selected. CSV is for loading into
+//! other tools, and can carry the column types (`--csv-types`). Markdown
+//! comes out as a GitHub table ready to paste into a PR or wiki page; HTML
+//! is a standalone page with its own CSS, whose columns sort when their
+//! heading is clicked. Markdown and HTML write numbers and dates in the
+//! table's [`Locale`]; CSV always writes them plainly.
+
+use std::borrow::Cow;
+use std::io::{self, Write};
+
+use fmql_core::sql::ast::{FileAttribute, FileValue};
+use fmql_core::sql::catalog::ValueType;
+use fmql_core::sql::executor::{FileResult, GroupRow, OperationStatus, RollupNode};
+use fmql_core::sql::functions::to_text_in;
+use fmql_core::sql::locale::Locale;
+use fmql_core::sql::paths::display_path;
+use fmql_core::sql::permissions::PERMISSION_BITS;
+use fmql_core::sql::schema::SchemaColumn;
+use fmql_core::sql::timezone::Tz;
+
+/// The styles and sorting script embedded in every HTML report.
+const HTML_HEAD: &str = r#"<style>
+body { font-family: system-ui, sans-serif; margin: 2em; color: #222; }
+table { border-collapse: collapse; font-size: 0.9em; }
+th, td { border: 1px solid #ccc; padding: 0.3em 0.6em; text-align: left; vertical-align: top; }
+th { background: #f3f3f3; cursor: pointer; user-select: none; }
+th::after { content: " \2195"; color: #aaa; }
+tr:nth-child(even) td { background: #fafafa; }
+td.number { text-align: right; font-variant-numeric: tabular-nums; }
+</style>
+<script>
+document.addEventListener("DOMContentLoaded", () => {
+  document.querySelectorAll("th").forEach((th, column) => {
+    th.addEventListener("click", () => {
+      const body = th.closest("table").tBodies[0];
+      const descending = th.dataset.order !== "asc";
+      th.dataset.order = descending ? "asc" : "desc";
+      const key = row => row.cells[column].dataset.value ?? row.cells[column].textContent;
+      const rows = Array.from(body.rows).sort((a, b) => {
+        const [x, y] = [key(a), key(b)];
+        const order = x !== "" && y !== "" && !isNaN(x) && !isNaN(y)
+          ? Number(x) - Number(y)
+          : x.localeCompare(y, undefined, { numeric: true });
+        return descending ? order : -order;
+      });
+      rows.forEach(row => body.appendChild(row));
+    });
+  });
+});
+</script>"#;
+
+/// Returns true for the formats this module renders.
+pub fn is_table_format(format: &str) -> bool {
+    matches!(format, "csv" | "markdown" | "html")
+}
+
+/// A table of results, ready to render: one column for each column of the
+/// query's schema.
+pub struct Table<'a> {
+    columns: &'a [SchemaColumn],
+    rows: Rows<'a>,
+    /// The time zone dates are shown in.
+    zone: Tz,
+    /// How Markdown and HTML write numbers and dates.
+    locale: Locale,
+}
+
+/// The rows of a table. A file's row is made as it is written, so a CSV of
+/// millions of files never holds more than one row; the rows of groups and
+/// rollups, which are few, are made up front.
+enum Rows<'a> {
+    Files(&'a [FileResult]),
+    Values(Vec<Vec<FileValue>>),
+}
+
+impl<'a> Table<'a> {
+    /// Builds the table for a list of files.
+    pub fn from_results(results: &'a [FileResult], columns: &'a [SchemaColumn]) -> Self {
+        Table { columns, rows: Rows::Files(results), zone: Tz::UTC, locale: Locale::C }
+    }
+
+    /// Builds the table for the rows of a grouped query.
+    pub fn from_groups(rows: &[GroupRow], columns: &'a [SchemaColumn]) -> Self {
+        let rows = rows
+            .iter()
+            .map(|row| row.columns.iter().map(|(_, value)| value.clone()).collect())
+            .collect();
+        Table { columns, rows: Rows::Values(rows), zone: Tz::UTC, locale: Locale::C }
+    }
+
+    /// Builds the table for a rollup: one row per directory, parents before
+    /// their children.
+    pub fn from_rollup(trees: &[RollupNode], columns: &'a [SchemaColumn]) -> Self {
+        fn add(node: &RollupNode, rows: &mut Vec<Vec<FileValue>>) {
+            let mut row = vec![FileValue::String(display_path(&node.path))];
+            row.extend(node.columns.iter().map(|(_, value)| value.clone()));
+            rows.push(row);
+            for child in &node.children {
+                add(child, rows);
+            }
+        }
+
+        let mut rows = Vec::new();
+        for tree in trees {
+            add(tree, &mut rows);
+        }
+        Table { columns, rows: Rows::Values(rows), zone: Tz::UTC, locale: Locale::C }
+    }
+
+    /// Shows the table's dates as local times in `zone` instead of UTC.
+    pub fn in_zone(mut self, zone: Tz) -> Self {
+        self.zone = zone;
+        self
+    }
+
+    /// Writes the Markdown and HTML tables' numbers and dates as `locale`
+    /// does.
+    pub fn in_locale(mut self, locale: Locale) -> Self {
+        self.locale = locale;
+        self
+    }
+
+    /// Writes the table in the given format: `csv`, `markdown` or `html`.
+    /// `types` adds a row of column types under the CSV header.
+    pub fn write(&self, format: &str, types: bool, out: &mut dyn Write) -> io::Result<()> {
+        match format {
+            "csv" => self.write_csv(types, out),
+            "html" => write!(out, "{}", self.to_html()),
+            _ => write!(out, "{}", self.to_markdown()),
+        }
+    }
+
+    /// The number of rows.
+    fn len(&self) -> usize {
+        match &self.rows {
+            Rows::Files(files) => files.len(),
+            Rows::Values(rows) => rows.len(),
+        }
+    }
+
+    /// The rows, each with a value for every column.
+    fn rows(&self) -> Box<dyn Iterator<Item = Cow<'_, [FileValue]>> + '_> {
+        match &self.rows {
+            Rows::Files(files) => Box::new(files.iter().map(|file| {
+                let row: Vec<FileValue> = self
+                    .columns
+                    .iter()
+                    .map(|column| file_value(file, &column.name))
+                    .collect();
+                Cow::Owned(row)
+            })),
+            Rows::Values(rows) => Box::new(rows.iter().map(|row| Cow::Borrowed(row.as_slice()))),
+        }
+    }
+
+    fn is_number(&self, column: usize) -> bool {
+        self.columns[column].value_type == ValueType::Number
+    }
+
+    /// Writes CSV as RFC 4180 has it: a header, then one line per row,
+    /// with fields quoted where they need to be. Each row is written as
+    /// soon as it is made.
+    pub fn write_csv(&self, types: bool, out: &mut dyn Write) -> io::Result<()> {
+        let mut line = |fields: Vec<String>| {
+            let fields: Vec<String> = fields.iter().map(|field| csv_escape(field)).collect();
+            write!(out, "{}\r\n", fields.join(","))
+        };
+        line(self.columns.iter().map(|column| column.name.clone()).collect())?;
+        if types {
+            line(
+                self.columns
+                    .iter()
+                    .map(|column| column.value_type.to_string())
+                    .collect(),
+            )?;
+        }
+        for row in self.rows() {
+            line(row.iter().map(|value| to_text_in(value, self.zone)).collect())?;
+        }
+        Ok(())
+    }
+
+    /// Renders a GitHub-flavoured Markdown table.
+    pub fn to_markdown(&self) -> String {
+        let line = |cells: Vec<String>| format!("| {} |\n", cells.join(" | "));
+        let mut out = line(
+            self.columns
+                .iter()
+                .map(|column| markdown_escape(&column.name))
+                .collect(),
+        );
+        out.push_str(&line(
+            (0..self.columns.len())
+                .map(|i| if self.is_number(i) { "---:" } else { "---" }.to_string())
+                .collect(),
+        ));
+        for row in self.rows() {
+            out.push_str(&line(
+                row.iter()
+                    .map(|value| markdown_escape(&self.locale.format_value(value, self.zone)))
+                    .collect(),
+            ));
+        }
+        out
+    }
+
+    /// Renders a standalone HTML page holding the table.
+    pub fn to_html(&self) -> String {
+        let mut out = String::from("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+        out.push_str("<title>fmql results</title>\n");
+        out.push_str(HTML_HEAD);
+        out.push_str("\n</head>\n<body>\n");
+        out.push_str(&format!("<p>{} rows</p>\n<table>\n<thead>\n<tr>", self.len()));
+        for column in self.columns {
+            out.push_str(&format!("<th>{}</th>", html_escape(&column.name)));
+        }
+        out.push_str("</tr>\n</thead>\n<tbody>\n");
+        for row in self.rows() {
+            out.push_str("<tr>");
+            for (i, value) in row.iter().enumerate() {
+                let plain = to_text_in(value, self.zone);
+                let shown = self.locale.format_value(value, self.zone);
+                let text = html_escape(&shown).replace('\n', "<br>");
+                // Columns sort by the plain value, which the locale may
+                // have written with separators or the day first
+                let sort = match shown == plain {
+                    true => String::new(),
+                    false => format!(" data-value=\"{}\"", html_escape(&plain)),
+                };
+                if self.is_number(i) {
+                    out.push_str(&format!("<td class=\"number\"{}>{}</td>", sort, text));
+                } else {
+                    out.push_str(&format!("<td{}>{}</td>", sort, text));
+                }
+            }
+            out.push_str("</tr>\n");
+        }
+        out.push_str("</tbody>\n</table>\n</body>\n</html>\n");
+        out
+    }
+}
+
+/// Returns a file's value for the column of a file query's schema named
+/// `name`: a computed column, an attribute, or a column a statement such as
+/// COPY reports.
+pub fn file_value(file: &FileResult, name: &str) -> FileValue {
+    if let Some(value) = file.computed.get(name) {
+        return value.clone();
+    }
+    let selected = FileAttribute::from_name(name).and_then(|a| file.selected.get(a));
+    if let Some(value) = selected {
+        return value.clone();
+    }
+    match name {
+        "path" => FileValue::String(display_path(&file.path)),
+        "name" => FileValue::String(file.name.clone()),
+        "size" => FileValue::from_u64(file.size),
+        "modified" => FileValue::DateTime(file.modified),
+        "extension" => file
+            .extension
+            .as_deref()
+            .map_or(FileValue::Null, |extension| FileValue::String(extension.to_string())),
+        "permissions" => FileValue::Integer((file.permissions & PERMISSION_BITS).into()),
+        "is_directory" => FileValue::Boolean(file.is_directory),
+        "is_symlink" => FileValue::Boolean(file.is_symlink),
+        "preview" => file.preview.clone().map_or(FileValue::Null, FileValue::String),
+        "matches" => {
+            let lines: Vec<String> = file
+                .matches
+                .iter()
+                .map(|found| format!("{}: {}", found.line, found.text))
+                .collect();
+            FileValue::String(lines.join("\n"))
+        }
+        "source" => file
+            .changes
+            .iter()
+            .find(|change| change.attribute == FileAttribute::Path)
+            .map_or(FileValue::Null, |change| change.old_value.clone()),
+        "checksum" => file.checksum.clone().map_or(FileValue::Null, FileValue::String),
+        "original_size" => file
+            .changes
+            .iter()
+            .find(|change| change.attribute == FileAttribute::Size)
+            .map_or(FileValue::Null, |change| change.old_value.clone()),
+        "destination" => file
+            .changes
+            .iter()
+            .find(|change| change.attribute == FileAttribute::Path)
+            .map_or(FileValue::Null, |change| change.new_value.clone()),
+        "files" | "bytes" | "skipped" => match file.extracted {
+            Some(extracted) => FileValue::from_u64(match name {
+                "files" => extracted.files,
+                "bytes" => extracted.bytes,
+                _ => extracted.skipped,
+            }),
+            None => FileValue::Null,
+        },
+        "transferred" | "resumed" | "retries" | "seconds" => match file.transfer {
+            Some(transfer) => match name {
+                "transferred" => FileValue::from_u64(transfer.bytes),
+                "resumed" => FileValue::from_u64(transfer.resumed),
+                "retries" => FileValue::Integer(i64::from(transfer.retries)),
+                _ => FileValue::Number(transfer.seconds),
+            },
+            None => FileValue::Null,
+        },
+        "status" => FileValue::String(
+            match (&file.error_message, file.status) {
+                (Some(message), _) => format!("failed: {}", message),
+                (None, Some(OperationStatus::Ok)) => match (file.copied, file.compressed) {
+                    (Some(method), _) => method.verb().to_string(),
+                    (None, Some(_)) => "compressed".to_string(),
+                    (None, None) if file.extracted.is_some() => "extracted".to_string(),
+                    (None, None) if file.archived.is_some() => "archived".to_string(),
+                    (None, None) => "updated".to_string(),
+                },
+                (None, Some(OperationStatus::Skipped)) => "unchanged".to_string(),
+                (None, Some(OperationStatus::Failed)) => "failed".to_string(),
+                (None, None) => String::new(),
+            },
+        ),
+        _ => FileValue::Null,
+    }
+}
+
+/// Quotes a CSV field if it holds a separator, a quote or a line break.
+fn csv_escape(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Escapes text for a Markdown table cell: pipes would end the cell and
+/// newlines the row.
+fn markdown_escape(text: &str) -> String {
+    text.replace('|', "\\|").replace('\n', "<br>")
+}
+
+/// Escapes text for HTML.
+fn html_escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
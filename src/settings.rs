@@ -0,0 +1,112 @@
+//! Where the settings of a run come from, when the command line leaves
+//! them out: the environment, then the config file's profile.
+//!
+//! Scripts and CI jobs set these once rather than repeating flags:
+//!
+//! - `FMQL_PROFILE` picks a profile of the config file, as `--profile` does
+//! - `FMQL_FORMAT` is the output format of every command with `--format`
+//! - `FMQL_ASSUME_YES` answers yes to every question, as `--yes` does
+//! - `FMQL_NO_COLOR`, like `NO_COLOR`, turns color off
+//! - `CI`, which CI services set, means nobody is there to answer: questions
+//!   take their defaults, color is off and results come in a stable order
+//!
+//! A variable counts as set unless it is empty, `0`, `false`, `no` or `off`.
+//! Options given on the command line win over the environment, and the
+//! environment over the config file.
+
+use std::env;
+use std::path::PathBuf;
+
+use crate::config::Config;
+
+/// The settings of a run that the command line may leave out.
+#[derive(Debug, Default)]
+pub struct Settings {
+    /// The output format of commands with `--format`.
+    format: Option<String>,
+    /// The time zone of commands with `--tz`.
+    tz: Option<String>,
+    /// The directory commands with `--root` are confined to.
+    root: Option<PathBuf>,
+    /// Reject queries that would modify files.
+    read_only: bool,
+    /// Answer yes to every question.
+    assume_yes: bool,
+    /// Nobody is there to answer questions or read colors.
+    ci: bool,
+    /// Color was turned off.
+    no_color: bool,
+}
+
+impl Settings {
+    /// Returns the name of the profile to use: `--profile`'s, or else
+    /// `FMQL_PROFILE`'s.
+    pub fn profile(flag: Option<&str>) -> Option<String> {
+        flag.map(str::to_string)
+            .or_else(|| env::var("FMQL_PROFILE").ok().filter(|name| !name.is_empty()))
+    }
+
+    /// Resolves the settings of a run from the environment and `config`,
+    /// with `assume_yes` set if `--yes` was given.
+    pub fn resolve(config: &Config, assume_yes: bool) -> Settings {
+        let format = env::var("FMQL_FORMAT").ok().filter(|format| !format.is_empty());
+        Settings {
+            format: format.or_else(|| config.format().map(str::to_string)),
+            tz: config.tz().map(str::to_string),
+            root: config.root(),
+            read_only: config.read_only(),
+            assume_yes: assume_yes || is_set("FMQL_ASSUME_YES"),
+            ci: is_set("CI"),
+            no_color: is_set("FMQL_NO_COLOR") || env::var_os("NO_COLOR").is_some(),
+        }
+    }
+
+    /// Returns the values of command-line options that aren't given, by
+    /// the name of the option: `format`, `tz`, `root` and `read_only`.
+    pub fn defaults(&self) -> Vec<(&'static str, String)> {
+        let root = self.root.as_ref().map(|root| root.to_string_lossy().into_owned());
+        let read_only = self.read_only.then(|| "true".to_string());
+        [
+            ("format", self.format.clone()),
+            ("tz", self.tz.clone()),
+            ("root", root),
+            ("read_only", read_only),
+        ]
+        .into_iter()
+        .filter_map(|(name, value)| Some((name, value?)))
+        .collect()
+    }
+
+    /// Returns the answer to a yes-or-no question that isn't asked, or
+    /// None if it is to be asked: yes with `--yes`, and the question's
+    /// `default` in CI.
+    pub fn answer(&self, default: bool) -> Option<bool> {
+        if self.assume_yes {
+            Some(true)
+        } else if self.ci {
+            Some(default)
+        } else {
+            None
+        }
+    }
+
+    /// Returns whether color may be used, where the output can show it.
+    pub fn color(&self) -> bool {
+        !self.no_color && !self.ci
+    }
+
+    /// Returns whether the run is in CI, which makes results come in a
+    /// stable order.
+    pub fn ci(&self) -> bool {
+        self.ci
+    }
+}
+
+/// Returns whether an environment variable is set to something other than
+/// a word for no.
+fn is_set(name: &str) -> bool {
+    env::var(name).is_ok_and(|value| {
+        let value = value.trim().to_ascii_lowercase();
+        !matches!(value.as_str(), "" | "0" | "false" | "no" | "off")
+    })
+}
